@@ -1,353 +1,1514 @@
+use crate::{
+    frame::FrameStages,
+    loading::{Boot, CAMERA_SPEED},
+    menu::{Menu, MenuAction, MenuKind},
+    viewport::{PendingResize, ViewportContext, ViewportKind},
+};
 use engine::{
-    camera::{Camera, CameraControls, Projection},
-    debug_scene, log_debug, log_error, log_info, BindGroup, DebugMode, DebugUniform, EngineError,
-    Entity, FrameBuffer, Light, Medium, RenderPass, RenderTargetKind, RenderTargetManager,
-    RenderText, Renderer3d, Rotation, ScreenCorner, SurfaceExt, TextRegion, Texture, Time,
-    Velocity, WgpuBuffer, World,
+    camera::{
+        Binding, Camera, CameraControls, CameraProjection, InputAction, InputMap, OrbitSettings,
+        Projection,
+    },
+    log_debug, log_error, log_info, log_warning, Console, ConsoleAction, DebugMode, DebugUniform,
+    DepthPolicy, DepthVariant, EngineError, EngineSettings, Entity, EnvironmentSource, FrameBuffer,
+    FrameBufferSize, GpuProfiler, Light, LodSelection, Medium, NavAgent, NavDirection, NavTarget,
+    PassGraph, Position, PostProcessChain, RenderSettings, RenderTargetKind, Renderer3d, Rotation,
+    ScreenCorner, SequenceEvent, SequenceHost, SequencePlayer, Shader, SurfaceExt, TextAnchor,
+    TextRegion, TextStack, Time, UiDevice, WgpuBuffer, World, WorldEvent, WorldProjection, AIR,
+    GPU, HARD_LANDING_SPEED, SAMPLE_COUNTS, STONE,
+};
+use glam::{IVec3, Quat, Vec2, Vec3};
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::Arc,
+    time::{Duration, Instant},
 };
-use glam::Vec3;
-use std::sync::Arc;
 use wgpu::BufferUsages;
 use winit::{
-    dpi::PhysicalSize,
+    dpi::{PhysicalPosition, PhysicalSize},
+    event::{ElementState, MouseScrollDelta, WindowEvent},
     event_loop::ActiveEventLoop,
-    window::{Window, WindowAttributes},
+    keyboard::PhysicalKey,
+    window::{Window, WindowId},
 };
 
+/// How long a shader file has to go unwritten before it's reloaded.
+const SHADER_SETTLE: Duration = Duration::from_millis(200);
+const PRESENT_MODES: [wgpu::PresentMode; 3] = [
+    wgpu::PresentMode::Fifo,
+    wgpu::PresentMode::Mailbox,
+    wgpu::PresentMode::Immediate,
+];
+const PLACED_BLOCK: engine::Block = STONE;
+
 #[allow(dead_code)]
 pub struct Rupy {
     time: Time,
-    window: Arc<Window>,
-    surface: wgpu::Surface<'static>,
-    surface_config: wgpu::SurfaceConfiguration,
+    main: ViewportContext,
+    /// Closing one only drops its entry.
+    viewports: HashMap<WindowId, ViewportContext>,
     world: World,
     render3d: Renderer3d,
-    render_targets: RenderTargetManager,
-    rendertxt: RenderText,
-    camera: Camera,
+    post_process: PostProcessChain,
+    profiler: GpuProfiler,
     projection: Projection,
     light: Light,
     controls: CameraControls,
-    last_shape_time: std::time::Instant,
-    uniform_bind_group: wgpu::BindGroup,
     model_manager: engine::ModelManager,
     bossman: Entity,
+    hud: Vec<TextRegion>,
     debug_mode: DebugMode,
+    depth_stencil: wgpu::DepthStencilState,
+    menu: Option<Menu>,
+    console: Console,
+    sequence: Option<SequencePlayer>,
+    /// Set while a sequence drives the light orbit instead of the clock.
+    sun_angle: Option<f32>,
+    screenshot: bool,
+    cursor: PhysicalPosition<f64>,
+    picked: Option<Entity>,
+    changed_shaders: HashMap<String, Instant>,
+    /// What's applied may be lower.
+    msaa_request: u32,
+    /// What's applied may differ, see [`engine::present_mode_fallbacks`].
+    present_request: wgpu::PresentMode,
+    settings: EngineSettings,
+    skybox_alternate: EnvironmentSource,
+    #[cfg(feature = "devtools")]
+    egui: engine::devtools::EguiLayer,
+    #[cfg(feature = "scripting")]
+    scripts: engine::scripting::ScriptHost,
+    #[cfg(feature = "hot-reload-game")]
+    game: engine::game_module::GameModuleHost,
+    #[cfg(feature = "audio")]
+    audio: engine::audio::AudioManager,
 }
 
 impl Rupy {
-    pub fn new(event_loop: &ActiveEventLoop) -> Result<Rupy, EngineError> {
-        let win_attrs = WindowAttributes::default().with_title("RupyEngine");
-        let window = Arc::new(event_loop.create_window(win_attrs)?);
-        let win_clone = Arc::clone(&window);
-        let (width, height) = {
-            let inenr_size = window.inner_size();
-            (inenr_size.width, inenr_size.height)
+    pub fn from_boot(boot: Boot) -> Result<Rupy, EngineError> {
+        let mut controls = CameraControls::new(CAMERA_SPEED, 0.1);
+        match InputMap::load() {
+            Ok(input_map) => controls.set_input_map(input_map),
+            Err(e) => log_error!("Input bindings, using the defaults: {}", e),
+        }
+
+        #[cfg(feature = "devtools")]
+        let egui = {
+            let mut egui = engine::devtools::EguiLayer::new(
+                &boot.device,
+                &boot.window,
+                boot.surface_config.format,
+            );
+            egui.add_panel(engine::devtools::entity_inspector);
+            egui.add_panel(engine::devtools::material_tweaker);
+            egui.add_panel(engine::devtools::resource_stats);
+            egui.add_panel(engine::devtools::draw_stats);
+            egui.add_panel(engine::devtools::console);
+            egui
         };
-        let binding = crate::GPU::get();
-        let (surface, surface_config, gpu) = {
-            let gpu = binding
-                .read()
-                .map_err(|e| crate::EngineError::GpuError(format!("{}", e.to_string())))?;
-
-            let surface = gpu.instance().create_surface(win_clone)?;
-            let mut surface_config = surface
-                .get_default_config(&gpu.adapter(), width, height)
-                .ok_or(EngineError::SurfaceConfigError(
-                    "surface isn't supported by this adapter".into(),
-                ))?;
-            surface_config.present_mode = wgpu::PresentMode::AutoVsync;
-            (surface, surface_config, gpu)
+        #[cfg(feature = "scripting")]
+        let scripts = {
+            let mut scripts = engine::scripting::ScriptHost::default();
+            if let Err(e) = scripts.watch() {
+                log_error!("Script hot reload unavailable: {}", e);
+            }
+            scripts
         };
-
-        let device = gpu.device();
-        let queue = gpu.queue();
-        let mut model_manager = engine::ModelManager::new(queue.clone(), device.clone());
-
-        surface.configure(&device, &surface_config);
-
-        let time = Time::new();
-        let render3d = Renderer3d::new(&device, &surface_config)?;
-        let depth_stencil = wgpu::DepthStencilState {
-            format: Texture::DEPTH_FORMAT,
-            depth_write_enabled: true,
-            depth_compare: wgpu::CompareFunction::LessEqual,
-            stencil: wgpu::StencilState::default(),
-            bias: wgpu::DepthBiasState::default(),
+        let world = Boot::take(boot.world, "world")?;
+        let profiler = GpuProfiler::new(&boot.device, &boot.queue);
+        // `RUPY_DEPTH_PREPASS=1` starts with the depth pre-pass on; F9 toggles it.
+        if std::env::var("RUPY_DEPTH_PREPASS").is_ok_and(|v| v == "1") {
+            RenderSettings::set_depth_prepass(true);
+        }
+        // `RUPY_INSTANCE_STORAGE=0` keeps instances in vertex attributes where storage works.
+        if std::env::var("RUPY_INSTANCE_STORAGE").is_ok_and(|v| v == "0") {
+            RenderSettings::set_instance_storage(false);
+        }
+        #[cfg(feature = "hot-reload-game")]
+        let (world, game) = {
+            let mut world = world;
+            let mut game = engine::game_module::GameModuleHost::default();
+            if let Err(e) = game.load(&mut world) {
+                log_error!("Game module not loaded: {}", e);
+            }
+            if let Err(e) = game.watch() {
+                log_error!("Game module hot reload unavailable: {}", e);
+            }
+            (world, game)
+        };
+        let main = ViewportContext {
+            kind: ViewportKind::Main,
+            window: boot.window,
+            surface: boot.surface,
+            surface_config: boot.surface_config,
+            surface_suspended: boot.surface_suspended,
+            render_targets: Boot::take(boot.render_targets, "render targets")?,
+            rendertxt: Boot::take(boot.rendertxt, "text layer")?,
+            camera: Boot::take(boot.camera, "camera")?,
+            uniform_bind_group: Boot::take(boot.uniform_bind_group, "uniform bind group")?,
+            lods: LodSelection::default(),
+            last_shape_time: Instant::now(),
+            pending_resize: PendingResize::default(),
         };
-        let rendertxt = RenderText::new(
-            &device,
-            &queue,
-            surface_config.format,
-            &Some(depth_stencil.clone()),
-        );
-
-        let projection = Projection::ThirdPerson;
-        let mut camera = Camera::new(&device, width as f32 / height as f32);
-        let light = Light::new(&device)?;
-        let controls = CameraControls::new(5.0, 0.1);
-
-        let mut world = World::new(queue, device, &surface_config, Some(depth_stencil.clone()))?;
-
-        let mut render_targets = RenderTargetManager::new();
-        render_targets.insert(
-            FrameBuffer::new_with_depth(
-                &device,
-                (surface_config.width, surface_config.height).into(),
-                surface_config.format,
-                Texture::DEPTH_FORMAT,
-                "scene buffer",
-            ),
-            RenderTargetKind::Scene,
-        );
-        render_targets.insert(
-            FrameBuffer::new_color_only(
-                &device,
-                (surface_config.width, surface_config.height).into(),
-                surface_config.format,
-                "hdr buffer",
-            ),
-            RenderTargetKind::Hdr,
-        );
-
-        let uniform_bind_group = BindGroup::uniform(&device, camera.buffer(), light.buffer());
-
-        let debug_mode = DebugMode::new(
-            device,
-            &mut model_manager.materials.shaders,
-            &camera,
-            &light,
-            &surface_config,
-        )?;
-
-        let bossman = debug_scene(
-            &mut model_manager,
-            &mut world,
-            &surface_config,
-            depth_stencil.clone(),
-        );
-        camera.world_spawn(&mut world, &mut model_manager, &surface_config);
-        let mediums = vec![Medium::Water, Medium::Water, Medium::Vacuum, Medium::Vacuum];
-        world.generate_terrain(
-            *camera.eye(),
-            1,
-            mediums,
-            &surface_config,
-            &depth_stencil,
-            &mut model_manager,
-        );
-        model_manager.materials.build_storage(device);
         Ok(Rupy {
-            time,
-            window,
-            surface,
-            surface_config,
+            time: Time::new(),
+            present_request: boot.settings.present_mode,
+            main,
+            viewports: HashMap::new(),
             world,
-            render3d,
-            rendertxt,
-            camera,
-            projection,
-            light,
+            render3d: Boot::take(boot.render3d, "renderer")?,
+            projection: Projection::ThirdPerson,
+            light: Boot::take(boot.light, "light")?,
             controls,
-            render_targets,
-            last_shape_time: std::time::Instant::now(),
-            uniform_bind_group,
-            model_manager,
-            bossman,
-            debug_mode,
+            post_process: Boot::take(boot.post_process, "post processing")?,
+            profiler,
+            model_manager: boot.model_manager,
+            bossman: Boot::take(boot.bossman, "scene")?,
+            hud: Vec::new(),
+            debug_mode: Boot::take(boot.debug_mode, "debug pipelines")?,
+            depth_stencil: boot.depth_stencil,
+            menu: None,
+            console: Console::default(),
+            sequence: None,
+            sun_angle: None,
+            screenshot: false,
+            cursor: PhysicalPosition::new(0.0, 0.0),
+            picked: None,
+            changed_shaders: HashMap::new(),
+            msaa_request: RenderSettings::sample_count(),
+            skybox_alternate: Self::skybox_faces(),
+            settings: boot.settings,
+            #[cfg(feature = "devtools")]
+            egui,
+            #[cfg(feature = "scripting")]
+            scripts,
+            #[cfg(feature = "hot-reload-game")]
+            game,
+            #[cfg(feature = "audio")]
+            audio: engine::audio::AudioManager::new(),
         })
     }
-    pub fn shutdown(&self, el: &ActiveEventLoop) {
+    pub fn shutdown(&mut self, el: &ActiveEventLoop) {
         log_info!("Shutdown");
         World::stop();
+        let saved = self.world.terrain.save_modified();
+        if saved > 0 {
+            log_info!("Saved {} edited chunks", saved);
+        }
         if !el.exiting() {
             el.exit();
         }
     }
+    /// Textures built at runtime can't be reloaded and are dropped. Secondary windows close.
+    pub fn into_recovery(self) -> (Arc<Window>, Option<String>, Vec<String>) {
+        let textures = &self.model_manager.materials.textures;
+        for (key, label) in textures.unrebuildable() {
+            log_warning!(
+                "Dropping texture '{}' ({:?}): no asset to reload it from",
+                label,
+                key
+            );
+        }
+        let sources = textures.sources().map(str::to_string).collect();
+        let scene = self.world.scene().map(|scene| scene.name.clone());
+        (self.main.window.clone(), scene, sources)
+    }
     pub fn input(&mut self, event: &winit::event::WindowEvent) -> bool {
         self.controls.process_event(event)
     }
+    pub fn commands(&self, binding: Binding) -> Vec<InputAction> {
+        let actions = self.controls.input_map().actions(binding).iter();
+        actions.copied().filter(|action| !action.held()).collect()
+    }
+    pub fn run_command(&mut self, action: InputAction, el: &ActiveEventLoop) {
+        match action {
+            InputAction::NextProjection => self.next_projection(),
+            InputAction::NextDebugMode => self.next_debug_mode(),
+            InputAction::ToggleCameraProjection => self.toggle_camera_projection(),
+            InputAction::ToggleFreeLook => {
+                let free_look = !self.main.camera.free_look();
+                self.main.camera.set_free_look(free_look)
+            }
+            InputAction::ScenePicker => self.toggle_scene_picker(),
+            InputAction::Devtools => self.toggle_devtools(),
+            InputAction::ReverseZ => self.toggle_reverse_z(),
+            InputAction::CycleMsaa => self.cycle_msaa(),
+            InputAction::ReloadScene => self.reload_scene(),
+            InputAction::CyclePresentMode => self.cycle_present_mode(),
+            InputAction::ToggleBloom => self.toggle_bloom(),
+            InputAction::ToggleSkybox => self.toggle_skybox(),
+            InputAction::ToggleDepthPrepass => self.toggle_depth_prepass(),
+            InputAction::Screenshot => self.screenshot(),
+            InputAction::ToggleMapWindow => self.toggle_map_window(el),
+            InputAction::PauseTime => self.toggle_time_pause(),
+            InputAction::StepTime => self.step_time(),
+            InputAction::SlowerTime => self.set_time_scale(self.time.scale() * 0.5),
+            InputAction::FasterTime => self.set_time_scale(self.time.scale() * 2.0),
+            InputAction::Console => self.toggle_console(),
+            InputAction::MoveForward
+            | InputAction::MoveBack
+            | InputAction::MoveLeft
+            | InputAction::MoveRight
+            | InputAction::Jump
+            | InputAction::Pan => {}
+        }
+    }
+    /// `true` when the console took `event`.
+    pub fn console_input(&mut self, event: &WindowEvent, el: &ActiveEventLoop) -> bool {
+        if !self.console.is_open() {
+            return false;
+        }
+        if let WindowEvent::KeyboardInput { event, .. } = event {
+            let toggle = match event.physical_key {
+                PhysicalKey::Code(code) => self.commands(Binding::Key(code)),
+                _ => Vec::new(),
+            };
+            if event.state.is_pressed() && toggle.contains(&InputAction::Console) {
+                self.toggle_console();
+                return true;
+            }
+        }
+        let consumed = self.console.process_event(event);
+        self.console
+            .run_submitted(&mut self.world, &mut self.model_manager);
+        for action in self.console.take_actions() {
+            match action {
+                ConsoleAction::Teleport(position) => {
+                    self.main.camera.teleport(&mut self.world, position)
+                }
+                ConsoleAction::TimeScale(scale) => self.set_time_scale(scale),
+                ConsoleAction::Quit => self.shutdown(el),
+            }
+        }
+        if !self.console.is_open() {
+            self.main.window.set_ime_allowed(false);
+        }
+        if consumed {
+            self.main.reshape();
+        }
+        consumed
+    }
+    pub fn toggle_console(&mut self) {
+        let open = !self.console.is_open();
+        self.console.set_open(open);
+        self.main.window.set_ime_allowed(open);
+        self.main.reshape();
+    }
+    /// `true` when the dev UI claimed `event`.
+    #[cfg(feature = "devtools")]
+    pub fn ui_input(&mut self, event: &winit::event::WindowEvent) -> bool {
+        self.egui.on_window_event(&self.main.window, event)
+    }
+    #[cfg(not(feature = "devtools"))]
+    pub fn ui_input(&mut self, _event: &winit::event::WindowEvent) -> bool {
+        false
+    }
+    #[cfg(feature = "devtools")]
+    pub fn toggle_devtools(&mut self) {
+        self.egui.set_visible(!self.egui.visible());
+    }
+    #[cfg(not(feature = "devtools"))]
+    pub fn toggle_devtools(&mut self) {}
     pub fn window(&self) -> &Window {
-        &self.window
+        &self.main.window
+    }
+    fn viewports_mut(&mut self) -> impl Iterator<Item = &mut ViewportContext> {
+        std::iter::once(&mut self.main).chain(self.viewports.values_mut())
+    }
+    pub fn is_main(&self, id: WindowId) -> bool {
+        self.main.id() == id
+    }
+    pub fn toggle_map_window(&mut self, el: &ActiveEventLoop) {
+        let map = self
+            .viewports
+            .iter()
+            .find(|(_, viewport)| matches!(viewport.kind, ViewportKind::Map { .. }))
+            .map(|(id, _)| *id);
+        if let Some(id) = map {
+            self.close_viewport(id);
+            return;
+        }
+        match ViewportContext::open(
+            el,
+            "RupyEngine - Map",
+            ViewportKind::Map {
+                height: ViewportKind::MAP_HEIGHT,
+            },
+            self.main.surface_config.format,
+            self.main.surface_config.present_mode,
+            &self.depth_stencil,
+            &self.light,
+        ) {
+            Ok(viewport) => {
+                log_info!("Opened map window");
+                viewport.window.request_redraw();
+                self.viewports.insert(viewport.id(), viewport);
+            }
+            Err(e) => log_error!("Map window: {}", e),
+        }
+    }
+    pub fn close_viewport(&mut self, id: WindowId) {
+        if self.viewports.remove(&id).is_some() {
+            log_info!("Closed window {:?}", id);
+        }
+    }
+    pub fn viewport_event(&mut self, id: WindowId, event: &WindowEvent) {
+        if matches!(event, WindowEvent::CloseRequested | WindowEvent::Destroyed) {
+            self.close_viewport(id);
+            return;
+        }
+        let Some(viewport) = self.viewports.get_mut(&id) else {
+            return;
+        };
+        match event {
+            WindowEvent::Resized(size) => viewport.request_resize(*size),
+            WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+                let scale_factor = viewport.text_scale_factor(*scale_factor);
+                viewport.rendertxt.set_scale_factor(scale_factor);
+                viewport.reshape();
+            }
+            WindowEvent::MouseWheel { delta, .. } => viewport.zoom(match delta {
+                MouseScrollDelta::LineDelta(_, y) => *y,
+                MouseScrollDelta::PixelDelta(position) => position.y as f32 / 40.0,
+            }),
+            _ => {}
+        }
+    }
+    pub fn screenshot(&mut self) {
+        self.screenshot = true;
     }
-    pub fn cam(&self) -> &Camera {
-        &self.camera
+    // Pays off in scenes dominated by fragment shading.
+    pub fn toggle_depth_prepass(&mut self) {
+        RenderSettings::set_depth_prepass(!RenderSettings::depth_prepass());
+        log_info!("Depth pre-pass: {}", RenderSettings::depth_prepass());
     }
-    pub fn cam_mut(&mut self) -> &mut Camera {
-        &mut self.camera
+    pub fn toggle_bloom(&mut self) {
+        if let Some(bloom) = self.post_process.pass_mut("Bloom") {
+            bloom.set_enabled(!bloom.enabled());
+            log_info!("Bloom: {}", bloom.enabled());
+        }
+    }
+    /// `RUPY_SKYBOX_FACES`: six files in `px,nx,py,ny,pz,nz` order, a folder or a `{face}`
+    /// pattern; `skybox` when unset.
+    fn skybox_faces() -> EnvironmentSource {
+        let faces = std::env::var("RUPY_SKYBOX_FACES").unwrap_or_else(|_| "skybox".to_string());
+        let files: Vec<String> = faces.split(',').map(|f| f.trim().to_string()).collect();
+        match <[String; 6]>::try_from(files) {
+            Ok(faces) => EnvironmentSource::CubemapFiles {
+                faces,
+                orientation: Default::default(),
+            },
+            Err(_) => EnvironmentSource::CubemapFaces {
+                path: faces,
+                orientation: Default::default(),
+            },
+        }
+    }
+    /// Keeps the current sky when the other doesn't load.
+    pub fn toggle_skybox(&mut self) {
+        let device = self.model_manager.device.clone();
+        let queue = self.model_manager.queue.clone();
+        match WorldProjection::from_source(
+            &queue,
+            &device,
+            &self.main.surface_config,
+            &mut self.model_manager.materials.textures,
+            &self.skybox_alternate,
+            Some(self.depth_stencil.clone()),
+        ) {
+            Ok(projection) => {
+                log_info!("Skybox: {:?}", projection.source);
+                if let Some(current) = self.world.projection() {
+                    self.skybox_alternate = current.source.clone();
+                }
+                self.world.set_projection(projection);
+            }
+            Err(e) => log_error!("Skybox: {}", e),
+        }
     }
     pub fn next_projection(&mut self) {
-        self.projection = if self.projection == Projection::FirstPerson {
-            Projection::ThirdPerson
-        } else {
-            Projection::FirstPerson
+        self.projection = self.projection.next();
+    }
+    pub fn toggle_camera_projection(&mut self) {
+        self.main.camera.toggle_projection_mode();
+        log_info!(
+            "Camera projection: {:?}",
+            self.main.camera.projection_mode()
+        );
+    }
+    pub fn set_camera_projection(&mut self, projection: CameraProjection) {
+        self.main.camera.set_projection_mode(projection);
+        log_info!("Camera projection: {:?}", projection);
+    }
+    pub fn shader_changed(&mut self, path: &Path) {
+        if let Some(name) = Shader::name_of(path) {
+            self.changed_shaders.insert(name, Instant::now());
+        }
+    }
+    pub fn textures_loaded(&mut self) {
+        for material in self
+            .model_manager
+            .apply_loaded_textures(&self.main.surface_config)
+        {
+            self.world.terrain.rebind_material(&material);
+        }
+    }
+    // Waits out an editor's truncate-then-write save so the finished file compiles once.
+    fn reload_shaders(&mut self) {
+        let settled: Vec<String> = self
+            .changed_shaders
+            .iter()
+            .filter(|(_, written)| written.elapsed() >= SHADER_SETTLE)
+            .map(|(name, _)| name.clone())
+            .collect();
+        for name in settled {
+            self.changed_shaders.remove(&name);
+            for material in self.model_manager.reload_shader(&name) {
+                self.world.terrain.rebind_material(&material);
+            }
+            self.render3d.instances.objects.clear_pipelines();
+            self.render3d.instances.draws.clear_pipelines();
+            self.render3d.instances.prepass.clear_pipelines();
+            self.render3d.instances.picker.clear_pipelines();
+            self.model_manager.animations.clear_pipelines();
+            self.render3d.particles.clear_pipelines();
+            self.render3d.water.clear_pipelines();
+        }
+    }
+    pub fn toggle_reverse_z(&mut self) {
+        let policy = match RenderSettings::depth_policy() {
+            DepthPolicy::Standard => DepthPolicy::ReverseZ,
+            DepthPolicy::ReverseZ => DepthPolicy::Standard,
+        };
+        RenderSettings::set_depth_policy(policy);
+        let device = self.model_manager.device.clone();
+        let queue = self.model_manager.queue.clone();
+
+        for material in self.model_manager.apply_depth_policy() {
+            self.world.terrain.rebind_material(&material);
+        }
+        for viewport in self.viewports_mut() {
+            viewport.rendertxt.apply_depth_policy(&device, &queue);
+        }
+        self.rebuild_scene_target();
+        log_info!("Depth policy: {:?}", policy);
+    }
+    /// Falls back to 1 when the adapter can't render the scene formats with the count.
+    pub fn cycle_msaa(&mut self) {
+        self.msaa_request = SAMPLE_COUNTS
+            .iter()
+            .copied()
+            .find(|count| *count > self.msaa_request)
+            .unwrap_or(1);
+        let device = self.model_manager.device.clone();
+        let queue = self.model_manager.queue.clone();
+        let adapter = match GPU::with_read_recovered(|gpu| gpu.adapter().clone()) {
+            Ok(adapter) => adapter,
+            Err(e) => {
+                log_error!("MSAA switch: {}", e);
+                return;
+            }
         };
+        let formats = [
+            self.main.surface_config.format,
+            RenderSettings::depth_policy().format(),
+        ];
+        let count =
+            RenderSettings::supported_sample_count(&adapter, &device, &formats, self.msaa_request);
+        if count != RenderSettings::sample_count() {
+            RenderSettings::set_sample_count(count);
+            for material in self.model_manager.apply_sample_count() {
+                self.world.terrain.rebind_material(&material);
+            }
+            for viewport in self.viewports_mut() {
+                viewport.rendertxt.apply_sample_count(&device, &queue);
+            }
+            self.rebuild_scene_target();
+        }
+        log_info!("MSAA: {}x (requested {}x)", count, self.msaa_request);
+    }
+    pub fn cycle_present_mode(&mut self) {
+        let position = PRESENT_MODES
+            .iter()
+            .position(|mode| *mode == self.present_request);
+        self.present_request = match position {
+            Some(i) => PRESENT_MODES[(i + 1) % PRESENT_MODES.len()],
+            None => PRESENT_MODES[1],
+        };
+        self.set_present_mode(self.present_request);
+        self.settings.present_mode = self.present_request;
+        if let Err(e) = self.settings.save() {
+            log_error!("Saving the settings: {}", e);
+        }
+    }
+    pub fn set_present_mode(&mut self, mode: wgpu::PresentMode) {
+        let adapter = match GPU::with_read_recovered(|gpu| gpu.adapter().clone()) {
+            Ok(adapter) => adapter,
+            Err(e) => {
+                log_error!("Present mode switch: {}", e);
+                return;
+            }
+        };
+        let device = self.model_manager.device.clone();
+        for viewport in self.viewports_mut() {
+            let applied = viewport.surface.set_present_mode(
+                &adapter,
+                &device,
+                &mut viewport.surface_config,
+                mode,
+            );
+            log_info!("Present mode: {:?} (requested {:?})", applied, mode);
+        }
+    }
+    fn rebuild_scene_target(&mut self) {
+        let policy = RenderSettings::depth_policy();
+        let device = self.model_manager.device.clone();
+        let queue = self.model_manager.queue.clone();
+
+        self.render3d.instances.objects.clear_pipelines();
+        self.render3d.instances.draws.clear_pipelines();
+        self.render3d.instances.prepass.clear_pipelines();
+        self.render3d.instances.picker.clear_pipelines();
+        self.model_manager.animations.clear_pipelines();
+        self.render3d.instances.impostors.clear_pipeline();
+        self.render3d.instances.billboards.clear_pipeline();
+        self.render3d.particles.clear_pipelines();
+        self.render3d.water.clear_pipelines();
+        for viewport in self.viewports_mut() {
+            let config = &viewport.surface_config;
+            let size = FrameBufferSize::from(config).scaled(viewport.render_targets.scale());
+            viewport.render_targets.insert(
+                FrameBuffer::new_with_depth(
+                    &device,
+                    size,
+                    config.format,
+                    policy.format(),
+                    RenderSettings::sample_count(),
+                    "scene buffer",
+                ),
+                RenderTargetKind::Scene,
+            );
+        }
+        if let Some(source) = self.world.projection().map(|p| p.source.clone()) {
+            match WorldProjection::from_source(
+                &queue,
+                &device,
+                &self.main.surface_config,
+                &mut self.model_manager.materials.textures,
+                &source,
+                Some(self.depth_stencil.clone()),
+            ) {
+                Ok(projection) => self.world.set_projection(projection),
+                Err(e) => log_error!("Environment rebuild: {}", e),
+            }
+        }
+        match DebugMode::new(
+            &device,
+            &mut self.model_manager.materials.shaders,
+            &mut self.model_manager.materials.pipelines,
+            &self.main.camera,
+            &self.light,
+            &self.main.surface_config,
+        ) {
+            Ok(debug_mode) => self.debug_mode = debug_mode,
+            Err(e) => log_error!("Debug pipeline rebuild: {}", e),
+        }
+        self.depth_stencil = RenderSettings::depth_state(DepthVariant::Opaque);
+        self.layout_menu();
     }
     pub fn next_debug_mode(&mut self) {
         self.debug_mode
-            .next_mode(&self.model_manager.device, &self.camera, &self.light);
+            .next_mode(&self.model_manager.device, &self.main.camera, &self.light);
+        self.render3d
+            .instances
+            .set_object_path(self.debug_mode.mode() == 0);
         log_debug!("Debug mode: {:?}", self.debug_mode.mode());
     }
-    pub fn resize(&mut self, new_size: &PhysicalSize<u32>) {
-        self.camera
-            .resize(new_size.width as f32, new_size.height as f32);
-        self.surface.resize(
-            &self.model_manager.device,
-            &mut self.surface_config,
-            *new_size,
-        );
-        self.rendertxt.resize(&self.model_manager.queue, *new_size);
-        self.render_targets
-            .resize(&self.model_manager.device, *new_size);
+    /// Applied by [`Rupy::apply_resizes`], once a frame.
+    pub fn request_resize(&mut self, new_size: &PhysicalSize<u32>) {
+        self.main.request_resize(*new_size);
+    }
+    pub fn apply_resizes(&mut self) {
+        let (device, queue) = (&self.model_manager.device, &self.model_manager.queue);
+        for viewport in self.viewports.values_mut() {
+            viewport.apply_resize(device, queue);
+        }
+        if self.main.apply_resize(device, queue) {
+            self.layout_menu();
+        }
+    }
+    pub fn set_scale_factor(&mut self, scale_factor: f64) {
+        let scale_factor = self.main.text_scale_factor(scale_factor);
+        self.main.rendertxt.set_scale_factor(scale_factor);
+        self.layout_menu();
+        self.main.reshape();
     }
 
+    // Each viewport presents its own surface, so one that can't draw doesn't hold up the rest.
     pub fn render(&mut self) {
-        match self.surface.texture() {
-            Ok(frame) => {
-                let surface_view = frame.texture.create_view(&Default::default());
-
-                // === 1. Render scene to scene framebuffer ===
-                let mut encoder = self.model_manager.device.create_command_encoder(
-                    &wgpu::CommandEncoderDescriptor {
-                        label: Some("Scene Encoder"),
-                    },
-                );
+        engine::advance_gpu_frame();
+        self.render_main();
+        let ids: Vec<WindowId> = self.viewports.keys().copied().collect();
+        for id in ids {
+            self.render_viewport(id);
+        }
+    }
+    fn render_main(&mut self) {
+        let device = self.model_manager.device.clone();
+        let queue = self.model_manager.queue.clone();
+        let Some(frame) = self.main.acquire(&device, &queue) else {
+            return;
+        };
+        let surface_view = frame.texture.create_view(&Default::default());
 
-                if let Some(frame) = self.render_targets.get(&RenderTargetKind::Scene) {
-                    let projection = self.world.projection();
-                    projection.compute_projection(
-                        &self.model_manager.queue,
-                        &self.model_manager.device,
-                        Some("Equirect Projection Pass"),
-                    );
-
-                    let mut rpass: wgpu::RenderPass<'_> =
-                        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                            label: Some("Scene Pass"),
-                            color_attachments: &[Some(frame.color_attachment())],
-                            depth_stencil_attachment: frame.depth_attachment(),
-                            timestamp_writes: None,
-                            occlusion_query_set: None,
-                        });
-
-                    self.render3d.render(
-                        &mut self.model_manager,
-                        &mut rpass,
-                        &self.world,
-                        &self.uniform_bind_group,
-                        &self.debug_mode,
-                    );
-
-                    self.rendertxt.render(
-                        &mut self.model_manager,
-                        &mut rpass,
-                        &self.world,
-                        &self.uniform_bind_group,
-                        &self.debug_mode,
-                    );
-                }
+        // === 0. Prepare: recording below only reads ===
+        self.render3d.exposure_mut().prepare();
+        self.render3d
+            .prepare_debug(&mut self.debug_mode, &self.model_manager, &self.world);
+        let size = self.main.render_size();
+        self.post_process
+            .prepare(&device, &queue, &mut self.main.render_targets, size);
+        self.render3d
+            .instances
+            .picker
+            .prepare_target(&device, &mut self.main.render_targets, size);
+        self.render3d
+            .water
+            .prepare_target(&device, &mut self.main.render_targets);
+        let underwater = self.world.terrain.medium_at(*self.main.camera.eye()) == Medium::Water;
+        #[cfg(feature = "devtools")]
+        self.egui.prepare(
+            &device,
+            &queue,
+            [
+                self.main.surface_config.width,
+                self.main.surface_config.height,
+            ],
+        );
 
-                // === 2. Postprocess Scene -> HDR ===
-                if let Some(scene_fb) = self.render_targets.get(&RenderTargetKind::Scene) {
-                    if let Some(hdr_fb) = self.render_targets.get(&RenderTargetKind::Hdr) {
-                        self.render3d.hdr(
-                            &mut encoder,
-                            &self.model_manager,
-                            &scene_fb.color(),
-                            hdr_fb,
-                        );
-                    }
-                }
+        let stages = FrameStages {
+            render3d: &self.render3d,
+            rendertxt: &self.main.rendertxt,
+            models: &self.model_manager,
+            world: &self.world,
+            uniform_bind_group: &self.main.uniform_bind_group,
+            debug_mode: &self.debug_mode,
+            render_targets: &self.main.render_targets,
+            post_process: &self.post_process,
+            surface_view: &surface_view,
+        };
+        let exposure = RenderTargetKind::Custom("exposure");
+        let post = RenderTargetKind::Custom("post process");
+        let surface = RenderTargetKind::Custom("surface");
+        let particles = RenderTargetKind::Custom("particles");
+        let environment = RenderTargetKind::Custom("environment");
+        let scene = RenderTargetKind::Scene;
+        let hdr = RenderTargetKind::Hdr;
+        let entity_ids = RenderTargetKind::EntityId;
+        let mut graph = PassGraph::new();
+        graph.profiled(&self.profiler);
+
+        // === 1. Render scene to scene framebuffer ===
+        if stages.prepass() {
+            graph.try_pass("Depth Prepass", &[], &[scene], false, move |encoder| {
+                stages.depth_prepass(encoder)
+            });
+        }
+        graph.try_pass(
+            "Equirect Projection",
+            &[],
+            &[environment],
+            false,
+            move |encoder| stages.equirect(encoder),
+        );
+        graph.try_pass("Particles", &[], &[particles], false, move |encoder| {
+            stages.particles(encoder)
+        });
+        graph.try_pass(
+            "Scene Pass",
+            &[particles, environment],
+            &[scene],
+            true,
+            move |encoder| stages.scene_pass(encoder),
+        );
+        if stages.water() {
+            graph.try_pass("Water Pass", &[scene], &[scene], false, move |encoder| {
+                stages.water_pass(encoder, underwater)
+            });
+        }
+        if stages.picking() {
+            graph.try_pass(
+                "Entity Id Pass",
+                &[],
+                &[entity_ids],
+                false,
+                move |encoder| stages.entity_ids(encoder),
+            );
+        }
 
-                // === 3. Final HDR -> swapchain ===
-                if let Some(hdr_fb) = self.render_targets.get(&RenderTargetKind::Hdr) {
-                    self.render3d.final_blit_to_surface(
-                        &self.model_manager.device,
-                        &mut encoder,
-                        hdr_fb.color(),
-                        &surface_view,
-                    );
+        // === 2. Postprocess Scene -> HDR ===
+        graph.try_pass(
+            "Auto Exposure",
+            &[scene],
+            &[exposure],
+            false,
+            move |encoder| stages.auto_exposure(encoder),
+        );
+        graph.try_pass(
+            "HDR Pass",
+            &[scene, exposure],
+            &[hdr],
+            false,
+            move |encoder| stages.hdr_pass(encoder),
+        );
+
+        // === 3. Post-process chain over HDR ===
+        if self.post_process.active() {
+            graph.try_pass("Post Process", &[hdr], &[post], false, move |encoder| {
+                stages.post_process(encoder)
+            });
+        }
+
+        // === 4. Final output -> swapchain ===
+        graph.try_pass(
+            "Final Blit",
+            &[hdr, post],
+            &[surface],
+            false,
+            move |encoder| stages.final_blit(encoder),
+        );
+
+        // === 5. Dev UI on top, at native resolution ===
+        #[cfg(feature = "devtools")]
+        {
+            let ui = self.egui.draw();
+            let surface_view = &surface_view;
+            graph.pass("Egui Pass", &[], &[surface], false, move |encoder| {
+                ui.record(encoder, surface_view);
+            });
+        }
+        graph.submit(&device, &queue);
+        // What the final blit showed, for screenshots.
+        let output = self
+            .post_process
+            .output(&self.main.render_targets)
+            .or(self.main.render_targets.get(&RenderTargetKind::Hdr));
+        self.profiler
+            .end_frame(&device, &queue, &mut self.model_manager.readback);
+        self.render3d.exposure_mut().after_submit();
+        if std::mem::take(&mut self.screenshot) {
+            if let Some(output_fb) = output {
+                request_screenshot(&mut self.model_manager.readback, output_fb);
+            }
+        }
+        self.render3d
+            .instances
+            .picker
+            .read_back(&mut self.model_manager.readback, &self.main.render_targets);
+        if let Some(projection) = self.world.projection() {
+            projection
+                .reflection
+                .read_back_average(&mut self.model_manager.readback);
+        }
+        self.model_manager.readback.advance(&device, &queue);
+        frame.present();
+    }
+    // The shared instance buffers are culled for this camera; the next update culls them for
+    // the main camera again.
+    fn render_viewport(&mut self, id: WindowId) {
+        let device = self.model_manager.device.clone();
+        let queue = self.model_manager.queue.clone();
+        let Some(viewport) = self.viewports.get_mut(&id) else {
+            return;
+        };
+        let Some(frame) = viewport.acquire(&device, &queue) else {
+            return;
+        };
+        // The draw stats overlay reports the main view.
+        let lod_stats = self.model_manager.lod_stats.clone();
+        let instances = &mut self.render3d.instances;
+        std::mem::swap(&mut instances.lods, &mut viewport.lods);
+        instances.update(&self.world, &viewport.camera, &mut self.model_manager);
+        std::mem::swap(&mut instances.lods, &mut viewport.lods);
+        instances.upload(&queue, &device, &self.model_manager.materials.storage_buffer);
+        self.model_manager.lod_stats = lod_stats;
+        self.render3d
+            .water
+            .prepare_target(&device, &mut viewport.render_targets);
+
+        let surface_view = frame.texture.create_view(&Default::default());
+        let stages = FrameStages {
+            render3d: &self.render3d,
+            rendertxt: &viewport.rendertxt,
+            models: &self.model_manager,
+            world: &self.world,
+            uniform_bind_group: &viewport.uniform_bind_group,
+            debug_mode: &self.debug_mode,
+            render_targets: &viewport.render_targets,
+            post_process: &self.post_process,
+            surface_view: &surface_view,
+        };
+        let surface = RenderTargetKind::Custom("surface");
+        let scene = RenderTargetKind::Scene;
+        let hdr = RenderTargetKind::Hdr;
+        let mut graph = PassGraph::new();
+        if stages.prepass() {
+            graph.try_pass("Depth Prepass", &[], &[scene], false, move |encoder| {
+                stages.depth_prepass(encoder)
+            });
+        }
+        graph.try_pass("Scene Pass", &[], &[scene], true, move |encoder| {
+            stages.scene_pass(encoder)
+        });
+        if stages.water() {
+            graph.try_pass("Water Pass", &[scene], &[scene], false, move |encoder| {
+                stages.water_pass(encoder, false)
+            });
+        }
+        graph.try_pass("HDR Pass", &[scene], &[hdr], false, move |encoder| {
+            stages.hdr_pass(encoder)
+        });
+        graph.try_pass("Final Blit", &[hdr], &[surface], false, move |encoder| {
+            stages.final_blit(encoder)
+        });
+        graph.submit(&device, &queue);
+        frame.present();
+    }
+    fn text_regions(&mut self) -> Vec<TextRegion> {
+        let (width, height) = self.main.rendertxt.logical_size();
+        let margin = [5.0, 5.0];
+        let anchor = TextAnchor::new(ScreenCorner::TopLeft, margin);
+        let mut hud = TextStack::new(anchor);
+        let mut time = self.time.text_region([0.0; 2]);
+        time.text.push_str(&format!(
+            " Present: {:?}",
+            self.main.surface_config.present_mode
+        ));
+        let cull = self.world.terrain.cull_stats();
+        hud.push(time)
+            .push(self.main.camera.text_region([0.0; 2]))
+            .push(self.controls.text_region([0.0; 2]))
+            .push(TextRegion::new(
+                format!("Chunks: {} drawn, {} culled", cull.drawn, cull.culled),
+                [0.0; 2],
+                glyphon::Color::rgb(1, 1, 1),
+            ));
+        let device = &self.model_manager.device;
+        if let Some(exposure) = self.render3d.exposure_mut().readback(device) {
+            hud.push(TextRegion::new(
+                format!(
+                    "Exposure: {:.2} (avg luminance {:.3})",
+                    exposure.exposure, exposure.average_luminance
+                ),
+                [0.0; 2],
+                glyphon::Color::rgb(1, 1, 1),
+            ));
+        }
+        let (ambient, intensity) = self.light.ambient();
+        hud.push(TextRegion::new(
+            format!(
+                "Ambient: {:.2?} x {:.2} ({:?})",
+                ambient,
+                intensity,
+                self.light.ambient_source()
+            ),
+            [0.0; 2],
+            glyphon::Color::rgb(1, 1, 1),
+        ));
+        let mut timings = TextStack::new(TextAnchor::new(ScreenCorner::TopRight, margin));
+        timings.push(self.profiler.text_region([0.0; 2]));
+
+        let mut subtitles = TextStack::new(TextAnchor::new(ScreenCorner::BottomLeft, margin));
+        if let Some(subtitle) = self.sequence.as_ref().and_then(|s| s.subtitle()) {
+            subtitles.push(TextRegion::new(
+                subtitle.to_string(),
+                [0.0; 2],
+                glyphon::Color::rgb(255, 255, 255),
+            ));
+        }
+        let mut errors = TextStack::new(TextAnchor::new(
+            ScreenCorner::TopLeft,
+            [margin[0], height as f32 / 2.0],
+        ));
+        if let Some(error) = self.world.tick_error() {
+            errors.push(TextRegion::new(
+                format!(
+                    "World tick failed: {}. Press F5 to reload the scene.",
+                    error
+                ),
+                [0.0; 2],
+                glyphon::Color::rgb(255, 80, 80),
+            ));
+        }
+        #[cfg(feature = "hot-reload-game")]
+        if let Some(error) = self.game.last_error() {
+            errors.push(TextRegion::new(
+                format!(
+                    "Game module error: {}. Rebuild the game crate to reload it.",
+                    error
+                ),
+                [0.0; 2],
+                glyphon::Color::rgb(255, 80, 80),
+            ));
+        }
+
+        let mut regions = Vec::new();
+        if self.console.is_open() {
+            hud = self.console.text_stack(anchor);
+        }
+        for stack in [hud, timings, subtitles, errors] {
+            regions.extend(stack.layout(&mut self.main.rendertxt));
+        }
+        if let Some(menu) = &self.menu {
+            regions.extend(menu.regions(width, height));
+        }
+        regions
+    }
+
+    fn world_labels(&self) -> Vec<TextRegion> {
+        let i = self.bossman.0;
+        let (Some(Some(bounds)), Some(Some(transform))) =
+            (self.world.bounds.get(i), self.world.transforms.get(i))
+        else {
+            return Vec::new();
+        };
+        let bounds = bounds.transformed(&transform.model_matrix);
+        let top = Vec3::new(bounds.center().x, bounds.max.y, bounds.center().z);
+        let text = match &self.world.healths[i] {
+            Some(health) => format!("Bossman {:.0}/{:.0}", health.current, health.max),
+            None => "Bossman".to_string(),
+        };
+        vec![
+            TextRegion::world_anchored(text, top, glyphon::Color::rgb(255, 220, 120))
+                .with_pixel_offset([0.0, -4.0]),
+        ]
+    }
+
+    /// Only the pause menu pauses the world.
+    fn set_menu(&mut self, menu: Option<Menu>) {
+        self.main.window.set_cursor_visible(menu.is_some());
+        self.menu = menu;
+        self.layout_menu();
+    }
+    fn layout_menu(&mut self) {
+        if let Some(menu) = &mut self.menu {
+            let (width, height) = self.main.rendertxt.logical_size();
+            let current = self
+                .world
+                .scene()
+                .map(|s| s.name.as_str())
+                .unwrap_or_default();
+            menu.layout(width, height, self.main.rendertxt.line_height(), current);
+        }
+        self.main.reshape();
+    }
+    fn menu_kind(&self) -> Option<MenuKind> {
+        self.menu.as_ref().map(|m| m.kind)
+    }
+    pub fn menu_open(&self) -> bool {
+        self.menu.is_some()
+    }
+    pub fn close_menu(&mut self) {
+        self.set_menu(None);
+    }
+    pub fn toggle_scene_picker(&mut self) {
+        match self.menu_kind() {
+            Some(MenuKind::ScenePicker) => self.set_menu(None),
+            _ => self.set_menu(Some(Menu::scene_picker())),
+        }
+    }
+    pub fn scene_picker_open(&self) -> bool {
+        self.menu_kind() == Some(MenuKind::ScenePicker)
+    }
+    pub fn toggle_time_pause(&mut self) {
+        let paused = !self.time.paused();
+        self.time.set_paused(paused);
+        log_info!("Time {}", if paused { "paused" } else { "resumed" });
+        self.main.reshape();
+    }
+    pub fn step_time(&mut self) {
+        self.time.request_step();
+    }
+    pub fn set_time_scale(&mut self, scale: f32) {
+        self.time.set_scale(scale);
+        log_info!("Time scale: {:.2}x", self.time.scale());
+        self.main.reshape();
+    }
+    pub fn toggle_pause_menu(&mut self) {
+        match self.menu_kind() {
+            Some(MenuKind::Pause) => self.set_menu(None),
+            _ => self.set_menu(Some(Menu::pause())),
+        }
+    }
+    pub fn pick_scene(&mut self, index: usize) {
+        let Some(name) = self
+            .menu
+            .as_ref()
+            .and_then(|menu| menu.scenes().get(index).cloned())
+        else {
+            return;
+        };
+        self.dispatch_menu(MenuAction::LoadScene(name), None);
+    }
+    // Releases still pass, so keys held when the menu opened don't get stuck.
+    pub fn menu_consumes(&self, event: &WindowEvent) -> bool {
+        self.menu_open()
+            && match event {
+                WindowEvent::KeyboardInput { event, .. } => event.state.is_pressed(),
+                WindowEvent::MouseInput { state, .. } => *state == ElementState::Pressed,
+                WindowEvent::CursorMoved { .. } | WindowEvent::MouseWheel { .. } => true,
+                _ => false,
+            }
+    }
+    pub fn menu_navigate(&mut self, dir: NavDirection, device: UiDevice) {
+        if let Some(menu) = &mut self.menu {
+            if menu.focus.navigate(dir, device) {
+                self.main.reshape();
+            }
+        }
+    }
+    pub fn menu_cursor_moved(&mut self, position: PhysicalPosition<f64>) {
+        self.cursor = position;
+        // Cursor positions are in window pixels, the text layer's in render target pixels.
+        let scale = self.main.rendertxt.scale() / self.main.render_targets.scale();
+        if let Some(menu) = &mut self.menu {
+            let pos = Vec2::new(position.x as f32, position.y as f32) / scale;
+            if menu.focus.on_cursor_moved(pos) {
+                self.main.reshape();
+            }
+        }
+    }
+    pub fn menu_activate(&mut self, el: &ActiveEventLoop) {
+        if let Some(action) = self.menu.as_ref().and_then(|m| m.focus.activate()) {
+            self.dispatch_menu(action, Some(el));
+        }
+    }
+    pub fn click(&mut self, el: &ActiveEventLoop) {
+        if self.menu_open() {
+            self.menu_click(el);
+        } else if !self.break_aimed_block() {
+            self.pick_under_cursor();
+        }
+    }
+    pub fn break_aimed_block(&mut self) -> bool {
+        let Some((cell, _)) = self.main.camera.aimed_block(&self.world.terrain) else {
+            return false;
+        };
+        self.world.terrain.break_block(cell)
+    }
+    pub fn place_aimed_block(&mut self) {
+        let Some((cell, normal)) = self.main.camera.aimed_block(&self.world.terrain) else {
+            return;
+        };
+        let target = cell + normal;
+        let empty = self
+            .world
+            .terrain
+            .block_at(target)
+            .is_some_and(|(block, _)| block == AIR);
+        if normal != IVec3::ZERO && empty {
+            self.world.terrain.set_block(target, PLACED_BLOCK);
+        }
+    }
+    pub fn pick_under_cursor(&mut self) {
+        let scale = self.main.render_targets.scale() as f64;
+        let (x, y) = (self.cursor.x * scale, self.cursor.y * scale);
+        self.render3d
+            .instances
+            .picker
+            .pick_at(x.max(0.0) as u32, y.max(0.0) as u32);
+    }
+    pub fn select_picked(&mut self, entity: Entity) {
+        log_info!("Picked entity {}", entity.0);
+        self.picked = Some(entity);
+        self.world.select(Some(entity));
+        #[cfg(feature = "devtools")]
+        engine::devtools::select_entity(self.egui.context(), Some(entity));
+    }
+    pub fn menu_click(&mut self, el: &ActiveEventLoop) {
+        let action = self.menu.as_mut().and_then(|m| {
+            let cursor = m.focus.cursor()?;
+            m.focus.click(cursor)
+        });
+        if let Some(action) = action {
+            self.dispatch_menu(action, Some(el));
+        }
+    }
+    fn dispatch_menu(&mut self, action: MenuAction, el: Option<&ActiveEventLoop>) {
+        log_debug!("Menu: {:?}", action);
+        match action {
+            MenuAction::LoadScene(name) => {
+                self.switch_scene(&name);
+                self.set_menu(None);
+            }
+            MenuAction::Resume => self.set_menu(None),
+            MenuAction::CycleDepth => self.toggle_reverse_z(),
+            MenuAction::Quit => {
+                if let Some(el) = el {
+                    self.shutdown(el);
                 }
-                self.model_manager.queue.submit(Some(encoder.finish()));
-                frame.present();
             }
+        }
+    }
+    pub fn switch_scene(&mut self, name: &str) {
+        self.stop_sequence();
+        let (loaded, sequence) = match self.world.load_scene_by_name(
+            &mut self.model_manager,
+            name,
+            &self.main.surface_config,
+            &self.depth_stencil,
+        ) {
+            Ok(scene) => (scene.entity("bossman"), scene.sequence.clone()),
             Err(e) => {
-                log_error!("SurfaceError: {}", e);
-                if let wgpu::SurfaceError::Outdated = e {
-                    self.resize(&self.window.inner_size());
-                }
+                log_error!("Scene '{}': {}", name, e);
+                return;
             }
         };
+        self.bossman = loaded.unwrap_or_else(|| self.world.spawn());
+        if let Some(target) = self.main.camera.entity() {
+            self.world.insert_nav_agent(
+                self.bossman,
+                NavAgent::new(NavTarget::Entity(target), self.controls.speed() / 2.0),
+            );
+        }
+        self.model_manager
+            .materials
+            .build_storage(&self.model_manager.device);
+        if let Some(sequence) = sequence {
+            self.play_sequence(&sequence);
+        }
     }
-    fn text_regions(&mut self) -> Vec<TextRegion> {
-        let corner =
-            ScreenCorner::TopLeft.pos(self.surface_config.width, self.surface_config.height, 5.0);
-        let camera = self.camera.text_region(corner);
-        let controller = self.controls.text_region(corner);
-        let time = self.time.text_region(corner);
-        let regions = vec![time, camera, controller];
-        regions
+
+    pub fn reload_scene(&mut self) {
+        let name = match self.world.scene() {
+            Some(scene) => scene.name.clone(),
+            None => std::env::var("RUPY_SCENE").unwrap_or_else(|_| "debug".to_string()),
+        };
+        self.switch_scene(&name);
+    }
+
+    pub fn play_sequence(&mut self, name: &str) {
+        self.stop_sequence();
+        let mut player = match SequencePlayer::load(name) {
+            Ok(player) => player,
+            Err(e) => {
+                log_error!("Sequence '{}': {}", name, e);
+                return;
+            }
+        };
+        player.play(&mut self.stage());
+        self.sequence = Some(player);
+    }
+    // The light orbit goes back to the clock, not to the angle it had at the start.
+    pub fn stop_sequence(&mut self) {
+        if let Some(mut player) = self.sequence.take() {
+            player.stop(&mut self.stage());
+        }
+        self.sun_angle = None;
+    }
+    fn stage(&mut self) -> Stage<'_> {
+        Stage {
+            world: &mut self.world,
+            camera: &mut self.main.camera,
+            render3d: &mut self.render3d,
+            sun_angle: &mut self.sun_angle,
+            elapsed: self.time.scaled_elapsed,
+        }
     }
 
     pub fn upload(&mut self) {
         let queue = &self.model_manager.queue;
         let device = &self.model_manager.device;
         self.light.upload(queue, device);
-        self.camera.upload(queue, device);
-        self.render3d.instances.upload(queue, device);
+        self.main.camera.upload(queue, device);
+        for viewport in self.viewports.values_mut() {
+            viewport.camera.upload(queue, device);
+        }
+        let materials = &mut self.model_manager.materials;
+        if materials.storage_rebuild {
+            materials.build_storage(device);
+        }
+        materials.flush(queue);
+        // After the material storage, which the instance storage bind group shares.
+        self.render3d.instances.upload(queue, device, &materials.storage_buffer);
+        let dt = self.time.delta_time as f32;
+        self.render3d.exposure_mut().update(queue, device, dt);
     }
 
     pub fn update(&mut self) {
         self.time.update();
+        // Real time for the camera and other view-only work, scaled time for the world.
         let dt = self.time.delta_time as f32;
+        let world_dt = self.time.scaled_delta() as f32;
 
-        self.camera.update(
+        self.main.camera.update(
             &mut self.world,
             &mut self.controls,
             &self.projection,
             &self.bossman,
+            dt,
         );
 
-        if let Some(entity) = self.camera.entity() {
+        if let Some(entity) = self.main.camera.entity() {
             if let (Some(cam_pos), Some(boss_pos)) = (
                 self.world.physics.positions[entity.0],
                 self.world.physics.positions[self.bossman.0],
             ) {
                 let direction = cam_pos.0 - boss_pos.0;
                 let mut direction_normalized = direction.normalize_or_zero();
-                let speed = self.controls.speed() - (self.controls.speed() / 2.0);
-                let velocity = direction_normalized * speed;
                 direction_normalized.y = 0.0;
                 let rot_to_camera = glam::Quat::from_rotation_arc(Vec3::Z, direction_normalized);
                 self.world
                     .insert_rotation(self.bossman, Rotation::from(rot_to_camera));
-                self.world.insert_velocity(self.bossman, Velocity(velocity));
             }
         }
 
-        self.world.terrain.update_streaming(*self.camera.eye(), 4);
+        // Before anything reads positions this frame, so camera and world shift together.
+        if let Some(rebase) = self.world.rebase_around(*self.main.camera.eye()) {
+            self.main.camera.rebase(rebase.offset());
+            self.render3d.particles.rebase(rebase.offset());
+            #[cfg(feature = "audio")]
+            self.audio.rebase(rebase.offset());
+        }
+        self.world
+            .terrain
+            .update_streaming(*self.main.camera.eye(), self.settings.view_distance);
+        self.world.terrain.update_water(world_dt);
+
+        let reloaded = self
+            .model_manager
+            .reload_materials(&self.main.surface_config);
+        for material in &reloaded {
+            self.world.terrain.rebind_material(material);
+        }
+        let rebuilt = self.model_manager.apply_shader_constants();
+        for material in &rebuilt {
+            self.world.terrain.rebind_material(material);
+        }
+        if !rebuilt.is_empty() {
+            self.render3d.instances.objects.clear_pipelines();
+            self.render3d.instances.draws.clear_pipelines();
+            self.model_manager.animations.clear_pipelines();
+        }
+        if !reloaded.is_empty() || !rebuilt.is_empty() {
+            self.render3d.instances.prepass.clear_pipelines();
+            self.render3d.instances.picker.clear_pipelines();
+        }
+        self.reload_shaders();
 
-        self.light.orbit(self.time.elapsed * 0.1);
+        if let Some(mut player) = self.sequence.take() {
+            player.update(&mut self.stage(), dt);
+            if player.is_active() {
+                self.sequence = Some(player);
+            } else {
+                self.sun_angle = None;
+            }
+        }
+        let sun_angle = self
+            .sun_angle
+            .map_or(self.time.scaled_elapsed * 0.1, f64::from);
+        self.light.orbit(sun_angle);
+        if let Some(projection) = self.world.projection() {
+            self.light.follow_environment(&projection.reflection);
+        }
         self.render3d
             .instances
-            .update(&self.world, &self.camera, &mut self.model_manager);
+            .update(&self.world, &self.main.camera, &mut self.model_manager);
 
-        self.world.update(
-            &self.model_manager.queue,
+        let paused = self.main.camera.orbit().is_some() && OrbitSettings::pause_simulation();
+        let menu_paused = self.menu_kind() == Some(MenuKind::Pause);
+        self.world.set_paused(menu_paused || self.time.paused());
+        if self.time.take_step() {
+            self.world.step();
+        }
+        if !paused {
+            self.world.update(
+                &self.model_manager.queue,
+                &self.model_manager.device,
+                &self.main.camera,
+                world_dt,
+            );
+        }
+        self.render3d.particles.update(
+            &self.world,
+            &mut self.model_manager,
+            if paused { 0.0 } else { world_dt },
+        );
+        self.render3d.water.update(
+            &self.world,
+            &mut self.model_manager,
+            self.time.elapsed as f32,
+        );
+        let events: Vec<WorldEvent> = self.world.drain_events().collect();
+        #[cfg(feature = "scripting")]
+        if !paused && self.world.is_healthy() {
+            let auto_load =
+                engine::AutoLoad::new(&self.main.surface_config, Some(self.depth_stencil.clone()));
+            self.scripts.update(
+                &mut self.world,
+                &mut self.model_manager,
+                &events,
+                Some(&auto_load),
+                world_dt,
+            );
+        }
+        #[cfg(feature = "hot-reload-game")]
+        if !paused && self.world.is_healthy() {
+            self.game.update(&mut self.world, &events, world_dt);
+        }
+        for event in events {
+            log_debug!("World event: {:?}", event);
+            #[cfg(feature = "audio")]
+            self.audio.handle_event(&event);
+            if let WorldEvent::Landed(entity, speed) = event {
+                if Some(entity) == self.main.camera.entity() {
+                    self.main
+                        .camera
+                        .add_trauma(((speed - HARD_LANDING_SPEED) / 20.0).clamp(0.1, 0.6));
+                }
+            }
+        }
+        #[cfg(feature = "audio")]
+        self.audio.update(&self.world, &self.main.camera);
+        #[cfg(feature = "devtools")]
+        {
+            let view_proj = self.main.camera.view_projection_matrix().0;
+            engine::devtools::set_view_projection(self.egui.context(), view_proj);
+            self.egui
+                .run(&self.main.window, &mut self.world, &mut self.model_manager);
+            // The entity picked in the dev UI draws highlighted while the UI is up.
+            let selected = engine::devtools::selected_entity(self.egui.context());
+            let selected = selected.filter(|_| self.egui.visible()).or(self.picked);
+            self.world.select(selected);
+            for request in engine::devtools::take_console_requests(self.egui.context()) {
+                match request {
+                    engine::devtools::ConsoleRequest::PlaySequence(name) => {
+                        self.play_sequence(&name)
+                    }
+                    engine::devtools::ConsoleRequest::StopSequence => self.stop_sequence(),
+                    engine::devtools::ConsoleRequest::Orbit(target) => {
+                        self.main.camera.enter_orbit(target, crate::loading::ORBIT_DISTANCE)
+                    }
+                    engine::devtools::ConsoleRequest::ExitOrbit => {
+                        self.main.camera.exit_orbit();
+                    }
+                }
+            }
+        }
+
+        for report in engine::take_crash_reports() {
+            log_error!("GPU crash report: {}", report.display());
+        }
+
+        let focus = *self.main.camera.eye();
+        for viewport in self.viewports.values_mut() {
+            viewport.follow(focus, dt);
+            if viewport.shape_due() {
+                let (width, height) = viewport.rendertxt.logical_size();
+                let label = TextRegion::new(
+                    "Map - scroll to zoom".to_string(),
+                    ScreenCorner::TopLeft.pos(width, height, 5.0),
+                    glyphon::Color::rgb(255, 255, 255),
+                );
+                viewport.rendertxt.prepare_regions(
+                    &self.model_manager.device,
+                    &self.model_manager.queue,
+                    &[label],
+                    &viewport.surface_config,
+                    None,
+                );
+            }
+        }
+        if self.main.shape_due() {
+            self.hud = self.text_regions();
+        }
+        // Labels follow the camera, so the text is prepared every frame around the HUD.
+        let mut regions = self.hud.clone();
+        regions.extend(self.world_labels());
+        self.main.rendertxt.prepare_regions(
             &self.model_manager.device,
-            &self.camera,
-            dt,
+            &self.model_manager.queue,
+            &regions,
+            &self.main.surface_config,
+            Some(&self.main.camera.text_projection()),
         );
+    }
+}
 
-        if self.last_shape_time.elapsed().as_millis() > 1000 {
-            self.last_shape_time = std::time::Instant::now();
-            let regions = self.text_regions();
-            self.rendertxt.prepare_regions(
-                &self.model_manager.device,
-                &self.model_manager.queue,
-                &regions,
-                &self.surface_config,
-            );
+/// Properties: `exposure_compensation` (stops) and `sun_angle` (radians).
+struct Stage<'a> {
+    world: &'a mut World,
+    camera: &'a mut Camera,
+    render3d: &'a mut Renderer3d,
+    sun_angle: &'a mut Option<f32>,
+    elapsed: f64,
+}
+
+impl SequenceHost for Stage<'_> {
+    fn float(&self, property: &str) -> Option<f32> {
+        match property {
+            "exposure_compensation" => Some(self.render3d.exposure().settings.compensation),
+            "sun_angle" => Some(self.sun_angle.unwrap_or((self.elapsed * 0.1) as f32)),
+            _ => None,
         }
     }
+    fn set_float(&mut self, property: &str, value: f32) {
+        match property {
+            "exposure_compensation" => self.render3d.exposure_mut().settings.compensation = value,
+            "sun_angle" => *self.sun_angle = Some(value),
+            _ => {}
+        }
+    }
+    fn entity(&self, name: &str) -> Option<Entity> {
+        self.world.scene()?.entity(name)
+    }
+    fn transform(&self, entity: Entity) -> Option<(Vec3, Quat)> {
+        let position = self
+            .world
+            .physics
+            .positions
+            .get(entity.0)
+            .copied()
+            .flatten()?;
+        let rotation = self.world.rotations.get(entity.0).copied().flatten();
+        Some((position.0, rotation.map_or(Quat::IDENTITY, |r| r.quat())))
+    }
+    fn set_transform(&mut self, entity: Entity, position: Vec3, rotation: Quat) {
+        self.world.insert_position(entity, Position(position));
+        self.world.insert_rotation(entity, Rotation::from(rotation));
+    }
+    fn set_camera(&mut self, view: Option<(Vec3, Vec3)>) {
+        self.camera.set_override(view);
+    }
+    fn fire(&mut self, event: &SequenceEvent) {
+        match event {
+            SequenceEvent::Publish(name) => {
+                self.world.push_event(WorldEvent::Sequence(name.clone()))
+            }
+            // No audio backend yet; the cue is only logged.
+            SequenceEvent::PlaySound(sound) => log_info!("Sequence sound cue: {}", sound),
+            SequenceEvent::Subtitle { .. } => {}
+        }
+    }
+}
+
+fn request_screenshot(readback: &mut engine::ReadbackService, fb: &FrameBuffer) {
+    let color = fb.color();
+    let size = color.texture.size();
+    let format = color.texture.format();
+    let ticket = readback.request_texture(color, 0, wgpu::Origin3d::ZERO, size);
+    ticket.on_ready(move |result| {
+        std::thread::spawn(move || {
+            let saved = result.and_then(|pixels| {
+                let path = engine::screenshot_path()?;
+                engine::save_png(&path, pixels, size.width, size.height, format)?;
+                Ok(path)
+            });
+            match saved {
+                Ok(path) => log_info!("Screenshot saved to {}", path.display()),
+                Err(e) => log_error!("Screenshot failed: {}", e),
+            }
+        });
+    });
 }
@@ -0,0 +1,162 @@
+//! Command line tools. `rupy-cli assets import` brings every derivative under
+//! `assets/.import` up to date, the same work startup does incrementally.
+//! `rupy-cli assets bake-impostors` renders the impostor atlas of every model whose
+//! sidecar asks for one. `rupy-cli dev` runs the app with the game crate hot-reloaded,
+//! rebuilding it whenever `game/src` changes. With the `headless` feature,
+//! `rupy-cli render <scene> <out.png>` renders one frame of a scene without a window.
+
+use std::process::Command;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use engine::{
+    bake_model_impostor, logger::LogFactory, AssetPaths, AssetWatcher, EngineError,
+    GpuRequirements, ImportPipeline, GPU,
+};
+
+const USAGE: &str =
+    "usage: rupy-cli (assets (import | bake-impostors) | dev | render <scene> <out.png>)";
+
+fn main() -> Result<(), EngineError> {
+    #[cfg(feature = "logging")]
+    {
+        let logger = LogFactory::default();
+        let _ = logger.init();
+    }
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args
+        .iter()
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .as_slice()
+    {
+        ["assets", "import"] => import(),
+        ["assets", "bake-impostors"] => bake_impostors(),
+        ["dev"] => dev(),
+        #[cfg(feature = "headless")]
+        ["render", scene, out] => render(scene, out),
+        _ => {
+            eprintln!("{}", USAGE);
+            std::process::exit(2);
+        }
+    }
+}
+
+fn import() -> Result<(), EngineError> {
+    let mut pipeline = ImportPipeline::with_defaults(AssetPaths::root());
+    let queued = pipeline.scan()?;
+    println!(
+        "{} to import, {} up to date",
+        queued,
+        pipeline.report().up_to_date
+    );
+    let report = pipeline.run()?;
+    for (source, error) in &report.failed {
+        eprintln!("failed: {}: {}", source, error);
+    }
+    println!(
+        "imported {}, failed {}",
+        report.imported.len(),
+        report.failed.len()
+    );
+    if !report.failed.is_empty() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+fn bake_impostors() -> Result<(), EngineError> {
+    GPU::init(GpuRequirements::default())?;
+    let (device, queue) = GPU::with_read(|gpu| (gpu.device().clone(), gpu.queue().clone()))?;
+    let mut models: Vec<String> = std::fs::read_dir(AssetPaths::models_dir())?
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .filter(|name| name.ends_with(".obj"))
+        .collect();
+    models.sort();
+
+    let (mut baked, mut failed) = (0, 0);
+    for file in &models {
+        match bake_model_impostor(&device, &queue, file) {
+            Ok(Some(meta)) => {
+                println!("baked: {} ({} views)", file, meta.angles);
+                baked += 1;
+            }
+            Ok(None) => {}
+            Err(e) => {
+                eprintln!("failed: {}: {}", file, e);
+                failed += 1;
+            }
+        }
+    }
+    println!("baked {}, failed {}", baked, failed);
+    if failed > 0 {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// Renders a 1280x720 frame of `scene` from a fixed view and saves it as `out`.
+#[cfg(feature = "headless")]
+fn render(scene: &str, out: &str) -> Result<(), EngineError> {
+    let mut renderer = engine::headless::HeadlessRenderer::new(1280, 720)?;
+    renderer.load_scene(scene)?;
+    renderer.look_at(
+        glam::Vec3::new(4.5, 8.0, 16.0),
+        glam::Vec3::new(4.5, 5.5, 5.0),
+    );
+    renderer.update(0.0);
+    renderer.render();
+    renderer.capture()?.save(out)?;
+    println!("rendered {} to {}", scene, out);
+    Ok(())
+}
+
+/// Builds the game library, runs the app with `hot-reload-game` and rebuilds the library
+/// when a source file under `game/src` changes; the app swaps each build in. Exits with
+/// the app's status.
+fn dev() -> Result<(), EngineError> {
+    if !build_game()? {
+        eprintln!("game build failed; the app starts without it until the next build");
+    }
+    let changed: Arc<Mutex<Option<Instant>>> = Arc::default();
+    let edits = changed.clone();
+    let _watcher = AssetWatcher::new("game/src".into(), move |event| {
+        if event
+            .paths
+            .iter()
+            .any(|path| path.extension().is_some_and(|ext| ext == "rs"))
+        {
+            *edits.lock().unwrap_or_else(PoisonError::into_inner) = Some(Instant::now());
+        }
+    })
+    .map_err(|e| EngineError::FileSystemError(e.to_string()))?;
+
+    let mut app = Command::new("cargo")
+        .args(["run", "-p", "app", "--features", "hot-reload-game"])
+        .spawn()?;
+    loop {
+        if let Some(status) = app.try_wait()? {
+            std::process::exit(status.code().unwrap_or(1));
+        }
+        // Editors save in bursts; build once they've stopped.
+        let settled = {
+            let mut changed = changed.lock().unwrap_or_else(PoisonError::into_inner);
+            match *changed {
+                Some(at) if at.elapsed() >= Duration::from_millis(300) => changed.take(),
+                _ => None,
+            }
+        };
+        if settled.is_some() && !build_game()? {
+            eprintln!("game build failed; the app keeps the last good build");
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+}
+
+fn build_game() -> Result<bool, EngineError> {
+    Ok(Command::new("cargo")
+        .args(["build", "-p", "game"])
+        .status()?
+        .success())
+}
@@ -0,0 +1,175 @@
+//! The stages [`crate::app::Rupy::render`] records each frame. Each stage looks up the
+//! targets it needs itself and fails on its own, so a missing target or an invalid
+//! attachment after a resize skips that stage while the rest of the frame is still
+//! submitted and the surface texture presented.
+
+use engine::{
+    DebugMode, EngineError, FrameBuffer, ModelManager, PostProcessChain, RenderPass,
+    RenderTargetKind, RenderTargetManager, RenderText, Renderer3d, World,
+};
+
+/// What the stages of one frame read; recording only reads, so it is shared by every pass.
+#[derive(Clone, Copy)]
+pub struct FrameStages<'a> {
+    pub render3d: &'a Renderer3d,
+    pub rendertxt: &'a RenderText,
+    pub models: &'a ModelManager,
+    pub world: &'a World,
+    pub uniform_bind_group: &'a wgpu::BindGroup,
+    pub debug_mode: &'a DebugMode,
+    pub render_targets: &'a RenderTargetManager,
+    pub post_process: &'a PostProcessChain,
+    pub surface_view: &'a wgpu::TextureView,
+}
+
+impl FrameStages<'_> {
+    fn scene(&self) -> Result<&FrameBuffer, EngineError> {
+        self.render_targets.require(&RenderTargetKind::Scene)
+    }
+    fn hdr(&self) -> Result<&FrameBuffer, EngineError> {
+        self.render_targets.require(&RenderTargetKind::Hdr)
+    }
+    /// What the final blit shows: the post-process chain's result, or the HDR target as is.
+    pub fn output(&self) -> Result<&FrameBuffer, EngineError> {
+        match self.post_process.output(self.render_targets) {
+            Some(output) => Ok(output),
+            None => self.hdr(),
+        }
+    }
+    /// Whether the depth pre-pass runs this frame, and the scene pass keeps its depth.
+    pub fn prepass(&self) -> bool {
+        self.render3d.instances.prepass.active(self.debug_mode)
+    }
+
+    /// Whether the entity id pass runs this frame.
+    pub fn picking(&self) -> bool {
+        self.render3d.instances.picker.pending()
+    }
+    /// Whether the water pass runs this frame; debug views draw water with the scene.
+    pub fn water(&self) -> bool {
+        self.render3d.water.active() && self.debug_mode.mode() == 0
+    }
+
+    pub fn depth_prepass(self, encoder: &mut wgpu::CommandEncoder) -> Result<(), EngineError> {
+        let scene_fb = self.scene()?;
+        self.render3d
+            .depth_prepass(encoder, scene_fb, self.world, self.uniform_bind_group);
+        Ok(())
+    }
+    pub fn equirect(self, encoder: &mut wgpu::CommandEncoder) -> Result<(), EngineError> {
+        if let Some(projection) = self.world.projection() {
+            projection.record_projection(encoder, Some("Equirect Projection Pass"));
+        }
+        Ok(())
+    }
+    pub fn particles(self, encoder: &mut wgpu::CommandEncoder) -> Result<(), EngineError> {
+        self.render3d.simulate_particles(encoder);
+        Ok(())
+    }
+    /// The world, then text on top, into the scene target. With water to draw, the text
+    /// waits for [`FrameStages::water_pass`].
+    pub fn scene_pass(self, encoder: &mut wgpu::CommandEncoder) -> Result<(), EngineError> {
+        let scene_fb = self.scene()?;
+        let color = if self.water() {
+            scene_fb.color_attachment_kept()
+        } else {
+            scene_fb.color_attachment()
+        };
+        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Scene Pass"),
+            color_attachments: &[Some(color)],
+            depth_stencil_attachment: if self.prepass() {
+                scene_fb.depth_attachment_loaded()
+            } else {
+                scene_fb.depth_attachment()
+            },
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        self.render3d.render(
+            self.models,
+            &mut rpass,
+            self.world,
+            self.uniform_bind_group,
+            self.debug_mode,
+        );
+        if self.water() {
+            return Ok(());
+        }
+        self.text(&mut rpass)
+    }
+    /// Water over the scene, with the fog when `underwater`, then text on top. The water
+    /// only tests the scene depth, so the text, which writes it, gets a pass of its own.
+    pub fn water_pass(
+        self,
+        encoder: &mut wgpu::CommandEncoder,
+        underwater: bool,
+    ) -> Result<(), EngineError> {
+        self.render3d.water.record(
+            &self.models.device,
+            encoder,
+            self.render_targets,
+            self.world,
+            self.uniform_bind_group,
+            underwater,
+        )?;
+        let scene_fb = self.scene()?;
+        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Text Pass"),
+            color_attachments: &[Some(scene_fb.color_attachment_loaded())],
+            depth_stencil_attachment: scene_fb.depth_attachment_loaded(),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        self.text(&mut rpass)
+    }
+    pub fn entity_ids(self, encoder: &mut wgpu::CommandEncoder) -> Result<(), EngineError> {
+        self.render3d.instances.picker.record(
+            encoder,
+            self.render_targets,
+            self.uniform_bind_group,
+            &self.world.terrain,
+        )
+    }
+    pub fn text(self, rpass: &mut wgpu::RenderPass) -> Result<(), EngineError> {
+        self.rendertxt.render(
+            self.models,
+            rpass,
+            self.world,
+            self.uniform_bind_group,
+            self.debug_mode,
+        );
+        Ok(())
+    }
+    pub fn auto_exposure(self, encoder: &mut wgpu::CommandEncoder) -> Result<(), EngineError> {
+        let scene_fb = self.scene()?;
+        self.render3d
+            .auto_exposure(&self.models.device, encoder, scene_fb.color());
+        Ok(())
+    }
+    pub fn hdr_pass(self, encoder: &mut wgpu::CommandEncoder) -> Result<(), EngineError> {
+        let (scene_fb, hdr_fb) = (self.scene()?, self.hdr()?);
+        self.render3d
+            .hdr(encoder, self.models, scene_fb.color(), hdr_fb)
+    }
+    pub fn post_process(self, encoder: &mut wgpu::CommandEncoder) -> Result<(), EngineError> {
+        let hdr_fb = self.hdr()?;
+        self.post_process.record(
+            &self.models.device,
+            encoder,
+            self.render_targets,
+            hdr_fb.color(),
+        );
+        Ok(())
+    }
+    /// The output target onto the swapchain image.
+    pub fn final_blit(self, encoder: &mut wgpu::CommandEncoder) -> Result<(), EngineError> {
+        let output_fb = self.output()?;
+        self.render3d.final_blit_to_surface(
+            &self.models.device,
+            encoder,
+            output_fb.color(),
+            self.surface_view,
+        )
+    }
+}
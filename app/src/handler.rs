@@ -1,8 +1,10 @@
 use crate::state::{AppInnerState, ApplicationState};
-use engine::{ApplicationEvent, World};
+use engine::{
+    camera::Binding, log_error, ApplicationEvent, NavDirection, StartupStatus, UiDevice, World,
+};
 use pollster::FutureExt;
 use winit::{
-    event::WindowEvent,
+    event::{ElementState, MouseButton, WindowEvent},
     event_loop::ActiveEventLoop,
     keyboard::{KeyCode, PhysicalKey},
 };
@@ -10,9 +12,12 @@ use winit::{
 impl winit::application::ApplicationHandler<ApplicationEvent> for ApplicationState {
     fn resumed(&mut self, event_loop: &ActiveEventLoop) {
         if let AppInnerState::Stopped = self.inner {
-            ApplicationState::init(self, event_loop)
-                .block_on()
-                .expect("State init on resume failed");
+            if let Err(e) = ApplicationState::init(self, event_loop).block_on() {
+                log_error!("Startup: {}", e);
+                World::stop();
+                event_loop.exit();
+                return;
+            }
 
             event_loop.set_control_flow(winit::event_loop::ControlFlow::Poll);
         }
@@ -21,37 +26,134 @@ impl winit::application::ApplicationHandler<ApplicationEvent> for ApplicationSta
     fn window_event(
         &mut self,
         event_loop: &ActiveEventLoop,
-        _: winit::window::WindowId,
+        window_id: winit::window::WindowId,
         event: WindowEvent,
     ) {
+        if let AppInnerState::Loading(loading) = &mut self.inner {
+            match &event {
+                WindowEvent::CloseRequested => {
+                    World::stop();
+                    event_loop.exit();
+                }
+                WindowEvent::KeyboardInput { event, .. }
+                    if event.physical_key == PhysicalKey::Code(KeyCode::Escape) =>
+                {
+                    World::stop();
+                    event_loop.exit();
+                }
+                WindowEvent::Resized(size) => loading.resize(size),
+                WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+                    loading.set_scale_factor(*scale_factor)
+                }
+                WindowEvent::RedrawRequested => {
+                    let finished = matches!(loading.frame(), StartupStatus::Finished);
+                    loading.window().request_redraw();
+                    if finished {
+                        if let Err(e) = self.finish_loading() {
+                            log_error!("Startup: {}", e);
+                            World::stop();
+                            event_loop.exit();
+                        }
+                    }
+                }
+                _ => {}
+            }
+            return;
+        }
         if let AppInnerState::Running(app) = &mut self.inner {
+            if !app.is_main(window_id) {
+                app.viewport_event(window_id, &event);
+                return;
+            }
             if matches!(event, WindowEvent::CloseRequested) {
                 app.shutdown(event_loop)
             }
-            app.input(&event);
+            let consumed = app.console_input(&event, event_loop) || app.ui_input(&event);
+            if !consumed && !app.menu_consumes(&event) {
+                app.input(&event);
+            }
             match &event {
-                WindowEvent::Resized(size) => app.resize(&size),
-                WindowEvent::CursorEntered { .. } => app.window().set_cursor_visible(false),
+                WindowEvent::Resized(size) => app.request_resize(size),
+                WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+                    app.set_scale_factor(*scale_factor)
+                }
+                WindowEvent::CursorEntered { .. } => {
+                    app.window().set_cursor_visible(app.menu_open())
+                }
                 WindowEvent::CursorLeft { .. } => app.window().set_cursor_visible(true),
+                WindowEvent::CursorMoved { position, .. } if !consumed => {
+                    app.menu_cursor_moved(*position)
+                }
+                WindowEvent::MouseInput {
+                    state: ElementState::Pressed,
+                    button: MouseButton::Left,
+                    ..
+                } if !consumed => app.click(event_loop),
+                WindowEvent::MouseInput {
+                    state: ElementState::Pressed,
+                    button: MouseButton::Right,
+                    ..
+                } if !consumed && !app.menu_open() => app.place_aimed_block(),
 
-                WindowEvent::KeyboardInput { event, .. } => {
+                WindowEvent::KeyboardInput { event, .. } if !consumed => {
                     if event.state.is_pressed() && event.repeat == false {
+                        let commands = match event.physical_key {
+                            PhysicalKey::Code(code) => app.commands(Binding::Key(code)),
+                            _ => Vec::new(),
+                        };
                         match event.physical_key {
-                            PhysicalKey::Code(KeyCode::KeyM) => app.next_projection(),
-                            PhysicalKey::Code(KeyCode::KeyP) => app.next_debug_mode(),
-                            PhysicalKey::Code(KeyCode::KeyL) => {
-                                let free_look = if app.cam().free_look() { false } else { true };
-                                app.cam_mut().set_free_look(free_look)
+                            PhysicalKey::Code(KeyCode::Escape) => {
+                                if app.menu_open() {
+                                    app.close_menu()
+                                } else {
+                                    app.toggle_pause_menu()
+                                }
+                            }
+                            PhysicalKey::Code(
+                                KeyCode::Enter | KeyCode::NumpadEnter | KeyCode::Space,
+                            ) if app.menu_open() => app.menu_activate(event_loop),
+                            PhysicalKey::Code(code)
+                                if app.menu_open() && NavDirection::from_key(code).is_some() =>
+                            {
+                                if let Some(dir) = NavDirection::from_key(code) {
+                                    app.menu_navigate(dir, UiDevice::Keyboard)
+                                }
+                            }
+                            PhysicalKey::Code(code)
+                                if commands.is_empty() && app.scene_picker_open() =>
+                            {
+                                let digits = [
+                                    KeyCode::Digit1,
+                                    KeyCode::Digit2,
+                                    KeyCode::Digit3,
+                                    KeyCode::Digit4,
+                                    KeyCode::Digit5,
+                                    KeyCode::Digit6,
+                                    KeyCode::Digit7,
+                                    KeyCode::Digit8,
+                                    KeyCode::Digit9,
+                                ];
+                                if let Some(index) = digits.iter().position(|d| *d == code) {
+                                    app.pick_scene(index);
+                                }
+                            }
+                            _ => {
+                                for command in commands {
+                                    app.run_command(command, event_loop);
+                                }
                             }
-                            PhysicalKey::Code(KeyCode::Escape) => app.shutdown(event_loop),
-                            _ => {}
                         }
                     }
                 }
                 WindowEvent::RedrawRequested => {
+                    app.apply_resizes();
                     app.update();
                     app.upload();
                     app.render();
+                    if !World::running() {
+                        event_loop.exit();
+                        return;
+                    }
                     app.window().request_redraw();
                 }
                 _ => {}
@@ -70,6 +172,14 @@ impl winit::application::ApplicationHandler<ApplicationEvent> for ApplicationSta
     }
 
     fn user_event(&mut self, event_loop: &ActiveEventLoop, event: ApplicationEvent) {
+        if let ApplicationEvent::DeviceLost = event {
+            if let Err(e) = self.recover_device() {
+                log_error!("Recovering from GPU device loss: {}", e);
+                World::stop();
+                event_loop.exit();
+            }
+            return;
+        }
         if let AppInnerState::Running(app) = &mut self.inner {
             match event {
                 ApplicationEvent::Shutdown => {
@@ -79,6 +189,34 @@ impl winit::application::ApplicationHandler<ApplicationEvent> for ApplicationSta
                 ApplicationEvent::Projection => {
                     app.next_projection();
                 }
+                ApplicationEvent::ShaderChanged(path) => {
+                    app.shader_changed(&path);
+                }
+                ApplicationEvent::TextureLoaded(_) => {
+                    app.textures_loaded();
+                }
+                ApplicationEvent::SetPresentMode(mode) => {
+                    app.set_present_mode(mode);
+                }
+                ApplicationEvent::ToggleCameraProjection => {
+                    app.toggle_camera_projection();
+                }
+                ApplicationEvent::SetCameraProjection(projection) => {
+                    app.set_camera_projection(projection);
+                }
+                ApplicationEvent::Pause => {
+                    app.toggle_time_pause();
+                }
+                ApplicationEvent::Step => {
+                    app.step_time();
+                }
+                ApplicationEvent::SetTimeScale(scale) => {
+                    app.set_time_scale(scale);
+                }
+                ApplicationEvent::Picked(entity) => {
+                    app.select_picked(entity);
+                }
+                ApplicationEvent::DeviceLost => {}
             }
         }
     }
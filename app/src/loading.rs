@@ -0,0 +1,502 @@
+use crate::{
+    app::Rupy,
+    viewport::{render_size, scene_targets},
+};
+use engine::{
+    camera::Camera, log_error, log_warning, AssetPaths, BindGroup, ChunkStore, DebugMode,
+    DepthVariant, EngineError, EngineSettings, Entity, ImportPipeline, InitTask, Light, NavAgent,
+    NavTarget, PostProcessChain, RenderSettings, RenderTargetKind, RenderTargetManager, RenderText,
+    Renderer3d, ScreenCorner, Startup, StartupStatus, SurfaceExt, TaskProgress, TextRegion, World,
+};
+use std::{sync::Arc, time::Duration};
+use winit::{
+    dpi::{LogicalSize, PhysicalSize},
+    event_loop::ActiveEventLoop,
+    window::{Window, WindowAttributes},
+};
+
+/// Navigation speed of the boss agent, half the default camera speed.
+pub const BOSS_SPEED: f32 = CAMERA_SPEED / 2.0;
+pub const CAMERA_SPEED: f32 = 5.0;
+/// Starting distance of the inspection orbit camera; clamped to the target's bounds.
+#[cfg(feature = "devtools")]
+pub const ORBIT_DISTANCE: f32 = 4.0;
+/// Time the startup asset import may spend per step before yielding to the loading screen.
+pub const IMPORT_BUDGET: Duration = Duration::from_millis(12);
+
+/// Everything startup produces. Phase 1 fills the window/surface/GPU handles; the rest is
+/// `None` until the [`InitTask`] that builds it has finished, so nothing can use a
+/// half-initialized world or renderer.
+pub struct Boot {
+    pub window: Arc<Window>,
+    pub surface: wgpu::Surface<'static>,
+    pub surface_config: wgpu::SurfaceConfiguration,
+    /// Set while the window has a zero-sized client area; nothing is drawn to the surface.
+    pub surface_suspended: bool,
+    pub device: Arc<wgpu::Device>,
+    pub queue: Arc<wgpu::Queue>,
+    pub depth_stencil: wgpu::DepthStencilState,
+    pub model_manager: engine::ModelManager,
+    pub render3d: Option<Renderer3d>,
+    pub render_targets: Option<RenderTargetManager>,
+    pub post_process: Option<PostProcessChain>,
+    pub rendertxt: Option<RenderText>,
+    pub camera: Option<Camera>,
+    pub light: Option<Light>,
+    pub uniform_bind_group: Option<wgpu::BindGroup>,
+    pub debug_mode: Option<DebugMode>,
+    pub world: Option<World>,
+    pub bossman: Option<Entity>,
+    /// Scene the "scene" task loads.
+    pub scene: String,
+    /// Textures to load once the scene is up, beyond those it asks for; what was loaded
+    /// before a device loss, see [`Loading::after_device_loss`].
+    pub restore_textures: Vec<String>,
+    /// Read from [`EngineSettings::FILE`] before the window was created.
+    pub settings: EngineSettings,
+}
+
+/// [`EngineSettings::load`], falling back to the defaults when the file can't be read.
+fn load_settings() -> EngineSettings {
+    EngineSettings::load().unwrap_or_else(|e| {
+        log_error!("Settings, using the defaults: {}", e);
+        EngineSettings::default()
+    })
+}
+
+/// A new surface for `window` from the shared GPU instance, still to be configured.
+pub fn create_surface(window: &Arc<Window>) -> Result<wgpu::Surface<'static>, EngineError> {
+    let instance = crate::GPU::with_read_recovered(|gpu| gpu.instance().clone())?;
+    Ok(instance.create_surface(Arc::clone(window))?)
+}
+
+fn missing(what: &str) -> EngineError {
+    EngineError::StartupError(format!("{} isn't initialized yet", what))
+}
+
+impl Boot {
+    /// Phase 1: window, surface and GPU handles, enough to draw the loading screen.
+    fn new(window: Arc<Window>, settings: EngineSettings) -> Result<Self, EngineError> {
+        let (width, height) = {
+            let inner_size = window.inner_size();
+            (inner_size.width, inner_size.height)
+        };
+        let (adapter, device, queue) = crate::GPU::with_read_recovered(|gpu| {
+            (
+                gpu.adapter().clone(),
+                gpu.device().clone(),
+                gpu.queue().clone(),
+            )
+        })?;
+
+        let surface = create_surface(&window)?;
+        // A window created minimized has no area yet: start at 1x1, suspended until the
+        // first resize to a real size.
+        let surface_suspended = width == 0 || height == 0;
+        let mut surface_config = surface
+            .get_default_config(&adapter, width.max(1), height.max(1))
+            .ok_or(EngineError::SurfaceConfigError(
+                "surface isn't supported by this adapter".into(),
+            ))?;
+        surface.set_present_mode(
+            &adapter,
+            &device,
+            &mut surface_config,
+            settings.present_mode,
+        );
+
+        let depth_stencil = RenderSettings::depth_state(DepthVariant::Opaque);
+        let model_manager = engine::ModelManager::new(queue.clone(), device.clone());
+        Ok(Self {
+            window,
+            surface,
+            surface_config,
+            surface_suspended,
+            device,
+            queue,
+            depth_stencil,
+            model_manager,
+            render3d: None,
+            render_targets: None,
+            post_process: None,
+            rendertxt: None,
+            camera: None,
+            light: None,
+            uniform_bind_group: None,
+            debug_mode: None,
+            world: None,
+            bossman: None,
+            scene: std::env::var("RUPY_SCENE").unwrap_or_else(|_| "debug".to_string()),
+            restore_textures: Vec::new(),
+            settings,
+        })
+    }
+
+    /// Brings `assets/.import` up to date a few imports per step, so stale derivatives are
+    /// refreshed before anything loads. Import problems are logged, never fatal: loaders
+    /// fall back to the source assets.
+    fn asset_import() -> InitTask<Boot> {
+        let mut pipeline: Option<ImportPipeline> = None;
+        InitTask::new("asset import", move |_: &mut Boot| {
+            if pipeline.is_none() {
+                let mut scanned = ImportPipeline::with_defaults(AssetPaths::root());
+                if let Err(e) = scanned.scan() {
+                    log_warning!("Asset import: {}", e);
+                    return Ok(TaskProgress::Done);
+                }
+                pipeline = Some(scanned);
+            }
+            let Some(pipeline) = pipeline.as_mut() else {
+                return Ok(TaskProgress::Done);
+            };
+            match pipeline.step(IMPORT_BUDGET) {
+                Ok(false) => {
+                    let (done, total) = pipeline.progress();
+                    Ok(TaskProgress::Partial(done as f32 / total.max(1) as f32))
+                }
+                Ok(true) => Ok(TaskProgress::Done),
+                Err(e) => {
+                    log_warning!("Asset import: {}", e);
+                    Ok(TaskProgress::Done)
+                }
+            }
+        })
+    }
+
+    /// Phase 2: the default task list. Apps add their own with [`Loading::add_task`].
+    fn tasks() -> Vec<InitTask<Boot>> {
+        vec![
+            InitTask::once("renderer", |boot: &mut Boot| {
+                let (device, config) = (&boot.device, &boot.surface_config);
+                let scale = boot.settings.render_scale;
+                boot.render3d = Some(Renderer3d::new(device, config)?);
+                let targets = scene_targets(device, config, scale);
+                // Text is drawn inside the scene pass, so its pipeline follows the scene target format.
+                let text_format = targets
+                    .get(&RenderTargetKind::Scene)
+                    .map(|fb| fb.color().texture.format())
+                    .unwrap_or(config.format);
+                let mut rendertxt = RenderText::new(
+                    device,
+                    &boot.queue,
+                    text_format,
+                    &Some(boot.depth_stencil.clone()),
+                    boot.window.scale_factor() * scale as f64,
+                );
+                rendertxt.resize(&boot.queue, render_size(boot.window.inner_size(), scale));
+                boot.render_targets = Some(targets);
+                boot.post_process = Some(PostProcessChain::with_defaults(device, config.format)?);
+                boot.rendertxt = Some(rendertxt);
+                Ok(())
+            }),
+            InitTask::once("camera", |boot: &mut Boot| {
+                let size = boot.window.inner_size();
+                let mut camera = Camera::new(&boot.device, size.width as f32 / size.height as f32);
+                camera.set_projection_mode(boot.settings.projection());
+                let mut light = Light::new(&boot.device)?;
+                boot.settings.apply_ambient(&mut light);
+                boot.uniform_bind_group = Some(BindGroup::uniform(
+                    &boot.device,
+                    camera.buffer(),
+                    light.buffer(),
+                ));
+                boot.camera = Some(camera);
+                boot.light = Some(light);
+                Ok(())
+            }),
+            InitTask::once("core shaders", |boot: &mut Boot| {
+                let camera = boot.camera.as_ref().ok_or_else(|| missing("camera"))?;
+                let light = boot.light.as_ref().ok_or_else(|| missing("light"))?;
+                boot.debug_mode = Some(DebugMode::new(
+                    &boot.device,
+                    &mut boot.model_manager.materials.shaders,
+                    &mut boot.model_manager.materials.pipelines,
+                    camera,
+                    light,
+                    &boot.surface_config,
+                )?);
+                Ok(())
+            })
+            .after("camera"),
+            Self::asset_import(),
+            InitTask::once("materials", |boot: &mut Boot| {
+                if let Err(e) = boot
+                    .model_manager
+                    .load_material_library(&boot.surface_config, Some(boot.depth_stencil.clone()))
+                {
+                    log_warning!("Material library: {}", e);
+                }
+                Ok(())
+            })
+            .after("asset import"),
+            InitTask::once("environment", |boot: &mut Boot| {
+                let mut world = World::new(
+                    &boot.queue,
+                    &boot.device,
+                    &boot.surface_config,
+                    Some(boot.depth_stencil.clone()),
+                )?;
+                let store = boot.settings.world_dir.as_ref().map(ChunkStore::new);
+                world.terrain.set_store(store.map(Arc::new));
+                boot.world = Some(world);
+                Ok(())
+            })
+            .after("asset import"),
+            InitTask::once("scene", |boot: &mut Boot| {
+                let world = boot.world.as_mut().ok_or_else(|| missing("world"))?;
+                let scene = boot.scene.clone();
+                let loaded = match world.load_scene_by_name(
+                    &mut boot.model_manager,
+                    &scene,
+                    &boot.surface_config,
+                    &boot.depth_stencil,
+                ) {
+                    Ok(loaded) => loaded.entity("bossman"),
+                    Err(e) => {
+                        log_error!("Scene '{}': {}", scene, e);
+                        None
+                    }
+                };
+                boot.bossman = Some(loaded.unwrap_or_else(|| world.spawn()));
+                Ok(())
+            })
+            .after("environment")
+            .after("materials"),
+            InitTask::once("spawn camera", |boot: &mut Boot| {
+                let world = boot.world.as_mut().ok_or_else(|| missing("world"))?;
+                let camera = boot.camera.as_mut().ok_or_else(|| missing("camera"))?;
+                let bossman = boot.bossman.ok_or_else(|| missing("scene"))?;
+                camera.world_spawn(world, &mut boot.model_manager, &boot.surface_config);
+                if let Some(target) = camera.entity() {
+                    world.insert_nav_agent(
+                        bossman,
+                        NavAgent::new(NavTarget::Entity(target), BOSS_SPEED),
+                    );
+                }
+                boot.model_manager.materials.build_storage(&boot.device);
+                Ok(())
+            })
+            .after("scene")
+            .after("camera"),
+            InitTask::once("restore textures", |boot: &mut Boot| {
+                for texture in std::mem::take(&mut boot.restore_textures) {
+                    if let Err(e) = boot.model_manager.materials.textures.get_or_load_texture(
+                        &boot.queue,
+                        &boot.device,
+                        &texture,
+                        &boot.surface_config,
+                    ) {
+                        log_warning!("Restoring texture '{}': {}", texture, e);
+                    }
+                }
+                Ok(())
+            })
+            .after("scene"),
+        ]
+    }
+
+    pub fn take<T>(slot: Option<T>, what: &str) -> Result<T, EngineError> {
+        slot.ok_or_else(|| missing(what))
+    }
+}
+
+/// Startup in progress: runs the init tasks a frame's budget at a time and draws their
+/// progress, or the failure, until [`Loading::into_app`] can hand over to the game loop.
+pub struct Loading {
+    boot: Boot,
+    startup: Startup<Boot>,
+    screen: RenderText,
+    status: StartupStatus,
+}
+
+impl Loading {
+    pub fn new(event_loop: &ActiveEventLoop) -> Result<Self, EngineError> {
+        let settings = load_settings();
+        let (width, height) = settings.window_size;
+        let win_attrs = WindowAttributes::default()
+            .with_title(&settings.window_title)
+            .with_inner_size(LogicalSize::new(width, height));
+        let window = Arc::new(event_loop.create_window(win_attrs)?);
+        Self::with_boot(Boot::new(window, settings)?)
+    }
+    /// Runs startup again in `window` on the device [`engine::GPU::recover`] created,
+    /// loading `scene` and then the `textures` loaded before the loss.
+    pub fn after_device_loss(
+        window: Arc<Window>,
+        scene: Option<String>,
+        textures: Vec<String>,
+    ) -> Result<Self, EngineError> {
+        let mut boot = Boot::new(window, load_settings())?;
+        if let Some(scene) = scene {
+            boot.scene = scene;
+        }
+        boot.restore_textures = textures;
+        Self::with_boot(boot)
+    }
+    /// Starts over on a new device after the old one was lost mid-startup. The old surface
+    /// is dropped before the window gets a new one.
+    pub fn restart(self) -> Result<Self, EngineError> {
+        let window = self.boot.window.clone();
+        let scene = self.boot.scene.clone();
+        let textures = self.boot.restore_textures.clone();
+        drop(self);
+        Self::after_device_loss(window, Some(scene), textures)
+    }
+    fn with_boot(boot: Boot) -> Result<Self, EngineError> {
+        let mut screen = RenderText::new(
+            &boot.device,
+            &boot.queue,
+            boot.surface_config.format,
+            &None,
+            boot.window.scale_factor(),
+        );
+        screen.resize(&boot.queue, boot.window.inner_size());
+        let mut startup = Startup::new();
+        for task in Boot::tasks() {
+            startup.add(task);
+        }
+        Ok(Self {
+            boot,
+            startup,
+            screen,
+            status: StartupStatus::Running,
+        })
+    }
+    pub fn window(&self) -> &Window {
+        &self.boot.window
+    }
+    pub fn resize(&mut self, new_size: &PhysicalSize<u32>) {
+        self.boot.surface_suspended =
+            !self
+                .boot
+                .surface
+                .resize(&self.boot.device, &mut self.boot.surface_config, *new_size);
+        if !self.boot.surface_suspended {
+            self.screen.resize(&self.boot.queue, *new_size);
+        }
+    }
+    pub fn set_scale_factor(&mut self, scale_factor: f64) {
+        self.screen.set_scale_factor(scale_factor);
+    }
+
+    /// Phase 2 for one frame: advances the tasks (unless startup already failed) and draws
+    /// the loading or error screen.
+    pub fn frame(&mut self) -> &StartupStatus {
+        if matches!(self.status, StartupStatus::Running) {
+            self.status = self.startup.step(&mut self.boot);
+        }
+        self.render();
+        &self.status
+    }
+
+    fn text(&self) -> String {
+        match &self.status {
+            StartupStatus::Failed(failure) => {
+                let report = failure
+                    .report
+                    .as_ref()
+                    .map(|path| path.display().to_string())
+                    .unwrap_or_else(|| "not written".to_string());
+                format!(
+                    "Startup failed in '{}':\n{}\n\nReport: {}\nPress Esc to quit",
+                    failure.task, failure.message, report
+                )
+            }
+            _ => format!(
+                "Loading {:.0}%\n{}",
+                self.startup.progress() * 100.0,
+                self.startup.lines().join("\n")
+            ),
+        }
+    }
+
+    fn render(&mut self) {
+        if self.boot.surface_suspended {
+            return;
+        }
+        let (width, height) = self.screen.logical_size();
+        let region = TextRegion::new(
+            self.text(),
+            ScreenCorner::TopLeft.pos(width, height, 20.0),
+            glyphon::Color::rgb(255, 255, 255),
+        );
+        self.screen.prepare_regions(
+            &self.boot.device,
+            &self.boot.queue,
+            &[region],
+            &self.boot.surface_config,
+            None,
+        );
+        let frame = match self.boot.surface.texture() {
+            Ok(frame) => frame,
+            Err(wgpu::SurfaceError::Outdated) => {
+                self.resize(&self.boot.window.inner_size());
+                return;
+            }
+            Err(wgpu::SurfaceError::Lost) => {
+                match create_surface(&self.boot.window) {
+                    Ok(surface) => {
+                        self.boot.surface = surface;
+                        self.resize(&self.boot.window.inner_size());
+                    }
+                    Err(e) => log_error!("Recreating the lost surface: {}", e),
+                }
+                return;
+            }
+            Err(e) => {
+                log_error!("SurfaceError: {}", e);
+                return;
+            }
+        };
+        let view = frame.texture.create_view(&Default::default());
+        let mut encoder =
+            self.boot
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("Loading Encoder"),
+                });
+        {
+            let clear = match self.status {
+                StartupStatus::Failed(_) => wgpu::Color {
+                    r: 0.25,
+                    g: 0.02,
+                    b: 0.02,
+                    a: 1.0,
+                },
+                _ => wgpu::Color {
+                    r: 0.02,
+                    g: 0.02,
+                    b: 0.03,
+                    a: 1.0,
+                },
+            };
+            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Loading Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(clear),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            self.screen.draw(&mut rpass);
+        }
+        self.boot.queue.submit(Some(encoder.finish()));
+        frame.present();
+    }
+
+    /// Phase 3: hands the finished startup over to the game loop.
+    pub fn into_app(self) -> Result<Rupy, EngineError> {
+        if !self.startup.finished() {
+            return Err(EngineError::StartupError(
+                "startup tasks haven't finished".to_string(),
+            ));
+        }
+        Rupy::from_boot(self.boot)
+    }
+}
@@ -1,11 +1,17 @@
 mod app;
+mod frame;
 mod handler;
+mod loading;
+mod menu;
 mod state;
+mod viewport;
 use crossbeam::channel::{self, Receiver, Sender};
 use engine::{
     event_bus::{EventBusProxy, EventProxy, EventProxyTrait},
+    log_error,
     logger::LogFactory,
-    ApplicationEvent, EngineError, RenderBindGroupLayouts, GPU,
+    ApplicationEvent, EngineError, EngineEvent, EngineSettings, EventBus, RenderBindGroupLayouts,
+    Shader, TextureLoader, GPU,
 };
 use state::ApplicationState;
 use std::sync::Arc;
@@ -19,7 +25,7 @@ async fn main() -> Result<(), EngineError> {
         let _ = logger.init();
     }
 
-    let (_tx, rx): (Sender<ApplicationEvent>, Receiver<ApplicationEvent>) = channel::unbounded();
+    let (tx, rx): (Sender<ApplicationEvent>, Receiver<ApplicationEvent>) = channel::unbounded();
 
     let arc_rx = Arc::new(rx);
 
@@ -27,11 +33,27 @@ async fn main() -> Result<(), EngineError> {
     let proxy: Arc<dyn EventProxyTrait<ApplicationEvent> + Send + Sync> =
         Arc::new(EventProxy::new(Arc::new(event_loop.create_proxy())));
 
-    GPU::init();
+    // The loading screen reads the settings again and reports a file it can't read.
+    GPU::init(EngineSettings::load().unwrap_or_default().gpu_requirements())?;
 
     EventBusProxy::new(&arc_rx, proxy).run_tokio();
 
-    let _ = RenderBindGroupLayouts::get();
+    // The winit loop is one subscriber among many: application events published on the bus
+    // and the engine events it has an answer for reach it through the proxy.
+    EventBus::forward(tx.clone(), |event: &ApplicationEvent| Some(event.clone()));
+    EventBus::forward(tx, EngineEvent::application_event);
+
+    TextureLoader::enable();
+
+    let _shader_watcher = match Shader::watch() {
+        Ok(watcher) => Some(watcher),
+        Err(e) => {
+            log_error!("Shader hot reload unavailable: {}", e);
+            None
+        }
+    };
+
+    RenderBindGroupLayouts::try_get()?;
 
     Ok(event_loop.run_app(&mut ApplicationState::new())?)
 }
@@ -0,0 +1,100 @@
+use engine::{DepthPolicy, RenderSettings, SceneFile, TextRegion, UiFocusManager};
+
+/// What activating a menu entry does.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MenuAction {
+    LoadScene(String),
+    Resume,
+    /// Steps to the next depth preset.
+    CycleDepth,
+    Quit,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MenuKind {
+    ScenePicker,
+    Pause,
+}
+
+/// An open menu and its focus state. Entries are re-registered by [`Menu::layout`] whenever
+/// the screen size or their labels change.
+pub struct Menu {
+    pub kind: MenuKind,
+    scenes: Vec<String>,
+    pub focus: UiFocusManager<MenuAction>,
+}
+
+impl Menu {
+    /// Rescans the scenes directory.
+    pub fn scene_picker() -> Self {
+        Self {
+            kind: MenuKind::ScenePicker,
+            scenes: SceneFile::available(),
+            focus: UiFocusManager::new(),
+        }
+    }
+    pub fn pause() -> Self {
+        Self {
+            kind: MenuKind::Pause,
+            scenes: Vec::new(),
+            focus: UiFocusManager::new(),
+        }
+    }
+    pub fn scenes(&self) -> &[String] {
+        &self.scenes
+    }
+    fn title(&self) -> &'static str {
+        match self.kind {
+            MenuKind::ScenePicker => "Scenes (F1 to close)",
+            MenuKind::Pause => "Paused",
+        }
+    }
+    fn entries(&self, current_scene: &str) -> Vec<(String, MenuAction)> {
+        match self.kind {
+            MenuKind::ScenePicker => self
+                .scenes
+                .iter()
+                .enumerate()
+                .map(|(i, name)| {
+                    let marker = if name == current_scene { "*" } else { " " };
+                    let label = if i < 9 {
+                        format!("{}{} {}", marker, i + 1, name)
+                    } else {
+                        format!("{}  {}", marker, name)
+                    };
+                    (label, MenuAction::LoadScene(name.clone()))
+                })
+                .collect(),
+            MenuKind::Pause => {
+                let depth = match RenderSettings::depth_policy() {
+                    DepthPolicy::Standard => "Standard",
+                    DepthPolicy::ReverseZ => "Reverse-Z",
+                };
+                vec![
+                    ("Resume".to_string(), MenuAction::Resume),
+                    (format!("Depth preset: {}", depth), MenuAction::CycleDepth),
+                    ("Quit".to_string(), MenuAction::Quit),
+                ]
+            }
+        }
+    }
+
+    /// Lays the entries out below the title, centered on a `width` x `height` logical screen.
+    pub fn layout(&mut self, width: u32, height: u32, line_height: f32, current_scene: &str) {
+        let origin = [width as f32 * 0.5, height as f32 * 0.5 + line_height];
+        let entries = self.entries(current_scene);
+        self.focus.clear();
+        self.focus.register_column(origin, line_height, entries);
+    }
+
+    pub fn regions(&self, width: u32, height: u32) -> Vec<TextRegion> {
+        let title = TextRegion::new(
+            self.title(),
+            [width as f32 * 0.5, height as f32 * 0.5],
+            glyphon::Color::rgb(255, 255, 255),
+        );
+        let mut regions = vec![title];
+        regions.extend(self.focus.regions());
+        regions
+    }
+}
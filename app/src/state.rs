@@ -1,11 +1,13 @@
-use crate::app::Rupy;
-use engine::EngineError;
+use crate::{app::Rupy, loading::Loading};
+use engine::{EngineError, GPU};
 use winit::event_loop::ActiveEventLoop;
 
 #[allow(dead_code)]
 
 pub enum AppInnerState {
     Stopped,
+    /// Window is up and the loading screen draws while startup tasks run.
+    Loading(Loading),
     Running(Rupy),
 }
 
@@ -21,18 +23,56 @@ impl ApplicationState {
         }
     }
 
-    /// One-time async initialization, called from `resumed()`.
+    /// One-time async initialization, called from `resumed()`. Only opens the window and
+    /// queues the startup tasks; [`ApplicationState::finish_loading`] swaps in the game.
     pub async fn init(
         state: &mut ApplicationState,
         event_loop: &ActiveEventLoop,
     ) -> Result<(), EngineError> {
         match state.inner {
             AppInnerState::Stopped => {
-                let run = Rupy::new(event_loop)?;
-                state.inner = AppInnerState::Running(run);
+                let loading = Loading::new(event_loop)?;
+                loading.window().request_redraw();
+                state.inner = AppInnerState::Loading(loading);
                 Ok(())
             }
             _ => Ok(()),
         }
     }
+
+    /// Replaces the lost GPU device and runs startup again on the new one, in the same
+    /// window. The world is loaded again from its scene file; what happened in it since is
+    /// lost, like everything else that lived on the old device.
+    pub fn recover_device(&mut self) -> Result<(), EngineError> {
+        GPU::recover()?;
+        let loading = match std::mem::replace(&mut self.inner, AppInnerState::Stopped) {
+            AppInnerState::Running(app) => {
+                let (window, scene, textures) = app.into_recovery();
+                Loading::after_device_loss(window, scene, textures)?
+            }
+            AppInnerState::Loading(loading) => loading.restart()?,
+            AppInnerState::Stopped => return Ok(()),
+        };
+        loading.window().request_redraw();
+        self.inner = AppInnerState::Loading(loading);
+        Ok(())
+    }
+
+    /// Replaces the finished loading state with the running game.
+    pub fn finish_loading(&mut self) -> Result<(), EngineError> {
+        match std::mem::replace(&mut self.inner, AppInnerState::Stopped) {
+            AppInnerState::Loading(loading) => {
+                let mut app = loading.into_app()?;
+                // Texture events posted while loading weren't handled.
+                app.textures_loaded();
+                app.window().request_redraw();
+                self.inner = AppInnerState::Running(app);
+                Ok(())
+            }
+            other => {
+                self.inner = other;
+                Ok(())
+            }
+        }
+    }
 }
@@ -0,0 +1,366 @@
+//! Per-window state. Every window the app draws into has its own [`ViewportContext`]: the
+//! surface, the targets sized after it, a text layer, and a camera with its uniform bind
+//! group. The world, models and GPU are shared, so a second window is another view of the
+//! same scene.
+
+use engine::{
+    camera::Camera, log_error, log_warning, BindGroup, EngineError, FrameBuffer, FrameBufferSize,
+    Layers, Light, LodSelection, RenderSettings, RenderTargetKind, RenderTargetManager, RenderText,
+    SurfaceExt, World,
+};
+use glam::Vec3;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use winit::{
+    dpi::PhysicalSize,
+    event_loop::ActiveEventLoop,
+    window::{Window, WindowAttributes, WindowId},
+};
+
+use crate::loading::create_surface;
+
+/// What drives a viewport's camera.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ViewportKind {
+    /// The game view: camera controls, menus, the HUD and the dev UI.
+    Main,
+    /// Looks down on the main camera's eye from `height` above it.
+    Map { height: f32 },
+}
+
+impl ViewportKind {
+    pub const MAP_HEIGHT: f32 = 40.0;
+    pub const MAP_HEIGHT_RANGE: (f32, f32) = (5.0, 400.0);
+}
+
+/// `size` times `scale`, at least one pixel a side: what a viewport's targets, and the text
+/// drawn into them, are sized to.
+pub fn render_size(size: PhysicalSize<u32>, scale: f32) -> PhysicalSize<u32> {
+    let side = |length: u32| ((length as f32 * scale).round() as u32).max(1);
+    PhysicalSize::new(side(size.width), side(size.height))
+}
+
+/// The scene target, with depth, and the HDR target a viewport of `config`'s size renders
+/// through, both `scale` times that size.
+pub fn scene_targets(
+    device: &wgpu::Device,
+    config: &wgpu::SurfaceConfiguration,
+    scale: f32,
+) -> RenderTargetManager {
+    let size = FrameBufferSize::from(config).scaled(scale);
+    let mut targets = RenderTargetManager::new();
+    targets.set_scale(scale);
+    targets.insert(
+        FrameBuffer::new_with_depth(
+            device,
+            size,
+            config.format,
+            RenderSettings::depth_policy().format(),
+            RenderSettings::sample_count(),
+            "scene buffer",
+        ),
+        RenderTargetKind::Scene,
+    );
+    targets.insert(
+        FrameBuffer::new_color_only(device, size, config.format, "hdr buffer"),
+        RenderTargetKind::Hdr,
+    );
+    targets
+}
+
+/// The latest size a window reported and the frame hasn't applied yet. Resizing a window
+/// by dragging reports many sizes between two frames; only the last one is applied.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PendingResize(Option<PhysicalSize<u32>>);
+
+impl PendingResize {
+    /// Records `size`, replacing any size not applied yet.
+    pub fn request(&mut self, size: PhysicalSize<u32>) {
+        self.0 = Some(size);
+    }
+    /// The size to apply, clearing it; `None` when none is pending or it is `current`, the
+    /// size already applied, as platforms repeating a size report it.
+    pub fn take(&mut self, current: Option<PhysicalSize<u32>>) -> Option<PhysicalSize<u32>> {
+        self.0.take().filter(|size| Some(*size) != current)
+    }
+}
+
+pub struct ViewportContext {
+    pub kind: ViewportKind,
+    pub window: Arc<Window>,
+    pub surface: wgpu::Surface<'static>,
+    pub surface_config: wgpu::SurfaceConfiguration,
+    /// Set while the window is minimized or otherwise zero-sized; frames are skipped.
+    pub surface_suspended: bool,
+    pub render_targets: RenderTargetManager,
+    pub rendertxt: RenderText,
+    pub camera: Camera,
+    pub uniform_bind_group: wgpu::BindGroup,
+    /// Levels of detail picked for this camera. Swapped into the shared instance buffers
+    /// while the viewport culls, so each view keeps its own hysteresis.
+    pub lods: LodSelection,
+    /// When text was last laid out; backdated by [`ViewportContext::reshape`].
+    pub last_shape_time: Instant,
+    /// Size to resize to at the start of the next frame.
+    pub pending_resize: PendingResize,
+}
+
+impl ViewportContext {
+    /// Opens another window onto the world. Its surface is configured with `format`, the
+    /// one every pipeline was built for; a surface that can't present it is refused.
+    pub fn open(
+        event_loop: &ActiveEventLoop,
+        title: &str,
+        kind: ViewportKind,
+        format: wgpu::TextureFormat,
+        present_mode: wgpu::PresentMode,
+        depth_stencil: &wgpu::DepthStencilState,
+        light: &Light,
+    ) -> Result<Self, EngineError> {
+        let attributes = WindowAttributes::default()
+            .with_title(title)
+            .with_inner_size(PhysicalSize::new(480, 480));
+        let window = Arc::new(event_loop.create_window(attributes)?);
+        let (adapter, device, queue) = engine::GPU::with_read_recovered(|gpu| {
+            (
+                gpu.adapter().clone(),
+                gpu.device().clone(),
+                gpu.queue().clone(),
+            )
+        })?;
+        let surface = create_surface(&window)?;
+        if !surface.get_capabilities(&adapter).formats.contains(&format) {
+            return Err(EngineError::SurfaceConfigError(format!(
+                "window '{}' can't present {:?}",
+                title, format
+            )));
+        }
+        let size = window.inner_size();
+        let surface_suspended = size.width == 0 || size.height == 0;
+        let mut surface_config = surface
+            .get_default_config(&adapter, size.width.max(1), size.height.max(1))
+            .ok_or(EngineError::SurfaceConfigError(
+                "surface isn't supported by this adapter".into(),
+            ))?;
+        surface_config.format = format;
+        surface_config.present_mode = present_mode;
+        surface.configure(&device, &surface_config);
+
+        let render_targets = scene_targets(&device, &surface_config, 1.0);
+        let mut rendertxt = RenderText::new(
+            &device,
+            &queue,
+            format,
+            &Some(depth_stencil.clone()),
+            window.scale_factor(),
+        );
+        rendertxt.resize(&queue, size);
+        let aspect = surface_config.width as f32 / surface_config.height as f32;
+        let mut camera = Camera::new(&device, aspect);
+        if let ViewportKind::Map { .. } = kind {
+            camera.set_render_mask(Camera::DEFAULT_RENDER_MASK.with(Layers::MAP));
+        }
+        let uniform_bind_group = BindGroup::uniform(&device, camera.buffer(), light.buffer());
+        let mut viewport = Self {
+            kind,
+            window,
+            surface,
+            surface_config,
+            surface_suspended,
+            render_targets,
+            rendertxt,
+            camera,
+            uniform_bind_group,
+            lods: LodSelection::default(),
+            last_shape_time: Instant::now(),
+            pending_resize: PendingResize::default(),
+        };
+        viewport.reshape();
+        Ok(viewport)
+    }
+    pub fn id(&self) -> WindowId {
+        self.window.id()
+    }
+    pub fn size(&self) -> FrameBufferSize {
+        FrameBufferSize::from(&self.surface_config)
+    }
+    /// Size of the targets the scene is drawn into, the window size times the render scale.
+    pub fn render_size(&self) -> FrameBufferSize {
+        self.size().scaled(self.render_targets.scale())
+    }
+    /// DPI scale for the text layer. Text is drawn into the scaled scene target, so it's
+    /// the window's scale times the render scale, which keeps logical sizes unchanged.
+    pub fn text_scale_factor(&self, window_scale_factor: f64) -> f64 {
+        window_scale_factor * self.render_targets.scale() as f64
+    }
+
+    /// Reconfigures everything sized after the window: the surface, the camera aspect, the
+    /// framebuffers with their depth textures, and the text viewport. A zero-sized window,
+    /// as a minimized one reports, only suspends rendering until a resize to a real size.
+    /// Returns whether the viewport can be drawn.
+    pub fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        new_size: PhysicalSize<u32>,
+    ) -> bool {
+        self.surface_suspended = !self
+            .surface
+            .resize(device, &mut self.surface_config, new_size);
+        if self.surface_suspended {
+            return false;
+        }
+        self.camera
+            .resize(new_size.width as f32, new_size.height as f32);
+        self.render_targets.resize(device, new_size);
+        self.rendertxt
+            .resize(queue, render_size(new_size, self.render_targets.scale()));
+        if let Some(scene) = self.render_targets.get(&RenderTargetKind::Scene) {
+            self.rendertxt
+                .set_format(device, queue, scene.color().texture.format());
+        }
+        self.reshape();
+        true
+    }
+    /// Resizes to `new_size` at the start of the next frame, see
+    /// [`ViewportContext::apply_resize`]. Cheap, so every resize event can call it.
+    pub fn request_resize(&mut self, new_size: PhysicalSize<u32>) {
+        self.pending_resize.request(new_size);
+    }
+    /// The size the surface is configured at, `None` while suspended.
+    pub fn applied_size(&self) -> Option<PhysicalSize<u32>> {
+        (!self.surface_suspended)
+            .then(|| PhysicalSize::new(self.surface_config.width, self.surface_config.height))
+    }
+    /// Resizes to the last size requested since the previous frame, unless it is the size
+    /// already applied. Returns whether the viewport was resized and can be drawn.
+    pub fn apply_resize(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) -> bool {
+        match self.pending_resize.take(self.applied_size()) {
+            Some(new_size) => self.resize(device, queue, new_size),
+            None => false,
+        }
+    }
+    /// Replaces a lost surface with a new one for the window and resizes to the window.
+    pub fn recreate_surface(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        log_warning!("Surface lost, recreating it");
+        match create_surface(&self.window) {
+            Ok(surface) => {
+                self.surface = surface;
+                self.resize(device, queue, self.window.inner_size());
+            }
+            Err(e) => log_error!("Recreating the lost surface: {}", e),
+        }
+    }
+    /// The next surface texture, or `None` when this viewport skips the frame. An outdated
+    /// or lost surface is fixed up for the next frame; running out of memory stops the app.
+    pub fn acquire(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Option<wgpu::SurfaceTexture> {
+        if self.surface_suspended {
+            return None;
+        }
+        match self.surface.texture() {
+            Ok(frame) => Some(frame),
+            Err(wgpu::SurfaceError::Outdated) => {
+                self.resize(device, queue, self.window.inner_size());
+                None
+            }
+            Err(wgpu::SurfaceError::Lost) => {
+                self.recreate_surface(device, queue);
+                None
+            }
+            Err(wgpu::SurfaceError::OutOfMemory) => {
+                log_error!("Out of memory acquiring the surface texture, shutting down");
+                World::stop();
+                None
+            }
+            Err(e) => {
+                log_error!("SurfaceError: {}", e);
+                None
+            }
+        }
+    }
+    /// Lays the text out again on the next update.
+    pub fn reshape(&mut self) {
+        self.last_shape_time = Instant::now() - Duration::from_secs(2);
+    }
+    /// Whether the text is due for another layout; once a second, or after a reshape.
+    pub fn shape_due(&mut self) -> bool {
+        let due = self.last_shape_time.elapsed().as_millis() > 1000;
+        if due {
+            self.last_shape_time = Instant::now();
+        }
+        due
+    }
+
+    /// Places a map viewport's camera above `focus`, looking down with a slight tilt so the
+    /// view never lines up with the camera's up axis.
+    pub fn follow(&mut self, focus: Vec3, dt: f32) {
+        if let ViewportKind::Map { height } = self.kind {
+            let eye = focus + Vec3::new(0.0, height, height * 0.01);
+            self.camera.set_override(Some((eye, focus)));
+            self.camera.update_effects(dt);
+        }
+    }
+    /// Moves a map viewport's camera closer or further by `lines` of scrolling.
+    pub fn zoom(&mut self, lines: f32) {
+        if let ViewportKind::Map { height } = &mut self.kind {
+            let (min, max) = ViewportKind::MAP_HEIGHT_RANGE;
+            *height = (*height * 0.9f32.powf(lines)).clamp(min, max);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feeds each frame's resize events through a [`PendingResize`], then applies what it
+    /// settled on the way [`ViewportContext::apply_resize`] does. Returns the sizes applied.
+    fn run(start: (u32, u32), frames: &[&[(u32, u32)]]) -> Vec<PhysicalSize<u32>> {
+        let mut current = PhysicalSize::new(start.0, start.1);
+        let mut pending = PendingResize::default();
+        let mut applied = Vec::new();
+        for events in frames {
+            for &(width, height) in *events {
+                pending.request(PhysicalSize::new(width, height));
+            }
+            if let Some(size) = pending.take(Some(current)) {
+                current = size;
+                applied.push(size);
+            }
+        }
+        applied
+    }
+
+    #[test]
+    fn a_drag_applies_once_per_frame_at_the_last_size() {
+        let drag: &[(u32, u32)] = &[(801, 600), (805, 602), (812, 610), (820, 615)];
+        assert_eq!(
+            run((800, 600), &[drag, &[(830, 620), (840, 630)], &[]]),
+            vec![PhysicalSize::new(820, 615), PhysicalSize::new(840, 630)]
+        );
+    }
+
+    #[test]
+    fn repeated_and_round_trip_sizes_are_skipped() {
+        assert!(run((800, 600), &[&[(800, 600)], &[(900, 700), (800, 600)], &[]]).is_empty());
+        assert_eq!(
+            run((800, 600), &[&[(900, 700)], &[(900, 700)], &[(900, 700)]]),
+            vec![PhysicalSize::new(900, 700)]
+        );
+    }
+
+    #[test]
+    fn a_taken_size_is_cleared() {
+        let mut pending = PendingResize::default();
+        assert_eq!(pending.take(None), None);
+        pending.request(PhysicalSize::new(10, 10));
+        assert_eq!(pending.take(None), Some(PhysicalSize::new(10, 10)));
+        assert_eq!(pending.take(None), None);
+    }
+}
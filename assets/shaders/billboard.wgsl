@@ -0,0 +1,61 @@
+struct Camera {
+    view_proj: mat4x4<f32>,
+    inv_proj:  mat4x4<f32>,
+    inv_view:  mat4x4<f32>,
+    view_pos:  vec3<f32>,
+    _pad:      f32,
+};
+
+@group(0) @binding(0) var<uniform> camera: Camera;
+
+@group(1) @binding(0) var t_sprite: texture_2d<f32>;
+@group(1) @binding(1) var s_sprite: sampler;
+
+
+struct InstanceInput {
+    @location(0) center: vec3<f32>,
+    @location(1) size:   vec2<f32>,
+    @location(2) tint:   vec4<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) tint: vec4<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32, instance: InstanceInput) -> VertexOutput {
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(-1.0, -1.0),
+        vec2<f32>( 1.0, -1.0),
+        vec2<f32>( 1.0,  1.0),
+        vec2<f32>(-1.0, -1.0),
+        vec2<f32>( 1.0,  1.0),
+        vec2<f32>(-1.0,  1.0),
+    );
+    let corner = corners[index];
+
+    // The camera's own axes, so the quad faces it from any angle.
+    let right = camera.inv_view[0].xyz;
+    let up = camera.inv_view[1].xyz;
+    let half_size = instance.size * 0.5;
+    let world_position = instance.center
+        + right * corner.x * half_size.x
+        + up * corner.y * half_size.y;
+
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * vec4<f32>(world_position, 1.0);
+    out.uv = vec2<f32>(corner.x * 0.5 + 0.5, 0.5 - corner.y * 0.5);
+    out.tint = instance.tint;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(t_sprite, s_sprite, in.uv) * in.tint;
+    if (color.a < 0.5) {
+        discard;
+    }
+    return vec4<f32>(color.rgb, 1.0);
+}
@@ -0,0 +1,105 @@
+// Steps of BloomPass in bloom.rs: bright pass, downsample, separable blur and the
+// additive composite. Each draws a fullscreen triangle sampling group 0.
+
+struct VertexOutput {
+    @location(0) uv: vec2<f32>,
+    @builtin(position) clip_position: vec4<f32>,
+};
+
+@vertex
+fn vs_main(
+    @builtin(vertex_index) vi: u32,
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.uv = vec2<f32>(
+        f32((vi << 1u) & 2u),
+        f32(vi & 2u),
+    );
+    out.clip_position = vec4<f32>(out.uv * 2.0 - 1.0, 0.0, 1.0);
+    out.uv.y = 1.0 - out.uv.y;
+    return out;
+}
+
+@group(0)
+@binding(0)
+var source: texture_2d<f32>;
+
+@group(0)
+@binding(1)
+var source_sampler: sampler;
+
+// See BloomUniform in bloom.rs
+struct Bloom {
+    threshold: f32,
+    knee: f32,
+    intensity: f32,
+    _pad: f32,
+};
+
+@group(1)
+@binding(0)
+var<uniform> bloom: Bloom;
+
+fn luminance(color: vec3<f32>) -> f32 {
+    return dot(color, vec3(0.2126, 0.7152, 0.0722));
+}
+
+// Keeps what's brighter than the threshold, fading in over the knee below it so the
+// cutoff doesn't band.
+@fragment
+fn fs_bright(vs: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(source, source_sampler, vs.uv).rgb;
+    let lum = luminance(color);
+    let knee = max(bloom.knee, 1e-4);
+    let soft = clamp(lum - bloom.threshold + knee, 0.0, 2.0 * knee);
+    let curve = soft * soft / (4.0 * knee);
+    let weight = max(curve, lum - bloom.threshold) / max(lum, 1e-4);
+    return vec4(color * weight, 1.0);
+}
+
+// Four bilinear taps between the source texels: a 4x4 box at half the size.
+@fragment
+fn fs_down(vs: VertexOutput) -> @location(0) vec4<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(source));
+    var sum = textureSample(source, source_sampler, vs.uv + texel * vec2(-1.0, -1.0)).rgb;
+    sum += textureSample(source, source_sampler, vs.uv + texel * vec2(1.0, -1.0)).rgb;
+    sum += textureSample(source, source_sampler, vs.uv + texel * vec2(-1.0, 1.0)).rgb;
+    sum += textureSample(source, source_sampler, vs.uv + texel * vec2(1.0, 1.0)).rgb;
+    return vec4(sum * 0.25, 1.0);
+}
+
+// 9-tap gaussian in five samples, using bilinear filtering for the in-between taps.
+fn blur(uv: vec2<f32>, direction: vec2<f32>) -> vec3<f32> {
+    let texel = direction / vec2<f32>(textureDimensions(source));
+    let near = texel * 1.3846153846;
+    let far = texel * 3.2307692308;
+    var sum = textureSample(source, source_sampler, uv).rgb * 0.2270270270;
+    sum += textureSample(source, source_sampler, uv + near).rgb * 0.3162162162;
+    sum += textureSample(source, source_sampler, uv - near).rgb * 0.3162162162;
+    sum += textureSample(source, source_sampler, uv + far).rgb * 0.0702702703;
+    sum += textureSample(source, source_sampler, uv - far).rgb * 0.0702702703;
+    return sum;
+}
+
+@fragment
+fn fs_blur_h(vs: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4(blur(vs.uv, vec2(1.0, 0.0)), 1.0);
+}
+
+@fragment
+fn fs_blur_v(vs: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4(blur(vs.uv, vec2(0.0, 1.0)), 1.0);
+}
+
+// The untouched input, under the additive levels.
+@fragment
+fn fs_copy(vs: VertexOutput) -> @location(0) vec4<f32> {
+    return textureSample(source, source_sampler, vs.uv);
+}
+
+// One blurred level, blended additively.
+@fragment
+fn fs_add(vs: VertexOutput) -> @location(0) vec4<f32> {
+    let glow = textureSample(source, source_sampler, vs.uv).rgb * bloom.intensity;
+    return vec4(glow, 0.0);
+}
@@ -15,17 +15,30 @@ struct Camera {
 struct Light {
     position: vec3<f32>,
     color:    vec3<f32>,
+    // Premultiplied by its intensity, see Light::uniform in light.rs
+    ambient:  vec3<f32>,
 };
 @group(0) @binding(1) var<uniform> light: Light;
 
+// See DebugUniform in debug.rs
 struct Debug {
-    mode:  u32,
-    pad0:  vec3<f32>,
-    znear: f32,
-    pad1:  vec3<f32>,
-    zfar:  f32,
+    mode:          u32,
+    normal_length: f32,
+    pad0:          vec2<f32>,
+    zfar:          f32,
+    pad1:          f32,
+    pad2:          vec2<f32>,
+    znear:         f32,
+    pad3:          f32,
+    pad4:          vec2<f32>,
 };
 
+// DebugMode::WIREFRAME, DebugMode::NORMALS and DebugMode::REFLECTIONS
+const MODE_WIREFRAME:   u32 = 7u;
+const MODE_NORMALS:     u32 = 8u;
+const MODE_REFLECTIONS: u32 = 9u;
+const WIRE_COLOR = vec3<f32>(0.2, 1.0, 0.4);
+
 @group(0) @binding(2) var<uniform> debug: Debug;
 
 // --------------------------------------------------
@@ -44,12 +57,13 @@ struct InstanceInput {
     @location(6)  model_1: vec4<f32>,
     @location(7)  model_2: vec4<f32>,
     @location(8)  model_3: vec4<f32>,
-    @location(9)  color: vec3<f32>,
+    @location(9)  color: vec4<f32>,
     @location(10) translation: vec3<f32>,
     @location(11) uv_offset: vec2<f32>,
     @location(12) normal: vec3<f32>,
     @location(13) tangent: vec3<f32>,
-    @location(14) material_id: u32,
+    // x: material index, y: instance flags, see VertexInstance in vertex.rs
+    @location(14) material: vec2<u32>,
 };
 
 struct VertexOutput {
@@ -59,18 +73,22 @@ struct VertexOutput {
     @location(2) world_view_pos:    vec3<f32>,
     @location(3) world_normal:      vec3<f32>,
     @location(4) world_tangent:     vec3<f32>,
-    @location(5) tint_color:        vec3<f32>,
+    @location(5) tint_color:        vec4<f32>,
     @location(6) material_id:       u32,
+    // Raw vertex color, barycentric coordinates in the wireframe fallback's triangle lists
+    @location(7) barycentric:       vec3<f32>,
 };
 
 @group(1) @binding(0) var env_map:    texture_cube<f32>;
 @group(1) @binding(1) var env_samp:   sampler;
 
+// See MaterialData in material.rs
 struct Material {
     ambient:   vec3<f32>,
     diffuse:   vec3<f32>,
     specular:  vec3<f32>,
     shininess: f32,
+    flags:     u32,
 };
 @group(2) @binding(0) var<storage, read> materials: array<Material>;
 
@@ -80,6 +98,49 @@ struct Material {
 @group(3) @binding(2) var t_normal:  texture_2d<f32>;
 @group(3) @binding(3) var s_normal:  sampler;
 
+// Same as v_normal.wgsl
+fn unpack_normal(sampled: vec4<f32>) -> vec3<f32> {
+    let xy = sampled.xy * 2.0 - 1.0;
+    if (sampled.z == 0.0) {
+        return vec3<f32>(xy, sqrt(max(1.0 - dot(xy, xy), 0.0)));
+    }
+    return sampled.xyz * 2.0 - 1.0;
+}
+
+// MaterialData::NO_REFLECTIONS
+const MATERIAL_NO_REFLECTIONS: u32 = 1u;
+// EnvironmentReflection::MAX_SHININESS
+const MAX_SHININESS: f32 = 256.0;
+
+// Same as v_normal.wgsl
+fn environment_reflection(material: Material, normal: vec3<f32>, view_dir: vec3<f32>) -> vec3<f32> {
+    if ((material.flags & MATERIAL_NO_REFLECTIONS) != 0u) {
+        return vec3<f32>(0.0);
+    }
+    let gloss = clamp(log2(max(material.shininess, 1.0)) / log2(MAX_SHININESS), 0.0, 1.0);
+    let lod = (1.0 - gloss) * f32(textureNumLevels(env_map) - 1u);
+    let world_reflect = reflect(-view_dir, normal);
+    let environment = textureSampleLevel(env_map, env_samp, world_reflect, lod).rgb;
+
+    let fresnel = pow(1.0 - max(dot(normal, view_dir), 0.0), 5.0);
+    let reflectivity = material.specular + (max(vec3<f32>(gloss), material.specular) - material.specular) * fresnel;
+    return environment * reflectivity;
+}
+
+// Normal transform of `model` up to scale, see normal_matrix in v_normal.wgsl.
+fn normal_matrix(model: mat4x4<f32>) -> mat3x3<f32> {
+    let x = model[0].xyz;
+    let y = model[1].xyz;
+    let z = model[2].xyz;
+    let cofactor = mat3x3<f32>(cross(y, z), cross(z, x), cross(x, y));
+    return cofactor * select(1.0, -1.0, dot(x, cross(y, z)) < 0.0);
+}
+
+fn world_tangent(model: mat4x4<f32>, tangent: vec3<f32>, normal: vec3<f32>) -> vec3<f32> {
+    let t = (model * vec4<f32>(tangent, 0.0)).xyz;
+    return normalize(t - dot(t, normal) * normal);
+}
+
 @vertex
 fn vs_main(
     vertex: VertexInput,
@@ -92,19 +153,14 @@ fn vs_main(
         instance.model_2,
         instance.model_3,
     );
-    let normal_matrix = mat3x3<f32>(
-        instance.tangent,
-        cross(instance.normal, instance.tangent),
-        instance.normal,
-    );
 
     // World space position
     let world_pos4 = model_matrix * vec4<f32>(vertex.position, 1.0);
     let world_pos = world_pos4.xyz + instance.translation;
 
     // Transform normals and tangent
-    let wn = normalize(normal_matrix * vertex.normal);
-    let wt = normalize(normal_matrix * vertex.tangent);
+    let wn = normalize(normal_matrix(model_matrix) * vertex.normal);
+    let wt = world_tangent(model_matrix, vertex.tangent, wn);
 
     var out: VertexOutput;
     out.clip_position   = camera.view_proj * world_pos4;
@@ -113,8 +169,9 @@ fn vs_main(
     out.world_view_pos  = camera.view_pos;
     out.world_normal    = wn;
     out.world_tangent   = wt;
-    out.tint_color      = vertex.color * instance.color;
-    out.material_id     = instance.material_id;
+    out.tint_color      = vec4<f32>(vertex.color, 1.0) * instance.color;
+    out.material_id     = instance.material.x;
+    out.barycentric     = vertex.color;
 
     return out;
 }
@@ -124,7 +181,7 @@ fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
     // Camera view direction (normalize view - position)
     let view_dir = normalize(in.world_view_pos - in.world_position);
 
-    var out_color = vec4<f32>(in.tint_color.r, in.tint_color.g, in.tint_color.b, 1.0);
+    var out_color = in.tint_color;
 
     switch debug.mode {
         case 0u: {
@@ -169,9 +226,84 @@ fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
             let mid = f32(in.material_id) / 16.0; // assuming <=16 materials
             out_color = vec4<f32>(mid, 1.0 - mid, 0.3 + 0.7 * mid, 1.0);
         }
+        case MODE_WIREFRAME: {
+            // PolygonMode::Line rasterizes only the edges
+            out_color = vec4<f32>(WIRE_COLOR, 1.0);
+        }
+        case MODE_NORMALS: {
+            // Plain diffuse, so the normal segments stand out
+            let N = normalize(in.world_normal);
+            let L = normalize(light.position - in.world_position);
+            let shade = 0.2 + 0.5 * max(dot(N, L), 0.0);
+            out_color = vec4<f32>(vec3<f32>(shade), 1.0);
+        }
+        case MODE_REFLECTIONS: {
+            // The environment reflection term alone, normal mapped as in v_normal.wgsl
+            let world_tangent = normalize(in.world_tangent - dot(in.world_tangent, in.world_normal) * in.world_normal);
+            let TBN = mat3x3(world_tangent, cross(in.world_normal, world_tangent), in.world_normal);
+            let tangent_normal = unpack_normal(textureSample(t_normal, s_normal, in.tex_coords));
+            let N = normalize(TBN * tangent_normal);
+            let reflection = environment_reflection(materials[in.material_id], N, view_dir);
+            out_color = vec4<f32>(reflection, 1.0);
+        }
         default: {
             out_color = vec4<f32>(1.0, 0.0, 1.0, 1.0); // magenta, error
         }
     }
     return out_color;
 }
+
+// Wireframe without PolygonMode::Line: keeps the fragments near a triangle edge, where one
+// barycentric coordinate goes to zero, antialiased over about a pixel.
+@fragment
+fn fs_wireframe(in: VertexOutput) -> @location(0) vec4<f32> {
+    let width = fwidth(in.barycentric);
+    let edge = smoothstep(vec3<f32>(0.0), width * 1.5, in.barycentric);
+    let coverage = 1.0 - min(min(edge.x, edge.y), edge.z);
+    if (coverage < 0.01) {
+        discard;
+    }
+    return vec4<f32>(WIRE_COLOR, coverage);
+}
+
+// --------------------------------------------------
+// Normals: one segment per vertex, see NormalLineVertex in debug.rs
+// --------------------------------------------------
+
+struct NormalLineInput {
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) tip: f32,
+};
+
+struct NormalLineOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) color: vec3<f32>,
+};
+
+@vertex
+fn vs_normals(
+    line: NormalLineInput,
+    instance: InstanceInput
+) -> NormalLineOutput {
+    let model_matrix = mat4x4<f32>(
+        instance.model_0,
+        instance.model_1,
+        instance.model_2,
+        instance.model_3,
+    );
+    // Scaled models keep the segments normal_length long, and perpendicular to the surface
+    let direction = normalize(normal_matrix(model_matrix) * line.normal);
+    let base = (model_matrix * vec4<f32>(line.position, 1.0)).xyz;
+    let world_pos = base + direction * debug.normal_length * line.tip;
+
+    var out: NormalLineOutput;
+    out.clip_position = camera.view_proj * vec4<f32>(world_pos, 1.0);
+    out.color = direction * 0.5 + 0.5;
+    return out;
+}
+
+@fragment
+fn fs_normals(in: NormalLineOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(in.color, 1.0);
+}
@@ -0,0 +1,67 @@
+// Derives the vertex buffers DebugMode draws from a mesh's vertex and index buffers: a
+// segment along each vertex normal, or the triangles unindexed with barycentric colors.
+
+// See DebugGeometryParams in debug.rs
+struct Params {
+    vertex_count: u32,
+    index_count: u32,
+    wide_indices: u32,
+    _pad: u32,
+};
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var<storage, read> vertices: array<u32>;
+@group(0) @binding(2) var<storage, read> indices: array<u32>;
+@group(0) @binding(3) var<storage, read_write> out: array<u32>;
+
+// Vertex in vertex.rs, in words, and where its color and normal start
+const VERTEX_WORDS: u32 = 16u;
+const COLOR: u32 = 3u;
+const NORMAL: u32 = 8u;
+// NormalLineVertex in debug.rs, in words
+const LINE_WORDS: u32 = 8u;
+
+// Two line vertices per mesh vertex, at the vertex and at its tip.
+@compute @workgroup_size(64)
+fn cs_normals(@builtin(global_invocation_id) id: vec3<u32>) {
+    let i = id.x;
+    if (i >= params.vertex_count) {
+        return;
+    }
+    let src = i * VERTEX_WORDS;
+    for (var tip = 0u; tip < 2u; tip++) {
+        let dst = (i * 2u + tip) * LINE_WORDS;
+        for (var w = 0u; w < 3u; w++) {
+            out[dst + w] = vertices[src + w];
+            out[dst + 3u + w] = vertices[src + NORMAL + w];
+        }
+        out[dst + 6u] = bitcast<u32>(f32(tip));
+        out[dst + 7u] = 0u;
+    }
+}
+
+// Index k of the mesh; Uint16 indices are packed two to a word.
+fn index_at(k: u32) -> u32 {
+    if (params.wide_indices != 0u) {
+        return indices[k];
+    }
+    return (indices[k / 2u] >> ((k % 2u) * 16u)) & 0xffffu;
+}
+
+// The vertex behind each index, its color replaced by the corner's barycentric coordinate.
+@compute @workgroup_size(64)
+fn cs_triangles(@builtin(global_invocation_id) id: vec3<u32>) {
+    let k = id.x;
+    if (k >= params.index_count) {
+        return;
+    }
+    let src = index_at(k) * VERTEX_WORDS;
+    let dst = k * VERTEX_WORDS;
+    for (var w = 0u; w < VERTEX_WORDS; w++) {
+        out[dst + w] = vertices[src + w];
+    }
+    let corner = k % 3u;
+    for (var c = 0u; c < 3u; c++) {
+        out[dst + COLOR + c] = bitcast<u32>(select(0.0, 1.0, c == corner));
+    }
+}
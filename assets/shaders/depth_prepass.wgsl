@@ -0,0 +1,30 @@
+// Depth-only pre-pass for opaque geometry with the standard vertex and instance layouts.
+// The clip position is computed exactly like the material shaders do and is invariant in
+// both, so the main pass can test for equal depth.
+
+struct Camera {
+    view_proj: mat4x4<f32>,
+};
+@group(0) @binding(0) var<uniform> camera: Camera;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+};
+struct InstanceInput {
+    @location(5) model_0: vec4<f32>,
+    @location(6) model_1: vec4<f32>,
+    @location(7) model_2: vec4<f32>,
+    @location(8) model_3: vec4<f32>,
+};
+
+@vertex
+fn vs_main(vertex: VertexInput, instance: InstanceInput) -> @builtin(position) @invariant vec4<f32> {
+    let model_matrix = mat4x4<f32>(
+        instance.model_0,
+        instance.model_1,
+        instance.model_2,
+        instance.model_3,
+    );
+    let world_pos4 = model_matrix * vec4<f32>(vertex.position, 1.0);
+    return camera.view_proj * world_pos4;
+}
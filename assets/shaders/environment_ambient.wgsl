@@ -0,0 +1,38 @@
+// Averages one mip of the reflection cubemap into a single color, see
+// EnvironmentReflection::average_color in environment.rs. A single workgroup strides over
+// every texel of the six faces, then sums the partial results in shared memory. Texels
+// count equally, so the corners of each face weigh in a little more than their solid angle.
+
+const GROUP_SIZE: u32 = 64u;
+
+@group(0) @binding(0) var src: texture_2d_array<f32>;
+@group(0) @binding(1) var<storage, read_write> average: vec4<f32>;
+
+var<workgroup> partial: array<vec3<f32>, GROUP_SIZE>;
+
+@compute @workgroup_size(64)
+fn average_color(@builtin(local_invocation_index) index: u32) {
+    let size = textureDimensions(src);
+    let per_face = size.x * size.y;
+    let count = per_face * textureNumLayers(src);
+
+    var sum = vec3<f32>(0.0);
+    for (var i = index; i < count; i = i + GROUP_SIZE) {
+        let face = i / per_face;
+        let texel = i % per_face;
+        let pixel = vec2<u32>(texel % size.x, texel / size.x);
+        sum = sum + textureLoad(src, pixel, face, 0).rgb;
+    }
+    partial[index] = sum;
+    workgroupBarrier();
+
+    for (var stride = GROUP_SIZE / 2u; stride > 0u; stride = stride / 2u) {
+        if (index < stride) {
+            partial[index] = partial[index] + partial[index + stride];
+        }
+        workgroupBarrier();
+    }
+    if (index == 0u) {
+        average = vec4<f32>(partial[0] / f32(count), 1.0);
+    }
+}
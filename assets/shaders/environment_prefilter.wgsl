@@ -0,0 +1,64 @@
+// Convolves the environment cubemap into one mip of the reflection cubemap, see
+// EnvironmentReflection in environment.rs. Each texel averages the source over a lobe of
+// `params.shininess` around its direction, importance sampled so sharp mips stay sharp.
+
+const PI: f32 = 3.1415926535897932384626433832795;
+const SAMPLE_COUNT: u32 = 64u;
+
+struct Params {
+    shininess: f32,
+};
+
+@group(0) @binding(0) var src: texture_cube<f32>;
+@group(0) @binding(1) var src_sampler: sampler;
+@group(0) @binding(2) var dst: texture_storage_2d_array<rgba32float, write>;
+@group(0) @binding(3) var<uniform> params: Params;
+
+// Direction through texel `st` (-1..1, y down) of `face`, in the order and orientation
+// cube sampling uses, so sampling the result along a direction finds that direction's texel.
+fn face_direction(face: u32, st: vec2<f32>) -> vec3<f32> {
+    switch face {
+        case 0u: { return vec3(1.0, -st.y, -st.x); }
+        case 1u: { return vec3(-1.0, -st.y, st.x); }
+        case 2u: { return vec3(st.x, 1.0, st.y); }
+        case 3u: { return vec3(st.x, -1.0, -st.y); }
+        case 4u: { return vec3(st.x, -st.y, 1.0); }
+        default: { return vec3(-st.x, -st.y, -1.0); }
+    }
+}
+
+// Hammersley point `i` of SAMPLE_COUNT.
+fn hammersley(i: u32) -> vec2<f32> {
+    return vec2(f32(i) / f32(SAMPLE_COUNT), f32(reverseBits(i)) * 2.3283064365386963e-10);
+}
+
+@compute
+@workgroup_size(8, 8, 1)
+fn prefilter(
+    @builtin(global_invocation_id)
+    gid: vec3<u32>,
+) {
+    let size = textureDimensions(dst);
+    if gid.x >= size.x || gid.y >= size.y {
+        return;
+    }
+
+    let st = (vec2<f32>(gid.xy) + 0.5) / vec2<f32>(size) * 2.0 - 1.0;
+    let n = normalize(face_direction(gid.z, st));
+    let up = select(vec3(0.0, 0.0, 1.0), vec3(1.0, 0.0, 0.0), abs(n.z) > 0.999);
+    let tangent = normalize(cross(up, n));
+    let bitangent = cross(n, tangent);
+
+    var sum = vec3<f32>(0.0);
+    for (var i = 0u; i < SAMPLE_COUNT; i++) {
+        let xi = hammersley(i);
+        // cos^shininess lobe around n
+        let cos_theta = pow(1.0 - xi.x, 1.0 / (params.shininess + 1.0));
+        let sin_theta = sqrt(max(1.0 - cos_theta * cos_theta, 0.0));
+        let phi = 2.0 * PI * xi.y;
+        let dir = tangent * (cos(phi) * sin_theta) + bitangent * (sin(phi) * sin_theta) + n * cos_theta;
+        sum += textureSampleLevel(src, src_sampler, dir, 0.0).rgb;
+    }
+
+    textureStore(dst, gid.xy, gid.z, vec4(sum / f32(SAMPLE_COUNT), 1.0));
+}
@@ -16,6 +16,9 @@ var env_map: texture_cube<f32>;
 @binding(1)
 var env_sampler: sampler;
 
+// Depth of the far plane under the active depth policy: 1.0, or 0.0 with reverse-Z.
+override FAR_DEPTH: f32 = 1.0;
+
 struct VertexOutput {
     @builtin(position) frag_position: vec4<f32>,
     @location(0) clip_position: vec4<f32>,
@@ -30,7 +33,7 @@ fn vs_main(
         (id >> 1u) & 1u,
     ));
     var out: VertexOutput;
-    out.clip_position = vec4(uv * 4.0 - 1.0, 1.0, 1.0);
+    out.clip_position = vec4(uv * 4.0 - 1.0, FAR_DEPTH, 1.0);
     out.frag_position = out.clip_position;
     return out;
 }
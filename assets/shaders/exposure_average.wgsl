@@ -0,0 +1,106 @@
+// --------------------------------------------------
+// Auto-exposure, pass 2: percentile-trimmed average of the histogram, eased into the
+// tonemap uniform. Clears the histogram for the next frame. Mirrors TonemapSettings in
+// exposure.rs.
+// --------------------------------------------------
+
+struct ExposureParams {
+    min_log_luminance: f32,
+    log_range:         f32,
+    low_percentile:    f32,
+    high_percentile:   f32,
+    speed_up:          f32,
+    speed_down:        f32,
+    dt:                f32,
+    compensation:      f32,
+    min_exposure:      f32,
+    max_exposure:      f32,
+    manual:            f32,
+    key:               f32,
+};
+
+struct Tonemap {
+    exposure:          f32,
+    average_luminance: f32,
+    _pad:              vec2<f32>,
+};
+
+@group(0) @binding(0) var<storage, read_write> histogram: array<atomic<u32>, 256>;
+@group(0) @binding(1) var<storage, read_write> tonemap: Tonemap;
+@group(0) @binding(2) var<uniform> params: ExposureParams;
+
+const MIN_LUMINANCE: f32 = 1.0 / 4096.0;
+
+var<workgroup> counts: array<u32, 256>;
+
+fn bin_log_luminance(bin: u32) -> f32 {
+    if (bin == 0u) {
+        return params.min_log_luminance;
+    }
+    return params.min_log_luminance + (f32(bin) - 0.5) / 254.0 * params.log_range;
+}
+
+fn clamp_exposure(exposure: f32) -> f32 {
+    return clamp(exposure, params.min_exposure, max(params.max_exposure, params.min_exposure));
+}
+
+@compute @workgroup_size(256)
+fn average_histogram(@builtin(local_invocation_index) lid: u32) {
+    counts[lid] = atomicExchange(&histogram[lid], 0u);
+    workgroupBarrier();
+
+    if (lid != 0u) {
+        return;
+    }
+
+    var total = 0.0;
+    for (var bin = 0u; bin < 256u; bin = bin + 1u) {
+        total = total + f32(counts[bin]);
+    }
+    if (total <= 0.0) {
+        return;
+    }
+
+    let low = total * clamp(params.low_percentile, 0.0, 1.0);
+    let high = max(total * clamp(params.high_percentile, 0.0, 1.0), low);
+    var seen = 0.0;
+    var weight = 0.0;
+    var sum = 0.0;
+    var fallback = -1.0;
+    for (var bin = 0u; bin < 256u; bin = bin + 1u) {
+        let count = f32(counts[bin]);
+        let kept = max(min(seen + count, high) - max(seen, low), 0.0);
+        sum = sum + kept * bin_log_luminance(bin);
+        weight = weight + kept;
+        seen = seen + count;
+        if (fallback < 0.0 && seen >= low) {
+            fallback = bin_log_luminance(bin);
+        }
+    }
+    var average_log = fallback;
+    if (weight > 0.0) {
+        average_log = sum / weight;
+    }
+    let average = exp2(average_log);
+
+    var target_exposure = params.key / max(average, MIN_LUMINANCE);
+    if (params.manual > 0.0) {
+        target_exposure = params.manual;
+    }
+    target_exposure = clamp_exposure(target_exposure * exp2(params.compensation));
+
+    var exposure = target_exposure;
+    if (params.manual <= 0.0) {
+        let current_ev = log2(max(tonemap.exposure, 1e-30));
+        let target_ev = log2(target_exposure);
+        var rate = params.speed_down;
+        if (target_ev > current_ev) {
+            rate = params.speed_up;
+        }
+        let t = 1.0 - exp(-max(params.dt, 0.0) * rate);
+        exposure = clamp_exposure(exp2(current_ev + (target_ev - current_ev) * t));
+    }
+
+    tonemap.exposure = exposure;
+    tonemap.average_luminance = average;
+}
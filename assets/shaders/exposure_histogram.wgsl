@@ -0,0 +1,59 @@
+// --------------------------------------------------
+// Auto-exposure, pass 1: log-luminance histogram of the scene target.
+// Bin 0 holds black; bins 1..255 split [min_log_luminance, min_log_luminance + log_range].
+// --------------------------------------------------
+
+struct ExposureParams {
+    min_log_luminance: f32,
+    log_range:         f32,
+    low_percentile:    f32,
+    high_percentile:   f32,
+    speed_up:          f32,
+    speed_down:        f32,
+    dt:                f32,
+    compensation:      f32,
+    min_exposure:      f32,
+    max_exposure:      f32,
+    manual:            f32,
+    key:               f32,
+};
+
+@group(0) @binding(0) var scene: texture_2d<f32>;
+@group(0) @binding(1) var<storage, read_write> histogram: array<atomic<u32>, 256>;
+@group(0) @binding(2) var<uniform> params: ExposureParams;
+
+// Mirrors MIN_LUMINANCE in exposure.rs
+const MIN_LUMINANCE: f32 = 1.0 / 4096.0;
+
+var<workgroup> local_bins: array<atomic<u32>, 256>;
+
+fn bin_of(color: vec3<f32>) -> u32 {
+    let lum = dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+    if (lum < MIN_LUMINANCE) {
+        return 0u;
+    }
+    let t = clamp((log2(lum) - params.min_log_luminance) / params.log_range, 0.0, 1.0);
+    return u32(t * 254.0) + 1u;
+}
+
+// Workgroup size mirrors HISTOGRAM_GROUP in exposure.rs
+@compute @workgroup_size(16, 16)
+fn build_histogram(
+    @builtin(global_invocation_id) gid: vec3<u32>,
+    @builtin(local_invocation_index) lid: u32,
+) {
+    atomicStore(&local_bins[lid], 0u);
+    workgroupBarrier();
+
+    let size = textureDimensions(scene);
+    if (gid.x < size.x && gid.y < size.y) {
+        let color = textureLoad(scene, vec2<i32>(gid.xy), 0).rgb;
+        atomicAdd(&local_bins[bin_of(color)], 1u);
+    }
+    workgroupBarrier();
+
+    let count = atomicLoad(&local_bins[lid]);
+    if (count > 0u) {
+        atomicAdd(&histogram[lid], count);
+    }
+}
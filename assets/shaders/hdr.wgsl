@@ -44,9 +44,20 @@ var hdr_image: texture_2d<f32>;
 @binding(1)
 var hdr_sampler: sampler;
 
+// Written by exposure_average.wgsl, see TonemapUniform in exposure.rs
+struct Tonemap {
+    exposure: f32,
+    average_luminance: f32,
+    _pad: vec2<f32>,
+};
+
+@group(1)
+@binding(0)
+var<uniform> tonemap: Tonemap;
+
 @fragment
 fn fs_main(vs: VertexOutput) -> @location(0) vec4<f32> {
     let hdr = textureSample(hdr_image, hdr_sampler, vs.uv);
-    let sdr = aces_tone_map(hdr.rgb);
+    let sdr = aces_tone_map(hdr.rgb * tonemap.exposure);
     return vec4(sdr, hdr.a);
 }
\ No newline at end of file
@@ -0,0 +1,93 @@
+struct Camera {
+    view_proj: mat4x4<f32>,
+    inv_proj:  mat4x4<f32>,
+    inv_view:  mat4x4<f32>,
+    view_pos:  vec3<f32>,
+    _pad:      f32,
+};
+
+@group(0) @binding(0) var<uniform> camera: Camera;
+
+
+struct Light {
+    position: vec3<f32>,
+    color: vec3<f32>,
+    // Premultiplied by its intensity, see Light::uniform in light.rs
+    ambient: vec3<f32>,
+}
+
+@group(0) @binding(1) var<uniform> light: Light;
+
+@group(1) @binding(0) var t_atlas: texture_2d<f32>;
+@group(1) @binding(1) var s_atlas: sampler;
+
+
+struct InstanceInput {
+    @location(0) center: vec3<f32>,
+    @location(1) size:   f32,
+    // x, y: the two cells to blend, z, w: atlas columns and rows
+    @location(2) cells:  vec4<u32>,
+    @location(3) tint:   vec4<f32>,
+    @location(4) weight: f32,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv_a: vec2<f32>,
+    @location(1) uv_b: vec2<f32>,
+    @location(2) tint: vec4<f32>,
+    @location(3) weight: f32,
+};
+
+fn cell_uv(cell: u32, grid: vec2<u32>, uv: vec2<f32>) -> vec2<f32> {
+    let origin = vec2<f32>(f32(cell % grid.x), f32(cell / grid.x));
+    return (origin + uv) / vec2<f32>(grid);
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32, instance: InstanceInput) -> VertexOutput {
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(-1.0, -1.0),
+        vec2<f32>( 1.0, -1.0),
+        vec2<f32>( 1.0,  1.0),
+        vec2<f32>(-1.0, -1.0),
+        vec2<f32>( 1.0,  1.0),
+        vec2<f32>(-1.0,  1.0),
+    );
+    let corner = corners[index];
+
+    // Turns around the vertical axis only, like the views were baked.
+    let up = vec3<f32>(0.0, 1.0, 0.0);
+    var to_camera = camera.view_pos - instance.center;
+    to_camera.y = 0.0;
+    if (dot(to_camera, to_camera) < 1e-6) {
+        to_camera = vec3<f32>(0.0, 0.0, 1.0);
+    }
+    let right = cross(up, normalize(to_camera));
+
+    let half_size = instance.size * 0.5;
+    let world_position = instance.center + (right * corner.x + up * corner.y) * half_size;
+
+    let uv = vec2<f32>(corner.x * 0.5 + 0.5, 0.5 - corner.y * 0.5);
+    let grid = instance.cells.zw;
+
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * vec4<f32>(world_position, 1.0);
+    out.uv_a = cell_uv(instance.cells.x, grid, uv);
+    out.uv_b = cell_uv(instance.cells.y, grid, uv);
+    out.tint = instance.tint;
+    out.weight = instance.weight;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let a = textureSample(t_atlas, s_atlas, in.uv_a);
+    let b = textureSample(t_atlas, s_atlas, in.uv_b);
+    let color = mix(a, b, in.weight);
+    if (color.a < 0.5) {
+        discard;
+    }
+    // No normals survive the bake; the sun color and the ambient stand in for lighting.
+    return vec4<f32>(color.rgb * in.tint.rgb * (light.color + light.ambient), 1.0);
+}
@@ -0,0 +1,44 @@
+struct View {
+    view_proj: mat4x4<f32>,
+};
+
+@group(0) @binding(0) var<uniform> view: View;
+
+@group(1) @binding(0) var t_diffuse: texture_2d<f32>;
+@group(1) @binding(1) var s_diffuse: sampler;
+
+struct Surface {
+    color: vec4<f32>,
+};
+
+@group(1) @binding(2) var<uniform> surface: Surface;
+
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) color: vec3<f32>,
+    @location(2) tex_coords: vec2<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+};
+
+@vertex
+fn vs_main(in: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = view.view_proj * vec4<f32>(in.position, 1.0);
+    out.tex_coords = in.tex_coords;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let texel = textureSample(t_diffuse, s_diffuse, in.tex_coords);
+    if (texel.a < 0.5) {
+        discard;
+    }
+    // Unlit; the impostor shader lights the result with the sun color at runtime.
+    return vec4<f32>(texel.rgb * surface.color.rgb, 1.0);
+}
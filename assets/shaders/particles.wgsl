@@ -0,0 +1,105 @@
+// --------------------------------------------------
+// Particles as camera-facing billboards, one instance per particle. Dead and unowned
+// particles collapse to a point and draw nothing.
+// --------------------------------------------------
+
+struct Camera {
+    view_proj: mat4x4<f32>,
+    inv_proj:  mat4x4<f32>,
+    inv_view:  mat4x4<f32>,
+    view_pos:  vec3<f32>,
+    _pad:      f32,
+};
+
+@group(0) @binding(0) var<uniform> camera: Camera;
+
+struct Particle {
+    position: vec3<f32>,
+    age:      f32,
+    velocity: vec3<f32>,
+    lifetime: f32,
+    emitter:  u32,
+    _pad0:    u32,
+    _pad1:    u32,
+    _pad2:    u32,
+};
+
+struct Emitter {
+    position:    vec3<f32>,
+    spawn:       u32,
+    velocity:    vec3<f32>,
+    spread:      f32,
+    gravity:     vec3<f32>,
+    drag:        f32,
+    start_color: vec4<f32>,
+    end_color:   vec4<f32>,
+    lifetime:    f32,
+    size:        f32,
+    first:       u32,
+    count:       u32,
+};
+
+// Mirrors ParticleSystem::MAX_EMITTERS in particles.rs
+struct Simulation {
+    dt:            f32,
+    seed:          u32,
+    emitter_count: u32,
+    _pad0:         u32,
+    shift:         vec3<f32>,
+    _pad1:         f32,
+    emitters:      array<Emitter, 64>,
+};
+
+@group(1) @binding(0) var<storage, read> particles: array<Particle>;
+@group(1) @binding(1) var<uniform> sim: Simulation;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) corner: vec2<f32>,
+    @location(1) color: vec4<f32>,
+};
+
+@vertex
+fn vs_main(
+    @builtin(vertex_index) index: u32,
+    @builtin(instance_index) instance: u32,
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(0.0, 0.0, 0.0, 1.0);
+    out.corner = vec2<f32>(0.0);
+    out.color = vec4<f32>(0.0);
+
+    let p = particles[instance];
+    if (p.emitter >= sim.emitter_count || p.age >= p.lifetime) {
+        return out;
+    }
+    let emitter = sim.emitters[p.emitter];
+
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(-1.0, -1.0),
+        vec2<f32>( 1.0, -1.0),
+        vec2<f32>( 1.0,  1.0),
+        vec2<f32>(-1.0, -1.0),
+        vec2<f32>( 1.0,  1.0),
+        vec2<f32>(-1.0,  1.0),
+    );
+    let corner = corners[index];
+    let right = camera.inv_view[0].xyz;
+    let up = camera.inv_view[1].xyz;
+    let world = p.position + (right * corner.x + up * corner.y) * (emitter.size * 0.5);
+
+    out.clip_position = camera.view_proj * vec4<f32>(world, 1.0);
+    out.corner = corner;
+    out.color = mix(emitter.start_color, emitter.end_color, p.age / p.lifetime);
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let falloff = 1.0 - smoothstep(0.5, 1.0, length(in.corner));
+    let alpha = in.color.a * falloff;
+    if (alpha <= 0.0) {
+        discard;
+    }
+    return vec4<f32>(in.color.rgb, alpha);
+}
@@ -0,0 +1,92 @@
+// --------------------------------------------------
+// Particle simulation: advances live particles and respawns dead ones in their emitter's
+// range, at most `spawn` per emitter per frame, counted through `spawned`.
+// --------------------------------------------------
+
+struct Particle {
+    position: vec3<f32>,
+    age:      f32,
+    velocity: vec3<f32>,
+    lifetime: f32,
+    emitter:  u32,
+    _pad0:    u32,
+    _pad1:    u32,
+    _pad2:    u32,
+};
+
+struct Emitter {
+    position:    vec3<f32>,
+    spawn:       u32,
+    velocity:    vec3<f32>,
+    spread:      f32,
+    gravity:     vec3<f32>,
+    drag:        f32,
+    start_color: vec4<f32>,
+    end_color:   vec4<f32>,
+    lifetime:    f32,
+    size:        f32,
+    first:       u32,
+    count:       u32,
+};
+
+// Mirrors ParticleSystem::MAX_EMITTERS in particles.rs
+struct Simulation {
+    dt:            f32,
+    seed:          u32,
+    emitter_count: u32,
+    _pad0:         u32,
+    shift:         vec3<f32>,
+    _pad1:         f32,
+    emitters:      array<Emitter, 64>,
+};
+
+@group(0) @binding(0) var<storage, read_write> particles: array<Particle>;
+@group(0) @binding(1) var<storage, read_write> spawned: array<atomic<u32>, 64>;
+@group(0) @binding(2) var<uniform> sim: Simulation;
+
+fn pcg(input: u32) -> u32 {
+    let state = input * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+fn unit(hash: u32) -> f32 {
+    return f32(hash >> 8u) / 16777216.0;
+}
+
+// Workgroup size mirrors WORKGROUP in particles.rs
+@compute @workgroup_size(64)
+fn simulate(@builtin(global_invocation_id) id: vec3<u32>) {
+    let i = id.x;
+    if (i >= arrayLength(&particles)) {
+        return;
+    }
+    var p = particles[i];
+    p.position += sim.shift;
+    if (p.emitter >= sim.emitter_count) {
+        particles[i] = p;
+        return;
+    }
+    let emitter = sim.emitters[p.emitter];
+
+    if (p.age < p.lifetime) {
+        p.velocity += emitter.gravity * sim.dt;
+        // Like physics, the medium only drags horizontal motion
+        let drag = pow(emitter.drag, sim.dt);
+        p.velocity.x *= drag;
+        p.velocity.z *= drag;
+        p.position += p.velocity * sim.dt;
+        p.age += sim.dt;
+    } else if (emitter.spawn > 0u && atomicAdd(&spawned[p.emitter], 1u) < emitter.spawn) {
+        let h0 = pcg(i ^ pcg(sim.seed));
+        let h1 = pcg(h0);
+        let h2 = pcg(h1);
+        let h3 = pcg(h2);
+        let jitter = vec3<f32>(unit(h0), unit(h1), unit(h2)) * 2.0 - 1.0;
+        p.position = emitter.position;
+        p.velocity = emitter.velocity + jitter * emitter.spread;
+        p.age = 0.0;
+        p.lifetime = emitter.lifetime * (0.75 + 0.5 * unit(h3));
+    }
+    particles[i] = p;
+}
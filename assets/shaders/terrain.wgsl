@@ -0,0 +1,270 @@
+// --------------------------------------------------
+// Blended terrain: v_normal.wgsl with the group 3 textures replaced by texture arrays,
+// one layer per terrain material.
+// --------------------------------------------------
+
+struct Camera {
+    view_proj: mat4x4<f32>,
+    inv_proj:  mat4x4<f32>,
+    inv_view:  mat4x4<f32>,
+    view_pos:  vec3<f32>,
+};
+@group(0) @binding(0) var<uniform> camera: Camera;
+
+struct Light {
+    position: vec3<f32>,
+    color:    vec3<f32>,
+    // Premultiplied by its intensity, see Light::uniform in light.rs
+    ambient:  vec3<f32>,
+};
+@group(0) @binding(1) var<uniform> light: Light;
+
+// --------------------------------------------------
+// Vertex inputs
+// --------------------------------------------------
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) color: vec3<f32>,
+    @location(2) tex_coords: vec2<f32>,
+    @location(3) normal: vec3<f32>,
+    @location(4) tangent: vec3<f32>,
+    // x: terrain layers (low 24 bits) + block light emission (top 8), y: blend weights
+    @location(15) surface: vec2<u32>,
+};
+struct InstanceInput {
+    @location(5)  model_0: vec4<f32>,
+    @location(6)  model_1: vec4<f32>,
+    @location(7)  model_2: vec4<f32>,
+    @location(8)  model_3: vec4<f32>,
+    @location(9)  color: vec4<f32>,
+    @location(10) translation: vec3<f32>,
+    @location(11) uv_offset: vec2<f32>,
+    @location(12) normal: vec3<f32>,
+    @location(13) tangent: vec3<f32>,
+    // x: material index, y: instance flags, see VertexInstance in vertex.rs
+    @location(14) material: vec2<u32>,
+};
+
+struct VertexOutput {
+    @builtin(position) @invariant clip_position:      vec4<f32>,
+    @location(0) tex_coords:        vec2<f32>,
+    @location(1) world_position:    vec3<f32>,
+    @location(2) world_view_pos:    vec3<f32>,
+    @location(3) world_normal:      vec3<f32>,
+    @location(4) world_tangent:     vec3<f32>,
+    @location(5) tint_color:        vec4<f32>,
+    @location(6) material_id:       u32,
+    @location(7) emission:          f32,
+    // Weight of every array layer (0-3, 4-7). Spread per layer rather than per vertex slot
+    // so corners with different layer sets still interpolate correctly.
+    @location(8) layer_weights_lo:  vec4<f32>,
+    @location(9) layer_weights_hi:  vec4<f32>,
+    @location(10) flags:            u32,
+};
+
+// Matches PerObjectData::SELECTED and VertexInstance::SELECTED.
+const INSTANCE_SELECTED: u32 = 2u;
+
+// Brightens a selected instance, most at its silhouette so it reads as an outline.
+fn selection_highlight(color: vec3<f32>, flags: u32, normal: vec3<f32>, view_dir: vec3<f32>) -> vec3<f32> {
+    if ((flags & INSTANCE_SELECTED) == 0u) {
+        return color;
+    }
+    let rim = pow(1.0 - max(dot(normal, view_dir), 0.0), 3.0);
+    return color * 1.3 + vec3<f32>(1.0, 0.8, 0.3) * rim;
+}
+
+const MAX_TERRAIN_LAYERS: u32 = 8u;
+// Mirrors BLEND_SHARPNESS in terrain_blend.rs
+const BLEND_SHARPNESS: f32 = 0.2;
+
+// Normal transform of `model` up to scale, see normal_matrix in v_normal.wgsl.
+fn normal_matrix(model: mat4x4<f32>) -> mat3x3<f32> {
+    let x = model[0].xyz;
+    let y = model[1].xyz;
+    let z = model[2].xyz;
+    let cofactor = mat3x3<f32>(cross(y, z), cross(z, x), cross(x, y));
+    return cofactor * select(1.0, -1.0, dot(x, cross(y, z)) < 0.0);
+}
+
+fn world_tangent(model: mat4x4<f32>, tangent: vec3<f32>, normal: vec3<f32>) -> vec3<f32> {
+    let t = (model * vec4<f32>(tangent, 0.0)).xyz;
+    return normalize(t - dot(t, normal) * normal);
+}
+
+@vertex
+fn vs_main(
+    vertex: VertexInput,
+    instance: InstanceInput
+) -> VertexOutput {
+    let model_matrix = mat4x4<f32>(
+        instance.model_0,
+        instance.model_1,
+        instance.model_2,
+        instance.model_3,
+    );
+
+    let world_pos4 = model_matrix * vec4<f32>(vertex.position, 1.0);
+    let world_pos = world_pos4.xyz + instance.translation;
+
+    let wn = normalize(normal_matrix(model_matrix) * vertex.normal);
+    let wt = world_tangent(model_matrix, vertex.tangent, wn);
+
+    var weights = array<f32, 8>(0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0);
+    for (var i = 0u; i < 3u; i = i + 1u) {
+        let layer = min((vertex.surface.x >> (i * 8u)) & 0xffu, MAX_TERRAIN_LAYERS - 1u);
+        let weight = f32((vertex.surface.y >> (i * 10u)) & 0x3ffu) / 1023.0;
+        weights[layer] = weights[layer] + weight;
+    }
+
+    var out: VertexOutput;
+    out.clip_position    = camera.view_proj * world_pos4;
+    out.tex_coords       = vertex.tex_coords + instance.uv_offset;
+    out.world_position   = world_pos;
+    out.world_view_pos   = camera.view_pos;
+    out.world_normal     = wn;
+    out.world_tangent    = wt;
+    out.tint_color       = vec4<f32>(vertex.color, 1.0) * instance.color;
+    out.material_id      = instance.material.x;
+    out.flags            = instance.material.y;
+    out.emission         = f32(vertex.surface.x >> 24u) / 15.0;
+    out.layer_weights_lo = vec4<f32>(weights[0], weights[1], weights[2], weights[3]);
+    out.layer_weights_hi = vec4<f32>(weights[4], weights[5], weights[6], weights[7]);
+
+    return out;
+}
+
+// --------------------------------------------------
+// Fragment inputs & bindings
+// --------------------------------------------------
+
+@group(1) @binding(0) var env_map:    texture_cube<f32>;
+@group(1) @binding(1) var env_samp:   sampler;
+
+// See MaterialData in material.rs
+struct Material {
+    ambient:   vec3<f32>,
+    diffuse:   vec3<f32>,
+    specular:  vec3<f32>,
+    shininess: f32,
+    flags:     u32,
+};
+@group(2) @binding(0) var<storage, read> materials: array<Material>;
+
+// MaterialData::NO_REFLECTIONS
+const MATERIAL_NO_REFLECTIONS: u32 = 1u;
+// EnvironmentReflection::MAX_SHININESS, the shininess of env_map's top mip
+const MAX_SHININESS: f32 = 256.0;
+
+// Environment seen along the normal's reflection of the view, from the env_map mip
+// prefiltered for the material's shininess, weighted by Schlick Fresnel over its specular.
+fn environment_reflection(material: Material, normal: vec3<f32>, view_dir: vec3<f32>) -> vec3<f32> {
+    if ((material.flags & MATERIAL_NO_REFLECTIONS) != 0u) {
+        return vec3<f32>(0.0);
+    }
+    let gloss = clamp(log2(max(material.shininess, 1.0)) / log2(MAX_SHININESS), 0.0, 1.0);
+    let lod = (1.0 - gloss) * f32(textureNumLevels(env_map) - 1u);
+    let world_reflect = reflect(-view_dir, normal);
+    let environment = textureSampleLevel(env_map, env_samp, world_reflect, lod).rgb;
+
+    let fresnel = pow(1.0 - max(dot(normal, view_dir), 0.0), 5.0);
+    let reflectivity = material.specular + (max(vec3<f32>(gloss), material.specular) - material.specular) * fresnel;
+    return environment * reflectivity;
+}
+
+@group(3) @binding(0) var t_layers:        texture_2d_array<f32>;
+@group(3) @binding(1) var s_layers:        sampler;
+@group(3) @binding(2) var t_layer_normals: texture_2d_array<f32>;
+@group(3) @binding(3) var s_layer_normals: sampler;
+// Per-layer priority, packed four to a vec4
+@group(3) @binding(4) var<uniform> priorities: array<vec4<f32>, 2>;
+
+fn layer_weight(in: VertexOutput, layer: u32) -> f32 {
+    if (layer < 4u) {
+        return in.layer_weights_lo[layer];
+    }
+    return in.layer_weights_hi[layer - 4u];
+}
+
+fn layer_priority(layer: u32) -> f32 {
+    return priorities[layer / 4u][layer % 4u];
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let material = materials[in.material_id];
+
+    // Three strongest layers at this fragment
+    var top = array<u32, 3>(0u, 0u, 0u);
+    var top_weight = array<f32, 3>(-1.0, -1.0, -1.0);
+    for (var layer = 0u; layer < MAX_TERRAIN_LAYERS; layer = layer + 1u) {
+        let w = layer_weight(in, layer);
+        if (w > top_weight[0]) {
+            top[2] = top[1]; top_weight[2] = top_weight[1];
+            top[1] = top[0]; top_weight[1] = top_weight[0];
+            top[0] = layer;  top_weight[0] = w;
+        } else if (w > top_weight[1]) {
+            top[2] = top[1]; top_weight[2] = top_weight[1];
+            top[1] = layer;  top_weight[1] = w;
+        } else if (w > top_weight[2]) {
+            top[2] = layer;  top_weight[2] = w;
+        }
+    }
+
+    // Priority blend (see priority_blend in terrain_blend.rs)
+    var height = array<f32, 3>(0.0, 0.0, 0.0);
+    var highest = -1.0e9;
+    for (var i = 0u; i < 3u; i = i + 1u) {
+        height[i] = top_weight[i] + layer_priority(top[i]);
+        if (top_weight[i] > 0.0) {
+            highest = max(highest, height[i]);
+        }
+    }
+    var blend = array<f32, 3>(0.0, 0.0, 0.0);
+    var sum = 0.0;
+    for (var i = 0u; i < 3u; i = i + 1u) {
+        if (top_weight[i] > 0.0) {
+            blend[i] = max(height[i] - highest + BLEND_SHARPNESS, 0.0);
+            sum = sum + blend[i];
+        }
+    }
+    if (sum <= 0.0) {
+        blend[0] = 1.0;
+        sum = 1.0;
+    }
+
+    var object_color = vec4<f32>(0.0);
+    var object_normal = vec4<f32>(0.0);
+    for (var i = 0u; i < 3u; i = i + 1u) {
+        let w = blend[i] / sum;
+        object_color = object_color + w * textureSample(t_layers, s_layers, in.tex_coords, top[i]);
+        object_normal = object_normal + w * textureSample(t_layer_normals, s_layer_normals, in.tex_coords, top[i]);
+    }
+
+    // TBN
+    let world_tangent = normalize(in.world_tangent - dot(in.world_tangent, in.world_normal) * in.world_normal);
+    let world_bitangent = cross(in.world_normal, world_tangent);
+    let TBN = mat3x3(world_tangent, world_bitangent, in.world_normal);
+
+    let tangent_normal = object_normal.xyz * 2.0 - 1.0;
+    let world_normal = normalize(TBN * tangent_normal);
+
+    let light_dir = normalize(light.position - in.world_position);
+    let view_dir = normalize(in.world_view_pos - in.world_position);
+    let half_dir = normalize(view_dir + light_dir);
+
+    let diffuse_strength = max(dot(world_normal, light_dir), 0.0);
+    let diffuse_color = material.diffuse * light.color * diffuse_strength;
+
+    let specular_strength = pow(max(dot(world_normal, half_dir), 0.0), material.shininess);
+    let specular_color =  material.specular * light.color * specular_strength;
+
+    let reflection = environment_reflection(material, world_normal, view_dir);
+
+    let emissive = object_color.xyz * in.tint_color.rgb * in.emission;
+    let lit_color = (light.ambient + diffuse_color + specular_color) * (object_color.xyz * in.tint_color.rgb) + reflection + emissive;
+    let final_color = selection_highlight(lit_color, in.flags, world_normal, view_dir);
+
+    return vec4<f32>(final_color, object_color.a * in.tint_color.a);
+}
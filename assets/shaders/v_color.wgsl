@@ -12,6 +12,8 @@ struct Camera {
 struct Light {
     position: vec3<f32>,
     color: vec3<f32>,
+    // Premultiplied by its intensity, see Light::uniform in light.rs
+    ambient: vec3<f32>,
 }
 
 @group(0) @binding(1) var<uniform> light: Light;
@@ -36,7 +38,7 @@ struct InstanceInput {
 };
 
 struct VertexOutput {
-    @builtin(position) clip_position: vec4<f32>,
+    @builtin(position) @invariant clip_position: vec4<f32>,
     @location(0) color: vec3<f32>,
     @location(1) world_position: vec3<f32>,
     @location(2) world_view_position: vec3<f32>,
@@ -97,7 +99,7 @@ fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
     let reflection = textureSample(env_map, env_sampler, world_reflect).rgb;
     let shininess = 0.1;
 
-    let final_color = (diffuse_color + specular_color) * in.color + reflection * shininess;
+    let final_color = (light.ambient + diffuse_color + specular_color) * in.color + reflection * shininess;
 
     return vec4<f32>(final_color, 1.0);
 }
\ No newline at end of file
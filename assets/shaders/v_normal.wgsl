@@ -13,6 +13,8 @@ struct Camera {
 struct Light {
     position: vec3<f32>,
     color:    vec3<f32>,
+    // Premultiplied by its intensity, see Light::uniform in light.rs
+    ambient:  vec3<f32>,
 };
 @group(0) @binding(1) var<uniform> light: Light;
 
@@ -26,36 +28,77 @@ struct VertexInput {
     @location(2) tex_coords: vec2<f32>,
     @location(3) normal: vec3<f32>,
     @location(4) tangent: vec3<f32>,
+    // x: terrain layers (low 24 bits) + block light emission (top 8), y: blend weights
+    @location(15) surface: vec2<u32>,
 };
 struct InstanceInput {
     @location(5)  model_0: vec4<f32>,
     @location(6)  model_1: vec4<f32>,
     @location(7)  model_2: vec4<f32>,
     @location(8)  model_3: vec4<f32>,
-    @location(9)  color: vec3<f32>,
+    @location(9)  color: vec4<f32>,
     @location(10) translation: vec3<f32>,
     @location(11) uv_offset: vec2<f32>,
     @location(12) normal: vec3<f32>,
     @location(13) tangent: vec3<f32>,
-    @location(14) material_id: u32,
+    // x: material index, y: instance flags, see VertexInstance in vertex.rs
+    @location(14) material: vec2<u32>,
 };
 
 struct VertexOutput {
-    @builtin(position) clip_position:      vec4<f32>,
+    @builtin(position) @invariant clip_position:      vec4<f32>,
     @location(0) tex_coords:        vec2<f32>,
     @location(1) world_position:    vec3<f32>,
     @location(2) world_view_pos:    vec3<f32>,
     @location(3) world_normal:      vec3<f32>,
     @location(4) world_tangent:     vec3<f32>,
-    @location(5) tint_color:        vec3<f32>,
+    @location(5) tint_color:        vec4<f32>,
     @location(6) material_id:       u32,
+    @location(7) emission:          f32,
+    @location(8) flags:             u32,
 };
 
+// Matches PerObjectData::SELECTED and VertexInstance::SELECTED.
+const INSTANCE_SELECTED: u32 = 2u;
+
+// Brightens a selected instance, most at its silhouette so it reads as an outline.
+fn selection_highlight(color: vec3<f32>, flags: u32, normal: vec3<f32>, view_dir: vec3<f32>) -> vec3<f32> {
+    if ((flags & INSTANCE_SELECTED) == 0u) {
+        return color;
+    }
+    let rim = pow(1.0 - max(dot(normal, view_dir), 0.0), 3.0);
+    return color * 1.3 + vec3<f32>(1.0, 0.8, 0.3) * rim;
+}
+
+// Inverse transpose of the model matrix's upper 3x3, up to scale: its cofactor matrix,
+// flipped for mirroring transforms. Unlike the model matrix itself, it keeps normals
+// perpendicular to their surface under non-uniform scale. Mirrors Transform::normal_matrix
+// in component.rs.
+fn normal_matrix(model: mat4x4<f32>) -> mat3x3<f32> {
+    let x = model[0].xyz;
+    let y = model[1].xyz;
+    let z = model[2].xyz;
+    let cofactor = mat3x3<f32>(cross(y, z), cross(z, x), cross(x, y));
+    return cofactor * select(1.0, -1.0, dot(x, cross(y, z)) < 0.0);
+}
+
+// Tangent under `model`, made perpendicular to the transformed `normal` again.
+fn world_tangent(model: mat4x4<f32>, tangent: vec3<f32>, normal: vec3<f32>) -> vec3<f32> {
+    let t = (model * vec4<f32>(tangent, 0.0)).xyz;
+    return normalize(t - dot(t, normal) * normal);
+}
+
 @vertex
 fn vs_main(
     vertex: VertexInput,
     instance: InstanceInput
 ) -> VertexOutput {
+    return instance_vertex(vertex, instance);
+}
+
+// Instanced path, shared by vs_main and the instance storage variant that fetches
+// InstanceInput by instance_index instead of reading it from attributes.
+fn instance_vertex(vertex: VertexInput, instance: InstanceInput) -> VertexOutput {
     // Reconstruct matrices
     let model_matrix = mat4x4<f32>(
         instance.model_0,
@@ -63,19 +106,14 @@ fn vs_main(
         instance.model_2,
         instance.model_3,
     );
-    let normal_matrix = mat3x3<f32>(
-        instance.tangent,
-        cross(instance.normal, instance.tangent),
-        instance.normal,
-    );
 
     // World space position
     let world_pos4 = model_matrix * vec4<f32>(vertex.position, 1.0);
     let world_pos = world_pos4.xyz + instance.translation;
 
     // Transform normals and tangent
-    let wn = normalize(normal_matrix * vertex.normal);
-    let wt = normalize(normal_matrix * vertex.tangent);
+    let wn = normalize(normal_matrix(model_matrix) * vertex.normal);
+    let wt = world_tangent(model_matrix, vertex.tangent, wn);
 
     var out: VertexOutput;
     out.clip_position   = camera.view_proj * world_pos4;
@@ -84,8 +122,39 @@ fn vs_main(
     out.world_view_pos  = camera.view_pos;
     out.world_normal    = wn;
     out.world_tangent   = wt;
-    out.tint_color      = vertex.color * instance.color;
-    out.material_id     = instance.material_id;
+    out.tint_color      = vec4<f32>(vertex.color, 1.0) * instance.color;
+    out.material_id     = instance.material.x;
+    out.emission        = f32(vertex.surface.x >> 24u) / 15.0;
+    out.flags           = instance.material.y;
+
+    return out;
+}
+
+// Object path: one PerObjectData per draw instead of an instance stream. The binding and
+// the vs_object entry point are appended per indexing mode, see ObjectIndexing.
+struct PerObjectData {
+    model:        mat4x4<f32>,
+    prev_model:   mat4x4<f32>,
+    tint:         vec4<f32>,
+    material_idx: u32,
+    flags:        u32,
+};
+
+fn object_vertex(vertex: VertexInput, object: PerObjectData) -> VertexOutput {
+    let world_normal = normalize(normal_matrix(object.model) * vertex.normal);
+    let world_pos4 = object.model * vec4<f32>(vertex.position, 1.0);
+
+    var out: VertexOutput;
+    out.clip_position   = camera.view_proj * world_pos4;
+    out.tex_coords      = vertex.tex_coords;
+    out.world_position  = world_pos4.xyz;
+    out.world_view_pos  = camera.view_pos;
+    out.world_normal    = world_normal;
+    out.world_tangent   = world_tangent(object.model, vertex.tangent, world_normal);
+    out.tint_color      = vec4<f32>(vertex.color, 1.0) * object.tint;
+    out.material_id     = object.material_idx;
+    out.emission        = f32(vertex.surface.x >> 24u) / 15.0;
+    out.flags           = object.flags;
 
     return out;
 }
@@ -98,20 +167,53 @@ fn vs_main(
 @group(1) @binding(0) var env_map:    texture_cube<f32>;
 @group(1) @binding(1) var env_samp:   sampler;
 
+// See MaterialData in material.rs
 struct Material {
     ambient:   vec3<f32>,
     diffuse:   vec3<f32>,
     specular:  vec3<f32>,
     shininess: f32,
+    flags:     u32,
 };
 @group(2) @binding(0) var<storage, read> materials: array<Material>;
 
+// MaterialData::NO_REFLECTIONS
+const MATERIAL_NO_REFLECTIONS: u32 = 1u;
+// EnvironmentReflection::MAX_SHININESS, the shininess of env_map's top mip
+const MAX_SHININESS: f32 = 256.0;
+
+// Environment seen along the normal's reflection of the view, from the env_map mip
+// prefiltered for the material's shininess, weighted by Schlick Fresnel over its specular.
+fn environment_reflection(material: Material, normal: vec3<f32>, view_dir: vec3<f32>) -> vec3<f32> {
+    if ((material.flags & MATERIAL_NO_REFLECTIONS) != 0u) {
+        return vec3<f32>(0.0);
+    }
+    let gloss = clamp(log2(max(material.shininess, 1.0)) / log2(MAX_SHININESS), 0.0, 1.0);
+    let lod = (1.0 - gloss) * f32(textureNumLevels(env_map) - 1u);
+    let world_reflect = reflect(-view_dir, normal);
+    let environment = textureSampleLevel(env_map, env_samp, world_reflect, lod).rgb;
+
+    let fresnel = pow(1.0 - max(dot(normal, view_dir), 0.0), 5.0);
+    let reflectivity = material.specular + (max(vec3<f32>(gloss), material.specular) - material.specular) * fresnel;
+    return environment * reflectivity;
+}
+
 
 @group(3) @binding(0) var t_diffuse: texture_2d<f32>;
 @group(3) @binding(1) var s_diffuse: sampler;
 @group(3) @binding(2) var t_normal:  texture_2d<f32>;
 @group(3) @binding(3) var s_normal:  sampler;
 
+// Two-channel (BC5) normal maps sample blue as 0, which no stored normal encodes: rebuild
+// z from the unit length instead.
+fn unpack_normal(sampled: vec4<f32>) -> vec3<f32> {
+    let xy = sampled.xy * 2.0 - 1.0;
+    if (sampled.z == 0.0) {
+        return vec3<f32>(xy, sqrt(max(1.0 - dot(xy, xy), 0.0)));
+    }
+    return sampled.xyz * 2.0 - 1.0;
+}
+
 
 
 @fragment
@@ -126,7 +228,7 @@ fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
     let world_bitangent = cross(in.world_normal, world_tangent);
     let TBN = mat3x3(world_tangent, world_bitangent, in.world_normal);
 
-    let tangent_normal = object_normal.xyz * 2.0 - 1.0;
+    let tangent_normal = unpack_normal(object_normal);
     let world_normal = normalize(TBN * tangent_normal);
 
     let light_dir = normalize(light.position - in.world_position);
@@ -139,10 +241,11 @@ fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
     let specular_strength = pow(max(dot(world_normal, half_dir), 0.0), material.shininess);
     let specular_color =  material.specular * light.color * specular_strength;
 
-    let world_reflect = reflect(-view_dir, world_normal);
-    let reflection = textureSample(env_map, env_samp, world_reflect).rgb;
+    let reflection = environment_reflection(material, world_normal, view_dir);
 
-    let final_color = (diffuse_color + specular_color) * (object_color.xyz * in.tint_color.rgb) + reflection * material.shininess;
+    let emissive = object_color.xyz * in.tint_color.rgb * in.emission;
+    let lit_color = (light.ambient + diffuse_color + specular_color) * (object_color.xyz * in.tint_color.rgb) + reflection + emissive;
+    let final_color = selection_highlight(lit_color, in.flags, world_normal, view_dir);
 
-    return vec4<f32>(final_color, object_color.a);
+    return vec4<f32>(final_color, object_color.a * in.tint_color.a);
 }
@@ -0,0 +1,153 @@
+// --------------------------------------------------
+// Water surfaces over the finished scene, see WaterPass in water.rs. The scene color is
+// refracted through a scrolling normal map and fades into the water color with the depth
+// of water behind; the environment reflects at grazing angles. Under water, a fog tint
+// covers the view.
+//
+// `scene_depth` and `scene_depth_at` are appended by WaterPass, declared for a single or
+// multisampled scene depth.
+// --------------------------------------------------
+
+struct Camera {
+    view_proj: mat4x4<f32>,
+    inv_proj:  mat4x4<f32>,
+    inv_view:  mat4x4<f32>,
+    view_pos:  vec3<f32>,
+};
+@group(0) @binding(0) var<uniform> camera: Camera;
+
+struct Light {
+    position: vec3<f32>,
+    color:    vec3<f32>,
+    // Premultiplied by its intensity, see Light::uniform in light.rs
+    ambient:  vec3<f32>,
+};
+@group(0) @binding(1) var<uniform> light: Light;
+
+@group(1) @binding(0) var env_map:  texture_cube<f32>;
+@group(1) @binding(1) var env_samp: sampler;
+
+// See WaterUniform in water.rs
+struct Water {
+    color:       vec3<f32>,
+    time:        f32,
+    wave_scale:  f32,
+    wave_speed:  f32,
+    refraction:  f32,
+    fog_density: f32,
+};
+@group(2) @binding(0) var<uniform> water: Water;
+@group(2) @binding(1) var t_scene:  texture_2d<f32>;
+@group(2) @binding(2) var s_scene:  sampler;
+@group(2) @binding(4) var t_normal: texture_2d<f32>;
+@group(2) @binding(5) var s_normal: sampler;
+
+// Reflectance of water looking straight down.
+const WATER_F0: f32 = 0.02;
+// Least fog under water, so even the nearest things are tinted.
+const MIN_UNDERWATER_FOG: f32 = 0.2;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+};
+struct InstanceInput {
+    @location(5) model_0: vec4<f32>,
+    @location(6) model_1: vec4<f32>,
+    @location(7) model_2: vec4<f32>,
+    @location(8) model_3: vec4<f32>,
+};
+
+struct SurfaceOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world_position: vec3<f32>,
+};
+
+@vertex
+fn vs_surface(vertex: VertexInput, instance: InstanceInput) -> SurfaceOutput {
+    let model = mat4x4<f32>(instance.model_0, instance.model_1, instance.model_2, instance.model_3);
+    let world_position = model * vec4<f32>(vertex.position, 1.0);
+    var out: SurfaceOutput;
+    out.clip_position = camera.view_proj * world_position;
+    out.world_position = world_position.xyz;
+    return out;
+}
+
+// Texel of the scene under `uv`, clamped to the target.
+fn scene_pixel(uv: vec2<f32>) -> vec2<i32> {
+    let size = vec2<i32>(textureDimensions(t_scene));
+    return clamp(vec2<i32>(uv * vec2<f32>(size)), vec2<i32>(0), size - 1);
+}
+
+// Distance from the eye to what the scene drew under `uv`.
+fn scene_distance(uv: vec2<f32>) -> f32 {
+    let depth = scene_depth_at(scene_pixel(uv));
+    let ndc = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+    let view = camera.inv_proj * ndc;
+    return length(view.xyz / view.w);
+}
+
+@fragment
+fn fs_surface(in: SurfaceOutput) -> @location(0) vec4<f32> {
+    let uv = in.clip_position.xy / vec2<f32>(textureDimensions(t_scene));
+
+    // Two copies of the normal map scrolling across each other.
+    let drift = water.time * water.wave_speed;
+    let coords = in.world_position.xz / water.wave_scale;
+    let a = textureSample(t_normal, s_normal, coords + vec2<f32>(drift, drift * 0.4)).xyz;
+    let b = textureSample(t_normal, s_normal, coords * 1.37 - vec2<f32>(drift * 0.6, drift)).xyz;
+    let tangent_normal = normalize(a + b - 1.0);
+    // The surfaces face +Y, with u along X and v along Z.
+    let normal = normalize(vec3<f32>(tangent_normal.x, tangent_normal.z, tangent_normal.y));
+
+    let to_eye = camera.view_pos - in.world_position;
+    let view_dir = normalize(to_eye);
+    let from_below = dot(normal, view_dir) < 0.0;
+    let facing = select(normal, -normal, from_below);
+
+    // The scene behind, pushed around by the waves. Where the pushed sample lands on
+    // something in front of the water, the straight one is used instead.
+    let surface_distance = length(to_eye);
+    let pushed_uv = clamp(uv + normal.xz * water.refraction, vec2<f32>(0.0), vec2<f32>(1.0));
+    let pushed_distance = scene_distance(pushed_uv);
+    let behind_surface = pushed_distance > surface_distance;
+    let refract_uv = select(uv, pushed_uv, behind_surface);
+    let behind = select(scene_distance(uv), pushed_distance, behind_surface);
+    let refracted = textureSampleLevel(t_scene, s_scene, refract_uv, 0.0).rgb;
+
+    // Looking down, light fades into the water color with the water it crossed; looking
+    // up, what's behind is above the water.
+    let thickness = select(max(behind - surface_distance, 0.0), 0.0, from_below);
+    let murk = 1.0 - exp(-thickness * water.fog_density);
+    let below = mix(refracted, water.color * light.color, murk);
+
+    // Schlick's approximation.
+    let cos_theta = clamp(dot(facing, view_dir), 0.0, 1.0);
+    let fresnel = WATER_F0 + (1.0 - WATER_F0) * pow(1.0 - cos_theta, 5.0);
+    let reflected = textureSampleLevel(env_map, env_samp, reflect(-view_dir, facing), 0.0).rgb;
+
+    let light_dir = normalize(light.position - in.world_position);
+    let half_dir = normalize(view_dir + light_dir);
+    let glint = light.color * pow(max(dot(facing, half_dir), 0.0), 256.0);
+
+    return vec4<f32>(mix(below, reflected, fresnel) + glint, 1.0);
+}
+
+struct FullscreenOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+@vertex
+fn vs_fullscreen(@builtin(vertex_index) vi: u32) -> FullscreenOutput {
+    var out: FullscreenOutput;
+    out.uv = vec2<f32>(f32((vi << 1u) & 2u), f32(vi & 2u));
+    out.clip_position = vec4<f32>(out.uv * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0), 0.0, 1.0);
+    return out;
+}
+
+// The view under water: everything fades into the water color with distance.
+@fragment
+fn fs_underwater(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let fog = 1.0 - exp(-scene_distance(in.uv) * water.fog_density);
+    return vec4<f32>(water.color * light.color, max(fog, MIN_UNDERWATER_FOG));
+}
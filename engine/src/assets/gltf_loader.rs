@@ -0,0 +1,468 @@
+//! glTF 2.0 (`.gltf` and `.glb`) parsed into CPU-side meshes, materials and images. Node
+//! transforms of the default scene are baked into the vertices, so every primitive lands
+//! in model space the way an OBJ's meshes do. Skinned meshes are the exception: their
+//! node transform is ignored as the spec asks, and the first skin and every animation
+//! driving it come along as a [`Skeleton`] and [`AnimationClip`]s.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use base64::Engine as _;
+use glam::{Mat3, Mat4, Quat, Vec3};
+
+use crate::{
+    AnimationClip, Asset, AssetPaths, EngineError, Joint, JointChannel, Keyframes, MeshAsset,
+    Skeleton, Vertex,
+};
+
+/// Whether `file` is loaded by [`Asset::gltf`] rather than as OBJ.
+pub fn is_gltf(file: &str) -> bool {
+    let ext = Path::new(file).extension().and_then(|e| e.to_str());
+    ext.is_some_and(|e| e.eq_ignore_ascii_case("gltf") || e.eq_ignore_ascii_case("glb"))
+}
+
+/// Joint indices into [`GltfModel::skeleton`] and their weights, one set per vertex.
+pub type GltfSkinning = (Vec<[u32; 4]>, Vec<[f32; 4]>);
+
+/// One triangle primitive with its own material.
+#[derive(Debug, Clone)]
+pub struct GltfPrimitive {
+    pub name: String,
+    pub mesh: MeshAsset,
+    /// Index into [`GltfModel::materials`].
+    pub material: Option<usize>,
+    /// Set for primitives of a skinned node.
+    pub skinning: Option<GltfSkinning>,
+}
+
+/// The metallic-roughness parameters the engine's materials can use.
+#[derive(Debug, Clone)]
+pub struct GltfMaterial {
+    pub name: String,
+    pub base_color: [f32; 4],
+    pub metallic: f32,
+    pub roughness: f32,
+    /// Indices into [`GltfModel::images`].
+    pub base_color_texture: Option<usize>,
+    pub normal_texture: Option<usize>,
+    pub blend: bool,
+    pub double_sided: bool,
+}
+
+/// Stands in for primitives without a material: white, dielectric and fully rough.
+impl Default for GltfMaterial {
+    fn default() -> Self {
+        Self {
+            name: "default".to_string(),
+            base_color: [1.0; 4],
+            metallic: 0.0,
+            roughness: 1.0,
+            base_color_texture: None,
+            normal_texture: None,
+            blend: false,
+            double_sided: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct GltfModel {
+    pub primitives: Vec<GltfPrimitive>,
+    pub materials: Vec<GltfMaterial>,
+    pub images: Vec<image::RgbaImage>,
+    pub skeleton: Option<Skeleton>,
+    pub animations: Vec<AnimationClip>,
+}
+
+impl Asset {
+    /// Parses `file` under `assets/models`. Buffers may be the GLB binary chunk, base64
+    /// data URIs or `.bin` files next to the model; images the same, decoded to RGBA.
+    pub fn gltf(file: &str) -> Result<GltfModel, EngineError> {
+        let path = AssetPaths::model(file);
+        let dir = path.parent().unwrap_or(Path::new(""));
+        let err =
+            |e: &dyn std::fmt::Display| EngineError::AssetLoadError(format!("{}: {}", file, e));
+
+        let gltf::Gltf { document, mut blob } =
+            gltf::Gltf::from_slice(&Asset::read_bytes(&path)?).map_err(|e| err(&e))?;
+
+        let buffers = document
+            .buffers()
+            .map(|buffer| {
+                let data = match buffer.source() {
+                    gltf::buffer::Source::Bin => {
+                        blob.take().ok_or_else(|| err(&"missing binary chunk"))?
+                    }
+                    gltf::buffer::Source::Uri(uri) => read_uri(dir, uri).map_err(|e| err(&e))?,
+                };
+                if data.len() < buffer.length() {
+                    return Err(err(&format!("buffer {} is truncated", buffer.index())));
+                }
+                Ok(data)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let images = document
+            .images()
+            .map(|img| {
+                let bytes = match img.source() {
+                    gltf::image::Source::View { view, .. } => {
+                        let start = view.offset();
+                        buffers[view.buffer().index()]
+                            .get(start..start + view.length())
+                            .ok_or_else(|| err(&"image view out of range"))?
+                            .to_vec()
+                    }
+                    gltf::image::Source::Uri { uri, .. } => {
+                        read_uri(dir, uri).map_err(|e| err(&e))?
+                    }
+                };
+                Ok(image::load_from_memory(&bytes)?.to_rgba8())
+            })
+            .collect::<Result<Vec<_>, EngineError>>()?;
+
+        let materials = document
+            .materials()
+            .map(|material| {
+                let pbr = material.pbr_metallic_roughness();
+                GltfMaterial {
+                    name: material
+                        .name()
+                        .map(str::to_string)
+                        .unwrap_or_else(|| format!("material{}", material.index().unwrap_or(0))),
+                    base_color: pbr.base_color_factor(),
+                    metallic: pbr.metallic_factor(),
+                    roughness: pbr.roughness_factor(),
+                    base_color_texture: pbr
+                        .base_color_texture()
+                        .map(|info| info.texture().source().index()),
+                    normal_texture: material
+                        .normal_texture()
+                        .map(|normal| normal.texture().source().index()),
+                    blend: material.alpha_mode() == gltf::material::AlphaMode::Blend,
+                    double_sided: material.double_sided(),
+                }
+            })
+            .collect();
+
+        let mut model = GltfModel {
+            primitives: Vec::new(),
+            materials,
+            images,
+            skeleton: None,
+            animations: Vec::new(),
+        };
+        let scene = document
+            .default_scene()
+            .or_else(|| document.scenes().next());
+        match scene {
+            Some(scene) => {
+                for node in scene.nodes() {
+                    model.push_node(&node, Mat4::IDENTITY, &buffers)?;
+                }
+            }
+            None => {
+                for mesh in document.meshes() {
+                    model.push_mesh(&mesh, Mat4::IDENTITY, false, &buffers)?;
+                }
+            }
+        }
+        if let Some(skin) = document.skins().next() {
+            let mut parents = HashMap::new();
+            for scene in document.scenes() {
+                for node in scene.nodes() {
+                    parent_transforms(&node, Mat4::IDENTITY, &mut parents);
+                }
+            }
+            let (skeleton, joint_of) = read_skeleton(&skin, &parents, &buffers);
+            model.animations = document
+                .animations()
+                .filter_map(|animation| read_animation(&animation, &joint_of, &buffers))
+                .collect();
+            model.skeleton = Some(skeleton);
+        }
+        Ok(model)
+    }
+}
+
+impl GltfModel {
+    /// Texture cache name of image `index` of `file`.
+    pub fn image_name(file: &str, index: usize) -> String {
+        format!("{}#image{}", file, index)
+    }
+    fn push_node(
+        &mut self,
+        node: &gltf::Node,
+        parent: Mat4,
+        buffers: &[Vec<u8>],
+    ) -> Result<(), EngineError> {
+        let transform = parent * Mat4::from_cols_array_2d(&node.transform().matrix());
+        if let Some(mesh) = node.mesh() {
+            match node.skin() {
+                Some(_) => self.push_mesh(&mesh, Mat4::IDENTITY, true, buffers)?,
+                None => self.push_mesh(&mesh, transform, false, buffers)?,
+            }
+        }
+        for child in node.children() {
+            self.push_node(&child, transform, buffers)?;
+        }
+        Ok(())
+    }
+    fn push_mesh(
+        &mut self,
+        mesh: &gltf::Mesh,
+        transform: Mat4,
+        skinned: bool,
+        buffers: &[Vec<u8>],
+    ) -> Result<(), EngineError> {
+        let name = mesh
+            .name()
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("mesh{}", mesh.index()));
+        for primitive in mesh.primitives() {
+            if primitive.mode() != gltf::mesh::Mode::Triangles {
+                crate::log_warning!("{}: skipping non-triangle primitive", name);
+                continue;
+            }
+            let reader = primitive.reader(|buffer| Some(buffers[buffer.index()].as_slice()));
+            let Some(positions) = reader.read_positions() else {
+                continue;
+            };
+            let mut vertices: Vec<Vertex> = positions
+                .map(|position| Vertex {
+                    position,
+                    tex_coords: [0.0; 2],
+                    normal: [0.0, 0.0, 1.0],
+                    tangent: [0.0; 3],
+                    color: [1.0; 3],
+                    surface: [0; 2],
+                })
+                .collect();
+            if let Some(uvs) = reader.read_tex_coords(0) {
+                vertices
+                    .iter_mut()
+                    .zip(uvs.into_f32())
+                    .for_each(|(v, uv)| v.tex_coords = uv);
+            }
+            if let Some(colors) = reader.read_colors(0) {
+                vertices
+                    .iter_mut()
+                    .zip(colors.into_rgb_f32())
+                    .for_each(|(v, color)| v.color = color);
+            }
+            let has_normals = match reader.read_normals() {
+                Some(normals) => {
+                    vertices
+                        .iter_mut()
+                        .zip(normals)
+                        .for_each(|(v, normal)| v.normal = normal);
+                    true
+                }
+                None => false,
+            };
+            let has_tangents = match reader.read_tangents() {
+                Some(tangents) => {
+                    vertices
+                        .iter_mut()
+                        .zip(tangents)
+                        .for_each(|(v, [x, y, z, _])| v.tangent = [x, y, z]);
+                    true
+                }
+                None => false,
+            };
+            let indices: Vec<u32> = match reader.read_indices() {
+                Some(indices) => indices.into_u32().collect(),
+                None => (0..vertices.len() as u32).collect(),
+            };
+            if !has_tangents {
+                MeshAsset::generate_tangents(&mut vertices, &indices, !has_normals);
+            }
+            let skinning = match (skinned, reader.read_joints(0), reader.read_weights(0)) {
+                (true, Some(joints), Some(weights)) => {
+                    let joints = joints
+                        .into_u16()
+                        .map(|j| j.map(u32::from))
+                        .collect::<Vec<_>>();
+                    Some((joints, weights.into_f32().collect::<Vec<_>>()))
+                }
+                _ => None,
+            };
+
+            let normal_matrix = Mat3::from_mat4(transform).inverse().transpose();
+            for v in &mut vertices {
+                v.position = transform.transform_point3(v.position.into()).into();
+                v.normal = (normal_matrix * Vec3::from(v.normal))
+                    .normalize_or_zero()
+                    .into();
+                v.tangent = transform
+                    .transform_vector3(v.tangent.into())
+                    .normalize_or_zero()
+                    .into();
+            }
+
+            self.primitives.push(GltfPrimitive {
+                name: format!("{}#{}", name, primitive.index()),
+                mesh: MeshAsset { vertices, indices },
+                material: primitive.material().index(),
+                skinning,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Records the global transform of every node's parent, for skeletons whose root joint
+/// hangs under other nodes.
+fn parent_transforms(node: &gltf::Node, parent: Mat4, out: &mut HashMap<usize, Mat4>) {
+    out.insert(node.index(), parent);
+    let transform = parent * Mat4::from_cols_array_2d(&node.transform().matrix());
+    for child in node.children() {
+        parent_transforms(&child, transform, out);
+    }
+}
+
+/// The joints of `skin` in skin order, which vertex joint indices refer to, and the
+/// joint index of each joint node.
+fn read_skeleton(
+    skin: &gltf::Skin,
+    parents: &HashMap<usize, Mat4>,
+    buffers: &[Vec<u8>],
+) -> (Skeleton, HashMap<usize, usize>) {
+    let joint_of: HashMap<usize, usize> = skin
+        .joints()
+        .enumerate()
+        .map(|(joint, node)| (node.index(), joint))
+        .collect();
+    let mut joints: Vec<Joint> = skin
+        .joints()
+        .map(|node| {
+            let (translation, rotation, scale) = node.transform().decomposed();
+            Joint {
+                name: node
+                    .name()
+                    .map(str::to_string)
+                    .unwrap_or_else(|| format!("joint{}", node.index())),
+                parent: None,
+                translation: Vec3::from(translation),
+                rotation: Quat::from_array(rotation),
+                scale: Vec3::from(scale),
+            }
+        })
+        .collect();
+    for (joint, node) in skin.joints().enumerate() {
+        for child in node.children() {
+            if let Some(&child) = joint_of.get(&child.index()) {
+                joints[child].parent = Some(joint);
+            }
+        }
+    }
+    // Whatever sits above the first root joint, e.g. an armature node's axis conversion.
+    let root = joints
+        .iter()
+        .zip(skin.joints())
+        .find(|(joint, _)| joint.parent.is_none())
+        .and_then(|(_, node)| parents.get(&node.index()).copied())
+        .unwrap_or(Mat4::IDENTITY);
+    let inverse_bind = skin
+        .reader(|buffer| Some(buffers[buffer.index()].as_slice()))
+        .read_inverse_bind_matrices()
+        .map(|matrices| matrices.map(|m| Mat4::from_cols_array_2d(&m)).collect())
+        .unwrap_or_default();
+    let skeleton = Skeleton {
+        joints,
+        inverse_bind,
+        root,
+    };
+    (skeleton, joint_of)
+}
+
+/// The channels of `animation` that target joints; `None` when none do. Cubic spline
+/// tangents are dropped and the keyframes interpolated linearly.
+fn read_animation(
+    animation: &gltf::Animation,
+    joint_of: &HashMap<usize, usize>,
+    buffers: &[Vec<u8>],
+) -> Option<AnimationClip> {
+    use gltf::animation::util::ReadOutputs;
+
+    let mut channels: HashMap<usize, JointChannel> = HashMap::new();
+    let mut duration = 0.0f32;
+    for channel in animation.channels() {
+        let Some(&joint) = joint_of.get(&channel.target().node().index()) else {
+            continue;
+        };
+        let reader = channel.reader(|buffer| Some(buffers[buffer.index()].as_slice()));
+        let Some(times) = reader.read_inputs() else {
+            continue;
+        };
+        let times: Vec<f32> = times.collect();
+        duration = times.last().copied().unwrap_or(0.0).max(duration);
+        let interpolation = channel.sampler().interpolation();
+        let entry = channels.entry(joint).or_insert(JointChannel {
+            joint,
+            translation: None,
+            rotation: None,
+            scale: None,
+        });
+        match reader.read_outputs() {
+            Some(ReadOutputs::Translations(values)) => {
+                let values = values.map(Vec3::from).collect();
+                entry.translation = Some(keyframes(times, values, interpolation));
+            }
+            Some(ReadOutputs::Rotations(values)) => {
+                let values = values.into_f32().map(Quat::from_array).collect();
+                entry.rotation = Some(keyframes(times, values, interpolation));
+            }
+            Some(ReadOutputs::Scales(values)) => {
+                let values = values.map(Vec3::from).collect();
+                entry.scale = Some(keyframes(times, values, interpolation));
+            }
+            _ => {}
+        }
+    }
+    if channels.is_empty() {
+        return None;
+    }
+    let mut channels: Vec<JointChannel> = channels.into_values().collect();
+    channels.sort_by_key(|channel| channel.joint);
+    Some(AnimationClip {
+        name: animation
+            .name()
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("animation{}", animation.index())),
+        duration,
+        channels,
+    })
+}
+
+fn keyframes<T>(
+    times: Vec<f32>,
+    values: Vec<T>,
+    interpolation: gltf::animation::Interpolation,
+) -> Keyframes<T> {
+    use gltf::animation::Interpolation;
+    let values = match interpolation {
+        // in-tangent, value, out-tangent per keyframe
+        Interpolation::CubicSpline => values.into_iter().skip(1).step_by(3).collect(),
+        _ => values,
+    };
+    Keyframes {
+        times,
+        values,
+        step: interpolation == Interpolation::Step,
+    }
+}
+
+/// Contents of a data URI, or of a file relative to the model.
+fn read_uri(dir: &Path, uri: &str) -> Result<Vec<u8>, String> {
+    if let Some(data) = uri.strip_prefix("data:") {
+        let (_, encoded) = data
+            .split_once(";base64,")
+            .ok_or_else(|| "unsupported data URI".to_string())?;
+        return base64::engine::general_purpose::STANDARD
+            .decode(encoded)
+            .map_err(|e| e.to_string());
+    }
+    let path = dir.join(uri.replace("%20", " "));
+    std::fs::read(&path).map_err(|e| format!("{}: {}", path.display(), e))
+}
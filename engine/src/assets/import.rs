@@ -0,0 +1,532 @@
+//! Offline import of source assets into engine-ready derivatives under `assets/.import`.
+//! Derivatives are an optimization only: every loader checks for an up-to-date one first
+//! and falls back to the source when it's missing, stale or unreadable.
+//!
+//! Change detection is by content hash, recorded per source in `manifest.ron`, one entry
+//! per line so a damaged line only costs that asset a re-import. Per-asset options live in
+//! a `<source>.import.ron` sidecar and are part of the hash.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use rayon::prelude::*;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{log_debug, log_info, log_warning, EngineError};
+
+/// Folder under the asset root holding derivatives and the manifest.
+pub const IMPORT_DIR: &str = ".import";
+pub const MANIFEST_FILE: &str = "manifest.ron";
+pub const SETTINGS_SUFFIX: &str = ".import.ron";
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+
+/// FNV-1a over `bytes`; stable across runs and toolchains, unlike `DefaultHasher`.
+pub fn content_hash(bytes: &[u8]) -> u64 {
+    hash_more(FNV_OFFSET, bytes)
+}
+
+fn hash_more(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Hash of `source` under `root` together with its import settings sidecar, if any.
+pub fn source_hash(root: &Path, source: &str) -> Result<u64, EngineError> {
+    let hash = content_hash(&std::fs::read(root.join(source))?);
+    match std::fs::read(root.join(format!("{}{}", source, SETTINGS_SUFFIX))) {
+        Ok(settings) => Ok(hash_more(hash, &settings)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(hash),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Import options for `source` from its `<source>.import.ron` sidecar, or the defaults.
+pub fn import_settings<T: DeserializeOwned + Default>(
+    root: &Path,
+    source: &str,
+) -> Result<T, EngineError> {
+    let path = root.join(format!("{}{}", source, SETTINGS_SUFFIX));
+    match std::fs::read_to_string(&path) {
+        Ok(text) => ron::de::from_str(&text)
+            .map_err(|e| EngineError::ImportError(format!("{}: {}", path.display(), e))),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(T::default()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// Source path relative to the asset root, `/`-separated.
+    pub source: String,
+    /// [`source_hash`] of the source the outputs were made from, as hex.
+    pub hash: String,
+    pub importer: String,
+    /// Derivatives relative to [`IMPORT_DIR`].
+    pub outputs: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ImportManifest {
+    pub entries: BTreeMap<String, ManifestEntry>,
+}
+
+impl ImportManifest {
+    /// Reads `path`, skipping lines that don't parse. A missing file is an empty manifest.
+    pub fn load(path: &Path) -> Self {
+        let Ok(text) = std::fs::read_to_string(path) else {
+            return Self::default();
+        };
+        let mut entries = BTreeMap::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with("//") {
+                continue;
+            }
+            match ron::de::from_str::<ManifestEntry>(line) {
+                Ok(entry) => {
+                    entries.insert(entry.source.clone(), entry);
+                }
+                Err(e) => log_warning!("{}:{}: {}", path.display(), number + 1, e),
+            }
+        }
+        Self { entries }
+    }
+    pub fn save(&self, path: &Path) -> Result<(), EngineError> {
+        let mut out = String::from("// Written by the asset importer; one entry per line.\n");
+        for entry in self.entries.values() {
+            let line = ron::ser::to_string(entry)
+                .map_err(|e| EngineError::ImportError(format!("{}: {}", entry.source, e)))?;
+            out.push_str(&line);
+            out.push('\n');
+        }
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, out)?;
+        Ok(())
+    }
+    /// Whether `source` was imported from content hashing to `hash` and every output is
+    /// still on disk under `import_dir`.
+    pub fn is_current(&self, source: &str, hash: u64, import_dir: &Path) -> bool {
+        self.entries.get(source).is_some_and(|entry| {
+            entry.hash == hex(hash)
+                && entry
+                    .outputs
+                    .iter()
+                    .all(|output| import_dir.join(output).exists())
+        })
+    }
+}
+
+pub fn hex(hash: u64) -> String {
+    format!("{:016x}", hash)
+}
+
+/// Turns one kind of source asset into derivatives.
+pub trait Importer: Send + Sync {
+    fn name(&self) -> &'static str;
+    /// Whether this importer handles `source`, a `/`-separated path under the asset root.
+    fn accepts(&self, source: &str) -> bool;
+    /// Writes the derivatives of `source` and returns their paths relative to the import
+    /// dir. `hash` is the source's [`source_hash`], to stamp into each derivative.
+    fn import(&self, root: &Path, source: &str, hash: u64) -> Result<Vec<String>, EngineError>;
+}
+
+#[derive(Debug, Clone)]
+struct Pending {
+    source: String,
+    hash: u64,
+    importer: usize,
+}
+
+/// Outcome of the imports run so far.
+#[derive(Debug, Clone, Default)]
+pub struct ImportReport {
+    pub imported: Vec<String>,
+    pub failed: Vec<(String, String)>,
+    pub up_to_date: usize,
+}
+
+/// Scans an asset root and brings its derivatives up to date, in parallel batches.
+pub struct ImportPipeline {
+    root: PathBuf,
+    importers: Vec<Arc<dyn Importer>>,
+    manifest: ImportManifest,
+    pending: Vec<Pending>,
+    total: usize,
+    report: ImportReport,
+}
+
+impl ImportPipeline {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        let root = root.into();
+        let manifest = ImportManifest::load(&root.join(IMPORT_DIR).join(MANIFEST_FILE));
+        Self {
+            root,
+            importers: Vec::new(),
+            manifest,
+            pending: Vec::new(),
+            total: 0,
+            report: ImportReport::default(),
+        }
+    }
+    /// Pipeline with the texture, model and HDR importers.
+    pub fn with_defaults(root: impl Into<PathBuf>) -> Self {
+        Self::new(root)
+            .with(crate::TextureImporter)
+            .with(crate::ModelImporter)
+            .with(crate::HdrImporter)
+    }
+    pub fn with(mut self, importer: impl Importer + 'static) -> Self {
+        self.register(importer);
+        self
+    }
+    pub fn register(&mut self, importer: impl Importer + 'static) {
+        self.importers.push(Arc::new(importer));
+    }
+    pub fn import_dir(&self) -> PathBuf {
+        self.root.join(IMPORT_DIR)
+    }
+    pub fn manifest(&self) -> &ImportManifest {
+        &self.manifest
+    }
+    pub fn report(&self) -> &ImportReport {
+        &self.report
+    }
+    /// Imports finished and queued, for progress display.
+    pub fn progress(&self) -> (usize, usize) {
+        (self.total - self.pending.len(), self.total)
+    }
+
+    /// Hashes every source some importer accepts and queues the ones whose manifest entry
+    /// is missing, stale or lost an output. Returns how many were queued.
+    pub fn scan(&mut self) -> Result<usize, EngineError> {
+        let mut sources = Vec::new();
+        collect_sources(&self.root, &self.root, &mut sources)?;
+        sources.sort();
+        let import_dir = self.import_dir();
+        self.pending.clear();
+        self.report = ImportReport::default();
+        for source in sources {
+            let Some(importer) = self.importers.iter().position(|i| i.accepts(&source)) else {
+                continue;
+            };
+            let hash = source_hash(&self.root, &source)?;
+            if self.manifest.is_current(&source, hash, &import_dir) {
+                self.report.up_to_date += 1;
+                continue;
+            }
+            self.pending.push(Pending {
+                source,
+                hash,
+                importer,
+            });
+        }
+        self.total = self.pending.len();
+        log_debug!(
+            "Asset import: {} queued, {} up to date",
+            self.total,
+            self.report.up_to_date
+        );
+        Ok(self.total)
+    }
+
+    /// Runs queued imports a batch at a time until `budget` is spent or the queue is
+    /// empty, saving the manifest after each batch. Returns `true` once nothing is left.
+    pub fn step(&mut self, budget: Duration) -> Result<bool, EngineError> {
+        let start = Instant::now();
+        let batch_size = rayon::current_num_threads().max(1);
+        while !self.pending.is_empty() && start.elapsed() < budget {
+            let batch: Vec<Pending> = self
+                .pending
+                .drain(..batch_size.min(self.pending.len()))
+                .collect();
+            self.run_batch(batch)?;
+        }
+        Ok(self.pending.is_empty())
+    }
+    /// Runs every queued import.
+    pub fn run(&mut self) -> Result<&ImportReport, EngineError> {
+        let batch = std::mem::take(&mut self.pending);
+        self.run_batch(batch)?;
+        Ok(&self.report)
+    }
+
+    fn run_batch(&mut self, batch: Vec<Pending>) -> Result<(), EngineError> {
+        let done = AtomicUsize::new(self.total - self.pending.len() - batch.len());
+        let total = self.total;
+        let results: Vec<(Pending, Result<Vec<String>, EngineError>)> = batch
+            .into_par_iter()
+            .map(|job| {
+                let importer = &self.importers[job.importer];
+                let result = importer.import(&self.root, &job.source, job.hash);
+                let done = done.fetch_add(1, Ordering::Relaxed) + 1;
+                log_info!("Imported {}/{}: {}", done, total, job.source);
+                (job, result)
+            })
+            .collect();
+        for (job, result) in results {
+            match result {
+                Ok(outputs) => {
+                    self.manifest.entries.insert(
+                        job.source.clone(),
+                        ManifestEntry {
+                            source: job.source.clone(),
+                            hash: hex(job.hash),
+                            importer: self.importers[job.importer].name().to_string(),
+                            outputs,
+                        },
+                    );
+                    self.report.imported.push(job.source);
+                }
+                Err(e) => {
+                    log_warning!("Import {}: {}", job.source, e);
+                    self.manifest.entries.remove(&job.source);
+                    self.report.failed.push((job.source, e.to_string()));
+                }
+            }
+        }
+        self.manifest.save(&self.import_dir().join(MANIFEST_FILE))
+    }
+}
+
+fn collect_sources(root: &Path, dir: &Path, out: &mut Vec<String>) -> Result<(), EngineError> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let hidden = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .is_some_and(|n| n.starts_with('.'));
+        if hidden {
+            continue;
+        }
+        if path.is_dir() {
+            collect_sources(root, &path, out)?;
+        } else if let Ok(relative) = path.strip_prefix(root) {
+            let parts: Vec<_> = relative
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect();
+            out.push(parts.join("/"));
+        }
+    }
+    Ok(())
+}
+
+/// Derivative files: a magic, a format version and the hash of the source they were made
+/// from, then the importer's body.
+pub struct Derivative;
+
+impl Derivative {
+    pub const VERSION: u32 = 1;
+
+    /// `<root>/.import/<source>.<ext>`.
+    pub fn path(root: &Path, source: &str, ext: &str) -> PathBuf {
+        root.join(IMPORT_DIR).join(format!("{}.{}", source, ext))
+    }
+    pub fn write(
+        root: &Path,
+        source: &str,
+        ext: &str,
+        magic: [u8; 4],
+        hash: u64,
+        body: &[u8],
+    ) -> Result<String, EngineError> {
+        let path = Self::path(root, source, ext);
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut out = ByteWriter::default();
+        out.raw(&magic);
+        out.u32(Self::VERSION);
+        out.u64(hash);
+        out.raw(body);
+        std::fs::write(&path, out.0)?;
+        Ok(format!("{}.{}", source, ext))
+    }
+    /// Body of the derivative of `source` under the asset root, if there is one made from
+    /// the source as it is now. Anything else, a read error included, means "use the source".
+    pub fn read_fresh(source: &str, ext: &str, magic: [u8; 4]) -> Option<Vec<u8>> {
+        let root = crate::AssetPaths::root();
+        let path = Self::path(root, source, ext);
+        let bytes = std::fs::read(&path).ok()?;
+        let current = source_hash(root, source).ok()?;
+        let mut reader = ByteReader::new(&bytes);
+        let fresh = reader.raw(4).ok()? == magic
+            && reader.u32().ok()? == Self::VERSION
+            && reader.u64().ok()? == current;
+        if !fresh {
+            log_debug!("Stale derivative {}, loading the source", path.display());
+            return None;
+        }
+        Some(reader.rest().to_vec())
+    }
+}
+
+/// Little-endian encoder for derivative bodies.
+#[derive(Debug, Default)]
+pub struct ByteWriter(pub Vec<u8>);
+
+impl ByteWriter {
+    pub fn raw(&mut self, bytes: &[u8]) {
+        self.0.extend_from_slice(bytes);
+    }
+    pub fn u8(&mut self, value: u8) {
+        self.0.push(value);
+    }
+    pub fn u32(&mut self, value: u32) {
+        self.raw(&value.to_le_bytes());
+    }
+    pub fn u64(&mut self, value: u64) {
+        self.raw(&value.to_le_bytes());
+    }
+    pub fn f32(&mut self, value: f32) {
+        self.raw(&value.to_le_bytes());
+    }
+    /// Length-prefixed bytes.
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.u64(bytes.len() as u64);
+        self.raw(bytes);
+    }
+    pub fn str(&mut self, value: &str) {
+        self.bytes(value.as_bytes());
+    }
+}
+
+/// Decoder matching [`ByteWriter`]; running short is an [`EngineError::ImportError`].
+#[derive(Debug)]
+pub struct ByteReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, pos: 0 }
+    }
+    pub fn raw(&mut self, len: usize) -> Result<&'a [u8], EngineError> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or_else(|| EngineError::ImportError("derivative is truncated".to_string()))?;
+        let slice = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(slice)
+    }
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], EngineError> {
+        let mut out = [0; N];
+        out.copy_from_slice(self.raw(N)?);
+        Ok(out)
+    }
+    pub fn u8(&mut self) -> Result<u8, EngineError> {
+        Ok(self.raw(1)?[0])
+    }
+    pub fn u32(&mut self) -> Result<u32, EngineError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+    pub fn u64(&mut self) -> Result<u64, EngineError> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+    pub fn f32(&mut self) -> Result<f32, EngineError> {
+        Ok(f32::from_le_bytes(self.array()?))
+    }
+    pub fn bytes(&mut self) -> Result<&'a [u8], EngineError> {
+        let len = self.u64()? as usize;
+        self.raw(len)
+    }
+    pub fn str(&mut self) -> Result<String, EngineError> {
+        String::from_utf8(self.bytes()?.to_vec())
+            .map_err(|e| EngineError::ImportError(e.to_string()))
+    }
+    pub fn rest(&self) -> &'a [u8] {
+        &self.bytes[self.pos..]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Empty asset root of its own under the temp dir.
+    fn scratch(name: &str) -> PathBuf {
+        let root =
+            std::env::temp_dir().join(format!("rupy_import_{}_{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("textures")).unwrap();
+        root
+    }
+
+    fn write_png(root: &Path, source: &str, shade: u8) {
+        image::RgbaImage::from_pixel(4, 4, image::Rgba([shade, 0, 0, 255]))
+            .save(root.join(source))
+            .unwrap();
+    }
+
+    fn imported(root: &Path) -> Vec<String> {
+        let mut pipeline = ImportPipeline::new(root).with(crate::TextureImporter);
+        pipeline.scan().unwrap();
+        pipeline.run().unwrap().imported.clone()
+    }
+
+    #[test]
+    fn manifests_round_trip_and_skip_bad_lines() {
+        let root = scratch("manifest");
+        let path = root.join(IMPORT_DIR).join(MANIFEST_FILE);
+        let mut manifest = ImportManifest::default();
+        for (source, outputs) in [("textures/a.png", 1), ("models/b.obj", 2)] {
+            manifest.entries.insert(
+                source.to_string(),
+                ManifestEntry {
+                    source: source.to_string(),
+                    hash: hex(content_hash(source.as_bytes())),
+                    importer: "test".to_string(),
+                    outputs: (0..outputs).map(|i| format!("{}.{}", source, i)).collect(),
+                },
+            );
+        }
+        manifest.save(&path).unwrap();
+        assert_eq!(ImportManifest::load(&path), manifest);
+
+        let mut text = std::fs::read_to_string(&path).unwrap();
+        text.push_str("(source: \"cut off\n");
+        std::fs::write(&path, text).unwrap();
+        let loaded = ImportManifest::load(&path);
+        std::fs::remove_dir_all(&root).unwrap();
+        assert_eq!(loaded, manifest);
+        assert_eq!(
+            ImportManifest::load(&root.join(MANIFEST_FILE)),
+            ImportManifest::default()
+        );
+    }
+
+    #[test]
+    fn only_content_changes_reimport() {
+        let root = scratch("changes");
+        let source = "textures/red.png";
+        write_png(&root, source, 200);
+        assert_eq!(imported(&root), [source]);
+
+        // Rewriting the same bytes bumps the mtime, which the hash doesn't see.
+        write_png(&root, source, 200);
+        assert!(imported(&root).is_empty());
+
+        write_png(&root, source, 100);
+        assert_eq!(imported(&root), [source]);
+
+        let derivative = Derivative::path(&root, source, crate::ImportedTexture::EXT);
+        std::fs::remove_file(derivative).unwrap();
+        assert_eq!(imported(&root), [source]);
+
+        let sidecar = root.join(format!("{}{}", source, SETTINGS_SUFFIX));
+        std::fs::write(sidecar, "(mips: false)").unwrap();
+        let reimported = imported(&root);
+        std::fs::remove_dir_all(&root).unwrap();
+        assert_eq!(reimported, [source]);
+    }
+}
@@ -0,0 +1,633 @@
+use std::collections::HashMap;
+use std::f32::consts::FRAC_1_PI;
+use std::path::Path;
+
+use image::{imageops, Rgba32FImage, RgbaImage};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::{import_settings, ByteReader, ByteWriter, Derivative, Importer};
+use crate::{CubeFace, CubemapFaces, EngineError, Ktx2Texture, MeshAsset, Vertex, AABB};
+
+fn extension(source: &str) -> String {
+    Path::new(source)
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase()
+}
+
+/// Options for [`TextureImporter`], from `<texture>.import.ron`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TextureImportSettings {
+    /// Color space; guessed from the file name when unset, see [`TextureImporter::guess_srgb`].
+    pub srgb: Option<bool>,
+    pub mips: bool,
+    /// Writes a BC1/BC3 KTX2 instead of RGBA8; needs whole 4x4 blocks.
+    pub compress: bool,
+}
+
+impl Default for TextureImportSettings {
+    fn default() -> Self {
+        Self {
+            srgb: None,
+            mips: true,
+            compress: false,
+        }
+    }
+}
+
+/// Decoded RGBA8 texture with its mip chain, as written by [`TextureImporter`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportedTexture {
+    pub width: u32,
+    pub height: u32,
+    pub srgb: bool,
+    /// Tightly packed RGBA8 levels, largest first.
+    pub mips: Vec<Vec<u8>>,
+}
+
+impl ImportedTexture {
+    pub const EXT: &'static str = "rtex";
+    pub const MAGIC: [u8; 4] = *b"RTEX";
+
+    pub fn from_image(image: &RgbaImage, srgb: bool, mips: bool) -> Self {
+        let (width, height) = image.dimensions();
+        let mut levels = vec![image.as_raw().clone()];
+        if mips {
+            let mut level = image.clone();
+            while level.width() > 1 || level.height() > 1 {
+                let (w, h) = ((level.width() / 2).max(1), (level.height() / 2).max(1));
+                level = imageops::resize(&level, w, h, imageops::FilterType::Triangle);
+                levels.push(level.as_raw().clone());
+            }
+        }
+        Self {
+            width,
+            height,
+            srgb,
+            mips: levels,
+        }
+    }
+    pub fn format(&self) -> wgpu::TextureFormat {
+        if self.srgb {
+            wgpu::TextureFormat::Rgba8UnormSrgb
+        } else {
+            wgpu::TextureFormat::Rgba8Unorm
+        }
+    }
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = ByteWriter::default();
+        out.u32(self.width);
+        out.u32(self.height);
+        out.u8(self.srgb as u8);
+        out.u32(self.mips.len() as u32);
+        for level in &self.mips {
+            out.bytes(level);
+        }
+        out.0
+    }
+    pub fn decode(bytes: &[u8]) -> Result<Self, EngineError> {
+        let mut reader = ByteReader::new(bytes);
+        let width = reader.u32()?;
+        let height = reader.u32()?;
+        let srgb = reader.u8()? != 0;
+        let count = reader.u32()?;
+        let mips = (0..count)
+            .map(|_| reader.bytes().map(<[u8]>::to_vec))
+            .collect::<Result<Vec<_>, _>>()?;
+        if mips.first().map(Vec::len) != Some((width * height * 4) as usize) {
+            return Err(EngineError::ImportError(format!(
+                "texture derivative doesn't match its {}x{} size",
+                width, height
+            )));
+        }
+        Ok(Self {
+            width,
+            height,
+            srgb,
+            mips,
+        })
+    }
+    /// Fresh derivative of `textures/<texture>`, if any.
+    pub fn load(texture: &str) -> Option<Self> {
+        let source = format!("textures/{}", texture);
+        let body = Derivative::read_fresh(&source, Self::EXT, Self::MAGIC)?;
+        Self::decode(&body)
+            .map_err(|e| crate::log_warning!("{}: {}", source, e))
+            .ok()
+    }
+}
+
+/// Decodes images under `textures/` into RGBA8 with a full mip chain.
+pub struct TextureImporter;
+
+impl TextureImporter {
+    const EXTENSIONS: [&'static str; 5] = ["png", "jpg", "jpeg", "tga", "bmp"];
+    const LINEAR_HINTS: [&'static str; 7] =
+        ["normal", "rough", "metal", "height", "_ao", "orm", "mask"];
+
+    /// Data maps are linear, anything else is color.
+    pub fn guess_srgb(source: &str) -> bool {
+        let stem = Path::new(source)
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        !Self::LINEAR_HINTS.iter().any(|hint| stem.contains(hint))
+    }
+}
+
+impl Importer for TextureImporter {
+    fn name(&self) -> &'static str {
+        "texture"
+    }
+    fn accepts(&self, source: &str) -> bool {
+        source.starts_with("textures/") && Self::EXTENSIONS.contains(&extension(source).as_str())
+    }
+    fn import(&self, root: &Path, source: &str, hash: u64) -> Result<Vec<String>, EngineError> {
+        let settings: TextureImportSettings = import_settings(root, source)?;
+        let image = image::open(root.join(source))?.to_rgba8();
+        let srgb = settings.srgb.unwrap_or_else(|| Self::guess_srgb(source));
+        let texture = ImportedTexture::from_image(&image, srgb, settings.mips);
+        if settings.compress {
+            match Ktx2Texture::compress(&texture) {
+                Some(compressed) => {
+                    let output = Derivative::write(
+                        root,
+                        source,
+                        Ktx2Texture::EXT,
+                        Ktx2Texture::MAGIC,
+                        hash,
+                        &compressed.encode(),
+                    )?;
+                    return Ok(vec![output]);
+                }
+                None => crate::log_warning!(
+                    "{}: {}x{} isn't whole 4x4 blocks, importing uncompressed",
+                    source,
+                    texture.width,
+                    texture.height
+                ),
+            }
+        }
+        let output = Derivative::write(
+            root,
+            source,
+            ImportedTexture::EXT,
+            ImportedTexture::MAGIC,
+            hash,
+            &texture.encode(),
+        )?;
+        Ok(vec![output])
+    }
+}
+
+/// Options for [`ModelImporter`], from `<model>.import.ron`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ModelImportSettings {
+    /// Merges vertices that are identical in every attribute.
+    pub weld: bool,
+    /// Recenters the model on the origin and scales its largest extent to 1.
+    pub normalize: bool,
+}
+
+impl Default for ModelImportSettings {
+    fn default() -> Self {
+        Self {
+            weld: true,
+            normalize: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ImportedMesh {
+    pub name: String,
+    pub mesh: MeshAsset,
+    pub aabb: AABB,
+    /// Index into the model's MTL library.
+    pub material_id: Option<usize>,
+}
+
+/// Meshes of one OBJ with tangents and bounds already computed, as written by [`ModelImporter`].
+#[derive(Debug, Clone)]
+pub struct ImportedModel {
+    /// MTL library next to the OBJ, if it names one.
+    pub mtllib: Option<String>,
+    pub meshes: Vec<ImportedMesh>,
+}
+
+impl ImportedModel {
+    pub const EXT: &'static str = "rmesh";
+    pub const MAGIC: [u8; 4] = *b"RMSH";
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = ByteWriter::default();
+        out.str(self.mtllib.as_deref().unwrap_or_default());
+        out.u32(self.meshes.len() as u32);
+        for mesh in &self.meshes {
+            out.str(&mesh.name);
+            out.u64(mesh.material_id.map_or(u64::MAX, |id| id as u64));
+            for value in [mesh.aabb.min, mesh.aabb.max]
+                .iter()
+                .flat_map(|v| v.to_array())
+            {
+                out.f32(value);
+            }
+            out.bytes(bytemuck::cast_slice(&mesh.mesh.vertices));
+            out.bytes(bytemuck::cast_slice(&mesh.mesh.indices));
+        }
+        out.0
+    }
+    pub fn decode(bytes: &[u8]) -> Result<Self, EngineError> {
+        let mut reader = ByteReader::new(bytes);
+        let mtllib = Some(reader.str()?).filter(|lib| !lib.is_empty());
+        let count = reader.u32()?;
+        let mut meshes = Vec::new();
+        for _ in 0..count {
+            let name = reader.str()?;
+            let material_id = Some(reader.u64()?)
+                .filter(|id| *id != u64::MAX)
+                .map(|id| id as usize);
+            let mut bounds = [0.0; 6];
+            for value in &mut bounds {
+                *value = reader.f32()?;
+            }
+            let vertices = read_pods::<Vertex>(reader.bytes()?)?;
+            let indices = read_pods::<u32>(reader.bytes()?)?;
+            if indices.iter().any(|i| *i as usize >= vertices.len()) {
+                return Err(EngineError::ImportError(format!(
+                    "{}: index out of range",
+                    name
+                )));
+            }
+            meshes.push(ImportedMesh {
+                name,
+                mesh: MeshAsset { vertices, indices },
+                aabb: AABB {
+                    min: glam::Vec3::from_slice(&bounds[..3]),
+                    max: glam::Vec3::from_slice(&bounds[3..]),
+                },
+                material_id,
+            });
+        }
+        Ok(Self { mtllib, meshes })
+    }
+    /// Fresh derivative of `models/<file>`, if any.
+    pub fn load(file: &str) -> Option<Self> {
+        let source = format!("models/{}", file);
+        let body = Derivative::read_fresh(&source, Self::EXT, Self::MAGIC)?;
+        Self::decode(&body)
+            .map_err(|e| crate::log_warning!("{}: {}", source, e))
+            .ok()
+    }
+}
+
+/// Derivative bytes carry no alignment guarantee, so values are read one at a time.
+fn read_pods<T: bytemuck::Pod>(bytes: &[u8]) -> Result<Vec<T>, EngineError> {
+    let size = std::mem::size_of::<T>();
+    if bytes.len() % size != 0 {
+        return Err(EngineError::ImportError(
+            "derivative array has a partial element".to_string(),
+        ));
+    }
+    Ok(bytes
+        .chunks_exact(size)
+        .map(bytemuck::pod_read_unaligned)
+        .collect())
+}
+
+/// Parses OBJs under `models/` into [`ImportedModel`]s. glTF models aren't imported: their
+/// materials, images and skins load with the meshes from one parse, so a mesh-only derivative
+/// wouldn't save that parse.
+pub struct ModelImporter;
+
+impl ModelImporter {
+    /// Merges vertices whose attributes are bit-identical and remaps the indices.
+    pub fn weld(mesh: &mut tobj::Mesh) {
+        let count = mesh.positions.len() / 3;
+        let attribute = |values: &[f32], width: usize, i: usize| -> Vec<u32> {
+            values
+                .get(i * width..(i + 1) * width)
+                .map(|v| v.iter().map(|f| f.to_bits()).collect())
+                .unwrap_or_default()
+        };
+        let mut seen: HashMap<Vec<u32>, u32> = HashMap::new();
+        let mut remap = Vec::with_capacity(count);
+        let mut kept = Vec::new();
+        for i in 0..count {
+            let mut key = attribute(&mesh.positions, 3, i);
+            key.extend(attribute(&mesh.texcoords, 2, i));
+            key.extend(attribute(&mesh.normals, 3, i));
+            key.extend(attribute(&mesh.vertex_color, 3, i));
+            let next = kept.len() as u32;
+            let index = *seen.entry(key).or_insert_with(|| {
+                kept.push(i);
+                next
+            });
+            remap.push(index);
+        }
+        if kept.len() == count {
+            return;
+        }
+        let gather = |values: &[f32], width: usize| -> Vec<f32> {
+            if values.len() < count * width {
+                return values.to_vec();
+            }
+            kept.iter()
+                .flat_map(|&i| values[i * width..(i + 1) * width].iter().copied())
+                .collect()
+        };
+        mesh.positions = gather(&mesh.positions, 3);
+        mesh.texcoords = gather(&mesh.texcoords, 2);
+        mesh.normals = gather(&mesh.normals, 3);
+        mesh.vertex_color = gather(&mesh.vertex_color, 3);
+        for index in &mut mesh.indices {
+            *index = remap[*index as usize];
+        }
+    }
+    /// Recenters `meshes` on the origin and scales their combined largest extent to 1.
+    pub fn normalize(meshes: &mut [ImportedMesh]) {
+        let bounds = meshes.iter().fold(None::<AABB>, |acc, m| {
+            Some(acc.map_or(m.aabb, |a| AABB {
+                min: a.min.min(m.aabb.min),
+                max: a.max.max(m.aabb.max),
+            }))
+        });
+        let Some(bounds) = bounds else {
+            return;
+        };
+        let center = (bounds.min + bounds.max) * 0.5;
+        let extent = (bounds.max - bounds.min).max_element();
+        let scale = if extent > 0.0 { 1.0 / extent } else { 1.0 };
+        for mesh in meshes {
+            for vertex in &mut mesh.mesh.vertices {
+                let p = (glam::Vec3::from(vertex.position) - center) * scale;
+                vertex.position = p.to_array();
+            }
+            mesh.aabb = AABB::from_vertices(&mesh.mesh.vertices);
+        }
+    }
+    /// Reads the `mtllib` the OBJ names, if any.
+    fn mtllib(obj: &Path) -> Option<String> {
+        let text = std::fs::read_to_string(obj).ok()?;
+        text.lines().find_map(|line| {
+            line.trim()
+                .strip_prefix("mtllib")
+                .map(|lib| lib.trim().to_string())
+                .filter(|lib| !lib.is_empty())
+        })
+    }
+}
+
+impl Importer for ModelImporter {
+    fn name(&self) -> &'static str {
+        "model"
+    }
+    fn accepts(&self, source: &str) -> bool {
+        source.starts_with("models/") && extension(source) == "obj"
+    }
+    fn import(&self, root: &Path, source: &str, hash: u64) -> Result<Vec<String>, EngineError> {
+        let settings: ModelImportSettings = import_settings(root, source)?;
+        let path = root.join(source);
+        let (models, _) = tobj::load_obj(
+            &path,
+            &tobj::LoadOptions {
+                triangulate: true,
+                single_index: true,
+                ..Default::default()
+            },
+        )?;
+        let mut meshes: Vec<ImportedMesh> = models
+            .into_iter()
+            .map(|mut model| {
+                if settings.weld {
+                    Self::weld(&mut model.mesh);
+                }
+                let mesh = MeshAsset::from_tobj(&model);
+                ImportedMesh {
+                    aabb: AABB::from_vertices(&mesh.vertices),
+                    name: model.name,
+                    material_id: model.mesh.material_id,
+                    mesh,
+                }
+            })
+            .collect();
+        if settings.normalize {
+            Self::normalize(&mut meshes);
+        }
+        let model = ImportedModel {
+            mtllib: Self::mtllib(&path),
+            meshes,
+        };
+        let output = Derivative::write(
+            root,
+            source,
+            ImportedModel::EXT,
+            ImportedModel::MAGIC,
+            hash,
+            &model.encode(),
+        )?;
+        Ok(vec![output])
+    }
+}
+
+/// Options for [`HdrImporter`], from `<hdr>.import.ron`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HdrImportSettings {
+    pub face_size: u32,
+}
+
+impl Default for HdrImportSettings {
+    fn default() -> Self {
+        Self { face_size: 512 }
+    }
+}
+
+/// Bakes equirect HDRs under `hdr/` into cubemap faces, so startup skips the projection.
+pub struct HdrImporter;
+
+impl HdrImporter {
+    pub const EXT: &'static str = "rcube";
+    pub const MAGIC: [u8; 4] = *b"RCUB";
+
+    /// `(forward, up, right)` per layer, matching `equirect_src.wgsl`.
+    const FACES: [[[f32; 3]; 3]; 6] = [
+        [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, -1.0]],
+        [[-1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+        [[0.0, -1.0, 0.0], [0.0, 0.0, 1.0], [1.0, 0.0, 0.0]],
+        [[0.0, 1.0, 0.0], [0.0, 0.0, -1.0], [1.0, 0.0, 0.0]],
+        [[0.0, 0.0, 1.0], [0.0, 1.0, 0.0], [1.0, 0.0, 0.0]],
+        [[0.0, 0.0, -1.0], [0.0, 1.0, 0.0], [-1.0, 0.0, 0.0]],
+    ];
+
+    /// CPU port of the `compute_equirect_to_cubemap` pass.
+    pub fn project(pixels: &[[f32; 4]], width: u32, height: u32, size: u32) -> CubemapFaces {
+        let faces = Self::FACES
+            .par_iter()
+            .map(|[forward, up, right]| {
+                let (forward, up, right) = (
+                    glam::Vec3::from(*forward),
+                    glam::Vec3::from(*up),
+                    glam::Vec3::from(*right),
+                );
+                Rgba32FImage::from_fn(size, size, |x, y| {
+                    let u = x as f32 / size as f32 * 2.0 - 1.0;
+                    let v = y as f32 / size as f32 * 2.0 - 1.0;
+                    let dir = (forward + right * u + up * v).normalize();
+                    let eq_u = dir.z.atan2(dir.x) * FRAC_1_PI * 0.5 + 0.5;
+                    let eq_v = dir.y.asin() * FRAC_1_PI + 0.5;
+                    let px = ((eq_u * width as f32) as u32).min(width - 1);
+                    let py = ((eq_v * height as f32) as u32).min(height - 1);
+                    image::Rgba(pixels[(py * width + px) as usize])
+                })
+            })
+            .collect();
+        CubemapFaces { size, faces }
+    }
+    pub fn encode(faces: &CubemapFaces) -> Vec<u8> {
+        let mut out = ByteWriter::default();
+        out.u32(faces.size);
+        for face in &faces.faces {
+            out.bytes(bytemuck::cast_slice(face.as_raw()));
+        }
+        out.0
+    }
+    pub fn decode(bytes: &[u8]) -> Result<CubemapFaces, EngineError> {
+        let mut reader = ByteReader::new(bytes);
+        let size = reader.u32()?;
+        let faces = CubeFace::ALL
+            .iter()
+            .map(|_| {
+                let texels = read_pods::<f32>(reader.bytes()?)?;
+                Rgba32FImage::from_raw(size, size, texels).ok_or_else(|| {
+                    EngineError::ImportError(format!("cube face isn't {}x{}", size, size))
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(CubemapFaces { size, faces })
+    }
+    /// Fresh baked faces of `hdr/<hdr>`, if any.
+    pub fn load(hdr: &str) -> Option<CubemapFaces> {
+        let source = format!("hdr/{}", hdr);
+        let body = Derivative::read_fresh(&source, Self::EXT, Self::MAGIC)?;
+        Self::decode(&body)
+            .map_err(|e| crate::log_warning!("{}: {}", source, e))
+            .ok()
+    }
+}
+
+impl Importer for HdrImporter {
+    fn name(&self) -> &'static str {
+        "hdr"
+    }
+    fn accepts(&self, source: &str) -> bool {
+        source.starts_with("hdr/") && extension(source) == "hdr"
+    }
+    fn import(&self, root: &Path, source: &str, hash: u64) -> Result<Vec<String>, EngineError> {
+        let settings: HdrImportSettings = import_settings(root, source)?;
+        let bytes = std::fs::read(root.join(source))?;
+        let (pixels, meta) = crate::Texture::decode_hdr(&bytes)?;
+        let faces = Self::project(&pixels, meta.width, meta.height, settings.face_size.max(1));
+        let output = Derivative::write(
+            root,
+            source,
+            Self::EXT,
+            Self::MAGIC,
+            hash,
+            &Self::encode(&faces),
+        )?;
+        Ok(vec![output])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{source_hash, DecodedTexture, SETTINGS_SUFFIX};
+
+    fn import(importer: &dyn Importer, root: &Path, source: &str) {
+        let hash = source_hash(root, source).unwrap();
+        importer.import(root, source, hash).unwrap();
+    }
+
+    #[test]
+    fn stale_or_missing_derivatives_fall_back_to_the_source() {
+        let root = crate::assets::loader::test_root();
+        let write = |name: &str, shade: u8| {
+            RgbaImage::from_pixel(8, 8, image::Rgba([shade, shade, shade, 255]))
+                .save(root.join("textures").join(name))
+                .unwrap();
+        };
+        let decode = |name: &str| DecodedTexture::decode(name).unwrap();
+
+        write("fallback-plain.png", 10);
+        assert!(matches!(
+            decode("fallback-plain.png"),
+            DecodedTexture::Image(_)
+        ));
+        import(&TextureImporter, root, "textures/fallback-plain.png");
+        assert!(matches!(
+            decode("fallback-plain.png"),
+            DecodedTexture::Imported(_)
+        ));
+        write("fallback-plain.png", 20);
+        assert!(matches!(
+            decode("fallback-plain.png"),
+            DecodedTexture::Image(_)
+        ));
+
+        write("fallback-packed.png", 10);
+        let sidecar = format!("textures/fallback-packed.png{}", SETTINGS_SUFFIX);
+        std::fs::write(root.join(sidecar), "(compress: true)").unwrap();
+        import(&TextureImporter, root, "textures/fallback-packed.png");
+        assert!(matches!(
+            decode("fallback-packed.png"),
+            DecodedTexture::Compressed(_)
+        ));
+        write("fallback-packed.png", 20);
+        assert!(matches!(
+            decode("fallback-packed.png"),
+            DecodedTexture::Image(_)
+        ));
+    }
+
+    #[test]
+    fn imported_models_keep_the_parsed_vertices() {
+        let root = crate::assets::loader::test_root();
+        let models = root.join("models");
+        std::fs::copy(models.join("cube.obj"), models.join("import-copy.obj")).unwrap();
+        std::fs::write(
+            models.join(format!("import-copy.obj{}", SETTINGS_SUFFIX)),
+            "(weld: false)",
+        )
+        .unwrap();
+        import(&ModelImporter, root, "models/import-copy.obj");
+
+        let model = ImportedModel::load("import-copy.obj").unwrap();
+        assert_eq!(model.mtllib.as_deref(), Some("cube.mtl"));
+        let (parsed, _) = tobj::load_obj(
+            models.join("import-copy.obj"),
+            &tobj::LoadOptions {
+                triangulate: true,
+                single_index: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(model.meshes.len(), parsed.len());
+        for (imported, parsed) in model.meshes.iter().zip(&parsed) {
+            let expected = MeshAsset::from_tobj(parsed);
+            assert_eq!(imported.name, parsed.name);
+            assert_eq!(
+                bytemuck::cast_slice::<_, u8>(&imported.mesh.vertices),
+                bytemuck::cast_slice::<_, u8>(&expected.vertices)
+            );
+            assert_eq!(imported.mesh.indices, expected.indices);
+        }
+    }
+}
@@ -1,22 +1,112 @@
+use std::{
+    ffi::OsStr,
+    path::{Path, PathBuf},
+};
+
 use crate::EngineError;
-pub const DIR_ASSETS: &str = "assets";
 
-pub fn asset_dir() -> Result<std::path::PathBuf, crate::EngineError> {
-    let curr_dir = std::env::current_dir()?;
-    let assets_dir = curr_dir.join(DIR_ASSETS);
-    Ok(assets_dir)
-}
+static ROOT: once_cell::sync::OnceCell<PathBuf> = once_cell::sync::OnceCell::new();
+
+/// Where asset files live. The root is, first match wins, the directory given to
+/// [`AssetPaths::set_root`] before anything was loaded, `RUPY_ASSET_DIR`, or `assets` under
+/// the working directory. Relative roots are taken against the working directory, and every
+/// path below is built with [`Path::join`], so it uses the platform's separator.
+pub struct AssetPaths;
+impl AssetPaths {
+    pub const ENV_ROOT: &'static str = "RUPY_ASSET_DIR";
+    pub const DEFAULT_ROOT: &'static str = "assets";
+
+    pub const HDR: &'static str = "hdr";
+    pub const MODELS: &'static str = "models";
+    pub const TEXTURES: &'static str = "textures";
+    pub const SHADERS: &'static str = "shaders";
+    pub const AUDIO: &'static str = "audio";
+
+    /// Pins the root for the rest of the run, e.g. from engine init. Fails once the root
+    /// was resolved, since paths handed out before would point at the old one.
+    pub fn set_root(root: impl AsRef<Path>) -> Result<(), EngineError> {
+        let root = Self::absolute(root.as_ref());
+        ROOT.set(root).map_err(|root| {
+            EngineError::FileSystemError(format!(
+                "asset root is already {}, can't move it to {}",
+                Self::root().display(),
+                root.display()
+            ))
+        })
+    }
+    pub fn root() -> &'static Path {
+        Self::root_buf()
+    }
+    fn root_buf() -> &'static PathBuf {
+        ROOT.get_or_init(|| {
+            let env = std::env::var_os(Self::ENV_ROOT);
+            Self::root_from(env.as_deref(), std::env::current_dir().ok().as_deref())
+        })
+    }
+    /// The root `env`, the value of `RUPY_ASSET_DIR`, picks, relative ones taken against
+    /// `cwd`.
+    fn root_from(env: Option<&OsStr>, cwd: Option<&Path>) -> PathBuf {
+        let root = match env {
+            Some(dir) if !dir.is_empty() => Path::new(dir),
+            _ => Path::new(Self::DEFAULT_ROOT),
+        };
+        match cwd {
+            Some(cwd) if root.is_relative() => cwd.join(root),
+            _ => root.to_path_buf(),
+        }
+    }
+    fn absolute(path: &Path) -> PathBuf {
+        match std::env::current_dir() {
+            Ok(cwd) if path.is_relative() => cwd.join(path),
+            _ => path.to_path_buf(),
+        }
+    }
 
-static BASE_PATH: once_cell::sync::Lazy<std::path::PathBuf> =
-    once_cell::sync::Lazy::new(|| super::asset_dir().expect("couldn’t find asset dir"));
+    /// Subdirectory `name` of the root.
+    pub fn dir(name: impl AsRef<Path>) -> PathBuf {
+        Self::root().join(name)
+    }
+    pub fn hdr_dir() -> PathBuf {
+        Self::dir(Self::HDR)
+    }
+    pub fn models_dir() -> PathBuf {
+        Self::dir(Self::MODELS)
+    }
+    pub fn textures_dir() -> PathBuf {
+        Self::dir(Self::TEXTURES)
+    }
+    pub fn shaders_dir() -> PathBuf {
+        Self::dir(Self::SHADERS)
+    }
+    pub fn audio_dir() -> PathBuf {
+        Self::dir(Self::AUDIO)
+    }
+
+    pub fn hdr(file: impl AsRef<Path>) -> PathBuf {
+        Self::hdr_dir().join(file)
+    }
+    pub fn model(file: impl AsRef<Path>) -> PathBuf {
+        Self::models_dir().join(file)
+    }
+    pub fn texture(file: impl AsRef<Path>) -> PathBuf {
+        Self::textures_dir().join(file)
+    }
+    pub fn shader(file: impl AsRef<Path>) -> PathBuf {
+        Self::shaders_dir().join(file)
+    }
+    pub fn audio(file: impl AsRef<Path>) -> PathBuf {
+        Self::audio_dir().join(file)
+    }
+}
 
 pub struct Asset;
 impl Asset {
-    pub fn base_path() -> &'static std::path::PathBuf {
-        &*BASE_PATH
+    /// The asset root, see [`AssetPaths`].
+    pub fn base_path() -> &'static PathBuf {
+        AssetPaths::root_buf()
     }
-    pub fn resolve(rel_path: &str) -> std::path::PathBuf {
-        Asset::base_path().join(rel_path)
+    pub fn resolve(rel_path: impl AsRef<Path>) -> PathBuf {
+        AssetPaths::root().join(rel_path)
     }
 
     pub fn read_text(rel_path: &str) -> Result<String, EngineError> {
@@ -25,8 +115,86 @@ impl Asset {
             .map_err(|e| EngineError::FileSystemError(format!("{:?}: {}", path, e)))
     }
 
-    pub fn read_bytes<P: AsRef<std::path::Path>>(path: &P) -> Result<Vec<u8>, EngineError> {
+    pub fn read_bytes<P: AsRef<Path>>(path: &P) -> Result<Vec<u8>, EngineError> {
         let bytes = std::fs::read(path)?;
         Ok(bytes)
     }
 }
+
+/// Pins the root, once per test run, to a temp copy of the repo's assets, so tests load
+/// real assets whatever the working directory. Tests reading assets call it first.
+#[cfg(test)]
+pub(crate) fn test_root() -> &'static Path {
+    static COPIED: std::sync::Once = std::sync::Once::new();
+    COPIED.call_once(|| {
+        let root = std::env::temp_dir().join("rupy_test_assets");
+        let _ = std::fs::remove_dir_all(&root);
+        let assets = Path::new(env!("CARGO_MANIFEST_DIR")).join("../assets");
+        copy_dir(&assets, &root).expect("copying the assets");
+        AssetPaths::set_root(&root).expect("a test resolved the root before test_root");
+    });
+    AssetPaths::root()
+}
+#[cfg(test)]
+fn copy_dir(from: &Path, to: &Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(to)?;
+    for entry in std::fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            std::fs::copy(entry.path(), &target)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsString;
+
+    use super::*;
+
+    #[test]
+    fn root_comes_from_the_environment_before_the_default() {
+        let cwd = Path::new("/work");
+        let temp = std::env::temp_dir();
+        let env = OsString::from(&temp);
+        assert_eq!(AssetPaths::root_from(Some(&env), Some(cwd)), temp);
+        assert_eq!(AssetPaths::root_from(Some(&env), None), temp);
+        let relative = OsString::from("data");
+        assert_eq!(
+            AssetPaths::root_from(Some(&relative), Some(cwd)),
+            cwd.join("data")
+        );
+        let empty = OsString::new();
+        assert_eq!(
+            AssetPaths::root_from(Some(&empty), Some(cwd)),
+            cwd.join(AssetPaths::DEFAULT_ROOT)
+        );
+        assert_eq!(
+            AssetPaths::root_from(None, Some(cwd)),
+            cwd.join(AssetPaths::DEFAULT_ROOT)
+        );
+    }
+
+    #[test]
+    fn assets_load_from_a_temp_root() {
+        let root = test_root();
+        assert!(root.starts_with(std::env::temp_dir()));
+        std::fs::write(root.join(AssetPaths::SHADERS).join("test.wgsl"), "// test").unwrap();
+
+        let text = Asset::read_text("shaders/test.wgsl");
+        let shader = AssetPaths::shader("test.wgsl");
+        let texture = AssetPaths::texture("a.png");
+        let moved = AssetPaths::set_root(std::env::temp_dir());
+
+        assert_eq!(text.unwrap(), "// test");
+        assert_eq!(shader, root.join("shaders").join("test.wgsl"));
+        assert_eq!(texture, root.join("textures").join("a.png"));
+        assert_eq!(Asset::base_path(), root);
+        assert!(moved.is_err());
+        assert!(AssetPaths::models_dir().join("cube.obj").is_file());
+    }
+}
@@ -3,3 +3,12 @@ pub use loader::*;
 
 pub mod watcher;
 pub use watcher::*;
+
+pub mod import;
+pub use import::*;
+
+pub mod importers;
+pub use importers::*;
+
+pub mod gltf_loader;
+pub use gltf_loader::*;
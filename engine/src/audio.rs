@@ -0,0 +1,470 @@
+//! Sound through rodio, behind the `audio` feature so headless and CI builds never open an
+//! output device. [`AudioManager`] caches clips from `assets/audio` and mixes three kinds
+//! of voice: an entity's [`AudioSource`], one-shots at a point from
+//! [`World::play_sound_at`], and UI sounds with no position. Positional voices are
+//! attenuated and panned against the camera every [`AudioManager::update`].
+
+use std::io::Cursor;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use glam::Vec3;
+use rodio::{Decoder, OutputStream, OutputStreamHandle, Sink, Source};
+
+use crate::{
+    attenuation, camera::Camera, log_error, log_info, log_warning_once, AssetPaths, AudioSource,
+    CacheKey, CacheStorage, EngineError, HashCache, World, WorldEvent,
+};
+
+/// An encoded clip; each voice decodes its own copy of the bytes as it plays.
+#[derive(Debug, Clone)]
+pub struct AudioClip {
+    pub name: String,
+    bytes: Arc<[u8]>,
+}
+
+impl AudioClip {
+    pub const EXTENSIONS: [&'static str; 2] = ["ogg", "wav"];
+
+    /// Wraps `bytes`, failing unless they decode as a format rodio was built with.
+    pub fn new(name: impl Into<String>, bytes: Vec<u8>) -> Result<Self, EngineError> {
+        let clip = Self {
+            name: name.into(),
+            bytes: bytes.into(),
+        };
+        clip.decoder()?;
+        Ok(clip)
+    }
+    fn decoder(&self) -> Result<Decoder<Cursor<Arc<[u8]>>>, EngineError> {
+        Decoder::new(Cursor::new(self.bytes.clone()))
+            .map_err(|e| EngineError::AssetLoadError(format!("{}: {}", self.name, e)))
+    }
+    fn looped_decoder(
+        &self,
+    ) -> Result<rodio::decoder::LoopedDecoder<Cursor<Arc<[u8]>>>, EngineError> {
+        Decoder::new_looped(Cursor::new(self.bytes.clone()))
+            .map_err(|e| EngineError::AssetLoadError(format!("{}: {}", self.name, e)))
+    }
+    pub fn duration(&self) -> Option<Duration> {
+        self.decoder().ok()?.total_duration()
+    }
+}
+
+/// Left and right gains a playing voice reads per sample, so pan changes land mid-clip.
+#[derive(Debug)]
+struct PanGains {
+    left: AtomicU32,
+    right: AtomicU32,
+}
+
+impl PanGains {
+    fn centered() -> Self {
+        let gains = Self {
+            left: AtomicU32::new(0),
+            right: AtomicU32::new(0),
+        };
+        gains.set(0.0);
+        gains
+    }
+    /// Equal-power pan from -1, hard left, to 1, hard right.
+    fn set(&self, pan: f32) {
+        let angle = (pan.clamp(-1.0, 1.0) + 1.0) * std::f32::consts::FRAC_PI_4;
+        self.left.store(angle.cos().to_bits(), Ordering::Relaxed);
+        self.right.store(angle.sin().to_bits(), Ordering::Relaxed);
+    }
+    fn get(&self) -> (f32, f32) {
+        (
+            f32::from_bits(self.left.load(Ordering::Relaxed)),
+            f32::from_bits(self.right.load(Ordering::Relaxed)),
+        )
+    }
+}
+
+/// Applies [`PanGains`] to a source, spreading mono sources over two channels. With more
+/// than two channels, even channels count as left and odd ones as right.
+struct Panned<S> {
+    inner: S,
+    gains: Arc<PanGains>,
+    channel: u16,
+    /// Right half of the last mono sample.
+    pending: Option<f32>,
+}
+
+impl<S: Source<Item = f32>> Panned<S> {
+    fn new(inner: S, gains: Arc<PanGains>) -> Self {
+        Self {
+            inner,
+            gains,
+            channel: 0,
+            pending: None,
+        }
+    }
+}
+
+impl<S: Source<Item = f32>> Iterator for Panned<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if let Some(right) = self.pending.take() {
+            return Some(right);
+        }
+        let sample = self.inner.next()?;
+        let (left, right) = self.gains.get();
+        let channels = self.inner.channels();
+        if channels <= 1 {
+            self.pending = Some(sample * right);
+            return Some(sample * left);
+        }
+        let gain = if self.channel % 2 == 0 { left } else { right };
+        self.channel = (self.channel + 1) % channels;
+        Some(sample * gain)
+    }
+}
+
+impl<S: Source<Item = f32>> Source for Panned<S> {
+    fn current_frame_len(&self) -> Option<usize> {
+        let len = self.inner.current_frame_len()?;
+        Some(if self.inner.channels() <= 1 {
+            len * 2
+        } else {
+            len
+        })
+    }
+    fn channels(&self) -> u16 {
+        self.inner.channels().max(2)
+    }
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum VoiceKind {
+    /// Follows the entity's position while it keeps this component.
+    Entity {
+        entity: usize,
+        source: AudioSource,
+    },
+    At {
+        position: Vec3,
+        volume: f32,
+        max_distance: f32,
+    },
+    Ui {
+        volume: f32,
+    },
+}
+
+struct Voice {
+    sink: Sink,
+    gains: Arc<PanGains>,
+    kind: VoiceKind,
+    /// Gain after attenuation as of the last update; the quietest voice is dropped first.
+    loudness: f32,
+    /// Dropped by the voice cap; the sink may take a moment to empty.
+    stopped: bool,
+}
+
+impl Voice {
+    /// A one-shot entity voice that finished stays as a marker, so the clip doesn't
+    /// restart until the component is replaced; it no longer counts as playing.
+    fn playing(&self) -> bool {
+        !self.stopped && !self.sink.empty()
+    }
+}
+
+/// Where sounds are heard from, as of the last update.
+#[derive(Debug, Clone, Copy)]
+struct Listener {
+    eye: Vec3,
+    right: Vec3,
+}
+
+impl Listener {
+    /// Gain and pan of a sound at `position`.
+    fn hear(&self, position: Vec3, volume: f32, max_distance: f32) -> (f32, f32) {
+        let offset = position - self.eye;
+        let distance = offset.length();
+        let pan = if distance > f32::EPSILON {
+            offset.dot(self.right) / distance
+        } else {
+            0.0
+        };
+        (volume * attenuation(distance, max_distance), pan)
+    }
+}
+
+/// Clip cache and voice mixer. Without an output device everything still loads and the
+/// voices are simply never started.
+pub struct AudioManager {
+    /// The stream must outlive every sink playing through its handle.
+    output: Option<(OutputStream, OutputStreamHandle)>,
+    clips: HashCache<AudioClip>,
+    voices: Vec<Voice>,
+    max_voices: usize,
+    listener: Listener,
+}
+
+impl AudioManager {
+    pub const DEFAULT_MAX_VOICES: usize = 32;
+
+    /// Opens the default output device, or runs silent when there is none.
+    pub fn new() -> Self {
+        let output = match OutputStream::try_default() {
+            Ok(output) => Some(output),
+            Err(e) => {
+                log_error!("No audio output, sound is disabled: {}", e);
+                None
+            }
+        };
+        Self {
+            output,
+            clips: HashCache::new(),
+            voices: Vec::new(),
+            max_voices: Self::DEFAULT_MAX_VOICES,
+            listener: Listener {
+                eye: Vec3::ZERO,
+                right: Vec3::X,
+            },
+        }
+    }
+    pub fn has_output(&self) -> bool {
+        self.output.is_some()
+    }
+    pub fn max_voices(&self) -> usize {
+        self.max_voices
+    }
+    /// Most voices playing at once; past it the quietest are stopped.
+    pub fn set_max_voices(&mut self, max_voices: usize) {
+        self.max_voices = max_voices;
+        self.enforce_voice_cap();
+    }
+    pub fn active_voices(&self) -> usize {
+        self.voices.iter().filter(|voice| voice.playing()).count()
+    }
+
+    /// Loads `file` from `assets/audio` once, keyed by its name.
+    pub fn load(&mut self, file: &str) -> Result<CacheKey, EngineError> {
+        let key = CacheKey::from(file);
+        if self.clips.contains(&key) {
+            return Ok(key);
+        }
+        let path = AssetPaths::audio(file);
+        let supported = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| AudioClip::EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()));
+        if !supported {
+            return Err(EngineError::AssetLoadError(format!(
+                "{}: expected one of {:?}",
+                path.display(),
+                AudioClip::EXTENSIONS
+            )));
+        }
+        let bytes = std::fs::read(&path)
+            .map_err(|e| EngineError::AssetLoadError(format!("{}: {}", path.display(), e)))?;
+        self.clips.insert(key, AudioClip::new(file, bytes)?);
+        log_info!("Loaded sound {}", file);
+        Ok(key)
+    }
+
+    /// Plays `key` once without position, e.g. a button click.
+    pub fn play_ui(&mut self, key: CacheKey, volume: f32) {
+        self.start(key, false, VoiceKind::Ui { volume }, (volume, 0.0));
+    }
+    /// Plays `key` once at `position`, fading out at `max_distance` from the camera.
+    pub fn play_at(&mut self, key: CacheKey, position: Vec3, volume: f32, max_distance: f32) {
+        let kind = VoiceKind::At {
+            position,
+            volume,
+            max_distance,
+        };
+        let heard = self.listener.hear(position, volume, max_distance);
+        self.start(key, false, kind, heard);
+    }
+    /// Plays the sounds among the world's drained events.
+    pub fn handle_event(&mut self, event: &WorldEvent) {
+        if let WorldEvent::PlaySound { key, position } = event {
+            self.play_at(*key, *position, 1.0, AudioSource::DEFAULT_MAX_DISTANCE);
+        }
+    }
+    pub fn stop_all(&mut self) {
+        // Dropping a sink stops it.
+        self.voices.clear();
+    }
+    /// Shifts the one-shots placed before a [`World::rebase`] into the new frame.
+    pub fn rebase(&mut self, offset: Vec3) {
+        self.listener.eye -= offset;
+        for voice in &mut self.voices {
+            if let VoiceKind::At { position, .. } = &mut voice.kind {
+                *position -= offset;
+            }
+        }
+    }
+
+    /// Starts and stops entity voices as [`AudioSource`]s come and go, then attenuates and
+    /// pans every positional voice from the camera.
+    pub fn update(&mut self, world: &World, camera: &Camera) {
+        // `forward x up` points left in the left-handed view, so take it the other way.
+        self.listener = Listener {
+            eye: *camera.eye(),
+            right: Vec3::Y.cross(camera.forward()).normalize_or_zero(),
+        };
+
+        let source_of = |entity: usize| world.audio_sources.get(entity).copied().flatten();
+        self.voices.retain(|voice| match voice.kind {
+            VoiceKind::Entity { entity, source } => source_of(entity) == Some(source),
+            _ => voice.playing(),
+        });
+        let voiced: std::collections::HashSet<usize> = self
+            .voices
+            .iter()
+            .filter_map(|voice| match voice.kind {
+                VoiceKind::Entity { entity, .. } => Some(entity),
+                _ => None,
+            })
+            .collect();
+        let listener = self.listener;
+        let hear_entity = |entity: usize, source: &AudioSource| match world
+            .physics
+            .positions
+            .get(entity)
+            .copied()
+            .flatten()
+        {
+            Some(position) => listener.hear(position.0, source.volume, source.max_distance),
+            None => (0.0, 0.0),
+        };
+        for (entity, source) in world.audio_sources.iter().enumerate() {
+            let Some(source) = *source else {
+                continue;
+            };
+            if voiced.contains(&entity) {
+                continue;
+            }
+            let kind = VoiceKind::Entity { entity, source };
+            let heard = hear_entity(entity, &source);
+            self.start(source.clip, source.looping, kind, heard);
+        }
+
+        for voice in &mut self.voices {
+            let (gain, pan) = match voice.kind {
+                VoiceKind::Entity { entity, source } => hear_entity(entity, &source),
+                VoiceKind::At {
+                    position,
+                    volume,
+                    max_distance,
+                } => listener.hear(position, volume, max_distance),
+                VoiceKind::Ui { volume } => (volume, 0.0),
+            };
+            voice.loudness = gain;
+            voice.sink.set_volume(gain);
+            voice.gains.set(pan);
+        }
+        self.enforce_voice_cap();
+    }
+
+    /// Decodes `key` into a new voice starting at `gain` and `pan`. Missing clips are
+    /// warned about once; entity voices start on a later update once loaded.
+    fn start(&mut self, key: CacheKey, looping: bool, kind: VoiceKind, (gain, pan): (f32, f32)) {
+        let Some((_, handle)) = &self.output else {
+            return;
+        };
+        let Some(clip) = self.clips.get(&key) else {
+            log_warning_once!("Sound {:?} is not loaded", key);
+            return;
+        };
+        let sink = match Sink::try_new(handle) {
+            Ok(sink) => sink,
+            Err(e) => {
+                log_warning_once!("Sound {}: {}", clip.name, e);
+                return;
+            }
+        };
+        let gains = Arc::new(PanGains::centered());
+        sink.set_volume(gain);
+        gains.set(pan);
+        let appended = if looping {
+            clip.looped_decoder()
+                .map(|decoder| sink.append(Panned::new(decoder.convert_samples(), gains.clone())))
+        } else {
+            clip.decoder()
+                .map(|decoder| sink.append(Panned::new(decoder.convert_samples(), gains.clone())))
+        };
+        if let Err(e) = appended {
+            log_warning_once!("{}", e);
+            return;
+        }
+        self.voices.push(Voice {
+            sink,
+            gains,
+            kind,
+            loudness: gain,
+            stopped: false,
+        });
+        self.enforce_voice_cap();
+    }
+    /// Stops the quietest playing voices until no more than `max_voices` are left.
+    fn enforce_voice_cap(&mut self) {
+        while self.active_voices() > self.max_voices {
+            let quietest = self
+                .voices
+                .iter()
+                .enumerate()
+                .filter(|(_, voice)| voice.playing())
+                .min_by(|(_, a), (_, b)| a.loudness.total_cmp(&b.loudness))
+                .map(|(i, _)| i);
+            let Some(i) = quietest else {
+                break;
+            };
+            let voice = &mut self.voices[i];
+            match voice.kind {
+                // Keep the marker so the entity's clip doesn't restart next update.
+                VoiceKind::Entity { .. } => {
+                    voice.sink.stop();
+                    voice.stopped = true;
+                }
+                _ => {
+                    self.voices.swap_remove(i);
+                }
+            }
+        }
+    }
+}
+
+impl Default for AudioManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CacheStorage<AudioClip> for AudioManager {
+    fn get(&self, key: &CacheKey) -> Option<&AudioClip> {
+        self.clips.get(key)
+    }
+    fn contains(&self, key: &CacheKey) -> bool {
+        self.clips.contains_key(key)
+    }
+    fn get_mut(&mut self, key: &CacheKey) -> Option<&mut AudioClip> {
+        self.clips.get_mut(key)
+    }
+    fn get_or_create<F>(&mut self, key: CacheKey, create_fn: F) -> &mut AudioClip
+    where
+        F: FnOnce() -> AudioClip,
+    {
+        self.clips.entry(key).or_insert_with(create_fn)
+    }
+    fn insert(&mut self, key: CacheKey, resource: AudioClip) {
+        self.clips.insert(key, resource);
+    }
+    fn remove(&mut self, key: &CacheKey) -> Option<AudioClip> {
+        self.clips.remove(key)
+    }
+    fn iter(&self) -> impl Iterator<Item = (&CacheKey, &AudioClip)> {
+        self.clips.iter()
+    }
+}
@@ -1,17 +1,14 @@
+use std::collections::HashSet;
+
+use super::{Binding, InputAction, InputMap};
 use crate::TextRegion;
+use glam::Vec2;
 use winit::{
     dpi::PhysicalPosition,
     event::{ElementState, MouseScrollDelta, WindowEvent},
-    keyboard::{KeyCode, PhysicalKey},
+    keyboard::PhysicalKey,
 };
 
-pub const W: usize = 0;
-pub const A: usize = 1;
-pub const S: usize = 2;
-pub const D: usize = 3;
-pub const J: usize = 4;
-pub const WASDJ: [usize; 5] = [W, A, S, D, J];
-
 #[derive(Debug)]
 pub enum Action {
     Projection,
@@ -22,15 +19,19 @@ pub enum Action {
 pub struct CameraControls {
     speed: f32,
     sensitivity: f32,
-    forward: bool,
-    back: bool,
-    left: bool,
-    right: bool,
-    jump: bool,
+    input_map: InputMap,
+    /// Bindings currently down, so an action with two bindings stays held until both are
+    /// released.
+    pressed: HashSet<Binding>,
     pitch: f32,
     yaw: f32,
     zoom: f32,
     last_mouse: Option<(f32, f32)>,
+    /// Wheel steps since the last [`CameraControls::take_scroll`], positive away from the user.
+    scroll: f32,
+    /// Cursor motion while [`InputAction::Pan`] was held since the last
+    /// [`CameraControls::take_pan`].
+    pan: Vec2,
 }
 
 impl CameraControls {
@@ -38,16 +39,40 @@ impl CameraControls {
         Self {
             speed,
             sensitivity,
-            forward: false,
-            back: false,
-            left: false,
-            right: false,
-            jump: false,
+            input_map: InputMap::default(),
+            pressed: HashSet::new(),
             pitch: 0.0,
             yaw: 0.0,
             zoom: 0.0,
             last_mouse: None,
+            scroll: 0.0,
+            pan: Vec2::ZERO,
+        }
+    }
+    pub fn input_map(&self) -> &InputMap {
+        &self.input_map
+    }
+    /// Replaces the bindings; held bindings stay held under the new ones.
+    pub fn set_input_map(&mut self, input_map: InputMap) {
+        self.input_map = input_map;
+    }
+    /// Whether any binding of `action` is down.
+    pub fn held(&self, action: InputAction) -> bool {
+        self.pressed
+            .iter()
+            .any(|binding| self.input_map.actions(*binding).contains(&action))
+    }
+    /// Records `binding` going down or up; `false` when it isn't bound to anything.
+    fn press(&mut self, binding: Binding, down: bool) -> bool {
+        if self.input_map.actions(binding).is_empty() {
+            return false;
+        }
+        if down {
+            self.pressed.insert(binding);
+        } else {
+            self.pressed.remove(&binding);
         }
+        true
     }
     pub fn set_zoom(&mut self, level: f32) {
         self.zoom = level
@@ -71,28 +96,8 @@ impl CameraControls {
         match event {
             WindowEvent::KeyboardInput { event, .. } => {
                 let down = event.state == ElementState::Pressed;
-
                 match event.physical_key {
-                    PhysicalKey::Code(KeyCode::KeyW) => {
-                        self.forward = down;
-                        true
-                    }
-                    PhysicalKey::Code(KeyCode::KeyS) => {
-                        self.back = down;
-                        true
-                    }
-                    PhysicalKey::Code(KeyCode::KeyA) => {
-                        self.left = down;
-                        true
-                    }
-                    PhysicalKey::Code(KeyCode::KeyD) => {
-                        self.right = down;
-                        true
-                    }
-                    PhysicalKey::Code(KeyCode::Space) => {
-                        self.jump = down;
-                        true
-                    }
+                    PhysicalKey::Code(code) => self.press(Binding::Key(code), down),
                     _ => false,
                 }
             }
@@ -100,16 +105,26 @@ impl CameraControls {
             WindowEvent::CursorMoved { position, .. } => {
                 let (x, y) = (position.x as f32, position.y as f32);
                 if let Some((lx, ly)) = self.last_mouse {
-                    let dx = (x - lx) * self.sensitivity;
-                    let dy = (y - ly) * self.sensitivity;
-                    self.yaw += dx;
-                    self.pitch = (self.pitch + dy).clamp(-89.9, 89.9);
+                    if self.held(InputAction::Pan) {
+                        self.pan += Vec2::new(x - lx, y - ly);
+                    } else {
+                        let dx = (x - lx) * self.sensitivity;
+                        let dy = (y - ly) * self.sensitivity;
+                        self.yaw += dx;
+                        self.pitch = (self.pitch + dy).clamp(-89.9, 89.9);
+                    }
                 }
                 self.last_mouse = Some((x, y));
                 true
             }
+            WindowEvent::MouseInput { state, button, .. } => {
+                self.press(Binding::Mouse(*button), state.is_pressed())
+            }
             WindowEvent::MouseWheel { delta, .. } => {
-                if let MouseScrollDelta::LineDelta(_, _scroll) = delta {}
+                self.scroll -= match delta {
+                    MouseScrollDelta::LineDelta(_, lines) => *lines,
+                    MouseScrollDelta::PixelDelta(PhysicalPosition { y, .. }) => *y as f32 / 50.0,
+                };
                 true
             }
             _ => false,
@@ -126,9 +141,15 @@ impl CameraControls {
     pub fn rotation(&self) -> (f32, f32) {
         (self.yaw, self.pitch)
     }
-
-    pub fn inputs(&self) -> [bool; 5] {
-        [self.forward, self.right, self.back, self.left, self.jump]
+    pub fn set_rotation(&mut self, yaw: f32, pitch: f32) {
+        self.yaw = yaw;
+        self.pitch = pitch.clamp(-89.9, 89.9);
+    }
+    pub fn take_scroll(&mut self) -> f32 {
+        std::mem::take(&mut self.scroll)
+    }
+    pub fn take_pan(&mut self) -> Vec2 {
+        std::mem::take(&mut self.pan)
     }
 
     pub fn text_region(&mut self, position: [f32; 2]) -> TextRegion {
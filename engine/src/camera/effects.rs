@@ -0,0 +1,355 @@
+use glam::{Quat, Vec3};
+
+/// A transient modifier layered on top of the computed eye/target.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum CameraEffect {
+    /// Adds trauma to the shared shake; see [`CameraEffects::add_trauma`].
+    Trauma(f32),
+    /// Pushes the view along `direction` (view space, e.g. `-Z` for recoil) and springs back.
+    Kick {
+        direction: Vec3,
+        strength: f32,
+        duration: f32,
+    },
+    /// Widens the FOV by `amount` radians and eases back over `duration`.
+    FovPunch { amount: f32, duration: f32 },
+    /// Blends the FOV towards `fovy` over `blend` seconds and holds it for `duration`.
+    FovOverride {
+        fovy: f32,
+        blend: f32,
+        duration: f32,
+    },
+}
+
+impl CameraEffect {
+    fn duration(&self) -> f32 {
+        match *self {
+            CameraEffect::Trauma(_) => 0.0,
+            CameraEffect::Kick { duration, .. } | CameraEffect::FovPunch { duration, .. } => {
+                duration
+            }
+            CameraEffect::FovOverride {
+                blend, duration, ..
+            } => duration + blend,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+struct ActiveEffect {
+    effect: CameraEffect,
+    age: f32,
+}
+
+/// The view after effects: applied to the rendered camera only.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CameraView {
+    pub eye: Vec3,
+    pub target: Vec3,
+    pub up: Vec3,
+    pub fovy: f32,
+}
+
+/// Ordered stack of active camera effects plus the shared trauma value driving shake.
+#[derive(Debug, Clone)]
+pub struct CameraEffects {
+    effects: Vec<ActiveEffect>,
+    trauma: f32,
+    time: f32,
+    /// Exponential trauma decay rate, per second.
+    pub trauma_decay: f32,
+    /// Rotation at full trauma, radians.
+    pub max_angle: f32,
+    /// Translation at full trauma, world units.
+    pub max_offset: f32,
+    /// Noise frequency of the shake, Hz.
+    pub frequency: f32,
+    /// Global shake scale for accessibility; 0 disables shake and kicks.
+    intensity: f32,
+}
+
+impl Default for CameraEffects {
+    fn default() -> Self {
+        Self {
+            effects: Vec::new(),
+            trauma: 0.0,
+            time: 0.0,
+            trauma_decay: 2.0,
+            max_angle: 6.0_f32.to_radians(),
+            max_offset: 0.15,
+            frequency: 12.0,
+            intensity: 1.0,
+        }
+    }
+}
+
+impl CameraEffects {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn trauma(&self) -> f32 {
+        self.trauma
+    }
+    /// Shake magnitude, trauma² scaled by intensity.
+    pub fn shake(&self) -> f32 {
+        self.trauma * self.trauma * self.intensity
+    }
+    pub fn intensity(&self) -> f32 {
+        self.intensity
+    }
+    pub fn set_intensity(&mut self, intensity: f32) {
+        self.intensity = intensity.clamp(0.0, 1.0);
+    }
+    pub fn len(&self) -> usize {
+        self.effects.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.effects.is_empty()
+    }
+
+    /// Trauma from several sources accumulates, clamped to 1.
+    pub fn add_trauma(&mut self, amount: f32) {
+        self.trauma = (self.trauma + amount.max(0.0)).min(1.0);
+    }
+    /// Trauma from an explosion at `origin`, falling off linearly to zero at `radius`.
+    pub fn add_trauma_at(&mut self, eye: Vec3, origin: Vec3, amount: f32, radius: f32) {
+        if radius <= 0.0 {
+            return;
+        }
+        let falloff = 1.0 - (eye.distance(origin) / radius).min(1.0);
+        self.add_trauma(amount * falloff);
+    }
+    pub fn add(&mut self, effect: CameraEffect) {
+        match effect {
+            CameraEffect::Trauma(amount) => self.add_trauma(amount),
+            _ => self.effects.push(ActiveEffect { effect, age: 0.0 }),
+        }
+    }
+    pub fn clear(&mut self) {
+        self.effects.clear();
+        self.trauma = 0.0;
+    }
+
+    /// Ages effects, drops expired ones and decays trauma.
+    pub fn update(&mut self, dt: f32) {
+        self.time += dt;
+        self.trauma *= (-self.trauma_decay * dt).exp();
+        if self.trauma < 1e-4 {
+            self.trauma = 0.0;
+        }
+        for active in &mut self.effects {
+            active.age += dt;
+        }
+        self.effects
+            .retain(|active| active.age < active.effect.duration());
+    }
+
+    /// Applies shake, then every active effect in insertion order, to the base view.
+    pub fn apply(&self, base: CameraView) -> CameraView {
+        let mut view = base;
+        let forward = (base.target - base.eye).normalize_or_zero();
+        let right = forward.cross(base.up).normalize_or_zero();
+        let up = right.cross(forward).normalize_or_zero();
+        let to_world = |v: Vec3| right * v.x + up * v.y - forward * v.z;
+
+        let shake = self.shake();
+        if shake > 0.0 {
+            let t = self.time * self.frequency;
+            let yaw = self.max_angle * shake * smooth_noise(1, t);
+            let pitch = self.max_angle * shake * smooth_noise(2, t);
+            let roll = self.max_angle * shake * smooth_noise(3, t);
+            let offset = Vec3::new(smooth_noise(4, t), smooth_noise(5, t), smooth_noise(6, t))
+                * self.max_offset
+                * shake;
+
+            let rotation = Quat::from_axis_angle(up, yaw) * Quat::from_axis_angle(right, pitch);
+            let dir = rotation * (view.target - view.eye);
+            view.eye += to_world(offset);
+            view.target = view.eye + dir;
+            view.up = (Quat::from_axis_angle(forward, roll) * view.up).normalize();
+        }
+
+        for active in &self.effects {
+            let progress = (active.age / active.effect.duration().max(f32::EPSILON)).min(1.0);
+            match active.effect {
+                CameraEffect::Trauma(_) => {}
+                CameraEffect::Kick {
+                    direction,
+                    strength,
+                    ..
+                } => {
+                    let amount = strength * self.intensity * (1.0 - progress).powi(2);
+                    let offset = to_world(direction.normalize_or_zero()) * amount;
+                    view.eye += offset;
+                    view.target += offset;
+                }
+                CameraEffect::FovPunch { amount, .. } => {
+                    view.fovy += amount * (1.0 - progress).powi(2);
+                }
+                CameraEffect::FovOverride {
+                    fovy,
+                    blend,
+                    duration,
+                } => {
+                    let fade_in = if blend > 0.0 {
+                        (active.age / blend).min(1.0)
+                    } else {
+                        1.0
+                    };
+                    let fade_out = if blend > 0.0 {
+                        ((duration + blend - active.age) / blend).clamp(0.0, 1.0)
+                    } else {
+                        1.0
+                    };
+                    let weight = smoothstep(fade_in.min(fade_out));
+                    view.fovy += (fovy - view.fovy) * weight;
+                }
+            }
+        }
+        view.fovy = view
+            .fovy
+            .clamp(1.0_f32.to_radians(), 179.0_f32.to_radians());
+        view
+    }
+}
+
+fn smoothstep(t: f32) -> f32 {
+    t * t * (3.0 - 2.0 * t)
+}
+
+fn hash(seed: u32, i: i32) -> f32 {
+    let mut x = (i as u32)
+        .wrapping_mul(0x9E37_79B9)
+        .wrapping_add(seed.wrapping_mul(0x85EB_CA6B));
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7FEB_352D);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846C_A68B);
+    x ^= x >> 16;
+    (x as f32 / u32::MAX as f32) * 2.0 - 1.0
+}
+
+/// 1D value noise in `[-1, 1]`, smoothly interpolated between integer lattice points.
+pub fn smooth_noise(seed: u32, t: f32) -> f32 {
+    let i = t.floor();
+    let f = t - i;
+    let a = hash(seed, i as i32);
+    let b = hash(seed, i as i32 + 1);
+    a + (b - a) * smoothstep(f)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn base() -> CameraView {
+        CameraView {
+            eye: Vec3::ZERO,
+            target: Vec3::Z * 10.0,
+            up: Vec3::Y,
+            fovy: 60.0_f32.to_radians(),
+        }
+    }
+
+    #[test]
+    fn trauma_accumulates_and_decays_exponentially() {
+        let mut effects = CameraEffects::new();
+        effects.add(CameraEffect::Trauma(0.3));
+        effects.add_trauma(0.4);
+        effects.add_trauma(-1.0);
+        assert!((effects.trauma() - 0.7).abs() < 1e-6);
+        assert!((effects.shake() - 0.49).abs() < 1e-6);
+        effects.add_trauma(0.6);
+        assert_eq!(effects.trauma(), 1.0);
+
+        effects.update(0.5);
+        assert!((effects.trauma() - (-effects.trauma_decay * 0.5).exp()).abs() < 1e-6);
+        effects.update(10.0);
+        assert_eq!(effects.trauma(), 0.0, "snaps to rest");
+
+        effects.add_trauma_at(Vec3::ZERO, Vec3::X * 5.0, 0.8, 10.0);
+        assert!((effects.trauma() - 0.4).abs() < 1e-6);
+        effects.add_trauma_at(Vec3::ZERO, Vec3::X * 20.0, 0.8, 10.0);
+        assert!((effects.trauma() - 0.4).abs() < 1e-6, "out of range");
+    }
+
+    #[test]
+    fn shake_stays_within_its_limits() {
+        let mut effects = CameraEffects::new();
+        let max_turn = 2.0 * effects.max_angle + 1e-4;
+        let max_offset = effects.max_offset * 3.0_f32.sqrt() + 1e-4;
+        for step in 0..2000 {
+            let t = step as f32 * 0.037;
+            assert!((-1.0..=1.0).contains(&smooth_noise(7, t)));
+
+            effects.add_trauma(1.0);
+            effects.update(0.011);
+            effects.add_trauma(1.0);
+            let view = effects.apply(base());
+            let forward = (view.target - view.eye).normalize();
+            assert!(view.eye.length() <= max_offset, "offset {}", view.eye);
+            assert!(forward.angle_between(Vec3::Z) <= max_turn);
+            assert!((view.target.distance(view.eye) - 10.0).abs() < 1e-3);
+            assert!((view.up.length() - 1.0).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn effects_apply_in_order_and_expire() {
+        let punch = CameraEffect::FovPunch {
+            amount: 0.2,
+            duration: 1.0,
+        };
+        let aim = CameraEffect::FovOverride {
+            fovy: 0.5,
+            blend: 0.0,
+            duration: 2.0,
+        };
+
+        let mut effects = CameraEffects::new();
+        effects.add(punch);
+        effects.add(aim);
+        assert_eq!(effects.len(), 2);
+        assert!(
+            (effects.apply(base()).fovy - 0.5).abs() < 1e-6,
+            "override last"
+        );
+
+        let mut effects = CameraEffects::new();
+        effects.add(aim);
+        effects.add(punch);
+        assert!(
+            (effects.apply(base()).fovy - 0.7).abs() < 1e-6,
+            "punch on top"
+        );
+
+        effects.update(0.5);
+        assert!(
+            (effects.apply(base()).fovy - 0.55).abs() < 1e-6,
+            "punch eases out"
+        );
+        effects.update(0.5);
+        assert_eq!(effects.len(), 1);
+        effects.update(1.0);
+        assert!(effects.is_empty());
+        assert_eq!(effects.apply(base()), base());
+    }
+
+    #[test]
+    fn zero_intensity_leaves_the_view_still() {
+        let mut effects = CameraEffects::new();
+        effects.set_intensity(0.0);
+        effects.add_trauma(1.0);
+        effects.add(CameraEffect::Kick {
+            direction: Vec3::NEG_Z,
+            strength: 0.5,
+            duration: 1.0,
+        });
+        effects.update(0.1);
+        assert_eq!(effects.shake(), 0.0);
+        assert_eq!(effects.apply(base()), base());
+
+        effects.set_intensity(1.0);
+        assert_ne!(effects.apply(base()).eye, base().eye);
+    }
+}
@@ -1,4 +1,6 @@
-use glam::{Mat4, Vec3};
+use glam::{Mat3, Mat4, Vec3};
+
+use crate::{DepthPolicy, AABB};
 
 #[derive(Copy, Clone, Debug)]
 pub struct Plane {
@@ -32,12 +34,22 @@ impl Frustum {
     }
 
     pub fn from_matrix(m: Mat4) -> Self {
+        Self::from_matrix_with(m, DepthPolicy::Standard)
+    }
+
+    /// Extracts the planes of a view-projection built under `policy`; reverse-Z swaps
+    /// which clip-space depth bound is the near plane.
+    pub fn from_matrix_with(m: Mat4, policy: DepthPolicy) -> Self {
         let m = m.to_cols_array_2d();
         let row = |i| glam::Vec4::new(m[0][i], m[1][i], m[2][i], m[3][i]);
         let r0 = row(0);
         let r1 = row(1);
         let r2 = row(2);
         let r3 = row(3);
+        let (near, far) = match policy {
+            DepthPolicy::Standard => (r3 + r2, r3 - r2),
+            DepthPolicy::ReverseZ => (r3 - r2, r2),
+        };
 
         Self {
             planes: [
@@ -45,8 +57,8 @@ impl Frustum {
                 Plane::from_components(r3.x - r0.x, r3.y - r0.y, r3.z - r0.z, r3.w - r0.w), // right
                 Plane::from_components(r3.x + r1.x, r3.y + r1.y, r3.z + r1.z, r3.w + r1.w), // bottom
                 Plane::from_components(r3.x - r1.x, r3.y - r1.y, r3.z - r1.z, r3.w - r1.w), // top
-                Plane::from_components(r3.x + r2.x, r3.y + r2.y, r3.z + r2.z, r3.w + r2.w), // near
-                Plane::from_components(r3.x - r2.x, r3.y - r2.y, r3.z - r2.z, r3.w - r2.w), // far
+                Plane::from_components(near.x, near.y, near.z, near.w),
+                Plane::from_components(far.x, far.y, far.z, far.w),
             ],
         }
     }
@@ -60,4 +72,263 @@ impl Frustum {
             .iter()
             .all(|plane| plane.distance(center) >= -radius)
     }
+
+    /// Whether any of `aabb` can be inside: its corner furthest along each plane's normal
+    /// is tested, so boxes near a frustum corner may pass while fully outside.
+    pub fn contains_aabb(&self, aabb: &AABB) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane.distance(aabb.get_normal_positive_vertex(plane.normal)) >= 0.0)
+    }
+    /// `aabb` is in model space. Straddled planes are tested in model space, which is exact
+    /// for the rotated and scaled box.
+    pub fn contains_transformed_aabb(&self, aabb: &AABB, transform: &Mat4) -> bool {
+        let (center, radius) = aabb.bounding_sphere(transform);
+        let linear = Mat3::from_mat4(*transform).transpose();
+        let translation = transform.w_axis.truncate();
+        self.planes.iter().all(|plane| {
+            let distance = plane.distance(center);
+            if distance.abs() >= radius {
+                return distance >= 0.0;
+            }
+            let normal = linear * plane.normal;
+            let vertex = aabb.get_normal_positive_vertex(normal);
+            normal.dot(vertex) + plane.normal.dot(translation) + plane.d >= 0.0
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Quat;
+
+    use super::*;
+    use crate::camera::CameraProjection;
+
+    /// At the origin looking down +Z with a 90 degree square lens, so the side planes are
+    /// `|x| = z` and `|y| = z`.
+    fn frustum() -> Frustum {
+        let view = Mat4::look_at_lh(Vec3::ZERO, Vec3::Z, Vec3::Y);
+        let proj = Mat4::perspective_lh(std::f32::consts::FRAC_PI_2, 1.0, 0.1, 100.0);
+        Frustum::from_matrix(proj * view)
+    }
+
+    fn unit() -> AABB {
+        AABB {
+            min: Vec3::splat(-0.5),
+            max: Vec3::splat(0.5),
+        }
+    }
+
+    #[test]
+    fn boxes_inside_outside_and_straddling_planes() {
+        let frustum = frustum();
+        let at = |x, y, z| Mat4::from_translation(Vec3::new(x, y, z));
+        assert!(frustum.contains_transformed_aabb(&unit(), &at(0.0, 0.0, 10.0)));
+        assert!(!frustum.contains_transformed_aabb(&unit(), &at(0.0, 0.0, -10.0)));
+        assert!(!frustum.contains_transformed_aabb(&unit(), &at(0.0, 0.0, 200.0)));
+        // Straddling the far plane and each side plane.
+        assert!(frustum.contains_transformed_aabb(&unit(), &at(0.0, 0.0, 100.0)));
+        for side in [Vec3::X, Vec3::NEG_X, Vec3::Y, Vec3::NEG_Y] {
+            let straddling = Mat4::from_translation(side * 10.0 + Vec3::new(0.0, 0.0, 10.0));
+            assert!(frustum.contains_transformed_aabb(&unit(), &straddling));
+            let outside = Mat4::from_translation(side * 12.0 + Vec3::new(0.0, 0.0, 10.0));
+            assert!(!frustum.contains_transformed_aabb(&unit(), &outside));
+        }
+    }
+
+    #[test]
+    fn scale_and_rotation_move_the_box() {
+        let frustum = frustum();
+        // Off to the right; stretched along x it reaches back into view.
+        let right = Mat4::from_translation(Vec3::new(15.0, 0.0, 10.0));
+        assert!(!frustum.contains_transformed_aabb(&unit(), &right));
+        let stretched = right * Mat4::from_scale(Vec3::new(20.0, 1.0, 1.0));
+        assert!(frustum.contains_transformed_aabb(&unit(), &stretched));
+
+        // A plank behind the camera, across the view; turned lengthwise it reaches past
+        // the near plane.
+        let plank = Mat4::from_translation(Vec3::new(0.0, 0.0, -5.0))
+            * Mat4::from_scale(Vec3::new(20.0, 1.0, 1.0));
+        assert!(!frustum.contains_transformed_aabb(&unit(), &plank));
+        let turned = Mat4::from_translation(Vec3::new(0.0, 0.0, -5.0))
+            * Mat4::from_quat(Quat::from_rotation_y(std::f32::consts::FRAC_PI_2))
+            * Mat4::from_scale(Vec3::new(20.0, 1.0, 1.0));
+        assert!(frustum.contains_transformed_aabb(&unit(), &turned));
+    }
+
+    #[test]
+    fn exact_where_the_bounding_sphere_is_not() {
+        let frustum = frustum();
+        // Thin plank just behind the camera: its sphere crosses the near plane, the box
+        // doesn't.
+        let plank = Mat4::from_translation(Vec3::new(0.0, 0.0, -3.0))
+            * Mat4::from_scale(Vec3::new(20.0, 1.0, 0.2));
+        let (center, radius) = unit().bounding_sphere(&plank);
+        assert!(frustum.contains_sphere(center, radius));
+        assert!(!frustum.contains_transformed_aabb(&unit(), &plank));
+    }
+
+    #[test]
+    fn never_culls_a_box_with_a_corner_inside() {
+        let frustum = frustum();
+        let corners = |t: Mat4| {
+            (0..8).map(move |i| {
+                let local = Vec3::new(
+                    if i & 1 == 0 { -0.5 } else { 0.5 },
+                    if i & 2 == 0 { -0.5 } else { 0.5 },
+                    if i & 4 == 0 { -0.5 } else { 0.5 },
+                );
+                t.transform_point3(local)
+            })
+        };
+        for step in 0..400 {
+            let f = step as f32;
+            let transform = Mat4::from_scale_rotation_translation(
+                Vec3::new(1.0 + f % 7.0, 0.5 + f % 3.0, 1.0 + f % 5.0),
+                Quat::from_euler(glam::EulerRot::YXZ, f * 0.37, f * 0.21, f * 0.13),
+                Vec3::new(
+                    (f * 0.7) % 30.0 - 15.0,
+                    (f * 0.3) % 20.0 - 10.0,
+                    f % 40.0 - 10.0,
+                ),
+            );
+            if corners(transform).any(|c| frustum.contains_point(c)) {
+                assert!(
+                    frustum.contains_transformed_aabb(&unit(), &transform),
+                    "culled a visible box at step {}",
+                    step
+                );
+            }
+        }
+    }
+
+    /// Both modes at an aspect of 1.5, looking from off-axis so no plane lines up with an
+    /// axis of the sample grid.
+    fn lenses() -> [(CameraProjection, DepthPolicy); 4] {
+        let perspective = CameraProjection::Perspective {
+            fovy: 1.1,
+            znear: 0.5,
+            zfar: 40.0,
+        };
+        let orthographic = CameraProjection::Orthographic {
+            half_height: 8.0,
+            znear: 0.5,
+            zfar: 40.0,
+        };
+        [
+            (perspective, DepthPolicy::Standard),
+            (perspective, DepthPolicy::ReverseZ),
+            (orthographic, DepthPolicy::Standard),
+            (orthographic, DepthPolicy::ReverseZ),
+        ]
+    }
+
+    fn view_projection(projection: CameraProjection, policy: DepthPolicy) -> Mat4 {
+        let view = Mat4::look_at_lh(
+            Vec3::new(3.0, 5.0, -12.0),
+            Vec3::new(0.0, 0.0, 8.0),
+            Vec3::Y,
+        );
+        policy.remap_projection(projection.matrix(1.5)) * view
+    }
+
+    /// Inside by clip coordinates, or `None` within `margin` of a bound, where rounding
+    /// may go either way.
+    fn brute_force(m: Mat4, point: Vec3, margin: f32) -> Option<bool> {
+        let clip = m * point.extend(1.0);
+        let bounds = [
+            clip.w + clip.x,
+            clip.w - clip.x,
+            clip.w + clip.y,
+            clip.w - clip.y,
+            clip.z,
+            clip.w - clip.z,
+        ];
+        if bounds.iter().any(|bound| bound.abs() < margin) {
+            return None;
+        }
+        Some(bounds.iter().all(|bound| *bound > 0.0))
+    }
+
+    fn sample_points() -> impl Iterator<Item = Vec3> {
+        (0..24 * 24 * 24).map(|i| {
+            let step = |n: i32| (n % 24) as f32 * 2.3 - 27.0;
+            Vec3::new(step(i), step(i / 24), step(i / 576) + 20.0)
+        })
+    }
+
+    #[test]
+    fn points_match_clip_space_in_both_modes() {
+        for (projection, policy) in lenses() {
+            let m = view_projection(projection, policy);
+            let frustum = Frustum::from_matrix_with(m, policy);
+            let (mut inside, mut outside) = (0, 0);
+            for point in sample_points() {
+                let Some(expected) = brute_force(m, point, 1e-3) else {
+                    continue;
+                };
+                assert_eq!(
+                    frustum.contains_point(point),
+                    expected,
+                    "{:?} {:?} at {}",
+                    projection,
+                    policy,
+                    point
+                );
+                if expected {
+                    inside += 1;
+                } else {
+                    outside += 1;
+                }
+            }
+            assert!(
+                inside > 100 && outside > 100,
+                "{:?}: {} in, {} out",
+                projection,
+                inside,
+                outside
+            );
+        }
+    }
+
+    #[test]
+    fn boxes_with_a_visible_point_are_kept_in_both_modes() {
+        for (projection, policy) in lenses() {
+            let m = view_projection(projection, policy);
+            let frustum = Frustum::from_matrix_with(m, policy);
+            let mut culled = 0;
+            for (i, center) in sample_points().step_by(7).enumerate() {
+                let f = i as f32;
+                let transform = Mat4::from_scale_rotation_translation(
+                    Vec3::new(1.0 + f % 3.0, 0.5 + f % 2.0, 1.0 + f % 4.0),
+                    Quat::from_euler(glam::EulerRot::YXZ, f * 0.37, f * 0.21, f * 0.13),
+                    center,
+                );
+                // Corners and a lattice through the box, so a box straddling a corner of
+                // the frustum still shows as visible.
+                let visible = (0..125).any(|j| {
+                    let t = |n: i32| (n % 5) as f32 / 4.0 - 0.5;
+                    let local = Vec3::new(t(j), t(j / 5), t(j / 25));
+                    brute_force(m, transform.transform_point3(local), 0.0) == Some(true)
+                });
+                let kept = frustum.contains_transformed_aabb(&unit(), &transform);
+                if visible {
+                    assert!(
+                        kept,
+                        "{:?} {:?} culled a visible box at {}",
+                        projection, policy, center
+                    );
+                } else if !kept {
+                    culled += 1;
+                }
+            }
+            assert!(
+                culled > 100,
+                "{:?}: only {} boxes culled",
+                projection,
+                culled
+            );
+        }
+    }
 }
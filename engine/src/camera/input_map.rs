@@ -0,0 +1,273 @@
+//! Named input actions and the keys and mouse buttons bound to them. The bindings live in
+//! `input.ron` under the asset root: a map from action name to binding names, e.g.
+//! `{ "MoveForward": ["KeyW", "ArrowUp"], "Pan": ["MouseMiddle"] }`. Key names are winit's
+//! [`KeyCode`] variants; names that don't parse are logged and skipped, so a bad entry
+//! never costs the rest of the file.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+
+use winit::{event::MouseButton, keyboard::KeyCode};
+
+use crate::{log_warning, AssetPaths, EngineError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+pub enum InputAction {
+    MoveForward,
+    MoveBack,
+    MoveLeft,
+    MoveRight,
+    Jump,
+    /// Drags the orbit camera's focus while held.
+    Pan,
+    NextProjection,
+    NextDebugMode,
+    ToggleCameraProjection,
+    ToggleFreeLook,
+    ScenePicker,
+    Devtools,
+    ReverseZ,
+    CycleMsaa,
+    ReloadScene,
+    CyclePresentMode,
+    ToggleBloom,
+    ToggleSkybox,
+    ToggleDepthPrepass,
+    Screenshot,
+    /// Opens or closes the top-down map window.
+    ToggleMapWindow,
+    /// Freezes or resumes the world while the camera keeps moving.
+    PauseTime,
+    /// Runs one fixed world step while paused.
+    StepTime,
+    /// Halves the time scale.
+    SlowerTime,
+    /// Doubles the time scale.
+    FasterTime,
+    /// Opens or closes the command console.
+    Console,
+}
+
+impl InputAction {
+    pub const ALL: [InputAction; 26] = [
+        InputAction::MoveForward,
+        InputAction::MoveBack,
+        InputAction::MoveLeft,
+        InputAction::MoveRight,
+        InputAction::Jump,
+        InputAction::Pan,
+        InputAction::NextProjection,
+        InputAction::NextDebugMode,
+        InputAction::ToggleCameraProjection,
+        InputAction::ToggleFreeLook,
+        InputAction::ScenePicker,
+        InputAction::Devtools,
+        InputAction::ReverseZ,
+        InputAction::CycleMsaa,
+        InputAction::ReloadScene,
+        InputAction::CyclePresentMode,
+        InputAction::ToggleBloom,
+        InputAction::ToggleSkybox,
+        InputAction::ToggleDepthPrepass,
+        InputAction::Screenshot,
+        InputAction::ToggleMapWindow,
+        InputAction::PauseTime,
+        InputAction::StepTime,
+        InputAction::SlowerTime,
+        InputAction::FasterTime,
+        InputAction::Console,
+    ];
+    /// Whether the action lasts while its binding is held, like movement, rather than
+    /// firing once per press.
+    pub fn held(self) -> bool {
+        matches!(
+            self,
+            InputAction::MoveForward
+                | InputAction::MoveBack
+                | InputAction::MoveLeft
+                | InputAction::MoveRight
+                | InputAction::Jump
+                | InputAction::Pan
+        )
+    }
+    pub fn name(self) -> String {
+        format!("{:?}", self)
+    }
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|action| action.name() == name)
+    }
+}
+
+/// A physical input an action can be bound to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+}
+
+impl Binding {
+    const MOUSE_PREFIX: &'static str = "Mouse";
+
+    /// Name in the bindings file: the [`KeyCode`] variant, or `Mouse` followed by the
+    /// button, e.g. `MouseMiddle` or `Mouse4`.
+    pub fn name(&self) -> String {
+        match self {
+            Binding::Key(code) => format!("{:?}", code),
+            Binding::Mouse(MouseButton::Other(n)) => format!("{}{}", Self::MOUSE_PREFIX, n),
+            Binding::Mouse(button) => format!("{}{:?}", Self::MOUSE_PREFIX, button),
+        }
+    }
+    pub fn parse(name: &str) -> Option<Self> {
+        if let Some(button) = name.strip_prefix(Self::MOUSE_PREFIX) {
+            let button = match button {
+                "Left" => MouseButton::Left,
+                "Right" => MouseButton::Right,
+                "Middle" => MouseButton::Middle,
+                "Back" => MouseButton::Back,
+                "Forward" => MouseButton::Forward,
+                other => MouseButton::Other(other.parse().ok()?),
+            };
+            return Some(Binding::Mouse(button));
+        }
+        ron::de::from_str(name).ok().map(Binding::Key)
+    }
+}
+
+/// Which actions each binding triggers. A binding may trigger several actions and an
+/// action may have several bindings.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InputMap {
+    bindings: HashMap<Binding, Vec<InputAction>>,
+}
+
+impl Default for InputMap {
+    /// The bindings the engine always had.
+    fn default() -> Self {
+        let mut map = Self::empty();
+        for (action, binding) in [
+            (InputAction::MoveForward, Binding::Key(KeyCode::KeyW)),
+            (InputAction::MoveBack, Binding::Key(KeyCode::KeyS)),
+            (InputAction::MoveLeft, Binding::Key(KeyCode::KeyA)),
+            (InputAction::MoveRight, Binding::Key(KeyCode::KeyD)),
+            (InputAction::Jump, Binding::Key(KeyCode::Space)),
+            (InputAction::Pan, Binding::Mouse(MouseButton::Middle)),
+            (InputAction::NextProjection, Binding::Key(KeyCode::KeyM)),
+            (InputAction::NextDebugMode, Binding::Key(KeyCode::KeyP)),
+            (
+                InputAction::ToggleCameraProjection,
+                Binding::Key(KeyCode::KeyO),
+            ),
+            (InputAction::ToggleFreeLook, Binding::Key(KeyCode::KeyL)),
+            (InputAction::ScenePicker, Binding::Key(KeyCode::F1)),
+            (InputAction::Devtools, Binding::Key(KeyCode::F2)),
+            (InputAction::ReverseZ, Binding::Key(KeyCode::F3)),
+            (InputAction::CycleMsaa, Binding::Key(KeyCode::F4)),
+            (InputAction::ReloadScene, Binding::Key(KeyCode::F5)),
+            (InputAction::CyclePresentMode, Binding::Key(KeyCode::F6)),
+            (InputAction::ToggleBloom, Binding::Key(KeyCode::F7)),
+            (InputAction::ToggleSkybox, Binding::Key(KeyCode::F8)),
+            (InputAction::ToggleDepthPrepass, Binding::Key(KeyCode::F9)),
+            (InputAction::ToggleMapWindow, Binding::Key(KeyCode::F10)),
+            (InputAction::Screenshot, Binding::Key(KeyCode::F12)),
+            (InputAction::PauseTime, Binding::Key(KeyCode::Pause)),
+            (InputAction::StepTime, Binding::Key(KeyCode::Period)),
+            (InputAction::SlowerTime, Binding::Key(KeyCode::BracketLeft)),
+            (InputAction::FasterTime, Binding::Key(KeyCode::BracketRight)),
+            (InputAction::Console, Binding::Key(KeyCode::Backquote)),
+        ] {
+            map.bind(action, binding);
+        }
+        map
+    }
+}
+
+impl InputMap {
+    pub const FILE: &'static str = "input.ron";
+
+    pub fn empty() -> Self {
+        Self {
+            bindings: HashMap::new(),
+        }
+    }
+    pub fn path() -> PathBuf {
+        AssetPaths::root().join(Self::FILE)
+    }
+
+    /// Adds `binding` to `action`, keeping its other bindings.
+    pub fn bind(&mut self, action: InputAction, binding: Binding) {
+        let actions = self.bindings.entry(binding).or_default();
+        if !actions.contains(&action) {
+            actions.push(action);
+        }
+    }
+    /// Removes every binding of `action`.
+    pub fn unbind(&mut self, action: InputAction) {
+        self.bindings.retain(|_, actions| {
+            actions.retain(|a| *a != action);
+            !actions.is_empty()
+        });
+    }
+    /// Actions `binding` triggers.
+    pub fn actions(&self, binding: Binding) -> &[InputAction] {
+        self.bindings
+            .get(&binding)
+            .map(Vec::as_slice)
+            .unwrap_or(&[])
+    }
+    /// Bindings of `action`, sorted by name.
+    pub fn bindings(&self, action: InputAction) -> Vec<Binding> {
+        let mut bindings: Vec<Binding> = self
+            .bindings
+            .iter()
+            .filter(|(_, actions)| actions.contains(&action))
+            .map(|(binding, _)| *binding)
+            .collect();
+        bindings.sort_by_key(Binding::name);
+        bindings
+    }
+
+    /// Reads a bindings file. Unknown action and binding names are logged and skipped;
+    /// actions the file leaves out have no bindings.
+    pub fn parse(source: &str) -> Result<Self, ron::error::SpannedError> {
+        let file: BTreeMap<String, Vec<String>> = ron::de::from_str(source)?;
+        let mut map = Self::empty();
+        for (name, bindings) in &file {
+            let Some(action) = InputAction::from_name(name) else {
+                log_warning!("Input bindings: unknown action '{}'", name);
+                continue;
+            };
+            for binding in bindings {
+                match Binding::parse(binding) {
+                    Some(binding) => map.bind(action, binding),
+                    None => log_warning!("Input bindings: unknown key '{}' for {}", binding, name),
+                }
+            }
+        }
+        Ok(map)
+    }
+    pub fn to_ron(&self) -> Result<String, EngineError> {
+        let file: BTreeMap<String, Vec<String>> = InputAction::ALL
+            .into_iter()
+            .map(|action| {
+                let bindings = self.bindings(action).iter().map(Binding::name).collect();
+                (action.name(), bindings)
+            })
+            .collect();
+        ron::ser::to_string_pretty(&file, ron::ser::PrettyConfig::default())
+            .map_err(|e| EngineError::AssetLoadError(e.to_string()))
+    }
+    /// The bindings in [`InputMap::path`], or the defaults when there is no such file.
+    pub fn load() -> Result<Self, EngineError> {
+        let path = Self::path();
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let source = std::fs::read_to_string(&path)?;
+        Self::parse(&source)
+            .map_err(|e| EngineError::AssetLoadError(format!("{}: {}", path.display(), e)))
+    }
+    pub fn save(&self) -> Result<(), EngineError> {
+        std::fs::write(Self::path(), self.to_ron()?)?;
+        Ok(())
+    }
+}
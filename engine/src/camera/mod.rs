@@ -2,6 +2,9 @@ pub mod controller;
 
 pub use controller::*;
 
+pub mod input_map;
+pub use input_map::*;
+
 pub mod frustum;
 pub use frustum::*;
 
@@ -14,28 +17,61 @@ pub use model::*;
 pub mod projection;
 pub use projection::*;
 
+pub mod effects;
+pub use effects::*;
+
+pub mod orbit;
+pub use orbit::*;
+
 use crate::{
-    log_debug, log_warning, Entity, ModelManager, Position, RenderBindGroupLayouts, Renderable,
-    Rotation, Scale, TextRegion, Velocity, Vertex, VertexInstance, WgpuBuffer, World, GROUND_Y,
+    log_debug, log_warning, Collider, Entity, Layers, ModelManager, Position,
+    RenderBindGroupLayouts, Renderable, Rotation, Scale, TextProjection, TextRegion, Velocity,
+    Vertex, VertexInstance, WgpuBuffer, World, GROUND_Y,
 };
 
 use glam::{FloatExt, Mat4, Quat, Vec3};
 
+/// Vertical field of view of a new camera's perspective lens, 89 degrees.
+const DEFAULT_FOVY: f32 = 89.0 * std::f32::consts::PI / 180.0;
+/// Half size of the camera model's terrain collision box, which stands on the model's origin.
+const MODEL_HALF_EXTENTS: Vec3 = Vec3::new(0.3, 0.9, 0.3);
+/// Room the third-person eye keeps from whatever blocks its view of the model.
+const BOOM_MARGIN: f32 = 0.2;
+/// Shortest the third-person boom gets, however close the obstruction.
+const BOOM_MIN: f32 = 0.3;
+/// Rates per second the boom closes the gap to its unobstructed length at: fast when
+/// pulling in, slow when growing back.
+const BOOM_PULL_IN_RATE: f32 = 30.0;
+const BOOM_RETURN_RATE: f32 = 3.0;
+
 #[derive(Debug)]
 pub struct Camera {
     eye: Vec3,
     target: Vec3,
     up: Vec3,
     aspect: f32,
-    fovy: f32,
-    znear: f32,
-    zfar: f32,
+    projection: CameraProjection,
+    /// The lens [`Camera::toggle_projection_mode`] switches to.
+    alternate_projection: CameraProjection,
     forward: Vec3,
     reach_distance: f32,
     model: CameraModel,
     bind_group: wgpu::BindGroup,
     uniform_buffer: WgpuBuffer,
     free_look: bool,
+    effects: CameraEffects,
+    view: CameraView,
+    view_override: Option<(Vec3, Vec3)>,
+    orbit: Option<OrbitCamera>,
+    /// Pose before [`Camera::enter_orbit`], restored by [`Camera::exit_orbit`].
+    orbit_return: Option<CameraPose>,
+    /// Look angles the controls had when orbiting started; orbit input is read as the
+    /// change from these and the controls are reset to them every frame.
+    orbit_look: Option<(f32, f32)>,
+    render_mask: Layers,
+    /// Length of the third-person boom after occlusion, see [`Camera::occluded_eye`];
+    /// `None` outside third person.
+    boom: Option<f32>,
 }
 
 impl Camera {
@@ -48,6 +84,8 @@ impl Camera {
             >() as u64),
         },
     };
+    /// Every layer but the opt-in ones, [`Layers::EDITOR`] and [`Layers::MAP`].
+    pub const DEFAULT_RENDER_MASK: Layers = Layers(!(Layers::EDITOR.0 | Layers::MAP.0));
     pub fn new(device: &wgpu::Device, aspect: f32) -> Self {
         let model = CameraModel::new("goblin.obj", "v_normal.wgsl");
         let uniform_buffer = WgpuBuffer::from_data(
@@ -62,7 +100,7 @@ impl Camera {
         let target = z;
         let forward = z;
         let up = Vec3::Y;
-        let fovy = 89.0_f32.to_radians();
+        let fovy = DEFAULT_FOVY;
         let zfar = 100.0;
         let znear = 0.1;
         let reach_distance = 2.0;
@@ -73,20 +111,37 @@ impl Camera {
             target,
             up,
             aspect,
-            fovy,
-            znear,
-            zfar,
+            projection: CameraProjection::Perspective { fovy, znear, zfar },
+            alternate_projection: CameraProjection::Orthographic {
+                half_height: 10.0,
+                znear,
+                zfar,
+            },
             reach_distance,
             forward,
             model,
             bind_group,
             uniform_buffer,
             free_look,
+            effects: CameraEffects::new(),
+            view: CameraView {
+                eye,
+                target,
+                up,
+                fovy,
+            },
+            view_override: None,
+            orbit: None,
+            orbit_return: None,
+            orbit_look: None,
+            render_mask: Self::DEFAULT_RENDER_MASK,
+            boom: None,
         }
     }
 
+    /// See [`aspect_ratio`].
     pub fn resize(&mut self, width: f32, height: f32) {
-        self.aspect = width / height;
+        self.aspect = aspect_ratio(width, height);
     }
     pub fn forward(&self) -> Vec3 {
         self.forward
@@ -95,10 +150,10 @@ impl Camera {
         &self.eye
     }
     pub fn zfar(&self) -> f32 {
-        self.zfar
+        self.projection.zfar()
     }
     pub fn znear(&self) -> f32 {
-        self.znear
+        self.projection.znear()
     }
     pub fn look_at(&mut self, pos: Vec3) {
         self.target = pos;
@@ -109,8 +164,28 @@ impl Camera {
     pub fn up(&self) -> &Vec3 {
         &self.up
     }
+    /// Field of view of the perspective lens, kept while the camera is orthographic.
     pub fn fovy(&self) -> f32 {
-        self.fovy
+        self.projection
+            .fovy()
+            .or(self.alternate_projection.fovy())
+            .unwrap_or(DEFAULT_FOVY)
+    }
+    pub fn projection_mode(&self) -> CameraProjection {
+        self.projection
+    }
+    /// Draws through `projection` from the next frame; eye and target are left alone.
+    /// Switching between perspective and orthographic keeps the lens switched from for
+    /// [`Camera::toggle_projection_mode`].
+    pub fn set_projection_mode(&mut self, projection: CameraProjection) {
+        if projection.is_orthographic() != self.projection.is_orthographic() {
+            self.alternate_projection = self.projection;
+        }
+        self.projection = projection;
+    }
+    /// Swaps between the perspective and the orthographic lens.
+    pub fn toggle_projection_mode(&mut self) {
+        std::mem::swap(&mut self.projection, &mut self.alternate_projection);
     }
     pub fn set_free_look(&mut self, val: bool) {
         self.free_look = val;
@@ -122,12 +197,132 @@ impl Camera {
     pub fn entity(&self) -> Option<Entity> {
         self.model.entity()
     }
+    /// Layers this camera draws; entities on none of them are skipped when batching.
+    pub fn render_mask(&self) -> Layers {
+        self.render_mask
+    }
+    /// Takes effect with the next instance batch. [`Camera::update`] still swaps the first
+    /// and third person layers as the view changes.
+    pub fn set_render_mask(&mut self, mask: Layers) {
+        self.render_mask = mask;
+    }
+    /// The rendered view after camera effects. [`Camera::eye`] stays the unshaken,
+    /// simulation-facing position.
+    pub fn view(&self) -> &CameraView {
+        &self.view
+    }
+    pub fn effects(&self) -> &CameraEffects {
+        &self.effects
+    }
+    pub fn effects_mut(&mut self) -> &mut CameraEffects {
+        &mut self.effects
+    }
+    pub fn add_effect(&mut self, effect: CameraEffect) {
+        self.effects.add(effect);
+    }
+    pub fn add_trauma(&mut self, amount: f32) {
+        self.effects.add_trauma(amount);
+    }
+    /// Forces the rendered eye and target, e.g. for a sequence camera track. Effects still
+    /// apply on top; `None` hands the view back to the simulated camera.
+    pub fn set_override(&mut self, view: Option<(Vec3, Vec3)>) {
+        self.view_override = view;
+    }
+    pub fn view_override(&self) -> Option<(Vec3, Vec3)> {
+        self.view_override
+    }
+    pub fn pose(&self) -> CameraPose {
+        CameraPose {
+            eye: self.eye,
+            target: self.target,
+            up: self.up,
+        }
+    }
+    pub fn rebase(&mut self, offset: Vec3) {
+        self.eye -= offset;
+        self.target -= offset;
+        self.view.eye -= offset;
+        self.view.target -= offset;
+        if let Some(orbit) = &mut self.orbit {
+            orbit.rebase(offset);
+        }
+        if let Some(pose) = &mut self.orbit_return {
+            pose.eye -= offset;
+            pose.target -= offset;
+        }
+    }
+    /// Moves the eye to `position` facing the same way, and the entity the camera follows
+    /// along with it.
+    pub fn teleport(&mut self, world: &mut World, position: Vec3) {
+        let offset = self.eye - position;
+        if let Some(entity) = self.entity() {
+            if let Some(Some(Position(pos))) = world.physics.positions.get(entity.0).copied() {
+                world.insert_position(entity, Position(pos - offset));
+            }
+        }
+        self.rebase(offset);
+    }
+    /// Switches to orbiting `target` from `initial_distance`, starting at the current viewing
+    /// angle. Movement input is ignored until [`Camera::exit_orbit`].
+    pub fn enter_orbit(&mut self, target: impl Into<OrbitTarget>, initial_distance: f32) {
+        let target = target.into();
+        let focus = match target {
+            OrbitTarget::Point(point) => point,
+            OrbitTarget::Entity(_) => self.target,
+        };
+        if self.orbit.is_none() {
+            self.orbit_return = Some(self.pose());
+        }
+        self.orbit = Some(OrbitCamera::new(target, initial_distance).looking_from(self.eye, focus));
+        log_debug!("Orbit: {:?}", target);
+    }
+    /// Leaves orbit mode and restores the pose from before it; `false` if not orbiting.
+    pub fn exit_orbit(&mut self) -> bool {
+        if self.orbit.take().is_none() {
+            return false;
+        }
+        if let Some(pose) = self.orbit_return.take() {
+            self.eye = pose.eye;
+            self.target = pose.target;
+            self.up = pose.up;
+        }
+        true
+    }
+    pub fn orbit(&self) -> Option<&OrbitCamera> {
+        self.orbit.as_ref()
+    }
+    pub fn orbit_mut(&mut self) -> Option<&mut OrbitCamera> {
+        self.orbit.as_mut()
+    }
+    /// The mode `update` runs this frame: orbit while orbiting, otherwise `projection`.
+    pub fn mode(&self, projection: Projection) -> Projection {
+        if self.orbit.is_some() {
+            Projection::Orbit
+        } else {
+            projection
+        }
+    }
+    /// Ticks the effect stack and recomputes the rendered view from the base eye/target.
+    pub fn update_effects(&mut self, dt: f32) {
+        self.effects.update(dt);
+        let (eye, target) = self.view_override.unwrap_or((self.eye, self.target));
+        self.view = self.effects.apply(CameraView {
+            eye,
+            target,
+            up: self.up,
+            fovy: self.fovy(),
+        });
+    }
     pub fn buffer(&self) -> &crate::WgpuBuffer {
         &self.uniform_buffer
     }
     pub fn upload(&mut self, queue: &wgpu::Queue, device: &wgpu::Device) {
-        self.uniform_buffer
+        let grew = self
+            .uniform_buffer
             .write_data(queue, device, &[self.uniform()], None);
+        if grew {
+            self.bind_group = crate::BindGroup::camera(device, &self.uniform_buffer);
+        }
     }
     pub fn bind_group(&self) -> &wgpu::BindGroup {
         &self.bind_group
@@ -181,6 +376,12 @@ impl Camera {
             world.insert_scale(entity, Scale::one());
             world.insert_position(entity, Position::new(0.0, GROUND_Y + 1.0, 0.0));
             world.insert_renderable(entity, renderable);
+            // Drawn from first person, the model would fill the view from inside.
+            world.insert_layers(entity, Layers::THIRD_PERSON);
+            world.insert_collider(
+                entity,
+                Collider::new(MODEL_HALF_EXTENTS).with_offset(Vec3::Y * MODEL_HALF_EXTENTS.y),
+            );
             log_debug!("Spawned camera model: {}", self.model.model());
         } else {
             log_warning!("No camera model available");
@@ -193,7 +394,22 @@ impl Camera {
         cam: &mut CameraControls,
         projection: &Projection,
         bossman: &Entity,
+        dt: f32,
     ) {
+        let first_person = self.orbit.is_none() && *projection == Projection::FirstPerson;
+        self.switch_view_layers(first_person);
+        if self.orbit.is_some() || *projection != Projection::ThirdPerson || self.free_look {
+            self.boom = None;
+        }
+        if self.orbit.is_some() {
+            self.update_orbit(world, cam, dt);
+            return;
+        }
+        self.orbit_look = None;
+        if *projection == Projection::FreeFly {
+            self.update_free_fly(cam, dt);
+            return;
+        }
         let Some(model_entity) = self.model.entity() else {
             return;
         };
@@ -240,8 +456,11 @@ impl Camera {
 
                 let behind = cam_rot * Vec3::Z * cam_distance;
                 let above = Vec3::Y * cam_height;
-                self.eye = player_pos + behind + above;
                 self.target = player_pos + Vec3::Y * 1.0;
+                self.eye = player_pos + behind + above;
+                if !self.free_look {
+                    self.eye = self.occluded_eye(world, self.target, self.eye, dt);
+                }
                 self.up = Vec3::Y;
 
                 world.insert_rotation(
@@ -252,6 +471,7 @@ impl Camera {
                     )),
                 );
             }
+            Projection::Orbit | Projection::FreeFly => return,
         }
 
         let mut forward = self.target - self.eye;
@@ -262,18 +482,18 @@ impl Camera {
         let right = forward.cross(Vec3::Y).normalize_or_zero();
 
         let mut displacement = Vec3::ZERO;
-        let inputs = cam.inputs();
 
-        if inputs[W] {
+        if cam.held(InputAction::MoveForward) {
             displacement += forward;
         }
-        if inputs[S] {
+        if cam.held(InputAction::MoveBack) {
             displacement -= forward;
         }
-        if inputs[A] {
+        // `right` is forward x up, which the left-handed view shows pointing left.
+        if cam.held(InputAction::MoveRight) {
             displacement -= right;
         }
-        if inputs[D] {
+        if cam.held(InputAction::MoveLeft) {
             displacement += right;
         }
 
@@ -286,27 +506,136 @@ impl Camera {
             velocity.z = FloatExt::lerp(prev_vel.z, move_vec.z, blend);
         }
 
-        if inputs.len() > 4 && inputs[4] && prev_vel.y.abs() < 0.01 {
+        let grounded = world
+            .grounded(model_entity)
+            .unwrap_or(prev_vel.y.abs() < 0.01);
+        if cam.held(InputAction::Jump) && grounded {
             velocity.y = 5.0;
         }
 
         world.insert_velocity(model_entity, Velocity(velocity));
+        self.update_effects(dt);
     }
 
+    /// The third-person eye on the line from `target` to the `desired` eye, pulled in to
+    /// just short of the first solid block or camera-blocking collider on it. The boom
+    /// shortens within a few frames and grows back slowly, so sliding along a wall doesn't
+    /// make the view pop.
+    fn occluded_eye(&mut self, world: &World, target: Vec3, desired: Vec3, dt: f32) -> Vec3 {
+        let offset = desired - target;
+        let length = offset.length();
+        if length <= f32::EPSILON {
+            return desired;
+        }
+        let dir = offset / length;
+        let hit = world.camera_obstruction(target, dir, length + BOOM_MARGIN);
+        let clear = clear_boom(hit, length);
+        let boom = smooth_boom(self.boom.unwrap_or(clear), clear, dt);
+        self.boom = Some(boom);
+        target + dir * boom
+    }
+    /// Shows [`Layers::FIRST_PERSON`] and hides [`Layers::THIRD_PERSON`] while looking
+    /// from first person, the other way round otherwise.
+    fn switch_view_layers(&mut self, first_person: bool) {
+        let (shown, hidden) = if first_person {
+            (Layers::FIRST_PERSON, Layers::THIRD_PERSON)
+        } else {
+            (Layers::THIRD_PERSON, Layers::FIRST_PERSON)
+        };
+        self.render_mask = self.render_mask.with(shown).without(hidden);
+    }
+
+    fn update_orbit(&mut self, world: &World, cam: &mut CameraControls, dt: f32) {
+        let (yaw, pitch) = cam.rotation();
+        let (look_yaw, look_pitch) = *self.orbit_look.get_or_insert((yaw, pitch));
+        cam.set_rotation(look_yaw, look_pitch);
+        let (scroll, pan) = (cam.take_scroll(), cam.take_pan());
+        let (fovy, aspect) = (self.fovy(), self.aspect);
+        let Some(orbit) = self.orbit.as_mut() else {
+            return;
+        };
+        orbit.rotate(yaw - look_yaw, pitch - look_pitch);
+        orbit.zoom(scroll);
+        orbit.pan(pan * cam.sensitivity() * 10.0);
+        let focus = match orbit.target {
+            OrbitTarget::Point(point) => point,
+            OrbitTarget::Entity(entity) => match world.spatial().bounds(entity) {
+                Some(aabb) => {
+                    if !orbit.fitted() {
+                        orbit.fit_bounds(&aabb, fovy, aspect);
+                    }
+                    (aabb.min + aabb.max) * 0.5
+                }
+                None => world
+                    .physics
+                    .positions
+                    .get(entity.0)
+                    .and_then(|p| *p)
+                    .map_or(self.target, |p| p.0),
+            },
+        };
+        let (eye, target) = orbit.update(focus, dt);
+        self.eye = eye;
+        self.target = target;
+        self.up = Vec3::Y;
+        self.update_effects(dt);
+    }
+    fn update_free_fly(&mut self, cam: &CameraControls, dt: f32) {
+        let rotation =
+            Rotation::from_euler(cam.yaw().to_radians(), cam.pitch().to_radians(), 0.0).quat();
+        let forward = rotation * -Vec3::Z;
+        let right = forward.cross(Vec3::Y).normalize_or_zero();
+        let mut displacement = Vec3::ZERO;
+        if cam.held(InputAction::MoveForward) {
+            displacement += forward;
+        }
+        if cam.held(InputAction::MoveBack) {
+            displacement -= forward;
+        }
+        if cam.held(InputAction::MoveRight) {
+            displacement -= right;
+        }
+        if cam.held(InputAction::MoveLeft) {
+            displacement += right;
+        }
+        self.eye += displacement.normalize_or_zero() * cam.speed() * dt;
+        self.target = self.eye + forward;
+        self.up = Vec3::Y;
+        self.update_effects(dt);
+    }
+
+    /// Matrices of the rendered (post-effect) view. Field of view effects only apply to
+    /// the perspective lens.
     pub fn view_projection_matrix(&self) -> (Mat4, Mat4, Mat4) {
-        let view = Mat4::look_at_lh(self.eye, self.target, self.up);
-        let proj = Mat4::perspective_lh(self.fovy, self.aspect, self.znear, self.zfar);
+        let view = Mat4::look_at_lh(self.view.eye, self.view.target, self.view.up);
+        let lens = match self.projection {
+            CameraProjection::Perspective { znear, zfar, .. } => CameraProjection::Perspective {
+                fovy: self.view.fovy,
+                znear,
+                zfar,
+            },
+            orthographic => orthographic,
+        };
+        let proj = crate::RenderSettings::depth_policy().remap_projection(lens.matrix(self.aspect));
         let inv_view = view.inverse();
         let inv_proj = proj.inverse();
         (proj * view, inv_proj, inv_view)
     }
+    /// Built from the rendered view so shaken frames don't cull visible geometry.
     pub fn frustum(&self) -> Frustum {
         let vp = self.view_projection_matrix();
-        Frustum::from_matrix(vp.0)
+        Frustum::from_matrix_with(vp.0, crate::RenderSettings::depth_policy())
+    }
+    /// What world-anchored text is placed with, from the rendered view.
+    pub fn text_projection(&self) -> TextProjection {
+        TextProjection {
+            view_proj: self.view_projection_matrix().0,
+            eye: self.view.eye,
+        }
     }
     pub fn uniform(&self) -> crate::camera::CameraUniform {
         let mut uniform = crate::camera::CameraUniform::new();
-        uniform.update(self.view_projection_matrix(), self.eye);
+        uniform.update(self.view_projection_matrix(), self.view.eye);
         uniform
     }
     pub fn text_region(&mut self, position: [f32; 2]) -> TextRegion {
@@ -328,6 +657,40 @@ impl Camera {
     pub fn reach_distance(&self) -> f32 {
         self.reach_distance
     }
+    /// Entity the camera looks at within [`Camera::reach_distance`].
+    pub fn pick(&self, world: &crate::World) -> Option<crate::RayHit> {
+        let dir = *self.target() - *self.eye();
+        world.raycast(*self.eye(), dir, self.reach_distance)
+    }
+    pub fn aimed_block(&self, terrain: &crate::Terrain) -> Option<(glam::IVec3, glam::IVec3)> {
+        let dir = *self.target() - *self.eye();
+        terrain.raycast_voxel(*self.eye(), dir, self.reach_distance)
+    }
+}
+
+/// Length of a boom `length` long that stops [`BOOM_MARGIN`] short of an obstruction
+/// `hit` along it, but no shorter than [`BOOM_MIN`].
+fn clear_boom(hit: Option<f32>, length: f32) -> f32 {
+    hit.map_or(length, |hit| {
+        (hit - BOOM_MARGIN).clamp(BOOM_MIN.min(length), length)
+    })
+}
+
+/// One `dt` step of the boom from `current` toward `clear`, at [`BOOM_PULL_IN_RATE`] when
+/// shortening and [`BOOM_RETURN_RATE`] when lengthening.
+fn smooth_boom(current: f32, clear: f32, dt: f32) -> f32 {
+    let rate = if clear < current {
+        BOOM_PULL_IN_RATE
+    } else {
+        BOOM_RETURN_RATE
+    };
+    FloatExt::lerp(current, clear, 1.0 - (-rate * dt).exp())
+}
+
+/// Width over height. Sizes under 1x1, as a minimized window reports, are clamped to keep
+/// the aspect finite.
+pub fn aspect_ratio(width: f32, height: f32) -> f32 {
+    width.max(1.0) / height.max(1.0)
 }
 
 pub fn compute_target_from_rotation(eye: Vec3, yaw: f32, pitch: f32, distance: f32) -> Vec3 {
@@ -378,3 +741,92 @@ pub fn ray_intersects_ray_sphere(
         None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn boom_stops_short_of_a_wall() {
+        // A solid chunk spanning x 8..12, between the target and the desired eye.
+        let mut terrain = crate::Terrain::new(crate::Medium::Air);
+        terrain.insert_chunk_stream(crate::chunk::Chunk::new((2, 0, 0)), crate::Medium::Ground);
+        let target = Vec3::new(1.5, 1.5, 1.5);
+        let length = 15.0;
+        let hit = crate::raycast_terrain(&terrain, target, Vec3::X, length + BOOM_MARGIN);
+        assert_eq!(hit, Some(6.5));
+        let clear = clear_boom(hit, length);
+        assert!((clear - (6.5 - BOOM_MARGIN)).abs() < 1e-5);
+
+        // Nothing in the way, and a wall right behind the target.
+        assert_eq!(clear_boom(None, length), length);
+        assert_eq!(clear_boom(Some(0.1), length), BOOM_MIN);
+        assert_eq!(clear_boom(Some(0.1), 0.2), 0.2);
+
+        // Pulled in within a few frames, let out again slowly.
+        let mut boom = length;
+        for _ in 0..6 {
+            boom = smooth_boom(boom, clear, 1.0 / 60.0);
+        }
+        assert!(boom < clear + 1.0, "boom {}", boom);
+        let mut boom = clear;
+        for _ in 0..6 {
+            boom = smooth_boom(boom, length, 1.0 / 60.0);
+        }
+        assert!(boom < clear + 3.0, "boom {}", boom);
+    }
+
+    /// Needs an adapter; passes without one.
+    #[test]
+    fn orbiting_restores_the_pose_from_before() {
+        let _ = crate::GPU::init(crate::GpuRequirements::default());
+        let Ok(device) = crate::GPU::with_read_recovered(|gpu| gpu.device().clone()) else {
+            return;
+        };
+        let mut camera = Camera::new(&device, 1.0);
+        let before = CameraPose {
+            eye: Vec3::new(1.0, 2.0, 3.0),
+            target: Vec3::new(0.0, 1.0, 0.0),
+            up: Vec3::Y,
+        };
+        (camera.eye, camera.target, camera.up) = (before.eye, before.target, before.up);
+        assert!(!camera.exit_orbit());
+        assert_eq!(
+            camera.mode(Projection::ThirdPerson),
+            Projection::ThirdPerson
+        );
+
+        camera.enter_orbit(Vec3::new(5.0, 0.0, 5.0), 4.0);
+        assert_eq!(camera.mode(Projection::ThirdPerson), Projection::Orbit);
+        assert_eq!(camera.orbit().unwrap().distance, 4.0);
+        (camera.eye, camera.target, camera.up) = (Vec3::splat(9.0), Vec3::ZERO, Vec3::X);
+        // Retargeting while orbiting keeps the pose from before the first enter.
+        camera.enter_orbit(Vec3::ZERO, 2.0);
+        (camera.eye, camera.target) = (Vec3::splat(-9.0), Vec3::ONE);
+
+        assert!(camera.exit_orbit());
+        assert_eq!(camera.pose(), before);
+        assert!(camera.orbit().is_none());
+        assert!(!camera.exit_orbit());
+        assert_eq!(camera.pose(), before);
+    }
+
+    #[test]
+    fn aspect_is_clamped_for_zero_sizes() {
+        for (width, height, aspect) in [
+            (0.0, 0.0, 1.0),
+            (1280.0, 0.0, 1280.0),
+            (0.0, 720.0, 1.0 / 720.0),
+            (1280.0, 720.0, 1280.0 / 720.0),
+        ] {
+            assert_eq!(aspect_ratio(width, height), aspect);
+            let lens = CameraProjection::Perspective {
+                fovy: DEFAULT_FOVY,
+                znear: 0.1,
+                zfar: 100.0,
+            };
+            let proj = lens.matrix(aspect_ratio(width, height));
+            assert!(proj.is_finite() && proj.inverse().is_finite());
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use crate::{CacheKey, CacheStorage, Entity, ModelManager, Texture, World};
+use crate::{CacheKey, CacheStorage, Entity, ModelManager, World};
 
 #[derive(Debug, Default)]
 pub struct CameraModel {
@@ -92,13 +92,7 @@ impl CameraModel {
             write_mask: wgpu::ColorWrites::all(),
         };
 
-        let depth_stencil = wgpu::DepthStencilState {
-            format: Texture::DEPTH_FORMAT,
-            depth_write_enabled: true,
-            depth_compare: wgpu::CompareFunction::LessEqual,
-            stencil: wgpu::StencilState::default(),
-            bias: wgpu::DepthBiasState::default(),
-        };
+        let depth_stencil = crate::RenderSettings::depth_state(crate::DepthVariant::Opaque);
 
         self.model_key = World::load_object(
             model_manager,
@@ -0,0 +1,318 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use glam::{Quat, Vec2, Vec3};
+
+use crate::{Entity, AABB};
+
+static PAUSE_SIMULATION: AtomicBool = AtomicBool::new(false);
+
+/// Global orbit camera toggles.
+pub struct OrbitSettings;
+
+impl OrbitSettings {
+    /// Whether the world stops ticking while the camera orbits. Off by default.
+    pub fn pause_simulation() -> bool {
+        PAUSE_SIMULATION.load(Ordering::Relaxed)
+    }
+    pub fn set_pause_simulation(paused: bool) {
+        PAUSE_SIMULATION.store(paused, Ordering::Relaxed);
+    }
+}
+
+/// What the orbit camera circles: an entity it follows, or a fixed point.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum OrbitTarget {
+    Entity(Entity),
+    Point(Vec3),
+}
+
+impl From<Entity> for OrbitTarget {
+    fn from(entity: Entity) -> Self {
+        OrbitTarget::Entity(entity)
+    }
+}
+impl From<Vec3> for OrbitTarget {
+    fn from(point: Vec3) -> Self {
+        OrbitTarget::Point(point)
+    }
+}
+
+/// Center of `aabb` and the distance at which a camera with vertical field of view `fovy`
+/// (radians) and `aspect` fits its bounding sphere on screen. Shared by anything framing a
+/// model: the orbit camera, previews, thumbnails.
+pub fn frame_aabb(aabb: &AABB, fovy: f32, aspect: f32) -> (Vec3, f32) {
+    let center = (aabb.min + aabb.max) * 0.5;
+    let radius = ((aabb.max - aabb.min).length() * 0.5).max(f32::EPSILON);
+    let half_fovx = ((fovy * 0.5).tan() * aspect.max(f32::EPSILON)).atan();
+    let half_fov = (fovy * 0.5).min(half_fovx).max(f32::EPSILON);
+    (center, radius / half_fov.sin())
+}
+
+/// Eye, target and up of a camera, kept to restore the view after orbiting.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CameraPose {
+    pub eye: Vec3,
+    pub target: Vec3,
+    pub up: Vec3,
+}
+
+/// Turntable camera around an [`OrbitTarget`]. Input moves goal angles, distance and pan;
+/// the view eases toward them every [`OrbitCamera::update`].
+#[derive(Debug, Clone)]
+pub struct OrbitCamera {
+    pub target: OrbitTarget,
+    /// Added to the target position; moved by [`OrbitCamera::pan`].
+    pub focus_offset: Vec3,
+    /// Degrees around the vertical axis.
+    pub yaw: f32,
+    /// Degrees, clamped to [`OrbitCamera::MAX_PITCH`].
+    pub pitch: f32,
+    pub distance: f32,
+    pub min_distance: f32,
+    pub max_distance: f32,
+    /// How quickly the view catches up with the goal, per second.
+    pub sharpness: f32,
+    current: Option<(f32, f32, f32, Vec3)>,
+    fitted: bool,
+}
+
+impl OrbitCamera {
+    pub const MAX_PITCH: f32 = 89.0;
+    /// Fraction of the distance one scroll step zooms by.
+    pub const ZOOM_STEP: f32 = 0.1;
+    /// Pan per pixel as a fraction of the distance.
+    pub const PAN_SPEED: f32 = 0.002;
+
+    pub fn new(target: impl Into<OrbitTarget>, distance: f32) -> Self {
+        Self {
+            target: target.into(),
+            focus_offset: Vec3::ZERO,
+            yaw: 0.0,
+            pitch: 20.0,
+            distance: distance.max(f32::EPSILON),
+            min_distance: 0.5,
+            max_distance: 100.0,
+            sharpness: 12.0,
+            current: None,
+            fitted: false,
+        }
+    }
+    pub fn rebase(&mut self, offset: Vec3) {
+        if let OrbitTarget::Point(point) = &mut self.target {
+            *point -= offset;
+        }
+        if let Some((_, _, _, focus)) = &mut self.current {
+            *focus -= offset;
+        }
+    }
+    /// Starts from the angles `eye` currently looks at `focus` from.
+    pub fn looking_from(mut self, eye: Vec3, focus: Vec3) -> Self {
+        let offset = eye - focus;
+        if offset.length_squared() > f32::EPSILON {
+            let dir = offset.normalize();
+            self.yaw = dir.x.atan2(dir.z).to_degrees();
+            let pitch = dir.y.asin().to_degrees();
+            self.pitch = pitch.clamp(-Self::MAX_PITCH, Self::MAX_PITCH);
+        }
+        self
+    }
+    /// Zoom limits for a target with bounds `aabb`: from just outside it to well back.
+    pub fn fit_bounds(&mut self, aabb: &AABB, fovy: f32, aspect: f32) {
+        let (_, fit) = frame_aabb(aabb, fovy, aspect);
+        let radius = (aabb.max - aabb.min).length() * 0.5;
+        self.min_distance = (radius * 1.1).max(0.05);
+        self.max_distance = (fit * 10.0).max(self.min_distance);
+        self.distance = self.distance.clamp(self.min_distance, self.max_distance);
+        self.fitted = true;
+    }
+    /// Whether [`OrbitCamera::fit_bounds`] ran since the target was set.
+    pub fn fitted(&self) -> bool {
+        self.fitted
+    }
+    pub fn rotate(&mut self, yaw: f32, pitch: f32) {
+        self.yaw += yaw;
+        self.pitch = (self.pitch + pitch).clamp(-Self::MAX_PITCH, Self::MAX_PITCH);
+    }
+    /// Positive `steps` zoom out.
+    pub fn zoom(&mut self, steps: f32) {
+        let factor = (1.0 + Self::ZOOM_STEP).powf(steps);
+        self.distance = (self.distance * factor).clamp(self.min_distance, self.max_distance);
+    }
+    /// Moves the focus in the view plane by `pixels`, scaled with distance so the target
+    /// tracks the cursor at any zoom.
+    pub fn pan(&mut self, pixels: Vec2) {
+        let rotation = self.rotation();
+        let right = rotation * Vec3::X;
+        let up = rotation * Vec3::Y;
+        let scale = self.distance * Self::PAN_SPEED;
+        self.focus_offset += (-right * pixels.x + up * pixels.y) * scale;
+    }
+    fn rotation(&self) -> Quat {
+        orbit_rotation(self.yaw, self.pitch)
+    }
+    /// Eye position for `focus` at the goal angles and distance, without smoothing.
+    pub fn goal_eye(&self, focus: Vec3) -> Vec3 {
+        focus + self.focus_offset + self.rotation() * Vec3::Z * self.distance
+    }
+    /// Eases the view toward the goal and returns `(eye, target)`. `focus` is where the
+    /// target is this frame.
+    pub fn update(&mut self, focus: Vec3, dt: f32) -> (Vec3, Vec3) {
+        let goal = (self.yaw, self.pitch, self.distance, self.focus_offset);
+        let t = 1.0 - (-self.sharpness * dt.max(0.0)).exp();
+        let (yaw, pitch, distance, offset) = match self.current {
+            Some((yaw, pitch, distance, offset)) => (
+                yaw + (goal.0 - yaw) * t,
+                pitch + (goal.1 - pitch) * t,
+                distance + (goal.2 - distance) * t,
+                offset.lerp(goal.3, t),
+            ),
+            None => goal,
+        };
+        self.current = Some((yaw, pitch, distance, offset));
+        let target = focus + offset;
+        let eye = target + orbit_rotation(yaw, pitch) * Vec3::Z * distance;
+        (eye, target)
+    }
+}
+
+/// Rotation taking `+Z` to the direction from the focus to the eye.
+fn orbit_rotation(yaw: f32, pitch: f32) -> Quat {
+    Quat::from_rotation_y(yaw.to_radians()) * Quat::from_rotation_x(-pitch.to_radians())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: Vec3, b: Vec3) -> bool {
+        (a - b).length() < 1e-4
+    }
+
+    #[test]
+    fn aabb_framing_fits_the_bounding_sphere() {
+        let cube = AABB {
+            min: Vec3::new(1.0, 1.0, 1.0),
+            max: Vec3::new(3.0, 3.0, 3.0),
+        };
+        let radius = 3f32.sqrt();
+        let fovy = 60f32.to_radians();
+        let (center, distance) = frame_aabb(&cube, fovy, 16.0 / 9.0);
+        assert_eq!(center, Vec3::splat(2.0));
+        // Wide screens fit vertically: the sphere touches the top and bottom of the view.
+        assert!((distance - radius / 30f32.to_radians().sin()).abs() < 1e-4);
+
+        // Tall screens fit horizontally, which needs more distance.
+        let (_, tall) = frame_aabb(&cube, fovy, 0.5);
+        let half_fovx = (30f32.to_radians().tan() * 0.5).atan();
+        assert!((tall - radius / half_fovx.sin()).abs() < 1e-4);
+        assert!(tall > distance);
+
+        // Twice the size, twice the distance; a point still gets a finite one.
+        let big = AABB {
+            min: cube.min * 2.0,
+            max: cube.max * 2.0,
+        };
+        assert!((frame_aabb(&big, fovy, 16.0 / 9.0).1 - distance * 2.0).abs() < 1e-3);
+        let point = AABB {
+            min: Vec3::ONE,
+            max: Vec3::ONE,
+        };
+        assert!(frame_aabb(&point, fovy, 1.0).1.is_finite());
+    }
+
+    #[test]
+    fn zoom_limits_follow_the_target_bounds() {
+        let aabb = AABB {
+            min: Vec3::splat(-1.0),
+            max: Vec3::splat(1.0),
+        };
+        let mut orbit = OrbitCamera::new(Vec3::ZERO, 0.1);
+        assert!(!orbit.fitted());
+        orbit.fit_bounds(&aabb, 60f32.to_radians(), 1.0);
+        assert!(orbit.fitted());
+        let radius = 3f32.sqrt();
+        assert!((orbit.min_distance - radius * 1.1).abs() < 1e-4);
+        let (_, fit) = frame_aabb(&aabb, 60f32.to_radians(), 1.0);
+        assert!((orbit.max_distance - fit * 10.0).abs() < 1e-3);
+        // A start inside the model is pushed out to the limit.
+        assert_eq!(orbit.distance, orbit.min_distance);
+
+        orbit.zoom(1.0);
+        assert!((orbit.distance - orbit.min_distance * 1.1).abs() < 1e-4);
+        orbit.zoom(-5.0);
+        assert_eq!(orbit.distance, orbit.min_distance);
+        orbit.zoom(1000.0);
+        assert_eq!(orbit.distance, orbit.max_distance);
+    }
+
+    #[test]
+    fn pitch_is_clamped_short_of_the_poles() {
+        let mut orbit = OrbitCamera::new(Vec3::ZERO, 5.0);
+        orbit.rotate(30.0, 200.0);
+        assert_eq!((orbit.yaw, orbit.pitch), (30.0, OrbitCamera::MAX_PITCH));
+        orbit.rotate(-60.0, -400.0);
+        assert_eq!((orbit.yaw, orbit.pitch), (-30.0, -OrbitCamera::MAX_PITCH));
+
+        // Looking straight down still starts at a clamped angle.
+        let orbit = OrbitCamera::new(Vec3::ZERO, 5.0).looking_from(Vec3::Y * 5.0, Vec3::ZERO);
+        assert_eq!(orbit.pitch, OrbitCamera::MAX_PITCH);
+        let eye = orbit.goal_eye(Vec3::ZERO);
+        assert!(eye.y < 5.0 && eye.y > 4.99);
+    }
+
+    #[test]
+    fn starting_angles_reproduce_the_current_eye() {
+        let eye = Vec3::new(3.0, 2.0, -4.0);
+        let focus = Vec3::new(0.0, 1.0, 0.0);
+        let orbit = OrbitCamera::new(focus, (eye - focus).length()).looking_from(eye, focus);
+        assert!(
+            close(orbit.goal_eye(focus), eye),
+            "{}",
+            orbit.goal_eye(focus)
+        );
+    }
+
+    #[test]
+    fn panning_moves_the_focus_in_the_view_plane() {
+        let mut orbit = OrbitCamera::new(Vec3::ZERO, 10.0);
+        orbit.pitch = 0.0;
+        // Yaw 0 looks down -Z from +Z: screen right is +X, screen up is +Y.
+        orbit.pan(Vec2::new(100.0, 0.0));
+        let step = 100.0 * 10.0 * OrbitCamera::PAN_SPEED;
+        assert!(close(orbit.focus_offset, Vec3::new(-step, 0.0, 0.0)));
+        orbit.pan(Vec2::new(0.0, 100.0));
+        assert!(close(orbit.focus_offset, Vec3::new(-step, step, 0.0)));
+
+        // The same drag covers more ground further out, and follows the yaw.
+        let mut far = OrbitCamera::new(Vec3::ZERO, 20.0);
+        far.pitch = 0.0;
+        far.yaw = 90.0;
+        far.pan(Vec2::new(100.0, 0.0));
+        assert!(close(far.focus_offset, Vec3::new(0.0, 0.0, step * 2.0)));
+
+        // The eye and target move together, keeping the view direction.
+        let before = orbit.goal_eye(Vec3::ZERO) - orbit.focus_offset;
+        orbit.pan(Vec2::new(-37.0, 12.0));
+        assert!(close(
+            orbit.goal_eye(Vec3::ZERO) - orbit.focus_offset,
+            before
+        ));
+    }
+
+    #[test]
+    fn the_view_eases_toward_the_goal() {
+        let mut orbit = OrbitCamera::new(Vec3::ZERO, 5.0);
+        let (eye, target) = orbit.update(Vec3::ZERO, 1.0 / 60.0);
+        assert!(close(eye, orbit.goal_eye(Vec3::ZERO)));
+        assert_eq!(target, Vec3::ZERO);
+
+        orbit.rotate(90.0, 0.0);
+        let (eased, _) = orbit.update(Vec3::ZERO, 1.0 / 60.0);
+        assert!(!close(eased, orbit.goal_eye(Vec3::ZERO)));
+        for _ in 0..120 {
+            orbit.update(Vec3::ZERO, 1.0 / 60.0);
+        }
+        let (settled, _) = orbit.update(Vec3::ZERO, 1.0 / 60.0);
+        assert!(close(settled, orbit.goal_eye(Vec3::ZERO)));
+    }
+}
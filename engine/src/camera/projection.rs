@@ -1,5 +1,89 @@
+use glam::Mat4;
+
 #[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
 pub enum Projection {
     FirstPerson,
     ThirdPerson,
+    /// Turntable around a target, see [`super::Camera::enter_orbit`]; not part of the
+    /// [`Projection::next`] cycle.
+    Orbit,
+    /// Detached from the player, flying along the look direction.
+    FreeFly,
+}
+
+impl Projection {
+    /// Next mode in the user-facing cycle.
+    pub fn next(self) -> Self {
+        match self {
+            Projection::FirstPerson => Projection::ThirdPerson,
+            Projection::ThirdPerson => Projection::FreeFly,
+            Projection::FreeFly | Projection::Orbit => Projection::FirstPerson,
+        }
+    }
+}
+
+/// Lens of a [`super::Camera`], independent of where it looks.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CameraProjection {
+    Perspective {
+        /// Vertical field of view in radians.
+        fovy: f32,
+        znear: f32,
+        zfar: f32,
+    },
+    /// Parallel rays through a view `2 * half_height` world units tall, as wide as the
+    /// aspect makes it.
+    Orthographic {
+        half_height: f32,
+        znear: f32,
+        zfar: f32,
+    },
+}
+
+impl CameraProjection {
+    pub fn znear(&self) -> f32 {
+        match *self {
+            CameraProjection::Perspective { znear, .. }
+            | CameraProjection::Orthographic { znear, .. } => znear,
+        }
+    }
+    pub fn zfar(&self) -> f32 {
+        match *self {
+            CameraProjection::Perspective { zfar, .. }
+            | CameraProjection::Orthographic { zfar, .. } => zfar,
+        }
+    }
+    pub fn fovy(&self) -> Option<f32> {
+        match *self {
+            CameraProjection::Perspective { fovy, .. } => Some(fovy),
+            CameraProjection::Orthographic { .. } => None,
+        }
+    }
+    pub fn is_orthographic(&self) -> bool {
+        matches!(self, CameraProjection::Orthographic { .. })
+    }
+    /// Left-handed projection to `[0, 1]` depth for a viewport of `aspect`, before the
+    /// depth policy's remap.
+    pub fn matrix(&self, aspect: f32) -> Mat4 {
+        match *self {
+            CameraProjection::Perspective { fovy, znear, zfar } => {
+                Mat4::perspective_lh(fovy, aspect, znear, zfar)
+            }
+            CameraProjection::Orthographic {
+                half_height,
+                znear,
+                zfar,
+            } => {
+                let half_width = half_height * aspect;
+                Mat4::orthographic_lh(
+                    -half_width,
+                    half_width,
+                    -half_height,
+                    half_height,
+                    znear,
+                    zfar,
+                )
+            }
+        }
+    }
 }
@@ -0,0 +1,562 @@
+//! Command console and the gizmo layer it draws on. Gizmos are painted by egui after the
+//! final blit, so they only ever show up in the dev overlay and never in the scene image.
+
+use glam::{Mat4, Vec3};
+
+use super::{cursor_ray, pick, selected_entity, slope_angle, AnnotationList, Measurement};
+use crate::{camera::OrbitTarget, Entity, ModelManager, World, AABB};
+
+/// Lines kept in the console scrollback.
+const MAX_OUTPUT: usize = 200;
+const GIZMO_COLOR: egui::Color32 = egui::Color32::from_rgb(80, 220, 255);
+const NOTE_COLOR: egui::Color32 = egui::Color32::from_rgb(255, 210, 80);
+const BOUNDS_COLOR: egui::Color32 = egui::Color32::from_rgb(120, 255, 120);
+
+fn state_id() -> egui::Id {
+    egui::Id::new("devtools_console")
+}
+fn view_id() -> egui::Id {
+    egui::Id::new("devtools_view_projection")
+}
+fn requests_id() -> egui::Id {
+    egui::Id::new("devtools_console_requests")
+}
+
+/// Camera the console picks and projects with. Set every frame before the panels run.
+pub fn set_view_projection(ctx: &egui::Context, view_proj: Mat4) {
+    ctx.data_mut(|d| d.insert_temp(view_id(), view_proj));
+}
+fn view_projection(ctx: &egui::Context) -> Option<Mat4> {
+    ctx.data(|d| d.get_temp::<Mat4>(view_id()))
+}
+
+/// Console commands the application carries out, since they reach past the world.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConsoleRequest {
+    PlaySequence(String),
+    StopSequence,
+    Orbit(OrbitTarget),
+    ExitOrbit,
+}
+
+/// Requests queued by the console since the last call; drain after the panels run.
+pub fn take_console_requests(ctx: &egui::Context) -> Vec<ConsoleRequest> {
+    ctx.data_mut(|d| {
+        std::mem::take(d.get_temp_mut_or_default::<Vec<ConsoleRequest>>(requests_id()))
+    })
+}
+fn push_request(ctx: &egui::Context, request: ConsoleRequest) {
+    ctx.data_mut(|d| {
+        d.get_temp_mut_or_default::<Vec<ConsoleRequest>>(requests_id())
+            .push(request)
+    });
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DevCommand {
+    MeasureStart,
+    MeasureEnd,
+    MeasureClear,
+    Annotate(String),
+    ListNotes,
+    RemoveNote(usize),
+    Bounds,
+    Slope,
+    PlaySequence(String),
+    StopSequence,
+    Damage(Entity, f32),
+    /// Orbit a named scene entity or an entity id.
+    Orbit(String),
+    /// Orbit the point under the cursor.
+    OrbitHere,
+    OrbitExit,
+    OrbitPause,
+    /// Lists the global shader constants.
+    ListConstants,
+    /// Sets, or with `None` clears, a global shader constant.
+    SetConstant(String, Option<f64>),
+    /// Lists the `override` constants a shader declares.
+    Overrides(String),
+    /// Shows or sets `r.anisotropy`.
+    Anisotropy(Option<u16>),
+    /// Reads the batched material storage buffer back from the GPU and prints it.
+    DumpMaterials,
+    /// Loses the GPU device on purpose, see [`crate::GPU::simulate_loss`].
+    LoseDevice,
+    Help,
+}
+
+impl DevCommand {
+    pub const HELP: &'static str = "measure start|end|clear, annotate <text>, notes, \
+                                    notes remove <index>, bounds, slope, \
+                                    seq play <name>, seq stop, damage <entity> <amount>, \
+                                    orbit <entity>|here|exit|pause, \
+                                    const [<name> <value>|clear <name>], overrides <shader>, \
+                                    r.anisotropy [<level>], materials dump, gpu lose";
+
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let line = line.trim();
+        let (head, rest) = line.split_once(' ').unwrap_or((line, ""));
+        let rest = rest.trim();
+        match (head, rest) {
+            ("measure", "start") => Ok(DevCommand::MeasureStart),
+            ("measure", "end") => Ok(DevCommand::MeasureEnd),
+            ("measure", "clear") => Ok(DevCommand::MeasureClear),
+            ("annotate", "") => Err("annotate needs some text".to_string()),
+            ("annotate", text) => Ok(DevCommand::Annotate(text.to_string())),
+            ("notes", "") => Ok(DevCommand::ListNotes),
+            ("notes", args) => match args.split_once(' ') {
+                Some(("remove", index)) => index
+                    .trim()
+                    .parse()
+                    .map(DevCommand::RemoveNote)
+                    .map_err(|_| format!("bad note index '{}'", index.trim())),
+                _ => Err(format!("unknown notes command '{}'", args)),
+            },
+            ("bounds", "") => Ok(DevCommand::Bounds),
+            ("slope", "") => Ok(DevCommand::Slope),
+            ("seq", "stop") => Ok(DevCommand::StopSequence),
+            ("seq", args) => match args.split_once(' ') {
+                Some(("play", name)) if !name.trim().is_empty() => {
+                    Ok(DevCommand::PlaySequence(name.trim().to_string()))
+                }
+                _ => Err("usage: seq play <name>, seq stop".to_string()),
+            },
+            ("damage", args) => match args.split_once(' ') {
+                Some((entity, amount)) => {
+                    let entity = entity
+                        .parse()
+                        .map_err(|_| format!("bad entity '{}'", entity))?;
+                    let amount = amount
+                        .trim()
+                        .parse()
+                        .map_err(|_| format!("bad amount '{}'", amount.trim()))?;
+                    Ok(DevCommand::Damage(Entity(entity), amount))
+                }
+                None => Err("usage: damage <entity> <amount>".to_string()),
+            },
+            ("orbit", "") => Err("usage: orbit <entity>|here|exit|pause".to_string()),
+            ("orbit", "here") => Ok(DevCommand::OrbitHere),
+            ("orbit", "exit") => Ok(DevCommand::OrbitExit),
+            ("orbit", "pause") => Ok(DevCommand::OrbitPause),
+            ("orbit", name) => Ok(DevCommand::Orbit(name.to_string())),
+            ("const", "") => Ok(DevCommand::ListConstants),
+            ("const", args) => match args.split_once(' ') {
+                Some(("clear", name)) => Ok(DevCommand::SetConstant(name.trim().to_string(), None)),
+                Some((name, value)) => value
+                    .trim()
+                    .parse()
+                    .map(|value| DevCommand::SetConstant(name.to_string(), Some(value)))
+                    .map_err(|_| format!("bad value '{}'", value.trim())),
+                None => Err("usage: const [<name> <value>|clear <name>]".to_string()),
+            },
+            ("overrides", "") => Err("usage: overrides <shader>".to_string()),
+            ("overrides", shader) => Ok(DevCommand::Overrides(shader.to_string())),
+            ("r.anisotropy", "") => Ok(DevCommand::Anisotropy(None)),
+            ("r.anisotropy", level) => level
+                .parse()
+                .map(|level| DevCommand::Anisotropy(Some(level)))
+                .map_err(|_| format!("bad anisotropy level '{}'", level)),
+            ("materials", "dump") => Ok(DevCommand::DumpMaterials),
+            ("gpu", "lose") => Ok(DevCommand::LoseDevice),
+            ("help", "") => Ok(DevCommand::Help),
+            _ => Err(format!("unknown command '{}', try help", line)),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+struct ConsoleState {
+    input: String,
+    output: Vec<String>,
+    /// Point under the cursor, last time it was over the world rather than a panel.
+    hover: Option<Vec3>,
+    measure_start: Option<Vec3>,
+    measurement: Option<Measurement>,
+    notes: Option<AnnotationList>,
+    show_bounds: bool,
+    /// Material storage readback started by `materials dump`.
+    material_dump: Option<crate::ReadbackTicket>,
+}
+
+impl ConsoleState {
+    fn print(&mut self, line: impl Into<String>) {
+        self.output.push(line.into());
+        let excess = self.output.len().saturating_sub(MAX_OUTPUT);
+        self.output.drain(..excess);
+    }
+
+    /// Loads the notes of `scene` when the world switched scenes.
+    fn sync_notes(&mut self, scene: &str) {
+        if self.notes.as_ref().is_some_and(|n| n.scene() == scene) {
+            return;
+        }
+        let notes = match AnnotationList::load(scene) {
+            Ok(notes) if !scene.is_empty() => notes,
+            Ok(_) => AnnotationList::new(scene),
+            Err(e) => {
+                self.print(format!("could not load notes: {}", e));
+                AnnotationList::new(scene)
+            }
+        };
+        self.notes = Some(notes);
+    }
+
+    /// Writes the sidecar; notes made without a scene loaded only last the session.
+    fn save_notes(&mut self) {
+        let Some(notes) = &self.notes else {
+            return;
+        };
+        if notes.scene().is_empty() {
+            return;
+        }
+        if let Err(e) = notes.save() {
+            self.print(format!("could not save notes: {}", e));
+        }
+    }
+
+    /// Prints the material dump once its readback arrived.
+    fn poll_material_dump(&mut self) {
+        let Some(result) = self.material_dump.as_ref().and_then(|t| t.poll()) else {
+            return;
+        };
+        self.material_dump = None;
+        let bytes = match result {
+            Ok(bytes) => bytes,
+            Err(e) => return self.print(format!("materials dump failed: {}", e)),
+        };
+        let size = std::mem::size_of::<crate::MaterialData>();
+        for (i, chunk) in bytes.chunks_exact(size).enumerate() {
+            let m: crate::MaterialData = bytemuck::pod_read_unaligned(chunk);
+            self.print(format!(
+                "#{} ambient {:.2?} diffuse {:.2?} specular {:.2?} shininess {:.1}",
+                i, m.ambient, m.diffuse, m.specular, m.shininess
+            ));
+        }
+    }
+
+    fn run(
+        &mut self,
+        ctx: &egui::Context,
+        command: DevCommand,
+        world: &mut World,
+        models: &mut ModelManager,
+    ) {
+        let nothing = "nothing under the cursor";
+        match command {
+            DevCommand::MeasureStart => match self.hover {
+                Some(p) => {
+                    self.measure_start = Some(p);
+                    self.measurement = None;
+                    self.print(format!("start {:.2?}", p.to_array()));
+                }
+                None => self.print(nothing),
+            },
+            DevCommand::MeasureEnd => match (self.measure_start, self.hover) {
+                (None, _) => self.print("no start point, use measure start"),
+                (_, None) => self.print(nothing),
+                (Some(start), Some(end)) => {
+                    let measurement = Measurement::new(start, end);
+                    self.print(measurement.label());
+                    self.measurement = Some(measurement);
+                }
+            },
+            DevCommand::MeasureClear => {
+                self.measure_start = None;
+                self.measurement = None;
+            }
+            DevCommand::Annotate(text) => {
+                let added = self
+                    .hover
+                    .zip(self.notes.as_mut())
+                    .map(|(p, notes)| notes.add(p, text));
+                match added {
+                    Some(index) => {
+                        self.print(format!("added note #{}", index));
+                        self.save_notes();
+                    }
+                    None => self.print(nothing),
+                }
+            }
+            DevCommand::ListNotes => {
+                let lines: Vec<String> = self
+                    .notes
+                    .iter()
+                    .flat_map(|n| n.notes().iter().enumerate())
+                    .map(|(i, note)| format!("#{} {:.1?} {}", i, note.position, note.text))
+                    .collect();
+                if lines.is_empty() {
+                    self.print("no notes");
+                }
+                lines.into_iter().for_each(|line| self.print(line));
+            }
+            DevCommand::RemoveNote(index) => {
+                match self.notes.as_mut().and_then(|n| n.remove(index)) {
+                    Some(note) => {
+                        self.print(format!("removed #{} {}", index, note.text));
+                        self.save_notes();
+                    }
+                    None => self.print(format!("no note #{}", index)),
+                }
+            }
+            DevCommand::Bounds => {
+                self.show_bounds = !self.show_bounds;
+                let state = if self.show_bounds { "on" } else { "off" };
+                self.print(format!("bounds {}", state));
+            }
+            DevCommand::Slope => match self.hover.and_then(|p| slope_angle(&world.terrain, p)) {
+                Some(angle) => self.print(format!("slope {:.1} deg", angle)),
+                None => self.print("no terrain under the cursor"),
+            },
+            DevCommand::PlaySequence(name) => {
+                self.print(format!("playing sequence '{}'", name));
+                push_request(ctx, ConsoleRequest::PlaySequence(name));
+            }
+            DevCommand::StopSequence => push_request(ctx, ConsoleRequest::StopSequence),
+            DevCommand::Damage(entity, amount) => {
+                let result = world.apply_damage(entity, amount, None);
+                self.print(format!("damage {} -> {:?}", entity.0, result));
+            }
+            DevCommand::Orbit(name) => {
+                let entity = world
+                    .scene()
+                    .and_then(|scene| scene.entity(&name))
+                    .or_else(|| name.parse().ok().map(Entity));
+                match entity {
+                    Some(entity) => {
+                        self.print(format!("orbiting {} ({})", name, entity.0));
+                        push_request(ctx, ConsoleRequest::Orbit(OrbitTarget::Entity(entity)));
+                    }
+                    None => self.print(format!("no entity '{}'", name)),
+                }
+            }
+            DevCommand::OrbitHere => match self.hover {
+                Some(p) => push_request(ctx, ConsoleRequest::Orbit(OrbitTarget::Point(p))),
+                None => self.print(nothing),
+            },
+            DevCommand::OrbitExit => push_request(ctx, ConsoleRequest::ExitOrbit),
+            DevCommand::OrbitPause => {
+                let paused = !crate::camera::OrbitSettings::pause_simulation();
+                crate::camera::OrbitSettings::set_pause_simulation(paused);
+                let state = if paused { "paused" } else { "running" };
+                self.print(format!("simulation {} while orbiting", state));
+            }
+            DevCommand::ListConstants => {
+                let constants = crate::RenderSettings::shader_constants();
+                if constants.is_empty() {
+                    self.print("no shader constants");
+                }
+                for (name, value) in &constants.0 {
+                    self.print(format!("{} = {}", name, value));
+                }
+            }
+            DevCommand::SetConstant(name, value) => {
+                crate::RenderSettings::set_shader_constant(&name, value);
+                match value {
+                    Some(value) => self.print(format!("{} = {}", name, value)),
+                    None => self.print(format!("cleared {}", name)),
+                }
+            }
+            DevCommand::Anisotropy(level) => {
+                if let Some(level) = level {
+                    crate::RenderSettings::set_anisotropy(level);
+                }
+                self.print(format!(
+                    "r.anisotropy = {} (device max {}), applies to materials loaded from now on",
+                    crate::RenderSettings::anisotropy(),
+                    models.materials.textures.samplers.max_anisotropy()
+                ));
+            }
+            DevCommand::Overrides(shader) => match models.materials.shaders.overrides(&shader) {
+                Ok([]) => self.print(format!("{} declares no overrides", shader)),
+                Ok(decls) => {
+                    let lines: Vec<String> = decls
+                        .iter()
+                        .map(|d| {
+                            format!(
+                                "{}: {} = {}",
+                                d.name,
+                                d.ty.as_deref().unwrap_or("?"),
+                                d.default.as_deref().unwrap_or("(required)")
+                            )
+                        })
+                        .collect();
+                    lines.into_iter().for_each(|line| self.print(line));
+                }
+                Err(e) => self.print(format!("{}: {}", shader, e)),
+            },
+            DevCommand::DumpMaterials => {
+                let materials = &models.materials;
+                let size = materials.storage_buffer.get().size();
+                let ticket = models
+                    .readback
+                    .request_buffer(&materials.storage_buffer, 0..size);
+                self.print(format!(
+                    "reading back {} materials ({} bytes)",
+                    materials.storage_count, size
+                ));
+                self.material_dump = Some(ticket);
+            }
+            DevCommand::LoseDevice => {
+                #[cfg(feature = "gpu_test_hooks")]
+                match crate::GPU::simulate_loss() {
+                    Ok(()) => self.print("device lost, recovering"),
+                    Err(e) => self.print(format!("gpu lose: {}", e)),
+                }
+                #[cfg(not(feature = "gpu_test_hooks"))]
+                self.print("gpu lose needs the gpu_test_hooks feature");
+            }
+            DevCommand::Help => self.print(DevCommand::HELP),
+        }
+    }
+}
+
+/// Command line for the measurement and sequence tools, plus the gizmos.
+pub fn console(ctx: &egui::Context, world: &mut World, models: &mut ModelManager) {
+    let mut state =
+        ctx.data_mut(|d| std::mem::take(d.get_temp_mut_or_default::<ConsoleState>(state_id())));
+    let scene = world.scene().map(|s| s.name.clone()).unwrap_or_default();
+    state.sync_notes(&scene);
+    state.poll_material_dump();
+
+    let view_proj = view_projection(ctx);
+    if !ctx.is_pointer_over_area() {
+        if let (Some(vp), Some(pos)) = (view_proj, ctx.pointer_hover_pos()) {
+            let screen = ctx.screen_rect();
+            let ndc = [
+                (pos.x - screen.min.x) / screen.width() * 2.0 - 1.0,
+                1.0 - (pos.y - screen.min.y) / screen.height() * 2.0,
+            ];
+            let (origin, dir) = cursor_ray(vp, ndc);
+            state.hover = pick(world, origin, dir);
+        }
+    }
+
+    egui::Window::new("Console")
+        .default_open(false)
+        .default_width(360.0)
+        .show(ctx, |ui| {
+            egui::ScrollArea::vertical()
+                .max_height(160.0)
+                .stick_to_bottom(true)
+                .show(ui, |ui| {
+                    for line in &state.output {
+                        ui.monospace(line);
+                    }
+                });
+            let response = ui.text_edit_singleline(&mut state.input);
+            if response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                let line = std::mem::take(&mut state.input);
+                if !line.trim().is_empty() {
+                    state.print(format!("> {}", line));
+                    match DevCommand::parse(&line) {
+                        Ok(command) => state.run(ctx, command, world, models),
+                        Err(e) => state.print(e),
+                    }
+                }
+                response.request_focus();
+            }
+        });
+
+    if let Some(vp) = view_proj {
+        draw_gizmos(ctx, vp, &state, world);
+    }
+    ctx.data_mut(|d| d.insert_temp(state_id(), state));
+}
+
+/// World to screen (egui points); `None` behind the camera.
+fn project(ctx: &egui::Context, view_proj: Mat4, point: Vec3) -> Option<egui::Pos2> {
+    let clip = view_proj * point.extend(1.0);
+    if clip.w <= f32::EPSILON {
+        return None;
+    }
+    let ndc = clip.truncate() / clip.w;
+    let screen = ctx.screen_rect();
+    Some(egui::pos2(
+        screen.min.x + (ndc.x + 1.0) * 0.5 * screen.width(),
+        screen.min.y + (1.0 - ndc.y) * 0.5 * screen.height(),
+    ))
+}
+
+fn draw_gizmos(ctx: &egui::Context, view_proj: Mat4, state: &ConsoleState, world: &World) {
+    let painter = ctx.layer_painter(egui::LayerId::new(
+        egui::Order::Background,
+        egui::Id::new("devtools_gizmos"),
+    ));
+    let font = egui::FontId::monospace(13.0);
+    let to_screen = |p: Vec3| project(ctx, view_proj, p);
+    let segment = |a: Vec3, b: Vec3, color: egui::Color32| {
+        if let (Some(a), Some(b)) = (to_screen(a), to_screen(b)) {
+            painter.line_segment([a, b], egui::Stroke::new(2.0, color));
+        }
+    };
+
+    if let Some(start) = state.measure_start.and_then(to_screen) {
+        painter.circle_filled(start, 4.0, GIZMO_COLOR);
+    }
+    if let Some(m) = state.measurement {
+        segment(m.start, m.end, GIZMO_COLOR);
+        if let Some(end) = to_screen(m.end) {
+            painter.circle_filled(end, 4.0, GIZMO_COLOR);
+        }
+        if let Some(mid) = to_screen((m.start + m.end) * 0.5) {
+            painter.text(
+                mid,
+                egui::Align2::LEFT_BOTTOM,
+                m.label(),
+                font.clone(),
+                GIZMO_COLOR,
+            );
+        }
+    }
+
+    for (i, note) in state
+        .notes
+        .iter()
+        .flat_map(|n| n.notes().iter().enumerate())
+    {
+        if let Some(pos) = to_screen(Vec3::from_array(note.position)) {
+            painter.circle_filled(pos, 3.0, NOTE_COLOR);
+            let text = format!("#{} {}", i, note.text);
+            painter.text(
+                pos,
+                egui::Align2::LEFT_BOTTOM,
+                text,
+                font.clone(),
+                NOTE_COLOR,
+            );
+        }
+    }
+
+    let bounds = state
+        .show_bounds
+        .then(|| selected_entity(ctx))
+        .flatten()
+        .and_then(|entity| world.spatial().bounds(entity));
+    if let Some(AABB { min, max }) = bounds {
+        let corner = |i: usize| {
+            Vec3::new(
+                if i & 1 == 0 { min.x } else { max.x },
+                if i & 2 == 0 { min.y } else { max.y },
+                if i & 4 == 0 { min.z } else { max.z },
+            )
+        };
+        // Edges join corners differing in exactly one axis bit.
+        for a in 0..8 {
+            for bit in [1, 2, 4] {
+                if a & bit == 0 {
+                    segment(corner(a), corner(a | bit), BOUNDS_COLOR);
+                }
+            }
+        }
+        if let Some(top) = to_screen(Vec3::new(
+            (min.x + max.x) * 0.5,
+            max.y,
+            (min.z + max.z) * 0.5,
+        )) {
+            let size = max - min;
+            let text = format!("{:.2} x {:.2} x {:.2}", size.x, size.y, size.z);
+            painter.text(top, egui::Align2::CENTER_BOTTOM, text, font, BOUNDS_COLOR);
+        }
+    }
+}
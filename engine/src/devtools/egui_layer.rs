@@ -0,0 +1,179 @@
+use winit::{event::WindowEvent, window::Window};
+
+use super::InputFocus;
+use crate::{gpu_scope, ModelManager, World};
+
+/// Draws UI for one frame. Registered with [`EguiLayer::add_panel`].
+pub type Panel = fn(&egui::Context, &mut World, &mut ModelManager);
+
+/// Owns the egui context, routes window events into it and draws its output on top of the
+/// final frame at native resolution.
+pub struct EguiLayer {
+    ctx: egui::Context,
+    state: egui_winit::State,
+    renderer: egui_wgpu::Renderer,
+    panels: Vec<Panel>,
+    focus: InputFocus,
+    paint_jobs: Vec<egui::ClippedPrimitive>,
+    textures: egui::TexturesDelta,
+    /// Textures the last prepared frame stopped using, freed on the next prepare.
+    free: Vec<egui::TextureId>,
+    size: [u32; 2],
+    pixels_per_point: f32,
+    visible: bool,
+}
+
+impl EguiLayer {
+    pub fn new(device: &wgpu::Device, window: &Window, format: wgpu::TextureFormat) -> Self {
+        let ctx = egui::Context::default();
+        let state = egui_winit::State::new(
+            ctx.clone(),
+            egui::ViewportId::ROOT,
+            window,
+            Some(window.scale_factor() as f32),
+            window.theme(),
+            Some(device.limits().max_texture_dimension_2d as usize),
+        );
+        let renderer = egui_wgpu::Renderer::new(device, format, None, 1, false);
+        Self {
+            ctx,
+            state,
+            renderer,
+            panels: Vec::new(),
+            focus: InputFocus::default(),
+            paint_jobs: Vec::new(),
+            textures: Default::default(),
+            free: Vec::new(),
+            size: [1, 1],
+            pixels_per_point: window.scale_factor() as f32,
+            visible: true,
+        }
+    }
+    pub fn context(&self) -> &egui::Context {
+        &self.ctx
+    }
+    pub fn add_panel(&mut self, panel: Panel) {
+        self.panels.push(panel);
+    }
+    pub fn visible(&self) -> bool {
+        self.visible
+    }
+    /// Hidden, the layer neither draws nor claims input.
+    pub fn set_visible(&mut self, visible: bool) {
+        self.visible = visible;
+        if !visible {
+            self.focus = InputFocus::default();
+        }
+    }
+
+    /// Feeds `event` to egui (keys, pointer, IME, clipboard shortcuts, DPI changes) and
+    /// returns `true` when the game shouldn't see it.
+    pub fn on_window_event(&mut self, window: &Window, event: &WindowEvent) -> bool {
+        if !self.visible {
+            return false;
+        }
+        let response = self.state.on_window_event(window, event);
+        if response.repaint {
+            window.request_redraw();
+        }
+        self.focus.consumes(event)
+    }
+
+    /// Runs every registered panel and tessellates the result for [`EguiLayer::render`].
+    pub fn run(&mut self, window: &Window, world: &mut World, models: &mut ModelManager) {
+        if !self.visible {
+            self.paint_jobs.clear();
+            return;
+        }
+        let input = self.state.take_egui_input(window);
+        let panels = &self.panels;
+        let output = self.ctx.run(input, |ctx| {
+            for panel in panels {
+                panel(ctx, world, models);
+            }
+        });
+        self.state
+            .handle_platform_output(window, output.platform_output);
+        self.focus = InputFocus::from_context(&self.ctx);
+        self.pixels_per_point = output.pixels_per_point;
+        self.paint_jobs = self.ctx.tessellate(output.shapes, output.pixels_per_point);
+        self.textures.append(output.textures_delta);
+    }
+
+    /// Uploads the textures and buffers of the last [`EguiLayer::run`] for a `size` target,
+    /// and frees the textures the previous frame was done with. Call before recording
+    /// [`EguiLayer::draw`].
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, size: [u32; 2]) {
+        for id in std::mem::take(&mut self.free) {
+            self.renderer.free_texture(&id);
+        }
+        let textures = std::mem::take(&mut self.textures);
+        for (id, delta) in &textures.set {
+            self.renderer.update_texture(device, queue, *id, delta);
+        }
+        self.free = textures.free;
+        self.size = size;
+        if !self.paint_jobs.is_empty() {
+            let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Egui Upload"),
+            });
+            let commands = self.renderer.update_buffers(
+                device,
+                queue,
+                &mut encoder,
+                &self.paint_jobs,
+                &self.screen(),
+            );
+            queue.submit(commands.into_iter().chain(Some(encoder.finish())));
+        }
+    }
+    fn screen(&self) -> egui_wgpu::ScreenDescriptor {
+        egui_wgpu::ScreenDescriptor {
+            size_in_pixels: self.size,
+            pixels_per_point: self.pixels_per_point,
+        }
+    }
+    /// The prepared UI, drawable from a recording thread.
+    pub fn draw(&self) -> EguiDraw<'_> {
+        EguiDraw {
+            renderer: &self.renderer,
+            paint_jobs: &self.paint_jobs,
+            screen: self.screen(),
+        }
+    }
+}
+
+/// What [`EguiLayer::prepare`] uploaded; borrows only the parts of the layer recording needs.
+pub struct EguiDraw<'a> {
+    renderer: &'a egui_wgpu::Renderer,
+    paint_jobs: &'a [egui::ClippedPrimitive],
+    screen: egui_wgpu::ScreenDescriptor,
+}
+
+impl EguiDraw<'_> {
+    /// Draws over `target`, which already holds the final image.
+    pub fn record(&self, encoder: &mut wgpu::CommandEncoder, target: &wgpu::TextureView) {
+        gpu_scope!(Pass, "Egui Pass");
+        if self.paint_jobs.is_empty() {
+            return;
+        }
+        let mut rpass = encoder
+            .begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Egui Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: target,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            })
+            .forget_lifetime();
+        self.renderer
+            .render(&mut rpass, self.paint_jobs, &self.screen);
+    }
+}
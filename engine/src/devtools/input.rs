@@ -0,0 +1,137 @@
+use winit::event::{ElementState, WindowEvent};
+
+/// What the UI claimed last frame. Events it claims don't reach the game, except releases,
+/// so a key or button pressed before the pointer moved over a panel can't get stuck down.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct InputFocus {
+    pub pointer: bool,
+    pub keyboard: bool,
+}
+
+impl InputFocus {
+    pub fn from_context(ctx: &egui::Context) -> Self {
+        Self {
+            pointer: ctx.wants_pointer_input() || ctx.is_pointer_over_area(),
+            keyboard: ctx.wants_keyboard_input(),
+        }
+    }
+
+    /// Whether `event` belongs to the UI rather than the game.
+    pub fn consumes(&self, event: &WindowEvent) -> bool {
+        match event {
+            WindowEvent::KeyboardInput { event, .. } => {
+                self.keyboard && event.state == ElementState::Pressed
+            }
+            WindowEvent::Ime(_) => self.keyboard,
+            WindowEvent::MouseInput { state, .. } => {
+                self.pointer && *state == ElementState::Pressed
+            }
+            WindowEvent::CursorMoved { .. }
+            | WindowEvent::MouseWheel { .. }
+            | WindowEvent::Touch(_)
+            | WindowEvent::PinchGesture { .. } => self.pointer,
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use winit::{
+        dpi::PhysicalPosition,
+        event::{DeviceId, Ime, MouseButton, MouseScrollDelta, TouchPhase},
+    };
+
+    fn device() -> DeviceId {
+        DeviceId::dummy()
+    }
+    fn click(state: ElementState) -> WindowEvent {
+        WindowEvent::MouseInput {
+            device_id: device(),
+            state,
+            button: MouseButton::Left,
+        }
+    }
+    fn focus(pointer: bool, keyboard: bool) -> InputFocus {
+        InputFocus { pointer, keyboard }
+    }
+
+    #[test]
+    fn claimed_presses_stay_with_the_ui_and_releases_reach_the_game() {
+        let ui = focus(true, false);
+        assert!(ui.consumes(&click(ElementState::Pressed)));
+        assert!(!ui.consumes(&click(ElementState::Released)));
+
+        let game = InputFocus::default();
+        assert!(!game.consumes(&click(ElementState::Pressed)));
+        assert!(!game.consumes(&click(ElementState::Released)));
+    }
+
+    #[test]
+    fn events_route_by_the_focus_they_belong_to() {
+        let moved = WindowEvent::CursorMoved {
+            device_id: device(),
+            position: PhysicalPosition::new(10.0, 20.0),
+        };
+        let wheel = WindowEvent::MouseWheel {
+            device_id: device(),
+            delta: MouseScrollDelta::LineDelta(0.0, 1.0),
+            phase: TouchPhase::Moved,
+        };
+        let ime = WindowEvent::Ime(Ime::Commit("é".into()));
+
+        let pointer = focus(true, false);
+        assert!(pointer.consumes(&moved));
+        assert!(pointer.consumes(&wheel));
+        assert!(!pointer.consumes(&ime));
+
+        let keyboard = focus(false, true);
+        assert!(!keyboard.consumes(&moved));
+        assert!(!keyboard.consumes(&click(ElementState::Pressed)));
+        assert!(keyboard.consumes(&ime));
+
+        // Window-level events always reach the game.
+        let both = focus(true, true);
+        assert!(!both.consumes(&WindowEvent::Focused(true)));
+        assert!(!both.consumes(&WindowEvent::Resized((64, 64).into())));
+    }
+
+    fn frame(ctx: &egui::Context, events: Vec<egui::Event>, ui: impl FnMut(&egui::Context)) {
+        let input = egui::RawInput {
+            screen_rect: Some(egui::Rect::from_min_size(
+                egui::Pos2::ZERO,
+                egui::vec2(800.0, 600.0),
+            )),
+            events,
+            ..Default::default()
+        };
+        let _ = ctx.run(input, ui);
+    }
+
+    #[test]
+    fn focus_follows_the_context() {
+        let ctx = egui::Context::default();
+        let text_id = egui::Id::new("text");
+        let mut text = String::new();
+        let mut show = |ctx: &egui::Context| {
+            egui::Window::new("Panel")
+                .fixed_pos((0.0, 0.0))
+                .show(ctx, |ui| {
+                    ui.add(egui::TextEdit::singleline(&mut text).id(text_id));
+                });
+        };
+
+        frame(&ctx, vec![], &mut show);
+        assert_eq!(InputFocus::from_context(&ctx), InputFocus::default());
+
+        let over_panel = egui::Event::PointerMoved(egui::pos2(20.0, 20.0));
+        frame(&ctx, vec![over_panel], &mut show);
+        assert!(InputFocus::from_context(&ctx).pointer);
+
+        let off_panel = egui::Event::PointerMoved(egui::pos2(700.0, 500.0));
+        ctx.memory_mut(|m| m.request_focus(text_id));
+        frame(&ctx, vec![off_panel], &mut show);
+        assert_eq!(InputFocus::from_context(&ctx), focus(false, true));
+    }
+}
@@ -0,0 +1,242 @@
+//! Measurement and annotation helpers behind the console's `measure`, `annotate`, `bounds`
+//! and `slope` commands.
+
+use std::path::PathBuf;
+
+use glam::{IVec3, Mat4, Vec3};
+use serde::{Deserialize, Serialize};
+
+use crate::{EngineError, SceneFile, Terrain, World, AIR};
+
+/// Farthest point the cursor can pick, in world units.
+pub const MAX_PICK_DISTANCE: f32 = 256.0;
+/// Step of the terrain march in [`pick`].
+const PICK_STEP: f32 = 0.05;
+
+/// Segment between two picked points.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Measurement {
+    pub start: Vec3,
+    pub end: Vec3,
+}
+
+impl Measurement {
+    pub fn new(start: Vec3, end: Vec3) -> Self {
+        Self { start, end }
+    }
+    pub fn delta(&self) -> Vec3 {
+        self.end - self.start
+    }
+    pub fn length(&self) -> f32 {
+        self.delta().length()
+    }
+    /// Angle to the horizontal plane in degrees, positive when `end` is higher.
+    pub fn angle(&self) -> f32 {
+        let d = self.delta();
+        d.y.atan2(d.x.hypot(d.z)).to_degrees()
+    }
+    pub fn label(&self) -> String {
+        let d = self.delta();
+        format!(
+            "{:.2} m  dx {:.2} dy {:.2} dz {:.2}  {:.1} deg",
+            self.length(),
+            d.x,
+            d.y,
+            d.z,
+            self.angle()
+        )
+    }
+}
+
+/// A world-space note.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Annotation {
+    pub position: [f32; 3],
+    pub text: String,
+}
+
+/// Notes for one scene, kept next to it in `assets/scenes/<name>.notes.ron`.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct AnnotationList {
+    scene: String,
+    notes: Vec<Annotation>,
+}
+
+impl AnnotationList {
+    pub const EXTENSION: &'static str = ".notes.ron";
+
+    pub fn new(scene: impl Into<String>) -> Self {
+        Self {
+            scene: scene.into(),
+            notes: Vec::new(),
+        }
+    }
+    pub fn path(scene: &str) -> PathBuf {
+        SceneFile::dir().join(format!("{}{}", scene, Self::EXTENSION))
+    }
+    /// Notes saved for `scene`; empty if there is no sidecar yet.
+    pub fn load(scene: &str) -> Result<Self, EngineError> {
+        let path = Self::path(scene);
+        let mut list = Self::new(scene);
+        if !path.exists() {
+            return Ok(list);
+        }
+        let source = std::fs::read_to_string(&path)?;
+        list.notes = Self::from_ron(&source)
+            .map_err(|e| EngineError::AssetLoadError(format!("{}: {}", path.display(), e)))?;
+        Ok(list)
+    }
+    pub fn save(&self) -> Result<(), EngineError> {
+        std::fs::write(Self::path(&self.scene), self.to_ron()?)?;
+        Ok(())
+    }
+    pub fn to_ron(&self) -> Result<String, EngineError> {
+        ron::ser::to_string_pretty(&self.notes, ron::ser::PrettyConfig::default())
+            .map_err(|e| EngineError::AssetLoadError(e.to_string()))
+    }
+    pub fn from_ron(source: &str) -> Result<Vec<Annotation>, EngineError> {
+        ron::de::from_str(source).map_err(|e| EngineError::AssetLoadError(e.to_string()))
+    }
+
+    pub fn scene(&self) -> &str {
+        &self.scene
+    }
+    pub fn notes(&self) -> &[Annotation] {
+        &self.notes
+    }
+    /// Adds a note and returns its index.
+    pub fn add(&mut self, position: Vec3, text: impl Into<String>) -> usize {
+        self.notes.push(Annotation {
+            position: position.to_array(),
+            text: text.into(),
+        });
+        self.notes.len() - 1
+    }
+    /// Removes the note at `index`; later notes move down by one.
+    pub fn remove(&mut self, index: usize) -> Option<Annotation> {
+        (index < self.notes.len()).then(|| self.notes.remove(index))
+    }
+}
+
+/// Origin and direction of the ray through `ndc` (normalized device x/y) for `view_proj`.
+pub fn cursor_ray(view_proj: Mat4, ndc: [f32; 2]) -> (Vec3, Vec3) {
+    let policy = crate::RenderSettings::depth_policy();
+    let inv = view_proj.inverse();
+    let near = inv.project_point3(Vec3::new(ndc[0], ndc[1], policy.near_depth()));
+    let far = inv.project_point3(Vec3::new(ndc[0], ndc[1], policy.far_depth()));
+    (near, (far - near).normalize_or_zero())
+}
+
+/// Nearest solid block or entity bounds along the ray, within [`MAX_PICK_DISTANCE`].
+pub fn pick(world: &World, origin: Vec3, dir: Vec3) -> Option<Vec3> {
+    let entity = world
+        .spatial()
+        .query_ray(origin, dir, MAX_PICK_DISTANCE, u32::MAX)
+        .next()
+        .map(|(_, t)| t);
+    let max = entity.unwrap_or(MAX_PICK_DISTANCE);
+    let steps = (max / PICK_STEP) as usize;
+    let terrain = (0..=steps)
+        .map(|i| i as f32 * PICK_STEP)
+        .find(|t| solid(&world.terrain, block_cell(origin + dir * *t)));
+    terrain.or(entity).map(|t| origin + dir * t)
+}
+
+/// Surface normal of the terrain at `point`, from which of the surrounding blocks are solid.
+pub fn terrain_normal(terrain: &Terrain, point: Vec3) -> Option<Vec3> {
+    let cell = block_cell(point);
+    let mut normal = Vec3::ZERO;
+    for x in -1..=1 {
+        for y in -1..=1 {
+            for z in -1..=1 {
+                let offset = IVec3::new(x, y, z);
+                if solid(terrain, cell + offset) {
+                    normal -= offset.as_vec3();
+                }
+            }
+        }
+    }
+    let normal = normal.normalize_or_zero();
+    (normal != Vec3::ZERO).then_some(normal)
+}
+
+/// Angle between the terrain surface at `point` and the horizontal, in degrees.
+pub fn slope_angle(terrain: &Terrain, point: Vec3) -> Option<f32> {
+    terrain_normal(terrain, point).map(|n| n.y.clamp(-1.0, 1.0).acos().to_degrees())
+}
+
+fn block_cell(point: Vec3) -> IVec3 {
+    point.floor().as_ivec3()
+}
+
+fn solid(terrain: &Terrain, cell: IVec3) -> bool {
+    terrain
+        .block_at(cell)
+        .is_some_and(|(block, _)| block != AIR)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-4
+    }
+
+    #[test]
+    fn measurements_report_length_deltas_and_angle() {
+        let m = Measurement::new(Vec3::new(1.0, 2.0, 3.0), Vec3::new(4.0, 6.0, 3.0));
+        assert_eq!(m.delta(), Vec3::new(3.0, 4.0, 0.0));
+        assert!(close(m.length(), 5.0));
+        assert!(close(m.angle(), 4.0f32.atan2(3.0).to_degrees()));
+        assert_eq!(m.label(), "5.00 m  dx 3.00 dy 4.00 dz 0.00  53.1 deg");
+
+        let flat = Measurement::new(Vec3::ZERO, Vec3::new(2.0, 0.0, -2.0));
+        assert!(close(flat.angle(), 0.0));
+        let down = Measurement::new(Vec3::new(0.0, 3.0, 0.0), Vec3::new(0.0, 0.0, 3.0));
+        assert!(close(down.angle(), -45.0));
+        let up = Measurement::new(Vec3::ZERO, Vec3::Y);
+        assert!(close(up.angle(), 90.0));
+        assert!(close(Measurement::new(Vec3::ONE, Vec3::ONE).length(), 0.0));
+    }
+
+    #[test]
+    fn annotations_survive_a_save_and_load() {
+        crate::assets::loader::test_root();
+        let scene = format!("notes_{}", std::process::id());
+        let _ = std::fs::remove_file(AnnotationList::path(&scene));
+        assert!(AnnotationList::load(&scene).unwrap().notes().is_empty());
+
+        let mut list = AnnotationList::new(scene.as_str());
+        list.add(Vec3::new(1.5, -2.0, 30.25), "spawn too close to lava");
+        list.add(Vec3::ZERO, "check \"quotes\", commas\nand newlines");
+        list.save().unwrap();
+
+        let loaded = AnnotationList::load(&scene).unwrap();
+        std::fs::remove_file(AnnotationList::path(&scene)).unwrap();
+        assert_eq!(loaded, list);
+        assert_eq!(loaded.scene(), scene);
+
+        std::fs::write(AnnotationList::path(&scene), "not ron").unwrap();
+        let error = AnnotationList::load(&scene).unwrap_err().to_string();
+        std::fs::remove_file(AnnotationList::path(&scene)).unwrap();
+        assert!(error.contains(".notes.ron"), "{}", error);
+    }
+
+    #[test]
+    fn removing_a_note_shifts_the_later_ones_down() {
+        let mut list = AnnotationList::new("scene");
+        assert_eq!(list.add(Vec3::X, "a"), 0);
+        assert_eq!(list.add(Vec3::Y, "b"), 1);
+        assert_eq!(list.add(Vec3::Z, "c"), 2);
+
+        assert_eq!(list.remove(1).map(|n| n.text), Some("b".to_string()));
+        let texts: Vec<_> = list.notes().iter().map(|n| n.text.as_str()).collect();
+        assert_eq!(texts, ["a", "c"]);
+        assert_eq!(list.notes()[1].position, [0.0, 0.0, 1.0]);
+
+        assert_eq!(list.remove(2), None);
+        assert_eq!(list.notes().len(), 2);
+        assert_eq!(list.add(Vec3::ONE, "d"), 2);
+    }
+}
@@ -0,0 +1,18 @@
+//! Immediate-mode developer UI on top of egui. Only compiled with the `devtools` feature.
+
+pub use egui;
+
+pub mod input;
+pub use input::*;
+
+pub mod egui_layer;
+pub use egui_layer::*;
+
+pub mod panels;
+pub use panels::*;
+
+pub mod measure;
+pub use measure::*;
+
+pub mod console;
+pub use console::*;
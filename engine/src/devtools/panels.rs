@@ -0,0 +1,284 @@
+use glam::Vec3;
+
+use crate::{Entity, ModelManager, Position, Scale, World};
+
+fn selection_id() -> egui::Id {
+    egui::Id::new("devtools_selected_entity")
+}
+
+/// Entity picked in the inspector, if any.
+pub fn selected_entity(ctx: &egui::Context) -> Option<Entity> {
+    ctx.data(|d| d.get_temp::<usize>(selection_id()))
+        .map(Entity)
+}
+pub fn select_entity(ctx: &egui::Context, entity: Option<Entity>) {
+    ctx.data_mut(|d| match entity {
+        Some(entity) => d.insert_temp(selection_id(), entity.0),
+        None => d.remove::<usize>(selection_id()),
+    });
+}
+
+/// Drag-edits a vector; returns the new value only when it changed.
+fn drag_vec3(ui: &mut egui::Ui, label: &str, value: Vec3, speed: f32) -> Option<Vec3> {
+    let mut v = value.to_array();
+    let mut changed = false;
+    ui.horizontal(|ui| {
+        ui.label(label);
+        for c in v.iter_mut() {
+            changed |= ui
+                .add(egui::DragValue::new(c).speed(speed).max_decimals(3))
+                .changed();
+        }
+    });
+    changed.then(|| Vec3::from_array(v))
+}
+
+/// Entity list plus position/scale editing for the selected entity. Edits go through
+/// `World::insert_*`, so transforms and the spatial grid pick them up on the next update.
+pub fn entity_inspector(ctx: &egui::Context, world: &mut World, _models: &mut ModelManager) {
+    let mut selected = selected_entity(ctx);
+    egui::Window::new("Inspector")
+        .default_width(280.0)
+        .show(ctx, |ui| {
+            ui.label(format!("{} entities", world.entity_count()));
+            egui::ScrollArea::vertical()
+                .max_height(160.0)
+                .show(ui, |ui| {
+                    for i in 0..world.entity_count() {
+                        let entity = Entity(i);
+                        if world.physics.positions.get(i).copied().flatten().is_none()
+                            && world.get_renderable(entity).is_none()
+                        {
+                            continue;
+                        }
+                        let label = match world.get_renderable(entity) {
+                            Some(r) => format!("{} (model {})", i, r.model_key.id()),
+                            None => i.to_string(),
+                        };
+                        if ui
+                            .selectable_label(selected == Some(entity), label)
+                            .clicked()
+                        {
+                            selected = Some(entity);
+                        }
+                    }
+                });
+            ui.separator();
+
+            let Some(entity) = selected else {
+                ui.label("Nothing selected");
+                return;
+            };
+            ui.heading(format!("Entity {}", entity.0));
+            if let Some(pos) = world.physics.positions.get(entity.0).copied().flatten() {
+                if let Some(v) = drag_vec3(ui, "Position", pos.0, 0.05) {
+                    world.insert_position(entity, Position(v));
+                }
+            }
+            if let Some(scale) = world.scales.get(entity.0).copied().flatten() {
+                if let Some(v) = drag_vec3(ui, "Scale", scale.0, 0.01) {
+                    world.insert_scale(entity, Scale(v.max(Vec3::splat(0.001))));
+                }
+            }
+            if let Some(bounds) = world.spatial().bounds(entity) {
+                ui.label(format!("Bounds {:.2?} .. {:.2?}", bounds.min, bounds.max));
+            }
+            if ui.button("Despawn").clicked() {
+                world.despawn(entity);
+                selected = None;
+            }
+        });
+    select_entity(ctx, selected);
+}
+
+/// Scalar parameters of every material in the storage buffer. Edits are written to the
+/// buffer in place on the next flush.
+pub fn material_tweaker(ctx: &egui::Context, _world: &mut World, models: &mut ModelManager) {
+    egui::Window::new("Materials")
+        .default_open(false)
+        .show(ctx, |ui| {
+            let materials = &mut models.materials;
+            let mut names: Vec<(String, u32)> = materials
+                .storage_names
+                .iter()
+                .map(|(name, idx)| (name.clone(), *idx))
+                .collect();
+            names.sort();
+            for (name, idx) in names {
+                let Some(mut data) = materials.storage.get(idx as usize).copied() else {
+                    continue;
+                };
+                let changed = ui
+                    .horizontal(|ui| {
+                        ui.label(&name);
+                        ui.add(
+                            egui::DragValue::new(&mut data.shininess)
+                                .speed(0.5)
+                                .range(0.0..=1024.0),
+                        )
+                        .changed()
+                    })
+                    .inner;
+                if changed {
+                    materials.set_storage(idx, |d| *d = data);
+                }
+            }
+        });
+}
+
+/// Resident texture memory against its budget, in MiB. A budget of 0 never evicts.
+pub fn resource_stats(ctx: &egui::Context, _world: &mut World, models: &mut ModelManager) {
+    const MIB: f64 = 1024.0 * 1024.0;
+    egui::Window::new("Resources")
+        .default_open(false)
+        .show(ctx, |ui| {
+            let textures = &mut models.materials.textures;
+            let stats = textures.stats();
+            ui.label(format!(
+                "Textures {:.1} MiB",
+                stats.texture_bytes as f64 / MIB
+            ));
+            ui.label(format!("{} evictions", stats.evictions));
+
+            let mut budget = textures
+                .residency()
+                .budget()
+                .map_or(0.0, |b| b as f64 / MIB);
+            ui.horizontal(|ui| {
+                ui.label("Texture budget (MiB)");
+                if ui
+                    .add(
+                        egui::DragValue::new(&mut budget)
+                            .speed(16.0)
+                            .range(0.0..=65536.0),
+                    )
+                    .changed()
+                {
+                    textures.set_budget((budget > 0.0).then(|| (budget * MIB) as u64));
+                }
+            });
+        });
+}
+
+/// Last frame's instanced draws, the pipeline and bind group changes recording them took,
+/// and the instances drawn at each level of detail.
+pub fn draw_stats(ctx: &egui::Context, world: &mut World, models: &mut ModelManager) {
+    egui::Window::new("Draws")
+        .default_open(false)
+        .show(ctx, |ui| {
+            let stats = models.draw_stats;
+            ui.label(format!("{} draw calls", stats.draw_calls));
+            ui.label(format!("{} pipeline switches", stats.pipeline_switches));
+            ui.label(format!("{} bind group switches", stats.bind_group_switches));
+            for (level, count) in models.lod_stats.instances.iter().enumerate() {
+                ui.label(format!("LOD {}: {} instances", level, count));
+            }
+            let meshes = world.terrain.mesh_stats();
+            ui.label(format!(
+                "Terrain meshes: {} uploaded, {} shared ({} KiB saved), {} live",
+                meshes.uploads,
+                meshes.hits,
+                meshes.bytes_saved / 1024,
+                meshes.live
+            ));
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(ctx: &egui::Context, events: Vec<egui::Event>, ui: impl FnMut(&egui::Context)) {
+        let input = egui::RawInput {
+            screen_rect: Some(egui::Rect::from_min_size(
+                egui::Pos2::ZERO,
+                egui::vec2(800.0, 600.0),
+            )),
+            events,
+            ..Default::default()
+        };
+        let _ = ctx.run(input, ui);
+    }
+
+    /// The inspector's position row, without the model manager the real panel takes.
+    fn inspector(ctx: &egui::Context, world: &mut World) {
+        egui::CentralPanel::default().show(ctx, |ui| {
+            let Some(entity) = selected_entity(ctx) else {
+                ui.label("Nothing selected");
+                return;
+            };
+            if let Some(pos) = world.physics.positions.get(entity.0).copied().flatten() {
+                if let Some(v) = drag_vec3(ui, "Position", pos.0, 0.05) {
+                    world.insert_position(entity, Position(v));
+                }
+            }
+        });
+    }
+
+    /// Centers of last frame's drag values, left to right.
+    fn drag_targets(ctx: &egui::Context) -> Vec<egui::Pos2> {
+        let mut centers: Vec<egui::Pos2> = ctx.viewport(|v| {
+            v.prev_pass
+                .widgets
+                .layers()
+                .flat_map(|(_, widgets)| widgets)
+                .filter(|w| w.sense.senses_drag())
+                .map(|w| w.rect.center())
+                .collect()
+        });
+        centers.sort_by(|a, b| a.x.total_cmp(&b.x));
+        centers
+    }
+
+    fn press(pos: egui::Pos2, pressed: bool) -> egui::Event {
+        egui::Event::PointerButton {
+            pos,
+            button: egui::PointerButton::Primary,
+            pressed,
+            modifiers: Default::default(),
+        }
+    }
+
+    #[test]
+    fn dragging_in_the_inspector_moves_the_selected_entity() {
+        let mut world = World::empty();
+        world.spawn();
+        let entity = world.spawn();
+        world.insert_position(entity, Position(Vec3::new(1.0, 2.0, 3.0)));
+
+        let ctx = egui::Context::default();
+        ctx.style_mut(|s| s.interaction.selectable_labels = false);
+        frame(&ctx, vec![], |ctx| inspector(ctx, &mut world));
+        assert!(drag_targets(&ctx).is_empty());
+
+        select_entity(&ctx, Some(entity));
+        frame(&ctx, vec![], |ctx| inspector(ctx, &mut world));
+        let targets = drag_targets(&ctx);
+        assert_eq!(targets.len(), 3);
+
+        let start = targets[0];
+        let end = start + egui::vec2(40.0, 0.0);
+        frame(
+            &ctx,
+            vec![egui::Event::PointerMoved(start), press(start, true)],
+            |ctx| inspector(ctx, &mut world),
+        );
+        for step in 1..=4 {
+            let pos = start + egui::vec2(10.0 * step as f32, 0.0);
+            frame(&ctx, vec![egui::Event::PointerMoved(pos)], |ctx| {
+                inspector(ctx, &mut world)
+            });
+        }
+        frame(&ctx, vec![press(end, false)], |ctx| {
+            inspector(ctx, &mut world)
+        });
+
+        let pos = world.physics.positions[entity.0].unwrap().0;
+        assert!(pos.x > 1.0, "{:?}", pos);
+        assert_eq!((pos.y, pos.z), (2.0, 3.0));
+        assert_eq!(selected_entity(&ctx), Some(entity));
+
+        select_entity(&ctx, None);
+        assert_eq!(selected_entity(&ctx), None);
+    }
+}
@@ -0,0 +1,47 @@
+use crate::CacheKey;
+
+/// Plays an [`crate::AnimationClip`] on a skinned model. The world tick only advances
+/// `time`; wrapping or clamping to the clip happens when the pose is sampled.
+#[derive(Debug, Copy, Clone)]
+pub struct Animator {
+    /// Key the clip was registered under, `"<model file>#<animation name>"`.
+    pub clip: CacheKey,
+    pub time: f32,
+    pub looping: bool,
+    /// Playback rate, 1 for real time.
+    pub speed: f32,
+}
+
+impl Animator {
+    pub fn new(clip: impl Into<CacheKey>) -> Self {
+        Self {
+            clip: clip.into(),
+            time: 0.0,
+            looping: true,
+            speed: 1.0,
+        }
+    }
+    pub fn with_looping(mut self, looping: bool) -> Self {
+        self.looping = looping;
+        self
+    }
+    pub fn with_speed(mut self, speed: f32) -> Self {
+        self.speed = speed;
+        self
+    }
+    /// Switches to `clip` from its start.
+    pub fn play(&mut self, clip: impl Into<CacheKey>) {
+        self.clip = clip.into();
+        self.time = 0.0;
+    }
+    pub fn tick(&mut self, dt: f32) {
+        self.time += dt * self.speed;
+    }
+}
+
+/// Advances every animator by `dt`.
+pub fn update_animators(dt: f32, animators: &mut [Option<Animator>]) {
+    for animator in animators.iter_mut().flatten() {
+        animator.tick(dt);
+    }
+}
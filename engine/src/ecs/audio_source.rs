@@ -0,0 +1,55 @@
+use crate::CacheKey;
+
+/// Plays a clip at the entity's [`super::Position`], louder and panned toward whichever
+/// ear is closer to it. Mixed by `crate::audio::AudioManager` with the `audio` feature;
+/// without it the component is plain data and nothing plays.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct AudioSource {
+    /// Key of a clip loaded with `AudioManager::load`.
+    pub clip: CacheKey,
+    /// Gain at the emitter, before distance attenuation.
+    pub volume: f32,
+    /// Restarts the clip when it ends; otherwise it plays once per component.
+    pub looping: bool,
+    /// Distance from the camera at which the sound fades out completely.
+    pub max_distance: f32,
+}
+
+impl AudioSource {
+    pub const DEFAULT_MAX_DISTANCE: f32 = 30.0;
+
+    pub fn new(clip: impl Into<CacheKey>) -> Self {
+        Self {
+            clip: clip.into(),
+            volume: 1.0,
+            looping: false,
+            max_distance: Self::DEFAULT_MAX_DISTANCE,
+        }
+    }
+    pub fn looping(mut self) -> Self {
+        self.looping = true;
+        self
+    }
+    pub fn with_volume(mut self, volume: f32) -> Self {
+        self.volume = volume;
+        self
+    }
+    pub fn with_max_distance(mut self, max_distance: f32) -> Self {
+        self.max_distance = max_distance;
+        self
+    }
+    /// Gain of the sound heard `distance` away: `volume` at the emitter, falling off
+    /// quadratically to silence at `max_distance`.
+    pub fn gain_at(&self, distance: f32) -> f32 {
+        self.volume * attenuation(distance, self.max_distance)
+    }
+}
+
+/// Share of a sound's volume left `distance` away when it fades out at `max_distance`.
+pub fn attenuation(distance: f32, max_distance: f32) -> f32 {
+    if max_distance <= 0.0 {
+        return 0.0;
+    }
+    let t = (1.0 - distance / max_distance).clamp(0.0, 1.0);
+    t * t
+}
@@ -0,0 +1,343 @@
+//! Entity against terrain voxel collision. Each moving entity's box is swept through the
+//! blocks one axis at a time, vertical first, and stops at the first solid block on its way.
+
+use glam::{IVec3, Vec3};
+
+use crate::{Terrain, AIR};
+
+/// Keeps box faces resting exactly on a block boundary, chunk borders included, from
+/// counting the blocks on the other side as overlapped.
+const CONTACT_EPSILON: f32 = 1e-4;
+
+/// Axis-aligned box an entity collides with terrain as, around its position.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Collider {
+    pub half_extents: Vec3,
+    /// Box center relative to the entity position.
+    pub offset: Vec3,
+    /// Whether the last physics step ended on a solid block.
+    pub grounded: bool,
+    /// Keeps the third-person camera out of the box, like a solid block does.
+    pub blocks_camera: bool,
+}
+
+impl Collider {
+    pub fn new(half_extents: Vec3) -> Self {
+        Self {
+            half_extents: half_extents.abs(),
+            offset: Vec3::ZERO,
+            grounded: false,
+            blocks_camera: false,
+        }
+    }
+    pub fn with_offset(mut self, offset: Vec3) -> Self {
+        self.offset = offset;
+        self
+    }
+    pub fn with_blocks_camera(mut self, blocks_camera: bool) -> Self {
+        self.blocks_camera = blocks_camera;
+        self
+    }
+    pub fn min(&self, position: Vec3) -> Vec3 {
+        position + self.offset - self.half_extents
+    }
+    pub fn max(&self, position: Vec3) -> Vec3 {
+        position + self.offset + self.half_extents
+    }
+}
+
+/// Whether the block at `cell` stops colliders: non-air in a chunk whose medium is solid.
+/// Unloaded chunks don't.
+pub fn is_solid_block(terrain: &Terrain, cell: IVec3) -> bool {
+    terrain
+        .block_at(cell)
+        .is_some_and(|(block, medium)| block != AIR && medium.is_solid())
+}
+
+/// Distance along `dir`, which is normalized, from `origin` to the first solid block the
+/// ray enters within `max_dist`, 0 if it starts in one.
+pub fn raycast_terrain(terrain: &Terrain, origin: Vec3, dir: Vec3, max_dist: f32) -> Option<f32> {
+    raycast_blocks(origin, dir, max_dist, |cell| is_solid_block(terrain, cell))
+        .map(|hit| hit.distance)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BlockHit {
+    pub cell: IVec3,
+    /// Zero when the ray started inside.
+    pub normal: IVec3,
+    pub distance: f32,
+}
+
+/// `dir` must be normalized. Walks one block boundary at a time, chunk borders included.
+pub fn raycast_blocks(
+    origin: Vec3,
+    dir: Vec3,
+    max_dist: f32,
+    hit: impl Fn(IVec3) -> bool,
+) -> Option<BlockHit> {
+    let mut cell = origin.floor().as_ivec3();
+    if hit(cell) {
+        return Some(BlockHit {
+            cell,
+            normal: IVec3::ZERO,
+            distance: 0.0,
+        });
+    }
+    let mut step = IVec3::ZERO;
+    let mut next = Vec3::INFINITY;
+    let mut delta = Vec3::INFINITY;
+    for axis in 0..3 {
+        let d = dir[axis];
+        if d == 0.0 {
+            continue;
+        }
+        step[axis] = if d > 0.0 { 1 } else { -1 };
+        let boundary = (cell[axis] + (d > 0.0) as i32) as f32;
+        next[axis] = (boundary - origin[axis]) / d;
+        delta[axis] = 1.0 / d.abs();
+    }
+    loop {
+        let axis = if next.x <= next.y && next.x <= next.z {
+            0
+        } else if next.y <= next.z {
+            1
+        } else {
+            2
+        };
+        let t = next[axis];
+        if t > max_dist {
+            return None;
+        }
+        cell[axis] += step[axis];
+        next[axis] += delta[axis];
+        if hit(cell) {
+            let mut normal = IVec3::ZERO;
+            normal[axis] = -step[axis];
+            return Some(BlockHit {
+                cell,
+                normal,
+                distance: t,
+            });
+        }
+    }
+}
+
+/// Moves `position` by `velocity * dt` unless terrain is in the way. Velocity along a
+/// blocked axis drops to zero and `collider.grounded` is set when the way down was blocked.
+/// Every block row between the start and end of the move is tested, so a step of any length
+/// can't pass through a wall. Returns the downward speed the collider landed with.
+pub fn sweep(
+    terrain: &Terrain,
+    collider: &mut Collider,
+    position: &mut Vec3,
+    velocity: &mut Vec3,
+    dt: f32,
+) -> Option<f32> {
+    let motion = *velocity * dt;
+    let mut landed = None;
+    collider.grounded = false;
+    for axis in [1, 0, 2] {
+        let wanted = motion[axis];
+        if wanted == 0.0 {
+            continue;
+        }
+        let allowed = sweep_axis(
+            terrain,
+            collider.min(*position),
+            collider.max(*position),
+            axis,
+            wanted,
+        );
+        position[axis] += allowed;
+        if allowed != wanted {
+            if axis == 1 && wanted < 0.0 {
+                collider.grounded = true;
+                landed = Some(-velocity.y);
+            }
+            velocity[axis] = 0.0;
+        }
+    }
+    landed
+}
+
+/// How far the `min`..`max` box can move by `distance` along `axis` before a solid block.
+/// Only blocks inside the swept box are looked at.
+fn sweep_axis(terrain: &Terrain, min: Vec3, max: Vec3, axis: usize, distance: f32) -> f32 {
+    let lo = (min + CONTACT_EPSILON).floor().as_ivec3();
+    let hi = (max - CONTACT_EPSILON).floor().as_ivec3();
+    let (first, last, step) = if distance > 0.0 {
+        let face = max[axis];
+        (
+            (face - CONTACT_EPSILON).floor() as i32 + 1,
+            (face + distance - CONTACT_EPSILON).floor() as i32,
+            1,
+        )
+    } else {
+        let face = min[axis];
+        (
+            (face + CONTACT_EPSILON).floor() as i32 - 1,
+            (face + distance + CONTACT_EPSILON).floor() as i32,
+            -1,
+        )
+    };
+    let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+    let mut row = first;
+    while (step > 0 && row <= last) || (step < 0 && row >= last) {
+        let blocked = (lo[u]..=hi[u]).any(|a| {
+            (lo[v]..=hi[v]).any(|b| {
+                let mut cell = IVec3::ZERO;
+                cell[axis] = row;
+                cell[u] = a;
+                cell[v] = b;
+                is_solid_block(terrain, cell)
+            })
+        });
+        if blocked {
+            return if step > 0 {
+                (row as f32 - max[axis]).clamp(0.0, distance)
+            } else {
+                (row as f32 + 1.0 - min[axis]).clamp(distance, 0.0)
+            };
+        }
+        row += step;
+    }
+    distance
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+    use crate::{Chunk, Medium, CHUNK_SIZE, STONE};
+
+    const GROUND: f32 = 3.0;
+
+    /// Three by three chunks of solid ground, stone below `GROUND` and air above.
+    fn flat() -> Terrain {
+        let mut terrain = Terrain::new(Medium::Air);
+        for pos in (0..9).map(|i| (i % 3, 0, i / 3)) {
+            let mut chunk = Chunk::new(pos);
+            chunk.blocks = [[[AIR; CHUNK_SIZE]; CHUNK_SIZE]; CHUNK_SIZE];
+            for x in 0..CHUNK_SIZE {
+                for y in 0..GROUND as usize {
+                    for z in 0..CHUNK_SIZE {
+                        chunk.blocks[x][y][z] = STONE;
+                    }
+                }
+            }
+            terrain.insert_chunk_stream(chunk, Medium::Ground);
+        }
+        terrain
+    }
+
+    /// A person-sized box with its position at the feet.
+    fn body() -> Collider {
+        Collider::new(Vec3::new(0.3, 0.9, 0.3)).with_offset(Vec3::new(0.0, 0.9, 0.0))
+    }
+
+    /// Falls under gravity from `start` until grounded, at most ten seconds.
+    fn drop(terrain: &Terrain, start: Vec3, dt: f32) -> (Vec3, Collider, Option<f32>) {
+        let (mut collider, mut position, mut velocity) = (body(), start, Vec3::ZERO);
+        for _ in 0..(10.0 / dt) as usize {
+            velocity.y -= 9.81 * dt;
+            if let Some(speed) = sweep(terrain, &mut collider, &mut position, &mut velocity, dt) {
+                return (position, collider, Some(speed));
+            }
+        }
+        (position, collider, None)
+    }
+
+    #[test]
+    fn falling_lands_on_the_ground_surface() {
+        let terrain = flat();
+        let (position, collider, landed) = drop(&terrain, Vec3::new(5.5, 12.0, 5.5), 1.0 / 60.0);
+        assert!(
+            (position.y - GROUND).abs() < 1e-4,
+            "landed at {}",
+            position.y
+        );
+        assert!(collider.grounded);
+        assert!(landed.unwrap() > 0.0);
+        assert_eq!((position.x, position.z), (5.5, 5.5));
+
+        // Resting there, the next step stays put and grounded.
+        let (mut collider, mut resting, mut velocity) =
+            (body(), position, Vec3::new(0.0, -0.2, 0.0));
+        sweep(
+            &terrain,
+            &mut collider,
+            &mut resting,
+            &mut velocity,
+            1.0 / 60.0,
+        );
+        assert!(collider.grounded);
+        assert_eq!(velocity.y, 0.0);
+        assert!((resting.y - GROUND).abs() < 1e-4);
+    }
+
+    #[test]
+    fn lands_astride_a_chunk_corner() {
+        let terrain = flat();
+        let border = CHUNK_SIZE as f32;
+        let (position, collider, _) = drop(&terrain, Vec3::new(border, 9.0, border), 1.0 / 60.0);
+        assert!(collider.grounded);
+        assert!((position.y - GROUND).abs() < 1e-4);
+    }
+
+    #[test]
+    fn one_long_step_does_not_tunnel_through_the_ground() {
+        let terrain = flat();
+        let (mut collider, mut position) = (body(), Vec3::new(2.5, 30.0, 2.5));
+        let mut velocity = Vec3::new(0.0, -3_000.0, 0.0);
+        let landed = sweep(&terrain, &mut collider, &mut position, &mut velocity, 0.5);
+        assert_eq!(landed, Some(3_000.0));
+        assert!((position.y - GROUND).abs() < 1e-4);
+        assert_eq!(velocity, Vec3::ZERO);
+    }
+
+    #[test]
+    fn ray_crosses_chunk_borders_block_by_block() {
+        let size = CHUNK_SIZE as i32;
+        let target = IVec3::new(size + 1, 1, 1);
+        let visited = RefCell::new(Vec::new());
+        let hit = raycast_blocks(Vec3::new(0.5, 1.5, 1.5), Vec3::X, 16.0, |cell| {
+            visited.borrow_mut().push(cell);
+            cell == target
+        })
+        .unwrap();
+        assert_eq!(hit.cell, target);
+        assert_eq!(hit.normal, IVec3::NEG_X);
+        assert!((hit.distance - (size as f32 + 0.5)).abs() < 1e-5);
+        let xs: Vec<i32> = visited.borrow().iter().map(|cell| cell.x).collect();
+        assert_eq!(xs, (0..=size + 1).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn diagonal_ray_steps_through_face_neighbors() {
+        let size = CHUNK_SIZE as i32;
+        let dir = Vec3::new(-1.0, 0.0, -0.6).normalize();
+        let visited = RefCell::new(Vec::new());
+        let hit = raycast_blocks(Vec3::new(0.5, 0.5, 0.5), dir, 32.0, |cell| {
+            visited.borrow_mut().push(cell);
+            cell.x <= -size - 2
+        })
+        .unwrap();
+        assert_eq!(hit.normal, IVec3::X);
+        assert_eq!(hit.cell.x, -size - 2);
+        // Every step enters a block sharing a face with the last, across the chunk border.
+        for pair in visited.borrow().windows(2) {
+            let step = pair[1] - pair[0];
+            assert_eq!(step.abs().element_sum(), 1, "{:?}", pair);
+        }
+        assert!(visited.borrow().iter().any(|cell| cell.z < 0));
+    }
+
+    #[test]
+    fn ray_misses_past_max_distance() {
+        let wall = |cell: IVec3| cell.x == CHUNK_SIZE as i32 * 2;
+        assert!(raycast_blocks(Vec3::splat(0.5), Vec3::X, 4.0, wall).is_none());
+        assert!(raycast_blocks(Vec3::splat(0.5), Vec3::NEG_X, 64.0, wall).is_none());
+    }
+}
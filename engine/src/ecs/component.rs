@@ -37,6 +37,80 @@ impl From<Vec3> for Velocity {
         Velocity(value)
     }
 }
+/// Per-instance RGBA multiplier applied on top of the material.
+#[derive(Debug, Copy, Clone)]
+pub struct Tint(pub [f32; 4]);
+
+impl Tint {
+    pub fn white() -> Self {
+        Tint([1.0; 4])
+    }
+    pub fn alpha(&self) -> f32 {
+        self.0[3]
+    }
+}
+
+/// Draws a texture as a quad that always faces the camera, centered on the entity's
+/// [`Position`] and tinted by its [`Tint`]. Needs no model.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Billboard {
+    /// Key of a texture already in the [`crate::TextureManager`].
+    pub texture: CacheKey,
+    /// World-space width and height.
+    pub size: [f32; 2],
+}
+
+impl Billboard {
+    pub fn new(texture: impl Into<CacheKey>, size: [f32; 2]) -> Self {
+        Self {
+            texture: texture.into(),
+            size,
+        }
+    }
+}
+
+/// Visibility layers of a renderable entity, as a bitmask. A camera draws the entity
+/// when its [`crate::camera::Camera::render_mask`] shares a layer with it; an entity
+/// without the component is on every layer.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Layers(pub u32);
+
+impl Layers {
+    pub const NONE: Self = Self(0);
+    pub const ALL: Self = Self(u32::MAX);
+    /// Seen only from first person, like a held item.
+    pub const FIRST_PERSON: Self = Self(1 << 0);
+    /// Seen from every view but first person, like the camera's own model.
+    pub const THIRD_PERSON: Self = Self(1 << 1);
+    /// Gizmos and other tooling; cameras leave it out unless asked.
+    pub const EDITOR: Self = Self(1 << 2);
+    /// Markers for map views; cameras leave it out unless asked.
+    pub const MAP: Self = Self(1 << 3);
+
+    pub fn intersects(self, other: Self) -> bool {
+        self.0 & other.0 != 0
+    }
+    pub fn with(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+    pub fn without(self, other: Self) -> Self {
+        Self(self.0 & !other.0)
+    }
+}
+
+impl Default for Layers {
+    fn default() -> Self {
+        Self::ALL
+    }
+}
+
+impl std::ops::BitOr for Layers {
+    type Output = Self;
+    fn bitor(self, rhs: Self) -> Self {
+        self.with(rhs)
+    }
+}
+
 #[derive(Debug, Copy, Clone)]
 pub struct Rotation(pub Quat);
 
@@ -70,6 +144,9 @@ impl Rotation {
     pub fn zero() -> Self {
         Self(Quat::from_array([0.0, 0.0, 0.0, 0.0]))
     }
+    pub fn identity() -> Self {
+        Self(Quat::IDENTITY)
+    }
     /// Returns a rotation that makes the model's forward (+Z) face the -Z direction in world space.
     /// Optionally takes an up vector (default Y).
     pub fn face_neg_z(up: Vec3) -> Self {
@@ -95,6 +172,29 @@ impl Rotation {
     }
 }
 
+/// Position and rotation before the last fixed step. Transforms blend from it to the
+/// current pose by [`super::Tick::alpha`], so motion stays smooth between steps.
+#[derive(Debug, Copy, Clone)]
+pub struct PreviousPose {
+    pub position: Vec3,
+    pub rotation: Quat,
+}
+
+impl PreviousPose {
+    /// The pose `alpha` of the way from this one to `position` and `rotation`.
+    pub fn blend(
+        &self,
+        position: &Position,
+        rotation: &Rotation,
+        alpha: f32,
+    ) -> (Position, Rotation) {
+        (
+            Position(self.position.lerp(position.0, alpha)),
+            Rotation(self.rotation.slerp(rotation.0, alpha)),
+        )
+    }
+}
+
 #[derive(Debug, Copy, Clone)]
 pub struct Scale(pub Vec3);
 
@@ -120,14 +220,16 @@ impl From<Vec3> for Scale {
 #[derive(Debug, Copy, Clone)]
 pub struct Transform {
     pub model_matrix: glam::Mat4,
-    pub normal_matrix: glam::Mat4,
+    /// Inverse transpose of the model matrix's upper 3x3, which takes normals to world
+    /// space under non-uniform scale too. The shaders derive the same from the model matrix.
+    pub normal_matrix: glam::Mat3,
 }
 
 impl Default for Transform {
     fn default() -> Self {
         Self {
             model_matrix: glam::Mat4::IDENTITY,
-            normal_matrix: glam::Mat4::IDENTITY,
+            normal_matrix: glam::Mat3::IDENTITY,
         }
     }
 }
@@ -139,7 +241,7 @@ impl Transform {
         let scaling = Mat4::from_scale(scale.0);
 
         let model_matrix = translation * rotation * scaling;
-        let normal_matrix = model_matrix.inverse().transpose();
+        let normal_matrix = glam::Mat3::from_mat4(model_matrix).inverse().transpose();
         Self {
             model_matrix,
             normal_matrix,
@@ -148,23 +250,23 @@ impl Transform {
 
     pub fn to_vertex_instance(&self, mat_id: u32) -> VertexInstance {
         let model: [[f32; 4]; 4] = self.model_matrix.to_cols_array_2d();
-        let normal: [[f32; 4]; 4] = self.normal_matrix.to_cols_array_2d();
         let translation = self.model_matrix.w_axis.truncate().to_array();
 
         VertexInstance {
             model,
-            color: [1.0, 1.0, 1.0],
-            _pad0: 0.0,
+            color: [1.0; 4],
             translation,
             _pad1: 0.0,
             uv_offset: [0.0, 0.0],
             _pad2: [0.0; 2],
-            normal: [normal[0][0], normal[0][1], normal[0][2]],
+            normal: self.normal_matrix.x_axis.to_array(),
             _pad3: 0.0,
-            tangent: [normal[1][0], normal[1][1], normal[1][2]],
+            tangent: self.normal_matrix.y_axis.to_array(),
             _pad4: 0.0,
             material_id: mat_id,
-            _pad5: [0.0; 3],
+            flags: 0,
+            entity_id: 0,
+            _pad5: 0.0,
         }
     }
 }
@@ -264,3 +366,41 @@ impl<T> ComponentVec<T> {
             .zip(self.components.iter_mut())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use glam::Mat3;
+
+    use super::*;
+
+    #[test]
+    fn normals_stay_perpendicular_under_non_uniform_scale() {
+        let transform = Transform::from_components(
+            &Position(Vec3::new(3.0, -1.0, 2.0)),
+            &Rotation(Quat::from_rotation_y(0.7)),
+            &Scale::new(2.0, 1.0, 1.0),
+        );
+        // A 45 degree slope: two directions along the surface and its normal.
+        let along = [Vec3::new(1.0, 1.0, 0.0), Vec3::Z];
+        let normal = Vec3::new(1.0, -1.0, 0.0).normalize();
+
+        let linear = Mat3::from_mat4(transform.model_matrix);
+        let world_along = along.map(|v| linear * v);
+        let expected = world_along[0].cross(world_along[1]).normalize();
+        let world_normal = (transform.normal_matrix * normal).normalize();
+        assert!(
+            world_normal.abs_diff_eq(expected, 1e-5) || world_normal.abs_diff_eq(-expected, 1e-5)
+        );
+        for v in world_along {
+            assert!(world_normal.dot(v).abs() < 1e-5);
+        }
+
+        // The model matrix alone tilts the normal off the surface.
+        let skewed = (linear * normal).normalize();
+        assert!(skewed.dot(world_along[0]).abs() > 0.1);
+
+        let instance = transform.to_vertex_instance(0);
+        assert_eq!(instance.normal, transform.normal_matrix.x_axis.to_array());
+        assert_eq!(instance.tangent, transform.normal_matrix.y_axis.to_array());
+    }
+}
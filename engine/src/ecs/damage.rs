@@ -0,0 +1,191 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+static FRIENDLY_FIRE: AtomicBool = AtomicBool::new(false);
+
+/// Global damage rules.
+pub struct DamageSettings;
+
+impl DamageSettings {
+    /// Whether damage from a source on the same team goes through. Off by default.
+    pub fn friendly_fire() -> bool {
+        FRIENDLY_FIRE.load(Ordering::Relaxed)
+    }
+    pub fn set_friendly_fire(enabled: bool) {
+        FRIENDLY_FIRE.store(enabled, Ordering::Relaxed);
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Health {
+    pub current: f32,
+    pub max: f32,
+}
+
+impl Health {
+    pub fn new(max: f32) -> Self {
+        Self { current: max, max }
+    }
+    pub fn is_dead(&self) -> bool {
+        self.current <= 0.0
+    }
+    pub fn fraction(&self) -> f32 {
+        if self.max > 0.0 {
+            (self.current / self.max).clamp(0.0, 1.0)
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Marks an entity as something damage applies to. `invulnerable_until` is in world time,
+/// see [`crate::World::elapsed`].
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct Damageable {
+    pub team: u8,
+    pub invulnerable_until: Option<f64>,
+}
+
+impl Damageable {
+    pub fn new(team: u8) -> Self {
+        Self {
+            team,
+            invulnerable_until: None,
+        }
+    }
+    pub fn is_invulnerable(&self, now: f64) -> bool {
+        self.invulnerable_until.is_some_and(|until| now < until)
+    }
+    /// Ignores damage for `secs` from `now`, never shortening a longer window.
+    pub fn grant_invulnerability(&mut self, now: f64, secs: f64) {
+        let until = now + secs.max(0.0);
+        self.invulnerable_until = Some(self.invulnerable_until.map_or(until, |u| u.max(until)));
+    }
+}
+
+/// What happens to an entity once its health reaches zero. Entities without one despawn.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub enum DeathBehavior {
+    #[default]
+    Despawn,
+    /// Fades out through [`super::FadeOutThenDespawn`], then despawns.
+    FadeOut(Duration),
+    /// Only publishes [`crate::WorldEvent::EntityDied`]; the entity stays as it is.
+    EventOnly,
+}
+
+/// Outcome of [`crate::World::apply_damage`].
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum DamageResult {
+    /// Damage went through and the target survived with `remaining` health.
+    Applied { remaining: f32 },
+    /// Damage went through and took the target to zero.
+    Killed,
+    /// The target is inside an invulnerability window.
+    Invulnerable,
+    /// Source and target are on the same team and friendly fire is off.
+    FriendlyFire,
+    /// The target was already dead.
+    AlreadyDead,
+    /// The target has no [`Health`] or isn't [`Damageable`].
+    NotDamageable,
+}
+
+impl DamageResult {
+    pub fn landed(&self) -> bool {
+        matches!(self, DamageResult::Applied { .. } | DamageResult::Killed)
+    }
+}
+
+/// Checks a hit against invulnerability and team rules and lowers `health`, clamped at zero.
+/// `source_team` is the team of the attacker, if it has one.
+pub fn resolve_damage(
+    health: &mut Health,
+    target: &Damageable,
+    source_team: Option<u8>,
+    amount: f32,
+    now: f64,
+) -> DamageResult {
+    if health.is_dead() {
+        return DamageResult::AlreadyDead;
+    }
+    if target.is_invulnerable(now) {
+        return DamageResult::Invulnerable;
+    }
+    if source_team == Some(target.team) && !DamageSettings::friendly_fire() {
+        return DamageResult::FriendlyFire;
+    }
+    health.current = (health.current - amount.max(0.0)).max(0.0);
+    if health.is_dead() {
+        DamageResult::Killed
+    } else {
+        DamageResult::Applied {
+            remaining: health.current,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn damage_clamps_at_zero() {
+        let mut health = Health::new(10.0);
+        let target = Damageable::new(1);
+        assert_eq!(
+            resolve_damage(&mut health, &target, None, 4.0, 0.0),
+            DamageResult::Applied { remaining: 6.0 }
+        );
+        assert_eq!(
+            resolve_damage(&mut health, &target, None, -5.0, 0.0),
+            DamageResult::Applied { remaining: 6.0 },
+            "negative damage doesn't heal"
+        );
+        assert_eq!(
+            resolve_damage(&mut health, &target, None, 100.0, 0.0),
+            DamageResult::Killed
+        );
+        assert_eq!(health.current, 0.0);
+        assert_eq!(health.fraction(), 0.0);
+        assert_eq!(
+            resolve_damage(&mut health, &target, None, 1.0, 0.0),
+            DamageResult::AlreadyDead
+        );
+    }
+
+    #[test]
+    fn invulnerability_ends_at_its_deadline() {
+        let mut health = Health::new(10.0);
+        let mut target = Damageable::new(1);
+        target.grant_invulnerability(2.0, 1.5);
+        target.grant_invulnerability(2.0, 0.5);
+        assert_eq!(target.invulnerable_until, Some(3.5), "never shortened");
+
+        assert_eq!(
+            resolve_damage(&mut health, &target, None, 1.0, 3.49),
+            DamageResult::Invulnerable
+        );
+        assert_eq!(health.current, 10.0);
+        assert!(resolve_damage(&mut health, &target, None, 1.0, 3.5).landed());
+        assert_eq!(health.current, 9.0);
+    }
+
+    /// The only test that depends on the friendly fire setting, so nothing races it.
+    #[test]
+    fn teammates_only_hurt_each_other_with_friendly_fire() {
+        let mut health = Health::new(10.0);
+        let target = Damageable::new(2);
+        assert!(resolve_damage(&mut health, &target, Some(1), 1.0, 0.0).landed());
+        assert_eq!(
+            resolve_damage(&mut health, &target, Some(2), 1.0, 0.0),
+            DamageResult::FriendlyFire
+        );
+        assert_eq!(health.current, 9.0);
+
+        DamageSettings::set_friendly_fire(true);
+        let result = resolve_damage(&mut health, &target, Some(2), 1.0, 0.0);
+        DamageSettings::set_friendly_fire(false);
+        assert_eq!(result, DamageResult::Applied { remaining: 8.0 });
+    }
+}
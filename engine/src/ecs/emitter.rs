@@ -0,0 +1,76 @@
+use glam::Vec3;
+
+/// Spawns GPU particles at the entity's [`super::Position`]; simulated and drawn by
+/// [`crate::ParticleSystem`], so nothing here is touched per particle.
+#[derive(Debug, Copy, Clone)]
+pub struct ParticleEmitter {
+    /// Particles per second.
+    pub rate: f32,
+    /// Seconds each particle lives, varied by a quarter either way.
+    pub lifetime: f32,
+    /// Initial velocity, with up to `spread` added along each axis at random.
+    pub velocity: Vec3,
+    pub spread: f32,
+    /// Multiplier on the medium's gravity; negative rises.
+    pub gravity_scale: f32,
+    /// Color at birth, blended toward `end_color` over the particle's life.
+    pub start_color: [f32; 4],
+    pub end_color: [f32; 4],
+    /// Billboard side in world units.
+    pub size: f32,
+    /// Most particles alive at once, clamped to [`crate::ParticleSystem::MAX_PER_EMITTER`].
+    pub max_particles: u32,
+}
+
+impl ParticleEmitter {
+    pub fn new(rate: f32, lifetime: f32) -> Self {
+        Self {
+            rate,
+            lifetime,
+            velocity: Vec3::ZERO,
+            spread: 0.0,
+            gravity_scale: 1.0,
+            start_color: [1.0; 4],
+            end_color: [1.0, 1.0, 1.0, 0.0],
+            size: 0.1,
+            max_particles: 256,
+        }
+    }
+    /// Short-lived bright specks thrown up and pulled down hard.
+    pub fn sparks() -> Self {
+        Self::new(60.0, 0.6)
+            .with_velocity(Vec3::new(0.0, 4.0, 0.0), 2.5)
+            .with_colors([1.0, 0.8, 0.3, 1.0], [1.0, 0.2, 0.0, 0.0])
+            .with_size(0.05)
+    }
+    /// Slow, large puffs drifting upward.
+    pub fn smoke() -> Self {
+        Self::new(12.0, 3.0)
+            .with_velocity(Vec3::new(0.0, 0.5, 0.0), 0.3)
+            .with_gravity_scale(-0.05)
+            .with_colors([0.4, 0.4, 0.4, 0.6], [0.6, 0.6, 0.6, 0.0])
+            .with_size(0.6)
+    }
+    pub fn with_velocity(mut self, velocity: Vec3, spread: f32) -> Self {
+        self.velocity = velocity;
+        self.spread = spread;
+        self
+    }
+    pub fn with_gravity_scale(mut self, gravity_scale: f32) -> Self {
+        self.gravity_scale = gravity_scale;
+        self
+    }
+    pub fn with_colors(mut self, start: [f32; 4], end: [f32; 4]) -> Self {
+        self.start_color = start;
+        self.end_color = end;
+        self
+    }
+    pub fn with_size(mut self, size: f32) -> Self {
+        self.size = size;
+        self
+    }
+    pub fn with_max_particles(mut self, max_particles: u32) -> Self {
+        self.max_particles = max_particles;
+        self
+    }
+}
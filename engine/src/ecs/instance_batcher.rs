@@ -0,0 +1,56 @@
+use std::collections::HashMap;
+
+use crate::{CacheKey, Entity};
+
+/// Renderable entities grouped by model key, in id order. Kept up to date as renderables
+/// come and go, so the instance buffers don't regroup every entity each frame.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct InstanceBatcher {
+    batches: HashMap<CacheKey, Vec<Entity>>,
+}
+
+impl InstanceBatcher {
+    pub fn insert(&mut self, key: CacheKey, entity: Entity) {
+        let batch = self.batches.entry(key).or_default();
+        match batch.last() {
+            Some(last) if last.0 >= entity.0 => {
+                if let Err(i) = batch.binary_search_by_key(&entity.0, |e| e.0) {
+                    batch.insert(i, entity);
+                }
+            }
+            _ => batch.push(entity),
+        }
+    }
+    /// Appends `entities`, which must come after every entity batched under `key`.
+    pub fn extend(&mut self, key: CacheKey, entities: &[Entity]) {
+        self.batches
+            .entry(key)
+            .or_default()
+            .extend_from_slice(entities);
+    }
+    pub fn remove(&mut self, key: &CacheKey, entity: Entity) {
+        let Some(batch) = self.batches.get_mut(key) else {
+            return;
+        };
+        if let Ok(i) = batch.binary_search_by_key(&entity.0, |e| e.0) {
+            batch.remove(i);
+        }
+        if batch.is_empty() {
+            self.batches.remove(key);
+        }
+    }
+    pub fn get(&self, key: &CacheKey) -> &[Entity] {
+        self.batches.get(key).map_or(&[], Vec::as_slice)
+    }
+    pub fn iter(&self) -> impl Iterator<Item = (&CacheKey, &[Entity])> {
+        self.batches
+            .iter()
+            .map(|(key, batch)| (key, batch.as_slice()))
+    }
+    pub fn len(&self) -> usize {
+        self.batches.values().map(Vec::len).sum()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.batches.is_empty()
+    }
+}
@@ -0,0 +1,348 @@
+use std::time::Duration;
+
+use glam::Vec3;
+
+use super::{Entity, Position, SpatialGrid, Tint, ALL_LAYERS};
+
+/// Why an entity was despawned by the lifetime system.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ExpiryReason {
+    Lifetime,
+    TooFar,
+    Faded,
+    /// Health reached zero with [`super::DeathBehavior::FadeOut`].
+    Killed,
+}
+
+/// Remaining time before the entity is despawned (or starts fading, with [`FadeOutThenDespawn`]).
+#[derive(Debug, Copy, Clone)]
+pub struct Lifetime(pub Duration);
+
+impl Lifetime {
+    pub fn from_secs(secs: f32) -> Self {
+        Self(Duration::from_secs_f32(secs.max(0.0)))
+    }
+    /// Ticks the lifetime down, returning `true` once it has run out.
+    pub fn tick(&mut self, dt: f32) -> bool {
+        self.0 = self.0.saturating_sub(Duration::from_secs_f32(dt.max(0.0)));
+        self.0.is_zero()
+    }
+}
+
+/// Despawns the entity once it has stayed further than `distance` from the camera for
+/// `linger`. Coming back inside `distance * HYSTERESIS` resets the timer; in between it holds.
+#[derive(Debug, Copy, Clone)]
+pub struct DespawnWhenFar {
+    pub distance: f32,
+    pub linger: Duration,
+    far_for: f32,
+}
+
+impl DespawnWhenFar {
+    pub const HYSTERESIS: f32 = 0.9;
+    pub const DEFAULT_LINGER: Duration = Duration::from_secs(1);
+
+    pub fn new(distance: f32) -> Self {
+        Self {
+            distance,
+            linger: Self::DEFAULT_LINGER,
+            far_for: 0.0,
+        }
+    }
+    pub fn with_linger(mut self, linger: Duration) -> Self {
+        self.linger = linger;
+        self
+    }
+
+    /// Returns `true` once the entity has been far for long enough.
+    pub fn tick(&mut self, dt: f32, distance: f32) -> bool {
+        if distance > self.distance {
+            self.far_for += dt;
+        } else if distance < self.distance * Self::HYSTERESIS {
+            self.far_for = 0.0;
+        }
+        self.far_for >= self.linger.as_secs_f32()
+    }
+}
+
+/// Fades the entity's [`Tint`] alpha to zero over `fade`, then despawns it. With a
+/// [`Lifetime`] or [`DespawnWhenFar`] the fade starts when those expire; on its own it
+/// starts immediately.
+#[derive(Debug, Copy, Clone)]
+pub struct FadeOutThenDespawn {
+    pub fade: Duration,
+    elapsed: Option<f32>,
+    reason: ExpiryReason,
+}
+
+impl FadeOutThenDespawn {
+    pub fn new(fade: Duration) -> Self {
+        Self {
+            fade,
+            elapsed: None,
+            reason: ExpiryReason::Faded,
+        }
+    }
+    pub fn started(&self) -> bool {
+        self.elapsed.is_some()
+    }
+    pub fn start(&mut self, reason: ExpiryReason) {
+        if self.elapsed.is_none() {
+            self.elapsed = Some(0.0);
+            self.reason = reason;
+        }
+    }
+    /// Current alpha, 1.0 before the fade starts.
+    pub fn alpha(&self) -> f32 {
+        match self.elapsed {
+            Some(elapsed) if !self.fade.is_zero() => {
+                (1.0 - elapsed / self.fade.as_secs_f32()).clamp(0.0, 1.0)
+            }
+            Some(_) => 0.0,
+            None => 1.0,
+        }
+    }
+    /// Advances the fade, returning `true` once it has finished.
+    pub fn tick(&mut self, dt: f32) -> bool {
+        match self.elapsed.as_mut() {
+            Some(elapsed) => {
+                *elapsed += dt;
+                *elapsed >= self.fade.as_secs_f32()
+            }
+            None => false,
+        }
+    }
+}
+
+/// The columns [`update_lifetimes`] ticks and writes.
+pub struct LifetimeColumns<'a> {
+    pub lifetimes: &'a mut [Option<Lifetime>],
+    pub far: &'a mut [Option<DespawnWhenFar>],
+    pub fades: &'a mut [Option<FadeOutThenDespawn>],
+    pub tints: &'a mut [Option<Tint>],
+}
+
+/// Ticks every lifetime component and returns the entities that expired this step, in
+/// entity order. Fading entities get their tint alpha written. Only entities the grid
+/// finds within the largest [`DespawnWhenFar::distance`] get an exact distance check; the
+/// rest are known to be beyond it. An expired entity loses its lifetime components, so
+/// later steps don't report it again before it is despawned.
+pub fn update_lifetimes(
+    dt: f32,
+    camera_pos: Vec3,
+    columns: LifetimeColumns,
+    positions: &[Option<Position>],
+    spatial: &SpatialGrid,
+) -> Vec<(Entity, ExpiryReason)> {
+    let LifetimeColumns {
+        lifetimes,
+        far,
+        fades,
+        tints,
+    } = columns;
+    let mut expired = Vec::new();
+    let count = lifetimes.len().min(far.len()).min(fades.len());
+
+    let far_radius = far
+        .iter()
+        .flatten()
+        .map(|despawn| despawn.distance)
+        .fold(f32::NEG_INFINITY, f32::max);
+    let mut near = vec![false; count];
+    if far_radius.is_finite() {
+        for entity in spatial.query_sphere(camera_pos, far_radius, ALL_LAYERS) {
+            if let Some(slot) = near.get_mut(entity.0) {
+                *slot = true;
+            }
+        }
+    }
+
+    for i in 0..count {
+        let mut reason = None;
+        if let Some(lifetime) = lifetimes[i].as_mut() {
+            if lifetime.tick(dt) {
+                reason = Some(ExpiryReason::Lifetime);
+            }
+        }
+        if let (Some(despawn), Some(pos)) = (far[i].as_mut(), positions.get(i).and_then(|p| *p)) {
+            let distance = if near[i] {
+                pos.0.distance(camera_pos)
+            } else {
+                f32::INFINITY
+            };
+            if despawn.tick(dt, distance) && reason.is_none() {
+                reason = Some(ExpiryReason::TooFar);
+            }
+        }
+
+        let reason = match fades[i].as_mut() {
+            Some(fade) => {
+                let standalone = lifetimes[i].is_none() && far[i].is_none();
+                if let Some(reason) = reason.or(standalone.then_some(ExpiryReason::Faded)) {
+                    fade.start(reason);
+                }
+                let done = fade.tick(dt);
+                let tint = tints[i].get_or_insert_with(Tint::white);
+                tint.0[3] = fade.alpha();
+                done.then_some(fade.reason)
+            }
+            None => reason,
+        };
+        if let Some(reason) = reason {
+            expired.push((Entity(i), reason));
+            lifetimes[i] = None;
+            far[i] = None;
+            fades[i] = None;
+        }
+    }
+    expired
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STEP: f32 = 0.1;
+
+    /// Lifetime columns of a few entities, all at `position`, stepped together.
+    struct Fixture {
+        lifetimes: Vec<Option<Lifetime>>,
+        far: Vec<Option<DespawnWhenFar>>,
+        fades: Vec<Option<FadeOutThenDespawn>>,
+        tints: Vec<Option<Tint>>,
+        positions: Vec<Option<Position>>,
+        spatial: SpatialGrid,
+    }
+
+    impl Fixture {
+        fn new(count: usize, position: Vec3) -> Self {
+            let positions = vec![Some(Position(position)); count];
+            let mut spatial = SpatialGrid::default();
+            spatial.sync(&positions);
+            Self {
+                lifetimes: vec![None; count],
+                far: vec![None; count],
+                fades: vec![None; count],
+                tints: vec![None; count],
+                positions,
+                spatial,
+            }
+        }
+        fn step(&mut self, camera_pos: Vec3) -> Vec<(Entity, ExpiryReason)> {
+            let columns = LifetimeColumns {
+                lifetimes: &mut self.lifetimes,
+                far: &mut self.far,
+                fades: &mut self.fades,
+                tints: &mut self.tints,
+            };
+            update_lifetimes(STEP, camera_pos, columns, &self.positions, &self.spatial)
+        }
+        fn steps(&mut self, steps: usize) -> Vec<(Entity, ExpiryReason)> {
+            (0..steps).flat_map(|_| self.step(Vec3::ZERO)).collect()
+        }
+        fn alpha(&self, entity: usize) -> Option<f32> {
+            self.tints[entity].map(|tint| tint.0[3])
+        }
+    }
+
+    #[test]
+    fn expired_entities_are_reported_once_across_steps() {
+        let mut world = Fixture::new(3, Vec3::ZERO);
+        world.lifetimes[0] = Some(Lifetime::from_secs(0.15));
+        world.fades[1] = Some(FadeOutThenDespawn::new(Duration::from_millis(150)));
+        // Several fixed steps in one update, with nothing despawned in between.
+        let expired = world.steps(10);
+        assert_eq!(
+            expired,
+            vec![
+                (Entity(0), ExpiryReason::Lifetime),
+                (Entity(1), ExpiryReason::Faded),
+            ]
+        );
+        assert!(world.lifetimes.iter().all(Option::is_none));
+        assert!(world.fades.iter().all(Option::is_none));
+    }
+
+    #[test]
+    fn lifetime_fades_before_expiring() {
+        let mut world = Fixture::new(1, Vec3::ZERO);
+        world.lifetimes[0] = Some(Lifetime::from_secs(0.1));
+        world.fades[0] = Some(FadeOutThenDespawn::new(Duration::from_millis(250)));
+        assert!(world.steps(2).is_empty());
+        assert!(world.fades[0].is_some_and(|fade| fade.started()));
+        assert_eq!(world.steps(10), vec![(Entity(0), ExpiryReason::Lifetime)]);
+    }
+
+    #[test]
+    fn expiries_within_a_step_come_out_in_entity_order() {
+        let mut world = Fixture::new(4, Vec3::ZERO);
+        world.fades[0] = Some(FadeOutThenDespawn::new(Duration::from_millis(50)));
+        world.lifetimes[1] = Some(Lifetime::from_secs(0.05));
+        world.lifetimes[3] = Some(Lifetime::from_secs(0.05));
+        world.fades[3] = Some(FadeOutThenDespawn::new(Duration::ZERO));
+        let expired = world.step(Vec3::ZERO);
+        assert_eq!(
+            expired,
+            vec![
+                (Entity(0), ExpiryReason::Faded),
+                (Entity(1), ExpiryReason::Lifetime),
+                (Entity(3), ExpiryReason::Lifetime),
+            ]
+        );
+        // The fade that finished with its entity leaves a transparent tint, not a
+        // half-faded one for the frame before the despawn lands.
+        assert_eq!(world.alpha(0), Some(0.0));
+        assert_eq!(world.alpha(3), Some(0.0));
+        assert!(world.step(Vec3::ZERO).is_empty());
+    }
+
+    #[test]
+    fn far_despawn_holds_inside_the_hysteresis_band() {
+        let distance = 10.0;
+        let mut world = Fixture::new(1, Vec3::ZERO);
+        world.far[0] = Some(DespawnWhenFar::new(distance).with_linger(Duration::from_millis(250)));
+        let far = Vec3::new(distance + 1.0, 0.0, 0.0);
+        let band = Vec3::new(
+            distance * (1.0 + DespawnWhenFar::HYSTERESIS) / 2.0,
+            0.0,
+            0.0,
+        );
+        let near = Vec3::new(distance * DespawnWhenFar::HYSTERESIS - 1.0, 0.0, 0.0);
+
+        // Far for two steps, then in the band: the timer holds rather than resetting.
+        assert!(world.step(far).is_empty());
+        assert!(world.step(far).is_empty());
+        for _ in 0..10 {
+            assert!(world.step(band).is_empty());
+        }
+        assert_eq!(world.step(far), vec![(Entity(0), ExpiryReason::TooFar)]);
+
+        // Coming back well inside resets it.
+        world.far[0] = Some(DespawnWhenFar::new(distance).with_linger(Duration::from_millis(250)));
+        assert!(world.step(far).is_empty());
+        assert!(world.step(far).is_empty());
+        assert!(world.step(near).is_empty());
+        assert!(world.step(far).is_empty());
+        assert!(world.step(far).is_empty());
+        assert_eq!(world.step(far), vec![(Entity(0), ExpiryReason::TooFar)]);
+    }
+
+    #[test]
+    fn fade_alpha_falls_linearly_to_zero() {
+        let mut world = Fixture::new(1, Vec3::ZERO);
+        world.fades[0] = Some(FadeOutThenDespawn::new(Duration::from_millis(400)));
+        let mut alphas = Vec::new();
+        for _ in 0..3 {
+            assert!(world.step(Vec3::ZERO).is_empty());
+            alphas.push(world.alpha(0).unwrap());
+        }
+        for (alpha, expected) in alphas.iter().zip([0.75, 0.5, 0.25]) {
+            assert!((alpha - expected).abs() < 1e-5, "{:?}", alphas);
+        }
+        assert_eq!(
+            world.step(Vec3::ZERO),
+            vec![(Entity(0), ExpiryReason::Faded)]
+        );
+        assert_eq!(world.alpha(0), Some(0.0));
+    }
+}
@@ -7,5 +7,59 @@ pub use entity::*;
 pub mod world;
 pub use world::*;
 
+pub mod origin;
+pub use origin::*;
+
 pub mod physics;
 pub use physics::*;
+
+pub mod collision;
+pub use collision::*;
+
+pub mod snapshot;
+pub use snapshot::*;
+
+pub mod placement;
+pub use placement::*;
+
+pub mod navigation;
+pub use navigation::*;
+
+pub mod lifetime;
+pub use lifetime::*;
+
+pub mod animator;
+pub use animator::*;
+
+pub mod emitter;
+pub use emitter::*;
+
+pub mod audio_source;
+pub use audio_source::*;
+
+pub mod damage;
+pub use damage::*;
+
+pub mod spatial;
+pub use spatial::*;
+
+pub mod scene;
+pub use scene::*;
+
+pub mod sequence;
+pub use sequence::*;
+
+pub mod script;
+pub use script::*;
+
+pub mod parallel;
+pub use parallel::*;
+
+pub mod schedule;
+pub use schedule::*;
+
+pub mod systems;
+pub use systems::*;
+
+pub mod instance_batcher;
+pub use instance_batcher::*;
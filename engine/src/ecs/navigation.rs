@@ -0,0 +1,630 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
+
+use glam::{IVec3, Vec3};
+
+use crate::{ChunkEvent, Medium, Terrain, CHUNK_SIZE};
+
+use super::{Entity, Rebase};
+
+pub const NAV_WALKABLE: u8 = 1 << 0;
+pub const NAV_WATER: u8 = 1 << 1;
+
+type ChunkPos = (i32, i32, i32);
+
+fn chunk_of(cell: IVec3) -> ChunkPos {
+    let size = CHUNK_SIZE as i32;
+    (
+        cell.x.div_euclid(size),
+        cell.y.div_euclid(size),
+        cell.z.div_euclid(size),
+    )
+}
+
+#[derive(Debug, Clone)]
+struct NavChunk {
+    cells: [[[u8; CHUNK_SIZE]; CHUNK_SIZE]; CHUNK_SIZE],
+}
+
+/// Walkability grid derived from terrain chunks. A cell is walkable when the block
+/// below it is solid (or water, see [`NAV_WATER`]) and it has two blocks of clearance.
+#[derive(Debug, Default)]
+pub struct NavGrid {
+    chunks: HashMap<ChunkPos, NavChunk>,
+}
+
+impl NavGrid {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn rebase(&mut self, rebase: &Rebase) {
+        self.chunks = std::mem::take(&mut self.chunks)
+            .into_iter()
+            .map(|(pos, chunk)| (rebase.chunk(pos), chunk))
+            .collect();
+    }
+
+    pub fn flags(&self, cell: IVec3) -> u8 {
+        let Some(chunk) = self.chunks.get(&chunk_of(cell)) else {
+            return 0;
+        };
+        let size = CHUNK_SIZE as i32;
+        chunk.cells[cell.x.rem_euclid(size) as usize][cell.y.rem_euclid(size) as usize]
+            [cell.z.rem_euclid(size) as usize]
+    }
+
+    pub fn is_walkable(&self, cell: IVec3, water_walkable: bool) -> bool {
+        let flags = self.flags(cell);
+        flags & NAV_WALKABLE != 0 && (water_walkable || flags & NAV_WATER == 0)
+    }
+
+    fn is_air(terrain: &Terrain, cell: IVec3) -> bool {
+        terrain.block_at(cell).map_or(true, |(block, _)| block == 0)
+    }
+
+    /// Recomputes the flags of every cell in the chunk at `pos`.
+    pub fn rebuild_chunk(&mut self, terrain: &Terrain, pos: ChunkPos) {
+        if terrain.get_chunk_stream(pos).is_none() {
+            self.chunks.remove(&pos);
+            return;
+        }
+        let size = CHUNK_SIZE as i32;
+        let origin = IVec3::new(pos.0, pos.1, pos.2) * size;
+        let mut cells = [[[0u8; CHUNK_SIZE]; CHUNK_SIZE]; CHUNK_SIZE];
+
+        for (x, plane) in cells.iter_mut().enumerate() {
+            for (y, row) in plane.iter_mut().enumerate() {
+                for (z, flags) in row.iter_mut().enumerate() {
+                    let cell = origin + IVec3::new(x as i32, y as i32, z as i32);
+                    if !Self::is_air(terrain, cell) || !Self::is_air(terrain, cell + IVec3::Y) {
+                        continue;
+                    }
+                    *flags = match terrain.block_at(cell - IVec3::Y) {
+                        Some((block, Medium::Water)) if block != 0 => NAV_WALKABLE | NAV_WATER,
+                        Some((block, _)) if block != 0 => NAV_WALKABLE,
+                        _ => 0,
+                    };
+                }
+            }
+        }
+        self.chunks.insert(pos, NavChunk { cells });
+    }
+
+    /// Applies pending terrain chunk events. A chunk change also rebuilds its vertical
+    /// neighbours since their edge cells depend on it. Returns every chunk that changed.
+    pub fn sync(&mut self, terrain: &mut Terrain) -> HashSet<ChunkPos> {
+        let mut changed = HashSet::new();
+        for event in terrain.drain_chunk_events() {
+            let pos = match event {
+                ChunkEvent::Loaded(pos) | ChunkEvent::Unloaded(pos) | ChunkEvent::Edited(pos) => {
+                    pos
+                }
+            };
+            changed.insert(pos);
+            changed.insert((pos.0, pos.1 - 1, pos.2));
+            changed.insert((pos.0, pos.1 + 1, pos.2));
+        }
+        for pos in &changed {
+            self.rebuild_chunk(terrain, *pos);
+        }
+        changed
+    }
+
+    /// Finds the walkable cell an entity at `pos` is standing in or above.
+    pub fn snap(&self, pos: Vec3, water_walkable: bool) -> Option<IVec3> {
+        let cell = pos.floor().as_ivec3();
+        (-2..=8)
+            .map(|dy| cell - IVec3::Y * dy)
+            .find(|c| self.is_walkable(*c, water_walkable))
+    }
+
+    fn neighbours(
+        &self,
+        cell: IVec3,
+        water_walkable: bool,
+    ) -> impl Iterator<Item = (IVec3, u32)> + '_ {
+        const DIRS: [IVec3; 4] = [IVec3::X, IVec3::NEG_X, IVec3::Z, IVec3::NEG_Z];
+        DIRS.into_iter().filter_map(move |dir| {
+            [0, 1, -1].into_iter().find_map(|dy| {
+                let next = cell + dir + IVec3::Y * dy;
+                self.is_walkable(next, water_walkable)
+                    .then_some((next, if dy == 0 { 10 } else { 14 }))
+            })
+        })
+    }
+
+    fn heuristic(a: IVec3, b: IVec3) -> u32 {
+        let d = (a - b).abs();
+        (10 * (d.x + d.z) + 4 * d.y) as u32
+    }
+
+    /// A* search between two world positions, expanding at most `node_budget` nodes.
+    /// When the budget runs out or the goal is unreachable, returns a partial path to
+    /// the explored cell closest to the goal.
+    pub fn find_path(
+        &self,
+        start: Vec3,
+        goal: Vec3,
+        water_walkable: bool,
+        node_budget: usize,
+    ) -> Option<NavPath> {
+        let start = self.snap(start, water_walkable)?;
+        let goal_cell = self
+            .snap(goal, water_walkable)
+            .unwrap_or(goal.floor().as_ivec3());
+
+        let mut open = BinaryHeap::new();
+        let mut came_from: HashMap<IVec3, IVec3> = HashMap::new();
+        let mut cost: HashMap<IVec3, u32> = HashMap::new();
+        let mut closed: HashSet<IVec3> = HashSet::new();
+        let mut best = (Self::heuristic(start, goal_cell), start);
+
+        cost.insert(start, 0);
+        open.push(Reverse((best.0, start.to_array())));
+
+        let mut expanded = 0;
+        let mut complete = false;
+        while let Some(Reverse((_, cell))) = open.pop() {
+            let cell = IVec3::from_array(cell);
+            if !closed.insert(cell) {
+                continue;
+            }
+            if cell == goal_cell {
+                best = (0, cell);
+                complete = true;
+                break;
+            }
+            let h = Self::heuristic(cell, goal_cell);
+            if h < best.0 {
+                best = (h, cell);
+            }
+            expanded += 1;
+            if expanded >= node_budget {
+                break;
+            }
+            let g = cost[&cell];
+            for (next, step) in self.neighbours(cell, water_walkable) {
+                let next_g = g + step;
+                if cost.get(&next).map_or(true, |&c| next_g < c) {
+                    cost.insert(next, next_g);
+                    came_from.insert(next, cell);
+                    open.push(Reverse((
+                        next_g + Self::heuristic(next, goal_cell),
+                        next.to_array(),
+                    )));
+                }
+            }
+        }
+
+        let mut cells = vec![best.1];
+        while let Some(prev) = came_from.get(cells.last().unwrap()) {
+            cells.push(*prev);
+        }
+        cells.reverse();
+        Some(NavPath::new(cells, complete))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct NavPath {
+    pub cells: Vec<IVec3>,
+    pub waypoints: Vec<Vec3>,
+    pub complete: bool,
+    chunks: HashSet<ChunkPos>,
+}
+
+impl NavPath {
+    pub fn new(cells: Vec<IVec3>, complete: bool) -> Self {
+        let waypoints = cells
+            .iter()
+            .map(|c| c.as_vec3() + Vec3::new(0.5, 0.0, 0.5))
+            .collect();
+        let chunks = cells.iter().map(|c| chunk_of(*c)).collect();
+        Self {
+            cells,
+            waypoints,
+            complete,
+            chunks,
+        }
+    }
+    pub fn crosses(&self, chunk: &ChunkPos) -> bool {
+        self.chunks.contains(chunk)
+    }
+    fn rebase(&mut self, rebase: &Rebase) {
+        self.cells.iter_mut().for_each(|c| *c = rebase.cell(*c));
+        self.waypoints
+            .iter_mut()
+            .for_each(|w| *w = rebase.position(*w));
+        self.chunks = self.chunks.iter().map(|c| rebase.chunk(*c)).collect();
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub enum NavTarget {
+    Position(Vec3),
+    Entity(Entity),
+}
+
+/// Moves an entity along A* paths toward a target by writing its horizontal velocity.
+#[derive(Debug, Clone)]
+pub struct NavAgent {
+    pub target: NavTarget,
+    pub speed: f32,
+    /// Minimum seconds between repaths for a moving target.
+    pub repath_interval: f32,
+    /// How far the target may move from the last path goal before a repath.
+    pub repath_distance: f32,
+    pub arrival_radius: f32,
+    pub water_walkable: bool,
+    path: Option<NavPath>,
+    waypoint: usize,
+    path_goal: Option<Vec3>,
+    since_repath: f32,
+    pending: bool,
+}
+
+impl NavAgent {
+    pub fn new(target: NavTarget, speed: f32) -> Self {
+        Self {
+            target,
+            speed,
+            repath_interval: 0.5,
+            repath_distance: 1.5,
+            arrival_radius: 1.0,
+            water_walkable: false,
+            path: None,
+            waypoint: 0,
+            path_goal: None,
+            since_repath: 0.0,
+            pending: false,
+        }
+    }
+    pub fn path(&self) -> Option<&NavPath> {
+        self.path.as_ref()
+    }
+    pub fn invalidate(&mut self) {
+        self.path = None;
+        self.since_repath = self.repath_interval;
+    }
+    pub fn rebase(&mut self, rebase: &Rebase) {
+        if let NavTarget::Position(p) = &mut self.target {
+            *p = rebase.position(*p);
+        }
+        if let Some(path) = &mut self.path {
+            path.rebase(rebase);
+        }
+        self.path_goal = self.path_goal.map(|g| rebase.position(g));
+    }
+
+    fn set_path(&mut self, path: Option<NavPath>) {
+        self.pending = false;
+        self.waypoint = 1.min(
+            path.as_ref()
+                .map_or(0, |p| p.waypoints.len().saturating_sub(1)),
+        );
+        self.path = path;
+    }
+
+    fn needs_repath(&self, goal: Vec3) -> bool {
+        if self.pending {
+            return false;
+        }
+        match self.path_goal {
+            None => true,
+            _ if self.path.is_none() => self.since_repath >= self.repath_interval,
+            Some(last) => {
+                self.since_repath >= self.repath_interval
+                    && last.distance(goal) > self.repath_distance
+            }
+        }
+    }
+
+    /// Horizontal velocity toward the next waypoint, advancing along the path.
+    fn steer(&mut self, position: Vec3, goal: Vec3) -> Vec3 {
+        let flat = |v: Vec3| Vec3::new(v.x, 0.0, v.z);
+        if flat(goal - position).length() <= self.arrival_radius {
+            return Vec3::ZERO;
+        }
+        let Some(path) = &self.path else {
+            return Vec3::ZERO;
+        };
+        while let Some(waypoint) = path.waypoints.get(self.waypoint) {
+            let to = flat(*waypoint - position);
+            if to.length() > 0.25 || self.waypoint + 1 >= path.waypoints.len() {
+                return to.normalize_or_zero() * self.speed;
+            }
+            self.waypoint += 1;
+        }
+        Vec3::ZERO
+    }
+}
+
+#[derive(Debug)]
+struct PathRequest {
+    entity: Entity,
+    start: Vec3,
+    goal: Vec3,
+    water_walkable: bool,
+}
+
+/// Owns the nav grid and a budgeted path request queue; results are delivered
+/// to agents on the following update.
+#[derive(Debug)]
+pub struct Navigation {
+    pub grid: NavGrid,
+    pub requests_per_frame: usize,
+    pub node_budget: usize,
+    requests: VecDeque<PathRequest>,
+    results: Vec<(Entity, Option<NavPath>)>,
+}
+
+impl Default for Navigation {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Navigation {
+    pub fn new() -> Self {
+        Self {
+            grid: NavGrid::new(),
+            requests_per_frame: 4,
+            node_budget: 2048,
+            requests: VecDeque::new(),
+            results: Vec::new(),
+        }
+    }
+    /// Agents rebase themselves.
+    pub fn rebase(&mut self, rebase: &Rebase) {
+        self.grid.rebase(rebase);
+        for request in &mut self.requests {
+            request.start = rebase.position(request.start);
+            request.goal = rebase.position(request.goal);
+        }
+        for path in self.results.iter_mut().filter_map(|(_, p)| p.as_mut()) {
+            path.rebase(rebase);
+        }
+    }
+
+    /// One navigation tick: syncs the grid, delivers last frame's paths, steers agents
+    /// and services up to `requests_per_frame` new path requests.
+    pub fn update(
+        &mut self,
+        dt: f32,
+        terrain: &mut Terrain,
+        agents: &mut [Option<NavAgent>],
+        positions: &[Option<super::Position>],
+        velocities: &mut [Option<super::Velocity>],
+    ) {
+        let changed = self.grid.sync(terrain);
+
+        for (entity, path) in self.results.drain(..) {
+            if let Some(Some(agent)) = agents.get_mut(entity.0) {
+                agent.set_path(path);
+            }
+        }
+
+        for (idx, agent) in agents.iter_mut().enumerate() {
+            let Some(agent) = agent else {
+                continue;
+            };
+            let Some(Some(position)) = positions.get(idx) else {
+                continue;
+            };
+            let goal = match agent.target {
+                NavTarget::Position(p) => p,
+                NavTarget::Entity(e) => match positions.get(e.0) {
+                    Some(Some(p)) => p.0,
+                    _ => continue,
+                },
+            };
+
+            agent.since_repath += dt;
+            if agent
+                .path
+                .as_ref()
+                .is_some_and(|p| changed.iter().any(|c| p.crosses(c)))
+            {
+                agent.invalidate();
+            }
+            if agent.needs_repath(goal) {
+                agent.pending = true;
+                agent.since_repath = 0.0;
+                agent.path_goal = Some(goal);
+                self.requests.push_back(PathRequest {
+                    entity: Entity(idx),
+                    start: position.0,
+                    goal,
+                    water_walkable: agent.water_walkable,
+                });
+            }
+
+            let steer = agent.steer(position.0, goal);
+            if let Some(velocity) = velocities.get_mut(idx) {
+                let y = velocity.map_or(0.0, |v| v.0.y);
+                *velocity = Some(super::Velocity(Vec3::new(steer.x, y, steer.z)));
+            }
+        }
+
+        for _ in 0..self.requests_per_frame {
+            let Some(request) = self.requests.pop_front() else {
+                break;
+            };
+            let path = self.grid.find_path(
+                request.start,
+                request.goal,
+                request.water_walkable,
+                self.node_budget,
+            );
+            self.results.push((request.entity, path));
+        }
+    }
+
+    /// Line segments of every active agent path, for debug drawing.
+    pub fn debug_lines(&self, agents: &[Option<NavAgent>]) -> Vec<[Vec3; 2]> {
+        agents
+            .iter()
+            .flatten()
+            .filter_map(|a| a.path.as_ref())
+            .flat_map(|p| p.waypoints.windows(2).map(|w| [w[0], w[1]]))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::{Position, Velocity};
+    use crate::{Chunk, AIR, STONE};
+
+    /// Terrain of the given chunks, empty but for `solid`.
+    fn terrain(chunks: &[(ChunkPos, Medium)], solid: &[IVec3]) -> Terrain {
+        let size = CHUNK_SIZE as i32;
+        let mut terrain = Terrain::new(Medium::Air);
+        for &(pos, medium) in chunks {
+            let mut chunk = Chunk::new(pos);
+            chunk.blocks = [[[AIR; CHUNK_SIZE]; CHUNK_SIZE]; CHUNK_SIZE];
+            for cell in solid.iter().filter(|c| chunk_of(**c) == pos) {
+                let local = cell.rem_euclid(IVec3::splat(size));
+                chunk.blocks[local.x as usize][local.y as usize][local.z as usize] = STONE;
+            }
+            terrain.insert_chunk_stream(chunk, medium);
+        }
+        terrain
+    }
+
+    fn floor(y: i32, size: i32) -> Vec<IVec3> {
+        (0..size)
+            .flat_map(|x| (0..size).map(move |z| IVec3::new(x, y, z)))
+            .collect()
+    }
+
+    fn grid(terrain: &mut Terrain) -> NavGrid {
+        let mut grid = NavGrid::new();
+        grid.sync(terrain);
+        grid
+    }
+
+    fn centre(cell: IVec3) -> Vec3 {
+        cell.as_vec3() + Vec3::new(0.5, 0.0, 0.5)
+    }
+
+    #[test]
+    fn walkability_needs_ground_and_headroom() {
+        let mut solid = floor(0, 4);
+        // A ledge one block up and a ceiling leaving a single block of room.
+        solid.push(IVec3::new(3, 1, 3));
+        solid.push(IVec3::new(2, 2, 2));
+        solid.extend(floor(0, 4).into_iter().map(|c| c + IVec3::X * 4));
+        let mut terrain = terrain(
+            &[((0, 0, 0), Medium::Air), ((1, 0, 0), Medium::Water)],
+            &solid,
+        );
+        let grid = grid(&mut terrain);
+
+        assert_eq!(grid.flags(IVec3::new(1, 1, 1)), NAV_WALKABLE);
+        assert_eq!(grid.flags(IVec3::new(1, 2, 1)), 0, "nothing to stand on");
+        assert_eq!(grid.flags(IVec3::new(2, 1, 2)), 0, "ceiling too low");
+        assert_eq!(grid.flags(IVec3::new(3, 1, 3)), 0, "inside the ledge");
+        assert_eq!(
+            grid.flags(IVec3::new(3, 2, 3)),
+            NAV_WALKABLE,
+            "on the ledge"
+        );
+
+        let water = IVec3::new(5, 1, 1);
+        assert_eq!(grid.flags(water), NAV_WALKABLE | NAV_WATER);
+        assert!(!grid.is_walkable(water, false));
+        assert!(grid.is_walkable(water, true));
+    }
+
+    #[test]
+    fn paths_are_as_short_as_a_breadth_first_search() {
+        let chunks = [(0, 0, 0), (1, 0, 0), (0, 0, 1), (1, 0, 1)];
+        let mut solid = floor(0, 8);
+        // A wall two blocks high across x = 3 with a gap at the far end.
+        for z in 0..6 {
+            solid.push(IVec3::new(3, 1, z));
+            solid.push(IVec3::new(3, 2, z));
+        }
+        let mut terrain = terrain(&chunks.map(|c| (c, Medium::Air)), &solid);
+        let grid = grid(&mut terrain);
+
+        let (start, goal) = (IVec3::new(1, 1, 1), IVec3::new(5, 1, 1));
+        let path = grid
+            .find_path(centre(start), centre(goal), false, 4096)
+            .unwrap();
+        assert!(path.complete);
+        assert_eq!(path.cells.first(), Some(&start));
+        assert_eq!(path.cells.last(), Some(&goal));
+        for step in path.cells.windows(2) {
+            assert_eq!((step[1] - step[0]).abs().element_sum(), 1);
+            assert!(grid.is_walkable(step[1], false));
+        }
+
+        let mut distance = HashMap::from([(start, 0)]);
+        let mut queue = VecDeque::from([start]);
+        while let Some(cell) = queue.pop_front() {
+            for dir in [IVec3::X, IVec3::NEG_X, IVec3::Z, IVec3::NEG_Z] {
+                let next = cell + dir;
+                if grid.is_walkable(next, false) && !distance.contains_key(&next) {
+                    distance.insert(next, distance[&cell] + 1);
+                    queue.push_back(next);
+                }
+            }
+        }
+        assert_eq!(path.cells.len() - 1, distance[&goal]);
+    }
+
+    #[test]
+    fn edits_rebuild_the_chunk_and_the_one_above() {
+        let mut terrain = terrain(
+            &[((0, 0, 0), Medium::Air), ((0, 1, 0), Medium::Air)],
+            &floor(3, 4),
+        );
+        let mut grid = grid(&mut terrain);
+        let above = IVec3::new(1, 4, 1);
+        assert!(grid.is_walkable(above, false));
+
+        terrain.set_block(IVec3::new(1, 3, 1), AIR);
+        assert!(grid.is_walkable(above, false), "stale until synced");
+        let changed = grid.sync(&mut terrain);
+        assert!(changed.contains(&(0, 0, 0)) && changed.contains(&(0, 1, 0)));
+        assert!(!grid.is_walkable(above, false));
+        assert!(grid.is_walkable(IVec3::new(2, 4, 1), false));
+    }
+
+    #[test]
+    fn edits_on_a_path_trigger_a_repath() {
+        let mut solid = floor(0, 8);
+        solid.retain(|c| c.z < 4);
+        let mut terrain = terrain(
+            &[((0, 0, 0), Medium::Air), ((1, 0, 0), Medium::Air)],
+            &solid,
+        );
+        let mut navigation = Navigation::new();
+        let goal = centre(IVec3::new(7, 1, 1));
+        let mut agents = vec![Some(NavAgent::new(NavTarget::Position(goal), 2.0))];
+        let positions = vec![Some(Position(centre(IVec3::new(0, 1, 1))))];
+        let mut velocities = vec![None::<Velocity>];
+        let mut tick = |terrain: &mut Terrain, agents: &mut Vec<Option<NavAgent>>| {
+            navigation.update(0.1, terrain, agents, &positions, &mut velocities);
+        };
+
+        tick(&mut terrain, &mut agents);
+        tick(&mut terrain, &mut agents);
+        let path = agents[0].as_ref().unwrap().path().unwrap().clone();
+        assert!(path.complete);
+
+        let blocked = path.cells[3];
+        terrain.set_block(blocked, STONE);
+        terrain.set_block(blocked + IVec3::Y, STONE);
+        tick(&mut terrain, &mut agents);
+        assert!(agents[0].as_ref().unwrap().path().is_none());
+
+        tick(&mut terrain, &mut agents);
+        let repath = agents[0].as_ref().unwrap().path().unwrap();
+        assert!(repath.complete);
+        assert!(!repath.cells.contains(&blocked));
+    }
+}
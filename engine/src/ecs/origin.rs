@@ -0,0 +1,173 @@
+use glam::{DVec3, I64Vec3, IVec3, Vec3};
+
+use crate::CHUNK_SIZE;
+
+/// `global = local + origin`. Moves in whole chunks so block coordinates stay integers.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
+pub struct WorldOrigin {
+    chunk: I64Vec3,
+}
+
+impl WorldOrigin {
+    pub fn from_chunk(chunk: I64Vec3) -> Self {
+        Self { chunk }
+    }
+    pub fn chunk(&self) -> I64Vec3 {
+        self.chunk
+    }
+    pub fn block(&self) -> I64Vec3 {
+        self.chunk * CHUNK_SIZE as i64
+    }
+    pub fn to_global(&self, local: Vec3) -> DVec3 {
+        local.as_dvec3() + self.block().as_dvec3()
+    }
+    pub fn to_local(&self, global: DVec3) -> Vec3 {
+        (global - self.block().as_dvec3()).as_vec3()
+    }
+    pub fn block_to_global(&self, local: IVec3) -> I64Vec3 {
+        local.as_i64vec3() + self.block()
+    }
+    pub fn block_to_local(&self, global: I64Vec3) -> IVec3 {
+        (global - self.block()).as_ivec3()
+    }
+    fn shifted(self, chunks: IVec3) -> Self {
+        Self {
+            chunk: self.chunk + chunks.as_i64vec3(),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Rebase {
+    pub chunks: IVec3,
+    pub origin: WorldOrigin,
+}
+
+impl Rebase {
+    pub fn new(previous: WorldOrigin, chunks: IVec3) -> Self {
+        Self {
+            chunks,
+            origin: previous.shifted(chunks),
+        }
+    }
+    pub fn blocks(&self) -> IVec3 {
+        self.chunks * CHUNK_SIZE as i32
+    }
+    pub fn offset(&self) -> Vec3 {
+        self.blocks().as_vec3()
+    }
+    pub fn position(&self, local: Vec3) -> Vec3 {
+        local - self.offset()
+    }
+    pub fn cell(&self, local: IVec3) -> IVec3 {
+        local - self.blocks()
+    }
+    pub fn chunk(&self, local: (i32, i32, i32)) -> (i32, i32, i32) {
+        (
+            local.0 - self.chunks.x,
+            local.1 - self.chunks.y,
+            local.2 - self.chunks.z,
+        )
+    }
+}
+
+pub type RebaseHook = Box<dyn FnMut(&Rebase) + Send + Sync>;
+
+#[derive(Default)]
+pub struct RebaseHooks(Vec<RebaseHook>);
+
+impl RebaseHooks {
+    pub fn push(&mut self, hook: RebaseHook) {
+        self.0.push(hook);
+    }
+    pub fn run(&mut self, rebase: &Rebase) {
+        self.0.iter_mut().for_each(|hook| hook(rebase));
+    }
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl std::fmt::Debug for RebaseHooks {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "RebaseHooks({})", self.0.len())
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RebasePolicy {
+    /// In blocks; `None` pins the origin.
+    pub threshold: Option<f32>,
+}
+
+impl Default for RebasePolicy {
+    fn default() -> Self {
+        Self {
+            threshold: Some(Self::DEFAULT_THRESHOLD),
+        }
+    }
+}
+
+impl RebasePolicy {
+    pub const DEFAULT_THRESHOLD: f32 = 2048.0;
+
+    pub fn pinned() -> Self {
+        Self { threshold: None }
+    }
+    // Kept above two chunks so recentering can't flip back and forth.
+    pub fn shift_for(&self, focus: Vec3) -> Option<IVec3> {
+        let threshold = self.threshold?.max(2.0 * CHUNK_SIZE as f32);
+        if focus.length() <= threshold {
+            return None;
+        }
+        let chunks = (focus / CHUNK_SIZE as f32).floor().as_ivec3();
+        (chunks != IVec3::ZERO).then_some(chunks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rebasing_keeps_global_positions() {
+        let origin = WorldOrigin::from_chunk(I64Vec3::new(-2, 0, 7));
+        let local = Vec3::new(2500.25, 3.5, -1800.75);
+        let global = origin.to_global(local);
+        let block = IVec3::new(625, 1, -450);
+        let global_block = origin.block_to_global(block);
+
+        let policy = RebasePolicy::default();
+        let chunks = policy.shift_for(local).unwrap();
+        let rebase = Rebase::new(origin, chunks);
+        let moved = rebase.position(local);
+        assert!(moved.length() < 2.0 * CHUNK_SIZE as f32);
+        assert_eq!(rebase.origin.to_global(moved), global);
+        assert_eq!(
+            rebase.origin.block_to_global(rebase.cell(block)),
+            global_block
+        );
+        assert_eq!(policy.shift_for(moved), None);
+
+        // And back again.
+        let back = Rebase::new(rebase.origin, -chunks);
+        assert_eq!(back.origin, origin);
+        assert_eq!(back.position(moved), local);
+        assert_eq!(back.cell(rebase.cell(block)), block);
+        let chunk = (3, -1, 4);
+        assert_eq!(back.chunk(rebase.chunk(chunk)), chunk);
+    }
+
+    #[test]
+    fn pinned_origin_never_moves() {
+        assert_eq!(RebasePolicy::pinned().shift_for(Vec3::splat(1e6)), None);
+        // Thresholds under two chunks are raised, so a rebase always leaves room to move.
+        let tight = RebasePolicy {
+            threshold: Some(0.0),
+        };
+        assert_eq!(tight.shift_for(Vec3::X * CHUNK_SIZE as f32), None);
+    }
+}
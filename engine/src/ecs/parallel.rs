@@ -0,0 +1,89 @@
+//! Chunked loops over component columns, parallel or serial. Chunks are fixed ranges of
+//! entity ids and per-chunk results are joined in chunk order, so a parallel run produces
+//! exactly what the serial one does, whatever the thread count or finishing order.
+
+use rayon::prelude::*;
+
+/// Entities per chunk. Fixed rather than derived from the thread count so the split is the
+/// same on every machine.
+pub const ENTITY_CHUNK: usize = 1024;
+
+/// Runs `f(first_entity, chunk)` over `ENTITY_CHUNK` chunks of `column` in parallel and
+/// concatenates what each chunk returns, in entity order.
+pub fn par_chunks<A, R, F>(column: &mut [A], f: F) -> Vec<R>
+where
+    A: Send,
+    R: Send,
+    F: Fn(usize, &mut [A]) -> Vec<R> + Sync,
+{
+    column
+        .par_chunks_mut(ENTITY_CHUNK)
+        .enumerate()
+        .flat_map_iter(|(i, chunk)| f(i * ENTITY_CHUNK, chunk))
+        .collect()
+}
+
+/// [`par_chunks`] over two columns zipped by entity; both are cut to the shorter one.
+pub fn par_chunks2<A, B, R, F>(a: &mut [A], b: &mut [B], f: F) -> Vec<R>
+where
+    A: Send,
+    B: Send,
+    R: Send,
+    F: Fn(usize, &mut [A], &mut [B]) -> Vec<R> + Sync,
+{
+    let len = a.len().min(b.len());
+    a[..len]
+        .par_chunks_mut(ENTITY_CHUNK)
+        .zip(b[..len].par_chunks_mut(ENTITY_CHUNK))
+        .enumerate()
+        .flat_map_iter(|(i, (a, b))| f(i * ENTITY_CHUNK, a, b))
+        .collect()
+}
+
+/// Serial reference for [`par_chunks2`]: same chunks, same order, one thread.
+pub fn chunks2<A, B, R, F>(a: &mut [A], b: &mut [B], f: F) -> Vec<R>
+where
+    F: Fn(usize, &mut [A], &mut [B]) -> Vec<R>,
+{
+    let len = a.len().min(b.len());
+    a[..len]
+        .chunks_mut(ENTITY_CHUNK)
+        .zip(b[..len].chunks_mut(ENTITY_CHUNK))
+        .enumerate()
+        .flat_map(|(i, (a, b))| f(i * ENTITY_CHUNK, a, b))
+        .collect()
+}
+
+/// [`par_chunks2`] over three columns zipped by entity; all are cut to the shortest one.
+pub fn par_chunks3<A, B, C, R, F>(a: &mut [A], b: &mut [B], c: &mut [C], f: F) -> Vec<R>
+where
+    A: Send,
+    B: Send,
+    C: Send,
+    R: Send,
+    F: Fn(usize, &mut [A], &mut [B], &mut [C]) -> Vec<R> + Sync,
+{
+    let len = a.len().min(b.len()).min(c.len());
+    a[..len]
+        .par_chunks_mut(ENTITY_CHUNK)
+        .zip(b[..len].par_chunks_mut(ENTITY_CHUNK))
+        .zip(c[..len].par_chunks_mut(ENTITY_CHUNK))
+        .enumerate()
+        .flat_map_iter(|(i, ((a, b), c))| f(i * ENTITY_CHUNK, a, b, c))
+        .collect()
+}
+
+/// Serial reference for [`par_chunks3`].
+pub fn chunks3<A, B, C, R, F>(a: &mut [A], b: &mut [B], c: &mut [C], f: F) -> Vec<R>
+where
+    F: Fn(usize, &mut [A], &mut [B], &mut [C]) -> Vec<R>,
+{
+    let len = a.len().min(b.len()).min(c.len());
+    a[..len]
+        .chunks_mut(ENTITY_CHUNK)
+        .zip(b[..len].chunks_mut(ENTITY_CHUNK))
+        .zip(c[..len].chunks_mut(ENTITY_CHUNK))
+        .enumerate()
+        .flat_map(|(i, ((a, b), c))| f(i * ENTITY_CHUNK, a, b, c))
+        .collect()
+}
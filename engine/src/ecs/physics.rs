@@ -1,19 +1,24 @@
 use glam::Vec3;
 
-use crate::{camera::Camera, Medium, Terrain};
+use crate::{camera::Camera, Medium, MediumProperties, Terrain};
 
-use super::{Entity, Position, Velocity};
+use super::{chunks3, par_chunks3, sweep, Collider, Entity, Position, RemoteTransform, Velocity};
 
 pub const GROUND_Y: f32 = 0.0;
 
 pub const GRAVITY: f32 = -9.81;
 
+/// Lowest an entity without a [`Collider`] goes.
 pub const ENTITY_MIN_Y: f32 = GROUND_Y + 2.0;
 
+/// Downward speed above which hitting the ground is reported as a landing.
+pub const HARD_LANDING_SPEED: f32 = 8.0;
+
 #[derive(Debug)]
 pub struct Physics {
     pub positions: Vec<Option<Position>>,
     pub velocities: Vec<Option<Velocity>>,
+    pub colliders: Vec<Option<Collider>>,
 }
 
 impl Physics {
@@ -21,17 +26,22 @@ impl Physics {
         Self {
             positions: Vec::new(),
             velocities: Vec::new(),
+            colliders: Vec::new(),
         }
     }
 
     fn resize(&mut self, size: usize) {
         self.positions.resize(size, None);
         self.velocities.resize(size, None);
+        self.colliders.resize(size, None);
     }
 
     fn ensure_capacity(&mut self, idx: usize) {
         let needed = idx + 1;
-        if self.positions.len() < needed || self.velocities.len() < needed {
+        if self.positions.len() < needed
+            || self.velocities.len() < needed
+            || self.colliders.len() < needed
+        {
             self.resize(needed);
         }
     }
@@ -46,10 +56,43 @@ impl Physics {
         self.velocities[entity.0] = Some(vel);
     }
 
-    /// Physics tick: updates positions/velocities
-    pub fn update(&mut self, camera: &Camera, dt: f32, terrain: &Terrain) {
-        let camera_pos = *camera.eye();
+    pub fn insert_collider(&mut self, entity: Entity, collider: Collider) {
+        self.ensure_capacity(entity.0);
+        self.colliders[entity.0] = Some(collider);
+    }
+    /// Whether `entity` stood on terrain after the last step; `None` without a [`Collider`].
+    pub fn grounded(&self, entity: Entity) -> Option<bool> {
+        self.colliders
+            .get(entity.0)
+            .and_then(|c| c.as_ref())
+            .map(|c| c.grounded)
+    }
 
+    /// Physics tick: updates positions/velocities.
+    /// Entities with a remote transform are driven by snapshots and skipped here.
+    /// Returns entities that hit the ground faster than [`HARD_LANDING_SPEED`], with their speed.
+    pub fn update(
+        &mut self,
+        camera: &Camera,
+        dt: f32,
+        terrain: &Terrain,
+        remote: &[Option<RemoteTransform>],
+    ) -> Vec<(Entity, f32)> {
+        let medium = Physics::medium(camera, terrain);
+        integrate(
+            &mut self.positions,
+            &mut self.velocities,
+            &mut self.colliders,
+            remote,
+            terrain,
+            medium,
+            dt,
+        )
+    }
+
+    /// Medium entities integrate in this tick, from where the camera is.
+    pub fn medium(camera: &Camera, terrain: &Terrain) -> MediumProperties {
+        let camera_pos = *camera.eye();
         let medium = if camera.free_look() || camera_pos.y > GROUND_Y + 4.0 {
             Medium::Air
         } else {
@@ -60,32 +103,227 @@ impl Physics {
             };
             terrain.medium_at(pos)
         };
-        let medium_props = medium.properties();
+        medium.properties()
+    }
+}
+
+/// Integrates every entity with a position and velocity, in parallel over entity chunks.
+/// Entities with a [`Collider`] move through `terrain` by [`sweep`]; the rest stop at
+/// [`ENTITY_MIN_Y`]. Landings come back in entity order, identical to [`integrate_serial`].
+pub fn integrate(
+    positions: &mut [Option<Position>],
+    velocities: &mut [Option<Velocity>],
+    colliders: &mut [Option<Collider>],
+    remote: &[Option<RemoteTransform>],
+    terrain: &Terrain,
+    medium: MediumProperties,
+    dt: f32,
+) -> Vec<(Entity, f32)> {
+    par_chunks3(
+        positions,
+        velocities,
+        colliders,
+        |first, positions, velocities, colliders| {
+            let bodies = Bodies {
+                positions,
+                velocities,
+                colliders,
+            };
+            integrate_chunk(first, bodies, remote, terrain, medium, dt)
+        },
+    )
+}
+
+/// Single-threaded reference for [`integrate`].
+pub fn integrate_serial(
+    positions: &mut [Option<Position>],
+    velocities: &mut [Option<Velocity>],
+    colliders: &mut [Option<Collider>],
+    remote: &[Option<RemoteTransform>],
+    terrain: &Terrain,
+    medium: MediumProperties,
+    dt: f32,
+) -> Vec<(Entity, f32)> {
+    chunks3(
+        positions,
+        velocities,
+        colliders,
+        |first, positions, velocities, colliders| {
+            let bodies = Bodies {
+                positions,
+                velocities,
+                colliders,
+            };
+            integrate_chunk(first, bodies, remote, terrain, medium, dt)
+        },
+    )
+}
+
+/// One entity chunk of the columns [`integrate`] moves.
+struct Bodies<'a> {
+    positions: &'a mut [Option<Position>],
+    velocities: &'a mut [Option<Velocity>],
+    colliders: &'a mut [Option<Collider>],
+}
+
+fn integrate_chunk(
+    first: usize,
+    bodies: Bodies,
+    remote: &[Option<RemoteTransform>],
+    terrain: &Terrain,
+    medium: MediumProperties,
+    dt: f32,
+) -> Vec<(Entity, f32)> {
+    let mut landings = Vec::new();
+    let drag_factor = medium.drag.powf(dt);
+    let max_fall_speed = -50.0;
+
+    let entities = bodies
+        .positions
+        .iter_mut()
+        .zip(bodies.velocities)
+        .zip(bodies.colliders);
+    for (offset, ((pos_opt, vel_opt), collider)) in entities.enumerate() {
+        let idx = first + offset;
+        if matches!(remote.get(idx), Some(Some(_))) {
+            continue;
+        }
+        if let (Some(pos), Some(vel)) = (pos_opt, vel_opt) {
+            vel.0.x *= drag_factor;
+            vel.0.z *= drag_factor;
+            if vel.0.x.abs() < 0.01 {
+                vel.0.x = 0.0;
+            }
+            if vel.0.z.abs() < 0.01 {
+                vel.0.z = 0.0;
+            }
+            vel.0.y += medium.gravity.y * dt;
+            vel.0.y = vel.0.y.max(max_fall_speed);
 
-        let drag_factor = medium_props.drag.powf(dt);
-        let max_fall_speed = -50.0;
+            if let Some(collider) = collider {
+                let landed = sweep(terrain, collider, &mut pos.0, &mut vel.0, dt);
+                if let Some(speed) = landed.filter(|speed| *speed > HARD_LANDING_SPEED) {
+                    landings.push((Entity(idx), speed));
+                }
+                continue;
+            }
+            pos.0 += vel.0 * dt;
 
-        for (pos_opt, vel_opt) in self.positions.iter_mut().zip(&mut self.velocities) {
-            if let (Some(pos), Some(vel)) = (pos_opt, vel_opt) {
-                vel.0.x *= drag_factor;
-                vel.0.z *= drag_factor;
-                if vel.0.x.abs() < 0.01 {
-                    vel.0.x = 0.0;
+            if pos.0.y < ENTITY_MIN_Y {
+                pos.0.y = ENTITY_MIN_Y;
+                if vel.0.y < -HARD_LANDING_SPEED {
+                    landings.push((Entity(idx), -vel.0.y));
                 }
-                if vel.0.z.abs() < 0.01 {
-                    vel.0.z = 0.0;
+                if vel.0.y < 0.0 {
+                    vel.0.y = 0.0;
                 }
-                vel.0.y += medium_props.gravity.y * dt;
-                vel.0.y = vel.0.y.max(max_fall_speed);
-                pos.0 += vel.0 * dt;
-
-                if pos.0.y < ENTITY_MIN_Y {
-                    pos.0.y = ENTITY_MIN_Y;
-                    if vel.0.y < 0.0 {
-                        vel.0.y = 0.0;
+            }
+        }
+    }
+    landings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Chunk, AIR, CHUNK_SIZE, ENTITY_CHUNK, STONE};
+
+    /// Deterministic pseudo-random numbers in [0, 1).
+    fn sequence(mut seed: u32) -> impl FnMut() -> f32 {
+        move || {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            (seed >> 8) as f32 / (1 << 24) as f32
+        }
+    }
+
+    /// Uneven stone floors over a 4x4 grid of chunks.
+    fn terrain(next: &mut impl FnMut() -> f32) -> Terrain {
+        let mut terrain = Terrain::new(Medium::Air);
+        for pos in (0..16).map(|i| (i % 4, 0, i / 4)) {
+            let mut chunk = Chunk::new(pos);
+            chunk.blocks = [[[AIR; CHUNK_SIZE]; CHUNK_SIZE]; CHUNK_SIZE];
+            for x in 0..CHUNK_SIZE {
+                for z in 0..CHUNK_SIZE {
+                    for y in 0..1 + (next() * 3.0) as usize {
+                        chunk.blocks[x][y][z] = STONE;
                     }
                 }
             }
+            terrain.insert_chunk_stream(chunk, Medium::Ground);
+        }
+        terrain
+    }
+
+    #[test]
+    fn parallel_integration_matches_the_serial_reference() {
+        let mut next = sequence(0x2545_f491);
+        let terrain = terrain(&mut next);
+        let count = ENTITY_CHUNK * 3 + 17;
+        let mut physics = Physics::new();
+        let mut remote = Vec::with_capacity(count);
+        for _ in 0..count {
+            let extent = CHUNK_SIZE as f32 * 4.0;
+            let position = Vec3::new(next() * extent, 4.0 + next() * 30.0, next() * extent);
+            let velocity = (Vec3::new(next(), next(), next()) - 0.5) * 40.0;
+            let collider = (next() < 0.7).then(|| Collider::new(Vec3::splat(0.1 + next() * 0.4)));
+            physics
+                .positions
+                .push((next() < 0.95).then_some(Position(position)));
+            physics.velocities.push(Some(Velocity(velocity)));
+            physics.colliders.push(collider);
+            remote.push((next() < 0.05).then(RemoteTransform::default));
         }
+        let mut serial = Physics {
+            positions: physics.positions.clone(),
+            velocities: physics.velocities.clone(),
+            colliders: physics.colliders.clone(),
+        };
+
+        let medium = Medium::Air.properties();
+        for _ in 0..120 {
+            let parallel = integrate(
+                &mut physics.positions,
+                &mut physics.velocities,
+                &mut physics.colliders,
+                &remote,
+                &terrain,
+                medium,
+                1.0 / 60.0,
+            );
+            let reference = integrate_serial(
+                &mut serial.positions,
+                &mut serial.velocities,
+                &mut serial.colliders,
+                &remote,
+                &terrain,
+                medium,
+                1.0 / 60.0,
+            );
+            assert_eq!(parallel, reference);
+        }
+        let bits = |physics: &Physics| -> Vec<Option<[u32; 6]>> {
+            physics
+                .positions
+                .iter()
+                .zip(&physics.velocities)
+                .map(|(p, v)| {
+                    let (p, v) = (p.as_ref()?.0, v.as_ref()?.0);
+                    Some([p.x, p.y, p.z, v.x, v.y, v.z].map(f32::to_bits))
+                })
+                .collect()
+        };
+        assert_eq!(bits(&physics), bits(&serial));
+        let grounded = |physics: &Physics| -> Vec<bool> {
+            physics
+                .colliders
+                .iter()
+                .flatten()
+                .map(|c| c.grounded)
+                .collect()
+        };
+        assert_eq!(grounded(&physics), grounded(&serial));
+        assert!(grounded(&physics).iter().any(|g| *g));
     }
 }
@@ -0,0 +1,106 @@
+use glam::{Quat, Vec3};
+
+use super::{Position, Rotation, Scale};
+
+/// Pipeline parameters used when [`Placement::auto_load`] is set and the model
+/// isn't cached yet.
+#[derive(Debug, Clone)]
+pub struct AutoLoad {
+    pub shader: String,
+    pub surface_configuration: wgpu::SurfaceConfiguration,
+    pub primitive: wgpu::PrimitiveState,
+    pub color_target: wgpu::ColorTargetState,
+    pub depth_stencil: Option<wgpu::DepthStencilState>,
+}
+
+impl AutoLoad {
+    pub fn new(
+        surface_configuration: &wgpu::SurfaceConfiguration,
+        depth_stencil: Option<wgpu::DepthStencilState>,
+    ) -> Self {
+        Self {
+            shader: crate::Shader::DEFAULT.to_string(),
+            surface_configuration: surface_configuration.clone(),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: Some(wgpu::Face::Front),
+                unclipped_depth: false,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                conservative: false,
+            },
+            color_target: wgpu::ColorTargetState {
+                format: surface_configuration.format,
+                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                write_mask: wgpu::ColorWrites::all(),
+            },
+            depth_stencil,
+        }
+    }
+    pub fn with_shader(mut self, shader: &str) -> Self {
+        self.shader = shader.to_string();
+        self
+    }
+    pub fn with_primitive(mut self, primitive: wgpu::PrimitiveState) -> Self {
+        self.primitive = primitive;
+        self
+    }
+}
+
+/// Where and how a model entity is spawned. Defaults to the origin with identity
+/// rotation, unit scale and visible.
+#[derive(Debug, Clone)]
+pub struct Placement {
+    pub position: Position,
+    pub rotation: Rotation,
+    pub scale: Scale,
+    pub visible: bool,
+    pub auto_load: Option<AutoLoad>,
+}
+
+impl Default for Placement {
+    fn default() -> Self {
+        Self {
+            position: Position::origin(),
+            rotation: Rotation::identity(),
+            scale: Scale::one(),
+            visible: true,
+            auto_load: None,
+        }
+    }
+}
+
+impl Placement {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn at(position: Vec3) -> Self {
+        Self::default().with_position(position)
+    }
+    pub fn with_position(mut self, position: Vec3) -> Self {
+        self.position = Position(position);
+        self
+    }
+    pub fn with_rotation(mut self, rotation: Quat) -> Self {
+        self.rotation = Rotation(rotation);
+        self
+    }
+    pub fn with_scale(mut self, scale: Vec3) -> Self {
+        self.scale = Scale(scale);
+        self
+    }
+    pub fn with_uniform_scale(mut self, scale: f32) -> Self {
+        self.scale = Scale::uniform(scale);
+        self
+    }
+    pub fn with_visible(mut self, visible: bool) -> Self {
+        self.visible = visible;
+        self
+    }
+    /// Loads the OBJ with the given pipeline parameters if it isn't cached yet.
+    pub fn with_auto_load(mut self, auto_load: AutoLoad) -> Self {
+        self.auto_load = Some(auto_load);
+        self
+    }
+}
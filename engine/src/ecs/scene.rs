@@ -0,0 +1,330 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use serde::{Deserialize, Serialize};
+
+use glam::{Quat, Vec3};
+
+use crate::{
+    AssetPaths, CacheKey, ChunkGenerator, EngineError, Entity, EnvironmentSource, FlatGenerator,
+    Medium, NoiseGenerator, Placement, Position, Rotation, Scale, ScriptBehavior, Velocity,
+    WorldOrigin,
+};
+
+/// One entry of a scene file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum SceneEntry {
+    /// A single model instance. `rotation` is yaw/pitch/roll in degrees.
+    Model {
+        model: String,
+        #[serde(default)]
+        name: Option<String>,
+        position: [f32; 3],
+        #[serde(default)]
+        rotation: [f32; 3],
+        #[serde(default = "unit_scale")]
+        scale: f32,
+        /// Makes the instance [`crate::Damageable`] with this much [`crate::Health`].
+        #[serde(default)]
+        health: Option<f32>,
+        /// Labels scripts can look the instance up by.
+        #[serde(default)]
+        tags: Vec<String>,
+        #[serde(default)]
+        script: Option<ScriptBehavior>,
+    },
+    /// A filled box of instances at `origin + cell * spacing` for every cell in `from..=to`.
+    Grid {
+        model: String,
+        origin: [f32; 3],
+        from: [i32; 3],
+        to: [i32; 3],
+        #[serde(default = "unit_spacing")]
+        spacing: f32,
+        #[serde(default = "unit_scale")]
+        scale: f32,
+    },
+}
+
+fn unit_scale() -> f32 {
+    1.0
+}
+fn unit_spacing() -> f32 {
+    1.0
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SceneTerrain {
+    pub center: [f32; 3],
+    pub radius: i32,
+    pub mediums: Vec<Medium>,
+    /// Noise the chunks are generated from; flat ground when unset.
+    #[serde(default)]
+    pub noise: Option<NoiseGenerator>,
+}
+
+impl SceneTerrain {
+    pub fn generator(&self) -> Arc<dyn ChunkGenerator> {
+        match self.noise {
+            Some(noise) => Arc::new(noise),
+            None => Arc::new(FlatGenerator),
+        }
+    }
+}
+
+/// Positions are global, see [`crate::WorldOrigin`].
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SceneFile {
+    pub shader: Option<String>,
+    pub terrain: Option<SceneTerrain>,
+    pub entities: Vec<SceneEntry>,
+    /// Sky and reflection cubemap; the current one is kept when unset.
+    pub environment: Option<EnvironmentSource>,
+    /// Sequence in `assets/sequences` to play once the scene is loaded.
+    pub sequence: Option<String>,
+}
+
+impl SceneFile {
+    pub const EXTENSION: &'static str = ".scene.ron";
+
+    pub fn dir() -> PathBuf {
+        AssetPaths::dir("scenes")
+    }
+    pub fn path(name: &str) -> PathBuf {
+        Self::dir().join(format!("{}{}", name, Self::EXTENSION))
+    }
+    pub fn load(name: &str) -> Result<Self, EngineError> {
+        let path = Self::path(name);
+        let source = std::fs::read_to_string(&path)
+            .map_err(|e| EngineError::AssetMissing(format!("{}: {}", path.display(), e)))?;
+        ron::de::from_str(&source).map_err(|e| {
+            EngineError::AssetLoadError(format!(
+                "{}:{}:{}: {}",
+                path.display(),
+                e.position.line,
+                e.position.col,
+                e.code
+            ))
+        })
+    }
+    /// Names of every scene file in the scenes directory, sorted.
+    pub fn available() -> Vec<String> {
+        let Ok(entries) = std::fs::read_dir(Self::dir()) else {
+            return Vec::new();
+        };
+        let mut names: Vec<String> = entries
+            .filter_map(|entry| {
+                let name = entry.ok()?.file_name().into_string().ok()?;
+                name.strip_suffix(Self::EXTENSION).map(str::to_string)
+            })
+            .collect();
+        names.sort();
+        names
+    }
+}
+
+/// `rotation` is a quaternion as `[x, y, z, w]`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedEntity {
+    pub model: String,
+    pub position: [f32; 3],
+    #[serde(default = "identity_rotation")]
+    pub rotation: [f32; 4],
+    #[serde(default = "unit_scale3")]
+    pub scale: [f32; 3],
+    #[serde(default)]
+    pub velocity: Option<[f32; 3]>,
+    #[serde(default = "visible")]
+    pub visible: bool,
+}
+
+impl SavedEntity {
+    /// Snapshot of an entity at local `position`, stored globally under `origin`. Missing
+    /// rotation and scale are saved as identity and one.
+    pub fn capture(
+        model: String,
+        origin: &WorldOrigin,
+        position: Position,
+        rotation: Option<Rotation>,
+        scale: Option<Scale>,
+        velocity: Option<Velocity>,
+        visible: bool,
+    ) -> Self {
+        Self {
+            model,
+            position: origin.to_global(position.0).as_vec3().to_array(),
+            rotation: rotation.map_or(Quat::IDENTITY, |r| r.0).to_array(),
+            scale: scale.map_or(Vec3::ONE, |s| s.0).to_array(),
+            velocity: velocity.map(|v| v.0.to_array()),
+            visible,
+        }
+    }
+    /// Where to spawn the entity again, local to `origin`.
+    pub fn placement(&self, origin: &WorldOrigin) -> Placement {
+        Placement::at(origin.to_local(Vec3::from_array(self.position).as_dvec3()))
+            .with_rotation(Quat::from_array(self.rotation).normalize())
+            .with_scale(Vec3::from_array(self.scale))
+            .with_visible(self.visible)
+    }
+}
+
+fn identity_rotation() -> [f32; 4] {
+    [0.0, 0.0, 0.0, 1.0]
+}
+fn unit_scale3() -> [f32; 3] {
+    [1.0; 3]
+}
+fn visible() -> bool {
+    true
+}
+
+/// Positions are global, see [`crate::WorldOrigin`].
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SavedScene {
+    pub entities: Vec<SavedEntity>,
+}
+
+impl SavedScene {
+    pub fn read(path: &Path) -> Result<Self, EngineError> {
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| EngineError::AssetMissing(format!("{}: {}", path.display(), e)))?;
+        ron::de::from_str(&source).map_err(|e| {
+            EngineError::AssetLoadError(format!(
+                "{}:{}:{}: {}",
+                path.display(),
+                e.position.line,
+                e.position.col,
+                e.code
+            ))
+        })
+    }
+    pub fn to_ron(&self) -> Result<String, EngineError> {
+        ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|e| EngineError::AssetLoadError(e.to_string()))
+    }
+    pub fn write(&self, path: &Path) -> Result<(), EngineError> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, self.to_ron()?)?;
+        Ok(())
+    }
+}
+
+/// Bookkeeping for the scene currently in the world, so it can be unloaded.
+#[derive(Debug, Clone, Default)]
+pub struct LoadedScene {
+    pub name: String,
+    pub entities: Vec<Entity>,
+    pub named: HashMap<String, Entity>,
+    pub tagged: HashMap<String, Vec<Entity>>,
+    pub models: Vec<CacheKey>,
+    /// Sequence the scene file asks to autoplay.
+    pub sequence: Option<String>,
+    /// Entries that failed to load; the rest of the scene is still spawned.
+    pub warnings: Vec<String>,
+}
+
+impl LoadedScene {
+    pub fn entity(&self, name: &str) -> Option<Entity> {
+        self.named.get(name).copied()
+    }
+    pub fn tagged(&self, tag: &str) -> &[Entity] {
+        self.tagged.get(tag).map_or(&[], Vec::as_slice)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::I64Vec3;
+
+    use super::*;
+    use crate::CHUNK_SIZE;
+
+    #[test]
+    fn saved_scene_round_trips() {
+        let origin = WorldOrigin::from_chunk(I64Vec3::new(250, 0, -3));
+        let rotation = Quat::from_rotation_y(1.2) * Quat::from_rotation_x(-0.4);
+        let entities = [
+            (
+                Position::new(1.5, 2.0, -3.25),
+                Some(Rotation(rotation)),
+                Some(Scale::new(2.0, 1.0, 0.5)),
+                Some(Velocity(Vec3::new(0.0, -9.5, 1.0))),
+                true,
+            ),
+            (Position::new(-7.0, 0.0, 12.0), None, None, None, false),
+        ];
+        let saved = SavedScene {
+            entities: entities
+                .iter()
+                .map(|&(position, rotation, scale, velocity, visible)| {
+                    let model = "models/goblin.obj".to_string();
+                    SavedEntity::capture(
+                        model, &origin, position, rotation, scale, velocity, visible,
+                    )
+                })
+                .collect(),
+        };
+
+        let path = std::env::temp_dir()
+            .join(format!("rupy_scene_{}", std::process::id()))
+            .join("round_trip.ron");
+        saved.write(&path).unwrap();
+        let loaded = SavedScene::read(&path);
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+        let loaded = loaded.unwrap();
+        assert_eq!(loaded, saved);
+
+        for ((position, rotation, scale, velocity, visible), entry) in
+            entities.iter().zip(&loaded.entities)
+        {
+            let placement = entry.placement(&origin);
+            assert_eq!(placement.position.0, position.0);
+            let rotation = rotation.map_or(Quat::IDENTITY, |r| r.0);
+            assert!(placement.rotation.0.abs_diff_eq(rotation, 1e-6));
+            assert_eq!(placement.scale.0, scale.map_or(Vec3::ONE, |s| s.0));
+            assert_eq!(entry.velocity, velocity.map(|v| v.0.to_array()));
+            assert_eq!(placement.visible, *visible);
+        }
+
+        // Stored globally, so loading under another origin lands in the same place.
+        let moved = WorldOrigin::from_chunk(I64Vec3::new(249, 0, -3));
+        let placement = loaded.entities[0].placement(&moved);
+        assert_eq!(
+            placement.position.0,
+            Vec3::new(1.5 + CHUNK_SIZE as f32, 2.0, -3.25)
+        );
+    }
+
+    #[test]
+    fn missing_fields_take_defaults() {
+        let scene: SavedScene =
+            ron::de::from_str("(entities: [(model: \"a.obj\", position: (1.0, 2.0, 3.0))])")
+                .unwrap();
+        let placement = scene.entities[0].placement(&WorldOrigin::default());
+        assert_eq!(placement.position.0, Vec3::new(1.0, 2.0, 3.0));
+        assert_eq!(placement.rotation.0, Quat::IDENTITY);
+        assert_eq!(placement.scale.0, Vec3::ONE);
+        assert!(placement.visible);
+        assert_eq!(scene.entities[0].velocity, None);
+    }
+
+    #[test]
+    fn the_picker_lists_scene_files_only() {
+        crate::assets::loader::test_root();
+        std::fs::write(SceneFile::dir().join("notes.txt"), "").unwrap();
+        let available = SceneFile::available();
+        for name in ["debug", "empty", "lighting_demo", "terrain_demo"] {
+            assert!(available.iter().any(|a| a == name), "{:?}", available);
+            SceneFile::load(name).unwrap();
+        }
+        assert!(!available.iter().any(|a| a.contains("notes")));
+        assert!(available.windows(2).all(|w| w[0] <= w[1]));
+    }
+}
@@ -0,0 +1,365 @@
+//! Stage scheduler for the world tick. Each system declares the storages it reads and
+//! writes; systems that don't conflict share a stage and run in parallel on rayon, and
+//! conflicting systems keep the order they were added in.
+//!
+//! Storages are handed out through per-storage locks in a [`WorldView`]. A correct
+//! declaration never contends on them. In debug builds touching an undeclared storage
+//! panics with the system and storage named; in release an undeclared access that would
+//! race with another system panics as a conflict instead of blocking.
+
+use std::any::{type_name, TypeId};
+use std::ops::{Deref, DerefMut};
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard, TryLockError};
+
+use glam::Vec3;
+
+use super::{
+    Animator, Collider, DespawnWhenFar, ExpiryReason, FadeOutThenDespawn, Lifetime, NavAgent,
+    Navigation, Position, PreviousPose, RemoteTransform, Rotation, Scale, SpatialGrid, Tint,
+    Transform, Velocity,
+};
+use crate::{Entity, MediumProperties, Terrain, WorldEvent};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct StorageKey {
+    id: TypeId,
+    name: &'static str,
+}
+
+impl StorageKey {
+    pub fn of<T: Storage>() -> Self {
+        Self {
+            id: TypeId::of::<T>(),
+            name: type_name::<T>(),
+        }
+    }
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+/// Storages a system reads and writes. Writing implies reading.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Access {
+    reads: Vec<StorageKey>,
+    writes: Vec<StorageKey>,
+}
+
+impl Access {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn reads<T: Storage>(mut self) -> Self {
+        self.reads.push(StorageKey::of::<T>());
+        self
+    }
+    pub fn writes<T: Storage>(mut self) -> Self {
+        self.writes.push(StorageKey::of::<T>());
+        self
+    }
+    pub fn can_read(&self, key: StorageKey) -> bool {
+        self.reads.contains(&key) || self.can_write(key)
+    }
+    pub fn can_write(&self, key: StorageKey) -> bool {
+        self.writes.contains(&key)
+    }
+    /// Two systems conflict when either writes something the other touches.
+    pub fn conflicts(&self, other: &Access) -> bool {
+        self.writes.iter().any(|key| other.can_read(*key))
+            || other.writes.iter().any(|key| self.can_read(*key))
+    }
+}
+
+/// Something a [`WorldView`] holds a lock for: a component column or a world resource.
+pub trait Storage: 'static {
+    type Target: ?Sized + Send + Sync;
+    fn cell<'w, 'a>(view: &'w WorldView<'a>) -> &'w RwLock<&'a mut Self::Target>;
+}
+
+macro_rules! storages {
+    ($($key:ty => $target:ty, $field:ident;)*) => {
+        /// Borrows of everything a tick touches, one lock per storage. Built by the world
+        /// for the length of one [`Schedule::run`].
+        pub struct WorldView<'a> {
+            $(pub(crate) $field: RwLock<&'a mut $target>,)*
+        }
+        $(
+            impl Storage for $key {
+                type Target = $target;
+                fn cell<'w, 'a>(view: &'w WorldView<'a>) -> &'w RwLock<&'a mut $target> {
+                    &view.$field
+                }
+            }
+        )*
+    };
+}
+
+storages! {
+    Position => [Option<Position>], positions;
+    Velocity => [Option<Velocity>], velocities;
+    Collider => [Option<Collider>], colliders;
+    Rotation => [Option<Rotation>], rotations;
+    Scale => [Option<Scale>], scales;
+    Transform => [Option<Transform>], transforms;
+    PreviousPose => [Option<PreviousPose>], previous_poses;
+    RemoteTransform => [Option<RemoteTransform>], remote_transforms;
+    NavAgent => [Option<NavAgent>], nav_agents;
+    Lifetime => [Option<Lifetime>], lifetimes;
+    DespawnWhenFar => [Option<DespawnWhenFar>], despawn_when_far;
+    FadeOutThenDespawn => [Option<FadeOutThenDespawn>], fades;
+    Tint => [Option<Tint>], tints;
+    Animator => [Option<Animator>], animators;
+    Terrain => Terrain, terrain;
+    Navigation => Navigation, navigation;
+    SpatialGrid => SpatialGrid, spatial;
+    WorldEvent => Vec<WorldEvent>, events;
+    ExpiryReason => Vec<(Entity, ExpiryReason)>, expired;
+}
+
+/// Fixed simulation step of [`crate::World::update`]. Real time accumulates across frames
+/// and runs as whole steps, so the simulation doesn't depend on the frame rate.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct FixedTimestep {
+    /// Seconds per step.
+    pub step: f32,
+    /// Most steps one update runs. Time past that is dropped rather than carried, so a
+    /// stall can't snowball into ever longer updates.
+    pub max_steps: u32,
+}
+
+impl Default for FixedTimestep {
+    fn default() -> Self {
+        Self {
+            step: 1.0 / 60.0,
+            max_steps: 8,
+        }
+    }
+}
+
+/// Per-tick inputs every system can see.
+#[derive(Debug, Copy, Clone)]
+pub struct Tick<'a> {
+    /// The fixed step inside [`crate::World::update`]'s simulation steps, the frame's
+    /// real time in the per-frame schedule after them.
+    pub dt: f32,
+    /// Simulated seconds since the world was created.
+    pub elapsed: f64,
+    /// How far the frame is past the last fixed step, in steps, for blending
+    /// [`PreviousPose`] toward the current pose. 1 while paused.
+    pub alpha: f32,
+    pub camera_pos: Vec3,
+    /// View frustum of the camera, for culling.
+    pub frustum: crate::camera::Frustum,
+    /// Medium the physics step integrates in this tick.
+    pub medium: MediumProperties,
+    pub queue: &'a wgpu::Queue,
+    pub device: &'a wgpu::Device,
+}
+
+pub struct StorageRef<'w, 'a, T: Storage>(RwLockReadGuard<'w, &'a mut T::Target>);
+
+impl<T: Storage> Deref for StorageRef<'_, '_, T> {
+    type Target = T::Target;
+    fn deref(&self) -> &T::Target {
+        &self.0
+    }
+}
+
+pub struct StorageMut<'w, 'a, T: Storage>(RwLockWriteGuard<'w, &'a mut T::Target>);
+
+impl<T: Storage> Deref for StorageMut<'_, '_, T> {
+    type Target = T::Target;
+    fn deref(&self) -> &T::Target {
+        &self.0
+    }
+}
+impl<T: Storage> DerefMut for StorageMut<'_, '_, T> {
+    fn deref_mut(&mut self) -> &mut T::Target {
+        &mut self.0
+    }
+}
+
+/// What a running system sees: the tick and the storages it declared.
+pub struct SystemContext<'w, 'a> {
+    system: &'w System,
+    view: &'w WorldView<'a>,
+    tick: &'w Tick<'w>,
+}
+
+impl<'w, 'a> SystemContext<'w, 'a> {
+    pub fn tick(&self) -> &Tick<'w> {
+        self.tick
+    }
+    pub fn read<T: Storage>(&self) -> StorageRef<'w, 'a, T> {
+        self.check::<T>(false);
+        match T::cell(self.view).try_read() {
+            Ok(guard) => StorageRef(guard),
+            Err(TryLockError::Poisoned(e)) => StorageRef(e.into_inner()),
+            Err(TryLockError::WouldBlock) => self.conflict::<T>(),
+        }
+    }
+    pub fn write<T: Storage>(&self) -> StorageMut<'w, 'a, T> {
+        self.check::<T>(true);
+        match T::cell(self.view).try_write() {
+            Ok(guard) => StorageMut(guard),
+            Err(TryLockError::Poisoned(e)) => StorageMut(e.into_inner()),
+            Err(TryLockError::WouldBlock) => self.conflict::<T>(),
+        }
+    }
+
+    fn check<T: Storage>(&self, write: bool) {
+        if !cfg!(debug_assertions) {
+            return;
+        }
+        let key = StorageKey::of::<T>();
+        let access = &self.system.access;
+        let declared = if write {
+            access.can_write(key)
+        } else {
+            access.can_read(key)
+        };
+        if !declared {
+            panic!(
+                "System '{}' {} {} without declaring it",
+                self.system.name,
+                if write { "writes" } else { "reads" },
+                key.name()
+            );
+        }
+    }
+    fn conflict<T: Storage>(&self) -> ! {
+        panic!(
+            "System '{}' raced another system for {}; check its declared access",
+            self.system.name,
+            type_name::<T>()
+        )
+    }
+}
+
+pub type SystemFn = fn(&SystemContext<'_, '_>);
+
+#[derive(Debug, Clone)]
+pub struct System {
+    pub name: &'static str,
+    pub access: Access,
+    pub run: SystemFn,
+}
+
+/// Systems grouped into stages. A system goes into the stage after the last one holding a
+/// system it conflicts with, so declared order is kept wherever it matters.
+#[derive(Debug, Clone, Default)]
+pub struct Schedule {
+    systems: Vec<System>,
+    stages: Vec<Vec<usize>>,
+}
+
+impl Schedule {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn with(mut self, name: &'static str, access: Access, run: SystemFn) -> Self {
+        self.add(name, access, run);
+        self
+    }
+    pub fn add(&mut self, name: &'static str, access: Access, run: SystemFn) {
+        let stage = self
+            .stages
+            .iter()
+            .rposition(|stage| {
+                stage
+                    .iter()
+                    .any(|&i| self.systems[i].access.conflicts(&access))
+            })
+            .map_or(0, |last| last + 1);
+        if stage == self.stages.len() {
+            self.stages.push(Vec::new());
+        }
+        self.stages[stage].push(self.systems.len());
+        self.systems.push(System { name, access, run });
+    }
+    /// System names per stage, in run order.
+    pub fn stages(&self) -> Vec<Vec<&'static str>> {
+        self.stages
+            .iter()
+            .map(|stage| stage.iter().map(|&i| self.systems[i].name).collect())
+            .collect()
+    }
+
+    pub fn run(&self, view: &WorldView<'_>, tick: &Tick<'_>) {
+        for stage in &self.stages {
+            match stage.as_slice() {
+                [only] => self.run_system(*only, view, tick),
+                systems => rayon::scope(|scope| {
+                    for &i in systems {
+                        scope.spawn(move |_| self.run_system(i, view, tick));
+                    }
+                }),
+            }
+        }
+    }
+    fn run_system(&self, index: usize, view: &WorldView<'_>, tick: &Tick<'_>) {
+        let system = &self.systems[index];
+        (system.run)(&SystemContext { system, view, tick });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn noop(_: &SystemContext) {}
+
+    #[test]
+    fn stages_follow_declared_access() {
+        let schedule = Schedule::new()
+            .with("physics", Access::new().writes::<Position>(), noop)
+            .with("spin", Access::new().writes::<Rotation>(), noop)
+            .with("follow", Access::new().reads::<Position>(), noop)
+            .with(
+                "transforms",
+                Access::new()
+                    .reads::<Position>()
+                    .reads::<Rotation>()
+                    .writes::<Transform>(),
+                noop,
+            )
+            .with("tint", Access::new().writes::<Tint>(), noop)
+            .with("teleport", Access::new().writes::<Position>(), noop)
+            .with("batches", Access::new().reads::<Transform>(), noop);
+        assert_eq!(
+            schedule.stages(),
+            vec![
+                vec!["physics", "spin", "tint"],
+                vec!["follow", "transforms"],
+                vec!["teleport", "batches"],
+            ]
+        );
+    }
+
+    /// No stage of the world's schedules runs two conflicting systems together, and
+    /// conflicting systems run in the order they were added.
+    #[test]
+    fn world_schedules_keep_conflicting_systems_in_order() {
+        let schedules = [
+            crate::tick_schedule(),
+            crate::frame_schedule(),
+            crate::paused_schedule(),
+        ];
+        for schedule in &schedules {
+            let stage_of = |system: usize| {
+                schedule
+                    .stages
+                    .iter()
+                    .position(|stage| stage.contains(&system))
+                    .unwrap()
+            };
+            for (i, a) in schedule.systems.iter().enumerate() {
+                for (j, b) in schedule.systems.iter().enumerate().skip(i + 1) {
+                    if a.access.conflicts(&b.access) {
+                        assert!(stage_of(i) < stage_of(j), "{} and {}", a.name, b.name);
+                    }
+                }
+            }
+        }
+    }
+}
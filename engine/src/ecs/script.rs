@@ -0,0 +1,50 @@
+use serde::{Deserialize, Serialize};
+
+/// Functions a [`ScriptBehavior`] calls in its script. Entry points the script doesn't
+/// define are skipped.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScriptEntryPoints {
+    /// Called once, on the first update after the behavior is attached: `fn(entity)`.
+    pub on_spawn: Option<String>,
+    /// Called every `update_divisor` ticks: `fn(entity, dt)`, with `dt` the time since the
+    /// last call.
+    pub on_update: Option<String>,
+    pub update_divisor: u32,
+    /// Called for every world event: `fn(entity, event)`.
+    pub on_event: Option<String>,
+}
+
+impl Default for ScriptEntryPoints {
+    fn default() -> Self {
+        Self {
+            on_spawn: Some("on_spawn".to_string()),
+            on_update: Some("on_update".to_string()),
+            update_divisor: 1,
+            on_event: Some("on_event".to_string()),
+        }
+    }
+}
+
+/// Runs gameplay logic from `assets/scripts/<script>` for the entity it's attached to.
+/// Only executed when the engine is built with the `scripting` feature.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScriptBehavior {
+    /// Script name without extension.
+    pub script: String,
+    #[serde(default)]
+    pub entry_points: ScriptEntryPoints,
+}
+
+impl ScriptBehavior {
+    pub fn new(script: impl Into<String>) -> Self {
+        Self {
+            script: script.into(),
+            entry_points: ScriptEntryPoints::default(),
+        }
+    }
+    pub fn with_update_divisor(mut self, divisor: u32) -> Self {
+        self.entry_points.update_divisor = divisor.max(1);
+        self
+    }
+}
@@ -0,0 +1,751 @@
+//! Authored sequences: parallel keyframe tracks played back on sim time by a
+//! [`SequencePlayer`]. Whatever the tracks drive (properties, entities, the camera) is
+//! reached through a [`SequenceHost`], which the application implements.
+
+use std::path::PathBuf;
+
+use glam::{Quat, Vec3};
+use serde::{Deserialize, Serialize};
+
+use crate::{log_debug, log_warning, AssetPaths, EngineError, Entity, Rotation};
+
+/// Shape of the segment arriving at a key, i.e. from the previous key to this one.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Easing {
+    #[default]
+    Linear,
+    /// Holds the previous value until the key is reached.
+    Step,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+}
+
+impl Easing {
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::Step => 0.0,
+            Easing::EaseIn => t * t,
+            Easing::EaseOut => 1.0 - (1.0 - t) * (1.0 - t),
+            Easing::EaseInOut => t * t * (3.0 - 2.0 * t),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FloatKey {
+    pub time: f32,
+    pub value: f32,
+    #[serde(default)]
+    pub easing: Easing,
+}
+
+/// `rotation` is yaw/pitch/roll in degrees, as in scene files.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TransformKey {
+    pub time: f32,
+    pub position: [f32; 3],
+    #[serde(default)]
+    pub rotation: [f32; 3],
+    #[serde(default)]
+    pub easing: Easing,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CameraKey {
+    pub time: f32,
+    pub eye: [f32; 3],
+    pub target: [f32; 3],
+    #[serde(default)]
+    pub easing: Easing,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum SequenceEvent {
+    /// Handed to the host to publish, e.g. as a [`crate::WorldEvent`].
+    Publish(String),
+    PlaySound(String),
+    /// Screen text shown for `duration` seconds. Subtitles are state rather than triggers:
+    /// see [`SequencePlayer::subtitle`].
+    Subtitle {
+        text: String,
+        duration: f32,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EventKey {
+    pub time: f32,
+    pub event: SequenceEvent,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Track {
+    /// A named host property, see [`SequenceHost::float`].
+    Float {
+        property: String,
+        keys: Vec<FloatKey>,
+    },
+    /// Position and rotation of a named scene entity.
+    Transform {
+        entity: String,
+        keys: Vec<TransformKey>,
+    },
+    /// Takes over the camera while the sequence plays.
+    Camera {
+        keys: Vec<CameraKey>,
+    },
+    Events {
+        keys: Vec<EventKey>,
+    },
+}
+
+impl Track {
+    fn label(&self) -> String {
+        match self {
+            Track::Float { property, .. } => format!("float '{}'", property),
+            Track::Transform { entity, .. } => format!("transform '{}'", entity),
+            Track::Camera { .. } => "camera".to_string(),
+            Track::Events { .. } => "events".to_string(),
+        }
+    }
+    fn end(&self) -> f32 {
+        let last = |times: &mut dyn Iterator<Item = f32>| times.fold(0.0, f32::max);
+        match self {
+            Track::Float { keys, .. } => last(&mut keys.iter().map(|k| k.time)),
+            Track::Transform { keys, .. } => last(&mut keys.iter().map(|k| k.time)),
+            Track::Camera { keys } => last(&mut keys.iter().map(|k| k.time)),
+            Track::Events { keys } => last(&mut keys.iter().map(|k| match &k.event {
+                SequenceEvent::Subtitle { duration, .. } => k.time + duration.max(0.0),
+                _ => k.time,
+            })),
+        }
+    }
+    fn sort(&mut self) {
+        let by_time = |a: f32, b: f32| a.total_cmp(&b);
+        match self {
+            Track::Float { keys, .. } => keys.sort_by(|a, b| by_time(a.time, b.time)),
+            Track::Transform { keys, .. } => keys.sort_by(|a, b| by_time(a.time, b.time)),
+            Track::Camera { keys } => keys.sort_by(|a, b| by_time(a.time, b.time)),
+            Track::Events { keys } => keys.sort_by(|a, b| by_time(a.time, b.time)),
+        }
+    }
+}
+
+/// `assets/sequences/<name>.seq.ron`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Sequence {
+    /// Length in seconds; defaults to the last key (or subtitle end).
+    pub duration: Option<f32>,
+    pub tracks: Vec<Track>,
+}
+
+impl Sequence {
+    pub const EXTENSION: &'static str = ".seq.ron";
+
+    pub fn dir() -> PathBuf {
+        AssetPaths::dir("sequences")
+    }
+    pub fn path(name: &str) -> PathBuf {
+        Self::dir().join(format!("{}{}", name, Self::EXTENSION))
+    }
+    pub fn load(name: &str) -> Result<Self, EngineError> {
+        let path = Self::path(name);
+        let source = std::fs::read_to_string(&path)
+            .map_err(|e| EngineError::AssetMissing(format!("{}: {}", path.display(), e)))?;
+        let mut sequence: Self = ron::de::from_str(&source).map_err(|e| {
+            EngineError::AssetLoadError(format!(
+                "{}:{}:{}: {}",
+                path.display(),
+                e.position.line,
+                e.position.col,
+                e.code
+            ))
+        })?;
+        sequence.tracks.iter_mut().for_each(Track::sort);
+        Ok(sequence)
+    }
+    pub fn duration(&self) -> f32 {
+        self.duration
+            .unwrap_or_else(|| self.tracks.iter().map(Track::end).fold(0.0, f32::max))
+    }
+}
+
+/// What a sequence drives. Getters return `None` when a binding can't be resolved, which
+/// disables that track with a warning.
+pub trait SequenceHost {
+    fn float(&self, property: &str) -> Option<f32>;
+    fn set_float(&mut self, property: &str, value: f32);
+    fn entity(&self, name: &str) -> Option<Entity>;
+    fn transform(&self, entity: Entity) -> Option<(Vec3, Quat)>;
+    fn set_transform(&mut self, entity: Entity, position: Vec3, rotation: Quat);
+    /// Eye and target to force, or `None` to hand the camera back.
+    fn set_camera(&mut self, view: Option<(Vec3, Vec3)>);
+    fn fire(&mut self, event: &SequenceEvent);
+}
+
+/// Keys around `time` and the eased blend between them. Before the first key and after the
+/// last the nearest key is held.
+fn segment<K>(
+    keys: &[K],
+    time: f32,
+    key_time: impl Fn(&K) -> f32,
+    easing: impl Fn(&K) -> Easing,
+) -> Option<(&K, &K, f32)> {
+    let first = keys.first()?;
+    let next = keys.partition_point(|k| key_time(k) <= time);
+    if next == 0 {
+        return Some((first, first, 0.0));
+    }
+    if next == keys.len() {
+        let last = &keys[keys.len() - 1];
+        return Some((last, last, 0.0));
+    }
+    let (a, b) = (&keys[next - 1], &keys[next]);
+    let span = key_time(b) - key_time(a);
+    let t = if span > 0.0 {
+        (time - key_time(a)) / span
+    } else {
+        1.0
+    };
+    Some((a, b, easing(b).apply(t)))
+}
+
+pub fn sample_float(keys: &[FloatKey], time: f32) -> Option<f32> {
+    segment(keys, time, |k| k.time, |k| k.easing).map(|(a, b, t)| a.value + (b.value - a.value) * t)
+}
+
+pub fn sample_transform(keys: &[TransformKey], time: f32) -> Option<(Vec3, Quat)> {
+    let rotation = |k: &TransformKey| {
+        let [yaw, pitch, roll] = k.rotation.map(f32::to_radians);
+        Rotation::from_euler(yaw, pitch, roll).quat()
+    };
+    segment(keys, time, |k| k.time, |k| k.easing).map(|(a, b, t)| {
+        (
+            Vec3::from_array(a.position).lerp(Vec3::from_array(b.position), t),
+            rotation(a).slerp(rotation(b), t),
+        )
+    })
+}
+
+pub fn sample_camera(keys: &[CameraKey], time: f32) -> Option<(Vec3, Vec3)> {
+    segment(keys, time, |k| k.time, |k| k.easing).map(|(a, b, t)| {
+        (
+            Vec3::from_array(a.eye).lerp(Vec3::from_array(b.eye), t),
+            Vec3::from_array(a.target).lerp(Vec3::from_array(b.target), t),
+        )
+    })
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum PlaybackState {
+    #[default]
+    Stopped,
+    Playing,
+    Paused,
+}
+
+/// A track resolved against the host, with what it overrode so stop can put it back.
+#[derive(Debug, Clone)]
+enum Binding {
+    Failed,
+    Float {
+        original: f32,
+    },
+    Transform {
+        entity: Entity,
+        original: (Vec3, Quat),
+    },
+    Camera,
+    /// Per key: whether it has fired or was skipped by a seek.
+    Events {
+        consumed: Vec<bool>,
+    },
+}
+
+/// Plays one [`Sequence`].
+///
+/// Event keys fire exactly once per playthrough, when playback reaches their time. Seeking
+/// is a jump rather than playback: every key at or before the seek target is consumed
+/// without firing, and seeking backward never re-arms a consumed key. Pause and resume
+/// leave keys alone. [`SequencePlayer::stop`] (also reached at the end) re-arms everything
+/// and restores every overridden property, entity transform and the camera.
+#[derive(Debug, Clone)]
+pub struct SequencePlayer {
+    name: String,
+    sequence: Sequence,
+    time: f32,
+    state: PlaybackState,
+    bindings: Vec<Binding>,
+}
+
+impl SequencePlayer {
+    pub fn new(name: impl Into<String>, sequence: Sequence) -> Self {
+        Self {
+            name: name.into(),
+            sequence,
+            time: 0.0,
+            state: PlaybackState::Stopped,
+            bindings: Vec::new(),
+        }
+    }
+    pub fn load(name: &str) -> Result<Self, EngineError> {
+        Ok(Self::new(name, Sequence::load(name)?))
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    pub fn sequence(&self) -> &Sequence {
+        &self.sequence
+    }
+    pub fn time(&self) -> f32 {
+        self.time
+    }
+    pub fn state(&self) -> PlaybackState {
+        self.state
+    }
+    pub fn is_active(&self) -> bool {
+        self.state != PlaybackState::Stopped
+    }
+
+    /// Starts from the beginning when stopped, resumes when paused.
+    pub fn play(&mut self, host: &mut dyn SequenceHost) {
+        match self.state {
+            PlaybackState::Playing => {}
+            PlaybackState::Paused => self.state = PlaybackState::Playing,
+            PlaybackState::Stopped => {
+                self.bind(host);
+                self.time = 0.0;
+                self.state = PlaybackState::Playing;
+                self.fire_due(host);
+                self.evaluate(host);
+            }
+        }
+    }
+    pub fn pause(&mut self) {
+        if self.state == PlaybackState::Playing {
+            self.state = PlaybackState::Paused;
+        }
+    }
+    /// Restores everything the sequence overrode and rewinds.
+    pub fn stop(&mut self, host: &mut dyn SequenceHost) {
+        if self.state == PlaybackState::Stopped {
+            return;
+        }
+        for (track, binding) in self.sequence.tracks.iter().zip(&self.bindings) {
+            match (track, binding) {
+                (Track::Float { property, .. }, Binding::Float { original }) => {
+                    host.set_float(property, *original)
+                }
+                (_, Binding::Transform { entity, original }) => {
+                    host.set_transform(*entity, original.0, original.1)
+                }
+                (_, Binding::Camera) => host.set_camera(None),
+                _ => {}
+            }
+        }
+        self.bindings.clear();
+        self.time = 0.0;
+        self.state = PlaybackState::Stopped;
+        log_debug!("Sequence '{}' stopped", self.name);
+    }
+    /// Jumps to `time` without firing the event keys it passes; see the type docs.
+    pub fn seek(&mut self, host: &mut dyn SequenceHost, time: f32) {
+        if self.state == PlaybackState::Stopped {
+            return;
+        }
+        self.time = time.clamp(0.0, self.sequence.duration());
+        for (track, binding) in self.sequence.tracks.iter().zip(&mut self.bindings) {
+            if let (Track::Events { keys }, Binding::Events { consumed }) = (track, binding) {
+                for (key, consumed) in keys.iter().zip(consumed) {
+                    *consumed |= key.time <= self.time;
+                }
+            }
+        }
+        self.evaluate(host);
+    }
+    /// Advances by `dt` seconds of sim time while playing; stops at the end.
+    pub fn update(&mut self, host: &mut dyn SequenceHost, dt: f32) {
+        if self.state != PlaybackState::Playing {
+            return;
+        }
+        let duration = self.sequence.duration();
+        self.time = (self.time + dt.max(0.0)).min(duration);
+        self.fire_due(host);
+        self.evaluate(host);
+        if self.time >= duration {
+            self.stop(host);
+        }
+    }
+
+    /// Subtitle on screen at the current time: the latest one started and not yet expired.
+    pub fn subtitle(&self) -> Option<&str> {
+        if self.state == PlaybackState::Stopped {
+            return None;
+        }
+        self.sequence
+            .tracks
+            .iter()
+            .filter_map(|track| match track {
+                Track::Events { keys } => Some(keys),
+                _ => None,
+            })
+            .flatten()
+            .filter_map(|key| match &key.event {
+                SequenceEvent::Subtitle { text, duration }
+                    if key.time <= self.time && self.time < key.time + duration =>
+                {
+                    Some((key.time, text.as_str()))
+                }
+                _ => None,
+            })
+            .max_by(|a, b| a.0.total_cmp(&b.0))
+            .map(|(_, text)| text)
+    }
+
+    /// Resolves every track once. A track that can't be resolved is skipped with one
+    /// warning; the rest of the sequence still plays.
+    fn bind(&mut self, host: &mut dyn SequenceHost) {
+        self.bindings = self
+            .sequence
+            .tracks
+            .iter()
+            .map(|track| {
+                let binding = match track {
+                    Track::Float { property, .. } => host
+                        .float(property)
+                        .map(|original| Binding::Float { original }),
+                    Track::Transform { entity, .. } => host.entity(entity).and_then(|entity| {
+                        host.transform(entity)
+                            .map(|original| Binding::Transform { entity, original })
+                    }),
+                    Track::Camera { .. } => Some(Binding::Camera),
+                    Track::Events { keys } => Some(Binding::Events {
+                        consumed: vec![false; keys.len()],
+                    }),
+                };
+                binding.unwrap_or_else(|| {
+                    log_warning!(
+                        "Sequence '{}': can't bind {} track, skipping it",
+                        self.name,
+                        track.label()
+                    );
+                    Binding::Failed
+                })
+            })
+            .collect();
+    }
+
+    fn fire_due(&mut self, host: &mut dyn SequenceHost) {
+        for (track, binding) in self.sequence.tracks.iter().zip(&mut self.bindings) {
+            let (Track::Events { keys }, Binding::Events { consumed }) = (track, binding) else {
+                continue;
+            };
+            for (key, consumed) in keys.iter().zip(consumed) {
+                if !*consumed && key.time <= self.time {
+                    *consumed = true;
+                    if !matches!(key.event, SequenceEvent::Subtitle { .. }) {
+                        host.fire(&key.event);
+                    }
+                }
+            }
+        }
+    }
+
+    fn evaluate(&self, host: &mut dyn SequenceHost) {
+        for (track, binding) in self.sequence.tracks.iter().zip(&self.bindings) {
+            match (track, binding) {
+                (Track::Float { property, keys }, Binding::Float { .. }) => {
+                    if let Some(value) = sample_float(keys, self.time) {
+                        host.set_float(property, value);
+                    }
+                }
+                (Track::Transform { keys, .. }, Binding::Transform { entity, .. }) => {
+                    if let Some((position, rotation)) = sample_transform(keys, self.time) {
+                        host.set_transform(*entity, position, rotation);
+                    }
+                }
+                (Track::Camera { keys }, Binding::Camera) => {
+                    if let Some(view) = sample_camera(keys, self.time) {
+                        host.set_camera(Some(view));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct Host {
+        floats: HashMap<String, f32>,
+        entities: HashMap<String, Entity>,
+        transforms: HashMap<usize, (Vec3, Quat)>,
+        camera: Option<(Vec3, Vec3)>,
+        fired: Vec<SequenceEvent>,
+    }
+
+    thread_local! {
+        static ENTITY_LOOKUPS: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+    }
+
+    impl SequenceHost for Host {
+        fn float(&self, property: &str) -> Option<f32> {
+            self.floats.get(property).copied()
+        }
+        fn set_float(&mut self, property: &str, value: f32) {
+            self.floats.insert(property.to_string(), value);
+        }
+        fn entity(&self, name: &str) -> Option<Entity> {
+            ENTITY_LOOKUPS.with(|n| n.set(n.get() + 1));
+            self.entities.get(name).copied()
+        }
+        fn transform(&self, entity: Entity) -> Option<(Vec3, Quat)> {
+            self.transforms.get(&entity.0).copied()
+        }
+        fn set_transform(&mut self, entity: Entity, position: Vec3, rotation: Quat) {
+            self.transforms.insert(entity.0, (position, rotation));
+        }
+        fn set_camera(&mut self, view: Option<(Vec3, Vec3)>) {
+            self.camera = view;
+        }
+        fn fire(&mut self, event: &SequenceEvent) {
+            self.fired.push(event.clone());
+        }
+    }
+
+    fn key(time: f32, value: f32, easing: Easing) -> FloatKey {
+        FloatKey {
+            time,
+            value,
+            easing,
+        }
+    }
+
+    fn publish(time: f32, name: &str) -> EventKey {
+        EventKey {
+            time,
+            event: SequenceEvent::Publish(name.to_string()),
+        }
+    }
+
+    fn published(host: &Host) -> Vec<&str> {
+        host.fired
+            .iter()
+            .map(|event| match event {
+                SequenceEvent::Publish(name) => name.as_str(),
+                _ => "",
+            })
+            .collect()
+    }
+
+    #[test]
+    fn tracks_evaluate_exactly_at_key_boundaries() {
+        let keys = vec![
+            key(1.0, 10.0, Easing::Linear),
+            key(2.0, 20.0, Easing::Linear),
+            key(3.0, 30.0, Easing::Step),
+            key(4.0, 40.0, Easing::EaseInOut),
+        ];
+        assert_eq!(sample_float(&[], 1.0), None);
+        // Held before the first key and after the last.
+        assert_eq!(sample_float(&keys, 0.0), Some(10.0));
+        assert_eq!(sample_float(&keys, 9.0), Some(40.0));
+        // Every key time evaluates to that key's value, whatever the easing into it.
+        for k in &keys {
+            assert_eq!(sample_float(&keys, k.time), Some(k.value), "at {}", k.time);
+        }
+        assert_eq!(sample_float(&keys, 1.5), Some(15.0));
+        // Step holds the previous value right up to its key.
+        assert_eq!(sample_float(&keys, 2.999), Some(20.0));
+        assert_eq!(sample_float(&keys, 3.5), Some(35.0));
+
+        // Coincident keys jump to the later one.
+        let jump = vec![key(1.0, 0.0, Easing::Linear), key(1.0, 5.0, Easing::Linear)];
+        assert_eq!(sample_float(&jump, 1.0), Some(5.0));
+
+        let transform = vec![
+            TransformKey {
+                time: 0.0,
+                position: [0.0; 3],
+                rotation: [0.0; 3],
+                easing: Easing::Linear,
+            },
+            TransformKey {
+                time: 2.0,
+                position: [2.0, 4.0, 0.0],
+                rotation: [90.0, 0.0, 0.0],
+                easing: Easing::Linear,
+            },
+        ];
+        let (position, rotation) = sample_transform(&transform, 2.0).unwrap();
+        assert_eq!(position, Vec3::new(2.0, 4.0, 0.0));
+        assert!(
+            rotation.angle_between(Rotation::from_euler(90f32.to_radians(), 0.0, 0.0).quat())
+                < 1e-4
+        );
+        assert_eq!(
+            sample_transform(&transform, 1.0).unwrap().0,
+            Vec3::new(1.0, 2.0, 0.0)
+        );
+    }
+
+    fn events_sequence() -> Sequence {
+        Sequence {
+            duration: Some(10.0),
+            tracks: vec![Track::Events {
+                keys: vec![
+                    publish(0.0, "start"),
+                    publish(2.0, "a"),
+                    publish(4.0, "b"),
+                    publish(6.0, "c"),
+                    publish(8.0, "d"),
+                ],
+            }],
+        }
+    }
+
+    #[test]
+    fn event_keys_fire_once_across_pause_seek_and_resume() {
+        let mut host = Host::default();
+        let mut player = SequencePlayer::new("events", events_sequence());
+        player.play(&mut host);
+        assert_eq!(published(&host), ["start"]);
+
+        player.update(&mut host, 2.0);
+        assert_eq!(published(&host), ["start", "a"]);
+
+        // Paused time doesn't advance or fire; resuming doesn't refire.
+        player.pause();
+        player.update(&mut host, 5.0);
+        player.play(&mut host);
+        assert_eq!(player.time(), 2.0);
+        assert_eq!(published(&host), ["start", "a"]);
+
+        // Seeking past "b" consumes it silently.
+        player.seek(&mut host, 5.0);
+        assert_eq!(published(&host), ["start", "a"]);
+        // Seeking back doesn't re-arm what was consumed.
+        player.seek(&mut host, 1.0);
+        player.update(&mut host, 5.5);
+        assert_eq!(published(&host), ["start", "a", "c"]);
+
+        // A seek while paused behaves the same.
+        player.pause();
+        player.seek(&mut host, 9.0);
+        player.play(&mut host);
+        player.update(&mut host, 1.0);
+        assert_eq!(published(&host), ["start", "a", "c"]);
+        assert_eq!(player.state(), PlaybackState::Stopped);
+
+        // The end stops and re-arms everything for the next playthrough.
+        host.fired.clear();
+        player.play(&mut host);
+        player.update(&mut host, 10.0);
+        assert_eq!(published(&host), ["start", "a", "b", "c", "d"]);
+    }
+
+    #[test]
+    fn stopping_restores_overridden_properties() {
+        let mut host = Host::default();
+        host.floats.insert("fog".into(), 0.25);
+        host.entities.insert("lamp".into(), Entity(7));
+        let lamp = (Vec3::new(1.0, 2.0, 3.0), Quat::IDENTITY);
+        host.transforms.insert(7, lamp);
+        let sequence = Sequence {
+            duration: Some(4.0),
+            tracks: vec![
+                Track::Float {
+                    property: "fog".into(),
+                    keys: vec![key(0.0, 1.0, Easing::Linear), key(4.0, 0.0, Easing::Linear)],
+                },
+                Track::Transform {
+                    entity: "lamp".into(),
+                    keys: vec![TransformKey {
+                        time: 0.0,
+                        position: [9.0, 9.0, 9.0],
+                        rotation: [45.0, 0.0, 0.0],
+                        easing: Easing::Linear,
+                    }],
+                },
+                Track::Camera {
+                    keys: vec![CameraKey {
+                        time: 0.0,
+                        eye: [0.0, 5.0, 5.0],
+                        target: [0.0; 3],
+                        easing: Easing::Linear,
+                    }],
+                },
+            ],
+        };
+        let mut player = SequencePlayer::new("restore", sequence);
+        player.play(&mut host);
+        player.update(&mut host, 1.0);
+        assert_eq!(host.floats["fog"], 0.75);
+        assert_eq!(host.transforms[&7].0, Vec3::splat(9.0));
+        assert!(host.camera.is_some());
+
+        player.stop(&mut host);
+        assert_eq!(host.floats["fog"], 0.25);
+        assert_eq!(host.transforms[&7], lamp);
+        assert_eq!(host.camera, None);
+        assert_eq!(player.time(), 0.0);
+
+        // Reaching the end restores too.
+        player.play(&mut host);
+        player.update(&mut host, 4.0);
+        assert!(!player.is_active());
+        assert_eq!(host.floats["fog"], 0.25);
+        assert_eq!(host.transforms[&7], lamp);
+    }
+
+    #[test]
+    fn a_missing_entity_disables_only_its_track() {
+        let mut host = Host::default();
+        host.floats.insert("fog".into(), 0.0);
+        let sequence = Sequence {
+            duration: Some(2.0),
+            tracks: vec![
+                Track::Transform {
+                    entity: "nobody".into(),
+                    keys: vec![TransformKey {
+                        time: 0.0,
+                        position: [1.0; 3],
+                        rotation: [0.0; 3],
+                        easing: Easing::Linear,
+                    }],
+                },
+                Track::Float {
+                    property: "fog".into(),
+                    keys: vec![key(0.0, 0.0, Easing::Linear), key(2.0, 1.0, Easing::Linear)],
+                },
+                Track::Events {
+                    keys: vec![publish(1.0, "half")],
+                },
+            ],
+        };
+        ENTITY_LOOKUPS.with(|n| n.set(0));
+        let mut player = SequencePlayer::new("partial", sequence);
+        player.play(&mut host);
+        assert!(matches!(player.bindings[0], Binding::Failed));
+        for _ in 0..3 {
+            player.update(&mut host, 0.5);
+        }
+        // Resolved (and warned about) once on play, not every frame.
+        assert_eq!(ENTITY_LOOKUPS.with(|n| n.get()), 1);
+        assert!(host.transforms.is_empty());
+        assert_eq!(host.floats["fog"], 0.75);
+        assert_eq!(published(&host), ["half"]);
+        assert!(player.is_active());
+    }
+}
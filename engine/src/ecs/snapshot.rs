@@ -0,0 +1,322 @@
+use std::collections::VecDeque;
+
+use glam::{Quat, Vec3};
+
+/// A single externally authoritative transform sample.
+#[derive(Debug, Copy, Clone)]
+pub struct TransformSnapshot {
+    pub timestamp: f64,
+    pub position: Vec3,
+    pub rotation: Quat,
+    pub velocity: Vec3,
+}
+
+/// Result of sampling a [`SnapshotBuffer`] at a given render time.
+#[derive(Debug, Copy, Clone)]
+pub enum SnapshotSample {
+    /// Render time is bracketed by two snapshots.
+    Interpolated(TransformSnapshot),
+    /// Render time is past the newest snapshot but within the extrapolation window.
+    Extrapolated(TransformSnapshot),
+    /// Render time is past the extrapolation window; the transform is frozen at the clamp.
+    Starved(TransformSnapshot),
+}
+
+impl SnapshotSample {
+    pub fn snapshot(&self) -> &TransformSnapshot {
+        match self {
+            SnapshotSample::Interpolated(s)
+            | SnapshotSample::Extrapolated(s)
+            | SnapshotSample::Starved(s) => s,
+        }
+    }
+}
+
+/// Small ring buffer of snapshots kept sorted by timestamp.
+#[derive(Debug, Clone)]
+pub struct SnapshotBuffer {
+    snapshots: VecDeque<TransformSnapshot>,
+    capacity: usize,
+}
+
+impl SnapshotBuffer {
+    pub const DEFAULT_CAPACITY: usize = 32;
+
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(2);
+        Self {
+            snapshots: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+    pub fn newest(&self) -> Option<&TransformSnapshot> {
+        self.snapshots.back()
+    }
+    pub fn oldest(&self) -> Option<&TransformSnapshot> {
+        self.snapshots.front()
+    }
+
+    /// Inserts a snapshot in timestamp order. A snapshot with the same timestamp as an
+    /// existing one replaces it; a snapshot older than everything in a full buffer is dropped.
+    /// Returns `true` if the snapshot was stored.
+    pub fn push(&mut self, snapshot: TransformSnapshot) -> bool {
+        let idx = self
+            .snapshots
+            .partition_point(|s| s.timestamp < snapshot.timestamp);
+
+        if let Some(existing) = self.snapshots.get_mut(idx) {
+            if existing.timestamp == snapshot.timestamp {
+                *existing = snapshot;
+                return true;
+            }
+        }
+        if self.snapshots.len() >= self.capacity {
+            if idx == 0 {
+                return false;
+            }
+            self.snapshots.pop_front();
+            self.snapshots.insert(idx - 1, snapshot);
+        } else {
+            self.snapshots.insert(idx, snapshot);
+        }
+        true
+    }
+
+    pub fn rebase(&mut self, offset: Vec3) {
+        for snapshot in &mut self.snapshots {
+            snapshot.position -= offset;
+        }
+    }
+
+    /// Drops snapshots that can no longer bracket `render_time`, keeping the one just before it.
+    pub fn prune(&mut self, render_time: f64) {
+        while self.snapshots.len() > 2 && self.snapshots[1].timestamp <= render_time {
+            self.snapshots.pop_front();
+        }
+    }
+
+    /// Samples the buffer at `render_time`, extrapolating from the newest snapshot for at
+    /// most `max_extrapolation` seconds.
+    pub fn sample(&self, render_time: f64, max_extrapolation: f64) -> Option<SnapshotSample> {
+        let newest = *self.snapshots.back()?;
+        let oldest = *self.snapshots.front()?;
+
+        if render_time <= oldest.timestamp {
+            return Some(SnapshotSample::Interpolated(oldest));
+        }
+        if render_time >= newest.timestamp {
+            let late = render_time - newest.timestamp;
+            let clamped = late.min(max_extrapolation.max(0.0));
+            let snapshot = TransformSnapshot {
+                timestamp: newest.timestamp + clamped,
+                position: newest.position + newest.velocity * clamped as f32,
+                rotation: newest.rotation,
+                velocity: newest.velocity,
+            };
+            return Some(if late > max_extrapolation {
+                SnapshotSample::Starved(snapshot)
+            } else if late == 0.0 {
+                SnapshotSample::Interpolated(newest)
+            } else {
+                SnapshotSample::Extrapolated(snapshot)
+            });
+        }
+
+        let idx = self
+            .snapshots
+            .partition_point(|s| s.timestamp <= render_time);
+        let a = self.snapshots[idx - 1];
+        let b = self.snapshots[idx];
+        let span = b.timestamp - a.timestamp;
+        let t = if span > 0.0 {
+            ((render_time - a.timestamp) / span) as f32
+        } else {
+            1.0
+        };
+        Some(SnapshotSample::Interpolated(TransformSnapshot {
+            timestamp: render_time,
+            position: a.position.lerp(b.position, t),
+            rotation: a.rotation.normalize().slerp(b.rotation.normalize(), t),
+            velocity: a.velocity.lerp(b.velocity, t),
+        }))
+    }
+}
+
+impl Default for SnapshotBuffer {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_CAPACITY)
+    }
+}
+
+/// Marks an entity as driven by remote snapshots instead of local physics.
+#[derive(Debug, Clone)]
+pub struct RemoteTransform {
+    pub buffer: SnapshotBuffer,
+    /// How far behind the newest data the entity is rendered, in seconds.
+    pub interpolation_delay: f64,
+    /// How long to keep extrapolating past the newest snapshot before freezing, in seconds.
+    pub max_extrapolation: f64,
+    starved: bool,
+}
+
+impl RemoteTransform {
+    pub const DEFAULT_INTERPOLATION_DELAY: f64 = 0.1;
+    pub const DEFAULT_MAX_EXTRAPOLATION: f64 = 0.25;
+
+    pub fn new(interpolation_delay: f64, max_extrapolation: f64) -> Self {
+        Self {
+            buffer: SnapshotBuffer::default(),
+            interpolation_delay,
+            max_extrapolation,
+            starved: false,
+        }
+    }
+    pub fn starved(&self) -> bool {
+        self.starved
+    }
+
+    pub fn push(&mut self, snapshot: TransformSnapshot) -> bool {
+        self.buffer.push(snapshot)
+    }
+    pub fn rebase(&mut self, offset: Vec3) {
+        self.buffer.rebase(offset);
+    }
+
+    /// Samples at `now - interpolation_delay`. The second value is `true` only on the frame
+    /// the entity transitions into starvation.
+    pub fn sample(&mut self, now: f64) -> Option<(SnapshotSample, bool)> {
+        let render_time = now - self.interpolation_delay;
+        let sample = self.buffer.sample(render_time, self.max_extrapolation)?;
+        self.buffer.prune(render_time);
+
+        let starved = matches!(sample, SnapshotSample::Starved(_));
+        let newly_starved = starved && !self.starved;
+        self.starved = starved;
+        Some((sample, newly_starved))
+    }
+}
+
+impl Default for RemoteTransform {
+    fn default() -> Self {
+        Self::new(
+            Self::DEFAULT_INTERPOLATION_DELAY,
+            Self::DEFAULT_MAX_EXTRAPOLATION,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(timestamp: f64, x: f32) -> TransformSnapshot {
+        TransformSnapshot {
+            timestamp,
+            position: Vec3::new(x, 0.0, 0.0),
+            rotation: Quat::from_rotation_y(x),
+            velocity: Vec3::X,
+        }
+    }
+
+    fn buffer(snapshots: &[TransformSnapshot]) -> SnapshotBuffer {
+        let mut buffer = SnapshotBuffer::default();
+        for snapshot in snapshots {
+            buffer.push(*snapshot);
+        }
+        buffer
+    }
+
+    fn timestamps(buffer: &SnapshotBuffer) -> Vec<f64> {
+        buffer.snapshots.iter().map(|s| s.timestamp).collect()
+    }
+
+    #[test]
+    fn interpolates_between_and_at_the_bracketing_snapshots() {
+        let buffer = buffer(&[at(1.0, 0.0), at(2.0, 1.0), at(3.0, 2.0)]);
+        for (time, x) in [(1.0, 0.0), (2.0, 1.0), (3.0, 2.0), (1.25, 0.25), (2.5, 1.5)] {
+            let Some(SnapshotSample::Interpolated(sample)) = buffer.sample(time, 0.25) else {
+                panic!("{} isn't interpolated", time);
+            };
+            assert!(
+                (sample.position.x - x).abs() < 1e-5,
+                "{}: {}",
+                time,
+                sample.position
+            );
+            let rotation = Quat::from_rotation_y(x);
+            assert!(sample.rotation.angle_between(rotation) < 1e-3, "{}", time);
+        }
+        // Before the oldest snapshot it holds the oldest.
+        let Some(SnapshotSample::Interpolated(sample)) = buffer.sample(0.5, 0.25) else {
+            panic!("not interpolated");
+        };
+        assert_eq!(sample.timestamp, 1.0);
+    }
+
+    #[test]
+    fn extrapolation_is_clamped_then_starves() {
+        let buffer = buffer(&[at(1.0, 0.0), at(2.0, 1.0)]);
+        let Some(SnapshotSample::Extrapolated(sample)) = buffer.sample(2.1, 0.25) else {
+            panic!("not extrapolated");
+        };
+        assert!((sample.position.x - 1.1).abs() < 1e-5);
+
+        let Some(SnapshotSample::Starved(sample)) = buffer.sample(5.0, 0.25) else {
+            panic!("not starved");
+        };
+        assert!((sample.position.x - 1.25).abs() < 1e-5);
+        assert_eq!(sample.timestamp, 2.25);
+    }
+
+    #[test]
+    fn out_of_order_and_duplicate_snapshots_stay_sorted() {
+        let mut buffer = buffer(&[at(3.0, 3.0), at(1.0, 1.0), at(2.0, 2.0)]);
+        assert_eq!(timestamps(&buffer), [1.0, 2.0, 3.0]);
+
+        // A duplicate timestamp replaces the stored snapshot.
+        assert!(buffer.push(at(2.0, 5.0)));
+        assert_eq!(timestamps(&buffer), [1.0, 2.0, 3.0]);
+        let Some(SnapshotSample::Interpolated(sample)) = buffer.sample(2.0, 0.25) else {
+            panic!("not interpolated");
+        };
+        assert_eq!(sample.position.x, 5.0);
+
+        // Full, a snapshot older than everything is dropped and a late one in the middle
+        // pushes the oldest out.
+        let mut full = SnapshotBuffer::new(3);
+        for snapshot in [at(1.0, 1.0), at(2.0, 2.0), at(4.0, 4.0)] {
+            full.push(snapshot);
+        }
+        assert!(!full.push(at(0.5, 0.5)));
+        assert!(full.push(at(3.0, 3.0)));
+        assert_eq!(timestamps(&full), [2.0, 3.0, 4.0]);
+    }
+
+    #[test]
+    fn starvation_fires_once_when_the_window_runs_out() {
+        let mut remote = RemoteTransform::new(0.1, 0.25);
+        remote.push(at(1.0, 0.0));
+        remote.push(at(2.0, 1.0));
+
+        let newly_starved = |remote: &mut RemoteTransform, now| remote.sample(now).unwrap().1;
+        // Renders at 2.25, the last moment extrapolation covers.
+        assert!(!newly_starved(&mut remote, 2.35));
+        assert!(!remote.starved());
+        assert!(newly_starved(&mut remote, 2.4));
+        assert!(remote.starved());
+        assert!(!newly_starved(&mut remote, 2.5));
+
+        // Fresh data recovers, and the next gap fires again.
+        remote.push(at(3.0, 2.0));
+        assert!(!newly_starved(&mut remote, 3.0));
+        assert!(!remote.starved());
+        assert!(newly_starved(&mut remote, 3.5));
+    }
+}
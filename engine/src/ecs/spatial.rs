@@ -0,0 +1,690 @@
+use std::collections::{HashMap, HashSet};
+
+use glam::{IVec3, Vec3};
+
+use super::{Entity, Position};
+use crate::AABB;
+
+/// Layer mask matching every layer.
+pub const ALL_LAYERS: u32 = u32::MAX;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct SpatialEntry {
+    position: Vec3,
+    min_cell: IVec3,
+    max_cell: IVec3,
+}
+
+/// Entity a ray hit, see [`crate::World::raycast`].
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RayHit {
+    pub entity: Entity,
+    /// Distance along the normalized ray direction.
+    pub distance: f32,
+    pub point: Vec3,
+}
+
+/// How much work the grid saved: `candidates` is how many entries queries actually looked
+/// at, `brute_force` how many a linear scan over every positioned entity would have.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct SpatialStats {
+    pub queries: u64,
+    pub candidates: u64,
+    pub brute_force: u64,
+    /// Entries re-bucketed by [`SpatialGrid::sync`] because they changed cells.
+    pub moves: u64,
+}
+
+/// Uniform hash grid over entity positions for broad-phase "what's near here" queries.
+/// Entities are bucketed by the cells their bounds overlap, so a large entity lives in
+/// several cells and queries dedup it.
+#[derive(Debug)]
+pub struct SpatialGrid {
+    cell_size: f32,
+    cells: HashMap<IVec3, Vec<Entity>>,
+    entries: Vec<Option<SpatialEntry>>,
+    /// Half size of each entity's bounds around its position; zero for points.
+    extents: Vec<Vec3>,
+    layers: Vec<u32>,
+    len: usize,
+    /// Behind a mutex rather than a `Cell` so the grid can be read from parallel systems.
+    stats: std::sync::Mutex<SpatialStats>,
+}
+
+impl Default for SpatialGrid {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_CELL_SIZE)
+    }
+}
+
+impl SpatialGrid {
+    pub const DEFAULT_CELL_SIZE: f32 = 4.0;
+    /// Upper bound on cells walked by a single ray, so a huge `max_dist` can't stall a frame.
+    const MAX_RAY_CELLS: usize = 4096;
+
+    pub fn new(cell_size: f32) -> Self {
+        Self {
+            cell_size: cell_size.max(f32::EPSILON),
+            cells: HashMap::new(),
+            entries: Vec::new(),
+            extents: Vec::new(),
+            layers: Vec::new(),
+            len: 0,
+            stats: Default::default(),
+        }
+    }
+    pub fn cell_size(&self) -> f32 {
+        self.cell_size
+    }
+    /// Changes the cell size and re-buckets every entity.
+    pub fn set_cell_size(&mut self, cell_size: f32) {
+        self.cell_size = cell_size.max(f32::EPSILON);
+        self.rebucket(Vec3::ZERO);
+    }
+    pub fn rebase(&mut self, offset: Vec3) {
+        self.rebucket(offset);
+    }
+    fn rebucket(&mut self, offset: Vec3) {
+        let positions: Vec<(Entity, Vec3)> = self
+            .entries
+            .iter()
+            .enumerate()
+            .filter_map(|(i, entry)| Some((Entity(i), entry.as_ref()?.position)))
+            .collect();
+        self.cells.clear();
+        self.entries.iter_mut().for_each(|entry| *entry = None);
+        self.len = 0;
+        for (entity, position) in positions {
+            self.insert(entity, position - offset);
+        }
+    }
+    /// Number of entities in the grid.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    pub fn stats(&self) -> SpatialStats {
+        *self.stats.lock().unwrap_or_else(|e| e.into_inner())
+    }
+    pub fn reset_stats(&self) {
+        *self.stats.lock().unwrap_or_else(|e| e.into_inner()) = SpatialStats::default();
+    }
+
+    pub fn cell_of(&self, point: Vec3) -> IVec3 {
+        (point / self.cell_size).floor().as_ivec3()
+    }
+
+    fn ensure_capacity(&mut self, idx: usize) {
+        let needed = idx + 1;
+        if self.entries.len() < needed {
+            self.entries.resize(needed, None);
+            self.extents.resize(needed, Vec3::ZERO);
+            self.layers.resize(needed, ALL_LAYERS);
+        }
+    }
+
+    /// Sets the bounds used for bucketing; `local` is relative to the entity's position.
+    /// Rotation isn't tracked, so the box is widened to the one containing every rotation.
+    pub fn set_extent(&mut self, entity: Entity, local: &AABB, scale: Vec3) {
+        self.ensure_capacity(entity.0);
+        let radius = (local.min.abs().max(local.max.abs()) * scale.abs()).length();
+        self.extents[entity.0] = Vec3::splat(radius);
+        if let Some(entry) = self.entries[entity.0] {
+            self.remove(entity);
+            self.insert(entity, entry.position);
+        }
+    }
+    pub fn set_layers(&mut self, entity: Entity, layers: u32) {
+        self.ensure_capacity(entity.0);
+        self.layers[entity.0] = layers;
+    }
+    pub fn layers(&self, entity: Entity) -> u32 {
+        self.layers.get(entity.0).copied().unwrap_or(ALL_LAYERS)
+    }
+    /// World-space bounds of an entity in the grid.
+    pub fn bounds(&self, entity: Entity) -> Option<AABB> {
+        let entry = self.entries.get(entity.0)?.as_ref()?;
+        let extent = self.extents[entity.0];
+        Some(AABB {
+            min: entry.position - extent,
+            max: entry.position + extent,
+        })
+    }
+
+    fn cell_range(&self, position: Vec3, extent: Vec3) -> (IVec3, IVec3) {
+        (
+            self.cell_of(position - extent),
+            self.cell_of(position + extent),
+        )
+    }
+
+    fn for_each_cell(min: IVec3, max: IVec3, mut f: impl FnMut(IVec3)) {
+        for x in min.x..=max.x {
+            for y in min.y..=max.y {
+                for z in min.z..=max.z {
+                    f(IVec3::new(x, y, z));
+                }
+            }
+        }
+    }
+
+    /// Inserts or moves `entity` to `position`, touching the cell map only when its cell
+    /// range changed.
+    pub fn insert(&mut self, entity: Entity, position: Vec3) {
+        self.ensure_capacity(entity.0);
+        let (min_cell, max_cell) = self.cell_range(position, self.extents[entity.0]);
+        if let Some(entry) = self.entries[entity.0].as_mut() {
+            if entry.min_cell == min_cell && entry.max_cell == max_cell {
+                entry.position = position;
+                return;
+            }
+            self.remove(entity);
+        }
+        let cells = &mut self.cells;
+        Self::for_each_cell(min_cell, max_cell, |cell| {
+            cells.entry(cell).or_default().push(entity)
+        });
+        self.entries[entity.0] = Some(SpatialEntry {
+            position,
+            min_cell,
+            max_cell,
+        });
+        self.len += 1;
+    }
+    pub fn remove(&mut self, entity: Entity) {
+        let Some(entry) = self.entries.get_mut(entity.0).and_then(Option::take) else {
+            return;
+        };
+        let cells = &mut self.cells;
+        Self::for_each_cell(entry.min_cell, entry.max_cell, |cell| {
+            if let Some(bucket) = cells.get_mut(&cell) {
+                bucket.retain(|e| *e != entity);
+                if bucket.is_empty() {
+                    cells.remove(&cell);
+                }
+            }
+        });
+        self.len -= 1;
+    }
+
+    /// Brings the grid in line with the position column. Entities whose position didn't
+    /// change are skipped, and moves within a cell only update the stored position.
+    pub fn sync(&mut self, positions: &[Option<Position>]) {
+        let count = positions.len().max(self.entries.len());
+        let mut stats = self.stats();
+        for i in 0..count {
+            let entity = Entity(i);
+            let stored = self.entries.get(i).copied().flatten();
+            match (positions.get(i).copied().flatten(), stored) {
+                (Some(pos), Some(entry)) if pos.0 == entry.position => {}
+                (Some(pos), entry) => {
+                    let before = entry.map(|e| (e.min_cell, e.max_cell));
+                    self.insert(entity, pos.0);
+                    let after = self.entries[i].map(|e| (e.min_cell, e.max_cell));
+                    if before != after {
+                        stats.moves += 1;
+                    }
+                }
+                (None, Some(_)) => self.remove(entity),
+                (None, None) => {}
+            }
+        }
+        *self.stats.lock().unwrap_or_else(|e| e.into_inner()) = stats;
+    }
+
+    fn record(&self, candidates: usize) {
+        let mut stats = self.stats.lock().unwrap_or_else(|e| e.into_inner());
+        stats.queries += 1;
+        stats.candidates += candidates as u64;
+        stats.brute_force += self.len as u64;
+    }
+
+    fn matches(&self, entity: Entity, layers: u32) -> bool {
+        self.layers[entity.0] & layers != 0
+    }
+
+    /// Entities whose bounds overlap `aabb`, each once, in no particular order.
+    pub fn query_aabb(&self, aabb: &AABB, layers: u32) -> impl Iterator<Item = Entity> {
+        let mut seen = HashSet::new();
+        let mut found = Vec::new();
+        let mut candidates = 0;
+        Self::for_each_cell(self.cell_of(aabb.min), self.cell_of(aabb.max), |cell| {
+            for &entity in self.cells.get(&cell).into_iter().flatten() {
+                candidates += 1;
+                if !seen.insert(entity) || !self.matches(entity, layers) {
+                    continue;
+                }
+                if let Some(bounds) = self.bounds(entity) {
+                    if overlaps(&bounds, aabb) {
+                        found.push(entity);
+                    }
+                }
+            }
+        });
+        self.record(candidates);
+        found.into_iter()
+    }
+
+    /// Entities whose bounds come within `radius` of `center`.
+    pub fn query_sphere(
+        &self,
+        center: Vec3,
+        radius: f32,
+        layers: u32,
+    ) -> impl Iterator<Item = Entity> {
+        let r = Vec3::splat(radius.max(0.0));
+        let aabb = AABB {
+            min: center - r,
+            max: center + r,
+        };
+        let found: Vec<Entity> = self
+            .query_aabb(&aabb, layers)
+            .filter(|entity| {
+                self.bounds(*entity).is_some_and(|b| {
+                    center.clamp(b.min, b.max).distance_squared(center) <= radius * radius
+                })
+            })
+            .collect();
+        found.into_iter()
+    }
+
+    /// Entities whose bounds the ray hits within `max_dist`, nearest first, with the hit
+    /// distance. Walks the cells the ray passes through rather than the whole grid.
+    pub fn query_ray(
+        &self,
+        origin: Vec3,
+        dir: Vec3,
+        max_dist: f32,
+        layers: u32,
+    ) -> impl Iterator<Item = (Entity, f32)> {
+        let mut seen = HashSet::new();
+        let mut hits = Vec::new();
+        let mut candidates = 0;
+        let dir = dir.normalize_or_zero();
+        if dir != Vec3::ZERO {
+            for cell in self.ray_cells(origin, dir, max_dist) {
+                for &entity in self.cells.get(&cell).into_iter().flatten() {
+                    candidates += 1;
+                    if !seen.insert(entity) || !self.matches(entity, layers) {
+                        continue;
+                    }
+                    let hit = self
+                        .bounds(entity)
+                        .and_then(|b| ray_aabb(origin, dir, &b))
+                        .filter(|t| *t <= max_dist);
+                    if let Some(t) = hit {
+                        hits.push((entity, t));
+                    }
+                }
+            }
+        }
+        self.record(candidates);
+        hits.sort_by(|a, b| a.1.total_cmp(&b.1));
+        hits.into_iter()
+    }
+    /// Nearest entity within `max_dist` that `distance`, the exact test along the
+    /// normalized ray, says the ray hits. Candidates come from [`SpatialGrid::query_ray`];
+    /// since their grid bounds contain whatever `distance` tests, the walk stops at the
+    /// first candidate entered beyond the best hit.
+    pub fn raycast(
+        &self,
+        origin: Vec3,
+        dir: Vec3,
+        max_dist: f32,
+        layers: u32,
+        distance: impl Fn(Entity) -> Option<f32>,
+    ) -> Option<RayHit> {
+        let dir = dir.normalize_or_zero();
+        if dir == Vec3::ZERO {
+            return None;
+        }
+        let mut nearest: Option<RayHit> = None;
+        for (entity, enter) in self.query_ray(origin, dir, max_dist, layers) {
+            if nearest.is_some_and(|hit| enter > hit.distance) {
+                break;
+            }
+            let Some(t) = distance(entity).filter(|t| *t <= max_dist) else {
+                continue;
+            };
+            if nearest.map_or(true, |hit| t < hit.distance) {
+                nearest = Some(RayHit {
+                    entity,
+                    distance: t,
+                    point: origin + dir * t,
+                });
+            }
+        }
+        nearest
+    }
+
+    /// Cells a ray passes through, in order (3D DDA).
+    pub fn ray_cells(&self, origin: Vec3, dir: Vec3, max_dist: f32) -> Vec<IVec3> {
+        let mut cells = Vec::new();
+        let mut cell = self.cell_of(origin);
+        let end = self.cell_of(origin + dir * max_dist.max(0.0));
+        let step = IVec3::new(
+            dir.x.signum() as i32,
+            dir.y.signum() as i32,
+            dir.z.signum() as i32,
+        );
+        let boundary = |c: i32, s: i32| (c + (s > 0) as i32) as f32 * self.cell_size;
+        let axis = |o: f32, d: f32, c: i32, s: i32| {
+            if d == 0.0 {
+                (f32::INFINITY, f32::INFINITY)
+            } else {
+                ((boundary(c, s) - o) / d, self.cell_size / d.abs())
+            }
+        };
+        let (mut tx, dx) = axis(origin.x, dir.x, cell.x, step.x);
+        let (mut ty, dy) = axis(origin.y, dir.y, cell.y, step.y);
+        let (mut tz, dz) = axis(origin.z, dir.z, cell.z, step.z);
+
+        loop {
+            cells.push(cell);
+            if cell == end || cells.len() >= Self::MAX_RAY_CELLS {
+                break;
+            }
+            let t = if tx <= ty && tx <= tz {
+                cell.x += step.x;
+                tx += dx;
+                tx - dx
+            } else if ty <= tz {
+                cell.y += step.y;
+                ty += dy;
+                ty - dy
+            } else {
+                cell.z += step.z;
+                tz += dz;
+                tz - dz
+            };
+            if t > max_dist {
+                break;
+            }
+        }
+        cells
+    }
+}
+
+fn overlaps(a: &AABB, b: &AABB) -> bool {
+    a.min.cmple(b.max).all() && a.max.cmpge(b.min).all()
+}
+
+/// Slab test; the distance along `dir` where the ray enters `aabb`, 0 if it starts inside.
+pub(crate) fn ray_aabb(origin: Vec3, dir: Vec3, aabb: &AABB) -> Option<f32> {
+    let inv = dir.recip();
+    let t1 = (aabb.min - origin) * inv;
+    let t2 = (aabb.max - origin) * inv;
+    let near = t1.min(t2).max_element();
+    let far = t1.max(t2).min_element();
+    (far >= near.max(0.0)).then_some(near.max(0.0))
+}
+
+/// The distance along `dir` where the ray enters the sphere through `aabb`'s corners.
+pub(crate) fn ray_bounding_sphere(origin: Vec3, dir: Vec3, aabb: &AABB) -> Option<f32> {
+    let center = (aabb.min + aabb.max) * 0.5;
+    let radius = (aabb.max - aabb.min).length() * 0.5;
+    crate::camera::ray_intersects_ray_sphere(origin, dir, center, radius)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Grid holding a box of half size `extent` around each position, entity `i` for the
+    /// `i`th.
+    fn boxes(boxes: &[(Vec3, Vec3)]) -> (SpatialGrid, Vec<AABB>) {
+        let mut grid = SpatialGrid::new(4.0);
+        let mut bounds = Vec::new();
+        for (i, &(position, extent)) in boxes.iter().enumerate() {
+            let local = AABB {
+                min: -extent,
+                max: extent,
+            };
+            grid.set_extent(Entity(i), &local, Vec3::ONE);
+            grid.insert(Entity(i), position);
+            bounds.push(AABB {
+                min: position - extent,
+                max: position + extent,
+            });
+        }
+        (grid, bounds)
+    }
+
+    fn cast(grid: &SpatialGrid, bounds: &[AABB], origin: Vec3, dir: Vec3) -> Option<RayHit> {
+        grid.raycast(origin, dir, 50.0, ALL_LAYERS, |entity| {
+            ray_aabb(origin, dir.normalize(), &bounds[entity.0])
+        })
+    }
+
+    #[test]
+    fn raycast_returns_the_nearest_hit() {
+        let half = Vec3::splat(0.5);
+        let (grid, bounds) = boxes(&[
+            (Vec3::new(20.0, 0.0, 0.0), half),
+            (Vec3::new(5.0, 0.0, 0.0), half),
+            (Vec3::new(10.0, 0.0, 0.0), half),
+        ]);
+        let hit = cast(&grid, &bounds, Vec3::ZERO, Vec3::X).unwrap();
+        assert_eq!(hit.entity, Entity(1));
+        assert!((hit.distance - 4.5).abs() < 1e-5);
+        assert!(hit.point.abs_diff_eq(Vec3::new(4.5, 0.0, 0.0), 1e-5));
+
+        // From the other side the order flips.
+        let hit = cast(&grid, &bounds, Vec3::new(30.0, 0.0, 0.0), Vec3::NEG_X).unwrap();
+        assert_eq!(hit.entity, Entity(0));
+
+        // Skipping the nearest, e.g. because it isn't renderable, finds the next.
+        let hit = grid.raycast(Vec3::ZERO, Vec3::X, 50.0, ALL_LAYERS, |entity| {
+            (entity != Entity(1)).then(|| ray_aabb(Vec3::ZERO, Vec3::X, &bounds[entity.0]))?
+        });
+        assert_eq!(hit.map(|hit| hit.entity), Some(Entity(2)));
+    }
+
+    #[test]
+    fn raycast_misses() {
+        let (grid, bounds) = boxes(&[(Vec3::new(10.0, 0.0, 0.0), Vec3::splat(0.5))]);
+        assert!(cast(&grid, &bounds, Vec3::ZERO, Vec3::NEG_X).is_none());
+        assert!(cast(&grid, &bounds, Vec3::new(0.0, 2.0, 0.0), Vec3::X).is_none());
+        assert!(cast(&grid, &bounds, Vec3::ZERO, Vec3::ZERO).is_none());
+        let short = grid.raycast(Vec3::ZERO, Vec3::X, 5.0, ALL_LAYERS, |entity| {
+            ray_aabb(Vec3::ZERO, Vec3::X, &bounds[entity.0])
+        });
+        assert!(short.is_none());
+    }
+
+    #[test]
+    fn bounding_sphere_reaches_the_corners() {
+        // Tall and thin: the radius has to come from the diagonal, not the x extent.
+        let tall = AABB {
+            min: Vec3::new(9.5, -4.0, -0.5),
+            max: Vec3::new(10.5, 4.0, 0.5),
+        };
+        let t = ray_bounding_sphere(Vec3::new(0.0, 3.5, 0.0), Vec3::X, &tall).unwrap();
+        assert!(t < 10.0);
+        assert!(ray_bounding_sphere(Vec3::new(0.0, 5.0, 0.0), Vec3::X, &tall).is_none());
+    }
+
+    /// Deterministic pseudo-random numbers in [0, 1).
+    fn sequence(mut seed: u32) -> impl FnMut() -> f32 {
+        move || {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            (seed >> 8) as f32 / (1 << 24) as f32
+        }
+    }
+
+    fn sorted(entities: impl Iterator<Item = Entity>) -> Vec<usize> {
+        let mut ids: Vec<usize> = entities.map(|e| e.0).collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    #[test]
+    fn moving_across_a_cell_boundary_rebuckets() {
+        let mut grid = SpatialGrid::new(4.0);
+        let cell = |x: f32| AABB {
+            min: Vec3::new(x, 0.0, 0.0),
+            max: Vec3::new(x + 3.9, 3.9, 3.9),
+        };
+        let mut positions = vec![Some(Position(Vec3::new(3.5, 1.0, 1.0)))];
+        grid.sync(&positions);
+        assert_eq!(sorted(grid.query_aabb(&cell(0.0), ALL_LAYERS)), [0]);
+        grid.reset_stats();
+
+        // Within the cell only the stored position changes.
+        positions[0] = Some(Position(Vec3::new(3.9, 1.0, 1.0)));
+        grid.sync(&positions);
+        assert_eq!(grid.stats().moves, 0);
+        assert_eq!(grid.bounds(Entity(0)).unwrap().min.x, 3.9);
+
+        positions[0] = Some(Position(Vec3::new(4.1, 1.0, 1.0)));
+        grid.sync(&positions);
+        assert_eq!(grid.stats().moves, 1);
+        assert!(sorted(grid.query_aabb(&cell(0.0), ALL_LAYERS)).is_empty());
+        assert_eq!(sorted(grid.query_aabb(&cell(4.0), ALL_LAYERS)), [0]);
+        assert_eq!(grid.cells.len(), 1);
+
+        positions[0] = None;
+        grid.sync(&positions);
+        assert!(grid.is_empty() && grid.cells.is_empty());
+    }
+
+    #[test]
+    fn entities_spanning_cells_are_found_once() {
+        // Widened to the rotation-proof radius, 2.6, the bounds run from 1.4 to 6.6 on each
+        // axis: cells 0 and 1 of each.
+        let (mut grid, _) = boxes(&[(Vec3::splat(4.0), Vec3::splat(1.5))]);
+        assert_eq!(grid.cells.len(), 8);
+        let everything = AABB {
+            min: Vec3::splat(-10.0),
+            max: Vec3::splat(10.0),
+        };
+        assert_eq!(sorted(grid.query_aabb(&everything, ALL_LAYERS)), [0]);
+        assert_eq!(
+            sorted(grid.query_sphere(Vec3::splat(4.0), 8.0, ALL_LAYERS)),
+            [0]
+        );
+        let hits: Vec<_> = grid
+            .query_ray(
+                Vec3::new(-5.0, 1.5, 1.5),
+                Vec3::new(1.0, 0.5, 0.5),
+                40.0,
+                ALL_LAYERS,
+            )
+            .collect();
+        assert_eq!(hits.len(), 1);
+
+        grid.remove(Entity(0));
+        assert!(grid.cells.is_empty());
+    }
+
+    #[test]
+    fn ray_walks_cover_every_cell_the_segment_crosses() {
+        let grid = SpatialGrid::new(4.0);
+        let mut next = sequence(0x1234_5678);
+        for _ in 0..200 {
+            let origin = (Vec3::new(next(), next(), next()) - 0.5) * 40.0;
+            let dir = (Vec3::new(next(), next(), next()) - 0.5).normalize_or_zero();
+            if dir == Vec3::ZERO {
+                continue;
+            }
+            let length = next() * 60.0;
+            let cells = grid.ray_cells(origin, dir, length);
+
+            assert_eq!(cells[0], grid.cell_of(origin));
+            for pair in cells.windows(2) {
+                let step = (pair[1] - pair[0]).abs();
+                assert_eq!(step.element_sum(), 1, "{:?} to {:?}", pair[0], pair[1]);
+            }
+            // Dense samples along the segment, kept off the boundaries the DDA may round
+            // either way.
+            for i in 0..=1000 {
+                let point = origin + dir * length * i as f32 / 1000.0;
+                let cell = grid.cell_of(point);
+                let near_boundary = (point / grid.cell_size() - cell.as_vec3())
+                    .to_array()
+                    .iter()
+                    .any(|f| *f < 1e-3 || *f > 1.0 - 1e-3);
+                if !near_boundary {
+                    assert!(cells.contains(&cell), "{:?} missing at {:?}", cell, point);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn queries_match_brute_force() {
+        let mut next = sequence(0x9e37_79b9);
+        let mut random_vec = |scale: f32| (Vec3::new(next(), next(), next()) - 0.5) * scale;
+        let count = 300;
+        let mut layers = Vec::with_capacity(count);
+        let mut grid = SpatialGrid::new(3.0);
+        let mut positions: Vec<Option<Position>> = Vec::with_capacity(count);
+        for i in 0..count {
+            let extent = random_vec(4.0).abs();
+            grid.set_extent(
+                Entity(i),
+                &AABB {
+                    min: -extent,
+                    max: extent,
+                },
+                Vec3::ONE,
+            );
+            layers.push(1u32 << (i % 3));
+            grid.set_layers(Entity(i), layers[i]);
+            positions.push(Some(Position(random_vec(60.0))));
+        }
+
+        for round in 0..4 {
+            // Move some, drop some, bring some back.
+            for (i, position) in positions.iter_mut().enumerate() {
+                if (i + round) % 7 == 0 {
+                    *position = None;
+                } else if position.is_none() || (i + round) % 3 == 0 {
+                    *position = Some(Position(random_vec(60.0)));
+                }
+            }
+            grid.sync(&positions);
+            let bounds: Vec<Option<AABB>> = (0..count).map(|i| grid.bounds(Entity(i))).collect();
+            let brute = |keep: &dyn Fn(&AABB) -> bool, mask: u32| -> Vec<usize> {
+                (0..count)
+                    .filter(|&i| layers[i] & mask != 0)
+                    .filter(|&i| bounds[i].as_ref().is_some_and(keep))
+                    .collect()
+            };
+
+            for _ in 0..50 {
+                let mask = [ALL_LAYERS, 1, 2 | 4][round % 3];
+                let center = random_vec(70.0);
+                let half = random_vec(20.0).abs();
+                let query = AABB {
+                    min: center - half,
+                    max: center + half,
+                };
+                assert_eq!(
+                    sorted(grid.query_aabb(&query, mask)),
+                    brute(&|b| overlaps(b, &query), mask)
+                );
+
+                let radius = half.x;
+                let near = |b: &AABB| center.clamp(b.min, b.max).distance(center) <= radius;
+                assert_eq!(
+                    sorted(grid.query_sphere(center, radius, mask)),
+                    brute(&near, mask)
+                );
+
+                let dir = random_vec(2.0).normalize_or_zero();
+                if dir == Vec3::ZERO {
+                    continue;
+                }
+                let hit = |b: &AABB| ray_aabb(center, dir, b).is_some_and(|t| t <= 40.0);
+                let hits: Vec<(Entity, f32)> = grid.query_ray(center, dir, 40.0, mask).collect();
+                assert!(hits.windows(2).all(|pair| pair[0].1 <= pair[1].1));
+                assert_eq!(sorted(hits.iter().map(|hit| hit.0)), brute(&hit, mask));
+            }
+        }
+    }
+}
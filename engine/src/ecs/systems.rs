@@ -0,0 +1,282 @@
+//! The world tick as declared systems, see [`Schedule`]. Despawning expired entities and
+//! anything else that touches every column stays outside the schedule, in [`crate::World`].
+
+use glam::Vec3;
+
+use super::{
+    integrate, par_chunks, update_animators, update_lifetimes, Access, Animator, Collider,
+    DespawnWhenFar, ExpiryReason, FadeOutThenDespawn, Lifetime, LifetimeColumns, NavAgent,
+    Navigation, Position, PreviousPose, RemoteTransform, Rotation, Scale, Schedule, SnapshotSample,
+    SpatialGrid, SystemContext, Tint, Transform, Velocity,
+};
+use crate::{log_warning, Entity, Terrain, WorldEvent};
+
+/// Everything a running world does per fixed step.
+///
+/// Stages come out as: pose snapshot alongside navigation and animators; physics; remote
+/// transforms; spatial sync; lifetimes.
+pub fn tick_schedule() -> Schedule {
+    Schedule::new()
+        .with("poses", poses_access(), poses)
+        .with("navigation", navigation_access(), navigation)
+        .with("animators", animators_access(), animators)
+        .with("physics", physics_access(), physics)
+        .with("remote_transforms", remote_access(), remote_transforms)
+        .with("spatial_sync", spatial_access(), spatial_sync)
+        .with("lifetimes", lifetimes_access(), lifetimes)
+}
+
+/// What a running world does once per frame, after its fixed steps: blending transforms
+/// between the last two steps and batching instances, in one stage.
+pub fn frame_schedule() -> Schedule {
+    Schedule::new()
+        .with("transforms", transforms_access(), transforms)
+        .with("instances", instances_access(), instances)
+}
+
+/// What still runs while the world is paused: keeping derived data current.
+pub fn paused_schedule() -> Schedule {
+    Schedule::new()
+        .with("spatial_sync", spatial_access(), spatial_sync)
+        .with("transforms", transforms_access(), transforms)
+        .with("instances", instances_access(), instances)
+}
+
+fn poses_access() -> Access {
+    Access::new()
+        .reads::<Position>()
+        .reads::<Rotation>()
+        .writes::<PreviousPose>()
+}
+fn poses(ctx: &SystemContext) {
+    snapshot_poses(
+        &ctx.read::<Position>(),
+        &ctx.read::<Rotation>(),
+        &mut ctx.write::<PreviousPose>(),
+    );
+}
+
+fn navigation_access() -> Access {
+    Access::new()
+        .reads::<Position>()
+        .writes::<Navigation>()
+        .writes::<Terrain>()
+        .writes::<NavAgent>()
+        .writes::<Velocity>()
+}
+fn navigation(ctx: &SystemContext) {
+    let mut terrain = ctx.write::<Terrain>();
+    let mut agents = ctx.write::<NavAgent>();
+    let mut velocities = ctx.write::<Velocity>();
+    ctx.write::<Navigation>().update(
+        ctx.tick().dt,
+        &mut terrain,
+        &mut agents,
+        &ctx.read::<Position>(),
+        &mut velocities,
+    );
+}
+
+fn animators_access() -> Access {
+    Access::new().writes::<Animator>()
+}
+fn animators(ctx: &SystemContext) {
+    update_animators(ctx.tick().dt, &mut ctx.write::<Animator>());
+}
+
+fn physics_access() -> Access {
+    Access::new()
+        .reads::<RemoteTransform>()
+        .reads::<Terrain>()
+        .writes::<Position>()
+        .writes::<Velocity>()
+        .writes::<Collider>()
+        .writes::<WorldEvent>()
+}
+fn physics(ctx: &SystemContext) {
+    let tick = ctx.tick();
+    let landings = integrate(
+        &mut ctx.write::<Position>(),
+        &mut ctx.write::<Velocity>(),
+        &mut ctx.write::<Collider>(),
+        &ctx.read::<RemoteTransform>(),
+        &ctx.read::<Terrain>(),
+        tick.medium,
+        tick.dt,
+    );
+    ctx.write::<WorldEvent>().extend(
+        landings
+            .into_iter()
+            .map(|(entity, speed)| WorldEvent::Landed(entity, speed)),
+    );
+}
+
+fn remote_access() -> Access {
+    Access::new()
+        .writes::<RemoteTransform>()
+        .writes::<Position>()
+        .writes::<Velocity>()
+        .writes::<Rotation>()
+        .writes::<WorldEvent>()
+}
+fn remote_transforms(ctx: &SystemContext) {
+    let starved = sample_remote_transforms(
+        ctx.tick().elapsed,
+        &mut ctx.write::<RemoteTransform>(),
+        &mut ctx.write::<Position>(),
+        &mut ctx.write::<Velocity>(),
+        &mut ctx.write::<Rotation>(),
+    );
+    ctx.write::<WorldEvent>()
+        .extend(starved.into_iter().map(WorldEvent::SnapshotStarved));
+}
+
+fn spatial_access() -> Access {
+    Access::new().reads::<Position>().writes::<SpatialGrid>()
+}
+fn spatial_sync(ctx: &SystemContext) {
+    ctx.write::<SpatialGrid>().sync(&ctx.read::<Position>());
+}
+
+fn lifetimes_access() -> Access {
+    Access::new()
+        .reads::<Position>()
+        .reads::<SpatialGrid>()
+        .writes::<Lifetime>()
+        .writes::<DespawnWhenFar>()
+        .writes::<FadeOutThenDespawn>()
+        .writes::<Tint>()
+        .writes::<ExpiryReason>()
+}
+fn lifetimes(ctx: &SystemContext) {
+    let tick = ctx.tick();
+    let expired = update_lifetimes(
+        tick.dt,
+        tick.camera_pos,
+        LifetimeColumns {
+            lifetimes: &mut ctx.write::<Lifetime>(),
+            far: &mut ctx.write::<DespawnWhenFar>(),
+            fades: &mut ctx.write::<FadeOutThenDespawn>(),
+            tints: &mut ctx.write::<Tint>(),
+        },
+        &ctx.read::<Position>(),
+        &ctx.read::<SpatialGrid>(),
+    );
+    ctx.write::<ExpiryReason>().extend(expired);
+}
+
+fn transforms_access() -> Access {
+    Access::new()
+        .reads::<Position>()
+        .reads::<Rotation>()
+        .reads::<Scale>()
+        .reads::<PreviousPose>()
+        .writes::<Transform>()
+}
+fn transforms(ctx: &SystemContext) {
+    propagate_transforms(
+        &ctx.read::<Position>(),
+        &ctx.read::<Rotation>(),
+        &ctx.read::<Scale>(),
+        &ctx.read::<PreviousPose>(),
+        ctx.tick().alpha,
+        &mut ctx.write::<Transform>(),
+    );
+}
+
+fn instances_access() -> Access {
+    Access::new().writes::<Terrain>()
+}
+fn instances(ctx: &SystemContext) {
+    let tick = ctx.tick();
+    ctx.write::<Terrain>()
+        .update_instance_buffer(tick.queue, tick.device, &tick.frustum);
+}
+
+/// Records every entity's pose before a fixed step moves it. Entities without a rotation
+/// blend from identity, as their transform has none.
+pub fn snapshot_poses(
+    positions: &[Option<Position>],
+    rotations: &[Option<Rotation>],
+    previous: &mut [Option<PreviousPose>],
+) {
+    for (i, previous) in previous.iter_mut().enumerate() {
+        *previous = positions.get(i).copied().flatten().map(|pos| PreviousPose {
+            position: pos.0,
+            rotation: rotations
+                .get(i)
+                .copied()
+                .flatten()
+                .map_or(glam::Quat::IDENTITY, |rot| rot.0),
+        });
+    }
+}
+
+/// Rebuilds the transform of every entity with a position, rotation and scale, in parallel
+/// over entity chunks. Entities with a [`PreviousPose`] are placed `alpha` of the way from
+/// it to their current pose.
+pub fn propagate_transforms(
+    positions: &[Option<Position>],
+    rotations: &[Option<Rotation>],
+    scales: &[Option<Scale>],
+    previous: &[Option<PreviousPose>],
+    alpha: f32,
+    transforms: &mut [Option<Transform>],
+) {
+    par_chunks(transforms, |first, chunk| {
+        for (offset, transform) in chunk.iter_mut().enumerate() {
+            let i = first + offset;
+            if let (Some(Some(pos)), Some(Some(rot)), Some(Some(scale))) =
+                (positions.get(i), rotations.get(i), scales.get(i))
+            {
+                *transform = Some(match previous.get(i).copied().flatten() {
+                    Some(previous) if alpha < 1.0 => {
+                        let (pos, rot) = previous.blend(pos, rot, alpha);
+                        Transform::from_components(&pos, &rot, scale)
+                    }
+                    _ => Transform::from_components(pos, rot, scale),
+                });
+            }
+        }
+        Vec::<()>::new()
+    });
+}
+
+/// Writes interpolated snapshot state into the position/rotation/velocity columns of every
+/// entity with a [`RemoteTransform`]. Returns the entities that starved this tick.
+pub fn sample_remote_transforms(
+    now: f64,
+    remote: &mut [Option<RemoteTransform>],
+    positions: &mut [Option<Position>],
+    velocities: &mut [Option<Velocity>],
+    rotations: &mut [Option<Rotation>],
+) -> Vec<Entity> {
+    let mut starved = Vec::new();
+    for (i, remote) in remote.iter_mut().enumerate() {
+        let Some(remote) = remote.as_mut() else {
+            continue;
+        };
+        let Some((sample, newly_starved)) = remote.sample(now) else {
+            continue;
+        };
+        let snapshot = sample.snapshot();
+        let velocity = match sample {
+            SnapshotSample::Starved(_) => Vec3::ZERO,
+            _ => snapshot.velocity,
+        };
+        if let Some(slot) = positions.get_mut(i) {
+            *slot = Some(Position(snapshot.position));
+        }
+        if let Some(slot) = velocities.get_mut(i) {
+            *slot = Some(Velocity(velocity));
+        }
+        if let Some(slot) = rotations.get_mut(i) {
+            *slot = Some(Rotation(snapshot.rotation));
+        }
+        if newly_starved {
+            log_warning!("Snapshot starved: {}", i);
+            starved.push(Entity(i));
+        }
+    }
+    starved
+}
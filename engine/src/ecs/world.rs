@@ -1,10 +1,21 @@
-use super::{Physics, Position, Renderable, Rotation, Scale, Transform, Velocity};
+use super::{
+    frame_schedule, paused_schedule, propagate_transforms, raycast_terrain,
+    sample_remote_transforms, tick_schedule, update_lifetimes, Animator, AudioSource, AutoLoad,
+    Billboard, Collider, DamageResult, Damageable, DeathBehavior, DespawnWhenFar, ExpiryReason,
+    FadeOutThenDespawn, FixedTimestep, Health, InstanceBatcher, Layers, Lifetime, LifetimeColumns,
+    LoadedScene, NavAgent, Navigation, ParticleEmitter, Physics, Placement, Position, PreviousPose,
+    RayHit, Rebase, RebaseHooks, RebasePolicy, RemoteTransform, Renderable, Rotation, SavedEntity,
+    SavedScene, Scale, SceneEntry, SceneFile, Schedule, ScriptBehavior, SpatialGrid, Tick, Tint,
+    Transform, TransformSnapshot, Velocity, WorldOrigin, WorldView, ALL_LAYERS,
+};
 use crate::{
-    camera::Camera, log_error, CacheKey, EngineError, Entity, Medium, ModelManager, Terrain,
-    WorldProjection,
+    camera::Camera, log_debug, log_error, log_info, log_warning, CacheKey, CacheStorage,
+    DepthVariant, EngineError, Entity, Medium, ModelManager, RenderBindGroupLayouts,
+    RenderSettings, Terrain, Vertex, VertexInstance, WorldEvent, WorldProjection, AABB,
 };
-use glam::Vec3;
+use glam::{IVec3, Quat, Vec3};
 use pollster::FutureExt;
+use std::{collections::HashMap, path::Path, sync::RwLock};
 
 pub static RUNNING: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(true);
 
@@ -31,9 +42,50 @@ pub struct World {
     pub rotations: Vec<Option<Rotation>>,
     pub scales: Vec<Option<Scale>>,
     pub transforms: Vec<Option<Transform>>,
-    projection: WorldProjection,
+    /// Poses before the last fixed step.
+    pub previous_poses: Vec<Option<PreviousPose>>,
+    pub remote_transforms: Vec<Option<RemoteTransform>>,
+    pub nav_agents: Vec<Option<NavAgent>>,
+    pub navigation: Navigation,
+    pub tints: Vec<Option<Tint>>,
+    pub lifetimes: Vec<Option<Lifetime>>,
+    pub despawn_when_far: Vec<Option<DespawnWhenFar>>,
+    pub fades: Vec<Option<FadeOutThenDespawn>>,
+    pub healths: Vec<Option<Health>>,
+    pub damageables: Vec<Option<Damageable>>,
+    pub death_behaviors: Vec<Option<DeathBehavior>>,
+    pub scripts: Vec<Option<ScriptBehavior>>,
+    pub animators: Vec<Option<Animator>>,
+    pub particle_emitters: Vec<Option<ParticleEmitter>>,
+    pub audio_sources: Vec<Option<AudioSource>>,
+    pub billboards: Vec<Option<Billboard>>,
+    pub layers: Vec<Option<Layers>>,
+    pub bounds: Vec<Option<AABB>>,
+    /// For [`World::save_scene`].
+    model_paths: HashMap<CacheKey, String>,
+    spatial: SpatialGrid,
+    paused: bool,
+    /// Set by [`World::step`]: the next paused update runs one fixed step.
+    step_requested: bool,
+    selected: Option<Entity>,
+    scene: Option<LoadedScene>,
+    projection: Option<WorldProjection>,
+    instance_batcher: InstanceBatcher,
     entity_count: usize,
     pub terrain: Terrain,
+    elapsed: f64,
+    origin: WorldOrigin,
+    rebase_policy: RebasePolicy,
+    rebase_hooks: RebaseHooks,
+    events: Vec<WorldEvent>,
+    schedule: Schedule,
+    frame_schedule: Schedule,
+    paused_schedule: Schedule,
+    fixed_timestep: FixedTimestep,
+    /// Real time not yet simulated, less than one step after an update.
+    accumulator: f64,
+    alpha: f32,
+    tick_error: Option<String>,
 }
 
 impl World {
@@ -59,28 +111,143 @@ impl World {
             "pure-sky.hdr",
             depth_stencil_state,
         )?;
+        Ok(Self::with_projection(Some(projection)))
+    }
+    /// Needs no GPU; draws nothing until a sky projection is set.
+    pub fn empty() -> Self {
+        Self::with_projection(None)
+    }
+    fn with_projection(projection: Option<WorldProjection>) -> Self {
         let terrain = Terrain::new(Medium::Ground);
-        Ok(Self {
+        Self {
             physics: Physics::new(),
             renderables: Vec::new(),
             rotations: Vec::new(),
             scales: Vec::new(),
             transforms: Vec::new(),
+            previous_poses: Vec::new(),
+            remote_transforms: Vec::new(),
+            nav_agents: Vec::new(),
+            navigation: Navigation::new(),
+            tints: Vec::new(),
+            lifetimes: Vec::new(),
+            despawn_when_far: Vec::new(),
+            fades: Vec::new(),
+            healths: Vec::new(),
+            damageables: Vec::new(),
+            death_behaviors: Vec::new(),
+            scripts: Vec::new(),
+            animators: Vec::new(),
+            particle_emitters: Vec::new(),
+            audio_sources: Vec::new(),
+            billboards: Vec::new(),
+            layers: Vec::new(),
+            bounds: Vec::new(),
+            model_paths: HashMap::new(),
+            spatial: SpatialGrid::default(),
+            paused: false,
+            step_requested: false,
+            selected: None,
+            scene: None,
             projection,
+            instance_batcher: InstanceBatcher::default(),
             entity_count: 0,
             terrain,
-        })
+            elapsed: 0.0,
+            origin: WorldOrigin::default(),
+            rebase_policy: RebasePolicy::default(),
+            rebase_hooks: RebaseHooks::default(),
+            events: Vec::new(),
+            schedule: tick_schedule(),
+            frame_schedule: frame_schedule(),
+            paused_schedule: paused_schedule(),
+            fixed_timestep: FixedTimestep::default(),
+            accumulator: 0.0,
+            alpha: 1.0,
+            tick_error: None,
+        }
     }
     pub fn entity_count(&self) -> usize {
         self.entity_count
     }
     pub fn set_projection(&mut self, projection: WorldProjection) {
-        self.projection = projection;
+        self.projection = Some(projection);
+    }
+    pub fn projection(&self) -> Option<&WorldProjection> {
+        self.projection.as_ref()
     }
-    pub fn projection(&self) -> &WorldProjection {
-        &self.projection
+    pub fn instance_batches(&self) -> &InstanceBatcher {
+        &self.instance_batcher
     }
 
+    pub fn spatial(&self) -> &SpatialGrid {
+        &self.spatial
+    }
+    /// Tests cached model bounds, or the grid bounding sphere without them.
+    pub fn raycast(&self, origin: Vec3, dir: Vec3, max_dist: f32) -> Option<RayHit> {
+        let dir = dir.normalize_or_zero();
+        self.spatial
+            .raycast(origin, dir, max_dist, ALL_LAYERS, |entity| {
+                self.renderables.get(entity.0)?.as_ref()?;
+                self.ray_distance(entity, origin, dir)
+            })
+    }
+    /// Stops at solid blocks and [`Collider::blocks_camera`] boxes.
+    pub fn camera_obstruction(&self, origin: Vec3, dir: Vec3, max_dist: f32) -> Option<f32> {
+        let dir = dir.normalize_or_zero();
+        if dir == Vec3::ZERO {
+            return None;
+        }
+        let terrain = raycast_terrain(&self.terrain, origin, dir, max_dist);
+        let colliders = self.physics.colliders.iter().enumerate();
+        let blockers = colliders.filter_map(|(i, collider)| {
+            let collider = collider.as_ref().filter(|c| c.blocks_camera)?;
+            let Position(position) = self.physics.positions.get(i).copied().flatten()?;
+            let aabb = AABB {
+                min: collider.min(position),
+                max: collider.max(position),
+            };
+            super::spatial::ray_aabb(origin, dir, &aabb).filter(|t| *t <= max_dist)
+        });
+        terrain.into_iter().chain(blockers).min_by(f32::total_cmp)
+    }
+    fn ray_distance(&self, entity: Entity, origin: Vec3, dir: Vec3) -> Option<f32> {
+        let i = entity.0;
+        let position = self.physics.positions.get(i)?.as_ref()?;
+        let Some(bounds) = self.bounds.get(i).copied().flatten() else {
+            let grid = self.spatial.bounds(entity)?;
+            return super::spatial::ray_bounding_sphere(origin, dir, &grid);
+        };
+        let default_rotation = Rotation::zero();
+        let default_scale = Scale::one();
+        let rotation = self
+            .rotations
+            .get(i)
+            .and_then(Option::as_ref)
+            .unwrap_or(&default_rotation);
+        let scale = self
+            .scales
+            .get(i)
+            .and_then(Option::as_ref)
+            .unwrap_or(&default_scale);
+        let inverse = Transform::from_components(position, rotation, scale)
+            .model_matrix
+            .inverse();
+        // The transform is affine, so t along the local ray is t along the world one.
+        super::spatial::ray_aabb(
+            inverse.transform_point3(origin),
+            inverse.transform_vector3(dir),
+            &bounds,
+        )
+    }
+    pub fn set_spatial_cell_size(&mut self, cell_size: f32) {
+        self.spatial.set_cell_size(cell_size);
+    }
+    pub fn set_spatial_layers(&mut self, entity: Entity, layers: u32) {
+        self.spatial.set_layers(entity, layers);
+    }
+
+    #[deprecated(note = "use `World::spawn_model` with a `Placement`")]
     pub fn insert_object(
         &mut self,
         renderable: Renderable,
@@ -98,6 +265,176 @@ impl World {
         self.insert_renderable(entity, renderable);
         crate::log_debug!("Spawned model entity: {}", entity.0);
     }
+    /// Fails with [`EngineError::AssetMissing`] rather than spawning an entity that never renders.
+    pub fn spawn_model(
+        &mut self,
+        model_manager: &mut ModelManager,
+        model: &str,
+        placement: Placement,
+    ) -> Result<Entity, EngineError> {
+        let key = CacheKey::from(model);
+        if !model_manager.contains(&key) {
+            if let Some(auto_load) = placement.auto_load.as_ref() {
+                if model.ends_with(".obj") || crate::is_gltf(model) {
+                    model_manager
+                        .load_object_file(
+                            model,
+                            &auto_load.shader,
+                            &[Vertex::LAYOUT, VertexInstance::LAYOUT],
+                            vec![
+                                RenderBindGroupLayouts::uniform().clone(),
+                                RenderBindGroupLayouts::equirect_dst().clone(),
+                                RenderBindGroupLayouts::material_storage().clone(),
+                                RenderBindGroupLayouts::normal().clone(),
+                            ],
+                            &auto_load.surface_configuration,
+                            auto_load.primitive,
+                            auto_load.color_target.clone(),
+                            auto_load.depth_stencil.clone(),
+                        )
+                        .block_on()?;
+                }
+            }
+        }
+        let entity = self
+            .spawn_model_key(model_manager, key, placement)
+            .map_err(|e| match e {
+                EngineError::MissingResource { .. } => EngineError::AssetMissing(model.to_string()),
+                e => e,
+            })?;
+        self.model_paths
+            .entry(key)
+            .or_insert_with(|| model.to_string());
+        Ok(entity)
+    }
+
+    pub fn spawn_model_key(
+        &mut self,
+        model_manager: &ModelManager,
+        key: CacheKey,
+        placement: Placement,
+    ) -> Result<Entity, EngineError> {
+        let Some(model) = model_manager.get(&key) else {
+            return Err(EngineError::MissingResource {
+                kind: "model",
+                key: key.id().to_string(),
+            });
+        };
+        let aabb = model.aabb;
+        let position = placement.position.0;
+        let entity = self.spawn_instance(key, aabb, placement);
+        log_debug!(
+            "Spawned {} as entity {} at {:?} (aabb min: {:?} max: {:?})",
+            model.name,
+            entity.0,
+            position,
+            aabb.min,
+            aabb.max
+        );
+        Ok(entity)
+    }
+    fn spawn_instance(&mut self, key: CacheKey, aabb: AABB, placement: Placement) -> Entity {
+        let entity = self.spawn();
+        self.insert_position(entity, placement.position);
+        self.insert_rotation(entity, placement.rotation);
+        self.insert_scale(entity, placement.scale);
+        self.spatial.set_extent(entity, &aabb, placement.scale.0);
+        self.insert_bounds(entity, aabb);
+        self.insert_renderable(
+            entity,
+            Renderable {
+                model_key: key,
+                visible: placement.visible,
+            },
+        );
+        entity
+    }
+    /// `scale` overrides every transform's scale.
+    pub fn spawn_batch(
+        &mut self,
+        model_manager: &ModelManager,
+        model_key: CacheKey,
+        transforms: &[Transform],
+        velocity: Option<Velocity>,
+        scale: Option<Scale>,
+    ) -> Result<Vec<Entity>, EngineError> {
+        let Some(model) = model_manager.get(&model_key) else {
+            return Err(EngineError::MissingResource {
+                kind: "model",
+                key: model_key.id().to_string(),
+            });
+        };
+        let entities = self.spawn_instances(model_key, model.aabb, transforms, velocity, scale);
+        log_debug!(
+            "Spawned {} instances of {} as entities {}..{}",
+            entities.len(),
+            model.name,
+            entities.first().map_or(self.entity_count, |e| e.0),
+            self.entity_count
+        );
+        Ok(entities)
+    }
+    fn spawn_instances(
+        &mut self,
+        model_key: CacheKey,
+        aabb: AABB,
+        transforms: &[Transform],
+        velocity: Option<Velocity>,
+        scale: Option<Scale>,
+    ) -> Vec<Entity> {
+        let start = self.entity_count;
+        let count = transforms.len();
+        let poses: Vec<(Position, Rotation, Scale)> = transforms
+            .iter()
+            .map(|transform| {
+                let (s, r, t) = transform.model_matrix.to_scale_rotation_translation();
+                (Position(t), Rotation(r), scale.unwrap_or(Scale(s)))
+            })
+            .collect();
+
+        let end = start + count;
+        let positions = poses.iter().map(|(p, _, _)| Some(*p));
+        fill_column(&mut self.physics.positions, start, positions);
+        let rotations = poses.iter().map(|(_, r, _)| Some(*r));
+        fill_column(&mut self.rotations, start, rotations);
+        let scales = poses.iter().map(|(_, _, s)| Some(*s));
+        fill_column(&mut self.scales, start, scales);
+        fill_column(&mut self.bounds, start, repeat(Some(aabb), count));
+        let renderable = Some(Renderable {
+            model_key,
+            visible: true,
+        });
+        fill_column(&mut self.renderables, start, repeat(renderable, count));
+        let velocities = repeat(velocity, count);
+        fill_column(&mut self.physics.velocities, start, velocities);
+        pad_column(&mut self.transforms, end);
+        pad_column(&mut self.physics.colliders, end);
+        pad_column(&mut self.remote_transforms, end);
+        pad_column(&mut self.nav_agents, end);
+        pad_column(&mut self.tints, end);
+        pad_column(&mut self.lifetimes, end);
+        pad_column(&mut self.despawn_when_far, end);
+        pad_column(&mut self.fades, end);
+        pad_column(&mut self.healths, end);
+        pad_column(&mut self.damageables, end);
+        pad_column(&mut self.death_behaviors, end);
+        pad_column(&mut self.scripts, end);
+        pad_column(&mut self.animators, end);
+        pad_column(&mut self.particle_emitters, end);
+        pad_column(&mut self.audio_sources, end);
+        pad_column(&mut self.billboards, end);
+        pad_column(&mut self.layers, end);
+        pad_column(&mut self.previous_poses, end);
+        self.entity_count += count;
+
+        let entities: Vec<Entity> = (start..end).map(Entity).collect();
+        for (entity, (_, _, scale)) in entities.iter().zip(&poses) {
+            self.spatial.set_extent(*entity, &aabb, scale.0);
+        }
+        self.instance_batcher.extend(model_key, &entities);
+        entities
+    }
+
     pub fn load_object(
         model_manager: &mut ModelManager,
         file: &str,
@@ -138,42 +475,390 @@ impl World {
     fn resize(&mut self, size: usize) {
         self.physics.positions.resize(size, None);
         self.physics.velocities.resize(size, None);
+        self.physics.colliders.resize(size, None);
         self.renderables.resize(size, None);
         self.rotations.resize(size, None);
         self.scales.resize(size, None);
         self.transforms.resize(size, None);
+        self.remote_transforms.resize(size, None);
+        self.nav_agents.resize(size, None);
+        self.tints.resize(size, None);
+        self.lifetimes.resize(size, None);
+        self.despawn_when_far.resize(size, None);
+        self.fades.resize(size, None);
+        self.healths.resize(size, None);
+        self.damageables.resize(size, None);
+        self.death_behaviors.resize(size, None);
+        self.scripts.resize(size, None);
+        self.animators.resize(size, None);
+        self.particle_emitters.resize(size, None);
+        self.audio_sources.resize(size, None);
+        self.billboards.resize(size, None);
+        self.layers.resize(size, None);
+        self.previous_poses.resize(size, None);
+        self.bounds.resize(size, None);
     }
     fn ensure_capacity(&mut self, idx: usize) {
         let needed = idx + 1;
         if self.physics.positions.len() < needed
             || self.physics.velocities.len() < needed
+            || self.physics.colliders.len() < needed
             || self.rotations.len() < needed
             || self.renderables.len() < needed
             || self.scales.len() < needed
             || self.transforms.len() < needed
+            || self.remote_transforms.len() < needed
+            || self.nav_agents.len() < needed
+            || self.tints.len() < needed
+            || self.lifetimes.len() < needed
+            || self.despawn_when_far.len() < needed
+            || self.fades.len() < needed
+            || self.healths.len() < needed
+            || self.damageables.len() < needed
+            || self.death_behaviors.len() < needed
+            || self.scripts.len() < needed
+            || self.animators.len() < needed
+            || self.particle_emitters.len() < needed
+            || self.audio_sources.len() < needed
+            || self.billboards.len() < needed
+            || self.layers.len() < needed
+            || self.previous_poses.len() < needed
+            || self.bounds.len() < needed
         {
             self.resize(needed);
         }
     }
+    /// Entity ids aren't reused.
+    pub fn despawn(&mut self, entity: Entity) {
+        let i = entity.0;
+        if i >= self.entity_count {
+            return;
+        }
+        self.physics.positions[i] = None;
+        self.physics.velocities[i] = None;
+        self.physics.colliders[i] = None;
+        if let Some(renderable) = self.renderables[i].take() {
+            self.instance_batcher.remove(&renderable.model_key, entity);
+        }
+        self.rotations[i] = None;
+        self.scales[i] = None;
+        self.transforms[i] = None;
+        self.remote_transforms[i] = None;
+        self.nav_agents[i] = None;
+        self.tints[i] = None;
+        self.lifetimes[i] = None;
+        self.despawn_when_far[i] = None;
+        self.fades[i] = None;
+        self.healths[i] = None;
+        self.damageables[i] = None;
+        self.death_behaviors[i] = None;
+        self.scripts[i] = None;
+        self.animators[i] = None;
+        self.particle_emitters[i] = None;
+        self.audio_sources[i] = None;
+        self.billboards[i] = None;
+        self.layers[i] = None;
+        self.previous_poses[i] = None;
+        self.bounds[i] = None;
+        if self.selected == Some(entity) {
+            self.selected = None;
+        }
+        self.spatial.remove(entity);
+        _set_batch_dirty(true);
+        log_debug!("Despawned: {}", i);
+    }
     pub fn insert_position(&mut self, entity: Entity, pos: Position) {
         self.physics.insert_position(entity, pos);
+        if let Some(previous) = self.previous_poses.get_mut(entity.0) {
+            *previous = None;
+        }
     }
     pub fn insert_velocity(&mut self, entity: Entity, vel: Velocity) {
         self.physics.insert_velocity(entity, vel);
     }
+    pub fn insert_collider(&mut self, entity: Entity, collider: Collider) {
+        self.ensure_capacity(entity.0);
+        self.physics.insert_collider(entity, collider);
+    }
+    pub fn grounded(&self, entity: Entity) -> Option<bool> {
+        self.physics.grounded(entity)
+    }
     pub fn insert_scale(&mut self, entity: Entity, scale: Scale) {
         self.ensure_capacity(entity.0);
         self.scales[entity.0] = Some(scale);
     }
+    pub fn insert_bounds(&mut self, entity: Entity, bounds: AABB) {
+        self.ensure_capacity(entity.0);
+        self.bounds[entity.0] = Some(bounds);
+    }
     pub fn insert_rotation(&mut self, entity: Entity, rot: Rotation) {
         self.ensure_capacity(entity.0);
         self.rotations[entity.0] = Some(rot);
+        if let Some(previous) = self.previous_poses[entity.0].as_mut() {
+            previous.rotation = rot.0;
+        }
     }
     pub fn insert_renderable(&mut self, entity: Entity, renderable: Renderable) {
         self.ensure_capacity(entity.0);
+        if let Some(old) = &self.renderables[entity.0] {
+            self.instance_batcher.remove(&old.model_key, entity);
+        }
+        self.instance_batcher.insert(renderable.model_key, entity);
         self.renderables[entity.0] = Some(renderable);
     }
 
+    pub fn insert_remote_transform(&mut self, entity: Entity, remote: RemoteTransform) {
+        self.ensure_capacity(entity.0);
+        self.remote_transforms[entity.0] = Some(remote);
+    }
+    pub fn remove_remote_transform(&mut self, entity: Entity) -> Option<RemoteTransform> {
+        self.remote_transforms.get_mut(entity.0)?.take()
+    }
+
+    pub fn insert_tint(&mut self, entity: Entity, tint: Tint) {
+        self.ensure_capacity(entity.0);
+        self.tints[entity.0] = Some(tint);
+    }
+    pub fn insert_animator(&mut self, entity: Entity, animator: Animator) {
+        self.ensure_capacity(entity.0);
+        self.animators[entity.0] = Some(animator);
+    }
+    pub fn insert_particle_emitter(&mut self, entity: Entity, emitter: ParticleEmitter) {
+        self.ensure_capacity(entity.0);
+        self.particle_emitters[entity.0] = Some(emitter);
+    }
+    pub fn remove_particle_emitter(&mut self, entity: Entity) -> Option<ParticleEmitter> {
+        self.particle_emitters.get_mut(entity.0)?.take()
+    }
+    pub fn insert_audio_source(&mut self, entity: Entity, source: AudioSource) {
+        self.ensure_capacity(entity.0);
+        self.audio_sources[entity.0] = Some(source);
+    }
+    pub fn remove_audio_source(&mut self, entity: Entity) -> Option<AudioSource> {
+        self.audio_sources.get_mut(entity.0)?.take()
+    }
+    pub fn insert_billboard(&mut self, entity: Entity, billboard: Billboard) {
+        self.ensure_capacity(entity.0);
+        self.billboards[entity.0] = Some(billboard);
+    }
+    pub fn remove_billboard(&mut self, entity: Entity) -> Option<Billboard> {
+        self.billboards.get_mut(entity.0)?.take()
+    }
+    pub fn insert_layers(&mut self, entity: Entity, layers: Layers) {
+        self.ensure_capacity(entity.0);
+        self.layers[entity.0] = Some(layers);
+    }
+    pub fn remove_layers(&mut self, entity: Entity) -> Option<Layers> {
+        self.layers.get_mut(entity.0)?.take()
+    }
+    /// Spatial queries filter by [`World::set_spatial_layers`] instead.
+    pub fn layers(&self, entity: Entity) -> Layers {
+        self.layers
+            .get(entity.0)
+            .copied()
+            .flatten()
+            .unwrap_or_default()
+    }
+    pub fn play_sound_at(&mut self, key: CacheKey, position: Vec3) {
+        self.events.push(WorldEvent::PlaySound { key, position });
+    }
+    pub fn insert_lifetime(&mut self, entity: Entity, lifetime: Lifetime) {
+        self.ensure_capacity(entity.0);
+        self.lifetimes[entity.0] = Some(lifetime);
+    }
+    pub fn insert_despawn_when_far(&mut self, entity: Entity, despawn: DespawnWhenFar) {
+        self.ensure_capacity(entity.0);
+        self.despawn_when_far[entity.0] = Some(despawn);
+    }
+    pub fn insert_fade_out(&mut self, entity: Entity, fade: FadeOutThenDespawn) {
+        self.ensure_capacity(entity.0);
+        self.fades[entity.0] = Some(fade);
+    }
+
+    pub fn insert_health(&mut self, entity: Entity, health: Health) {
+        self.ensure_capacity(entity.0);
+        self.healths[entity.0] = Some(health);
+    }
+    pub fn insert_damageable(&mut self, entity: Entity, damageable: Damageable) {
+        self.ensure_capacity(entity.0);
+        self.damageables[entity.0] = Some(damageable);
+    }
+    pub fn insert_death_behavior(&mut self, entity: Entity, behavior: DeathBehavior) {
+        self.ensure_capacity(entity.0);
+        self.death_behaviors[entity.0] = Some(behavior);
+    }
+    pub fn insert_script(&mut self, entity: Entity, script: ScriptBehavior) {
+        self.ensure_capacity(entity.0);
+        self.scripts[entity.0] = Some(script);
+    }
+    pub fn get_health(&self, entity: Entity) -> Option<&Health> {
+        self.healths.get(entity.0)?.as_ref()
+    }
+    pub fn get_damageable_mut(&mut self, entity: Entity) -> Option<&mut Damageable> {
+        self.damageables.get_mut(entity.0)?.as_mut()
+    }
+
+    /// Respects invulnerability windows and, unless friendly fire is on, teams.
+    pub fn apply_damage(
+        &mut self,
+        target: Entity,
+        amount: f32,
+        source: Option<Entity>,
+    ) -> DamageResult {
+        let source_team = source
+            .filter(|source| *source != target)
+            .and_then(|source| self.damageables.get(source.0).copied().flatten())
+            .map(|source| source.team);
+        let now = self.elapsed;
+        let (Some(Some(health)), Some(Some(damageable))) = (
+            self.healths.get_mut(target.0),
+            self.damageables.get(target.0),
+        ) else {
+            return DamageResult::NotDamageable;
+        };
+        let before = health.current;
+        let result = super::resolve_damage(health, damageable, source_team, amount, now);
+        if !result.landed() {
+            return result;
+        }
+        self.events.push(WorldEvent::DamageTaken {
+            target,
+            amount: before - health.current,
+            source,
+            remaining: health.current,
+        });
+        if result == DamageResult::Killed {
+            self.events.push(WorldEvent::EntityDied {
+                entity: target,
+                source,
+            });
+            self.kill(target);
+        }
+        result
+    }
+    fn kill(&mut self, entity: Entity) {
+        let behavior = self.death_behaviors[entity.0].unwrap_or_default();
+        match behavior {
+            DeathBehavior::Despawn => self.despawn(entity),
+            DeathBehavior::FadeOut(fade) => {
+                let mut fade = FadeOutThenDespawn::new(fade);
+                fade.start(ExpiryReason::Killed);
+                self.lifetimes[entity.0] = None;
+                self.insert_fade_out(entity, fade);
+                if self.tints[entity.0].is_none() {
+                    self.insert_tint(entity, Tint::white());
+                }
+            }
+            DeathBehavior::EventOnly => {}
+        }
+    }
+
+    pub fn insert_nav_agent(&mut self, entity: Entity, agent: NavAgent) {
+        self.ensure_capacity(entity.0);
+        self.nav_agents[entity.0] = Some(agent);
+    }
+    pub fn get_nav_agent_mut(&mut self, entity: Entity) -> Option<&mut NavAgent> {
+        self.nav_agents.get_mut(entity.0)?.as_mut()
+    }
+    pub fn nav_debug_lines(&self) -> Vec<[Vec3; 2]> {
+        self.navigation.debug_lines(&self.nav_agents)
+    }
+
+    /// Timestamps share the world clock, see [`World::elapsed`].
+    pub fn push_snapshot(
+        &mut self,
+        entity: Entity,
+        timestamp: f64,
+        position: Vec3,
+        rotation: Quat,
+        velocity: Vec3,
+    ) {
+        self.ensure_capacity(entity.0);
+        let remote = self.remote_transforms[entity.0].get_or_insert_with(RemoteTransform::default);
+        remote.push(TransformSnapshot {
+            timestamp,
+            position,
+            rotation,
+            velocity,
+        });
+    }
+
+    /// While paused, updates only refresh transforms.
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+        self.step_requested &= paused;
+    }
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+    pub fn select(&mut self, entity: Option<Entity>) {
+        self.selected = entity;
+    }
+    pub fn selected(&self) -> Option<Entity> {
+        self.selected
+    }
+    pub fn step(&mut self) {
+        self.step_requested = self.paused;
+    }
+
+    pub fn elapsed(&self) -> f64 {
+        self.elapsed
+    }
+
+    pub fn origin(&self) -> WorldOrigin {
+        self.origin
+    }
+    pub fn rebase_policy(&self) -> RebasePolicy {
+        self.rebase_policy
+    }
+    pub fn set_rebase_policy(&mut self, policy: RebasePolicy) {
+        self.rebase_policy = policy;
+    }
+    pub fn add_rebase_hook(&mut self, hook: impl FnMut(&Rebase) + Send + Sync + 'static) {
+        self.rebase_hooks.push(Box::new(hook));
+    }
+    /// Move the camera by the returned shift before anything renders.
+    pub fn rebase_around(&mut self, focus: Vec3) -> Option<Rebase> {
+        let chunks = self.rebase_policy.shift_for(focus)?;
+        Some(self.rebase(chunks))
+    }
+    pub fn rebase(&mut self, chunks: IVec3) -> Rebase {
+        let rebase = Rebase::new(self.origin, chunks);
+        let offset = rebase.offset();
+        for position in self.physics.positions.iter_mut().flatten() {
+            position.0 -= offset;
+        }
+        for previous in self.previous_poses.iter_mut().flatten() {
+            previous.position -= offset;
+        }
+        for remote in self.remote_transforms.iter_mut().flatten() {
+            remote.rebase(offset);
+        }
+        for agent in self.nav_agents.iter_mut().flatten() {
+            agent.rebase(&rebase);
+        }
+        self.navigation.rebase(&rebase);
+        self.spatial.rebase(offset);
+        self.terrain.rebase(&rebase);
+        self.update_transforms();
+        self.origin = rebase.origin;
+        self.rebase_hooks.run(&rebase);
+        _set_batch_dirty(true);
+        log_info!(
+            "Rebased by {:?} chunks, origin now at block {:?}",
+            chunks,
+            self.origin.block()
+        );
+        rebase
+    }
+
+    pub fn drain_events(&mut self) -> std::vec::Drain<'_, WorldEvent> {
+        self.events.drain(..)
+    }
+    pub fn push_event(&mut self, event: WorldEvent) {
+        self.events.push(event);
+    }
+
     pub fn get_renderable(&self, entity: Entity) -> Option<&Renderable> {
         self.renderables.get(entity.0)?.as_ref()
     }
@@ -183,24 +868,180 @@ impl World {
     pub fn get_transform(&self, entity: Entity) -> Option<&Transform> {
         self.transforms.get(entity.0)?.as_ref()
     }
-    pub fn update(&mut self, queue: &wgpu::Queue, device: &wgpu::Device, camera: &Camera, dt: f32) {
-        self.physics.update(camera, dt, &self.terrain);
-        self.update_transforms();
-        self.terrain.update_instance_buffer(queue, device);
+    /// Ticks are skipped after a panic until a scene load starts over.
+    pub fn is_healthy(&self) -> bool {
+        self.tick_error.is_none()
+    }
+    pub fn tick_error(&self) -> Option<&str> {
+        self.tick_error.as_deref()
     }
 
-    pub fn update_transforms(&mut self) {
-        for i in 0..self.entity_count {
-            if let (Some(pos), Some(rot), Some(scale)) = (
-                self.physics.positions[i].as_ref(),
-                self.rotations[i].as_ref(),
-                self.scales[i].as_ref(),
-            ) {
-                self.transforms[i] = Some(Transform::from_components(pos, rot, scale));
+    /// Runs whole fixed steps and carries the remainder. A panicking system marks the
+    /// world unhealthy instead of taking the frame loop down.
+    pub fn update(&mut self, queue: &wgpu::Queue, device: &wgpu::Device, camera: &Camera, dt: f32) {
+        if !self.is_healthy() {
+            return;
+        }
+        let step = self.fixed_timestep.step.max(f32::EPSILON);
+        let mut tick = Tick {
+            dt: step,
+            elapsed: self.elapsed,
+            alpha: 1.0,
+            camera_pos: *camera.eye(),
+            frustum: camera.frustum(),
+            medium: Physics::medium(camera, &self.terrain),
+            queue,
+            device,
+        };
+        let mut expired = Vec::new();
+        if self.paused {
+            if std::mem::take(&mut self.step_requested) {
+                self.elapsed += step as f64;
+                tick.elapsed = self.elapsed;
+                if !self.run_schedule(|world| &mut world.schedule, &tick, &mut expired) {
+                    return;
+                }
+            }
+            self.alpha = 1.0;
+        } else {
+            self.accumulator += dt as f64;
+            let mut steps = 0;
+            while self.accumulator >= step as f64 {
+                if steps == self.fixed_timestep.max_steps {
+                    log_debug!(
+                        "Dropped {:.3}s of simulation after {} steps",
+                        self.accumulator,
+                        steps
+                    );
+                    self.accumulator %= step as f64;
+                    break;
+                }
+                self.elapsed += step as f64;
+                tick.elapsed = self.elapsed;
+                if !self.run_schedule(|world| &mut world.schedule, &tick, &mut expired) {
+                    return;
+                }
+                self.accumulator -= step as f64;
+                steps += 1;
             }
+            self.alpha = (self.accumulator / step as f64) as f32;
+        }
+
+        tick.dt = dt;
+        tick.alpha = self.alpha;
+        let ran = if self.paused {
+            self.run_schedule(|world| &mut world.paused_schedule, &tick, &mut expired)
+        } else {
+            self.run_schedule(|world| &mut world.frame_schedule, &tick, &mut expired)
+        };
+        if ran {
+            self.despawn_expired(expired);
+        }
+    }
+    fn run_schedule(
+        &mut self,
+        slot: fn(&mut Self) -> &mut Schedule,
+        tick: &Tick,
+        expired: &mut Vec<(Entity, ExpiryReason)>,
+    ) -> bool {
+        let schedule = std::mem::take(slot(self));
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            schedule.run(&self.view(expired), tick)
+        }));
+        *slot(self) = schedule;
+        if let Err(panic) = result {
+            let message = panic
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| panic.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown panic".to_string());
+            log_error!("World tick panicked: {}", message);
+            self.tick_error = Some(message);
+            return false;
+        }
+        true
+    }
+    /// How far the last update got past its final fixed step, as a fraction of a step.
+    pub fn interpolation_alpha(&self) -> f32 {
+        self.alpha
+    }
+    pub fn fixed_timestep(&self) -> FixedTimestep {
+        self.fixed_timestep
+    }
+    pub fn set_fixed_timestep(&mut self, fixed_timestep: FixedTimestep) {
+        self.fixed_timestep = fixed_timestep;
+    }
+
+    fn view<'a>(&'a mut self, expired: &'a mut Vec<(Entity, ExpiryReason)>) -> WorldView<'a> {
+        WorldView {
+            positions: RwLock::new(&mut self.physics.positions),
+            velocities: RwLock::new(&mut self.physics.velocities),
+            colliders: RwLock::new(&mut self.physics.colliders),
+            rotations: RwLock::new(&mut self.rotations),
+            scales: RwLock::new(&mut self.scales),
+            transforms: RwLock::new(&mut self.transforms),
+            previous_poses: RwLock::new(&mut self.previous_poses),
+            remote_transforms: RwLock::new(&mut self.remote_transforms),
+            nav_agents: RwLock::new(&mut self.nav_agents),
+            lifetimes: RwLock::new(&mut self.lifetimes),
+            despawn_when_far: RwLock::new(&mut self.despawn_when_far),
+            fades: RwLock::new(&mut self.fades),
+            tints: RwLock::new(&mut self.tints),
+            animators: RwLock::new(&mut self.animators),
+            terrain: RwLock::new(&mut self.terrain),
+            navigation: RwLock::new(&mut self.navigation),
+            spatial: RwLock::new(&mut self.spatial),
+            events: RwLock::new(&mut self.events),
+            expired: RwLock::new(expired),
+        }
+    }
+
+    pub fn update_lifetimes(&mut self, camera_pos: Vec3, dt: f32) {
+        self.spatial.sync(&self.physics.positions);
+        let expired: Vec<(Entity, ExpiryReason)> = update_lifetimes(
+            dt,
+            camera_pos,
+            LifetimeColumns {
+                lifetimes: &mut self.lifetimes,
+                far: &mut self.despawn_when_far,
+                fades: &mut self.fades,
+                tints: &mut self.tints,
+            },
+            &self.physics.positions,
+            &self.spatial,
+        );
+        self.despawn_expired(expired);
+    }
+    fn despawn_expired(&mut self, expired: Vec<(Entity, ExpiryReason)>) {
+        for (entity, reason) in expired {
+            self.despawn(entity);
+            self.events.push(WorldEvent::EntityExpired(entity, reason));
         }
     }
 
+    pub fn update_remote_transforms(&mut self) {
+        let starved = sample_remote_transforms(
+            self.elapsed,
+            &mut self.remote_transforms,
+            &mut self.physics.positions,
+            &mut self.physics.velocities,
+            &mut self.rotations,
+        );
+        self.events
+            .extend(starved.into_iter().map(WorldEvent::SnapshotStarved));
+    }
+
+    pub fn update_transforms(&mut self) {
+        propagate_transforms(
+            &self.physics.positions,
+            &self.rotations,
+            &self.scales,
+            &self.previous_poses,
+            self.alpha,
+            &mut self.transforms,
+        );
+    }
+
     pub fn generate_terrain(
         &mut self,
         center: Vec3,
@@ -209,8 +1050,7 @@ impl World {
         surface_config: &wgpu::SurfaceConfiguration,
         depth_stencil: &wgpu::DepthStencilState,
         model_manager: &mut crate::ModelManager,
-    ) {
-        let entity = self.spawn();
+    ) -> Result<Entity, EngineError> {
         let component = self.terrain.chunks(
             center,
             radius,
@@ -218,8 +1058,846 @@ impl World {
             surface_config,
             depth_stencil,
             model_manager,
+        )?;
+        let entity = self.spawn();
+        self.insert_renderable(entity, component);
+        Ok(entity)
+    }
+
+    pub fn scene(&self) -> Option<&LoadedScene> {
+        self.scene.as_ref()
+    }
+
+    /// Entries whose assets fail to load end up in [`LoadedScene::warnings`].
+    pub fn load_scene_by_name(
+        &mut self,
+        model_manager: &mut ModelManager,
+        name: &str,
+        surface_config: &wgpu::SurfaceConfiguration,
+        depth_stencil: &wgpu::DepthStencilState,
+    ) -> Result<&LoadedScene, EngineError> {
+        let file = SceneFile::load(name)?;
+        self.unload_scene(model_manager);
+        self.tick_error = None;
+
+        // Scene files are authored in global coordinates.
+        let world_origin = self.origin;
+        let local = |p: &[f32; 3]| world_origin.to_local(Vec3::from_array(*p).as_dvec3());
+        let mut auto_load = AutoLoad::new(surface_config, Some(depth_stencil.clone()));
+        if let Some(shader) = &file.shader {
+            auto_load = auto_load.with_shader(shader);
+        }
+        let mut scene = LoadedScene {
+            name: name.to_string(),
+            sequence: file.sequence.clone(),
+            ..Default::default()
+        };
+
+        for entry in &file.entities {
+            match entry {
+                SceneEntry::Model {
+                    model,
+                    name,
+                    position,
+                    rotation,
+                    scale,
+                    health,
+                    tags,
+                    script,
+                } => {
+                    let [yaw, pitch, roll] = rotation.map(f32::to_radians);
+                    let placement = Placement::at(local(position))
+                        .with_rotation(Rotation::from_euler(yaw, pitch, roll).quat())
+                        .with_uniform_scale(*scale)
+                        .with_auto_load(auto_load.clone());
+                    match self.spawn_model(model_manager, model, placement) {
+                        Ok(entity) => {
+                            scene.entities.push(entity);
+                            if let Some(health) = health {
+                                self.insert_health(entity, Health::new(*health));
+                                self.insert_damageable(entity, Damageable::default());
+                            }
+                            if let Some(name) = name {
+                                scene.named.insert(name.clone(), entity);
+                            }
+                            for tag in tags {
+                                scene.tagged.entry(tag.clone()).or_default().push(entity);
+                            }
+                            if let Some(script) = script {
+                                self.insert_script(entity, script.clone());
+                            }
+                            scene.models.push(CacheKey::from(model.as_str()));
+                        }
+                        Err(e) => scene.warnings.push(format!("{}: {}", model, e)),
+                    }
+                }
+                SceneEntry::Grid {
+                    model,
+                    origin,
+                    from,
+                    to,
+                    spacing,
+                    scale,
+                } => {
+                    let origin = local(origin);
+                    let mut cells = Vec::new();
+                    for x in from[0]..=to[0] {
+                        for y in from[1]..=to[1] {
+                            for z in from[2]..=to[2] {
+                                let cell = Vec3::new(x as f32, y as f32, z as f32) * *spacing;
+                                cells.push(origin + cell);
+                            }
+                        }
+                    }
+                    // The first cell loads the model if needed, the rest spawn as one batch.
+                    if let Some((first, rest)) = cells.split_first() {
+                        let placement = Placement::at(*first)
+                            .with_uniform_scale(*scale)
+                            .with_auto_load(auto_load.clone());
+                        match self.spawn_model(model_manager, model, placement) {
+                            Ok(entity) => {
+                                scene.entities.push(entity);
+                                let transforms: Vec<Transform> = rest
+                                    .iter()
+                                    .map(|cell| {
+                                        Transform::from_components(
+                                            &Position(*cell),
+                                            &Rotation::identity(),
+                                            &Scale::uniform(*scale),
+                                        )
+                                    })
+                                    .collect();
+                                let key = CacheKey::from(model.as_str());
+                                let scale = Some(Scale::uniform(*scale));
+                                match self.spawn_batch(model_manager, key, &transforms, None, scale)
+                                {
+                                    Ok(entities) => scene.entities.extend(entities),
+                                    Err(e) => scene.warnings.push(format!("{}: {}", model, e)),
+                                }
+                            }
+                            Err(e) => scene.warnings.push(format!("{}: {}", model, e)),
+                        }
+                    }
+                    scene.models.push(CacheKey::from(model.as_str()));
+                }
+            }
+        }
+
+        if let Some(terrain) = file.terrain {
+            self.terrain.set_generator(terrain.generator());
+            match self.generate_terrain(
+                local(&terrain.center),
+                terrain.radius,
+                terrain.mediums,
+                surface_config,
+                depth_stencil,
+                model_manager,
+            ) {
+                Ok(entity) => scene.entities.push(entity),
+                Err(e) => scene.warnings.push(format!("terrain: {}", e)),
+            }
+        }
+
+        if let Some(source) = &file.environment {
+            match WorldProjection::from_source(
+                &model_manager.queue,
+                &model_manager.device,
+                surface_config,
+                &mut model_manager.materials.textures,
+                source,
+                Some(depth_stencil.clone()),
+            ) {
+                Ok(projection) => self.projection = Some(projection),
+                Err(e) => scene.warnings.push(format!("environment: {}", e)),
+            }
+        }
+
+        for warning in &scene.warnings {
+            log_warning!("Scene '{}': {}", name, warning);
+        }
+        log_debug!(
+            "Loaded scene '{}': {} entities, {} warnings",
+            name,
+            scene.entities.len(),
+            scene.warnings.len()
+        );
+        Ok(self.scene.insert(scene))
+    }
+
+    /// Entities without a known asset path, like terrain, are left out.
+    pub fn save_scene(&self, path: impl AsRef<Path>) -> Result<(), EngineError> {
+        let mut saved = SavedScene::default();
+        for (i, renderable) in self.renderables.iter().enumerate() {
+            let Some(renderable) = renderable else {
+                continue;
+            };
+            let Some(position) = self.physics.positions.get(i).copied().flatten() else {
+                continue;
+            };
+            let Some(model) = self.model_paths.get(&renderable.model_key) else {
+                log_debug!("Not saving entity {}: no asset path", i);
+                continue;
+            };
+            saved.entities.push(SavedEntity::capture(
+                model.clone(),
+                &self.origin,
+                position,
+                self.rotations.get(i).copied().flatten(),
+                self.scales.get(i).copied().flatten(),
+                self.physics.velocities.get(i).copied().flatten(),
+                renderable.visible,
+            ));
+        }
+        saved.write(path.as_ref())?;
+        log_info!(
+            "Saved {} entities to {}",
+            saved.entities.len(),
+            path.as_ref().display()
         );
+        Ok(())
+    }
 
-        self.insert_renderable(entity, component);
+    /// Entities whose model fails to load end up in [`LoadedScene::warnings`].
+    pub fn load_scene(
+        &mut self,
+        path: impl AsRef<Path>,
+        model_manager: &mut ModelManager,
+        surface_config: &wgpu::SurfaceConfiguration,
+    ) -> Result<&LoadedScene, EngineError> {
+        let path = path.as_ref();
+        let saved = SavedScene::read(path)?;
+        self.unload_scene(model_manager);
+        self.tick_error = None;
+
+        let auto_load = AutoLoad::new(
+            surface_config,
+            Some(RenderSettings::depth_state(DepthVariant::Opaque)),
+        );
+        let mut scene = LoadedScene {
+            name: path.display().to_string(),
+            ..Default::default()
+        };
+        for entry in &saved.entities {
+            let placement = entry
+                .placement(&self.origin)
+                .with_auto_load(auto_load.clone());
+            match self.spawn_model(model_manager, &entry.model, placement) {
+                Ok(entity) => {
+                    if let Some(velocity) = entry.velocity {
+                        self.insert_velocity(entity, Velocity(Vec3::from_array(velocity)));
+                    }
+                    scene.entities.push(entity);
+                    let key = CacheKey::from(entry.model.as_str());
+                    if !scene.models.contains(&key) {
+                        scene.models.push(key);
+                    }
+                }
+                Err(e) => scene.warnings.push(e.to_string()),
+            }
+        }
+
+        for warning in &scene.warnings {
+            log_warning!("Scene {}: {}", path.display(), warning);
+        }
+        log_debug!(
+            "Loaded {}: {} entities, {} warnings",
+            path.display(),
+            scene.entities.len(),
+            scene.warnings.len()
+        );
+        Ok(self.scene.insert(scene))
+    }
+
+    pub fn unload_scene(&mut self, model_manager: &mut ModelManager) -> Option<LoadedScene> {
+        let scene = self.scene.take()?;
+        for entity in &scene.entities {
+            self.despawn(*entity);
+        }
+        for key in &scene.models {
+            let in_use = self
+                .renderables
+                .iter()
+                .flatten()
+                .any(|r| r.model_key == *key);
+            if !in_use && model_manager.remove(key).is_some() {
+                log_debug!("Released model {} with scene '{}'", key.id(), scene.name);
+            }
+        }
+        Some(scene)
+    }
+}
+
+fn fill_column<T: Clone>(
+    column: &mut Vec<Option<T>>,
+    start: usize,
+    values: impl Iterator<Item = Option<T>>,
+) {
+    pad_column(column, start);
+    column.reserve(values.size_hint().0);
+    column.extend(values);
+}
+
+/// Cuts or pads `column` to exactly `len` slots.
+fn pad_column<T: Clone>(column: &mut Vec<Option<T>>, len: usize) {
+    column.truncate(len);
+    column.resize(len, None);
+}
+
+fn repeat<T: Clone>(value: T, count: usize) -> std::iter::Take<std::iter::Repeat<T>> {
+    std::iter::repeat(value).take(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::*;
+    use crate::AssetPaths;
+
+    fn cube() -> AABB {
+        AABB {
+            min: Vec3::splat(-0.5),
+            max: Vec3::splat(0.5),
+        }
+    }
+
+    /// A model manager on the global GPU, with the assets under the test root. `None`
+    /// without an adapter.
+    fn models() -> Option<(ModelManager, AutoLoad)> {
+        crate::assets::loader::test_root();
+        let _ = crate::GPU::init(crate::GpuRequirements::default());
+        RenderBindGroupLayouts::try_get().ok()?;
+        let (device, queue) =
+            crate::GPU::with_read_recovered(|gpu| (gpu.device().clone(), gpu.queue().clone()))
+                .ok()?;
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            width: 64,
+            height: 64,
+            present_mode: wgpu::PresentMode::Fifo,
+            alpha_mode: wgpu::CompositeAlphaMode::Opaque,
+            view_formats: vec![],
+            desired_maximum_frame_latency: 2,
+        };
+        let depth = RenderSettings::depth_state(DepthVariant::Opaque);
+        let auto_load = AutoLoad::new(&config, Some(depth));
+        Some((ModelManager::new(queue, device), auto_load))
+    }
+
+    fn transforms(count: usize) -> Vec<Transform> {
+        (0..count)
+            .map(|i| {
+                let position = Vec3::new((i % 250) as f32, 0.0, (i / 250) as f32) * 2.0;
+                let rotation = Quat::from_rotation_y(i as f32 * 0.01);
+                let scale = Scale::uniform(1.0 + (i % 3) as f32 * 0.5);
+                Transform::from_components(&Position(position), &Rotation(rotation), &scale)
+            })
+            .collect()
+    }
+
+    fn placement(transform: &Transform) -> Placement {
+        let (scale, rotation, translation) = transform.model_matrix.to_scale_rotation_translation();
+        Placement::at(translation)
+            .with_rotation(rotation)
+            .with_scale(scale)
+    }
+
+    /// Fastest of a few runs of `spawn` on an empty world, with the world it left.
+    fn fastest(spawn: impl Fn(&mut World)) -> (Duration, World) {
+        let mut fastest: Option<(Duration, World)> = None;
+        for _ in 0..3 {
+            let mut world = World::empty();
+            let start = Instant::now();
+            spawn(&mut world);
+            let time = start.elapsed();
+            match &fastest {
+                Some((best, _)) if *best <= time => {}
+                _ => fastest = Some((time, world)),
+            }
+        }
+        fastest.expect("ran at least once")
+    }
+
+    #[test]
+    fn spawn_batch_builds_the_spawn_loops_batches_faster() {
+        let key = CacheKey::from("cube.obj");
+        let transforms = transforms(50_000);
+
+        let (loop_time, looped) = fastest(|world| {
+            for transform in &transforms {
+                world.spawn_instance(key, cube(), placement(transform));
+            }
+        });
+        let (batch_time, batched) = fastest(|world| {
+            world.spawn_instances(key, cube(), &transforms, None, None);
+        });
+
+        let entities: Vec<Entity> = (0..transforms.len()).map(Entity).collect();
+        assert_eq!(batched.entity_count(), transforms.len());
+        assert_eq!(batched.instance_batches().get(&key), entities.as_slice());
+        assert_eq!(looped.instance_batches(), batched.instance_batches());
+        for i in 0..transforms.len() {
+            let position = |world: &World| world.physics.positions[i].map(|p| p.0);
+            let rotation = |world: &World| world.rotations[i].map(|r| r.0);
+            let scale = |world: &World| world.scales[i].map(|s| s.0);
+            assert_eq!(position(&looped), position(&batched), "entity {}", i);
+            assert_eq!(rotation(&looped), rotation(&batched), "entity {}", i);
+            assert_eq!(scale(&looped), scale(&batched), "entity {}", i);
+        }
+        assert!(
+            batch_time * 3 / 2 < loop_time,
+            "spawn_batch took {:?}, the loop {:?}",
+            batch_time,
+            loop_time
+        );
+    }
+
+    #[test]
+    fn batches_follow_renderables() {
+        let (boxes, spheres) = (CacheKey::from("cube.obj"), CacheKey::from("sphere.obj"));
+        let mut world = World::empty();
+        let entities = world.spawn_instances(boxes, cube(), &transforms(4), None, None);
+        world.insert_renderable(
+            entities[1],
+            Renderable {
+                model_key: spheres,
+                visible: true,
+            },
+        );
+        world.despawn(entities[2]);
+        let batches = world.instance_batches();
+        assert_eq!(batches.get(&boxes), &[entities[0], entities[3]]);
+        assert_eq!(batches.get(&spheres), &[entities[1]]);
+
+        world.insert_renderable(
+            entities[1],
+            Renderable {
+                model_key: boxes,
+                visible: false,
+            },
+        );
+        let batches = world.instance_batches();
+        assert_eq!(
+            batches.get(&boxes),
+            &[entities[0], entities[1], entities[3]]
+        );
+        assert!(batches.get(&spheres).is_empty());
+    }
+
+    /// Needs an adapter; passes without one.
+    #[test]
+    fn spawning_a_missing_model_errors() {
+        let Some((mut models, auto_load)) = models() else {
+            return;
+        };
+        let mut world = World::empty();
+        let missing = world.spawn_model(&mut models, "missing.obj", Placement::default());
+        assert!(matches!(missing, Err(EngineError::AssetMissing(model)) if model == "missing.obj"));
+        // Asked to load it, the load's own error comes back.
+        let placement = Placement::default().with_auto_load(auto_load);
+        match world.spawn_model(&mut models, "missing.obj", placement) {
+            Err(EngineError::AssetMissing(_)) | Ok(_) => panic!("the load error got lost"),
+            Err(_) => {}
+        }
+        assert_eq!(world.entity_count(), 0);
+    }
+
+    /// Needs an adapter; passes without one.
+    #[test]
+    fn undeclared_storage_access_is_reported() {
+        use crate::ecs::{Access, SystemContext};
+
+        if models().is_none() {
+            return;
+        }
+        let (device, queue) =
+            crate::GPU::with_read_recovered(|gpu| (gpu.device().clone(), gpu.queue().clone()))
+                .unwrap();
+        let tick = Tick {
+            dt: 0.1,
+            elapsed: 0.0,
+            alpha: 1.0,
+            camera_pos: Vec3::ZERO,
+            frustum: crate::camera::Frustum::new(),
+            medium: Medium::Air.properties(),
+            queue: &queue,
+            device: &device,
+        };
+        let run = |schedule: Schedule| {
+            let mut world = World::empty();
+            let mut expired = Vec::new();
+            let view = world.view(&mut expired);
+            std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| schedule.run(&view, &tick)))
+                .err()
+                .and_then(|panic| panic.downcast_ref::<String>().cloned())
+        };
+        fn move_things(ctx: &SystemContext) {
+            ctx.write::<Position>();
+            ctx.read::<Rotation>();
+        }
+
+        let declared = Access::new().writes::<Position>().reads::<Rotation>();
+        assert_eq!(
+            run(Schedule::new().with("mover", declared, move_things)),
+            None
+        );
+        let read_only = Access::new().reads::<Position>().reads::<Rotation>();
+        let message = run(Schedule::new().with("mover", read_only, move_things)).unwrap();
+        assert!(message.contains("'mover' writes"), "{}", message);
+        assert!(
+            message.contains("Position without declaring it"),
+            "{}",
+            message
+        );
+        let no_rotation = Access::new().writes::<Position>();
+        let message = run(Schedule::new().with("mover", no_rotation, move_things)).unwrap();
+        assert!(
+            message.contains("Rotation without declaring it"),
+            "{}",
+            message
+        );
+    }
+
+    /// Needs an adapter; passes without one.
+    #[test]
+    fn pausing_stops_lifetimes() {
+        if models().is_none() {
+            return;
+        }
+        let (device, queue) =
+            crate::GPU::with_read_recovered(|gpu| (gpu.device().clone(), gpu.queue().clone()))
+                .unwrap();
+        let camera = Camera::new(&device, 1.0);
+        let mut world = World::empty();
+        world.set_fixed_timestep(FixedTimestep {
+            step: 0.1,
+            max_steps: 8,
+        });
+        let entity = world.spawn_instance(CacheKey::from("cube.obj"), cube(), Placement::new());
+        world.insert_lifetime(entity, Lifetime::from_secs(0.25));
+        let expired = |world: &mut World| {
+            world
+                .drain_events()
+                .any(|event| matches!(event, WorldEvent::EntityExpired(e, _) if e == entity))
+        };
+
+        world.set_paused(true);
+        for _ in 0..10 {
+            world.update(&queue, &device, &camera, 1.0);
+        }
+        assert!(!expired(&mut world));
+        assert_eq!(
+            world.lifetimes[entity.0].map(|l| l.0),
+            Some(Duration::from_secs_f32(0.25))
+        );
+
+        // A requested step ticks it once, by the fixed step only.
+        world.step();
+        world.update(&queue, &device, &camera, 1.0);
+        assert!(!expired(&mut world));
+        world.step();
+        world.update(&queue, &device, &camera, 1.0);
+        assert!(!expired(&mut world));
+        world.step();
+        world.update(&queue, &device, &camera, 1.0);
+        assert!(expired(&mut world));
+    }
+
+    /// Needs an adapter; passes without one.
+    #[test]
+    fn auto_load_loads_then_spawns() {
+        let Some((mut models, auto_load)) = models() else {
+            return;
+        };
+        let model = AssetPaths::models_dir().join("auto_load_triangle.obj");
+        std::fs::write(model, "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n").unwrap();
+        let key = CacheKey::from("auto_load_triangle.obj");
+
+        let mut world = World::empty();
+        let at = Vec3::new(1.0, 2.0, 3.0);
+        let placement = Placement::at(at).with_auto_load(auto_load);
+        let entity = world
+            .spawn_model(&mut models, "auto_load_triangle.obj", placement)
+            .unwrap();
+        assert!(models.contains(&key));
+        assert_eq!(world.get_renderable(entity).map(|r| r.model_key), Some(key));
+        assert_eq!(world.physics.positions[entity.0].map(|p| p.0), Some(at));
+        assert_eq!(world.instance_batches().get(&key), &[entity]);
+
+        // Loaded now, so it spawns without asking again.
+        let again = world.spawn_model(&mut models, "auto_load_triangle.obj", Placement::new());
+        assert_eq!(again.unwrap(), Entity(1));
+    }
+
+    #[test]
+    fn placement_defaults_to_the_origin_identity_and_unit_scale() {
+        let mut world = World::empty();
+        let key = CacheKey::from("cube.obj");
+        let entity = world.spawn_instance(key, cube(), Placement::default());
+        let i = entity.0;
+        assert_eq!(world.physics.positions[i].map(|p| p.0), Some(Vec3::ZERO));
+        assert_eq!(world.rotations[i].map(|r| r.0), Some(Quat::IDENTITY));
+        assert_eq!(world.scales[i].map(|s| s.0), Some(Vec3::ONE));
+        assert!(world.get_renderable(entity).is_some_and(|r| r.visible));
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn deprecated_insert_object_still_spawns() {
+        let mut world = World::empty();
+        let renderable = Renderable {
+            model_key: CacheKey::from("cube.obj"),
+            visible: true,
+        };
+        world.insert_object(renderable, None, None, None);
+        assert_eq!(world.entity_count(), 1);
+        assert_eq!(world.physics.positions[0].map(|p| p.0), Some(Vec3::ZERO));
+        assert_eq!(world.scales[0].map(|s| s.0), Some(Vec3::ONE));
+        assert_eq!(world.instance_batches().len(), 1);
+    }
+
+    fn damageable(world: &mut World, team: u8, health: f32, death: DeathBehavior) -> Entity {
+        let entity = world.spawn();
+        world.insert_health(entity, Health::new(health));
+        world.insert_damageable(entity, Damageable::new(team));
+        world.insert_death_behavior(entity, death);
+        entity
+    }
+
+    #[test]
+    fn killing_blows_report_damage_before_death() {
+        let mut world = World::empty();
+        let attacker = damageable(&mut world, 1, 10.0, DeathBehavior::EventOnly);
+        let target = damageable(&mut world, 2, 5.0, DeathBehavior::EventOnly);
+        assert_eq!(
+            world.apply_damage(target, 2.0, Some(attacker)),
+            DamageResult::Applied { remaining: 3.0 }
+        );
+        assert_eq!(
+            world.apply_damage(target, 10.0, Some(attacker)),
+            DamageResult::Killed
+        );
+        assert_eq!(
+            world.apply_damage(target, 1.0, Some(attacker)),
+            DamageResult::AlreadyDead
+        );
+
+        let events: Vec<WorldEvent> = world.drain_events().collect();
+        assert!(
+            matches!(
+                events.as_slice(),
+                [
+                    WorldEvent::DamageTaken { amount: a, remaining: r, .. },
+                    WorldEvent::DamageTaken { target: t, amount: b, source: Some(s), remaining: 0.0 },
+                    WorldEvent::EntityDied { entity, source: Some(killer) },
+                ] if *a == 2.0 && *r == 3.0 && *t == target && *b == 3.0 && *s == attacker
+                    && *entity == target && *killer == attacker
+            ),
+            "{events:?}"
+        );
+    }
+
+    #[test]
+    fn invulnerability_follows_world_time() {
+        let mut world = World::empty();
+        let target = damageable(&mut world, 1, 10.0, DeathBehavior::EventOnly);
+        let now = world.elapsed();
+        world.damageables[target.0]
+            .as_mut()
+            .unwrap()
+            .grant_invulnerability(now, 1.0);
+        assert_eq!(
+            world.apply_damage(target, 1.0, None),
+            DamageResult::Invulnerable
+        );
+        assert_eq!(world.drain_events().count(), 0);
+
+        world.elapsed = 1.0;
+        assert!(world.apply_damage(target, 1.0, None).landed());
+        assert_eq!(world.get_health(target).unwrap().current, 9.0);
+    }
+
+    #[test]
+    fn self_damage_lands_despite_the_team() {
+        let mut world = World::empty();
+        let a = damageable(&mut world, 1, 10.0, DeathBehavior::EventOnly);
+        assert!(world.apply_damage(a, 1.0, Some(a)).landed());
+        let plain = world.spawn();
+        assert_eq!(
+            world.apply_damage(plain, 1.0, None),
+            DamageResult::NotDamageable
+        );
+    }
+
+    #[test]
+    fn each_death_behavior_runs_on_the_killing_blow() {
+        let mut world = World::empty();
+        let despawned = damageable(&mut world, 0, 1.0, DeathBehavior::Despawn);
+        let faded = damageable(
+            &mut world,
+            0,
+            1.0,
+            DeathBehavior::FadeOut(Duration::from_secs(1)),
+        );
+        let kept = damageable(&mut world, 0, 1.0, DeathBehavior::EventOnly);
+        for entity in [despawned, faded, kept] {
+            assert_eq!(world.apply_damage(entity, 5.0, None), DamageResult::Killed);
+        }
+
+        assert!(world.get_health(despawned).is_none());
+
+        assert!(world.fades[faded.0].is_some_and(|fade| fade.started()));
+        assert!(world.tints[faded.0].is_some());
+        assert_eq!(world.get_health(faded).map(|h| h.current), Some(0.0));
+
+        assert_eq!(world.get_health(kept).map(|h| h.current), Some(0.0));
+        assert!(world.fades[kept.0].is_none());
+        let died = world
+            .drain_events()
+            .filter(|e| matches!(e, WorldEvent::EntityDied { .. }))
+            .count();
+        assert_eq!(died, 3);
+    }
+
+    static EXPLODE: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(true);
+
+    fn explode(_: &crate::ecs::SystemContext) {
+        if EXPLODE.load(std::sync::atomic::Ordering::Relaxed) {
+            panic!("system blew up");
+        }
+    }
+
+    /// Needs an adapter; passes without one.
+    #[test]
+    fn a_panicking_tick_is_contained_until_a_scene_reload() {
+        let Some((mut models, _)) = models() else {
+            return;
+        };
+        let (device, queue) =
+            crate::GPU::with_read_recovered(|gpu| (gpu.device().clone(), gpu.queue().clone()))
+                .unwrap();
+        let camera = Camera::new(&device, 1.0);
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            width: 64,
+            height: 64,
+            present_mode: wgpu::PresentMode::Fifo,
+            alpha_mode: wgpu::CompositeAlphaMode::Opaque,
+            view_formats: vec![],
+            desired_maximum_frame_latency: 2,
+        };
+        let scene = std::env::temp_dir().join(format!("rupy_poison_{}.ron", std::process::id()));
+        World::empty().save_scene(&scene).unwrap();
+
+        let mut world = World::empty();
+        world.set_fixed_timestep(FixedTimestep {
+            step: 0.1,
+            max_steps: 8,
+        });
+        world
+            .schedule
+            .add("explode", crate::ecs::Access::new(), explode);
+        world.update(&queue, &device, &camera, 0.1);
+        assert!(!world.is_healthy());
+        assert_eq!(world.tick_error(), Some("system blew up"));
+
+        let elapsed = world.elapsed();
+        world.update(&queue, &device, &camera, 1.0);
+        assert_eq!(world.elapsed(), elapsed, "skipped while unhealthy");
+
+        EXPLODE.store(false, std::sync::atomic::Ordering::Relaxed);
+        world.load_scene(&scene, &mut models, &config).unwrap();
+        assert!(world.is_healthy());
+        world.update(&queue, &device, &camera, 0.1);
+        assert!(world.is_healthy());
+        assert!(world.elapsed() > elapsed);
+        let _ = std::fs::remove_file(scene);
+    }
+
+    fn write_scene(name: &str, source: &str) {
+        std::fs::write(SceneFile::path(name), source).unwrap();
+    }
+
+    /// A single untextured triangle; the shipped models reference their
+    /// textures with Windows separators.
+    fn write_model(name: &str) {
+        let obj =
+            "v 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0 0\nvt 1 0\nvt 0 1\nvn 0 0 1\nf 1/1/1 2/2/1 3/3/1\n";
+        std::fs::write(crate::AssetPaths::model(name), obj).unwrap();
+    }
+
+    /// Needs an adapter; passes without one.
+    #[test]
+    fn switching_scenes_releases_the_previous_models() {
+        let Some((mut models, auto_load)) = models() else {
+            return;
+        };
+        write_model("switch_tile.obj");
+        write_model("switch_boss.obj");
+        write_scene(
+            "switch_from",
+            r#"(entities: [
+                Grid(model: "switch_tile.obj", origin: (0.0, 0.0, 0.0), from: (0, 0, 0), to: (2, 0, 2)),
+                Model(model: "switch_boss.obj", name: Some("boss"), position: (5.0, 0.0, 0.0)),
+            ])"#,
+        );
+        write_scene("switch_to", "(entities: [])");
+        let config = &auto_load.surface_configuration;
+        let depth = RenderSettings::depth_state(DepthVariant::Opaque);
+        let baseline = models.models.len();
+        let mut world = World::empty();
+
+        let scene = world
+            .load_scene_by_name(&mut models, "switch_from", config, &depth)
+            .unwrap();
+        assert_eq!(scene.entities.len(), 10);
+        assert!(scene.warnings.is_empty(), "{:?}", scene.warnings);
+        assert!(scene.entity("boss").is_some());
+        assert_eq!(models.models.len(), baseline + 2);
+
+        let scene = world
+            .load_scene_by_name(&mut models, "switch_to", config, &depth)
+            .unwrap();
+        assert_eq!(scene.name, "switch_to");
+        assert_eq!(models.models.len(), baseline);
+        assert!(world.renderables.iter().all(Option::is_none));
+    }
+
+    /// Needs an adapter; passes without one.
+    #[test]
+    fn scenes_with_missing_assets_load_partially() {
+        let Some((mut models, auto_load)) = models() else {
+            return;
+        };
+        write_model("partial_tile.obj");
+        write_scene(
+            "partial",
+            r#"(entities: [
+                Model(model: "partial_tile.obj", position: (0.0, 0.0, 0.0)),
+                Model(model: "missing.obj", position: (1.0, 0.0, 0.0)),
+            ])"#,
+        );
+        let config = &auto_load.surface_configuration;
+        let depth = RenderSettings::depth_state(DepthVariant::Opaque);
+        let mut world = World::empty();
+
+        let scene = world
+            .load_scene_by_name(&mut models, "partial", config, &depth)
+            .unwrap();
+        assert_eq!(scene.entities.len(), 1);
+        assert_eq!(scene.warnings.len(), 1);
+        assert!(
+            scene.warnings[0].contains("missing.obj"),
+            "{:?}",
+            scene.warnings
+        );
+
+        // A scene that isn't there fails before the current one is unloaded.
+        assert!(world
+            .load_scene_by_name(&mut models, "no_such_scene", config, &depth)
+            .is_err());
+        assert_eq!(world.scene().map(|s| s.name.as_str()), Some("partial"));
     }
 }
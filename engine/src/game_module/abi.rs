@@ -0,0 +1,287 @@
+//! The C ABI between the engine and a game library. Everything here is `#[repr(C)]` and
+//! passed by pointer; the engine never hands out Rust types whose layout could differ
+//! between the two builds.
+
+use std::ffi::c_void;
+
+/// Bumped whenever anything in this file changes shape. A library built against another
+/// version is refused before any of its functions run.
+pub const GAME_ABI_VERSION: u32 = 1;
+
+/// Status every exported game function returns.
+pub const GAME_OK: u32 = 0;
+/// The call panicked; the panic was caught inside the library.
+pub const GAME_PANICKED: u32 = 1;
+/// The [`EngineApi`] handed in has a different [`GAME_ABI_VERSION`].
+pub const GAME_ABI_MISMATCH: u32 = 2;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct GameVec3 {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+impl From<glam::DVec3> for GameVec3 {
+    fn from(v: glam::DVec3) -> Self {
+        Self {
+            x: v.x,
+            y: v.y,
+            z: v.z,
+        }
+    }
+}
+impl From<GameVec3> for glam::DVec3 {
+    fn from(v: GameVec3) -> Self {
+        glam::DVec3::new(v.x, v.y, v.z)
+    }
+}
+
+#[repr(u32)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum GameEventKind {
+    SnapshotStarved = 0,
+    Expired = 1,
+    Landed = 2,
+    Sequence = 3,
+    DamageTaken = 4,
+    Died = 5,
+}
+
+/// A [`crate::WorldEvent`] flattened for the boundary. Entity fields are `-1` when absent;
+/// `value` is the landing speed or damage amount; `name` the sequence event name.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct GameEvent {
+    pub kind: GameEventKind,
+    pub entity: i64,
+    pub other: i64,
+    pub value: f32,
+    pub name: *const u8,
+    pub name_len: usize,
+}
+
+impl GameEvent {
+    pub fn entity(&self) -> Option<u64> {
+        u64::try_from(self.entity).ok()
+    }
+    pub fn other(&self) -> Option<u64> {
+        u64::try_from(self.other).ok()
+    }
+    /// Only valid during the `game_on_event` call that received the event.
+    pub fn name(&self) -> &str {
+        if self.name.is_null() {
+            return "";
+        }
+        // SAFETY: the host points `name` at a live UTF-8 string for the length of the call.
+        unsafe {
+            std::str::from_utf8_unchecked(std::slice::from_raw_parts(self.name, self.name_len))
+        }
+    }
+}
+
+/// Byte sink the library writes saved state or error messages into.
+#[repr(C)]
+pub struct ByteSink {
+    pub ctx: *mut c_void,
+    pub write: extern "C" fn(ctx: *mut c_void, data: *const u8, len: usize),
+}
+
+impl ByteSink {
+    pub fn write(&mut self, bytes: &[u8]) {
+        (self.write)(self.ctx, bytes.as_ptr(), bytes.len());
+    }
+}
+
+/// What game code can do to the world: a table of engine functions over an opaque
+/// context. Positions are global (see [`crate::WorldOrigin`]); entities are ids.
+#[repr(C)]
+pub struct EngineApi {
+    pub abi_version: u32,
+    pub ctx: *mut c_void,
+    pub elapsed: extern "C" fn(ctx: *mut c_void) -> f64,
+    pub position: extern "C" fn(ctx: *mut c_void, entity: u64, out: *mut GameVec3) -> bool,
+    pub set_position: extern "C" fn(ctx: *mut c_void, entity: u64, position: GameVec3) -> bool,
+    pub velocity: extern "C" fn(ctx: *mut c_void, entity: u64, out: *mut GameVec3) -> bool,
+    pub set_velocity: extern "C" fn(ctx: *mut c_void, entity: u64, velocity: GameVec3) -> bool,
+    /// Scene entity by name, `-1` if there's none.
+    pub find: extern "C" fn(ctx: *mut c_void, name: *const u8, len: usize) -> i64,
+    pub damage: extern "C" fn(ctx: *mut c_void, target: u64, amount: f32, source: i64),
+    pub despawn: extern "C" fn(ctx: *mut c_void, entity: u64),
+    pub log: extern "C" fn(ctx: *mut c_void, message: *const u8, len: usize),
+}
+
+impl EngineApi {
+    pub fn elapsed(&self) -> f64 {
+        (self.elapsed)(self.ctx)
+    }
+    pub fn position(&self, entity: u64) -> Option<GameVec3> {
+        let mut out = GameVec3::default();
+        (self.position)(self.ctx, entity, &mut out).then_some(out)
+    }
+    pub fn set_position(&mut self, entity: u64, position: GameVec3) -> bool {
+        (self.set_position)(self.ctx, entity, position)
+    }
+    pub fn velocity(&self, entity: u64) -> Option<GameVec3> {
+        let mut out = GameVec3::default();
+        (self.velocity)(self.ctx, entity, &mut out).then_some(out)
+    }
+    pub fn set_velocity(&mut self, entity: u64, velocity: GameVec3) -> bool {
+        (self.set_velocity)(self.ctx, entity, velocity)
+    }
+    pub fn find(&self, name: &str) -> Option<u64> {
+        u64::try_from((self.find)(self.ctx, name.as_ptr(), name.len())).ok()
+    }
+    pub fn damage(&mut self, target: u64, amount: f32, source: Option<u64>) {
+        let source = source.and_then(|s| i64::try_from(s).ok()).unwrap_or(-1);
+        (self.damage)(self.ctx, target, amount, source);
+    }
+    pub fn despawn(&mut self, entity: u64) {
+        (self.despawn)(self.ctx, entity);
+    }
+    pub fn log(&self, message: &str) {
+        (self.log)(self.ctx, message.as_ptr(), message.len());
+    }
+}
+
+/// Gameplay living in a game library. Implement it on a `Default` type and export it
+/// with [`crate::export_game`].
+pub trait GameModule: Default + Send {
+    fn init(&mut self, _api: &mut EngineApi) {}
+    fn update(&mut self, api: &mut EngineApi, dt: f32);
+    fn on_event(&mut self, _api: &mut EngineApi, _event: &GameEvent) {}
+    fn shutdown(&mut self, _api: &mut EngineApi) {}
+    /// State handed to the next build of the library on reload.
+    fn save_state(&self) -> Vec<u8> {
+        Vec::new()
+    }
+    fn load_state(&mut self, _state: &[u8]) {}
+}
+
+/// Exports a [`GameModule`] as the `game_*` functions the host looks up. Every call is
+/// wrapped in `catch_unwind`, since a panic must not unwind across `extern "C"`; a caught
+/// panic is reported as [`GAME_PANICKED`] and its message through `game_last_error`. The
+/// functions taking pointers are `unsafe`: the host must pass live ones for each call.
+#[macro_export]
+macro_rules! export_game {
+    ($game:ty) => {
+        static __GAME: std::sync::Mutex<Option<$game>> = std::sync::Mutex::new(None);
+        static __GAME_ERROR: std::sync::Mutex<String> = std::sync::Mutex::new(String::new());
+
+        fn __game_guard(f: impl FnOnce() -> u32) -> u32 {
+            match std::panic::catch_unwind(std::panic::AssertUnwindSafe(f)) {
+                Ok(status) => status,
+                Err(panic) => {
+                    let message = panic
+                        .downcast_ref::<&str>()
+                        .map(|s| s.to_string())
+                        .or_else(|| panic.downcast_ref::<String>().cloned())
+                        .unwrap_or_else(|| "unknown panic".to_string());
+                    *__GAME_ERROR
+                        .lock()
+                        .unwrap_or_else(std::sync::PoisonError::into_inner) = message;
+                    $crate::game_module::GAME_PANICKED
+                }
+            }
+        }
+        fn __game_with(f: impl FnOnce(&mut $game) -> u32) -> u32 {
+            __game_guard(|| {
+                let mut game = __GAME
+                    .lock()
+                    .unwrap_or_else(std::sync::PoisonError::into_inner);
+                f(game.get_or_insert_with(<$game as Default>::default))
+            })
+        }
+        fn __game_call(
+            api: *mut $crate::game_module::EngineApi,
+            f: impl FnOnce(&mut $game, &mut $crate::game_module::EngineApi),
+        ) -> u32 {
+            __game_with(|game| {
+                // SAFETY: the host passes a live `EngineApi` for the length of the call.
+                match unsafe { api.as_mut() } {
+                    Some(api) if api.abi_version == $crate::game_module::GAME_ABI_VERSION => {
+                        f(game, api);
+                        $crate::game_module::GAME_OK
+                    }
+                    _ => $crate::game_module::GAME_ABI_MISMATCH,
+                }
+            })
+        }
+
+        #[no_mangle]
+        pub extern "C" fn game_abi_version() -> u32 {
+            $crate::game_module::GAME_ABI_VERSION
+        }
+        #[no_mangle]
+        pub unsafe extern "C" fn game_init(api: *mut $crate::game_module::EngineApi) -> u32 {
+            __game_call(api, |game, api| {
+                $crate::game_module::GameModule::init(game, api)
+            })
+        }
+        #[no_mangle]
+        pub unsafe extern "C" fn game_update(
+            api: *mut $crate::game_module::EngineApi,
+            dt: f32,
+        ) -> u32 {
+            __game_call(api, |game, api| {
+                $crate::game_module::GameModule::update(game, api, dt)
+            })
+        }
+        #[no_mangle]
+        pub unsafe extern "C" fn game_on_event(
+            api: *mut $crate::game_module::EngineApi,
+            event: *const $crate::game_module::GameEvent,
+        ) -> u32 {
+            // SAFETY: the host passes a live event for the length of the call.
+            let Some(event) = (unsafe { event.as_ref() }) else {
+                return $crate::game_module::GAME_OK;
+            };
+            __game_call(api, |game, api| {
+                $crate::game_module::GameModule::on_event(game, api, event)
+            })
+        }
+        #[no_mangle]
+        pub unsafe extern "C" fn game_shutdown(api: *mut $crate::game_module::EngineApi) -> u32 {
+            let status = __game_call(api, |game, api| {
+                $crate::game_module::GameModule::shutdown(game, api)
+            });
+            __GAME
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner)
+                .take();
+            status
+        }
+        #[no_mangle]
+        pub unsafe extern "C" fn game_save_state(sink: *mut $crate::game_module::ByteSink) -> u32 {
+            __game_with(|game| {
+                // SAFETY: the host passes a live sink for the length of the call.
+                if let Some(sink) = unsafe { sink.as_mut() } {
+                    sink.write(&$crate::game_module::GameModule::save_state(game));
+                }
+                $crate::game_module::GAME_OK
+            })
+        }
+        #[no_mangle]
+        pub unsafe extern "C" fn game_load_state(data: *const u8, len: usize) -> u32 {
+            __game_with(|game| {
+                if !data.is_null() {
+                    // SAFETY: the host passes `len` readable bytes for the length of the call.
+                    let state = unsafe { std::slice::from_raw_parts(data, len) };
+                    $crate::game_module::GameModule::load_state(game, state);
+                }
+                $crate::game_module::GAME_OK
+            })
+        }
+        #[no_mangle]
+        pub unsafe extern "C" fn game_last_error(sink: *mut $crate::game_module::ByteSink) {
+            let error = __GAME_ERROR
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner);
+            // SAFETY: the host passes a live sink for the length of the call.
+            if let Some(sink) = unsafe { sink.as_mut() } {
+                sink.write(error.as_bytes());
+            }
+        }
+    };
+}
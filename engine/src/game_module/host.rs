@@ -0,0 +1,725 @@
+use std::ffi::c_void;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use libloading::Library;
+
+use super::{
+    ByteSink, EngineApi, GameEvent, GameEventKind, GameVec3, GAME_ABI_MISMATCH, GAME_ABI_VERSION,
+    GAME_OK, GAME_PANICKED,
+};
+use crate::{
+    log_debug, log_error, log_info, AssetWatcher, EngineError, Entity, Position, Velocity, World,
+    WorldEvent,
+};
+
+type AbiVersionFn = unsafe extern "C" fn() -> u32;
+type CallFn = unsafe extern "C" fn(*mut EngineApi) -> u32;
+type UpdateFn = unsafe extern "C" fn(*mut EngineApi, f32) -> u32;
+type EventFn = unsafe extern "C" fn(*mut EngineApi, *const GameEvent) -> u32;
+type SaveStateFn = unsafe extern "C" fn(*mut ByteSink) -> u32;
+type LoadStateFn = unsafe extern "C" fn(*const u8, usize) -> u32;
+type LastErrorFn = unsafe extern "C" fn(*mut ByteSink);
+
+/// The `game_*` functions of a loaded library. Only valid while the library it was
+/// resolved from is loaded.
+#[derive(Copy, Clone)]
+struct Exports {
+    init: CallFn,
+    update: UpdateFn,
+    on_event: EventFn,
+    shutdown: CallFn,
+    save_state: SaveStateFn,
+    load_state: LoadStateFn,
+    last_error: LastErrorFn,
+}
+
+struct LoadedModule {
+    exports: Exports,
+    library: Option<Library>,
+    /// The shadow copy the library was loaded from, so the build can overwrite the
+    /// original while it's in use.
+    copy: PathBuf,
+}
+
+impl LoadedModule {
+    fn open(path: &Path, copy: PathBuf) -> Result<Self, EngineError> {
+        std::fs::copy(path, &copy).map_err(|e| {
+            EngineError::GameModuleError(format!("copying {}: {}", path.display(), e))
+        })?;
+        // SAFETY: loading runs the library's initializers; game libraries are built from
+        // this workspace against the same engine.
+        let module = unsafe { Library::new(&copy) }
+            .map_err(|e| EngineError::GameModuleError(format!("loading {}: {}", path.display(), e)))
+            .and_then(|library| {
+                // SAFETY: as above; the symbols are resolved against the loaded library.
+                let exports = unsafe { Exports::resolve(&library, path) }?;
+                Ok(Self {
+                    exports,
+                    library: Some(library),
+                    copy: copy.clone(),
+                })
+            });
+        if module.is_err() {
+            let _ = std::fs::remove_file(&copy);
+        }
+        module
+    }
+    fn last_error(&self) -> String {
+        let mut message = Vec::new();
+        // SAFETY: the library is loaded and the sink outlives the call.
+        unsafe { (self.exports.last_error)(&mut byte_sink(&mut message)) };
+        String::from_utf8_lossy(&message).into_owned()
+    }
+}
+
+impl Drop for LoadedModule {
+    fn drop(&mut self) {
+        // Unload before removing the file it was mapped from.
+        drop(self.library.take());
+        let _ = std::fs::remove_file(&self.copy);
+    }
+}
+
+impl Exports {
+    /// The symbol types match what `export_game!` generates for [`GAME_ABI_VERSION`],
+    /// which is checked before anything else is resolved.
+    unsafe fn resolve(library: &Library, path: &Path) -> Result<Self, EngineError> {
+        let version = (*symbol::<AbiVersionFn>(library, "game_abi_version")?)();
+        if version != GAME_ABI_VERSION {
+            return Err(EngineError::GameModuleError(format!(
+                "{} was built for game ABI {}, the engine speaks {}; rebuild it",
+                path.display(),
+                version,
+                GAME_ABI_VERSION
+            )));
+        }
+        Ok(Self {
+            init: *symbol(library, "game_init")?,
+            update: *symbol(library, "game_update")?,
+            on_event: *symbol(library, "game_on_event")?,
+            shutdown: *symbol(library, "game_shutdown")?,
+            save_state: *symbol(library, "game_save_state")?,
+            load_state: *symbol(library, "game_load_state")?,
+            last_error: *symbol(library, "game_last_error")?,
+        })
+    }
+}
+
+unsafe fn symbol<'a, T>(
+    library: &'a Library,
+    name: &str,
+) -> Result<libloading::Symbol<'a, T>, EngineError> {
+    library
+        .get(name.as_bytes())
+        .map_err(|e| EngineError::GameModuleError(format!("missing `{}`: {}", name, e)))
+}
+
+/// Loads gameplay code from a dynamic library built with [`crate::export_game`] and swaps
+/// it for the rebuilt library whenever the artifact changes, carrying the module's saved
+/// state across. Only compiled with the `hot-reload-game` feature.
+///
+/// A panic in game code is caught inside the library; the host then drops the module
+/// without calling `game_shutdown`, keeps the message in [`GameModuleHost::last_error`] and
+/// runs without it until the next build lands.
+pub struct GameModuleHost {
+    path: PathBuf,
+    module: Option<LoadedModule>,
+    /// State saved by the last module, waiting for the next one to load it.
+    state: Option<Vec<u8>>,
+    last_error: Option<String>,
+    generation: u64,
+    /// When the artifact last changed, if that change hasn't been picked up yet.
+    changed: Arc<Mutex<Option<Instant>>>,
+    _watcher: Option<AssetWatcher>,
+}
+
+impl GameModuleHost {
+    /// How long the artifact has to stay untouched before it's reloaded, so a library the
+    /// linker is still writing isn't picked up.
+    pub const SETTLE: Duration = Duration::from_millis(250);
+
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            module: None,
+            state: None,
+            last_error: None,
+            generation: 0,
+            changed: Arc::new(Mutex::new(None)),
+            _watcher: None,
+        }
+    }
+    /// `target/debug/libgame.so` (`game.dll`, `libgame.dylib`), where `cargo build -p game`
+    /// puts the workspace's game library.
+    pub fn default_path() -> PathBuf {
+        PathBuf::from("target").join("debug").join(format!(
+            "{}game{}",
+            std::env::consts::DLL_PREFIX,
+            std::env::consts::DLL_SUFFIX
+        ))
+    }
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+    pub fn is_loaded(&self) -> bool {
+        self.module.is_some()
+    }
+    /// Why the module isn't running, if it failed to load or panicked.
+    pub fn last_error(&self) -> Option<&str> {
+        self.last_error.as_deref()
+    }
+
+    /// Loads the library, replacing the current module without saving its state. Saved
+    /// state from an earlier module is handed to the new one before `game_init`.
+    pub fn load(&mut self, world: &mut World) -> Result<(), EngineError> {
+        self.unload(world);
+        self.generation += 1;
+        let copy = std::env::temp_dir().join(format!(
+            "rupy-game-{}-{}{}",
+            std::process::id(),
+            self.generation,
+            std::env::consts::DLL_SUFFIX
+        ));
+        let module = LoadedModule::open(&self.path, copy).inspect_err(|e| {
+            self.last_error = Some(e.to_string());
+        })?;
+        let exports = module.exports;
+        self.module = Some(module);
+        self.last_error = None;
+
+        if let Some(state) = self.state.take() {
+            // SAFETY: the library is loaded and `state` outlives the call.
+            let status = unsafe { (exports.load_state)(state.as_ptr(), state.len()) };
+            if !self.check(status, "game_load_state") {
+                // Keep it for a build that can read it.
+                self.state = Some(state);
+                return Err(self.failure());
+            }
+        }
+        let mut api = engine_api(world);
+        // SAFETY: the library is loaded and `api` outlives the call.
+        let status = unsafe { (exports.init)(&mut api) };
+        if !self.check(status, "game_init") {
+            return Err(self.failure());
+        }
+        log_info!("Loaded game module {}", self.path.display());
+        Ok(())
+    }
+    /// Saves the current module's state, shuts it down and loads the library again.
+    pub fn reload(&mut self, world: &mut World) -> Result<(), EngineError> {
+        if let Some(module) = &self.module {
+            let exports = module.exports;
+            let mut state = Vec::new();
+            // SAFETY: the library is loaded and the sink outlives the call.
+            let status = unsafe { (exports.save_state)(&mut byte_sink(&mut state)) };
+            if self.check(status, "game_save_state") {
+                self.state = Some(state);
+                self.shutdown(world);
+            }
+        }
+        self.load(world)
+    }
+    /// Shuts the module down and unloads the library.
+    pub fn unload(&mut self, world: &mut World) {
+        self.shutdown(world);
+        self.module = None;
+    }
+    fn shutdown(&mut self, world: &mut World) {
+        let Some(module) = &self.module else {
+            return;
+        };
+        let exports = module.exports;
+        let mut api = engine_api(world);
+        // SAFETY: the library is loaded and `api` outlives the call.
+        let status = unsafe { (exports.shutdown)(&mut api) };
+        if self.check(status, "game_shutdown") {
+            self.module = None;
+        }
+    }
+
+    /// Starts watching the library. Rebuilds are loaded at the start of the update after
+    /// the file settles, whether or not the previous module was still running.
+    pub fn watch(&mut self) -> Result<(), EngineError> {
+        let Some(dir) = self.path.parent().filter(|dir| dir.exists()) else {
+            return Ok(());
+        };
+        if self._watcher.is_some() {
+            return Ok(());
+        }
+        let file_name = self.path.file_name().map(|name| name.to_os_string());
+        let changed = self.changed.clone();
+        let watcher = AssetWatcher::new(dir.to_path_buf(), move |event| {
+            if !matches!(
+                event.kind,
+                notify::EventKind::Modify(_) | notify::EventKind::Create(_)
+            ) {
+                return;
+            }
+            if event
+                .paths
+                .iter()
+                .any(|path| path.file_name() == file_name.as_deref())
+            {
+                *changed.lock().unwrap_or_else(PoisonError::into_inner) = Some(Instant::now());
+            }
+        })
+        .map_err(|e| EngineError::FileSystemError(e.to_string()))?;
+        self._watcher = Some(watcher);
+        Ok(())
+    }
+    fn settled_change(&self) -> bool {
+        let mut changed = self.changed.lock().unwrap_or_else(PoisonError::into_inner);
+        match *changed {
+            Some(at) if at.elapsed() >= Self::SETTLE => {
+                *changed = None;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Reloads a rebuilt library, then hands the module this update's world events (in the
+    /// order raised) and calls `game_update`.
+    pub fn update(&mut self, world: &mut World, events: &[WorldEvent], dt: f32) {
+        if self.settled_change() {
+            log_debug!("Game module changed: {}", self.path.display());
+            if let Err(e) = self.reload(world) {
+                log_error!("{}", e);
+            }
+        }
+        let Some(module) = &self.module else {
+            return;
+        };
+        let exports = module.exports;
+        let mut api = engine_api(world);
+        for event in events {
+            let status = with_game_event(event, |event| {
+                // SAFETY: the library is loaded; `api` and `event` outlive the call.
+                unsafe { (exports.on_event)(&mut api, event) }
+            });
+            if !self.check(status, "game_on_event") {
+                return;
+            }
+        }
+        // SAFETY: the library is loaded and `api` outlives the call.
+        let status = unsafe { (exports.update)(&mut api, dt) };
+        self.check(status, "game_update");
+    }
+
+    /// `true` if `status` is [`GAME_OK`]; otherwise the module is dropped without a
+    /// shutdown call and the reason kept as the last error.
+    fn check(&mut self, status: u32, call: &str) -> bool {
+        if status == GAME_OK {
+            return true;
+        }
+        let reason = match status {
+            GAME_PANICKED => format!(
+                "{} panicked: {}",
+                call,
+                self.module
+                    .as_ref()
+                    .map(LoadedModule::last_error)
+                    .unwrap_or_default()
+            ),
+            GAME_ABI_MISMATCH => format!("{} rejected the engine API version", call),
+            other => format!("{} failed with status {}", call, other),
+        };
+        log_error!("Game module unloaded: {}", reason);
+        self.last_error = Some(reason);
+        self.module = None;
+        false
+    }
+    fn failure(&self) -> EngineError {
+        EngineError::GameModuleError(self.last_error.clone().unwrap_or_default())
+    }
+}
+
+impl Default for GameModuleHost {
+    fn default() -> Self {
+        Self::new(Self::default_path())
+    }
+}
+
+impl std::fmt::Debug for GameModuleHost {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GameModuleHost")
+            .field("path", &self.path)
+            .field("loaded", &self.is_loaded())
+            .field("last_error", &self.last_error)
+            .finish()
+    }
+}
+
+fn byte_sink(out: &mut Vec<u8>) -> ByteSink {
+    extern "C" fn write(ctx: *mut c_void, data: *const u8, len: usize) {
+        if data.is_null() {
+            return;
+        }
+        // SAFETY: `ctx` is the `Vec` the sink was made from, `data` has `len` bytes.
+        unsafe {
+            (*(ctx as *mut Vec<u8>)).extend_from_slice(std::slice::from_raw_parts(data, len))
+        };
+    }
+    ByteSink {
+        ctx: out as *mut Vec<u8> as *mut c_void,
+        write,
+    }
+}
+
+fn with_game_event(event: &WorldEvent, f: impl FnOnce(&GameEvent) -> u32) -> u32 {
+    let id = |entity: Entity| entity.0 as i64;
+    let none = -1;
+    let (kind, entity, other, value, name) = match event {
+        WorldEvent::SnapshotStarved(entity) => {
+            (GameEventKind::SnapshotStarved, id(*entity), none, 0.0, None)
+        }
+        WorldEvent::EntityExpired(entity, reason) => (
+            GameEventKind::Expired,
+            id(*entity),
+            none,
+            0.0,
+            Some(format!("{:?}", reason)),
+        ),
+        WorldEvent::Landed(entity, speed) => {
+            (GameEventKind::Landed, id(*entity), none, *speed, None)
+        }
+        WorldEvent::Sequence(name) => {
+            (GameEventKind::Sequence, none, none, 0.0, Some(name.clone()))
+        }
+        WorldEvent::DamageTaken {
+            target,
+            amount,
+            source,
+            ..
+        } => (
+            GameEventKind::DamageTaken,
+            id(*target),
+            source.map_or(none, id),
+            *amount,
+            None,
+        ),
+        WorldEvent::EntityDied { entity, source } => (
+            GameEventKind::Died,
+            id(*entity),
+            source.map_or(none, id),
+            0.0,
+            None,
+        ),
+        // Sounds are for the mixer; the ABI has no event kind for them.
+        WorldEvent::PlaySound { .. } => return GAME_OK,
+    };
+    let name = name.unwrap_or_default();
+    f(&GameEvent {
+        kind,
+        entity,
+        other,
+        value,
+        name: name.as_ptr(),
+        name_len: name.len(),
+    })
+}
+
+/// The engine side of [`EngineApi`], over `world`. The functions never panic: a panic
+/// can't unwind back into the library.
+fn engine_api(world: &mut World) -> EngineApi {
+    EngineApi {
+        abi_version: GAME_ABI_VERSION,
+        ctx: world as *mut World as *mut c_void,
+        elapsed: api_elapsed,
+        position: api_position,
+        set_position: api_set_position,
+        velocity: api_velocity,
+        set_velocity: api_set_velocity,
+        find: api_find,
+        damage: api_damage,
+        despawn: api_despawn,
+        log: api_log,
+    }
+}
+
+fn world_of<'a>(ctx: *mut c_void) -> &'a mut World {
+    // SAFETY: `ctx` is the world the `EngineApi` was made from, exclusively borrowed by the
+    // host for the length of the call into the library.
+    unsafe { &mut *(ctx as *mut World) }
+}
+
+fn text<'a>(data: *const u8, len: usize) -> Option<&'a str> {
+    if data.is_null() {
+        return None;
+    }
+    // SAFETY: the library passes `len` readable bytes for the length of the call.
+    std::str::from_utf8(unsafe { std::slice::from_raw_parts(data, len) }).ok()
+}
+
+fn entity(id: u64) -> Entity {
+    Entity(usize::try_from(id).unwrap_or(usize::MAX))
+}
+
+extern "C" fn api_elapsed(ctx: *mut c_void) -> f64 {
+    world_of(ctx).elapsed()
+}
+extern "C" fn api_position(ctx: *mut c_void, id: u64, out: *mut GameVec3) -> bool {
+    let world = world_of(ctx);
+    let Some(Some(position)) = world.physics.positions.get(entity(id).0) else {
+        return false;
+    };
+    // SAFETY: `out` is null or points at a `GameVec3` the library owns.
+    if let Some(out) = unsafe { out.as_mut() } {
+        *out = world.origin().to_global(position.0).into();
+    }
+    true
+}
+extern "C" fn api_set_position(ctx: *mut c_void, id: u64, position: GameVec3) -> bool {
+    let world = world_of(ctx);
+    let local = world.origin().to_local(position.into());
+    match world.physics.positions.get_mut(entity(id).0) {
+        Some(slot) => {
+            *slot = Some(Position(local));
+            true
+        }
+        None => false,
+    }
+}
+extern "C" fn api_velocity(ctx: *mut c_void, id: u64, out: *mut GameVec3) -> bool {
+    let Some(Some(velocity)) = world_of(ctx).physics.velocities.get(entity(id).0) else {
+        return false;
+    };
+    // SAFETY: `out` is null or points at a `GameVec3` the library owns.
+    if let Some(out) = unsafe { out.as_mut() } {
+        *out = velocity.0.as_dvec3().into();
+    }
+    true
+}
+extern "C" fn api_set_velocity(ctx: *mut c_void, id: u64, velocity: GameVec3) -> bool {
+    let velocity = glam::DVec3::from(velocity).as_vec3();
+    match world_of(ctx).physics.velocities.get_mut(entity(id).0) {
+        Some(slot) => {
+            *slot = Some(Velocity(velocity));
+            true
+        }
+        None => false,
+    }
+}
+extern "C" fn api_find(ctx: *mut c_void, name: *const u8, len: usize) -> i64 {
+    text(name, len)
+        .and_then(|name| world_of(ctx).scene()?.entity(name))
+        .map_or(-1, |entity| entity.0 as i64)
+}
+extern "C" fn api_damage(ctx: *mut c_void, target: u64, amount: f32, source: i64) {
+    let source = u64::try_from(source).ok().map(entity);
+    world_of(ctx).apply_damage(entity(target), amount, source);
+}
+extern "C" fn api_despawn(ctx: *mut c_void, id: u64) {
+    world_of(ctx).despawn(entity(id));
+}
+extern "C" fn api_log(_ctx: *mut c_void, message: *const u8, len: usize) {
+    if let Some(message) = text(message, len) {
+        log_info!("[game] {}", message);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A game library without the engine: it keeps a log of the calls it got as its
+    /// state, and `game_update` with a negative `dt` panics inside the library the way
+    /// `export_game!` contains it.
+    const STUB: &str = r#"
+        use std::ffi::c_void;
+        use std::sync::Mutex;
+
+        #[repr(C)]
+        pub struct ByteSink {
+            ctx: *mut c_void,
+            write: extern "C" fn(*mut c_void, *const u8, usize),
+        }
+
+        static CALLS: Mutex<Vec<u8>> = Mutex::new(Vec::new());
+        static ERROR: Mutex<String> = Mutex::new(String::new());
+
+        fn call(tag: u8) -> u32 {
+            CALLS.lock().unwrap().push(tag);
+            0
+        }
+
+        #[no_mangle]
+        pub extern "C" fn game_abi_version() -> u32 {
+            VERSION
+        }
+        #[no_mangle]
+        pub unsafe extern "C" fn game_init(api: *const u32) -> u32 {
+            if *api != VERSION {
+                return 2;
+            }
+            call(b'i')
+        }
+        #[no_mangle]
+        pub unsafe extern "C" fn game_update(_api: *mut c_void, dt: f32) -> u32 {
+            if dt >= 0.0 {
+                return call(b'u');
+            }
+            std::panic::set_hook(Box::new(|_| {}));
+            match std::panic::catch_unwind(|| panic!("negative dt")) {
+                Ok(()) => 0,
+                Err(panic) => {
+                    *ERROR.lock().unwrap() = panic.downcast_ref::<&str>().unwrap().to_string();
+                    1
+                }
+            }
+        }
+        #[no_mangle]
+        pub unsafe extern "C" fn game_on_event(_api: *mut c_void, _event: *const c_void) -> u32 {
+            call(b'e')
+        }
+        #[no_mangle]
+        pub unsafe extern "C" fn game_shutdown(_api: *mut c_void) -> u32 {
+            0
+        }
+        #[no_mangle]
+        pub unsafe extern "C" fn game_save_state(sink: *mut ByteSink) -> u32 {
+            let calls = CALLS.lock().unwrap();
+            ((*sink).write)((*sink).ctx, calls.as_ptr(), calls.len());
+            0
+        }
+        #[no_mangle]
+        pub unsafe extern "C" fn game_load_state(data: *const u8, len: usize) -> u32 {
+            let mut calls = CALLS.lock().unwrap();
+            *calls = std::slice::from_raw_parts(data, len).to_vec();
+            calls.push(b'l');
+            0
+        }
+        #[no_mangle]
+        pub unsafe extern "C" fn game_last_error(sink: *mut ByteSink) {
+            let error = ERROR.lock().unwrap();
+            ((*sink).write)((*sink).ctx, error.as_ptr(), error.len());
+        }
+    "#;
+
+    /// Builds [`STUB`] for ABI `version` into a scratch directory. `None` without a
+    /// `rustc` to build it with.
+    fn stub(name: &str, version: u32) -> Option<PathBuf> {
+        let dir = std::env::temp_dir().join(format!("rupy_game_{}_{}", std::process::id(), name));
+        std::fs::create_dir_all(&dir).ok()?;
+        let source = dir.join("stub.rs");
+        let source_text = format!("const VERSION: u32 = {};\n{}", version, STUB);
+        std::fs::write(&source, source_text).ok()?;
+        let library = dir.join(format!(
+            "{}stub{}",
+            std::env::consts::DLL_PREFIX,
+            std::env::consts::DLL_SUFFIX
+        ));
+        let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+        let status = std::process::Command::new(rustc)
+            .args(["--crate-type", "cdylib", "--edition", "2021", "-o"])
+            .arg(&library)
+            .arg(&source)
+            .status()
+            .ok()?;
+        status.success().then_some(library)
+    }
+
+    /// The calls the loaded module has logged, read through `game_save_state`.
+    fn calls(host: &GameModuleHost) -> String {
+        let exports = host.module.as_ref().unwrap().exports;
+        let mut state = Vec::new();
+        // SAFETY: the library is loaded and the sink outlives the call.
+        unsafe { (exports.save_state)(&mut byte_sink(&mut state)) };
+        String::from_utf8(state).unwrap()
+    }
+
+    fn shadow_copy(generation: u64) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "rupy-game-{}-{}{}",
+            std::process::id(),
+            generation,
+            std::env::consts::DLL_SUFFIX
+        ))
+    }
+
+    /// Needs `rustc`; passes without one.
+    #[test]
+    fn modules_load_unload_and_refuse_other_abi_versions() {
+        let (Some(current), Some(newer)) = (
+            stub("current", GAME_ABI_VERSION),
+            stub("newer", GAME_ABI_VERSION + 1),
+        ) else {
+            return;
+        };
+        let mut world = World::empty();
+
+        let mut host = GameModuleHost::new(&current);
+        assert!(!host.is_loaded());
+        host.load(&mut world).unwrap();
+        assert!(host.is_loaded());
+        assert_eq!(calls(&host), "i");
+        let copy = shadow_copy(host.generation);
+        assert!(copy.exists());
+        host.unload(&mut world);
+        assert!(!host.is_loaded());
+        assert!(!copy.exists(), "the shadow copy goes with the library");
+
+        let mut host = GameModuleHost::new(&newer);
+        let error = host.load(&mut world).unwrap_err().to_string();
+        assert!(
+            error.contains(&format!(
+                "was built for game ABI {}, the engine speaks {}; rebuild it",
+                GAME_ABI_VERSION + 1,
+                GAME_ABI_VERSION
+            )),
+            "{}",
+            error
+        );
+        assert!(!host.is_loaded());
+        assert!(error.ends_with(host.last_error().unwrap()));
+
+        let mut host = GameModuleHost::new(current.with_file_name("missing"));
+        assert!(host.load(&mut world).is_err());
+        assert!(host.last_error().unwrap().contains("copying "));
+    }
+
+    /// Needs `rustc`; passes without one.
+    #[test]
+    fn reloads_hand_the_saved_state_to_the_new_library() {
+        let Some(path) = stub("reload", GAME_ABI_VERSION) else {
+            return;
+        };
+        let mut world = World::empty();
+        let mut host = GameModuleHost::new(path);
+        host.load(&mut world).unwrap();
+        host.update(&mut world, &[WorldEvent::Sequence("door".into())], 0.1);
+        host.update(&mut world, &[], 0.1);
+        assert_eq!(calls(&host), "ieuu");
+
+        host.reload(&mut world).unwrap();
+        assert_eq!(calls(&host), "ieuuli", "loaded before init");
+        assert!(host.state.is_none());
+
+        // A plain load starts over.
+        host.load(&mut world).unwrap();
+        assert_eq!(calls(&host), "i");
+    }
+
+    /// Needs `rustc`; passes without one.
+    #[test]
+    fn panics_in_game_code_unload_the_module() {
+        let Some(path) = stub("panic", GAME_ABI_VERSION) else {
+            return;
+        };
+        let mut world = World::empty();
+        let mut host = GameModuleHost::new(path);
+        host.load(&mut world).unwrap();
+        host.update(&mut world, &[], -1.0);
+        assert!(!host.is_loaded());
+        assert_eq!(host.last_error(), Some("game_update panicked: negative dt"));
+        assert!(!shadow_copy(host.generation).exists());
+
+        host.update(&mut world, &[], 0.1);
+        assert!(!host.is_loaded());
+        host.load(&mut world).unwrap();
+        assert!(host.is_loaded());
+        assert_eq!(host.last_error(), None);
+    }
+}
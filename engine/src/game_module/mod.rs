@@ -0,0 +1,15 @@
+//! Gameplay code in a dynamic library that's swapped while the app runs. Only compiled
+//! with the `hot-reload-game` feature.
+//!
+//! The `game` crate builds as a `cdylib` exporting the functions of [`abi`] through
+//! [`crate::export_game`]; [`GameModuleHost`] loads it, calls into it every update and
+//! reloads it when `cargo build -p game` replaces it. Only `#[repr(C)]` types cross the
+//! boundary, and game code reaches the world through the [`EngineApi`] function table
+//! rather than engine types, so the two sides stay compatible as long as
+//! [`GAME_ABI_VERSION`] matches.
+
+pub mod abi;
+pub use abi::*;
+
+pub mod host;
+pub use host::*;
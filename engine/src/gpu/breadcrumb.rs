@@ -0,0 +1,224 @@
+//! GPU breadcrumbs: a per-thread stack of what the renderer is currently doing, dumped into
+//! crash reports. Compiled out unless `debug_assertions` or the `gpu_diagnostics` feature is on.
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BreadcrumbKind {
+    Encoder,
+    Pass,
+    Pipeline,
+    Draw,
+    Submit,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Breadcrumb {
+    pub kind: BreadcrumbKind,
+    pub label: String,
+    /// Material/pipeline cache key, shader path, model name or entity id when known.
+    pub detail: Option<String>,
+}
+
+impl std::fmt::Display for Breadcrumb {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.detail {
+            Some(detail) => write!(f, "{:?} '{}' ({})", self.kind, self.label, detail),
+            None => write!(f, "{:?} '{}'", self.kind, self.label),
+        }
+    }
+}
+
+#[cfg(any(debug_assertions, feature = "gpu_diagnostics"))]
+mod enabled {
+    use super::{Breadcrumb, BreadcrumbKind};
+    use std::{cell::RefCell, collections::VecDeque};
+
+    /// How many closed scopes are remembered; errors in a pass often surface at submit,
+    /// after the draw that caused them has been popped.
+    pub const TRAIL_LEN: usize = 32;
+
+    thread_local! {
+        static STACK: RefCell<Vec<Breadcrumb>> = const { RefCell::new(Vec::new()) };
+        static TRAIL: RefCell<VecDeque<Breadcrumb>> = const { RefCell::new(VecDeque::new()) };
+    }
+
+    /// Pops its breadcrumb (and anything pushed after it) when dropped, so early returns
+    /// and `?` leave the stack balanced.
+    #[must_use]
+    pub struct BreadcrumbGuard {
+        depth: usize,
+    }
+
+    impl BreadcrumbGuard {
+        pub fn new(kind: BreadcrumbKind, label: &str, detail: Option<String>) -> Self {
+            let depth = STACK.with(|stack| {
+                let mut stack = stack.borrow_mut();
+                stack.push(Breadcrumb {
+                    kind,
+                    label: label.to_string(),
+                    detail,
+                });
+                stack.len() - 1
+            });
+            Self { depth }
+        }
+    }
+
+    impl Drop for BreadcrumbGuard {
+        fn drop(&mut self) {
+            let popped: Vec<Breadcrumb> = STACK.with(|stack| {
+                let mut stack = stack.borrow_mut();
+                let depth = self.depth.min(stack.len());
+                stack.drain(depth..).collect()
+            });
+            TRAIL.with(|trail| {
+                let mut trail = trail.borrow_mut();
+                for crumb in popped.into_iter().rev() {
+                    if trail.len() >= TRAIL_LEN {
+                        trail.pop_front();
+                    }
+                    trail.push_back(crumb);
+                }
+            });
+        }
+    }
+
+    /// The open scopes on this thread, outermost first.
+    pub fn breadcrumbs() -> Vec<Breadcrumb> {
+        STACK.with(|stack| stack.borrow().clone())
+    }
+    /// The most recently closed scopes on this thread, oldest first.
+    pub fn breadcrumb_trail() -> Vec<Breadcrumb> {
+        TRAIL.with(|trail| trail.borrow().iter().cloned().collect())
+    }
+}
+
+#[cfg(not(any(debug_assertions, feature = "gpu_diagnostics")))]
+mod enabled {
+    use super::{Breadcrumb, BreadcrumbKind};
+
+    pub struct BreadcrumbGuard;
+
+    impl BreadcrumbGuard {
+        #[inline(always)]
+        pub fn new(_kind: BreadcrumbKind, _label: &str, _detail: Option<String>) -> Self {
+            Self
+        }
+    }
+
+    #[inline(always)]
+    pub fn breadcrumbs() -> Vec<Breadcrumb> {
+        Vec::new()
+    }
+    #[inline(always)]
+    pub fn breadcrumb_trail() -> Vec<Breadcrumb> {
+        Vec::new()
+    }
+}
+
+pub use enabled::*;
+
+/// Opens a breadcrumb scope until the end of the enclosing block:
+/// `gpu_scope!(Pass, "HDR Pass")` or `gpu_scope!(Draw, "model", key.id())`.
+#[cfg(any(debug_assertions, feature = "gpu_diagnostics"))]
+#[macro_export]
+macro_rules! gpu_scope {
+    ($kind:ident, $label:expr) => {
+        let _gpu_scope = $crate::BreadcrumbGuard::new($crate::BreadcrumbKind::$kind, &$label, None);
+    };
+    ($kind:ident, $label:expr, $detail:expr) => {
+        let _gpu_scope = $crate::BreadcrumbGuard::new(
+            $crate::BreadcrumbKind::$kind,
+            &$label,
+            Some(format!("{}", $detail)),
+        );
+    };
+}
+
+#[cfg(not(any(debug_assertions, feature = "gpu_diagnostics")))]
+#[macro_export]
+macro_rules! gpu_scope {
+    ($($arg:tt)*) => {};
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(any(debug_assertions, feature = "gpu_diagnostics"))]
+    fn labels(crumbs: Vec<Breadcrumb>) -> Vec<String> {
+        crumbs.into_iter().map(|c| c.label).collect()
+    }
+
+    #[cfg(any(debug_assertions, feature = "gpu_diagnostics"))]
+    fn draw(fail: bool) -> Result<(), ()> {
+        crate::gpu_scope!(Draw, "model", 7);
+        if fail {
+            return Err(());
+        }
+        crate::gpu_scope!(Submit, "queue");
+        assert_eq!(labels(breadcrumbs()), ["frame", "hdr", "model", "queue"]);
+        Ok(())
+    }
+
+    #[cfg(any(debug_assertions, feature = "gpu_diagnostics"))]
+    #[test]
+    fn nested_scopes_unwind_on_early_returns() {
+        crate::gpu_scope!(Encoder, "frame");
+        {
+            crate::gpu_scope!(Pass, "hdr");
+            assert_eq!(
+                breadcrumbs().last(),
+                Some(&Breadcrumb {
+                    kind: BreadcrumbKind::Pass,
+                    label: "hdr".to_string(),
+                    detail: None,
+                })
+            );
+            assert!(draw(true).is_err());
+            assert_eq!(labels(breadcrumbs()), ["frame", "hdr"]);
+            draw(false).unwrap();
+            assert_eq!(labels(breadcrumbs()), ["frame", "hdr"]);
+        }
+        assert_eq!(labels(breadcrumbs()), ["frame"]);
+        assert_eq!(
+            labels(breadcrumb_trail()),
+            ["model", "queue", "model", "hdr"]
+        );
+        assert_eq!(breadcrumb_trail()[0].detail.as_deref(), Some("7"));
+    }
+
+    #[cfg(any(debug_assertions, feature = "gpu_diagnostics"))]
+    #[test]
+    fn out_of_order_drops_pop_everything_above() {
+        let outer = BreadcrumbGuard::new(BreadcrumbKind::Pass, "outer", None);
+        let inner = BreadcrumbGuard::new(BreadcrumbKind::Draw, "inner", None);
+        drop(outer);
+        assert!(breadcrumbs().is_empty());
+        drop(inner);
+        assert!(breadcrumbs().is_empty());
+        assert_eq!(labels(breadcrumb_trail()), ["inner", "outer"]);
+    }
+
+    #[cfg(any(debug_assertions, feature = "gpu_diagnostics"))]
+    #[test]
+    fn the_trail_keeps_the_latest_scopes() {
+        for i in 0..TRAIL_LEN + 5 {
+            crate::gpu_scope!(Draw, format!("draw {}", i));
+        }
+        let trail = breadcrumb_trail();
+        assert_eq!(trail.len(), TRAIL_LEN);
+        assert_eq!(trail[0].label, "draw 5");
+    }
+
+    /// Runs with `cargo test --release`, where `debug_assertions` is off.
+    #[cfg(not(any(debug_assertions, feature = "gpu_diagnostics")))]
+    #[test]
+    fn compiled_out_scopes_record_nothing() {
+        assert_eq!(std::mem::size_of::<BreadcrumbGuard>(), 0);
+        crate::gpu_scope!(Pass, "hdr");
+        crate::gpu_scope!(Draw, "model", 7);
+        let _guard = BreadcrumbGuard::new(BreadcrumbKind::Submit, "queue", None);
+        assert!(breadcrumbs().is_empty());
+        assert!(breadcrumb_trail().is_empty());
+    }
+}
@@ -0,0 +1,221 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{log_info, log_warning, EngineError, GPU};
+
+/// Which adapter [`GPU::init`] takes when there are several. `RUPY_GPU_ADAPTER` overrides
+/// the one asked for, as `high-performance`, `low-power` or an index into the adapters in
+/// the order the instance lists them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AdapterSelection {
+    /// Discrete GPUs first, then integrated, virtual and software ones.
+    #[default]
+    HighPerformance,
+    /// Integrated GPUs first, then discrete, virtual and software ones.
+    LowPower,
+    Index(usize),
+}
+
+impl AdapterSelection {
+    pub const ENV: &'static str = "RUPY_GPU_ADAPTER";
+
+    /// The selection `RUPY_GPU_ADAPTER` names, `None` when it's unset or names none.
+    pub fn from_env() -> Option<Self> {
+        let value = std::env::var(Self::ENV).ok()?;
+        let selection = Self::parse(&value);
+        if selection.is_none() {
+            log_warning!("{}: no adapter selection '{}'", Self::ENV, value);
+        }
+        selection
+    }
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "high-performance" | "discrete" => Some(Self::HighPerformance),
+            "low-power" | "integrated" => Some(Self::LowPower),
+            index => index.parse().ok().map(Self::Index),
+        }
+    }
+    /// Sort key of an adapter of `device_type`, lowest preferred.
+    fn rank(self, device_type: wgpu::DeviceType) -> u8 {
+        let preferred = match self {
+            Self::LowPower => [
+                wgpu::DeviceType::IntegratedGpu,
+                wgpu::DeviceType::DiscreteGpu,
+            ],
+            _ => [
+                wgpu::DeviceType::DiscreteGpu,
+                wgpu::DeviceType::IntegratedGpu,
+            ],
+        };
+        if let Some(rank) = preferred.iter().position(|t| *t == device_type) {
+            return rank as u8;
+        }
+        match device_type {
+            wgpu::DeviceType::VirtualGpu => 2,
+            wgpu::DeviceType::Other => 3,
+            _ => 4,
+        }
+    }
+    fn power_preference(self) -> wgpu::PowerPreference {
+        match self {
+            Self::LowPower => wgpu::PowerPreference::LowPower,
+            _ => wgpu::PowerPreference::HighPerformance,
+        }
+    }
+}
+
+/// What [`GPU::init`] asks of the adapter and device. Missing `required_features` or
+/// `required_limits` fail the init; `optional_features` are enabled where the adapter has
+/// them, and [`GpuCapabilities`] tells which were.
+#[derive(Debug, Clone)]
+pub struct GpuRequirements {
+    pub required_features: wgpu::Features,
+    pub optional_features: wgpu::Features,
+    pub required_limits: wgpu::Limits,
+    pub adapter: AdapterSelection,
+}
+
+impl Default for GpuRequirements {
+    /// Nothing required beyond downlevel limits. MSAA counts other than 1 and 4 are only
+    /// usable with adapter-specific format features, line and point polygon modes only by
+    /// debug views that fall back without them, timestamps only by the profiler, which
+    /// times on the CPU instead, and BCn textures only by KTX2 files, which are decoded to
+    /// RGBA8 instead.
+    fn default() -> Self {
+        Self {
+            required_features: wgpu::Features::empty(),
+            optional_features: wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES
+                | wgpu::Features::POLYGON_MODE_LINE
+                | wgpu::Features::POLYGON_MODE_POINT
+                | wgpu::Features::TEXTURE_COMPRESSION_BC
+                | crate::GpuProfiler::FEATURES,
+            required_limits: wgpu::Limits::downlevel_defaults(),
+            adapter: AdapterSelection::default(),
+        }
+    }
+}
+
+impl GpuRequirements {
+    pub fn with_adapter(mut self, adapter: AdapterSelection) -> Self {
+        self.adapter = adapter;
+        self
+    }
+    pub fn require(mut self, features: wgpu::Features) -> Self {
+        self.required_features |= features;
+        self
+    }
+
+    /// The adapter to create the device on: the one [`AdapterSelection`] prefers, or
+    /// `RUPY_GPU_ADAPTER` overrides with, among those with every required feature.
+    pub fn select_adapter(&self, instance: &wgpu::Instance) -> Result<wgpu::Adapter, EngineError> {
+        let selection = AdapterSelection::from_env().unwrap_or(self.adapter);
+        let mut adapters = instance.enumerate_adapters(wgpu::Backends::default());
+        if let AdapterSelection::Index(index) = selection {
+            if index < adapters.len() {
+                return Ok(adapters.swap_remove(index));
+            }
+            log_warning!(
+                "GPU adapter {} asked for, but there are {}; picking one instead",
+                index,
+                adapters.len()
+            );
+        }
+        let required = self.required_features;
+        let best = adapters.into_iter().min_by_key(|adapter| {
+            let lacking = !adapter.features().contains(required);
+            (lacking, selection.rank(adapter.get_info().device_type))
+        });
+        if let Some(adapter) = best {
+            return Ok(adapter);
+        }
+        // Some backends only list adapters when asked for one.
+        pollster::FutureExt::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: selection.power_preference(),
+            ..Default::default()
+        }))
+        .ok_or(EngineError::AdapterNotFound)
+    }
+
+    /// Fails naming the first thing `adapter` lacks.
+    pub fn check(&self, adapter: &wgpu::Adapter) -> Result<(), EngineError> {
+        let name = adapter.get_info().name;
+        let missing = self.required_features - adapter.features();
+        if !missing.is_empty() {
+            return Err(EngineError::GpuError(format!(
+                "adapter {} lacks required features {:?}",
+                name, missing
+            )));
+        }
+        let mut short = Vec::new();
+        self.required_limits.check_limits_with_fail_fn(
+            &adapter.limits(),
+            false,
+            |limit, required, allowed| {
+                short.push(format!("{} (needs {}, has {})", limit, required, allowed))
+            },
+        );
+        if !short.is_empty() {
+            return Err(EngineError::GpuError(format!(
+                "adapter {} falls short of required limits: {}",
+                name,
+                short.join(", ")
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// What the device [`GPU::init`] created has: its adapter and the features and limits it
+/// was created with. Subsystems with optional paths ask this instead of assuming.
+#[derive(Debug, Clone)]
+pub struct GpuCapabilities {
+    pub adapter: wgpu::AdapterInfo,
+    pub features: wgpu::Features,
+    pub limits: wgpu::Limits,
+}
+
+impl GpuCapabilities {
+    pub fn new(adapter: &wgpu::Adapter, device: &wgpu::Device) -> Self {
+        Self {
+            adapter: adapter.get_info(),
+            features: device.features(),
+            limits: device.limits(),
+        }
+    }
+    /// Those of the global [`GPU`], `None` before [`GPU::init`].
+    pub fn current() -> Option<Self> {
+        GPU::with_read_recovered(|gpu| gpu.capabilities().clone()).ok()
+    }
+    /// Whether the global device was created with every one of `features`; `false`
+    /// without a global [`GPU`].
+    pub fn supported(features: wgpu::Features) -> bool {
+        GPU::with_read_recovered(|gpu| gpu.capabilities().supports(features)).unwrap_or(false)
+    }
+    pub fn supports(&self, features: wgpu::Features) -> bool {
+        self.features.contains(features)
+    }
+    pub fn adapter_name(&self) -> &str {
+        &self.adapter.name
+    }
+    pub fn backend(&self) -> wgpu::Backend {
+        self.adapter.backend
+    }
+
+    pub fn log(&self) {
+        log_info!(
+            "GPU: {} ({:?}, {:?}), driver {} {}",
+            self.adapter.name,
+            self.adapter.backend,
+            self.adapter.device_type,
+            self.adapter.driver,
+            self.adapter.driver_info
+        );
+        log_info!("GPU features: {:?}", self.features);
+        log_info!(
+            "GPU limits: 2D textures {}, bind groups {}, storage binding {} bytes, push constants {} bytes",
+            self.limits.max_texture_dimension_2d,
+            self.limits.max_bind_groups,
+            self.limits.max_storage_buffer_binding_size,
+            self.limits.max_push_constant_size
+        );
+    }
+}
@@ -1,62 +1,147 @@
 static GPU: std::sync::OnceLock<std::sync::Arc<std::sync::RwLock<GPU>>> =
     std::sync::OnceLock::new();
 
-fn init_gpu() {
-    let gpu = GPU::new();
+fn init_gpu(requirements: crate::GpuRequirements) -> Result<(), crate::EngineError> {
+    let gpu = GPU::new(requirements)?;
     let arc_gpu = std::sync::Arc::new(std::sync::RwLock::new(gpu));
     GPU.set(arc_gpu)
-        .expect("Global gpu was already initialized");
+        .map_err(|_| crate::EngineError::GpuError("global gpu was already initialized".into()))
 }
 
 fn get_gpu() -> std::sync::Arc<std::sync::RwLock<GPU>> {
     GPU.get().expect("Global gpu is not initialized").clone()
 }
 
+fn try_get_gpu() -> Result<std::sync::Arc<std::sync::RwLock<GPU>>, crate::EngineError> {
+    GPU.get()
+        .cloned()
+        .ok_or(crate::EngineError::GpuNotInitialized)
+}
+
 #[derive(Debug)]
 pub struct GPU {
     instance: std::sync::Arc<wgpu::Instance>,
     adapter: std::sync::Arc<wgpu::Adapter>,
     device: std::sync::Arc<wgpu::Device>,
     queue: std::sync::Arc<wgpu::Queue>,
+    /// What the device was asked for, asked again by [`GPU::recover`].
+    requirements: crate::GpuRequirements,
+    capabilities: crate::GpuCapabilities,
 }
 
 impl GPU {
     pub fn get() -> std::sync::Arc<std::sync::RwLock<GPU>> {
         get_gpu()
     }
-    pub fn init() {
-        init_gpu();
+    /// Creates the global GPU handles to meet `requirements`. Fails without a usable
+    /// adapter or device, naming a required feature or limit the adapter lacks, or when they
+    /// were already created.
+    pub fn init(requirements: crate::GpuRequirements) -> Result<(), crate::EngineError> {
+        init_gpu(requirements)
+    }
+    /// Runs `f` with the GPU handles. A lock poisoned by a panic on another thread is an
+    /// [`crate::EngineError::PoisonError`]; see [`GPU::with_read_recovered`] for reads
+    /// that can ignore it.
+    pub fn with_read<T>(f: impl FnOnce(&GPU) -> T) -> Result<T, crate::EngineError> {
+        let binding = try_get_gpu()?;
+        let gpu = binding
+            .read()
+            .map_err(|e| crate::EngineError::PoisonError(format!("GPU: {}", e)))?;
+        Ok(f(&gpu))
+    }
+    /// [`GPU::with_read`] that takes the guard back out of a poisoned lock. The handles are
+    /// set once by [`GPU::init`] and are all reference counted wgpu objects, so a holder
+    /// panicking can't have left them half-written; only not being initialized fails.
+    pub fn with_read_recovered<T>(f: impl FnOnce(&GPU) -> T) -> Result<T, crate::EngineError> {
+        let binding = try_get_gpu()?;
+        let gpu = binding
+            .read()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        Ok(f(&gpu))
+    }
+    /// Runs `f` with the GPU handles writable. Never recovers from poisoning: a writer that
+    /// panicked may have swapped only some of the handles, so they can't be trusted.
+    pub fn with_write<T>(f: impl FnOnce(&mut GPU) -> T) -> Result<T, crate::EngineError> {
+        let binding = try_get_gpu()?;
+        let mut gpu = binding
+            .write()
+            .map_err(|e| crate::EngineError::PoisonError(format!("GPU: {}", e)))?;
+        Ok(f(&mut gpu))
     }
-    pub fn new() -> Self {
+    /// Replaces a lost device: a new instance, adapter, device and queue go into the global
+    /// handles, and [`crate::RenderBindGroupLayouts`] are rebuilt on the new device.
+    /// Everything else created on the old device is invalid and has to be created again;
+    /// surfaces too, since they belong to the old instance.
+    pub fn recover() -> Result<(), crate::EngineError> {
+        let requirements = GPU::with_read_recovered(|gpu| gpu.requirements.clone())?;
+        let gpu = GPU::new(requirements)?;
+        let device = gpu.device().clone();
+        GPU::with_write(|current| *current = gpu)?;
+        crate::RenderBindGroupLayouts::rebuild(device);
+        crate::DeviceLoss::clear();
+        crate::log_info!("GPU device recreated");
+        Ok(())
+    }
+    /// Loses the device on purpose, as a driver reset would, to exercise the recovery
+    /// path: the loss is reported, then the device destroyed.
+    #[cfg(feature = "gpu_test_hooks")]
+    pub fn simulate_loss() -> Result<(), crate::EngineError> {
+        let device = GPU::with_read_recovered(|gpu| gpu.device().clone())?;
+        crate::DeviceLoss::report("simulated");
+        device.destroy();
+        Ok(())
+    }
+    pub fn new(requirements: crate::GpuRequirements) -> Result<Self, crate::EngineError> {
         let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor {
             backends: wgpu::Backends::default(),
             flags: wgpu::InstanceFlags::empty(),
             backend_options: Default::default(),
         });
 
-        let adapter = pollster::FutureExt::block_on(
-            instance.request_adapter(&wgpu::RequestAdapterOptions::default()),
-        )
-        .expect("Request adapter");
+        let adapter = requirements.select_adapter(&instance)?;
+        requirements.check(&adapter)?;
+
+        // Push constants only index per-object data; without them it uses dynamic offsets.
+        let push_constants = crate::ObjectIndexing::select(adapter.features(), &adapter.limits())
+            == crate::ObjectIndexing::PushConstants;
+        let (push_constant_feature, max_push_constant_size) = if push_constants {
+            (
+                wgpu::Features::PUSH_CONSTANTS,
+                crate::ObjectIndexing::PUSH_CONSTANT_SIZE,
+            )
+        } else {
+            (wgpu::Features::empty(), 0)
+        };
+        let features = requirements.required_features
+            | push_constant_feature
+            | (adapter.features() & requirements.optional_features);
+        let required_limits = wgpu::Limits {
+            max_push_constant_size: max_push_constant_size
+                .max(requirements.required_limits.max_push_constant_size),
+            ..requirements.required_limits.clone()
+        };
 
         let (device, queue) = pollster::FutureExt::block_on(adapter.request_device(
             &wgpu::DeviceDescriptor {
                 label: None,
-                required_features: wgpu::Features::POLYGON_MODE_LINE
-                    | wgpu::Features::POLYGON_MODE_POINT,
-                required_limits: wgpu::Limits::downlevel_defaults(),
+                required_features: features,
+                required_limits,
                 memory_hints: wgpu::MemoryHints::Performance,
             },
             None,
-        ))
-        .expect("Failed to request device and/or queue from adapter");
+        ))?;
+        super::install_crash_handlers(&adapter, &device);
+        let capabilities = crate::GpuCapabilities::new(&adapter, &device);
+        capabilities.log();
 
-        Self {
+        Ok(Self {
             instance: instance.into(),
             adapter: adapter.into(),
             device: device.into(),
             queue: queue.into(),
-        }
+            requirements,
+            capabilities,
+        })
     }
 
     pub fn instance(&self) -> &std::sync::Arc<wgpu::Instance> {
@@ -74,4 +159,38 @@ impl GPU {
     pub fn queue(&self) -> &std::sync::Arc<wgpu::Queue> {
         &self.queue
     }
+
+    pub fn capabilities(&self) -> &crate::GpuCapabilities {
+        &self.capabilities
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::EngineError;
+
+    /// Needs an adapter; passes without one.
+    #[test]
+    fn a_panicking_holder_poisons_only_the_strict_accessors() {
+        let _ = GPU::init(crate::GpuRequirements::default());
+        let Ok(lock) = try_get_gpu() else {
+            return;
+        };
+        let holder = std::thread::spawn(|| {
+            let _ = GPU::with_write(|_| panic!("system blew up"));
+        });
+        assert!(holder.join().is_err());
+        assert!(lock.is_poisoned());
+
+        let read = GPU::with_read(|gpu| gpu.device().clone());
+        let write = GPU::with_write(|_| ());
+        let recovered = GPU::with_read_recovered(|gpu| gpu.device().clone());
+        lock.clear_poison();
+
+        assert!(matches!(read, Err(EngineError::PoisonError(m)) if m.starts_with("GPU: ")));
+        assert!(matches!(write, Err(EngineError::PoisonError(_))));
+        assert!(recovered.is_ok());
+        assert!(GPU::with_write(|_| ()).is_ok());
+    }
 }
@@ -0,0 +1,216 @@
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+};
+
+use crate::{
+    breadcrumb_trail, breadcrumbs, log_error, recent_log_lines, Breadcrumb, DeviceLoss, EngineError,
+};
+
+static FRAME_INDEX: AtomicU64 = AtomicU64::new(0);
+static ADAPTER_INFO: Mutex<Option<wgpu::AdapterInfo>> = Mutex::new(None);
+static CRASH_REPORTS: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+/// Advances the frame index recorded in crash reports. Called once per rendered frame.
+pub fn advance_gpu_frame() -> u64 {
+    FRAME_INDEX.fetch_add(1, Ordering::Relaxed) + 1
+}
+pub fn gpu_frame_index() -> u64 {
+    FRAME_INDEX.load(Ordering::Relaxed)
+}
+
+/// Reports written since the last call, for the app to surface to the user.
+pub fn take_crash_reports() -> Vec<PathBuf> {
+    CRASH_REPORTS
+        .lock()
+        .map(|mut reports| std::mem::take(&mut *reports))
+        .unwrap_or_default()
+}
+
+#[derive(Debug, Clone)]
+pub struct GpuCrashReport {
+    pub reason: String,
+    pub frame_index: u64,
+    pub adapter: Option<wgpu::AdapterInfo>,
+    pub breadcrumbs: Vec<Breadcrumb>,
+    pub trail: Vec<Breadcrumb>,
+    pub log_lines: Vec<String>,
+}
+
+impl GpuCrashReport {
+    /// Collects the current breadcrumbs, log history, adapter info and frame index.
+    pub fn capture(reason: &str) -> Self {
+        Self {
+            reason: reason.to_string(),
+            frame_index: gpu_frame_index(),
+            adapter: ADAPTER_INFO.lock().ok().and_then(|info| info.clone()),
+            breadcrumbs: breadcrumbs(),
+            trail: breadcrumb_trail(),
+            log_lines: recent_log_lines(),
+        }
+    }
+
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        out.push_str(&format!("GPU failure: {}\n", self.reason));
+        out.push_str(&format!("Frame: {}\n", self.frame_index));
+        match &self.adapter {
+            Some(info) => out.push_str(&format!(
+                "Adapter: {} ({:?}, {:?}) driver {} {}\n",
+                info.name, info.backend, info.device_type, info.driver, info.driver_info
+            )),
+            None => out.push_str("Adapter: unknown\n"),
+        }
+        out.push_str("\nOpen scopes (outermost first):\n");
+        for crumb in &self.breadcrumbs {
+            out.push_str(&format!("  {}\n", crumb));
+        }
+        out.push_str("\nRecently closed scopes (oldest first):\n");
+        for crumb in &self.trail {
+            out.push_str(&format!("  {}\n", crumb));
+        }
+        out.push_str("\nRecent log:\n");
+        for line in &self.log_lines {
+            out.push_str(&format!("  {}\n", line));
+        }
+        out
+    }
+
+    /// Writes the report to `crash/gpu-<unix seconds>.txt` next to the working directory.
+    pub fn write(&self) -> Result<PathBuf, EngineError> {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        let dir = PathBuf::from("crash");
+        std::fs::create_dir_all(&dir)?;
+        let path = dir.join(format!("gpu-{}.txt", timestamp));
+        std::fs::write(&path, self.render())?;
+        Ok(path)
+    }
+}
+
+fn report(reason: &str) {
+    let report = GpuCrashReport::capture(reason);
+    match report.write() {
+        Ok(path) => {
+            log_error!("GPU failure, report written to {}", path.display());
+            if let Ok(mut reports) = CRASH_REPORTS.lock() {
+                reports.push(path);
+            }
+        }
+        Err(e) => log_error!("GPU failure ({}), report not written: {}", reason, e),
+    }
+}
+
+/// Routes uncaptured validation errors and device loss into crash reports. A loss is also
+/// handed to [`DeviceLoss`]; errors after it are what losing the device looks like and
+/// aren't reported again. Dropping the device on purpose isn't a loss.
+pub fn install_crash_handlers(adapter: &wgpu::Adapter, device: &wgpu::Device) {
+    if let Ok(mut info) = ADAPTER_INFO.lock() {
+        *info = Some(adapter.get_info());
+    }
+    device.on_uncaptured_error(Box::new(|error| {
+        if !DeviceLoss::is_lost() {
+            report(&error.to_string());
+        }
+    }));
+    device.set_device_lost_callback(|reason, message| {
+        if reason == wgpu::DeviceLostReason::Destroyed {
+            return;
+        }
+        let reason = format!("device lost ({:?}): {}", reason, message);
+        report(&reason);
+        DeviceLoss::report(&reason);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BreadcrumbKind;
+
+    fn crumb(kind: BreadcrumbKind, label: &str, detail: Option<&str>) -> Breadcrumb {
+        Breadcrumb {
+            kind,
+            label: label.to_string(),
+            detail: detail.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn reports_render_every_section() {
+        let report = GpuCrashReport {
+            reason: "Validation Error".to_string(),
+            frame_index: 42,
+            adapter: Some(wgpu::AdapterInfo {
+                name: "Fake GPU".to_string(),
+                vendor: 0,
+                device: 0,
+                device_type: wgpu::DeviceType::Cpu,
+                driver: "fake".to_string(),
+                driver_info: "1.0".to_string(),
+                backend: wgpu::Backend::Vulkan,
+            }),
+            breadcrumbs: vec![
+                crumb(BreadcrumbKind::Pass, "HDR Pass", None),
+                crumb(BreadcrumbKind::Draw, "cube.obj", Some("entity 3")),
+            ],
+            trail: vec![crumb(
+                BreadcrumbKind::Pipeline,
+                "ground",
+                Some("v_normal.wgsl"),
+            )],
+            log_lines: vec!["[INFO] loaded".to_string()],
+        };
+        assert_eq!(
+            report.render(),
+            "GPU failure: Validation Error\n\
+             Frame: 42\n\
+             Adapter: Fake GPU (Vulkan, Cpu) driver fake 1.0\n\
+             \n\
+             Open scopes (outermost first):\n  \
+             Pass 'HDR Pass'\n  \
+             Draw 'cube.obj' (entity 3)\n\
+             \n\
+             Recently closed scopes (oldest first):\n  \
+             Pipeline 'ground' (v_normal.wgsl)\n\
+             \n\
+             Recent log:\n  \
+             [INFO] loaded\n"
+        );
+    }
+
+    #[test]
+    fn unknown_adapters_are_reported_as_such() {
+        let report = GpuCrashReport {
+            reason: "device lost".to_string(),
+            frame_index: 0,
+            adapter: None,
+            breadcrumbs: Vec::new(),
+            trail: Vec::new(),
+            log_lines: Vec::new(),
+        };
+        assert!(report.render().contains("Adapter: unknown\n"));
+    }
+
+    #[cfg(any(debug_assertions, feature = "gpu_diagnostics"))]
+    #[test]
+    fn captures_take_this_threads_breadcrumbs() {
+        crate::gpu_scope!(Pass, "Shadow Pass", "cascade 0");
+        let report = GpuCrashReport::capture("lost");
+        assert_eq!(report.reason, "lost");
+        assert_eq!(
+            report.breadcrumbs,
+            [crumb(
+                BreadcrumbKind::Pass,
+                "Shadow Pass",
+                Some("cascade 0")
+            )]
+        );
+        assert!(report.frame_index <= gpu_frame_index());
+    }
+}
@@ -0,0 +1,32 @@
+//! Device loss. A driver reset or update takes the device with it, and every submission
+//! after that fails. The callback [`super::install_crash_handlers`] registers marks the
+//! loss and publishes [`EngineEvent::DeviceLost`] once; the app answers with
+//! [`crate::GPU::recover`] and rebuilds what it created on the old device.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::{log_error, EngineEvent, EventBus};
+
+static LOST: AtomicBool = AtomicBool::new(false);
+
+pub struct DeviceLoss;
+
+impl DeviceLoss {
+    /// Whether the device was lost and hasn't been replaced yet.
+    pub fn is_lost() -> bool {
+        LOST.load(Ordering::Acquire)
+    }
+    /// Marks the device lost and publishes the event, once per device. Without a
+    /// subscriber the loss is only logged and reported.
+    pub(crate) fn report(reason: &str) {
+        if LOST.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        log_error!("GPU device lost: {}", reason);
+        EventBus::publish(EngineEvent::DeviceLost);
+    }
+    /// Called once a new device replaced the lost one.
+    pub(crate) fn clear() {
+        LOST.store(false, Ordering::Release);
+    }
+}
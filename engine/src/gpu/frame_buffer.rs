@@ -4,11 +4,27 @@ pub enum RenderTargetKind {
     Hdr,
     Shadow,
     Bloom,
+    /// Entity ids for picking, see [`crate::EntityPicker`].
+    EntityId,
+    /// The scene as it was before water, which water refracts; see [`crate::WaterPass`].
+    Refraction,
     Custom(&'static str),
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
 pub struct FrameBufferSize(pub u32, u32);
+impl FrameBufferSize {
+    /// Both sides divided by `divisor`, at least one texel each.
+    pub fn scaled_down(self, divisor: u32) -> Self {
+        let divisor = divisor.max(1);
+        FrameBufferSize((self.0 / divisor).max(1), (self.1 / divisor).max(1))
+    }
+    /// Both sides multiplied by `scale` and rounded, at least one texel each.
+    pub fn scaled(self, scale: f32) -> Self {
+        let side = |length: u32| ((length as f32 * scale).round() as u32).max(1);
+        FrameBufferSize(side(self.0), side(self.1))
+    }
+}
 impl From<winit::dpi::PhysicalSize<u32>> for FrameBufferSize {
     fn from(value: winit::dpi::PhysicalSize<u32>) -> Self {
         FrameBufferSize(value.width,value.height)
@@ -37,8 +53,11 @@ impl Into<glyphon::Resolution> for FrameBufferSize {
 
 pub struct FrameBuffer {
     color: crate::Texture,
+    /// Multisampled color drawn into and resolved to `color`, when `sample_count` > 1.
+    msaa: Option<crate::Texture>,
     depth: Option<crate::Texture>,
     size: FrameBufferSize,
+    sample_count: u32,
 }
 
 impl FrameBuffer {
@@ -58,7 +77,10 @@ impl FrameBuffer {
             format,
             1,
             wgpu::TextureViewDimension::D2,
-            wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_SRC
+                | wgpu::TextureUsages::COPY_DST,
             Some(wgpu::AddressMode::ClampToEdge),
             wgpu::FilterMode::Linear,
             None,
@@ -66,19 +88,47 @@ impl FrameBuffer {
         );
         Self {
             color,
+            msaa: None,
             depth: None,
             size,
+            sample_count: 1,
         }
     }
 
+    /// Color and depth target. With `sample_count` > 1 both are multisampled and the color
+    /// resolves into [`FrameBuffer::color`] at the end of each pass.
     pub fn new_with_depth(
         device: &wgpu::Device,
         size: FrameBufferSize,
         color_format: wgpu::TextureFormat,
         depth_format: wgpu::TextureFormat,
+        sample_count: u32,
         label: &str,
     ) -> Self {
         let mut fb = Self::new_color_only(device, size, color_format, label);
+        if sample_count > 1 {
+            let extent = wgpu::Extent3d {
+                width: size.0,
+                height: size.1,
+                depth_or_array_layers: 1,
+            };
+            fb.msaa = Some(crate::Texture::multisampled(
+                device,
+                extent,
+                color_format,
+                sample_count,
+                Some(label),
+            ));
+            fb.depth = Some(crate::Texture::multisampled(
+                device,
+                extent,
+                depth_format,
+                sample_count,
+                Some("depth buffer"),
+            ));
+            fb.sample_count = sample_count;
+            return fb;
+        }
         let depth = crate::Texture::new(
             device,
             wgpu::Extent3d {
@@ -99,7 +149,7 @@ impl FrameBuffer {
                 mag_filter: wgpu::FilterMode::Linear,
                 min_filter: wgpu::FilterMode::Linear,
                 mipmap_filter: wgpu::FilterMode::Nearest,
-                compare: Some(wgpu::CompareFunction::LessEqual),
+                compare: Some(crate::RenderSettings::depth_policy().compare()),
                 ..Default::default()
             })),
             Some("depth buffer"),
@@ -113,22 +163,67 @@ impl FrameBuffer {
     pub fn depth(&self) -> &Option<crate::Texture> {
         &self.depth
     }
+    pub fn sample_count(&self) -> u32 {
+        self.sample_count
+    }
     pub fn color_attachment(&self) -> wgpu::RenderPassColorAttachment {
-        wgpu::RenderPassColorAttachment {
-            view: &self.color.view,
-            resolve_target: None,
-            ops: wgpu::Operations {
-                load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
-                store: wgpu::StoreOp::Store,
+        match &self.msaa {
+            Some(msaa) => wgpu::RenderPassColorAttachment {
+                view: &msaa.view,
+                resolve_target: Some(&self.color.view),
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: wgpu::StoreOp::Discard,
+                },
+            },
+            None => wgpu::RenderPassColorAttachment {
+                view: &self.color.view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: wgpu::StoreOp::Store,
+                },
             },
         }
     }
+    /// [`FrameBuffer::color_attachment`] keeping the multisampled color, for a later pass
+    /// to draw over with [`FrameBuffer::color_attachment_loaded`].
+    pub fn color_attachment_kept(&self) -> wgpu::RenderPassColorAttachment {
+        let mut attachment = self.color_attachment();
+        attachment.ops.store = wgpu::StoreOp::Store;
+        attachment
+    }
+    /// Color attachment drawing over what an earlier pass kept, resolved again at the end.
+    pub fn color_attachment_loaded(&self) -> wgpu::RenderPassColorAttachment {
+        let mut attachment = self.color_attachment_kept();
+        attachment.ops.load = wgpu::LoadOp::Load;
+        attachment
+    }
 
     pub fn depth_attachment(&self) -> Option<wgpu::RenderPassDepthStencilAttachment> {
+        let clear = crate::RenderSettings::depth_policy().clear_value();
+        self.depth_attachment_with(wgpu::LoadOp::Clear(clear))
+    }
+    /// Depth attachment keeping what an earlier pass, e.g. a depth pre-pass, wrote.
+    pub fn depth_attachment_loaded(&self) -> Option<wgpu::RenderPassDepthStencilAttachment> {
+        self.depth_attachment_with(wgpu::LoadOp::Load)
+    }
+    /// Depth attachment that is only tested against, so the pass can also sample it.
+    pub fn depth_attachment_read_only(&self) -> Option<wgpu::RenderPassDepthStencilAttachment> {
+        self.depth.as_ref().map(|d| wgpu::RenderPassDepthStencilAttachment {
+            view: &d.view,
+            depth_ops: None,
+            stencil_ops: None,
+        })
+    }
+    fn depth_attachment_with(
+        &self,
+        load: wgpu::LoadOp<f32>,
+    ) -> Option<wgpu::RenderPassDepthStencilAttachment> {
         self.depth.as_ref().map(|d| wgpu::RenderPassDepthStencilAttachment {
             view: &d.view,
             depth_ops: Some(wgpu::Operations {
-                load: wgpu::LoadOp::Clear(1.0),
+                load,
                 store: wgpu::StoreOp::Store,
             }),
             stencil_ops: None,
@@ -148,31 +243,55 @@ impl FrameBuffer {
                 format,
                 1,
                 wgpu::TextureViewDimension::D2,
-                wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+                wgpu::TextureUsages::RENDER_ATTACHMENT
+                    | wgpu::TextureUsages::TEXTURE_BINDING
+                    | wgpu::TextureUsages::COPY_SRC
+                    | wgpu::TextureUsages::COPY_DST,
                 Some(wgpu::AddressMode::ClampToEdge),
                 wgpu::FilterMode::Linear,
                 None,
                 Some("resized framebuffer color"),
             );
-            if let Some(depth) = self.depth.as_mut() {
-                let format = depth.texture.format();
-                *depth = crate::Texture::new(
+            let extent = wgpu::Extent3d {
+                width: new_size.0,
+                height: new_size.1,
+                depth_or_array_layers: 1,
+            };
+            if let Some(msaa) = self.msaa.as_mut() {
+                *msaa = crate::Texture::multisampled(
                     device,
-                    wgpu::Extent3d {
-                        width: new_size.0,
-                        height: new_size.1,
-                        depth_or_array_layers: 1,
-                    },
+                    extent,
                     format,
-                    1,
-                    wgpu::TextureViewDimension::D2,
-                    wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
-                    Some(wgpu::AddressMode::ClampToEdge),
-                    wgpu::FilterMode::Linear,
-                    None,
-                    Some("resized framebuffer depth"),
+                    self.sample_count,
+                    Some("resized framebuffer msaa color"),
                 );
             }
+            if let Some(depth) = self.depth.as_mut() {
+                let format = depth.texture.format();
+                *depth = if self.sample_count > 1 {
+                    crate::Texture::multisampled(
+                        device,
+                        extent,
+                        format,
+                        self.sample_count,
+                        Some("resized framebuffer depth"),
+                    )
+                } else {
+                    crate::Texture::new(
+                        device,
+                        extent,
+                        format,
+                        1,
+                        wgpu::TextureViewDimension::D2,
+                        wgpu::TextureUsages::RENDER_ATTACHMENT
+                            | wgpu::TextureUsages::TEXTURE_BINDING,
+                        Some(wgpu::AddressMode::ClampToEdge),
+                        wgpu::FilterMode::Linear,
+                        None,
+                        Some("resized framebuffer depth"),
+                    )
+                };
+            }
             self.size = new_size;
         }
     }
@@ -0,0 +1,181 @@
+use crate::CacheKey;
+use std::collections::HashMap;
+
+/// Smallest buffer the pool allocates, in bytes.
+const MIN_CAPACITY: u64 = 256;
+
+/// Frames a key can go unwritten before its buffer is freed.
+pub const INSTANCE_RETIRE_FRAMES: u64 = 120;
+
+/// Capacity a buffer of `capacity` bytes must grow to for `required` bytes, or `None`
+/// when they already fit. Grows to the next power of two so a slowly rising instance
+/// count doesn't reallocate every frame; [`crate::WgpuBuffer`] grows the same way.
+pub fn grown_capacity(capacity: u64, required: u64) -> Option<u64> {
+    (required > capacity).then(|| required.next_power_of_two().max(MIN_CAPACITY))
+}
+
+/// Whether a buffer last written on frame `last_used` is freed on frame `frame`.
+pub fn is_retired(last_used: u64, frame: u64, retire_after: u64) -> bool {
+    frame.saturating_sub(last_used) > retire_after
+}
+
+#[derive(Debug)]
+pub struct PooledInstances {
+    pub buffer: wgpu::Buffer,
+    /// Instances written this frame; zero for keys not written since
+    /// [`InstanceBufferPool::begin_frame`].
+    pub count: u32,
+    capacity: u64,
+    last_used: u64,
+}
+
+/// Instance vertex buffers keyed by model, kept across frames. Writes go through
+/// `queue.write_buffer`; a buffer is only reallocated when a frame's instances outgrow
+/// it, and freed once its key hasn't been written for `retire_after` frames.
+#[derive(Debug)]
+pub struct InstanceBufferPool {
+    label: String,
+    entries: HashMap<CacheKey, PooledInstances>,
+    frame: u64,
+    retire_after: u64,
+    allocations: u64,
+}
+
+impl InstanceBufferPool {
+    pub fn new(label: &str) -> Self {
+        Self {
+            label: label.to_string(),
+            entries: HashMap::new(),
+            frame: 0,
+            retire_after: INSTANCE_RETIRE_FRAMES,
+            allocations: 0,
+        }
+    }
+    pub fn with_retire_after(mut self, frames: u64) -> Self {
+        self.retire_after = frames;
+        self
+    }
+    /// Starts a frame: every count drops to zero until written again, and buffers idle
+    /// past the retirement window are freed.
+    pub fn begin_frame(&mut self) {
+        self.frame += 1;
+        let (frame, retire_after) = (self.frame, self.retire_after);
+        self.entries
+            .retain(|_, entry| !is_retired(entry.last_used, frame, retire_after));
+        for entry in self.entries.values_mut() {
+            entry.count = 0;
+        }
+    }
+    /// Writes `instances` as this frame's data for `key`, growing its buffer if needed.
+    pub fn write<T: bytemuck::Pod>(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        key: CacheKey,
+        instances: &[T],
+    ) {
+        let bytes: &[u8] = bytemuck::cast_slice(instances);
+        let required = bytes.len() as u64;
+        let capacity = self.entries.get(&key).map_or(0, |entry| entry.capacity);
+        if let Some(capacity) = grown_capacity(capacity, required) {
+            let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(&format!("{} {}", self.label, key.id())),
+                size: capacity,
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+            self.allocations += 1;
+            self.entries.insert(
+                key,
+                PooledInstances {
+                    buffer,
+                    count: 0,
+                    capacity,
+                    last_used: self.frame,
+                },
+            );
+        }
+        let Some(entry) = self.entries.get_mut(&key) else {
+            return;
+        };
+        if !bytes.is_empty() {
+            queue.write_buffer(&entry.buffer, 0, bytes);
+        }
+        entry.count = instances.len() as u32;
+        entry.last_used = self.frame;
+    }
+    pub fn get(&self, key: &CacheKey) -> Option<&PooledInstances> {
+        self.entries.get(key).filter(|entry| entry.count > 0)
+    }
+    /// Buffers written this frame.
+    pub fn iter(&self) -> impl Iterator<Item = (&CacheKey, &PooledInstances)> {
+        self.entries.iter().filter(|(_, entry)| entry.count > 0)
+    }
+    /// Buffers currently held, written this frame or not.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+    /// Buffers created since the pool was, for tracing allocation churn.
+    pub fn allocations(&self) -> u64 {
+        self.allocations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn device() -> Option<(wgpu::Device, wgpu::Queue)> {
+        let instance = wgpu::Instance::default();
+        let adapter =
+            pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions::default()))?;
+        pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor::default(), None)).ok()
+    }
+
+    #[test]
+    fn retires_only_past_the_window() {
+        assert!(!is_retired(10, 10, 2));
+        assert!(!is_retired(10, 12, 2));
+        assert!(is_retired(10, 13, 2));
+        // A frame counter behind the last use never retires.
+        assert!(!is_retired(10, 5, 2));
+    }
+
+    /// Needs an adapter; passes without one.
+    #[test]
+    fn grows_reuses_and_retires() {
+        let Some((device, queue)) = device() else {
+            return;
+        };
+        let (a, b) = (CacheKey::from("a"), CacheKey::from("b"));
+        let mut pool = InstanceBufferPool::new("test").with_retire_after(2);
+
+        pool.begin_frame();
+        pool.write(&device, &queue, a, &[0u32; 16]);
+        pool.write(&device, &queue, b, &[0u32; 16]);
+        assert_eq!(pool.allocations(), 2);
+        assert_eq!(pool.get(&a).map(|e| e.count), Some(16));
+
+        // Fewer instances reuse the buffer; more than fit grow it.
+        pool.begin_frame();
+        pool.write(&device, &queue, a, &[0u32; 8]);
+        assert_eq!(pool.allocations(), 2);
+        assert_eq!(pool.get(&a).map(|e| e.count), Some(8));
+        assert!(pool.get(&b).is_none(), "b wasn't written this frame");
+        pool.write(&device, &queue, a, &[0u32; 1000]);
+        assert_eq!(pool.allocations(), 3);
+        assert!(pool.get(&a).is_some_and(|e| e.capacity >= 4000));
+
+        // b was last written on frame 1; it's held through frame 3 and freed on frame 4.
+        pool.begin_frame();
+        pool.write(&device, &queue, a, &[0u32; 8]);
+        assert_eq!(pool.len(), 2);
+        pool.begin_frame();
+        assert_eq!(pool.len(), 1);
+        assert_eq!(pool.iter().count(), 0);
+        assert_eq!(pool.allocations(), 3);
+    }
+}
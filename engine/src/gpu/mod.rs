@@ -1,8 +1,29 @@
 pub mod context;
 pub use context::*;
 
+pub mod capabilities;
+pub use capabilities::*;
+
 pub mod wgpu_buffer;
 pub use wgpu_buffer::*;
 
 pub mod frame_buffer;
 pub use frame_buffer::*;
+
+pub mod breadcrumb;
+pub use breadcrumb::*;
+
+pub mod crash_report;
+pub use crash_report::*;
+
+pub mod device_loss;
+pub use device_loss::*;
+
+pub mod readback;
+pub use readback::*;
+
+pub mod instance_pool;
+pub use instance_pool::*;
+
+pub mod profiler;
+pub use profiler::*;
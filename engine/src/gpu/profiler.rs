@@ -0,0 +1,215 @@
+//! Per-scope frame timings. Where the device has timestamp queries inside encoders, every
+//! scope is stamped on the GPU at its start and end, and the stamps come back through the
+//! [`ReadbackService`] a frame or more later. Elsewhere scopes fall back to the CPU time
+//! between their begin and end, and the frame's `queue.submit` is timed the same way.
+
+use std::collections::HashMap;
+use std::sync::{Mutex, PoisonError};
+use std::time::Instant;
+
+use crate::{log_info, ReadbackService, ReadbackTicket, TextRegion, WgpuBuffer};
+
+/// Scopes stamped on the GPU per frame; the rest of a frame's scopes are timed on the CPU.
+const MAX_SCOPES: u32 = 32;
+
+struct Timestamps {
+    query_set: wgpu::QuerySet,
+    resolve: WgpuBuffer,
+    /// Nanoseconds per timestamp tick.
+    period: f32,
+}
+
+/// Scopes of the frame being recorded.
+#[derive(Default)]
+struct FrameScopes {
+    /// Label of each query pair, by pair index.
+    gpu: Vec<&'static str>,
+    cpu: Vec<(&'static str, f32)>,
+}
+
+/// A scope opened by [`GpuProfiler::begin_scope`], to hand back to [`GpuProfiler::end_scope`].
+#[must_use]
+pub struct ProfileScope {
+    label: &'static str,
+    pair: Option<u32>,
+    start: Instant,
+}
+
+pub struct GpuProfiler {
+    timestamps: Option<Timestamps>,
+    frame: Mutex<FrameScopes>,
+    /// Stamps of an earlier frame on their way back, with that frame's pair labels.
+    pending: Option<(ReadbackTicket, Vec<&'static str>)>,
+    gpu_timings: Vec<(&'static str, f32)>,
+    cpu_timings: Vec<(&'static str, f32)>,
+}
+
+impl GpuProfiler {
+    /// Device features GPU timing needs; without them every scope is timed on the CPU.
+    pub const FEATURES: wgpu::Features =
+        wgpu::Features::TIMESTAMP_QUERY.union(wgpu::Features::TIMESTAMP_QUERY_INSIDE_ENCODERS);
+
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let timed = crate::GpuCapabilities::supported(Self::FEATURES);
+        if !timed {
+            log_info!("GPU profiler times on the CPU: no timestamp queries");
+        }
+        let timestamps = timed.then(|| Timestamps {
+            query_set: device.create_query_set(&wgpu::QuerySetDescriptor {
+                label: Some("profiler timestamps"),
+                ty: wgpu::QueryType::Timestamp,
+                count: MAX_SCOPES * 2,
+            }),
+            resolve: WgpuBuffer::from_data(
+                device,
+                &[0u64; MAX_SCOPES as usize * 2],
+                wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+                Some("profiler timestamp resolve"),
+            ),
+            period: queue.get_timestamp_period(),
+        });
+        Self {
+            timestamps,
+            frame: Mutex::new(FrameScopes::default()),
+            pending: None,
+            gpu_timings: Vec::new(),
+            cpu_timings: Vec::new(),
+        }
+    }
+    /// Whether scopes are timed on the GPU rather than the CPU.
+    pub fn gpu_timing(&self) -> bool {
+        self.timestamps.is_some()
+    }
+
+    fn with_frame<R>(&self, f: impl FnOnce(&mut FrameScopes) -> R) -> R {
+        f(&mut self.frame.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+    /// Starts timing `label` at this point of `encoder`. Scopes may be opened from several
+    /// recording threads at once.
+    pub fn begin_scope(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        label: &'static str,
+    ) -> ProfileScope {
+        let pair = self.timestamps.as_ref().and_then(|timestamps| {
+            let pair = self.with_frame(|frame| {
+                let pair = frame.gpu.len() as u32;
+                (pair < MAX_SCOPES).then(|| {
+                    frame.gpu.push(label);
+                    pair
+                })
+            })?;
+            encoder.write_timestamp(&timestamps.query_set, pair * 2);
+            Some(pair)
+        });
+        ProfileScope {
+            label,
+            pair,
+            start: Instant::now(),
+        }
+    }
+    pub fn end_scope(&self, encoder: &mut wgpu::CommandEncoder, scope: ProfileScope) {
+        match (scope.pair, &self.timestamps) {
+            (Some(pair), Some(timestamps)) => {
+                encoder.write_timestamp(&timestamps.query_set, pair * 2 + 1);
+            }
+            _ => self.record_cpu(scope.label, scope.start),
+        }
+    }
+    /// Adds the CPU time since `start` to `label`, for work without an encoder.
+    pub fn record_cpu(&self, label: &'static str, start: Instant) {
+        let ms = start.elapsed().as_secs_f32() * 1000.0;
+        self.with_frame(|frame| frame.cpu.push((label, ms)));
+    }
+
+    /// Closes the frame once its commands were submitted: resolves its timestamps, unless an
+    /// earlier frame's are still on their way back, and takes in whatever arrived. Call before
+    /// `readback` advances.
+    pub fn end_frame(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        readback: &mut ReadbackService,
+    ) {
+        let frame = self.with_frame(std::mem::take);
+        self.cpu_timings = summed(frame.cpu);
+
+        if let Some((ticket, labels)) = &self.pending {
+            match ticket.poll() {
+                Some(Ok(bytes)) => {
+                    let period = self.timestamps.as_ref().map_or(0.0, |t| t.period);
+                    let stamps: Vec<u64> = bytes
+                        .chunks_exact(std::mem::size_of::<u64>())
+                        .map(bytemuck::pod_read_unaligned)
+                        .collect();
+                    let timings = labels
+                        .iter()
+                        .zip(stamps.chunks_exact(2))
+                        .map(|(label, pair)| {
+                            let ticks = pair[1].saturating_sub(pair[0]);
+                            (*label, ticks as f32 * period / 1_000_000.0)
+                        });
+                    self.gpu_timings = summed(timings);
+                    self.pending = None;
+                }
+                Some(Err(_)) => self.pending = None,
+                None if ticket.is_pending() => return,
+                None => self.pending = None,
+            }
+        }
+
+        let Some(timestamps) = &self.timestamps else {
+            return;
+        };
+        if frame.gpu.is_empty() {
+            return;
+        }
+        let queries = frame.gpu.len() as u32 * 2;
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Profiler Resolve"),
+        });
+        encoder.resolve_query_set(
+            &timestamps.query_set,
+            0..queries,
+            timestamps.resolve.get(),
+            0,
+        );
+        queue.submit(Some(encoder.finish()));
+        let bytes = queries as u64 * std::mem::size_of::<u64>() as u64;
+        let ticket = readback.request_buffer(&timestamps.resolve, 0..bytes);
+        self.pending = Some((ticket, frame.gpu));
+    }
+
+    /// Milliseconds per scope label: GPU time of the last frame that came back, CPU time of
+    /// the last frame for scopes timed there.
+    pub fn timings(&self) -> HashMap<&'static str, f32> {
+        self.gpu_timings
+            .iter()
+            .chain(&self.cpu_timings)
+            .copied()
+            .collect()
+    }
+    /// One overlay line with every timing; CPU ones are marked.
+    pub fn text_region(&self, position: [f32; 2]) -> TextRegion {
+        let mut text = "Timings:".to_string();
+        for (label, ms) in &self.gpu_timings {
+            text.push_str(&format!(" {} {:.2}ms", label, ms));
+        }
+        for (label, ms) in &self.cpu_timings {
+            text.push_str(&format!(" {} {:.2}ms (cpu)", label, ms));
+        }
+        TextRegion::new(text, position, glyphon::Color::rgb(1, 1, 1))
+    }
+}
+
+/// Timings with repeated labels added up, in order of first appearance.
+fn summed(timings: impl IntoIterator<Item = (&'static str, f32)>) -> Vec<(&'static str, f32)> {
+    let mut summed: Vec<(&'static str, f32)> = Vec::new();
+    for (label, ms) in timings {
+        match summed.iter_mut().find(|(l, _)| *l == label) {
+            Some((_, total)) => *total += ms,
+            None => summed.push((label, ms)),
+        }
+    }
+    summed
+}
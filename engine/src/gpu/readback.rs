@@ -0,0 +1,817 @@
+//! GPU to CPU copies that never stall a frame. Requests are copied into pooled staging
+//! buffers, mapped asynchronously and handed back a few frames later through a
+//! [`ReadbackTicket`]. The service only ever polls the device with `Maintain::Poll`.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+
+use crate::{EngineError, Texture, WgpuBuffer};
+
+const MAPPING: u8 = 0;
+const MAPPED: u8 = 1;
+const MAP_FAILED: u8 = 2;
+
+/// Staging buffers kept around for reuse; extra ones are dropped when they come back.
+const MAX_POOLED: usize = 8;
+/// Smallest staging buffer, so small requests share buffers.
+const MIN_STAGING: u64 = 256;
+
+type ReadbackResult = Result<Vec<u8>, EngineError>;
+type ReadbackCallback = Box<dyn FnOnce(ReadbackResult) + Send>;
+
+enum Delivery {
+    Pending,
+    Ready(ReadbackResult),
+    Taken,
+}
+
+struct TicketShared {
+    delivery: Delivery,
+    callback: Option<ReadbackCallback>,
+}
+
+/// Handle to a pending readback. Clones share the result, which can be taken once.
+#[derive(Clone)]
+pub struct ReadbackTicket {
+    id: u64,
+    shared: Arc<Mutex<TicketShared>>,
+}
+
+impl ReadbackTicket {
+    fn new(id: u64) -> Self {
+        Self {
+            id,
+            shared: Arc::new(Mutex::new(TicketShared {
+                delivery: Delivery::Pending,
+                callback: None,
+            })),
+        }
+    }
+    fn with<R>(&self, f: impl FnOnce(&mut TicketShared) -> R) -> R {
+        f(&mut self.shared.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+    pub fn is_pending(&self) -> bool {
+        self.with(|shared| matches!(shared.delivery, Delivery::Pending))
+    }
+    /// The bytes once they arrived; `None` while pending and after the result was taken.
+    pub fn poll(&self) -> Option<ReadbackResult> {
+        self.with(|shared| match shared.delivery {
+            Delivery::Ready(_) => match std::mem::replace(&mut shared.delivery, Delivery::Taken) {
+                Delivery::Ready(result) => Some(result),
+                _ => None,
+            },
+            _ => None,
+        })
+    }
+    /// Calls `f` with the result on delivery, or right away if it already arrived. The
+    /// result then no longer shows up in [`Self::poll`].
+    pub fn on_ready(&self, f: impl FnOnce(ReadbackResult) + Send + 'static) {
+        let ready = self.with(|shared| match shared.delivery {
+            Delivery::Pending => {
+                shared.callback = Some(Box::new(f));
+                None
+            }
+            _ => Some(f),
+        });
+        if let Some(f) = ready {
+            if let Some(result) = self.poll() {
+                f(result);
+            }
+        }
+    }
+    fn resolve(&self, result: ReadbackResult) {
+        let pending = self.with(|shared| match shared.callback.take() {
+            Some(callback) => {
+                shared.delivery = Delivery::Taken;
+                Some((callback, result))
+            }
+            None => {
+                shared.delivery = Delivery::Ready(result);
+                None
+            }
+        });
+        if let Some((callback, result)) = pending {
+            callback(result);
+        }
+    }
+}
+
+impl fmt::Debug for ReadbackTicket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.with(|shared| match shared.delivery {
+            Delivery::Pending => "pending",
+            Delivery::Ready(Ok(_)) => "ready",
+            Delivery::Ready(Err(_)) => "failed",
+            Delivery::Taken => "taken",
+        });
+        f.debug_struct("ReadbackTicket")
+            .field("id", &self.id)
+            .field("state", &state)
+            .finish()
+    }
+}
+
+enum Source {
+    Buffer {
+        buffer: wgpu::Buffer,
+        /// Aligned range that is copied.
+        offset: u64,
+        size: u64,
+        /// Bytes of the staging copy the caller asked for.
+        trim: Range<usize>,
+    },
+    Texture {
+        texture: wgpu::Texture,
+        mip: u32,
+        origin: wgpu::Origin3d,
+        extent: wgpu::Extent3d,
+        row_bytes: u32,
+        padded_row: u32,
+    },
+}
+
+impl Source {
+    fn staging_size(&self) -> u64 {
+        match self {
+            Source::Buffer { size, .. } => *size,
+            Source::Texture {
+                extent, padded_row, ..
+            } => *padded_row as u64 * rows(extent) as u64,
+        }
+    }
+    fn encode(&self, encoder: &mut wgpu::CommandEncoder, staging: &wgpu::Buffer) {
+        match self {
+            Source::Buffer {
+                buffer,
+                offset,
+                size,
+                ..
+            } => encoder.copy_buffer_to_buffer(buffer, *offset, staging, 0, *size),
+            Source::Texture {
+                texture,
+                mip,
+                origin,
+                extent,
+                padded_row,
+                ..
+            } => encoder.copy_texture_to_buffer(
+                wgpu::TexelCopyTextureInfo {
+                    texture,
+                    mip_level: *mip,
+                    origin: *origin,
+                    aspect: wgpu::TextureAspect::All,
+                },
+                wgpu::TexelCopyBufferInfo {
+                    buffer: staging,
+                    layout: wgpu::TexelCopyBufferLayout {
+                        offset: 0,
+                        bytes_per_row: Some(*padded_row),
+                        rows_per_image: Some(extent.height),
+                    },
+                },
+                *extent,
+            ),
+        }
+    }
+    fn unpack(&self, data: &[u8]) -> Vec<u8> {
+        match self {
+            Source::Buffer { trim, .. } => data[trim.clone()].to_vec(),
+            Source::Texture {
+                extent,
+                row_bytes,
+                padded_row,
+                ..
+            } => strip_row_padding(
+                data,
+                *row_bytes as usize,
+                *padded_row as usize,
+                rows(extent) as usize,
+            ),
+        }
+    }
+}
+
+fn rows(extent: &wgpu::Extent3d) -> u32 {
+    extent.height * extent.depth_or_array_layers
+}
+
+struct Request {
+    ticket: ReadbackTicket,
+    source: Source,
+}
+
+struct InFlight {
+    request: Request,
+    staging: wgpu::Buffer,
+    state: Arc<AtomicU8>,
+}
+
+/// Queues GPU to CPU copies and delivers them without blocking; see the module docs.
+/// Call [`Self::advance`] once per frame, after the frame's commands were submitted.
+pub struct ReadbackService {
+    max_in_flight: usize,
+    queued: VecDeque<Request>,
+    in_flight: Vec<InFlight>,
+    pool: Vec<wgpu::Buffer>,
+    next_id: u64,
+}
+
+impl ReadbackService {
+    pub const DEFAULT_MAX_IN_FLIGHT: usize = 4;
+
+    pub fn new(max_in_flight: usize) -> Self {
+        Self {
+            max_in_flight: max_in_flight.max(1),
+            queued: VecDeque::new(),
+            in_flight: Vec::new(),
+            pool: Vec::new(),
+            next_id: 0,
+        }
+    }
+    pub fn max_in_flight(&self) -> usize {
+        self.max_in_flight
+    }
+    /// Requests beyond this many in flight wait in the queue.
+    pub fn set_max_in_flight(&mut self, max: usize) {
+        self.max_in_flight = max.max(1);
+    }
+    pub fn queued(&self) -> usize {
+        self.queued.len()
+    }
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.len()
+    }
+    pub fn pooled(&self) -> usize {
+        self.pool.len()
+    }
+
+    fn ticket(&mut self) -> ReadbackTicket {
+        self.next_id += 1;
+        ReadbackTicket::new(self.next_id)
+    }
+    fn fail(&mut self, message: String) -> ReadbackTicket {
+        let ticket = self.ticket();
+        ticket.resolve(Err(EngineError::GpuError(message)));
+        ticket
+    }
+
+    /// Reads `range` of `src`, which needs `COPY_SRC` usage. The copy is widened to the
+    /// 4 byte alignment wgpu requires and trimmed back on delivery.
+    pub fn request_buffer(&mut self, src: &WgpuBuffer, range: Range<u64>) -> ReadbackTicket {
+        let buffer = src.get();
+        if !buffer.usage().contains(wgpu::BufferUsages::COPY_SRC) {
+            return self.fail("readback source buffer lacks COPY_SRC usage".to_string());
+        }
+        if range.start >= range.end || range.end > buffer.size() {
+            return self.fail(format!(
+                "readback range {:?} outside buffer of {} bytes",
+                range,
+                buffer.size()
+            ));
+        }
+        let offset = range.start - range.start % wgpu::COPY_BUFFER_ALIGNMENT;
+        let end = range
+            .end
+            .next_multiple_of(wgpu::COPY_BUFFER_ALIGNMENT)
+            .min(buffer.size());
+        let skip = (range.start - offset) as usize;
+        let source = Source::Buffer {
+            buffer: buffer.clone(),
+            offset,
+            size: end - offset,
+            trim: skip..skip + (range.end - range.start) as usize,
+        };
+        self.enqueue(source)
+    }
+
+    /// Reads a region of mip level `mip` of `src`, which needs `COPY_SRC` usage. Rows come
+    /// back tightly packed, layer after layer.
+    pub fn request_texture(
+        &mut self,
+        src: &Texture,
+        mip: u32,
+        origin: wgpu::Origin3d,
+        extent: wgpu::Extent3d,
+    ) -> ReadbackTicket {
+        let texture = &src.texture;
+        if !texture.usage().contains(wgpu::TextureUsages::COPY_SRC) {
+            return self.fail(format!("readback of '{}' lacks COPY_SRC usage", src.label));
+        }
+        let format = texture.format();
+        let block_size = match format.block_copy_size(Some(wgpu::TextureAspect::All)) {
+            Some(size) if format.block_dimensions() == (1, 1) => size,
+            _ => {
+                return self.fail(format!(
+                    "readback of '{}': {:?} can't be read back",
+                    src.label, format
+                ))
+            }
+        };
+        if mip >= texture.mip_level_count() {
+            return self.fail(format!("readback of '{}': no mip {}", src.label, mip));
+        }
+        let size = texture.size().mip_level_size(mip, texture.dimension());
+        if origin.x + extent.width > size.width
+            || origin.y + extent.height > size.height
+            || origin.z + extent.depth_or_array_layers > size.depth_or_array_layers
+        {
+            return self.fail(format!(
+                "readback of '{}': region outside mip {} ({}x{})",
+                src.label, mip, size.width, size.height
+            ));
+        }
+        let row_bytes = extent.width * block_size;
+        let source = Source::Texture {
+            texture: texture.clone(),
+            mip,
+            origin,
+            extent,
+            row_bytes,
+            padded_row: padded_row_bytes(row_bytes),
+        };
+        self.enqueue(source)
+    }
+
+    fn enqueue(&mut self, source: Source) -> ReadbackTicket {
+        let ticket = self.ticket();
+        self.queued.push_back(Request {
+            ticket: ticket.clone(),
+            source,
+        });
+        ticket
+    }
+
+    /// Delivers finished readbacks and starts queued ones within the in-flight budget.
+    pub fn advance(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        if self.in_flight.is_empty() && self.queued.is_empty() {
+            return;
+        }
+        let _ = device.poll(wgpu::Maintain::Poll);
+        self.deliver();
+        self.start(device, queue);
+    }
+
+    fn deliver(&mut self) {
+        let mut index = 0;
+        while index < self.in_flight.len() {
+            let result = match self.in_flight[index].state.load(Ordering::Acquire) {
+                MAPPING => {
+                    index += 1;
+                    continue;
+                }
+                MAPPED => {
+                    let flight = &self.in_flight[index];
+                    let bytes = {
+                        let size = flight.request.source.staging_size();
+                        let data = flight.staging.slice(..size).get_mapped_range();
+                        flight.request.source.unpack(&data)
+                    };
+                    flight.staging.unmap();
+                    Ok(bytes)
+                }
+                _ => Err(EngineError::GpuError("readback mapping failed".to_string())),
+            };
+            let flight = self.in_flight.swap_remove(index);
+            if result.is_ok() {
+                self.release(flight.staging);
+            }
+            flight.request.ticket.resolve(result);
+        }
+    }
+
+    fn start(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        let free = self.max_in_flight.saturating_sub(self.in_flight.len());
+        let count = free.min(self.queued.len());
+        if count == 0 {
+            return;
+        }
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Readback Copies"),
+        });
+        let mut started = Vec::with_capacity(count);
+        for request in self.queued.drain(..count).collect::<Vec<_>>() {
+            let staging = self.acquire(device, request.source.staging_size());
+            request.source.encode(&mut encoder, &staging);
+            started.push((request, staging));
+        }
+        queue.submit(Some(encoder.finish()));
+        for (request, staging) in started {
+            let state = Arc::new(AtomicU8::new(MAPPING));
+            let callback_state = state.clone();
+            staging.slice(..request.source.staging_size()).map_async(
+                wgpu::MapMode::Read,
+                move |result| {
+                    let state = if result.is_ok() { MAPPED } else { MAP_FAILED };
+                    callback_state.store(state, Ordering::Release);
+                },
+            );
+            self.in_flight.push(InFlight {
+                request,
+                staging,
+                state,
+            });
+        }
+    }
+
+    /// Smallest pooled buffer that fits, or a new one rounded up to a power of two.
+    fn acquire(&mut self, device: &wgpu::Device, size: u64) -> wgpu::Buffer {
+        let best = self
+            .pool
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| b.size() >= size)
+            .min_by_key(|(_, b)| b.size())
+            .map(|(i, _)| i);
+        match best {
+            Some(index) => self.pool.swap_remove(index),
+            None => device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("readback staging"),
+                size: size.next_power_of_two().max(MIN_STAGING),
+                usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }),
+        }
+    }
+    fn release(&mut self, buffer: wgpu::Buffer) {
+        if self.pool.len() < MAX_POOLED {
+            self.pool.push(buffer);
+        }
+    }
+}
+
+impl Default for ReadbackService {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_MAX_IN_FLIGHT)
+    }
+}
+
+impl fmt::Debug for ReadbackService {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReadbackService")
+            .field("max_in_flight", &self.max_in_flight)
+            .field("queued", &self.queued.len())
+            .field("in_flight", &self.in_flight.len())
+            .field("pooled", &self.pool.len())
+            .finish()
+    }
+}
+
+/// Bytes per row of a texture copy, padded to wgpu's 256 byte row alignment.
+pub fn padded_row_bytes(row_bytes: u32) -> u32 {
+    row_bytes.next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT)
+}
+
+/// Drops the padding at the end of each of `rows` rows of `padded_row` bytes.
+pub fn strip_row_padding(data: &[u8], row_bytes: usize, padded_row: usize, rows: usize) -> Vec<u8> {
+    data.chunks(padded_row)
+        .take(rows)
+        .flat_map(|line| &line[..row_bytes])
+        .copied()
+        .collect()
+}
+
+/// Writes 8-bit RGBA or BGRA `pixels` as a PNG.
+pub fn save_png(
+    path: &Path,
+    pixels: Vec<u8>,
+    width: u32,
+    height: u32,
+    format: wgpu::TextureFormat,
+) -> Result<(), EngineError> {
+    rgba_image(pixels, width, height, format)?.save(path)?;
+    Ok(())
+}
+
+/// 8-bit RGBA or BGRA `pixels` of a `format` texture as an RGBA image.
+pub fn rgba_image(
+    mut pixels: Vec<u8>,
+    width: u32,
+    height: u32,
+    format: wgpu::TextureFormat,
+) -> Result<image::RgbaImage, EngineError> {
+    match format {
+        wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Rgba8UnormSrgb => {}
+        wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb => {
+            pixels.chunks_exact_mut(4).for_each(|px| px.swap(0, 2));
+        }
+        other => {
+            return Err(EngineError::GpuError(format!(
+                "can't save {:?} as PNG",
+                other
+            )))
+        }
+    }
+    image::RgbaImage::from_raw(width, height, pixels)
+        .ok_or_else(|| EngineError::GpuError("screenshot size mismatch".to_string()))
+}
+
+/// Copies mip 0 of `texture` out through a mapped buffer, finishing `encoder` first. Waits
+/// on the device, which is fine for offline bakes and headless captures; frames read back
+/// through [`ReadbackService`].
+pub fn read_texture_blocking(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    mut encoder: wgpu::CommandEncoder,
+    texture: &wgpu::Texture,
+) -> Result<Vec<u8>, EngineError> {
+    let extent = wgpu::Extent3d {
+        depth_or_array_layers: 1,
+        ..texture.size()
+    };
+    let block_bytes = texture
+        .format()
+        .block_copy_size(Some(wgpu::TextureAspect::All))
+        .ok_or_else(|| EngineError::GpuError(format!("can't read back {:?}", texture.format())))?;
+    let row = extent.width * block_bytes;
+    let padded = padded_row_bytes(row);
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("blocking readback"),
+        size: padded as u64 * extent.height as u64,
+        usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    encoder.copy_texture_to_buffer(
+        texture.as_image_copy(),
+        wgpu::TexelCopyBufferInfo {
+            buffer: &buffer,
+            layout: wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(padded),
+                rows_per_image: Some(extent.height),
+            },
+        },
+        extent,
+    );
+    queue.submit(Some(encoder.finish()));
+
+    let (sender, receiver) = crossbeam::channel::bounded(1);
+    buffer
+        .slice(..)
+        .map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+    let _ = device.poll(wgpu::Maintain::Wait);
+    receiver
+        .recv()
+        .map_err(|e| EngineError::GpuError(e.to_string()))?
+        .map_err(|e| EngineError::GpuError(e.to_string()))?;
+
+    let pixels = {
+        let data = buffer.slice(..).get_mapped_range();
+        strip_row_padding(&data, row as usize, padded as usize, extent.height as usize)
+    };
+    buffer.unmap();
+    Ok(pixels)
+}
+
+/// `framebuffer`'s color as an image, waiting for the GPU. For tests and tools; the game
+/// takes screenshots through [`ReadbackService::request_texture`] instead.
+pub fn capture_to_image(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    framebuffer: &crate::FrameBuffer,
+) -> Result<image::RgbaImage, EngineError> {
+    let texture = &framebuffer.color().texture;
+    let encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("capture encoder"),
+    });
+    let pixels = read_texture_blocking(device, queue, encoder, texture)?;
+    let size = texture.size();
+    rgba_image(pixels, size.width, size.height, texture.format())
+}
+
+/// Path for a new screenshot, `screenshots/<unix millis>.png` next to the working directory.
+pub fn screenshot_path() -> Result<PathBuf, EngineError> {
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or_default();
+    let dir = PathBuf::from("screenshots");
+    std::fs::create_dir_all(&dir)?;
+    Ok(dir.join(format!("{}.png", timestamp)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gpu() -> Option<(Arc<wgpu::Device>, Arc<wgpu::Queue>)> {
+        let _ = crate::GPU::init(crate::GpuRequirements::default());
+        crate::GPU::with_read_recovered(|gpu| (gpu.device().clone(), gpu.queue().clone())).ok()
+    }
+
+    /// Advances one frame at a time until nothing is queued or in flight; returns how many
+    /// frames that took.
+    fn drain(service: &mut ReadbackService, device: &wgpu::Device, queue: &wgpu::Queue) -> usize {
+        let mut frames = 0;
+        while service.queued() + service.in_flight() > 0 {
+            assert!(frames < 5000, "readback never finished");
+            service.advance(device, queue);
+            frames += 1;
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+        frames
+    }
+
+    fn source(device: &wgpu::Device, bytes: &[u8]) -> WgpuBuffer {
+        WgpuBuffer::from_data(
+            device,
+            bytes,
+            wgpu::BufferUsages::COPY_SRC,
+            Some("readback source"),
+        )
+    }
+
+    #[test]
+    fn row_padding_is_stripped_for_any_width() {
+        for width in [1u32, 3, 63, 64, 65, 100] {
+            let row = width * 4;
+            let padded = padded_row_bytes(row);
+            assert_eq!(padded % wgpu::COPY_BYTES_PER_ROW_ALIGNMENT, 0);
+            assert!(padded >= row && padded - row < wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+            let rows = 3;
+            let data: Vec<u8> = (0..padded * rows)
+                .map(|i| {
+                    if i % padded < row {
+                        (i / padded) as u8 + 1
+                    } else {
+                        0xee
+                    }
+                })
+                .collect();
+            let stripped = strip_row_padding(&data, row as usize, padded as usize, rows as usize);
+            assert_eq!(stripped.len(), (row * rows) as usize, "width {}", width);
+            let expected: Vec<u8> = (1..=rows as u8)
+                .flat_map(|r| vec![r; row as usize])
+                .collect();
+            assert_eq!(stripped, expected, "width {}", width);
+        }
+    }
+
+    #[test]
+    fn tickets_hand_their_result_out_once() {
+        let ticket = ReadbackTicket::new(1);
+        let clone = ticket.clone();
+        assert!(ticket.is_pending());
+        assert!(ticket.poll().is_none());
+        ticket.resolve(Ok(vec![1, 2]));
+        assert!(!clone.is_pending());
+        assert_eq!(clone.poll().unwrap().unwrap(), [1, 2]);
+        assert!(ticket.poll().is_none());
+        assert_eq!(
+            format!("{:?}", ticket),
+            "ReadbackTicket { id: 1, state: \"taken\" }"
+        );
+
+        // Callbacks registered early run on delivery; late ones run right away.
+        let delivered = Arc::new(Mutex::new(Vec::new()));
+        let early = ReadbackTicket::new(2);
+        let log = delivered.clone();
+        early.on_ready(move |result| log.lock().unwrap().push(result.unwrap()));
+        assert!(delivered.lock().unwrap().is_empty());
+        early.resolve(Ok(vec![2]));
+        let late = ReadbackTicket::new(3);
+        late.resolve(Ok(vec![3]));
+        let log = delivered.clone();
+        late.on_ready(move |result| log.lock().unwrap().push(result.unwrap()));
+        assert_eq!(*delivered.lock().unwrap(), [vec![2], vec![3]]);
+        assert!(early.poll().is_none() && late.poll().is_none());
+    }
+
+    /// Needs an adapter; passes without one.
+    #[test]
+    fn tickets_resolve_over_later_frames() {
+        let Some((device, queue)) = gpu() else {
+            return;
+        };
+        let bytes: Vec<u8> = (0..64).collect();
+        let src = source(&device, &bytes);
+        let mut service = ReadbackService::default();
+
+        let ticket = service.request_buffer(&src, 5..19);
+        assert!(ticket.is_pending());
+        assert_eq!(service.queued(), 1);
+        service.advance(&device, &queue);
+        assert_eq!((service.queued(), service.in_flight()), (0, 1));
+        drain(&mut service, &device, &queue);
+        assert_eq!(ticket.poll().unwrap().unwrap(), &bytes[5..19]);
+        assert!(ticket.poll().is_none());
+
+        // Bad requests fail at once instead of reaching the GPU.
+        let failed = service.request_buffer(&src, 60..70);
+        assert!(matches!(failed.poll(), Some(Err(_))));
+        assert_eq!(service.queued(), 0);
+
+        // Texture regions come back without their row padding.
+        let (width, height) = (65, 3);
+        let texture = Texture::from_desc(
+            &device,
+            &wgpu::TextureDescriptor {
+                label: Some("readback texture"),
+                size: wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba8Unorm,
+                usage: wgpu::TextureUsages::COPY_SRC | wgpu::TextureUsages::COPY_DST,
+                view_formats: &[],
+            },
+        );
+        let texels: Vec<u8> = (0..width * height * 4).map(|i| i as u8).collect();
+        queue.write_texture(
+            texture.texture.as_image_copy(),
+            &texels,
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(width * 4),
+                rows_per_image: None,
+            },
+            texture.texture.size(),
+        );
+        let origin = wgpu::Origin3d { x: 1, y: 1, z: 0 };
+        let extent = wgpu::Extent3d {
+            width: 64,
+            height: 2,
+            depth_or_array_layers: 1,
+        };
+        let ticket = service.request_texture(&texture, 0, origin, extent);
+        drain(&mut service, &device, &queue);
+        let expected: Vec<u8> = (1..3)
+            .flat_map(|y| {
+                let start = ((y * width + 1) * 4) as usize;
+                texels[start..start + 64 * 4].to_vec()
+            })
+            .collect();
+        assert_eq!(ticket.poll().unwrap().unwrap(), expected);
+    }
+
+    /// Needs an adapter; passes without one.
+    #[test]
+    fn staging_buffers_are_recycled() {
+        let Some((device, queue)) = gpu() else {
+            return;
+        };
+        let src = source(&device, &[7u8; 1024]);
+        let mut service = ReadbackService::new(1);
+        for size in [1024, 16, 256, 100, 600] {
+            let ticket = service.request_buffer(&src, 0..size);
+            service.advance(&device, &queue);
+            // The first, largest buffer fits every later request and is out of the pool while
+            // its copy is in flight.
+            assert_eq!(service.pooled(), 0);
+            drain(&mut service, &device, &queue);
+            assert_eq!(ticket.poll().unwrap().unwrap().len(), size as usize);
+            assert_eq!(service.pooled(), 1, "size {}", size);
+        }
+        // Both in flight at once need a second buffer; both come back.
+        service.set_max_in_flight(2);
+        let tickets = [
+            service.request_buffer(&src, 0..8),
+            service.request_buffer(&src, 0..8),
+        ];
+        drain(&mut service, &device, &queue);
+        assert!(tickets.iter().all(|t| t.poll().unwrap().is_ok()));
+        assert_eq!(service.pooled(), 2);
+    }
+
+    /// Needs an adapter; passes without one.
+    #[test]
+    fn requests_past_the_budget_wait_in_the_queue() {
+        let Some((device, queue)) = gpu() else {
+            return;
+        };
+        let bytes: Vec<u8> = (0..20).collect();
+        let src = source(&device, &bytes);
+        let mut service = ReadbackService::new(2);
+        let tickets: Vec<_> = (0..5)
+            .map(|i| service.request_buffer(&src, i * 4..i * 4 + 4))
+            .collect();
+        assert_eq!((service.queued(), service.in_flight()), (5, 0));
+        service.advance(&device, &queue);
+        assert_eq!((service.queued(), service.in_flight()), (3, 2));
+        assert!(tickets.iter().all(ReadbackTicket::is_pending));
+
+        let mut frames = 0;
+        while service.queued() + service.in_flight() > 0 {
+            assert!(frames < 5000, "readback never finished");
+            service.advance(&device, &queue);
+            assert!(service.in_flight() <= 2);
+            frames += 1;
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+        for (i, ticket) in tickets.iter().enumerate() {
+            assert_eq!(ticket.poll().unwrap().unwrap(), &bytes[i * 4..i * 4 + 4]);
+        }
+    }
+}
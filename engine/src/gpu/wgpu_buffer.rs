@@ -1,10 +1,11 @@
 use wgpu::util::DeviceExt;
 
-/// Wrapper around WGPU buffers
+/// Wrapper around WGPU buffers. Tracks the bytes written, `len`, apart from the allocation,
+/// `capacity`; writes past the capacity move the data to a larger buffer.
 #[derive(Debug)]
 pub struct WgpuBuffer {
     buffer: wgpu::Buffer,
-    size: usize,
+    len: usize,
     usage: wgpu::BufferUsages,
     label: String,
 }
@@ -17,8 +18,6 @@ impl WgpuBuffer {
         usage: wgpu::BufferUsages,
         label: Option<&str>,
     ) -> Self {
-        use wgpu::util::DeviceExt;
-        let size = (std::mem::size_of::<T>() * data.len()) as u64;
         let contents = bytemuck::cast_slice(data);
         let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label,
@@ -27,7 +26,7 @@ impl WgpuBuffer {
         });
         crate::WgpuBuffer {
             buffer,
-            size: size as usize,
+            len: contents.len(),
             usage,
             label: label.unwrap_or("unnamed").to_string(),
         }
@@ -35,8 +34,24 @@ impl WgpuBuffer {
     pub fn get(&self) -> &wgpu::Buffer {
         &self.buffer
     }
-    pub fn size(&self) -> usize {
-        self.size
+    /// Bytes written by the last write, counted from the start of the buffer.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    /// Bytes allocated; at least [`WgpuBuffer::len`].
+    pub fn capacity(&self) -> u64 {
+        self.buffer.size()
+    }
+    /// The written bytes only, for binding as a vertex or index buffer. Nothing written, it's
+    /// the whole allocation, since wgpu slices can't be empty.
+    pub fn slice(&self) -> wgpu::BufferSlice<'_> {
+        match self.len {
+            0 => self.buffer.slice(..),
+            len => self.buffer.slice(..len as u64),
+        }
     }
 
     /// Create a new empty GPU buffer with given usage flags
@@ -45,7 +60,6 @@ impl WgpuBuffer {
         usage: wgpu::BufferUsages,
         label: Option<&str>,
     ) -> Self {
-        use wgpu::util::DeviceExt;
         let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label,
             contents: &[],
@@ -53,67 +67,169 @@ impl WgpuBuffer {
         });
         WgpuBuffer {
             buffer,
-            size: 0,
+            len: 0,
             usage,
             label: label.unwrap_or("unnamed").to_string(),
         }
     }
 
-    /// Update the buffer with new data via queue write
+    /// Replaces the buffer with a larger one, same usage and label, if `required` bytes
+    /// don't fit. The old contents aren't carried over. Returns whether it was replaced.
+    fn reserve(&mut self, device: &wgpu::Device, required: u64) -> bool {
+        let Some(capacity) = crate::grown_capacity(self.capacity(), required) else {
+            return false;
+        };
+        self.buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(&self.label),
+            size: capacity,
+            usage: self.usage,
+            mapped_at_creation: false,
+        });
+        true
+    }
+
+    /// Writes `data` at `offset` via the queue; the written range then ends where the data
+    /// does. When it doesn't fit, the buffer is first replaced with a larger one, and
+    /// anything before `offset` is lost. Returns `true` in that case: bind groups made
+    /// from the old buffer are stale and must be rebuilt.
     pub fn write_data<T: bytemuck::Pod>(
         &mut self,
         queue: &wgpu::Queue,
         device: &wgpu::Device,
         data: &[T],
         offset: Option<u64>,
-    ) {
-        let bytes = bytemuck::cast_slice(data);
-        let size = (std::mem::size_of::<T>() * data.len()) as u64;
-        if size > self.buffer.size() {
-            self.buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some(&self.label),
-                contents: bytes,
-                usage: self.usage,
-            })
-        }
-        self.size = size as usize;
-        queue.write_buffer(&self.buffer, offset.unwrap_or(0), bytes);
+    ) -> bool {
+        let bytes: &[u8] = bytemuck::cast_slice(data);
+        let offset = offset.unwrap_or(0);
+        let grew = self.reserve(device, offset + bytes.len() as u64);
+        queue.write_buffer(&self.buffer, offset, bytes);
+        self.len = offset as usize + bytes.len();
+        grew
     }
 }
 
 pub type WgpuBufferCacheType = crate::HashCache<WgpuBuffer>;
 pub struct WgpuBufferManager {
     inner: WgpuBufferCacheType,
+    residency: crate::Residency,
 }
 
 impl WgpuBufferManager {
     pub fn new() -> Self {
         Self {
             inner: Default::default(),
+            residency: crate::Residency::default(),
+        }
+    }
+    /// Bytes of cached buffers allowed before the least recently used are dropped;
+    /// `None`, the default, never evicts.
+    pub fn set_budget(&mut self, budget: Option<u64>) {
+        self.residency.set_budget(budget);
+        self.enforce_budget();
+    }
+    pub fn residency(&self) -> &crate::Residency {
+        &self.residency
+    }
+    pub fn stats(&self) -> crate::ResourceStats {
+        crate::ResourceStats {
+            buffer_bytes: self.residency.bytes(),
+            evictions: self.residency.evictions(),
+            ..Default::default()
+        }
+    }
+    /// Drops least recently used buffers until the cache fits its budget. Sizes are the
+    /// ones recorded at insert; a buffer grown through `get_mut` counts at its old size.
+    fn enforce_budget(&mut self) {
+        for key in self.residency.evict(|_| false) {
+            self.inner.remove(&key);
         }
     }
 }
 
 impl crate::CacheStorage<WgpuBuffer> for WgpuBufferManager {
     fn get(&self, key: &crate::CacheKey) -> Option<&WgpuBuffer> {
+        self.residency.touch(key);
         self.inner.get(key)
     }
     fn contains(&self, key: &crate::CacheKey) -> bool {
         self.inner.contains_key(key)
     }
     fn get_mut(&mut self, key: &crate::CacheKey) -> Option<&mut WgpuBuffer> {
+        self.residency.touch(key);
         self.inner.get_mut(key)
     }
     fn get_or_create<F>(&mut self, key: crate::CacheKey, create_fn: F) -> &mut WgpuBuffer
     where
         F: FnOnce() -> WgpuBuffer,
     {
-        self.inner.entry(key).or_insert_with(create_fn)
+        if !self.inner.contains_key(&key) {
+            self.insert(key, create_fn());
+        }
+        self.residency.touch(&key);
+        self.inner.get_mut(&key).expect("inserted above")
     }
     fn insert(&mut self, key: crate::CacheKey, resource: WgpuBuffer) {
+        self.residency.track(key, resource.get().size());
         self.inner.insert(key, resource);
+        self.enforce_budget();
     }
     fn remove(&mut self, key: &crate::CacheKey) -> Option<WgpuBuffer> {
+        self.residency.untrack(key);
         self.inner.remove(key)
     }
+    fn iter(&self) -> impl Iterator<Item = (&crate::CacheKey, &WgpuBuffer)> {
+        self.inner.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn device() -> Option<(wgpu::Device, wgpu::Queue)> {
+        let instance = wgpu::Instance::default();
+        let adapter =
+            pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions::default()))?;
+        pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor::default(), None)).ok()
+    }
+
+    #[test]
+    fn grown_capacity_only_when_outgrown() {
+        assert_eq!(crate::grown_capacity(1024, 1024), None);
+        assert_eq!(crate::grown_capacity(1024, 512), None);
+        assert_eq!(crate::grown_capacity(1024, 1025), Some(2048));
+        assert_eq!(crate::grown_capacity(0, 4), Some(256));
+        for required in [1, 255, 257, 4000, 70_000] {
+            let grown = crate::grown_capacity(0, required).unwrap();
+            assert!(grown >= required);
+            assert_eq!(grown % wgpu::COPY_BUFFER_ALIGNMENT, 0);
+        }
+    }
+
+    /// Needs an adapter; passes without one.
+    #[test]
+    fn len_tracks_writes_and_capacity_grows() {
+        let Some((device, queue)) = device() else {
+            return;
+        };
+        let usage = wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST;
+        let mut buffer = WgpuBuffer::new_empty(&device, usage, Some("test"));
+        assert!(buffer.is_empty());
+
+        let grew = buffer.write_data(&queue, &device, &[0u32; 100], None);
+        assert!(grew);
+        assert_eq!(buffer.len(), 400);
+        assert!(buffer.capacity() >= 400);
+
+        let capacity = buffer.capacity();
+        let grew = buffer.write_data(&queue, &device, &[0u32; 10], None);
+        assert!(!grew);
+        assert_eq!(buffer.len(), 40);
+        assert_eq!(buffer.capacity(), capacity);
+
+        let grew = buffer.write_data(&queue, &device, &[0u32; 10], Some(capacity));
+        assert!(grew);
+        assert_eq!(buffer.len(), capacity as usize + 40);
+        assert!(buffer.capacity() > capacity);
+    }
 }
@@ -0,0 +1,387 @@
+//! Rendering without a window. [`HeadlessRenderer`] owns the same scene, HDR and final
+//! passes the app runs each frame, but draws them into an offscreen [`HeadlessTarget`]
+//! instead of a swapchain, so tools and CI can render a scene and read it back with
+//! [`capture_to_image`]. Needs no event loop: the global [`GPU`] is created without a
+//! surface when nothing has created it yet.
+
+use glam::Vec3;
+
+use crate::{
+    advance_gpu_frame, camera::Camera, capture_to_image, log_warning, BindGroup, DebugMode,
+    DepthVariant, EngineError, FrameBuffer, FrameBufferSize, GpuRequirements, Light, ModelManager,
+    PassGraph, PostProcessChain, RenderBindGroupLayouts, RenderPass, RenderSettings,
+    RenderTargetKind, RenderTargetManager, Renderer3d, World, GPU,
+};
+
+/// Offscreen stand-in for a window surface: the scene and HDR targets the passes render
+/// through, plus the color target the final blit writes instead of a swapchain image.
+pub struct HeadlessTarget {
+    /// What a surface of this size would be configured with; pipelines are built for it.
+    config: wgpu::SurfaceConfiguration,
+    targets: RenderTargetManager,
+    output: FrameBuffer,
+}
+
+impl HeadlessTarget {
+    /// Format of the final image; 8-bit RGBA so captures need no swizzle.
+    pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+    pub fn new(device: &wgpu::Device, width: u32, height: u32) -> Self {
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format: Self::FORMAT,
+            width: width.max(1),
+            height: height.max(1),
+            present_mode: wgpu::PresentMode::Fifo,
+            alpha_mode: wgpu::CompositeAlphaMode::Opaque,
+            view_formats: vec![],
+            desired_maximum_frame_latency: 2,
+        };
+        let size = FrameBufferSize::from(&config);
+        let mut targets = RenderTargetManager::new();
+        targets.insert(
+            FrameBuffer::new_with_depth(
+                device,
+                size,
+                config.format,
+                RenderSettings::depth_policy().format(),
+                RenderSettings::sample_count(),
+                "scene buffer",
+            ),
+            RenderTargetKind::Scene,
+        );
+        targets.insert(
+            FrameBuffer::new_color_only(device, size, config.format, "hdr buffer"),
+            RenderTargetKind::Hdr,
+        );
+        let output = FrameBuffer::new_color_only(device, size, config.format, "headless output");
+        Self {
+            config,
+            targets,
+            output,
+        }
+    }
+    pub fn surface_config(&self) -> &wgpu::SurfaceConfiguration {
+        &self.config
+    }
+    pub fn targets(&self) -> &RenderTargetManager {
+        &self.targets
+    }
+    /// The finished frame, after the final blit.
+    pub fn output(&self) -> &FrameBuffer {
+        &self.output
+    }
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        self.config.width = width.max(1);
+        self.config.height = height.max(1);
+        let size = FrameBufferSize::from(&self.config);
+        self.targets.resize(device, size);
+        self.output.resize(device, size);
+    }
+}
+
+/// A world, its renderer and a camera drawing into a [`HeadlessTarget`]. Frames are
+/// advanced explicitly with [`HeadlessRenderer::update`], so the same calls always render
+/// the same image.
+pub struct HeadlessRenderer {
+    world: World,
+    model_manager: ModelManager,
+    renderer: Renderer3d,
+    camera: Camera,
+    light: Light,
+    debug_mode: DebugMode,
+    uniform_bind_group: wgpu::BindGroup,
+    depth_stencil: wgpu::DepthStencilState,
+    post_process: PostProcessChain,
+    target: HeadlessTarget,
+}
+
+impl HeadlessRenderer {
+    /// Builds the renderer for a `width` x `height` image, creating the global GPU first if
+    /// nothing has. The world starts empty; see [`HeadlessRenderer::load_scene`].
+    pub fn new(width: u32, height: u32) -> Result<Self, EngineError> {
+        if GPU::with_read_recovered(|_| ()).is_err() {
+            GPU::init(GpuRequirements::default())?;
+        }
+        RenderBindGroupLayouts::try_get()?;
+        let (device, queue) =
+            GPU::with_read_recovered(|gpu| (gpu.device().clone(), gpu.queue().clone()))?;
+
+        let target = HeadlessTarget::new(&device, width, height);
+        let config = target.surface_config().clone();
+        let depth_stencil = RenderSettings::depth_state(DepthVariant::Opaque);
+        let mut model_manager = ModelManager::new(queue.clone(), device.clone());
+        let renderer = Renderer3d::new(&device, &config)?;
+        let camera = Camera::new(&device, config.width as f32 / config.height as f32);
+        let light = Light::new(&device)?;
+        let uniform_bind_group = BindGroup::uniform(&device, camera.buffer(), light.buffer());
+        let debug_mode = DebugMode::new(
+            &device,
+            &mut model_manager.materials.shaders,
+            &mut model_manager.materials.pipelines,
+            &camera,
+            &light,
+            &config,
+        )?;
+        if let Err(e) = model_manager.load_material_library(&config, Some(depth_stencil.clone())) {
+            log_warning!("Material library: {}", e);
+        }
+        let world = World::new(&queue, &device, &config, Some(depth_stencil.clone()))?;
+        let post_process = PostProcessChain::with_defaults(&device, config.format)?;
+
+        let mut headless = Self {
+            world,
+            model_manager,
+            renderer,
+            camera,
+            light,
+            debug_mode,
+            uniform_bind_group,
+            depth_stencil,
+            post_process,
+            target,
+        };
+        headless.look_at(Vec3::new(0.0, 5.0, 10.0), Vec3::ZERO);
+        Ok(headless)
+    }
+
+    /// Replaces the world's scene with the scene file `name`.
+    pub fn load_scene(&mut self, name: &str) -> Result<(), EngineError> {
+        self.world.load_scene_by_name(
+            &mut self.model_manager,
+            name,
+            &self.target.config,
+            &self.depth_stencil,
+        )?;
+        self.model_manager
+            .materials
+            .build_storage(&self.model_manager.device);
+        Ok(())
+    }
+    /// Points the camera from `eye` at `target`.
+    pub fn look_at(&mut self, eye: Vec3, target: Vec3) {
+        self.camera.set_override(Some((eye, target)));
+        self.camera.update_effects(0.0);
+    }
+    pub fn world(&self) -> &World {
+        &self.world
+    }
+    pub fn world_mut(&mut self) -> &mut World {
+        &mut self.world
+    }
+    pub fn target(&self) -> &HeadlessTarget {
+        &self.target
+    }
+    pub fn resize(&mut self, width: u32, height: u32) {
+        let device = self.model_manager.device.clone();
+        self.target.resize(&device, width, height);
+        self.camera.resize(
+            self.target.config.width as f32,
+            self.target.config.height as f32,
+        );
+        self.camera.update_effects(0.0);
+    }
+
+    /// Steps the world by `dt` seconds and rebuilds this frame's instances.
+    pub fn update(&mut self, dt: f32) {
+        self.camera.update_effects(dt);
+        self.world
+            .terrain
+            .update_streaming(self.camera.view().eye, 4);
+        self.renderer
+            .instances
+            .update(&self.world, &self.camera, &mut self.model_manager);
+        self.world.update(
+            &self.model_manager.queue,
+            &self.model_manager.device,
+            &self.camera,
+            dt,
+        );
+        self.renderer
+            .particles
+            .update(&self.world, &mut self.model_manager, dt);
+        self.upload(dt);
+    }
+    fn upload(&mut self, dt: f32) {
+        let queue = &self.model_manager.queue;
+        let device = &self.model_manager.device;
+        self.light.upload(queue, device);
+        self.camera.upload(queue, device);
+        let materials = &mut self.model_manager.materials;
+        if materials.storage_rebuild {
+            materials.build_storage(device);
+        }
+        materials.flush(queue);
+        // After the material storage, which the instance storage bind group shares.
+        self.renderer
+            .instances
+            .upload(queue, device, &materials.storage_buffer);
+        self.renderer.exposure_mut().update(queue, device, dt);
+    }
+
+    /// Renders one frame into [`HeadlessTarget::output`] and submits it.
+    pub fn render(&mut self) {
+        advance_gpu_frame();
+        let device = self.model_manager.device.clone();
+        let queue = self.model_manager.queue.clone();
+        let size = FrameBufferSize::from(&self.target.config);
+        self.renderer.exposure_mut().prepare();
+        self.renderer
+            .prepare_debug(&mut self.debug_mode, &self.model_manager, &self.world);
+        self.post_process
+            .prepare(&device, &queue, &mut self.target.targets, size);
+
+        let render3d = &self.renderer;
+        let models = &self.model_manager;
+        let world = &self.world;
+        let uniform_bind_group = &self.uniform_bind_group;
+        let debug_mode = &self.debug_mode;
+        let render_targets = &self.target.targets;
+        let post_process = &self.post_process;
+        let output_view = &self.target.output.color().view;
+        let scene = render_targets.get(&RenderTargetKind::Scene);
+        let hdr = render_targets.get(&RenderTargetKind::Hdr);
+        let output = post_process.output(render_targets).or(hdr);
+        let exposure = RenderTargetKind::Custom("exposure");
+        let post = RenderTargetKind::Custom("post process");
+        let headless = RenderTargetKind::Custom("headless output");
+        let particles = RenderTargetKind::Custom("particles");
+        let environment = RenderTargetKind::Custom("environment");
+        let mut graph = PassGraph::new();
+
+        let prepass = render3d.instances.prepass.active(debug_mode);
+        if let Some(scene_fb) = scene.filter(|_| prepass) {
+            graph.pass(
+                "Depth Prepass",
+                &[],
+                &[RenderTargetKind::Scene],
+                false,
+                move |encoder| {
+                    render3d.depth_prepass(encoder, scene_fb, world, uniform_bind_group);
+                },
+            );
+        }
+        if let Some(scene_fb) = scene {
+            graph.pass(
+                "Equirect Projection",
+                &[],
+                &[environment],
+                false,
+                move |encoder| {
+                    if let Some(projection) = world.projection() {
+                        projection.record_projection(encoder, Some("Equirect Projection Pass"));
+                    }
+                },
+            );
+            graph.pass("Particles", &[], &[particles], false, move |encoder| {
+                render3d.simulate_particles(encoder);
+            });
+            graph.pass(
+                "Scene Pass",
+                &[particles, environment],
+                &[RenderTargetKind::Scene],
+                true,
+                move |encoder| {
+                    let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                        label: Some("Scene Pass"),
+                        color_attachments: &[Some(scene_fb.color_attachment())],
+                        depth_stencil_attachment: if prepass {
+                            scene_fb.depth_attachment_loaded()
+                        } else {
+                            scene_fb.depth_attachment()
+                        },
+                        timestamp_writes: None,
+                        occlusion_query_set: None,
+                    });
+                    render3d.render(models, &mut rpass, world, uniform_bind_group, debug_mode);
+                },
+            );
+            graph.pass(
+                "Auto Exposure",
+                &[RenderTargetKind::Scene],
+                &[exposure],
+                false,
+                move |encoder| {
+                    render3d.auto_exposure(&models.device, encoder, scene_fb.color());
+                },
+            );
+            if let Some(hdr_fb) = hdr {
+                graph.try_pass(
+                    "HDR Pass",
+                    &[RenderTargetKind::Scene, exposure],
+                    &[RenderTargetKind::Hdr],
+                    false,
+                    move |encoder| render3d.hdr(encoder, models, scene_fb.color(), hdr_fb),
+                );
+            }
+        }
+        if let Some(hdr_fb) = hdr.filter(|_| post_process.active()) {
+            graph.pass(
+                "Post Process",
+                &[RenderTargetKind::Hdr],
+                &[post],
+                false,
+                move |encoder| {
+                    post_process.record(&models.device, encoder, render_targets, hdr_fb.color());
+                },
+            );
+        }
+        if let Some(output_fb) = output {
+            graph.try_pass(
+                "Final Blit",
+                &[RenderTargetKind::Hdr, post],
+                &[headless],
+                false,
+                move |encoder| {
+                    render3d.final_blit_to_surface(
+                        &models.device,
+                        encoder,
+                        output_fb.color(),
+                        output_view,
+                    )
+                },
+            );
+        }
+        graph.submit(&device, &queue);
+        self.renderer.exposure_mut().after_submit();
+        self.model_manager.readback.advance(&device, &queue);
+    }
+
+    /// Waits for the last rendered frame and returns it.
+    pub fn capture(&self) -> Result<image::RgbaImage, EngineError> {
+        capture_to_image(
+            &self.model_manager.device,
+            &self.model_manager.queue,
+            self.target.output(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Needs an adapter; passes without one.
+    #[test]
+    fn renders_the_debug_scene() {
+        let instance = wgpu::Instance::default();
+        if pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions::default()))
+            .is_none()
+        {
+            return;
+        }
+        crate::assets::loader::test_root();
+        let mut headless = HeadlessRenderer::new(64, 48).unwrap();
+        headless.load_scene("debug").unwrap();
+        headless.look_at(Vec3::new(4.5, 8.0, 14.0), Vec3::new(4.5, 5.0, 4.5));
+        headless.update(1.0 / 60.0);
+        headless.render();
+        let image = headless.capture().unwrap();
+        assert_eq!(image.dimensions(), (64, 48));
+        let lit = image
+            .pixels()
+            .filter(|p| p[0] > 0 || p[1] > 0 || p[2] > 0)
+            .count();
+        assert!(lit > 64 * 48 / 2, "only {} pixels aren't black", lit);
+    }
+}
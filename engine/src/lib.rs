@@ -1,9 +1,19 @@
 pub mod assets;
+#[cfg(feature = "audio")]
+pub mod audio;
 pub mod camera;
+#[cfg(feature = "devtools")]
+pub mod devtools;
 pub mod ecs;
+#[cfg(feature = "hot-reload-game")]
+pub mod game_module;
 pub mod gpu;
+#[cfg(feature = "headless")]
+pub mod headless;
 pub mod rendering;
 pub mod resources;
+#[cfg(feature = "scripting")]
+pub mod scripting;
 pub mod text;
 pub mod util;
 
@@ -17,31 +27,44 @@ pub use logger as rupyLogger;
 #[macro_export]
 macro_rules! log_debug {
     ($($arg:tt)*) => {
-        log::debug!($($arg)*);
+        log::debug!($($arg)*)
     };
 }
 #[cfg(feature = "logging")]
 #[macro_export]
 macro_rules! log_info {
     ($($arg:tt)*) => {
-        log::info!($($arg)*);
+        log::info!($($arg)*)
     };
 }
 #[cfg(feature = "logging")]
 #[macro_export]
 macro_rules! log_error {
     ($($arg:tt)*) => {
-        log::error!($($arg)*);
+        log::error!($($arg)*)
     };
 }
 #[cfg(feature = "logging")]
 #[macro_export]
 macro_rules! log_warning {
     ($($arg:tt)*) => {
-        log::warn!($($arg)*);
+        log::warn!($($arg)*)
     };
 }
 
+/// [`log_warning`] for conditions that repeat every frame: each distinct message is logged
+/// the first time only.
+#[cfg(feature = "logging")]
+#[macro_export]
+macro_rules! log_warning_once {
+    ($($arg:tt)*) => {{
+        let message = format!($($arg)*);
+        if $crate::first_warning(&message) {
+            log::warn!("{}", message);
+        }
+    }};
+}
+
 #[cfg(not(feature = "logging"))]
 #[macro_export]
 macro_rules! log_debug {
@@ -62,3 +85,8 @@ macro_rules! log_error {
 macro_rules! log_warning {
     ($($arg:tt)*) => {};
 }
+#[cfg(not(feature = "logging"))]
+#[macro_export]
+macro_rules! log_warning_once {
+    ($($arg:tt)*) => {};
+}
@@ -1,5 +1,5 @@
 use super::Vertex;
-use glam::Vec3;
+use glam::{Mat4, Vec3};
 
 #[derive(Copy, Clone, Debug)]
 pub struct AABB {
@@ -30,6 +30,33 @@ impl AABB {
         Self { min, max }
     }
 
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+    pub fn half_extents(&self) -> Vec3 {
+        (self.max - self.min) * 0.5
+    }
+    pub fn transformed(&self, transform: &Mat4) -> AABB {
+        let center = transform.transform_point3(self.center());
+        let half = self.half_extents();
+        let axes = [transform.x_axis, transform.y_axis, transform.z_axis].map(|a| a.truncate());
+        let extent = axes[0].abs() * half.x + axes[1].abs() * half.y + axes[2].abs() * half.z;
+        AABB {
+            min: center - extent,
+            max: center + extent,
+        }
+    }
+    pub fn bounding_sphere(&self, transform: &Mat4) -> (Vec3, f32) {
+        let scale = transform
+            .x_axis
+            .truncate()
+            .length()
+            .max(transform.y_axis.truncate().length())
+            .max(transform.z_axis.truncate().length());
+        let center = transform.transform_point3(self.center());
+        (center, self.half_extents().length() * scale)
+    }
+
     pub fn get_normal_positive_vertex(&self, normal: Vec3) -> Vec3 {
         Vec3::new(
             if normal.x >= 0.0 {
@@ -0,0 +1,552 @@
+//! Skeletal animation: skeletons and clips parsed from glTF, sampled per entity with an
+//! [`Animator`] and skinned on the GPU. Each animated entity gets its joint matrices in a
+//! storage buffer bound on group 2 in place of the object data, and its skinned meshes
+//! draw through a `vs_skinned` variant of their material's shader.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use glam::{Mat4, Quat, Vec3};
+
+use crate::{
+    Animator, BindGroup, CacheKey, CacheStorage, HashCache, Material, MaterialManager, Model,
+    ModelManager, VertexSkinned, WgpuBuffer,
+};
+
+/// One bone: its parent and rest pose relative to that parent.
+#[derive(Debug, Clone)]
+pub struct Joint {
+    pub name: String,
+    pub parent: Option<usize>,
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+}
+
+impl Joint {
+    pub fn local(&self) -> Mat4 {
+        Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.translation)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Skeleton {
+    pub joints: Vec<Joint>,
+    /// Model space to each joint's bind space; identity where the skin has none.
+    pub inverse_bind: Vec<Mat4>,
+    /// Transform above the joints without a parent.
+    pub root: Mat4,
+}
+
+impl Skeleton {
+    /// Local transform of every joint in its rest pose.
+    pub fn rest_pose(&self) -> Vec<Mat4> {
+        self.joints.iter().map(Joint::local).collect()
+    }
+    /// Model space transform of every joint given their `locals`.
+    pub fn global_poses(&self, locals: &[Mat4]) -> Vec<Mat4> {
+        let mut globals = vec![None; self.joints.len()];
+        (0..self.joints.len())
+            .map(|joint| self.global(joint, locals, &mut globals))
+            .collect()
+    }
+    fn global(&self, joint: usize, locals: &[Mat4], globals: &mut [Option<Mat4>]) -> Mat4 {
+        if let Some(global) = globals[joint] {
+            return global;
+        }
+        let local = locals.get(joint).copied().unwrap_or(Mat4::IDENTITY);
+        let global = match self.joints[joint].parent {
+            Some(parent) if parent < self.joints.len() && parent != joint => {
+                self.global(parent, locals, globals) * local
+            }
+            _ => self.root * local,
+        };
+        globals[joint] = Some(global);
+        global
+    }
+    /// Joint matrices for the vertex shader: bind space to posed model space.
+    pub fn skin_matrices(&self, locals: &[Mat4]) -> Vec<Mat4> {
+        self.global_poses(locals)
+            .into_iter()
+            .enumerate()
+            .map(|(joint, global)| {
+                let inverse_bind = self.inverse_bind.get(joint).copied();
+                global * inverse_bind.unwrap_or(Mat4::IDENTITY)
+            })
+            .collect()
+    }
+}
+
+/// Values [`Keyframes`] can blend between.
+pub trait Interpolate: Copy {
+    fn interpolate(from: Self, to: Self, t: f32) -> Self;
+}
+
+impl Interpolate for Vec3 {
+    fn interpolate(from: Self, to: Self, t: f32) -> Self {
+        from.lerp(to, t)
+    }
+}
+
+impl Interpolate for Quat {
+    fn interpolate(from: Self, to: Self, t: f32) -> Self {
+        from.slerp(to, t).normalize()
+    }
+}
+
+/// Keyframe `times` in seconds, ascending, with one value each.
+#[derive(Debug, Clone)]
+pub struct Keyframes<T> {
+    pub times: Vec<f32>,
+    pub values: Vec<T>,
+    /// Hold each value until the next keyframe instead of interpolating.
+    pub step: bool,
+}
+
+impl<T: Interpolate> Keyframes<T> {
+    /// The value at `time`, holding the first and last keyframes outside their range.
+    pub fn sample(&self, time: f32) -> Option<T> {
+        let count = self.times.len().min(self.values.len());
+        if count == 0 {
+            return None;
+        }
+        let next = self.times[..count].partition_point(|t| *t <= time);
+        if next == 0 {
+            return Some(self.values[0]);
+        }
+        if next == count {
+            return Some(self.values[count - 1]);
+        }
+        let (start, end) = (self.times[next - 1], self.times[next]);
+        let from = self.values[next - 1];
+        if self.step || end <= start {
+            return Some(from);
+        }
+        Some(T::interpolate(
+            from,
+            self.values[next],
+            (time - start) / (end - start),
+        ))
+    }
+}
+
+/// The animated properties of one joint; the rest keep their rest pose.
+#[derive(Debug, Clone)]
+pub struct JointChannel {
+    pub joint: usize,
+    pub translation: Option<Keyframes<Vec3>>,
+    pub rotation: Option<Keyframes<Quat>>,
+    pub scale: Option<Keyframes<Vec3>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct AnimationClip {
+    pub name: String,
+    /// Seconds, the last keyframe of any channel.
+    pub duration: f32,
+    pub channels: Vec<JointChannel>,
+}
+
+impl AnimationClip {
+    /// Key a clip from `file` is registered under, what [`Animator::clip`] refers to.
+    pub fn key(file: &str, name: &str) -> CacheKey {
+        CacheKey::from(format!("{}#{}", file, name))
+    }
+    /// `time` wrapped into the clip when looping, clamped to it otherwise.
+    pub fn local_time(&self, time: f32, looping: bool) -> f32 {
+        if self.duration <= 0.0 {
+            0.0
+        } else if looping {
+            time.rem_euclid(self.duration)
+        } else {
+            time.clamp(0.0, self.duration)
+        }
+    }
+    /// Local joint transforms of `skeleton` at `time`.
+    pub fn sample(&self, skeleton: &Skeleton, time: f32, looping: bool) -> Vec<Mat4> {
+        let time = self.local_time(time, looping);
+        let mut pose: Vec<(Vec3, Quat, Vec3)> = skeleton
+            .joints
+            .iter()
+            .map(|joint| (joint.scale, joint.rotation, joint.translation))
+            .collect();
+        for channel in &self.channels {
+            let Some((scale, rotation, translation)) = pose.get_mut(channel.joint) else {
+                continue;
+            };
+            if let Some(value) = channel.translation.as_ref().and_then(|k| k.sample(time)) {
+                *translation = value;
+            }
+            if let Some(value) = channel.rotation.as_ref().and_then(|k| k.sample(time)) {
+                *rotation = value;
+            }
+            if let Some(value) = channel.scale.as_ref().and_then(|k| k.sample(time)) {
+                *scale = value;
+            }
+        }
+        pose.into_iter()
+            .map(|(scale, rotation, translation)| {
+                Mat4::from_scale_rotation_translation(scale, rotation, translation)
+            })
+            .collect()
+    }
+}
+
+/// Mirrors the head of `Skin` in [`SKINNED_ENTRY`]; the joint matrices follow it.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable, Default)]
+pub struct SkinHeader {
+    pub tint: [f32; 4],    // 0–15
+    pub material_idx: u32, // 16–19
+    pub joint_count: u32,  // 20–23
+    pub _pad: [u32; 2],    // 24–31
+}
+
+impl SkinHeader {
+    pub const SIZE: u64 = std::mem::size_of::<SkinHeader>() as u64;
+    /// The header and one joint, the least a binding of `Skin` can be.
+    pub const MIN_BINDING_SIZE: u64 = Self::SIZE + std::mem::size_of::<Mat4>() as u64;
+}
+
+/// Binding and `vs_skinned` entry point appended to a shader that defines
+/// [`crate::OBJECT_VERTEX_FN`]. The joint matrices already include the entity's
+/// transform, so their weighted sum stands in for the object's model matrix.
+pub const SKINNED_ENTRY: &str = "struct SkinnedVertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) color: vec3<f32>,
+    @location(2) tex_coords: vec2<f32>,
+    @location(3) normal: vec3<f32>,
+    @location(4) tangent: vec3<f32>,
+    @location(15) surface: vec2<u32>,
+    @location(5) joints: vec4<u32>,
+    @location(6) weights: vec4<f32>,
+};
+struct Skin {
+    tint: vec4<f32>,
+    material_idx: u32,
+    joint_count: u32,
+    joints: array<mat4x4<f32>>,
+};
+@group(2) @binding(1) var<storage, read> skin: Skin;
+
+@vertex
+fn vs_skinned(in: SkinnedVertexInput) -> VertexOutput {
+    let skin_matrix = skin.joints[in.joints.x] * in.weights.x
+        + skin.joints[in.joints.y] * in.weights.y
+        + skin.joints[in.joints.z] * in.weights.z
+        + skin.joints[in.joints.w] * in.weights.w;
+
+    var vertex: VertexInput;
+    vertex.position = in.position;
+    vertex.color = in.color;
+    vertex.tex_coords = in.tex_coords;
+    vertex.normal = in.normal;
+    vertex.tangent = in.tangent;
+    vertex.surface = in.surface;
+
+    var object: PerObjectData;
+    object.model = skin_matrix;
+    object.prev_model = skin_matrix;
+    object.tint = skin.tint;
+    object.material_idx = skin.material_idx;
+    object.flags = 0u;
+    return object_vertex(vertex, object);
+}
+";
+
+/// A skinned copy of one of a model's primitives. Indices and material stay with the
+/// static model cached under `model_key`.
+#[derive(Debug)]
+pub struct SkinnedMesh {
+    pub model_key: CacheKey,
+    pub vertex_buffer: WgpuBuffer,
+}
+
+impl SkinnedMesh {
+    pub fn new(device: &wgpu::Device, model_key: CacheKey, vertices: &[VertexSkinned]) -> Self {
+        Self {
+            model_key,
+            vertex_buffer: WgpuBuffer::from_data(
+                device,
+                vertices,
+                wgpu::BufferUsages::VERTEX,
+                Some("skinned vertex buffer"),
+            ),
+        }
+    }
+}
+
+/// A model's skeleton and the skinned meshes it drives.
+#[derive(Debug)]
+pub struct Skin {
+    pub skeleton: Arc<Skeleton>,
+    pub meshes: Vec<SkinnedMesh>,
+}
+
+impl Skin {
+    /// Whether the primitive cached under `part_key` has a skinned copy here.
+    pub fn covers(&self, part_key: &CacheKey) -> bool {
+        self.meshes.iter().any(|mesh| mesh.model_key == *part_key)
+    }
+}
+
+/// An entity drawn skinned this frame, as [`AnimationManager::push`] queues it.
+#[derive(Debug, Clone)]
+pub struct SkinnedInstance {
+    pub model_key: CacheKey,
+    pub model: Mat4,
+    pub tint: [f32; 4],
+    pub animator: Animator,
+}
+
+/// Joint matrices of one skinned mesh of one entity this frame.
+#[derive(Debug)]
+struct JointSlot {
+    buffer: WgpuBuffer,
+    bind_group: Option<wgpu::BindGroup>,
+    bytes: Vec<u8>,
+}
+
+#[derive(Debug, Copy, Clone)]
+struct SkinnedDraw {
+    model_key: CacheKey,
+    mesh: usize,
+    slot: usize,
+}
+
+/// Skins and clips per model, and this frame's skinned draws.
+#[derive(Debug, Default)]
+pub struct AnimationManager {
+    skins: HashMap<CacheKey, Skin>,
+    clips: HashMap<CacheKey, Arc<AnimationClip>>,
+    slots: Vec<JointSlot>,
+    draws: Vec<SkinnedDraw>,
+    /// Skinned pipeline per material key; `None` when its shader has no variant.
+    pipelines: HashMap<CacheKey, Option<Arc<wgpu::RenderPipeline>>>,
+}
+
+impl AnimationManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn insert_skin(&mut self, model_key: CacheKey, skin: Skin) {
+        self.skins.insert(model_key, skin);
+    }
+    pub fn remove_skin(&mut self, model_key: &CacheKey) -> Option<Skin> {
+        self.skins.remove(model_key)
+    }
+    pub fn insert_clip(&mut self, key: CacheKey, clip: AnimationClip) {
+        self.clips.insert(key, Arc::new(clip));
+    }
+    pub fn skin(&self, model_key: &CacheKey) -> Option<&Skin> {
+        self.skins.get(model_key)
+    }
+    pub fn has_skin(&self, model_key: &CacheKey) -> bool {
+        self.skins.contains_key(model_key)
+    }
+    pub fn clip(&self, key: &CacheKey) -> Option<&Arc<AnimationClip>> {
+        self.clips.get(key)
+    }
+    pub fn clip_names(&self) -> impl Iterator<Item = &str> {
+        self.clips.values().map(|clip| clip.name.as_str())
+    }
+
+    /// Starts a frame of skinned draws.
+    pub fn clear(&mut self) {
+        self.draws.clear();
+    }
+    /// Drops the skinned pipelines so they're rebuilt on next use, e.g. after a depth
+    /// policy switch.
+    pub fn clear_pipelines(&mut self) {
+        self.pipelines.clear();
+    }
+    /// Skinned pipeline for `material`, built the first time it's asked for.
+    pub fn pipeline(
+        &mut self,
+        device: &wgpu::Device,
+        materials: &mut MaterialManager,
+        material: &Material,
+    ) -> Option<Arc<wgpu::RenderPipeline>> {
+        self.pipelines
+            .entry(material.asset.key)
+            .or_insert_with(|| {
+                material
+                    .asset
+                    .skinned_pipeline(device, &mut materials.shaders, &mut materials.pipelines)
+                    .unwrap_or_else(|e| {
+                        crate::log_warning!("{}: {}", material.asset.name, e);
+                        None
+                    })
+            })
+            .clone()
+    }
+
+    /// Whether `model_key` has a skin with a mesh the skinned path can draw, building the
+    /// pipelines it needs.
+    pub fn can_draw(
+        &mut self,
+        device: &wgpu::Device,
+        materials: &mut MaterialManager,
+        models: &HashCache<Arc<Model>>,
+        model_key: &CacheKey,
+    ) -> bool {
+        let Some(skin) = self.skins.get(model_key) else {
+            return false;
+        };
+        let parts: Vec<CacheKey> = skin.meshes.iter().map(|mesh| mesh.model_key).collect();
+        parts.iter().any(|part_key| {
+            models
+                .get(part_key)
+                .and_then(|part| part.instance.material.clone())
+                .and_then(|material| self.pipeline(device, materials, &material))
+                .is_some()
+        })
+    }
+    /// Samples the instance's animator on the skin of its model and queues its meshes.
+    /// Returns `false` when the model has no skin or none of its materials a skinned
+    /// pipeline, leaving the entity to the static paths. Takes [`ModelManager`]'s fields
+    /// apart since the manager itself lives there.
+    pub fn push(
+        &mut self,
+        device: &wgpu::Device,
+        materials: &mut MaterialManager,
+        models: &HashCache<Arc<Model>>,
+        instance: &SkinnedInstance,
+    ) -> bool {
+        let SkinnedInstance {
+            model_key,
+            model,
+            tint,
+            ref animator,
+        } = *instance;
+        let Some(skin) = self.skins.get(&model_key) else {
+            return false;
+        };
+        let skeleton = skin.skeleton.clone();
+        let locals = match self.clips.get(&animator.clip) {
+            Some(clip) => clip.sample(&skeleton, animator.time, animator.looping),
+            None => skeleton.rest_pose(),
+        };
+        let mut joints: Vec<Mat4> = skeleton
+            .skin_matrices(&locals)
+            .into_iter()
+            .map(|joint| model * joint)
+            .collect();
+        if joints.is_empty() {
+            joints.push(model);
+        }
+
+        let meshes: Vec<(usize, CacheKey)> = skin
+            .meshes
+            .iter()
+            .enumerate()
+            .map(|(i, mesh)| (i, mesh.model_key))
+            .collect();
+        let mut pushed = false;
+        for (mesh, part_key) in meshes {
+            let Some(material) = models
+                .get(&part_key)
+                .and_then(|part| part.instance.material.clone())
+            else {
+                continue;
+            };
+            if self.pipeline(device, materials, &material).is_none() {
+                continue;
+            }
+            let header = SkinHeader {
+                tint,
+                material_idx: material.idx,
+                joint_count: joints.len() as u32,
+                _pad: [0; 2],
+            };
+            let slot = self.draws.len();
+            if slot == self.slots.len() {
+                self.slots.push(JointSlot {
+                    buffer: WgpuBuffer::new_empty(
+                        device,
+                        wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+                        Some("joint matrix buffer"),
+                    ),
+                    bind_group: None,
+                    bytes: Vec::new(),
+                });
+            }
+            let bytes = &mut self.slots[slot].bytes;
+            bytes.clear();
+            bytes.extend_from_slice(bytemuck::bytes_of(&header));
+            for joint in &joints {
+                bytes.extend_from_slice(bytemuck::bytes_of(&joint.to_cols_array()));
+            }
+            self.draws.push(SkinnedDraw {
+                model_key,
+                mesh,
+                slot,
+            });
+            pushed = true;
+        }
+        pushed
+    }
+
+    /// Writes this frame's joint matrices and rebinds them next to the material storage,
+    /// which either buffer growing would otherwise leave stale.
+    pub fn upload(&mut self, queue: &wgpu::Queue, device: &wgpu::Device, materials: &WgpuBuffer) {
+        for draw in &self.draws {
+            let slot = &mut self.slots[draw.slot];
+            slot.buffer.write_data(queue, device, &slot.bytes, Some(0));
+            slot.bind_group = Some(BindGroup::joints(
+                device,
+                materials,
+                &slot.buffer,
+                "skinned",
+            ));
+        }
+    }
+
+    /// Replays the skinned draws and puts the material storage back on group 2 for
+    /// whatever is drawn next.
+    pub fn draw(
+        &self,
+        rpass: &mut wgpu::RenderPass,
+        models: &ModelManager,
+        uniform_bind_group: &wgpu::BindGroup,
+    ) {
+        if self.draws.is_empty() {
+            return;
+        }
+        rpass.set_bind_group(0, uniform_bind_group, &[]);
+        for draw in &self.draws {
+            let Some(skin) = self.skins.get(&draw.model_key) else {
+                continue;
+            };
+            let Some(skinned) = skin.meshes.get(draw.mesh) else {
+                continue;
+            };
+            let Some(model) = models.get(&skinned.model_key) else {
+                continue;
+            };
+            let Some(mat) = &model.instance.material else {
+                continue;
+            };
+            let Some(Some(pipeline)) = self.pipelines.get(&mat.asset.key) else {
+                continue;
+            };
+            let Some(bind_group) = self.slots[draw.slot].bind_group.as_ref() else {
+                continue;
+            };
+            let mesh = &model.instance.mesh;
+            crate::gpu_scope!(
+                Draw,
+                model.name,
+                format!("material {}, skinned", mat.asset.name)
+            );
+
+            rpass.set_pipeline(pipeline);
+            rpass.set_bind_group(2, bind_group, &[]);
+            rpass.set_bind_group(3, mat.bind_group.as_ref(), &[]);
+            rpass.set_vertex_buffer(0, skinned.vertex_buffer.get().slice(..));
+            rpass.set_index_buffer(mesh.index_buffer.get().slice(..), mesh.index_format);
+            rpass.draw_indexed(0..mesh.index_count, 0, 0..1);
+        }
+        rpass.set_bind_group(2, &models.materials.storage_bind_group, &[]);
+    }
+}
@@ -0,0 +1,178 @@
+//! Sprites for [`crate::Billboard`] entities: a texture on a quad turned to face the camera,
+//! drawn instanced in the scene pass with one draw per texture.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use bytemuck::{Pod, Zeroable};
+
+use crate::{
+    log_warning, BindGroup, CacheKey, CacheStorage, EngineError, ModelManager,
+    RenderBindGroupLayouts, Texture,
+};
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+pub struct BillboardInstance {
+    pub center: [f32; 3],
+    pub _pad: f32,
+    /// World-space width and height.
+    pub size: [f32; 2],
+    pub _pad2: [f32; 2],
+    pub tint: [f32; 4],
+}
+
+impl BillboardInstance {
+    pub const LAYOUT: wgpu::VertexBufferLayout<'static> = wgpu::VertexBufferLayout {
+        array_stride: std::mem::size_of::<BillboardInstance>() as wgpu::BufferAddress,
+        step_mode: wgpu::VertexStepMode::Instance,
+        attributes: &wgpu::vertex_attr_array![
+            0 => Float32x3,
+            1 => Float32x2,
+            2 => Float32x4,
+        ],
+    };
+
+    pub fn new(center: [f32; 3], size: [f32; 2], tint: [f32; 4]) -> Self {
+        Self {
+            center,
+            _pad: 0.0,
+            size,
+            _pad2: [0.0; 2],
+            tint,
+        }
+    }
+}
+
+/// This frame's billboard instances, batched per texture, and a bind group for each
+/// texture drawn so far.
+#[derive(Debug)]
+pub struct BillboardBuffers {
+    format: wgpu::TextureFormat,
+    pipeline: Option<Arc<wgpu::RenderPipeline>>,
+    batch: HashMap<CacheKey, Vec<BillboardInstance>>,
+    buffers: crate::InstanceBufferPool,
+    /// Rebuilt when the texture under the key is replaced.
+    bind_groups: HashMap<CacheKey, (Arc<Texture>, wgpu::BindGroup)>,
+}
+
+impl BillboardBuffers {
+    /// `format` is the color target the billboards are drawn into.
+    pub fn new(format: wgpu::TextureFormat) -> Self {
+        Self {
+            format,
+            pipeline: None,
+            batch: HashMap::new(),
+            buffers: crate::InstanceBufferPool::new("billboard instance buffer"),
+            bind_groups: HashMap::new(),
+        }
+    }
+    pub fn clear(&mut self) {
+        self.batch.clear();
+    }
+    /// Drops the pipeline so it's rebuilt on next use, e.g. after a depth policy switch.
+    pub fn clear_pipeline(&mut self) {
+        self.pipeline = None;
+    }
+    pub fn push(&mut self, texture: CacheKey, instance: BillboardInstance) {
+        self.batch.entry(texture).or_default().push(instance);
+    }
+
+    /// Builds the pipeline and bind groups if needed and writes this frame's instances.
+    /// Textures that aren't loaded are skipped.
+    pub fn upload(&mut self, model_manager: &mut ModelManager) {
+        self.buffers.begin_frame();
+        if self.batch.is_empty() {
+            return;
+        }
+        if self.pipeline.is_none() {
+            self.pipeline = self
+                .create_pipeline(model_manager)
+                .map_err(|e| log_warning!("billboard pipeline: {}", e))
+                .ok();
+        }
+        let device = &model_manager.device;
+        let textures = &model_manager.materials.textures;
+        for (key, instances) in &self.batch {
+            let Some(texture) = textures.get(key) else {
+                crate::log_warning_once!("Billboard texture {} isn't loaded", key.id());
+                continue;
+            };
+            let current = self
+                .bind_groups
+                .get(key)
+                .is_some_and(|(bound, _)| Arc::ptr_eq(bound, texture));
+            if !current {
+                let bind_group = BindGroup::texture(device, texture);
+                self.bind_groups.insert(*key, (texture.clone(), bind_group));
+            }
+            self.buffers
+                .write(device, &model_manager.queue, *key, instances);
+        }
+    }
+    fn create_pipeline(
+        &self,
+        model_manager: &mut ModelManager,
+    ) -> Result<Arc<wgpu::RenderPipeline>, EngineError> {
+        let device = &model_manager.device;
+        let shader = model_manager
+            .materials
+            .shaders
+            .load(device, "billboard.wgsl")?;
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("billboard"),
+            bind_group_layouts: &[
+                RenderBindGroupLayouts::uniform(),
+                RenderBindGroupLayouts::texture(),
+            ],
+            push_constant_ranges: &[],
+        });
+        Ok(Arc::new(device.create_render_pipeline(
+            &wgpu::RenderPipelineDescriptor {
+                label: Some("billboard"),
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: Some("vs_main"),
+                    buffers: &[BillboardInstance::LAYOUT],
+                    compilation_options: Default::default(),
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: Some("fs_main"),
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: self.format,
+                        blend: None,
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                    compilation_options: Default::default(),
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: Some(crate::RenderSettings::depth_state(
+                    crate::DepthVariant::Opaque,
+                )),
+                multisample: crate::RenderSettings::multisample(),
+                multiview: None,
+                cache: None,
+            },
+        )))
+    }
+
+    /// One instanced draw per texture.
+    pub fn draw(&self, rpass: &mut wgpu::RenderPass, uniform_bind_group: &wgpu::BindGroup) {
+        let Some(pipeline) = &self.pipeline else {
+            return;
+        };
+        rpass.set_pipeline(pipeline);
+        rpass.set_bind_group(0, uniform_bind_group, &[]);
+        for (key, data) in self.buffers.iter() {
+            let Some((_, bind_group)) = self.bind_groups.get(key) else {
+                continue;
+            };
+            crate::gpu_scope!(Draw, "billboard", format!("texture key {}", key.id()));
+            rpass.set_bind_group(1, bind_group, &[]);
+            rpass.set_vertex_buffer(0, data.buffer.slice(..));
+            rpass.draw(0..6, 0..data.count);
+        }
+    }
+}
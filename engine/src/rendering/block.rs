@@ -0,0 +1,140 @@
+use std::{collections::HashMap, sync::RwLock};
+
+use once_cell::sync::Lazy;
+
+use super::Block;
+
+pub const AIR: Block = 0;
+pub const STONE: Block = 1;
+pub const LAVA: Block = 2;
+pub const CRYSTAL: Block = 3;
+pub const GRASS: Block = 4;
+pub const SAND: Block = 5;
+
+/// Highest block light / emission level.
+pub const MAX_LIGHT: u8 = 15;
+
+/// Face color multipliers selected by [`BlockMeta::tint`].
+pub const TINT_PALETTE: [[f32; 3]; 8] = [
+    [1.0, 1.0, 1.0],
+    [1.0, 0.55, 0.2],
+    [0.45, 0.8, 1.0],
+    [0.55, 1.0, 0.55],
+    [1.0, 0.45, 0.45],
+    [0.8, 0.55, 1.0],
+    [1.0, 0.95, 0.5],
+    [0.6, 0.6, 0.6],
+];
+
+/// Per-block data that doesn't need its own block id: light emission and a tint.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct BlockMeta {
+    /// Light level the block emits, `0..=MAX_LIGHT`.
+    pub emission: u8,
+    /// Index into [`TINT_PALETTE`].
+    pub tint: u8,
+}
+
+impl BlockMeta {
+    pub fn new(emission: u8, tint: u8) -> Self {
+        Self {
+            emission: emission.min(MAX_LIGHT),
+            tint: tint.min(TINT_PALETTE.len() as u8 - 1),
+        }
+    }
+    pub fn tint_color(&self) -> [f32; 3] {
+        TINT_PALETTE[self.tint as usize % TINT_PALETTE.len()]
+    }
+    /// Emission in `0..=1`, as the shaders see it.
+    pub fn emission_factor(&self) -> f32 {
+        self.emission as f32 / MAX_LIGHT as f32
+    }
+}
+
+static BLOCK_DEFAULTS: Lazy<RwLock<HashMap<Block, BlockMeta>>> = Lazy::new(|| {
+    RwLock::new(HashMap::from([
+        (LAVA, BlockMeta::new(MAX_LIGHT, 1)),
+        (CRYSTAL, BlockMeta::new(10, 2)),
+    ]))
+});
+
+/// Terrain material a block type draws with: its layer in the terrain texture array and
+/// how strongly it wins where materials meet.
+#[derive(Debug, Copy, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct TerrainLayer {
+    pub layer: u8,
+    /// Added to the blend weight before comparing, so e.g. rock shows through grass at
+    /// equal coverage.
+    pub priority: f32,
+}
+
+impl TerrainLayer {
+    pub fn new(layer: u8, priority: f32) -> Self {
+        Self {
+            layer,
+            priority: priority.clamp(0.0, 1.0),
+        }
+    }
+}
+
+static TERRAIN_LAYERS: Lazy<RwLock<HashMap<Block, TerrainLayer>>> = Lazy::new(|| {
+    RwLock::new(HashMap::from([
+        (STONE, TerrainLayer::new(0, 0.6)),
+        (LAVA, TerrainLayer::new(1, 0.2)),
+        (CRYSTAL, TerrainLayer::new(2, 0.9)),
+    ]))
+});
+
+/// Default metadata per block type. Chunks only store metadata that differs from it.
+pub struct BlockRegistry;
+
+impl BlockRegistry {
+    pub fn defaults(block: Block) -> BlockMeta {
+        BLOCK_DEFAULTS
+            .read()
+            .ok()
+            .and_then(|defaults| defaults.get(&block).copied())
+            .unwrap_or_default()
+    }
+    /// Sets the defaults for `block`. Existing chunks pick them up the next time they're meshed.
+    pub fn register(block: Block, meta: BlockMeta) {
+        if let Ok(mut defaults) = BLOCK_DEFAULTS.write() {
+            defaults.insert(block, meta);
+        }
+    }
+    /// Terrain layer of `block`; unregistered solid blocks use layer 0.
+    pub fn terrain_layer(block: Block) -> TerrainLayer {
+        TERRAIN_LAYERS
+            .read()
+            .ok()
+            .and_then(|layers| layers.get(&block).copied())
+            .unwrap_or(TerrainLayer::new(0, 0.0))
+    }
+    pub fn register_terrain_layer(block: Block, layer: TerrainLayer) {
+        if let Ok(mut layers) = TERRAIN_LAYERS.write() {
+            layers.insert(block, layer);
+        }
+    }
+    /// Priority of each layer up to [`BlockRegistry::terrain_layer_count`], the highest of
+    /// the blocks sharing it.
+    pub fn terrain_layer_priorities() -> Vec<f32> {
+        let Ok(layers) = TERRAIN_LAYERS.read() else {
+            return vec![0.0];
+        };
+        let count = layers.values().map(|l| l.layer as usize + 1).max();
+        let mut priorities = vec![0.0f32; count.unwrap_or(1)];
+        for layer in layers.values() {
+            let slot = &mut priorities[layer.layer as usize];
+            *slot = (*slot).max(layer.priority);
+        }
+        priorities
+    }
+    /// Highest layer index any block uses, plus one.
+    pub fn terrain_layer_count() -> u32 {
+        TERRAIN_LAYERS
+            .read()
+            .ok()
+            .and_then(|layers| layers.values().map(|l| l.layer as u32 + 1).max())
+            .unwrap_or(1)
+    }
+}
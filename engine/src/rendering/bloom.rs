@@ -0,0 +1,276 @@
+//! Bloom post-process. A bright pass keeps what's above the threshold at half size, each
+//! further level halves it again, every level gets a separable gaussian blur, and the
+//! composite adds the levels over the input. All steps are in `bloom.wgsl`.
+
+use bytemuck::{Pod, Zeroable};
+
+use crate::{
+    BindGroup, EngineError, FrameBuffer, FrameBufferSize, PostProcessPass, RenderBindGroupLayouts,
+    RenderTargetKind, RenderTargetManager, Texture, WgpuBuffer,
+};
+
+/// How [`BloomPass`] picks and spreads the glow.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct BloomSettings {
+    pub enabled: bool,
+    /// Luminance above which pixels glow.
+    pub threshold: f32,
+    /// Width of the fade-in below `threshold`.
+    pub knee: f32,
+    /// Strength of each blurred level added back over the image.
+    pub intensity: f32,
+}
+
+impl Default for BloomSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            threshold: 0.8,
+            knee: 0.2,
+            intensity: 0.6,
+        }
+    }
+}
+
+/// Parameters uniform of `bloom.wgsl`.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Zeroable, Pod)]
+pub struct BloomUniform {
+    pub threshold: f32,
+    pub knee: f32,
+    pub intensity: f32,
+    _pad: f32,
+}
+
+impl From<&BloomSettings> for BloomUniform {
+    fn from(settings: &BloomSettings) -> Self {
+        Self {
+            threshold: settings.threshold,
+            knee: settings.knee.max(0.0),
+            intensity: settings.intensity.max(0.0),
+            _pad: 0.0,
+        }
+    }
+}
+
+pub struct BloomPass {
+    pub settings: BloomSettings,
+    params: WgpuBuffer,
+    params_bind_group: wgpu::BindGroup,
+    bright: wgpu::RenderPipeline,
+    down: wgpu::RenderPipeline,
+    blur_h: wgpu::RenderPipeline,
+    blur_v: wgpu::RenderPipeline,
+    copy: wgpu::RenderPipeline,
+    add: wgpu::RenderPipeline,
+    format: wgpu::TextureFormat,
+}
+
+impl BloomPass {
+    pub const SHADER: &'static str = "bloom.wgsl";
+    /// Blurred level and the target its horizontal blur goes through, from half the window
+    /// size down, each half the one before.
+    pub const LEVELS: [(RenderTargetKind, RenderTargetKind); 3] = [
+        (
+            RenderTargetKind::Bloom,
+            RenderTargetKind::Custom("bloom blur 0"),
+        ),
+        (
+            RenderTargetKind::Custom("bloom 1"),
+            RenderTargetKind::Custom("bloom blur 1"),
+        ),
+        (
+            RenderTargetKind::Custom("bloom 2"),
+            RenderTargetKind::Custom("bloom blur 2"),
+        ),
+    ];
+
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Result<Self, EngineError> {
+        let settings = BloomSettings::default();
+        let params = WgpuBuffer::from_data(
+            device,
+            &[BloomUniform::from(&settings)],
+            wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            Some("bloom params"),
+        );
+        let params_bind_group = BindGroup::bloom(device, &params, "bloom");
+
+        let module = crate::Shader::load(Self::SHADER)?;
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("bloom pipeline layout"),
+            bind_group_layouts: &[
+                RenderBindGroupLayouts::texture(),
+                RenderBindGroupLayouts::bloom(),
+            ],
+            push_constant_ranges: &[],
+        });
+        let pipeline = |entry: &str, blend: Option<wgpu::BlendState>| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(&format!("bloom {}", entry)),
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &module,
+                    entry_point: Some("vs_main"),
+                    buffers: &[],
+                    compilation_options: Default::default(),
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &module,
+                    entry_point: Some(entry),
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format,
+                        blend,
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                    compilation_options: Default::default(),
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+                cache: None,
+            })
+        };
+        let additive = wgpu::BlendState {
+            color: wgpu::BlendComponent {
+                src_factor: wgpu::BlendFactor::One,
+                dst_factor: wgpu::BlendFactor::One,
+                operation: wgpu::BlendOperation::Add,
+            },
+            alpha: wgpu::BlendComponent::OVER,
+        };
+        Ok(Self {
+            settings,
+            params,
+            params_bind_group,
+            bright: pipeline("fs_bright", None),
+            down: pipeline("fs_down", None),
+            blur_h: pipeline("fs_blur_h", None),
+            blur_v: pipeline("fs_blur_v", None),
+            copy: pipeline("fs_copy", None),
+            add: pipeline("fs_add", Some(additive)),
+            format,
+        })
+    }
+
+    /// One fullscreen draw of `source` into `target` with `pipeline`.
+    fn draw(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        pipeline: &wgpu::RenderPipeline,
+        source: &Texture,
+        target: &FrameBuffer,
+        label: &str,
+    ) {
+        let bind_group = BindGroup::hdr(device, source, label);
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some(label),
+            color_attachments: &[Some(target.color_attachment())],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        pass.set_pipeline(pipeline);
+        pass.set_bind_group(0, &bind_group, &[]);
+        pass.set_bind_group(1, &self.params_bind_group, &[]);
+        pass.draw(0..3, 0..1);
+    }
+}
+
+impl PostProcessPass for BloomPass {
+    fn name(&self) -> &'static str {
+        "Bloom"
+    }
+    fn enabled(&self) -> bool {
+        self.settings.enabled
+    }
+    fn set_enabled(&mut self, enabled: bool) {
+        self.settings.enabled = enabled;
+    }
+    fn parameters(&mut self) -> Vec<(&'static str, &mut f32)> {
+        vec![
+            ("threshold", &mut self.settings.threshold),
+            ("knee", &mut self.settings.knee),
+            ("intensity", &mut self.settings.intensity),
+        ]
+    }
+    fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        targets: &mut RenderTargetManager,
+        size: FrameBufferSize,
+    ) {
+        for (level, (target, blur)) in Self::LEVELS.iter().enumerate() {
+            let divisor = 2 << level;
+            for kind in [target, blur] {
+                if !targets.contains(kind) {
+                    let fb = FrameBuffer::new_color_only(
+                        device,
+                        size.scaled_down(divisor),
+                        self.format,
+                        "bloom",
+                    );
+                    targets.insert_scaled(fb, *kind, divisor);
+                }
+            }
+        }
+        let params = BloomUniform::from(&self.settings);
+        self.params.write_data(queue, device, &[params], None);
+    }
+    fn record(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        targets: &RenderTargetManager,
+        input: &Texture,
+        output: &FrameBuffer,
+    ) {
+        let mut levels = Vec::with_capacity(Self::LEVELS.len());
+        let mut source = input;
+        for (level, (target, blur)) in Self::LEVELS.iter().enumerate() {
+            let (Some(target), Some(blur)) = (targets.get(target), targets.get(blur)) else {
+                break;
+            };
+            let step = if level == 0 { &self.bright } else { &self.down };
+            self.draw(device, encoder, step, source, target, "bloom downsample");
+            self.draw(
+                device,
+                encoder,
+                &self.blur_h,
+                target.color(),
+                blur,
+                "bloom blur",
+            );
+            self.draw(
+                device,
+                encoder,
+                &self.blur_v,
+                blur.color(),
+                target,
+                "bloom blur",
+            );
+            levels.push(target.color());
+            source = target.color();
+        }
+
+        let bind_groups: Vec<wgpu::BindGroup> = std::iter::once(input)
+            .chain(levels)
+            .map(|texture| BindGroup::hdr(device, texture, "bloom composite"))
+            .collect();
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("bloom composite"),
+            color_attachments: &[Some(output.color_attachment())],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        pass.set_bind_group(1, &self.params_bind_group, &[]);
+        for (i, bind_group) in bind_groups.iter().enumerate() {
+            pass.set_pipeline(if i == 0 { &self.copy } else { &self.add });
+            pass.set_bind_group(0, bind_group, &[]);
+            pass.draw(0..3, 0..1);
+        }
+    }
+}
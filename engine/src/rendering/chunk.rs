@@ -1,15 +1,46 @@
-use crate::{MeshAsset, Vertex};
+use std::collections::{HashMap, VecDeque};
+
+use crate::{
+    BlockMeta, BlockRegistry, MeshAsset, TerrainBlend, Vertex, AIR, MAX_LIGHT, STONE, WATER_FULL,
+};
 
 pub type Block = u8;
 #[derive(Debug)]
 pub struct Chunk {
     pub blocks: [[[Block; CHUNK_SIZE]; CHUNK_SIZE]; CHUNK_SIZE],
+    /// Metadata overrides keyed by [`Chunk::index`]; blocks without one use
+    /// [`BlockRegistry::defaults`].
+    pub meta: HashMap<usize, BlockMeta>,
+    /// Water fill levels of air blocks keyed by [`Chunk::index`], out of
+    /// [`crate::WATER_FULL`]; dry blocks have no entry.
+    pub water: HashMap<usize, u8>,
     pub mesh: Option<MeshAsset>,
+    pub water_mesh: Option<MeshAsset>,
     pub pos: (i32, i32, i32),
     pub dirty: bool,
+    /// Edited since it was generated or loaded, so [`crate::Terrain`] saves it to its
+    /// [`crate::ChunkStore`] when unloading it.
+    pub modified: bool,
 }
 pub const CHUNK_SIZE: usize = 4;
 
+/// Color of water surfaces.
+pub const WATER_COLOR: [f32; 3] = [0.2, 0.45, 0.8];
+/// Opacity of water surfaces.
+pub const WATER_ALPHA: f32 = 0.6;
+
+/// How much full block light brightens a face on top of its base color.
+pub const BLOCK_LIGHT_BOOST: f32 = 1.5;
+
+const NEIGHBORS: [(isize, isize, isize); 6] = [
+    (1, 0, 0),
+    (-1, 0, 0),
+    (0, 1, 0),
+    (0, -1, 0),
+    (0, 0, 1),
+    (0, 0, -1),
+];
+
 // (normal, tangent, [4 vertex positions])
 pub const CHUNK_FACES: [([f32; 3], [f32; 3], [[f32; 3]; 4], [[f32; 2]; 4]); 6] = [
     // +X
@@ -89,16 +120,20 @@ impl Chunk {
     pub fn new(pos: (i32, i32, i32)) -> Self {
         Self {
             blocks: [[[1; CHUNK_SIZE]; CHUNK_SIZE]; CHUNK_SIZE],
+            meta: HashMap::new(),
+            water: HashMap::new(),
             pos,
             mesh: None,
+            water_mesh: None,
             dirty: true,
+            modified: false,
         }
     }
     pub fn flat(pos: (i32, i32, i32)) -> Chunk {
         let mut chunk = Chunk::new(pos);
         for x in 0..CHUNK_SIZE {
             for z in 0..CHUNK_SIZE {
-                chunk.set_block(x, 0, z, 1);
+                chunk.set_block(x, 0, z, STONE);
                 for y in 1..CHUNK_SIZE {
                     chunk.set_block(x, y, z, 0);
                 }
@@ -124,12 +159,152 @@ impl Chunk {
         }
         chunk
     }
+    pub fn index(x: usize, y: usize, z: usize) -> usize {
+        (x * CHUNK_SIZE + y) * CHUNK_SIZE + z
+    }
+    /// Inverse of [`Chunk::index`].
+    pub fn coords(index: usize) -> (usize, usize, usize) {
+        (
+            index / (CHUNK_SIZE * CHUNK_SIZE),
+            index / CHUNK_SIZE % CHUNK_SIZE,
+            index % CHUNK_SIZE,
+        )
+    }
+    /// Sets a block, dropping any metadata override so it takes the registry defaults. A
+    /// solid block displaces the water in its cell; the water is gone, not pushed aside.
+    /// Center of the chunk in the local frame.
+    pub fn center(&self) -> glam::Vec3 {
+        (glam::Vec3::new(self.pos.0 as f32, self.pos.1 as f32, self.pos.2 as f32) + 0.5)
+            * CHUNK_SIZE as f32
+    }
+    /// Bounds of the chunk in the local frame.
+    pub fn aabb(&self) -> crate::AABB {
+        let min = glam::Vec3::new(self.pos.0 as f32, self.pos.1 as f32, self.pos.2 as f32)
+            * CHUNK_SIZE as f32;
+        crate::AABB {
+            min,
+            max: min + CHUNK_SIZE as f32,
+        }
+    }
+    /// Block data and the chunk mesh are chunk-relative and stay as they are.
+    pub fn rebase(&mut self, rebase: &crate::Rebase) {
+        self.pos = rebase.chunk(self.pos);
+        let offset = rebase.offset().to_array();
+        for mesh in self.water_mesh.iter_mut() {
+            for vertex in &mut mesh.vertices {
+                for (p, o) in vertex.position.iter_mut().zip(offset) {
+                    *p -= o;
+                }
+            }
+        }
+    }
+
     pub fn set_block(&mut self, x: usize, y: usize, z: usize, block: Block) {
         if x < CHUNK_SIZE && y < CHUNK_SIZE && z < CHUNK_SIZE {
             self.blocks[x][y][z] = block;
+            self.meta.remove(&Self::index(x, y, z));
+            if block != AIR {
+                self.water.remove(&Self::index(x, y, z));
+            }
+            self.dirty = true;
+        }
+    }
+    pub fn get_water(&self, x: usize, y: usize, z: usize) -> u8 {
+        if x >= CHUNK_SIZE || y >= CHUNK_SIZE || z >= CHUNK_SIZE {
+            return 0;
+        }
+        self.water.get(&Self::index(x, y, z)).copied().unwrap_or(0)
+    }
+    /// Sets the water level of an air block, capped at [`WATER_FULL`]. Solid blocks stay dry.
+    pub fn set_water(&mut self, x: usize, y: usize, z: usize, level: u8) {
+        if x >= CHUNK_SIZE || y >= CHUNK_SIZE || z >= CHUNK_SIZE || self.blocks[x][y][z] != AIR {
+            return;
+        }
+        let idx = Self::index(x, y, z);
+        if level == 0 {
+            self.water.remove(&idx);
+        } else {
+            self.water.insert(idx, level.min(WATER_FULL));
+        }
+        self.dirty = true;
+    }
+    /// Fills every air block below local height `height` with water, as much of the chunk
+    /// as lies under a water surface there.
+    pub fn flood_below(&mut self, height: i64) {
+        let top = height.clamp(0, CHUNK_SIZE as i64) as usize;
+        for x in 0..CHUNK_SIZE {
+            for y in 0..top {
+                for z in 0..CHUNK_SIZE {
+                    self.set_water(x, y, z, WATER_FULL);
+                }
+            }
+        }
+    }
+    /// Sum of every fill level in the chunk.
+    pub fn water_volume(&self) -> u64 {
+        self.water.values().map(|&level| level as u64).sum()
+    }
+    /// Overrides the metadata of one block. Matching the registry defaults clears the override.
+    pub fn set_block_meta(&mut self, x: usize, y: usize, z: usize, meta: BlockMeta) {
+        if x < CHUNK_SIZE && y < CHUNK_SIZE && z < CHUNK_SIZE {
+            let idx = Self::index(x, y, z);
+            if meta == BlockRegistry::defaults(self.blocks[x][y][z]) {
+                self.meta.remove(&idx);
+            } else {
+                self.meta.insert(idx, meta);
+            }
             self.dirty = true;
         }
     }
+    pub fn get_block_meta(&self, x: usize, y: usize, z: usize) -> BlockMeta {
+        if x >= CHUNK_SIZE || y >= CHUNK_SIZE || z >= CHUNK_SIZE {
+            return BlockMeta::default();
+        }
+        self.meta
+            .get(&Self::index(x, y, z))
+            .copied()
+            .unwrap_or_else(|| BlockRegistry::defaults(self.blocks[x][y][z]))
+    }
+
+    /// Block light per cell, indexed like [`Chunk::index`]: emitters hold their emission and
+    /// light spreads through air, losing one level per step. Stays within the chunk.
+    pub fn block_light(&self) -> Vec<u8> {
+        let mut light = vec![0u8; CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE];
+        let mut queue = VecDeque::new();
+        for x in 0..CHUNK_SIZE {
+            for y in 0..CHUNK_SIZE {
+                for z in 0..CHUNK_SIZE {
+                    let emission = self.get_block_meta(x, y, z).emission;
+                    if self.blocks[x][y][z] != AIR && emission > 0 {
+                        light[Self::index(x, y, z)] = emission;
+                        queue.push_back((x, y, z));
+                    }
+                }
+            }
+        }
+        while let Some((x, y, z)) = queue.pop_front() {
+            let level = light[Self::index(x, y, z)];
+            if level <= 1 {
+                continue;
+            }
+            for (dx, dy, dz) in NEIGHBORS {
+                let (nx, ny, nz) = (
+                    x.wrapping_add_signed(dx),
+                    y.wrapping_add_signed(dy),
+                    z.wrapping_add_signed(dz),
+                );
+                if nx >= CHUNK_SIZE || ny >= CHUNK_SIZE || nz >= CHUNK_SIZE {
+                    continue;
+                }
+                let idx = Self::index(nx, ny, nz);
+                if self.blocks[nx][ny][nz] == AIR && light[idx] < level - 1 {
+                    light[idx] = level - 1;
+                    queue.push_back((nx, ny, nz));
+                }
+            }
+        }
+        light
+    }
 
     pub fn get_block(&self, x: isize, y: isize, z: isize) -> Block {
         if x >= 0
@@ -144,9 +319,45 @@ impl Chunk {
             0
         }
     }
+    /// Terrain blend at the `corner` offset of block `(x, y, z)`, from the eight cells that
+    /// share that lattice point. Cells outside the chunk count as air.
+    pub fn corner_blend(&self, x: usize, y: usize, z: usize, corner: [f32; 3]) -> TerrainBlend {
+        let lattice = [
+            x as isize + corner[0] as isize,
+            y as isize + corner[1] as isize,
+            z as isize + corner[2] as isize,
+        ];
+        let cells = (0..8).map(|i| {
+            self.get_block(
+                lattice[0] - (i & 1),
+                lattice[1] - ((i >> 1) & 1),
+                lattice[2] - ((i >> 2) & 1),
+            )
+        });
+        TerrainBlend::from_neighborhood(cells)
+    }
+    /// Base color times tint, brightened by the block light reaching the face. Emitters keep
+    /// their own level so they don't depend on the air next to them.
+    fn face_color(block: Block, meta: BlockMeta, light: u8) -> [f32; 3] {
+        let base = match block {
+            1 => [0.5, 0.5, 0.5],  // stone
+            4 => [0.35, 0.6, 0.3], // grass
+            5 => [0.85, 0.8, 0.6], // sand
+            _ => [1.0, 1.0, 1.0],  // default
+        };
+        let tint = meta.tint_color();
+        let level = light.max(meta.emission) as f32 / MAX_LIGHT as f32;
+        let boost = 1.0 + level * (BLOCK_LIGHT_BOOST - 1.0);
+        [
+            base[0] * tint[0] * boost,
+            base[1] * tint[1] * boost,
+            base[2] * tint[2] * boost,
+        ]
+    }
     pub fn build_flat_chunk_mesh(&self) -> MeshAsset {
         let mut vertices = Vec::new();
         let mut indices = Vec::new();
+        let light = self.block_light();
 
         let (normal, tangent, corners, uvs) = &CHUNK_FACES[2];
 
@@ -162,12 +373,12 @@ impl Chunk {
                     y as f32 + self.pos.1 as f32 * CHUNK_SIZE as f32,
                     z as f32 + self.pos.2 as f32 * CHUNK_SIZE as f32,
                 ];
-                let color = match block {
-                    1 => [0.5, 0.5, 0.5],
-                    _ => [1.0, 1.0, 1.0],
-                };
+                let meta = self.get_block_meta(x, y, z);
+                let face_light = light[Self::index(x, (y + 1).min(CHUNK_SIZE - 1), z)];
+                let color = Self::face_color(block, meta, face_light);
                 let base = vertices.len() as u32;
                 for i in 0..4 {
+                    let blend = self.corner_blend(x, y, z, corners[i]);
                     vertices.push(Vertex {
                         position: [
                             world_pos[0] + corners[i][0],
@@ -178,6 +389,7 @@ impl Chunk {
                         tex_coords: uvs[i],
                         normal: *normal,
                         tangent: *tangent,
+                        surface: blend.pack(meta.emission),
                     });
                 }
                 indices.extend_from_slice(&[base, base + 1, base + 2, base, base + 2, base + 3]);
@@ -185,10 +397,14 @@ impl Chunk {
         }
         MeshAsset { vertices, indices }
     }
+    /// Block faces next to air, relative to the chunk's minimum corner; the instance
+    /// transform places them at [`Chunk::aabb`]. Chunks with the same blocks build the same
+    /// mesh wherever they are, so they can share its buffers.
     pub fn build_chunk_mesh(&self) -> MeshAsset {
         let mut vertices = Vec::new();
         let mut indices = Vec::new();
         let _index_offset = 0u32;
+        let light = self.block_light();
 
         for x in 0..CHUNK_SIZE {
             for y in 0..CHUNK_SIZE {
@@ -198,11 +414,7 @@ impl Chunk {
                         continue;
                     } // "air"
 
-                    let world_pos = [
-                        x as f32 + self.pos.0 as f32 * CHUNK_SIZE as f32,
-                        y as f32 + self.pos.1 as f32 * CHUNK_SIZE as f32,
-                        z as f32 + self.pos.2 as f32 * CHUNK_SIZE as f32,
-                    ];
+                    let local_pos = [x as f32, y as f32, z as f32];
 
                     for (face_idx, (normal, tangent, corners, uvs)) in
                         CHUNK_FACES.iter().enumerate()
@@ -217,14 +429,14 @@ impl Chunk {
                             _ => (0, 0, 0),
                         };
 
-                        let neighbor = {
+                        let (neighbor, neighbor_light) = {
                             let nx = x.wrapping_add_signed(nx);
                             let ny = y.wrapping_add_signed(ny);
                             let nz = z.wrapping_add_signed(nz);
                             if nx < CHUNK_SIZE && ny < CHUNK_SIZE && nz < CHUNK_SIZE {
-                                self.blocks[nx][ny][nz]
+                                (self.blocks[nx][ny][nz], light[Self::index(nx, ny, nz)])
                             } else {
-                                0 // "air"
+                                (0, 0) // "air"
                             }
                         };
 
@@ -232,23 +444,23 @@ impl Chunk {
                             continue;
                         }
 
-                        let color = match block {
-                            1 => [0.5, 0.5, 0.5], // stone
-                            _ => [1.0, 1.0, 1.0], // default
-                        };
+                        let meta = self.get_block_meta(x, y, z);
+                        let color = Self::face_color(block, meta, neighbor_light);
 
                         let base = vertices.len() as u32;
                         for i in 0..4 {
+                            let blend = self.corner_blend(x, y, z, corners[i]);
                             vertices.push(Vertex {
                                 position: [
-                                    world_pos[0] + corners[i][0],
-                                    world_pos[1] + corners[i][1],
-                                    world_pos[2] + corners[i][2],
+                                    local_pos[0] + corners[i][0],
+                                    local_pos[1] + corners[i][1],
+                                    local_pos[2] + corners[i][2],
                                 ],
                                 color,
                                 tex_coords: uvs[i],
                                 normal: *normal,
                                 tangent: *tangent,
+                                surface: blend.pack(meta.emission),
                             });
                         }
                         indices.extend_from_slice(&[
@@ -265,4 +477,146 @@ impl Chunk {
         }
         MeshAsset { vertices, indices }
     }
+    /// Top surface of every water column, at the fill height within its cell. Cells with
+    /// water above them inside the chunk are skipped; the water above draws the surface.
+    pub fn build_water_mesh(&self) -> MeshAsset {
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+        let (normal, tangent, corners, uvs) = &CHUNK_FACES[2];
+
+        for (&idx, &level) in &self.water {
+            let (x, y, z) = Self::coords(idx);
+            if self.get_water(x, y + 1, z) > 0 {
+                continue;
+            }
+            let height = level as f32 / WATER_FULL as f32;
+            let world_pos = [
+                x as f32 + self.pos.0 as f32 * CHUNK_SIZE as f32,
+                y as f32 + self.pos.1 as f32 * CHUNK_SIZE as f32,
+                z as f32 + self.pos.2 as f32 * CHUNK_SIZE as f32,
+            ];
+            let base = vertices.len() as u32;
+            for i in 0..4 {
+                vertices.push(Vertex {
+                    position: [
+                        world_pos[0] + corners[i][0],
+                        world_pos[1] + height,
+                        world_pos[2] + corners[i][2],
+                    ],
+                    color: WATER_COLOR,
+                    tex_coords: uvs[i],
+                    normal: *normal,
+                    tangent: *tangent,
+                    surface: TerrainBlend::single(0).pack(0),
+                });
+            }
+            indices.extend_from_slice(&[base, base + 1, base + 2, base, base + 2, base + 3]);
+        }
+        MeshAsset { vertices, indices }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CRYSTAL, LAVA};
+
+    fn air_chunk() -> Chunk {
+        let mut chunk = Chunk::new((0, 0, 0));
+        chunk.blocks = [[[AIR; CHUNK_SIZE]; CHUNK_SIZE]; CHUNK_SIZE];
+        chunk
+    }
+
+    #[test]
+    fn only_overrides_of_the_registry_defaults_are_stored() {
+        let mut chunk = Chunk::new((0, 0, 0));
+        assert_eq!(chunk.get_block_meta(0, 0, 0), BlockMeta::default());
+
+        chunk.set_block(1, 1, 1, LAVA);
+        assert_eq!(chunk.get_block_meta(1, 1, 1), BlockRegistry::defaults(LAVA));
+        assert!(chunk.meta.is_empty());
+
+        chunk.dirty = false;
+        let dim = BlockMeta::new(4, 3);
+        chunk.set_block_meta(1, 1, 1, dim);
+        assert!(chunk.dirty);
+        assert_eq!(chunk.get_block_meta(1, 1, 1), dim);
+        assert_eq!(chunk.meta.len(), 1);
+
+        // Back to the defaults, the override goes away.
+        chunk.set_block_meta(1, 1, 1, BlockRegistry::defaults(LAVA));
+        assert!(chunk.meta.is_empty());
+
+        // A new block takes its own defaults rather than the old override.
+        chunk.set_block_meta(1, 1, 1, dim);
+        chunk.set_block(1, 1, 1, CRYSTAL);
+        assert!(chunk.meta.is_empty());
+        assert_eq!(
+            chunk.get_block_meta(1, 1, 1),
+            BlockRegistry::defaults(CRYSTAL)
+        );
+
+        chunk.set_block_meta(CHUNK_SIZE, 0, 0, dim);
+        assert!(chunk.meta.is_empty());
+        assert_eq!(chunk.get_block_meta(CHUNK_SIZE, 0, 0), BlockMeta::default());
+    }
+
+    #[test]
+    fn block_light_loses_a_level_per_step_through_air() {
+        let mut chunk = air_chunk();
+        chunk.set_block(0, 0, 0, LAVA);
+        let light = chunk.block_light();
+        for (index, &level) in light.iter().enumerate() {
+            let (x, y, z) = Chunk::coords(index);
+            assert_eq!(level as usize, MAX_LIGHT as usize - (x + y + z));
+        }
+
+        // A dim emitter runs out of light within the chunk.
+        chunk.set_block_meta(0, 0, 0, BlockMeta::new(3, 0));
+        let light = chunk.block_light();
+        assert_eq!(light[Chunk::index(0, 0, 0)], 3);
+        assert_eq!(light[Chunk::index(1, 0, 0)], 2);
+        assert_eq!(light[Chunk::index(1, 1, 0)], 1);
+        assert_eq!(light[Chunk::index(1, 1, 1)], 0);
+        assert_eq!(light[Chunk::index(3, 0, 0)], 0);
+    }
+
+    #[test]
+    fn block_light_is_stopped_by_solid_blocks() {
+        let mut chunk = air_chunk();
+        for y in 0..CHUNK_SIZE {
+            for z in 0..CHUNK_SIZE {
+                chunk.set_block(1, y, z, STONE);
+            }
+        }
+        chunk.set_block(0, 0, 0, LAVA);
+        let light = chunk.block_light();
+        assert_eq!(light[Chunk::index(0, 3, 3)], MAX_LIGHT - 6);
+        assert_eq!(light[Chunk::index(1, 0, 0)], 0);
+        for (index, &level) in light.iter().enumerate() {
+            if Chunk::coords(index).0 > 1 {
+                assert_eq!(level, 0);
+            }
+        }
+    }
+
+    #[test]
+    fn only_faces_of_emitting_blocks_carry_emission() {
+        let mut chunk = air_chunk();
+        chunk.set_block(0, 0, 0, STONE);
+        chunk.set_block(2, 0, 0, LAVA);
+        let mesh = chunk.build_chunk_mesh();
+        // Every face of both blocks is exposed.
+        assert_eq!(mesh.vertices.len(), 2 * 6 * 4);
+
+        let (lava, stone): (Vec<&Vertex>, Vec<&Vertex>) =
+            mesh.vertices.iter().partition(|v| v.position[0] >= 2.0);
+        assert!(lava.iter().all(|v| v.emission() == MAX_LIGHT));
+        assert!(stone.iter().all(|v| v.emission() == 0));
+
+        // The stone face toward the lava is lit by it, the one away from it isn't.
+        let facing = stone.iter().find(|v| v.normal == [1.0, 0.0, 0.0]).unwrap();
+        let away = stone.iter().find(|v| v.normal == [-1.0, 0.0, 0.0]).unwrap();
+        assert!(facing.color[0] > away.color[0]);
+    }
 }
@@ -0,0 +1,343 @@
+//! Edited chunks, one file per chunk: a version byte, the chunk size, `(run, block)`
+//! pairs in [`Chunk::index`] order, then water levels and metadata overrides, each a
+//! little-endian `u16` count of `(index, ..)` entries.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use glam::I64Vec3;
+
+use crate::{log_warning, BlockMeta, EngineError, WATER_FULL};
+
+use super::{Chunk, CHUNK_SIZE};
+
+const CHUNK_VOLUME: usize = CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE;
+
+#[derive(Debug)]
+pub struct ChunkStore {
+    dir: PathBuf,
+    /// Saves not yet on disk; loads read these first.
+    in_flight: Mutex<HashMap<I64Vec3, Arc<Vec<u8>>>>,
+    /// Keeps saves of the same chunk in order.
+    writing: Mutex<()>,
+}
+
+impl ChunkStore {
+    pub const VERSION: u8 = 1;
+    pub const EXTENSION: &'static str = "chunk";
+
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            in_flight: Mutex::new(HashMap::new()),
+            writing: Mutex::new(()),
+        }
+    }
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+    pub fn path(&self, global: I64Vec3) -> PathBuf {
+        self.dir.join(format!(
+            "{}_{}_{}.{}",
+            global.x,
+            global.y,
+            global.z,
+            Self::EXTENSION
+        ))
+    }
+
+    /// Blocks on the file.
+    pub fn load(&self, global: I64Vec3) -> Result<Option<Chunk>, EngineError> {
+        let pending = self
+            .in_flight
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(&global)
+            .cloned();
+        if let Some(bytes) = pending {
+            return decode_chunk(&bytes).map(Some);
+        }
+        match std::fs::read(self.path(global)) {
+            Ok(bytes) => decode_chunk(&bytes).map(Some),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn save(&self, global: I64Vec3, chunk: &Chunk) -> Result<(), EngineError> {
+        let _writing = self.writing.lock().unwrap_or_else(|e| e.into_inner());
+        self.write(global, &encode_chunk(chunk))
+    }
+
+    pub fn save_in_background(self: &Arc<Self>, global: I64Vec3, chunk: &Chunk) {
+        let bytes = Arc::new(encode_chunk(chunk));
+        self.in_flight
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(global, bytes.clone());
+        let store = self.clone();
+        let job = move || {
+            let _writing = store.writing.lock().unwrap_or_else(|e| e.into_inner());
+            // A later save of the same chunk replaced this one; it writes instead.
+            if !store.is_latest(global, &bytes) {
+                return;
+            }
+            if let Err(e) = store.write(global, &bytes) {
+                log_warning!("Saving chunk {}: {}", global, e);
+            }
+            let mut in_flight = store.in_flight.lock().unwrap_or_else(|e| e.into_inner());
+            if in_flight
+                .get(&global)
+                .is_some_and(|latest| Arc::ptr_eq(latest, &bytes))
+            {
+                in_flight.remove(&global);
+            }
+        };
+        match tokio::runtime::Handle::try_current() {
+            Ok(handle) => {
+                handle.spawn_blocking(job);
+            }
+            Err(_) => {
+                std::thread::spawn(job);
+            }
+        }
+    }
+    fn is_latest(&self, global: I64Vec3, bytes: &Arc<Vec<u8>>) -> bool {
+        self.in_flight
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(&global)
+            .is_some_and(|latest| Arc::ptr_eq(latest, bytes))
+    }
+    // Write-then-rename, so an interrupted save leaves the old file whole.
+    fn write(&self, global: I64Vec3, bytes: &[u8]) -> Result<(), EngineError> {
+        std::fs::create_dir_all(&self.dir)?;
+        let path = self.path(global);
+        let partial = path.with_extension("partial");
+        std::fs::write(&partial, bytes)?;
+        std::fs::rename(&partial, &path)?;
+        Ok(())
+    }
+}
+
+pub fn encode_chunk(chunk: &Chunk) -> Vec<u8> {
+    let mut bytes = vec![ChunkStore::VERSION, CHUNK_SIZE as u8];
+    let mut run: Option<(u8, u8)> = None;
+    for index in 0..CHUNK_VOLUME {
+        let (x, y, z) = Chunk::coords(index);
+        let block = chunk.blocks[x][y][z];
+        run = match run {
+            Some((length, current)) if current == block && length < u8::MAX => {
+                Some((length + 1, current))
+            }
+            Some((length, current)) => {
+                bytes.extend([length, current]);
+                Some((1, block))
+            }
+            None => Some((1, block)),
+        };
+    }
+    if let Some((length, block)) = run {
+        bytes.extend([length, block]);
+    }
+
+    let mut water: Vec<_> = chunk.water.iter().collect();
+    water.sort_unstable();
+    bytes.extend((water.len() as u16).to_le_bytes());
+    for (&index, &level) in water {
+        bytes.extend((index as u16).to_le_bytes());
+        bytes.push(level);
+    }
+
+    let mut meta: Vec<_> = chunk.meta.iter().collect();
+    meta.sort_unstable_by_key(|(&index, _)| index);
+    bytes.extend((meta.len() as u16).to_le_bytes());
+    for (&index, meta) in meta {
+        bytes.extend((index as u16).to_le_bytes());
+        bytes.extend([meta.emission, meta.tint]);
+    }
+    bytes
+}
+
+pub fn decode_chunk(bytes: &[u8]) -> Result<Chunk, EngineError> {
+    let corrupt = |detail: &str| EngineError::AssetLoadError(format!("chunk file {}", detail));
+    let mut reader = ByteReader { bytes, at: 0 };
+    let version = reader.u8()?;
+    if version != ChunkStore::VERSION {
+        return Err(corrupt(&format!(
+            "has version {}, expected {}",
+            version,
+            ChunkStore::VERSION
+        )));
+    }
+    let size = reader.u8()? as usize;
+    if size != CHUNK_SIZE {
+        return Err(corrupt(&format!(
+            "holds {} wide chunks, expected {}",
+            size, CHUNK_SIZE
+        )));
+    }
+
+    let mut chunk = Chunk::new((0, 0, 0));
+    let mut index = 0;
+    while index < CHUNK_VOLUME {
+        let (length, block) = (reader.u8()? as usize, reader.u8()?);
+        if length == 0 || index + length > CHUNK_VOLUME {
+            return Err(corrupt("has a bad block run"));
+        }
+        for (x, y, z) in (index..index + length).map(Chunk::coords) {
+            chunk.blocks[x][y][z] = block;
+        }
+        index += length;
+    }
+
+    for _ in 0..reader.u16()? {
+        let (index, level) = (reader.index()?, reader.u8()?);
+        if level > 0 {
+            chunk.water.insert(index, level.min(WATER_FULL));
+        }
+    }
+    for _ in 0..reader.u16()? {
+        let (index, emission, tint) = (reader.index()?, reader.u8()?, reader.u8()?);
+        chunk.meta.insert(index, BlockMeta::new(emission, tint));
+    }
+    if reader.at != bytes.len() {
+        return Err(corrupt("has bytes past its end"));
+    }
+    Ok(chunk)
+}
+
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+    at: usize,
+}
+
+impl ByteReader<'_> {
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], EngineError> {
+        let taken = self
+            .bytes
+            .get(self.at..self.at + N)
+            .and_then(|slice| slice.try_into().ok())
+            .ok_or_else(|| EngineError::AssetLoadError("chunk file is cut short".to_string()))?;
+        self.at += N;
+        Ok(taken)
+    }
+    fn u8(&mut self) -> Result<u8, EngineError> {
+        self.take::<1>().map(|[byte]| byte)
+    }
+    fn u16(&mut self) -> Result<u16, EngineError> {
+        self.take().map(u16::from_le_bytes)
+    }
+    fn index(&mut self) -> Result<usize, EngineError> {
+        let index = self.u16()? as usize;
+        if index >= CHUNK_VOLUME {
+            return Err(EngineError::AssetLoadError(format!(
+                "chunk file has block index {} past {}",
+                index, CHUNK_VOLUME
+            )));
+        }
+        Ok(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BlockRegistry, AIR, LAVA};
+
+    /// A chunk of pseudo-random blocks, with water in some of its air and metadata on
+    /// some of its solid blocks.
+    fn random_chunk(mut seed: u32) -> Chunk {
+        let mut next = move || {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            seed
+        };
+        let mut chunk = Chunk::new((0, 0, 0));
+        for index in 0..CHUNK_VOLUME {
+            let (x, y, z) = Chunk::coords(index);
+            chunk.blocks[x][y][z] = (next() % 4) as u8;
+            if chunk.blocks[x][y][z] == AIR {
+                if next() % 2 == 0 {
+                    let level = (next() % WATER_FULL as u32) as u8 + 1;
+                    chunk.water.insert(index, level);
+                }
+            } else if next() % 3 == 0 {
+                let meta = BlockMeta::new((next() % 16) as u8, (next() % 8) as u8);
+                chunk.meta.insert(index, meta);
+            }
+        }
+        chunk
+    }
+
+    fn assert_same(a: &Chunk, b: &Chunk) {
+        assert_eq!(a.blocks, b.blocks);
+        assert_eq!(a.water, b.water);
+        assert_eq!(a.meta, b.meta);
+    }
+
+    #[test]
+    fn round_trips_blocks_water_and_meta() {
+        for seed in [1, 7, 0x9e37_79b9, 12345] {
+            let chunk = random_chunk(seed);
+            assert!(!chunk.water.is_empty() && !chunk.meta.is_empty());
+            let decoded = decode_chunk(&encode_chunk(&chunk)).unwrap();
+            assert_same(&chunk, &decoded);
+        }
+        let uniform = Chunk::new((3, -1, 2));
+        let bytes = encode_chunk(&uniform);
+        // Version, size, one block run and two empty counts.
+        assert_eq!(bytes.len(), 8);
+        assert_same(&uniform, &decode_chunk(&bytes).unwrap());
+    }
+
+    #[test]
+    fn registry_defaults_are_not_stored() {
+        let mut chunk = Chunk::new((0, 0, 0));
+        chunk.set_block(0, 0, 0, LAVA);
+        chunk.set_block(1, 0, 0, LAVA);
+        let defaults_only = encode_chunk(&chunk);
+
+        chunk.set_block_meta(1, 0, 0, BlockMeta::new(2, 5));
+        let bytes = encode_chunk(&chunk);
+        // One override: its index, emission and tint.
+        assert_eq!(bytes.len(), defaults_only.len() + 4);
+
+        let decoded = decode_chunk(&bytes).unwrap();
+        assert_same(&chunk, &decoded);
+        assert_eq!(
+            decoded.get_block_meta(0, 0, 0),
+            BlockRegistry::defaults(LAVA)
+        );
+        assert_eq!(decoded.get_block_meta(1, 0, 0), BlockMeta::new(2, 5));
+    }
+
+    #[test]
+    fn truncated_files_are_refused() {
+        let bytes = encode_chunk(&random_chunk(42));
+        for len in 0..bytes.len() {
+            assert!(decode_chunk(&bytes[..len]).is_err(), "{} bytes", len);
+        }
+    }
+
+    #[test]
+    fn other_versions_and_sizes_are_refused() {
+        let mut bytes = encode_chunk(&random_chunk(3));
+        bytes[0] = ChunkStore::VERSION + 1;
+        assert!(decode_chunk(&bytes).is_err());
+        bytes[0] = ChunkStore::VERSION;
+        bytes[1] = CHUNK_SIZE as u8 * 2;
+        assert!(decode_chunk(&bytes).is_err());
+    }
+
+    #[test]
+    fn trailing_bytes_are_refused() {
+        let mut bytes = encode_chunk(&random_chunk(5));
+        bytes.push(0);
+        assert!(decode_chunk(&bytes).is_err());
+    }
+}
@@ -1,13 +1,22 @@
-use super::{Light, ShaderManager, Vertex, VertexInstance};
-use crate::{camera::Camera, BindGroup, EngineError, RenderBindGroupLayouts, Texture, WgpuBuffer};
+use super::{Light, PipelineManager, ShaderManager, Vertex, VertexInstance};
+use crate::{
+    camera::Camera, log_info, log_warning, BindGroup, CacheKey, CacheStorage, DepthVariant,
+    EngineError, Mesh, RenderBindGroupLayouts, RenderSettings, WgpuBuffer,
+};
 use bytemuck::{Pod, Zeroable};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Weak},
+};
 use wgpu::{BufferUsages, RenderPipeline};
 
 #[repr(C)]
 #[derive(Copy, Clone, Zeroable, Pod)]
 pub struct DebugUniform {
     pub mode: u32,
-    _pad0: [f32; 3],
+    /// Length of the segments the normals view draws, in world units.
+    pub normal_length: f32,
+    _pad0: [f32; 2],
     pub zfar: f32,
     _pad1: [f32; 3],
     pub znear: f32,
@@ -16,15 +25,210 @@ pub struct DebugUniform {
 
 impl DebugUniform {
     pub fn next(&mut self) {
-        self.mode = match self.mode {
-            0 => 1,
-            1 => 2,
-            2 => 3,
-            3 => 4,
-            4 => 5,
-            5 => 6,
-            _ => 0,
+        self.mode = (self.mode + 1) % DebugMode::COUNT;
+    }
+}
+
+/// One end of a normals view segment, two per mesh vertex. Written by `debug_geometry.wgsl`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Zeroable, Pod)]
+pub struct NormalLineVertex {
+    pub position: [f32; 3], // @location(0)
+    pub normal: [f32; 3],   // @location(1)
+    /// 0 at the vertex, 1 at the tip.
+    pub tip: f32, // @location(2)
+    _pad: f32,
+}
+impl NormalLineVertex {
+    pub const LAYOUT: wgpu::VertexBufferLayout<'static> = wgpu::VertexBufferLayout {
+        array_stride: std::mem::size_of::<NormalLineVertex>() as wgpu::BufferAddress,
+        step_mode: wgpu::VertexStepMode::Vertex,
+        attributes: &[
+            wgpu::VertexAttribute {
+                offset: 0,
+                shader_location: 0,
+                format: wgpu::VertexFormat::Float32x3,
+            },
+            wgpu::VertexAttribute {
+                offset: 12,
+                shader_location: 1,
+                format: wgpu::VertexFormat::Float32x3,
+            },
+            wgpu::VertexAttribute {
+                offset: 24,
+                shader_location: 2,
+                format: wgpu::VertexFormat::Float32,
+            },
+        ],
+    };
+}
+
+/// Parameters uniform of `debug_geometry.wgsl`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Zeroable, Pod)]
+pub struct DebugGeometryParams {
+    pub vertex_count: u32,
+    pub index_count: u32,
+    /// 1 for `Uint32` indices, 0 for `Uint16`.
+    pub wide_indices: u32,
+    _pad: u32,
+}
+
+/// A vertex buffer derived from a mesh, kept while the mesh's vertex buffer lives.
+struct DerivedBuffer {
+    buffer: wgpu::Buffer,
+    count: u32,
+    source: Weak<WgpuBuffer>,
+}
+
+/// Vertex buffers the wireframe fallback and normals views draw instead of a mesh's own,
+/// built on the GPU from its vertex and index buffers the first time the view meets the
+/// mesh, and cached by [`Mesh::key`] so switching views doesn't rebuild them.
+struct DebugGeometry {
+    normals_pipeline: Arc<wgpu::ComputePipeline>,
+    triangles_pipeline: Arc<wgpu::ComputePipeline>,
+    /// [`NormalLineVertex`] pairs per mesh.
+    normals: HashMap<CacheKey, DerivedBuffer>,
+    /// Triangle lists per mesh, their vertex colors barycentric coordinates.
+    triangles: HashMap<CacheKey, DerivedBuffer>,
+}
+
+impl DebugGeometry {
+    const SHADER: &'static str = "debug_geometry.wgsl";
+    const WORKGROUP_SIZE: u32 = 64;
+
+    fn new(
+        device: &wgpu::Device,
+        shaders: &mut ShaderManager,
+        pipelines: &mut PipelineManager,
+    ) -> Result<Self, EngineError> {
+        let shader = shaders.load(device, Self::SHADER)?;
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("debug geometry pipeline layout"),
+            bind_group_layouts: &[RenderBindGroupLayouts::debug_geometry()],
+            push_constant_ranges: &[],
+        });
+        let mut pipeline = |entry: &str| {
+            let key = CacheKey::from(format!("debug_geometry_{}", entry));
+            pipelines.track(Self::SHADER, key);
+            pipelines
+                .compute
+                .get_or_create(key, || {
+                    Arc::new(
+                        device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                            label: Some(&format!("debug geometry {}", entry)),
+                            layout: Some(&layout),
+                            module: &shader,
+                            entry_point: Some(entry),
+                            compilation_options: Default::default(),
+                            cache: None,
+                        }),
+                    )
+                })
+                .clone()
+        };
+        Ok(Self {
+            normals_pipeline: pipeline("cs_normals"),
+            triangles_pipeline: pipeline("cs_triangles"),
+            normals: HashMap::new(),
+            triangles: HashMap::new(),
+        })
+    }
+
+    /// Records the derivation of `mesh`'s normals or triangles into `encoder`, unless cached.
+    fn build(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        mesh: &Mesh,
+        normals: bool,
+    ) {
+        let (cache, pipeline) = if normals {
+            (&mut self.normals, &self.normals_pipeline)
+        } else {
+            (&mut self.triangles, &self.triangles_pipeline)
         };
+        if cache.contains_key(&mesh.key) {
+            return;
+        }
+        // One thread per mesh vertex writing both ends of its segment, or per index.
+        let (threads, count, stride) = if normals {
+            let vertices = mesh.vertex_count();
+            let stride = std::mem::size_of::<NormalLineVertex>();
+            (vertices, vertices * 2, stride)
+        } else {
+            let stride = std::mem::size_of::<Vertex>();
+            (mesh.index_count, mesh.index_count, stride)
+        };
+        let size = count as u64 * stride as u64;
+        if size == 0 {
+            return;
+        }
+        let limits = device.limits();
+        let groups = threads.div_ceil(Self::WORKGROUP_SIZE);
+        if size > limits.max_storage_buffer_binding_size as u64
+            || groups > limits.max_compute_workgroups_per_dimension
+        {
+            log_warning!(
+                "Mesh {} too large for the debug view: {} bytes",
+                mesh.key.id(),
+                size
+            );
+            return;
+        }
+
+        let params = WgpuBuffer::from_data(
+            device,
+            &[DebugGeometryParams {
+                vertex_count: mesh.vertex_count(),
+                index_count: mesh.index_count,
+                wide_indices: (mesh.index_format == wgpu::IndexFormat::Uint32) as u32,
+                _pad: 0,
+            }],
+            BufferUsages::UNIFORM,
+            Some("debug geometry params"),
+        );
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(if normals {
+                "debug normals vertex buffer"
+            } else {
+                "debug triangles vertex buffer"
+            }),
+            size,
+            usage: BufferUsages::VERTEX | BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+        let bind_group = BindGroup::debug_geometry(
+            device,
+            &params,
+            &mesh.vertex_buffer,
+            &mesh.index_buffer,
+            &buffer,
+        );
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("debug geometry"),
+                timestamp_writes: None,
+            });
+            pass.set_pipeline(pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.dispatch_workgroups(groups, 1, 1);
+        }
+        cache.insert(
+            mesh.key,
+            DerivedBuffer {
+                buffer,
+                count,
+                source: Arc::downgrade(&mesh.vertex_buffer),
+            },
+        );
+    }
+
+    /// Drops the buffers of meshes that no longer exist, e.g. rebuilt terrain chunks.
+    fn retain_live(&mut self) {
+        for cache in [&mut self.normals, &mut self.triangles] {
+            cache.retain(|_, derived| derived.source.strong_count() > 0);
+        }
     }
 }
 
@@ -32,28 +236,36 @@ pub struct DebugMode {
     buffer: WgpuBuffer,
     uniform: DebugUniform,
     bind_group: wgpu::BindGroup,
-    pipeline: RenderPipeline,
+    pipeline: Arc<RenderPipeline>,
+    /// `PolygonMode::Line` variant of `pipeline`, on devices with `POLYGON_MODE_LINE`.
+    wireframe: Option<Arc<RenderPipeline>>,
+    /// Draws the barycentric triangle lists instead, without `POLYGON_MODE_LINE`.
+    wireframe_fallback: Arc<RenderPipeline>,
+    normals: Arc<RenderPipeline>,
+    geometry: DebugGeometry,
     mode: u32,
 }
 
 impl DebugMode {
+    /// Mesh edges as lines over the cleared scene.
+    pub const WIREFRAME: u32 = 7;
+    /// Shaded meshes with a segment along each vertex normal.
+    pub const NORMALS: u32 = 8;
+    /// The environment reflection term of each material alone.
+    pub const REFLECTIONS: u32 = 9;
+    /// Modes [`DebugMode::next_mode`] cycles through, 0 being off.
+    pub const COUNT: u32 = 10;
+    pub const DEFAULT_NORMAL_LENGTH: f32 = 0.1;
+
     pub fn new(
         device: &wgpu::Device,
         shaders: &mut ShaderManager,
+        pipelines: &mut PipelineManager,
         camera: &Camera,
         light: &Light,
         surface_configuration: &wgpu::SurfaceConfiguration,
     ) -> Result<Self, EngineError> {
-        let zfar = camera.zfar();
-        let znear = camera.znear();
-        let uniform = DebugUniform {
-            mode: 0,
-            _pad0: [0.0; 3],
-            zfar,
-            _pad1: [0.0; 3],
-            znear,
-            _pad2: [0.0; 3],
-        };
+        let uniform = Self::uniform_for(0, camera);
         let buffer = WgpuBuffer::from_data(
             device,
             bytemuck::bytes_of(&uniform),
@@ -62,7 +274,6 @@ impl DebugMode {
         );
         let bind_group = BindGroup::debug(device, camera.buffer(), light.buffer(), &buffer);
         let shader = shaders.load(device, "debug.wgsl")?;
-        let buffers = &[Vertex::LAYOUT, VertexInstance::LAYOUT];
         let bind_group_layouts = [
             RenderBindGroupLayouts::debug(),
             RenderBindGroupLayouts::equirect_dst(),
@@ -84,6 +295,10 @@ impl DebugMode {
             polygon_mode: wgpu::PolygonMode::Fill,
             conservative: false,
         };
+        let unculled = wgpu::PrimitiveState {
+            cull_mode: None,
+            ..primitive
+        };
 
         let color_target = wgpu::ColorTargetState {
             format: surface_configuration.format,
@@ -91,46 +306,92 @@ impl DebugMode {
             write_mask: wgpu::ColorWrites::all(),
         };
 
-        let depth_stencil = wgpu::DepthStencilState {
-            format: Texture::DEPTH_FORMAT,
-            depth_write_enabled: true,
-            depth_compare: wgpu::CompareFunction::LessEqual,
-            stencil: wgpu::StencilState::default(),
-            bias: wgpu::DepthBiasState::default(),
-        };
+        let depth_stencil = RenderSettings::depth_state(DepthVariant::Opaque);
+        let multisample = crate::RenderSettings::multisample();
 
-        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("debug_pipeline"),
-            layout: Some(&pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: Some("vs_main"),
-                buffers,
-                compilation_options: Default::default(),
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: Some("fs_main"),
-                targets: &[Some(color_target)],
-                compilation_options: Default::default(),
-            }),
-            primitive: primitive,
-            depth_stencil: Some(depth_stencil),
+        // Variants are cached by what they're built from, so a rebuild after a settings
+        // change only creates the ones that changed.
+        let mut pipeline = |variant: &str,
+                            vs_entry: &str,
+                            fs_entry: &str,
+                            buffers: &[wgpu::VertexBufferLayout],
+                            primitive: wgpu::PrimitiveState| {
+            let key = CacheKey::from(format!(
+                "debug_pipeline_{}_{:?}_{}_{:?}",
+                variant, color_target.format, multisample.count, depth_stencil.depth_compare
+            ));
+            pipelines.track("debug.wgsl", key);
+            pipelines
+                .render
+                .get_or_create(key, || {
+                    Arc::new(
+                        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                            label: Some(&format!("debug_pipeline_{}", variant)),
+                            layout: Some(&pipeline_layout),
+                            vertex: wgpu::VertexState {
+                                module: &shader,
+                                entry_point: Some(vs_entry),
+                                buffers,
+                                compilation_options: Default::default(),
+                            },
+                            fragment: Some(wgpu::FragmentState {
+                                module: &shader,
+                                entry_point: Some(fs_entry),
+                                targets: &[Some(color_target.clone())],
+                                compilation_options: Default::default(),
+                            }),
+                            primitive,
+                            depth_stencil: Some(depth_stencil.clone()),
 
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-            multiview: None,
-            cache: None,
+                            multisample,
+                            multiview: None,
+                            cache: None,
+                        }),
+                    )
+                })
+                .clone()
+        };
+        let mesh_buffers = &[Vertex::LAYOUT, VertexInstance::LAYOUT];
+        let pipeline_fill = pipeline("fill", "vs_main", "fs_main", mesh_buffers, primitive);
+        let line_mode = crate::GpuCapabilities::supported(wgpu::Features::POLYGON_MODE_LINE);
+        if !line_mode {
+            log_info!("Wireframe drawn with barycentric lines: no POLYGON_MODE_LINE");
+        }
+        let wireframe = line_mode.then(|| {
+            let lines = wgpu::PrimitiveState {
+                polygon_mode: wgpu::PolygonMode::Line,
+                ..unculled
+            };
+            pipeline("wireframe", "vs_main", "fs_main", mesh_buffers, lines)
         });
+        let wireframe_fallback = pipeline(
+            "wireframe_fallback",
+            "vs_main",
+            "fs_wireframe",
+            mesh_buffers,
+            unculled,
+        );
+        let normals = pipeline(
+            "normals",
+            "vs_normals",
+            "fs_normals",
+            &[NormalLineVertex::LAYOUT, VertexInstance::LAYOUT],
+            wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::LineList,
+                ..unculled
+            },
+        );
+        let geometry = DebugGeometry::new(device, shaders, pipelines)?;
 
         Ok(Self {
             buffer,
             uniform,
             bind_group,
-            pipeline,
+            pipeline: pipeline_fill,
+            wireframe,
+            wireframe_fallback,
+            normals,
+            geometry,
             mode: 0,
         })
     }
@@ -151,33 +412,85 @@ impl DebugMode {
         self.mode
     }
     pub fn next_mode(&mut self, device: &wgpu::Device, camera: &Camera, light: &Light) {
-        let mut mode = if self.mode == 0 {
-            1
-        } else {
-            let current_mode = self.mode;
-            let next_mode = current_mode + 1;
-            next_mode
+        self.rebuild(device, (self.mode + 1) % Self::COUNT, camera, light);
+    }
+    /// Whether the current view draws buffers derived from each mesh, which
+    /// [`DebugMode::prepare`] has to build first.
+    fn derives_geometry(&self) -> bool {
+        self.mode == Self::NORMALS || (self.mode == Self::WIREFRAME && self.wireframe.is_none())
+    }
+    /// Builds the derived buffers the current view draws for `meshes`, those not cached
+    /// yet, and drops the ones of meshes gone since. Call before recording.
+    pub fn prepare<'a>(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        meshes: impl IntoIterator<Item = &'a Mesh>,
+    ) {
+        if !self.derives_geometry() {
+            return;
+        }
+        self.geometry.retain_live();
+        let normals = self.mode == Self::NORMALS;
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("debug geometry encoder"),
+        });
+        for mesh in meshes {
+            self.geometry.build(device, &mut encoder, mesh, normals);
+        }
+        queue.submit(Some(encoder.finish()));
+    }
+    /// Draws `instances` of `mesh` from `instance_buffer` in the current view. Groups 1 to
+    /// 3 are the caller's, as for the material pipeline.
+    pub fn draw(
+        &self,
+        rpass: &mut wgpu::RenderPass,
+        mesh: &Mesh,
+        instance_buffer: &wgpu::Buffer,
+        instances: std::ops::Range<u32>,
+    ) {
+        rpass.set_bind_group(0, &self.bind_group, &[]);
+        rpass.set_vertex_buffer(1, instance_buffer.slice(..));
+        if self.mode == Self::WIREFRAME && self.wireframe.is_none() {
+            if let Some(triangles) = self.geometry.triangles.get(&mesh.key) {
+                rpass.set_pipeline(&self.wireframe_fallback);
+                rpass.set_vertex_buffer(0, triangles.buffer.slice(..));
+                rpass.draw(0..triangles.count, instances);
+            }
+            return;
+        }
+        let pipeline = match &self.wireframe {
+            Some(wireframe) if self.mode == Self::WIREFRAME => wireframe,
+            _ => &self.pipeline,
         };
-
-        if mode > 7 {
-            mode = 0;
+        rpass.set_pipeline(pipeline);
+        rpass.set_vertex_buffer(0, mesh.vertex_buffer.get().slice(..));
+        rpass.set_index_buffer(mesh.index_buffer.get().slice(..), mesh.index_format);
+        rpass.draw_indexed(0..mesh.index_count, 0, instances.clone());
+        if self.mode == Self::NORMALS {
+            if let Some(lines) = self.geometry.normals.get(&mesh.key) {
+                rpass.set_pipeline(&self.normals);
+                rpass.set_vertex_buffer(0, lines.buffer.slice(..));
+                rpass.draw(0..lines.count, instances);
+            }
         }
-        self.rebuild(device, mode, camera, light);
     }
-    fn rebuild(&mut self, device: &wgpu::Device, mode: u32, camera: &Camera, light: &Light) {
-        let zfar = camera.zfar();
-        let znear = camera.znear();
-        let uniform = DebugUniform {
+    fn uniform_for(mode: u32, camera: &Camera) -> DebugUniform {
+        DebugUniform {
             mode,
-            _pad0: [0.0; 3],
-            zfar,
+            normal_length: Self::DEFAULT_NORMAL_LENGTH,
+            _pad0: [0.0; 2],
+            zfar: camera.zfar(),
             _pad1: [0.0; 3],
-            znear,
+            znear: camera.znear(),
             _pad2: [0.0; 3],
-        };
+        }
+    }
+    fn rebuild(&mut self, device: &wgpu::Device, mode: u32, camera: &Camera, light: &Light) {
+        let uniform = Self::uniform_for(mode, camera);
         let buffer = WgpuBuffer::from_data(
             device,
-            bytemuck::bytes_of(&self.uniform),
+            bytemuck::bytes_of(&uniform),
             BufferUsages::UNIFORM,
             Some("debug uniform buffer"),
         );
@@ -0,0 +1,205 @@
+//! Single source of truth for the depth convention. Pipelines ask the active
+//! [`DepthPolicy`] for a [`DepthVariant`] instead of writing `DepthStencilState` by hand.
+
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use glam::Mat4;
+
+/// Which way depth runs. Reverse-Z maps near to 1 and far to 0, which spreads float
+/// precision evenly over the view distance.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum DepthPolicy {
+    #[default]
+    Standard,
+    ReverseZ,
+}
+
+/// What a pipeline does with depth.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DepthVariant {
+    /// Tests and writes.
+    Opaque,
+    /// Tests against opaque geometry without writing.
+    Transparent,
+    /// Writes depth only; the main pass then tests with [`DepthVariant::AfterPrepass`].
+    Prepass,
+    /// Passes only where depth equals what the pre-pass wrote, without writing.
+    AfterPrepass,
+    /// Drawn at the far plane without writing, so it only fills untouched pixels.
+    Skybox,
+}
+
+impl DepthPolicy {
+    pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+    pub fn format(&self) -> wgpu::TextureFormat {
+        Self::FORMAT
+    }
+    /// Comparison that lets nearer fragments pass.
+    pub fn compare(&self) -> wgpu::CompareFunction {
+        match self {
+            DepthPolicy::Standard => wgpu::CompareFunction::LessEqual,
+            DepthPolicy::ReverseZ => wgpu::CompareFunction::GreaterEqual,
+        }
+    }
+    /// Depth of the far plane, which is also the clear value.
+    pub fn far_depth(&self) -> f32 {
+        match self {
+            DepthPolicy::Standard => 1.0,
+            DepthPolicy::ReverseZ => 0.0,
+        }
+    }
+    pub fn near_depth(&self) -> f32 {
+        1.0 - self.far_depth()
+    }
+    pub fn clear_value(&self) -> f32 {
+        self.far_depth()
+    }
+
+    pub fn state(&self, variant: DepthVariant) -> wgpu::DepthStencilState {
+        let depth_write_enabled = matches!(variant, DepthVariant::Opaque | DepthVariant::Prepass);
+        let depth_compare = match variant {
+            DepthVariant::AfterPrepass => wgpu::CompareFunction::Equal,
+            _ => self.compare(),
+        };
+        wgpu::DepthStencilState {
+            format: self.format(),
+            depth_write_enabled,
+            depth_compare,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }
+    }
+
+    /// Remaps a `[0, 1]` depth projection to this policy; identity for [`DepthPolicy::Standard`].
+    pub fn remap_projection(&self, proj: Mat4) -> Mat4 {
+        match self {
+            DepthPolicy::Standard => proj,
+            // z' = w - z, i.e. ndc depth 1 - d.
+            DepthPolicy::ReverseZ => {
+                Mat4::from_cols_array(&[
+                    1.0, 0.0, 0.0, 0.0, //
+                    0.0, 1.0, 0.0, 0.0, //
+                    0.0, 0.0, -1.0, 0.0, //
+                    0.0, 0.0, 1.0, 1.0,
+                ]) * proj
+            }
+        }
+    }
+}
+
+static DEPTH_POLICY: AtomicU8 = AtomicU8::new(0);
+
+/// Global render configuration read by pipeline creation sites.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct RenderSettings {
+    pub depth: DepthPolicy,
+}
+
+impl RenderSettings {
+    pub fn current() -> Self {
+        Self {
+            depth: Self::depth_policy(),
+        }
+    }
+    pub fn depth_policy() -> DepthPolicy {
+        match DEPTH_POLICY.load(Ordering::Relaxed) {
+            1 => DepthPolicy::ReverseZ,
+            _ => DepthPolicy::Standard,
+        }
+    }
+    /// Switches the depth convention. Pipelines built before the switch keep the old state
+    /// until rebuilt, e.g. with `ModelManager::apply_depth_policy`.
+    pub fn set_depth_policy(policy: DepthPolicy) {
+        let value = match policy {
+            DepthPolicy::Standard => 0,
+            DepthPolicy::ReverseZ => 1,
+        };
+        DEPTH_POLICY.store(value, Ordering::Relaxed);
+    }
+    /// Depth state for `variant` under the active policy.
+    pub fn depth_state(variant: DepthVariant) -> wgpu::DepthStencilState {
+        Self::depth_policy().state(variant)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{Vec3, Vec4};
+
+    use super::*;
+    use crate::camera::frustum::Frustum;
+
+    const NEAR: f32 = 0.1;
+    const FAR: f32 = 1000.0;
+
+    fn projection(policy: DepthPolicy) -> Mat4 {
+        policy.remap_projection(Mat4::perspective_lh(1.0, 1.5, NEAR, FAR))
+    }
+
+    fn ndc_depth(proj: Mat4, z: f32) -> f32 {
+        let clip = proj * Vec4::new(0.0, 0.0, z, 1.0);
+        clip.z / clip.w
+    }
+
+    #[test]
+    fn reverse_z_flips_depth() {
+        let standard = projection(DepthPolicy::Standard);
+        let reverse = projection(DepthPolicy::ReverseZ);
+        for policy in [DepthPolicy::Standard, DepthPolicy::ReverseZ] {
+            let proj = projection(policy);
+            assert!((ndc_depth(proj, NEAR) - policy.near_depth()).abs() < 1e-5);
+            assert!((ndc_depth(proj, FAR) - policy.far_depth()).abs() < 1e-5);
+        }
+        for z in [0.5, 3.0, 40.0, 700.0] {
+            let flipped = 1.0 - ndc_depth(standard, z);
+            assert!((ndc_depth(reverse, z) - flipped).abs() < 1e-5);
+        }
+        // Nearer passes the policy's comparison.
+        assert!(ndc_depth(reverse, 2.0) > ndc_depth(reverse, 3.0));
+        assert_eq!(
+            DepthPolicy::ReverseZ.compare(),
+            wgpu::CompareFunction::GreaterEqual
+        );
+        assert_eq!(DepthPolicy::ReverseZ.clear_value(), 0.0);
+    }
+
+    #[test]
+    fn reverse_z_keeps_the_frustum() {
+        let view = Mat4::look_at_lh(Vec3::ZERO, Vec3::Z, Vec3::Y);
+        let standard = Frustum::from_matrix_with(
+            projection(DepthPolicy::Standard) * view,
+            DepthPolicy::Standard,
+        );
+        let reverse = Frustum::from_matrix_with(
+            projection(DepthPolicy::ReverseZ) * view,
+            DepthPolicy::ReverseZ,
+        );
+        for z in [-1.0, 0.0, 1.0, 500.0, FAR - 1.0, FAR + 1.0] {
+            let point = Vec3::new(0.0, 0.0, z);
+            assert_eq!(
+                standard.contains_point(point),
+                reverse.contains_point(point),
+                "z = {}",
+                z
+            );
+        }
+        assert!(reverse.contains_point(Vec3::new(0.0, 0.0, 500.0)));
+        assert!(!reverse.contains_point(Vec3::new(0.0, 0.0, FAR + 1.0)));
+    }
+
+    #[test]
+    fn variants_write_and_compare() {
+        let policy = DepthPolicy::ReverseZ;
+        assert!(policy.state(DepthVariant::Opaque).depth_write_enabled);
+        assert!(policy.state(DepthVariant::Prepass).depth_write_enabled);
+        for variant in [DepthVariant::Transparent, DepthVariant::Skybox] {
+            let state = policy.state(variant);
+            assert!(!state.depth_write_enabled);
+            assert_eq!(state.depth_compare, wgpu::CompareFunction::GreaterEqual);
+        }
+        let after = policy.state(DepthVariant::AfterPrepass);
+        assert_eq!(after.depth_compare, wgpu::CompareFunction::Equal);
+        assert!(!after.depth_write_enabled);
+    }
+}
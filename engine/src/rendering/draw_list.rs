@@ -0,0 +1,284 @@
+//! Opaque instanced model draws, sorted by pipeline, then material, then mesh, so that
+//! recording only switches what differs from the draw before. Instances of draws sharing
+//! all three are laid out next to each other in one instance buffer and drawn as one range.
+//! The buffer is read as vertex attributes or, see [`InstanceFetch`], as storage.
+
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::Arc;
+
+use crate::{
+    BindGroup, CacheKey, DebugMode, DepthPrepass, InstanceFetch, Material, Mesh, ModelManager,
+    VertexInstance, WgpuBuffer,
+};
+
+/// Key of the object behind `arc`: two draws share it exactly when they bind the same one.
+fn identity<T>(arc: &Arc<T>) -> CacheKey {
+    CacheKey::new(Arc::as_ptr(arc) as *const () as usize as u64)
+}
+
+/// One draw call of a [`DrawList`].
+#[derive(Debug, Clone)]
+pub struct DrawCommand {
+    pub pipeline_key: CacheKey,
+    pub material_key: CacheKey,
+    pub mesh_key: CacheKey,
+    /// Instances in the list's instance buffer.
+    pub instances: Range<u32>,
+    pub mesh: Arc<Mesh>,
+    pub material: Arc<Material>,
+}
+
+impl DrawCommand {
+    fn order(&self) -> (u64, u64, u64) {
+        (
+            self.pipeline_key.id(),
+            self.material_key.id(),
+            self.mesh_key.id(),
+        )
+    }
+}
+
+/// What recording a frame's [`DrawList`] costs, for the dev overlay.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DrawStats {
+    pub draw_calls: u32,
+    pub pipeline_switches: u32,
+    /// `set_bind_group` calls, the uniforms once plus one per material change.
+    pub bind_group_switches: u32,
+}
+
+/// This frame's opaque instanced draws and their instances in one buffer.
+#[derive(Debug, Default)]
+pub struct DrawList {
+    commands: Vec<DrawCommand>,
+    instances: Vec<VertexInstance>,
+    buffer: Option<WgpuBuffer>,
+    stats: DrawStats,
+    fetch: InstanceFetch,
+    /// Group 2 of the storage path over `buffer`, rebuilt on every upload.
+    bind_group: Option<wgpu::BindGroup>,
+    /// Storage path pipeline per material key; `None` when its shader has no variant.
+    pipelines: HashMap<CacheKey, Option<Arc<wgpu::RenderPipeline>>>,
+}
+
+impl DrawList {
+    pub fn clear(&mut self) {
+        self.commands.clear();
+        self.instances.clear();
+        self.stats = DrawStats::default();
+    }
+    /// Replaces the list with `batches`, each instances drawn with one mesh and material,
+    /// sorted and merged.
+    pub fn build<'a>(
+        &mut self,
+        batches: impl IntoIterator<Item = (&'a Arc<Mesh>, &'a Arc<Material>, &'a [VertexInstance])>,
+    ) {
+        self.clear();
+        let mut batches: Vec<(DrawCommand, &[VertexInstance])> = batches
+            .into_iter()
+            .filter(|(_, _, instances)| !instances.is_empty())
+            .map(|(mesh, material, instances)| {
+                let command = DrawCommand {
+                    pipeline_key: identity(&material.pipeline),
+                    material_key: identity(&material.bind_group),
+                    mesh_key: mesh.key,
+                    instances: 0..0,
+                    mesh: mesh.clone(),
+                    material: material.clone(),
+                };
+                (command, instances)
+            })
+            .collect();
+        batches.sort_by_key(|(command, _)| command.order());
+
+        for (mut command, instances) in batches {
+            let start = self.instances.len() as u32;
+            self.instances.extend_from_slice(instances);
+            let end = self.instances.len() as u32;
+            match self.commands.last_mut() {
+                Some(last) if last.order() == command.order() => last.instances.end = end,
+                _ => {
+                    command.instances = start..end;
+                    self.commands.push(command);
+                }
+            }
+        }
+        self.stats = Self::count(&self.commands);
+    }
+    /// The state changes [`DrawList::record`] makes for `commands`.
+    fn count(commands: &[DrawCommand]) -> DrawStats {
+        let mut stats = DrawStats {
+            draw_calls: commands.len() as u32,
+            bind_group_switches: (!commands.is_empty()) as u32,
+            ..Default::default()
+        };
+        let mut previous: Option<&DrawCommand> = None;
+        for command in commands {
+            if previous.map_or(true, |p| p.pipeline_key != command.pipeline_key) {
+                stats.pipeline_switches += 1;
+            }
+            if previous.map_or(true, |p| p.material_key != command.material_key) {
+                stats.bind_group_switches += 1;
+            }
+            previous = Some(command);
+        }
+        stats
+    }
+    pub fn commands(&self) -> &[DrawCommand] {
+        &self.commands
+    }
+    pub fn stats(&self) -> DrawStats {
+        self.stats
+    }
+    /// Instances of every command, once uploaded.
+    pub fn buffer(&self) -> Option<&WgpuBuffer> {
+        self.buffer.as_ref()
+    }
+    pub fn fetch(&self) -> InstanceFetch {
+        self.fetch
+    }
+
+    /// Drops the storage path pipelines so they're rebuilt on next use, e.g. after a depth
+    /// policy switch.
+    pub fn clear_pipelines(&mut self) {
+        self.pipelines.clear();
+    }
+    /// Picks this frame's [`InstanceFetch`] and, for the storage path, builds the pipelines
+    /// of the listed materials that are missing.
+    pub fn prepare(&mut self, model_manager: &mut ModelManager) {
+        self.fetch = InstanceFetch::for_device(&model_manager.device);
+        if self.fetch == InstanceFetch::Attributes {
+            return;
+        }
+        for command in &self.commands {
+            let material = &command.material;
+            self.pipelines.entry(material.asset.key).or_insert_with(|| {
+                let materials = &mut model_manager.materials;
+                material
+                    .asset
+                    .instance_storage_pipeline(
+                        &model_manager.device,
+                        &mut materials.shaders,
+                        &mut materials.pipelines,
+                    )
+                    .unwrap_or_else(|e| {
+                        crate::log_warning!("{}: {}", material.asset.name, e);
+                        None
+                    })
+            });
+        }
+    }
+    /// Writes this frame's instances and, for the storage path, rebinds them next to the
+    /// material storage, which either buffer growing would otherwise leave stale.
+    pub fn upload(&mut self, queue: &wgpu::Queue, device: &wgpu::Device, materials: &WgpuBuffer) {
+        if self.instances.is_empty() {
+            return;
+        }
+        let bytes = VertexInstance::bytes(&self.instances);
+        match &mut self.buffer {
+            Some(buffer) => {
+                buffer.write_data(queue, device, &bytes, None);
+            }
+            None => {
+                self.buffer = Some(WgpuBuffer::from_data(
+                    device,
+                    &bytes,
+                    InstanceFetch::buffer_usage(&device.limits()),
+                    Some("instance buffer"),
+                ))
+            }
+        }
+        self.bind_group = self
+            .buffer
+            .as_ref()
+            .filter(|_| self.fetch == InstanceFetch::Storage)
+            .map(|buffer| BindGroup::instance_storage(device, materials, buffer));
+    }
+    /// The pipeline `material` draws with and whether it fetches its instances from
+    /// storage. Materials `prepass` covers keep the attribute path, whose pipeline tests
+    /// against the pre-pass depth.
+    fn pipeline<'a>(
+        &'a self,
+        material: &'a Material,
+        prepass: &'a DepthPrepass,
+    ) -> (&'a Arc<wgpu::RenderPipeline>, bool) {
+        if let Some(pipeline) = prepass.main_pipeline(material) {
+            return (pipeline, false);
+        }
+        let storage = self
+            .bind_group
+            .as_ref()
+            .and_then(|_| self.pipelines.get(&material.asset.key)?.as_ref());
+        match storage {
+            Some(pipeline) => (pipeline, true),
+            None => (&material.pipeline, false),
+        }
+    }
+
+    /// Records the draws, setting the pipeline, material bind group and mesh buffers only
+    /// when they change. Groups 1 and 2 are the caller's; draws on the storage path swap
+    /// `material_storage` in group 2 for the instances and put it back after. Materials
+    /// `prepass` covers draw with their pipeline testing against its depth.
+    pub fn record(
+        &self,
+        rpass: &mut wgpu::RenderPass,
+        uniform_bind_group: &wgpu::BindGroup,
+        material_storage: &wgpu::BindGroup,
+        debug: &DebugMode,
+        prepass: &DepthPrepass,
+    ) {
+        let Some(buffer) = self.buffer.as_ref().filter(|_| !self.commands.is_empty()) else {
+            return;
+        };
+        if debug.mode() > 0 {
+            for command in &self.commands {
+                rpass.set_bind_group(3, command.material.bind_group.as_ref(), &[]);
+                debug.draw(
+                    rpass,
+                    &command.mesh,
+                    buffer.get(),
+                    command.instances.clone(),
+                );
+            }
+            return;
+        }
+        rpass.set_bind_group(0, uniform_bind_group, &[]);
+        rpass.set_vertex_buffer(1, buffer.slice());
+        let mut previous: Option<&DrawCommand> = None;
+        let mut bound: Option<&Arc<wgpu::RenderPipeline>> = None;
+        let mut storage_bound = false;
+        for command in &self.commands {
+            crate::gpu_scope!(
+                Draw,
+                command.material.asset.name,
+                format!("mesh {}", command.mesh_key.id())
+            );
+            let (pipeline, storage) = self.pipeline(&command.material, prepass);
+            if !bound.is_some_and(|bound| Arc::ptr_eq(bound, pipeline)) {
+                rpass.set_pipeline(pipeline);
+                bound = Some(pipeline);
+            }
+            if storage != storage_bound {
+                match self.bind_group.as_ref().filter(|_| storage) {
+                    Some(instances) => rpass.set_bind_group(2, instances, &[]),
+                    None => rpass.set_bind_group(2, material_storage, &[]),
+                }
+                storage_bound = storage;
+            }
+            if previous.map_or(true, |p| p.material_key != command.material_key) {
+                rpass.set_bind_group(3, command.material.bind_group.as_ref(), &[]);
+            }
+            if previous.map_or(true, |p| p.mesh_key != command.mesh_key) {
+                let mesh = &command.mesh;
+                rpass.set_vertex_buffer(0, mesh.vertex_buffer.get().slice(..));
+                rpass.set_index_buffer(mesh.index_buffer.get().slice(..), mesh.index_format);
+            }
+            rpass.draw_indexed(0..command.mesh.index_count, 0, command.instances.clone());
+            previous = Some(command);
+        }
+        if storage_bound {
+            rpass.set_bind_group(2, material_storage, &[]);
+        }
+    }
+}
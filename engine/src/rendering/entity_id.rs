@@ -0,0 +1,332 @@
+//! Pixel-exact picking. On a frame with a pick pending, opaque instanced models and terrain
+//! are drawn again into the [`RenderTargetKind::EntityId`] target, each pixel holding the
+//! [`entity_id`] of what is in front, and the texel under the cursor is read back through
+//! the [`ReadbackService`]. The answer arrives a frame or more later, as
+//! [`ApplicationEvent::Picked`] and from [`EntityPicker::last`].
+//!
+//! The pass has its own single-sampled depth rather than sharing the scene pass: integer
+//! targets can't be resolved, and the text and sprite pipelines drawn in the scene pass
+//! have a single color target. It only runs when asked, so frames without a pick pay
+//! nothing. Terrain writes id 0, hiding what is behind it without being pickable.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::{
+    log_warning, ApplicationEvent, CacheKey, CacheStorage, DepthVariant, DrawList, EngineError,
+    Entity, EventBus, FrameBuffer, FrameBufferSize, Material, MaterialManager, Mesh,
+    ReadbackService, ReadbackTicket, RenderBindGroupLayouts, RenderSettings, RenderTargetKind,
+    RenderTargetManager, Terrain, Vertex, VertexInstance, WgpuBuffer,
+};
+
+pub const ENTITY_ID_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R32Uint;
+
+/// What the id pass writes for `entity`; 0 is left for pixels without one.
+pub fn entity_id(entity: Entity) -> u32 {
+    entity.0 as u32 + 1
+}
+/// The entity [`entity_id`] made `id` from, `None` for 0.
+pub fn entity_from_id(id: u32) -> Option<Entity> {
+    id.checked_sub(1).map(|index| Entity(index as usize))
+}
+
+/// The model matrix and entity id of [`VertexInstance`], all the id pass reads of it.
+const INSTANCE_LAYOUT: wgpu::VertexBufferLayout<'static> = wgpu::VertexBufferLayout {
+    array_stride: VertexInstance::SIZE,
+    step_mode: wgpu::VertexStepMode::Instance,
+    attributes: &[
+        wgpu::VertexAttribute {
+            offset: 0,
+            shader_location: 5,
+            format: wgpu::VertexFormat::Float32x4,
+        },
+        wgpu::VertexAttribute {
+            offset: 16,
+            shader_location: 6,
+            format: wgpu::VertexFormat::Float32x4,
+        },
+        wgpu::VertexAttribute {
+            offset: 32,
+            shader_location: 7,
+            format: wgpu::VertexFormat::Float32x4,
+        },
+        wgpu::VertexAttribute {
+            offset: 48,
+            shader_location: 8,
+            format: wgpu::VertexFormat::Float32x4,
+        },
+        // entity_id
+        wgpu::VertexAttribute {
+            offset: 152,
+            shader_location: 9,
+            format: wgpu::VertexFormat::Uint32,
+        },
+    ],
+};
+
+/// A pending pick, its readback and the id pass drawing it; see the module docs.
+#[derive(Debug, Default)]
+pub struct EntityPicker {
+    /// Texel of the next id pass to read, in render target pixels.
+    request: Option<(u32, u32)>,
+    ticket: Option<ReadbackTicket>,
+    last: Option<Entity>,
+    /// The frame's opaque instanced draws, with entity ids.
+    draws: DrawList,
+    /// Pipeline of each material by asset key; `None` when building it failed.
+    pipelines: HashMap<CacheKey, Option<Arc<wgpu::RenderPipeline>>>,
+}
+
+impl EntityPicker {
+    pub const SHADER: &'static str = "entity_id.wgsl";
+
+    /// Picks what is under render target pixel (`x`, `y`) on the next frame. Returns what
+    /// the last finished pick found; a pick still in flight when the next one is read back
+    /// is dropped.
+    pub fn pick_at(&mut self, x: u32, y: u32) -> Option<Entity> {
+        self.request = Some((x, y));
+        self.last
+    }
+    /// Whether the next frame draws the id pass.
+    pub fn pending(&self) -> bool {
+        self.request.is_some()
+    }
+    /// What the last finished pick found.
+    pub fn last(&self) -> Option<Entity> {
+        self.last
+    }
+    /// Drops the cached pipelines, after shaders or the depth policy changed.
+    pub fn clear_pipelines(&mut self) {
+        self.pipelines.clear();
+    }
+
+    /// Takes a finished readback, publishing [`ApplicationEvent::Picked`] when it hit an
+    /// entity. Call once per frame.
+    pub fn poll(&mut self) {
+        let Some(result) = self.ticket.as_ref().and_then(|ticket| ticket.poll()) else {
+            return;
+        };
+        self.ticket = None;
+        match result {
+            Ok(bytes) => {
+                let id = bytes
+                    .get(..4)
+                    .map_or(0, |id| u32::from_le_bytes([id[0], id[1], id[2], id[3]]));
+                self.last = entity_from_id(id);
+                if let Some(entity) = self.last {
+                    EventBus::publish(ApplicationEvent::Picked(entity));
+                }
+            }
+            Err(e) => log_warning!("Entity pick: {}", e),
+        }
+    }
+
+    /// Builds the id pass over `batches` and the pipelines it and the `terrain` materials
+    /// draw with, when a pick is pending.
+    pub fn prepare<'a>(
+        &mut self,
+        device: &wgpu::Device,
+        materials: &mut MaterialManager,
+        batches: impl IntoIterator<Item = (&'a Arc<Mesh>, &'a Arc<Material>, &'a [VertexInstance])>,
+        terrain: impl IntoIterator<Item = &'a Material>,
+    ) {
+        if !self.pending() {
+            return;
+        }
+        self.draws.build(batches);
+        let drawn: Vec<Arc<Material>> = self
+            .draws
+            .commands()
+            .iter()
+            .map(|c| c.material.clone())
+            .collect();
+        for material in &drawn {
+            self.ensure_pipeline(device, materials, material);
+        }
+        for material in terrain {
+            self.ensure_pipeline(device, materials, material);
+        }
+    }
+    fn ensure_pipeline(
+        &mut self,
+        device: &wgpu::Device,
+        materials: &mut MaterialManager,
+        material: &Material,
+    ) {
+        self.pipelines.entry(material.asset.key).or_insert_with(|| {
+            Self::pipeline(device, materials, material.asset.primitive)
+                .map_err(|e| log_warning!("{} entity id pass: {}", material.asset.name, e))
+                .ok()
+        });
+    }
+    /// Writes the id pass's instances when a pick is pending.
+    pub fn upload(&mut self, queue: &wgpu::Queue, device: &wgpu::Device, materials: &WgpuBuffer) {
+        if self.pending() {
+            self.draws.upload(queue, device, materials);
+        }
+    }
+    /// Adds the id target at `size` to `targets` when a pick is pending and it's missing or
+    /// has a stale depth format; [`RenderTargetManager::resize`] keeps it sized after that.
+    pub fn prepare_target(
+        &self,
+        device: &wgpu::Device,
+        targets: &mut RenderTargetManager,
+        size: FrameBufferSize,
+    ) {
+        let depth_format = RenderSettings::depth_policy().format();
+        let current = targets
+            .get(&RenderTargetKind::EntityId)
+            .and_then(|fb| fb.depth().as_ref())
+            .is_some_and(|depth| depth.texture.format() == depth_format);
+        if !self.pending() || current {
+            return;
+        }
+        targets.insert(
+            FrameBuffer::new_with_depth(
+                device,
+                size,
+                ENTITY_ID_FORMAT,
+                depth_format,
+                1,
+                "entity id buffer",
+            ),
+            RenderTargetKind::EntityId,
+        );
+    }
+
+    /// The pipeline for the standard vertex layout and `primitive`, so culling matches the
+    /// scene pass.
+    fn pipeline(
+        device: &wgpu::Device,
+        materials: &mut MaterialManager,
+        primitive: wgpu::PrimitiveState,
+    ) -> Result<Arc<wgpu::RenderPipeline>, EngineError> {
+        let shader = materials.shaders.load(device, Self::SHADER)?;
+        let label = format!(
+            "entity_id_{:?}_{:?}",
+            primitive,
+            RenderSettings::depth_policy()
+        );
+        let key = CacheKey::from(label.clone());
+        let pipeline = materials
+            .pipelines
+            .render
+            .get_or_create(key, || {
+                let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: Some("entity id"),
+                    bind_group_layouts: &[RenderBindGroupLayouts::uniform()],
+                    push_constant_ranges: &[],
+                });
+                Arc::new(
+                    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                        label: Some(&label),
+                        layout: Some(&layout),
+                        vertex: wgpu::VertexState {
+                            module: &shader,
+                            entry_point: Some("vs_main"),
+                            buffers: &[Vertex::LAYOUT, INSTANCE_LAYOUT],
+                            compilation_options: Default::default(),
+                        },
+                        fragment: Some(wgpu::FragmentState {
+                            module: &shader,
+                            entry_point: Some("fs_main"),
+                            targets: &[Some(wgpu::ColorTargetState {
+                                format: ENTITY_ID_FORMAT,
+                                blend: None,
+                                write_mask: wgpu::ColorWrites::ALL,
+                            })],
+                            compilation_options: Default::default(),
+                        }),
+                        primitive,
+                        depth_stencil: Some(RenderSettings::depth_state(DepthVariant::Opaque)),
+                        multisample: wgpu::MultisampleState::default(),
+                        multiview: None,
+                        cache: None,
+                    }),
+                )
+            })
+            .clone();
+        materials.pipelines.track(Self::SHADER, key);
+        Ok(pipeline)
+    }
+    fn pipeline_for(&self, material: &Material) -> Option<&Arc<wgpu::RenderPipeline>> {
+        self.pipelines.get(&material.asset.key)?.as_ref()
+    }
+
+    /// Draws the ids of this frame's opaque models over the terrain's depth, when a pick is
+    /// pending.
+    pub fn record(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        targets: &RenderTargetManager,
+        uniform_bind_group: &wgpu::BindGroup,
+        terrain: &Terrain,
+    ) -> Result<(), EngineError> {
+        if !self.pending() {
+            return Ok(());
+        }
+        let fb = targets.require(&RenderTargetKind::EntityId)?;
+        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Entity Id Pass"),
+            color_attachments: &[Some(fb.color_attachment())],
+            depth_stencil_attachment: fb.depth_attachment(),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        rpass.set_bind_group(0, uniform_bind_group, &[]);
+        if let Some(buffer) = self.draws.buffer() {
+            rpass.set_vertex_buffer(1, buffer.slice());
+            for command in self.draws.commands() {
+                let Some(pipeline) = self.pipeline_for(&command.material) else {
+                    continue;
+                };
+                let mesh = &command.mesh;
+                rpass.set_pipeline(pipeline);
+                rpass.set_vertex_buffer(0, mesh.vertex_buffer.get().slice(..));
+                rpass.set_index_buffer(mesh.index_buffer.get().slice(..), mesh.index_format);
+                rpass.draw_indexed(0..mesh.index_count, 0, command.instances.clone());
+            }
+        }
+        let Some(buffer) = terrain.instance_buffer() else {
+            return Ok(());
+        };
+        rpass.set_vertex_buffer(1, buffer.buffer.slice());
+        for (instance, instances) in terrain.visible_chunk_draws() {
+            let Some(pipeline) = instance
+                .material
+                .as_ref()
+                .and_then(|m| self.pipeline_for(m))
+            else {
+                continue;
+            };
+            let mesh = &instance.mesh;
+            rpass.set_pipeline(pipeline);
+            rpass.set_vertex_buffer(0, mesh.vertex_buffer.get().slice(..));
+            rpass.set_index_buffer(mesh.index_buffer.get().slice(..), mesh.index_format);
+            rpass.draw_indexed(0..mesh.index_count, 0, instances);
+        }
+        Ok(())
+    }
+    /// Queues the copy of the picked texel out of the id target, after the frame holding
+    /// the id pass was submitted. [`EntityPicker::poll`] takes the result.
+    pub fn read_back(&mut self, readback: &mut ReadbackService, targets: &RenderTargetManager) {
+        let Some((x, y)) = self.request.take() else {
+            return;
+        };
+        let Some(fb) = targets.get(&RenderTargetKind::EntityId) else {
+            return;
+        };
+        let size = fb.color().texture.size();
+        let origin = wgpu::Origin3d {
+            x: x.min(size.width - 1),
+            y: y.min(size.height - 1),
+            z: 0,
+        };
+        let texel = wgpu::Extent3d {
+            width: 1,
+            height: 1,
+            depth_or_array_layers: 1,
+        };
+        self.ticket = Some(readback.request_texture(fb.color(), 0, origin, texel));
+    }
+}
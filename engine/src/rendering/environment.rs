@@ -1,21 +1,271 @@
-use crate::RenderBindGroupLayouts;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{log_warning, CubemapOrientation, ReadbackService, RenderBindGroupLayouts};
+
+/// Where the environment cubemap comes from.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum EnvironmentSource {
+    /// Equirectangular HDR in `assets/hdr`, projected onto the cube by a compute pass unless
+    /// the import pipeline has already baked it, see [`crate::HdrImporter`].
+    Equirect(String),
+    /// Six face images under `assets/textures`, see [`crate::CubemapFaces::load_faces`].
+    CubemapFaces {
+        path: String,
+        #[serde(default)]
+        orientation: CubemapOrientation,
+    },
+    /// Six face images under `assets/textures` named one by one, in `px`, `nx`, `py`, `ny`,
+    /// `pz`, `nz` order, see [`crate::CubemapFaces::load_face_files`].
+    CubemapFiles {
+        faces: [String; 6],
+        #[serde(default)]
+        orientation: CubemapOrientation,
+    },
+    /// One cross image under `assets/textures`, see [`crate::CubemapFaces::load_cross`].
+    CubemapCross {
+        path: String,
+        #[serde(default)]
+        orientation: CubemapOrientation,
+    },
+}
+
+/// Compute pass projecting an equirect HDR onto the destination cubemap.
+#[derive(Debug)]
+pub struct EquirectProjection {
+    pub shader: wgpu::ShaderModule,
+    pub texture: crate::Texture,
+    pub pipeline: wgpu::ComputePipeline,
+    pub bind_group: wgpu::BindGroup,
+}
+
+/// Parameters of one `environment_prefilter.wgsl` dispatch.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct PrefilterParams {
+    /// Exponent of the lobe the mip is convolved with.
+    pub shininess: f32,
+    _pad: [f32; 3],
+}
+
+/// The environment convolved for reflections: a small cubemap whose mips are blurred with
+/// lobes of decreasing shininess, so materials pick the mip matching their own instead of
+/// aliasing on a sharp sample. The blurriest mip is also averaged into a single color, for
+/// an ambient term that follows the environment, see [`crate::AmbientSource`].
+#[derive(Debug)]
+pub struct EnvironmentReflection {
+    pub texture: crate::Texture,
+    /// Samples `texture` through the `equirect_dst` layout material pipelines bind at group 1.
+    pub bind_group: wgpu::BindGroup,
+    pipeline: wgpu::ComputePipeline,
+    /// One per mip, writing it from the source cubemap.
+    mips: Vec<wgpu::BindGroup>,
+    /// Set once recorded; the source doesn't change for the projection's lifetime.
+    prefiltered: AtomicBool,
+    average_pipeline: wgpu::ComputePipeline,
+    average_bind_group: wgpu::BindGroup,
+    /// The average color, written by the same dispatch as the prefilter.
+    average: crate::WgpuBuffer,
+    /// Set once the average is queued for readback.
+    average_requested: AtomicBool,
+    average_color: Arc<Mutex<Option<[f32; 3]>>>,
+}
+
+impl EnvironmentReflection {
+    pub const SHADER: &'static str = "environment_prefilter.wgsl";
+    pub const AVERAGE_SHADER: &'static str = "environment_ambient.wgsl";
+    pub const SIZE: u32 = 128;
+    pub const MIP_LEVELS: u32 = 6;
+    /// Shininess of mip 0. Shaders map shininess to a mip logarithmically up to this,
+    /// matching [`EnvironmentReflection::shininess`].
+    pub const MAX_SHININESS: f32 = 256.0;
+
+    /// Lobe exponent of `mip`, from [`EnvironmentReflection::MAX_SHININESS`] down to 1.
+    pub fn shininess(mip: u32) -> f32 {
+        let t = mip as f32 / (Self::MIP_LEVELS - 1) as f32;
+        Self::MAX_SHININESS.powf(1.0 - t)
+    }
+
+    pub fn new(device: &wgpu::Device, source: &crate::Texture) -> Result<Self, crate::EngineError> {
+        let texture = crate::Texture::new(
+            device,
+            wgpu::Extent3d {
+                width: Self::SIZE,
+                height: Self::SIZE,
+                depth_or_array_layers: WorldProjection::DEPTH_OR_ARRAY_LAYERS,
+            },
+            crate::Texture::HDR_FORMAT,
+            Self::MIP_LEVELS,
+            wgpu::TextureViewDimension::Cube,
+            wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING,
+            Some(wgpu::AddressMode::ClampToEdge),
+            wgpu::FilterMode::Nearest,
+            None,
+            Some(&format!("{} reflection", source.label)),
+        );
+        let bind_group = crate::BindGroup::equirect_dst(device, &texture);
+
+        let shader = crate::Shader::load(Self::SHADER)?;
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some(&format!("{} layout", Self::SHADER)),
+            bind_group_layouts: &[RenderBindGroupLayouts::environment_prefilter()],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some(Self::SHADER),
+            layout: Some(&layout),
+            module: &shader,
+            entry_point: Some("prefilter"),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+
+        // Sources may carry their own mips, the prefilter reads the top one.
+        let source_view = source.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("Prefilter source view"),
+            dimension: Some(wgpu::TextureViewDimension::Cube),
+            mip_level_count: Some(1),
+            ..Default::default()
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("prefilter source sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            ..Default::default()
+        });
+        let mips = (0..Self::MIP_LEVELS)
+            .map(|mip| {
+                let params = PrefilterParams {
+                    shininess: Self::shininess(mip),
+                    _pad: [0.0; 3],
+                };
+                let buffer = crate::WgpuBuffer::from_data(
+                    device,
+                    &[params],
+                    wgpu::BufferUsages::UNIFORM,
+                    Some("prefilter params"),
+                );
+                crate::BindGroup::environment_prefilter(
+                    device,
+                    &source_view,
+                    &sampler,
+                    &texture,
+                    mip,
+                    &buffer,
+                )
+            })
+            .collect();
+
+        let average_shader = crate::Shader::load(Self::AVERAGE_SHADER)?;
+        let average_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some(&format!("{} layout", Self::AVERAGE_SHADER)),
+            bind_group_layouts: &[RenderBindGroupLayouts::environment_ambient()],
+            push_constant_ranges: &[],
+        });
+        let average_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some(Self::AVERAGE_SHADER),
+            layout: Some(&average_layout),
+            module: &average_shader,
+            entry_point: Some("average_color"),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+        let average = crate::WgpuBuffer::from_data(
+            device,
+            &[[0.0f32; 4]],
+            wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            Some("environment average"),
+        );
+        let average_bind_group =
+            crate::BindGroup::environment_ambient(device, &texture, Self::MIP_LEVELS - 1, &average);
+
+        Ok(Self {
+            texture,
+            bind_group,
+            pipeline,
+            mips,
+            prefiltered: AtomicBool::new(false),
+            average_pipeline,
+            average_bind_group,
+            average,
+            average_requested: AtomicBool::new(false),
+            average_color: Arc::new(Mutex::new(None)),
+        })
+    }
+
+    /// Records the prefilter dispatches and the average of the blurriest mip the first time
+    /// it's called, after the source has been written; nothing afterwards.
+    pub fn record(&self, encoder: &mut wgpu::CommandEncoder) {
+        if self.prefiltered.swap(true, Ordering::Relaxed) {
+            return;
+        }
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("environment prefilter pass"),
+            timestamp_writes: None,
+        });
+        pass.set_pipeline(&self.pipeline);
+        for (mip, bind_group) in self.mips.iter().enumerate() {
+            let size = (Self::SIZE >> mip).max(1);
+            let groups = size.div_ceil(8);
+            pass.set_bind_group(0, bind_group, &[]);
+            pass.dispatch_workgroups(groups, groups, WorldProjection::DEPTH_OR_ARRAY_LAYERS);
+        }
+        pass.set_pipeline(&self.average_pipeline);
+        pass.set_bind_group(0, &self.average_bind_group, &[]);
+        pass.dispatch_workgroups(1, 1, 1);
+    }
+    /// Queues the readback of the average color once it has been recorded and submitted.
+    /// Call after each submit; only the first call after the prefilter does anything.
+    pub fn read_back_average(&self, readback: &mut ReadbackService) {
+        if !self.prefiltered.load(Ordering::Relaxed)
+            || self.average_requested.swap(true, Ordering::Relaxed)
+        {
+            return;
+        }
+        let ticket = readback.request_buffer(&self.average, 0..12);
+        let average_color = self.average_color.clone();
+        ticket.on_ready(move |result| match result {
+            Ok(bytes) => {
+                let color = bytes
+                    .get(..12)
+                    .map(bytemuck::pod_read_unaligned::<[f32; 3]>);
+                *average_color.lock().unwrap_or_else(PoisonError::into_inner) = color;
+            }
+            Err(e) => log_warning!("Environment average: {}", e),
+        });
+    }
+    /// The average color of the environment, once read back.
+    pub fn average_color(&self) -> Option<[f32; 3]> {
+        *self
+            .average_color
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
 
 #[derive(Debug)]
 pub struct WorldProjection {
-    pub src_shader: wgpu::ShaderModule,
+    pub source: EnvironmentSource,
+    /// `None` when the source already is a cubemap, or was baked offline, and there is
+    /// nothing to project.
+    pub equirect: Option<EquirectProjection>,
     pub dst_shader: wgpu::ShaderModule,
-    pub src_texture: crate::Texture,
-    pub dst_texture: crate::Texture,
-    pub src_pipeline: wgpu::ComputePipeline,
+    pub dst_texture: Arc<crate::Texture>,
     pub dst_pipeline: wgpu::RenderPipeline,
-    pub src_bind_group: wgpu::BindGroup,
     pub dst_bind_group: wgpu::BindGroup,
+    /// `dst_texture` prefiltered for material reflections.
+    pub reflection: EnvironmentReflection,
 }
 
 impl WorldProjection {
     pub const DEST_SIZE: u32 = 1080;
     pub const NUM_WORKGROUPS: u32 = (Self::DEST_SIZE + 15) / 16;
     pub const DEPTH_OR_ARRAY_LAYERS: u32 = 6;
+    pub const SRC_SHADER: &'static str = "equirect_src.wgsl";
+    pub const DST_SHADER: &'static str = "equirect_dst.wgsl";
     pub fn new(
         queue: &wgpu::Queue,
         device: &wgpu::Device,
@@ -25,7 +275,19 @@ impl WorldProjection {
         hdr_texture: &str,
         depth_stencil_state: Option<wgpu::DepthStencilState>,
     ) -> Result<Self, crate::EngineError> {
-        let path = &crate::Asset::resolve(&format!("hdr/{}", hdr_texture));
+        if let Some(faces) = crate::HdrImporter::load(hdr_texture) {
+            let texture = faces.upload(device, queue, &format!("{} baked cubemap", hdr_texture));
+            return Self::with_cubemap(
+                device,
+                config,
+                dst_shader,
+                EnvironmentSource::Equirect(hdr_texture.to_string()),
+                None,
+                Arc::new(texture),
+                depth_stencil_state,
+            );
+        }
+        let path = &crate::AssetPaths::hdr(hdr_texture);
         let bytes = crate::Asset::read_bytes(&path)?;
         let (pixels, meta) = crate::Texture::decode_hdr(&bytes)?;
 
@@ -79,7 +341,6 @@ impl WorldProjection {
             src_texture.texture.size(),
         );
 
-        let dst_bind_group = crate::BindGroup::equirect_dst(device, &dst_texture);
         let src_bind_group = crate::BindGroup::equirect_src(device, &src_texture, &dst_texture);
 
         let equirect_src_shader = crate::Shader::load(src_shader)?;
@@ -99,6 +360,106 @@ impl WorldProjection {
             compilation_options: Default::default(),
             cache: None,
         });
+        let equirect = EquirectProjection {
+            shader: equirect_src_shader,
+            texture: src_texture,
+            pipeline: src_pipeline,
+            bind_group: src_bind_group,
+        };
+        Self::with_cubemap(
+            device,
+            config,
+            dst_shader,
+            EnvironmentSource::Equirect(hdr_texture.to_string()),
+            Some(equirect),
+            Arc::new(dst_texture),
+            depth_stencil_state,
+        )
+    }
+
+    /// Environment from any [`EnvironmentSource`], with the default shaders. Cubemap
+    /// sources are loaded through `textures` and skip the compute projection entirely.
+    pub fn from_source(
+        queue: &wgpu::Queue,
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        textures: &mut crate::TextureManager,
+        source: &EnvironmentSource,
+        depth_stencil_state: Option<wgpu::DepthStencilState>,
+    ) -> Result<Self, crate::EngineError> {
+        let cubemap = match source {
+            EnvironmentSource::Equirect(hdr) => {
+                return Self::new(
+                    queue,
+                    device,
+                    config,
+                    Self::SRC_SHADER,
+                    Self::DST_SHADER,
+                    hdr,
+                    depth_stencil_state,
+                )
+            }
+            EnvironmentSource::CubemapFaces { path, orientation } => {
+                textures.load_cubemap_faces(queue, device, path, *orientation)?
+            }
+            EnvironmentSource::CubemapCross { path, orientation } => {
+                textures.load_cubemap_cross(queue, device, path, *orientation)?
+            }
+            EnvironmentSource::CubemapFiles { faces, orientation } => textures.load_cubemap_files(
+                queue,
+                device,
+                faces.each_ref().map(String::as_str),
+                *orientation,
+            )?,
+        };
+        Self::with_cubemap(
+            device,
+            config,
+            Self::DST_SHADER,
+            source.clone(),
+            None,
+            cubemap.0,
+            depth_stencil_state,
+        )
+    }
+
+    /// Skybox from six LDR or HDR face images under `assets/textures`, in `px`, `nx`, `py`,
+    /// `ny`, `pz`, `nz` order, sampled by the same sky pipeline as a projected HDR. Fails with
+    /// [`crate::EngineError::AssetLoadError`] when the faces differ in size or format.
+    pub fn from_faces(
+        queue: &wgpu::Queue,
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        textures: &mut crate::TextureManager,
+        paths: [&str; 6],
+        depth_stencil_state: Option<wgpu::DepthStencilState>,
+    ) -> Result<Self, crate::EngineError> {
+        let source = EnvironmentSource::CubemapFiles {
+            faces: paths.map(str::to_string),
+            orientation: CubemapOrientation::default(),
+        };
+        Self::from_source(
+            queue,
+            device,
+            config,
+            textures,
+            &source,
+            depth_stencil_state,
+        )
+    }
+
+    /// Sky pipeline sampling `dst_texture`.
+    fn with_cubemap(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        dst_shader: &str,
+        source: EnvironmentSource,
+        equirect: Option<EquirectProjection>,
+        dst_texture: Arc<crate::Texture>,
+        depth_stencil_state: Option<wgpu::DepthStencilState>,
+    ) -> Result<Self, crate::EngineError> {
+        let dst_bind_group = crate::BindGroup::equirect_dst(device, &dst_texture);
+        let reflection = EnvironmentReflection::new(device, &dst_texture)?;
         let equirect_dst_shader = crate::Shader::load(dst_shader)?;
 
         let equirect_dst_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
@@ -110,6 +471,9 @@ impl WorldProjection {
             push_constant_ranges: &[],
         });
 
+        let policy = crate::RenderSettings::depth_policy();
+        let far_depth =
+            std::collections::HashMap::from([("FAR_DEPTH".to_string(), policy.far_depth() as f64)]);
         let dst_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
             label: Some(dst_shader),
             layout: Some(&equirect_dst_layout),
@@ -117,7 +481,10 @@ impl WorldProjection {
                 module: &equirect_dst_shader,
                 entry_point: Some("vs_main"),
                 buffers: &[],
-                compilation_options: Default::default(),
+                compilation_options: wgpu::PipelineCompilationOptions {
+                    constants: &far_depth,
+                    ..Default::default()
+                },
             },
             fragment: Some(wgpu::FragmentState {
                 module: &equirect_dst_shader,
@@ -138,26 +505,23 @@ impl WorldProjection {
                 unclipped_depth: false,
                 conservative: false,
             },
-            depth_stencil: depth_stencil_state.as_ref().cloned(),
+            depth_stencil: depth_stencil_state
+                .as_ref()
+                .map(|_| policy.state(crate::DepthVariant::Skybox)),
 
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
+            multisample: crate::RenderSettings::multisample(),
             multiview: None,
             cache: None,
         });
 
         Ok(WorldProjection {
-            src_shader: equirect_src_shader,
+            source,
+            equirect,
             dst_shader: equirect_dst_shader,
-            src_texture,
             dst_texture,
             dst_pipeline,
-            src_pipeline,
-            src_bind_group,
             dst_bind_group,
+            reflection,
         })
     }
 
@@ -170,18 +534,24 @@ impl WorldProjection {
         let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
             label: Some("compute encoder"),
         });
-        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
-            label,
-            timestamp_writes: None,
-        });
-
-        pass.set_pipeline(&self.src_pipeline);
-        pass.set_bind_group(0, &self.src_bind_group, &[]);
-        pass.dispatch_workgroups(Self::NUM_WORKGROUPS, Self::NUM_WORKGROUPS, 6);
-
-        drop(pass);
+        self.record_projection(&mut encoder, label);
         queue.submit([encoder.finish()]);
     }
+    /// Records the equirect projection into `encoder`, when there is one, then the
+    /// reflection prefilter the first time.
+    pub fn record_projection(&self, encoder: &mut wgpu::CommandEncoder, label: Option<&str>) {
+        if let Some(equirect) = &self.equirect {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label,
+                timestamp_writes: None,
+            });
+
+            pass.set_pipeline(&equirect.pipeline);
+            pass.set_bind_group(0, &equirect.bind_group, &[]);
+            pass.dispatch_workgroups(Self::NUM_WORKGROUPS, Self::NUM_WORKGROUPS, 6);
+        }
+        self.reflection.record(encoder);
+    }
     pub fn render(&self, rpass: &mut wgpu::RenderPass, uniform_bind_group: &wgpu::BindGroup) {
         rpass.set_bind_group(0, uniform_bind_group, &[]);
         rpass.set_bind_group(1, &self.dst_bind_group, &[]);
@@ -0,0 +1,656 @@
+//! Histogram auto-exposure. `exposure_histogram.wgsl` bins the log luminance of the scene
+//! target, `exposure_average.wgsl` averages the histogram without its darkest and brightest
+//! tails and eases the exposure toward the result. The exposure stays on the GPU, where the
+//! HDR pass reads it as its tonemap uniform; [`ExposureReadback`] copies it back for display.
+
+use std::sync::{
+    atomic::{AtomicU8, Ordering},
+    Arc,
+};
+
+use bytemuck::{Pod, Zeroable};
+
+use crate::{BindGroup, EngineError, RenderBindGroupLayouts, Texture, WgpuBuffer};
+
+pub const HISTOGRAM_BINS: usize = 256;
+/// Scene luminance that maps to middle gray at zero compensation.
+pub const EXPOSURE_KEY: f32 = 0.18;
+/// Luminance below this lands in bin 0 instead of taking a log.
+const MIN_LUMINANCE: f32 = 1.0 / 4096.0;
+/// Side of the histogram workgroup, mirrored in `exposure_histogram.wgsl`.
+const HISTOGRAM_GROUP: u32 = 16;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ExposureMode {
+    Auto,
+    /// Fixed exposure multiplier; compensation still applies.
+    Manual(f32),
+}
+
+/// How the tonemapper picks its exposure.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TonemapSettings {
+    pub mode: ExposureMode,
+    /// Bias in stops added to the target exposure.
+    pub compensation: f32,
+    pub min_exposure: f32,
+    pub max_exposure: f32,
+    /// Log2 luminance range the histogram covers.
+    pub min_log_luminance: f32,
+    pub max_log_luminance: f32,
+    /// Fraction of pixels ignored at the dark and the bright end.
+    pub low_percentile: f32,
+    pub high_percentile: f32,
+    /// Adaptation rates, per second, when the exposure has to rise (scene got darker)
+    /// and when it has to fall (scene got brighter).
+    pub speed_up: f32,
+    pub speed_down: f32,
+}
+
+impl Default for TonemapSettings {
+    fn default() -> Self {
+        Self {
+            mode: ExposureMode::Auto,
+            compensation: 0.0,
+            min_exposure: 0.05,
+            max_exposure: 16.0,
+            min_log_luminance: -10.0,
+            max_log_luminance: 6.0,
+            low_percentile: 0.1,
+            high_percentile: 0.95,
+            speed_up: 1.5,
+            speed_down: 3.0,
+        }
+    }
+}
+
+impl TonemapSettings {
+    fn log_range(&self) -> f32 {
+        (self.max_log_luminance - self.min_log_luminance).max(f32::EPSILON)
+    }
+
+    /// Histogram bin of a pixel. Bin 0 holds black, the rest split the log range evenly.
+    /// Mirrors `bin_of` in `exposure_histogram.wgsl`.
+    pub fn histogram_bin(&self, luminance: f32) -> usize {
+        if luminance < MIN_LUMINANCE {
+            return 0;
+        }
+        let t = ((luminance.log2() - self.min_log_luminance) / self.log_range()).clamp(0.0, 1.0);
+        (t * (HISTOGRAM_BINS - 2) as f32) as usize + 1
+    }
+    /// Log2 luminance at the center of `bin`.
+    pub fn bin_log_luminance(&self, bin: usize) -> f32 {
+        if bin == 0 {
+            return self.min_log_luminance;
+        }
+        let t = (bin as f32 - 0.5) / (HISTOGRAM_BINS - 2) as f32;
+        self.min_log_luminance + t * self.log_range()
+    }
+
+    /// CPU reference of the histogram pass.
+    pub fn histogram(&self, pixels: &[[f32; 3]]) -> [u32; HISTOGRAM_BINS] {
+        let mut bins = [0; HISTOGRAM_BINS];
+        for rgb in pixels {
+            bins[self.histogram_bin(luminance(*rgb))] += 1;
+        }
+        bins
+    }
+
+    /// Average luminance of the pixels between the low and high percentile. Mirrors
+    /// `exposure_average.wgsl`; `None` for an empty histogram.
+    pub fn percentile_average(&self, histogram: &[u32; HISTOGRAM_BINS]) -> Option<f32> {
+        let total: u32 = histogram.iter().sum();
+        if total == 0 {
+            return None;
+        }
+        let low = total as f32 * self.low_percentile.clamp(0.0, 1.0);
+        let high = (total as f32 * self.high_percentile.clamp(0.0, 1.0)).max(low);
+        let mut seen = 0.0;
+        let mut weight = 0.0;
+        let mut sum = 0.0;
+        for (bin, count) in histogram.iter().enumerate() {
+            let count = *count as f32;
+            let kept = ((seen + count).min(high) - seen.max(low)).max(0.0);
+            sum += kept * self.bin_log_luminance(bin);
+            weight += kept;
+            seen += count;
+        }
+        if weight <= 0.0 {
+            // Percentiles collapsed onto one point: use the bin holding it.
+            let mut seen = 0;
+            let bin = histogram.iter().position(|count| {
+                seen += count;
+                seen as f32 >= low
+            })?;
+            return Some(self.bin_log_luminance(bin).exp2());
+        }
+        Some((sum / weight).exp2())
+    }
+
+    /// Exposure that brings `average` luminance to [`EXPOSURE_KEY`], compensated and clamped.
+    pub fn target_exposure(&self, average: f32) -> f32 {
+        let base = match self.mode {
+            ExposureMode::Auto => EXPOSURE_KEY / average.max(MIN_LUMINANCE),
+            ExposureMode::Manual(exposure) => exposure,
+        };
+        self.clamp(base * self.compensation.exp2())
+    }
+    fn clamp(&self, exposure: f32) -> f32 {
+        exposure.clamp(self.min_exposure, self.max_exposure.max(self.min_exposure))
+    }
+
+    /// Moves `current` toward `target` in stops, at `speed_up` when brightening and
+    /// `speed_down` when darkening. Manual mode snaps.
+    pub fn ease(&self, current: f32, target: f32, dt: f32) -> f32 {
+        if matches!(self.mode, ExposureMode::Manual(_)) {
+            return target;
+        }
+        let (from, to) = (current.max(f32::MIN_POSITIVE).log2(), target.log2());
+        let rate = if to > from {
+            self.speed_up
+        } else {
+            self.speed_down
+        };
+        let t = 1.0 - (-dt.max(0.0) * rate).exp();
+        self.clamp((from + (to - from) * t).exp2())
+    }
+}
+
+/// Rec. 709 luminance.
+pub fn luminance(rgb: [f32; 3]) -> f32 {
+    rgb[0] * 0.2126 + rgb[1] * 0.7152 + rgb[2] * 0.0722
+}
+
+/// Parameters of both exposure passes; see `ExposureParams` in the shaders.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Zeroable, Pod)]
+pub struct ExposureParams {
+    pub min_log_luminance: f32,
+    pub log_range: f32,
+    pub low_percentile: f32,
+    pub high_percentile: f32,
+    pub speed_up: f32,
+    pub speed_down: f32,
+    pub dt: f32,
+    pub compensation: f32,
+    pub min_exposure: f32,
+    pub max_exposure: f32,
+    /// Non-zero in manual mode, holding the exposure.
+    pub manual: f32,
+    pub key: f32,
+}
+
+impl ExposureParams {
+    pub fn new(settings: &TonemapSettings, dt: f32) -> Self {
+        Self {
+            min_log_luminance: settings.min_log_luminance,
+            log_range: settings.log_range(),
+            low_percentile: settings.low_percentile,
+            high_percentile: settings.high_percentile,
+            speed_up: settings.speed_up,
+            speed_down: settings.speed_down,
+            dt,
+            compensation: settings.compensation,
+            min_exposure: settings.min_exposure,
+            max_exposure: settings.max_exposure,
+            manual: match settings.mode {
+                ExposureMode::Auto => 0.0,
+                ExposureMode::Manual(exposure) => exposure.max(f32::MIN_POSITIVE),
+            },
+            key: EXPOSURE_KEY,
+        }
+    }
+}
+
+/// Tonemap uniform of `hdr.wgsl`, written by the average pass.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Zeroable, Pod)]
+pub struct TonemapUniform {
+    pub exposure: f32,
+    /// Average scene luminance the exposure is heading for.
+    pub average_luminance: f32,
+    _pad: [f32; 2],
+}
+
+impl TonemapUniform {
+    pub fn new(exposure: f32) -> Self {
+        Self {
+            exposure,
+            average_luminance: EXPOSURE_KEY,
+            _pad: [0.0; 2],
+        }
+    }
+}
+
+/// GPU side of auto-exposure.
+pub struct AutoExposure {
+    pub settings: TonemapSettings,
+    params: WgpuBuffer,
+    histogram: WgpuBuffer,
+    tonemap: WgpuBuffer,
+    histogram_pipeline: wgpu::ComputePipeline,
+    average_pipeline: wgpu::ComputePipeline,
+    average_bind_group: wgpu::BindGroup,
+    tonemap_bind_group: wgpu::BindGroup,
+    readback: ExposureReadback,
+}
+
+impl AutoExposure {
+    pub fn new(device: &wgpu::Device, settings: TonemapSettings) -> Result<Self, EngineError> {
+        let params = WgpuBuffer::from_data(
+            device,
+            &[ExposureParams::new(&settings, 0.0)],
+            wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            Some("exposure params"),
+        );
+        let histogram = WgpuBuffer::from_data(
+            device,
+            &[0u32; HISTOGRAM_BINS],
+            wgpu::BufferUsages::STORAGE,
+            Some("exposure histogram"),
+        );
+        let tonemap = WgpuBuffer::from_data(
+            device,
+            &[TonemapUniform::new(1.0)],
+            wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::UNIFORM
+                | wgpu::BufferUsages::COPY_SRC
+                | wgpu::BufferUsages::COPY_DST,
+            Some("tonemap uniform"),
+        );
+
+        let pipeline = |shader: &str, layout: &wgpu::BindGroupLayout, entry: &str| {
+            let module = crate::Shader::load(shader)?;
+            let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some(&format!("{} layout", shader)),
+                bind_group_layouts: &[layout],
+                push_constant_ranges: &[],
+            });
+            Ok::<_, EngineError>(
+                device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                    label: Some(shader),
+                    layout: Some(&layout),
+                    module: &module,
+                    entry_point: Some(entry),
+                    compilation_options: Default::default(),
+                    cache: None,
+                }),
+            )
+        };
+        let histogram_pipeline = pipeline(
+            "exposure_histogram.wgsl",
+            RenderBindGroupLayouts::exposure_histogram(),
+            "build_histogram",
+        )?;
+        let average_pipeline = pipeline(
+            "exposure_average.wgsl",
+            RenderBindGroupLayouts::exposure_average(),
+            "average_histogram",
+        )?;
+
+        let average_bind_group = BindGroup::exposure_average(device, &histogram, &tonemap, &params);
+        let tonemap_bind_group = BindGroup::tonemap(device, &tonemap, "auto exposure");
+        Ok(Self {
+            settings,
+            params,
+            histogram,
+            tonemap,
+            histogram_pipeline,
+            average_pipeline,
+            average_bind_group,
+            tonemap_bind_group,
+            readback: ExposureReadback::new(device),
+        })
+    }
+
+    /// Uploads the settings for a frame `dt` seconds long.
+    pub fn update(&mut self, queue: &wgpu::Queue, device: &wgpu::Device, dt: f32) {
+        let params = ExposureParams::new(&self.settings, dt);
+        self.params.write_data(queue, device, &[params], None);
+    }
+
+    /// Bind group for group 1 of `hdr.wgsl`.
+    pub fn tonemap_bind_group(&self) -> &wgpu::BindGroup {
+        &self.tonemap_bind_group
+    }
+
+    /// Picks the readback buffer the next [`Self::dispatch`] copies into. Call before
+    /// recording, which only reads.
+    pub fn prepare(&mut self) {
+        self.readback.prepare();
+    }
+    /// Builds the histogram of `scene`, updates the exposure, and queues a readback copy.
+    pub fn dispatch(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        scene: &Texture,
+    ) {
+        crate::gpu_scope!(Pass, "Auto Exposure");
+        let histogram_bind_group =
+            BindGroup::exposure_histogram(device, scene, &self.histogram, &self.params);
+        let size = scene.texture.size();
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Exposure Histogram"),
+                timestamp_writes: None,
+            });
+            pass.set_pipeline(&self.histogram_pipeline);
+            pass.set_bind_group(0, &histogram_bind_group, &[]);
+            pass.dispatch_workgroups(
+                size.width.div_ceil(HISTOGRAM_GROUP),
+                size.height.div_ceil(HISTOGRAM_GROUP),
+                1,
+            );
+        }
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Exposure Average"),
+                timestamp_writes: None,
+            });
+            pass.set_pipeline(&self.average_pipeline);
+            pass.set_bind_group(0, &self.average_bind_group, &[]);
+            pass.dispatch_workgroups(1, 1, 1);
+        }
+        self.readback.copy(encoder, self.tonemap.get());
+    }
+
+    /// Call after submitting the encoder passed to [`Self::dispatch`].
+    pub fn after_submit(&mut self) {
+        self.readback.map_pending();
+    }
+    /// Latest exposure read back from the GPU, a few frames old. Never waits on the GPU.
+    pub fn readback(&mut self, device: &wgpu::Device) -> Option<TonemapUniform> {
+        self.readback.poll(device)
+    }
+}
+
+const READBACK_IDLE: u8 = 0;
+const READBACK_COPIED: u8 = 1;
+const READBACK_MAPPING: u8 = 2;
+const READBACK_READY: u8 = 3;
+
+/// Ring of staging buffers read back without stalling: a buffer is copied into, mapped after
+/// submission and read on a later frame once the map callback has fired. Frames that find no
+/// idle buffer skip their copy.
+pub struct ExposureReadback {
+    slots: Vec<ReadbackSlot>,
+    frame: u64,
+    latest: Option<(u64, TonemapUniform)>,
+    /// Slot picked by [`ExposureReadback::prepare`] for this frame's copy.
+    pending: Option<usize>,
+}
+
+struct ReadbackSlot {
+    buffer: wgpu::Buffer,
+    state: Arc<AtomicU8>,
+    /// Frame the buffer was copied on, so results landing out of order aren't taken.
+    frame: u64,
+}
+
+impl ExposureReadback {
+    const FRAMES: usize = 3;
+
+    pub fn new(device: &wgpu::Device) -> Self {
+        let slots = (0..Self::FRAMES)
+            .map(|i| ReadbackSlot {
+                buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some(&format!("exposure readback {}", i)),
+                    size: std::mem::size_of::<TonemapUniform>() as u64,
+                    usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                }),
+                state: Arc::new(AtomicU8::new(READBACK_IDLE)),
+                frame: 0,
+            })
+            .collect();
+        Self {
+            slots,
+            frame: 0,
+            latest: None,
+            pending: None,
+        }
+    }
+
+    pub fn prepare(&mut self) {
+        self.frame += 1;
+        self.pending = self
+            .slots
+            .iter()
+            .position(|slot| slot.state.load(Ordering::Acquire) == READBACK_IDLE);
+        if let Some(i) = self.pending {
+            self.slots[i].frame = self.frame;
+        }
+    }
+
+    pub fn copy(&self, encoder: &mut wgpu::CommandEncoder, source: &wgpu::Buffer) {
+        if let Some(slot) = self.pending.map(|i| &self.slots[i]) {
+            encoder.copy_buffer_to_buffer(source, 0, &slot.buffer, 0, slot.buffer.size());
+            slot.state.store(READBACK_COPIED, Ordering::Release);
+        }
+    }
+
+    pub fn map_pending(&mut self) {
+        self.pending = None;
+        for slot in &self.slots {
+            if slot.state.load(Ordering::Acquire) != READBACK_COPIED {
+                continue;
+            }
+            slot.state.store(READBACK_MAPPING, Ordering::Release);
+            let state = slot.state.clone();
+            slot.buffer
+                .slice(..)
+                .map_async(wgpu::MapMode::Read, move |result| {
+                    let next = if result.is_ok() {
+                        READBACK_READY
+                    } else {
+                        READBACK_IDLE
+                    };
+                    state.store(next, Ordering::Release);
+                });
+        }
+    }
+
+    pub fn poll(&mut self, device: &wgpu::Device) -> Option<TonemapUniform> {
+        let _ = device.poll(wgpu::Maintain::Poll);
+        for slot in &self.slots {
+            if slot.state.load(Ordering::Acquire) != READBACK_READY {
+                continue;
+            }
+            let value = {
+                let data = slot.buffer.slice(..).get_mapped_range();
+                *bytemuck::from_bytes::<TonemapUniform>(&data)
+            };
+            slot.buffer.unmap();
+            slot.state.store(READBACK_IDLE, Ordering::Release);
+            if self.latest.map_or(true, |(frame, _)| slot.frame > frame) {
+                self.latest = Some((slot.frame, value));
+            }
+        }
+        self.latest.map(|(_, value)| value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f32, b: f32, tolerance: f32) -> bool {
+        (a - b).abs() <= tolerance * b.abs().max(f32::MIN_POSITIVE)
+    }
+
+    /// `width` x 32 pixels, the left `split` columns `left` and the rest `right`.
+    fn image(width: u32, split: u32, left: u8, right: u8) -> image::RgbaImage {
+        image::RgbaImage::from_fn(width, 32, |x, _| {
+            let v = if x < split { left } else { right };
+            image::Rgba([v, v, v, 255])
+        })
+    }
+
+    fn linear_pixels(image: &image::RgbaImage) -> Vec<[f32; 3]> {
+        image
+            .pixels()
+            .map(|p| [0, 1, 2].map(|c| p.0[c] as f32 / 255.0))
+            .collect()
+    }
+
+    /// Runs both passes once over `image` and reads the tonemap uniform back.
+    fn run_on_gpu(
+        image: &image::RgbaImage,
+        settings: TonemapSettings,
+        dt: f32,
+    ) -> Option<TonemapUniform> {
+        crate::assets::loader::test_root();
+        let _ = crate::GPU::init(crate::GpuRequirements::default());
+        RenderBindGroupLayouts::try_get().ok()?;
+        let (device, queue) =
+            crate::GPU::with_read_recovered(|gpu| (gpu.device().clone(), gpu.queue().clone()))
+                .ok()?;
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::TEXTURE_BINDING,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            width: image.width(),
+            height: image.height(),
+            present_mode: wgpu::PresentMode::Fifo,
+            alpha_mode: wgpu::CompositeAlphaMode::Opaque,
+            view_formats: vec![],
+            desired_maximum_frame_latency: 2,
+        };
+        let scene = Texture::from_image(&device, &queue, &config, image, "exposure test scene");
+        let mut exposure = AutoExposure::new(&device, settings).unwrap();
+        exposure.update(&queue, &device, dt);
+        exposure.prepare();
+        let mut encoder = device.create_command_encoder(&Default::default());
+        exposure.dispatch(&device, &mut encoder, &scene);
+        queue.submit(Some(encoder.finish()));
+        exposure.after_submit();
+        for _ in 0..100 {
+            let _ = device.poll(wgpu::Maintain::Wait);
+            if let Some(uniform) = exposure.readback(&device) {
+                return Some(uniform);
+            }
+        }
+        panic!("exposure never read back");
+    }
+
+    #[test]
+    fn uniform_gray_averages_to_its_own_luminance() {
+        let settings = TonemapSettings::default();
+        let gray = linear_pixels(&image(32, 32, 128, 128));
+        let average = settings
+            .percentile_average(&settings.histogram(&gray))
+            .unwrap();
+        // Within half a bin, in stops.
+        let half_bin = settings.log_range() / (HISTOGRAM_BINS - 2) as f32 / 2.0;
+        assert!((average.log2() - (128.0f32 / 255.0).log2()).abs() <= half_bin);
+        assert_eq!(settings.percentile_average(&[0; HISTOGRAM_BINS]), None);
+    }
+
+    #[test]
+    fn the_percentile_tails_are_ignored() {
+        let settings = TonemapSettings::default();
+        let mut pixels = linear_pixels(&image(32, 32, 64, 64));
+        let plain = settings
+            .percentile_average(&settings.histogram(&pixels))
+            .unwrap();
+        // Under 5% of the pixels staring at the sun, under 10% in a black corner.
+        let len = pixels.len();
+        pixels[..len / 25].fill([50.0; 3]);
+        pixels[len / 2..len / 2 + len / 12].fill([0.0; 3]);
+        let with_tails = settings
+            .percentile_average(&settings.histogram(&pixels))
+            .unwrap();
+        assert!(
+            close(with_tails, plain, 1e-4),
+            "{} vs {}",
+            with_tails,
+            plain
+        );
+    }
+
+    #[test]
+    fn brightening_and_darkening_ease_at_their_own_rates() {
+        let settings = TonemapSettings::default();
+        let dt = 0.1;
+        let up = settings.ease(1.0, 4.0, dt).log2();
+        let down = -settings.ease(1.0, 0.25, dt).log2();
+        assert!(close(
+            up,
+            2.0 * (1.0 - (-dt * settings.speed_up).exp()),
+            1e-4
+        ));
+        assert!(close(
+            down,
+            2.0 * (1.0 - (-dt * settings.speed_down).exp()),
+            1e-4
+        ));
+        assert!(down > up);
+
+        // Easing settles on the target and never overshoots it.
+        let mut exposure = 1.0;
+        for _ in 0..200 {
+            exposure = settings.ease(exposure, 4.0, dt);
+            assert!(exposure <= 4.0);
+        }
+        assert!(close(exposure, 4.0, 1e-3));
+
+        let manual = TonemapSettings {
+            mode: ExposureMode::Manual(2.0),
+            ..settings
+        };
+        assert_eq!(manual.ease(1.0, manual.target_exposure(0.01), dt), 2.0);
+    }
+
+    #[test]
+    fn exposure_stays_within_its_clamps() {
+        let settings = TonemapSettings::default();
+        let black = settings
+            .percentile_average(&settings.histogram(&[[0.0; 3]; 64]))
+            .unwrap();
+        assert_eq!(settings.target_exposure(black), settings.max_exposure);
+        let sun = settings
+            .percentile_average(&settings.histogram(&[[1000.0; 3]; 64]))
+            .unwrap();
+        assert_eq!(settings.target_exposure(sun), settings.min_exposure);
+        assert_eq!(settings.ease(100.0, 1000.0, 1.0), settings.max_exposure);
+
+        // Compensation biases the target in stops, still inside the clamps.
+        let brighter = TonemapSettings {
+            compensation: 1.0,
+            ..settings
+        };
+        assert!(close(
+            brighter.target_exposure(0.18),
+            2.0 * settings.target_exposure(0.18),
+            1e-6
+        ));
+        assert_eq!(brighter.target_exposure(black), settings.max_exposure);
+    }
+
+    /// Needs an adapter; passes without one.
+    #[test]
+    fn the_gpu_passes_match_the_cpu_reference() {
+        let settings = TonemapSettings::default();
+        let dt = 0.25;
+        for image in [image(32, 32, 128, 128), image(64, 32, 0, 255)] {
+            let Some(gpu) = run_on_gpu(&image, settings, dt) else {
+                return;
+            };
+            let pixels = linear_pixels(&image);
+            let average = settings
+                .percentile_average(&settings.histogram(&pixels))
+                .unwrap();
+            let exposure = settings.ease(1.0, settings.target_exposure(average), dt);
+            assert!(close(gpu.average_luminance, average, 1e-3), "{:?}", gpu);
+            assert!(close(gpu.exposure, exposure, 1e-3), "{:?}", gpu);
+        }
+
+        // All black runs into the clamp on the GPU too.
+        let gpu = run_on_gpu(&image(32, 32, 0, 0), settings, 100.0).unwrap();
+        assert!(
+            close(gpu.exposure, settings.max_exposure, 1e-4),
+            "{:?}",
+            gpu
+        );
+    }
+}
@@ -0,0 +1,330 @@
+//! Coarse water over the voxel grid. Each air cell holds a fill level out of
+//! [`WATER_FULL`]; a fixed-step cellular update lets water fall into open cells below and
+//! level out with its horizontal neighbors, a bounded amount per step. Only chunks with
+//! recent changes are stepped, so still water costs nothing. Every move takes from one cell
+//! what it gives another, so the total only changes through [`crate::Terrain::set_water`]
+//! and blocks placed into water.
+
+use std::collections::HashSet;
+
+use glam::IVec3;
+
+use crate::{Terrain, AIR, CHUNK_SIZE};
+
+/// Fill level of a full cell.
+pub const WATER_FULL: u8 = 16;
+/// Most water one cell passes to one horizontal neighbor per step.
+pub const WATER_MAX_FLOW: u8 = 4;
+/// Seconds between simulation steps.
+pub const WATER_STEP: f32 = 0.1;
+/// Steps run at most per update, so a long frame doesn't stall on catching up.
+const MAX_STEPS_PER_UPDATE: u32 = 4;
+/// How far along a flat stretch of surface a cell looks for lower water.
+const LEVEL_REACH: usize = 8;
+
+const HORIZONTAL: [IVec3; 4] = [IVec3::X, IVec3::NEG_X, IVec3::Z, IVec3::NEG_Z];
+
+/// Chunk position of a world-space block coordinate.
+pub fn chunk_of(cell: IVec3) -> (i32, i32, i32) {
+    let size = CHUNK_SIZE as i32;
+    (
+        cell.x.div_euclid(size),
+        cell.y.div_euclid(size),
+        cell.z.div_euclid(size),
+    )
+}
+
+/// Which chunks need stepping, and the time left over from the last update.
+#[derive(Debug, Default)]
+pub struct WaterSim {
+    awake: HashSet<(i32, i32, i32)>,
+    accumulator: f32,
+}
+
+impl WaterSim {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn wake(&mut self, chunk: (i32, i32, i32)) {
+        self.awake.insert(chunk);
+    }
+    /// Wakes the chunk holding `cell` and any chunk across a face from it, for edits that
+    /// may open or close a path on a chunk border.
+    pub fn wake_around(&mut self, cell: IVec3) {
+        self.wake(chunk_of(cell));
+        for offset in [IVec3::Y, IVec3::NEG_Y].iter().chain(&HORIZONTAL) {
+            self.wake(chunk_of(cell + *offset));
+        }
+    }
+    pub fn rebase(&mut self, rebase: &crate::Rebase) {
+        self.awake = self.awake.drain().map(|c| rebase.chunk(c)).collect();
+    }
+    pub fn is_awake(&self, chunk: (i32, i32, i32)) -> bool {
+        self.awake.contains(&chunk)
+    }
+    /// Chunks the next step looks at; empty once all water has settled.
+    pub fn awake(&self) -> impl Iterator<Item = &(i32, i32, i32)> {
+        self.awake.iter()
+    }
+
+    /// Runs however many fixed steps `dt` adds up to. Returns the steps taken.
+    pub fn update(&mut self, terrain: &mut Terrain, dt: f32) -> u32 {
+        self.accumulator = (self.accumulator + dt).min(WATER_STEP * MAX_STEPS_PER_UPDATE as f32);
+        let mut steps = 0;
+        while self.accumulator >= WATER_STEP {
+            self.accumulator -= WATER_STEP;
+            self.step(terrain);
+            steps += 1;
+        }
+        steps
+    }
+
+    /// One cellular update over the awake chunks. Cells go bottom-up in world order rather
+    /// than chunk by chunk, so how the grid is split into chunks doesn't change the result.
+    /// Chunks that moved water stay awake, along with the ones it moved into.
+    pub fn step(&mut self, terrain: &mut Terrain) {
+        let mut cells: Vec<IVec3> = Vec::new();
+        for pos in self.awake.drain() {
+            let Some((chunk, _)) = terrain.get_chunk_stream(pos) else {
+                continue;
+            };
+            let origin = IVec3::new(pos.0, pos.1, pos.2) * CHUNK_SIZE as i32;
+            cells.extend(chunk.water.keys().map(|&idx| {
+                let (x, y, z) = crate::Chunk::coords(idx);
+                origin + IVec3::new(x as i32, y as i32, z as i32)
+            }));
+        }
+        cells.sort_by_key(|c| (c.y, c.x, c.z));
+
+        for cell in cells {
+            let mut level = terrain.water_at(cell);
+            if level == 0 {
+                continue;
+            }
+            let below = cell + IVec3::NEG_Y;
+            if is_open(terrain, below) {
+                let fall = level.min(WATER_FULL - terrain.water_at(below));
+                if fall > 0 {
+                    self.transfer(terrain, cell, below, fall);
+                    level -= fall;
+                }
+                // Still sinking: it levels out once it lands.
+                if terrain.water_at(below) < WATER_FULL {
+                    continue;
+                }
+            }
+            for offset in HORIZONTAL {
+                let next = cell + offset;
+                if level <= 1 || !is_open(terrain, next) {
+                    continue;
+                }
+                let other = terrain.water_at(next);
+                let flow = if level > other + 1 {
+                    ((level - other) / 2).min(WATER_MAX_FLOW)
+                } else if level == other + 1 {
+                    // One unit at a time down a single step, while the surface past `next`
+                    // falls further; otherwise water settles as a staircase, not flat.
+                    u8::from(falls_beyond(terrain, next, offset, other))
+                } else {
+                    0
+                };
+                if flow > 0 {
+                    self.transfer(terrain, cell, next, flow);
+                    level -= flow;
+                }
+            }
+        }
+    }
+
+    fn transfer(&mut self, terrain: &mut Terrain, from: IVec3, to: IVec3, amount: u8) {
+        let source = terrain.water_at(from);
+        let target = terrain.water_at(to);
+        terrain.store_water(from, source - amount);
+        terrain.store_water(to, target + amount);
+        self.wake(chunk_of(from));
+        self.wake(chunk_of(to));
+    }
+}
+
+/// Whether, walking from `from` along `dir` over water at `level`, the surface drops
+/// before a wall, a rise or [`LEVEL_REACH`] cells.
+fn falls_beyond(terrain: &Terrain, from: IVec3, dir: IVec3, level: u8) -> bool {
+    let mut cell = from;
+    for _ in 0..LEVEL_REACH {
+        cell += dir;
+        if !is_open(terrain, cell) {
+            return false;
+        }
+        let other = terrain.water_at(cell);
+        if other != level {
+            return other < level;
+        }
+    }
+    false
+}
+
+/// Whether water can be in `cell`: a loaded air block. Unloaded chunks act as walls.
+fn is_open(terrain: &Terrain, cell: IVec3) -> bool {
+    matches!(terrain.block_at(cell), Some((AIR, _)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Chunk, Medium};
+
+    /// Terrain over `chunks`, solid except where `open` says, with every chunk awake in
+    /// the returned simulation.
+    fn terrain(chunks: &[(i32, i32, i32)], open: impl Fn(IVec3) -> bool) -> (Terrain, WaterSim) {
+        let size = CHUNK_SIZE as i32;
+        let mut terrain = Terrain::new(Medium::Air);
+        let mut sim = WaterSim::new();
+        for &pos in chunks {
+            let mut chunk = Chunk::new(pos);
+            let origin = IVec3::new(pos.0, pos.1, pos.2) * size;
+            for x in 0..CHUNK_SIZE {
+                for y in 0..CHUNK_SIZE {
+                    for z in 0..CHUNK_SIZE {
+                        if open(origin + IVec3::new(x as i32, y as i32, z as i32)) {
+                            chunk.blocks[x][y][z] = AIR;
+                        }
+                    }
+                }
+            }
+            terrain.insert_chunk_stream(chunk, Medium::Air);
+            sim.wake(pos);
+        }
+        (terrain, sim)
+    }
+
+    fn chunks(count: IVec3) -> Vec<(i32, i32, i32)> {
+        let mut chunks = Vec::new();
+        for x in 0..count.x {
+            for y in 0..count.y {
+                for z in 0..count.z {
+                    chunks.push((x, y, z));
+                }
+            }
+        }
+        chunks
+    }
+
+    /// Open cells of a U-shaped basin at `z = 1`: four cells wide at the bottom, six above.
+    fn u_basin(cell: IVec3) -> bool {
+        let width = if cell.y == 1 { 2..=5 } else { 1..=6 };
+        cell.z == 1 && (1..=6).contains(&cell.y) && width.contains(&cell.x)
+    }
+
+    fn settle(terrain: &mut Terrain, sim: &mut WaterSim) -> usize {
+        for steps in 0..2000 {
+            if sim.awake().next().is_none() {
+                return steps;
+            }
+            sim.step(terrain);
+        }
+        panic!("water never settled");
+    }
+
+    #[test]
+    fn closed_containers_conserve_water() {
+        let open = |c: IVec3| c.cmpge(IVec3::ONE).all() && c.cmple(IVec3::splat(6)).all();
+        let (mut terrain, mut sim) = terrain(&chunks(IVec3::splat(2)), open);
+        let mut seed = 0x2545_f491_u32;
+        for _ in 0..40 {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            let cell = IVec3::new(
+                1 + (seed % 6) as i32,
+                1 + (seed / 6 % 6) as i32,
+                1 + (seed / 36 % 6) as i32,
+            );
+            terrain.set_water(cell, (seed / 216 % WATER_FULL as u32) as u8 + 1);
+        }
+        let volume = terrain.water_volume();
+        assert!(volume > 0);
+        for _ in 0..500 {
+            sim.step(&mut terrain);
+            assert_eq!(terrain.water_volume(), volume);
+        }
+        settle(&mut terrain, &mut sim);
+        assert_eq!(terrain.water_volume(), volume);
+    }
+
+    #[test]
+    fn a_column_levels_out_across_a_u_basin() {
+        let (mut terrain, mut sim) = terrain(&chunks(IVec3::new(2, 2, 1)), u_basin);
+        for y in 2..=6 {
+            terrain.set_water(IVec3::new(1, y, 1), WATER_FULL);
+        }
+        let volume = terrain.water_volume();
+        settle(&mut terrain, &mut sim);
+        assert_eq!(terrain.water_volume(), volume);
+
+        for x in 2..=5 {
+            assert_eq!(terrain.water_at(IVec3::new(x, 1, 1)), WATER_FULL);
+        }
+        let surface: Vec<u8> = (1..=6)
+            .map(|x| terrain.water_at(IVec3::new(x, 2, 1)))
+            .collect();
+        let (low, high) = (surface.iter().min(), surface.iter().max());
+        assert!(high.unwrap() - low.unwrap() <= 1, "{surface:?}");
+        for y in 3..=6 {
+            for x in 1..=6 {
+                assert_eq!(terrain.water_at(IVec3::new(x, y, 1)), 0);
+            }
+        }
+    }
+
+    #[test]
+    fn settled_water_puts_its_chunks_to_sleep() {
+        let (mut terrain, mut sim) = terrain(&chunks(IVec3::new(2, 2, 1)), u_basin);
+        terrain.set_water(IVec3::new(1, 6, 1), WATER_FULL);
+        assert!(settle(&mut terrain, &mut sim) > 0);
+        sim.step(&mut terrain);
+        assert_eq!(sim.awake().count(), 0);
+
+        // Opening the basin wall wakes it again and the water drains into the gap.
+        let gap = IVec3::new(3, 1, 0);
+        terrain.set_block(gap, AIR);
+        assert!(terrain.water_sim().is_awake((0, 0, 0)));
+        sim.wake((0, 0, 0));
+        settle(&mut terrain, &mut sim);
+        assert!(terrain.water_at(gap) > 0);
+    }
+
+    #[test]
+    fn flow_across_chunks_matches_a_single_chunk() {
+        let shape = |c: IVec3| {
+            c.cmpge(IVec3::ZERO).all()
+                && c.cmplt(IVec3::splat(CHUNK_SIZE as i32)).all()
+                && !(c.y == 1 && c.x == 1)
+        };
+        let offset = IVec3::new(2, 0, 2);
+        let (mut single, mut single_sim) = terrain(&[(0, 0, 0)], shape);
+        let (mut split, mut split_sim) =
+            terrain(&chunks(IVec3::new(2, 1, 2)), |c| shape(c - offset));
+        for (x, z, level) in [(0, 0, 16), (1, 2, 9), (3, 3, 16), (2, 0, 5)] {
+            let cell = IVec3::new(x, 3, z);
+            single.set_water(cell, level);
+            split.set_water(cell + offset, level);
+        }
+
+        for step in 0..200 {
+            single_sim.step(&mut single);
+            split_sim.step(&mut split);
+            for x in 0..4 {
+                for y in 0..4 {
+                    for z in 0..4 {
+                        let cell = IVec3::new(x, y, z);
+                        assert_eq!(
+                            single.water_at(cell),
+                            split.water_at(cell + offset),
+                            "{cell} after step {step}"
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
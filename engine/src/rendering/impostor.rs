@@ -0,0 +1,865 @@
+//! Impostors: distant models drawn as a camera-facing billboard of a pre-rendered view.
+//! [`bake_model_impostor`] renders a model flagged in its [`crate::ModelSidecar`] from
+//! evenly spaced azimuths around the horizon into an atlas, saved next to the model as
+//! `<stem>.impostor.png` plus `<stem>.impostor.ron`. At runtime an entity past the switch
+//! distance is drawn from the atlas cell baked closest to its view direction, crossfading
+//! into the next one, and tinted with the light color.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use bytemuck::{Pod, Zeroable};
+use glam::{Mat4, Vec3};
+use image::RgbaImage;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    log_warning, AssetPaths, BindGroup, CacheKey, EngineError, ImportedTexture, MeshAsset,
+    ModelManager, ModelSidecar, RenderBindGroupLayouts, ShaderManager, Texture, WgpuBuffer, AABB,
+};
+
+/// Fraction of the switch distance an entity has to move back past before it flips
+/// between mesh and impostor again, so entities near the boundary don't flicker.
+pub const IMPOSTOR_HYSTERESIS: f32 = 0.1;
+
+/// Per-model impostor settings, the `impostor` field of a [`crate::ModelSidecar`]. Models
+/// without one aren't baked.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ImpostorSettings {
+    /// Views around the horizon, 8 or 16.
+    pub angles: u32,
+    /// Side of one atlas cell in pixels.
+    pub cell_size: u32,
+    /// Camera distance beyond which the impostor replaces the mesh.
+    pub distance: f32,
+    /// Blend the two nearest views instead of snapping to one.
+    pub crossfade: bool,
+}
+
+impl Default for ImpostorSettings {
+    fn default() -> Self {
+        Self {
+            angles: 8,
+            cell_size: 256,
+            distance: 150.0,
+            crossfade: true,
+        }
+    }
+}
+
+/// What a baked atlas holds. Cell `i` was rendered from azimuth `i * 360 / angles` degrees,
+/// cells laid out row by row.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ImpostorMeta {
+    pub angles: u32,
+    pub columns: u32,
+    pub rows: u32,
+    pub cell_size: u32,
+    /// World-space side of the square each cell shows.
+    pub size: f32,
+    /// Model-space point the cells are centered on.
+    pub center: [f32; 3],
+    pub distance: f32,
+    pub crossfade: bool,
+}
+
+impl ImpostorMeta {
+    pub fn path(file: &str) -> PathBuf {
+        Self::sibling(file, "impostor.ron")
+    }
+    pub fn atlas_path(file: &str) -> PathBuf {
+        Self::sibling(file, "impostor.png")
+    }
+    fn sibling(file: &str, ext: &str) -> PathBuf {
+        let stem = Path::new(file)
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or(file);
+        AssetPaths::model(format!("{}.{}", stem, ext))
+    }
+    pub fn load(file: &str) -> Result<Option<Self>, EngineError> {
+        let path = Self::path(file);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let source = std::fs::read_to_string(&path)?;
+        Self::parse(&source)
+            .map(Some)
+            .map_err(|e| EngineError::AssetLoadError(format!("{}: {}", path.display(), e)))
+    }
+    pub fn parse(source: &str) -> Result<Self, ron::error::SpannedError> {
+        ron::de::from_str(source)
+    }
+    pub fn to_ron(&self) -> Result<String, EngineError> {
+        ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|e| EngineError::AssetLoadError(e.to_string()))
+    }
+    /// Writes the metadata and `atlas` next to the model `file`.
+    pub fn save(&self, file: &str, atlas: &RgbaImage) -> Result<(), EngineError> {
+        atlas.save(Self::atlas_path(file))?;
+        std::fs::write(Self::path(file), self.to_ron()?)?;
+        Ok(())
+    }
+
+    /// The two cells around `azimuth` (degrees) and how far toward the second to blend.
+    /// Without crossfade the nearest cell is returned twice.
+    pub fn cells(&self, azimuth: f32) -> (u32, u32, f32) {
+        let (a, b, weight) = azimuth_cells(azimuth, self.angles);
+        match (self.crossfade, weight >= 0.5) {
+            (true, _) => (a, b, weight),
+            (false, false) => (a, a, 0.0),
+            (false, true) => (b, b, 0.0),
+        }
+    }
+}
+
+/// Atlas grid for `angles` cells: as square as possible, filled row by row.
+pub fn atlas_grid(angles: u32) -> (u32, u32) {
+    let angles = angles.max(1);
+    let columns = (angles as f32).sqrt().ceil() as u32;
+    (columns, angles.div_ceil(columns))
+}
+
+/// Azimuth in degrees, `[0, 360)`, of `direction` around the vertical axis; `+Z` is 0 and
+/// `+X` is 90, the way bake views are placed.
+pub fn azimuth(direction: Vec3) -> f32 {
+    direction
+        .x
+        .atan2(direction.z)
+        .to_degrees()
+        .rem_euclid(360.0)
+}
+
+/// Cell baked at or before `azimuth`, the next one around, wrapping after the last, and
+/// the crossfade weight toward the next one.
+pub fn azimuth_cells(azimuth: f32, angles: u32) -> (u32, u32, f32) {
+    let angles = angles.max(1);
+    let step = 360.0 / angles as f32;
+    let t = azimuth.rem_euclid(360.0) / step;
+    let a = (t.floor() as u32) % angles;
+    (a, (a + 1) % angles, t.fract())
+}
+
+/// Whether an entity `distance` away draws as its impostor, given whether it did last frame.
+pub fn use_impostor(was_impostor: bool, distance: f32, switch: f32) -> bool {
+    if was_impostor {
+        distance > switch * (1.0 - IMPOSTOR_HYSTERESIS)
+    } else {
+        distance > switch * (1.0 + IMPOSTOR_HYSTERESIS)
+    }
+}
+
+/// One part of a model to bake: a mesh with its diffuse texture or color.
+pub struct ImpostorSource {
+    pub mesh: MeshAsset,
+    pub texture: Option<RgbaImage>,
+    pub color: [f32; 3],
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+struct BakeView {
+    view_proj: [[f32; 4]; 4],
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+struct BakeColor {
+    color: [f32; 4],
+}
+
+const BAKE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+const BAKE_DEPTH: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+/// Renders `sources` from `settings.angles` azimuths into an atlas with alpha. Needs no
+/// window; `rupy-cli assets bake-impostors` runs it on a headless device.
+pub fn bake_impostor(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    sources: &[ImpostorSource],
+    settings: &ImpostorSettings,
+) -> Result<(RgbaImage, ImpostorMeta), EngineError> {
+    let vertices: Vec<crate::Vertex> = sources
+        .iter()
+        .flat_map(|s| s.mesh.vertices.iter().copied())
+        .collect();
+    if vertices.is_empty() {
+        return Err(EngineError::AssetLoadError(
+            "nothing to bake an impostor from".to_string(),
+        ));
+    }
+    let aabb = AABB::from_vertices(&vertices);
+    let center = (aabb.min + aabb.max) * 0.5;
+    let extent = aabb.max - aabb.min;
+    let size = extent.x.hypot(extent.z).max(extent.y).max(f32::EPSILON);
+    let radius = extent.length() * 0.5 + 0.1;
+
+    let angles = settings.angles.max(1);
+    let cell = settings.cell_size.max(1);
+    let (columns, rows) = atlas_grid(angles);
+    let meta = ImpostorMeta {
+        angles,
+        columns,
+        rows,
+        cell_size: cell,
+        size,
+        center: center.to_array(),
+        distance: settings.distance,
+        crossfade: settings.crossfade,
+    };
+    let extent = wgpu::Extent3d {
+        width: columns * cell,
+        height: rows * cell,
+        depth_or_array_layers: 1,
+    };
+    let target = |format, usage, label| {
+        device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: extent,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage,
+            view_formats: &[],
+        })
+    };
+    let atlas = target(
+        BAKE_FORMAT,
+        wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        "impostor atlas",
+    );
+    let depth = target(
+        BAKE_DEPTH,
+        wgpu::TextureUsages::RENDER_ATTACHMENT,
+        "impostor depth",
+    );
+
+    let view_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("impostor bake view layout"),
+        entries: &[wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::VERTEX,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        }],
+    });
+    let surface_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("impostor bake surface layout"),
+        entries: &[
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ],
+    });
+    let shader = ShaderManager::new().load(device, "impostor_bake.wgsl")?;
+    let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("impostor bake"),
+        bind_group_layouts: &[&view_layout, &surface_layout],
+        push_constant_ranges: &[],
+    });
+    let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("impostor bake"),
+        layout: Some(&layout),
+        vertex: wgpu::VertexState {
+            module: &shader,
+            entry_point: Some("vs_main"),
+            buffers: &[crate::Vertex::LAYOUT],
+            compilation_options: Default::default(),
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: &shader,
+            entry_point: Some("fs_main"),
+            targets: &[Some(wgpu::ColorTargetState {
+                format: BAKE_FORMAT,
+                blend: None,
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+            compilation_options: Default::default(),
+        }),
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: BAKE_DEPTH,
+            depth_write_enabled: true,
+            depth_compare: wgpu::CompareFunction::Less,
+            stencil: Default::default(),
+            bias: Default::default(),
+        }),
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+        cache: None,
+    });
+
+    let views: Vec<wgpu::BindGroup> = (0..angles)
+        .map(|i| {
+            let yaw = (i as f32 * 360.0 / angles as f32).to_radians();
+            let eye = center + Vec3::new(yaw.sin(), 0.0, yaw.cos()) * radius * 2.0;
+            let view = Mat4::look_at_rh(eye, center, Vec3::Y);
+            let half = size * 0.5;
+            let proj = Mat4::orthographic_rh(-half, half, -half, half, 0.0, radius * 4.0);
+            let buffer = WgpuBuffer::from_data(
+                device,
+                &[BakeView {
+                    view_proj: (proj * view).to_cols_array_2d(),
+                }],
+                wgpu::BufferUsages::UNIFORM,
+                Some("impostor bake view"),
+            );
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("impostor bake view"),
+                layout: &view_layout,
+                entries: &[wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.get().as_entire_binding(),
+                }],
+            })
+        })
+        .collect();
+
+    let white = RgbaImage::from_pixel(1, 1, image::Rgba([255; 4]));
+    let parts: Vec<(
+        WgpuBuffer,
+        WgpuBuffer,
+        u32,
+        wgpu::IndexFormat,
+        wgpu::BindGroup,
+    )> = sources
+        .iter()
+        .map(|source| {
+            let image = source.texture.as_ref().unwrap_or(&white);
+            let imported = ImportedTexture::from_image(image, true, false);
+            let texture = Texture::from_imported(device, queue, &imported, "impostor bake source");
+            let color = WgpuBuffer::from_data(
+                device,
+                &[BakeColor {
+                    color: [source.color[0], source.color[1], source.color[2], 1.0],
+                }],
+                wgpu::BufferUsages::UNIFORM,
+                Some("impostor bake color"),
+            );
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("impostor bake surface"),
+                layout: &surface_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&texture.view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(&texture.sampler),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: color.get().as_entire_binding(),
+                    },
+                ],
+            });
+            let vertices = WgpuBuffer::from_data(
+                device,
+                &source.mesh.vertices,
+                wgpu::BufferUsages::VERTEX,
+                Some("impostor bake vertices"),
+            );
+            let indices = WgpuBuffer::from_data(
+                device,
+                &source.mesh.index_bytes(),
+                wgpu::BufferUsages::INDEX,
+                Some("impostor bake indices"),
+            );
+            (
+                vertices,
+                indices,
+                source.mesh.indices.len() as u32,
+                source.mesh.index_format(),
+                bind_group,
+            )
+        })
+        .collect();
+
+    let atlas_view = atlas.create_view(&Default::default());
+    let depth_view = depth.create_view(&Default::default());
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("impostor bake"),
+    });
+    {
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("impostor bake"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &atlas_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &depth_view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: wgpu::StoreOp::Discard,
+                }),
+                stencil_ops: None,
+            }),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        pass.set_pipeline(&pipeline);
+        for (i, view) in views.iter().enumerate() {
+            let (x, y) = (i as u32 % columns * cell, i as u32 / columns * cell);
+            pass.set_viewport(x as f32, y as f32, cell as f32, cell as f32, 0.0, 1.0);
+            pass.set_scissor_rect(x, y, cell, cell);
+            pass.set_bind_group(0, view, &[]);
+            for (vertices, indices, count, format, bind_group) in &parts {
+                pass.set_bind_group(1, bind_group, &[]);
+                pass.set_vertex_buffer(0, vertices.get().slice(..));
+                pass.set_index_buffer(indices.get().slice(..), *format);
+                pass.draw_indexed(0..*count, 0, 0..1);
+            }
+        }
+    }
+    let pixels = crate::read_texture_blocking(device, queue, encoder, &atlas)?;
+    let image = RgbaImage::from_raw(extent.width, extent.height, pixels)
+        .ok_or_else(|| EngineError::AssetLoadError("impostor atlas size mismatch".to_string()))?;
+    Ok((image, meta))
+}
+
+/// Bakes the model `file` if its sidecar asks for an impostor and saves the result next to
+/// it. `None` when the model has no impostor settings.
+pub fn bake_model_impostor(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    file: &str,
+) -> Result<Option<ImpostorMeta>, EngineError> {
+    let Some(settings) = ModelSidecar::load(file)?.and_then(|sidecar| sidecar.impostor) else {
+        return Ok(None);
+    };
+    let (models, materials) = tobj::load_obj(
+        AssetPaths::model(file),
+        &tobj::LoadOptions {
+            triangulate: true,
+            single_index: true,
+            ..Default::default()
+        },
+    )?;
+    let materials = materials.unwrap_or_else(|e| {
+        log_warning!("{}: {}", file, e);
+        Vec::new()
+    });
+    let textures = AssetPaths::textures_dir();
+    let sources: Vec<ImpostorSource> = models
+        .iter()
+        .map(|model| {
+            let material = model.mesh.material_id.and_then(|id| materials.get(id));
+            let texture = material
+                .and_then(|mat| mat.diffuse_texture.as_ref())
+                .and_then(|name| {
+                    image::open(textures.join(name))
+                        .map_err(|e| log_warning!("{}: {}", name, e))
+                        .ok()
+                })
+                .map(|image| image.to_rgba8());
+            ImpostorSource {
+                mesh: MeshAsset::from_tobj(model),
+                texture,
+                color: material.and_then(|mat| mat.diffuse).unwrap_or([1.0; 3]),
+            }
+        })
+        .collect();
+    let (atlas, meta) = bake_impostor(device, queue, &sources, &settings)?;
+    meta.save(file, &atlas)?;
+    Ok(Some(meta))
+}
+
+/// A baked atlas loaded for drawing.
+#[derive(Debug)]
+pub struct Impostor {
+    pub meta: ImpostorMeta,
+    pub atlas: Texture,
+    pub bind_group: wgpu::BindGroup,
+}
+
+impl Impostor {
+    /// The impostor baked for the model `file`, if there is one.
+    pub fn load(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        file: &str,
+    ) -> Result<Option<Self>, EngineError> {
+        let Some(meta) = ImpostorMeta::load(file)? else {
+            return Ok(None);
+        };
+        let image = image::open(ImpostorMeta::atlas_path(file))
+            .map_err(|e| EngineError::AssetLoadError(format!("{}: {}", file, e)))?
+            .to_rgba8();
+        let imported = ImportedTexture::from_image(&image, true, true);
+        let atlas = Texture::from_imported(device, queue, &imported, format!("{} impostor", file));
+        let bind_group = BindGroup::texture(device, &atlas);
+        Ok(Some(Self {
+            meta,
+            atlas,
+            bind_group,
+        }))
+    }
+    /// Instance for an entity with `model` matrix seen from `eye`.
+    pub fn instance(&self, model: &Mat4, eye: Vec3, tint: [f32; 4]) -> ImpostorInstance {
+        let center = model.transform_point3(Vec3::from(self.meta.center));
+        let local = model.inverse().transform_vector3(eye - center);
+        let (a, b, weight) = self.meta.cells(azimuth(local));
+        let (scale, _, _) = model.to_scale_rotation_translation();
+        ImpostorInstance {
+            center: center.to_array(),
+            size: self.meta.size * scale.max_element(),
+            cells: [a, b, self.meta.columns, self.meta.rows],
+            tint,
+            weight,
+            _pad: [0.0; 3],
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+pub struct ImpostorInstance {
+    pub center: [f32; 3],
+    pub size: f32,
+    /// First and second cell, then the atlas columns and rows.
+    pub cells: [u32; 4],
+    pub tint: [f32; 4],
+    pub weight: f32,
+    pub _pad: [f32; 3],
+}
+
+impl ImpostorInstance {
+    pub const LAYOUT: wgpu::VertexBufferLayout<'static> = wgpu::VertexBufferLayout {
+        array_stride: std::mem::size_of::<ImpostorInstance>() as wgpu::BufferAddress,
+        step_mode: wgpu::VertexStepMode::Instance,
+        attributes: &wgpu::vertex_attr_array![
+            0 => Float32x3,
+            1 => Float32,
+            2 => Uint32x4,
+            3 => Float32x4,
+            4 => Float32,
+        ],
+    };
+}
+
+/// This frame's impostor instances, batched per atlas, and which entities were impostors
+/// last frame for the hysteresis.
+#[derive(Debug)]
+pub struct ImpostorBuffers {
+    format: wgpu::TextureFormat,
+    pipeline: Option<Arc<wgpu::RenderPipeline>>,
+    batch: HashMap<CacheKey, Vec<ImpostorInstance>>,
+    buffers: crate::InstanceBufferPool,
+    far: HashSet<usize>,
+}
+
+impl ImpostorBuffers {
+    /// `format` is the color target the impostors are drawn into.
+    pub fn new(format: wgpu::TextureFormat) -> Self {
+        Self {
+            format,
+            pipeline: None,
+            batch: HashMap::new(),
+            buffers: crate::InstanceBufferPool::new("impostor instance buffer"),
+            far: HashSet::new(),
+        }
+    }
+    pub fn clear(&mut self) {
+        self.batch.clear();
+    }
+    /// Drops the pipeline so it's rebuilt on next use, e.g. after a depth policy switch.
+    pub fn clear_pipeline(&mut self) {
+        self.pipeline = None;
+    }
+    /// Whether `entity`, `distance` from the camera, draws as an impostor switching at
+    /// `switch`; remembers the answer for next frame.
+    pub fn select(&mut self, entity: usize, distance: f32, switch: f32) -> bool {
+        let far = use_impostor(self.far.contains(&entity), distance, switch);
+        if far {
+            self.far.insert(entity);
+        } else {
+            self.far.remove(&entity);
+        }
+        far
+    }
+    pub fn push(&mut self, model_key: CacheKey, instance: ImpostorInstance) {
+        self.batch.entry(model_key).or_default().push(instance);
+    }
+
+    /// Builds the pipeline if needed and writes this frame's instances.
+    pub fn upload(&mut self, model_manager: &mut ModelManager) {
+        self.buffers.begin_frame();
+        if self.batch.is_empty() {
+            return;
+        }
+        if self.pipeline.is_none() {
+            self.pipeline = self
+                .create_pipeline(model_manager)
+                .map_err(|e| log_warning!("impostor pipeline: {}", e))
+                .ok();
+        }
+        for (key, instances) in &self.batch {
+            self.buffers
+                .write(&model_manager.device, &model_manager.queue, *key, instances);
+        }
+    }
+    fn create_pipeline(
+        &self,
+        model_manager: &mut ModelManager,
+    ) -> Result<Arc<wgpu::RenderPipeline>, EngineError> {
+        let device = &model_manager.device;
+        let shader = model_manager
+            .materials
+            .shaders
+            .load(device, "impostor.wgsl")?;
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("impostor"),
+            bind_group_layouts: &[
+                RenderBindGroupLayouts::uniform(),
+                RenderBindGroupLayouts::texture(),
+            ],
+            push_constant_ranges: &[],
+        });
+        Ok(Arc::new(device.create_render_pipeline(
+            &wgpu::RenderPipelineDescriptor {
+                label: Some("impostor"),
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: Some("vs_main"),
+                    buffers: &[ImpostorInstance::LAYOUT],
+                    compilation_options: Default::default(),
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: Some("fs_main"),
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: self.format,
+                        blend: None,
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                    compilation_options: Default::default(),
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: Some(crate::RenderSettings::depth_state(
+                    crate::DepthVariant::Opaque,
+                )),
+                multisample: crate::RenderSettings::multisample(),
+                multiview: None,
+                cache: None,
+            },
+        )))
+    }
+
+    /// One instanced draw per atlas.
+    pub fn draw(
+        &self,
+        rpass: &mut wgpu::RenderPass,
+        models: &ModelManager,
+        uniform_bind_group: &wgpu::BindGroup,
+    ) {
+        let Some(pipeline) = &self.pipeline else {
+            return;
+        };
+        rpass.set_pipeline(pipeline);
+        rpass.set_bind_group(0, uniform_bind_group, &[]);
+        for (key, data) in self.buffers.iter() {
+            let Some(impostor) = models.impostors.get(key) else {
+                continue;
+            };
+            crate::gpu_scope!(Draw, "impostor", format!("model key {}", key.id()));
+            rpass.set_bind_group(1, &impostor.bind_group, &[]);
+            rpass.set_vertex_buffer(0, data.buffer.slice(..));
+            rpass.draw(0..6, 0..data.count);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{LodSelection, ModelLod};
+
+    fn meta(angles: u32, crossfade: bool) -> ImpostorMeta {
+        let (columns, rows) = atlas_grid(angles);
+        ImpostorMeta {
+            angles,
+            columns,
+            rows,
+            cell_size: 128,
+            size: 12.5,
+            center: [0.0, 6.0, 0.5],
+            distance: 150.0,
+            crossfade,
+        }
+    }
+
+    fn close(a: (u32, u32, f32), b: (u32, u32, f32)) -> bool {
+        a.0 == b.0 && a.1 == b.1 && (a.2 - b.2).abs() < 1e-4
+    }
+
+    #[test]
+    fn azimuths_pick_cells_and_wrap_around() {
+        assert!((azimuth(Vec3::Z) - 0.0).abs() < 1e-4);
+        assert!((azimuth(Vec3::X) - 90.0).abs() < 1e-4);
+        assert!((azimuth(-Vec3::Z) - 180.0).abs() < 1e-4);
+        assert!((azimuth(-Vec3::X) - 270.0).abs() < 1e-4);
+        // Height doesn't change the view around the horizon.
+        assert!((azimuth(Vec3::new(1.0, 5.0, 0.0)) - 90.0).abs() < 1e-4);
+
+        for (azimuth, cells) in [
+            (0.0, (0, 1, 0.0)),
+            (22.5, (0, 1, 0.5)),
+            (45.0, (1, 2, 0.0)),
+            (330.0, (7, 0, 0.3333)),
+            // Past the last cell it blends back into the first.
+            (359.0, (7, 0, 0.9778)),
+            (360.0, (0, 1, 0.0)),
+            (-10.0, (7, 0, 0.7778)),
+            (725.0, (0, 1, 0.1111)),
+        ] {
+            let got = azimuth_cells(azimuth, 8);
+            assert!(close(got, cells), "{}: {:?}", azimuth, got);
+        }
+        assert!(close(azimuth_cells(350.0, 16), (15, 0, 0.5556)));
+        assert!(close(azimuth_cells(123.0, 1), (0, 0, 0.3417)));
+    }
+
+    #[test]
+    fn crossfade_weights_blend_toward_the_next_cell() {
+        let fading = meta(8, true);
+        let mut previous = 0.0;
+        for step in 0..45 {
+            let (a, b, weight) = fading.cells(step as f32);
+            assert_eq!((a, b), (0, 1));
+            assert!(weight >= previous && weight < 1.0);
+            assert!((weight - step as f32 / 45.0).abs() < 1e-4);
+            previous = weight;
+        }
+        // Continuous across the cell boundary: full weight on 1 is the start of cell 1.
+        assert!(close(fading.cells(45.0), (1, 2, 0.0)));
+
+        // Without crossfade the nearest cell is used alone, switching half way.
+        let snapping = meta(8, false);
+        assert_eq!(snapping.cells(22.0), (0, 0, 0.0));
+        assert_eq!(snapping.cells(23.0), (1, 1, 0.0));
+        assert_eq!(snapping.cells(350.0), (0, 0, 0.0));
+        assert_eq!(snapping.cells(330.0), (7, 7, 0.0));
+    }
+
+    #[test]
+    fn atlas_metadata_round_trips() {
+        assert_eq!(atlas_grid(8), (3, 3));
+        assert_eq!(atlas_grid(16), (4, 4));
+        assert_eq!(atlas_grid(0), (1, 1));
+
+        let meta = meta(16, false);
+        let source = meta.to_ron().unwrap();
+        assert_eq!(ImpostorMeta::parse(&source).unwrap(), meta);
+
+        crate::assets::loader::test_root();
+        let file = "impostor_round_trip.obj";
+        assert_eq!(ImpostorMeta::load(file).unwrap(), None);
+        let atlas = RgbaImage::from_pixel(
+            meta.columns * 4,
+            meta.rows * 4,
+            image::Rgba([10, 20, 30, 0]),
+        );
+        meta.save(file, &atlas).unwrap();
+        assert_eq!(ImpostorMeta::load(file).unwrap(), Some(meta));
+        assert_eq!(
+            ImpostorMeta::atlas_path(file),
+            AssetPaths::model("impostor_round_trip.impostor.png")
+        );
+        let saved = image::open(ImpostorMeta::atlas_path(file))
+            .unwrap()
+            .to_rgba8();
+        assert_eq!(saved, atlas);
+
+        std::fs::write(ImpostorMeta::path(file), "(angles: \"eight\")").unwrap();
+        let message = ImpostorMeta::load(file).unwrap_err().to_string();
+        assert!(
+            message.contains("impostor_round_trip.impostor.ron"),
+            "{}",
+            message
+        );
+    }
+
+    #[test]
+    fn far_entities_switch_to_the_impostor_past_the_last_mesh_level() {
+        let lods = [50.0, 100.0].map(|distance| ModelLod {
+            distance,
+            meshes: Vec::new(),
+        });
+        let switch = 150.0;
+        let mut impostors = ImpostorBuffers::new(wgpu::TextureFormat::Rgba8UnormSrgb);
+        let mut levels = LodSelection::default();
+        // What render3d draws for an entity: the impostor or a mesh level.
+        let mut draw = |distance: f32| {
+            if impostors.select(1, distance, switch) {
+                None
+            } else {
+                Some(levels.select(1, distance, &lods))
+            }
+        };
+
+        let outward: Vec<_> = [10.0, 54.0, 56.0, 109.0, 111.0, 160.0, 166.0, 400.0]
+            .map(&mut draw)
+            .to_vec();
+        assert_eq!(
+            outward,
+            [
+                Some(0),
+                Some(0),
+                Some(1),
+                Some(1),
+                Some(2),
+                Some(2),
+                None,
+                None
+            ]
+        );
+        // Inside the band the impostor holds on the way back in.
+        let inward: Vec<_> = [140.0, 136.0, 134.0, 120.0].map(&mut draw).to_vec();
+        assert_eq!(inward, [None, None, Some(2), Some(2)]);
+        // And a camera hovering at the switch doesn't flicker.
+        let hovering: Vec<_> = [148.0, 152.0, 148.0, 152.0].map(&mut draw).to_vec();
+        assert_eq!(hovering, [Some(2); 4]);
+
+        assert!(use_impostor(false, 166.0, switch));
+        assert!(!use_impostor(false, 164.0, switch));
+        assert!(use_impostor(true, 136.0, switch));
+        assert!(!use_impostor(true, 134.0, switch));
+    }
+}
@@ -0,0 +1,104 @@
+//! Instances read from a storage buffer instead of vertex attributes. The [`crate::DrawList`]
+//! instance buffer is bound next to the materials in group 2, and the vertex shader fetches
+//! its [`crate::VertexInstance`] by `instance_index`, which counts from the draw's first
+//! instance. That frees attribute slots 5–14 and leaves room for more per-instance data.
+//! Adapters without storage buffers in the vertex stage keep the attribute path.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::RenderSettings;
+
+static INSTANCE_STORAGE: AtomicBool = AtomicBool::new(true);
+
+impl RenderSettings {
+    pub fn instance_storage() -> bool {
+        INSTANCE_STORAGE.load(Ordering::Relaxed)
+    }
+    /// Prefers the storage path where the device has it, from the next
+    /// [`crate::DrawList::prepare`] on. Off, every adapter uses the attribute path.
+    pub fn set_instance_storage(enabled: bool) {
+        INSTANCE_STORAGE.store(enabled, Ordering::Relaxed);
+    }
+}
+
+/// Shaders that define this function get an instance storage variant; the rest read their
+/// instances from attributes.
+pub const INSTANCE_VERTEX_FN: &str = "fn instance_vertex(";
+
+/// Where the instanced draws of a [`crate::DrawList`] read their instances from.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum InstanceFetch {
+    /// [`crate::VertexInstance::LAYOUT`] in vertex buffer slot 1.
+    #[default]
+    Attributes,
+    /// The same instances as a storage array in group 2.
+    Storage,
+}
+
+impl InstanceFetch {
+    /// Name of the shader variant built with [`InstanceFetch::SHADER_ENTRY`].
+    pub const VARIANT: &'static str = "instance_storage";
+    /// Storage buffers group 2 holds in the vertex stage: materials and instances.
+    pub const STORAGE_BUFFERS: u32 = 2;
+    /// Binding and `vs_instance_storage` entry point appended to a shader that defines
+    /// [`INSTANCE_VERTEX_FN`]. `StoredInstance` mirrors [`crate::VertexInstance`], its
+    /// padded fields widened to `vec4`.
+    pub const SHADER_ENTRY: &'static str = "struct StoredInstance {
+    model:       mat4x4<f32>,
+    color:       vec4<f32>,
+    translation: vec4<f32>,
+    uv_offset:   vec4<f32>,
+    normal:      vec4<f32>,
+    tangent:     vec4<f32>,
+    material:    vec4<u32>,
+};
+@group(2) @binding(1) var<storage, read> instances: array<StoredInstance>;
+
+@vertex
+fn vs_instance_storage(
+    vertex: VertexInput,
+    @builtin(instance_index) instance_index: u32,
+) -> VertexOutput {
+    let stored = instances[instance_index];
+    var instance: InstanceInput;
+    instance.model_0 = stored.model[0];
+    instance.model_1 = stored.model[1];
+    instance.model_2 = stored.model[2];
+    instance.model_3 = stored.model[3];
+    instance.color = stored.color;
+    instance.translation = stored.translation.xyz;
+    instance.uv_offset = stored.uv_offset.xy;
+    instance.normal = stored.normal.xyz;
+    instance.tangent = stored.tangent.xyz;
+    instance.material = stored.material.xy;
+    return instance_vertex(vertex, instance);
+}
+";
+
+    /// Whether `limits` allow group 2 of the storage path in the vertex stage. Downlevel
+    /// adapters, WebGL2 among them, report no storage buffers there.
+    pub fn supported(limits: &wgpu::Limits) -> bool {
+        limits.max_storage_buffers_per_shader_stage >= Self::STORAGE_BUFFERS
+    }
+    /// The storage path when the device supports it and
+    /// [`RenderSettings::instance_storage`] is on, the attribute path otherwise.
+    pub fn select(limits: &wgpu::Limits, enabled: bool) -> Self {
+        if enabled && Self::supported(limits) {
+            InstanceFetch::Storage
+        } else {
+            InstanceFetch::Attributes
+        }
+    }
+    pub fn for_device(device: &wgpu::Device) -> Self {
+        Self::select(&device.limits(), RenderSettings::instance_storage())
+    }
+    /// Usage of an instance buffer that serves both paths on `device`.
+    pub fn buffer_usage(limits: &wgpu::Limits) -> wgpu::BufferUsages {
+        let usage = wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST;
+        if Self::supported(limits) {
+            usage | wgpu::BufferUsages::STORAGE
+        } else {
+            usage
+        }
+    }
+}
@@ -1,4 +1,17 @@
-use crate::{RenderBindGroupLayouts, WgpuBuffer};
+use serde::{Deserialize, Serialize};
+
+use crate::{EnvironmentReflection, RenderBindGroupLayouts, WgpuBuffer};
+
+/// Where the ambient term's color comes from; its intensity is always [`Light`]'s own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum AmbientSource {
+    /// The color given to [`Light::set_ambient`].
+    #[default]
+    Fixed,
+    /// The average color of the environment, see [`EnvironmentReflection::average_color`].
+    /// The fixed color stands in until the environment's average has been read back.
+    Environment,
+}
 
 #[repr(C)]
 #[derive(
@@ -9,6 +22,8 @@ pub struct LightUniform {
     _pad0: f32,
     color: [f32; 3],
     _pad1: f32,
+    ambient: [f32; 3],
+    _pad2: f32,
 }
 
 impl LightUniform {
@@ -18,6 +33,8 @@ impl LightUniform {
             _pad0: 1.0,
             color: [1.0, 1.0, 1.0],
             _pad1: 1.0,
+            ambient: [0.0; 3],
+            _pad2: 1.0,
         }
     }
 }
@@ -26,6 +43,12 @@ impl LightUniform {
 pub struct Light {
     position: cgmath::Vector3<f32>,
     color: cgmath::Vector3<f32>,
+    ambient_color: [f32; 3],
+    ambient_intensity: f32,
+    ambient_source: AmbientSource,
+    /// The environment's average color, once read back while the source is
+    /// [`AmbientSource::Environment`].
+    environment_color: Option<[f32; 3]>,
     bind_group: wgpu::BindGroup,
     uniform_buffer: crate::WgpuBuffer,
 }
@@ -34,6 +57,8 @@ impl Light {
     pub const LAYOUT: wgpu::VertexBufferLayout<'static> = LightUniform::LAYOUT;
     pub const CENTER: cgmath::Vector3<f32> = cgmath::Vector3::new(1.0, 100.0, 1.0);
     pub const RADIUS: f32 = 360.0;
+    pub const DEFAULT_AMBIENT_COLOR: [f32; 3] = [1.0, 1.0, 1.0];
+    pub const DEFAULT_AMBIENT_INTENSITY: f32 = 0.1;
     pub const BUFFER_BINDING: crate::BindGroupBindingType = crate::BindGroupBindingType {
         binding: wgpu::BindingType::Buffer {
             ty: wgpu::BufferBindingType::Uniform,
@@ -47,30 +72,37 @@ impl Light {
     pub fn new(device: &wgpu::Device) -> Result<Self, crate::EngineError> {
         let position: cgmath::Vector3<f32> = Self::CENTER.into();
         let color: cgmath::Vector3<f32> = [1.0, 1.0, 1.0].into();
-        let bind_group_layout = RenderBindGroupLayouts::light();
         let uniform_buffer = WgpuBuffer::from_data(
             device,
             &[LightUniform::new()],
             wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
             Some("light uniform buffer"),
         );
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: uniform_buffer.get().as_entire_binding(),
-            }],
-            label: None,
-        });
+        let bind_group = Self::create_bind_group(device, &uniform_buffer);
 
         Ok(Light {
             position,
             color,
+            ambient_color: Self::DEFAULT_AMBIENT_COLOR,
+            ambient_intensity: Self::DEFAULT_AMBIENT_INTENSITY,
+            ambient_source: AmbientSource::Fixed,
+            environment_color: None,
             bind_group,
             uniform_buffer,
         })
     }
 
+    fn create_bind_group(device: &wgpu::Device, uniform_buffer: &WgpuBuffer) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: RenderBindGroupLayouts::light(),
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.get().as_entire_binding(),
+            }],
+            label: None,
+        })
+    }
+
     pub fn set_position(&mut self, new_position: cgmath::Vector3<f32>) {
         self.position = new_position;
     }
@@ -82,24 +114,60 @@ impl Light {
         self.position.x = Light::CENTER.x + Light::RADIUS * cos as f32;
         self.position.z = Light::CENTER.z + Light::RADIUS * sin as f32;
     }
+    /// Lights every surface with `color` times `intensity` on top of the directional light,
+    /// so faces turned away from it aren't black. Switches to [`AmbientSource::Fixed`].
+    pub fn set_ambient(&mut self, color: [f32; 3], intensity: f32) {
+        self.ambient_color = color;
+        self.ambient_intensity = intensity.max(0.0);
+        self.ambient_source = AmbientSource::Fixed;
+    }
+    pub fn set_ambient_source(&mut self, source: AmbientSource) {
+        self.ambient_source = source;
+    }
+    pub fn ambient_source(&self) -> AmbientSource {
+        self.ambient_source
+    }
+    /// The ambient color and intensity the next upload uses.
+    pub fn ambient(&self) -> ([f32; 3], f32) {
+        let color = match (self.ambient_source, self.environment_color) {
+            (AmbientSource::Environment, Some(color)) => color,
+            _ => self.ambient_color,
+        };
+        (color, self.ambient_intensity)
+    }
+    /// Takes the average color of `environment` once it's read back, when the ambient
+    /// follows it. A newly loaded environment is followed as soon as its average arrives.
+    pub fn follow_environment(&mut self, environment: &EnvironmentReflection) {
+        if self.ambient_source == AmbientSource::Environment {
+            self.environment_color = environment.average_color();
+        }
+    }
     pub fn buffer(&self) -> &crate::WgpuBuffer {
         &self.uniform_buffer
     }
     pub fn upload(&mut self, queue: &wgpu::Queue, device: &wgpu::Device) {
-        self.uniform_buffer
+        let grew = self
+            .uniform_buffer
             .write_data(queue, device, &[self.uniform()], None);
+        if grew {
+            self.bind_group = Self::create_bind_group(device, &self.uniform_buffer);
+        }
     }
     pub fn bind_group(&self) -> &wgpu::BindGroup {
         &self.bind_group
     }
+    /// What shaders read; the ambient color comes premultiplied by its intensity.
     pub fn uniform(&self) -> LightUniform {
         let position: [f32; 3] = [self.position.x, self.position.y, self.position.z];
         let color: [f32; 3] = [self.color.x, self.color.y, self.color.z];
+        let (ambient, intensity) = self.ambient();
         LightUniform {
             position,
             _pad0: 1.0,
             color,
             _pad1: 1.0,
+            ambient: ambient.map(|c| c * intensity),
+            _pad2: 1.0,
         }
     }
 }
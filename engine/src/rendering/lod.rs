@@ -0,0 +1,139 @@
+//! Distance based levels of detail for models. A [`crate::Model`] may carry coarser mesh
+//! sets in [`crate::Model::lods`], each drawn past its distance from the camera; level 0 is
+//! the model itself. [`LodSelection`] picks an entity's level with some hysteresis, so a
+//! camera hovering at a threshold doesn't flip the entity between two meshes every frame.
+
+use std::collections::HashMap;
+
+use glam::{UVec3, Vec3};
+use serde::{Deserialize, Serialize};
+
+use crate::{MeshAsset, ModelLod, Vertex, AABB};
+
+/// Fraction of a threshold the camera has to cross it by before the level changes.
+pub const LOD_HYSTERESIS: f32 = 0.1;
+
+/// One generated level in a model sidecar, see [`crate::ModelSidecar::lods`].
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LodSettings {
+    /// Camera distance beyond which this level replaces the one before.
+    pub distance: f32,
+    /// Cells along each side of the model's bounds that [`cluster_vertices`] merges
+    /// vertices in; fewer cells, coarser mesh.
+    pub cells: u32,
+}
+
+/// Level of `lods`, sorted by distance, for an entity `distance` away that drew at
+/// `previous` last frame. Each threshold moves [`LOD_HYSTERESIS`] outward when stepping
+/// coarser and inward when stepping finer.
+pub fn select_lod(previous: usize, distance: f32, lods: &[ModelLod]) -> usize {
+    let mut level = previous.min(lods.len());
+    while level < lods.len() && distance > lods[level].distance * (1.0 + LOD_HYSTERESIS) {
+        level += 1;
+    }
+    while level > 0 && distance < lods[level - 1].distance * (1.0 - LOD_HYSTERESIS) {
+        level -= 1;
+    }
+    level
+}
+
+/// Instances drawn at each level last frame, for the dev overlay.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct LodStats {
+    /// Visible instances per level, level 0 first.
+    pub instances: Vec<u32>,
+}
+
+/// The level each entity drew at last frame and this frame's counts.
+#[derive(Debug, Default)]
+pub struct LodSelection {
+    levels: HashMap<usize, usize>,
+    stats: LodStats,
+}
+
+impl LodSelection {
+    /// Resets the counts; the remembered levels stay for the hysteresis.
+    pub fn clear(&mut self) {
+        self.stats.instances.clear();
+    }
+    /// Level `entity`, `distance` from the camera, draws its model with `lods` at; remembers
+    /// the answer for next frame.
+    pub fn select(&mut self, entity: usize, distance: f32, lods: &[ModelLod]) -> usize {
+        let level = if lods.is_empty() {
+            0
+        } else {
+            let previous = self.levels.get(&entity).copied().unwrap_or(0);
+            let level = select_lod(previous, distance, lods);
+            self.levels.insert(entity, level);
+            level
+        };
+        let counts = &mut self.stats.instances;
+        if counts.len() <= level {
+            counts.resize(level + 1, 0);
+        }
+        counts[level] += 1;
+        level
+    }
+    pub fn stats(&self) -> &LodStats {
+        &self.stats
+    }
+}
+
+/// A coarser copy of `mesh` by vertex clustering: the bounds are split into `cells` cells
+/// per side, the vertices in each cell merge into their average, and triangles left with
+/// fewer than three corners are dropped. Naive; UV seams and thin features suffer.
+pub fn cluster_vertices(mesh: &MeshAsset, cells: u32) -> MeshAsset {
+    if mesh.vertices.is_empty() {
+        return mesh.clone();
+    }
+    let cells = cells.max(1);
+    let aabb = AABB::from_vertices(&mesh.vertices);
+    let size = (aabb.max - aabb.min).max(Vec3::splat(f32::EPSILON));
+    let cell_of = |position: [f32; 3]| {
+        let t = (Vec3::from(position) - aabb.min) / size * cells as f32;
+        t.as_uvec3().min(UVec3::splat(cells - 1))
+    };
+
+    let add = |a: &mut [f32], b: &[f32]| a.iter_mut().zip(b).for_each(|(a, b)| *a += b);
+    let mut clusters: HashMap<UVec3, u32> = HashMap::new();
+    let mut sums: Vec<(Vertex, f32)> = Vec::new();
+    let mut remap = Vec::with_capacity(mesh.vertices.len());
+    for vertex in &mesh.vertices {
+        let index = *clusters.entry(cell_of(vertex.position)).or_insert_with(|| {
+            let first = Vertex {
+                surface: vertex.surface,
+                ..Vertex::default()
+            };
+            sums.push((first, 0.0));
+            sums.len() as u32 - 1
+        });
+        let (sum, count) = &mut sums[index as usize];
+        add(&mut sum.position, &vertex.position);
+        add(&mut sum.color, &vertex.color);
+        add(&mut sum.tex_coords, &vertex.tex_coords);
+        add(&mut sum.normal, &vertex.normal);
+        add(&mut sum.tangent, &vertex.tangent);
+        *count += 1.0;
+        remap.push(index);
+    }
+
+    let vertices = sums
+        .into_iter()
+        .map(|(sum, count)| Vertex {
+            position: (Vec3::from(sum.position) / count).into(),
+            color: (Vec3::from(sum.color) / count).into(),
+            tex_coords: [sum.tex_coords[0] / count, sum.tex_coords[1] / count],
+            normal: Vec3::from(sum.normal).normalize_or_zero().into(),
+            tangent: Vec3::from(sum.tangent).normalize_or_zero().into(),
+            surface: sum.surface,
+        })
+        .collect();
+    let indices = mesh
+        .indices
+        .chunks_exact(3)
+        .map(|t| [t[0], t[1], t[2]].map(|i| remap[i as usize]))
+        .filter(|[a, b, c]| a != b && b != c && a != c)
+        .flatten()
+        .collect();
+    MeshAsset { vertices, indices }
+}
@@ -1,3 +1,15 @@
+pub mod depth;
+pub use depth::*;
+
+pub mod exposure;
+pub use exposure::*;
+
+pub mod post_process;
+pub use post_process::*;
+
+pub mod bloom;
+pub use bloom::*;
+
 pub mod environment;
 pub use environment::*;
 
@@ -7,6 +19,12 @@ pub use pipeline::*;
 pub mod shader;
 pub use shader::*;
 
+pub mod shader_constants;
+pub use shader_constants::*;
+
+pub mod msaa;
+pub use msaa::*;
+
 pub mod surface;
 pub use surface::*;
 
@@ -19,20 +37,77 @@ pub use light::*;
 pub mod render_target;
 pub use render_target::*;
 
+pub mod transient;
+pub use transient::*;
+
 pub mod traits;
 pub use traits::*;
 
 pub mod aabb;
 pub use aabb::*;
 
+pub mod impostor;
+pub use impostor::*;
+
+pub mod billboard;
+pub use billboard::*;
+
+pub mod lod;
+pub use lod::*;
+
+pub mod object_data;
+pub use object_data::*;
+
+pub mod animation;
+pub use animation::*;
+
+pub mod particles;
+pub use particles::*;
+
+pub mod transparent;
+pub use transparent::*;
+
+pub mod draw_list;
+pub use draw_list::*;
+
+pub mod instance_storage;
+pub use instance_storage::*;
+
+pub mod prepass;
+pub use prepass::*;
+
+pub mod entity_id;
+pub use entity_id::*;
+
 pub mod render3d;
 pub use render3d::*;
 
+pub mod record;
+pub use record::*;
+
+pub mod block;
+pub use block::*;
+
 pub mod chunk;
 pub use chunk::*;
 
+pub mod chunk_store;
+pub use chunk_store::*;
+
 pub mod debug;
 pub use debug::*;
 
+pub mod fluid;
+pub use fluid::*;
+
+pub mod water;
+pub use water::*;
+
 pub mod terrain;
 pub use terrain::*;
+
+pub mod terrain_blend;
+pub use terrain_blend::*;
+
+pub mod terrain_gen;
+pub use terrain_gen::*;
@@ -0,0 +1,60 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use crate::{log_warning, RenderSettings};
+
+static SAMPLE_COUNT: AtomicU32 = AtomicU32::new(1);
+
+/// Sample counts [`RenderSettings::set_sample_count`] accepts.
+pub const SAMPLE_COUNTS: [u32; 4] = [1, 2, 4, 8];
+
+impl RenderSettings {
+    /// MSAA samples per pixel of the scene target and every pipeline drawing into it.
+    pub fn sample_count() -> u32 {
+        SAMPLE_COUNT.load(Ordering::Relaxed)
+    }
+    /// Switches the scene sample count. Pipelines and targets built before the switch keep
+    /// the old count until rebuilt, e.g. with `ModelManager::apply_sample_count`; validate
+    /// first with [`RenderSettings::supported_sample_count`].
+    pub fn set_sample_count(count: u32) {
+        SAMPLE_COUNT.store(count, Ordering::Relaxed);
+    }
+    /// Multisample state for pipelines drawing into the scene target.
+    pub fn multisample() -> wgpu::MultisampleState {
+        wgpu::MultisampleState {
+            count: Self::sample_count(),
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        }
+    }
+    /// `requested` if `device` can render every one of `formats` with that many samples,
+    /// 1 with a warning otherwise. Counts other than 1 and 4 need
+    /// `TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES` on the device.
+    pub fn supported_sample_count(
+        adapter: &wgpu::Adapter,
+        device: &wgpu::Device,
+        formats: &[wgpu::TextureFormat],
+        requested: u32,
+    ) -> u32 {
+        if requested == 1 {
+            return 1;
+        }
+        let adapter_specific = device
+            .features()
+            .contains(wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES);
+        let supported = SAMPLE_COUNTS.contains(&requested)
+            && formats.iter().all(|format| {
+                let features = if adapter_specific {
+                    adapter.get_texture_format_features(*format)
+                } else {
+                    format.guaranteed_format_features(device.features())
+                };
+                features.flags.sample_count_supported(requested)
+            });
+        if supported {
+            requested
+        } else {
+            log_warning!("{}x MSAA unsupported for {:?}, using 1", requested, formats);
+            1
+        }
+    }
+}
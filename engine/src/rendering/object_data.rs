@@ -0,0 +1,513 @@
+//! Per-draw object data for entities drawn on their own. Instead of a one-element instance
+//! buffer each, their data goes into one frame-sized buffer and every draw picks its slot,
+//! through a push constant where the adapter has them and a dynamic uniform offset where
+//! it doesn't.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use glam::Mat4;
+
+use crate::{BindGroup, CacheKey, CacheStorage, Material, ModelManager, WgpuBuffer};
+
+static OBJECT_THRESHOLD: AtomicUsize = AtomicUsize::new(1);
+
+pub struct ObjectDataSettings;
+
+impl ObjectDataSettings {
+    /// Models with at most this many visible instances are drawn through the object path.
+    /// 0 sends everything through instancing.
+    pub fn threshold() -> usize {
+        OBJECT_THRESHOLD.load(Ordering::Relaxed)
+    }
+    pub fn set_threshold(threshold: usize) {
+        OBJECT_THRESHOLD.store(threshold, Ordering::Relaxed);
+    }
+}
+
+/// Mirrors `PerObjectData` in the shaders; 160 bytes, a multiple of 16 so it works as a
+/// uniform and as a storage array element alike.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable, Default, PartialEq)]
+pub struct PerObjectData {
+    pub model: [[f32; 4]; 4],      // 0–63
+    pub prev_model: [[f32; 4]; 4], // 64–127
+    pub tint: [f32; 4],            // 128–143
+    pub material_idx: u32,         // 144–147
+    pub flags: u32,                // 148–151
+    pub _pad: [u32; 2],            // 152–159
+}
+
+impl PerObjectData {
+    pub const SIZE: u64 = std::mem::size_of::<PerObjectData>() as u64;
+    /// `prev_model` holds last frame's transform rather than a copy of `model`.
+    pub const HAS_PREVIOUS: u32 = 1;
+    /// Drawn brightened with a rim outline, see [`crate::World::select`].
+    pub const SELECTED: u32 = 2;
+
+    pub fn new(model: Mat4, previous: Option<Mat4>, tint: [f32; 4], material_idx: u32) -> Self {
+        Self {
+            model: model.to_cols_array_2d(),
+            prev_model: previous.unwrap_or(model).to_cols_array_2d(),
+            tint,
+            material_idx,
+            flags: if previous.is_some() {
+                Self::HAS_PREVIOUS
+            } else {
+                0
+            },
+            _pad: [0; 2],
+        }
+    }
+}
+
+/// How a draw tells the shader which [`PerObjectData`] slot is its own.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ObjectIndexing {
+    /// A `u32` push constant indexing a storage array.
+    PushConstants,
+    /// A uniform binding moved to the slot with a dynamic offset.
+    DynamicOffset,
+}
+
+impl ObjectIndexing {
+    pub const PUSH_CONSTANT_SIZE: u32 = std::mem::size_of::<u32>() as u32;
+
+    /// Push constants when the device has them with room for the index, the dynamic
+    /// offset path everywhere else.
+    pub fn select(features: wgpu::Features, limits: &wgpu::Limits) -> Self {
+        if features.contains(wgpu::Features::PUSH_CONSTANTS)
+            && limits.max_push_constant_size >= Self::PUSH_CONSTANT_SIZE
+        {
+            ObjectIndexing::PushConstants
+        } else {
+            ObjectIndexing::DynamicOffset
+        }
+    }
+    pub fn for_device(device: &wgpu::Device) -> Self {
+        Self::select(device.features(), &device.limits())
+    }
+    /// Bytes between slots: tightly packed for the storage array, rounded up to the
+    /// uniform offset alignment for dynamic offsets.
+    pub fn stride(&self, uniform_alignment: u32) -> u64 {
+        match self {
+            ObjectIndexing::PushConstants => PerObjectData::SIZE,
+            ObjectIndexing::DynamicOffset => {
+                PerObjectData::SIZE.next_multiple_of(uniform_alignment.max(1) as u64)
+            }
+        }
+    }
+    pub fn buffer_usage(&self) -> wgpu::BufferUsages {
+        let binding = match self {
+            ObjectIndexing::PushConstants => wgpu::BufferUsages::STORAGE,
+            ObjectIndexing::DynamicOffset => wgpu::BufferUsages::UNIFORM,
+        };
+        binding | wgpu::BufferUsages::COPY_DST
+    }
+    pub fn push_constant_ranges(&self) -> &'static [wgpu::PushConstantRange] {
+        match self {
+            ObjectIndexing::PushConstants => &[wgpu::PushConstantRange {
+                stages: wgpu::ShaderStages::VERTEX,
+                range: 0..Self::PUSH_CONSTANT_SIZE,
+            }],
+            ObjectIndexing::DynamicOffset => &[],
+        }
+    }
+    /// Name of the shader variant built with [`ObjectIndexing::shader_entry`].
+    pub fn variant(&self) -> &'static str {
+        match self {
+            ObjectIndexing::PushConstants => "object_push",
+            ObjectIndexing::DynamicOffset => "object_dynamic",
+        }
+    }
+    /// Binding and `vs_object` entry point appended to a shader that defines
+    /// [`OBJECT_VERTEX_FN`], one per indexing mode since the bindings differ in type.
+    pub fn shader_entry(&self) -> &'static str {
+        match self {
+            ObjectIndexing::PushConstants => {
+                "struct ObjectIndex { index: u32 };
+var<push_constant> object_index: ObjectIndex;
+@group(2) @binding(1) var<storage, read> objects: array<PerObjectData>;
+
+@vertex
+fn vs_object(vertex: VertexInput) -> VertexOutput {
+    return object_vertex(vertex, objects[object_index.index]);
+}
+"
+            }
+            ObjectIndexing::DynamicOffset => {
+                "@group(2) @binding(1) var<uniform> object: PerObjectData;
+
+@vertex
+fn vs_object(vertex: VertexInput) -> VertexOutput {
+    return object_vertex(vertex, object);
+}
+"
+            }
+        }
+    }
+}
+
+/// Shaders that define this function get an object path variant; the rest stay instanced.
+pub const OBJECT_VERTEX_FN: &str = "fn object_vertex(";
+
+/// Which path a model's visible instances take this frame.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DrawPath {
+    Instanced,
+    Object,
+}
+
+impl DrawPath {
+    pub fn route(instances: usize, threshold: usize, has_object_pipeline: bool) -> Self {
+        if has_object_pipeline && instances > 0 && instances <= threshold {
+            DrawPath::Object
+        } else {
+            DrawPath::Instanced
+        }
+    }
+}
+
+/// Lays `objects` out `stride` bytes apart, zero-filling the gaps.
+pub fn pack_objects(objects: &[PerObjectData], stride: u64) -> Vec<u8> {
+    let stride = stride.max(PerObjectData::SIZE) as usize;
+    let mut bytes = vec![0u8; objects.len() * stride];
+    for (slot, object) in bytes.chunks_exact_mut(stride).zip(objects) {
+        slot[..PerObjectData::SIZE as usize].copy_from_slice(bytemuck::bytes_of(object));
+    }
+    bytes
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct ObjectDraw {
+    pub model_key: CacheKey,
+    pub object: u32,
+}
+
+/// This frame's object path draws and the buffer they index.
+#[derive(Debug)]
+pub struct ObjectBuffer {
+    indexing: ObjectIndexing,
+    stride: u64,
+    buffer: WgpuBuffer,
+    bind_group: Option<wgpu::BindGroup>,
+    objects: Vec<PerObjectData>,
+    draws: Vec<ObjectDraw>,
+    /// Object path pipeline per material key; `None` when its shader has no variant.
+    pipelines: HashMap<CacheKey, Option<Arc<wgpu::RenderPipeline>>>,
+    previous: HashMap<usize, Mat4>,
+    current: HashMap<usize, Mat4>,
+}
+
+impl ObjectBuffer {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let indexing = ObjectIndexing::for_device(device);
+        let stride = indexing.stride(device.limits().min_uniform_buffer_offset_alignment);
+        let buffer = WgpuBuffer::from_data(
+            device,
+            &vec![0u8; stride as usize],
+            indexing.buffer_usage(),
+            Some("per-object data buffer"),
+        );
+        Self {
+            indexing,
+            stride,
+            buffer,
+            bind_group: None,
+            objects: Vec::new(),
+            draws: Vec::new(),
+            pipelines: HashMap::new(),
+            previous: HashMap::new(),
+            current: HashMap::new(),
+        }
+    }
+    pub fn indexing(&self) -> ObjectIndexing {
+        self.indexing
+    }
+    pub fn draws(&self) -> &[ObjectDraw] {
+        &self.draws
+    }
+
+    /// Starts a frame: this frame's transforms become next frame's previous ones.
+    pub fn clear(&mut self) {
+        self.objects.clear();
+        self.draws.clear();
+        self.previous = std::mem::take(&mut self.current);
+    }
+    pub fn push(
+        &mut self,
+        entity: usize,
+        model_key: CacheKey,
+        model: Mat4,
+        tint: [f32; 4],
+        material_idx: u32,
+        flags: u32,
+    ) {
+        let previous = self.previous.get(&entity).copied();
+        self.current.insert(entity, model);
+        self.draws.push(ObjectDraw {
+            model_key,
+            object: self.objects.len() as u32,
+        });
+        let mut object = PerObjectData::new(model, previous, tint, material_idx);
+        object.flags |= flags;
+        self.objects.push(object);
+    }
+
+    /// Drops the object path pipelines so they're rebuilt on next use, e.g. after a depth
+    /// policy switch.
+    pub fn clear_pipelines(&mut self) {
+        self.pipelines.clear();
+    }
+    /// Object path pipeline for `material`, built the first time it's asked for.
+    pub fn pipeline(
+        &mut self,
+        model_manager: &mut ModelManager,
+        material: &Material,
+    ) -> Option<Arc<wgpu::RenderPipeline>> {
+        let indexing = self.indexing;
+        self.pipelines
+            .entry(material.asset.key)
+            .or_insert_with(|| {
+                let materials = &mut model_manager.materials;
+                material
+                    .asset
+                    .object_pipeline(
+                        &model_manager.device,
+                        &mut materials.shaders,
+                        &mut materials.pipelines,
+                        indexing,
+                    )
+                    .unwrap_or_else(|e| {
+                        crate::log_warning!("{}: {}", material.asset.name, e);
+                        None
+                    })
+            })
+            .clone()
+    }
+
+    /// Writes this frame's objects and rebinds them next to the material storage, which
+    /// either buffer growing would otherwise leave stale.
+    pub fn upload(&mut self, queue: &wgpu::Queue, device: &wgpu::Device, materials: &WgpuBuffer) {
+        if self.draws.is_empty() {
+            return;
+        }
+        let bytes = pack_objects(&self.objects, self.stride);
+        self.buffer.write_data(queue, device, &bytes, Some(0));
+        self.bind_group = Some(BindGroup::object_data(
+            device,
+            self.indexing,
+            materials,
+            &self.buffer,
+        ));
+    }
+
+    /// Replays the object draws, one slot each, and puts the material storage back on
+    /// group 2 for whatever is drawn next.
+    pub fn draw(
+        &self,
+        rpass: &mut wgpu::RenderPass,
+        models: &ModelManager,
+        uniform_bind_group: &wgpu::BindGroup,
+    ) {
+        let Some(bind_group) = self.bind_group.as_ref() else {
+            return;
+        };
+        if self.draws.is_empty() {
+            return;
+        }
+        rpass.set_bind_group(0, uniform_bind_group, &[]);
+        for draw in &self.draws {
+            let Some(model) = models.get(&draw.model_key) else {
+                continue;
+            };
+            let Some(mat) = &model.instance.material else {
+                continue;
+            };
+            let Some(Some(pipeline)) = self.pipelines.get(&mat.asset.key) else {
+                continue;
+            };
+            let mesh = &model.instance.mesh;
+            crate::gpu_scope!(
+                Draw,
+                model.name,
+                format!("material {}, object {}", mat.asset.name, draw.object)
+            );
+
+            rpass.set_pipeline(pipeline);
+            match self.indexing {
+                ObjectIndexing::PushConstants => {
+                    rpass.set_bind_group(2, bind_group, &[]);
+                    rpass.set_push_constants(
+                        wgpu::ShaderStages::VERTEX,
+                        0,
+                        bytemuck::bytes_of(&draw.object),
+                    );
+                }
+                ObjectIndexing::DynamicOffset => {
+                    let offset = draw.object as u64 * self.stride;
+                    rpass.set_bind_group(2, bind_group, &[offset as u32]);
+                }
+            }
+            rpass.set_bind_group(3, mat.bind_group.as_ref(), &[]);
+            rpass.set_vertex_buffer(0, mesh.vertex_buffer.get().slice(..));
+            rpass.set_index_buffer(mesh.index_buffer.get().slice(..), mesh.index_format);
+            rpass.draw_indexed(0..mesh.index_count, 0, 0..1);
+        }
+        rpass.set_bind_group(2, &models.materials.storage_bind_group, &[]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::mem::offset_of;
+
+    use glam::Vec3;
+    use wgpu::naga;
+
+    use super::*;
+
+    #[test]
+    fn indexing_falls_back_to_dynamic_offsets() {
+        let limits = |max_push_constant_size| wgpu::Limits {
+            max_push_constant_size,
+            ..wgpu::Limits::downlevel_defaults()
+        };
+        let push = wgpu::Features::PUSH_CONSTANTS;
+        let none = wgpu::Features::empty();
+        assert_eq!(
+            ObjectIndexing::select(push, &limits(128)),
+            ObjectIndexing::PushConstants
+        );
+        assert_eq!(
+            ObjectIndexing::select(push, &limits(4)),
+            ObjectIndexing::PushConstants
+        );
+        // The feature without room for the index, and room without the feature.
+        assert_eq!(
+            ObjectIndexing::select(push, &limits(0)),
+            ObjectIndexing::DynamicOffset
+        );
+        assert_eq!(
+            ObjectIndexing::select(none, &limits(128)),
+            ObjectIndexing::DynamicOffset
+        );
+        assert_eq!(
+            ObjectIndexing::select(push | wgpu::Features::POLYGON_MODE_LINE, &limits(128)),
+            ObjectIndexing::PushConstants
+        );
+
+        assert!(ObjectIndexing::DynamicOffset
+            .push_constant_ranges()
+            .is_empty());
+        assert_eq!(
+            ObjectIndexing::PushConstants.push_constant_ranges()[0].range,
+            0..4
+        );
+        assert!(ObjectIndexing::PushConstants
+            .buffer_usage()
+            .contains(wgpu::BufferUsages::STORAGE));
+        assert!(ObjectIndexing::DynamicOffset
+            .buffer_usage()
+            .contains(wgpu::BufferUsages::UNIFORM));
+    }
+
+    #[test]
+    fn objects_pack_at_the_indexing_stride() {
+        assert_eq!(PerObjectData::SIZE, 160);
+        assert_eq!(PerObjectData::SIZE % 16, 0);
+        assert_eq!(offset_of!(PerObjectData, prev_model), 64);
+        assert_eq!(offset_of!(PerObjectData, tint), 128);
+        assert_eq!(offset_of!(PerObjectData, material_idx), 144);
+        assert_eq!(offset_of!(PerObjectData, flags), 148);
+
+        assert_eq!(ObjectIndexing::PushConstants.stride(256), 160);
+        assert_eq!(ObjectIndexing::DynamicOffset.stride(256), 256);
+        assert_eq!(ObjectIndexing::DynamicOffset.stride(64), 192);
+        assert_eq!(ObjectIndexing::DynamicOffset.stride(0), 160);
+
+        let objects: Vec<_> = (0..3)
+            .map(|i| {
+                PerObjectData::new(
+                    Mat4::from_translation(Vec3::splat(i as f32)),
+                    None,
+                    [1.0; 4],
+                    i,
+                )
+            })
+            .collect();
+        let bytes = pack_objects(&objects, 256);
+        assert_eq!(bytes.len(), 3 * 256);
+        for (i, object) in objects.iter().enumerate() {
+            let slot = &bytes[i * 256..(i + 1) * 256];
+            assert_eq!(&slot[..160], bytemuck::bytes_of(object));
+            assert!(slot[160..].iter().all(|&b| b == 0));
+        }
+        // A stride under the struct size still keeps objects whole.
+        assert_eq!(pack_objects(&objects, 16).len(), 3 * 160);
+    }
+
+    #[test]
+    fn previous_transforms_carry_over_one_frame() {
+        let moved = Mat4::from_translation(Vec3::X);
+        let first = PerObjectData::new(Mat4::IDENTITY, None, [1.0; 4], 0);
+        assert_eq!(first.flags, 0);
+        assert_eq!(first.prev_model, first.model);
+        let second = PerObjectData::new(moved, Some(Mat4::IDENTITY), [1.0; 4], 0);
+        assert_eq!(second.flags, PerObjectData::HAS_PREVIOUS);
+        assert_eq!(second.prev_model, Mat4::IDENTITY.to_cols_array_2d());
+        assert_eq!(second.model, moved.to_cols_array_2d());
+    }
+
+    #[test]
+    fn small_batches_route_through_the_object_path() {
+        assert_eq!(DrawPath::route(1, 1, true), DrawPath::Object);
+        assert_eq!(DrawPath::route(2, 1, true), DrawPath::Instanced);
+        assert_eq!(DrawPath::route(4, 4, true), DrawPath::Object);
+        assert_eq!(DrawPath::route(5, 4, true), DrawPath::Instanced);
+        // Nothing to draw, routing disabled, or no object pipeline for the shader.
+        assert_eq!(DrawPath::route(0, 4, true), DrawPath::Instanced);
+        assert_eq!(DrawPath::route(1, 0, true), DrawPath::Instanced);
+        assert_eq!(DrawPath::route(1, 4, false), DrawPath::Instanced);
+    }
+
+    #[test]
+    fn the_shader_struct_matches_the_rust_layout() {
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../assets/shaders/v_normal.wgsl");
+        let source = std::fs::read_to_string(path).unwrap();
+        for indexing in [ObjectIndexing::PushConstants, ObjectIndexing::DynamicOffset] {
+            let source = format!("{}\n{}", source, indexing.shader_entry());
+            let module = naga::front::wgsl::parse_str(&source).unwrap();
+            let (members, span) = module
+                .types
+                .iter()
+                .find_map(|(_, ty)| match (&ty.name, &ty.inner) {
+                    (Some(name), naga::TypeInner::Struct { members, span })
+                        if name == "PerObjectData" =>
+                    {
+                        Some((members.clone(), *span))
+                    }
+                    _ => None,
+                })
+                .expect("PerObjectData in v_normal.wgsl");
+            let offsets: Vec<_> = members
+                .iter()
+                .map(|m| (m.name.as_deref().unwrap(), m.offset as usize))
+                .collect();
+            assert_eq!(
+                offsets,
+                [
+                    ("model", offset_of!(PerObjectData, model)),
+                    ("prev_model", offset_of!(PerObjectData, prev_model)),
+                    ("tint", offset_of!(PerObjectData, tint)),
+                    ("material_idx", offset_of!(PerObjectData, material_idx)),
+                    ("flags", offset_of!(PerObjectData, flags)),
+                ]
+            );
+            // WGSL rounds the struct up to its 16 byte alignment, which the padding matches.
+            assert_eq!(span as u64, PerObjectData::SIZE, "{:?}", indexing);
+        }
+    }
+}
@@ -0,0 +1,480 @@
+//! GPU particles. Every [`ParticleEmitter`] owns a fixed range of one particle buffer, so
+//! neither an emitter nor all of them together can outgrow it. `particles_simulate.wgsl`
+//! advances the live particles each frame and respawns dead ones in their emitter's range,
+//! handing out this frame's spawns through an atomic counter per emitter; the CPU only
+//! decides how many to spawn and where the emitters are. `particles.wgsl` draws them as
+//! camera-facing billboards in the blend pass.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use bytemuck::{Pod, Zeroable};
+use glam::Vec3;
+
+use crate::{
+    log_debug, log_warning, BindGroup, CacheKey, CacheStorage, EngineError, ModelManager,
+    ParticleEmitter, RenderBindGroupLayouts, WgpuBuffer, World,
+};
+
+const SIMULATE_SHADER: &str = "particles_simulate.wgsl";
+const DRAW_SHADER: &str = "particles.wgsl";
+/// Side of the simulation workgroup, mirrored in `particles.wgsl`.
+const WORKGROUP: u32 = 64;
+
+/// One particle; see `Particle` in `particles.wgsl`. Dead once `age` reaches `lifetime`.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+pub struct Particle {
+    pub position: [f32; 3],
+    pub age: f32,
+    pub velocity: [f32; 3],
+    pub lifetime: f32,
+    /// Emitter slot owning the particle, [`Particle::UNOWNED`] outside every range.
+    pub emitter: u32,
+    pub _pad: [u32; 3],
+}
+
+impl Particle {
+    pub const UNOWNED: u32 = u32::MAX;
+
+    pub fn dead(emitter: u32) -> Self {
+        Self {
+            position: [0.0; 3],
+            age: 1.0,
+            velocity: [0.0; 3],
+            lifetime: 0.0,
+            emitter,
+            _pad: [0; 3],
+        }
+    }
+}
+
+/// One emitter slot of [`ParticleSimulation`]; see `Emitter` in `particles.wgsl`.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable, Default)]
+pub struct EmitterUniform {
+    pub position: [f32; 3],
+    /// Dead particles to respawn this frame.
+    pub spawn: u32,
+    pub velocity: [f32; 3],
+    pub spread: f32,
+    /// Medium gravity at the emitter, times its gravity scale.
+    pub gravity: [f32; 3],
+    /// Medium drag at the emitter, applied to horizontal velocity like physics does.
+    pub drag: f32,
+    pub start_color: [f32; 4],
+    pub end_color: [f32; 4],
+    pub lifetime: f32,
+    pub size: f32,
+    pub first: u32,
+    pub count: u32,
+}
+
+/// Uniform of both particle passes.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+pub struct ParticleSimulation {
+    pub dt: f32,
+    pub seed: u32,
+    pub emitter_count: u32,
+    pub _pad0: u32,
+    /// Added to every particle once, after a world rebase.
+    pub shift: [f32; 3],
+    pub _pad1: f32,
+    pub emitters: [EmitterUniform; ParticleSystem::MAX_EMITTERS],
+}
+
+/// The range an emitter slot owns and what it spawned from.
+#[derive(Debug, Copy, Clone)]
+struct EmitterSlot {
+    /// `None` once the entity or its emitter is gone; the slot then drains.
+    entity: Option<usize>,
+    emitter: ParticleEmitter,
+    position: Vec3,
+    first: u32,
+    count: u32,
+    /// Fractional spawns carried to the next frame.
+    carry: f32,
+    /// Seconds left before a drained slot's range is reused.
+    drain: f32,
+}
+
+pub struct ParticleSystem {
+    format: wgpu::TextureFormat,
+    capacity: u32,
+    particles: WgpuBuffer,
+    spawned: WgpuBuffer,
+    simulation: WgpuBuffer,
+    simulate_bind_group: wgpu::BindGroup,
+    draw_bind_group: wgpu::BindGroup,
+    compute_pipeline: Option<Arc<wgpu::ComputePipeline>>,
+    render_pipeline: Option<Arc<wgpu::RenderPipeline>>,
+    slots: Vec<Option<EmitterSlot>>,
+    /// Entities whose emitter found no room, warned about once.
+    rejected: HashSet<usize>,
+    shift: Vec3,
+    frame: u32,
+    active: bool,
+}
+
+impl ParticleSystem {
+    /// Particles alive at once across all emitters by default.
+    pub const DEFAULT_CAPACITY: u32 = 1 << 16;
+    pub const MAX_PER_EMITTER: u32 = 4096;
+    pub const MAX_EMITTERS: usize = 64;
+
+    /// `format` is the color target the particles are drawn into.
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat, capacity: u32) -> Self {
+        let capacity = capacity.max(1);
+        let particles = WgpuBuffer::from_data(
+            device,
+            &vec![Particle::dead(Particle::UNOWNED); capacity as usize],
+            wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            Some("particle buffer"),
+        );
+        let spawned = WgpuBuffer::from_data(
+            device,
+            &[0u32; Self::MAX_EMITTERS],
+            wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            Some("particle spawn counters"),
+        );
+        let simulation = WgpuBuffer::from_data(
+            device,
+            &[ParticleSimulation::zeroed()],
+            wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            Some("particle simulation"),
+        );
+        let simulate_bind_group =
+            BindGroup::particle_simulate(device, &particles, &spawned, &simulation);
+        let draw_bind_group = BindGroup::particle_draw(device, &particles, &simulation);
+        Self {
+            format,
+            capacity,
+            particles,
+            spawned,
+            simulation,
+            simulate_bind_group,
+            draw_bind_group,
+            compute_pipeline: None,
+            render_pipeline: None,
+            slots: Vec::new(),
+            rejected: HashSet::new(),
+            shift: Vec3::ZERO,
+            frame: 0,
+            active: false,
+        }
+    }
+    pub fn capacity(&self) -> u32 {
+        self.capacity
+    }
+    /// Emitters currently owning a range, draining ones included.
+    pub fn emitter_count(&self) -> usize {
+        self.slots.iter().flatten().count()
+    }
+    /// Drops the pipelines so they're rebuilt on next use, e.g. after a depth policy
+    /// switch or a shader reload.
+    pub fn clear_pipelines(&mut self) {
+        self.compute_pipeline = None;
+        self.render_pipeline = None;
+    }
+    /// Moves every particle with the world when its origin shifts by `offset`.
+    pub fn rebase(&mut self, offset: Vec3) {
+        self.shift -= offset;
+    }
+
+    /// Follows the world's emitters, picks this frame's spawns and writes the simulation
+    /// uniform for [`ParticleSystem::dispatch`]. A `dt` of zero freezes the particles.
+    pub fn update(&mut self, world: &World, model_manager: &mut ModelManager, dt: f32) {
+        let queue = model_manager.queue.clone();
+        self.sync_emitters(world, &queue, dt);
+        self.active = self.slots.iter().any(Option::is_some) || self.shift != Vec3::ZERO;
+        if !self.active {
+            return;
+        }
+        if self.compute_pipeline.is_none() || self.render_pipeline.is_none() {
+            if let Err(e) = self.create_pipelines(model_manager) {
+                log_warning!("particle pipelines: {}", e);
+                self.active = false;
+                return;
+            }
+        }
+
+        self.frame = self.frame.wrapping_add(1);
+        let mut simulation = ParticleSimulation {
+            dt,
+            seed: self.frame,
+            emitter_count: self.slots.len() as u32,
+            _pad0: 0,
+            shift: self.shift.to_array(),
+            _pad1: 0.0,
+            emitters: [EmitterUniform::default(); Self::MAX_EMITTERS],
+        };
+        self.shift = Vec3::ZERO;
+        for (uniform, slot) in simulation.emitters.iter_mut().zip(&mut self.slots) {
+            let Some(slot) = slot else {
+                continue;
+            };
+            let emitter = &slot.emitter;
+            let spawn = if slot.entity.is_some() {
+                slot.carry += emitter.rate.max(0.0) * dt;
+                let spawn = slot.carry.floor();
+                slot.carry -= spawn;
+                (spawn as u32).min(slot.count)
+            } else {
+                0
+            };
+            let medium = world.terrain.medium_properties_at(slot.position);
+            *uniform = EmitterUniform {
+                position: slot.position.to_array(),
+                spawn,
+                velocity: emitter.velocity.to_array(),
+                spread: emitter.spread,
+                gravity: (medium.gravity * emitter.gravity_scale).to_array(),
+                drag: medium.drag,
+                start_color: emitter.start_color,
+                end_color: emitter.end_color,
+                lifetime: emitter.lifetime.max(0.0),
+                size: emitter.size,
+                first: slot.first,
+                count: slot.count,
+            };
+        }
+        self.simulation
+            .write_data(&queue, &model_manager.device, &[simulation], None);
+    }
+
+    /// Gives new emitters a range, tracks moved ones and drains the ones that went away.
+    fn sync_emitters(&mut self, world: &World, queue: &wgpu::Queue, dt: f32) {
+        let mut seen = HashSet::new();
+        for slot in self.slots.iter_mut().flatten() {
+            let Some(entity) = slot.entity else {
+                continue;
+            };
+            let emitter = world.particle_emitters.get(entity).copied().flatten();
+            let position = world.physics.positions.get(entity).copied().flatten();
+            match (emitter, position) {
+                (Some(emitter), Some(position)) => {
+                    slot.emitter = emitter;
+                    slot.position = position.0;
+                    seen.insert(entity);
+                }
+                _ => {
+                    slot.entity = None;
+                    slot.drain = slot.emitter.lifetime.max(0.0) * 1.25;
+                }
+            }
+        }
+        let mut freed = Vec::new();
+        for slot in self.slots.iter_mut() {
+            let Some(draining) = slot.as_mut().filter(|slot| slot.entity.is_none()) else {
+                continue;
+            };
+            draining.drain -= dt;
+            if draining.drain <= 0.0 {
+                freed.push((draining.first, draining.count));
+                *slot = None;
+            }
+        }
+        for (first, count) in freed {
+            self.write_range(queue, first, count, Particle::UNOWNED);
+        }
+        while matches!(self.slots.last(), Some(None)) {
+            self.slots.pop();
+        }
+        self.rejected.retain(|entity| {
+            world
+                .particle_emitters
+                .get(*entity)
+                .is_some_and(Option::is_some)
+        });
+
+        for (entity, emitter) in world.particle_emitters.iter().enumerate() {
+            let Some(emitter) = emitter else {
+                continue;
+            };
+            if seen.contains(&entity) || self.rejected.contains(&entity) {
+                continue;
+            }
+            let Some(position) = world.physics.positions.get(entity).copied().flatten() else {
+                continue;
+            };
+            let count = emitter.max_particles.clamp(1, Self::MAX_PER_EMITTER);
+            let index = self
+                .slots
+                .iter()
+                .position(Option::is_none)
+                .unwrap_or(self.slots.len());
+            let first = self.allocate(count);
+            let (Some(first), true) = (first, index < Self::MAX_EMITTERS) else {
+                log_warning!(
+                    "No room for {} particles of entity {} ({} of {} in use)",
+                    count,
+                    entity,
+                    self.slots
+                        .iter()
+                        .flatten()
+                        .map(|slot| slot.count)
+                        .sum::<u32>(),
+                    self.capacity
+                );
+                self.rejected.insert(entity);
+                continue;
+            };
+            self.write_range(queue, first, count, index as u32);
+            let slot = EmitterSlot {
+                entity: Some(entity),
+                emitter: *emitter,
+                position: position.0,
+                first,
+                count,
+                carry: 0.0,
+                drain: 0.0,
+            };
+            if index == self.slots.len() {
+                self.slots.push(Some(slot));
+            } else {
+                self.slots[index] = Some(slot);
+            }
+            log_debug!(
+                "Particle emitter of entity {} owns {}..{}",
+                entity,
+                first,
+                first + count
+            );
+        }
+    }
+
+    /// Start of the first gap of `count` particles between the ranges in use.
+    fn allocate(&self, count: u32) -> Option<u32> {
+        let mut ranges: Vec<(u32, u32)> = self
+            .slots
+            .iter()
+            .flatten()
+            .map(|slot| (slot.first, slot.first + slot.count))
+            .collect();
+        ranges.sort_unstable();
+        let mut start = 0;
+        for (first, end) in ranges {
+            if first >= start + count {
+                break;
+            }
+            start = start.max(end);
+        }
+        (start + count <= self.capacity).then_some(start)
+    }
+    /// Marks `count` particles from `first` dead and owned by `emitter`.
+    fn write_range(&self, queue: &wgpu::Queue, first: u32, count: u32, emitter: u32) {
+        let particles = vec![Particle::dead(emitter); count as usize];
+        let offset = first as u64 * std::mem::size_of::<Particle>() as u64;
+        queue.write_buffer(
+            self.particles.get(),
+            offset,
+            bytemuck::cast_slice(&particles),
+        );
+    }
+
+    fn create_pipelines(&mut self, model_manager: &mut ModelManager) -> Result<(), EngineError> {
+        let device = &model_manager.device;
+        let materials = &mut model_manager.materials;
+        let shader = materials.shaders.load(device, SIMULATE_SHADER)?;
+
+        let compute_key = CacheKey::from("particles simulate");
+        let compute = materials
+            .pipelines
+            .compute
+            .get_or_create(compute_key, || {
+                let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: Some("particles simulate layout"),
+                    bind_group_layouts: &[RenderBindGroupLayouts::particle_simulate()],
+                    push_constant_ranges: &[],
+                });
+                Arc::new(
+                    device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                        label: Some("particles simulate"),
+                        layout: Some(&layout),
+                        module: &shader,
+                        entry_point: Some("simulate"),
+                        compilation_options: Default::default(),
+                        cache: None,
+                    }),
+                )
+            })
+            .clone();
+        materials.pipelines.track(SIMULATE_SHADER, compute_key);
+
+        let shader = materials.shaders.load(device, DRAW_SHADER)?;
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("particles draw layout"),
+            bind_group_layouts: &[
+                RenderBindGroupLayouts::uniform(),
+                RenderBindGroupLayouts::particle_draw(),
+            ],
+            push_constant_ranges: &[],
+        });
+        let render = Arc::new(
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("particles draw"),
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: Some("vs_main"),
+                    buffers: &[],
+                    compilation_options: Default::default(),
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: Some("fs_main"),
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: self.format,
+                        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                    compilation_options: Default::default(),
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: Some(crate::RenderSettings::depth_state(
+                    crate::DepthVariant::Transparent,
+                )),
+                multisample: crate::RenderSettings::multisample(),
+                multiview: None,
+                cache: None,
+            }),
+        );
+        self.compute_pipeline = Some(compute);
+        self.render_pipeline = Some(render);
+        Ok(())
+    }
+
+    /// Advances and respawns the particles. Record before the pass that draws them.
+    pub fn dispatch(&self, encoder: &mut wgpu::CommandEncoder) {
+        let Some(pipeline) = self.compute_pipeline.as_ref().filter(|_| self.active) else {
+            return;
+        };
+        crate::gpu_scope!(Pass, "Particles");
+        encoder.clear_buffer(self.spawned.get(), 0, None);
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Particles"),
+            timestamp_writes: None,
+        });
+        pass.set_pipeline(pipeline);
+        pass.set_bind_group(0, &self.simulate_bind_group, &[]);
+        pass.dispatch_workgroups(self.capacity.div_ceil(WORKGROUP), 1, 1);
+    }
+
+    /// One billboard per particle; dead ones collapse to nothing in the vertex shader.
+    pub fn draw(&self, rpass: &mut wgpu::RenderPass, uniform_bind_group: &wgpu::BindGroup) {
+        let Some(pipeline) = self.render_pipeline.as_ref().filter(|_| self.active) else {
+            return;
+        };
+        crate::gpu_scope!(
+            Draw,
+            "particles",
+            format!("{} emitters", self.emitter_count())
+        );
+        rpass.set_pipeline(pipeline);
+        rpass.set_bind_group(0, uniform_bind_group, &[]);
+        rpass.set_bind_group(1, &self.draw_bind_group, &[]);
+        rpass.draw(0..6, 0..self.capacity);
+    }
+}
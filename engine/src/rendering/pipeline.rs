@@ -11,7 +11,10 @@ impl HDR {
     ) -> wgpu::RenderPipeline {
         let layout = &device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: Some("hdr pipeline layout"),
-            bind_group_layouts: &[&RenderBindGroupLayouts::texture()],
+            bind_group_layouts: &[
+                RenderBindGroupLayouts::texture(),
+                RenderBindGroupLayouts::tonemap(),
+            ],
             push_constant_ranges: &[],
         });
         device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
@@ -70,6 +73,17 @@ impl HDR {
 pub struct PipelineManager {
     pub render: crate::RenderPipelineManager,
     pub compute: crate::ComputePipelineManager,
+    /// Pipeline keys built from each shader key, see [`PipelineManager::track`].
+    dependents:
+        std::collections::HashMap<crate::CacheKey, std::collections::HashSet<crate::CacheKey>>,
+}
+
+/// Pipelines dropped by [`PipelineManager::invalidate_shader`], kept so a failed rebuild
+/// can put them back.
+#[derive(Default)]
+pub struct InvalidatedPipelines {
+    pub render: Vec<(crate::CacheKey, std::sync::Arc<wgpu::RenderPipeline>)>,
+    pub compute: Vec<(crate::CacheKey, std::sync::Arc<wgpu::ComputePipeline>)>,
 }
 
 impl PipelineManager {
@@ -77,6 +91,42 @@ impl PipelineManager {
         Self {
             render: crate::RenderPipelineManager::new(),
             compute: crate::ComputePipelineManager::new(),
+            dependents: std::collections::HashMap::new(),
+        }
+    }
+    /// Records that the pipeline cached under `pipeline` was built from `shader`.
+    pub fn track(&mut self, shader: &str, pipeline: crate::CacheKey) {
+        self.dependents
+            .entry(crate::CacheKey::from(shader))
+            .or_default()
+            .insert(pipeline);
+    }
+    /// Drops every cached render and compute pipeline built from `shader`, so the next
+    /// `get_or_create` builds them from the current module.
+    pub fn invalidate_shader(&mut self, shader: &crate::CacheKey) -> InvalidatedPipelines {
+        use crate::CacheStorage;
+
+        let mut removed = InvalidatedPipelines::default();
+        for key in self.dependents.get(shader).into_iter().flatten() {
+            if let Some(pipeline) = self.render.remove(key) {
+                removed.render.push((*key, pipeline));
+            }
+            if let Some(pipeline) = self.compute.remove(key) {
+                removed.compute.push((*key, pipeline));
+            }
+        }
+        removed
+    }
+    /// Puts pipelines dropped by [`PipelineManager::invalidate_shader`] back, replacing
+    /// whatever was rebuilt under their keys since.
+    pub fn restore(&mut self, removed: InvalidatedPipelines) {
+        use crate::CacheStorage;
+
+        for (key, pipeline) in removed.render {
+            self.render.insert(key, pipeline);
+        }
+        for (key, pipeline) in removed.compute {
+            self.compute.insert(key, pipeline);
         }
     }
 
@@ -120,7 +170,10 @@ impl crate::CacheStorage<std::sync::Arc<wgpu::ComputePipeline>> for ComputePipel
         F: FnOnce() -> std::sync::Arc<wgpu::ComputePipeline>,
     {
         let start = std::time::Instant::now();
-        let pipeline = self.pipelines.entry(key).or_insert_with(create_fn);
+        let pipeline = self.pipelines.entry(key).or_insert_with(|| {
+            crate::assert_not_recording("render pipeline");
+            create_fn()
+        });
         crate::log_debug!("Loaded in {:.2?}", start.elapsed());
         pipeline
     }
@@ -130,6 +183,11 @@ impl crate::CacheStorage<std::sync::Arc<wgpu::ComputePipeline>> for ComputePipel
     fn remove(&mut self, key: &crate::CacheKey) -> Option<std::sync::Arc<wgpu::ComputePipeline>> {
         self.pipelines.remove(key)
     }
+    fn iter(
+        &self,
+    ) -> impl Iterator<Item = (&crate::CacheKey, &std::sync::Arc<wgpu::ComputePipeline>)> {
+        self.pipelines.iter()
+    }
 }
 pub struct RenderPipelineManager {
     pipelines: crate::HashCache<std::sync::Arc<wgpu::RenderPipeline>>,
@@ -164,7 +222,10 @@ impl crate::CacheStorage<std::sync::Arc<wgpu::RenderPipeline>> for RenderPipelin
         F: FnOnce() -> std::sync::Arc<wgpu::RenderPipeline>,
     {
         let start = std::time::Instant::now();
-        let pipeline = self.pipelines.entry(key).or_insert_with(create_fn);
+        let pipeline = self.pipelines.entry(key).or_insert_with(|| {
+            crate::assert_not_recording("render pipeline");
+            create_fn()
+        });
         crate::log_debug!("Loaded in {:.2?}", start.elapsed());
         pipeline
     }
@@ -174,4 +235,9 @@ impl crate::CacheStorage<std::sync::Arc<wgpu::RenderPipeline>> for RenderPipelin
     fn remove(&mut self, key: &crate::CacheKey) -> Option<std::sync::Arc<wgpu::RenderPipeline>> {
         self.pipelines.remove(key)
     }
+    fn iter(
+        &self,
+    ) -> impl Iterator<Item = (&crate::CacheKey, &std::sync::Arc<wgpu::RenderPipeline>)> {
+        self.pipelines.iter()
+    }
 }
@@ -0,0 +1,141 @@
+//! Full-screen passes between the HDR pass and the final blit. Each enabled pass of a
+//! [`PostProcessChain`] reads the previous one's color and draws into one of two full-size
+//! targets, alternating between them; with every pass disabled nothing is recorded and the
+//! blit reads the HDR target as before.
+
+use crate::{
+    BloomPass, EngineError, FrameBuffer, FrameBufferSize, RenderTargetKind, RenderTargetManager,
+    Texture,
+};
+
+/// One step of a [`PostProcessChain`].
+pub trait PostProcessPass: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn enabled(&self) -> bool;
+    fn set_enabled(&mut self, enabled: bool);
+    /// Tunables by name, for tools that don't know the pass type.
+    fn parameters(&mut self) -> Vec<(&'static str, &mut f32)> {
+        Vec::new()
+    }
+    /// Runs before recording, while the pass is enabled: creates the intermediate targets
+    /// the pass draws into, at `size` or a fraction of it, and uploads its parameters.
+    fn prepare(
+        &mut self,
+        _device: &wgpu::Device,
+        _queue: &wgpu::Queue,
+        _targets: &mut RenderTargetManager,
+        _size: FrameBufferSize,
+    ) {
+    }
+    /// Draws `input` into `output`, the same size.
+    fn record(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        targets: &RenderTargetManager,
+        input: &Texture,
+        output: &FrameBuffer,
+    );
+}
+
+/// Post-processing passes run in order. Their targets live in the [`RenderTargetManager`],
+/// so its resize covers the whole chain.
+pub struct PostProcessChain {
+    passes: Vec<Box<dyn PostProcessPass>>,
+    format: wgpu::TextureFormat,
+}
+
+impl PostProcessChain {
+    /// Full-size targets the enabled passes write in turn.
+    pub const TARGETS: [RenderTargetKind; 2] = [
+        RenderTargetKind::Custom("post process a"),
+        RenderTargetKind::Custom("post process b"),
+    ];
+
+    /// An empty chain drawing into `format` targets, the HDR target's format.
+    pub fn new(format: wgpu::TextureFormat) -> Self {
+        Self {
+            passes: Vec::new(),
+            format,
+        }
+    }
+    /// The engine's passes: bloom.
+    pub fn with_defaults(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+    ) -> Result<Self, EngineError> {
+        let mut chain = Self::new(format);
+        chain.push(BloomPass::new(device, format)?);
+        Ok(chain)
+    }
+    pub fn push(&mut self, pass: impl PostProcessPass + 'static) -> &mut Self {
+        self.passes.push(Box::new(pass));
+        self
+    }
+    pub fn passes(&self) -> impl Iterator<Item = &dyn PostProcessPass> {
+        self.passes.iter().map(|pass| pass.as_ref())
+    }
+    pub fn pass_mut(&mut self, name: &str) -> Option<&mut dyn PostProcessPass> {
+        self.passes
+            .iter_mut()
+            .find(|pass| pass.name() == name)
+            .map(|pass| pass.as_mut() as &mut dyn PostProcessPass)
+    }
+    /// Whether any pass is enabled, i.e. whether [`PostProcessChain::record`] draws.
+    pub fn active(&self) -> bool {
+        self.passes.iter().any(|pass| pass.enabled())
+    }
+
+    /// Creates the chain's targets and prepares the enabled passes. Call before recording,
+    /// with the window size.
+    pub fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        targets: &mut RenderTargetManager,
+        size: FrameBufferSize,
+    ) {
+        if !self.active() {
+            return;
+        }
+        for kind in Self::TARGETS {
+            if !targets.contains(&kind) {
+                targets.insert(
+                    FrameBuffer::new_color_only(device, size, self.format, "post process"),
+                    kind,
+                );
+            }
+        }
+        for pass in self.passes.iter_mut().filter(|pass| pass.enabled()) {
+            pass.prepare(device, queue, targets, size);
+        }
+    }
+
+    /// Target holding the chain's result, or `None` when no pass is enabled and the input
+    /// is the result.
+    pub fn output<'a>(&self, targets: &'a RenderTargetManager) -> Option<&'a FrameBuffer> {
+        let enabled = self.passes.iter().filter(|pass| pass.enabled()).count();
+        let last = enabled.checked_sub(1)?;
+        targets.get(&Self::TARGETS[last % 2])
+    }
+
+    /// Records the enabled passes, the first reading `input`.
+    pub fn record(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        targets: &RenderTargetManager,
+        input: &Texture,
+    ) {
+        let mut source = input;
+        let enabled = self.passes.iter().filter(|pass| pass.enabled());
+        for (i, pass) in enabled.enumerate() {
+            let Some(output) = targets.get(&Self::TARGETS[i % 2]) else {
+                return;
+            };
+            crate::gpu_scope!(Pass, pass.name());
+            pass.record(device, encoder, targets, source, output);
+            source = output.color();
+        }
+    }
+}
@@ -0,0 +1,198 @@
+//! Optional depth-only pre-pass. Opaque instanced models and terrain are drawn first with a
+//! position-only pipeline into the scene depth, then the main pass draws them again testing
+//! for equal depth without writing, so each covered pixel is shaded once. It pays off when
+//! fragment shading dominates; in light scenes the second vertex pass is a loss, so it is
+//! off by default and toggled with [`RenderSettings::set_depth_prepass`].
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::{
+    log_warning, CacheKey, CacheStorage, DebugMode, DepthVariant, DrawList, EngineError, Material,
+    MaterialManager, ModelManager, RenderBindGroupLayouts, RenderSettings, Terrain, Vertex,
+    VertexInstance,
+};
+
+static DEPTH_PREPASS: AtomicBool = AtomicBool::new(false);
+
+impl RenderSettings {
+    pub fn depth_prepass() -> bool {
+        DEPTH_PREPASS.load(Ordering::Relaxed)
+    }
+    /// Turns the pre-pass on or off from the next [`DepthPrepass::prepare`] on.
+    pub fn set_depth_prepass(enabled: bool) {
+        DEPTH_PREPASS.store(enabled, Ordering::Relaxed);
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Prepassed {
+    /// Position-only pipeline writing depth.
+    depth: Arc<wgpu::RenderPipeline>,
+    /// The material's own pipeline testing for equal depth.
+    main: Arc<wgpu::RenderPipeline>,
+}
+
+/// Pipelines of the pre-pass for the frame's opaque materials, built while preparing so
+/// recording only reads them. Both passes draw from the frame's [`DrawList`] and terrain
+/// instance buffer, so culling and instance building happen once.
+#[derive(Debug, Default)]
+pub struct DepthPrepass {
+    /// Pipelines of each material by asset key; `None` when building them failed.
+    pipelines: HashMap<CacheKey, Option<Prepassed>>,
+    enabled: bool,
+}
+
+impl DepthPrepass {
+    pub const SHADER: &'static str = "depth_prepass.wgsl";
+
+    /// Whether the pre-pass runs this frame. Debug views draw with their own pipelines and
+    /// go without it.
+    pub fn active(&self, debug: &DebugMode) -> bool {
+        self.enabled && debug.mode() == 0
+    }
+    /// Drops the cached pipelines, after shaders, the depth policy or the sample count
+    /// changed.
+    pub fn clear_pipelines(&mut self) {
+        self.pipelines.clear();
+    }
+    /// Builds the pipelines `materials` need when the pre-pass is enabled. Transparent
+    /// materials and ones that don't write depth are left out and draw as usual.
+    pub fn prepare<'a>(
+        &mut self,
+        model_manager: &mut ModelManager,
+        materials: impl IntoIterator<Item = &'a Material>,
+    ) {
+        self.enabled = RenderSettings::depth_prepass();
+        if !self.enabled {
+            return;
+        }
+        for material in materials {
+            let writes_depth = material
+                .asset
+                .depth_stencil
+                .as_ref()
+                .is_some_and(|ds| ds.depth_write_enabled);
+            if material.asset.transparent || !writes_depth {
+                continue;
+            }
+            self.pipelines.entry(material.asset.key).or_insert_with(|| {
+                Self::build(model_manager, material)
+                    .map_err(|e| log_warning!("{} pre-pass: {}", material.asset.name, e))
+                    .ok()
+            });
+        }
+    }
+    fn build(
+        model_manager: &mut ModelManager,
+        material: &Material,
+    ) -> Result<Prepassed, EngineError> {
+        let device = &model_manager.device;
+        let materials = &mut model_manager.materials;
+        let main = material.asset.prepassed_pipeline(
+            device,
+            &mut materials.shaders,
+            &mut materials.pipelines,
+            &[Vertex::LAYOUT, VertexInstance::LAYOUT],
+        )?;
+        let depth = Self::depth_pipeline(device, materials, material.asset.primitive)?;
+        Ok(Prepassed { depth, main })
+    }
+    /// The depth-only pipeline for the standard vertex and instance layouts, one per
+    /// primitive state so culling matches the main pass.
+    fn depth_pipeline(
+        device: &wgpu::Device,
+        materials: &mut MaterialManager,
+        primitive: wgpu::PrimitiveState,
+    ) -> Result<Arc<wgpu::RenderPipeline>, EngineError> {
+        let shader = materials.shaders.load(device, Self::SHADER)?;
+        let label = format!(
+            "depth_prepass_{:?}_{:?}_msaa{}",
+            primitive,
+            RenderSettings::depth_policy(),
+            RenderSettings::sample_count()
+        );
+        let key = CacheKey::from(label.clone());
+        let pipeline = materials
+            .pipelines
+            .render
+            .get_or_create(key, || {
+                let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: Some("depth prepass"),
+                    bind_group_layouts: &[RenderBindGroupLayouts::uniform()],
+                    push_constant_ranges: &[],
+                });
+                Arc::new(
+                    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                        label: Some(&label),
+                        layout: Some(&layout),
+                        vertex: wgpu::VertexState {
+                            module: &shader,
+                            entry_point: Some("vs_main"),
+                            buffers: &[Vertex::LAYOUT, VertexInstance::LAYOUT],
+                            compilation_options: Default::default(),
+                        },
+                        fragment: None,
+                        primitive,
+                        depth_stencil: Some(RenderSettings::depth_state(DepthVariant::Prepass)),
+                        multisample: RenderSettings::multisample(),
+                        multiview: None,
+                        cache: None,
+                    }),
+                )
+            })
+            .clone();
+        materials.pipelines.track(Self::SHADER, key);
+        Ok(pipeline)
+    }
+
+    fn prepassed(&self, material: &Material) -> Option<&Prepassed> {
+        self.pipelines.get(&material.asset.key)?.as_ref()
+    }
+    /// The pipeline `material` draws with in the main pass after the pre-pass; `None` when
+    /// it wasn't pre-passed and draws with its own.
+    pub fn main_pipeline(&self, material: &Material) -> Option<&Arc<wgpu::RenderPipeline>> {
+        self.prepassed(material)
+            .filter(|_| self.enabled)
+            .map(|prepassed| &prepassed.main)
+    }
+
+    /// Records the depth of the opaque draws of `draws` and of the visible opaque terrain.
+    pub fn record(
+        &self,
+        rpass: &mut wgpu::RenderPass,
+        uniform_bind_group: &wgpu::BindGroup,
+        draws: &DrawList,
+        terrain: &Terrain,
+    ) {
+        rpass.set_bind_group(0, uniform_bind_group, &[]);
+        if let Some(buffer) = draws.buffer() {
+            rpass.set_vertex_buffer(1, buffer.slice());
+            for command in draws.commands() {
+                let Some(prepassed) = self.prepassed(&command.material) else {
+                    continue;
+                };
+                let mesh = &command.mesh;
+                rpass.set_pipeline(&prepassed.depth);
+                rpass.set_vertex_buffer(0, mesh.vertex_buffer.get().slice(..));
+                rpass.set_index_buffer(mesh.index_buffer.get().slice(..), mesh.index_format);
+                rpass.draw_indexed(0..mesh.index_count, 0, command.instances.clone());
+            }
+        }
+        let Some(buffer) = terrain.instance_buffer() else {
+            return;
+        };
+        rpass.set_vertex_buffer(1, buffer.buffer.slice());
+        for (instance, instances) in terrain.visible_chunk_draws() {
+            let Some(prepassed) = instance.material.as_ref().and_then(|m| self.prepassed(m)) else {
+                continue;
+            };
+            let mesh = &instance.mesh;
+            rpass.set_pipeline(&prepassed.depth);
+            rpass.set_vertex_buffer(0, mesh.vertex_buffer.get().slice(..));
+            rpass.set_index_buffer(mesh.index_buffer.get().slice(..), mesh.index_format);
+            rpass.draw_indexed(0..mesh.index_count, 0, instances);
+        }
+    }
+}
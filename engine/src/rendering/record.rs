@@ -0,0 +1,474 @@
+//! Command recording for one frame. Passes declare the targets they read and write, like a
+//! [`crate::FramePlan`]; passes without hazards between them share a group, and with
+//! [`RecordSettings::parallel`] on a group with more than one heavy pass records on rayon,
+//! each pass into its own encoder. Buffers are submitted in the order the passes were
+//! added, in one `queue.submit`, whichever way they were recorded.
+//!
+//! Recording only reads: pipelines, bind groups and uploads are made in a prepare step
+//! before [`PassGraph::submit`]. Debug builds panic when a pipeline cache is filled while
+//! passes record.
+
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use rayon::prelude::*;
+
+use crate::{log_warning_once, EngineError, GpuProfiler, PassDecl, RenderTargetKind};
+
+static PARALLEL_RECORDING: AtomicBool = AtomicBool::new(true);
+static RECORDING: AtomicUsize = AtomicUsize::new(0);
+
+/// Switches for [`PassGraph`] recording.
+pub struct RecordSettings;
+
+impl RecordSettings {
+    pub fn parallel() -> bool {
+        PARALLEL_RECORDING.load(Ordering::Relaxed)
+    }
+    /// Records every pass on the render thread when off, for profiling and bisecting.
+    pub fn set_parallel(enabled: bool) {
+        PARALLEL_RECORDING.store(enabled, Ordering::Relaxed);
+    }
+    /// Whether a [`PassGraph`] is recording right now.
+    pub fn recording() -> bool {
+        RECORDING.load(Ordering::Acquire) > 0
+    }
+}
+
+/// Debug-asserts that no [`PassGraph`] is recording; called where caches are filled.
+pub fn assert_not_recording(what: &str) {
+    debug_assert!(
+        !RecordSettings::recording(),
+        "{} created while recording passes; create it before PassGraph::submit",
+        what
+    );
+}
+
+struct RecordingGuard;
+
+impl RecordingGuard {
+    fn new() -> Self {
+        RECORDING.fetch_add(1, Ordering::AcqRel);
+        Self
+    }
+}
+
+impl Drop for RecordingGuard {
+    fn drop(&mut self) {
+        RECORDING.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+pub type RecordFn<'a> = Box<dyn FnOnce(&mut wgpu::CommandEncoder) + Send + 'a>;
+
+struct PassNode<'a> {
+    decl: PassDecl,
+    heavy: bool,
+    record: RecordFn<'a>,
+}
+
+impl PassNode<'_> {
+    fn encode(self, device: &wgpu::Device, profiler: Option<&GpuProfiler>) -> wgpu::CommandBuffer {
+        crate::gpu_scope!(Encoder, self.decl.name);
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some(self.decl.name),
+        });
+        let scope = profiler.map(|p| p.begin_scope(&mut encoder, self.decl.name));
+        (self.record)(&mut encoder);
+        if let (Some(profiler), Some(scope)) = (profiler, scope) {
+            profiler.end_scope(&mut encoder, scope);
+        }
+        encoder.finish()
+    }
+}
+
+/// Passes of one frame, grouped like a [`crate::Schedule`]: a pass goes into the group
+/// after the last one holding a pass it conflicts with.
+#[derive(Default)]
+pub struct PassGraph<'a> {
+    passes: Vec<PassNode<'a>>,
+    groups: Vec<Vec<usize>>,
+    profiler: Option<&'a GpuProfiler>,
+}
+
+impl<'a> PassGraph<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Times every pass, named after it, and the submit in `profiler`.
+    pub fn profiled(&mut self, profiler: &'a GpuProfiler) -> &mut Self {
+        self.profiler = Some(profiler);
+        self
+    }
+    /// Adds a pass recorded by `record`. `heavy` passes record enough to be worth a thread
+    /// of their own; a group records serially unless it holds at least two.
+    pub fn pass(
+        &mut self,
+        name: &'static str,
+        reads: &[RenderTargetKind],
+        writes: &[RenderTargetKind],
+        heavy: bool,
+        record: impl FnOnce(&mut wgpu::CommandEncoder) + Send + 'a,
+    ) -> &mut Self {
+        let decl = PassDecl {
+            name,
+            reads: reads.to_vec(),
+            writes: writes.to_vec(),
+        };
+        let group = self
+            .groups
+            .iter()
+            .rposition(|group| group.iter().any(|&i| self.passes[i].decl.conflicts(&decl)))
+            .map_or(0, |last| last + 1);
+        if group == self.groups.len() {
+            self.groups.push(Vec::new());
+        }
+        self.groups[group].push(self.passes.len());
+        self.passes.push(PassNode {
+            decl,
+            heavy,
+            record: Box::new(record),
+        });
+        self
+    }
+    /// [`PassGraph::pass`] for a pass that can fail, e.g. on a missing target. A failed
+    /// pass is logged once per error and its command buffer holds whatever it recorded
+    /// before failing; the other passes are submitted as usual.
+    pub fn try_pass(
+        &mut self,
+        name: &'static str,
+        reads: &[RenderTargetKind],
+        writes: &[RenderTargetKind],
+        heavy: bool,
+        record: impl FnOnce(&mut wgpu::CommandEncoder) -> Result<(), EngineError> + Send + 'a,
+    ) -> &mut Self {
+        self.pass(name, reads, writes, heavy, move |encoder| {
+            if let Err(e) = record(encoder) {
+                log_warning_once!("{} skipped: {}", name, e);
+            }
+        })
+    }
+    /// Pass names per group, in recording order.
+    pub fn groups(&self) -> Vec<Vec<&'static str>> {
+        self.groups
+            .iter()
+            .map(|group| group.iter().map(|&i| self.passes[i].decl.name).collect())
+            .collect()
+    }
+
+    /// Records every pass and returns the command buffers in the order the passes were added.
+    pub fn record(self, device: &wgpu::Device) -> Vec<wgpu::CommandBuffer> {
+        let _recording = RecordingGuard::new();
+        let parallel = RecordSettings::parallel();
+        let profiler = self.profiler;
+        let mut buffers: Vec<Option<wgpu::CommandBuffer>> =
+            self.passes.iter().map(|_| None).collect();
+        let mut passes: Vec<Option<PassNode<'a>>> = self.passes.into_iter().map(Some).collect();
+        for group in &self.groups {
+            let nodes: Vec<(usize, PassNode<'a>)> = group
+                .iter()
+                .filter_map(|&i| passes[i].take().map(|node| (i, node)))
+                .collect();
+            let heavy = nodes.iter().filter(|(_, node)| node.heavy).count();
+            let recorded: Vec<(usize, wgpu::CommandBuffer)> = if parallel && heavy > 1 {
+                nodes
+                    .into_par_iter()
+                    .map(|(i, node)| (i, node.encode(device, profiler)))
+                    .collect()
+            } else {
+                nodes
+                    .into_iter()
+                    .map(|(i, node)| (i, node.encode(device, profiler)))
+                    .collect()
+            };
+            for (i, buffer) in recorded {
+                buffers[i] = Some(buffer);
+            }
+        }
+        buffers.into_iter().flatten().collect()
+    }
+    /// Records every pass and submits the buffers in one `queue.submit`.
+    pub fn submit(self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        let profiler = self.profiler;
+        let buffers = self.record(device);
+        crate::gpu_scope!(Submit, "Frame");
+        let start = std::time::Instant::now();
+        queue.submit(buffers);
+        if let Some(profiler) = profiler {
+            profiler.record_cpu("Submit", start);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicBool;
+
+    use super::*;
+    use crate::RenderTargetManager;
+
+    fn device() -> Option<(wgpu::Device, wgpu::Queue)> {
+        let instance = wgpu::Instance::default();
+        let adapter =
+            pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions::default()))?;
+        pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor::default(), None)).ok()
+    }
+
+    fn clear(device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder) {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("stage test target"),
+            size: wgpu::Extent3d {
+                width: 4,
+                height: 4,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&Default::default());
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("stage test clear"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &view,
+                resolve_target: None,
+                ops: wgpu::Operations::default(),
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+    }
+
+    #[test]
+    fn groups_follow_target_hazards() {
+        let mut graph = PassGraph::new();
+        let scene = RenderTargetKind::Scene;
+        let hdr = RenderTargetKind::Hdr;
+        graph
+            .pass("Equirect", &[], &[], false, |_| {})
+            .pass("Scene", &[], &[scene], false, |_| {})
+            .pass("Particles", &[], &[], false, |_| {})
+            .pass("Hdr", &[scene], &[hdr], false, |_| {})
+            .pass("Blit", &[hdr], &[], false, |_| {});
+        assert_eq!(
+            graph.groups(),
+            vec![
+                vec!["Equirect", "Scene", "Particles"],
+                vec!["Hdr"],
+                vec!["Blit"]
+            ]
+        );
+    }
+
+    #[test]
+    fn passes_join_the_group_after_their_last_hazard() {
+        let (scene, hdr, bloom, shadow) = (
+            RenderTargetKind::Scene,
+            RenderTargetKind::Hdr,
+            RenderTargetKind::Bloom,
+            RenderTargetKind::Shadow,
+        );
+        let mut graph = PassGraph::new();
+        graph
+            .pass("Shadow", &[], &[shadow], true, |_| {})
+            .pass("Scene", &[shadow], &[scene], true, |_| {})
+            // Reading what another pass reads is no hazard.
+            .pass("Debug", &[shadow], &[], false, |_| {})
+            // Writing what an earlier pass reads is one, as is writing the same target.
+            .pass("ShadowClear", &[], &[shadow], false, |_| {})
+            .pass("Hdr", &[scene], &[hdr], true, |_| {})
+            .pass("Bloom", &[hdr], &[bloom], true, |_| {})
+            .pass("HdrOverlay", &[], &[hdr], false, |_| {})
+            // Free of every target: back to the first group, however late it's added.
+            .pass("Compute", &[], &[], true, |_| {});
+        assert_eq!(
+            graph.groups(),
+            vec![
+                vec!["Shadow", "Compute"],
+                vec!["Scene", "Debug"],
+                vec!["ShadowClear", "Hdr"],
+                vec!["Bloom"],
+                vec!["HdrOverlay"],
+            ]
+        );
+
+        // Two independent heavy chains interleave group by group.
+        let custom = |name| RenderTargetKind::Custom(name);
+        let mut graph = PassGraph::new();
+        graph
+            .pass("A1", &[], &[custom("a")], true, |_| {})
+            .pass("B1", &[], &[custom("b")], true, |_| {})
+            .pass("A2", &[custom("a")], &[custom("a2")], true, |_| {})
+            .pass("B2", &[custom("b")], &[custom("b2")], true, |_| {})
+            .pass("Join", &[custom("a2"), custom("b2")], &[], false, |_| {});
+        assert_eq!(
+            graph.groups(),
+            vec![vec!["A1", "B1"], vec!["A2", "B2"], vec!["Join"]]
+        );
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    fn caches_are_not_filled_while_recording() {
+        let rejected = AtomicBool::new(false);
+        let mut graph = PassGraph::new();
+        graph.pass("Lazy", &[], &[], false, |_| {
+            let mut pipelines = crate::RenderPipelineManager::new();
+            let filled = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                crate::CacheStorage::get_or_create(
+                    &mut pipelines,
+                    crate::CacheKey::from("lazy pipeline"),
+                    || unreachable!("the assertion fires before the pipeline is made"),
+                );
+            }));
+            rejected.store(filled.is_err(), Ordering::Relaxed);
+        });
+        assert!(!RecordSettings::recording());
+        if let Some((device, _)) = device() {
+            graph.record(&device);
+            assert!(rejected.into_inner());
+        }
+        assert!(!RecordSettings::recording());
+        // Outside recording the same call is fine.
+        crate::assert_not_recording("render pipeline");
+    }
+
+    /// Pass `i` copies the id of whichever pass ran before it into `log[i]`, then writes
+    /// its own id, `i + 1`. Returns the log as read back.
+    fn submission_log(device: &wgpu::Device, queue: &wgpu::Queue, parallel: bool) -> Vec<u32> {
+        use wgpu::util::DeviceExt;
+
+        const PASSES: u32 = 6;
+        let buffer = |label, contents: &[u32], usage| {
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(label),
+                contents: bytemuck::cast_slice(contents),
+                usage,
+            })
+        };
+        let copy = wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::COPY_DST;
+        let last = buffer("last pass", &[0], copy);
+        let log = buffer("pass log", &[u32::MAX; PASSES as usize], copy);
+        let ids: Vec<_> = (1..=PASSES)
+            .map(|id| buffer("pass id", &[id], copy))
+            .collect();
+        let readback = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("pass log readback"),
+            size: PASSES as u64 * 4,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        RecordSettings::set_parallel(parallel);
+        let names = ["P0", "P1", "P2", "P3", "P4", "P5"];
+        let target = |i: usize| [RenderTargetKind::Custom(["x", "y"][i / 3])];
+        let mut graph = PassGraph::new();
+        for (i, id) in ids.iter().enumerate() {
+            // Three hazard-free heavy passes per group, two groups.
+            let (log, last) = (&log, &last);
+            let writes = if i % 3 == 0 {
+                target(i).to_vec()
+            } else {
+                Vec::new()
+            };
+            let reads = if i >= 3 {
+                target(0).to_vec()
+            } else {
+                Vec::new()
+            };
+            graph.pass(names[i], &reads, &writes, true, move |encoder| {
+                encoder.copy_buffer_to_buffer(last, 0, log, i as u64 * 4, 4);
+                encoder.copy_buffer_to_buffer(id, 0, last, 0, 4);
+            });
+        }
+        assert_eq!(
+            graph.groups(),
+            vec![vec!["P0", "P1", "P2"], vec!["P3", "P4", "P5"]]
+        );
+        graph.submit(device, queue);
+        RecordSettings::set_parallel(true);
+
+        let mut encoder = device.create_command_encoder(&Default::default());
+        encoder.copy_buffer_to_buffer(&log, 0, &readback, 0, PASSES as u64 * 4);
+        queue.submit([encoder.finish()]);
+        let slice = readback.slice(..);
+        slice.map_async(wgpu::MapMode::Read, |_| {});
+        device.poll(wgpu::Maintain::Wait);
+        let values = bytemuck::cast_slice(&slice.get_mapped_range()).to_vec();
+        values
+    }
+
+    /// Needs an adapter; passes without one.
+    #[test]
+    fn serial_and_parallel_recording_submit_in_the_same_order() {
+        let Some((device, queue)) = device() else {
+            return;
+        };
+        let serial = submission_log(&device, &queue, false);
+        let parallel = submission_log(&device, &queue, true);
+        // Every pass ran right after the one added before it.
+        assert_eq!(serial, [0, 1, 2, 3, 4, 5]);
+        assert_eq!(parallel, serial);
+    }
+
+    /// Needs an adapter; passes without one.
+    #[test]
+    fn failed_stages_are_skipped_and_the_rest_submitted() {
+        let Some((device, queue)) = device() else {
+            return;
+        };
+        // No targets at all, as after a resize race dropped them.
+        let targets = RenderTargetManager::new();
+        let scene = RenderTargetKind::Scene;
+        let missing = targets.require(&scene).err().unwrap().to_string();
+        let (before, after) = (AtomicBool::new(false), AtomicBool::new(false));
+        let failed_after_recording = AtomicBool::new(false);
+
+        for parallel in [false, true] {
+            RecordSettings::set_parallel(parallel);
+            let mut graph = PassGraph::new();
+            graph
+                .try_pass("Compute", &[], &[], true, |encoder| {
+                    clear(&device, encoder);
+                    before.store(true, Ordering::Relaxed);
+                    Ok(())
+                })
+                .try_pass("Scene", &[], &[scene], true, |_| {
+                    targets.require(&scene)?;
+                    unreachable!("the scene target is missing");
+                })
+                .try_pass("Half", &[], &[], false, |encoder| {
+                    clear(&device, encoder);
+                    failed_after_recording.store(true, Ordering::Relaxed);
+                    Err(EngineError::AssetLoadError("halfway".to_string()))
+                })
+                .try_pass("Blit", &[scene], &[], false, |encoder| {
+                    clear(&device, encoder);
+                    after.store(true, Ordering::Relaxed);
+                    Ok(())
+                });
+
+            device.push_error_scope(wgpu::ErrorFilter::Validation);
+            let buffers = graph.record(&device);
+            assert_eq!(buffers.len(), 4, "every stage gets a buffer, failed or not");
+            queue.submit(buffers);
+            let error = pollster::block_on(device.pop_error_scope());
+            assert!(error.is_none(), "{:?}", error);
+        }
+        RecordSettings::set_parallel(true);
+        assert!(before.into_inner() && after.into_inner());
+        assert!(failed_after_recording.into_inner());
+        if cfg!(feature = "logging") {
+            // Logged on the first frame, not again on the second.
+            assert!(!crate::first_warning(&format!(
+                "Scene skipped: {}",
+                missing
+            )));
+            let halfway = EngineError::AssetLoadError("halfway".to_string());
+            assert!(!crate::first_warning(&format!("Half skipped: {}", halfway)));
+        }
+    }
+}
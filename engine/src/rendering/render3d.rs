@@ -1,18 +1,30 @@
 use {
-    super::{DebugMode, PipelineManager, RenderPass, VertexInstance, AABB, HDR},
+    super::{
+        back_to_front, entity_id, AutoExposure, BillboardBuffers, BillboardInstance, DebugMode,
+        DepthPrepass, DrawList, DrawPath, EntityPicker, ImpostorBuffers, LodSelection,
+        ObjectBuffer, ObjectDataSettings, ParticleSystem, PipelineManager, RenderPass,
+        SkinnedInstance, TonemapSettings, TonemapUniform, TransparentInstances, TransparentRun,
+        VertexInstance, WaterPass, HDR,
+    },
     crate::{
-        camera::{self, Frustum},
-        BindGroup, CacheKey, CacheStorage, EngineError, FrameBuffer, ModelManager, Rotation, Scale,
+        camera, BindGroup, CacheKey, CacheStorage, EngineError, Entity, FrameBuffer, HashCache,
+        Material, Mesh, MeshInstance, Model, ModelManager, RenderBindGroupLayouts, Rotation, Scale,
         Texture, Transform, WgpuBuffer, World,
     },
-    glam::{Mat4, Vec3},
-    wgpu::IndexFormat,
+    glam::{Mat4, Vec2, Vec3},
+    std::ops::Range,
 };
 
 #[warn(dead_code)]
 pub struct Renderer3d {
     hdr: HDR,
     pub instances: InstanceBuffers,
+    pub particles: ParticleSystem,
+    pub water: WaterPass,
+    exposure: AutoExposure,
+    /// Exposure 1, for the final blit: exposure is applied once, in the HDR pass.
+    _neutral_tonemap: WgpuBuffer,
+    neutral_tonemap_bind_group: wgpu::BindGroup,
 }
 
 impl Renderer3d {
@@ -21,14 +33,104 @@ impl Renderer3d {
         surface_config: &wgpu::SurfaceConfiguration,
     ) -> Result<Self, EngineError> {
         let hdr = PipelineManager::hdr(device, surface_config)?;
-        let instances = InstanceBuffers::new();
+        let instances = InstanceBuffers::new(device, surface_config.format);
+        let particles = ParticleSystem::new(
+            device,
+            surface_config.format,
+            ParticleSystem::DEFAULT_CAPACITY,
+        );
+        let water = WaterPass::new(device, surface_config.format);
+        let exposure = AutoExposure::new(device, TonemapSettings::default())?;
+        let neutral_tonemap = WgpuBuffer::from_data(
+            device,
+            &[TonemapUniform::new(1.0)],
+            wgpu::BufferUsages::UNIFORM,
+            Some("neutral tonemap uniform"),
+        );
+        let neutral_tonemap_bind_group = BindGroup::tonemap(device, &neutral_tonemap, "neutral");
+
+        Ok(Renderer3d {
+            hdr,
+            instances,
+            particles,
+            water,
+            exposure,
+            _neutral_tonemap: neutral_tonemap,
+            neutral_tonemap_bind_group,
+        })
+    }
+
+    pub fn exposure(&self) -> &AutoExposure {
+        &self.exposure
+    }
+    pub fn exposure_mut(&mut self) -> &mut AutoExposure {
+        &mut self.exposure
+    }
+    /// Measures `scene_texture` and updates the exposure the next [`Renderer3d::hdr`] uses.
+    pub fn auto_exposure(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        scene_texture: &Texture,
+    ) {
+        self.exposure.dispatch(device, encoder, scene_texture);
+    }
+    /// Advances the particles the next [`RenderPass::render`] draws.
+    pub fn simulate_particles(&self, encoder: &mut wgpu::CommandEncoder) {
+        self.particles.dispatch(encoder);
+    }
 
-        Ok(Renderer3d { hdr, instances })
+    /// Builds what the debug view needs for the meshes the next [`RenderPass::render`] draws
+    /// with it: instanced and transparent models, and terrain with its water.
+    pub fn prepare_debug(&self, debug: &mut DebugMode, models: &ModelManager, world: &World) {
+        if debug.mode() == 0 {
+            return;
+        }
+        let instanced = self.instances.draws.commands().iter();
+        let transparent = self.instances.transparent.runs().iter();
+        let model_meshes = instanced.map(|command| command.mesh.as_ref()).chain(
+            transparent
+                .filter_map(|run| models.get(&run.model_key))
+                .map(|model| model.instance.mesh.as_ref()),
+        );
+        let terrain = world.terrain.visible_mesh_instances().chain(
+            world
+                .terrain
+                .transparent_meshes()
+                .chain(world.terrain.water_surfaces())
+                .map(|(instance, ..)| instance),
+        );
+        let meshes = model_meshes.chain(terrain.map(|instance| instance.mesh.as_ref()));
+        debug.prepare(&models.device, &models.queue, meshes);
     }
 
+    /// Records the depth pre-pass over this frame's opaque draws and terrain into the scene
+    /// depth, for a main pass started with [`FrameBuffer::depth_attachment_loaded`].
+    pub fn depth_prepass(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        scene_fb: &FrameBuffer,
+        world: &World,
+        uniform_bind_group: &wgpu::BindGroup,
+    ) {
+        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Depth Prepass"),
+            color_attachments: &[],
+            depth_stencil_attachment: scene_fb.depth_attachment(),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        self.instances.prepass.record(
+            &mut rpass,
+            uniform_bind_group,
+            &self.instances.draws,
+            &world.terrain,
+        );
+    }
     pub fn compute_pass(&self, world: &World, queue: &wgpu::Queue, device: &wgpu::Device) {
-        let projection = world.projection();
-        projection.compute_projection(queue, device, Some("equirect projection compute pass"));
+        if let Some(projection) = world.projection() {
+            projection.compute_projection(queue, device, Some("equirect projection compute pass"));
+        }
     }
     pub fn final_blit_to_surface(
         &self,
@@ -36,7 +138,9 @@ impl Renderer3d {
         encoder: &mut wgpu::CommandEncoder,
         hdr_texture: &Texture,
         surface_view: &wgpu::TextureView,
-    ) {
+    ) -> Result<(), EngineError> {
+        crate::gpu_scope!(Pass, "Final Blit to Surface");
+        RenderBindGroupLayouts::try_get()?;
         let bind_group = BindGroup::hdr(&device, hdr_texture, "final blit");
 
         let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
@@ -56,7 +160,9 @@ impl Renderer3d {
 
         pass.set_pipeline(&self.hdr.pipeline());
         pass.set_bind_group(0, &bind_group, &[]);
+        pass.set_bind_group(1, &self.neutral_tonemap_bind_group, &[]);
         pass.draw(0..3, 0..1);
+        Ok(())
     }
 
     pub fn hdr(
@@ -65,7 +171,9 @@ impl Renderer3d {
         model_manager: &ModelManager,
         scene_texture: &Texture,
         hdr_fb: &FrameBuffer,
-    ) {
+    ) -> Result<(), EngineError> {
+        crate::gpu_scope!(Pass, "HDR Pass");
+        RenderBindGroupLayouts::try_get()?;
         let bind_group = BindGroup::hdr(&model_manager.device, scene_texture, "hdr input");
 
         let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
@@ -78,20 +186,132 @@ impl Renderer3d {
 
         pass.set_pipeline(&self.hdr.pipeline());
         pass.set_bind_group(0, &bind_group, &[]);
+        pass.set_bind_group(1, self.exposure.tonemap_bind_group(), &[]);
         pass.draw(0..3, 0..1);
+        Ok(())
+    }
+
+    /// The blend pass: transparent model instances and terrain meshes, farthest from the
+    /// eye first. Their pipelines test depth against the opaque pass without writing it.
+    /// Water surfaces are drawn here only when the water pass isn't, with their material.
+    fn render_transparent(
+        &self,
+        models: &ModelManager,
+        rpass: &mut wgpu::RenderPass,
+        world: &World,
+        uniform_bind_group: &wgpu::BindGroup,
+        debug_mode: &DebugMode,
+    ) {
+        enum Draw<'a> {
+            Instances(&'a TransparentRun),
+            Terrain(&'a MeshInstance, &'a InstanceBufferData, Range<u32>),
+        }
+        let eye = self.instances.eye();
+        let mut draws: Vec<(f32, Draw)> = self
+            .instances
+            .transparent
+            .runs()
+            .iter()
+            .map(|run| (run.distance, Draw::Instances(run)))
+            .collect();
+        let water = (debug_mode.mode() > 0 || !self.water.active())
+            .then(|| world.terrain.water_surfaces())
+            .into_iter()
+            .flatten();
+        draws.extend(world.terrain.transparent_meshes().chain(water).map(
+            |(instance, buffer, instances, origin)| {
+                (
+                    eye.distance(origin),
+                    Draw::Terrain(instance, buffer, instances),
+                )
+            },
+        ));
+        if draws.is_empty() {
+            return;
+        }
+        crate::gpu_scope!(Pass, "Transparent");
+        draws.sort_by(|a, b| back_to_front(a.0, b.0));
+
+        for (_, draw) in draws {
+            match draw {
+                Draw::Instances(run) => {
+                    let Some(buffer) = self.instances.transparent.buffer() else {
+                        continue;
+                    };
+                    let Some(model) = models.get(&run.model_key) else {
+                        continue;
+                    };
+                    let Some(mat) = &model.instance.material else {
+                        continue;
+                    };
+                    crate::gpu_scope!(Draw, model.name, mat.asset.name);
+                    draw_mesh(
+                        rpass,
+                        &model.instance.mesh,
+                        mat,
+                        buffer,
+                        run.instances.clone(),
+                        debug_mode,
+                        uniform_bind_group,
+                    );
+                }
+                Draw::Terrain(instance, buffer, instances) => {
+                    let Some(mat) = instance.material.as_ref() else {
+                        continue;
+                    };
+                    crate::gpu_scope!(Draw, "terrain", mat.asset.name);
+                    draw_mesh(
+                        rpass,
+                        &instance.mesh,
+                        mat,
+                        &buffer.buffer,
+                        instances,
+                        debug_mode,
+                        uniform_bind_group,
+                    );
+                }
+            }
+        }
     }
 }
 
+/// Draws `instances` of `mesh` from `instance_buffer` with `material`, or with the debug
+/// pipeline while a debug view is on.
+fn draw_mesh(
+    rpass: &mut wgpu::RenderPass,
+    mesh: &Mesh,
+    material: &Material,
+    instance_buffer: &WgpuBuffer,
+    instances: Range<u32>,
+    debug: &DebugMode,
+    uniform_bind_group: &wgpu::BindGroup,
+) {
+    rpass.set_bind_group(3, material.bind_group.as_ref(), &[]);
+    if debug.mode() > 0 {
+        debug.draw(rpass, mesh, instance_buffer.get(), instances);
+        return;
+    }
+    rpass.set_vertex_buffer(0, mesh.vertex_buffer.get().slice(..));
+    rpass.set_vertex_buffer(1, instance_buffer.slice());
+    rpass.set_index_buffer(mesh.index_buffer.get().slice(..), mesh.index_format);
+    rpass.set_bind_group(0, uniform_bind_group, &[]);
+    rpass.set_pipeline(&material.pipeline);
+    rpass.draw_indexed(0..mesh.index_count, 0, instances);
+}
+
 impl RenderPass for Renderer3d {
+    /// Draws the opaque pass, then the sorted transparent one and the particles.
     fn render(
         &self,
-        models: &mut ModelManager,
+        models: &ModelManager,
         rpass: &mut wgpu::RenderPass,
         world: &World,
         uniform_bind_group: &wgpu::BindGroup,
         debug_mode: &DebugMode,
     ) {
-        let projection = world.projection();
+        let Some(projection) = world.projection() else {
+            return;
+        };
 
         rpass.set_bind_group(0, uniform_bind_group, &[]);
         rpass.set_bind_group(1, &projection.dst_bind_group, &[]);
@@ -99,40 +319,58 @@ impl RenderPass for Renderer3d {
 
         rpass.set_pipeline(&projection.dst_pipeline);
         rpass.draw(0..3, 0..1);
+        // Materials reflect the prefiltered environment rather than the sky itself.
+        rpass.set_bind_group(1, &projection.reflection.bind_group, &[]);
 
         self.instances
             .draw(rpass, models, debug_mode, uniform_bind_group);
 
         {
-            for instance in world.terrain.mesh_instances() {
+            for (instance, instances) in world.terrain.visible_chunk_draws() {
                 let Some(mat) = instance.material.as_ref() else {
                     continue;
                 };
+                if mat.asset.transparent {
+                    continue;
+                }
 
                 let Some(instance_buffer) = world.terrain.instance_buffer() else {
                     continue;
                 };
 
                 let mesh = &instance.mesh;
+                crate::gpu_scope!(Draw, "terrain", mat.asset.name);
 
                 rpass.set_bind_group(3, mat.bind_group.as_ref(), &[]);
 
-                rpass.set_vertex_buffer(0, mesh.vertex_buffer.get().slice(..));
-                rpass.set_vertex_buffer(1, instance_buffer.buffer.get().slice(..));
-
-                rpass.set_index_buffer(mesh.index_buffer.get().slice(..), IndexFormat::Uint32);
-
                 if debug_mode.mode() > 0 {
-                    rpass.set_bind_group(0, debug_mode.bind_group(), &[]);
-                    rpass.set_pipeline(debug_mode.pipeline());
-                    rpass.draw_indexed(0..mesh.index_count, 0, 0..instance_buffer.count as u32);
+                    debug_mode.draw(rpass, mesh, instance_buffer.buffer.get(), instances);
                 } else {
+                    rpass.set_vertex_buffer(0, mesh.vertex_buffer.get().slice(..));
+                    rpass.set_vertex_buffer(1, instance_buffer.buffer.slice());
+                    rpass.set_index_buffer(mesh.index_buffer.get().slice(..), mesh.index_format);
                     rpass.set_bind_group(0, uniform_bind_group, &[]);
-                    rpass.set_pipeline(&mat.pipeline);
-                    rpass.draw_indexed(0..mesh.index_count, 0, 0..instance_buffer.count as u32);
+                    let prepass = &self.instances.prepass;
+                    rpass.set_pipeline(prepass.main_pipeline(mat).unwrap_or(&mat.pipeline));
+                    rpass.draw_indexed(0..mesh.index_count, 0, instances);
                 }
             }
         }
+
+        if debug_mode.mode() == 0 {
+            self.instances
+                .impostors
+                .draw(rpass, models, uniform_bind_group);
+            self.instances.billboards.draw(rpass, uniform_bind_group);
+            // Impostors and billboards bind their texture at group 1.
+            rpass.set_bind_group(1, &projection.reflection.bind_group, &[]);
+        }
+
+        self.render_transparent(models, rpass, world, uniform_bind_group, debug_mode);
+
+        if debug_mode.mode() == 0 {
+            self.particles.draw(rpass, uniform_bind_group);
+        }
     }
 }
 
@@ -147,16 +385,55 @@ pub struct InstanceBufferData {
 #[derive(Debug)]
 pub struct InstanceBuffers {
     pub batch: std::collections::HashMap<CacheKey, Vec<VertexInstance>>,
-    pub buffers: std::collections::HashMap<CacheKey, InstanceBufferData>,
+    /// What's left in `batch` after routing, as sorted draws over one instance buffer.
+    pub draws: DrawList,
+    /// Models with few enough visible instances, drawn one object at a time.
+    pub objects: ObjectBuffer,
+    /// Entities far enough out to draw as their model's impostor.
+    pub impostors: ImpostorBuffers,
+    /// Camera-facing sprites of [`crate::Billboard`] entities.
+    pub billboards: BillboardBuffers,
+    /// Depth-only pass over `draws` and opaque terrain, when enabled.
+    pub prepass: DepthPrepass,
+    /// Entity id pass over the opaque models and terrain, on frames with a pick pending.
+    pub picker: EntityPicker,
+    /// Instances of models with a transparent material, drawn in the blend pass.
+    pub transparent: TransparentInstances,
+    /// Level of detail each entity draws at.
+    pub lods: LodSelection,
+    /// Instances drawing a coarser level, by model key, level and mesh of the level. These
+    /// are always instanced, next to `batch` in `draws`.
+    lod_batch: std::collections::HashMap<(CacheKey, usize, usize), Vec<VertexInstance>>,
+    /// Camera eye of the last [`InstanceBuffers::update`], which transparent draws sort by.
+    eye: Vec3,
+    object_path: bool,
 }
 
 impl InstanceBuffers {
-    pub fn new() -> Self {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
         Self {
             batch: std::collections::HashMap::new(),
-            buffers: std::collections::HashMap::new(),
+            draws: DrawList::default(),
+            objects: ObjectBuffer::new(device),
+            impostors: ImpostorBuffers::new(format),
+            billboards: BillboardBuffers::new(format),
+            prepass: DepthPrepass::default(),
+            picker: EntityPicker::default(),
+            transparent: TransparentInstances::default(),
+            lods: LodSelection::default(),
+            lod_batch: std::collections::HashMap::new(),
+            eye: Vec3::ZERO,
+            object_path: true,
         }
     }
+    pub fn eye(&self) -> Vec3 {
+        self.eye
+    }
+    /// Turns routing to the object path on or off; off, everything is instanced. Debug
+    /// views only have an instanced pipeline, so they turn it off.
+    pub fn set_object_path(&mut self, enabled: bool) {
+        self.object_path = enabled;
+    }
 
     pub fn update(
         &mut self,
@@ -165,12 +442,26 @@ impl InstanceBuffers {
         model_manager: &mut ModelManager,
     ) {
         let frustum = camera.frustum();
+        let mask = camera.render_mask();
         self.batch.clear();
+        self.objects.clear();
+        self.impostors.clear();
+        self.billboards.clear();
+        self.transparent.clear();
+        self.lods.clear();
+        self.lod_batch.clear();
+        model_manager.animations.clear();
+        let eye = *camera.eye();
+        self.eye = eye;
+        let mut entities: std::collections::HashMap<CacheKey, Vec<usize>> =
+            std::collections::HashMap::new();
+        let mut skinned: Vec<SkinnedInstance> = Vec::new();
 
         let default_scale = Scale::one();
         let default_rotation = Rotation::zero();
 
-        for idx in 0..world.entity_count() {
+        let batched = world.instance_batches().iter();
+        for idx in batched.flat_map(|(_, entities)| entities.iter().map(|e| e.0)) {
             let Some(renderable) = &world.renderables[idx] else {
                 continue;
             };
@@ -178,7 +469,7 @@ impl InstanceBuffers {
                 continue;
             };
 
-            if !renderable.visible {
+            if !renderable.visible || !world.layers(Entity(idx)).intersects(mask) {
                 continue;
             }
 
@@ -186,57 +477,194 @@ impl InstanceBuffers {
             let scale = world.scales[idx].as_ref().unwrap_or(&default_scale);
 
             let transform = Transform::from_components(position, rotation, scale);
+            let animator = world.animators[idx]
+                .filter(|_| self.object_path && model_manager.can_skin(&renderable.model_key));
 
             if let Some(model) = model_manager.models.get(&renderable.model_key) {
-                if !frustum_cull_aabb(&frustum, &model.aabb, &transform.model_matrix) {
+                if !frustum.contains_transformed_aabb(&model.aabb, &transform.model_matrix) {
                     continue;
                 }
-                if let Some(material) = &model.instance.material {
-                    let data = transform.to_vertex_instance(material.idx);
-                    self.batch
-                        .entry(renderable.model_key)
-                        .or_default()
-                        .push(data);
+                let distance = eye.distance(transform.model_matrix.w_axis.truncate());
+                if let Some(impostor) = model_manager.impostors.get(&renderable.model_key) {
+                    if self.impostors.select(idx, distance, impostor.meta.distance) {
+                        let tint = world.tints[idx].as_ref().map_or([1.0; 4], |tint| tint.0);
+                        self.impostors.push(
+                            renderable.model_key,
+                            impostor.instance(&transform.model_matrix, eye, tint),
+                        );
+                        continue;
+                    }
+                }
+                let level = self.lods.select(idx, distance, &model.lods);
+                // Skinned primitives draw through the skinned path, the rest as usual.
+                let skin = animator.and_then(|animator| {
+                    let tint = world.tints[idx].as_ref().map_or([1.0; 4], |tint| tint.0);
+                    skinned.push(SkinnedInstance {
+                        model_key: renderable.model_key,
+                        model: transform.model_matrix,
+                        tint,
+                        animator,
+                    });
+                    model_manager.animations.skin(&renderable.model_key)
+                });
+                // Parts batch under their own keys so each draws with its own material.
+                let keys = std::iter::once(renderable.model_key).chain(model.parts.iter().copied());
+                for key in keys {
+                    if skin.is_some_and(|skin| skin.covers(&key)) {
+                        continue;
+                    }
+                    let Some(part) = model_manager.models.get(&key) else {
+                        continue;
+                    };
+                    let Some(material) = &part.instance.material else {
+                        continue;
+                    };
+                    let mut data = transform.to_vertex_instance(material.idx);
+                    if let Some(tint) = &world.tints[idx] {
+                        data.color = tint.0;
+                    }
+                    if world.selected().is_some_and(|selected| selected.0 == idx) {
+                        data.flags |= VertexInstance::SELECTED;
+                    }
+                    data.entity_id = entity_id(Entity(idx));
+                    if material.asset.transparent {
+                        self.transparent.push(key, data, eye);
+                        continue;
+                    }
+                    // Parts draw at the entity's level where they have one, else in full.
+                    if let Some(lod) = level.checked_sub(1).and_then(|i| part.lods.get(i)) {
+                        for (mesh, instance) in lod.meshes.iter().enumerate() {
+                            let mut data = data;
+                            if let Some(material) = &instance.material {
+                                data.material_id = material.idx;
+                            }
+                            self.lod_batch
+                                .entry((key, level, mesh))
+                                .or_default()
+                                .push(data);
+                        }
+                        continue;
+                    }
+                    self.batch.entry(key).or_default().push(data);
+                    entities.entry(key).or_default().push(idx);
                 }
             }
         }
+        self.transparent.sort();
 
-        for (key, data) in &self.batch {
-            let instances = data;
-
-            let byte_data = VertexInstance::bytes(instances);
-            let byte_size = data.len();
-            let buffer_data = self
-                .buffers
-                .entry(*key)
-                .or_insert_with(|| InstanceBufferData {
-                    buffer: WgpuBuffer::from_data(
-                        &model_manager.device,
-                        &byte_data,
-                        wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
-                        Some(&format!(" instance buffer {}", key.id())),
-                    ),
-                    count: instances.len(),
-                    capacity: byte_size,
-                    dirty: false,
-                });
-
-            buffer_data.count = instances.len();
-            buffer_data.dirty = true;
+        for (idx, billboard) in world.billboards.iter().enumerate() {
+            let Some(billboard) = billboard else {
+                continue;
+            };
+            if !world.layers(Entity(idx)).intersects(mask) {
+                continue;
+            }
+            let Some(position) = &world.physics.positions[idx] else {
+                continue;
+            };
+            let radius = Vec2::from(billboard.size).length() * 0.5;
+            if !frustum.contains_sphere(position.0, radius) {
+                continue;
+            }
+            let tint = world.tints[idx].as_ref().map_or([1.0; 4], |tint| tint.0);
+            self.billboards.push(
+                billboard.texture,
+                BillboardInstance::new(position.0.to_array(), billboard.size, tint),
+            );
         }
-    }
 
-    pub fn upload(&mut self, queue: &wgpu::Queue, device: &wgpu::Device) {
-        for (key, data) in &mut self.buffers {
-            if let Some(instances) = self.batch.get(key) {
-                if data.dirty {
-                    let byte_data = VertexInstance::bytes(instances);
-                    data.buffer.write_data(queue, device, &byte_data, Some(0));
-                    data.dirty = false;
-                    data.count = instances.len();
+        // Before objects leave `batch`, so the id pass covers both paths.
+        self.picker.poll();
+        self.picker.prepare(
+            &model_manager.device,
+            &mut model_manager.materials,
+            opaque_batches(&model_manager.models, &self.batch, &self.lod_batch),
+            world
+                .terrain
+                .visible_mesh_instances()
+                .filter_map(|instance| instance.material.as_deref()),
+        );
+
+        let threshold = if self.object_path {
+            ObjectDataSettings::threshold()
+        } else {
+            0
+        };
+        let keys: Vec<CacheKey> = self.batch.keys().copied().collect();
+        for key in keys {
+            let count = self.batch[&key].len();
+            if DrawPath::route(count, threshold, true) == DrawPath::Instanced {
+                continue;
+            }
+            let material = model_manager
+                .models
+                .get(&key)
+                .and_then(|model| model.instance.material.clone());
+            let has_pipeline = material
+                .and_then(|material| self.objects.pipeline(model_manager, &material))
+                .is_some();
+            if DrawPath::route(count, threshold, has_pipeline) == DrawPath::Object {
+                let instances = self.batch.remove(&key).unwrap_or_default();
+                for (data, entity) in instances.iter().zip(&entities[&key]) {
+                    self.objects.push(
+                        *entity,
+                        key,
+                        Mat4::from_cols_array_2d(&data.model),
+                        data.color,
+                        data.material_id,
+                        data.flags,
+                    );
                 }
             }
         }
+        self.objects.upload(
+            &model_manager.queue,
+            &model_manager.device,
+            &model_manager.materials.storage_buffer,
+        );
+        self.impostors.upload(model_manager);
+        self.billboards.upload(model_manager);
+        self.draws.build(opaque_batches(
+            &model_manager.models,
+            &self.batch,
+            &self.lod_batch,
+        ));
+        self.draws.prepare(model_manager);
+        model_manager.draw_stats = self.draws.stats();
+        model_manager.lod_stats = self.lods.stats().clone();
+        let terrain = world.terrain.visible_mesh_instances();
+        let materials = self
+            .draws
+            .commands()
+            .iter()
+            .map(|command| &command.material);
+        self.prepass.prepare(
+            model_manager,
+            materials
+                .chain(terrain.filter_map(|instance| instance.material.as_ref()))
+                .map(|material| material.as_ref()),
+        );
+
+        let ModelManager {
+            animations,
+            materials,
+            models,
+            device,
+            queue,
+            ..
+        } = model_manager;
+        for instance in &skinned {
+            animations.push(device, materials, models, instance);
+        }
+        animations.upload(queue, device, &materials.storage_buffer);
+    }
+
+    /// Writes this frame's instances. `materials` is the material storage buffer, bound
+    /// next to the instances on the storage path.
+    pub fn upload(&mut self, queue: &wgpu::Queue, device: &wgpu::Device, materials: &WgpuBuffer) {
+        self.transparent.upload(queue, device);
+        self.draws.upload(queue, device, materials);
+        self.picker.upload(queue, device, materials);
     }
 
     pub fn draw(
@@ -246,57 +674,46 @@ impl InstanceBuffers {
         debug: &DebugMode,
         uniform_bind_group: &wgpu::BindGroup,
     ) {
-        for (model_key, data) in &self.buffers {
-            if data.count == 0 {
-                continue;
-            }
-
-            let Some(model) = models.get(model_key) else {
-                continue;
-            };
-            let Some(mat) = &model.instance.material else {
-                continue;
-            };
-
-            let mesh = &model.instance.mesh;
-
-            rpass.set_bind_group(3, mat.bind_group.as_ref(), &[]);
-
-            rpass.set_vertex_buffer(0, mesh.vertex_buffer.get().slice(..));
-            rpass.set_vertex_buffer(1, data.buffer.get().slice(..));
-            rpass.set_index_buffer(mesh.index_buffer.get().slice(..), IndexFormat::Uint32);
-
-            if debug.mode() > 0 {
-                rpass.set_bind_group(0, debug.bind_group(), &[]);
-                rpass.set_pipeline(debug.pipeline());
-                rpass.draw_indexed(0..mesh.index_count, 0, 0..data.count as u32);
-            } else {
-                rpass.set_bind_group(0, uniform_bind_group, &[]);
-                rpass.set_pipeline(&mat.pipeline);
-                rpass.draw_indexed(0..mesh.index_count, 0, 0..data.count as u32);
-            }
+        self.draws.record(
+            rpass,
+            uniform_bind_group,
+            &models.materials.storage_bind_group,
+            debug,
+            &self.prepass,
+        );
+        if debug.mode() == 0 {
+            self.objects.draw(rpass, models, uniform_bind_group);
+            models.animations.draw(rpass, models, uniform_bind_group);
         }
     }
 }
 
-pub fn frustum_cull_aabb(frustum: &Frustum, aabb: &AABB, model_matrix: &Mat4) -> bool {
-    let corners = [
-        Vec3::new(aabb.min.x, aabb.min.y, aabb.min.z),
-        Vec3::new(aabb.min.x, aabb.min.y, aabb.max.z),
-        Vec3::new(aabb.min.x, aabb.max.y, aabb.min.z),
-        Vec3::new(aabb.min.x, aabb.max.y, aabb.max.z),
-        Vec3::new(aabb.max.x, aabb.min.y, aabb.min.z),
-        Vec3::new(aabb.max.x, aabb.min.y, aabb.max.z),
-        Vec3::new(aabb.max.x, aabb.max.y, aabb.min.z),
-        Vec3::new(aabb.max.x, aabb.max.y, aabb.max.z),
-    ];
-    for plane in frustum.planes.iter() {
-        if corners
-            .iter()
-            .all(|corner| plane.distance(model_matrix.transform_point3(*corner)) < 0.0)
-        {
-            return false;
-        }
-    }
-    return true;
+/// The instances of `batch` and `lod_batch` with the mesh and material each draws with.
+fn opaque_batches<'a>(
+    models: &'a HashCache<std::sync::Arc<Model>>,
+    batch: &'a std::collections::HashMap<CacheKey, Vec<VertexInstance>>,
+    lod_batch: &'a std::collections::HashMap<(CacheKey, usize, usize), Vec<VertexInstance>>,
+) -> impl Iterator<
+    Item = (
+        &'a std::sync::Arc<Mesh>,
+        &'a std::sync::Arc<Material>,
+        &'a [VertexInstance],
+    ),
+> {
+    let lods = lod_batch
+        .iter()
+        .filter_map(|((key, level, mesh), instances)| {
+            let model = models.get(key)?;
+            let lod = model.lods.get(level - 1)?.meshes.get(*mesh)?;
+            let material = lod.material.as_ref().or(model.instance.material.as_ref())?;
+            Some((&lod.mesh, material, instances.as_slice()))
+        });
+    batch
+        .iter()
+        .filter_map(|(key, instances)| {
+            let model = models.get(key)?;
+            let material = model.instance.material.as_ref()?;
+            Some((&model.instance.mesh, material, instances.as_slice()))
+        })
+        .chain(lods)
 }
@@ -1,32 +1,84 @@
 pub struct RenderTargetManager {
     targets: std::collections::HashMap<crate::RenderTargetKind, crate::FrameBuffer>,
+    /// Targets kept at a fraction of the window size, by the divisor of each side.
+    divisors: std::collections::HashMap<crate::RenderTargetKind, u32>,
+    /// Every target is kept at the window size times this, before the divisors.
+    scale: f32,
+    transient: crate::TransientPool,
 }
 
 impl RenderTargetManager {
     pub fn new() -> Self {
         Self {
             targets: std::collections::HashMap::new(),
+            divisors: std::collections::HashMap::new(),
+            scale: 1.0,
+            transient: crate::TransientPool::new(),
         }
     }
 
     pub fn insert(&mut self, fb: crate::FrameBuffer, kind: crate::RenderTargetKind) {
+        self.divisors.remove(&kind);
         self.targets.insert(kind, fb);
     }
 
+    /// Like [`RenderTargetManager::insert`], for a target created at the window size
+    /// divided by `divisor`; [`RenderTargetManager::resize`] keeps it at that fraction.
+    pub fn insert_scaled(
+        &mut self,
+        fb: crate::FrameBuffer,
+        kind: crate::RenderTargetKind,
+        divisor: u32,
+    ) {
+        self.targets.insert(kind, fb);
+        self.divisors.insert(kind, divisor);
+    }
+
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+    /// Sizes later [`RenderTargetManager::resize`]s at the window size times `scale`;
+    /// targets already inserted keep their size until then.
+    pub fn set_scale(&mut self, scale: f32) {
+        self.scale = scale;
+    }
+
+    pub fn contains(&self, kind: &crate::RenderTargetKind) -> bool {
+        self.targets.contains_key(kind)
+    }
+
     pub fn resize<S: Into<crate::FrameBufferSize> + std::marker::Copy>(
         &mut self,
         device: &wgpu::Device,
         size: S,
     ) {
-        for fb in &mut self.targets.values_mut() {
-            fb.resize(device, size.into());
+        let scaled = size.into().scaled(self.scale);
+        for (kind, fb) in &mut self.targets {
+            let size = match self.divisors.get(kind) {
+                Some(divisor) => scaled.scaled_down(*divisor),
+                None => scaled,
+            };
+            fb.resize(device, size);
         }
+        self.transient.invalidate();
     }
 
     pub fn get(&self, kind: &crate::RenderTargetKind) -> Option<&crate::FrameBuffer> {
         self.targets.get(kind)
     }
 
+    /// [`RenderTargetManager::get`] for passes that can't run without the target.
+    pub fn require(
+        &self,
+        kind: &crate::RenderTargetKind,
+    ) -> Result<&crate::FrameBuffer, crate::EngineError> {
+        self.get(kind)
+            .ok_or_else(|| crate::EngineError::MissingResource {
+                kind: "render target",
+                key: format!("{:?}", kind),
+            })
+    }
+
     pub fn get_mut(&mut self, kind: &crate::RenderTargetKind) -> Option<&mut crate::FrameBuffer> {
         self.targets.get_mut(kind)
     }
@@ -41,4 +93,13 @@ impl RenderTargetManager {
         self.get(kind)
             .map(|fb| (fb.color_attachment(), fb.depth_attachment()))
     }
+
+    /// Pooled targets that alias each other; persistent ones are inserted as `FrameBuffer`s.
+    pub fn transient(&self) -> &crate::TransientPool {
+        &self.transient
+    }
+
+    pub fn transient_mut(&mut self) -> &mut crate::TransientPool {
+        &mut self.transient
+    }
 }
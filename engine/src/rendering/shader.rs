@@ -3,32 +3,122 @@ impl Shader {
     pub const DEFAULT: &str = "v_normal.wgsl";
 
     pub fn load(shader: &str) -> Result<wgpu::ShaderModule, crate::EngineError> {
-        let binding = crate::GPU::get();
-        let gpu = binding
-            .read()
-            .map_err(|e| crate::EngineError::PoisonError(format!("{}", e.to_string())))?;
+        let device = crate::GPU::with_read_recovered(|gpu| gpu.device().clone())?;
 
-        let path = crate::Asset::base_path().join("shaders").join(shader);
+        let path = crate::AssetPaths::shader(shader);
 
         let shader_source = std::fs::read_to_string(&path)?;
-        let shader_module = gpu
-            .device()
-            .create_shader_module(wgpu::ShaderModuleDescriptor {
-                label: Some(shader),
-                source: wgpu::ShaderSource::Wgsl(shader_source.into()),
-            });
+        let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some(shader),
+            source: wgpu::ShaderSource::Wgsl(shader_source.into()),
+        });
         Ok(shader_module)
     }
+    /// Shader name of `path` under `assets/shaders`, if it is a WGSL file there.
+    pub fn name_of(path: &std::path::Path) -> Option<String> {
+        if path.extension().and_then(|e| e.to_str()) != Some("wgsl") {
+            return None;
+        }
+        let dir = crate::AssetPaths::shaders_dir();
+        let name = path.strip_prefix(&dir).ok()?.to_str()?;
+        Some(name.replace('\\', "/"))
+    }
+    /// Watches `assets/shaders` and publishes [`crate::EngineEvent::AssetChanged`] on the
+    /// [`crate::EventBus`] for every modified or created WGSL file. Keep the watcher alive
+    /// for as long as events should arrive.
+    pub fn watch() -> Result<crate::AssetWatcher, crate::EngineError> {
+        let dir = crate::AssetPaths::shaders_dir();
+        crate::AssetWatcher::new(dir, move |event| {
+            if !matches!(
+                event.kind,
+                notify::EventKind::Modify(_) | notify::EventKind::Create(_)
+            ) {
+                return;
+            }
+            for path in event.paths {
+                if Self::name_of(&path).is_some() {
+                    crate::EventBus::publish(crate::EngineEvent::AssetChanged(path));
+                }
+            }
+        })
+        .map_err(|e| crate::EngineError::FileSystemError(e.to_string()))
+    }
 }
 pub struct ShaderManager {
     pub shaders: crate::HashCache<std::sync::Arc<wgpu::ShaderModule>>,
+    overrides: std::collections::HashMap<String, Vec<crate::OverrideDecl>>,
+    /// Keys of the `load_variant` modules built from each shader.
+    variants:
+        std::collections::HashMap<crate::CacheKey, std::collections::HashSet<crate::CacheKey>>,
 }
 
 impl ShaderManager {
     pub fn new() -> Self {
         Self {
             shaders: crate::HashCache::new(),
+            overrides: std::collections::HashMap::new(),
+            variants: std::collections::HashMap::new(),
+        }
+    }
+    /// Drops the cached module under `key` along with its variants and parsed overrides,
+    /// so the next load reads the source again. Returns what was dropped, for
+    /// [`ShaderManager::restore`].
+    pub fn invalidate(
+        &mut self,
+        key: &crate::CacheKey,
+    ) -> Vec<(crate::CacheKey, std::sync::Arc<wgpu::ShaderModule>)> {
+        self.overrides
+            .retain(|name, _| crate::CacheKey::from(name.as_str()) != *key);
+        std::iter::once(*key)
+            .chain(self.variants.get(key).cloned().unwrap_or_default())
+            .filter_map(|key| Some((key, self.shaders.remove(&key)?)))
+            .collect()
+    }
+    /// Puts modules dropped by [`ShaderManager::invalidate`] back.
+    pub fn restore(&mut self, modules: Vec<(crate::CacheKey, std::sync::Arc<wgpu::ShaderModule>)>) {
+        self.shaders.extend(modules);
+    }
+    /// Compiles `shader` from its current source without touching the cache. WGSL errors
+    /// are caught in a validation scope and returned instead of reaching the device's
+    /// uncaptured error handler.
+    pub fn compile(
+        &self,
+        device: &wgpu::Device,
+        shader: &str,
+    ) -> Result<std::sync::Arc<wgpu::ShaderModule>, crate::EngineError> {
+        use pollster::FutureExt;
+
+        let path = crate::AssetPaths::shader(shader);
+        let shader_source = std::fs::read_to_string(&path)?;
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some(shader),
+            source: wgpu::ShaderSource::Wgsl(shader_source.into()),
+        });
+        match device.pop_error_scope().block_on() {
+            Some(error) => Err(crate::EngineError::ShaderCompileError {
+                path: path.display().to_string(),
+                detail: error.to_string(),
+            }),
+            None => Ok(std::sync::Arc::new(shader_module)),
+        }
+    }
+    /// `override` constants `shader` declares, parsed once from its source.
+    pub fn overrides(
+        &mut self,
+        shader: &str,
+    ) -> Result<&[crate::OverrideDecl], crate::EngineError> {
+        if !self.overrides.contains_key(shader) {
+            let path = crate::AssetPaths::shader(shader);
+            let source = std::fs::read_to_string(&path)?;
+            self.overrides
+                .insert(shader.to_string(), crate::parse_overrides(&source));
         }
+        Ok(self
+            .overrides
+            .get(shader)
+            .map(Vec::as_slice)
+            .unwrap_or_default())
     }
     pub fn load(
         &mut self,
@@ -38,18 +128,53 @@ impl ShaderManager {
         let cache_key = crate::CacheKey::from(shader);
         let start = std::time::Instant::now();
 
-        if !crate::CacheStorage::contains(self, &cache_key) {
-            let path = crate::Asset::base_path().join("shaders").join(shader);
+        if let Some(module) = crate::CacheStorage::get(self, &cache_key) {
+            return Ok(module.clone());
+        }
+        let path = crate::AssetPaths::shader(shader);
 
-            let shader_source = std::fs::read_to_string(&path)?;
-            let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        let shader_source = std::fs::read_to_string(&path)?;
+        let shader_module: std::sync::Arc<wgpu::ShaderModule> = device
+            .create_shader_module(wgpu::ShaderModuleDescriptor {
                 label: Some(shader),
                 source: wgpu::ShaderSource::Wgsl(shader_source.into()),
-            });
-            crate::CacheStorage::insert(self, cache_key.clone(), shader_module.into());
-        }
+            })
+            .into();
+        crate::CacheStorage::insert(self, cache_key, shader_module.clone());
         crate::log_debug!("Loaded in {:.2?}", start.elapsed());
-        Ok(crate::CacheStorage::get(self, &cache_key).unwrap().clone())
+        Ok(shader_module)
+    }
+    /// `shader` with `append` added to its source, cached as `<shader>#<variant>`. `None`
+    /// when the source doesn't contain `requires`, e.g. a function the appended code calls.
+    pub fn load_variant(
+        &mut self,
+        device: &wgpu::Device,
+        shader: &str,
+        variant: &str,
+        requires: &str,
+        append: &str,
+    ) -> Result<Option<std::sync::Arc<wgpu::ShaderModule>>, crate::EngineError> {
+        let label = format!("{}#{}", shader, variant);
+        let cache_key = crate::CacheKey::from(label.as_str());
+        if let Some(module) = crate::CacheStorage::get(self, &cache_key) {
+            return Ok(Some(module.clone()));
+        }
+        let path = crate::AssetPaths::shader(shader);
+        let shader_source = std::fs::read_to_string(&path)?;
+        if !shader_source.contains(requires) {
+            return Ok(None);
+        }
+        let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some(&label),
+            source: wgpu::ShaderSource::Wgsl(format!("{}\n{}", shader_source, append).into()),
+        });
+        let shader_module = std::sync::Arc::new(shader_module);
+        crate::CacheStorage::insert(self, cache_key, shader_module.clone());
+        self.variants
+            .entry(crate::CacheKey::from(shader))
+            .or_default()
+            .insert(cache_key);
+        Ok(Some(shader_module))
     }
 }
 
@@ -83,4 +208,55 @@ impl crate::CacheStorage<std::sync::Arc<wgpu::ShaderModule>> for ShaderManager {
     fn remove(&mut self, key: &crate::CacheKey) -> Option<std::sync::Arc<wgpu::ShaderModule>> {
         self.shaders.remove(key)
     }
+    fn iter(
+        &self,
+    ) -> impl Iterator<Item = (&crate::CacheKey, &std::sync::Arc<wgpu::ShaderModule>)> {
+        self.shaders.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wgpu::naga;
+
+    /// Parses and validates `source` the way wgpu does before building a module.
+    fn validate(label: &str, source: &str) {
+        let module = naga::front::wgsl::parse_str(source)
+            .unwrap_or_else(|e| panic!("{}: {}", label, e.emit_to_string(source)));
+        naga::valid::Validator::new(
+            naga::valid::ValidationFlags::all(),
+            naga::valid::Capabilities::PUSH_CONSTANT,
+        )
+        .validate(&module)
+        .unwrap_or_else(|e| panic!("{}: {}", label, e.emit_to_string(source)));
+    }
+
+    #[test]
+    fn lit_shaders_and_their_variants_validate() {
+        let variants = [
+            (
+                crate::OBJECT_VERTEX_FN,
+                crate::ObjectIndexing::PushConstants.shader_entry(),
+            ),
+            (
+                crate::OBJECT_VERTEX_FN,
+                crate::ObjectIndexing::DynamicOffset.shader_entry(),
+            ),
+            (crate::OBJECT_VERTEX_FN, crate::SKINNED_ENTRY),
+            (
+                crate::INSTANCE_VERTEX_FN,
+                crate::InstanceFetch::SHADER_ENTRY,
+            ),
+        ];
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("../assets/shaders");
+        for shader in ["v_normal.wgsl", "terrain.wgsl", "debug.wgsl"] {
+            let source = std::fs::read_to_string(dir.join(shader)).unwrap();
+            validate(shader, &source);
+            for (requires, append) in variants {
+                if source.contains(requires) {
+                    validate(shader, &format!("{}\n{}", source, append));
+                }
+            }
+        }
+    }
 }
@@ -0,0 +1,376 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::RwLock;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{log_warning, RenderSettings};
+
+static GLOBAL_CONSTANTS: once_cell::sync::Lazy<RwLock<ShaderConstants>> =
+    once_cell::sync::Lazy::new(|| RwLock::new(ShaderConstants::default()));
+static CONSTANTS_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// Values for WGSL `override` constants, by name. Sorted, so equal sets give equal
+/// [`ShaderConstants::cache_suffix`]es.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ShaderConstants(pub BTreeMap<String, f64>);
+
+impl ShaderConstants {
+    pub fn get(&self, name: &str) -> Option<f64> {
+        self.0.get(name).copied()
+    }
+    pub fn set(&mut self, name: impl Into<String>, value: f64) {
+        self.0.insert(name.into(), value);
+    }
+    pub fn remove(&mut self, name: &str) -> Option<f64> {
+        self.0.remove(name)
+    }
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+    /// `self` with `other` taking precedence.
+    pub fn merged(&self, other: &ShaderConstants) -> ShaderConstants {
+        let mut merged = self.clone();
+        merged
+            .0
+            .extend(other.0.iter().map(|(name, value)| (name.clone(), *value)));
+        merged
+    }
+    /// Only the constants in `declared`. wgpu rejects a pipeline that overrides a constant
+    /// its module doesn't declare.
+    pub fn retain_declared(&self, declared: &[OverrideDecl]) -> ShaderConstants {
+        let mut kept = self.clone();
+        kept.0
+            .retain(|name, _| declared.iter().any(|d| d.name == *name));
+        kept
+    }
+    /// Names of the constants missing from `declared`.
+    pub fn undeclared(&self, declared: &[OverrideDecl]) -> Vec<&str> {
+        self.0
+            .keys()
+            .filter(|name| !declared.iter().any(|d| d.name == **name))
+            .map(String::as_str)
+            .collect()
+    }
+    /// [`ShaderConstants::retain_declared`], warning about each constant `shader` lacks.
+    pub fn declared_by(&self, shader: &str, declared: &[OverrideDecl]) -> ShaderConstants {
+        for name in self.undeclared(declared) {
+            log_warning!("{} declares no override constant '{}'", shader, name);
+        }
+        self.retain_declared(declared)
+    }
+    /// Pipeline cache key suffix; empty when nothing is overridden.
+    pub fn cache_suffix(&self) -> String {
+        if self.0.is_empty() {
+            return String::new();
+        }
+        let pairs: Vec<String> = self
+            .0
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect();
+        format!("#{}", pairs.join(","))
+    }
+    /// The map `wgpu::PipelineCompilationOptions::constants` takes.
+    pub fn to_map(&self) -> HashMap<String, f64> {
+        self.0.iter().map(|(k, v)| (k.clone(), *v)).collect()
+    }
+}
+
+/// One `override` declaration of a WGSL module.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OverrideDecl {
+    pub name: String,
+    /// Declared type, if spelled out.
+    pub ty: Option<String>,
+    /// Default expression as written, `None` when the pipeline must provide a value.
+    pub default: Option<String>,
+}
+
+/// The `override` declarations in WGSL `source`, in declaration order.
+pub fn parse_overrides(source: &str) -> Vec<OverrideDecl> {
+    let code: String = source
+        .lines()
+        .map(|line| line.split("//").next().unwrap_or_default())
+        .collect::<Vec<_>>()
+        .join("\n");
+    code.split(';')
+        .filter_map(|statement| {
+            // A declaration can follow a `}`-terminated struct or function without a `;`.
+            let mut rest = statement.rsplit('}').next().unwrap_or_default().trim();
+            // Skip attributes such as `@id(0)`.
+            while let Some(attr) = rest.strip_prefix('@') {
+                let end = attr
+                    .find(')')
+                    .map(|i| i + 1)
+                    .or_else(|| attr.find(char::is_whitespace))?;
+                rest = attr[end..].trim_start();
+            }
+            let decl = rest.strip_prefix("override")?;
+            if !decl.starts_with(char::is_whitespace) {
+                return None;
+            }
+            let (head, default) = match decl.split_once('=') {
+                Some((head, default)) => (head, Some(default.trim().to_string())),
+                None => (decl, None),
+            };
+            let (name, ty) = match head.split_once(':') {
+                Some((name, ty)) => (name.trim(), Some(ty.trim().to_string())),
+                None => (head.trim(), None),
+            };
+            (!name.is_empty()).then(|| OverrideDecl {
+                name: name.to_string(),
+                ty,
+                default,
+            })
+        })
+        .collect()
+}
+
+impl RenderSettings {
+    /// Constants applied to every pipeline whose shader declares them. Material constants
+    /// take precedence.
+    pub fn shader_constants() -> ShaderConstants {
+        GLOBAL_CONSTANTS
+            .read()
+            .map(|c| c.clone())
+            .unwrap_or_default()
+    }
+    pub fn shader_constant(name: &str) -> Option<f64> {
+        GLOBAL_CONSTANTS.read().ok()?.get(name)
+    }
+    /// Sets or, with `None`, clears a global constant. Pipelines pick it up on the next
+    /// `ModelManager::apply_shader_constants`.
+    pub fn set_shader_constant(name: &str, value: Option<f64>) {
+        let Ok(mut constants) = GLOBAL_CONSTANTS.write() else {
+            return;
+        };
+        let changed = match value {
+            Some(value) => constants.0.insert(name.to_string(), value) != Some(value),
+            None => constants.remove(name).is_some(),
+        };
+        if changed {
+            CONSTANTS_GENERATION.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+    /// Bumped on every change to the global constants.
+    pub fn constants_generation() -> u64 {
+        CONSTANTS_GENERATION.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use wgpu::naga;
+
+    use super::*;
+    use crate::{CacheKey, Material, MaterialFile, MaterialManager};
+
+    const FIXTURE: &str = "// override NOT_THIS: f32 = 1.0;
+struct Params { gain: f32 }
+
+override GAIN: f32 = 1.5;
+@id(7) override BIAS: f32;
+override ENABLED = true; override STEPS: u32 = 4u;
+
+@vertex
+fn vs_main(@location(0) position: vec3<f32>) -> @builtin(position) vec4<f32> {
+    let steps = f32(STEPS) * select(0.0, 1.0, ENABLED);
+    return vec4<f32>(position * GAIN + BIAS * steps, 1.0);
+}
+
+@fragment
+fn fs_main() -> @location(0) vec4<f32> {
+    return vec4<f32>(GAIN, BIAS, 0.0, 1.0);
+}
+";
+
+    fn constants(pairs: &[(&str, f64)]) -> ShaderConstants {
+        ShaderConstants(pairs.iter().map(|(k, v)| (k.to_string(), *v)).collect())
+    }
+
+    #[test]
+    fn override_discovery_matches_naga() {
+        let parsed = parse_overrides(FIXTURE);
+        let module = naga::front::wgsl::parse_str(FIXTURE).unwrap();
+        let names: Vec<_> = module
+            .overrides
+            .iter()
+            .map(|(_, o)| o.name.clone().unwrap())
+            .collect();
+        assert_eq!(
+            parsed.iter().map(|d| d.name.clone()).collect::<Vec<_>>(),
+            names
+        );
+        for (decl, (_, o)) in parsed.iter().zip(module.overrides.iter()) {
+            assert_eq!(decl.default.is_some(), o.init.is_some(), "{}", decl.name);
+        }
+        assert_eq!(
+            parsed[0],
+            OverrideDecl {
+                name: "GAIN".into(),
+                ty: Some("f32".into()),
+                default: Some("1.5".into()),
+            }
+        );
+        assert_eq!(parsed[1].default, None);
+        assert_eq!(parsed[2].ty, None);
+
+        // The shipped shader that declares one.
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../assets/shaders/equirect_dst.wgsl");
+        let source = std::fs::read_to_string(path).unwrap();
+        let module = naga::front::wgsl::parse_str(&source).unwrap();
+        let parsed = parse_overrides(&source);
+        assert_eq!(parsed.len(), module.overrides.len());
+        assert_eq!(parsed[0].name, "FAR_DEPTH");
+    }
+
+    #[test]
+    fn each_override_set_gets_its_own_cache_key() {
+        let asset =
+            MaterialFile::default().to_asset("fixture", &surface_config(), None, Vec::new());
+        let none = ShaderConstants::default();
+        let a = constants(&[("GAIN", 2.0), ("BIAS", 0.5)]);
+        let b = constants(&[("BIAS", 0.5), ("GAIN", 2.0)]);
+        let c = constants(&[("GAIN", 3.0), ("BIAS", 0.5)]);
+        assert_eq!(none.cache_suffix(), "");
+        assert_eq!(a.cache_suffix(), "#BIAS=0.5,GAIN=2");
+        // Insertion order doesn't matter, values do.
+        assert_eq!(
+            asset.pipeline_label(&a, None),
+            asset.pipeline_label(&b, None)
+        );
+        assert_ne!(
+            asset.pipeline_label(&a, None),
+            asset.pipeline_label(&c, None)
+        );
+        assert_ne!(
+            asset.pipeline_label(&a, None),
+            asset.pipeline_label(&none, None)
+        );
+        assert_ne!(
+            asset.pipeline_label(&a, None),
+            asset.pipeline_label(&a, Some("object_push"))
+        );
+
+        // Material values win over global ones.
+        let merged = constants(&[("GAIN", 1.0), ("STEPS", 2.0)]).merged(&a);
+        assert_eq!(
+            merged,
+            constants(&[("GAIN", 2.0), ("BIAS", 0.5), ("STEPS", 2.0)])
+        );
+    }
+
+    #[test]
+    fn undeclared_constants_are_reported_and_dropped() {
+        let declared = parse_overrides(FIXTURE);
+        let set = constants(&[("GAIN", 2.0), ("FOG", 1.0), ("NOT_THIS", 3.0)]);
+        assert_eq!(set.undeclared(&declared), ["FOG", "NOT_THIS"]);
+        assert_eq!(
+            set.declared_by("fixture.wgsl", &declared),
+            constants(&[("GAIN", 2.0)])
+        );
+        assert!(constants(&[("BIAS", 1.0)]).undeclared(&declared).is_empty());
+        assert_eq!(set.undeclared(&[]).len(), 3);
+    }
+
+    fn surface_config() -> wgpu::SurfaceConfiguration {
+        wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            width: 64,
+            height: 64,
+            present_mode: wgpu::PresentMode::Fifo,
+            alpha_mode: wgpu::CompositeAlphaMode::Opaque,
+            view_formats: vec![],
+            desired_maximum_frame_latency: 2,
+        }
+    }
+
+    /// Needs an adapter; passes without one.
+    #[test]
+    fn changing_a_global_constant_rebuilds_affected_pipelines() {
+        let root = crate::assets::loader::test_root();
+        let _ = crate::GPU::init(crate::GpuRequirements::default());
+        let Ok(device) = crate::GPU::with_read_recovered(|gpu| gpu.device().clone()) else {
+            return;
+        };
+        std::fs::write(root.join("shaders/constants_fixture.wgsl"), FIXTURE).unwrap();
+        let buffers = [crate::Vertex::LAYOUT, crate::VertexInstance::LAYOUT];
+        let mut manager = MaterialManager::new(&device);
+        let material = |name: &str, shader: &str, manager: &mut MaterialManager| {
+            let mut asset =
+                MaterialFile::default().to_asset(name, &surface_config(), None, Vec::new());
+            asset.shader = shader.to_string();
+            asset.constants = constants(&[("BIAS", 0.25)]);
+            let pipeline = asset
+                .pipeline(
+                    &device,
+                    &mut manager.shaders,
+                    &mut manager.pipelines,
+                    &buffers,
+                )
+                .unwrap();
+            let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: None,
+                entries: &[],
+            });
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: None,
+                layout: &layout,
+                entries: &[],
+            });
+            let key = CacheKey::from(name);
+            manager.materials.insert(
+                key,
+                Arc::new(Material {
+                    asset,
+                    bind_group: Arc::new(bind_group),
+                    pipeline,
+                    idx: 0,
+                }),
+            );
+            key
+        };
+        let tuned = material("tuned", "constants_fixture.wgsl", &mut manager);
+        let pipeline = |manager: &MaterialManager| manager.materials[&tuned].pipeline.clone();
+        let original = pipeline(&manager);
+        let compiled = |manager: &MaterialManager| {
+            crate::CacheStorage::iter(&manager.pipelines.render).count()
+        };
+        let before = compiled(&manager);
+
+        // Nothing changed, nothing rebuilt.
+        assert!(manager.apply_shader_constants(&device).is_empty());
+
+        RenderSettings::set_shader_constant("GAIN", Some(4.0));
+        let rebuilt = manager.apply_shader_constants(&device);
+        assert_eq!(rebuilt.len(), 1);
+        assert_eq!(rebuilt[0].asset.name, "tuned");
+        assert!(!Arc::ptr_eq(&pipeline(&manager), &original));
+        assert_eq!(compiled(&manager), before + 1);
+        // Applying again is a no-op until the next change.
+        assert!(manager.apply_shader_constants(&device).is_empty());
+
+        // Setting the same value doesn't count as a change.
+        let generation = RenderSettings::constants_generation();
+        RenderSettings::set_shader_constant("GAIN", Some(4.0));
+        assert_eq!(RenderSettings::constants_generation(), generation);
+
+        // Clearing it switches back to the cached pipeline without compiling.
+        RenderSettings::set_shader_constant("GAIN", None);
+        let rebuilt = manager.apply_shader_constants(&device);
+        assert_eq!(rebuilt.len(), 1);
+        assert!(Arc::ptr_eq(&pipeline(&manager), &original));
+        assert_eq!(compiled(&manager), before + 1);
+
+        // A constant the shader doesn't declare leaves its pipeline alone.
+        RenderSettings::set_shader_constant("UNRELATED_CONSTANT", Some(1.0));
+        assert!(manager.apply_shader_constants(&device).is_empty());
+        RenderSettings::set_shader_constant("UNRELATED_CONSTANT", None);
+    }
+}
@@ -1,12 +1,22 @@
 /// Extends wgpu::Surface with setup and usage methods.
 pub trait SurfaceExt {
-    /// Resizes the surface.
+    /// Resizes the surface. Returns `false` and leaves the surface as it was configured when
+    /// `new_size` has a zero dimension, as a minimized window reports; nothing may be drawn
+    /// to it until a later resize succeeds.
     fn resize(
         &self,
         device: &wgpu::Device,
         config: &mut wgpu::SurfaceConfiguration,
         new_size: winit::dpi::PhysicalSize<u32>,
-    );
+    ) -> bool {
+        if new_size.width == 0 || new_size.height == 0 {
+            return false;
+        }
+        config.width = new_size.width;
+        config.height = new_size.height;
+        self.configure(device, config);
+        true
+    }
 
     /// Configures the surface using the provided device and configuration.
     fn configure(&self, device: &wgpu::Device, config: &wgpu::SurfaceConfiguration);
@@ -16,20 +26,33 @@ pub trait SurfaceExt {
 
     /// Acquires the next texture for rendering.
     fn texture(&self) -> Result<wgpu::SurfaceTexture, wgpu::SurfaceError>;
-}
 
-impl<'a> SurfaceExt for wgpu::Surface<'a> {
-    fn resize(
+    /// Reconfigures the surface with `mode`, or the first supported mode of
+    /// [`present_mode_fallbacks`], and returns the mode applied.
+    fn set_present_mode(
         &self,
+        adapter: &wgpu::Adapter,
         device: &wgpu::Device,
         config: &mut wgpu::SurfaceConfiguration,
-        new_size: winit::dpi::PhysicalSize<u32>,
-    ) {
-        config.width = new_size.width.max(1);
-        config.height = new_size.height.max(1);
-        self.configure(device, config);
+        mode: wgpu::PresentMode,
+    ) -> wgpu::PresentMode;
+}
+
+/// `mode` followed by what to try when it isn't supported, ending in `Fifo`, which every
+/// surface supports. The `Auto` modes are resolved by wgpu itself.
+pub fn present_mode_fallbacks(mode: wgpu::PresentMode) -> &'static [wgpu::PresentMode] {
+    use wgpu::PresentMode::*;
+    match mode {
+        AutoVsync => &[AutoVsync],
+        AutoNoVsync => &[AutoNoVsync],
+        Immediate => &[Immediate, Mailbox, Fifo],
+        Mailbox => &[Mailbox, Fifo],
+        FifoRelaxed => &[FifoRelaxed, Fifo],
+        Fifo => &[Fifo],
     }
+}
 
+impl<'a> SurfaceExt for wgpu::Surface<'a> {
     fn configure(&self, device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) {
         self.configure(device, config);
     }
@@ -41,6 +64,34 @@ impl<'a> SurfaceExt for wgpu::Surface<'a> {
     fn texture(&self) -> Result<wgpu::SurfaceTexture, wgpu::SurfaceError> {
         self.get_current_texture()
     }
+
+    fn set_present_mode(
+        &self,
+        adapter: &wgpu::Adapter,
+        device: &wgpu::Device,
+        config: &mut wgpu::SurfaceConfiguration,
+        mode: wgpu::PresentMode,
+    ) -> wgpu::PresentMode {
+        let supported = self.get_capabilities(adapter).present_modes;
+        let applied = present_mode_fallbacks(mode)
+            .iter()
+            .copied()
+            .find(|m| {
+                matches!(
+                    m,
+                    wgpu::PresentMode::AutoVsync
+                        | wgpu::PresentMode::AutoNoVsync
+                        | wgpu::PresentMode::Fifo
+                ) || supported.contains(m)
+            })
+            .unwrap_or(wgpu::PresentMode::Fifo);
+        if applied != mode {
+            crate::log_warning!("{:?} unsupported, presenting with {:?}", mode, applied);
+        }
+        config.present_mode = applied;
+        self.configure(device, config);
+        applied
+    }
 }
 
 /// Encapsulates width/height helper conversions for surfaces.
@@ -65,3 +116,97 @@ impl SurfaceSize {
         (x * self.0 as f32, y * self.1 as f32)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use winit::dpi::PhysicalSize;
+
+    use super::*;
+    use crate::camera::aspect_ratio;
+
+    /// Records the sizes it's configured at instead of presenting anything.
+    #[derive(Default)]
+    struct RecordingSurface {
+        configured: RefCell<Vec<(u32, u32)>>,
+    }
+
+    impl SurfaceExt for RecordingSurface {
+        fn configure(&self, _: &wgpu::Device, config: &wgpu::SurfaceConfiguration) {
+            self.configured
+                .borrow_mut()
+                .push((config.width, config.height));
+        }
+        fn size(config: &wgpu::SurfaceConfiguration) -> SurfaceSize {
+            SurfaceSize(config.width, config.height)
+        }
+        fn texture(&self) -> Result<wgpu::SurfaceTexture, wgpu::SurfaceError> {
+            Err(wgpu::SurfaceError::Outdated)
+        }
+        fn set_present_mode(
+            &self,
+            _: &wgpu::Adapter,
+            _: &wgpu::Device,
+            _: &mut wgpu::SurfaceConfiguration,
+            mode: wgpu::PresentMode,
+        ) -> wgpu::PresentMode {
+            mode
+        }
+    }
+
+    fn device() -> Option<wgpu::Device> {
+        let instance = wgpu::Instance::default();
+        let adapter =
+            pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions::default()))?;
+        pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor::default(), None))
+            .ok()
+            .map(|(device, _)| device)
+    }
+
+    /// Needs an adapter; passes without one.
+    #[test]
+    fn minimize_suspends_until_restored() {
+        let Some(device) = device() else { return };
+        let surface = RecordingSurface::default();
+        let mut config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format: wgpu::TextureFormat::Bgra8UnormSrgb,
+            width: 800,
+            height: 600,
+            present_mode: wgpu::PresentMode::Fifo,
+            desired_maximum_frame_latency: 2,
+            alpha_mode: wgpu::CompositeAlphaMode::Auto,
+            view_formats: Vec::new(),
+        };
+        // What a viewport keeps: suspended while the last resize was refused.
+        let mut suspended = false;
+        let mut resize = |width, height| {
+            suspended = !surface.resize(&device, &mut config, PhysicalSize::new(width, height));
+            let size = RecordingSurface::size(&config);
+            (
+                suspended,
+                aspect_ratio(width as f32, height as f32),
+                size.0,
+                size.1,
+            )
+        };
+
+        let (minimized, aspect, width, height) = resize(0, 0);
+        assert!(minimized);
+        assert!(aspect.is_finite() && aspect > 0.0);
+        assert_eq!((width, height), (800, 600));
+
+        let (still_minimized, aspect, width, height) = resize(0, 600);
+        assert!(still_minimized);
+        assert!(aspect.is_finite() && aspect > 0.0);
+        assert_eq!((width, height), (800, 600));
+
+        let (restored_minimized, aspect, width, height) = resize(1024, 768);
+        assert!(!restored_minimized);
+        assert_eq!(aspect, 1024.0 / 768.0);
+        assert_eq!((width, height), (1024, 768));
+        assert!(!suspended);
+        assert_eq!(*surface.configured.borrow(), vec![(1024, 768)]);
+    }
+}
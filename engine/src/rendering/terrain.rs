@@ -1,14 +1,33 @@
-use glam::Vec3;
+use crossbeam::channel::{Receiver, Sender};
+use glam::{I64Vec3, IVec3, Vec3};
 
 use crate::{
-    chunk::Chunk, log_info, CacheKey, Material, MaterialAsset, Mesh, MeshAsset, MeshInstance,
-    Position, RenderBindGroupLayouts, Renderable, Rotation, Scale, Transform, WgpuBuffer, GRAVITY,
+    chunk::Chunk, log_debug, log_info, log_warning, BlockRegistry, CacheKey, ChunkGenerator,
+    ChunkStore, EngineError, FlatGenerator, Material, MeshAsset, MeshDedupStats, MeshInstance,
+    MeshManager, Position, RenderBindGroupLayouts, Renderable, Rotation, Scale, TerrainLayer,
+    TerrainLayerTextures, TerrainTextureArray, Transform, WaterSim, WgpuBuffer, WorldOrigin, AIR,
+    GRAVITY, SEA_LEVEL, WATER_ALPHA, WATER_FULL,
 };
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    ops::Range,
+    sync::Arc,
+};
+
+use super::{Block, InstanceBufferData, VertexInstance, AABB, CHUNK_SIZE};
 
-use super::{InstanceBufferData, Vertex, VertexInstance, CHUNK_SIZE};
+/// Library material water surfaces draw with; mark it `transparent`.
+pub const WATER_MATERIAL: &str = "water";
 
+/// Chunk changes recorded by [`Terrain`] for systems that derive data from blocks.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkEvent {
+    Loaded((i32, i32, i32)),
+    Unloaded((i32, i32, i32)),
+    Edited((i32, i32, i32)),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub enum Medium {
     Air,
     Water,
@@ -50,30 +69,338 @@ impl Medium {
         matches!(self, Medium::Air | Medium::Water)
     }
 }
+/// A chunk built off the render thread, with its global chunk coordinate and whether it
+/// came from the [`ChunkStore`].
+type GeneratedChunk = (I64Vec3, Chunk, Medium, bool);
+
+/// Chunk meshes drawn and frustum culled by the last [`Terrain::update_instance_buffer`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TerrainCullStats {
+    pub drawn: usize,
+    pub culled: usize,
+}
 #[derive(Debug)]
 pub struct Terrain {
     chunk_stream: HashMap<(i32, i32, i32), (Chunk, Medium)>,
     default_medium: Medium,
     mesh_instances: Vec<MeshInstance>,
+    mesh_chunks: Vec<(i32, i32, i32)>,
+    /// Chunk centers of `mesh_instances`, for sorting the transparent ones.
+    mesh_centers: Vec<Vec3>,
+    /// Chunk bounds of `mesh_instances` in the local frame.
+    mesh_aabbs: Vec<AABB>,
+    /// Which `mesh_instances` were inside the camera frustum at the last update.
+    mesh_visible: Vec<bool>,
+    cull_stats: TerrainCullStats,
+    /// Chunk and water meshes by content, so identical ones share their buffers.
+    meshes: MeshManager,
+    /// One instance per entry of `mesh_instances`, placing its chunk-relative mesh.
     instance_buffer: Option<InstanceBufferData>,
+    water_material: Option<Arc<Material>>,
+    /// Uploaded water surfaces of the loaded chunks, with their chunk centers.
+    water_instances: Vec<(MeshInstance, Vec3)>,
+    water_instance: Option<InstanceBufferData>,
+    /// Water meshes changed since the last upload.
+    water_dirty: bool,
+    /// Edited chunks and their neighbors across an edited border.
+    remesh: HashSet<(i32, i32, i32)>,
     last_stream_center: Option<(i32, i32)>,
+    chunk_events: Vec<ChunkEvent>,
+    layer_textures: Vec<TerrainLayerTextures>,
+    layers: Option<TerrainTextureArray>,
+    water: WaterSim,
+    /// Where chunk keys sit in the global frame the generator works in.
+    origin: WorldOrigin,
+    generator: Arc<dyn ChunkGenerator>,
+    /// Where edited chunks are saved when unloaded and looked for before generating.
+    store: Option<Arc<ChunkStore>>,
+    /// Global coordinates of the chunks the last stream update wanted; generated chunks
+    /// outside it arrive too late and are dropped.
+    wanted: HashSet<I64Vec3>,
+    /// Global coordinates of chunks queued for generation and not back yet.
+    generating: HashSet<I64Vec3>,
+    generated_sender: Sender<GeneratedChunk>,
+    generated: Receiver<GeneratedChunk>,
 }
 
 impl Terrain {
     pub fn new(default_medium: Medium) -> Self {
+        let (generated_sender, generated) = crossbeam::channel::unbounded();
         Self {
             chunk_stream: HashMap::new(),
             default_medium,
             mesh_instances: Vec::new(),
+            mesh_chunks: Vec::new(),
+            mesh_centers: Vec::new(),
+            mesh_aabbs: Vec::new(),
+            mesh_visible: Vec::new(),
+            cull_stats: TerrainCullStats::default(),
+            meshes: MeshManager::default(),
             instance_buffer: None,
+            water_material: None,
+            water_instances: Vec::new(),
+            water_instance: None,
+            water_dirty: false,
+            remesh: HashSet::new(),
             last_stream_center: None,
+            chunk_events: Vec::new(),
+            layer_textures: vec![
+                TerrainLayerTextures::new("cube-diffuse.jpg", "cube-normal.png"),
+                TerrainLayerTextures::new("goblin-diffuse.png", "goblin-normal.png"),
+                TerrainLayerTextures::new("cube-diffuse.jpg", "goblin-normal.png"),
+            ],
+            layers: None,
+            water: WaterSim::new(),
+            origin: WorldOrigin::default(),
+            generator: Arc::new(FlatGenerator),
+            store: None,
+            wanted: HashSet::new(),
+            generating: HashSet::new(),
+            generated_sender,
+            generated,
+        }
+    }
+
+    /// Fills chunks loaded from now on with `generator`; loaded chunks are kept.
+    pub fn set_generator(&mut self, generator: Arc<dyn ChunkGenerator>) {
+        self.generator = generator;
+    }
+    pub fn generator(&self) -> &Arc<dyn ChunkGenerator> {
+        &self.generator
+    }
+    /// Saves edited chunks to `store` as they unload and loads them back from it; `None`
+    /// forgets edits. Chunks already loaded keep where they came from.
+    pub fn set_store(&mut self, store: Option<Arc<ChunkStore>>) {
+        self.store = store;
+    }
+    pub fn store(&self) -> Option<&Arc<ChunkStore>> {
+        self.store.as_ref()
+    }
+    /// Saves every loaded edited chunk to the store now, for shutdown, where unloading
+    /// never comes. Returns how many were saved; failures are warned about and stay edited.
+    pub fn save_modified(&mut self) -> usize {
+        let Some(store) = self.store.clone() else {
+            return 0;
+        };
+        let origin = self.origin.chunk();
+        let mut saved = 0;
+        for (pos, (chunk, _)) in self
+            .chunk_stream
+            .iter_mut()
+            .filter(|(_, (c, _))| c.modified)
+        {
+            let global = IVec3::from(*pos).as_i64vec3() + origin;
+            match store.save(global, chunk) {
+                Ok(()) => {
+                    chunk.modified = false;
+                    saved += 1;
+                }
+                Err(e) => log_warning!("Saving chunk {}: {}", global, e),
+            }
         }
+        saved
+    }
+
+    /// Textures for each terrain array layer, indexed by [`crate::TerrainLayer::layer`].
+    /// Takes effect the next time [`Terrain::chunks`] builds the terrain material.
+    pub fn set_layer_textures(&mut self, textures: Vec<TerrainLayerTextures>) {
+        self.layer_textures = textures;
+        self.layers = None;
+    }
+    /// Draws `block` with `textures`: the layer already holding them, or a new one, is
+    /// registered for it with [`BlockRegistry::register_terrain_layer`]. Returns the layer;
+    /// like [`Terrain::set_layer_textures`] it shows from the next [`Terrain::chunks`].
+    pub fn register_block_textures(
+        &mut self,
+        block: Block,
+        textures: TerrainLayerTextures,
+        priority: f32,
+    ) -> u8 {
+        let layer = match self.layer_textures.iter().position(|t| *t == textures) {
+            Some(layer) => layer,
+            None => {
+                self.layer_textures.push(textures);
+                self.layer_textures.len() - 1
+            }
+        };
+        // Rebuilt even for an existing layer, since `priority` may raise the layer's.
+        self.layers = None;
+        BlockRegistry::register_terrain_layer(block, TerrainLayer::new(layer as u8, priority));
+        layer as u8
+    }
+    /// Whether terrain draws with the blended array material rather than the single one.
+    pub fn blended(&self) -> bool {
+        self.layers.is_some()
     }
 
+    /// Adds a loaded chunk. A [`Medium::Water`] chunk is flooded up to [`SEA_LEVEL`], so its
+    /// surface is drawn by the water pass.
     pub fn insert_chunk_stream(&mut self, chunk: Chunk, medium: Medium) {
+        self.insert_chunk(chunk, medium, false);
+    }
+    /// [`Terrain::insert_chunk_stream`], leaving the water of a `stored` chunk as it was
+    /// saved.
+    fn insert_chunk(&mut self, mut chunk: Chunk, medium: Medium, stored: bool) {
+        if medium == Medium::Water && !stored {
+            let bottom = self.global_chunk(chunk.pos).y * CHUNK_SIZE as i64;
+            chunk.flood_below(SEA_LEVEL - bottom);
+        }
+        self.chunk_events.push(ChunkEvent::Loaded(chunk.pos));
+        // Water next to the new chunk may have somewhere to go now.
+        let (x, y, z) = chunk.pos;
+        for pos in [
+            (x, y, z),
+            (x + 1, y, z),
+            (x - 1, y, z),
+            (x, y + 1, z),
+            (x, y - 1, z),
+            (x, y, z + 1),
+            (x, y, z - 1),
+        ] {
+            self.water.wake(pos);
+        }
         self.chunk_stream.insert(chunk.pos, (chunk, medium));
     }
 
+    pub fn drain_chunk_events(&mut self) -> std::vec::Drain<'_, ChunkEvent> {
+        self.chunk_events.drain(..)
+    }
+
+    /// Block and chunk medium at a world-space block coordinate, `None` if the chunk isn't loaded.
+    pub fn block_at(&self, cell: IVec3) -> Option<(Block, Medium)> {
+        let size = CHUNK_SIZE as i32;
+        let chunk_pos = (
+            cell.x.div_euclid(size),
+            cell.y.div_euclid(size),
+            cell.z.div_euclid(size),
+        );
+        let (chunk, medium) = self.chunk_stream.get(&chunk_pos)?;
+        let block = chunk.get_block(
+            cell.x.rem_euclid(size) as isize,
+            cell.y.rem_euclid(size) as isize,
+            cell.z.rem_euclid(size) as isize,
+        );
+        Some((block, *medium))
+    }
+
+    /// `false` if the chunk isn't loaded. Meshes rebuild at the next
+    /// [`Terrain::update_instance_buffer`].
+    pub fn set_block(&mut self, cell: IVec3, block: Block) -> bool {
+        let size = CHUNK_SIZE as i32;
+        let chunk_pos = (
+            cell.x.div_euclid(size),
+            cell.y.div_euclid(size),
+            cell.z.div_euclid(size),
+        );
+        let Some((chunk, _)) = self.chunk_stream.get_mut(&chunk_pos) else {
+            return false;
+        };
+        chunk.set_block(
+            cell.x.rem_euclid(size) as usize,
+            cell.y.rem_euclid(size) as usize,
+            cell.z.rem_euclid(size) as usize,
+            block,
+        );
+        chunk.modified = true;
+        self.chunk_events.push(ChunkEvent::Edited(chunk_pos));
+        for pos in Self::chunks_touching(cell) {
+            if let Some((chunk, _)) = self.chunk_stream.get_mut(&pos) {
+                chunk.dirty = true;
+                self.remesh.insert(pos);
+            }
+        }
+        self.water.wake_around(cell);
+        true
+    }
+    pub fn break_block(&mut self, cell: IVec3) -> bool {
+        self.set_block(cell, AIR)
+    }
+    fn chunks_touching(cell: IVec3) -> Vec<(i32, i32, i32)> {
+        let size = CHUNK_SIZE as i32;
+        let chunk = cell.div_euclid(IVec3::splat(size));
+        let local = cell.rem_euclid(IVec3::splat(size));
+        let mut touching = vec![chunk.into()];
+        for axis in 0..3 {
+            let side = match local[axis] {
+                0 => -1,
+                l if l == size - 1 => 1,
+                _ => continue,
+            };
+            let mut neighbor = chunk;
+            neighbor[axis] += side;
+            touching.push(neighbor.into());
+        }
+        touching
+    }
+    /// Any block but air counts, whatever the chunk's medium; unloaded chunks are skipped.
+    pub fn raycast_voxel(&self, origin: Vec3, dir: Vec3, max_dist: f32) -> Option<(IVec3, IVec3)> {
+        let dir = dir.normalize_or_zero();
+        if dir == Vec3::ZERO {
+            return None;
+        }
+        crate::raycast_blocks(origin, dir, max_dist, |cell| {
+            self.block_at(cell).is_some_and(|(block, _)| block != AIR)
+        })
+        .map(|hit| (hit.cell, hit.normal))
+    }
+
+    /// Water level at a world-space block coordinate, 0 when dry or not loaded.
+    pub fn water_at(&self, cell: IVec3) -> u8 {
+        let size = CHUNK_SIZE as i32;
+        self.chunk_stream
+            .get(&crate::chunk_of(cell))
+            .map_or(0, |(chunk, _)| {
+                chunk.get_water(
+                    cell.x.rem_euclid(size) as usize,
+                    cell.y.rem_euclid(size) as usize,
+                    cell.z.rem_euclid(size) as usize,
+                )
+            })
+    }
+    /// Adds or removes water at a world-space block coordinate: the only way, besides
+    /// placing blocks, that the total changes. Returns `false` if the chunk isn't loaded.
+    pub fn set_water(&mut self, cell: IVec3, level: u8) -> bool {
+        if !self.store_water(cell, level) {
+            return false;
+        }
+        self.water.wake_around(cell);
+        true
+    }
+    /// Writes a level without waking anything; the simulation wakes chunks itself.
+    pub(crate) fn store_water(&mut self, cell: IVec3, level: u8) -> bool {
+        let size = CHUNK_SIZE as i32;
+        let Some((chunk, _)) = self.chunk_stream.get_mut(&crate::chunk_of(cell)) else {
+            return false;
+        };
+        chunk.set_water(
+            cell.x.rem_euclid(size) as usize,
+            cell.y.rem_euclid(size) as usize,
+            cell.z.rem_euclid(size) as usize,
+            level,
+        );
+        chunk.modified = true;
+        true
+    }
+    /// Sum of every loaded fill level, in units of 1 / [`WATER_FULL`] block.
+    pub fn water_volume(&self) -> u64 {
+        self.chunk_stream
+            .values()
+            .map(|(chunk, _)| chunk.water_volume())
+            .sum()
+    }
+    pub fn water_sim(&self) -> &WaterSim {
+        &self.water
+    }
+    /// Advances the water simulation and remeshes the chunks it changed.
+    pub fn update_water(&mut self, dt: f32) {
+        let mut water = std::mem::take(&mut self.water);
+        let steps = water.update(self, dt);
+        self.water = water;
+        if steps > 0 {
+            self.stream_build_meshes();
+        }
+    }
+
     pub fn get_chunk_stream(&self, pos: (i32, i32, i32)) -> Option<&(Chunk, Medium)> {
         self.chunk_stream.get(&pos)
     }
@@ -94,11 +421,21 @@ impl Terrain {
         );
 
         if let Some((chunk, medium)) = self.chunk_stream.get(&chunk_pos) {
-            let lx = (world_pos.x as isize % CHUNK_SIZE as isize).rem_euclid(CHUNK_SIZE as isize);
-            let ly = (world_pos.y as isize % CHUNK_SIZE as isize).rem_euclid(CHUNK_SIZE as isize);
-            let lz = (world_pos.z as isize % CHUNK_SIZE as isize).rem_euclid(CHUNK_SIZE as isize);
+            // Floored first, so positions just below zero land in the last cell of the
+            // chunk before rather than the first of this one.
+            let cell = world_pos.floor().as_ivec3();
+            let size = CHUNK_SIZE as i32;
+            let lx = cell.x.rem_euclid(size) as isize;
+            let ly = cell.y.rem_euclid(size) as isize;
+            let lz = cell.z.rem_euclid(size) as isize;
             if chunk.get_block(lx, ly, lz) == 0 {
-                Medium::Air
+                let level = chunk.get_water(lx as usize, ly as usize, lz as usize);
+                let surface = level as f32 / WATER_FULL as f32;
+                if world_pos.y - world_pos.y.floor() < surface {
+                    Medium::Water
+                } else {
+                    Medium::Air
+                }
             } else {
                 *medium
             }
@@ -115,29 +452,41 @@ impl Terrain {
         for (chunk, medium) in self.chunk_stream.values_mut() {
             if chunk.dirty {
                 chunk.mesh = Some(chunk.build_chunk_mesh());
+                chunk.water_mesh = (!chunk.water.is_empty()).then(|| chunk.build_water_mesh());
                 chunk.dirty = false;
+                self.water_dirty = true;
             }
         }
     }
     pub fn instance_buffer(&self) -> Option<&InstanceBufferData> {
         self.instance_buffer.as_ref()
     }
-    pub fn update_instance_buffer(&mut self, queue: &wgpu::Queue, device: &wgpu::Device) {
-        let mut instances = Vec::new();
-
-        for ((cx, cy, cz), _chunk) in &self.chunk_stream {
-            let transform = Transform::from_components(
-                &Position::new(*cx as f32, *cy as f32, *cz as f32),
-                &Rotation::zero(),
-                &Scale::one(),
-            );
-            let vertex_instances: VertexInstance = transform.to_vertex_instance(0);
-            instances.push(vertex_instances);
-        }
+    /// Culls chunk meshes against `frustum` and writes the instance placing each of them.
+    pub fn update_instance_buffer(
+        &mut self,
+        queue: &wgpu::Queue,
+        device: &wgpu::Device,
+        frustum: &crate::camera::Frustum,
+    ) {
+        self.rebuild_edited(queue, device);
+        self.upload_water(queue, device);
+        self.cull(frustum);
+        let instances: Vec<VertexInstance> = self
+            .mesh_chunks
+            .iter()
+            .map(|&(cx, cy, cz)| {
+                let origin = Vec3::new(cx as f32, cy as f32, cz as f32) * CHUNK_SIZE as f32;
+                Transform::from_components(&Position(origin), &Rotation::zero(), &Scale::one())
+                    .to_vertex_instance(0)
+            })
+            .collect();
 
         if let Some(instance) = &mut self.instance_buffer {
+            // Bound fresh every draw, so a grown buffer needs no rebinding.
             let byte_data = VertexInstance::bytes(&instances);
             instance.buffer.write_data(queue, device, &byte_data, None);
+            instance.count = instances.len();
+            instance.capacity = instance.buffer.capacity() as usize;
         } else {
             let byte_data = VertexInstance::bytes(&instances);
             let buffer = WgpuBuffer::from_data(
@@ -155,35 +504,269 @@ impl Terrain {
         }
     }
 
+    fn rebuild_edited(&mut self, queue: &wgpu::Queue, device: &wgpu::Device) {
+        if self.remesh.is_empty() {
+            return;
+        }
+        for pos in std::mem::take(&mut self.remesh) {
+            let Some((chunk, _)) = self.chunk_stream.get_mut(&pos) else {
+                continue;
+            };
+            let mesh = chunk.build_chunk_mesh();
+            chunk.water_mesh = (!chunk.water.is_empty()).then(|| chunk.build_water_mesh());
+            chunk.mesh = Some(mesh.clone());
+            chunk.dirty = false;
+            self.water_dirty = true;
+            let Some(index) = self.mesh_chunks.iter().position(|p| *p == pos) else {
+                continue;
+            };
+            self.mesh_instances[index].mesh =
+                self.meshes
+                    .get_or_upload(queue, device, mesh, &format!("chunk_{:?}", pos));
+        }
+        self.meshes.sweep();
+    }
+
+    fn cull(&mut self, frustum: &crate::camera::Frustum) {
+        self.mesh_visible = self
+            .mesh_aabbs
+            .iter()
+            .map(|aabb| frustum.contains_aabb(aabb))
+            .collect();
+        let drawn = self.mesh_visible.iter().filter(|visible| **visible).count();
+        self.cull_stats = TerrainCullStats {
+            drawn,
+            culled: self.mesh_visible.len() - drawn,
+        };
+    }
+    pub fn cull_stats(&self) -> TerrainCullStats {
+        self.cull_stats
+    }
+    /// How many chunk and water meshes shared the buffers of an identical one.
+    pub fn mesh_stats(&self) -> MeshDedupStats {
+        self.meshes.stats()
+    }
+
+    /// Uploads the water meshes rebuilt since the last call. Without the
+    /// [`WATER_MATERIAL`] loaded, water isn't drawn.
+    fn upload_water(&mut self, queue: &wgpu::Queue, device: &wgpu::Device) {
+        if !self.water_dirty {
+            return;
+        }
+        self.water_dirty = false;
+        // Kept alive until the rebuild is done, so unchanged surfaces reuse their buffers.
+        let previous = std::mem::take(&mut self.water_instances);
+        let Some(material) = &self.water_material else {
+            return;
+        };
+        for (chunk, _) in self.chunk_stream.values() {
+            let Some(asset) = chunk.water_mesh.as_ref().filter(|m| !m.indices.is_empty()) else {
+                continue;
+            };
+            let mesh = self.meshes.get_or_upload(
+                queue,
+                device,
+                asset.clone(),
+                &format!("water_{:?}", chunk.pos),
+            );
+            let instance = MeshInstance {
+                mesh,
+                material: Some(material.clone()),
+            };
+            self.water_instances.push((instance, chunk.center()));
+        }
+        drop(previous);
+        self.meshes.sweep();
+        // Water vertices are already in the local frame.
+        let mut instance =
+            Transform::from_components(&Position(Vec3::ZERO), &Rotation::zero(), &Scale::one())
+                .to_vertex_instance(material.idx);
+        instance.color = [1.0, 1.0, 1.0, WATER_ALPHA];
+        let byte_data = VertexInstance::bytes(&[instance]);
+        match &mut self.water_instance {
+            Some(data) => {
+                data.buffer.write_data(queue, device, &byte_data, None);
+            }
+            None => {
+                self.water_instance = Some(InstanceBufferData {
+                    buffer: WgpuBuffer::from_data(
+                        device,
+                        &byte_data,
+                        wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                        Some("water_instance_buffer"),
+                    ),
+                    count: 1,
+                    capacity: byte_data.len(),
+                    dirty: false,
+                })
+            }
+        }
+    }
+
     pub fn all_meshes(&self) -> impl Iterator<Item = &MeshAsset> {
         self.chunk_stream
             .values()
             .filter_map(|(c, m)| c.mesh.as_ref())
     }
+    /// Water surfaces of the loaded chunks that have any.
+    pub fn water_meshes(&self) -> impl Iterator<Item = &MeshAsset> {
+        self.chunk_stream
+            .values()
+            .filter_map(|(c, _)| c.water_mesh.as_ref())
+    }
 
+    /// Global chunk coordinate of the local chunk key `pos`.
+    fn global_chunk(&self, pos: (i32, i32, i32)) -> I64Vec3 {
+        IVec3::from(pos).as_i64vec3() + self.origin.chunk()
+    }
+    /// Local chunk key of the global chunk coordinate `global`.
+    fn local_chunk(&self, global: I64Vec3) -> (i32, i32, i32) {
+        (global - self.origin.chunk()).as_ivec3().into()
+    }
+    /// A chunk at the local key `pos` loaded or generated on this thread, see
+    /// [`load_or_generate`].
+    fn generate_chunk(&self, pos: (i32, i32, i32)) -> (Chunk, bool) {
+        let (mut chunk, stored) = load_or_generate(
+            self.generator.as_ref(),
+            self.store.as_deref(),
+            self.global_chunk(pos),
+        );
+        chunk.pos = pos;
+        (chunk, stored)
+    }
+
+    /// Queues the chunks of `center`'s neighborhood that aren't loaded for generation and
+    /// unloads the ones outside it, saving the edited ones. Queued chunks arrive through
+    /// [`Terrain::receive_generated`].
     fn stream_build_chunks(&mut self, center: (i32, i32), distance: i32) {
-        let mut needed: std::collections::HashSet<(i32, i32, i32)> =
-            std::collections::HashSet::new();
+        let mut needed: HashSet<(i32, i32, i32)> = HashSet::new();
+        let mut queued = Vec::new();
         for dx in -distance..=distance {
             for dz in -distance..=distance {
-                let chunk_pos = (center.0 + dx, 0, center.1 + dz);
-                if needed.insert(chunk_pos) && !self.chunk_stream.contains_key(&chunk_pos) {
-                    let medium = self.medium_at(Vec3 {
-                        x: chunk_pos.0 as f32,
-                        y: chunk_pos.1 as f32,
-                        z: chunk_pos.2 as f32,
-                    });
-                    self.insert_chunk_stream(Chunk::flat(chunk_pos), medium);
+                for y in self.generator.rows() {
+                    let chunk_pos = (center.0 + dx, y, center.1 + dz);
+                    if !needed.insert(chunk_pos) || self.chunk_stream.contains_key(&chunk_pos) {
+                        continue;
+                    }
+                    let global = self.global_chunk(chunk_pos);
+                    if self.generating.insert(global) {
+                        let medium = self.medium_at(Vec3 {
+                            x: chunk_pos.0 as f32,
+                            y: chunk_pos.1 as f32,
+                            z: chunk_pos.2 as f32,
+                        });
+                        queued.push((global, medium));
+                    }
                 }
             }
         }
-        self.chunk_stream.retain(|pos, _| needed.contains(pos));
+        self.wanted = needed.iter().map(|pos| self.global_chunk(*pos)).collect();
+        self.spawn_generation(queued);
+        let events = &mut self.chunk_events;
+        let water_dirty = &mut self.water_dirty;
+        let (store, origin) = (&self.store, self.origin.chunk());
+        self.chunk_stream.retain(|pos, (chunk, _)| {
+            let keep = needed.contains(pos);
+            if !keep {
+                events.push(ChunkEvent::Unloaded(*pos));
+                *water_dirty |= chunk.water_mesh.is_some();
+                if let Some(store) = store.as_ref().filter(|_| chunk.modified) {
+                    store.save_in_background(IVec3::from(*pos).as_i64vec3() + origin, chunk);
+                }
+            }
+            keep
+        });
         self.last_stream_center = Some(center);
     }
 
+    /// Loads or generates `queued` chunks on a blocking task of the tokio runtime, or a
+    /// thread of their own outside one, sending each back as it's done.
+    fn spawn_generation(&self, queued: Vec<(I64Vec3, Medium)>) {
+        if queued.is_empty() {
+            return;
+        }
+        log_debug!(
+            "Generating {} terrain chunks in the background",
+            queued.len()
+        );
+        let generator = self.generator.clone();
+        let store = self.store.clone();
+        let sender = self.generated_sender.clone();
+        let job = move || {
+            for (global, medium) in queued {
+                let (chunk, stored) =
+                    load_or_generate(generator.as_ref(), store.as_deref(), global);
+                if sender.send((global, chunk, medium, stored)).is_err() {
+                    return;
+                }
+            }
+        };
+        match tokio::runtime::Handle::try_current() {
+            Ok(handle) => {
+                handle.spawn_blocking(job);
+            }
+            Err(_) => {
+                std::thread::spawn(job);
+            }
+        }
+    }
+    /// Loads the generated chunks that are still wanted; `false` if none arrived.
+    fn receive_generated(&mut self) -> bool {
+        let mut received = false;
+        let finished: Vec<GeneratedChunk> = self.generated.try_iter().collect();
+        for (global, mut chunk, medium, stored) in finished {
+            self.generating.remove(&global);
+            if !self.wanted.contains(&global) {
+                continue;
+            }
+            chunk.pos = self.local_chunk(global);
+            self.insert_chunk(chunk, medium, stored);
+            received = true;
+        }
+        received
+    }
+
+    /// Chunk keys move by whole chunks, so nothing is rebuilt.
+    pub fn rebase(&mut self, rebase: &crate::Rebase) {
+        self.chunk_stream = std::mem::take(&mut self.chunk_stream)
+            .into_iter()
+            .map(|(pos, (mut chunk, medium))| {
+                chunk.rebase(rebase);
+                (rebase.chunk(pos), (chunk, medium))
+            })
+            .collect();
+        for pos in &mut self.mesh_chunks {
+            *pos = rebase.chunk(*pos);
+        }
+        self.remesh = self.remesh.iter().map(|pos| rebase.chunk(*pos)).collect();
+        for event in &mut self.chunk_events {
+            let (ChunkEvent::Loaded(pos) | ChunkEvent::Unloaded(pos) | ChunkEvent::Edited(pos)) =
+                event;
+            *pos = rebase.chunk(*pos);
+        }
+        let blocks = rebase.blocks();
+        self.last_stream_center = self
+            .last_stream_center
+            .map(|(x, z)| (x - blocks.x, z - blocks.z));
+        self.water.rebase(rebase);
+        self.origin = rebase.origin;
+        let offset = rebase.offset();
+        for aabb in &mut self.mesh_aabbs {
+            aabb.min -= offset;
+            aabb.max -= offset;
+        }
+        for center in &mut self.mesh_centers {
+            *center -= offset;
+        }
+        self.water_dirty = true;
+    }
+
     pub fn update_streaming(&mut self, camera_pos: Vec3, view_distance: i32) {
         let center = ((camera_pos.x).floor() as i32, (camera_pos.z).floor() as i32);
 
+        if self.receive_generated() {
+            self.stream_build_meshes();
+        }
         if self.last_stream_center == Some(center) {
             return;
         }
@@ -203,97 +786,351 @@ impl Terrain {
         surface_config: &wgpu::SurfaceConfiguration,
         depth_stencil: &wgpu::DepthStencilState,
         model_manager: &mut crate::ModelManager,
-    ) -> Renderable {
+    ) -> Result<Renderable, crate::EngineError> {
         let terrain_mat = "ground";
 
-        let mat_shader = "v_normal.wgsl";
-        let vec3_zero = [0.0; 3];
-        let mat_key = CacheKey::from(terrain_mat);
-        let mat_asset = MaterialAsset {
-            name: terrain_mat.to_string(),
-            key: mat_key,
-            shader: mat_shader.to_string(),
-            ambient: vec3_zero,
-            diffuse: vec3_zero,
-            specular: vec3_zero,
-            shininess: 32.0,
-            diffuse_texture: Some("cube-diffuse.jpg".to_string()),
-            normal_texture: Some("cube-normal.png".to_string()),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Front),
-                unclipped_depth: false,
-                polygon_mode: wgpu::PolygonMode::Fill,
-                conservative: false,
-            },
-            depth_stencil: Some(depth_stencil.clone()),
-            color_target: wgpu::ColorTargetState {
-                format: surface_config.format,
-                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
-                write_mask: wgpu::ColorWrites::all(),
-            },
-            bind_group_layouts: vec![
-                RenderBindGroupLayouts::uniform().clone(),
-                RenderBindGroupLayouts::equirect_dst().clone(),
-                RenderBindGroupLayouts::material_storage().clone(),
-                RenderBindGroupLayouts::normal().clone(),
-            ],
-        };
-
-        let (pipeline, bind_group) = mat_asset
-            .load_asset(
+        if model_manager.materials.library.depth_stencil().is_none() {
+            model_manager
+                .materials
+                .library
+                .set_depth_stencil(Some(depth_stencil.clone()));
+        }
+        let mat = model_manager.materials.library_material(
+            &model_manager.queue,
+            &model_manager.device,
+            terrain_mat,
+            surface_config,
+        )?;
+        self.water_material = model_manager
+            .materials
+            .library_material(
                 &model_manager.queue,
                 &model_manager.device,
-                &mut model_manager.materials.textures,
-                &mut model_manager.materials.shaders,
-                &mut model_manager.materials.pipelines,
+                WATER_MATERIAL,
                 surface_config,
-                &[Vertex::LAYOUT, VertexInstance::LAYOUT],
             )
-            .expect("Failed to load terrain material");
-
-        let mat = if let Some(cached_mat) = model_manager.materials.materials.get(&mat_asset.key) {
-            cached_mat.clone()
-        } else {
-            Arc::new(Material {
-                asset: mat_asset,
-                bind_group,
-                pipeline,
-                idx: model_manager.materials.storage_count as u32,
-            })
+            .map_err(|e| log_warning!("Water surfaces won't draw: {}", e))
+            .ok();
+        self.water_dirty = true;
+        let mat = match self.blended_material(terrain_mat, surface_config, model_manager) {
+            Ok(blended) => blended,
+            Err(e) => {
+                log_warning!(
+                    "Blended terrain unavailable, using '{}': {}",
+                    terrain_mat,
+                    e
+                );
+                mat
+            }
         };
 
-        self.mesh_instances.clear();
+        let previous = std::mem::take(&mut self.mesh_instances);
+        self.mesh_chunks.clear();
+        self.mesh_centers.clear();
+        self.mesh_aabbs.clear();
+        self.mesh_visible.clear();
         let default_medium = self.default_medium.clone();
+        let rows = self.generator.rows();
         for dx in -radius..=radius {
-            for dz in -radius..=radius {
-                let pos = (center.x as i32 + dx, 0, center.z as i32 + dz);
+            for (dz, y) in (-radius..=radius).flat_map(|dz| rows.clone().map(move |y| (dz, y))) {
+                let pos = (center.x as i32 + dx, y, center.z as i32 + dz);
                 let medium = *mediums.get(dx.abs() as usize).unwrap_or(&default_medium);
 
-                let chunk = Chunk::flat(pos);
+                let (chunk, stored) = self.generate_chunk(pos);
                 let mesh_asset = chunk.build_chunk_mesh();
-                let mesh = Mesh::from_asset(
+                let mesh = self.meshes.get_or_upload(
                     &model_manager.queue,
                     &model_manager.device,
                     mesh_asset,
                     &format!("chunk_{:?}", pos),
                 );
                 let mesh_instance = MeshInstance {
-                    mesh: Arc::new(mesh),
+                    mesh,
                     material: Some(mat.clone()),
                 };
                 log_info!("Building medium: {:?} at pos: {:?}", medium, pos);
-                self.insert_chunk_stream(chunk, medium);
+                self.mesh_chunks.push(pos);
+                self.mesh_centers.push(chunk.center());
+                self.mesh_aabbs.push(chunk.aabb());
+                self.mesh_visible.push(true);
+                self.insert_chunk(chunk, medium, stored);
                 self.mesh_instances.push(mesh_instance);
             }
         }
+        drop(previous);
+        self.meshes.sweep();
         let renderable = Renderable::new(terrain_mat.into());
 
-        renderable
+        Ok(renderable)
+    }
+    /// Builds `<base>_blend`: the `base` library material drawn with `terrain.wgsl` over a
+    /// texture array of every terrain layer. Fails when the adapter can't hold the layers.
+    fn blended_material(
+        &mut self,
+        base: &str,
+        surface_config: &wgpu::SurfaceConfiguration,
+        model_manager: &mut crate::ModelManager,
+    ) -> Result<Arc<Material>, EngineError> {
+        let name = format!("{}_blend", base);
+        let key = CacheKey::from(name.as_str());
+        if self.layers.is_some() {
+            if let Some(mat) = model_manager.materials.materials.get(&key) {
+                return Ok(mat.clone());
+            }
+        }
+        // The layers changed since the material was built; it still binds the old arrays.
+        model_manager.materials.materials.remove(&key);
+        let count = BlockRegistry::terrain_layer_count();
+        if !TerrainTextureArray::supported(&model_manager.device.limits(), count) {
+            return Err(EngineError::AssetLoadError(format!(
+                "{} terrain layers exceed the adapter's texture array limit",
+                count
+            )));
+        }
+        let sources = self.layer_textures.get(..count as usize).ok_or_else(|| {
+            EngineError::AssetMissing(format!(
+                "textures for {} terrain layers, have {}",
+                count,
+                self.layer_textures.len()
+            ))
+        })?;
+        let materials = &mut model_manager.materials;
+        let file = materials
+            .library
+            .get(base)
+            .ok_or_else(|| EngineError::AssetMissing(base.to_string()))?;
+        // The arrays are sampled at grazing angles more than anything else, so they follow
+        // the base material's diffuse sampler, anisotropy included.
+        let sampler = materials
+            .textures
+            .samplers
+            .get(&model_manager.device, &file.diffuse_sampler);
+        let layers = TerrainTextureArray::new(
+            &model_manager.device,
+            &model_manager.queue,
+            &mut materials.textures,
+            sources,
+            &BlockRegistry::terrain_layer_priorities(),
+            &sampler,
+        )?;
+        let mut asset = file.to_asset(
+            &name,
+            surface_config,
+            materials.library.depth_stencil().cloned(),
+            vec![
+                RenderBindGroupLayouts::uniform().clone(),
+                RenderBindGroupLayouts::equirect_dst().clone(),
+                RenderBindGroupLayouts::material_storage().clone(),
+                RenderBindGroupLayouts::terrain_layers().clone(),
+            ],
+        );
+        asset.shader = TerrainTextureArray::SHADER.to_string();
+        let material = materials.load_asset_with_bind_group(
+            &model_manager.device,
+            asset,
+            layers.bind_group.clone(),
+            &[crate::Vertex::LAYOUT, crate::VertexInstance::LAYOUT],
+        )?;
+        self.layers = Some(layers);
+        Ok(material)
+    }
+    /// Swaps a reloaded library material in on chunk and water meshes bound to the same
+    /// name.
+    pub fn rebind_material(&mut self, material: &Arc<Material>) {
+        if self
+            .water_material
+            .as_ref()
+            .is_some_and(|m| m.asset.name == material.asset.name)
+        {
+            self.water_material = Some(material.clone());
+            self.water_dirty = true;
+        }
+        for instance in &mut self.mesh_instances {
+            let bound = instance
+                .material
+                .as_ref()
+                .is_some_and(|m| m.asset.name == material.asset.name);
+            if bound {
+                instance.material = Some(material.clone());
+            }
+        }
     }
     pub fn mesh_instances(&self) -> &[MeshInstance] {
         &self.mesh_instances
     }
+    /// Chunk meshes inside the camera frustum at the last update.
+    pub fn visible_mesh_instances(&self) -> impl Iterator<Item = &MeshInstance> {
+        self.visible_chunk_draws().map(|(instance, _)| instance)
+    }
+    /// [`Terrain::visible_mesh_instances`], each with the range of
+    /// [`Terrain::instance_buffer`] that places it.
+    pub fn visible_chunk_draws(&self) -> impl Iterator<Item = (&MeshInstance, Range<u32>)> {
+        (0u32..)
+            .zip(&self.mesh_instances)
+            .zip(&self.mesh_visible)
+            .filter_map(|((i, instance), visible)| visible.then_some((instance, i..i + 1)))
+    }
+    /// Water surfaces of the loaded chunks, each with the instance buffer and range it
+    /// draws with and the local-space point it sorts by.
+    pub fn water_surfaces(
+        &self,
+    ) -> impl Iterator<Item = (&MeshInstance, &InstanceBufferData, Range<u32>, Vec3)> {
+        self.water_instance.iter().flat_map(move |buffer| {
+            self.water_instances
+                .iter()
+                .map(move |(instance, center)| (instance, buffer, 0..buffer.count as u32, *center))
+        })
+    }
+    /// Chunk meshes whose material is transparent, each with the instance buffer and range
+    /// it draws with and the local-space point it sorts by. Water surfaces come from
+    /// [`Terrain::water_surfaces`] instead.
+    pub fn transparent_meshes(
+        &self,
+    ) -> impl Iterator<Item = (&MeshInstance, &InstanceBufferData, Range<u32>, Vec3)> {
+        self.instance_buffer.iter().flat_map(move |buffer| {
+            self.visible_chunk_draws()
+                .filter(|(instance, _)| {
+                    instance
+                        .material
+                        .as_ref()
+                        .is_some_and(|m| m.asset.transparent)
+                })
+                .map(move |(instance, instances)| {
+                    let center = self.mesh_centers[instances.start as usize];
+                    (instance, buffer, instances, center)
+                })
+        })
+    }
+}
+
+/// The chunk at the global chunk coordinate `global`: the one saved in `store`, or else a
+/// fresh one from `generator`, with `true` for a saved one. Saves that can't be read are
+/// warned about and generated over.
+fn load_or_generate(
+    generator: &dyn ChunkGenerator,
+    store: Option<&ChunkStore>,
+    global: I64Vec3,
+) -> (Chunk, bool) {
+    if let Some(store) = store {
+        match store.load(global) {
+            Ok(Some(chunk)) => return (chunk, true),
+            Ok(None) => {}
+            Err(e) => {
+                log_warning!(
+                    "Saved chunk {} unreadable, generating it again: {}",
+                    store.path(global).display(),
+                    e
+                );
+            }
+        }
+    }
+    let mut chunk = Chunk::new((0, 0, 0));
+    generator.fill(&mut chunk, global);
+    (chunk, false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::STONE;
+
+    fn air(pos: (i32, i32, i32)) -> Chunk {
+        let mut chunk = Chunk::new(pos);
+        chunk.blocks = [[[AIR; CHUNK_SIZE]; CHUNK_SIZE]; CHUNK_SIZE];
+        chunk
+    }
+
+    /// Terrain of empty air chunks at `chunks`, none of them waiting for a rebuild.
+    fn loaded(chunks: &[(i32, i32, i32)]) -> Terrain {
+        let mut terrain = Terrain::new(Medium::Air);
+        for &pos in chunks {
+            terrain.insert_chunk_stream(air(pos), Medium::Air);
+        }
+        for (chunk, _) in terrain.chunk_stream.values_mut() {
+            chunk.dirty = false;
+        }
+        terrain
+    }
+
+    fn dirty(terrain: &Terrain) -> HashSet<(i32, i32, i32)> {
+        terrain
+            .chunk_stream
+            .iter()
+            .filter(|(_, (chunk, _))| chunk.dirty)
+            .map(|(pos, _)| *pos)
+            .collect()
+    }
+
+    #[test]
+    fn edits_on_a_border_dirty_the_neighbor() {
+        let size = CHUNK_SIZE as i32;
+        let neighbors = [(0, 0, 0), (1, 0, 0), (-1, 0, 0), (0, 1, 0), (0, 0, 1)];
+
+        let mut terrain = loaded(&neighbors);
+        assert!(terrain.set_block(IVec3::new(1, 1, 2), STONE));
+        assert_eq!(dirty(&terrain), HashSet::from([(0, 0, 0)]));
+        assert_eq!(terrain.remesh, HashSet::from([(0, 0, 0)]));
+
+        let mut terrain = loaded(&neighbors);
+        assert!(terrain.set_block(IVec3::new(size - 1, 1, 2), STONE));
+        assert_eq!(dirty(&terrain), HashSet::from([(0, 0, 0), (1, 0, 0)]));
+        assert_eq!(terrain.remesh, dirty(&terrain));
+
+        // A corner cell touches a neighbor on each of its border axes.
+        let mut terrain = loaded(&neighbors);
+        assert!(terrain.set_block(IVec3::new(0, size - 1, 2), STONE));
+        let expected = HashSet::from([(0, 0, 0), (-1, 0, 0), (0, 1, 0)]);
+        assert_eq!(dirty(&terrain), expected);
+        assert_eq!(terrain.remesh, expected);
+    }
+
+    #[test]
+    fn edits_skip_unloaded_neighbors() {
+        let mut terrain = loaded(&[(0, 0, 0)]);
+        assert!(terrain.set_block(IVec3::new(0, 0, 0), STONE));
+        assert_eq!(terrain.remesh, HashSet::from([(0, 0, 0)]));
+        assert!(!terrain.set_block(IVec3::new(-1, 0, 0), STONE));
+    }
+
+    #[test]
+    fn voxel_raycast_finds_blocks_in_the_next_chunk() {
+        let size = CHUNK_SIZE as i32;
+        let mut terrain = loaded(&[(0, 0, 0), (1, 0, 0)]);
+        let block = IVec3::new(size + 2, 1, 1);
+        terrain.set_block(block, STONE);
+        let hit = terrain.raycast_voxel(Vec3::new(0.5, 1.5, 1.5), Vec3::X, 16.0);
+        assert_eq!(hit, Some((block, IVec3::NEG_X)));
+        assert_eq!(
+            terrain.raycast_voxel(Vec3::new(0.5, 1.5, 1.5), Vec3::NEG_X, 16.0),
+            None
+        );
+    }
+
+    #[test]
+    fn edited_chunks_are_saved_on_request() {
+        let dir = std::env::temp_dir().join(format!("rupy_terrain_{}", std::process::id()));
+        let store = Arc::new(ChunkStore::new(&dir));
+        let mut terrain = loaded(&[(0, 0, 0), (1, 0, 0), (0, 1, 0)]);
+        terrain.set_store(Some(store.clone()));
+        let size = CHUNK_SIZE as i32;
+        terrain.set_block(IVec3::new(1, 1, 1), STONE);
+        terrain.set_block(IVec3::new(size + 1, 2, 3), STONE);
+
+        assert_eq!(terrain.save_modified(), 2);
+        let first = store.load(I64Vec3::ZERO).unwrap().unwrap();
+        let second = store.load(I64Vec3::X).unwrap().unwrap();
+        let untouched = store.load(I64Vec3::Y).unwrap();
+        // Saved chunks count as unedited, so a second flush writes nothing.
+        let again = terrain.save_modified();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(first.blocks[1][1][1], STONE);
+        assert_eq!(second.blocks[1][2][3], STONE);
+        assert!(untouched.is_none());
+        assert_eq!(again, 0);
+        assert!(terrain
+            .chunk_stream
+            .values()
+            .all(|(chunk, _)| !chunk.modified));
+    }
 }
@@ -0,0 +1,354 @@
+//! Blended terrain materials. Every vertex carries up to three texture array layers with
+//! weights taken from the block types around it, and `terrain.wgsl` mixes them by
+//! [`TerrainLayer::priority`] instead of a straight lerp.
+
+use std::sync::Arc;
+
+use crate::{
+    BindGroup, Block, BlockRegistry, EngineError, TerrainLayer, Texture, TextureManager, Vertex,
+    AIR,
+};
+
+/// Layers a single vertex blends between.
+pub const MAX_BLEND_LAYERS: usize = 3;
+/// Layers the terrain texture array and its priority uniform have room for.
+pub const MAX_TERRAIN_LAYERS: u32 = 8;
+/// Width of the priority blend transition; smaller is sharper. Mirrored in `terrain.wgsl`.
+pub const BLEND_SHARPNESS: f32 = 0.2;
+
+/// Contributing layers and weights at one vertex. Unused slots repeat the first layer
+/// with zero weight.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct TerrainBlend {
+    pub layers: [u8; MAX_BLEND_LAYERS],
+    pub weights: [f32; MAX_BLEND_LAYERS],
+}
+
+impl TerrainBlend {
+    pub fn single(layer: u8) -> Self {
+        Self {
+            layers: [layer; MAX_BLEND_LAYERS],
+            weights: [1.0, 0.0, 0.0],
+        }
+    }
+
+    /// Weights from the blocks sharing a vertex: each layer's share of the solid samples.
+    /// The three most common layers are kept, ties going to the higher priority.
+    pub fn from_neighborhood(blocks: impl IntoIterator<Item = Block>) -> Self {
+        let mut counts: Vec<(TerrainLayer, u32)> = Vec::new();
+        for block in blocks {
+            if block == AIR {
+                continue;
+            }
+            let layer = BlockRegistry::terrain_layer(block);
+            match counts.iter_mut().find(|(l, _)| l.layer == layer.layer) {
+                Some((_, count)) => *count += 1,
+                None => counts.push((layer, 1)),
+            }
+        }
+        counts.sort_by(|a, b| {
+            b.1.cmp(&a.1)
+                .then(b.0.priority.total_cmp(&a.0.priority))
+                .then(a.0.layer.cmp(&b.0.layer))
+        });
+        counts.truncate(MAX_BLEND_LAYERS);
+        let Some((first, _)) = counts.first() else {
+            return Self::single(0);
+        };
+        let total: u32 = counts.iter().map(|(_, count)| count).sum();
+        let mut blend = Self {
+            layers: [first.layer; MAX_BLEND_LAYERS],
+            weights: [0.0; MAX_BLEND_LAYERS],
+        };
+        for (i, (layer, count)) in counts.iter().enumerate() {
+            blend.layers[i] = layer.layer;
+            blend.weights[i] = *count as f32 / total as f32;
+        }
+        blend
+    }
+
+    /// Vertex attribute value, see [`Vertex::pack_surface`].
+    pub fn pack(&self, emission: u8) -> [u32; 2] {
+        Vertex::pack_surface(self.layers, self.weights, emission)
+    }
+    /// Identity of the blend as the GPU sees it, after quantization.
+    pub fn key(&self) -> u64 {
+        let [layers, weights] = self.pack(0);
+        layers as u64 | (weights as u64) << 32
+    }
+    /// Merge key for a face with these corner blends: faces may only be merged by a greedy
+    /// mesher when the blend is uniform across them, so blended edges get `None`.
+    pub fn face_key(corners: &[TerrainBlend; 4]) -> Option<u64> {
+        let key = corners[0].key();
+        corners[1..].iter().all(|c| c.key() == key).then_some(key)
+    }
+}
+
+/// Priority blend used by `terrain.wgsl`: each layer's weight plus its priority is compared
+/// with the strongest, and only layers within [`BLEND_SHARPNESS`] of it show.
+pub fn priority_blend(
+    weights: [f32; MAX_BLEND_LAYERS],
+    priorities: [f32; MAX_BLEND_LAYERS],
+) -> [f32; MAX_BLEND_LAYERS] {
+    let heights: [f32; MAX_BLEND_LAYERS] = std::array::from_fn(|i| weights[i] + priorities[i]);
+    let top = (0..MAX_BLEND_LAYERS)
+        .filter(|i| weights[*i] > 0.0)
+        .map(|i| heights[i])
+        .fold(f32::MIN, f32::max);
+    let mut out: [f32; MAX_BLEND_LAYERS] = std::array::from_fn(|i| {
+        if weights[i] > 0.0 {
+            (heights[i] - top + BLEND_SHARPNESS).max(0.0)
+        } else {
+            0.0
+        }
+    });
+    let sum: f32 = out.iter().sum();
+    if sum <= f32::EPSILON {
+        return weights;
+    }
+    out.iter_mut().for_each(|w| *w /= sum);
+    out
+}
+
+/// Diffuse and normal texture of one terrain layer, relative to `assets/textures`.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct TerrainLayerTextures {
+    pub diffuse: String,
+    pub normal: String,
+}
+
+impl TerrainLayerTextures {
+    pub fn new(diffuse: impl Into<String>, normal: impl Into<String>) -> Self {
+        Self {
+            diffuse: diffuse.into(),
+            normal: normal.into(),
+        }
+    }
+}
+
+/// Diffuse and normal `texture_2d_array`s with one layer per terrain material, plus the
+/// per-layer priorities, bound at group 3 of `terrain.wgsl`.
+#[derive(Debug)]
+pub struct TerrainTextureArray {
+    pub diffuse: Arc<Texture>,
+    pub normal: Arc<Texture>,
+    pub priorities: crate::WgpuBuffer,
+    pub bind_group: Arc<wgpu::BindGroup>,
+    pub layers: u32,
+}
+
+impl TerrainTextureArray {
+    pub const SHADER: &'static str = "terrain.wgsl";
+
+    /// Whether blended terrain can run with `layers` layers on a device with `limits`.
+    /// When it can't, terrain stays on its single library material.
+    pub fn supported(limits: &wgpu::Limits, layers: u32) -> bool {
+        layers > 0 && layers <= MAX_TERRAIN_LAYERS && layers <= limits.max_texture_array_layers
+    }
+
+    /// Loads both arrays through `textures`, see [`TextureManager::load_texture_array`].
+    /// Layer `i` of the arrays is `sources[i]`; `priorities` is indexed the same way.
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        textures: &mut TextureManager,
+        sources: &[TerrainLayerTextures],
+        priorities: &[f32],
+        sampler: &wgpu::Sampler,
+    ) -> Result<Self, EngineError> {
+        let layers = sources.len() as u32;
+        if !Self::supported(&device.limits(), layers) {
+            return Err(EngineError::AssetLoadError(format!(
+                "{} terrain layers not supported",
+                layers
+            )));
+        }
+        let diffuse_paths: Vec<&str> = sources.iter().map(|s| s.diffuse.as_str()).collect();
+        let normal_paths: Vec<&str> = sources.iter().map(|s| s.normal.as_str()).collect();
+        let (diffuse, _) =
+            textures.load_texture_array(queue, device, &diffuse_paths, Texture::DEFAULT_FORMAT)?;
+        let (normal, _) = textures.load_texture_array(
+            queue,
+            device,
+            &normal_paths,
+            wgpu::TextureFormat::Rgba8Unorm,
+        )?;
+
+        let mut packed = [[0.0f32; 4]; MAX_TERRAIN_LAYERS as usize / 4];
+        for (i, priority) in priorities
+            .iter()
+            .take(MAX_TERRAIN_LAYERS as usize)
+            .enumerate()
+        {
+            packed[i / 4][i % 4] = *priority;
+        }
+        let priorities = crate::WgpuBuffer::from_data(
+            device,
+            &packed,
+            wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            Some("terrain layer priorities"),
+        );
+        let bind_group = Arc::new(BindGroup::terrain_layers(
+            device,
+            &diffuse,
+            &normal,
+            sampler,
+            &priorities,
+            "terrain",
+        ));
+        Ok(Self {
+            diffuse,
+            normal,
+            priorities,
+            bind_group,
+            layers,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Chunk, CHUNK_SIZE, CRYSTAL, LAVA, STONE};
+
+    fn assert_weights(actual: [f32; MAX_BLEND_LAYERS], expected: [f32; MAX_BLEND_LAYERS]) {
+        for (a, e) in actual.iter().zip(expected) {
+            assert!((a - e).abs() < 1e-5, "{:?} vs {:?}", actual, expected);
+        }
+    }
+
+    fn air_chunk() -> Chunk {
+        let mut chunk = Chunk::new((0, 0, 0));
+        chunk.blocks = [[[AIR; CHUNK_SIZE]; CHUNK_SIZE]; CHUNK_SIZE];
+        chunk
+    }
+
+    #[test]
+    fn weights_follow_the_blocks_around_a_vertex() {
+        // The eight cells around lattice point (1, 1, 1): four stone, two crystal, one lava.
+        let mut chunk = air_chunk();
+        for (x, z) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+            chunk.set_block(x, 0, z, STONE);
+        }
+        chunk.set_block(0, 1, 0, CRYSTAL);
+        chunk.set_block(1, 1, 0, CRYSTAL);
+        chunk.set_block(0, 1, 1, LAVA);
+
+        let blend = chunk.corner_blend(0, 0, 0, [1.0, 1.0, 1.0]);
+        let stone = BlockRegistry::terrain_layer(STONE).layer;
+        let crystal = BlockRegistry::terrain_layer(CRYSTAL).layer;
+        let lava = BlockRegistry::terrain_layer(LAVA).layer;
+        assert_eq!(blend.layers, [stone, crystal, lava]);
+        assert_weights(blend.weights, [4.0 / 7.0, 2.0 / 7.0, 1.0 / 7.0]);
+
+        // A corner deep in stone only sees stone.
+        assert_eq!(
+            chunk.corner_blend(0, 0, 0, [0.0, 0.0, 0.0]),
+            TerrainBlend::single(stone)
+        );
+    }
+
+    #[test]
+    fn ties_go_to_the_higher_priority_and_air_is_ignored() {
+        let blend = TerrainBlend::from_neighborhood([LAVA, CRYSTAL, AIR, AIR]);
+        assert_eq!(
+            blend.layers[..2],
+            [
+                BlockRegistry::terrain_layer(CRYSTAL).layer,
+                BlockRegistry::terrain_layer(LAVA).layer
+            ]
+        );
+        assert_weights(blend.weights, [0.5, 0.5, 0.0]);
+        assert_eq!(
+            TerrainBlend::from_neighborhood([AIR; 8]),
+            TerrainBlend::single(0)
+        );
+    }
+
+    #[test]
+    fn priority_decides_equal_coverage() {
+        // Rock well above grass takes the vertex outright.
+        assert_weights(
+            priority_blend([0.5, 0.5, 0.0], [0.6, 0.0, 0.0]),
+            [1.0, 0.0, 0.0],
+        );
+        assert_weights(
+            priority_blend([0.5, 0.5, 0.0], [0.0, 0.6, 0.0]),
+            [0.0, 1.0, 0.0],
+        );
+        // Within the sharpness band both show, the higher one more.
+        assert_weights(
+            priority_blend([0.5, 0.5, 0.0], [0.1, 0.0, 0.0]),
+            [2.0 / 3.0, 1.0 / 3.0, 0.0],
+        );
+        // Equal priorities split evenly; enough coverage beats a priority edge.
+        assert_weights(
+            priority_blend([0.5, 0.5, 0.0], [0.3, 0.3, 0.3]),
+            [0.5, 0.5, 0.0],
+        );
+        assert_weights(
+            priority_blend([0.9, 0.1, 0.0], [0.0, 0.3, 0.0]),
+            [1.0, 0.0, 0.0],
+        );
+        // Unused slots never show, whatever their priority.
+        assert_weights(
+            priority_blend([1.0, 0.0, 0.0], [0.0, 0.0, 1.0]),
+            [1.0, 0.0, 0.0],
+        );
+    }
+
+    #[test]
+    fn only_uniform_faces_get_a_merge_key() {
+        let stone = TerrainBlend::single(0);
+        assert_eq!(TerrainBlend::face_key(&[stone; 4]), Some(stone.key()));
+
+        // Weights that quantize the same still merge.
+        let mut nudged = stone;
+        nudged.weights[0] -= 1e-5;
+        assert_eq!(
+            TerrainBlend::face_key(&[stone, nudged, stone, stone]),
+            Some(stone.key())
+        );
+
+        let mut chunk = air_chunk();
+        for x in 0..CHUNK_SIZE {
+            for z in 0..CHUNK_SIZE {
+                chunk.set_block(x, 0, z, STONE);
+            }
+        }
+        chunk.set_block(3, 0, 3, CRYSTAL);
+        let top = |x, z| {
+            let corners = [
+                [0.0, 1.0, 0.0],
+                [1.0, 1.0, 0.0],
+                [1.0, 1.0, 1.0],
+                [0.0, 1.0, 1.0],
+            ];
+            TerrainBlend::face_key(&corners.map(|c| chunk.corner_blend(x, 0, z, c)))
+        };
+        // Away from the crystal the tops merge; next to it the blended edge doesn't.
+        assert_eq!(top(0, 0), top(1, 0));
+        assert!(top(0, 0).is_some());
+        assert_eq!(top(2, 2), None);
+        assert_eq!(top(3, 3), None);
+    }
+
+    #[test]
+    fn blending_needs_room_for_every_layer() {
+        let limits = wgpu::Limits {
+            max_texture_array_layers: 4,
+            ..wgpu::Limits::downlevel_webgl2_defaults()
+        };
+        assert!(TerrainTextureArray::supported(&limits, 3));
+        assert!(TerrainTextureArray::supported(&limits, 4));
+        assert!(!TerrainTextureArray::supported(&limits, 5));
+        assert!(!TerrainTextureArray::supported(&limits, 0));
+
+        let roomy = wgpu::Limits::default();
+        assert!(TerrainTextureArray::supported(&roomy, MAX_TERRAIN_LAYERS));
+        assert!(!TerrainTextureArray::supported(
+            &roomy,
+            MAX_TERRAIN_LAYERS + 1
+        ));
+    }
+}
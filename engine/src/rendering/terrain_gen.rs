@@ -0,0 +1,152 @@
+//! What streamed terrain chunks are filled with. A [`ChunkGenerator`] sees only the global
+//! chunk coordinate, so a chunk comes out the same however often it is unloaded, which
+//! thread builds it and where the world origin sits at the time.
+
+use glam::{I64Vec3, Vec2};
+
+use crate::{Block, Chunk, AIR, CHUNK_SIZE, GRASS, SAND, STONE, WATER_FULL};
+
+/// Highest block row water fills to, exclusive: air cells below it start full.
+pub const SEA_LEVEL: i64 = 3;
+
+pub trait ChunkGenerator: std::fmt::Debug + Send + Sync {
+    /// Fills every cell of `chunk`, which sits at the global chunk coordinate `global`.
+    fn fill(&self, chunk: &mut Chunk, global: I64Vec3);
+    /// Chunk rows along y that streaming loads in each column.
+    fn rows(&self) -> std::ops::Range<i32> {
+        0..1
+    }
+}
+
+/// One row of stone at the bottom of row 0 and air everywhere else, like [`Chunk::flat`].
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FlatGenerator;
+
+impl ChunkGenerator for FlatGenerator {
+    fn fill(&self, chunk: &mut Chunk, global: I64Vec3) {
+        for x in 0..CHUNK_SIZE {
+            for y in 0..CHUNK_SIZE {
+                for z in 0..CHUNK_SIZE {
+                    let floor = global.y == 0 && y == 0;
+                    chunk.set_block(x, y, z, if floor { STONE } else { AIR });
+                }
+            }
+        }
+    }
+}
+
+/// Rolling ground from octaves of seeded gradient noise: stone under a few blocks of grass,
+/// sand where the surface comes near [`SEA_LEVEL`], and water filling the air below it.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct NoiseGenerator {
+    pub seed: u32,
+    /// Cycles per block of the first octave.
+    pub frequency: f32,
+    /// Octaves summed, each at twice the frequency and half the amplitude of the last.
+    pub octaves: u32,
+    /// Ground height in blocks where the noise is zero.
+    pub base_height: f32,
+    /// Most the ground rises or sinks from `base_height`, in blocks.
+    pub amplitude: f32,
+}
+
+impl Default for NoiseGenerator {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            frequency: 0.05,
+            octaves: 4,
+            base_height: 4.0,
+            amplitude: 4.0,
+        }
+    }
+}
+
+/// Blocks of grass or sand over the stone.
+const TOPSOIL: i64 = 2;
+
+impl NoiseGenerator {
+    pub fn new(seed: u32) -> Self {
+        Self {
+            seed,
+            ..Self::default()
+        }
+    }
+    /// Number of solid blocks in the column at global block `(x, z)`, at least one.
+    pub fn height(&self, x: i64, z: i64) -> i64 {
+        let point = Vec2::new(x as f32, z as f32) * self.frequency;
+        let height = self.base_height + self.amplitude * self.fbm(point);
+        (height.round() as i64).max(1)
+    }
+    /// Octaves of [`gradient_noise`] normalized back to about -1..1.
+    fn fbm(&self, point: Vec2) -> f32 {
+        let (mut sum, mut weight, mut total) = (0.0, 1.0, 0.0);
+        for octave in 0..self.octaves.max(1) {
+            let seed = self.seed.wrapping_add(octave.wrapping_mul(0x9e37_79b9));
+            sum += weight * gradient_noise(seed, point * (1 << octave.min(16)) as f32);
+            total += weight;
+            weight *= 0.5;
+        }
+        sum / total
+    }
+    fn block(height: i64, y: i64) -> Block {
+        if y >= height {
+            AIR
+        } else if y < height - TOPSOIL {
+            STONE
+        } else if height <= SEA_LEVEL + 1 {
+            SAND
+        } else {
+            GRASS
+        }
+    }
+}
+
+impl ChunkGenerator for NoiseGenerator {
+    fn fill(&self, chunk: &mut Chunk, global: I64Vec3) {
+        let origin = global * CHUNK_SIZE as i64;
+        for x in 0..CHUNK_SIZE {
+            for z in 0..CHUNK_SIZE {
+                let height = self.height(origin.x + x as i64, origin.z + z as i64);
+                for y in 0..CHUNK_SIZE {
+                    let cell_y = origin.y + y as i64;
+                    chunk.set_block(x, y, z, Self::block(height, cell_y));
+                    if cell_y >= height && cell_y < SEA_LEVEL {
+                        chunk.set_water(x, y, z, WATER_FULL);
+                    }
+                }
+            }
+        }
+    }
+    fn rows(&self) -> std::ops::Range<i32> {
+        let top = (self.base_height + self.amplitude.abs()).ceil() as i32;
+        let top = top.max(SEA_LEVEL as i32).max(1);
+        0..(top + CHUNK_SIZE as i32 - 1) / CHUNK_SIZE as i32
+    }
+}
+
+/// 2D gradient noise in about -1..1, zero on every integer lattice point.
+pub fn gradient_noise(seed: u32, point: Vec2) -> f32 {
+    let cell = point.floor();
+    let f = point - cell;
+    let (x, z) = (cell.x as i32, cell.y as i32);
+    let corner = |dx: i32, dz: i32| {
+        let angle = lattice_hash(seed, x.wrapping_add(dx), z.wrapping_add(dz)) as f32
+            * (std::f32::consts::TAU / u32::MAX as f32);
+        Vec2::from_angle(angle).dot(f - Vec2::new(dx as f32, dz as f32))
+    };
+    let fade = |t: f32| t * t * t * (t * (t * 6.0 - 15.0) + 10.0);
+    let (u, v) = (fade(f.x), fade(f.y));
+    let bottom = corner(0, 0) + u * (corner(1, 0) - corner(0, 0));
+    let top = corner(0, 1) + u * (corner(1, 1) - corner(0, 1));
+    // Gradient noise peaks at about ±0.7 in 2D.
+    (bottom + v * (top - bottom)) * std::f32::consts::SQRT_2
+}
+
+fn lattice_hash(seed: u32, x: i32, z: i32) -> u32 {
+    let mut h = seed ^ (x as u32).wrapping_mul(0x27d4_eb2d) ^ (z as u32).wrapping_mul(0x1656_67b1);
+    h = (h ^ (h >> 15)).wrapping_mul(0x85eb_ca6b);
+    h = (h ^ (h >> 13)).wrapping_mul(0xc2b2_ae35);
+    h ^ (h >> 16)
+}
@@ -1,9 +1,11 @@
 use super::DebugMode;
 
+/// Records draws into a pass. Recording only reads, so passes can record on any thread;
+/// anything created lazily belongs in a prepare step beforehand.
 pub trait RenderPass {
     fn render(
         &self,
-        models: &mut crate::ModelManager,
+        models: &crate::ModelManager,
         rpass: &mut wgpu::RenderPass,
         world: &crate::World,
         uniform_bind_group: &wgpu::BindGroup,
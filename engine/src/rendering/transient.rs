@@ -0,0 +1,567 @@
+//! Transient render targets that share memory. Passes declare which targets they read and
+//! write in submission order; from that every transient target gets a first/last use, and
+//! compatible targets whose lifetimes don't overlap are packed into one pooled texture.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::{log_info, EngineError, RenderTargetKind, Texture};
+
+static TRANSIENT_ALIASING: AtomicBool = AtomicBool::new(true);
+static POISON_TRANSIENTS: AtomicBool = AtomicBool::new(cfg!(debug_assertions));
+
+/// Debug switches for the transient pool.
+pub struct TransientSettings;
+
+impl TransientSettings {
+    pub fn aliasing() -> bool {
+        TRANSIENT_ALIASING.load(Ordering::Relaxed)
+    }
+    /// Gives every transient target its own texture, for bisecting rendering bugs. Takes
+    /// effect on the next [`TransientPool::prepare`].
+    pub fn set_aliasing(enabled: bool) {
+        TRANSIENT_ALIASING.store(enabled, Ordering::Relaxed);
+    }
+    pub fn poison() -> bool {
+        POISON_TRANSIENTS.load(Ordering::Relaxed)
+    }
+    /// Clears a slot to [`TransientPool::POISON`] whenever it changes owner, so a pass that
+    /// reads memory it doesn't own shows up on screen. On by default in debug builds.
+    pub fn set_poison(enabled: bool) {
+        POISON_TRANSIENTS.store(enabled, Ordering::Relaxed);
+    }
+}
+
+/// What a transient target needs from its allocation. Targets alias only when equal.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct TransientDesc {
+    pub width: u32,
+    pub height: u32,
+    pub format: wgpu::TextureFormat,
+    pub usage: wgpu::TextureUsages,
+}
+
+impl TransientDesc {
+    pub fn new(
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+        usage: wgpu::TextureUsages,
+    ) -> Self {
+        Self {
+            width: width.max(1),
+            height: height.max(1),
+            format,
+            usage,
+        }
+    }
+    pub fn bytes(&self) -> u64 {
+        let texel = self.format.block_copy_size(None).unwrap_or(4) as u64;
+        self.width as u64 * self.height as u64 * texel
+    }
+}
+
+/// Pass indices of a target's first and last use, inclusive.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TargetLifetime {
+    pub first: usize,
+    pub last: usize,
+}
+
+impl TargetLifetime {
+    pub fn overlaps(&self, other: &TargetLifetime) -> bool {
+        self.first <= other.last && other.first <= self.last
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PassDecl {
+    pub name: &'static str,
+    pub reads: Vec<RenderTargetKind>,
+    pub writes: Vec<RenderTargetKind>,
+}
+
+impl PassDecl {
+    /// Two passes conflict when either writes a target the other touches.
+    pub fn conflicts(&self, other: &PassDecl) -> bool {
+        let touches = |pass: &PassDecl, kind: &RenderTargetKind| {
+            pass.reads.contains(kind) || pass.writes.contains(kind)
+        };
+        self.writes.iter().any(|kind| touches(other, kind))
+            || other.writes.iter().any(|kind| touches(self, kind))
+    }
+}
+
+/// Passes of one frame in submission order, and the targets they touch.
+#[derive(Debug, Default, Clone)]
+pub struct FramePlan {
+    passes: Vec<PassDecl>,
+    transients: Vec<(RenderTargetKind, TransientDesc)>,
+    persistent: Vec<RenderTargetKind>,
+}
+
+impl FramePlan {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Declares a target the pool may alias.
+    pub fn transient(&mut self, kind: RenderTargetKind, desc: TransientDesc) -> &mut Self {
+        self.transients.retain(|(k, _)| *k != kind);
+        self.transients.push((kind, desc));
+        self
+    }
+    /// Opts `kind` out of aliasing: it keeps its own `FrameBuffer` in the
+    /// `RenderTargetManager`, and may be read before it is written since it outlives the frame.
+    pub fn persistent(&mut self, kind: RenderTargetKind) -> &mut Self {
+        self.transients.retain(|(k, _)| *k != kind);
+        if !self.persistent.contains(&kind) {
+            self.persistent.push(kind);
+        }
+        self
+    }
+    pub fn pass(
+        &mut self,
+        name: &'static str,
+        reads: &[RenderTargetKind],
+        writes: &[RenderTargetKind],
+    ) -> &mut Self {
+        self.passes.push(PassDecl {
+            name,
+            reads: reads.to_vec(),
+            writes: writes.to_vec(),
+        });
+        self
+    }
+    pub fn passes(&self) -> &[PassDecl] {
+        &self.passes
+    }
+    pub fn is_persistent(&self, kind: &RenderTargetKind) -> bool {
+        self.persistent.contains(kind)
+    }
+
+    /// First and last use of every transient target, in declaration order. Fails when a pass
+    /// reads a transient target before any pass wrote it this frame: whatever it finds there
+    /// belongs to another target.
+    pub fn lifetimes(&self) -> Result<Vec<(RenderTargetKind, TargetLifetime)>, EngineError> {
+        let mut lifetimes: Vec<(RenderTargetKind, Option<TargetLifetime>)> =
+            self.transients.iter().map(|(k, _)| (*k, None)).collect();
+        for (idx, pass) in self.passes.iter().enumerate() {
+            for kind in &pass.reads {
+                let Some((_, lifetime)) = lifetimes.iter_mut().find(|(k, _)| k == kind) else {
+                    continue;
+                };
+                match lifetime {
+                    Some(lifetime) => lifetime.last = idx,
+                    None => {
+                        return Err(EngineError::FramePlanError(format!(
+                            "pass '{}' reads {:?} before any pass writes it",
+                            pass.name, kind
+                        )))
+                    }
+                }
+            }
+            for kind in &pass.writes {
+                if let Some((_, lifetime)) = lifetimes.iter_mut().find(|(k, _)| k == kind) {
+                    let lifetime = lifetime.get_or_insert(TargetLifetime {
+                        first: idx,
+                        last: idx,
+                    });
+                    lifetime.last = idx;
+                }
+            }
+        }
+        // Declared but never touched: nothing to allocate.
+        Ok(lifetimes
+            .into_iter()
+            .filter_map(|(k, l)| l.map(|l| (k, l)))
+            .collect())
+    }
+
+    /// Assigns every used transient target a slot. Targets are placed in order of first use
+    /// into the first compatible slot that is free by then, which needs the fewest slots for
+    /// interval lifetimes; with `aliasing` off each target gets a slot of its own.
+    pub fn assign(&self, aliasing: bool) -> Result<AliasPlan, EngineError> {
+        let mut lifetimes = self.lifetimes()?;
+        lifetimes.sort_by_key(|(_, l)| l.first);
+
+        let mut plan = AliasPlan::default();
+        // Last pass using each slot so far.
+        let mut slot_last: Vec<usize> = Vec::new();
+        for (kind, lifetime) in lifetimes {
+            let desc = self
+                .transients
+                .iter()
+                .find(|(k, _)| *k == kind)
+                .map(|(_, d)| *d)
+                .expect("lifetimes only covers declared transients");
+            let free = aliasing
+                .then(|| {
+                    (0..plan.slots.len())
+                        .find(|s| plan.slots[*s] == desc && slot_last[*s] < lifetime.first)
+                })
+                .flatten();
+            let slot = match free {
+                Some(slot) => {
+                    slot_last[slot] = lifetime.last;
+                    slot
+                }
+                None => {
+                    plan.slots.push(desc);
+                    slot_last.push(lifetime.last);
+                    plan.slots.len() - 1
+                }
+            };
+            plan.targets.push(AliasedTarget {
+                kind,
+                desc,
+                lifetime,
+                slot,
+            });
+        }
+        Ok(plan)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct AliasedTarget {
+    pub kind: RenderTargetKind,
+    pub desc: TransientDesc,
+    pub lifetime: TargetLifetime,
+    pub slot: usize,
+}
+
+/// Result of [`FramePlan::assign`]: one allocation per slot, shared by its targets.
+#[derive(Debug, Default, Clone)]
+pub struct AliasPlan {
+    pub slots: Vec<TransientDesc>,
+    pub targets: Vec<AliasedTarget>,
+}
+
+impl AliasPlan {
+    pub fn target(&self, kind: &RenderTargetKind) -> Option<&AliasedTarget> {
+        self.targets.iter().find(|t| t.kind == *kind)
+    }
+    pub fn stats(&self) -> TransientStats {
+        let allocated_bytes = self.slots.iter().map(TransientDesc::bytes).sum();
+        let unaliased_bytes = self.targets.iter().map(|t| t.desc.bytes()).sum();
+        TransientStats {
+            targets: self.targets.len(),
+            slots: self.slots.len(),
+            allocated_bytes,
+            unaliased_bytes,
+        }
+    }
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct TransientStats {
+    pub targets: usize,
+    pub slots: usize,
+    pub allocated_bytes: u64,
+    /// What the same targets would take with a texture each.
+    pub unaliased_bytes: u64,
+}
+
+impl TransientStats {
+    pub fn saved_bytes(&self) -> u64 {
+        self.unaliased_bytes.saturating_sub(self.allocated_bytes)
+    }
+}
+
+/// Textures backing an [`AliasPlan`], created on [`TransientPool::prepare`] and dropped on
+/// [`TransientPool::invalidate`].
+#[derive(Default)]
+pub struct TransientPool {
+    plan: AliasPlan,
+    slots: Vec<Texture>,
+    owners: Vec<Option<RenderTargetKind>>,
+}
+
+impl TransientPool {
+    pub const POISON: wgpu::Color = wgpu::Color {
+        r: 1.0,
+        g: 0.0,
+        b: 1.0,
+        a: 1.0,
+    };
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Plans `frame` and allocates its slots, unless already allocated. Call again after
+    /// [`Self::invalidate`] with descriptors sized for the new surface.
+    pub fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        frame: &FramePlan,
+    ) -> Result<TransientStats, EngineError> {
+        if !self.slots.is_empty() {
+            return Ok(self.stats());
+        }
+        self.plan = frame.assign(TransientSettings::aliasing())?;
+        self.slots = self
+            .plan
+            .slots
+            .iter()
+            .enumerate()
+            .map(|(i, desc)| {
+                Texture::new(
+                    device,
+                    wgpu::Extent3d {
+                        width: desc.width,
+                        height: desc.height,
+                        depth_or_array_layers: 1,
+                    },
+                    desc.format,
+                    1,
+                    wgpu::TextureViewDimension::D2,
+                    desc.usage,
+                    Some(wgpu::AddressMode::ClampToEdge),
+                    wgpu::FilterMode::Linear,
+                    None,
+                    Some(&format!("transient slot {}", i)),
+                )
+            })
+            .collect();
+        self.owners = vec![None; self.slots.len()];
+        let stats = self.stats();
+        log_info!(
+            "Transient targets: {} in {} slots, {} KiB allocated, {} KiB saved",
+            stats.targets,
+            stats.slots,
+            stats.allocated_bytes / 1024,
+            stats.saved_bytes() / 1024
+        );
+        Ok(stats)
+    }
+
+    /// Drops every allocation, e.g. on resize.
+    pub fn invalidate(&mut self) {
+        self.plan = AliasPlan::default();
+        self.slots.clear();
+        self.owners.clear();
+    }
+
+    pub fn plan(&self) -> &AliasPlan {
+        &self.plan
+    }
+    pub fn stats(&self) -> TransientStats {
+        self.plan.stats()
+    }
+
+    /// Texture currently backing `kind`, for reading.
+    pub fn get(&self, kind: &RenderTargetKind) -> Option<&Texture> {
+        let slot = self.plan.target(kind)?.slot;
+        (self.owners[slot] == Some(*kind)).then(|| &self.slots[slot])
+    }
+
+    /// Texture for a pass about to write `kind`. If the slot last held another target it is
+    /// poison-cleared first (see [`TransientSettings::set_poison`]).
+    pub fn acquire(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        kind: &RenderTargetKind,
+    ) -> Option<&Texture> {
+        let slot = self.plan.target(kind)?.slot;
+        let previous = self.owners[slot].replace(*kind);
+        if previous.is_some_and(|owner| owner != *kind) && TransientSettings::poison() {
+            Self::poison(encoder, &self.slots[slot]);
+        }
+        Some(&self.slots[slot])
+    }
+
+    fn poison(encoder: &mut wgpu::CommandEncoder, texture: &Texture) {
+        let format = texture.texture.format();
+        let (color, depth) = if format.is_depth_stencil_format() {
+            let depth = wgpu::RenderPassDepthStencilAttachment {
+                view: &texture.view,
+                depth_ops: format.has_depth_aspect().then_some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(0.5),
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: format.has_stencil_aspect().then_some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(0),
+                    store: wgpu::StoreOp::Store,
+                }),
+            };
+            (None, Some(depth))
+        } else {
+            let color = wgpu::RenderPassColorAttachment {
+                view: &texture.view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(Self::POISON),
+                    store: wgpu::StoreOp::Store,
+                },
+            };
+            (Some(color), None)
+        };
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("transient poison"),
+            color_attachments: &[color],
+            depth_stencil_attachment: depth,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use RenderTargetKind::{Custom, Hdr, Scene};
+
+    const OIT_ACCUM: RenderTargetKind = Custom("oit_accum");
+    const OIT_REVEAL: RenderTargetKind = Custom("oit_reveal");
+    const BLOOM_DOWN: RenderTargetKind = Custom("bloom_down");
+    const BLOOM_QUARTER: RenderTargetKind = Custom("bloom_quarter");
+    const BLOOM_UP: RenderTargetKind = Custom("bloom_up");
+
+    fn desc(width: u32, height: u32) -> TransientDesc {
+        TransientDesc::new(
+            width,
+            height,
+            wgpu::TextureFormat::Rgba16Float,
+            wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        )
+    }
+
+    /// Half resolution OIT followed by a bloom chain, over a persistent HDR target.
+    fn oit_then_bloom() -> FramePlan {
+        let (half, quarter) = (desc(640, 360), desc(320, 180));
+        let mut frame = FramePlan::new();
+        frame
+            .persistent(Hdr)
+            .persistent(Scene)
+            .transient(OIT_ACCUM, half)
+            .transient(OIT_REVEAL, half)
+            .transient(BLOOM_DOWN, half)
+            .transient(BLOOM_QUARTER, quarter)
+            .transient(BLOOM_UP, half)
+            .pass("opaque", &[], &[Hdr])
+            .pass("oit accumulate", &[], &[OIT_ACCUM, OIT_REVEAL])
+            .pass("oit resolve", &[OIT_ACCUM, OIT_REVEAL], &[Hdr])
+            .pass("bloom down", &[Hdr], &[BLOOM_DOWN])
+            .pass("bloom quarter", &[BLOOM_DOWN], &[BLOOM_QUARTER])
+            .pass("bloom up", &[BLOOM_QUARTER, BLOOM_DOWN], &[BLOOM_UP])
+            .pass("composite", &[Hdr, BLOOM_UP], &[Scene]);
+        frame
+    }
+
+    fn lifetime(first: usize, last: usize) -> TargetLifetime {
+        TargetLifetime { first, last }
+    }
+
+    #[test]
+    fn lifetimes_run_from_first_write_to_last_use() {
+        let mut frame = oit_then_bloom();
+        frame.transient(Custom("unused"), desc(8, 8));
+        assert_eq!(
+            frame.lifetimes().unwrap(),
+            [
+                (OIT_ACCUM, lifetime(1, 2)),
+                (OIT_REVEAL, lifetime(1, 2)),
+                (BLOOM_DOWN, lifetime(3, 5)),
+                (BLOOM_QUARTER, lifetime(4, 5)),
+                (BLOOM_UP, lifetime(5, 6)),
+            ]
+        );
+        assert!(lifetime(1, 2).overlaps(&lifetime(2, 4)));
+        assert!(!lifetime(1, 2).overlaps(&lifetime(3, 5)));
+
+        let mut frame = FramePlan::new();
+        frame
+            .transient(BLOOM_DOWN, desc(8, 8))
+            .pass("blur", &[BLOOM_DOWN], &[BLOOM_DOWN]);
+        let error = frame.lifetimes().unwrap_err().to_string();
+        assert!(error.contains("'blur' reads"), "{}", error);
+    }
+
+    #[test]
+    fn bloom_reuses_the_oit_memory() {
+        let plan = oit_then_bloom().assign(true).unwrap();
+        let slot = |kind| plan.target(&kind).unwrap().slot;
+        // Two half resolution targets are ever live at once, so two slots hold all four.
+        assert_eq!(plan.slots.len(), 3);
+        assert_eq!(slot(BLOOM_DOWN), slot(OIT_ACCUM));
+        assert_eq!(slot(BLOOM_UP), slot(OIT_REVEAL));
+        assert_ne!(slot(BLOOM_QUARTER), slot(OIT_ACCUM));
+        assert_ne!(slot(BLOOM_QUARTER), slot(OIT_REVEAL));
+
+        let half = desc(640, 360).bytes();
+        let stats = plan.stats();
+        assert_eq!(stats.targets, 5);
+        assert_eq!(stats.saved_bytes(), 2 * half);
+    }
+
+    #[test]
+    fn persistent_targets_and_disabled_aliasing_opt_out() {
+        let mut frame = oit_then_bloom();
+        // Reading last frame's HDR before writing it is fine for a persistent target.
+        frame.pass("history", &[Hdr], &[]);
+        let plan = frame.assign(true).unwrap();
+        assert!(frame.is_persistent(&Hdr));
+        assert!(plan.target(&Hdr).is_none());
+        assert!(plan.target(&Scene).is_none());
+
+        // Opting out after declaring a target transient takes it out of the pool.
+        frame.persistent(BLOOM_UP);
+        assert!(frame.assign(true).unwrap().target(&BLOOM_UP).is_none());
+
+        let unaliased = oit_then_bloom().assign(false).unwrap();
+        assert_eq!(unaliased.slots.len(), unaliased.targets.len());
+        assert_eq!(unaliased.stats().saved_bytes(), 0);
+    }
+
+    /// Needs an adapter; passes without one.
+    #[test]
+    fn poison_shows_through_a_misdeclared_read() {
+        let _ = crate::GPU::init(crate::GpuRequirements::default());
+        let Ok((device, queue)) =
+            crate::GPU::with_read_recovered(|gpu| (gpu.device().clone(), gpu.queue().clone()))
+        else {
+            return;
+        };
+        TransientSettings::set_poison(true);
+        let small = TransientDesc::new(
+            4,
+            4,
+            wgpu::TextureFormat::Rgba8Unorm,
+            wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        );
+        // The bloom pass samples the OIT accumulation but only declares its own target.
+        let mut frame = FramePlan::new();
+        frame
+            .transient(OIT_ACCUM, small)
+            .transient(BLOOM_DOWN, small)
+            .pass("oit accumulate", &[], &[OIT_ACCUM])
+            .pass("bloom down", &[], &[BLOOM_DOWN]);
+        let mut pool = TransientPool::new();
+        pool.prepare(&device, &frame).unwrap();
+        assert_eq!(pool.stats().slots, 1);
+
+        let mut encoder = device.create_command_encoder(&Default::default());
+        let oit = pool.acquire(&mut encoder, &OIT_ACCUM).unwrap();
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("oit accumulate"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &oit.view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::GREEN),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        pool.acquire(&mut encoder, &BLOOM_DOWN).unwrap();
+
+        // The undeclared read finds the slot handed over and the accumulation gone.
+        assert!(pool.get(&OIT_ACCUM).is_none());
+        let slot = &pool.get(&BLOOM_DOWN).unwrap().texture;
+        let pixels = crate::read_texture_blocking(&device, &queue, encoder, slot).unwrap();
+        assert_eq!(pixels[..4], [255, 0, 255, 255]);
+    }
+}
@@ -0,0 +1,97 @@
+//! Transparent geometry is drawn after everything opaque, testing depth without writing
+//! it, and sorted farthest first so each blended surface composites over what's behind
+//! it. Sorting is per instance by distance from the eye to the instance origin, which is
+//! right for convex, non-intersecting surfaces such as glass panes and water chunks.
+
+use std::cmp::Ordering;
+use std::ops::Range;
+
+use glam::Vec3;
+
+use crate::{CacheKey, VertexInstance, WgpuBuffer};
+
+/// Orders distances farthest first.
+pub fn back_to_front(a: f32, b: f32) -> Ordering {
+    b.total_cmp(&a)
+}
+
+/// Consecutive instances of one model in the sorted order, drawn with one call.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransparentRun {
+    pub model_key: CacheKey,
+    pub instances: Range<u32>,
+    /// Eye distance of the run's farthest instance.
+    pub distance: f32,
+}
+
+/// This frame's instances of models with a transparent material, in one instance buffer
+/// sorted back to front.
+#[derive(Debug, Default)]
+pub struct TransparentInstances {
+    queued: Vec<(f32, CacheKey, VertexInstance)>,
+    sorted: Vec<VertexInstance>,
+    runs: Vec<TransparentRun>,
+    buffer: Option<WgpuBuffer>,
+}
+
+impl TransparentInstances {
+    pub fn clear(&mut self) {
+        self.queued.clear();
+        self.sorted.clear();
+        self.runs.clear();
+    }
+    pub fn push(&mut self, model_key: CacheKey, instance: VertexInstance, eye: Vec3) {
+        let origin = Vec3::from_slice(&instance.model[3][..3]);
+        self.queued
+            .push((eye.distance(origin), model_key, instance));
+    }
+    pub fn len(&self) -> usize {
+        self.sorted.len().max(self.queued.len())
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// Sorts what was pushed and groups it into runs. Equal distances keep push order.
+    pub fn sort(&mut self) {
+        self.queued.sort_by(|a, b| back_to_front(a.0, b.0));
+        self.sorted.clear();
+        self.runs.clear();
+        for (i, (distance, model_key, instance)) in self.queued.drain(..).enumerate() {
+            let i = i as u32;
+            self.sorted.push(instance);
+            match self.runs.last_mut() {
+                Some(run) if run.model_key == model_key => run.instances.end = i + 1,
+                _ => self.runs.push(TransparentRun {
+                    model_key,
+                    instances: i..i + 1,
+                    distance,
+                }),
+            }
+        }
+    }
+    pub fn runs(&self) -> &[TransparentRun] {
+        &self.runs
+    }
+    pub fn buffer(&self) -> Option<&WgpuBuffer> {
+        self.buffer.as_ref()
+    }
+    pub fn upload(&mut self, queue: &wgpu::Queue, device: &wgpu::Device) {
+        if self.sorted.is_empty() {
+            return;
+        }
+        let bytes = VertexInstance::bytes(&self.sorted);
+        match &mut self.buffer {
+            Some(buffer) => {
+                buffer.write_data(queue, device, &bytes, None);
+            }
+            None => {
+                self.buffer = Some(WgpuBuffer::from_data(
+                    device,
+                    &bytes,
+                    wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                    Some("transparent instance buffer"),
+                ))
+            }
+        }
+    }
+}
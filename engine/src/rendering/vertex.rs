@@ -6,6 +6,7 @@ pub struct Vertex {
     pub tex_coords: [f32; 2], // @location(2)
     pub normal: [f32; 3],     // @location(3)
     pub tangent: [f32; 3],    // @location(4)
+    pub surface: [u32; 2],    // @location(15)
 }
 impl Vertex {
     pub const LAYOUT: wgpu::VertexBufferLayout<'static> = wgpu::VertexBufferLayout {
@@ -37,8 +38,30 @@ impl Vertex {
                 shader_location: 4,
                 format: wgpu::VertexFormat::Float32x3,
             },
+            // after the instance attributes (5–14); the last slot under downlevel limits
+            wgpu::VertexAttribute {
+                offset: 56,
+                shader_location: 15,
+                format: wgpu::VertexFormat::Uint32x2,
+            },
         ],
     };
+
+    /// Packs terrain blend data and block light emission into [`Vertex::surface`]: `x` holds
+    /// three texture array layers in its low 24 bits and the emission level in the top 8,
+    /// `y` three 10-bit unorm weights.
+    pub fn pack_surface(layers: [u8; 3], weights: [f32; 3], emission: u8) -> [u32; 2] {
+        let x = layers[0] as u32
+            | (layers[1] as u32) << 8
+            | (layers[2] as u32) << 16
+            | (emission as u32) << 24;
+        let unorm = |w: f32| (w.clamp(0.0, 1.0) * 1023.0).round() as u32;
+        let y = unorm(weights[0]) | unorm(weights[1]) << 10 | unorm(weights[2]) << 20;
+        [x, y]
+    }
+    pub fn emission(&self) -> u8 {
+        (self.surface[0] >> 24) as u8
+    }
 }
 pub trait VertexLayout: bytemuck::Pod + bytemuck::Zeroable {
     const LAYOUT: wgpu::VertexBufferLayout<'static>;
@@ -48,12 +71,91 @@ impl VertexLayout for Vertex {
     const LAYOUT: wgpu::VertexBufferLayout<'static> = Vertex::LAYOUT;
 }
 
+/// A [`Vertex`] with up to four joint influences. The skinned path draws without an
+/// instance stream, so joints and weights take locations 5 and 6.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable, Default)]
+pub struct VertexSkinned {
+    pub position: [f32; 3],   // @location(0)
+    pub color: [f32; 3],      // @location(1)
+    pub tex_coords: [f32; 2], // @location(2)
+    pub normal: [f32; 3],     // @location(3)
+    pub tangent: [f32; 3],    // @location(4)
+    pub surface: [u32; 2],    // @location(15)
+    pub joints: [u32; 4],     // @location(5)
+    pub weights: [f32; 4],    // @location(6)
+}
+impl VertexSkinned {
+    pub const LAYOUT: wgpu::VertexBufferLayout<'static> = wgpu::VertexBufferLayout {
+        array_stride: std::mem::size_of::<VertexSkinned>() as wgpu::BufferAddress,
+        step_mode: wgpu::VertexStepMode::Vertex,
+        attributes: &[
+            wgpu::VertexAttribute {
+                offset: 0,
+                shader_location: 0,
+                format: wgpu::VertexFormat::Float32x3,
+            },
+            wgpu::VertexAttribute {
+                offset: 12,
+                shader_location: 1,
+                format: wgpu::VertexFormat::Float32x3,
+            },
+            wgpu::VertexAttribute {
+                offset: 24,
+                shader_location: 2,
+                format: wgpu::VertexFormat::Float32x2,
+            },
+            wgpu::VertexAttribute {
+                offset: 32,
+                shader_location: 3,
+                format: wgpu::VertexFormat::Float32x3,
+            },
+            wgpu::VertexAttribute {
+                offset: 44,
+                shader_location: 4,
+                format: wgpu::VertexFormat::Float32x3,
+            },
+            wgpu::VertexAttribute {
+                offset: 56,
+                shader_location: 15,
+                format: wgpu::VertexFormat::Uint32x2,
+            },
+            wgpu::VertexAttribute {
+                offset: 64,
+                shader_location: 5,
+                format: wgpu::VertexFormat::Uint32x4,
+            },
+            wgpu::VertexAttribute {
+                offset: 80,
+                shader_location: 6,
+                format: wgpu::VertexFormat::Float32x4,
+            },
+        ],
+    };
+
+    pub fn new(vertex: Vertex, joints: [u32; 4], weights: [f32; 4]) -> Self {
+        Self {
+            position: vertex.position,
+            color: vertex.color,
+            tex_coords: vertex.tex_coords,
+            normal: vertex.normal,
+            tangent: vertex.tangent,
+            surface: vertex.surface,
+            joints,
+            weights,
+        }
+    }
+}
+
+impl VertexLayout for VertexSkinned {
+    const LAYOUT: wgpu::VertexBufferLayout<'static> = VertexSkinned::LAYOUT;
+}
+
 #[repr(C)]
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable, Default)]
 pub struct VertexInstance {
     pub model: [[f32; 4]; 4],  // 0–63   | @location(5..8)
-    pub color: [f32; 3],       // 64–75  | @location(9)
-    pub _pad0: f32,            // 76–79
+    pub color: [f32; 4],       // 64–79  | @location(9)
     pub translation: [f32; 3], // 80–91  | @location(10)
     pub _pad1: f32,            // 92–95
     pub uv_offset: [f32; 2],   // 96–103 | @location(11)
@@ -62,10 +164,17 @@ pub struct VertexInstance {
     pub _pad3: f32,            // 124–127
     pub tangent: [f32; 3],     // 128–139| @location(13)
     pub _pad4: f32,            // 140–143
-    pub material_id: u32,      // 144–147| @location(14)
-    pub _pad5: [f32; 3],       // 148–159
+    pub material_id: u32,      // 144–147| @location(14).x
+    pub flags: u32,            // 148–151| @location(14).y
+    pub entity_id: u32,        // 152–155| entity id pass only
+    pub _pad5: f32,            // 156–159
 }
 impl VertexInstance {
+    /// Flag bit drawing the instance brightened with a rim outline. The same bit as
+    /// [`crate::PerObjectData::SELECTED`], so both paths share the shader's check.
+    pub const SELECTED: u32 = crate::PerObjectData::SELECTED;
+    pub const SIZE: u64 = std::mem::size_of::<VertexInstance>() as u64;
+
     pub const LAYOUT: wgpu::VertexBufferLayout<'static> = wgpu::VertexBufferLayout {
         array_stride: std::mem::size_of::<VertexInstance>() as wgpu::BufferAddress,
         step_mode: wgpu::VertexStepMode::Instance,
@@ -95,7 +204,7 @@ impl VertexInstance {
             wgpu::VertexAttribute {
                 offset: 64,
                 shader_location: 9,
-                format: wgpu::VertexFormat::Float32x3,
+                format: wgpu::VertexFormat::Float32x4,
             },
             // translation → 10
             wgpu::VertexAttribute {
@@ -121,11 +230,11 @@ impl VertexInstance {
                 shader_location: 13,
                 format: wgpu::VertexFormat::Float32x3,
             },
-            // material_id → 14
+            // material_id, flags → 14; shared, since 16 attributes is all wgpu guarantees
             wgpu::VertexAttribute {
                 offset: 144,
                 shader_location: 14,
-                format: wgpu::VertexFormat::Uint32,
+                format: wgpu::VertexFormat::Uint32x2,
             },
         ],
     };
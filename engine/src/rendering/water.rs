@@ -0,0 +1,416 @@
+//! Water surfaces, drawn in a pass of their own after the scene pass. The pass copies the
+//! scene color into the [`RenderTargetKind::Refraction`] target, then draws every water
+//! surface over the scene, sampling that copy through a scrolling normal map, fading it
+//! into the water color with the depth of water behind, and blending toward the
+//! environment reflection at grazing angles. With the camera under water, a fog tint over
+//! the whole view follows the surfaces.
+//!
+//! The scene pass keeps its multisampled color for this pass to load, and the scene depth
+//! is only tested here, so the same depth can be sampled. Text goes on top afterwards, in
+//! a pass that can write depth again.
+
+use std::f32::consts::TAU;
+use std::sync::Arc;
+
+use bytemuck::{Pod, Zeroable};
+
+use crate::{
+    log_warning, BindGroup, CacheKey, CacheStorage, DepthVariant, EngineError, FrameBuffer,
+    ModelManager, RenderBindGroupLayouts, RenderSettings, RenderTargetKind, RenderTargetManager,
+    Texture, Vertex, VertexInstance, WgpuBuffer, World, WATER_COLOR,
+};
+
+/// Side of the generated normal map, in texels.
+pub const WATER_NORMAL_MAP_SIZE: u32 = 64;
+
+/// Waves of the normal map's height field: cycles across the tile along u and v, then
+/// amplitude and phase. Whole cycles keep the tile seamless.
+const WAVES: [(f32, f32, f32, f32); 4] = [
+    (1.0, 2.0, 0.5, 0.0),
+    (3.0, -1.0, 0.25, 1.3),
+    (-2.0, 5.0, 0.12, 2.1),
+    (6.0, 3.0, 0.06, 0.4),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WaterSettings {
+    /// World units one repeat of the normal map covers.
+    pub wave_scale: f32,
+    /// Repeats of the normal map scrolled by per second.
+    pub wave_speed: f32,
+    /// How far the waves push the refracted scene, as a fraction of the screen.
+    pub refraction: f32,
+    /// How fast light fades into the water color, per unit of water it crosses.
+    pub fog_density: f32,
+}
+
+impl Default for WaterSettings {
+    fn default() -> Self {
+        Self {
+            wave_scale: 4.0,
+            wave_speed: 0.03,
+            refraction: 0.02,
+            fog_density: 0.15,
+        }
+    }
+}
+
+/// Group 2 uniform of `water.wgsl`.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+pub struct WaterUniform {
+    pub color: [f32; 3],
+    /// Seconds, from [`crate::Time::elapsed`].
+    pub time: f32,
+    pub wave_scale: f32,
+    pub wave_speed: f32,
+    pub refraction: f32,
+    pub fog_density: f32,
+}
+
+impl WaterUniform {
+    pub fn new(settings: &WaterSettings, time: f32) -> Self {
+        Self {
+            color: WATER_COLOR,
+            time,
+            wave_scale: settings.wave_scale,
+            wave_speed: settings.wave_speed,
+            refraction: settings.refraction,
+            fog_density: settings.fog_density,
+        }
+    }
+}
+
+/// `scene_depth` and the `scene_depth_at` it is read through, appended to `water.wgsl` for
+/// a single-sampled scene.
+const SINGLE_SAMPLED_DEPTH: &str = "
+@group(2) @binding(3) var scene_depth: texture_depth_2d;
+fn scene_depth_at(pixel: vec2<i32>) -> f32 { return textureLoad(scene_depth, pixel, 0); }
+";
+/// [`SINGLE_SAMPLED_DEPTH`] for a multisampled scene, reading the first sample.
+const MULTISAMPLED_DEPTH: &str = "
+@group(2) @binding(3) var scene_depth: texture_depth_multisampled_2d;
+fn scene_depth_at(pixel: vec2<i32>) -> f32 { return textureLoad(scene_depth, pixel, 0); }
+";
+
+#[derive(Debug)]
+pub struct WaterPass {
+    pub settings: WaterSettings,
+    format: wgpu::TextureFormat,
+    uniform: WgpuBuffer,
+    /// Tiling normals of [`WAVES`], made on the first update.
+    normal_map: Option<Texture>,
+    surface_pipeline: Option<Arc<wgpu::RenderPipeline>>,
+    underwater_pipeline: Option<Arc<wgpu::RenderPipeline>>,
+    /// Sample count the pipelines were built for.
+    sample_count: u32,
+    active: bool,
+}
+
+impl WaterPass {
+    pub const SHADER: &'static str = "water.wgsl";
+
+    /// `format` is the color target the water is drawn into.
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        let settings = WaterSettings::default();
+        let uniform = WgpuBuffer::from_data(
+            device,
+            &[WaterUniform::new(&settings, 0.0)],
+            wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            Some("water uniform"),
+        );
+        Self {
+            settings,
+            format,
+            uniform,
+            normal_map: None,
+            surface_pipeline: None,
+            underwater_pipeline: None,
+            sample_count: 0,
+            active: false,
+        }
+    }
+    /// Whether the next frames draw water in this pass rather than with the transparent
+    /// scene geometry: there is water to draw and the pipelines are built.
+    pub fn active(&self) -> bool {
+        self.active
+    }
+    /// Drops the pipelines so they're rebuilt on next use, e.g. after a depth policy,
+    /// sample count or shader change.
+    pub fn clear_pipelines(&mut self) {
+        self.surface_pipeline = None;
+        self.underwater_pipeline = None;
+    }
+
+    /// Builds what the pass needs when `world` has water and writes this frame's uniform,
+    /// animated by `elapsed` seconds.
+    pub fn update(&mut self, world: &World, model_manager: &mut ModelManager, elapsed: f32) {
+        self.active = world.terrain.water_surfaces().next().is_some();
+        if !self.active {
+            return;
+        }
+        if self.normal_map.is_none() {
+            self.normal_map = Some(normal_map(&model_manager.device, &model_manager.queue));
+        }
+        if self.surface_pipeline.is_none() || self.underwater_pipeline.is_none() {
+            if let Err(e) = self.create_pipelines(model_manager) {
+                log_warning!("water pipelines: {}", e);
+                self.active = false;
+                return;
+            }
+        }
+        let uniform = WaterUniform::new(&self.settings, elapsed);
+        self.uniform.write_data(
+            &model_manager.queue,
+            &model_manager.device,
+            bytemuck::bytes_of(&uniform),
+            None,
+        );
+    }
+    /// Adds the refraction target to `targets` when the pass is active and it's missing
+    /// or no longer matches the scene color; [`RenderTargetManager::resize`] keeps it
+    /// sized after that.
+    pub fn prepare_target(&self, device: &wgpu::Device, targets: &mut RenderTargetManager) {
+        if !self.active {
+            return;
+        }
+        let Some(scene) = targets.get(&RenderTargetKind::Scene) else {
+            return;
+        };
+        let scene = &scene.color().texture;
+        let current = targets
+            .get(&RenderTargetKind::Refraction)
+            .is_some_and(|fb| {
+                fb.color().texture.size() == scene.size()
+                    && fb.color().texture.format() == scene.format()
+            });
+        if current {
+            return;
+        }
+        let size = (scene.width(), scene.height()).into();
+        let refraction = FrameBuffer::new_color_only(device, size, scene.format(), "refraction");
+        targets.insert(refraction, RenderTargetKind::Refraction);
+    }
+
+    fn create_pipelines(&mut self, model_manager: &mut ModelManager) -> Result<(), EngineError> {
+        let device = &model_manager.device;
+        let materials = &mut model_manager.materials;
+        let format = self.format;
+        let sample_count = RenderSettings::sample_count();
+        let multisampled = sample_count > 1;
+        let (variant, depth) = if multisampled {
+            ("msaa", MULTISAMPLED_DEPTH)
+        } else {
+            ("single", SINGLE_SAMPLED_DEPTH)
+        };
+        let shader = materials
+            .shaders
+            .load_variant(device, Self::SHADER, variant, "scene_depth_at", depth)?
+            .ok_or_else(|| {
+                EngineError::AssetLoadError(format!("{} reads no scene depth", Self::SHADER))
+            })?;
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("water layout"),
+            bind_group_layouts: &[
+                RenderBindGroupLayouts::uniform(),
+                RenderBindGroupLayouts::equirect_dst(),
+                RenderBindGroupLayouts::water(multisampled),
+            ],
+            push_constant_ranges: &[],
+        });
+        let policy = RenderSettings::depth_policy();
+        let mut pipeline = |name: &str,
+                            vertex: &str,
+                            fragment: &str,
+                            buffers: &[wgpu::VertexBufferLayout],
+                            blend: Option<wgpu::BlendState>,
+                            depth: wgpu::DepthStencilState| {
+            let label = format!("water {} {}x {:?}", name, sample_count, policy);
+            let key = CacheKey::from(label.clone());
+            let pipeline = materials
+                .pipelines
+                .render
+                .get_or_create(key, || {
+                    Arc::new(
+                        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                            label: Some(&label),
+                            layout: Some(&layout),
+                            vertex: wgpu::VertexState {
+                                module: &shader,
+                                entry_point: Some(vertex),
+                                buffers,
+                                compilation_options: Default::default(),
+                            },
+                            fragment: Some(wgpu::FragmentState {
+                                module: &shader,
+                                entry_point: Some(fragment),
+                                targets: &[Some(wgpu::ColorTargetState {
+                                    format,
+                                    blend,
+                                    write_mask: wgpu::ColorWrites::ALL,
+                                })],
+                                compilation_options: Default::default(),
+                            }),
+                            // Surfaces are seen from below too.
+                            primitive: wgpu::PrimitiveState {
+                                cull_mode: None,
+                                ..Default::default()
+                            },
+                            depth_stencil: Some(depth),
+                            multisample: RenderSettings::multisample(),
+                            multiview: None,
+                            cache: None,
+                        }),
+                    )
+                })
+                .clone();
+            materials.pipelines.track(Self::SHADER, key);
+            pipeline
+        };
+        let surface = pipeline(
+            "surface",
+            "vs_surface",
+            "fs_surface",
+            &[Vertex::LAYOUT, VertexInstance::LAYOUT],
+            None,
+            RenderSettings::depth_state(DepthVariant::Transparent),
+        );
+        // The fog covers everything, whatever its depth.
+        let underwater = pipeline(
+            "underwater",
+            "vs_fullscreen",
+            "fs_underwater",
+            &[],
+            Some(wgpu::BlendState::ALPHA_BLENDING),
+            wgpu::DepthStencilState {
+                depth_compare: wgpu::CompareFunction::Always,
+                ..RenderSettings::depth_state(DepthVariant::Transparent)
+            },
+        );
+        self.surface_pipeline = Some(surface);
+        self.underwater_pipeline = Some(underwater);
+        self.sample_count = sample_count;
+        Ok(())
+    }
+
+    /// Copies the scene color aside and draws the water surfaces over the scene, then the
+    /// fog when `underwater`.
+    pub fn record(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        targets: &RenderTargetManager,
+        world: &World,
+        uniform_bind_group: &wgpu::BindGroup,
+        underwater: bool,
+    ) -> Result<(), EngineError> {
+        let (Some(surface), Some(fog), Some(normal_map)) = (
+            &self.surface_pipeline,
+            &self.underwater_pipeline,
+            &self.normal_map,
+        ) else {
+            return Err(EngineError::GpuError("water pipelines aren't built".into()));
+        };
+        let Some(projection) = world.projection() else {
+            return Ok(());
+        };
+        let scene_fb = targets.require(&RenderTargetKind::Scene)?;
+        let refraction = targets.require(&RenderTargetKind::Refraction)?;
+        let depth = scene_fb
+            .depth()
+            .as_ref()
+            .ok_or_else(|| EngineError::GpuError("scene target has no depth".into()))?;
+        if scene_fb.sample_count() != self.sample_count {
+            return Err(EngineError::GpuError(format!(
+                "water pipelines are built for {} samples, the scene has {}",
+                self.sample_count,
+                scene_fb.sample_count()
+            )));
+        }
+        encoder.copy_texture_to_texture(
+            scene_fb.color().texture.as_image_copy(),
+            refraction.color().texture.as_image_copy(),
+            scene_fb.color().texture.size(),
+        );
+        let bind_group =
+            BindGroup::water(device, &self.uniform, refraction.color(), depth, normal_map);
+
+        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Water Pass"),
+            color_attachments: &[Some(scene_fb.color_attachment_loaded())],
+            depth_stencil_attachment: scene_fb.depth_attachment_read_only(),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        rpass.set_bind_group(0, uniform_bind_group, &[]);
+        rpass.set_bind_group(1, &projection.reflection.bind_group, &[]);
+        rpass.set_bind_group(2, &bind_group, &[]);
+        rpass.set_pipeline(surface);
+        for (instance, buffer, instances, _) in world.terrain.water_surfaces() {
+            crate::gpu_scope!(
+                Draw,
+                "water",
+                format!("{} indices", instance.mesh.index_count)
+            );
+            let mesh = &instance.mesh;
+            rpass.set_vertex_buffer(0, mesh.vertex_buffer.get().slice(..));
+            rpass.set_vertex_buffer(1, buffer.buffer.slice());
+            rpass.set_index_buffer(mesh.index_buffer.get().slice(..), mesh.index_format);
+            rpass.draw_indexed(0..mesh.index_count, 0, instances);
+        }
+        if underwater {
+            rpass.set_pipeline(fog);
+            rpass.draw(0..3, 0..1);
+        }
+        Ok(())
+    }
+}
+
+/// Tangent-space normals of the [`WAVES`] height field, tiling on both axes.
+fn normal_map(device: &wgpu::Device, queue: &wgpu::Queue) -> Texture {
+    let size = WATER_NORMAL_MAP_SIZE;
+    let mut texels = Vec::with_capacity((size * size * 4) as usize);
+    for y in 0..size {
+        for x in 0..size {
+            let (u, v) = (x as f32 / size as f32, y as f32 / size as f32);
+            let (mut du, mut dv) = (0.0, 0.0);
+            for (cycles_u, cycles_v, amplitude, phase) in WAVES {
+                let slope = amplitude * TAU * (TAU * (cycles_u * u + cycles_v * v) + phase).cos();
+                du += slope * cycles_u;
+                dv += slope * cycles_v;
+            }
+            // Heights are in tile widths; the waves are a lot flatter than that.
+            let normal = glam::Vec3::new(-du * 0.05, -dv * 0.05, 1.0).normalize();
+            let encode = |c: f32| ((c * 0.5 + 0.5) * 255.0).round() as u8;
+            texels.extend_from_slice(&[encode(normal.x), encode(normal.y), encode(normal.z), 255]);
+        }
+    }
+    let extent = wgpu::Extent3d {
+        width: size,
+        height: size,
+        depth_or_array_layers: 1,
+    };
+    let texture = Texture::new(
+        device,
+        extent,
+        wgpu::TextureFormat::Rgba8Unorm,
+        1,
+        wgpu::TextureViewDimension::D2,
+        wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        Some(wgpu::AddressMode::Repeat),
+        wgpu::FilterMode::Linear,
+        None,
+        Some("water normal map"),
+    );
+    queue.write_texture(
+        texture.texture.as_image_copy(),
+        &texels,
+        wgpu::TexelCopyBufferLayout {
+            offset: 0,
+            bytes_per_row: Some(size * 4),
+            rows_per_image: Some(size),
+        },
+        extent,
+    );
+    texture
+}
@@ -42,26 +42,89 @@ pub struct RenderBindGroupLayouts {
     pub camera: wgpu::BindGroupLayout,
     pub equirect_src: wgpu::BindGroupLayout,
     pub equirect_dst: wgpu::BindGroupLayout,
+    pub environment_prefilter: wgpu::BindGroupLayout,
+    pub environment_ambient: wgpu::BindGroupLayout,
     pub uniform: wgpu::BindGroupLayout,
     pub normal: wgpu::BindGroupLayout,
     pub material_storage: wgpu::BindGroupLayout,
+    pub object_storage: wgpu::BindGroupLayout,
+    pub object_dynamic: wgpu::BindGroupLayout,
+    pub joints: wgpu::BindGroupLayout,
+    pub instance_storage: wgpu::BindGroupLayout,
     pub debug: wgpu::BindGroupLayout,
+    pub terrain_layers: wgpu::BindGroupLayout,
+    pub tonemap: wgpu::BindGroupLayout,
+    pub exposure_histogram: wgpu::BindGroupLayout,
+    pub exposure_average: wgpu::BindGroupLayout,
+    pub particle_simulate: wgpu::BindGroupLayout,
+    pub particle_draw: wgpu::BindGroupLayout,
+    pub bloom: wgpu::BindGroupLayout,
+    pub debug_geometry: wgpu::BindGroupLayout,
+    pub water: wgpu::BindGroupLayout,
+    pub water_msaa: wgpu::BindGroupLayout,
 }
 
+/// The layouts for the current device. Replaced by [`RenderBindGroupLayouts::rebuild`]
+/// after a device loss; the old set is leaked, since `'static` references to it are out.
+static LAYOUTS: std::sync::RwLock<Option<&'static RenderBindGroupLayouts>> =
+    std::sync::RwLock::new(None);
+
 impl RenderBindGroupLayouts {
-    /// Initialize and return the singleton.
+    fn current() -> Option<&'static Self> {
+        *LAYOUTS
+            .read()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+    /// Creates the singleton on `device`, for use without the global [`crate::GPU`]. Once
+    /// created, the existing layouts are returned and `device` is ignored.
+    pub fn init(device: std::sync::Arc<wgpu::Device>) -> &'static Self {
+        let mut slot = LAYOUTS
+            .write()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        slot.get_or_insert_with(|| Box::leak(Box::new(RenderBindGroupLayouts::new(device))))
+    }
+    /// The singleton, created on the global [`crate::GPU`]'s device on first use. Fails
+    /// while neither it nor [`RenderBindGroupLayouts::init`] has provided a device.
+    pub fn try_get() -> Result<&'static Self, crate::EngineError> {
+        if let Some(layouts) = Self::current() {
+            return Ok(layouts);
+        }
+        let device = crate::GPU::with_read_recovered(|gpu| gpu.device().clone())?;
+        Ok(Self::init(device))
+    }
+    /// Replaces the singleton with layouts on `device`, after the one they were created on
+    /// was lost. Pipelines and bind groups built from the old layouts must be rebuilt.
+    pub fn rebuild(device: std::sync::Arc<wgpu::Device>) -> &'static Self {
+        let layouts: &'static Self = Box::leak(Box::new(RenderBindGroupLayouts::new(device)));
+        *LAYOUTS
+            .write()
+            .unwrap_or_else(std::sync::PoisonError::into_inner) = Some(layouts);
+        layouts
+    }
+    /// [`RenderBindGroupLayouts::try_get`] for code that only runs once the GPU is up.
     pub fn get() -> &'static Self {
-        static LAYOUTS: once_cell::sync::OnceCell<RenderBindGroupLayouts> =
-            once_cell::sync::OnceCell::new();
-        LAYOUTS.get_or_init(|| {
-            let binding = crate::GPU::get();
-            let gpu = binding.read().expect("GPU resources not initialized");
-            RenderBindGroupLayouts::new(gpu.device().clone())
-        })
+        Self::try_get().unwrap_or_else(|e| panic!("{}", e))
     }
     pub fn material_storage() -> &'static wgpu::BindGroupLayout {
         &Self::get().material_storage
     }
+    /// Material storage plus per-object data, replacing group 2 on the object path.
+    pub fn object_data(indexing: crate::ObjectIndexing) -> &'static wgpu::BindGroupLayout {
+        match indexing {
+            crate::ObjectIndexing::PushConstants => &Self::get().object_storage,
+            crate::ObjectIndexing::DynamicOffset => &Self::get().object_dynamic,
+        }
+    }
+    /// Material storage plus one entity's [`crate::SkinHeader`] and joint matrices,
+    /// replacing group 2 on the skinned path.
+    pub fn joints() -> &'static wgpu::BindGroupLayout {
+        &Self::get().joints
+    }
+    /// Material storage plus the frame's [`crate::VertexInstance`]s, replacing group 2
+    /// where instances are fetched from storage, see [`crate::InstanceFetch`].
+    pub fn instance_storage() -> &'static wgpu::BindGroupLayout {
+        &Self::get().instance_storage
+    }
     pub fn texture() -> &'static wgpu::BindGroupLayout {
         &Self::get().diffuse
     }
@@ -77,6 +140,12 @@ impl RenderBindGroupLayouts {
     pub fn equirect_dst() -> &'static wgpu::BindGroupLayout {
         &Self::get().equirect_dst
     }
+    pub fn environment_prefilter() -> &'static wgpu::BindGroupLayout {
+        &Self::get().environment_prefilter
+    }
+    pub fn environment_ambient() -> &'static wgpu::BindGroupLayout {
+        &Self::get().environment_ambient
+    }
     pub fn uniform() -> &'static wgpu::BindGroupLayout {
         &Self::get().uniform
     }
@@ -86,6 +155,39 @@ impl RenderBindGroupLayouts {
     pub fn debug() -> &'static wgpu::BindGroupLayout {
         &Self::get().debug
     }
+    pub fn terrain_layers() -> &'static wgpu::BindGroupLayout {
+        &Self::get().terrain_layers
+    }
+    pub fn tonemap() -> &'static wgpu::BindGroupLayout {
+        &Self::get().tonemap
+    }
+    pub fn exposure_histogram() -> &'static wgpu::BindGroupLayout {
+        &Self::get().exposure_histogram
+    }
+    pub fn exposure_average() -> &'static wgpu::BindGroupLayout {
+        &Self::get().exposure_average
+    }
+    pub fn particle_simulate() -> &'static wgpu::BindGroupLayout {
+        &Self::get().particle_simulate
+    }
+    pub fn particle_draw() -> &'static wgpu::BindGroupLayout {
+        &Self::get().particle_draw
+    }
+    pub fn bloom() -> &'static wgpu::BindGroupLayout {
+        &Self::get().bloom
+    }
+    pub fn debug_geometry() -> &'static wgpu::BindGroupLayout {
+        &Self::get().debug_geometry
+    }
+    /// Group 2 of `water.wgsl`, for a scene depth with more than one sample when
+    /// `multisampled`.
+    pub fn water(multisampled: bool) -> &'static wgpu::BindGroupLayout {
+        if multisampled {
+            &Self::get().water_msaa
+        } else {
+            &Self::get().water
+        }
+    }
 
     fn new(device: std::sync::Arc<wgpu::Device>) -> Self {
         // Diffuse textures (2D)
@@ -155,6 +257,73 @@ impl RenderBindGroupLayouts {
         ];
         let equirect_dst = create_layout(&device, Some("equirect dst layout"), equirect_dst_defs);
 
+        // Environment prefilter: source cubemap + sampler in, one reflection mip out
+        let environment_prefilter_defs = &[
+            BindingDef {
+                binding: 0,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    view_dimension: wgpu::TextureViewDimension::Cube,
+                    multisampled: false,
+                },
+            },
+            BindingDef {
+                binding: 1,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::NonFiltering),
+            },
+            BindingDef {
+                binding: 2,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: crate::Texture::PROJECTION[1].binding,
+            },
+            BindingDef {
+                binding: 3,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: std::num::NonZeroU64::new(std::mem::size_of::<
+                        crate::PrefilterParams,
+                    >() as u64),
+                },
+            },
+        ];
+        let environment_prefilter = create_layout(
+            &device,
+            Some("environment prefilter layout"),
+            environment_prefilter_defs,
+        );
+        // Environment average: one reflection mip in, the average color out
+        let environment_ambient_defs = &[
+            BindingDef {
+                binding: 0,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    view_dimension: wgpu::TextureViewDimension::D2Array,
+                    multisampled: false,
+                },
+            },
+            BindingDef {
+                binding: 1,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: false },
+                    has_dynamic_offset: false,
+                    min_binding_size: std::num::NonZeroU64::new(
+                        std::mem::size_of::<[f32; 4]>() as u64
+                    ),
+                },
+            },
+        ];
+        let environment_ambient = create_layout(
+            &device,
+            Some("environment ambient layout"),
+            environment_ambient_defs,
+        );
+
         // Combined uniform (camera + light)
         let uniform_defs = &[
             BindingDef {
@@ -224,6 +393,78 @@ impl RenderBindGroupLayouts {
             material_storage_defs,
         );
 
+        // Materials at binding 0 as in `material_storage`, per-object data at 1
+        let object_data_defs = |ty: wgpu::BufferBindingType, has_dynamic_offset: bool| {
+            [
+                BindingDef {
+                    binding: 0,
+                    visibility: material_storage_defs[0].visibility,
+                    ty: material_storage_defs[0].ty,
+                },
+                BindingDef {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty,
+                        has_dynamic_offset,
+                        min_binding_size: std::num::NonZeroU64::new(crate::PerObjectData::SIZE),
+                    },
+                },
+            ]
+        };
+        let object_storage = create_layout(
+            &device,
+            Some("object storage bind group layout"),
+            &object_data_defs(wgpu::BufferBindingType::Storage { read_only: true }, false),
+        );
+        let object_dynamic = create_layout(
+            &device,
+            Some("object dynamic bind group layout"),
+            &object_data_defs(wgpu::BufferBindingType::Uniform, true),
+        );
+        // Materials at binding 0 again, the skin header and joint matrices at 1
+        let joints_defs = &[
+            BindingDef {
+                binding: 0,
+                visibility: material_storage_defs[0].visibility,
+                ty: material_storage_defs[0].ty,
+            },
+            BindingDef {
+                binding: 1,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: std::num::NonZeroU64::new(
+                        crate::SkinHeader::MIN_BINDING_SIZE,
+                    ),
+                },
+            },
+        ];
+        let joints = create_layout(&device, Some("joints bind group layout"), joints_defs);
+        // Materials at binding 0 again, the frame's instances at 1
+        let instance_storage_defs = &[
+            BindingDef {
+                binding: 0,
+                visibility: material_storage_defs[0].visibility,
+                ty: material_storage_defs[0].ty,
+            },
+            BindingDef {
+                binding: 1,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: std::num::NonZeroU64::new(crate::VertexInstance::SIZE),
+                },
+            },
+        ];
+        let instance_storage = create_layout(
+            &device,
+            Some("instance storage bind group layout"),
+            instance_storage_defs,
+        );
+
         // Combined uniform (camera + light)
         let debug_defs = &[
             BindingDef {
@@ -263,6 +504,290 @@ impl RenderBindGroupLayouts {
         ];
         let debug = create_layout(&device, Some("debug bind grop layout"), debug_defs);
 
+        // Terrain texture arrays (diffuse + normal) and per-layer priorities
+        let terrain_array = || wgpu::BindingType::Texture {
+            multisampled: false,
+            view_dimension: wgpu::TextureViewDimension::D2Array,
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+        };
+        let terrain_layers_defs = &[
+            BindingDef {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: terrain_array(),
+            },
+            BindingDef {
+                binding: 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+            },
+            BindingDef {
+                binding: 2,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: terrain_array(),
+            },
+            BindingDef {
+                binding: 3,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+            },
+            BindingDef {
+                binding: 4,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+            },
+        ];
+        let terrain_layers = create_layout(
+            &device,
+            Some("terrain layers bind group layout"),
+            terrain_layers_defs,
+        );
+
+        // Tonemap uniform of the HDR pass (exposure)
+        let tonemap_defs = &[BindingDef {
+            binding: 0,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: std::num::NonZeroU64::new(
+                    std::mem::size_of::<crate::TonemapUniform>() as u64,
+                ),
+            },
+        }];
+        let tonemap = create_layout(&device, Some("tonemap bind group layout"), tonemap_defs);
+
+        // Auto-exposure: scene texture + histogram + params, then histogram + tonemap + params
+        let storage = || wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage { read_only: false },
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        };
+        let exposure_params = || wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: std::num::NonZeroU64::new(
+                std::mem::size_of::<crate::ExposureParams>() as u64,
+            ),
+        };
+        let exposure_histogram_defs = &[
+            BindingDef {
+                binding: 0,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Texture {
+                    multisampled: false,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                },
+            },
+            BindingDef {
+                binding: 1,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: storage(),
+            },
+            BindingDef {
+                binding: 2,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: exposure_params(),
+            },
+        ];
+        let exposure_histogram = create_layout(
+            &device,
+            Some("exposure histogram bind group layout"),
+            exposure_histogram_defs,
+        );
+        let exposure_average_defs = &[
+            BindingDef {
+                binding: 0,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: storage(),
+            },
+            BindingDef {
+                binding: 1,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: storage(),
+            },
+            BindingDef {
+                binding: 2,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: exposure_params(),
+            },
+        ];
+        let exposure_average = create_layout(
+            &device,
+            Some("exposure average bind group layout"),
+            exposure_average_defs,
+        );
+
+        // Particles: particles + spawn counters + simulation, then read-only for drawing
+        let particle_simulation = || wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: std::num::NonZeroU64::new(
+                std::mem::size_of::<crate::ParticleSimulation>() as u64,
+            ),
+        };
+        let particle_simulate_defs = &[
+            BindingDef {
+                binding: 0,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: storage(),
+            },
+            BindingDef {
+                binding: 1,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: storage(),
+            },
+            BindingDef {
+                binding: 2,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: particle_simulation(),
+            },
+        ];
+        let particle_simulate = create_layout(
+            &device,
+            Some("particle simulate bind group layout"),
+            particle_simulate_defs,
+        );
+        let particle_draw_defs = &[
+            BindingDef {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+            },
+            BindingDef {
+                binding: 1,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: particle_simulation(),
+            },
+        ];
+        let particle_draw = create_layout(
+            &device,
+            Some("particle draw bind group layout"),
+            particle_draw_defs,
+        );
+
+        // Bloom parameters, shared by every bloom step
+        let bloom_defs = &[BindingDef {
+            binding: 0,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: std::num::NonZeroU64::new(
+                    std::mem::size_of::<crate::BloomUniform>() as u64,
+                ),
+            },
+        }];
+        let bloom = create_layout(&device, Some("bloom bind group layout"), bloom_defs);
+
+        // Debug geometry: params, mesh vertices and indices in, derived vertices out
+        let read_only_storage = || wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage { read_only: true },
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        };
+        let debug_geometry_defs = &[
+            BindingDef {
+                binding: 0,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: std::num::NonZeroU64::new(std::mem::size_of::<
+                        crate::DebugGeometryParams,
+                    >() as u64),
+                },
+            },
+            BindingDef {
+                binding: 1,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: read_only_storage(),
+            },
+            BindingDef {
+                binding: 2,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: read_only_storage(),
+            },
+            BindingDef {
+                binding: 3,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: storage(),
+            },
+        ];
+        let debug_geometry = create_layout(
+            &device,
+            Some("debug geometry bind group layout"),
+            debug_geometry_defs,
+        );
+
+        // Water: uniform, refracted scene color, scene depth, then the scrolling normal map
+        let water_defs = |multisampled: bool| {
+            let texture = || wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            };
+            let sampler = || wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering);
+            [
+                BindingDef {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: std::num::NonZeroU64::new(std::mem::size_of::<
+                            crate::WaterUniform,
+                        >()
+                            as u64),
+                    },
+                },
+                BindingDef {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: texture(),
+                },
+                BindingDef {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: sampler(),
+                },
+                BindingDef {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Depth,
+                    },
+                },
+                BindingDef {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: texture(),
+                },
+                BindingDef {
+                    binding: 5,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: sampler(),
+                },
+            ]
+        };
+        let water = create_layout(&device, Some("water bind group layout"), &water_defs(false));
+        let water_msaa = create_layout(
+            &device,
+            Some("water msaa bind group layout"),
+            &water_defs(true),
+        );
+
         RenderBindGroupLayouts {
             device: device.clone(),
             diffuse,
@@ -270,10 +795,26 @@ impl RenderBindGroupLayouts {
             camera,
             equirect_src,
             equirect_dst,
+            environment_prefilter,
+            environment_ambient,
             uniform,
             normal,
             material_storage,
+            object_storage,
+            object_dynamic,
+            joints,
+            instance_storage,
             debug,
+            terrain_layers,
+            tonemap,
+            exposure_histogram,
+            exposure_average,
+            particle_simulate,
+            particle_draw,
+            bloom,
+            debug_geometry,
+            water,
+            water_msaa,
         }
     }
 }
@@ -297,6 +838,78 @@ impl BindGroup {
             ],
         })
     }
+    /// Source cubemap, mip `mip` of `dst` as storage and the mip's parameters, for one
+    /// prefilter dispatch.
+    pub fn environment_prefilter(
+        device: &wgpu::Device,
+        src: &wgpu::TextureView,
+        sampler: &wgpu::Sampler,
+        dst: &super::Texture,
+        mip: u32,
+        params: &crate::WgpuBuffer,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(&format!("{} prefilter mip {} bind group", dst.label, mip)),
+            layout: RenderBindGroupLayouts::environment_prefilter(),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(src),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&dst.create_view(
+                        &wgpu::TextureViewDescriptor {
+                            label: Some("Prefilter mip view"),
+                            dimension: Some(wgpu::TextureViewDimension::D2Array),
+                            base_mip_level: mip,
+                            mip_level_count: Some(1),
+                            ..Default::default()
+                        },
+                    )),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: params.get().as_entire_binding(),
+                },
+            ],
+        })
+    }
+    /// Mip `mip` of the reflection cubemap `src` and the buffer its average color is
+    /// written to.
+    pub fn environment_ambient(
+        device: &wgpu::Device,
+        src: &super::Texture,
+        mip: u32,
+        average: &crate::WgpuBuffer,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(&format!("{} ambient bind group", src.label)),
+            layout: RenderBindGroupLayouts::environment_ambient(),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&src.create_view(
+                        &wgpu::TextureViewDescriptor {
+                            label: Some("Ambient mip view"),
+                            dimension: Some(wgpu::TextureViewDimension::D2Array),
+                            base_mip_level: mip,
+                            mip_level_count: Some(1),
+                            ..Default::default()
+                        },
+                    )),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: average.get().as_entire_binding(),
+                },
+            ],
+        })
+    }
     pub fn equirect_src(
         device: &wgpu::Device,
         src: &super::Texture,
@@ -381,10 +994,12 @@ impl BindGroup {
         })
     }
 
+    /// Diffuse and normal map with the samplers their material asks for, see
+    /// [`super::SamplerCache`].
     pub fn normal(
         device: &wgpu::Device,
-        diffuse: &std::sync::Arc<super::Texture>,
-        normal: &std::sync::Arc<super::Texture>,
+        (diffuse, diffuse_sampler): (&super::Texture, &wgpu::Sampler),
+        (normal, normal_sampler): (&super::Texture, &wgpu::Sampler),
         label: &str,
     ) -> wgpu::BindGroup {
         device.create_bind_group(&wgpu::BindGroupDescriptor {
@@ -397,7 +1012,7 @@ impl BindGroup {
                 },
                 wgpu::BindGroupEntry {
                     binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&diffuse.sampler),
+                    resource: wgpu::BindingResource::Sampler(diffuse_sampler),
                 },
                 wgpu::BindGroupEntry {
                     binding: 2,
@@ -405,7 +1020,7 @@ impl BindGroup {
                 },
                 wgpu::BindGroupEntry {
                     binding: 3,
-                    resource: wgpu::BindingResource::Sampler(&normal.sampler),
+                    resource: wgpu::BindingResource::Sampler(normal_sampler),
                 },
             ],
         })
@@ -426,6 +1041,160 @@ impl BindGroup {
             ],
         })
     }
+    /// Group 3 of `terrain.wgsl`, see [`crate::TerrainTextureArray`]. Both textures come from
+    /// [`crate::TextureManager::load_texture_array`] and share `sampler`.
+    pub fn terrain_layers(
+        device: &wgpu::Device,
+        diffuse: &crate::Texture,
+        normal: &crate::Texture,
+        sampler: &wgpu::Sampler,
+        priorities: &WgpuBuffer,
+        label: &str,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(&format!("{} layers bind group", label)),
+            layout: RenderBindGroupLayouts::terrain_layers(),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&diffuse.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&normal.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: priorities.get().as_entire_binding(),
+                },
+            ],
+        })
+    }
+    /// Group 1 of `hdr.wgsl`.
+    pub fn tonemap(device: &wgpu::Device, tonemap: &WgpuBuffer, label: &str) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(&format!("{} tonemap bind group", label)),
+            layout: RenderBindGroupLayouts::tonemap(),
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: tonemap.get().as_entire_binding(),
+            }],
+        })
+    }
+    /// Group 1 of `bloom.wgsl`.
+    pub fn bloom(device: &wgpu::Device, params: &WgpuBuffer, label: &str) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(&format!("{} bloom bind group", label)),
+            layout: RenderBindGroupLayouts::bloom(),
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: params.get().as_entire_binding(),
+            }],
+        })
+    }
+    pub fn exposure_histogram(
+        device: &wgpu::Device,
+        scene: &super::Texture,
+        histogram: &WgpuBuffer,
+        params: &WgpuBuffer,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("exposure histogram bind group"),
+            layout: RenderBindGroupLayouts::exposure_histogram(),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&scene.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: histogram.get().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: params.get().as_entire_binding(),
+                },
+            ],
+        })
+    }
+    pub fn exposure_average(
+        device: &wgpu::Device,
+        histogram: &WgpuBuffer,
+        tonemap: &WgpuBuffer,
+        params: &WgpuBuffer,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("exposure average bind group"),
+            layout: RenderBindGroupLayouts::exposure_average(),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: histogram.get().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: tonemap.get().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: params.get().as_entire_binding(),
+                },
+            ],
+        })
+    }
+    pub fn particle_simulate(
+        device: &wgpu::Device,
+        particles: &WgpuBuffer,
+        spawned: &WgpuBuffer,
+        simulation: &WgpuBuffer,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("particle simulate bind group"),
+            layout: RenderBindGroupLayouts::particle_simulate(),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: particles.get().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: spawned.get().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: simulation.get().as_entire_binding(),
+                },
+            ],
+        })
+    }
+    pub fn particle_draw(
+        device: &wgpu::Device,
+        particles: &WgpuBuffer,
+        simulation: &WgpuBuffer,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("particle draw bind group"),
+            layout: RenderBindGroupLayouts::particle_draw(),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: particles.get().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: simulation.get().as_entire_binding(),
+                },
+            ],
+        })
+    }
     pub fn material_storage(
         device: &wgpu::Device,
         material_buffer: &crate::WgpuBuffer,
@@ -440,6 +1209,154 @@ impl BindGroup {
             }],
         })
     }
+    /// Group 2 of the object path. The dynamic offset variant binds one
+    /// [`crate::PerObjectData`] at a time.
+    pub fn object_data(
+        device: &wgpu::Device,
+        indexing: crate::ObjectIndexing,
+        material_buffer: &crate::WgpuBuffer,
+        object_buffer: &crate::WgpuBuffer,
+    ) -> wgpu::BindGroup {
+        let objects = match indexing {
+            crate::ObjectIndexing::PushConstants => object_buffer.get().as_entire_binding(),
+            crate::ObjectIndexing::DynamicOffset => {
+                wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: object_buffer.get(),
+                    offset: 0,
+                    size: std::num::NonZeroU64::new(crate::PerObjectData::SIZE),
+                })
+            }
+        };
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(&format!("{} object data bind group", indexing.variant())),
+            layout: crate::RenderBindGroupLayouts::object_data(indexing),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: material_buffer.get().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: objects,
+                },
+            ],
+        })
+    }
+    /// Group 2 of the skinned path for one entity's joint buffer.
+    pub fn joints(
+        device: &wgpu::Device,
+        material_buffer: &crate::WgpuBuffer,
+        joint_buffer: &crate::WgpuBuffer,
+        label: &str,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(&format!("{} joints bind group", label)),
+            layout: crate::RenderBindGroupLayouts::joints(),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: material_buffer.get().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: joint_buffer.get().as_entire_binding(),
+                },
+            ],
+        })
+    }
+    /// Group 2 where instances are fetched from storage, over the frame's instance buffer.
+    pub fn instance_storage(
+        device: &wgpu::Device,
+        material_buffer: &crate::WgpuBuffer,
+        instance_buffer: &crate::WgpuBuffer,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("instance storage bind group"),
+            layout: crate::RenderBindGroupLayouts::instance_storage(),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: material_buffer.get().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: instance_buffer.get().as_entire_binding(),
+                },
+            ],
+        })
+    }
+    /// Group 0 of `debug_geometry.wgsl`: derives `output` from a mesh's buffers.
+    pub fn debug_geometry(
+        device: &wgpu::Device,
+        params: &WgpuBuffer,
+        vertices: &WgpuBuffer,
+        indices: &WgpuBuffer,
+        output: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("debug geometry bind group"),
+            layout: RenderBindGroupLayouts::debug_geometry(),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: params.get().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: vertices.get().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: indices.get().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: output.as_entire_binding(),
+                },
+            ],
+        })
+    }
+    /// Group 2 of `water.wgsl`. `scene_depth` is bound without its comparison sampler and
+    /// may be multisampled, matching [`RenderBindGroupLayouts::water`].
+    pub fn water(
+        device: &wgpu::Device,
+        uniform: &WgpuBuffer,
+        refraction: &super::Texture,
+        scene_depth: &super::Texture,
+        normal_map: &super::Texture,
+    ) -> wgpu::BindGroup {
+        let multisampled = scene_depth.texture.sample_count() > 1;
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("water bind group"),
+            layout: RenderBindGroupLayouts::water(multisampled),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform.get().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&refraction.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&refraction.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(&scene_depth.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::TextureView(&normal_map.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: wgpu::BindingResource::Sampler(&normal_map.sampler),
+                },
+            ],
+        })
+    }
     pub fn debug(
         device: &wgpu::Device,
         camera_uniform_buffer: &WgpuBuffer,
@@ -469,29 +1386,42 @@ impl BindGroup {
 
 pub struct BindGroupManager {
     bind_groups: super::HashCache<std::sync::Arc<wgpu::BindGroup>>,
+    /// Generation of the texture each bind group of [`BindGroupManager::bind_group_for`]
+    /// was built from, see [`TextureManager::generation`].
+    generations: super::HashCache<u64>,
 }
 
 impl BindGroupManager {
     pub fn new() -> Self {
         Self {
             bind_groups: HashCache::new(),
+            generations: HashCache::new(),
         }
     }
     pub fn bind_group(&self, key: &super::CacheKey) -> Option<&std::sync::Arc<wgpu::BindGroup>> {
         self.bind_groups.get(key)
     }
+    /// Drops the bind groups built from `textures`, which would otherwise keep the evicted
+    /// textures alive; see [`TextureManager::take_evicted`].
+    pub fn invalidate(&mut self, textures: &[super::CacheKey]) {
+        for key in textures {
+            self.bind_groups.remove(key);
+            self.generations.remove(key);
+        }
+    }
+    /// Texture and sampler bind group of the texture under `key`, rebuilt when the texture
+    /// was replaced since the cached one was made.
     pub fn bind_group_for(
         &mut self,
         texture_manager: &TextureManager,
         key: &super::CacheKey,
         layout: &wgpu::BindGroupLayout,
     ) -> Option<std::sync::Arc<wgpu::BindGroup>> {
-        let binding = crate::GPU::get();
-        if let Ok(gpu) = binding.read() {
-            if !self.bind_groups.contains(&key) {
-                let tex = texture_manager.get(*key)?;
-                let bind_group: std::sync::Arc<wgpu::BindGroup> = gpu
-                    .device()
+        if let Ok(device) = crate::GPU::with_read_recovered(|gpu| gpu.device().clone()) {
+            let generation = texture_manager.generation(key);
+            if self.generations.get(key) != Some(&generation) {
+                let tex = texture_manager.get(key)?;
+                let bind_group: std::sync::Arc<wgpu::BindGroup> = device
                     .create_bind_group(&wgpu::BindGroupDescriptor {
                         label: Some(&format!("tex_bg:{}", key.id())),
                         layout,
@@ -508,6 +1438,7 @@ impl BindGroupManager {
                     })
                     .into();
                 self.bind_groups.insert(key.clone(), bind_group);
+                self.generations.insert(*key, generation);
             }
         }
 
@@ -540,6 +1471,86 @@ impl super::CacheStorage<std::sync::Arc<wgpu::BindGroup>> for BindGroupManager {
         self.bind_groups.insert(key, resource);
     }
     fn remove(&mut self, key: &crate::CacheKey) -> Option<std::sync::Arc<wgpu::BindGroup>> {
+        self.generations.remove(key);
         self.bind_groups.remove(key)
     }
+    fn iter(&self) -> impl Iterator<Item = (&crate::CacheKey, &std::sync::Arc<wgpu::BindGroup>)> {
+        self.bind_groups.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{CacheKey, Texture};
+
+    /// The global GPU, created on first use. `None` without an adapter.
+    fn device() -> Option<Arc<wgpu::Device>> {
+        let _ = crate::GPU::init(crate::GpuRequirements::default());
+        crate::GPU::with_read_recovered(|gpu| gpu.device().clone()).ok()
+    }
+
+    fn texture(device: &wgpu::Device, label: &str) -> Arc<Texture> {
+        Arc::new(Texture::from_desc(
+            device,
+            &wgpu::TextureDescriptor {
+                label: Some(label),
+                size: wgpu::Extent3d {
+                    width: 4,
+                    height: 4,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: Texture::DEFAULT_FORMAT,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            },
+        ))
+    }
+
+    /// Needs an adapter; passes without one.
+    #[test]
+    fn replacing_a_texture_rebuilds_its_bind_group() {
+        let Some(device) = device() else {
+            return;
+        };
+        let defs: Vec<BindingDef> = Texture::D2
+            .iter()
+            .zip(0..)
+            .map(|(ty, binding)| BindingDef {
+                binding,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: ty.binding,
+            })
+            .collect();
+        let layout = create_layout(&device, Some("test layout"), &defs);
+        let key = CacheKey::from("replaced.png");
+        let material = CacheKey::from("material");
+        let mut textures = TextureManager::new();
+        let mut bind_groups = BindGroupManager::new();
+
+        textures.insert(key, texture(&device, "A"));
+        textures.add_dependent(key, material);
+        let a = bind_groups
+            .bind_group_for(&textures, &key, &layout)
+            .unwrap();
+        let again = bind_groups
+            .bind_group_for(&textures, &key, &layout)
+            .unwrap();
+        assert!(Arc::ptr_eq(&a, &again));
+        assert!(textures.take_stale_bind_groups().is_empty());
+
+        textures.insert(key, texture(&device, "B"));
+        assert_eq!(textures.generation(&key), 2);
+        assert_eq!(textures.take_replaced(), [key]);
+        assert_eq!(textures.take_stale_bind_groups(), [material]);
+        let b = bind_groups
+            .bind_group_for(&textures, &key, &layout)
+            .unwrap();
+        assert!(!Arc::ptr_eq(&a, &b));
+    }
 }
@@ -1,4 +1,7 @@
-pub trait CacheStorage<R> {
+/// Keyed store every resource manager implements, so eviction, debug listings and other
+/// helpers can be written once over any of them. Resources are `'static` so [`Self::iter`]
+/// can borrow them for as long as the manager.
+pub trait CacheStorage<R: 'static> {
     fn get(&self, key: &crate::CacheKey) -> Option<&R>;
     fn contains(&self, key: &crate::CacheKey) -> bool;
     fn get_mut(&mut self, key: &crate::CacheKey) -> Option<&mut R>;
@@ -7,11 +10,22 @@ pub trait CacheStorage<R> {
         F: FnOnce() -> R;
     fn insert(&mut self, key: crate::CacheKey, resource: R);
     fn remove(&mut self, key: &crate::CacheKey) -> Option<R>;
+    /// Every cached resource with its key, in no particular order.
+    fn iter(&self) -> impl Iterator<Item = (&crate::CacheKey, &R)>;
+    fn keys(&self) -> impl Iterator<Item = &crate::CacheKey> {
+        self.iter().map(|(key, _)| key)
+    }
+    fn len(&self) -> usize {
+        self.iter().count()
+    }
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
 }
 
 pub type HashCache<R> = std::collections::HashMap<crate::CacheKey, R>;
 
-impl<R> CacheStorage<R> for HashCache<R> {
+impl<R: 'static> CacheStorage<R> for HashCache<R> {
     fn get(&self, key: &crate::CacheKey) -> Option<&R> {
         self.get(&key)
     }
@@ -33,4 +47,10 @@ impl<R> CacheStorage<R> for HashCache<R> {
     fn remove(&mut self, key: &crate::CacheKey) -> Option<R> {
         self.remove(key)
     }
+    fn iter(&self) -> impl Iterator<Item = (&crate::CacheKey, &R)> {
+        self.iter()
+    }
+    fn len(&self) -> usize {
+        self.len()
+    }
 }
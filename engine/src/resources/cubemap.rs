@@ -0,0 +1,689 @@
+//! Cubemaps loaded straight from images: six face files or one cross layout. Faces are
+//! decoded to linear `Rgba32Float`, the format the equirect projection writes, so a loaded
+//! cubemap can stand in for the projected one anywhere.
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use image::{imageops, ColorType, Rgba, Rgba32FImage};
+use serde::{Deserialize, Serialize};
+
+use super::Texture;
+use crate::EngineError;
+
+static LABEL_FACES: AtomicBool = AtomicBool::new(false);
+
+pub struct CubemapSettings;
+
+impl CubemapSettings {
+    pub fn label_faces() -> bool {
+        LABEL_FACES.load(Ordering::Relaxed)
+    }
+    /// Stamps each face with the name it was loaded as (`PX`, `NX`, ...) before orientation
+    /// is applied, to check a preset by eye. Takes effect on the next load.
+    pub fn set_label_faces(enabled: bool) {
+        LABEL_FACES.store(enabled, Ordering::Relaxed);
+    }
+}
+
+/// Cube faces in array layer order.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum CubeFace {
+    PosX,
+    NegX,
+    PosY,
+    NegY,
+    PosZ,
+    NegZ,
+}
+
+impl CubeFace {
+    pub const ALL: [CubeFace; 6] = [
+        CubeFace::PosX,
+        CubeFace::NegX,
+        CubeFace::PosY,
+        CubeFace::NegY,
+        CubeFace::PosZ,
+        CubeFace::NegZ,
+    ];
+
+    pub fn layer(self) -> u32 {
+        self as u32
+    }
+    /// File name suffix of the face in a six-face folder.
+    pub fn suffix(self) -> &'static str {
+        match self {
+            CubeFace::PosX => "px",
+            CubeFace::NegX => "nx",
+            CubeFace::PosY => "py",
+            CubeFace::NegY => "ny",
+            CubeFace::PosZ => "pz",
+            CubeFace::NegZ => "nz",
+        }
+    }
+}
+
+/// How to turn one loaded face into an engine face.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct FaceTransform {
+    /// Loaded face this engine face is taken from.
+    pub source: CubeFace,
+    pub flip_x: bool,
+    pub flip_y: bool,
+    /// Clockwise quarter turns, applied after the flips.
+    pub quarter_turns: u8,
+}
+
+impl FaceTransform {
+    pub fn identity(face: CubeFace) -> Self {
+        Self {
+            source: face,
+            flip_x: false,
+            flip_y: false,
+            quarter_turns: 0,
+        }
+    }
+    pub fn apply(&self, image: &Rgba32FImage) -> Rgba32FImage {
+        let mut image = image.clone();
+        if self.flip_x {
+            image = imageops::flip_horizontal(&image);
+        }
+        if self.flip_y {
+            image = imageops::flip_vertical(&image);
+        }
+        match self.quarter_turns % 4 {
+            1 => imageops::rotate90(&image),
+            2 => imageops::rotate180(&image),
+            3 => imageops::rotate270(&image),
+            _ => image,
+        }
+    }
+}
+
+/// Face conventions of the tool that exported a cubemap.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum CubemapOrientation {
+    /// The OpenGL cube map convention, which is also what wgpu samples: faces are used as is.
+    #[default]
+    OpenGl,
+    /// Left-handed exports such as Unity's six-sided skybox: +X and -X swap places, side
+    /// faces are mirrored horizontally and the top and bottom are turned half way round.
+    Unity,
+}
+
+impl CubemapOrientation {
+    pub fn transform(self, face: CubeFace) -> FaceTransform {
+        match self {
+            CubemapOrientation::OpenGl => FaceTransform::identity(face),
+            CubemapOrientation::Unity => match face {
+                CubeFace::PosX | CubeFace::NegX => FaceTransform {
+                    source: if face == CubeFace::PosX {
+                        CubeFace::NegX
+                    } else {
+                        CubeFace::PosX
+                    },
+                    flip_x: true,
+                    ..FaceTransform::identity(face)
+                },
+                CubeFace::PosZ | CubeFace::NegZ => FaceTransform {
+                    flip_x: true,
+                    ..FaceTransform::identity(face)
+                },
+                CubeFace::PosY | CubeFace::NegY => FaceTransform {
+                    quarter_turns: 2,
+                    ..FaceTransform::identity(face)
+                },
+            },
+        }
+    }
+}
+
+/// Single-image cube layouts, told apart by aspect ratio.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CrossLayout {
+    /// 4:3, with -X +Z +X -Z across the middle row.
+    Horizontal,
+    /// 3:4, with -X +Z +X across the second row and -Z upside down at the bottom.
+    Vertical,
+}
+
+impl CrossLayout {
+    /// Layout and face size of a `width` x `height` cross image.
+    pub fn detect(width: u32, height: u32) -> Result<(Self, u32), EngineError> {
+        let (layout, size) = if width * 3 == height * 4 {
+            (CrossLayout::Horizontal, width / 4)
+        } else if width * 4 == height * 3 {
+            (CrossLayout::Vertical, width / 3)
+        } else {
+            return Err(EngineError::AssetLoadError(format!(
+                "{}x{} is not a 4:3 or 3:4 cubemap cross",
+                width, height
+            )));
+        };
+        if size == 0 {
+            return Err(EngineError::AssetLoadError(
+                "empty cubemap cross".to_string(),
+            ));
+        }
+        Ok((layout, size))
+    }
+    /// Column and row of `face` in face units, and the clockwise quarter turns that make
+    /// the slice upright.
+    pub fn cell(self, face: CubeFace) -> (u32, u32, u8) {
+        match (self, face) {
+            (_, CubeFace::PosY) => (1, 0, 0),
+            (_, CubeFace::NegX) => (0, 1, 0),
+            (_, CubeFace::PosZ) => (1, 1, 0),
+            (_, CubeFace::PosX) => (2, 1, 0),
+            (_, CubeFace::NegY) => (1, 2, 0),
+            (CrossLayout::Horizontal, CubeFace::NegZ) => (3, 1, 0),
+            (CrossLayout::Vertical, CubeFace::NegZ) => (1, 3, 2),
+        }
+    }
+    /// Pixel offset of `face` in a cross with `size` pixel faces.
+    pub fn offset(self, face: CubeFace, size: u32) -> (u32, u32) {
+        let (column, row, _) = self.cell(face);
+        (column * size, row * size)
+    }
+}
+
+/// Six square linear faces of one size, in [`CubeFace::ALL`] order.
+#[derive(Debug, Clone)]
+pub struct CubemapFaces {
+    pub size: u32,
+    pub faces: Vec<Rgba32FImage>,
+}
+
+impl CubemapFaces {
+    /// Faces from `dir_or_pattern` under `assets/textures`: either a folder holding
+    /// `px`/`nx`/`py`/`ny`/`pz`/`nz` images, or a file pattern with `{face}` in place of the
+    /// suffix, e.g. `skies/dusk_{face}.png`. Faces must be square, equal in size and share
+    /// one pixel format.
+    pub fn load_faces(dir_or_pattern: &str) -> Result<Self, EngineError> {
+        let base = crate::AssetPaths::textures_dir();
+        let mut paths = Vec::with_capacity(6);
+        for face in CubeFace::ALL {
+            paths.push(face_path(&base, dir_or_pattern, face)?);
+        }
+        Self::from_files(&paths)
+    }
+    /// Faces from six image files under `assets/textures`, one per face in
+    /// [`CubeFace::ALL`] order (`px`, `nx`, `py`, `ny`, `pz`, `nz`), for skyboxes whose
+    /// files don't follow a naming scheme. Same rules as [`CubemapFaces::load_faces`].
+    pub fn load_face_files(paths: [&str; 6]) -> Result<Self, EngineError> {
+        let paths = paths.map(crate::AssetPaths::texture);
+        Self::from_files(&paths)
+    }
+    fn from_files(paths: &[PathBuf]) -> Result<Self, EngineError> {
+        let mut faces = Vec::with_capacity(6);
+        let mut first: Option<(&Path, u32, ColorType)> = None;
+        for path in paths {
+            let image = image::open(path)
+                .map_err(|e| EngineError::AssetLoadError(format!("{}: {}", path.display(), e)))?;
+            let (width, height, color) = (image.width(), image.height(), image.color());
+            if width != height {
+                return Err(EngineError::AssetLoadError(format!(
+                    "{}: cube face is {}x{}, not square",
+                    path.display(),
+                    width,
+                    height
+                )));
+            }
+            match first {
+                Some((first_path, w, _)) if w != width => {
+                    return Err(EngineError::AssetLoadError(format!(
+                        "{} is {}px but {} is {}px",
+                        path.display(),
+                        width,
+                        first_path.display(),
+                        w
+                    )))
+                }
+                Some((first_path, _, c)) if c != color => {
+                    return Err(EngineError::AssetLoadError(format!(
+                        "{} is {:?} but {} is {:?}",
+                        path.display(),
+                        color,
+                        first_path.display(),
+                        c
+                    )))
+                }
+                Some(_) => {}
+                None => first = Some((path.as_path(), width, color)),
+            }
+            faces.push(to_linear(image));
+        }
+        let size = first.map_or(0, |(_, w, _)| w);
+        Ok(Self { size, faces })
+    }
+
+    /// Faces sliced out of one horizontal or vertical cross image under `assets/textures`.
+    pub fn load_cross(path: &str) -> Result<Self, EngineError> {
+        let path = crate::AssetPaths::texture(path);
+        let image = image::open(&path)
+            .map_err(|e| EngineError::AssetLoadError(format!("{}: {}", path.display(), e)))?;
+        Self::from_cross(&to_linear(image))
+            .map_err(|e| EngineError::AssetLoadError(format!("{}: {}", path.display(), e)))
+    }
+    pub fn from_cross(image: &Rgba32FImage) -> Result<Self, EngineError> {
+        let (layout, size) = CrossLayout::detect(image.width(), image.height())?;
+        let faces = CubeFace::ALL
+            .iter()
+            .map(|&face| {
+                let (x, y) = layout.offset(face, size);
+                let (_, _, quarter_turns) = layout.cell(face);
+                let slice = imageops::crop_imm(image, x, y, size, size).to_image();
+                FaceTransform {
+                    quarter_turns,
+                    ..FaceTransform::identity(face)
+                }
+                .apply(&slice)
+            })
+            .collect();
+        Ok(Self { size, faces })
+    }
+
+    pub fn face(&self, face: CubeFace) -> &Rgba32FImage {
+        &self.faces[face.layer() as usize]
+    }
+    /// Faces rearranged from `orientation`'s convention into the engine's.
+    pub fn oriented(&self, orientation: CubemapOrientation) -> Self {
+        let faces = CubeFace::ALL
+            .iter()
+            .map(|&face| {
+                let transform = orientation.transform(face);
+                transform.apply(self.face(transform.source))
+            })
+            .collect();
+        Self {
+            size: self.size,
+            faces,
+        }
+    }
+    /// Stamps every face with its name in the top left corner; see [`CubemapSettings`].
+    pub fn labeled(mut self) -> Self {
+        for face in CubeFace::ALL {
+            let text = face.suffix().to_uppercase();
+            stamp_label(&mut self.faces[face.layer() as usize], &text);
+        }
+        self
+    }
+
+    pub fn mip_level_count(&self) -> u32 {
+        u32::BITS - self.size.max(1).leading_zeros()
+    }
+    /// Uploads every face with a full box-filtered mip chain into a cube texture.
+    pub fn upload(&self, device: &wgpu::Device, queue: &wgpu::Queue, label: &str) -> Texture {
+        let mip_level_count = self.mip_level_count();
+        let texture = Texture::new(
+            device,
+            wgpu::Extent3d {
+                width: self.size,
+                height: self.size,
+                depth_or_array_layers: 6,
+            },
+            Texture::HDR_FORMAT,
+            mip_level_count,
+            wgpu::TextureViewDimension::Cube,
+            wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            Some(wgpu::AddressMode::ClampToEdge),
+            wgpu::FilterMode::Nearest,
+            None,
+            Some(label),
+        );
+        for face in CubeFace::ALL {
+            let mut level = self.face(face).clone();
+            for mip_level in 0..mip_level_count {
+                if mip_level > 0 {
+                    let size = (level.width() / 2).max(1);
+                    level = imageops::resize(&level, size, size, imageops::FilterType::Triangle);
+                }
+                queue.write_texture(
+                    wgpu::TexelCopyTextureInfo {
+                        texture: &texture.texture,
+                        mip_level,
+                        origin: wgpu::Origin3d {
+                            x: 0,
+                            y: 0,
+                            z: face.layer(),
+                        },
+                        aspect: wgpu::TextureAspect::All,
+                    },
+                    bytemuck::cast_slice(level.as_raw()),
+                    wgpu::TexelCopyBufferLayout {
+                        offset: 0,
+                        bytes_per_row: Some(level.width() * std::mem::size_of::<[f32; 4]>() as u32),
+                        rows_per_image: Some(level.height()),
+                    },
+                    wgpu::Extent3d {
+                        width: level.width(),
+                        height: level.height(),
+                        depth_or_array_layers: 1,
+                    },
+                );
+            }
+        }
+        texture
+    }
+}
+
+fn face_path(base: &Path, dir_or_pattern: &str, face: CubeFace) -> Result<PathBuf, EngineError> {
+    if dir_or_pattern.contains("{face}") {
+        return Ok(base.join(dir_or_pattern.replace("{face}", face.suffix())));
+    }
+    let dir = base.join(dir_or_pattern);
+    ["png", "jpg", "jpeg", "hdr", "tga", "bmp"]
+        .iter()
+        .map(|ext| dir.join(format!("{}.{}", face.suffix(), ext)))
+        .find(|path| path.exists())
+        .ok_or_else(|| {
+            EngineError::AssetMissing(format!("{}: no {} face", dir.display(), face.suffix()))
+        })
+}
+
+/// Float RGBA in linear light. HDR sources already are; 8 and 16 bit ones are sRGB.
+fn to_linear(image: image::DynamicImage) -> Rgba32FImage {
+    let srgb = !matches!(image.color(), ColorType::Rgb32F | ColorType::Rgba32F);
+    let mut image = image.to_rgba32f();
+    if srgb {
+        for Rgba([r, g, b, _]) in image.pixels_mut() {
+            for channel in [r, g, b] {
+                *channel = srgb_to_linear(*channel);
+            }
+        }
+    }
+    image
+}
+
+fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// 5x7 glyphs for the letters face names use, one row per byte, high bit on the left.
+fn glyph(letter: char) -> [u8; 7] {
+    match letter {
+        'P' => [0x1e, 0x11, 0x11, 0x1e, 0x10, 0x10, 0x10],
+        'N' => [0x11, 0x19, 0x15, 0x13, 0x11, 0x11, 0x11],
+        'X' => [0x11, 0x11, 0x0a, 0x04, 0x0a, 0x11, 0x11],
+        'Y' => [0x11, 0x11, 0x0a, 0x04, 0x04, 0x04, 0x04],
+        'Z' => [0x1f, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1f],
+        _ => [0; 7],
+    }
+}
+
+/// Bright enough to survive the tonemap as white.
+const LABEL_INK: Rgba<f32> = Rgba([4.0, 4.0, 4.0, 1.0]);
+const LABEL_PAPER: Rgba<f32> = Rgba([0.0, 0.0, 0.0, 1.0]);
+
+fn stamp_label(image: &mut Rgba32FImage, text: &str) {
+    let scale = (image.width() / 64).max(1);
+    let margin = scale * 2;
+    let width = (text.len() as u32 * 6 + 1) * scale;
+    let height = 9 * scale;
+    for y in 0..height.min(image.height().saturating_sub(margin)) {
+        for x in 0..width.min(image.width().saturating_sub(margin)) {
+            let (gx, gy) = (x / scale, y / scale);
+            let ink = (1..=7).contains(&gy)
+                && text.chars().nth((gx / 6) as usize).is_some_and(|letter| {
+                    let column = gx % 6;
+                    column >= 1 && glyph(letter)[(gy - 1) as usize] & (0x10 >> (column - 1)) != 0
+                });
+            let pixel = if ink { LABEL_INK } else { LABEL_PAPER };
+            image.put_pixel(margin + x, margin + y, pixel);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{ImageBuffer, Luma, RgbaImage};
+
+    use super::*;
+
+    /// Face `i` of [`CubeFace::ALL`] is filled with `i + 1` in red; `0` marks empty cells.
+    fn face_value(face: CubeFace) -> f32 {
+        face.layer() as f32 + 1.0
+    }
+
+    fn cross(layout: CrossLayout, size: u32) -> Rgba32FImage {
+        let (columns, rows) = match layout {
+            CrossLayout::Horizontal => (4, 3),
+            CrossLayout::Vertical => (3, 4),
+        };
+        let mut image = Rgba32FImage::new(columns * size, rows * size);
+        for face in CubeFace::ALL {
+            let (x, y) = layout.offset(face, size);
+            for dy in 0..size {
+                for dx in 0..size {
+                    // Green marks the top left texel of the cell as stored in the image.
+                    let corner = if dx == 0 && dy == 0 { 1.0 } else { 0.0 };
+                    image.put_pixel(x + dx, y + dy, Rgba([face_value(face), corner, 0.0, 1.0]));
+                }
+            }
+        }
+        image
+    }
+
+    #[test]
+    fn cross_layouts_are_detected_from_the_aspect_ratio() {
+        assert_eq!(
+            CrossLayout::detect(1024, 768).unwrap(),
+            (CrossLayout::Horizontal, 256)
+        );
+        assert_eq!(
+            CrossLayout::detect(768, 1024).unwrap(),
+            (CrossLayout::Vertical, 256)
+        );
+        assert!(CrossLayout::detect(1024, 1024).is_err());
+        assert!(CrossLayout::detect(2048, 1024).is_err());
+        assert!(CrossLayout::detect(0, 0).is_err());
+    }
+
+    #[test]
+    fn cross_slicing_offsets_match_both_layouts() {
+        let size = 16;
+        let horizontal = [
+            (CubeFace::PosX, (32, 16)),
+            (CubeFace::NegX, (0, 16)),
+            (CubeFace::PosY, (16, 0)),
+            (CubeFace::NegY, (16, 32)),
+            (CubeFace::PosZ, (16, 16)),
+            (CubeFace::NegZ, (48, 16)),
+        ];
+        let vertical = [
+            (CubeFace::PosX, (32, 16)),
+            (CubeFace::NegX, (0, 16)),
+            (CubeFace::PosY, (16, 0)),
+            (CubeFace::NegY, (16, 32)),
+            (CubeFace::PosZ, (16, 16)),
+            (CubeFace::NegZ, (16, 48)),
+        ];
+        for (layout, expected) in [
+            (CrossLayout::Horizontal, horizontal),
+            (CrossLayout::Vertical, vertical),
+        ] {
+            for (face, offset) in expected {
+                assert_eq!(layout.offset(face, size), offset, "{:?} {:?}", layout, face);
+            }
+
+            let faces = CubemapFaces::from_cross(&cross(layout, size)).unwrap();
+            assert_eq!(faces.size, size);
+            for face in CubeFace::ALL {
+                let image = faces.face(face);
+                assert_eq!(image.dimensions(), (size, size));
+                // Each slice holds its own face and nothing from a neighbouring cell.
+                assert!(
+                    image.pixels().all(|p| p[0] == face_value(face)),
+                    "{:?} {:?}",
+                    layout,
+                    face
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn the_vertical_cross_bottom_face_is_turned_upright() {
+        let size = 4;
+        let corner = |image: &Rgba32FImage| {
+            image
+                .enumerate_pixels()
+                .find(|(_, _, p)| p[1] == 1.0)
+                .map(|(x, y, _)| (x, y))
+        };
+        let horizontal = CubemapFaces::from_cross(&cross(CrossLayout::Horizontal, size)).unwrap();
+        let vertical = CubemapFaces::from_cross(&cross(CrossLayout::Vertical, size)).unwrap();
+        for face in CubeFace::ALL {
+            assert_eq!(corner(horizontal.face(face)), Some((0, 0)), "{:?}", face);
+        }
+        assert_eq!(
+            corner(vertical.face(CubeFace::NegZ)),
+            Some((size - 1, size - 1))
+        );
+        assert_eq!(corner(vertical.face(CubeFace::PosZ)), Some((0, 0)));
+    }
+
+    fn write_faces(dir: &Path, sizes: [u32; 6], gray_at: Option<usize>) -> Vec<PathBuf> {
+        std::fs::create_dir_all(dir).unwrap();
+        CubeFace::ALL
+            .iter()
+            .enumerate()
+            .map(|(i, face)| {
+                let path = dir.join(format!("{}.png", face.suffix()));
+                let (width, height) = if i == 0 && sizes[0] == 0 {
+                    (8, 4)
+                } else {
+                    (sizes[i], sizes[i])
+                };
+                if gray_at == Some(i) {
+                    ImageBuffer::<Luma<u8>, _>::new(width, height)
+                        .save(&path)
+                        .unwrap();
+                } else {
+                    RgbaImage::new(width, height).save(&path).unwrap();
+                }
+                path
+            })
+            .collect()
+    }
+
+    fn error(paths: &[PathBuf]) -> String {
+        match CubemapFaces::from_files(paths) {
+            Err(EngineError::AssetLoadError(message)) => message,
+            other => panic!("expected a load error, got {:?}", other.map(|f| f.size)),
+        }
+    }
+
+    #[test]
+    fn face_sets_must_be_square_equal_and_one_format() {
+        let root = std::env::temp_dir().join(format!("rupy_cubemap_{}", std::process::id()));
+
+        let paths = write_faces(&root.join("ok"), [8; 6], None);
+        let faces = CubemapFaces::from_files(&paths).unwrap();
+        assert_eq!((faces.size, faces.faces.len()), (8, 6));
+        assert_eq!(faces.mip_level_count(), 4);
+
+        let message = error(&write_faces(&root.join("oblong"), [0, 8, 8, 8, 8, 8], None));
+        assert!(message.contains("8x4, not square"), "{}", message);
+
+        let message = error(&write_faces(&root.join("sizes"), [8, 8, 8, 16, 8, 8], None));
+        assert!(message.contains("ny.png is 16px"), "{}", message);
+
+        let message = error(&write_faces(&root.join("formats"), [8; 6], Some(4)));
+        assert!(message.contains("pz.png is L8"), "{}", message);
+
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    /// Six 2x2 faces, each tagged with its own value in red and its texel position in green.
+    fn labeled_faces() -> CubemapFaces {
+        let faces = CubeFace::ALL
+            .iter()
+            .map(|&face| {
+                Rgba32FImage::from_fn(2, 2, |x, y| {
+                    Rgba([face_value(face), (y * 2 + x) as f32, 0.0, 1.0])
+                })
+            })
+            .collect();
+        CubemapFaces { size: 2, faces }
+    }
+
+    /// Face values and texel tags of `face`, row by row.
+    fn texels(faces: &CubemapFaces, face: CubeFace) -> (f32, [f32; 4]) {
+        let image = faces.face(face);
+        let tags = [(0, 0), (1, 0), (0, 1), (1, 1)].map(|(x, y)| image.get_pixel(x, y)[1]);
+        (image.get_pixel(0, 0)[0], tags)
+    }
+
+    #[test]
+    fn orientation_presets_rearrange_synthetic_faces() {
+        let faces = labeled_faces();
+
+        let opengl = faces.oriented(CubemapOrientation::OpenGl);
+        for face in CubeFace::ALL {
+            assert_eq!(texels(&opengl, face), texels(&faces, face), "{:?}", face);
+        }
+
+        let unity = faces.oriented(CubemapOrientation::Unity);
+        let mirrored = [1.0, 0.0, 3.0, 2.0];
+        let half_turn = [3.0, 2.0, 1.0, 0.0];
+        assert_eq!(
+            texels(&unity, CubeFace::PosX),
+            (face_value(CubeFace::NegX), mirrored)
+        );
+        assert_eq!(
+            texels(&unity, CubeFace::NegX),
+            (face_value(CubeFace::PosX), mirrored)
+        );
+        assert_eq!(
+            texels(&unity, CubeFace::PosZ),
+            (face_value(CubeFace::PosZ), mirrored)
+        );
+        assert_eq!(
+            texels(&unity, CubeFace::NegZ),
+            (face_value(CubeFace::NegZ), mirrored)
+        );
+        assert_eq!(
+            texels(&unity, CubeFace::PosY),
+            (face_value(CubeFace::PosY), half_turn)
+        );
+        assert_eq!(
+            texels(&unity, CubeFace::NegY),
+            (face_value(CubeFace::NegY), half_turn)
+        );
+    }
+
+    #[test]
+    fn face_transforms_flip_before_turning() {
+        let image = Rgba32FImage::from_fn(2, 2, |x, y| Rgba([(y * 2 + x) as f32, 0.0, 0.0, 1.0]));
+        let transform = FaceTransform {
+            flip_x: true,
+            quarter_turns: 1,
+            ..FaceTransform::identity(CubeFace::PosX)
+        };
+        let out = transform.apply(&image);
+        // Mirrored to [1 0 / 3 2], then turned clockwise to [3 1 / 2 0].
+        let values = [(0, 0), (1, 0), (0, 1), (1, 1)].map(|(x, y)| out.get_pixel(x, y)[0]);
+        assert_eq!(values, [3.0, 1.0, 2.0, 0.0]);
+    }
+
+    #[test]
+    fn labels_mark_the_top_left_corner_only() {
+        let faces = CubemapFaces {
+            size: 64,
+            faces: vec![Rgba32FImage::from_pixel(64, 64, Rgba([0.5, 0.5, 0.5, 1.0])); 6],
+        }
+        .labeled();
+        for face in CubeFace::ALL {
+            let image = faces.face(face);
+            assert!(image.pixels().any(|p| *p == LABEL_INK));
+            assert_eq!(*image.get_pixel(63, 63), Rgba([0.5, 0.5, 0.5, 1.0]));
+        }
+        // Different names stamp different ink.
+        assert_ne!(faces.face(CubeFace::PosX), faces.face(CubeFace::NegY));
+    }
+}
@@ -0,0 +1,648 @@
+//! KTX2 containers of block-compressed (BCn) textures, uploaded with the mip levels they
+//! hold. Only single 2D textures without supercompression are read; the data format
+//! descriptor and key/value data are skipped, the `vkFormat` says all an upload needs.
+//! The texture importer writes them too, compressing to BC1, or BC3 with alpha.
+
+use std::path::Path;
+
+use crate::{ByteReader, ByteWriter, Derivative, EngineError, ImportedTexture};
+
+const IDENTIFIER: [u8; 12] = [
+    0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A,
+];
+
+/// `VkFormat` of each BCn format read, with its wgpu format. BC1 without alpha is read as
+/// BC1 with alpha, which only differs for blocks that use the transparent palette entry.
+const FORMATS: [(u32, wgpu::TextureFormat); 10] = [
+    (131, wgpu::TextureFormat::Bc1RgbaUnorm),
+    (132, wgpu::TextureFormat::Bc1RgbaUnormSrgb),
+    (133, wgpu::TextureFormat::Bc1RgbaUnorm),
+    (134, wgpu::TextureFormat::Bc1RgbaUnormSrgb),
+    (137, wgpu::TextureFormat::Bc3RgbaUnorm),
+    (138, wgpu::TextureFormat::Bc3RgbaUnormSrgb),
+    (141, wgpu::TextureFormat::Bc5RgUnorm),
+    (142, wgpu::TextureFormat::Bc5RgSnorm),
+    (145, wgpu::TextureFormat::Bc7RgbaUnorm),
+    (146, wgpu::TextureFormat::Bc7RgbaUnormSrgb),
+];
+
+/// A BCn texture read from a `.ktx2` file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ktx2Texture {
+    pub width: u32,
+    pub height: u32,
+    pub format: wgpu::TextureFormat,
+    /// Compressed levels, largest first, each exactly [`Ktx2Texture::level_len`] bytes.
+    pub levels: Vec<Vec<u8>>,
+}
+
+impl Ktx2Texture {
+    pub const EXT: &'static str = "ktx2";
+    /// Header magic of the imported derivative, see [`Ktx2Texture::load_imported`].
+    pub const MAGIC: [u8; 4] = *b"RKT2";
+
+    pub fn accepts(texture: &str) -> bool {
+        Path::new(texture)
+            .extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| e.eq_ignore_ascii_case(Self::EXT))
+    }
+    /// Reads `textures/<texture>`.
+    pub fn load(texture: &str) -> Result<Self, EngineError> {
+        let path = crate::AssetPaths::texture(texture);
+        let bytes = std::fs::read(&path)?;
+        Self::parse(&bytes).map_err(|e| EngineError::AssetLoadError(format!("{}: {}", texture, e)))
+    }
+    /// Fresh compressed derivative of `textures/<texture>`, if the importer made one.
+    pub fn load_imported(texture: &str) -> Option<Self> {
+        let source = format!("textures/{}", texture);
+        let body = Derivative::read_fresh(&source, Self::EXT, Self::MAGIC)?;
+        Self::parse(&body)
+            .map_err(|e| crate::log_warning!("{}: {}", source, e))
+            .ok()
+    }
+    pub fn parse(bytes: &[u8]) -> Result<Self, EngineError> {
+        let invalid = |what: &str| EngineError::AssetLoadError(format!("KTX2 {}", what));
+        let truncated = |_: EngineError| invalid("file is truncated");
+        let mut reader = ByteReader::new(bytes);
+        if reader.raw(IDENTIFIER.len()).map_err(truncated)? != IDENTIFIER {
+            return Err(invalid("identifier missing"));
+        }
+        let mut header = [0u32; 9];
+        for field in &mut header {
+            *field = reader.u32().map_err(truncated)?;
+        }
+        let [vk_format, _type_size, width, height, depth, layers, faces, levels, supercompression] =
+            header;
+        // Data format descriptor, key/value data and supercompression global data offsets.
+        reader.raw(4 * 4 + 2 * 8).map_err(truncated)?;
+
+        if supercompression != 0 {
+            return Err(invalid("supercompression isn't supported"));
+        }
+        if depth > 1 || layers > 1 || faces != 1 {
+            return Err(invalid(
+                "files other than a single 2D texture aren't supported",
+            ));
+        }
+        let format = FORMATS
+            .iter()
+            .find(|(vk, _)| *vk == vk_format)
+            .map(|(_, format)| *format)
+            .ok_or_else(|| invalid(&format!("VkFormat {} isn't a BCn format", vk_format)))?;
+        let (block_width, block_height) = format.block_dimensions();
+        if width == 0 || height == 0 || width % block_width != 0 || height % block_height != 0 {
+            return Err(invalid(&format!(
+                "size {}x{} isn't whole {}x{} blocks",
+                width, height, block_width, block_height
+            )));
+        }
+        // A level count of 0 asks the loader to generate mips; only the base level is stored.
+        let count = levels.max(1);
+        if count > 32 - width.max(height).leading_zeros() {
+            return Err(invalid(&format!(
+                "{} levels exceed a full mip chain",
+                count
+            )));
+        }
+
+        let mut texture = Self {
+            width,
+            height,
+            format,
+            levels: Vec::with_capacity(count as usize),
+        };
+        for level in 0..count {
+            let offset = reader.u64().map_err(truncated)?;
+            let length = reader.u64().map_err(truncated)?;
+            let _uncompressed_length = reader.u64().map_err(truncated)?;
+            let (level_width, level_height) = texture.level_size(level);
+            let expected = Self::level_len(format, level_width, level_height);
+            let data = usize::try_from(offset)
+                .ok()
+                .filter(|_| length >= expected as u64)
+                .and_then(|start| bytes.get(start..start.checked_add(expected)?))
+                .ok_or_else(|| invalid(&format!("level {} is truncated", level)))?;
+            texture.levels.push(data.to_vec());
+        }
+        Ok(texture)
+    }
+
+    /// Texels of `level`, before rounding up to whole blocks.
+    pub fn level_size(&self, level: u32) -> (u32, u32) {
+        ((self.width >> level).max(1), (self.height >> level).max(1))
+    }
+    /// Bytes from one row of blocks to the next in a level `width` texels wide.
+    pub fn row_pitch(format: wgpu::TextureFormat, width: u32) -> u32 {
+        let (block_width, _) = format.block_dimensions();
+        width.div_ceil(block_width) * format.block_copy_size(None).unwrap_or(0)
+    }
+    /// Rows of blocks in a level `height` texels high.
+    pub fn block_rows(format: wgpu::TextureFormat, height: u32) -> u32 {
+        let (_, block_height) = format.block_dimensions();
+        height.div_ceil(block_height)
+    }
+    /// Bytes of a `width` by `height` level.
+    pub fn level_len(format: wgpu::TextureFormat, width: u32, height: u32) -> usize {
+        Self::row_pitch(format, width) as usize * Self::block_rows(format, height) as usize
+    }
+
+    /// Decodes every level to RGBA8, for adapters without `TEXTURE_COMPRESSION_BC`. BC1, BC3
+    /// and unsigned BC5 decode; BC5 leaves blue at zero like the GPU does, so shaders still
+    /// rebuild the normal's z. Signed BC5 and BC7 fail.
+    pub fn decompress(&self) -> Result<ImportedTexture, EngineError> {
+        use wgpu::TextureFormat::*;
+        let decode: fn(&[u8]) -> [[u8; 4]; 16] = match self.format {
+            Bc1RgbaUnorm | Bc1RgbaUnormSrgb => |block| decode_color(block, false),
+            Bc3RgbaUnorm | Bc3RgbaUnormSrgb => decode_bc3,
+            Bc5RgUnorm => decode_bc5,
+            other => {
+                return Err(EngineError::AssetLoadError(format!(
+                    "{:?} can't be decoded without BC texture compression",
+                    other
+                )))
+            }
+        };
+        let block_bytes = self.format.block_copy_size(None).unwrap_or(0) as usize;
+        let mips = self
+            .levels
+            .iter()
+            .enumerate()
+            .map(|(level, data)| {
+                let (width, height) = self.level_size(level as u32);
+                let blocks_wide = width.div_ceil(4) as usize;
+                let mut rgba = vec![0; width as usize * height as usize * 4];
+                for (index, block) in data.chunks_exact(block_bytes).enumerate() {
+                    let (bx, by) = (index % blocks_wide * 4, index / blocks_wide * 4);
+                    for (texel, color) in decode(block).iter().enumerate() {
+                        let (x, y) = (bx + texel % 4, by + texel / 4);
+                        if x < width as usize && y < height as usize {
+                            let at = (y * width as usize + x) * 4;
+                            rgba[at..at + 4].copy_from_slice(color);
+                        }
+                    }
+                }
+                rgba
+            })
+            .collect();
+        Ok(ImportedTexture {
+            width: self.width,
+            height: self.height,
+            srgb: self.format.is_srgb(),
+            mips,
+        })
+    }
+
+    /// Block-compresses every level of `texture`: to BC3 when any base texel isn't opaque,
+    /// to BC1 otherwise. `None` unless the base level is whole 4x4 blocks.
+    pub fn compress(texture: &ImportedTexture) -> Option<Self> {
+        use wgpu::TextureFormat::*;
+        if texture.width % 4 != 0 || texture.height % 4 != 0 {
+            return None;
+        }
+        let alpha = texture.mips.first()?.chunks_exact(4).any(|t| t[3] < 255);
+        let format = match (alpha, texture.srgb) {
+            (false, false) => Bc1RgbaUnorm,
+            (false, true) => Bc1RgbaUnormSrgb,
+            (true, false) => Bc3RgbaUnorm,
+            (true, true) => Bc3RgbaUnormSrgb,
+        };
+        let mut compressed = Self {
+            width: texture.width,
+            height: texture.height,
+            format,
+            levels: Vec::with_capacity(texture.mips.len()),
+        };
+        for (level, rgba) in texture.mips.iter().enumerate() {
+            let (width, height) = compressed.level_size(level as u32);
+            if rgba.len() != (width * height * 4) as usize {
+                return None;
+            }
+            let mut data = Vec::with_capacity(Self::level_len(format, width, height));
+            for by in (0..height).step_by(4) {
+                for bx in (0..width).step_by(4) {
+                    // Levels under 4x4 repeat their last row and column to fill the block.
+                    let texels: [[u8; 4]; 16] = std::array::from_fn(|i| {
+                        let x = (bx + i as u32 % 4).min(width - 1);
+                        let y = (by + i as u32 / 4).min(height - 1);
+                        let at = ((y * width + x) * 4) as usize;
+                        [rgba[at], rgba[at + 1], rgba[at + 2], rgba[at + 3]]
+                    });
+                    if alpha {
+                        data.extend(encode_channel(texels.map(|t| t[3])));
+                    }
+                    data.extend(encode_color(&texels));
+                }
+            }
+            compressed.levels.push(data);
+        }
+        Some(compressed)
+    }
+
+    /// The texture as a KTX2 file, levels stored smallest first and block aligned as the
+    /// format lays them out, with a basic data format descriptor for other readers.
+    pub fn encode(&self) -> Vec<u8> {
+        let vk_format = FORMATS
+            .iter()
+            .find(|(_, format)| *format == self.format)
+            .map_or(0, |(vk, _)| *vk);
+        let block_bytes = self.format.block_copy_size(None).unwrap_or(1) as usize;
+        let dfd = self.data_format_descriptor(block_bytes);
+        let dfd_offset = IDENTIFIER.len() + 9 * 4 + 4 * 4 + 2 * 8 + 24 * self.levels.len();
+        let mut offsets = vec![0; self.levels.len()];
+        let mut end = dfd_offset + dfd.len();
+        for (level, data) in self.levels.iter().enumerate().rev() {
+            end = end.next_multiple_of(block_bytes);
+            offsets[level] = end;
+            end += data.len();
+        }
+
+        let mut out = ByteWriter::default();
+        out.raw(&IDENTIFIER);
+        let levels = self.levels.len() as u32;
+        for field in [vk_format, 1, self.width, self.height, 0, 0, 1, levels, 0] {
+            out.u32(field);
+        }
+        for field in [dfd_offset as u32, dfd.len() as u32, 0, 0] {
+            out.u32(field);
+        }
+        out.u64(0);
+        out.u64(0);
+        for (offset, data) in offsets.iter().zip(&self.levels) {
+            out.u64(*offset as u64);
+            out.u64(data.len() as u64);
+            out.u64(data.len() as u64);
+        }
+        out.raw(&dfd);
+        for (offset, data) in offsets.iter().zip(&self.levels).rev() {
+            out.0.resize(*offset, 0);
+            out.raw(data);
+        }
+        out.0
+    }
+    /// Khronos basic descriptor block: 4x4 texel blocks of `block_bytes`, one sample per
+    /// 64-bit half holding its own channel, or one 128-bit sample for BC7.
+    fn data_format_descriptor(&self, block_bytes: usize) -> Vec<u8> {
+        use wgpu::TextureFormat::*;
+        const SIGNED: u8 = 0x40;
+        let (model, channels): (u8, &[u8]) = match self.format {
+            Bc1RgbaUnorm | Bc1RgbaUnormSrgb => (128, &[0]),
+            Bc3RgbaUnorm | Bc3RgbaUnormSrgb => (130, &[15, 0]),
+            Bc5RgUnorm => (132, &[0, 1]),
+            Bc5RgSnorm => (132, &[SIGNED, 1 | SIGNED]),
+            _ => (134, &[0]),
+        };
+        let transfer = if self.format.is_srgb() { 2 } else { 1 };
+        let bits = (block_bytes * 8 / channels.len()) as u32;
+        let block_size = 24 + 16 * channels.len() as u32;
+
+        let mut out = ByteWriter::default();
+        out.u32(4 + block_size);
+        out.u32(0);
+        out.u32(2 | block_size << 16);
+        out.raw(&[model, 1, transfer, 0]);
+        out.raw(&[3, 3, 0, 0]);
+        out.raw(&[block_bytes as u8, 0, 0, 0, 0, 0, 0, 0]);
+        for (i, &channel) in channels.iter().enumerate() {
+            out.u32((i as u32 * bits) | ((bits - 1) << 16) | ((channel as u32) << 24));
+            out.u32(0);
+            let (lower, upper) = if channel & SIGNED != 0 {
+                (i32::MIN as u32, i32::MAX as u32)
+            } else {
+                (0, u32::MAX)
+            };
+            out.u32(lower);
+            out.u32(upper);
+        }
+        out.0
+    }
+}
+
+fn rgb565(color: u16) -> [u8; 4] {
+    let expand = |value: u16, max: u16| (value as u32 * 255 / max as u32) as u8;
+    [
+        expand(color >> 11, 31),
+        expand((color >> 5) & 63, 63),
+        expand(color & 31, 31),
+        255,
+    ]
+}
+
+/// The four colors a BC1/BC3 color block picks from. BC3 always uses four colors; BC1
+/// switches to three and transparent black when the first endpoint isn't the greater one.
+fn color_palette(c0: u16, c1: u16, four_colors: bool) -> [[u8; 4]; 4] {
+    let (a, b) = (rgb565(c0), rgb565(c1));
+    let mix = |wa: u32, wb: u32| {
+        let channel = |i: usize| ((a[i] as u32 * wa + b[i] as u32 * wb) / (wa + wb)) as u8;
+        [channel(0), channel(1), channel(2), 255]
+    };
+    if four_colors || c0 > c1 {
+        [a, b, mix(2, 1), mix(1, 2)]
+    } else {
+        [a, b, mix(1, 1), [0; 4]]
+    }
+}
+
+/// The eight values a BC4 channel block picks from: six between the endpoints when the
+/// first is the greater one, else four plus 0 and 255.
+fn channel_palette(a0: u8, a1: u8) -> [u8; 8] {
+    let (a0, a1) = (a0 as u32, a1 as u32);
+    let mut palette = [a0 as u8, a1 as u8, 0, 0, 0, 0, 0, 255];
+    if a0 > a1 {
+        for i in 1..7 {
+            palette[i as usize + 1] = (((7 - i) * a0 + i * a1) / 7) as u8;
+        }
+    } else {
+        for i in 1..5 {
+            palette[i as usize + 1] = (((5 - i) * a0 + i * a1) / 5) as u8;
+        }
+        palette[6] = 0;
+    }
+    palette
+}
+
+/// Color half of a BC1/BC3 block.
+fn decode_color(block: &[u8], four_colors: bool) -> [[u8; 4]; 16] {
+    let c0 = u16::from_le_bytes([block[0], block[1]]);
+    let c1 = u16::from_le_bytes([block[2], block[3]]);
+    let palette = color_palette(c0, c1, four_colors);
+    let indices = u32::from_le_bytes([block[4], block[5], block[6], block[7]]);
+    std::array::from_fn(|texel| palette[(indices >> (2 * texel)) as usize & 3])
+}
+
+/// One BC4 channel block, as used for BC3 alpha and each BC5 channel.
+fn decode_channel(block: &[u8]) -> [u8; 16] {
+    let palette = channel_palette(block[0], block[1]);
+    let mut bits = [0u8; 8];
+    bits[..6].copy_from_slice(&block[2..8]);
+    let indices = u64::from_le_bytes(bits);
+    std::array::from_fn(|texel| palette[(indices >> (3 * texel)) as usize & 7])
+}
+
+fn decode_bc3(block: &[u8]) -> [[u8; 4]; 16] {
+    let alpha = decode_channel(&block[..8]);
+    let mut colors = decode_color(&block[8..], true);
+    for (color, alpha) in colors.iter_mut().zip(alpha) {
+        color[3] = alpha;
+    }
+    colors
+}
+
+fn decode_bc5(block: &[u8]) -> [[u8; 4]; 16] {
+    let (red, green) = (decode_channel(&block[..8]), decode_channel(&block[8..]));
+    std::array::from_fn(|texel| [red[texel], green[texel], 0, 255])
+}
+
+/// `color` rounded to 5:6:5.
+fn pack_565(color: [u8; 4]) -> u16 {
+    let quantize = |value: u8, max: u32| (value as u32 * max + 127) / 255;
+    (quantize(color[0], 31) << 11 | quantize(color[1], 63) << 5 | quantize(color[2], 31)) as u16
+}
+
+/// Color half of a BC1/BC3 block in four-color mode. The endpoints are corners of the
+/// block's bounding box, across the diagonal green and blue run along with red.
+fn encode_color(texels: &[[u8; 4]; 16]) -> [u8; 8] {
+    let (mut lo, mut hi) = ([255u8; 4], [0u8; 4]);
+    for texel in texels {
+        for i in 0..3 {
+            lo[i] = lo[i].min(texel[i]);
+            hi[i] = hi[i].max(texel[i]);
+        }
+    }
+    let mean: [i32; 3] =
+        std::array::from_fn(|i| texels.iter().map(|t| t[i] as i32).sum::<i32>() / 16);
+    for i in 1..3 {
+        let covariance: i32 = texels
+            .iter()
+            .map(|t| (t[0] as i32 - mean[0]) * (t[i] as i32 - mean[i]))
+            .sum();
+        if covariance < 0 {
+            std::mem::swap(&mut lo[i], &mut hi[i]);
+        }
+    }
+    let (mut c0, mut c1) = (pack_565(hi), pack_565(lo));
+    if c0 < c1 {
+        std::mem::swap(&mut c0, &mut c1);
+    }
+    let mut block = [0u8; 8];
+    block[..2].copy_from_slice(&c0.to_le_bytes());
+    block[2..4].copy_from_slice(&c1.to_le_bytes());
+    if c0 == c1 {
+        return block;
+    }
+    let palette = color_palette(c0, c1, true);
+    let distance = |a: [u8; 4], b: [u8; 4]| -> i32 {
+        (0..3).map(|i| (a[i] as i32 - b[i] as i32).pow(2)).sum()
+    };
+    let indices = texels
+        .iter()
+        .enumerate()
+        .fold(0u32, |bits, (texel, color)| {
+            let best = (0..4)
+                .min_by_key(|&i| distance(palette[i], *color))
+                .unwrap_or(0);
+            bits | (best as u32) << (2 * texel)
+        });
+    block[4..].copy_from_slice(&indices.to_le_bytes());
+    block
+}
+
+/// One BC4 channel block with six values between the block's extremes.
+fn encode_channel(values: [u8; 16]) -> [u8; 8] {
+    let (a0, a1) = (
+        values.iter().copied().max().unwrap_or(0),
+        values.iter().copied().min().unwrap_or(0),
+    );
+    let mut block = [a0, a1, 0, 0, 0, 0, 0, 0];
+    if a0 == a1 {
+        return block;
+    }
+    let palette = channel_palette(a0, a1);
+    let indices = values
+        .iter()
+        .enumerate()
+        .fold(0u64, |bits, (texel, value)| {
+            let best = (0..8)
+                .min_by_key(|&i| palette[i].abs_diff(*value))
+                .unwrap_or(0);
+            bits | (best as u64) << (3 * texel)
+        });
+    block[2..].copy_from_slice(&indices.to_le_bytes()[..6]);
+    block
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A KTX2 file with `levels` levels of `vk_format`, level `i` filled with byte `i + 1`.
+    /// The data is stored smallest level first, as the format lays it out.
+    fn ktx2(vk_format: u32, width: u32, height: u32, levels: u32) -> Vec<u8> {
+        let format = FORMATS.iter().find(|(vk, _)| *vk == vk_format).unwrap().1;
+        let lens: Vec<usize> = (0..levels)
+            .map(|level| {
+                let size = |s: u32| (s >> level).max(1);
+                Ktx2Texture::level_len(format, size(width), size(height))
+            })
+            .collect();
+        let mut bytes = IDENTIFIER.to_vec();
+        for field in [vk_format, 1, width, height, 0, 0, 1, levels, 0] {
+            bytes.extend(field.to_le_bytes());
+        }
+        bytes.extend([0; 4 * 4 + 2 * 8]);
+        let mut offset = bytes.len() + 24 * levels as usize;
+        let mut index = vec![0; levels as usize];
+        for level in (0..levels as usize).rev() {
+            index[level] = offset;
+            offset += lens[level];
+        }
+        for (&offset, &len) in index.iter().zip(&lens) {
+            for field in [offset, len, len] {
+                bytes.extend((field as u64).to_le_bytes());
+            }
+        }
+        for level in (0..levels as usize).rev() {
+            bytes.extend(std::iter::repeat(level as u8 + 1).take(lens[level]));
+        }
+        bytes
+    }
+
+    #[test]
+    fn row_pitch_and_level_len_round_up_to_blocks() {
+        use wgpu::TextureFormat::*;
+        // BC1 blocks are 8 bytes, BC3, BC5 and BC7 blocks 16, all 4x4 texels.
+        assert_eq!(Ktx2Texture::row_pitch(Bc1RgbaUnorm, 16), 32);
+        assert_eq!(Ktx2Texture::row_pitch(Bc7RgbaUnorm, 16), 64);
+        assert_eq!(Ktx2Texture::row_pitch(Bc1RgbaUnorm, 6), 16);
+        assert_eq!(Ktx2Texture::row_pitch(Bc5RgUnorm, 1), 16);
+        assert_eq!(Ktx2Texture::block_rows(Bc3RgbaUnorm, 8), 2);
+        assert_eq!(Ktx2Texture::block_rows(Bc3RgbaUnorm, 2), 1);
+        assert_eq!(Ktx2Texture::level_len(Bc1RgbaUnorm, 16, 8), 64);
+        assert_eq!(Ktx2Texture::level_len(Bc7RgbaUnorm, 2, 1), 16);
+    }
+
+    #[test]
+    fn levels_are_read_from_their_offsets() {
+        let texture = Ktx2Texture::parse(&ktx2(145, 16, 8, 5)).unwrap();
+        assert_eq!((texture.width, texture.height), (16, 8));
+        assert_eq!(texture.format, wgpu::TextureFormat::Bc7RgbaUnorm);
+        let sizes: Vec<_> = (0..5).map(|level| texture.level_size(level)).collect();
+        assert_eq!(sizes, [(16, 8), (8, 4), (4, 2), (2, 1), (1, 1)]);
+        let lens: Vec<_> = texture.levels.iter().map(Vec::len).collect();
+        assert_eq!(lens, [128, 32, 16, 16, 16]);
+        for (level, data) in texture.levels.iter().enumerate() {
+            assert!(
+                data.iter().all(|&b| b == level as u8 + 1),
+                "level {}",
+                level
+            );
+        }
+    }
+
+    #[test]
+    fn malformed_files_are_rejected() {
+        let file = ktx2(131, 8, 8, 4);
+        assert!(Ktx2Texture::parse(&file).is_ok());
+        assert!(Ktx2Texture::parse(&file[..file.len() - 1]).is_err());
+        assert!(Ktx2Texture::parse(&file[..60]).is_err());
+        let mut identifier = file.clone();
+        identifier[1] = b'X';
+        assert!(Ktx2Texture::parse(&identifier).is_err());
+        // RGBA8 isn't block compressed.
+        let mut format = file.clone();
+        format[12..16].copy_from_slice(&37u32.to_le_bytes());
+        assert!(Ktx2Texture::parse(&format).is_err());
+        // More levels than an 8x8 mip chain has.
+        assert!(Ktx2Texture::parse(&ktx2(131, 8, 8, 5)).is_err());
+    }
+
+    #[test]
+    fn bc1_decodes_without_the_gpu() {
+        // Endpoints pure red and pure blue, every texel on the first third between them.
+        let mut block = [0u8; 8];
+        block[..2].copy_from_slice(&0xF800u16.to_le_bytes());
+        block[2..4].copy_from_slice(&0x001Fu16.to_le_bytes());
+        block[4..].copy_from_slice(&0xAAAA_AAAAu32.to_le_bytes());
+        let texture = Ktx2Texture {
+            width: 4,
+            height: 4,
+            format: wgpu::TextureFormat::Bc1RgbaUnorm,
+            levels: vec![block.to_vec()],
+        };
+        let decoded = texture.decompress().unwrap();
+        assert_eq!(decoded.mips[0].len(), 64);
+        assert!(decoded.mips[0]
+            .chunks(4)
+            .all(|texel| texel == [170, 0, 85, 255]));
+    }
+
+    fn imported(width: u32, height: u32, texel: impl Fn(u32, u32) -> [u8; 4]) -> ImportedTexture {
+        let image = image::RgbaImage::from_fn(width, height, |x, y| image::Rgba(texel(x, y)));
+        ImportedTexture::from_image(&image, true, true)
+    }
+
+    fn max_error(a: &[u8], b: &[u8]) -> u8 {
+        a.iter()
+            .zip(b)
+            .map(|(a, b)| a.abs_diff(*b))
+            .max()
+            .unwrap_or(0)
+    }
+
+    #[test]
+    fn compressed_textures_round_trip_through_a_file() {
+        // Green falls as red rises, so the endpoints have to take the other diagonal.
+        let source = imported(16, 8, |x, y| {
+            let v = ((x + y) * 8) as u8;
+            [v, 255 - v, v / 2, 255]
+        });
+        let compressed = Ktx2Texture::compress(&source).unwrap();
+        assert_eq!(compressed.format, wgpu::TextureFormat::Bc1RgbaUnormSrgb);
+        assert_eq!(compressed.levels.len(), source.mips.len());
+
+        let file = compressed.encode();
+        assert_eq!(Ktx2Texture::parse(&file).unwrap(), compressed);
+        let decoded = compressed.decompress().unwrap();
+        assert_eq!((decoded.width, decoded.height, decoded.srgb), (16, 8, true));
+        let error = max_error(&decoded.mips[0], &source.mips[0]);
+        assert!(error <= 16, "base level off by {}", error);
+    }
+
+    #[test]
+    fn alpha_compresses_to_bc3() {
+        let source = imported(4, 4, |x, y| [200, 100, 50, ((y * 4 + x) * 16) as u8]);
+        let compressed = Ktx2Texture::compress(&source).unwrap();
+        assert_eq!(compressed.format, wgpu::TextureFormat::Bc3RgbaUnormSrgb);
+        let file = compressed.encode();
+        assert_eq!(Ktx2Texture::parse(&file).unwrap(), compressed);
+        let decoded = compressed.decompress().unwrap();
+        for (decoded, source) in decoded.mips[0].chunks(4).zip(source.mips[0].chunks(4)) {
+            // A flat color only loses the 5:6:5 rounding.
+            assert!(max_error(&decoded[..3], &source[..3]) <= 4);
+            assert!(decoded[3].abs_diff(source[3]) <= 18);
+        }
+    }
+
+    #[test]
+    fn sizes_that_are_not_whole_blocks_stay_uncompressed() {
+        assert!(Ktx2Texture::compress(&imported(6, 4, |_, _| [0; 4])).is_none());
+        assert!(Ktx2Texture::compress(&imported(4, 4, |_, _| [0; 4])).is_some());
+    }
+
+    #[test]
+    fn written_files_describe_their_format() {
+        let compressed =
+            Ktx2Texture::compress(&imported(8, 8, |x, _| [x as u8 * 30, 0, 0, 255])).unwrap();
+        let file = compressed.encode();
+        let field = |at: usize| u32::from_le_bytes(file[at..at + 4].try_into().unwrap());
+        // vkFormat BC1 RGB sRGB, then the descriptor offset and length.
+        assert_eq!(field(12), 132);
+        let (dfd_offset, dfd_len) = (field(48) as usize, field(52) as usize);
+        assert_eq!(dfd_len, 4 + 24 + 16);
+        assert_eq!(field(dfd_offset), dfd_len as u32);
+        // BC1A color model, BT.709 primaries, sRGB transfer, 8-byte 4x4 blocks.
+        assert_eq!(file[dfd_offset + 12..dfd_offset + 15], [128, 1, 2]);
+        assert_eq!(file[dfd_offset + 16..dfd_offset + 18], [3, 3]);
+        assert_eq!(file[dfd_offset + 20], 8);
+    }
+}
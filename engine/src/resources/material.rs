@@ -2,10 +2,13 @@ use crate::{
     log_debug, log_info, log_warning, CacheKey, CacheStorage, EngineError, PipelineManager, Shader,
     ShaderManager, WgpuBuffer,
 };
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::Arc,
+};
 use wgpu::BufferUsages;
 
-use super::{BindGroup, HashCache, Texture, TextureManager};
+use super::{BindGroup, HashCache, MaterialLibrary, Texture, TextureManager};
 
 #[derive(Clone, Debug)]
 pub struct MaterialAsset {
@@ -18,10 +21,19 @@ pub struct MaterialAsset {
     pub shininess: f32,
     pub diffuse_texture: Option<String>,
     pub normal_texture: Option<String>,
+    pub diffuse_sampler: super::SamplerDesc,
+    pub normal_sampler: super::SamplerDesc,
     pub primitive: wgpu::PrimitiveState,
     pub depth_stencil: Option<wgpu::DepthStencilState>,
     pub color_target: wgpu::ColorTargetState,
+    /// Drawn after opaque geometry, sorted back to front, without writing depth.
+    pub transparent: bool,
+    /// Reflects the environment, see [`MaterialData::NO_REFLECTIONS`].
+    pub reflections: bool,
     pub bind_group_layouts: Vec<wgpu::BindGroupLayout>,
+    /// Override constants for this material's pipelines, on top of
+    /// [`crate::RenderSettings::shader_constants`].
+    pub constants: crate::ShaderConstants,
 }
 
 #[repr(C)]
@@ -32,11 +44,16 @@ pub struct MaterialData {
     pub diffuse: [f32; 3],
     pub _pad1: f32,
     pub specular: [f32; 3],
-    pub _pad2: f32,
     pub shininess: f32,
-    pub _pad3: [f32; 3],
+    /// [`MaterialData::NO_REFLECTIONS`].
+    pub flags: u32,
+    pub _pad2: [u32; 3],
 }
 impl MaterialData {
+    /// Leaves the environment reflection out, for surfaces like terrain where it reads as
+    /// a sheen rather than a reflection.
+    pub const NO_REFLECTIONS: u32 = 1;
+
     pub fn bytes(&self) -> &[u8] {
         bytemuck::bytes_of(self)
     }
@@ -54,6 +71,8 @@ impl From<tobj::Material> for MaterialAsset {
             shininess: value.shininess.unwrap_or_default(),
             diffuse_texture: value.diffuse_texture,
             normal_texture: value.normal_texture,
+            diffuse_sampler: super::SamplerDesc::default(),
+            normal_sampler: super::SamplerDesc::default(),
             primitive: wgpu::PrimitiveState::default(),
             depth_stencil: None,
             color_target: wgpu::ColorTargetState {
@@ -61,7 +80,10 @@ impl From<tobj::Material> for MaterialAsset {
                 blend: None,
                 write_mask: wgpu::ColorWrites::default(),
             },
+            transparent: false,
+            reflections: true,
             bind_group_layouts: Vec::new(),
+            constants: crate::ShaderConstants::default(),
         }
     }
 }
@@ -78,6 +100,8 @@ impl From<&tobj::Material> for MaterialAsset {
             shininess: value.shininess.unwrap_or_default(),
             diffuse_texture: value.diffuse_texture.clone(),
             normal_texture: value.normal_texture.clone(),
+            diffuse_sampler: super::SamplerDesc::default(),
+            normal_sampler: super::SamplerDesc::default(),
             primitive: wgpu::PrimitiveState::default(),
             depth_stencil: None,
             color_target: wgpu::ColorTargetState {
@@ -85,12 +109,52 @@ impl From<&tobj::Material> for MaterialAsset {
                 blend: None,
                 write_mask: wgpu::ColorWrites::default(),
             },
+            transparent: false,
+            reflections: true,
             bind_group_layouts: Vec::new(),
+            constants: crate::ShaderConstants::default(),
         }
     }
 }
 
 impl MaterialAsset {
+    /// Maps metallic-roughness onto the engine's Blinn-Phong terms: metals tint their
+    /// highlight with the base color, and rougher surfaces get a broader one. The name is
+    /// prefixed with `file` since glTF names are only unique within a file.
+    pub fn from_gltf(file: &str, material: &crate::GltfMaterial) -> Self {
+        let name = format!("{}#{}", file, material.name);
+        let [r, g, b, _] = material.base_color;
+        let specular = [r, g, b].map(|c| 0.04 + (c - 0.04) * material.metallic);
+        let roughness = material.roughness.clamp(0.05, 1.0);
+        Self {
+            key: CacheKey::from(name.clone()),
+            name,
+            shader: Shader::DEFAULT.to_string(),
+            ambient: [r * 0.1, g * 0.1, b * 0.1],
+            diffuse: [r, g, b].map(|c| c * (1.0 - material.metallic)),
+            specular,
+            shininess: (2.0 / (roughness * roughness) - 2.0).clamp(1.0, 256.0),
+            diffuse_texture: material
+                .base_color_texture
+                .map(|i| crate::GltfModel::image_name(file, i)),
+            normal_texture: material
+                .normal_texture
+                .map(|i| crate::GltfModel::image_name(file, i)),
+            diffuse_sampler: super::SamplerDesc::default(),
+            normal_sampler: super::SamplerDesc::default(),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            color_target: wgpu::ColorTargetState {
+                format: Texture::DEFAULT_FORMAT,
+                blend: None,
+                write_mask: wgpu::ColorWrites::default(),
+            },
+            transparent: material.blend,
+            reflections: true,
+            bind_group_layouts: Vec::new(),
+            constants: crate::ShaderConstants::default(),
+        }
+    }
     pub fn data(&self) -> MaterialData {
         MaterialData {
             ambient: self.ambient,
@@ -98,9 +162,13 @@ impl MaterialAsset {
             diffuse: self.diffuse,
             _pad1: 0.0,
             specular: self.specular,
-            _pad2: 0.0,
             shininess: self.shininess,
-            _pad3: [0.0; 3],
+            flags: if self.reflections {
+                0
+            } else {
+                MaterialData::NO_REFLECTIONS
+            },
+            _pad2: [0; 3],
         }
     }
     pub fn buffer(&self, queue: &wgpu::Queue, device: &wgpu::Device, idx: u64) -> WgpuBuffer {
@@ -123,7 +191,7 @@ impl MaterialAsset {
         let white_pixel = [255u8, 255, 255, 255];
 
         let diffuse_cache_key = CacheKey::from("fallback_diffuse_texture");
-        if let Some(cached_diffuse_fallback) = textures.get(diffuse_cache_key) {
+        if let Some(cached_diffuse_fallback) = textures.get(&diffuse_cache_key) {
             (cached_diffuse_fallback.clone(), diffuse_cache_key)
         } else {
             let diffuse = crate::Texture::from_desc(
@@ -175,7 +243,7 @@ impl MaterialAsset {
         let flat_normal = [128u8, 128, 255, 255];
 
         let normal_cache_key = CacheKey::from("fallback_normal_texture");
-        if let Some(cached_normal_fallback) = textures.get(normal_cache_key) {
+        if let Some(cached_normal_fallback) = textures.get(&normal_cache_key) {
             (cached_normal_fallback.clone(), normal_cache_key)
         } else {
             let normal = crate::Texture::from_desc(
@@ -235,34 +303,148 @@ impl MaterialAsset {
         ),
         crate::EngineError,
     > {
-        let (dt, ..) = self
-            .diffuse_texture
-            .as_ref()
-            .map(|p| textures.get_or_load_texture(queue, device, p, surface_configuration))
-            .unwrap_or_else(|| Ok(Self::fallback_diffuse(queue, device, textures)))?;
-        let (nt, ..) = self
-            .normal_texture
-            .as_ref()
-            .map(|p| textures.get_or_load_texture(queue, device, p, surface_configuration))
-            .unwrap_or_else(|| Ok(Self::fallback_normal(queue, device, textures)))?;
+        let bind_group = self.texture_bind_group(queue, device, textures, surface_configuration)?;
+        let pipeline = self.pipeline(device, shaders, pipelines, buffers)?;
 
-        let shader = shaders.load(device, &self.shader)?;
-        let bgl_refs: Vec<&wgpu::BindGroupLayout> = self.bind_group_layouts.iter().collect();
+        Ok((pipeline, bind_group))
+    }
+    /// Diffuse/normal bind group. Textures still decoding in the background are bound as
+    /// the white or flat fallback until [`crate::ModelManager::apply_loaded_textures`].
+    pub fn texture_bind_group(
+        &self,
+        queue: &wgpu::Queue,
+        device: &wgpu::Device,
+        textures: &mut TextureManager,
+        surface_configuration: &wgpu::SurfaceConfiguration,
+    ) -> Result<Arc<wgpu::BindGroup>, crate::EngineError> {
+        let (dt, ..) = match &self.diffuse_texture {
+            Some(p) => textures.request_texture(queue, device, p, surface_configuration, |t| {
+                Self::fallback_diffuse(queue, device, t)
+            })?,
+            None => Self::fallback_diffuse(queue, device, textures),
+        };
+        let (nt, ..) = match &self.normal_texture {
+            Some(p) => textures.request_texture(queue, device, p, surface_configuration, |t| {
+                Self::fallback_normal(queue, device, t)
+            })?,
+            None => Self::fallback_normal(queue, device, textures),
+        };
 
-        let bind_group = Arc::new(crate::BindGroup::normal(
+        let diffuse_sampler = textures.samplers.get(device, &self.diffuse_sampler);
+        let normal_sampler = textures.samplers.get(device, &self.normal_sampler);
+        Ok(Arc::new(crate::BindGroup::normal(
             device,
-            &dt,
-            &nt,
+            (&dt, &diffuse_sampler),
+            (&nt, &normal_sampler),
             format!("{}_texture_binding", &self.name).as_ref(),
-        ));
+        )))
+    }
+    /// Whether the diffuse or normal texture is cached under `key`.
+    pub fn uses_texture(&self, key: &CacheKey) -> bool {
+        [&self.diffuse_texture, &self.normal_texture]
+            .into_iter()
+            .flatten()
+            .any(|p| CacheKey::from(p.to_string()) == *key)
+    }
+    /// Global and material override constants the shader declares; material constants it
+    /// doesn't declare are warned about and dropped.
+    pub fn resolved_constants(
+        &self,
+        shaders: &mut ShaderManager,
+    ) -> Result<crate::ShaderConstants, EngineError> {
+        let declared = shaders.overrides(&self.shader)?;
+        let global = crate::RenderSettings::shader_constants().retain_declared(declared);
+        let own = self.constants.declared_by(&self.shader, declared);
+        Ok(global.merged(&own))
+    }
+    /// Pipeline cache label: name, shader, object path `variant`, MSAA sample count and
+    /// override set, so each distinct combination gets its own pipeline.
+    pub fn pipeline_label(
+        &self,
+        constants: &crate::ShaderConstants,
+        variant: Option<&str>,
+    ) -> String {
+        let variant = variant.map(|v| format!("_{}", v)).unwrap_or_default();
+        let samples = match crate::RenderSettings::sample_count() {
+            1 => String::new(),
+            count => format!("_msaa{}", count),
+        };
+        format!(
+            "{}_{}{}{}{}",
+            self.name,
+            self.shader,
+            variant,
+            samples,
+            constants.cache_suffix()
+        )
+    }
+    /// Pipeline for this asset, cached by name, shader and override constants. Doesn't touch
+    /// textures, so materials with their own bind group (e.g. blended terrain) build through
+    /// this too.
+    pub fn pipeline(
+        &self,
+        device: &wgpu::Device,
+        shaders: &mut ShaderManager,
+        pipelines: &mut PipelineManager,
+        buffers: &[wgpu::VertexBufferLayout<'_>],
+    ) -> Result<Arc<wgpu::RenderPipeline>, EngineError> {
+        let depth_stencil = self.depth_stencil.clone();
+        self.build_pipeline(device, shaders, pipelines, buffers, None, depth_stencil)
+    }
+    /// Like [`MaterialAsset::pipeline`] for the main pass after a [`crate::DepthPrepass`]:
+    /// depth is tested for equality with the pre-pass and not written.
+    pub fn prepassed_pipeline(
+        &self,
+        device: &wgpu::Device,
+        shaders: &mut ShaderManager,
+        pipelines: &mut PipelineManager,
+        buffers: &[wgpu::VertexBufferLayout<'_>],
+    ) -> Result<Arc<wgpu::RenderPipeline>, EngineError> {
+        let depth_stencil = self
+            .depth_stencil
+            .as_ref()
+            .map(|_| crate::RenderSettings::depth_state(crate::DepthVariant::AfterPrepass));
+        self.build_pipeline(
+            device,
+            shaders,
+            pipelines,
+            buffers,
+            Some(Self::PREPASSED_VARIANT),
+            depth_stencil,
+        )
+    }
+    const PREPASSED_VARIANT: &'static str = "prepassed";
+    fn build_pipeline(
+        &self,
+        device: &wgpu::Device,
+        shaders: &mut ShaderManager,
+        pipelines: &mut PipelineManager,
+        buffers: &[wgpu::VertexBufferLayout<'_>],
+        variant: Option<&str>,
+        depth_stencil: Option<wgpu::DepthStencilState>,
+    ) -> Result<Arc<wgpu::RenderPipeline>, EngineError> {
+        let resolved = self.resolved_constants(shaders)?;
+        let constants = resolved.to_map();
+        let pipeline_label = self.pipeline_label(&resolved, variant);
+        let shader = shaders.load(device, &self.shader)?;
+        let bgl_refs: Vec<&wgpu::BindGroupLayout> = self.bind_group_layouts.iter().collect();
+
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: Some(&self.name),
             bind_group_layouts: &bgl_refs,
             push_constant_ranges: &[],
         });
 
-        let pipeline_label = format!("{}_{}", self.name, self.shader);
         let pipeline_cache_key = crate::CacheKey::from(pipeline_label.clone());
+        crate::gpu_scope!(
+            Pipeline,
+            pipeline_label,
+            format!(
+                "shader {}, key {}",
+                crate::AssetPaths::shader(&self.shader).display(),
+                pipeline_cache_key.id()
+            )
+        );
 
         let pipeline = pipelines
             .render
@@ -275,32 +457,196 @@ impl MaterialAsset {
                             module: &shader,
                             entry_point: Some("vs_main"),
                             buffers,
-                            compilation_options: Default::default(),
+                            compilation_options: wgpu::PipelineCompilationOptions {
+                                constants: &constants,
+                                ..Default::default()
+                            },
                         },
                         fragment: Some(wgpu::FragmentState {
                             module: &shader,
                             entry_point: Some("fs_main"),
                             targets: &[Some(self.color_target.clone())],
-                            compilation_options: Default::default(),
+                            compilation_options: wgpu::PipelineCompilationOptions {
+                                constants: &constants,
+                                ..Default::default()
+                            },
                         }),
                         primitive: self.primitive,
-                        depth_stencil: self.depth_stencil.clone(),
+                        depth_stencil,
+
+                        multisample: crate::RenderSettings::multisample(),
+                        multiview: None,
+                        cache: None,
+                    }),
+                )
+            })
+            .clone();
+        pipelines.track(&self.shader, pipeline_cache_key);
 
-                        multisample: wgpu::MultisampleState {
-                            count: 1,
-                            mask: !0,
-                            alpha_to_coverage_enabled: false,
+        Ok(pipeline)
+    }
+    /// Pipeline drawing this asset through the object path: vertices only, with the
+    /// per-object data bound next to the materials in group 2. `None` when the shader has
+    /// no [`crate::OBJECT_VERTEX_FN`] or the asset doesn't use the standard four groups.
+    pub fn object_pipeline(
+        &self,
+        device: &wgpu::Device,
+        shaders: &mut ShaderManager,
+        pipelines: &mut PipelineManager,
+        indexing: crate::ObjectIndexing,
+    ) -> Result<Option<Arc<wgpu::RenderPipeline>>, EngineError> {
+        self.variant_pipeline(
+            device,
+            shaders,
+            pipelines,
+            VariantPipeline {
+                variant: indexing.variant(),
+                requires: crate::OBJECT_VERTEX_FN,
+                append: indexing.shader_entry(),
+                entry_point: "vs_object",
+                vertex: crate::Vertex::LAYOUT,
+                group2: crate::RenderBindGroupLayouts::object_data(indexing),
+                push_constant_ranges: indexing.push_constant_ranges(),
+            },
+        )
+    }
+    /// Pipeline drawing this asset skinned: [`crate::VertexSkinned`] vertices with an
+    /// entity's joint matrices bound next to the materials in group 2. `None` under the
+    /// same conditions as [`MaterialAsset::object_pipeline`].
+    pub fn skinned_pipeline(
+        &self,
+        device: &wgpu::Device,
+        shaders: &mut ShaderManager,
+        pipelines: &mut PipelineManager,
+    ) -> Result<Option<Arc<wgpu::RenderPipeline>>, EngineError> {
+        self.variant_pipeline(
+            device,
+            shaders,
+            pipelines,
+            VariantPipeline {
+                variant: "skinned",
+                requires: crate::OBJECT_VERTEX_FN,
+                append: crate::SKINNED_ENTRY,
+                entry_point: "vs_skinned",
+                vertex: crate::VertexSkinned::LAYOUT,
+                group2: crate::RenderBindGroupLayouts::joints(),
+                push_constant_ranges: &[],
+            },
+        )
+    }
+    /// Pipeline drawing this asset's instances fetched from storage: vertices only, with
+    /// the frame's instance buffer bound next to the materials in group 2. `None` when the
+    /// shader has no [`crate::INSTANCE_VERTEX_FN`] or the asset doesn't use the standard
+    /// four groups.
+    pub fn instance_storage_pipeline(
+        &self,
+        device: &wgpu::Device,
+        shaders: &mut ShaderManager,
+        pipelines: &mut PipelineManager,
+    ) -> Result<Option<Arc<wgpu::RenderPipeline>>, EngineError> {
+        self.variant_pipeline(
+            device,
+            shaders,
+            pipelines,
+            VariantPipeline {
+                variant: crate::InstanceFetch::VARIANT,
+                requires: crate::INSTANCE_VERTEX_FN,
+                append: crate::InstanceFetch::SHADER_ENTRY,
+                entry_point: "vs_instance_storage",
+                vertex: crate::Vertex::LAYOUT,
+                group2: crate::RenderBindGroupLayouts::instance_storage(),
+                push_constant_ranges: &[],
+            },
+        )
+    }
+    /// A pipeline for a shader variant built on the function `desc.requires` names, which
+    /// swaps group 2 and the vertex entry point and keeps everything else of the asset.
+    fn variant_pipeline(
+        &self,
+        device: &wgpu::Device,
+        shaders: &mut ShaderManager,
+        pipelines: &mut PipelineManager,
+        desc: VariantPipeline,
+    ) -> Result<Option<Arc<wgpu::RenderPipeline>>, EngineError> {
+        if self.bind_group_layouts.len() != 4 {
+            return Ok(None);
+        }
+        let Some(shader) = shaders.load_variant(
+            device,
+            &self.shader,
+            desc.variant,
+            desc.requires,
+            desc.append,
+        )?
+        else {
+            return Ok(None);
+        };
+        let mut bgl_refs: Vec<&wgpu::BindGroupLayout> = self.bind_group_layouts.iter().collect();
+        bgl_refs[2] = desc.group2;
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some(&format!("{} {}", self.name, desc.variant)),
+            bind_group_layouts: &bgl_refs,
+            push_constant_ranges: desc.push_constant_ranges,
+        });
+
+        let resolved = self.resolved_constants(shaders)?;
+        let constants = resolved.to_map();
+        let pipeline_label = self.pipeline_label(&resolved, Some(desc.variant));
+        let pipeline_cache_key = crate::CacheKey::from(pipeline_label.clone());
+        let pipeline = pipelines
+            .render
+            .get_or_create(pipeline_cache_key, || {
+                Arc::new(
+                    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                        label: Some(&pipeline_label),
+                        layout: Some(&pipeline_layout),
+                        vertex: wgpu::VertexState {
+                            module: &shader,
+                            entry_point: Some(desc.entry_point),
+                            buffers: &[desc.vertex],
+                            compilation_options: wgpu::PipelineCompilationOptions {
+                                constants: &constants,
+                                ..Default::default()
+                            },
                         },
+                        fragment: Some(wgpu::FragmentState {
+                            module: &shader,
+                            entry_point: Some("fs_main"),
+                            targets: &[Some(self.color_target.clone())],
+                            compilation_options: wgpu::PipelineCompilationOptions {
+                                constants: &constants,
+                                ..Default::default()
+                            },
+                        }),
+                        primitive: self.primitive,
+                        depth_stencil: self.depth_stencil.clone(),
+
+                        multisample: crate::RenderSettings::multisample(),
                         multiview: None,
                         cache: None,
                     }),
                 )
             })
             .clone();
+        pipelines.track(&self.shader, pipeline_cache_key);
 
-        Ok((pipeline, bind_group))
+        Ok(Some(pipeline))
     }
 }
+
+/// What a shader variant changes about a material's pipeline.
+struct VariantPipeline {
+    variant: &'static str,
+    /// Function the shader must define to have the variant, e.g. [`crate::OBJECT_VERTEX_FN`].
+    requires: &'static str,
+    append: &'static str,
+    entry_point: &'static str,
+    vertex: wgpu::VertexBufferLayout<'static>,
+    group2: &'static wgpu::BindGroupLayout,
+    push_constant_ranges: &'static [wgpu::PushConstantRange],
+}
+
 #[derive(Debug)]
 pub struct Material {
     pub asset: MaterialAsset,
@@ -433,9 +779,18 @@ pub struct MaterialManager {
     pub materials: HashCache<Arc<Material>>,
     pub storage_buffer: WgpuBuffer,
     pub storage_bind_group: wgpu::BindGroup,
-    pub storage: HashMap<String, MaterialData>,
+    /// Shader-side parameters, indexed by [`Material::idx`].
+    pub storage: Vec<MaterialData>,
+    /// Storage index of each material name.
+    pub storage_names: HashMap<String, u32>,
     pub storage_rebuild: bool,
     pub storage_count: usize,
+    /// Entries the storage buffer holds; later ones wait for [`MaterialManager::build_storage`].
+    storage_capacity: usize,
+    /// Entries changed since the last [`MaterialManager::flush`].
+    storage_dirty: BTreeSet<u32>,
+    pub library: MaterialLibrary,
+    constants_generation: u64,
 }
 
 impl MaterialManager {
@@ -445,7 +800,7 @@ impl MaterialManager {
         let storage_buffer = WgpuBuffer::from_data(
             device,
             data,
-            BufferUsages::STORAGE | BufferUsages::COPY_DST,
+            BufferUsages::STORAGE | BufferUsages::COPY_DST | BufferUsages::COPY_SRC,
             Some(&format!("batched material storage buffer")),
         );
         let storage_bind_group = BindGroup::material_storage(
@@ -460,9 +815,14 @@ impl MaterialManager {
             materials: HashCache::new(),
             storage_buffer,
             storage_bind_group,
-            storage: HashMap::new(),
+            storage: Vec::new(),
+            storage_names: HashMap::new(),
             storage_rebuild: false,
             storage_count: 0,
+            storage_capacity: mat_data.len(),
+            storage_dirty: BTreeSet::new(),
+            library: MaterialLibrary::new(),
+            constants_generation: crate::RenderSettings::constants_generation(),
         }
     }
 
@@ -474,23 +834,66 @@ impl MaterialManager {
     }
     pub fn build_storage(&mut self, device: &wgpu::Device) {
         let label = "storage buffer";
-        let usage = BufferUsages::STORAGE | BufferUsages::COPY_DST;
-        let data: Vec<MaterialData> = self.storage.values().map(|m| m.clone()).collect();
-        if !data.is_empty() {
-            let storage = WgpuBuffer::from_data(device, &data, usage, Some(label));
+        let usage = BufferUsages::STORAGE | BufferUsages::COPY_DST | BufferUsages::COPY_SRC;
+        if !self.storage.is_empty() {
+            let storage = WgpuBuffer::from_data(device, &self.storage, usage, Some(label));
             let binding = BindGroup::material_storage(device, &storage, Some(label));
             self.storage_bind_group = binding;
             self.storage_buffer = storage;
             self.storage_rebuild = false;
-            self.storage_count = data.len();
+            self.storage_capacity = self.storage.len();
+            self.storage_dirty.clear();
             log_debug!("Storage rebuilt");
         }
     }
+    /// Puts `material`'s parameters in its storage slot. Slots the buffer already holds are
+    /// written by the next [`MaterialManager::flush`]; others need a rebuild.
     pub fn update_storage(&mut self, material: &Material) {
-        self.storage_rebuild = self
-            .storage
-            .insert(material.asset.name.clone(), material.asset.data())
-            .is_none();
+        self.storage_names
+            .insert(material.asset.name.clone(), material.idx);
+        self.set_storage(material.idx, |data| *data = material.asset.data());
+    }
+    /// Edits the parameters of the material cached under `key` in place. Returns `false`
+    /// when there's no such material.
+    pub fn set_param(&mut self, key: &CacheKey, f: impl FnOnce(&mut MaterialData)) -> bool {
+        let Some(idx) = self.materials.get(key).map(|m| m.idx) else {
+            return false;
+        };
+        self.set_storage(idx, f);
+        true
+    }
+    /// Edits storage slot `idx`, growing the storage up to it, and marks it dirty.
+    pub fn set_storage(&mut self, idx: u32, f: impl FnOnce(&mut MaterialData)) {
+        let slot = idx as usize;
+        if slot >= self.storage.len() {
+            self.storage.resize(slot + 1, MaterialData::default());
+        }
+        f(&mut self.storage[slot]);
+        if slot < self.storage_capacity {
+            self.storage_dirty.insert(idx);
+        } else {
+            self.storage_rebuild = true;
+        }
+    }
+    /// Writes the slots edited since the last flush to the storage buffer, one write per run
+    /// of neighbouring slots. The buffer and its bind group are kept.
+    pub fn flush(&mut self, queue: &wgpu::Queue) {
+        let stride = std::mem::size_of::<MaterialData>();
+        let mut dirty = std::mem::take(&mut self.storage_dirty)
+            .into_iter()
+            .peekable();
+        while let Some(start) = dirty.next() {
+            let mut end = start + 1;
+            while dirty.next_if_eq(&end).is_some() {
+                end += 1;
+            }
+            let range = start as usize..end as usize;
+            queue.write_buffer(
+                self.storage_buffer.get(),
+                (range.start * stride) as u64,
+                bytemuck::cast_slice(&self.storage[range]),
+            );
+        }
     }
 
     pub fn load_tobj<'a>(
@@ -620,4 +1023,299 @@ impl MaterialManager {
 
         Ok(material)
     }
+    /// Like [`MaterialManager::load_asset`] for materials that bring their own group 3 bind
+    /// group instead of a diffuse/normal pair.
+    pub fn load_asset_with_bind_group(
+        &mut self,
+        device: &wgpu::Device,
+        asset: crate::MaterialAsset,
+        bind_group: Arc<wgpu::BindGroup>,
+        buffers: &[wgpu::VertexBufferLayout<'_>],
+    ) -> Result<Arc<Material>, EngineError> {
+        if let Some(mat) = self.materials.get(&asset.key) {
+            return Ok(mat.clone());
+        }
+        let pipeline = asset.pipeline(device, &mut self.shaders, &mut self.pipelines, buffers)?;
+        let idx = self.create_storage_idx();
+        let material = Arc::new(Material {
+            asset: asset.clone(),
+            pipeline,
+            bind_group,
+            idx,
+        });
+        self.materials.insert(asset.key, material.clone());
+        self.update_storage(&material);
+
+        Ok(material)
+    }
+
+    fn build_library_material(
+        &mut self,
+        queue: &wgpu::Queue,
+        device: &wgpu::Device,
+        name: &str,
+        surface_configuration: &wgpu::SurfaceConfiguration,
+        idx: u32,
+    ) -> Result<Material, EngineError> {
+        let file = self.library.get(name).ok_or_else(|| {
+            EngineError::AssetMissing(self.library.path_of(name).display().to_string())
+        })?;
+        let asset = file.to_asset(
+            name,
+            surface_configuration,
+            self.library.depth_stencil().cloned(),
+            vec![
+                crate::RenderBindGroupLayouts::uniform().clone(),
+                crate::RenderBindGroupLayouts::equirect_dst().clone(),
+                crate::RenderBindGroupLayouts::material_storage().clone(),
+                crate::RenderBindGroupLayouts::normal().clone(),
+            ],
+        );
+        Material::from_asset(
+            queue,
+            device,
+            &mut self.textures,
+            &mut self.shaders,
+            &mut self.pipelines,
+            surface_configuration,
+            &[crate::Vertex::LAYOUT, crate::VertexInstance::LAYOUT],
+            asset,
+            idx,
+        )
+    }
+    /// Reads every `.mat.ron` definition, builds it keyed by name and starts watching the
+    /// directory for edits. Returns the number of materials built.
+    pub fn load_library(
+        &mut self,
+        queue: &wgpu::Queue,
+        device: &wgpu::Device,
+        surface_configuration: &wgpu::SurfaceConfiguration,
+        depth_stencil: Option<wgpu::DepthStencilState>,
+    ) -> Result<usize, EngineError> {
+        self.library.set_depth_stencil(depth_stencil);
+        let mut count = 0;
+        for name in self.library.scan()? {
+            let idx = self.create_storage_idx();
+            match self.build_library_material(queue, device, &name, surface_configuration, idx) {
+                Ok(material) => {
+                    let material = Arc::new(material);
+                    self.materials
+                        .insert(CacheKey::from(name.as_str()), material.clone());
+                    self.update_storage(&material);
+                    count += 1;
+                }
+                Err(e) => log_warning!("{}: {}", name, e),
+            }
+        }
+        if let Err(e) = self.library.watch() {
+            log_warning!("Material library watcher: {}", e);
+        }
+        Ok(count)
+    }
+    /// Returns the library material `name`, reading its definition if it wasn't loaded at startup.
+    pub fn library_material(
+        &mut self,
+        queue: &wgpu::Queue,
+        device: &wgpu::Device,
+        name: &str,
+        surface_configuration: &wgpu::SurfaceConfiguration,
+    ) -> Result<Arc<Material>, EngineError> {
+        let key = CacheKey::from(name);
+        if let Some(mat) = self.materials.get(&key) {
+            return Ok(mat.clone());
+        }
+        if self.library.get(name).is_none() {
+            self.library.read(name)?;
+        }
+        let idx = self.create_storage_idx();
+        let material = Arc::new(self.build_library_material(
+            queue,
+            device,
+            name,
+            surface_configuration,
+            idx,
+        )?);
+        self.materials.insert(key, material.clone());
+        self.update_storage(&material);
+        Ok(material)
+    }
+    /// Rebuilds library materials whose files changed since the last call, keeping their
+    /// storage index. Callers rebind the returned materials on anything that holds them.
+    pub fn reload_library(
+        &mut self,
+        queue: &wgpu::Queue,
+        device: &wgpu::Device,
+        surface_configuration: &wgpu::SurfaceConfiguration,
+    ) -> Vec<Arc<Material>> {
+        let mut reloaded = Vec::new();
+        for name in self.library.take_changed() {
+            let key = CacheKey::from(name.as_str());
+            let previous = self.materials.get(&key).cloned();
+            if let Err(e) = self.library.read(&name) {
+                log_warning!("Keeping previous '{}': {}", name, e);
+                continue;
+            }
+            let idx = match &previous {
+                Some(previous) => {
+                    let pipeline_key = CacheKey::from(format!(
+                        "{}_{}",
+                        previous.asset.name, previous.asset.shader
+                    ));
+                    self.pipelines.render.remove(&pipeline_key);
+                    previous.idx
+                }
+                None => self.create_storage_idx(),
+            };
+            match self.build_library_material(queue, device, &name, surface_configuration, idx) {
+                Ok(material) => {
+                    let material = Arc::new(material);
+                    self.materials.insert(key, material.clone());
+                    self.update_storage(&material);
+                    log_info!("Reloaded material: {}", name);
+                    reloaded.push(material);
+                }
+                Err(e) => log_warning!("Keeping previous '{}': {}", name, e),
+            }
+        }
+        if self.storage_rebuild {
+            self.build_storage(device);
+        }
+        reloaded
+    }
+    /// Rebuilds every cached material whose pipeline tests depth with the depth state of
+    /// the active [`crate::DepthPolicy`], reusing the retained asset descriptors.
+    pub fn rebuild_depth_pipelines(&mut self, device: &wgpu::Device) -> Vec<Arc<Material>> {
+        if self.library.depth_stencil().is_some() {
+            self.library
+                .set_depth_stencil(Some(crate::RenderSettings::depth_state(
+                    crate::DepthVariant::Opaque,
+                )));
+        }
+        let previous: Vec<(CacheKey, Arc<Material>)> = self
+            .materials
+            .iter()
+            .filter(|(_, m)| m.asset.depth_stencil.is_some())
+            .map(|(k, m)| (*k, m.clone()))
+            .collect();
+        let mut rebuilt = Vec::new();
+        for (key, previous) in previous {
+            let mut asset = previous.asset.clone();
+            let variant = match &asset.depth_stencil {
+                Some(ds) if !ds.depth_write_enabled => crate::DepthVariant::Transparent,
+                _ => crate::DepthVariant::Opaque,
+            };
+            asset.depth_stencil = Some(crate::RenderSettings::depth_state(variant));
+            let constants = asset
+                .resolved_constants(&mut self.shaders)
+                .unwrap_or_default();
+            self.pipelines
+                .render
+                .remove(&CacheKey::from(asset.pipeline_label(&constants, None)));
+            let variants = [
+                crate::ObjectIndexing::PushConstants.variant(),
+                crate::ObjectIndexing::DynamicOffset.variant(),
+                MaterialAsset::PREPASSED_VARIANT,
+            ];
+            for variant in variants {
+                self.pipelines.render.remove(&CacheKey::from(
+                    asset.pipeline_label(&constants, Some(variant)),
+                ));
+            }
+            // Textures don't depend on the depth convention; keep the bind group.
+            match asset.pipeline(
+                device,
+                &mut self.shaders,
+                &mut self.pipelines,
+                &[crate::Vertex::LAYOUT, crate::VertexInstance::LAYOUT],
+            ) {
+                Ok(pipeline) => {
+                    let material = Arc::new(Material {
+                        asset,
+                        pipeline,
+                        bind_group: previous.bind_group.clone(),
+                        idx: previous.idx,
+                    });
+                    self.materials.insert(key, material.clone());
+                    rebuilt.push(material);
+                }
+                Err(e) => log_warning!("Keeping previous '{}': {}", previous.asset.name, e),
+            }
+        }
+        rebuilt
+    }
+    /// Rebuilds the pipelines of materials whose override constants changed since the last
+    /// call, after a [`crate::RenderSettings::set_shader_constant`]. Pipelines for earlier
+    /// constant sets stay cached, so switching back doesn't recompile.
+    pub fn apply_shader_constants(&mut self, device: &wgpu::Device) -> Vec<Arc<Material>> {
+        let generation = crate::RenderSettings::constants_generation();
+        if generation == self.constants_generation {
+            return Vec::new();
+        }
+        self.constants_generation = generation;
+        self.rebuild_changed_pipelines(device)
+    }
+    /// Rebuilds every material pipeline after a [`crate::RenderSettings::set_sample_count`].
+    /// Pipelines for other counts stay cached, so switching back doesn't recompile.
+    pub fn apply_sample_count(&mut self, device: &wgpu::Device) -> Vec<Arc<Material>> {
+        self.rebuild_changed_pipelines(device)
+    }
+    /// Rebuilds each material whose pipeline cache label now resolves to a different
+    /// pipeline.
+    fn rebuild_changed_pipelines(&mut self, device: &wgpu::Device) -> Vec<Arc<Material>> {
+        let previous: Vec<(CacheKey, Arc<Material>)> = self
+            .materials
+            .iter()
+            .map(|(k, m)| (*k, m.clone()))
+            .collect();
+        let mut rebuilt = Vec::new();
+        for (key, previous) in previous {
+            match previous.asset.pipeline(
+                device,
+                &mut self.shaders,
+                &mut self.pipelines,
+                &[crate::Vertex::LAYOUT, crate::VertexInstance::LAYOUT],
+            ) {
+                Ok(pipeline) if !Arc::ptr_eq(&pipeline, &previous.pipeline) => {
+                    let material = Arc::new(Material {
+                        asset: previous.asset.clone(),
+                        pipeline,
+                        bind_group: previous.bind_group.clone(),
+                        idx: previous.idx,
+                    });
+                    self.materials.insert(key, material.clone());
+                    rebuilt.push(material);
+                }
+                Ok(_) => {}
+                Err(e) => log_warning!("Keeping previous '{}': {}", previous.asset.name, e),
+            }
+        }
+        rebuilt
+    }
+}
+
+impl CacheStorage<Arc<Material>> for MaterialManager {
+    fn get(&self, key: &CacheKey) -> Option<&Arc<Material>> {
+        self.materials.get(key)
+    }
+    fn contains(&self, key: &CacheKey) -> bool {
+        self.materials.contains_key(key)
+    }
+    fn get_mut(&mut self, key: &CacheKey) -> Option<&mut Arc<Material>> {
+        self.materials.get_mut(key)
+    }
+    fn get_or_create<F>(&mut self, key: CacheKey, create_fn: F) -> &mut Arc<Material>
+    where
+        F: FnOnce() -> Arc<Material>,
+    {
+        self.materials.entry(key).or_insert_with(create_fn)
+    }
+    fn insert(&mut self, key: CacheKey, resource: Arc<Material>) {
+        self.materials.insert(key, resource);
+    }
+    fn remove(&mut self, key: &CacheKey) -> Option<Arc<Material>> {
+        self.materials.remove(key)
+    }
+    fn iter(&self) -> impl Iterator<Item = (&CacheKey, &Arc<Material>)> {
+        self.materials.iter()
+    }
 }
@@ -0,0 +1,545 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    log_debug, log_info, log_warning, AssetPaths, AssetWatcher, CacheKey, EngineError, Shader,
+};
+
+use super::MaterialAsset;
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BlendMode {
+    Opaque,
+    #[default]
+    Alpha,
+    Additive,
+    Premultiplied,
+}
+
+impl BlendMode {
+    pub fn state(&self) -> Option<wgpu::BlendState> {
+        match self {
+            BlendMode::Opaque => None,
+            BlendMode::Alpha => Some(wgpu::BlendState::ALPHA_BLENDING),
+            BlendMode::Premultiplied => Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+            BlendMode::Additive => Some(wgpu::BlendState {
+                color: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::SrcAlpha,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::Add,
+                },
+                alpha: wgpu::BlendComponent::OVER,
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CullMode {
+    None,
+    #[default]
+    Front,
+    Back,
+}
+
+impl CullMode {
+    pub fn face(&self) -> Option<wgpu::Face> {
+        match self {
+            CullMode::None => None,
+            CullMode::Front => Some(wgpu::Face::Front),
+            CullMode::Back => Some(wgpu::Face::Back),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Topology {
+    #[default]
+    TriangleList,
+    TriangleStrip,
+    LineList,
+    LineStrip,
+    PointList,
+}
+
+impl From<Topology> for wgpu::PrimitiveTopology {
+    fn from(value: Topology) -> Self {
+        match value {
+            Topology::TriangleList => wgpu::PrimitiveTopology::TriangleList,
+            Topology::TriangleStrip => wgpu::PrimitiveTopology::TriangleStrip,
+            Topology::LineList => wgpu::PrimitiveTopology::LineList,
+            Topology::LineStrip => wgpu::PrimitiveTopology::LineStrip,
+            Topology::PointList => wgpu::PrimitiveTopology::PointList,
+        }
+    }
+}
+
+/// On-disk material definition, `assets/materials/<name>.mat.ron`. Omitted fields fall back
+/// to [`MaterialFile::default`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MaterialFile {
+    pub shader: String,
+    pub ambient: [f32; 3],
+    pub diffuse: [f32; 3],
+    pub specular: [f32; 3],
+    pub shininess: f32,
+    pub diffuse_texture: Option<String>,
+    pub normal_texture: Option<String>,
+    /// Sampling per texture slot, e.g. `diffuse_sampler: (mag: Nearest, min: Nearest,
+    /// mip: None)` for pixel art.
+    pub diffuse_sampler: super::SamplerDesc,
+    pub normal_sampler: super::SamplerDesc,
+    pub blend: BlendMode,
+    pub cull: CullMode,
+    pub topology: Topology,
+    pub depth_write: bool,
+    /// Draws in the transparent pass: after opaque geometry, sorted back to front, with
+    /// depth writes off whatever `depth_write` says. `blend` alone doesn't decide this,
+    /// since most materials keep the default alpha blend while drawing opaque.
+    pub transparent: bool,
+    /// Reflects the environment; off for surfaces that shouldn't, e.g. terrain.
+    pub reflections: bool,
+    /// WGSL `override` constants for this material, e.g. `constants: {"FOG_EXPONENT": 2.0}`.
+    pub constants: crate::ShaderConstants,
+}
+
+impl Default for MaterialFile {
+    fn default() -> Self {
+        Self {
+            shader: Shader::DEFAULT.to_string(),
+            ambient: [0.0; 3],
+            diffuse: [0.0; 3],
+            specular: [0.0; 3],
+            shininess: 32.0,
+            diffuse_texture: None,
+            normal_texture: None,
+            diffuse_sampler: super::SamplerDesc::default(),
+            normal_sampler: super::SamplerDesc::default(),
+            blend: BlendMode::default(),
+            cull: CullMode::default(),
+            topology: Topology::default(),
+            depth_write: true,
+            transparent: false,
+            reflections: true,
+            constants: crate::ShaderConstants::default(),
+        }
+    }
+}
+
+impl MaterialFile {
+    pub const EXTENSION: &'static str = ".mat.ron";
+
+    pub fn parse(path: &Path, source: &str) -> Result<Self, EngineError> {
+        ron::de::from_str(source).map_err(|e| {
+            EngineError::AssetLoadError(format!(
+                "{}:{}:{}: {}",
+                path.display(),
+                e.position.line,
+                e.position.col,
+                e.code
+            ))
+        })
+    }
+    pub fn to_ron(&self) -> Result<String, EngineError> {
+        ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|e| EngineError::AssetLoadError(e.to_string()))
+    }
+
+    /// Checks that the shader and textures the file references exist, reporting the line
+    /// they're declared on.
+    pub fn validate(&self, path: &Path, source: &str) -> Result<(), EngineError> {
+        let mut refs = vec![("shader", AssetPaths::shader(&self.shader), &self.shader)];
+        for (field, texture) in [
+            ("diffuse_texture", &self.diffuse_texture),
+            ("normal_texture", &self.normal_texture),
+        ] {
+            if let Some(texture) = texture {
+                refs.push((field, AssetPaths::texture(texture), texture));
+            }
+        }
+        for (field, file, value) in refs {
+            if !file.exists() {
+                let line = source
+                    .lines()
+                    .position(|l| l.contains(value.as_str()))
+                    .map(|i| i + 1)
+                    .unwrap_or(0);
+                return Err(EngineError::AssetMissing(format!(
+                    "{}:{}: {} '{}' not found at {}",
+                    path.display(),
+                    line,
+                    field,
+                    value,
+                    file.display()
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn to_asset(
+        &self,
+        name: &str,
+        surface_configuration: &wgpu::SurfaceConfiguration,
+        depth_stencil: Option<wgpu::DepthStencilState>,
+        bind_group_layouts: Vec<wgpu::BindGroupLayout>,
+    ) -> MaterialAsset {
+        MaterialAsset {
+            name: name.to_string(),
+            key: CacheKey::from(name),
+            shader: self.shader.clone(),
+            ambient: self.ambient,
+            diffuse: self.diffuse,
+            specular: self.specular,
+            shininess: self.shininess,
+            diffuse_texture: self.diffuse_texture.clone(),
+            normal_texture: self.normal_texture.clone(),
+            diffuse_sampler: self.diffuse_sampler,
+            normal_sampler: self.normal_sampler,
+            primitive: wgpu::PrimitiveState {
+                topology: self.topology.into(),
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: self.cull.face(),
+                unclipped_depth: false,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                conservative: false,
+            },
+            depth_stencil: depth_stencil.map(|_| {
+                crate::RenderSettings::depth_state(if self.depth_write && !self.transparent {
+                    crate::DepthVariant::Opaque
+                } else {
+                    crate::DepthVariant::Transparent
+                })
+            }),
+            color_target: wgpu::ColorTargetState {
+                format: surface_configuration.format,
+                blend: self.blend.state(),
+                write_mask: wgpu::ColorWrites::all(),
+            },
+            transparent: self.transparent,
+            reflections: self.reflections,
+            bind_group_layouts,
+            constants: self.constants.clone(),
+        }
+    }
+}
+
+/// Per-model sidecar, `assets/models/<stem>.model.ron`, remapping MTL material names to
+/// library materials, flagging the model for an impostor bake and listing the levels of
+/// detail to generate for it.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ModelSidecar {
+    pub materials: HashMap<String, String>,
+    pub impostor: Option<crate::ImpostorSettings>,
+    /// Decimated levels added at load, see [`crate::ModelManager::add_decimated_lod`].
+    pub lods: Vec<crate::LodSettings>,
+}
+
+impl ModelSidecar {
+    pub fn path(file: &str) -> PathBuf {
+        let stem = Path::new(file)
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or(file);
+        AssetPaths::model(format!("{}.model.ron", stem))
+    }
+    pub fn load(file: &str) -> Result<Option<Self>, EngineError> {
+        let path = Self::path(file);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let source = std::fs::read_to_string(&path)?;
+        ron::de::from_str(&source).map(Some).map_err(|e| {
+            EngineError::AssetLoadError(format!(
+                "{}:{}:{}: {}",
+                path.display(),
+                e.position.line,
+                e.position.col,
+                e.code
+            ))
+        })
+    }
+}
+
+/// Material definitions read from `assets/materials`, keyed by file name without the
+/// `.mat.ron` extension.
+pub struct MaterialLibrary {
+    dir: PathBuf,
+    files: HashMap<String, MaterialFile>,
+    changed: Arc<Mutex<HashSet<String>>>,
+    depth_stencil: Option<wgpu::DepthStencilState>,
+    _watcher: Option<AssetWatcher>,
+}
+
+impl MaterialLibrary {
+    pub fn new() -> Self {
+        Self {
+            dir: AssetPaths::dir("materials"),
+            files: HashMap::new(),
+            changed: Arc::new(Mutex::new(HashSet::new())),
+            depth_stencil: None,
+            _watcher: None,
+        }
+    }
+    pub fn name_of(path: &Path) -> Option<String> {
+        path.file_name()?
+            .to_str()?
+            .strip_suffix(MaterialFile::EXTENSION)
+            .map(str::to_string)
+    }
+    pub fn path_of(&self, name: &str) -> PathBuf {
+        self.dir
+            .join(format!("{}{}", name, MaterialFile::EXTENSION))
+    }
+    pub fn get(&self, name: &str) -> Option<&MaterialFile> {
+        self.files.get(name)
+    }
+    pub fn names(&self) -> impl Iterator<Item = &String> {
+        self.files.keys()
+    }
+    pub fn depth_stencil(&self) -> Option<&wgpu::DepthStencilState> {
+        self.depth_stencil.as_ref()
+    }
+    pub fn set_depth_stencil(&mut self, depth_stencil: Option<wgpu::DepthStencilState>) {
+        self.depth_stencil = depth_stencil;
+    }
+
+    /// Reads, validates and stores a single definition.
+    pub fn read(&mut self, name: &str) -> Result<&MaterialFile, EngineError> {
+        let path = self.path_of(name);
+        let source = std::fs::read_to_string(&path)
+            .map_err(|e| EngineError::AssetMissing(format!("{}: {}", path.display(), e)))?;
+        let file = MaterialFile::parse(&path, &source)?;
+        file.validate(&path, &source)?;
+        log_debug!("Read material definition: {}", path.display());
+        self.files.insert(name.to_string(), file);
+        Ok(&self.files[name])
+    }
+
+    /// Reads every definition in the materials directory. Invalid files are logged and skipped.
+    pub fn scan(&mut self) -> Result<Vec<String>, EngineError> {
+        let mut names = Vec::new();
+        if !self.dir.exists() {
+            return Ok(names);
+        }
+        for entry in std::fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            let Some(name) = Self::name_of(&path) else {
+                continue;
+            };
+            match self.read(&name) {
+                Ok(_) => names.push(name),
+                Err(e) => log_warning!("{}", e),
+            }
+        }
+        log_info!("Material library: {} definitions", names.len());
+        Ok(names)
+    }
+
+    /// Starts watching the materials directory; changed names are collected for
+    /// [`MaterialLibrary::take_changed`].
+    pub fn watch(&mut self) -> Result<(), EngineError> {
+        if self._watcher.is_some() || !self.dir.exists() {
+            return Ok(());
+        }
+        let changed = self.changed.clone();
+        let watcher = AssetWatcher::new(self.dir.clone(), move |event| {
+            if !matches!(
+                event.kind,
+                notify::EventKind::Modify(_) | notify::EventKind::Create(_)
+            ) {
+                return;
+            }
+            if let Ok(mut changed) = changed.lock() {
+                changed.extend(event.paths.iter().filter_map(|p| Self::name_of(p)));
+            }
+        })
+        .map_err(|e| EngineError::FileSystemError(e.to_string()))?;
+        self._watcher = Some(watcher);
+        Ok(())
+    }
+    pub fn take_changed(&self) -> Vec<String> {
+        self.changed
+            .lock()
+            .map(|mut changed| changed.drain().collect())
+            .unwrap_or_default()
+    }
+}
+
+impl Default for MaterialLibrary {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        MipFilter, ModelManager, RenderBindGroupLayouts, SamplerAddress, SamplerDesc,
+        SamplerFilter, ShaderConstants,
+    };
+
+    #[test]
+    fn material_files_round_trip_every_field() {
+        let file = MaterialFile {
+            shader: "terrain.wgsl".to_string(),
+            ambient: [0.1, 0.2, 0.3],
+            diffuse: [0.4, 0.5, 0.6],
+            specular: [0.7, 0.8, 0.9],
+            shininess: 12.5,
+            diffuse_texture: Some("cube-diffuse.jpg".to_string()),
+            normal_texture: Some("cube-normal.png".to_string()),
+            diffuse_sampler: SamplerDesc {
+                mag: SamplerFilter::Nearest,
+                min: SamplerFilter::Nearest,
+                mip: MipFilter::None,
+                address_u: SamplerAddress::ClampToEdge,
+                address_v: SamplerAddress::MirrorRepeat,
+                address_w: SamplerAddress::ClampToEdge,
+                anisotropy_clamp: Some(4),
+                compare: Some(wgpu::CompareFunction::LessEqual),
+            },
+            normal_sampler: SamplerDesc {
+                mip: MipFilter::Nearest,
+                ..SamplerDesc::default()
+            },
+            blend: BlendMode::Additive,
+            cull: CullMode::Back,
+            topology: Topology::LineStrip,
+            depth_write: false,
+            transparent: true,
+            reflections: false,
+            constants: ShaderConstants([("FOG_EXPONENT".to_string(), 2.0)].into()),
+        };
+        let source = file.to_ron().unwrap();
+        let parsed = MaterialFile::parse(Path::new("round_trip.mat.ron"), &source).unwrap();
+        assert_eq!(parsed, file);
+        assert_ne!(parsed, MaterialFile::default());
+    }
+
+    #[test]
+    fn missing_textures_point_at_the_declaring_line() {
+        crate::assets::loader::test_root();
+        let path = Path::new("materials/broken.mat.ron");
+        let source =
+            "(\n    shader: \"v_normal.wgsl\",\n    diffuse_texture: Some(\"nope.png\"),\n)";
+        let file = MaterialFile::parse(path, source).unwrap();
+        let message = file.validate(path, source).unwrap_err().to_string();
+        assert!(
+            message.starts_with("Asset missing: materials/broken.mat.ron:3: diffuse_texture 'nope.png' not found at "),
+            "{message}"
+        );
+        assert!(message.ends_with(&AssetPaths::texture("nope.png").display().to_string()));
+
+        let typo = "(\n    shader: \"v_normal.wgsl\",\n    shininess: \"high\",\n)";
+        let message = MaterialFile::parse(path, typo).unwrap_err().to_string();
+        assert!(message.contains("materials/broken.mat.ron:3:"), "{message}");
+    }
+
+    /// A copy of the cube under `name`, its MTL material remapped by a sidecar to a
+    /// library material also called `name`. Needs an adapter.
+    fn remapped_cube(name: &str) -> Option<(ModelManager, wgpu::SurfaceConfiguration, CacheKey)> {
+        crate::assets::loader::test_root();
+        let _ = crate::GPU::init(crate::GpuRequirements::default());
+        RenderBindGroupLayouts::try_get().ok()?;
+        let (device, queue) =
+            crate::GPU::with_read_recovered(|gpu| (gpu.device().clone(), gpu.queue().clone()))
+                .ok()?;
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            width: 64,
+            height: 64,
+            present_mode: wgpu::PresentMode::Fifo,
+            alpha_mode: wgpu::CompositeAlphaMode::Opaque,
+            view_formats: vec![],
+            desired_maximum_frame_latency: 2,
+        };
+        let depth = crate::RenderSettings::depth_state(crate::DepthVariant::Opaque);
+
+        let model = format!("{}.obj", name);
+        std::fs::copy(AssetPaths::model("cube.obj"), AssetPaths::model(&model)).unwrap();
+        let sidecar = ModelSidecar {
+            materials: [("Material.001".to_string(), name.to_string())].into(),
+            ..ModelSidecar::default()
+        };
+        let sidecar = ron::ser::to_string(&sidecar).unwrap();
+        std::fs::write(ModelSidecar::path(&model), sidecar).unwrap();
+        let material = MaterialFile {
+            diffuse: [0.1, 0.2, 0.3],
+            ..MaterialFile::default()
+        };
+        let library = MaterialLibrary::new();
+        std::fs::write(library.path_of(name), material.to_ron().unwrap()).unwrap();
+
+        let mut models = ModelManager::new(queue, device);
+        models
+            .load_material_library(&config, Some(depth.clone()))
+            .unwrap();
+        pollster::block_on(models.load_object_file(
+            &model,
+            Shader::DEFAULT,
+            &[crate::Vertex::LAYOUT, crate::VertexInstance::LAYOUT],
+            vec![
+                RenderBindGroupLayouts::uniform().clone(),
+                RenderBindGroupLayouts::equirect_dst().clone(),
+                RenderBindGroupLayouts::material_storage().clone(),
+                RenderBindGroupLayouts::normal().clone(),
+            ],
+            &config,
+            wgpu::PrimitiveState::default(),
+            wgpu::ColorTargetState::from(config.format),
+            Some(depth),
+        ))
+        .unwrap();
+        Some((models, config, CacheKey::from(model.as_str())))
+    }
+
+    /// Needs an adapter; passes without one.
+    #[test]
+    fn sidecars_remap_mtl_materials_to_the_library() {
+        let Some((models, _, key)) = remapped_cube("sidecar_cube") else {
+            return;
+        };
+        let material = models.models[&key].instance.material.clone().unwrap();
+        assert_eq!(material.asset.name, "sidecar_cube");
+        assert_eq!(material.asset.diffuse, [0.1, 0.2, 0.3]);
+    }
+
+    /// Needs an adapter; passes without one.
+    #[test]
+    fn edited_files_rebuild_the_models_using_them() {
+        let Some((mut models, config, key)) = remapped_cube("reloaded_cube") else {
+            return;
+        };
+        let before = models.models[&key].instance.material.clone().unwrap();
+
+        let edited = MaterialFile {
+            diffuse: [0.9, 0.8, 0.7],
+            ..MaterialFile::default()
+        };
+        let library = &models.materials.library;
+        std::fs::write(library.path_of("reloaded_cube"), edited.to_ron().unwrap()).unwrap();
+        library
+            .changed
+            .lock()
+            .unwrap()
+            .insert("reloaded_cube".to_string());
+
+        let reloaded = models.reload_materials(&config);
+        let after = models.models[&key].instance.material.clone().unwrap();
+        assert_eq!(reloaded.len(), 1);
+        assert!(Arc::ptr_eq(&after, &reloaded[0]));
+        assert!(!Arc::ptr_eq(&after, &before));
+        assert_eq!(after.asset.diffuse, [0.9, 0.8, 0.7]);
+        assert_eq!(after.idx, before.idx, "keeps its storage slot");
+    }
+}
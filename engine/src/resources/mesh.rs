@@ -1,6 +1,7 @@
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::sync::{Arc, Weak};
 
-use crate::{Vertex, WgpuBuffer};
+use crate::{CacheKey, Vertex, WgpuBuffer};
 
 use super::Material;
 
@@ -22,7 +23,9 @@ impl MeshAsset {
             let vb = crate::WgpuBuffer::from_data(
                 device,
                 data,
-                wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                wgpu::BufferUsages::VERTEX
+                    | wgpu::BufferUsages::STORAGE
+                    | wgpu::BufferUsages::COPY_DST,
                 Some(&format!("{}_vertex_buffer", label)),
             );
             queue.write_buffer(vb.get(), 0, data);
@@ -30,11 +33,14 @@ impl MeshAsset {
         };
 
         let index_buffer = {
-            let data: &[u8] = bytemuck::cast_slice(&self.indices);
+            let bytes = self.index_bytes();
+            let data: &[u8] = &bytes;
             let ib = crate::WgpuBuffer::from_data(
                 device,
                 data,
-                wgpu::BufferUsages::INDEX | wgpu::BufferUsages::COPY_DST,
+                wgpu::BufferUsages::INDEX
+                    | wgpu::BufferUsages::STORAGE
+                    | wgpu::BufferUsages::COPY_DST,
                 Some(&format!("{}_index_buffer", label)),
             );
             queue.write_buffer(ib.get(), 0, data);
@@ -45,6 +51,36 @@ impl MeshAsset {
 
         (vertex_buffer, index_buffer, index_count)
     }
+    /// Uint16 when every index fits, halving the index buffer, else Uint32. `u16::MAX` is
+    /// kept free since strip topologies read it as a primitive restart.
+    pub fn index_format(&self) -> wgpu::IndexFormat {
+        if self.vertices.len() <= u16::MAX as usize {
+            wgpu::IndexFormat::Uint16
+        } else {
+            wgpu::IndexFormat::Uint32
+        }
+    }
+    /// The indices in [`MeshAsset::index_format`], padded to the 4-byte multiple buffer
+    /// writes require.
+    pub fn index_bytes(&self) -> Vec<u8> {
+        match self.index_format() {
+            wgpu::IndexFormat::Uint16 => {
+                let mut indices: Vec<u16> = self.indices.iter().map(|&i| i as u16).collect();
+                if indices.len() % 2 == 1 {
+                    indices.push(0);
+                }
+                bytemuck::cast_slice(&indices).to_vec()
+            }
+            wgpu::IndexFormat::Uint32 => bytemuck::cast_slice(&self.indices).to_vec(),
+        }
+    }
+    /// Vertices with generated tangents plus the model's indices.
+    pub fn from_tobj(m: &tobj::Model) -> Self {
+        Self {
+            vertices: Self::compute_vertex(m),
+            indices: m.mesh.indices.clone(),
+        }
+    }
     pub fn compute_vertex(m: &tobj::Model) -> Vec<Vertex> {
         use std::iter::repeat;
 
@@ -70,17 +106,25 @@ impl MeshAsset {
                     normal: [nrm[0], nrm[1], nrm[2]],
                     tangent: [0.0; 3],
                     color: [col[0], col[1], col[2]],
+                    surface: [0; 2],
                 })
                 .collect()
         };
 
+        Self::generate_tangents(&mut vertices, &mesh.indices, true);
+        vertices
+    }
+    /// Per-vertex tangents accumulated from each triangle's positions and uvs, then
+    /// orthogonalized against the normal. With `recompute_normals` the normals are rebuilt
+    /// the same way from face normals; otherwise the vertices' own normals are kept.
+    pub fn generate_tangents(vertices: &mut [Vertex], indices: &[u32], recompute_normals: bool) {
         // Accumulators
         let mut accum_normals = vec![[0.0f32; 3]; vertices.len()];
         let mut accum_tangents = vec![[0.0f32; 3]; vertices.len()];
         let mut accum_bitangents = vec![[0.0f32; 3]; vertices.len()];
 
         // Accumulate
-        for idx in mesh.indices.chunks(3) {
+        for idx in indices.chunks_exact(3) {
             let [i0, i1, i2] = [idx[0] as usize, idx[1] as usize, idx[2] as usize];
 
             let v0 = vertices[i0].position;
@@ -143,7 +187,11 @@ impl MeshAsset {
         for (i, v) in vertices.iter_mut().enumerate() {
             // normalize normal
             let n = {
-                let nn = accum_normals[i];
+                let nn = if recompute_normals {
+                    accum_normals[i]
+                } else {
+                    v.normal
+                };
                 let l = (nn[0] * nn[0] + nn[1] * nn[1] + nn[2] * nn[2])
                     .sqrt()
                     .max(1e-6);
@@ -163,15 +211,16 @@ impl MeshAsset {
             v.normal = n;
             v.tangent = t;
         }
-
-        vertices
     }
 }
 #[derive(Debug)]
 pub struct Mesh {
+    /// Unique per uploaded mesh, for caches of data derived from its buffers.
+    pub key: CacheKey,
     pub vertex_buffer: std::sync::Arc<WgpuBuffer>,
     pub index_buffer: std::sync::Arc<WgpuBuffer>,
     pub index_count: u32,
+    pub index_format: wgpu::IndexFormat,
 }
 
 impl Mesh {
@@ -181,16 +230,203 @@ impl Mesh {
         asset: MeshAsset,
         label: &str,
     ) -> Self {
+        static NEXT_KEY: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+
         let (vertex_buffer, index_buffer, index_count) = asset.load_asset(queue, device, label);
         Self {
+            key: CacheKey::new(NEXT_KEY.fetch_add(1, std::sync::atomic::Ordering::Relaxed)),
             vertex_buffer: Arc::new(vertex_buffer),
             index_buffer: Arc::new(index_buffer),
             index_count,
+            index_format: asset.index_format(),
         }
     }
+    pub fn vertex_count(&self) -> u32 {
+        (self.vertex_buffer.len() / std::mem::size_of::<Vertex>()) as u32
+    }
 }
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct MeshInstance {
     pub mesh: std::sync::Arc<Mesh>,
     pub material: Option<std::sync::Arc<Material>>,
 }
+
+/// Identity of a [`MeshAsset`]'s geometry: a hash of its vertex and index bytes, with the
+/// counts to make a collision between different meshes less likely still.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct MeshContent {
+    hash: u64,
+    vertices: usize,
+    indices: usize,
+}
+
+impl MeshContent {
+    fn of(asset: &MeshAsset) -> (Self, u64) {
+        let vertex_bytes: &[u8] = bytemuck::cast_slice(&asset.vertices);
+        let index_bytes = asset.index_bytes();
+        let hash =
+            crate::content_hash(vertex_bytes) ^ crate::content_hash(&index_bytes).rotate_left(1);
+        let content = Self {
+            hash,
+            vertices: asset.vertices.len(),
+            indices: asset.indices.len(),
+        };
+        (content, (vertex_bytes.len() + index_bytes.len()) as u64)
+    }
+}
+
+/// What [`MeshManager`] uploaded and shared so far.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MeshDedupStats {
+    /// Meshes given their own buffers.
+    pub uploads: u64,
+    /// Meshes that reused the buffers of an identical live one.
+    pub hits: u64,
+    /// Buffer bytes the hits didn't upload.
+    pub bytes_saved: u64,
+    /// Distinct meshes still referenced, as of the last [`MeshManager::sweep`].
+    pub live: usize,
+}
+
+/// Uploaded meshes by content, so identical geometry shares one [`Mesh`] and its buffer
+/// pair however many owners it has. Entries only hold the mesh weakly: its buffers are
+/// released with the last [`MeshInstance`] using it, and [`MeshManager::sweep`] forgets
+/// the entries left behind.
+#[derive(Debug, Default)]
+pub struct MeshManager {
+    meshes: HashMap<MeshContent, Weak<Mesh>>,
+    stats: MeshDedupStats,
+}
+
+impl MeshManager {
+    /// The live mesh with the same vertices and indices as `asset`, or a new upload of it.
+    /// `label` only names the buffers of a new upload.
+    pub fn get_or_upload(
+        &mut self,
+        queue: &wgpu::Queue,
+        device: &wgpu::Device,
+        asset: MeshAsset,
+        label: &str,
+    ) -> Arc<Mesh> {
+        let (content, bytes) = MeshContent::of(&asset);
+        if let Some(mesh) = self.meshes.get(&content).and_then(Weak::upgrade) {
+            self.stats.hits += 1;
+            self.stats.bytes_saved += bytes;
+            return mesh;
+        }
+        let mesh = Arc::new(Mesh::from_asset(queue, device, asset, label));
+        self.meshes.insert(content, Arc::downgrade(&mesh));
+        self.stats.uploads += 1;
+        mesh
+    }
+    /// Forgets meshes nothing references anymore, e.g. those of chunks streamed out.
+    pub fn sweep(&mut self) {
+        self.meshes.retain(|_, mesh| mesh.strong_count() > 0);
+        self.stats.live = self.meshes.len();
+    }
+    pub fn stats(&self) -> MeshDedupStats {
+        self.stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{chunk::Chunk, AIR};
+
+    fn device() -> Option<(wgpu::Device, wgpu::Queue)> {
+        let instance = wgpu::Instance::default();
+        let adapter =
+            pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions::default()))?;
+        pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor::default(), None)).ok()
+    }
+
+    fn dug(pos: (i32, i32, i32)) -> Chunk {
+        let mut chunk = Chunk::flat(pos);
+        chunk.set_block(1, 0, 2, AIR);
+        chunk
+    }
+
+    #[test]
+    fn flat_chunks_build_the_same_mesh() {
+        let a = MeshContent::of(&Chunk::flat((0, 0, 0)).build_chunk_mesh());
+        let b = MeshContent::of(&Chunk::flat((4, 0, -7)).build_chunk_mesh());
+        let modified = MeshContent::of(&dug((1, 0, 0)).build_chunk_mesh());
+        assert_eq!(a, b);
+        assert_ne!(a.0, modified.0);
+    }
+
+    /// Needs an adapter; passes without one.
+    #[test]
+    fn flat_chunks_share_buffers() {
+        let Some((device, queue)) = device() else {
+            return;
+        };
+        let mut meshes = MeshManager::default();
+        let mut upload = |chunk: Chunk| {
+            let label = format!("chunk_{:?}", chunk.pos);
+            meshes.get_or_upload(&queue, &device, chunk.build_chunk_mesh(), &label)
+        };
+        let a = upload(Chunk::flat((0, 0, 0)));
+        let b = upload(Chunk::flat((2, 0, 3)));
+        let modified = upload(dug((1, 0, 0)));
+        assert!(Arc::ptr_eq(&a, &b));
+        assert!(!Arc::ptr_eq(&a, &modified));
+        meshes.sweep();
+        let stats = meshes.stats();
+        assert_eq!((stats.uploads, stats.hits, stats.live), (2, 1, 2));
+    }
+
+    /// `vertices` vertices with one triangle reaching the last of them.
+    fn strip(vertices: usize) -> MeshAsset {
+        let last = vertices as u32 - 1;
+        MeshAsset {
+            vertices: vec![Vertex::default(); vertices],
+            indices: vec![0, 1, last],
+        }
+    }
+
+    #[test]
+    fn large_meshes_fall_back_to_u32_indices() {
+        let mesh = strip(70_000);
+        assert_eq!(mesh.index_format(), wgpu::IndexFormat::Uint32);
+        let bytes = mesh.index_bytes();
+        assert_eq!(bytes, bytemuck::cast_slice::<u32, u8>(&[0, 1, 69_999]));
+    }
+
+    #[test]
+    fn small_meshes_keep_u16_indices() {
+        let mesh = strip(u16::MAX as usize);
+        assert_eq!(mesh.index_format(), wgpu::IndexFormat::Uint16);
+        // Three indices, padded to four for the 4-byte write alignment.
+        let bytes = mesh.index_bytes();
+        assert_eq!(bytes, bytemuck::cast_slice::<u16, u8>(&[0, 1, 65_534, 0]));
+
+        assert_eq!(
+            strip(u16::MAX as usize + 1).index_format(),
+            wgpu::IndexFormat::Uint32
+        );
+    }
+
+    /// Needs an adapter; passes without one.
+    #[test]
+    fn built_meshes_upload_in_their_index_format() {
+        let Some((device, queue)) = device() else {
+            return;
+        };
+        let wide = Mesh::from_asset(&queue, &device, strip(70_000), "wide");
+        assert_eq!(wide.index_format, wgpu::IndexFormat::Uint32);
+        assert_eq!((wide.index_count, wide.index_buffer.len()), (3, 12));
+
+        let narrow = Mesh::from_asset(&queue, &device, strip(100), "narrow");
+        assert_eq!(narrow.index_format, wgpu::IndexFormat::Uint16);
+        assert_eq!((narrow.index_count, narrow.index_buffer.len()), (3, 8));
+
+        let chunk = Mesh::from_asset(&queue, &device, dug((0, 0, 0)).build_chunk_mesh(), "chunk");
+        assert_eq!(chunk.index_format, wgpu::IndexFormat::Uint16);
+        assert_eq!(
+            chunk.index_buffer.len(),
+            (chunk.index_count as usize * 2).next_multiple_of(4)
+        );
+    }
+}
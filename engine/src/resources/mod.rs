@@ -4,6 +4,9 @@ pub use bind_group::*;
 pub mod material;
 pub use material::*;
 
+pub mod material_library;
+pub use material_library::*;
+
 pub mod buffer;
 pub use buffer::*;
 
@@ -13,12 +16,27 @@ pub use cache::*;
 pub mod cache_key;
 pub use cache_key::*;
 
+pub mod residency;
+pub use residency::*;
+
 pub mod texture;
 pub use texture::*;
 
+pub mod texture_loader;
+pub use texture_loader::*;
+
+pub mod ktx2;
+pub use ktx2::*;
+
+pub mod sampler;
+pub use sampler::*;
+
 pub mod mesh;
 pub use mesh::*;
 
+pub mod primitives;
+pub use primitives::*;
+
 pub mod model;
 pub use model::*;
 
@@ -52,6 +70,17 @@ impl Managers {
             bind_group_manager,
         }
     }
+    /// Drops bind groups of textures the texture budget evicted since the last call, and
+    /// dependent bind groups of textures replaced since then.
+    pub fn release_evicted(&mut self) {
+        let evicted = self.texture_manager.take_evicted();
+        self.bind_group_manager.invalidate(&evicted);
+        let stale = self.texture_manager.take_stale_bind_groups();
+        self.bind_group_manager.invalidate(&stale);
+    }
+    pub fn resource_stats(&self) -> ResourceStats {
+        self.texture_manager.stats() + self.buffer_manager.w_buffer.stats()
+    }
 }
 
 impl Into<Managers> for (&std::sync::Arc<wgpu::Queue>, &std::sync::Arc<wgpu::Device>) {
@@ -70,3 +99,6 @@ impl Into<Managers> for std::sync::RwLockReadGuard<'_, crate::GPU> {
         Managers::new(self.queue().clone(), self.device().clone())
     }
 }
+
+pub mod cubemap;
+pub use cubemap::*;
@@ -1,7 +1,11 @@
 use super::{
     CacheKey, HashCache, Material, MaterialAsset, MaterialManager, Mesh, MeshAsset, MeshInstance,
+    ModelSidecar, PrimitiveKind,
+};
+use crate::{
+    log_debug, log_info, log_warning, Asset, AssetPaths, CacheStorage, EngineError, ImportedModel,
+    AABB,
 };
-use crate::{log_info, log_warning, Asset, EngineError, AABB};
 use std::{collections::HashMap, sync::Arc};
 
 #[derive(Clone, Debug)]
@@ -52,10 +56,25 @@ impl ModelAsset {
     }
 }
 
+#[derive(Clone)]
 pub struct Model {
     pub name: String,
     pub instance: MeshInstance,
     pub aabb: AABB,
+    /// Further primitives drawn wherever this model is, each cached as its own model under
+    /// [`Model::part_key`] so it batches with its own material. `aabb` covers them all.
+    pub parts: Vec<CacheKey>,
+    /// Coarser levels by increasing distance, see [`crate::LodSelection`].
+    pub lods: Vec<ModelLod>,
+}
+
+/// Meshes a [`Model`] draws instead of its own past `distance` from the camera.
+#[derive(Debug, Clone)]
+pub struct ModelLod {
+    pub distance: f32,
+    /// Meshes without a material draw with the model's, which keeps them in step when
+    /// that material is rebuilt.
+    pub meshes: Vec<MeshInstance>,
 }
 
 impl Model {
@@ -73,13 +92,16 @@ impl Model {
             name: asset.name,
             instance,
             aabb,
+            parts: Vec::new(),
+            lods: Vec::new(),
         })
     }
     pub fn from_tobj(
         queue: &wgpu::Queue,
         device: &wgpu::Device,
         materials: &mut MaterialManager,
-        model: &tobj::Model,
+        name: &str,
+        mesh: MeshAsset,
         material: Option<&tobj::Material>,
         shader: &str,
         buffers: &[wgpu::VertexBufferLayout<'_>],
@@ -89,11 +111,9 @@ impl Model {
         color_target: wgpu::ColorTargetState,
         bind_group_layouts: Vec<wgpu::BindGroupLayout>,
     ) -> Result<Model, EngineError> {
-        let vertices = MeshAsset::compute_vertex(&model);
-        let indices = model.mesh.indices.clone();
         let model_asset = ModelAsset {
-            name: model.name.clone(),
-            asset: (MeshAsset { vertices, indices }, {
+            name: name.to_string(),
+            asset: (mesh, {
                 if let Some(mat) = material {
                     let mut mat_asset: MaterialAsset = mat.into();
                     mat_asset.primitive = primitive;
@@ -113,16 +133,67 @@ impl Model {
         let (instance, aabb) =
             model_asset.load_asset(queue, device, materials, surface_configuration, buffers)?;
         Ok(Self {
-            name: model.name.clone(),
+            name: name.to_string(),
             instance,
             aabb,
+            parts: Vec::new(),
+            lods: Vec::new(),
         })
     }
 }
 
+impl Model {
+    /// Builds the mesh and binds an already loaded material, skipping the MTL material.
+    pub fn with_material(
+        queue: &wgpu::Queue,
+        device: &wgpu::Device,
+        name: &str,
+        mesh_asset: MeshAsset,
+        material: Arc<Material>,
+    ) -> Self {
+        let aabb = AABB::from_vertices(&mesh_asset.vertices);
+        let mesh = Mesh::from_asset(queue, device, mesh_asset, name);
+        Self {
+            name: name.to_string(),
+            instance: MeshInstance {
+                mesh: Arc::new(mesh),
+                material: Some(material),
+            },
+            aabb,
+            parts: Vec::new(),
+            lods: Vec::new(),
+        }
+    }
+    /// Cache key of part `index` of the model loaded from `file`.
+    pub fn part_key(file: &str, index: usize) -> CacheKey {
+        CacheKey::from(format!("{}#part{}", file, index))
+    }
+}
+
+/// Pipeline state a loaded glTF model builds its materials with; each material adjusts
+/// culling, blending and depth to its own glTF settings.
+pub struct GltfPipeline<'a> {
+    pub shader: &'a str,
+    pub buffers: &'a [wgpu::VertexBufferLayout<'a>],
+    pub bind_group_layouts: Vec<wgpu::BindGroupLayout>,
+    pub primitive: wgpu::PrimitiveState,
+    pub color_target: wgpu::ColorTargetState,
+    pub depth_stencil: Option<wgpu::DepthStencilState>,
+}
+
 pub struct ModelManager {
     pub models: HashCache<Arc<Model>>,
     pub materials: MaterialManager,
+    /// Baked impostors, under the same key as their model.
+    pub impostors: HashMap<CacheKey, crate::Impostor>,
+    /// GPU to CPU copies, advanced once per frame after submit.
+    pub readback: crate::ReadbackService,
+    /// Skins and animation clips of skinned glTF models, under the model's key.
+    pub animations: crate::AnimationManager,
+    /// State changes of the last frame's instanced draws, see [`crate::DrawList`].
+    pub draw_stats: crate::DrawStats,
+    /// Instances per level of detail last frame, see [`crate::LodSelection`].
+    pub lod_stats: crate::LodStats,
     pub device: Arc<wgpu::Device>,
     pub queue: Arc<wgpu::Queue>,
 }
@@ -131,6 +202,11 @@ impl ModelManager {
         Self {
             models: HashMap::new(),
             materials: MaterialManager::new(&device),
+            impostors: HashMap::new(),
+            readback: crate::ReadbackService::default(),
+            animations: crate::AnimationManager::new(),
+            draw_stats: crate::DrawStats::default(),
+            lod_stats: crate::LodStats::default(),
             device,
             queue,
         }
@@ -146,46 +222,103 @@ impl ModelManager {
         color_target: wgpu::ColorTargetState,
         depth_stencil: Option<wgpu::DepthStencilState>,
     ) -> Result<(), EngineError> {
-        let base_dir = Asset::base_path();
-        let path = base_dir.join("models").join(file);
-        let (models, mat_res) = tobj::load_obj(
-            &path,
-            &tobj::LoadOptions {
-                triangulate: true,
-                single_index: true,
-                ..Default::default()
-            },
-        )?;
-
-        let materials = match mat_res {
-            Ok(mats) => mats,
-            Err(e) => {
-                log_warning!("{}: {}", file, e);
-                Vec::new()
+        if crate::is_gltf(file) {
+            let pipeline = GltfPipeline {
+                shader,
+                buffers,
+                bind_group_layouts,
+                primitive,
+                color_target,
+                depth_stencil,
+            };
+            return self.load_gltf(file, surface_configuration, pipeline);
+        }
+        let base_dir = AssetPaths::models_dir();
+        let (meshes, materials) = match ImportedModel::load(file) {
+            Some(imported) => {
+                let materials = match &imported.mtllib {
+                    Some(lib) => match tobj::load_mtl(base_dir.join(file).with_file_name(lib)) {
+                        Ok((mats, _)) => mats,
+                        Err(e) => {
+                            log_warning!("{}: {}", file, e);
+                            Vec::new()
+                        }
+                    },
+                    None => Vec::new(),
+                };
+                let meshes: Vec<(String, MeshAsset, Option<usize>)> = imported
+                    .meshes
+                    .into_iter()
+                    .map(|m| (m.name, m.mesh, m.material_id))
+                    .collect();
+                (meshes, materials)
+            }
+            None => {
+                let (models, mat_res) = tobj::load_obj(
+                    base_dir.join(file),
+                    &tobj::LoadOptions {
+                        triangulate: true,
+                        single_index: true,
+                        ..Default::default()
+                    },
+                )?;
+                let materials = match mat_res {
+                    Ok(mats) => mats,
+                    Err(e) => {
+                        log_warning!("{}: {}", file, e);
+                        Vec::new()
+                    }
+                };
+                let meshes = models
+                    .iter()
+                    .map(|m| (m.name.clone(), MeshAsset::from_tobj(m), m.mesh.material_id))
+                    .collect();
+                (meshes, materials)
             }
         };
 
-        for m in models {
+        let sidecar = ModelSidecar::load(file).unwrap_or_else(|e| {
+            log_warning!("{}", e);
+            None
+        });
+
+        for (name, mesh, material_id) in meshes {
             let m_key = CacheKey::from(file);
             if self.models.contains_key(&m_key) {
-                log_info!("Skipping cached model: {}", m.name);
+                log_info!("Skipping cached model: {}", name);
                 continue;
             }
-            let mesh = &m.mesh;
+            let mat = material_id.and_then(|id| materials.get(id));
+            // Kept past the upload only when the sidecar asks for levels of detail.
+            let lod_source = sidecar
+                .as_ref()
+                .filter(|sidecar| !sidecar.lods.is_empty())
+                .map(|_| mesh.clone());
 
-            let mat = {
-                if let Some(id) = mesh.material_id {
-                    materials.get(id)
-                } else {
-                    None
-                }
-            };
+            let remapped = mat
+                .and_then(|mat| sidecar.as_ref()?.materials.get(&mat.name))
+                .and_then(|name| {
+                    self.materials
+                        .library_material(&self.queue, &self.device, name, surface_configuration)
+                        .map_err(|e| {
+                            log_warning!("{}: {}", file, e);
+                        })
+                        .ok()
+                });
+            if let Some(material) = remapped {
+                let model = Model::with_material(&self.queue, &self.device, &name, mesh, material);
+                self.models.insert(m_key, Arc::new(model));
+                self.add_sidecar_lods(m_key, lod_source, sidecar.as_ref())?;
+                log_info!("Cached model: {} (remapped material)", name);
+                continue;
+            }
 
             let model = Arc::new(Model::from_tobj(
                 &self.queue,
                 &self.device,
                 &mut self.materials,
-                &m,
+                &name,
+                mesh,
                 mat,
                 &shader,
                 buffers,
@@ -197,10 +330,244 @@ impl ModelManager {
             )?);
 
             self.models.insert(m_key, model);
-            log_info!("Cached model: {}", m.name);
+            self.add_sidecar_lods(m_key, lod_source, sidecar.as_ref())?;
+            log_info!("Cached model: {}", name);
+        }
+
+        let key = CacheKey::from(file);
+        if !self.impostors.contains_key(&key) {
+            match crate::Impostor::load(&self.device, &self.queue, file) {
+                Ok(Some(impostor)) => {
+                    log_info!("Loaded impostor: {}", file);
+                    self.impostors.insert(key, impostor);
+                }
+                Ok(None) => {}
+                Err(e) => log_warning!("{}", e),
+            }
+        }
+        Ok(())
+    }
+    /// Loads a `.gltf` or `.glb` file as one model under `CacheKey::from(file)`: its first
+    /// primitive is the model's own mesh, the rest become [`Model::parts`]. glTF materials
+    /// are built once each and shared by the primitives using them.
+    pub fn load_gltf(
+        &mut self,
+        file: &str,
+        surface_configuration: &wgpu::SurfaceConfiguration,
+        pipeline: GltfPipeline,
+    ) -> Result<(), EngineError> {
+        let GltfPipeline {
+            shader,
+            buffers,
+            bind_group_layouts,
+            primitive,
+            color_target,
+            depth_stencil,
+        } = pipeline;
+        let key = CacheKey::from(file);
+        if self.models.contains_key(&key) {
+            log_info!("Skipping cached model: {}", file);
+            return Ok(());
+        }
+        let gltf = Asset::gltf(file)?;
+
+        for (i, image) in gltf.images.iter().enumerate() {
+            let name = crate::GltfModel::image_name(file, i);
+            let texture_key = CacheKey::from(name.clone());
+            if !self.materials.textures.contains(&texture_key) {
+                let texture = crate::Texture::from_image(
+                    &self.device,
+                    &self.queue,
+                    surface_configuration,
+                    image,
+                    name,
+                );
+                self.materials
+                    .textures
+                    .insert(texture_key, Arc::new(texture));
+            }
+        }
+
+        let fallback = crate::GltfMaterial::default();
+        let mut built: HashMap<Option<usize>, Arc<Material>> = HashMap::new();
+        let mut models: Vec<Model> = Vec::new();
+        let mut skinned: Vec<crate::SkinnedMesh> = Vec::new();
+        for part in gltf.primitives {
+            let material = match built.get(&part.material) {
+                Some(material) => material.clone(),
+                None => {
+                    let source = part
+                        .material
+                        .and_then(|i| gltf.materials.get(i))
+                        .unwrap_or(&fallback);
+                    let mut asset = MaterialAsset::from_gltf(file, source);
+                    asset.shader = shader.to_string();
+                    asset.bind_group_layouts = bind_group_layouts.clone();
+                    asset.primitive = wgpu::PrimitiveState {
+                        cull_mode: if source.double_sided {
+                            None
+                        } else {
+                            primitive.cull_mode
+                        },
+                        ..primitive
+                    };
+                    asset.color_target = wgpu::ColorTargetState {
+                        blend: if source.blend {
+                            Some(wgpu::BlendState::ALPHA_BLENDING)
+                        } else {
+                            color_target.blend
+                        },
+                        ..color_target.clone()
+                    };
+                    asset.depth_stencil = match &depth_stencil {
+                        Some(_) if source.blend => Some(crate::RenderSettings::depth_state(
+                            crate::DepthVariant::Transparent,
+                        )),
+                        other => other.clone(),
+                    };
+                    let idx = self.materials.create_storage_idx();
+                    let material = Arc::new(Material::from_asset(
+                        &self.queue,
+                        &self.device,
+                        &mut self.materials.textures,
+                        &mut self.materials.shaders,
+                        &mut self.materials.pipelines,
+                        surface_configuration,
+                        buffers,
+                        asset,
+                        idx,
+                    )?);
+                    self.materials.update_storage(&material);
+                    built.insert(part.material, material.clone());
+                    material
+                }
+            };
+            if let Some((joints, weights)) = &part.skinning {
+                let part_key = match models.len() {
+                    0 => key,
+                    i => Model::part_key(file, i),
+                };
+                let vertices: Vec<crate::VertexSkinned> = part
+                    .mesh
+                    .vertices
+                    .iter()
+                    .zip(joints.iter().zip(weights))
+                    .map(|(v, (j, w))| crate::VertexSkinned::new(*v, *j, *w))
+                    .collect();
+                skinned.push(crate::SkinnedMesh::new(&self.device, part_key, &vertices));
+            }
+            models.push(Model::with_material(
+                &self.queue,
+                &self.device,
+                &part.name,
+                part.mesh,
+                material,
+            ));
+        }
+        self.materials.build_storage(&self.device);
+        if let Some(skeleton) = gltf.skeleton {
+            if !skinned.is_empty() {
+                let skin = crate::Skin {
+                    skeleton: Arc::new(skeleton),
+                    meshes: skinned,
+                };
+                self.animations.insert_skin(key, skin);
+            }
+            for clip in gltf.animations {
+                log_debug!("Animation {} of {}: {:.2}s", clip.name, file, clip.duration);
+                let clip_key = crate::AnimationClip::key(file, &clip.name);
+                self.animations.insert_clip(clip_key, clip);
+            }
+        }
+
+        let mut models = models.into_iter();
+        let Some(mut root) = models.next() else {
+            return Err(EngineError::AssetLoadError(format!(
+                "{}: no triangle primitives",
+                file
+            )));
+        };
+        root.name = file.to_string();
+        for (i, part) in models.enumerate() {
+            root.aabb = AABB {
+                min: root.aabb.min.min(part.aabb.min),
+                max: root.aabb.max.max(part.aabb.max),
+            };
+            let part_key = Model::part_key(file, i + 1);
+            root.parts.push(part_key);
+            self.models.insert(part_key, Arc::new(part));
+        }
+        log_info!(
+            "Cached model: {} ({} primitives)",
+            file,
+            root.parts.len() + 1
+        );
+        self.models.insert(key, Arc::new(root));
+        Ok(())
+    }
+    /// Adds a level of detail to the model under `key`, drawn past `distance` in place of
+    /// the model's own mesh; levels stay sorted by distance. Parts keep their own levels.
+    pub fn add_lod(
+        &mut self,
+        key: CacheKey,
+        distance: f32,
+        meshes: Vec<MeshInstance>,
+    ) -> Result<(), EngineError> {
+        let model = self
+            .models
+            .get_mut(&key)
+            .ok_or_else(|| EngineError::MissingResource {
+                kind: "model",
+                key: key.id().to_string(),
+            })?;
+        let lods = &mut Arc::make_mut(model).lods;
+        let at = lods.partition_point(|lod| lod.distance < distance);
+        lods.insert(at, ModelLod { distance, meshes });
+        Ok(())
+    }
+    /// Adds a level of detail to the model under `key` from `mesh`, its source mesh,
+    /// decimated by [`crate::cluster_vertices`].
+    pub fn add_decimated_lod(
+        &mut self,
+        key: CacheKey,
+        mesh: &MeshAsset,
+        settings: crate::LodSettings,
+    ) -> Result<(), EngineError> {
+        let decimated = crate::cluster_vertices(mesh, settings.cells);
+        log_debug!(
+            "LOD at {}: {} of {} triangles",
+            settings.distance,
+            decimated.indices.len() / 3,
+            mesh.indices.len() / 3
+        );
+        let mesh = Mesh::from_asset(&self.queue, &self.device, decimated, "lod");
+        let instance = MeshInstance {
+            mesh: Arc::new(mesh),
+            material: None,
+        };
+        self.add_lod(key, settings.distance, vec![instance])
+    }
+    /// Adds the levels of detail `sidecar` lists to the model under `key`, decimated from
+    /// `mesh`.
+    fn add_sidecar_lods(
+        &mut self,
+        key: CacheKey,
+        mesh: Option<MeshAsset>,
+        sidecar: Option<&ModelSidecar>,
+    ) -> Result<(), EngineError> {
+        let (Some(mesh), Some(sidecar)) = (mesh, sidecar) else {
+            return Ok(());
+        };
+        for settings in &sidecar.lods {
+            self.add_decimated_lod(key, &mesh, *settings)?;
         }
         Ok(())
     }
+    /// Whether entities of `key` with an [`crate::Animator`] take the skinned path.
+    pub fn can_skin(&mut self, key: &CacheKey) -> bool {
+        self.animations
+            .can_draw(&self.device, &mut self.materials, &self.models, key)
+    }
     pub fn load_asset(
         &mut self,
         surface_configuration: &wgpu::SurfaceConfiguration,
@@ -222,6 +589,296 @@ impl ModelManager {
         self.models.insert(m_key, model.clone());
         Ok(model)
     }
+    /// The model of `kind` drawn with the cached material `material`, built on first use.
+    /// Returns its key, see [`PrimitiveKind::cache_key`], for [`crate::World::spawn_model_key`].
+    pub fn primitive(
+        &mut self,
+        kind: PrimitiveKind,
+        material: CacheKey,
+    ) -> Result<CacheKey, EngineError> {
+        let key = kind.cache_key(material);
+        if self.models.contains_key(&key) {
+            return Ok(key);
+        }
+        let material = self
+            .materials
+            .materials
+            .get(&material)
+            .cloned()
+            .ok_or_else(|| EngineError::MissingResource {
+                kind: "material",
+                key: material.id().to_string(),
+            })?;
+        let model = Model::with_material(
+            &self.queue,
+            &self.device,
+            kind.name(),
+            kind.mesh(),
+            material,
+        );
+        self.models.insert(key, Arc::new(model));
+        log_debug!("Cached primitive: {:?}", kind);
+        Ok(key)
+    }
+    pub fn load_material_library(
+        &mut self,
+        surface_configuration: &wgpu::SurfaceConfiguration,
+        depth_stencil: Option<wgpu::DepthStencilState>,
+    ) -> Result<usize, EngineError> {
+        self.materials.load_library(
+            &self.queue,
+            &self.device,
+            surface_configuration,
+            depth_stencil,
+        )
+    }
+    /// Rebuilds edited library materials and rebinds them on cached models. The returned
+    /// materials still need rebinding on anything outside the manager, e.g. terrain.
+    pub fn reload_materials(
+        &mut self,
+        surface_configuration: &wgpu::SurfaceConfiguration,
+    ) -> Vec<Arc<Material>> {
+        let reloaded =
+            self.materials
+                .reload_library(&self.queue, &self.device, surface_configuration);
+        for material in &reloaded {
+            self.rebind_material(material);
+        }
+        reloaded
+    }
+    /// Rebuilds depth-tested material pipelines after a
+    /// [`crate::RenderSettings::set_depth_policy`] switch and rebinds them on cached models.
+    pub fn apply_depth_policy(&mut self) -> Vec<Arc<Material>> {
+        let rebuilt = self.materials.rebuild_depth_pipelines(&self.device);
+        for material in &rebuilt {
+            self.rebind_material(material);
+        }
+        rebuilt
+    }
+    /// Rebuilds material pipelines after a [`crate::RenderSettings::set_shader_constant`]
+    /// and rebinds them on cached models.
+    pub fn apply_shader_constants(&mut self) -> Vec<Arc<Material>> {
+        let rebuilt = self.materials.apply_shader_constants(&self.device);
+        for material in &rebuilt {
+            self.rebind_material(material);
+        }
+        rebuilt
+    }
+    /// Rebuilds material pipelines after a [`crate::RenderSettings::set_sample_count`]
+    /// and rebinds them on cached models.
+    pub fn apply_sample_count(&mut self) -> Vec<Arc<Material>> {
+        let rebuilt = self.materials.apply_sample_count(&self.device);
+        for material in &rebuilt {
+            self.rebind_material(material);
+        }
+        rebuilt
+    }
+    /// Recompiles `shader` after its source changed and rebuilds every pipeline made from
+    /// it, on library materials and cached models alike. All of it happens in a validation
+    /// error scope: on a WGSL or pipeline error the previous module and pipelines stay in
+    /// place and the error is logged. Returns the rebuilt library materials, which still
+    /// need rebinding on anything outside the manager, e.g. terrain.
+    pub fn reload_shader(&mut self, shader: &str) -> Vec<Arc<Material>> {
+        use pollster::FutureExt;
+
+        let key = CacheKey::from(shader);
+        let materials = &mut self.materials;
+        let module = match materials.shaders.compile(&self.device, shader) {
+            Ok(module) => module,
+            Err(e) => {
+                log_warning!("Keeping previous '{}': {}", shader, e);
+                return Vec::new();
+            }
+        };
+        let previous_modules = materials.shaders.invalidate(&key);
+        materials.shaders.insert(key, module);
+        let previous_pipelines = materials.pipelines.invalidate_shader(&key);
+
+        let library = materials
+            .materials
+            .iter()
+            .map(|(k, m)| (Some(*k), m.clone()));
+        let bound = self
+            .models
+            .values()
+            .filter_map(|model| Some((None, model.instance.material.clone()?)));
+        let stale: Vec<(Option<CacheKey>, Arc<Material>)> = library
+            .chain(bound)
+            .filter(|(_, material)| material.asset.shader == shader)
+            .collect();
+
+        let mut rebuilt: Vec<(Option<CacheKey>, Arc<Material>, Arc<Material>)> = Vec::new();
+        let mut error = None;
+        self.device.push_error_scope(wgpu::ErrorFilter::Validation);
+        for (library_key, previous) in stale {
+            if rebuilt.iter().any(|(_, p, _)| Arc::ptr_eq(p, &previous)) {
+                continue;
+            }
+            match previous.asset.pipeline(
+                &self.device,
+                &mut materials.shaders,
+                &mut materials.pipelines,
+                &[crate::Vertex::LAYOUT, crate::VertexInstance::LAYOUT],
+            ) {
+                Ok(pipeline) => {
+                    let material = Arc::new(Material {
+                        asset: previous.asset.clone(),
+                        pipeline,
+                        bind_group: previous.bind_group.clone(),
+                        idx: previous.idx,
+                    });
+                    rebuilt.push((library_key, previous, material));
+                }
+                Err(e) => {
+                    error = Some(e.to_string());
+                    break;
+                }
+            }
+        }
+        if let Some(e) = self.device.pop_error_scope().block_on() {
+            error.get_or_insert(e.to_string());
+        }
+        if let Some(error) = error {
+            log_warning!("Keeping previous '{}': {}", shader, error);
+            materials.shaders.invalidate(&key);
+            materials.shaders.restore(previous_modules);
+            materials.pipelines.restore(previous_pipelines);
+            return Vec::new();
+        }
+
+        log_info!("Reloaded shader: {} ({} materials)", shader, rebuilt.len());
+        self.swap_rebuilt(rebuilt)
+    }
+    /// Uploads textures decoded in the background and rebuilds the bind group of every
+    /// material sampling one of them or a texture replaced since the last call, on library
+    /// materials and cached models alike. Returns the rebuilt library materials, which
+    /// still need rebinding on anything outside the manager, e.g. terrain.
+    pub fn apply_loaded_textures(
+        &mut self,
+        surface_config: &wgpu::SurfaceConfiguration,
+    ) -> Vec<Arc<Material>> {
+        let textures = &mut self.materials.textures;
+        let mut loaded = textures.upload_finished(&self.queue, &self.device, surface_config);
+        for key in textures.take_replaced() {
+            if !loaded.contains(&key) {
+                loaded.push(key);
+            }
+        }
+        self.rebind_textures(&loaded, surface_config)
+    }
+    /// Rebuilds the bind group of every material sampling a texture under one of `keys`,
+    /// which [`crate::BindGroup::normal`] baked the previous texture views into. Returns
+    /// the rebuilt library materials, like [`ModelManager::apply_loaded_textures`].
+    pub fn rebind_textures(
+        &mut self,
+        keys: &[CacheKey],
+        surface_config: &wgpu::SurfaceConfiguration,
+    ) -> Vec<Arc<Material>> {
+        if keys.is_empty() {
+            return Vec::new();
+        }
+        let materials = &mut self.materials;
+
+        let library = materials
+            .materials
+            .iter()
+            .map(|(k, m)| (Some(*k), m.clone()));
+        let bound = self
+            .models
+            .values()
+            .filter_map(|model| Some((None, model.instance.material.clone()?)));
+        let stale: Vec<(Option<CacheKey>, Arc<Material>)> = library
+            .chain(bound)
+            .filter(|(_, material)| keys.iter().any(|key| material.asset.uses_texture(key)))
+            .collect();
+
+        let mut rebuilt: Vec<(Option<CacheKey>, Arc<Material>, Arc<Material>)> = Vec::new();
+        for (library_key, previous) in stale {
+            if rebuilt.iter().any(|(_, p, _)| Arc::ptr_eq(p, &previous)) {
+                continue;
+            }
+            match previous.asset.texture_bind_group(
+                &self.queue,
+                &self.device,
+                &mut materials.textures,
+                surface_config,
+            ) {
+                Ok(bind_group) => {
+                    let material = Arc::new(Material {
+                        asset: previous.asset.clone(),
+                        pipeline: previous.pipeline.clone(),
+                        bind_group,
+                        idx: previous.idx,
+                    });
+                    rebuilt.push((library_key, previous, material));
+                }
+                Err(e) => log_warning!("Keeping fallback on '{}': {}", previous.asset.name, e),
+            }
+        }
+        log_debug!(
+            "Swapped in {} textures ({} materials)",
+            keys.len(),
+            rebuilt.len()
+        );
+        self.swap_rebuilt(rebuilt)
+    }
+    /// Puts each rebuilt material in place of the previous one on cached models and in the
+    /// library, returning the library ones.
+    fn swap_rebuilt(
+        &mut self,
+        rebuilt: Vec<(Option<CacheKey>, Arc<Material>, Arc<Material>)>,
+    ) -> Vec<Arc<Material>> {
+        for model in self.models.values_mut() {
+            let Some(bound) = &model.instance.material else {
+                continue;
+            };
+            let Some((.., material)) = rebuilt.iter().find(|(_, p, _)| Arc::ptr_eq(p, bound))
+            else {
+                continue;
+            };
+            *model = Arc::new(Model {
+                name: model.name.clone(),
+                instance: MeshInstance {
+                    mesh: model.instance.mesh.clone(),
+                    material: Some(material.clone()),
+                },
+                aabb: model.aabb,
+                parts: model.parts.clone(),
+                lods: model.lods.clone(),
+            });
+        }
+        rebuilt
+            .into_iter()
+            .filter_map(|(library_key, _, material)| {
+                self.materials
+                    .materials
+                    .insert(library_key?, material.clone());
+                Some(material)
+            })
+            .collect()
+    }
+    /// Swaps `material` in on every model bound to a material of the same name.
+    pub fn rebind_material(&mut self, material: &Arc<Material>) {
+        for model in self.models.values_mut() {
+            let bound = model
+                .instance
+                .material
+                .as_ref()
+                .is_some_and(|m| m.asset.name == material.asset.name);
+            if bound {
+                *model = Arc::new(Model {
+                    name: model.name.clone(),
+                    instance: MeshInstance {
+                        mesh: model.instance.mesh.clone(),
+                        material: Some(material.clone()),
+                    },
+                    aabb: model.aabb,
+                    parts: model.parts.clone(),
+                    lods: model.lods.clone(),
+                });
+            }
+        }
+    }
 }
 
 impl crate::CacheStorage<std::sync::Arc<Model>> for ModelManager {
@@ -247,6 +904,10 @@ impl crate::CacheStorage<std::sync::Arc<Model>> for ModelManager {
         self.models.insert(key, resource);
     }
     fn remove(&mut self, key: &crate::CacheKey) -> Option<std::sync::Arc<Model>> {
+        self.animations.remove_skin(key);
         self.models.remove(key)
     }
+    fn iter(&self) -> impl Iterator<Item = (&crate::CacheKey, &std::sync::Arc<Model>)> {
+        self.models.iter()
+    }
 }
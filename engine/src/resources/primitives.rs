@@ -0,0 +1,379 @@
+//! Meshes for common shapes, built in code so debug scenes need no asset files. Faces
+//! wind like OBJ models and the terrain, `(b - a) x (c - a)` pointing out of the shape,
+//! which is what the default `cull: Front` pipelines keep. Tangents follow increasing `u`;
+//! the shaders derive the bitangent from the normal and tangent.
+
+use std::f32::consts::{FRAC_PI_2, PI, TAU};
+
+use glam::Vec3;
+
+use crate::{CacheKey, MeshAsset, Vertex, AABB};
+
+/// Sectors around a capsule's axis.
+pub const CAPSULE_SECTORS: u32 = 24;
+/// Rings of each of a capsule's two caps.
+pub const CAPSULE_CAP_RINGS: u32 = 8;
+
+/// A shape [`crate::ModelManager::primitive`] builds and caches.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PrimitiveKind {
+    Cube {
+        size: f32,
+    },
+    UvSphere {
+        radius: f32,
+        rings: u32,
+        sectors: u32,
+    },
+    Plane {
+        width: f32,
+        depth: f32,
+        subdivisions: u32,
+    },
+    Capsule {
+        radius: f32,
+        height: f32,
+    },
+}
+
+impl PrimitiveKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Cube { .. } => "cube",
+            Self::UvSphere { .. } => "uv sphere",
+            Self::Plane { .. } => "plane",
+            Self::Capsule { .. } => "capsule",
+        }
+    }
+    pub fn mesh(&self) -> MeshAsset {
+        match *self {
+            Self::Cube { size } => cube(size),
+            Self::UvSphere {
+                radius,
+                rings,
+                sectors,
+            } => uv_sphere(radius, rings, sectors),
+            Self::Plane {
+                width,
+                depth,
+                subdivisions,
+            } => plane(width, depth, subdivisions),
+            Self::Capsule { radius, height } => capsule(radius, height),
+        }
+    }
+    /// Bounds of [`PrimitiveKind::mesh`], without building it.
+    pub fn aabb(&self) -> AABB {
+        let half = match *self {
+            Self::Cube { size } => Vec3::splat(size * 0.5),
+            Self::UvSphere { radius, .. } => Vec3::splat(radius),
+            Self::Plane { width, depth, .. } => Vec3::new(width * 0.5, 0.0, depth * 0.5),
+            Self::Capsule { radius, height } => {
+                Vec3::new(radius, height.max(2.0 * radius) * 0.5, radius)
+            }
+        };
+        AABB {
+            min: -half,
+            max: half,
+        }
+    }
+    /// Key the model of this shape with `material` is cached under; equal shapes with the
+    /// same material share it.
+    pub fn cache_key(&self, material: CacheKey) -> CacheKey {
+        CacheKey::from(format!("primitive {:?} material {}", self, material.id()))
+    }
+}
+
+/// A cube of side `size` centered on the origin, four vertices per face so each face has
+/// its own normal and full UV square.
+pub fn cube(size: f32) -> MeshAsset {
+    const FACES: [(Vec3, Vec3); 6] = [
+        (Vec3::X, Vec3::Z),
+        (Vec3::NEG_X, Vec3::NEG_Z),
+        (Vec3::Y, Vec3::X),
+        (Vec3::NEG_Y, Vec3::X),
+        (Vec3::Z, Vec3::NEG_X),
+        (Vec3::NEG_Z, Vec3::X),
+    ];
+    let half = size * 0.5;
+    let mut mesh = empty();
+    for (normal, tangent) in FACES {
+        // Seen from outside, `u` runs right and `v` down the side faces, as image rows do.
+        let down = normal.cross(tangent);
+        let origin = (normal - tangent - down) * half;
+        push_grid(&mut mesh, origin, tangent * size, down * size, normal, 1);
+    }
+    mesh
+}
+
+/// A flat grid of `width` along X by `depth` along Z, facing +Y, centered on the origin.
+/// Each side is cut `subdivisions` times.
+pub fn plane(width: f32, depth: f32, subdivisions: u32) -> MeshAsset {
+    let mut mesh = empty();
+    let origin = Vec3::new(-width * 0.5, 0.0, -depth * 0.5);
+    push_grid(
+        &mut mesh,
+        origin,
+        Vec3::X * width,
+        Vec3::Z * depth,
+        Vec3::Y,
+        subdivisions + 1,
+    );
+    mesh
+}
+
+/// A sphere of `radius` centered on the origin, `rings` bands from pole to pole and
+/// `sectors` around the Y axis. The seam at `u` = 0 repeats its vertices.
+pub fn uv_sphere(radius: f32, rings: u32, sectors: u32) -> MeshAsset {
+    let rings = rings.max(2);
+    let profile: Vec<Ring> = (0..=rings)
+        .map(|i| {
+            let t = i as f32 / rings as f32;
+            Ring {
+                center: 0.0,
+                theta: t * PI,
+                v: t,
+            }
+        })
+        .collect();
+    lathe(radius, &profile, sectors)
+}
+
+/// A capsule of `radius` standing `height` tall along Y, caps included, centered on the
+/// origin. A height under `2 * radius` makes a sphere.
+pub fn capsule(radius: f32, height: f32) -> MeshAsset {
+    let half = (height * 0.5 - radius).max(0.0);
+    let length = PI * radius + 2.0 * half;
+    let arc = |theta: f32| theta * radius / length.max(f32::EPSILON);
+    let cap = |center: f32, from: f32, offset: f32| {
+        (0..=CAPSULE_CAP_RINGS).map(move |i| {
+            let theta = from + i as f32 / CAPSULE_CAP_RINGS as f32 * FRAC_PI_2;
+            Ring {
+                center,
+                theta,
+                v: arc(theta) + offset,
+            }
+        })
+    };
+    let profile: Vec<Ring> = cap(half, 0.0, 0.0)
+        .chain(cap(-half, FRAC_PI_2, 2.0 * half / length.max(f32::EPSILON)))
+        .collect();
+    lathe(radius, &profile, CAPSULE_SECTORS)
+}
+
+fn empty() -> MeshAsset {
+    MeshAsset {
+        vertices: Vec::new(),
+        indices: Vec::new(),
+    }
+}
+
+fn vertex(position: Vec3, tex_coords: [f32; 2], normal: Vec3, tangent: Vec3) -> Vertex {
+    Vertex {
+        position: position.into(),
+        color: [1.0; 3],
+        tex_coords,
+        normal: normal.into(),
+        tangent: tangent.into(),
+        surface: [0; 2],
+    }
+}
+
+/// Appends a flat grid from `origin` spanning `u_axis` and `v_axis`, cut into `segments`
+/// cells per side and facing `normal`.
+fn push_grid(
+    mesh: &mut MeshAsset,
+    origin: Vec3,
+    u_axis: Vec3,
+    v_axis: Vec3,
+    normal: Vec3,
+    segments: u32,
+) {
+    let segments = segments.max(1);
+    let base = mesh.vertices.len() as u32;
+    let tangent = u_axis.normalize_or_zero();
+    for i in 0..=segments {
+        for j in 0..=segments {
+            let (u, v) = (j as f32 / segments as f32, i as f32 / segments as f32);
+            let position = origin + u_axis * u + v_axis * v;
+            mesh.vertices
+                .push(vertex(position, [u, v], normal, tangent));
+        }
+    }
+    let flip = u_axis.cross(v_axis).dot(normal) < 0.0;
+    let stride = segments + 1;
+    for i in 0..segments {
+        for j in 0..segments {
+            let p00 = base + i * stride + j;
+            let (p10, p01, p11) = (p00 + 1, p00 + stride, p00 + stride + 1);
+            let triangles = if flip {
+                [p00, p11, p10, p00, p01, p11]
+            } else {
+                [p00, p10, p11, p00, p11, p01]
+            };
+            mesh.indices.extend_from_slice(&triangles);
+        }
+    }
+}
+
+/// One row of vertices around the Y axis: a circle at polar angle `theta` on a
+/// hemisphere centered `center` up the axis.
+struct Ring {
+    center: f32,
+    theta: f32,
+    v: f32,
+}
+
+/// Sweeps `profile` around the Y axis in `sectors` steps. Rows at a pole collapse into a
+/// point, so the triangles touching them are left out.
+fn lathe(radius: f32, profile: &[Ring], sectors: u32) -> MeshAsset {
+    let sectors = sectors.max(3);
+    let mut mesh = empty();
+    for ring in profile {
+        let (sin_theta, cos_theta) = ring.theta.sin_cos();
+        for j in 0..=sectors {
+            let u = j as f32 / sectors as f32;
+            let (sin_phi, cos_phi) = (u * TAU).sin_cos();
+            let normal = Vec3::new(sin_theta * cos_phi, cos_theta, sin_theta * sin_phi);
+            let tangent = Vec3::new(-sin_phi, 0.0, cos_phi);
+            let position = Vec3::Y * ring.center + normal * radius;
+            mesh.vertices
+                .push(vertex(position, [u, ring.v], normal, tangent));
+        }
+    }
+    let pole = |ring: &Ring| ring.theta.sin().abs() < 1e-6;
+    let stride = sectors + 1;
+    for (i, rows) in profile.windows(2).enumerate() {
+        let i = i as u32;
+        for j in 0..sectors {
+            let a = i * stride + j;
+            let (b, c, d) = (a + 1, a + stride + 1, a + stride);
+            if !pole(&rows[0]) {
+                mesh.indices.extend_from_slice(&[a, b, d]);
+            }
+            if !pole(&rows[1]) {
+                mesh.indices.extend_from_slice(&[b, c, d]);
+            }
+        }
+    }
+    mesh
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn position(mesh: &MeshAsset, index: u32) -> Vec3 {
+        Vec3::from(mesh.vertices[index as usize].position)
+    }
+
+    /// Every triangle's `(b - a) x (c - a)` points the way its vertex normals do.
+    fn assert_winds_outward(mesh: &MeshAsset) {
+        for triangle in mesh.indices.chunks(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| position(mesh, triangle[i]));
+            let face = (b - a).cross(c - a);
+            for &index in triangle {
+                let normal = Vec3::from(mesh.vertices[index as usize].normal);
+                assert!(
+                    face.dot(normal) > 0.0,
+                    "triangle {:?} winds inward",
+                    triangle
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn index_counts() {
+        let cube = cube(1.0);
+        assert_eq!((cube.vertices.len(), cube.indices.len()), (24, 36));
+        let plane = plane(2.0, 2.0, 2);
+        assert_eq!((plane.vertices.len(), plane.indices.len()), (16, 54));
+        // Both pole bands have one triangle per sector, the others two.
+        let sphere = uv_sphere(1.0, 4, 8);
+        assert_eq!(
+            (sphere.vertices.len(), sphere.indices.len()),
+            (5 * 9, 8 * 6 * 3)
+        );
+        let rows = 2 * (CAPSULE_CAP_RINGS + 1);
+        let capsule = capsule(0.5, 2.0);
+        let triangles = CAPSULE_SECTORS * (2 * (rows - 1) - 2);
+        assert_eq!(capsule.indices.len() as u32, triangles * 3);
+        for mesh in [&cube, &plane, &sphere, &capsule] {
+            assert!(mesh
+                .indices
+                .iter()
+                .all(|&i| (i as usize) < mesh.vertices.len()));
+        }
+    }
+
+    #[test]
+    fn cube_faces_point_out_along_their_axis() {
+        let cube = cube(2.0);
+        for face in cube.vertices.chunks(4) {
+            let normal = Vec3::from(face[0].normal);
+            assert_eq!(normal.abs().max_element(), 1.0);
+            assert_eq!(normal.length(), 1.0);
+            for vertex in face {
+                assert_eq!(Vec3::from(vertex.normal), normal);
+                assert!((Vec3::from(vertex.position).dot(normal) - 1.0).abs() < 1e-6);
+            }
+        }
+        let normals: Vec<Vec3> = cube
+            .vertices
+            .chunks(4)
+            .map(|f| f[0].normal.into())
+            .collect();
+        for axis in [
+            Vec3::X,
+            Vec3::NEG_X,
+            Vec3::Y,
+            Vec3::NEG_Y,
+            Vec3::Z,
+            Vec3::NEG_Z,
+        ] {
+            assert!(normals.contains(&axis), "no face facing {}", axis);
+        }
+        assert_winds_outward(&cube);
+    }
+
+    #[test]
+    fn sphere_normals_are_normalized_positions() {
+        let sphere = uv_sphere(2.5, 8, 12);
+        for vertex in &sphere.vertices {
+            let position = Vec3::from(vertex.position);
+            assert!((position.length() - 2.5).abs() < 1e-5);
+            let normal = Vec3::from(vertex.normal);
+            assert!(normal.distance(position.normalize()) < 1e-5, "{}", position);
+        }
+        assert_winds_outward(&sphere);
+        assert_winds_outward(&plane(1.0, 1.0, 3));
+        assert_winds_outward(&capsule(0.5, 2.0));
+    }
+
+    #[test]
+    fn aabb_matches_the_mesh() {
+        let kinds = [
+            PrimitiveKind::Cube { size: 2.0 },
+            PrimitiveKind::UvSphere {
+                radius: 1.5,
+                rings: 8,
+                sectors: 16,
+            },
+            PrimitiveKind::Plane {
+                width: 3.0,
+                depth: 1.0,
+                subdivisions: 2,
+            },
+            PrimitiveKind::Capsule {
+                radius: 0.5,
+                height: 3.0,
+            },
+        ];
+        for kind in kinds {
+            let mesh = AABB::from_vertices(&kind.mesh().vertices);
+            let aabb = kind.aabb();
+            assert!(mesh.min.distance(aabb.min) < 1e-5, "{:?}", kind);
+            assert!(mesh.max.distance(aabb.max) < 1e-5, "{:?}", kind);
+        }
+    }
+}
@@ -0,0 +1,186 @@
+//! Byte accounting and least-recently-used eviction for the GPU resource caches. A cache
+//! records each entry's size when it's inserted and stamps the frame whenever it's looked
+//! up; once the total passes the budget, the entries idle the longest are dropped first.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::CacheKey;
+
+/// Resident GPU memory of the caches, for the dev overlay.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct ResourceStats {
+    pub texture_bytes: u64,
+    pub buffer_bytes: u64,
+    /// Entries dropped to stay under budget since startup.
+    pub evictions: u64,
+}
+
+impl std::ops::Add for ResourceStats {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self {
+            texture_bytes: self.texture_bytes + other.texture_bytes,
+            buffer_bytes: self.buffer_bytes + other.buffer_bytes,
+            evictions: self.evictions + other.evictions,
+        }
+    }
+}
+
+struct Resident {
+    bytes: u64,
+    /// [`crate::gpu_frame_index`] of the last lookup; atomic since lookups take `&self`.
+    last_used: AtomicU64,
+}
+
+#[derive(Default)]
+pub struct Residency {
+    entries: HashMap<CacheKey, Resident>,
+    bytes: u64,
+    budget: Option<u64>,
+    evictions: u64,
+}
+
+impl Residency {
+    /// Total bytes allowed before [`Residency::evict`] picks victims; `None` is unlimited.
+    pub fn budget(&self) -> Option<u64> {
+        self.budget
+    }
+    pub fn set_budget(&mut self, budget: Option<u64>) {
+        self.budget = budget;
+    }
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+    pub fn evictions(&self) -> u64 {
+        self.evictions
+    }
+    pub fn over_budget(&self) -> bool {
+        self.budget.is_some_and(|budget| self.bytes > budget)
+    }
+
+    /// Records `key` at `bytes`, replacing what it was tracked at, as used this frame.
+    pub fn track(&mut self, key: CacheKey, bytes: u64) {
+        self.track_at(key, bytes, crate::gpu_frame_index());
+    }
+    fn track_at(&mut self, key: CacheKey, bytes: u64, frame: u64) {
+        self.untrack(&key);
+        self.bytes += bytes;
+        self.entries.insert(
+            key,
+            Resident {
+                bytes,
+                last_used: AtomicU64::new(frame),
+            },
+        );
+    }
+    pub fn untrack(&mut self, key: &CacheKey) {
+        if let Some(resident) = self.entries.remove(key) {
+            self.bytes -= resident.bytes;
+        }
+    }
+    /// Marks `key` as used this frame.
+    pub fn touch(&self, key: &CacheKey) {
+        self.touch_at(key, crate::gpu_frame_index());
+    }
+    fn touch_at(&self, key: &CacheKey, frame: u64) {
+        if let Some(resident) = self.entries.get(key) {
+            resident.last_used.store(frame, Ordering::Relaxed);
+        }
+    }
+    pub fn last_used(&self, key: &CacheKey) -> Option<u64> {
+        self.entries
+            .get(key)
+            .map(|resident| resident.last_used.load(Ordering::Relaxed))
+    }
+
+    /// Untracks and returns the least recently used keys until the total fits the budget,
+    /// oldest first. Entries used this frame and those `pinned` reports are kept, even if
+    /// that leaves the cache over budget; the caller drops the returned keys.
+    pub fn evict(&mut self, pinned: impl Fn(&CacheKey) -> bool) -> Vec<CacheKey> {
+        self.evict_at(crate::gpu_frame_index(), pinned)
+    }
+    fn evict_at(&mut self, frame: u64, pinned: impl Fn(&CacheKey) -> bool) -> Vec<CacheKey> {
+        let Some(budget) = self.budget.filter(|budget| self.bytes > *budget) else {
+            return Vec::new();
+        };
+        let mut candidates: Vec<(u64, CacheKey)> = self
+            .entries
+            .iter()
+            .map(|(key, resident)| (resident.last_used.load(Ordering::Relaxed), *key))
+            .filter(|(last_used, key)| *last_used < frame && !pinned(key))
+            .collect();
+        candidates.sort_unstable_by_key(|(last_used, _)| *last_used);
+
+        let mut evicted = Vec::new();
+        for (_, key) in candidates {
+            if self.bytes <= budget {
+                break;
+            }
+            self.untrack(&key);
+            self.evictions += 1;
+            evicted.push(key);
+        }
+        evicted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(id: u64) -> CacheKey {
+        CacheKey::new(id)
+    }
+
+    #[test]
+    fn evicts_least_recently_used_first() {
+        let mut residency = Residency::default();
+        residency.set_budget(Some(300));
+        for (id, frame) in [(1, 1), (2, 2), (3, 3)] {
+            residency.track_at(key(id), 100, frame);
+        }
+        // 1 is used again after 2, so 2 becomes the oldest.
+        residency.touch_at(&key(1), 4);
+        assert!(residency.evict_at(5, |_| false).is_empty());
+
+        residency.track_at(key(4), 150, 5);
+        assert!(residency.over_budget());
+        assert_eq!(residency.evict_at(6, |_| false), vec![key(2), key(3)]);
+        assert_eq!(residency.bytes(), 250);
+        assert_eq!(residency.evictions(), 2);
+        assert_eq!(residency.last_used(&key(2)), None);
+        assert_eq!(residency.last_used(&key(1)), Some(4));
+    }
+
+    #[test]
+    fn entries_used_this_frame_are_never_evicted() {
+        let mut residency = Residency::default();
+        residency.set_budget(Some(100));
+        residency.track_at(key(1), 100, 1);
+        residency.track_at(key(2), 100, 2);
+        residency.track_at(key(3), 100, 3);
+        residency.touch_at(&key(1), 3);
+
+        // Everything is from this frame or pinned: stay over budget rather than evict.
+        assert!(residency.evict_at(3, |k| *k == key(2)).is_empty());
+        assert_eq!(residency.bytes(), 300);
+        assert_eq!(residency.evict_at(3, |_| false), vec![key(2)]);
+        assert!(residency.over_budget());
+        assert_eq!(residency.evictions(), 1);
+    }
+
+    #[test]
+    fn retracking_replaces_the_size_and_unlimited_never_evicts() {
+        let mut residency = Residency::default();
+        residency.track_at(key(1), 100, 1);
+        residency.track_at(key(1), 40, 1);
+        residency.track_at(key(2), 1_000, 1);
+        assert_eq!(residency.bytes(), 1_040);
+        assert!(!residency.over_budget());
+        assert!(residency.evict_at(2, |_| false).is_empty());
+        residency.untrack(&key(2));
+        assert_eq!(residency.bytes(), 40);
+    }
+}
@@ -0,0 +1,334 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::sync::Arc;
+
+/// Highest anisotropy wgpu accepts.
+pub const MAX_ANISOTROPY: u16 = 16;
+
+static ANISOTROPY: AtomicU16 = AtomicU16::new(MAX_ANISOTROPY);
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum SamplerFilter {
+    Nearest,
+    #[default]
+    Linear,
+}
+
+impl From<SamplerFilter> for wgpu::FilterMode {
+    fn from(value: SamplerFilter) -> Self {
+        match value {
+            SamplerFilter::Nearest => wgpu::FilterMode::Nearest,
+            SamplerFilter::Linear => wgpu::FilterMode::Linear,
+        }
+    }
+}
+
+/// Filtering between mip levels. `None` samples the top level only.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum MipFilter {
+    None,
+    Nearest,
+    #[default]
+    Linear,
+}
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum SamplerAddress {
+    #[default]
+    Repeat,
+    MirrorRepeat,
+    ClampToEdge,
+}
+
+impl From<SamplerAddress> for wgpu::AddressMode {
+    fn from(value: SamplerAddress) -> Self {
+        match value {
+            SamplerAddress::Repeat => wgpu::AddressMode::Repeat,
+            SamplerAddress::MirrorRepeat => wgpu::AddressMode::MirrorRepeat,
+            SamplerAddress::ClampToEdge => wgpu::AddressMode::ClampToEdge,
+        }
+    }
+}
+
+/// How a texture slot is sampled, as written in material files. Omitted fields default to
+/// repeating, trilinear filtering with [`crate::RenderSettings::anisotropy`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SamplerDesc {
+    pub mag: SamplerFilter,
+    pub min: SamplerFilter,
+    pub mip: MipFilter,
+    pub address_u: SamplerAddress,
+    pub address_v: SamplerAddress,
+    pub address_w: SamplerAddress,
+    /// Overrides the global anisotropy. Only applies when every filter is linear.
+    pub anisotropy_clamp: Option<u16>,
+    /// Makes this a comparison sampler, e.g. for shadow maps.
+    pub compare: Option<wgpu::CompareFunction>,
+}
+
+impl Default for SamplerDesc {
+    fn default() -> Self {
+        Self {
+            mag: SamplerFilter::Linear,
+            min: SamplerFilter::Linear,
+            mip: MipFilter::Linear,
+            address_u: SamplerAddress::Repeat,
+            address_v: SamplerAddress::Repeat,
+            address_w: SamplerAddress::Repeat,
+            anisotropy_clamp: None,
+            compare: None,
+        }
+    }
+}
+
+impl SamplerDesc {
+    /// Nearest-neighbor without mips, for pixel art.
+    pub fn pixel_art() -> Self {
+        Self {
+            mag: SamplerFilter::Nearest,
+            min: SamplerFilter::Nearest,
+            mip: MipFilter::None,
+            ..Default::default()
+        }
+    }
+    pub fn clamped(mut self) -> Self {
+        self.address_u = SamplerAddress::ClampToEdge;
+        self.address_v = SamplerAddress::ClampToEdge;
+        self.address_w = SamplerAddress::ClampToEdge;
+        self
+    }
+    fn all_linear(&self) -> bool {
+        self.mag == SamplerFilter::Linear
+            && self.min == SamplerFilter::Linear
+            && self.mip == MipFilter::Linear
+    }
+    /// The descriptor with its anisotropy settled: the override or the global setting,
+    /// clamped to `1..=max_anisotropy`, and 1 unless every filter is linear, as wgpu requires.
+    pub fn resolved(&self, max_anisotropy: u16) -> Self {
+        let requested = self
+            .anisotropy_clamp
+            .unwrap_or_else(crate::RenderSettings::anisotropy);
+        let anisotropy = if self.all_linear() {
+            requested.clamp(1, max_anisotropy.max(1))
+        } else {
+            1
+        };
+        Self {
+            anisotropy_clamp: Some(anisotropy),
+            ..*self
+        }
+    }
+    pub fn descriptor(&self) -> wgpu::SamplerDescriptor<'static> {
+        wgpu::SamplerDescriptor {
+            label: Some("cached sampler"),
+            address_mode_u: self.address_u.into(),
+            address_mode_v: self.address_v.into(),
+            address_mode_w: self.address_w.into(),
+            mag_filter: self.mag.into(),
+            min_filter: self.min.into(),
+            mipmap_filter: match self.mip {
+                MipFilter::Linear => wgpu::FilterMode::Linear,
+                MipFilter::Nearest | MipFilter::None => wgpu::FilterMode::Nearest,
+            },
+            lod_max_clamp: match self.mip {
+                MipFilter::None => 0.0,
+                _ => 32.0,
+            },
+            anisotropy_clamp: self.anisotropy_clamp.unwrap_or(1),
+            compare: self.compare,
+            ..Default::default()
+        }
+    }
+}
+
+/// Shared samplers, one per distinct resolved [`SamplerDesc`], so materials asking for the
+/// same sampling bind the same `wgpu::Sampler`.
+#[derive(Debug)]
+pub struct SamplerCache {
+    samplers: HashMap<SamplerDesc, Arc<wgpu::Sampler>>,
+    max_anisotropy: u16,
+}
+
+impl SamplerCache {
+    pub fn new(max_anisotropy: u16) -> Self {
+        Self {
+            samplers: HashMap::new(),
+            max_anisotropy: max_anisotropy.clamp(1, MAX_ANISOTROPY),
+        }
+    }
+    /// Highest anisotropy `adapter` can filter with; 1 without anisotropic filtering support.
+    pub fn adapter_max_anisotropy(adapter: &wgpu::Adapter) -> u16 {
+        let flags = adapter.get_downlevel_capabilities().flags;
+        if flags.contains(wgpu::DownlevelFlags::ANISOTROPIC_FILTERING) {
+            MAX_ANISOTROPY
+        } else {
+            1
+        }
+    }
+    pub fn max_anisotropy(&self) -> u16 {
+        self.max_anisotropy
+    }
+    pub fn get(&mut self, device: &wgpu::Device, desc: &SamplerDesc) -> Arc<wgpu::Sampler> {
+        let resolved = desc.resolved(self.max_anisotropy);
+        self.samplers
+            .entry(resolved)
+            .or_insert_with(|| Arc::new(device.create_sampler(&resolved.descriptor())))
+            .clone()
+    }
+    pub fn len(&self) -> usize {
+        self.samplers.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.samplers.is_empty()
+    }
+}
+
+impl Default for SamplerCache {
+    /// Limited by the global GPU's adapter, or [`MAX_ANISOTROPY`] before it's initialized.
+    fn default() -> Self {
+        let max =
+            crate::GPU::with_read_recovered(|gpu| Self::adapter_max_anisotropy(gpu.adapter()))
+                .unwrap_or(MAX_ANISOTROPY);
+        Self::new(max)
+    }
+}
+
+impl crate::RenderSettings {
+    /// `r.anisotropy`: anisotropic filtering level for samplers that don't set their own.
+    /// Samplers are resolved when bind groups are built, so changes apply to materials
+    /// (re)loaded afterwards.
+    pub fn anisotropy() -> u16 {
+        ANISOTROPY.load(Ordering::Relaxed)
+    }
+    pub fn set_anisotropy(level: u16) {
+        ANISOTROPY.store(level.clamp(1, MAX_ANISOTROPY), Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MaterialFile, RenderBindGroupLayouts, TextureManager};
+
+    fn device() -> Option<Arc<wgpu::Device>> {
+        let _ = crate::GPU::init(crate::GpuRequirements::default());
+        crate::GPU::with_read_recovered(|gpu| gpu.device().clone()).ok()
+    }
+
+    #[test]
+    fn anisotropy_is_clamped_to_the_device_and_dropped_for_nearest() {
+        let wants = |level| SamplerDesc {
+            anisotropy_clamp: Some(level),
+            ..SamplerDesc::default()
+        };
+        assert_eq!(wants(8).resolved(16).anisotropy_clamp, Some(8));
+        assert_eq!(wants(8).resolved(4).anisotropy_clamp, Some(4));
+        assert_eq!(wants(0).resolved(16).anisotropy_clamp, Some(1));
+        assert_eq!(wants(8).resolved(0).anisotropy_clamp, Some(1));
+        let nearest = SamplerDesc {
+            mip: MipFilter::Nearest,
+            ..wants(8)
+        };
+        assert_eq!(nearest.resolved(16).anisotropy_clamp, Some(1));
+        assert_eq!(
+            SamplerDesc::pixel_art().resolved(16).anisotropy_clamp,
+            Some(1)
+        );
+
+        assert_eq!(SamplerCache::new(64).max_anisotropy(), MAX_ANISOTROPY);
+        assert_eq!(SamplerCache::new(0).max_anisotropy(), 1);
+        let pixel_art = SamplerDesc::pixel_art().resolved(1).descriptor();
+        assert_eq!(pixel_art.mag_filter, wgpu::FilterMode::Nearest);
+        assert_eq!(pixel_art.lod_max_clamp, 0.0);
+    }
+
+    #[test]
+    fn material_files_parse_partial_samplers_over_the_defaults() {
+        let source = "(
+            diffuse_sampler: (mag: Nearest, min: Nearest, mip: None),
+            normal_sampler: (address_u: ClampToEdge, anisotropy_clamp: Some(2)),
+        )";
+        let file = MaterialFile::parse(std::path::Path::new("pixel.mat.ron"), source).unwrap();
+        assert_eq!(file.diffuse_sampler, SamplerDesc::pixel_art());
+        assert_eq!(
+            file.normal_sampler,
+            SamplerDesc {
+                address_u: SamplerAddress::ClampToEdge,
+                anisotropy_clamp: Some(2),
+                ..SamplerDesc::default()
+            }
+        );
+        let source = "(diffuse_sampler: (mag: Cubic))";
+        assert!(MaterialFile::parse(std::path::Path::new("pixel.mat.ron"), source).is_err());
+    }
+
+    /// Needs an adapter; passes without one.
+    #[test]
+    fn equal_descriptors_share_one_sampler() {
+        let Some(device) = device() else {
+            return;
+        };
+        let mut cache = SamplerCache::new(MAX_ANISOTROPY);
+        let a = cache.get(&device, &SamplerDesc::pixel_art());
+        let b = cache.get(&device, &SamplerDesc::pixel_art());
+        assert!(Arc::ptr_eq(&a, &b));
+        let clamped = cache.get(&device, &SamplerDesc::pixel_art().clamped());
+        assert!(!Arc::ptr_eq(&a, &clamped));
+        assert_eq!(cache.len(), 2);
+
+        // Descriptors that only differ before resolving collapse into one sampler.
+        let mut cache = SamplerCache::new(4);
+        let eight = SamplerDesc {
+            anisotropy_clamp: Some(8),
+            ..SamplerDesc::default()
+        };
+        let four = SamplerDesc {
+            anisotropy_clamp: Some(4),
+            ..SamplerDesc::default()
+        };
+        assert!(Arc::ptr_eq(
+            &cache.get(&device, &eight),
+            &cache.get(&device, &four)
+        ));
+        assert_eq!(cache.len(), 1);
+    }
+
+    /// Needs an adapter; passes without one.
+    #[test]
+    fn materials_with_the_same_sampling_bind_the_same_sampler() {
+        crate::assets::loader::test_root();
+        let Some(device) = device() else {
+            return;
+        };
+        RenderBindGroupLayouts::try_get().unwrap();
+        let queue = crate::GPU::with_read_recovered(|gpu| gpu.queue().clone()).unwrap();
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            width: 64,
+            height: 64,
+            present_mode: wgpu::PresentMode::Fifo,
+            alpha_mode: wgpu::CompositeAlphaMode::Opaque,
+            view_formats: vec![],
+            desired_maximum_frame_latency: 2,
+        };
+        let file = MaterialFile {
+            diffuse_sampler: SamplerDesc::pixel_art(),
+            ..MaterialFile::default()
+        };
+        let mut textures = TextureManager::new();
+        for name in ["first", "second"] {
+            file.to_asset(name, &config, None, Vec::new())
+                .texture_bind_group(&queue, &device, &mut textures, &config)
+                .unwrap();
+        }
+        // Pixel art diffuse and default normal sampling, created once for both.
+        assert_eq!(textures.samplers.len(), 2);
+        let first = textures.samplers.get(&device, &file.diffuse_sampler);
+        let second = textures.samplers.get(&device, &SamplerDesc::pixel_art());
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!(textures.samplers.len(), 2);
+    }
+}
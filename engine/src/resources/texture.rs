@@ -1,4 +1,7 @@
-use crate::{CacheKey, CacheStorage, EngineError, HashCache};
+use crate::{
+    log_warning, CacheKey, CacheStorage, CubemapFaces, CubemapOrientation, CubemapSettings,
+    DecodedTexture, EngineError, HashCache, TextureLoader,
+};
 use image::codecs::hdr::{HdrDecoder, HdrMetadata};
 use std::io::Cursor;
 use std::sync::Arc;
@@ -8,6 +11,7 @@ use std::sync::Arc;
 pub struct Texture {
     pub texture: wgpu::Texture,
     pub view: wgpu::TextureView,
+    /// Fallback for bind groups that don't pick a sampler from the [`super::SamplerCache`].
     pub sampler: wgpu::Sampler,
     pub label: String,
 }
@@ -68,6 +72,26 @@ impl Texture {
             binding: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
         },
     ];
+    /// GPU memory of every mip, layer and sample, for the residency budget. Formats without
+    /// a single copy size (combined depth-stencil) count four bytes per texel.
+    pub fn byte_size(&self) -> u64 {
+        let texture = &self.texture;
+        let format = texture.format();
+        let (block_width, block_height) = format.block_dimensions();
+        let block_bytes = format.block_copy_size(None).unwrap_or(4) as u64;
+        (0..texture.mip_level_count())
+            .map(|level| {
+                let size = texture
+                    .size()
+                    .mip_level_size(level, texture.dimension())
+                    .physical_size(format);
+                let blocks =
+                    (size.width / block_width) as u64 * (size.height / block_height) as u64;
+                blocks * size.depth_or_array_layers as u64 * block_bytes
+            })
+            .sum::<u64>()
+            * texture.sample_count() as u64
+    }
     pub fn create_view(&self, desc: &wgpu::TextureViewDescriptor) -> wgpu::TextureView {
         self.texture.create_view(desc)
     }
@@ -106,6 +130,89 @@ impl Texture {
             label: desc.label.map(|l| l.to_string()).unwrap_or_default(),
         }
     }
+    /// `texture_2d_array` with layer `i` holding `images[i]`, each resized to the first
+    /// one's size. `format` must take four bytes per texel.
+    pub fn from_layers(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        images: &[image::RgbaImage],
+        format: wgpu::TextureFormat,
+        label: impl Into<String>,
+    ) -> Texture {
+        let label: String = label.into();
+        let (width, height) = images.first().map_or((1, 1), |img| img.dimensions());
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(&label),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: images.len().max(1) as u32,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        for (layer, img) in images.iter().enumerate() {
+            let resized;
+            let img = if img.dimensions() == (width, height) {
+                img
+            } else {
+                resized = image::imageops::resize(
+                    img,
+                    width,
+                    height,
+                    image::imageops::FilterType::Triangle,
+                );
+                &resized
+            };
+            queue.write_texture(
+                wgpu::TexelCopyTextureInfo {
+                    texture: &texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d {
+                        x: 0,
+                        y: 0,
+                        z: layer as u32,
+                    },
+                    aspect: wgpu::TextureAspect::All,
+                },
+                img,
+                wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(4 * width),
+                    rows_per_image: Some(height),
+                },
+                wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some(&label),
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some(&label),
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+        Texture {
+            texture,
+            view,
+            sampler,
+            label,
+        }
+    }
     pub fn decode_hdr(data: &[u8]) -> Result<(Vec<[f32; 4]>, HdrMetadata), EngineError> {
         let decoder = HdrDecoder::new(Cursor::new(data))?;
         let meta = decoder.metadata();
@@ -186,6 +293,146 @@ impl Texture {
             label,
         }
     }
+    /// Uploads an imported texture with its whole mip chain, in the color space it was
+    /// imported with.
+    pub fn from_imported(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        imported: &crate::ImportedTexture,
+        label: impl Into<String>,
+    ) -> Texture {
+        let label: String = label.into();
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(&label),
+            size: wgpu::Extent3d {
+                width: imported.width,
+                height: imported.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: imported.mips.len() as u32,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: imported.format(),
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        for (mip_level, level) in imported.mips.iter().enumerate() {
+            let width = (imported.width >> mip_level).max(1);
+            let height = (imported.height >> mip_level).max(1);
+            queue.write_texture(
+                wgpu::TexelCopyTextureInfo {
+                    texture: &texture,
+                    mip_level: mip_level as u32,
+                    origin: wgpu::Origin3d::ZERO,
+                    aspect: wgpu::TextureAspect::All,
+                },
+                level,
+                wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(4 * width),
+                    rows_per_image: Some(height),
+                },
+                wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        Texture {
+            texture,
+            view,
+            sampler,
+            label,
+        }
+    }
+    /// Uploads a KTX2 texture with the mip levels it holds, block-compressed. Without
+    /// `TEXTURE_COMPRESSION_BC` on `device` the levels are decoded to RGBA8 first.
+    pub fn from_ktx2(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        ktx2: &crate::Ktx2Texture,
+        label: impl Into<String>,
+    ) -> Result<Texture, EngineError> {
+        if !device
+            .features()
+            .contains(wgpu::Features::TEXTURE_COMPRESSION_BC)
+        {
+            return Ok(Self::from_imported(
+                device,
+                queue,
+                &ktx2.decompress()?,
+                label,
+            ));
+        }
+        let label: String = label.into();
+        let size = wgpu::Extent3d {
+            width: ktx2.width,
+            height: ktx2.height,
+            depth_or_array_layers: 1,
+        };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(&label),
+            size,
+            mip_level_count: ktx2.levels.len() as u32,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: ktx2.format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        for (mip_level, level) in ktx2.levels.iter().enumerate() {
+            let mip_level = mip_level as u32;
+            let (width, height) = ktx2.level_size(mip_level);
+            queue.write_texture(
+                wgpu::TexelCopyTextureInfo {
+                    texture: &texture,
+                    mip_level,
+                    origin: wgpu::Origin3d::ZERO,
+                    aspect: wgpu::TextureAspect::All,
+                },
+                level,
+                wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(crate::Ktx2Texture::row_pitch(ktx2.format, width)),
+                    rows_per_image: Some(crate::Ktx2Texture::block_rows(ktx2.format, height)),
+                },
+                // Copies cover whole blocks, past the edge of levels smaller than one.
+                size.mip_level_size(mip_level, wgpu::TextureDimension::D2)
+                    .physical_size(ktx2.format),
+            );
+        }
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        Ok(Texture {
+            texture,
+            view,
+            sampler,
+            label,
+        })
+    }
     pub async fn from_bytes<P: AsRef<std::path::Path>>(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
@@ -301,6 +548,37 @@ impl Texture {
             label: label.unwrap_or("").to_string(),
         }
     }
+    /// Render attachment with `sample_count` samples per pixel. Bindable so a later pass can
+    /// read it per sample, as the water pass reads the scene depth.
+    pub fn multisampled(
+        device: &wgpu::Device,
+        size: wgpu::Extent3d,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+        label: Option<&str>,
+    ) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label,
+            size,
+            mip_level_count: 1,
+            sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label,
+            ..Default::default()
+        });
+        Self {
+            texture,
+            view,
+            sampler,
+            label: label.unwrap_or("").to_string(),
+        }
+    }
 }
 
 impl Into<CacheKey> for Texture {
@@ -311,6 +589,24 @@ impl Into<CacheKey> for Texture {
 
 pub struct TextureManager {
     textures: HashCache<Arc<Texture>>,
+    pub samplers: super::SamplerCache,
+    pub loader: TextureLoader,
+    residency: super::Residency,
+    /// Keys dropped by the budget since [`TextureManager::take_evicted`], whose bind groups
+    /// are stale.
+    evicted: Vec<CacheKey>,
+    /// Times a texture was inserted under each key. Kept across removal, so a texture
+    /// inserted again never matches a generation recorded for the one before.
+    generations: std::collections::HashMap<CacheKey, u64>,
+    /// Bind groups, by their own key, to drop when the texture under a key is replaced.
+    dependents: std::collections::HashMap<CacheKey, Vec<CacheKey>>,
+    /// Keys whose texture was replaced since [`TextureManager::take_replaced`].
+    replaced: Vec<CacheKey>,
+    /// Texture asset each key was loaded from, to load it again on a new device.
+    sources: std::collections::HashMap<CacheKey, String>,
+    /// Dependent bind groups of the replaced textures, see
+    /// [`TextureManager::take_stale_bind_groups`].
+    stale_bind_groups: Vec<CacheKey>,
 }
 impl TextureManager {
     pub fn get_or_load_texture(
@@ -320,23 +616,201 @@ impl TextureManager {
         texture: &str,
         surface_config: &wgpu::SurfaceConfiguration,
     ) -> Result<(Arc<Texture>, CacheKey), EngineError> {
-        let base_dir = crate::asset_dir()?.join("textures");
+        let base_dir = crate::AssetPaths::textures_dir();
         let cache_key = CacheKey::from(texture.to_string());
-        if let Some(tex) = self.get(cache_key.clone()) {
+        if let Some(tex) = self.get(&cache_key) {
             Ok((tex.clone(), cache_key))
         } else {
-            let img = image::open(base_dir.join(texture))
-                .map_err(|e| EngineError::AssetLoadError(e.to_string()))?
-                .to_rgba8();
-            let tex = Texture::from_image(device, queue, surface_config, &img, texture);
+            let tex = if crate::Ktx2Texture::accepts(texture) {
+                let ktx2 = crate::Ktx2Texture::load(texture)?;
+                Texture::from_ktx2(device, queue, &ktx2, texture)?
+            } else if let Some(ktx2) = crate::Ktx2Texture::load_imported(texture) {
+                Texture::from_ktx2(device, queue, &ktx2, texture)?
+            } else {
+                match crate::ImportedTexture::load(texture) {
+                    Some(imported) => Texture::from_imported(device, queue, &imported, texture),
+                    None => {
+                        let img = image::open(base_dir.join(texture))
+                            .map_err(|e| EngineError::AssetLoadError(e.to_string()))?
+                            .to_rgba8();
+                        Texture::from_image(device, queue, surface_config, &img, texture)
+                    }
+                }
+            };
             let arc = Arc::new(tex);
             self.insert(cache_key.clone(), arc.clone());
+            self.sources.insert(cache_key, texture.to_string());
             Ok((arc, cache_key))
         }
     }
+    /// Like [`TextureManager::get_or_load_texture`], except that once
+    /// [`TextureLoader::enable`] was called an uncached texture is decoded in the
+    /// background and `fallback` is returned until [`TextureManager::upload_finished`]
+    /// swaps it in. Keep the synchronous path for startup-critical textures.
+    pub fn request_texture(
+        &mut self,
+        queue: &wgpu::Queue,
+        device: &wgpu::Device,
+        texture: &str,
+        surface_config: &wgpu::SurfaceConfiguration,
+        fallback: impl FnOnce(&mut Self) -> (Arc<Texture>, CacheKey),
+    ) -> Result<(Arc<Texture>, CacheKey), EngineError> {
+        let cache_key = CacheKey::from(texture.to_string());
+        if let Some(tex) = self.get(&cache_key) {
+            return Ok((tex.clone(), cache_key));
+        }
+        if !TextureLoader::enabled() {
+            return self.get_or_load_texture(queue, device, texture, surface_config);
+        }
+        self.loader.request(cache_key, texture);
+        Ok(fallback(self))
+    }
+    /// Uploads the textures whose background decode finished and returns their keys.
+    /// Failed decodes are logged and keep their fallback.
+    pub fn upload_finished(
+        &mut self,
+        queue: &wgpu::Queue,
+        device: &wgpu::Device,
+        surface_config: &wgpu::SurfaceConfiguration,
+    ) -> Vec<CacheKey> {
+        let mut loaded = Vec::new();
+        for job in self.loader.finished() {
+            let tex = match job.result {
+                Ok(DecodedTexture::Imported(imported)) => {
+                    Texture::from_imported(device, queue, &imported, &job.name)
+                }
+                Ok(DecodedTexture::Image(img)) => {
+                    Texture::from_image(device, queue, surface_config, &img, &job.name)
+                }
+                Ok(DecodedTexture::Compressed(ktx2)) => {
+                    match Texture::from_ktx2(device, queue, &ktx2, &job.name) {
+                        Ok(tex) => tex,
+                        Err(e) => {
+                            log_warning!("{}: {}", job.name, e);
+                            continue;
+                        }
+                    }
+                }
+                Err(e) => {
+                    log_warning!("{}: {}", job.name, e);
+                    continue;
+                }
+            };
+            self.insert(job.key, Arc::new(tex));
+            self.sources.insert(job.key, job.name);
+            loaded.push(job.key);
+        }
+        loaded
+    }
+}
+impl TextureManager {
+    /// Cube texture from six face images, see [`CubemapFaces::load_faces`]. Cached per
+    /// source and orientation.
+    pub fn load_cubemap_faces(
+        &mut self,
+        queue: &wgpu::Queue,
+        device: &wgpu::Device,
+        dir_or_pattern: &str,
+        orientation: CubemapOrientation,
+    ) -> Result<(Arc<Texture>, CacheKey), EngineError> {
+        self.load_cubemap(queue, device, dir_or_pattern, orientation, || {
+            CubemapFaces::load_faces(dir_or_pattern)
+        })
+    }
+    /// Cube texture from six face files, see [`CubemapFaces::load_face_files`].
+    pub fn load_cubemap_files(
+        &mut self,
+        queue: &wgpu::Queue,
+        device: &wgpu::Device,
+        paths: [&str; 6],
+        orientation: CubemapOrientation,
+    ) -> Result<(Arc<Texture>, CacheKey), EngineError> {
+        self.load_cubemap(queue, device, &paths.join(","), orientation, || {
+            CubemapFaces::load_face_files(paths)
+        })
+    }
+    /// Cube texture from one cross image, see [`CubemapFaces::load_cross`].
+    pub fn load_cubemap_cross(
+        &mut self,
+        queue: &wgpu::Queue,
+        device: &wgpu::Device,
+        path: &str,
+        orientation: CubemapOrientation,
+    ) -> Result<(Arc<Texture>, CacheKey), EngineError> {
+        self.load_cubemap(queue, device, path, orientation, || {
+            CubemapFaces::load_cross(path)
+        })
+    }
+    fn load_cubemap(
+        &mut self,
+        queue: &wgpu::Queue,
+        device: &wgpu::Device,
+        source: &str,
+        orientation: CubemapOrientation,
+        load: impl FnOnce() -> Result<CubemapFaces, EngineError>,
+    ) -> Result<(Arc<Texture>, CacheKey), EngineError> {
+        let labeled = CubemapSettings::label_faces();
+        let cache_key = CacheKey::from(format!("cubemap:{}:{:?}:{}", source, orientation, labeled));
+        if let Some(tex) = self.get(&cache_key) {
+            return Ok((tex.clone(), cache_key));
+        }
+        let mut faces = load()?;
+        if labeled {
+            faces = faces.labeled();
+        }
+        let tex = faces
+            .oriented(orientation)
+            .upload(device, queue, &format!("{} cubemap", source));
+        let arc = Arc::new(tex);
+        self.insert(cache_key, arc.clone());
+        Ok((arc, cache_key))
+    }
+    /// Texture array with one layer per file in `paths`, relative to `assets/textures`, see
+    /// [`Texture::from_layers`]. Every file is decoded to RGBA8 and resized to the first
+    /// one's size; fails for formats that aren't four bytes per texel and for more layers
+    /// than the device allows. Cached per `paths` and `format`.
+    pub fn load_texture_array(
+        &mut self,
+        queue: &wgpu::Queue,
+        device: &wgpu::Device,
+        paths: &[&str],
+        format: wgpu::TextureFormat,
+    ) -> Result<(Arc<Texture>, CacheKey), EngineError> {
+        let label = format!("array:{}:{:?}", paths.join(","), format);
+        let cache_key = CacheKey::from(label.as_str());
+        if let Some(tex) = self.get(&cache_key) {
+            return Ok((tex.clone(), cache_key));
+        }
+        let layers = paths.len() as u32;
+        if layers == 0 || layers > device.limits().max_texture_array_layers {
+            return Err(EngineError::AssetLoadError(format!(
+                "{}: {} layers not supported",
+                label, layers
+            )));
+        }
+        if format.block_dimensions() != (1, 1) || format.block_copy_size(None) != Some(4) {
+            return Err(EngineError::AssetLoadError(format!(
+                "{}: layers are RGBA8, {:?} can't hold them",
+                label, format
+            )));
+        }
+        let dir = crate::AssetPaths::textures_dir();
+        let images = paths
+            .iter()
+            .map(|path| {
+                image::open(dir.join(path))
+                    .map(|img| img.to_rgba8())
+                    .map_err(|e| EngineError::AssetLoadError(format!("{}: {}", path, e)))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let arc = Arc::new(Texture::from_layers(device, queue, &images, format, &label));
+        self.insert(cache_key, arc.clone());
+        Ok((arc, cache_key))
+    }
 }
 impl CacheStorage<Arc<Texture>> for TextureManager {
     fn get(&self, key: &CacheKey) -> Option<&Arc<Texture>> {
+        self.residency.touch(key);
         self.textures.get(key)
     }
 
@@ -344,36 +818,133 @@ impl CacheStorage<Arc<Texture>> for TextureManager {
         self.textures.contains_key(key)
     }
     fn get_mut(&mut self, key: &CacheKey) -> Option<&mut Arc<Texture>> {
+        self.residency.touch(key);
         self.textures.get_mut(key)
     }
     fn get_or_create<F>(&mut self, key: CacheKey, create_fn: F) -> &mut Arc<Texture>
     where
         F: FnOnce() -> Arc<Texture>,
     {
-        self.textures.entry(key).or_insert_with(create_fn)
+        if !self.textures.contains_key(&key) {
+            self.insert(key, create_fn());
+        }
+        self.residency.touch(&key);
+        self.textures.get_mut(&key).expect("inserted above")
     }
+    /// Caches `resource` and bumps the key's generation. Replacing a texture that was
+    /// inserted before marks the key as replaced and its dependent bind groups as stale.
     fn insert(&mut self, key: CacheKey, resource: Arc<Texture>) {
+        self.residency.track(key, resource.byte_size());
         self.textures.insert(key, resource);
+        let generation = self.generations.entry(key).or_default();
+        *generation += 1;
+        if *generation > 1 {
+            self.replaced.push(key);
+            if let Some(dependents) = self.dependents.get(&key) {
+                self.stale_bind_groups.extend(dependents.iter().copied());
+            }
+        }
+        self.enforce_budget();
     }
     fn remove(&mut self, key: &CacheKey) -> Option<std::sync::Arc<Texture>> {
+        self.residency.untrack(key);
         self.textures.remove(key)
     }
+    fn iter(&self) -> impl Iterator<Item = (&CacheKey, &std::sync::Arc<Texture>)> {
+        self.textures.iter()
+    }
 }
 
 impl TextureManager {
     pub fn new() -> Self {
         Self {
             textures: HashCache::new(),
+            samplers: super::SamplerCache::default(),
+            loader: TextureLoader::new(),
+            residency: super::Residency::default(),
+            evicted: Vec::new(),
+            generations: std::collections::HashMap::new(),
+            dependents: std::collections::HashMap::new(),
+            replaced: Vec::new(),
+            stale_bind_groups: Vec::new(),
+            sources: std::collections::HashMap::new(),
         }
     }
-
-    /// Retrieve a previously loaded texture
-    pub fn get<K: Into<CacheKey>>(&self, key: K) -> Option<Arc<Texture>> {
-        self.textures.get(&key.into()).cloned()
+    /// Texture assets of the cached textures that were loaded from one, by name as
+    /// [`TextureManager::get_or_load_texture`] takes them.
+    pub fn sources(&self) -> impl Iterator<Item = &str> {
+        self.textures
+            .keys()
+            .filter_map(|key| self.sources.get(key).map(String::as_str))
+    }
+    /// Key and label of every cached texture built at runtime rather than loaded from an
+    /// asset. Nothing can load these again once the device they live on is lost.
+    pub fn unrebuildable(&self) -> Vec<(CacheKey, String)> {
+        self.textures
+            .iter()
+            .filter(|(key, _)| !self.sources.contains_key(key))
+            .map(|(key, texture)| (*key, texture.label.clone()))
+            .collect()
     }
 
-    /// Unload a texture from the manager (will free when Arc drops)
-    pub fn unload<K: Into<CacheKey>>(&mut self, key: K) {
-        self.textures.remove(&key.into());
+    /// Bytes of cached textures allowed before the least recently used are dropped;
+    /// `None`, the default, never evicts.
+    pub fn set_budget(&mut self, budget: Option<u64>) {
+        self.residency.set_budget(budget);
+        self.enforce_budget();
+    }
+    pub fn residency(&self) -> &super::Residency {
+        &self.residency
+    }
+    pub fn stats(&self) -> super::ResourceStats {
+        super::ResourceStats {
+            texture_bytes: self.residency.bytes(),
+            evictions: self.residency.evictions(),
+            ..Default::default()
+        }
+    }
+    /// Keys evicted since the last call, for dropping bind groups built from them; see
+    /// [`super::BindGroupManager::invalidate`].
+    pub fn take_evicted(&mut self) -> Vec<CacheKey> {
+        std::mem::take(&mut self.evicted)
+    }
+    /// How many times a texture was inserted under `key`; 0 when none ever was. Anything
+    /// built from the texture can record it and rebuild once it changes.
+    pub fn generation(&self, key: &CacheKey) -> u64 {
+        self.generations.get(key).copied().unwrap_or(0)
+    }
+    /// Registers the bind group cached under `bind_group` as built from the texture under
+    /// `texture`, so replacing the texture reports it from
+    /// [`TextureManager::take_stale_bind_groups`].
+    pub fn add_dependent(&mut self, texture: CacheKey, bind_group: CacheKey) {
+        let dependents = self.dependents.entry(texture).or_default();
+        if !dependents.contains(&bind_group) {
+            dependents.push(bind_group);
+        }
+    }
+    /// Keys whose texture was replaced since the last call, for rebuilding materials that
+    /// sample them; see [`crate::ModelManager::rebind_textures`].
+    pub fn take_replaced(&mut self) -> Vec<CacheKey> {
+        std::mem::take(&mut self.replaced)
+    }
+    /// Dependent bind groups of textures replaced since the last call; see
+    /// [`super::BindGroupManager::invalidate`].
+    pub fn take_stale_bind_groups(&mut self) -> Vec<CacheKey> {
+        std::mem::take(&mut self.stale_bind_groups)
+    }
+    /// Drops least recently used textures until the cache fits its budget. Textures still
+    /// held elsewhere, by a material or bind group, stay: dropping the cache's handle
+    /// wouldn't free them, and the next load would upload a second copy.
+    fn enforce_budget(&mut self) {
+        let textures = &self.textures;
+        let evicted = self.residency.evict(|key| {
+            textures
+                .get(key)
+                .is_some_and(|texture| Arc::strong_count(texture) > 1)
+        });
+        for key in &evicted {
+            self.textures.remove(key);
+        }
+        self.evicted.extend(evicted);
     }
 }
@@ -0,0 +1,112 @@
+use std::{
+    collections::HashSet,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use crossbeam::channel::{Receiver, Sender};
+
+use crate::{
+    log_debug, CacheKey, EngineError, EngineEvent, EventBus, ImportedTexture, Ktx2Texture,
+};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Texture data decoded off the render thread, waiting for upload.
+pub enum DecodedTexture {
+    Imported(ImportedTexture),
+    Image(image::RgbaImage),
+    Compressed(Ktx2Texture),
+}
+
+impl DecodedTexture {
+    /// Same lookup as [`crate::TextureManager::get_or_load_texture`]: a KTX2 file as is,
+    /// a fresh imported derivative, compressed or not, the source image otherwise.
+    pub fn decode(texture: &str) -> Result<Self, EngineError> {
+        if Ktx2Texture::accepts(texture) {
+            return Ok(Self::Compressed(Ktx2Texture::load(texture)?));
+        }
+        if let Some(compressed) = Ktx2Texture::load_imported(texture) {
+            return Ok(Self::Compressed(compressed));
+        }
+        if let Some(imported) = ImportedTexture::load(texture) {
+            return Ok(Self::Imported(imported));
+        }
+        let path = crate::AssetPaths::texture(texture);
+        let image = image::open(path).map_err(|e| EngineError::AssetLoadError(e.to_string()))?;
+        Ok(Self::Image(image.to_rgba8()))
+    }
+}
+
+/// A finished decode job.
+pub struct TextureJob {
+    pub key: CacheKey,
+    pub name: String,
+    pub result: Result<DecodedTexture, EngineError>,
+}
+
+/// Decodes textures on background tasks. Each finished job is announced with
+/// [`EngineEvent::TextureLoaded`] on the [`EventBus`] once [`TextureLoader::enable`] has
+/// been called; until then textures load synchronously.
+pub struct TextureLoader {
+    in_flight: HashSet<CacheKey>,
+    sender: Sender<TextureJob>,
+    receiver: Receiver<TextureJob>,
+}
+
+impl Default for TextureLoader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TextureLoader {
+    pub fn new() -> Self {
+        let (sender, receiver) = crossbeam::channel::unbounded();
+        Self {
+            in_flight: HashSet::new(),
+            sender,
+            receiver,
+        }
+    }
+    /// Turns on async loading. Something has to answer [`EngineEvent::TextureLoaded`] with
+    /// [`crate::TextureManager::upload_finished`], or decoded textures are never swapped in.
+    pub fn enable() {
+        ENABLED.store(true, Ordering::Release);
+    }
+    pub fn enabled() -> bool {
+        ENABLED.load(Ordering::Acquire)
+    }
+    pub fn is_loading(&self, key: &CacheKey) -> bool {
+        self.in_flight.contains(key)
+    }
+    /// Queues a decode of `texture` unless one for `key` is already running.
+    pub fn request(&mut self, key: CacheKey, texture: &str) {
+        if !self.in_flight.insert(key) {
+            return;
+        }
+        let name = texture.to_string();
+        let sender = self.sender.clone();
+        let job = move || {
+            let result = DecodedTexture::decode(&name);
+            let _ = sender.send(TextureJob { key, name, result });
+            EventBus::publish(EngineEvent::TextureLoaded(key));
+        };
+        match tokio::runtime::Handle::try_current() {
+            Ok(handle) => {
+                handle.spawn_blocking(job);
+            }
+            Err(_) => {
+                std::thread::spawn(job);
+            }
+        }
+        log_debug!("Decoding {} in the background", texture);
+    }
+    /// Finished jobs since the last call.
+    pub fn finished(&mut self) -> Vec<TextureJob> {
+        let jobs: Vec<TextureJob> = self.receiver.try_iter().collect();
+        for job in &jobs {
+            self.in_flight.remove(&job.key);
+        }
+        jobs
+    }
+}
@@ -0,0 +1,522 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use glam::{DVec3, Vec3};
+use rhai::{Array, Dynamic, Engine, Map, FLOAT, INT};
+
+use crate::{
+    Entity, Health, Position, RenderSettings, Tint, Velocity, World, WorldEvent, WorldOrigin,
+};
+
+/// Deferred world changes a script asked for, applied in call order once the pass ends.
+#[derive(Debug, Clone)]
+pub enum ScriptCommand {
+    Damage {
+        target: Entity,
+        amount: f32,
+        source: Option<Entity>,
+    },
+    Spawn {
+        model: String,
+        /// Local.
+        position: Vec3,
+        script: Option<String>,
+    },
+    Despawn(Entity),
+    /// Calls `function` on the caller's script `delay` seconds of sim time from now.
+    After {
+        entity: Entity,
+        delay: f64,
+        function: String,
+    },
+    /// Calls `function` on the caller's script for every event of `kind`.
+    Subscribe {
+        entity: Entity,
+        kind: String,
+        function: String,
+    },
+}
+
+/// Columns moved out of the [`World`] for one script pass. Scripts see global positions.
+#[derive(Debug, Default)]
+pub struct ScriptFrame {
+    pub now: f64,
+    pub origin: WorldOrigin,
+    /// Entity whose script is running.
+    pub caller: Option<Entity>,
+    pub positions: Vec<Option<Position>>,
+    pub velocities: Vec<Option<Velocity>>,
+    pub tints: Vec<Option<Tint>>,
+    pub healths: Vec<Option<Health>>,
+    pub named: HashMap<String, Entity>,
+    pub tagged: HashMap<String, Vec<Entity>>,
+    pub commands: Vec<ScriptCommand>,
+}
+
+impl ScriptFrame {
+    /// Moves the exposed columns out of `world`.
+    pub fn begin(&mut self, world: &mut World) {
+        self.now = world.elapsed();
+        self.origin = world.origin();
+        self.caller = None;
+        self.positions = std::mem::take(&mut world.physics.positions);
+        self.velocities = std::mem::take(&mut world.physics.velocities);
+        self.tints = std::mem::take(&mut world.tints);
+        self.healths = std::mem::take(&mut world.healths);
+        self.named.clear();
+        self.tagged.clear();
+        if let Some(scene) = world.scene() {
+            self.named.clone_from(&scene.named);
+            self.tagged.clone_from(&scene.tagged);
+        }
+    }
+    /// Moves the columns back into `world` and hands over the queued commands.
+    pub fn end(&mut self, world: &mut World) -> Vec<ScriptCommand> {
+        world.physics.positions = std::mem::take(&mut self.positions);
+        world.physics.velocities = std::mem::take(&mut self.velocities);
+        world.tints = std::mem::take(&mut self.tints);
+        world.healths = std::mem::take(&mut self.healths);
+        self.caller = None;
+        std::mem::take(&mut self.commands)
+    }
+}
+
+pub type SharedFrame = Arc<Mutex<ScriptFrame>>;
+
+fn with<R>(frame: &SharedFrame, f: impl FnOnce(&mut ScriptFrame) -> R) -> R {
+    f(&mut frame.lock().unwrap_or_else(PoisonError::into_inner))
+}
+
+fn entity(id: INT) -> Option<Entity> {
+    usize::try_from(id).ok().map(Entity)
+}
+
+fn slot<T>(column: &mut [Option<T>], id: INT) -> Option<&mut Option<T>> {
+    column.get_mut(entity(id)?.0)
+}
+
+fn dvec3(v: DVec3) -> Dynamic {
+    let array: Array = v.to_array().iter().map(|c| Dynamic::from(*c)).collect();
+    array.into()
+}
+
+fn vec3(v: Vec3) -> Dynamic {
+    let array: Array = v
+        .to_array()
+        .iter()
+        .map(|c| Dynamic::from(*c as FLOAT))
+        .collect();
+    array.into()
+}
+
+/// Script-side name of a world event, as used by `subscribe`, and its fields as a map.
+pub fn event_map(event: &WorldEvent) -> (&'static str, Map) {
+    let mut map = Map::new();
+    let id = |e: Entity| Dynamic::from(e.0 as INT);
+    let kind = match event {
+        WorldEvent::SnapshotStarved(entity) => {
+            map.insert("entity".into(), id(*entity));
+            "snapshot_starved"
+        }
+        WorldEvent::EntityExpired(entity, reason) => {
+            map.insert("entity".into(), id(*entity));
+            map.insert("reason".into(), format!("{:?}", reason).into());
+            "expired"
+        }
+        WorldEvent::Landed(entity, speed) => {
+            map.insert("entity".into(), id(*entity));
+            map.insert("speed".into(), Dynamic::from(*speed as FLOAT));
+            "landed"
+        }
+        WorldEvent::Sequence(name) => {
+            map.insert("name".into(), name.clone().into());
+            "sequence"
+        }
+        WorldEvent::DamageTaken {
+            target,
+            amount,
+            source,
+            remaining,
+        } => {
+            map.insert("entity".into(), id(*target));
+            map.insert("amount".into(), Dynamic::from(*amount as FLOAT));
+            map.insert("source".into(), source.map_or(Dynamic::UNIT, id));
+            map.insert("remaining".into(), Dynamic::from(*remaining as FLOAT));
+            "damage"
+        }
+        WorldEvent::EntityDied { entity, source } => {
+            map.insert("entity".into(), id(*entity));
+            map.insert("source".into(), source.map_or(Dynamic::UNIT, id));
+            "died"
+        }
+        WorldEvent::PlaySound { key, position } => {
+            map.insert("key".into(), Dynamic::from(key.id() as INT));
+            map.insert("position".into(), vec3(*position));
+            "sound"
+        }
+    };
+    map.insert("kind".into(), kind.into());
+    (kind, map)
+}
+
+/// Registers the functions scripts can call. Entities are integer ids; getters return `()`
+/// for entities without the component, setters ignore them.
+pub fn register_api(engine: &mut Engine, frame: &SharedFrame) {
+    engine.on_print(|text| crate::log_info!("[script] {}", text));
+    engine.on_debug(|text, source, pos| {
+        crate::log_debug!("[script {}:{}] {}", source.unwrap_or(""), pos, text)
+    });
+
+    let f = frame.clone();
+    engine.register_fn("now", move || with(&f, |frame| frame.now));
+    engine.register_fn("cvar", |name: &str, default: FLOAT| {
+        RenderSettings::shader_constant(name).unwrap_or(default)
+    });
+
+    let f = frame.clone();
+    engine.register_fn("find", move |name: &str| {
+        with(&f, |frame| frame.named.get(name).map_or(-1, |e| e.0 as INT))
+    });
+    let f = frame.clone();
+    engine.register_fn("tagged", move |tag: &str| -> Array {
+        with(&f, |frame| {
+            frame
+                .tagged
+                .get(tag)
+                .map(|entities| entities.iter().map(|e| Dynamic::from(e.0 as INT)).collect())
+                .unwrap_or_default()
+        })
+    });
+
+    let f = frame.clone();
+    engine.register_fn("position", move |id: INT| {
+        with(&f, |frame| match slot(&mut frame.positions, id) {
+            Some(Some(p)) => dvec3(frame.origin.to_global(p.0)),
+            _ => Dynamic::UNIT,
+        })
+    });
+    let f = frame.clone();
+    engine.register_fn(
+        "set_position",
+        move |id: INT, x: FLOAT, y: FLOAT, z: FLOAT| {
+            with(&f, |frame| {
+                let local = frame.origin.to_local(DVec3::new(x, y, z));
+                if let Some(slot) = slot(&mut frame.positions, id) {
+                    *slot = Some(Position(local));
+                }
+            })
+        },
+    );
+    let f = frame.clone();
+    engine.register_fn("velocity", move |id: INT| {
+        with(&f, |frame| match slot(&mut frame.velocities, id) {
+            Some(Some(v)) => vec3(v.0),
+            _ => Dynamic::UNIT,
+        })
+    });
+    let f = frame.clone();
+    engine.register_fn(
+        "set_velocity",
+        move |id: INT, x: FLOAT, y: FLOAT, z: FLOAT| {
+            with(&f, |frame| {
+                if let Some(slot) = slot(&mut frame.velocities, id) {
+                    *slot = Some(Velocity(Vec3::new(x as f32, y as f32, z as f32)));
+                }
+            })
+        },
+    );
+    let f = frame.clone();
+    engine.register_fn("impulse", move |id: INT, x: FLOAT, y: FLOAT, z: FLOAT| {
+        with(&f, |frame| {
+            if let Some(slot) = slot(&mut frame.velocities, id) {
+                let v = slot.map_or(Vec3::ZERO, |v| v.0);
+                *slot = Some(Velocity(v + Vec3::new(x as f32, y as f32, z as f32)));
+            }
+        })
+    });
+    let f = frame.clone();
+    engine.register_fn("tint", move |id: INT| {
+        with(&f, |frame| match slot(&mut frame.tints, id) {
+            Some(Some(t)) => {
+                let array: Array = t.0.iter().map(|c| Dynamic::from(*c as FLOAT)).collect();
+                array.into()
+            }
+            _ => Dynamic::UNIT,
+        })
+    });
+    let f = frame.clone();
+    engine.register_fn(
+        "set_tint",
+        move |id: INT, r: FLOAT, g: FLOAT, b: FLOAT, a: FLOAT| {
+            with(&f, |frame| {
+                if let Some(slot) = slot(&mut frame.tints, id) {
+                    *slot = Some(Tint([r as f32, g as f32, b as f32, a as f32]));
+                }
+            })
+        },
+    );
+    let f = frame.clone();
+    engine.register_fn("health", move |id: INT| {
+        with(&f, |frame| match slot(&mut frame.healths, id) {
+            Some(Some(h)) => Dynamic::from(h.current as FLOAT),
+            _ => Dynamic::UNIT,
+        })
+    });
+    let f = frame.clone();
+    engine.register_fn("set_health", move |id: INT, value: FLOAT| {
+        with(&f, |frame| {
+            if let Some(Some(health)) = slot(&mut frame.healths, id) {
+                health.current = (value as f32).clamp(0.0, health.max);
+            }
+        })
+    });
+
+    let f = frame.clone();
+    engine.register_fn("damage", move |target: INT, amount: FLOAT| {
+        with(&f, |frame| {
+            if let Some(target) = entity(target) {
+                let source = frame.caller;
+                frame.commands.push(ScriptCommand::Damage {
+                    target,
+                    amount: amount as f32,
+                    source,
+                });
+            }
+        })
+    });
+    // `spawn` is a reserved word in Rhai.
+    let f = frame.clone();
+    engine.register_fn(
+        "spawn_model",
+        move |model: &str, x: FLOAT, y: FLOAT, z: FLOAT| {
+            with(&f, |frame| {
+                frame.commands.push(ScriptCommand::Spawn {
+                    model: model.to_string(),
+                    position: frame.origin.to_local(DVec3::new(x, y, z)),
+                    script: None,
+                })
+            })
+        },
+    );
+    let f = frame.clone();
+    engine.register_fn(
+        "spawn_model",
+        move |model: &str, x: FLOAT, y: FLOAT, z: FLOAT, script: &str| {
+            with(&f, |frame| {
+                frame.commands.push(ScriptCommand::Spawn {
+                    model: model.to_string(),
+                    position: frame.origin.to_local(DVec3::new(x, y, z)),
+                    script: Some(script.to_string()),
+                })
+            })
+        },
+    );
+    let f = frame.clone();
+    engine.register_fn("despawn", move |id: INT| {
+        with(&f, |frame| {
+            if let Some(entity) = entity(id) {
+                frame.commands.push(ScriptCommand::Despawn(entity));
+            }
+        })
+    });
+
+    let f = frame.clone();
+    engine.register_fn("after", move |delay: FLOAT, function: &str| {
+        with(&f, |frame| {
+            if let Some(entity) = frame.caller {
+                frame.commands.push(ScriptCommand::After {
+                    entity,
+                    delay: delay.max(0.0),
+                    function: function.to_string(),
+                });
+            }
+        })
+    });
+    let f = frame.clone();
+    engine.register_fn("subscribe", move |kind: &str, function: &str| {
+        with(&f, |frame| {
+            if let Some(entity) = frame.caller {
+                frame.commands.push(ScriptCommand::Subscribe {
+                    entity,
+                    kind: kind.to_string(),
+                    function: function.to_string(),
+                });
+            }
+        })
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::I64Vec3;
+
+    use super::*;
+    use crate::CHUNK_SIZE;
+
+    /// An engine with the API over a frame holding entities 0 and 1, 0 fully populated.
+    fn engine() -> (Engine, SharedFrame) {
+        let frame: SharedFrame = Arc::default();
+        with(&frame, |frame| {
+            frame.origin = WorldOrigin::from_chunk(I64Vec3::new(2, 0, -1));
+            frame.caller = Some(Entity(1));
+            frame.now = 12.5;
+            frame.positions = vec![Some(Position(Vec3::new(1.0, 2.0, 3.0))), None];
+            frame.velocities = vec![Some(Velocity(Vec3::X)), None];
+            frame.tints = vec![Some(Tint([1.0, 0.5, 0.25, 1.0])), None];
+            frame.healths = vec![Some(Health::new(10.0)), None];
+            frame.named.insert("door".into(), Entity(0));
+            frame
+                .tagged
+                .insert("enemy".into(), vec![Entity(0), Entity(1)]);
+        });
+        let mut engine = Engine::new();
+        register_api(&mut engine, &frame);
+        (engine, frame)
+    }
+
+    #[test]
+    fn components_round_trip_through_scripts() {
+        let (engine, frame) = engine();
+        let offset = CHUNK_SIZE as f64;
+        // Positions are global in scripts and local in the world.
+        let global: Array = engine.eval("position(0)").unwrap();
+        let global: Vec<FLOAT> = global.into_iter().map(|c| c.cast()).collect();
+        assert_eq!(global, [1.0 + 2.0 * offset, 2.0, 3.0 - offset]);
+        engine
+            .run(&format!(
+                "set_position(0, {:.1}, 5.0, {:.1})",
+                4.0 + 2.0 * offset,
+                6.0 - offset
+            ))
+            .unwrap();
+        engine
+            .run("set_velocity(0, 0.0, 2.0, 0.0); impulse(0, 1.0, 0.0, 0.0);")
+            .unwrap();
+        engine.run("set_tint(0, 0.1, 0.2, 0.3, 0.4)").unwrap();
+        engine.run("set_health(0, 25.0)").unwrap();
+        with(&frame, |frame| {
+            assert_eq!(
+                frame.positions[0].map(|p| p.0),
+                Some(Vec3::new(4.0, 5.0, 6.0))
+            );
+            assert_eq!(
+                frame.velocities[0].map(|v| v.0),
+                Some(Vec3::new(1.0, 2.0, 0.0))
+            );
+            assert_eq!(frame.tints[0].map(|t| t.0), Some([0.1, 0.2, 0.3, 0.4]));
+            // Clamped to the maximum.
+            assert_eq!(frame.healths[0].unwrap().current, 10.0);
+        });
+        let tint: Array = engine.eval("tint(0)").unwrap();
+        assert_eq!(tint.len(), 4);
+        assert_eq!(engine.eval::<FLOAT>("health(0)").unwrap(), 10.0);
+        assert_eq!(engine.eval::<FLOAT>("velocity(0)[1]").unwrap(), 2.0);
+
+        // Missing components read as () and ignore writes; unknown ids too.
+        for script in [
+            "position(1)",
+            "velocity(1)",
+            "tint(1)",
+            "health(1)",
+            "health(99)",
+        ] {
+            assert!(
+                engine.eval::<Dynamic>(script).unwrap().is_unit(),
+                "{}",
+                script
+            );
+        }
+        engine
+            .run("set_health(1, 3.0); set_tint(-1, 0.0, 0.0, 0.0, 0.0); set_position(7, 0.0, 0.0, 0.0)")
+            .unwrap();
+        // Impulses start from rest on an entity without a velocity.
+        engine.run("impulse(1, 0.0, 0.0, 3.0)").unwrap();
+        with(&frame, |frame| {
+            assert!(frame.healths[1].is_none());
+            assert_eq!(frame.velocities[1].map(|v| v.0), Some(Vec3::Z * 3.0));
+        });
+    }
+
+    #[test]
+    fn queries_and_commands_reach_the_frame() {
+        let (engine, frame) = engine();
+        assert_eq!(engine.eval::<INT>("find(\"door\")").unwrap(), 0);
+        assert_eq!(engine.eval::<INT>("find(\"nobody\")").unwrap(), -1);
+        assert_eq!(engine.eval::<INT>("tagged(\"enemy\").len()").unwrap(), 2);
+        assert_eq!(engine.eval::<INT>("tagged(\"none\").len()").unwrap(), 0);
+        assert_eq!(engine.eval::<FLOAT>("now()").unwrap(), 12.5);
+        assert_eq!(
+            engine
+                .eval::<FLOAT>("cvar(\"NO_SUCH_CONSTANT\", 0.75)")
+                .unwrap(),
+            0.75
+        );
+
+        engine
+            .run(
+                "damage(0, 4.0);
+                 spawn_model(\"goblin.obj\", 0.0, 1.0, 0.0);
+                 spawn_model(\"goblin.obj\", 0.0, 1.0, 0.0, \"door\");
+                 despawn(0);
+                 after(-1.0, \"later\");
+                 subscribe(\"damage\", \"hurt\");",
+            )
+            .unwrap();
+        let commands = with(&frame, |frame| std::mem::take(&mut frame.commands));
+        assert_eq!(commands.len(), 6);
+        assert!(matches!(
+            commands[0],
+            ScriptCommand::Damage { target: Entity(0), amount, source: Some(Entity(1)) } if amount == 4.0
+        ));
+        let local = Vec3::new(-2.0 * CHUNK_SIZE as f32, 1.0, CHUNK_SIZE as f32);
+        assert!(matches!(
+            &commands[1],
+            ScriptCommand::Spawn { model, position, script: None } if model == "goblin.obj" && *position == local
+        ));
+        assert!(matches!(
+            &commands[2],
+            ScriptCommand::Spawn { script: Some(script), .. } if script == "door"
+        ));
+        assert!(matches!(commands[3], ScriptCommand::Despawn(Entity(0))));
+        // Negative delays run next update rather than in the past.
+        assert!(matches!(
+            &commands[4],
+            ScriptCommand::After { entity: Entity(1), delay, function } if *delay == 0.0 && function == "later"
+        ));
+        assert!(matches!(
+            &commands[5],
+            ScriptCommand::Subscribe { entity: Entity(1), kind, function } if kind == "damage" && function == "hurt"
+        ));
+
+        // Without a running script there's no one to call back.
+        with(&frame, |frame| frame.caller = None);
+        engine
+            .run("after(1.0, \"x\"); subscribe(\"died\", \"x\");")
+            .unwrap();
+        assert!(with(&frame, |frame| frame.commands.is_empty()));
+    }
+
+    #[test]
+    fn events_map_to_their_script_names_and_fields() {
+        let (kind, map) = event_map(&WorldEvent::DamageTaken {
+            target: Entity(3),
+            amount: 2.5,
+            source: None,
+            remaining: 7.5,
+        });
+        assert_eq!(kind, "damage");
+        assert_eq!(map["entity"].as_int().unwrap(), 3);
+        assert_eq!(map["amount"].as_float().unwrap(), 2.5);
+        assert!(map["source"].is_unit());
+        assert_eq!(map["kind"].clone().into_string().unwrap(), "damage");
+
+        let (kind, map) = event_map(&WorldEvent::Sequence("open_door".into()));
+        assert_eq!(kind, "sequence");
+        assert_eq!(map["name"].clone().into_string().unwrap(), "open_door");
+        let (kind, map) = event_map(&WorldEvent::EntityDied {
+            entity: Entity(4),
+            source: Some(Entity(1)),
+        });
+        assert_eq!((kind, map["source"].as_int().unwrap()), ("died", 1));
+    }
+}
@@ -0,0 +1,726 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use rhai::{CallFnOptions, Dynamic, Engine, EvalAltResult, Map, Scope, AST, INT};
+
+use super::{event_map, register_api, ScriptCommand, SharedFrame};
+use crate::{
+    log_debug, log_error, log_warning, AssetPaths, AssetWatcher, AutoLoad, EngineError, Entity,
+    ModelManager, Placement, ScriptBehavior, World, WorldEvent,
+};
+
+/// Limits on how much script work one update may do.
+#[derive(Debug, Clone, Copy)]
+pub struct ScriptSettings {
+    /// Wall time all scripts together get per update. Calls still pending when it runs out
+    /// are skipped until the next update.
+    pub frame_budget: Duration,
+    /// Operations a single call may run before it's aborted.
+    pub max_operations: u64,
+}
+
+impl Default for ScriptSettings {
+    fn default() -> Self {
+        Self {
+            frame_budget: Duration::from_millis(2),
+            max_operations: 100_000,
+        }
+    }
+}
+
+/// Per-entity script data. `state` is bound as `this` in every call and outlives reloads
+/// of the script.
+#[derive(Debug)]
+struct EntityScript {
+    script: String,
+    state: Dynamic,
+    ticks: u64,
+    since_update: f32,
+}
+
+#[derive(Debug, Clone)]
+struct Subscription {
+    entity: Entity,
+    kind: String,
+    function: String,
+}
+
+#[derive(Debug, Clone)]
+struct DelayedCall {
+    at: f64,
+    entity: Entity,
+    function: String,
+}
+
+/// Runs the [`ScriptBehavior`]s attached to world entities, with scripts compiled from
+/// `assets/scripts/<name>.rhai` and recompiled when the files change.
+///
+/// One [`ScriptHost::update`] runs, in order: `on_spawn` for newly scripted entities, event
+/// callbacks (per event in the order raised: `subscribe` callbacks in subscription order,
+/// then `on_event` by entity id), delayed calls that came due (by due time), then `on_update`.
+pub struct ScriptHost {
+    engine: Engine,
+    frame: SharedFrame,
+    deadline: Arc<Mutex<Instant>>,
+    settings: ScriptSettings,
+    dir: PathBuf,
+    scripts: HashMap<String, Arc<AST>>,
+    /// Scripts that failed to load, so the error is logged once per change instead of
+    /// every update.
+    failed: HashSet<String>,
+    entities: HashMap<Entity, EntityScript>,
+    subscriptions: Vec<Subscription>,
+    delayed: Vec<DelayedCall>,
+    changed: Arc<Mutex<HashSet<String>>>,
+    _watcher: Option<AssetWatcher>,
+}
+
+impl ScriptHost {
+    pub const EXTENSION: &'static str = ".rhai";
+
+    pub fn new(settings: ScriptSettings) -> Self {
+        let frame: SharedFrame = Arc::default();
+        let deadline = Arc::new(Mutex::new(Instant::now()));
+        let mut engine = Engine::new();
+        engine.set_max_operations(settings.max_operations);
+        let budget = deadline.clone();
+        engine.on_progress(move |ops| {
+            // Reading the clock on every operation would dominate small scripts.
+            if ops % 1024 != 0 {
+                return None;
+            }
+            let deadline = *budget.lock().unwrap_or_else(PoisonError::into_inner);
+            (Instant::now() > deadline).then(|| "frame budget exceeded".into())
+        });
+        register_api(&mut engine, &frame);
+        Self {
+            engine,
+            frame,
+            deadline,
+            settings,
+            dir: AssetPaths::dir("scripts"),
+            scripts: HashMap::new(),
+            failed: HashSet::new(),
+            entities: HashMap::new(),
+            subscriptions: Vec::new(),
+            delayed: Vec::new(),
+            changed: Arc::new(Mutex::new(HashSet::new())),
+            _watcher: None,
+        }
+    }
+    pub fn settings(&self) -> ScriptSettings {
+        self.settings
+    }
+    pub fn name_of(path: &Path) -> Option<String> {
+        path.file_name()?
+            .to_str()?
+            .strip_suffix(Self::EXTENSION)
+            .map(str::to_string)
+    }
+    pub fn path_of(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{}{}", name, Self::EXTENSION))
+    }
+
+    /// Compiles `name`, replacing the cached version. On failure the previous version, if
+    /// any, stays in use.
+    pub fn load(&mut self, name: &str) -> Result<(), EngineError> {
+        let path = self.path_of(name);
+        let source = std::fs::read_to_string(&path)
+            .map_err(|e| EngineError::AssetMissing(format!("{}: {}", path.display(), e)))?;
+        let ast = self.engine.compile(&source).map_err(|e| {
+            EngineError::AssetLoadError(format!(
+                "{}:{}: {}",
+                path.display(),
+                e.1.line().unwrap_or(0),
+                e.0
+            ))
+        })?;
+        log_debug!("Compiled script: {}", path.display());
+        self.scripts.insert(name.to_string(), Arc::new(ast));
+        self.failed.remove(name);
+        Ok(())
+    }
+
+    /// Starts watching the scripts directory. Changed scripts are recompiled at the start of
+    /// the next update; entities keep their `this` state across the swap.
+    pub fn watch(&mut self) -> Result<(), EngineError> {
+        if self._watcher.is_some() || !self.dir.exists() {
+            return Ok(());
+        }
+        let changed = self.changed.clone();
+        let watcher = AssetWatcher::new(self.dir.clone(), move |event| {
+            if !matches!(
+                event.kind,
+                notify::EventKind::Modify(_) | notify::EventKind::Create(_)
+            ) {
+                return;
+            }
+            if let Ok(mut changed) = changed.lock() {
+                changed.extend(event.paths.iter().filter_map(|p| Self::name_of(p)));
+            }
+        })
+        .map_err(|e| EngineError::FileSystemError(e.to_string()))?;
+        self._watcher = Some(watcher);
+        Ok(())
+    }
+    fn reload_changed(&mut self) {
+        let changed: Vec<String> = self
+            .changed
+            .lock()
+            .map(|mut changed| changed.drain().collect())
+            .unwrap_or_default();
+        for name in changed {
+            self.failed.remove(&name);
+            if !self.scripts.contains_key(&name) {
+                continue;
+            }
+            match self.load(&name) {
+                Ok(()) => log_debug!("Reloaded script: {}", name),
+                Err(e) => {
+                    log_error!("{}", e);
+                    self.failed.insert(name);
+                }
+            }
+        }
+    }
+    fn script(&mut self, name: &str) -> Option<Arc<AST>> {
+        if let Some(ast) = self.scripts.get(name) {
+            return Some(ast.clone());
+        }
+        if self.failed.contains(name) {
+            return None;
+        }
+        if let Err(e) = self.load(name) {
+            log_error!("{}", e);
+            self.failed.insert(name.to_string());
+            return None;
+        }
+        self.scripts.get(name).cloned()
+    }
+
+    /// Runs one pass over the world's scripted entities. `events` are the world events of
+    /// this update; `auto_load` is used for models scripts spawn that aren't cached yet.
+    pub fn update(
+        &mut self,
+        world: &mut World,
+        model_manager: &mut ModelManager,
+        events: &[WorldEvent],
+        auto_load: Option<&AutoLoad>,
+        dt: f32,
+    ) {
+        self.reload_changed();
+
+        let behaviors: Vec<(Entity, ScriptBehavior)> = world
+            .scripts
+            .iter()
+            .enumerate()
+            .filter_map(|(i, script)| Some((Entity(i), script.clone()?)))
+            .collect();
+        let scripted: HashSet<Entity> = behaviors.iter().map(|(e, _)| *e).collect();
+        self.entities.retain(|entity, _| scripted.contains(entity));
+        self.subscriptions
+            .retain(|sub| scripted.contains(&sub.entity));
+        self.delayed.retain(|call| scripted.contains(&call.entity));
+        if behaviors.is_empty() {
+            return;
+        }
+
+        *self.deadline.lock().unwrap_or_else(PoisonError::into_inner) =
+            Instant::now() + self.settings.frame_budget;
+        self.frame
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .begin(world);
+        let mut pass = Pass::default();
+
+        for (entity, behavior) in &behaviors {
+            let restarted = self
+                .entities
+                .get(entity)
+                .is_some_and(|e| e.script != behavior.script);
+            if restarted || !self.entities.contains_key(entity) {
+                self.entities.insert(
+                    *entity,
+                    EntityScript {
+                        script: behavior.script.clone(),
+                        state: Dynamic::from_map(Map::new()),
+                        ticks: 0,
+                        since_update: 0.0,
+                    },
+                );
+                let Some(function) = &behavior.entry_points.on_spawn else {
+                    continue;
+                };
+                if !self.call(&mut pass, *entity, function, (entity.0 as INT,)) {
+                    // Out of budget: spawn again next update rather than never.
+                    self.entities.remove(entity);
+                }
+            }
+        }
+
+        for event in events {
+            let (kind, map) = event_map(event);
+            let subscribers: Vec<Subscription> = self
+                .subscriptions
+                .iter()
+                .filter(|sub| sub.kind == kind)
+                .cloned()
+                .collect();
+            for sub in subscribers {
+                let args = (sub.entity.0 as INT, Dynamic::from_map(map.clone()));
+                self.call(&mut pass, sub.entity, &sub.function, args);
+            }
+            for (entity, behavior) in &behaviors {
+                if let Some(function) = &behavior.entry_points.on_event {
+                    let args = (entity.0 as INT, Dynamic::from_map(map.clone()));
+                    self.call(&mut pass, *entity, function, args);
+                }
+            }
+        }
+
+        let now = world_now(&self.frame);
+        self.delayed.sort_by(|a, b| a.at.total_cmp(&b.at));
+        let due = self.delayed.partition_point(|call| call.at <= now);
+        for call in self.delayed.drain(..due).collect::<Vec<_>>() {
+            self.call(
+                &mut pass,
+                call.entity,
+                &call.function,
+                (call.entity.0 as INT,),
+            );
+        }
+
+        for (entity, behavior) in &behaviors {
+            let Some(function) = &behavior.entry_points.on_update else {
+                continue;
+            };
+            let Some(state) = self.entities.get_mut(entity) else {
+                continue;
+            };
+            state.ticks += 1;
+            state.since_update += dt;
+            if state.ticks % behavior.entry_points.update_divisor.max(1) as u64 != 0 {
+                continue;
+            }
+            let elapsed = std::mem::take(&mut state.since_update);
+            self.call(
+                &mut pass,
+                *entity,
+                function,
+                (entity.0 as INT, elapsed as f64),
+            );
+        }
+
+        let commands = self
+            .frame
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .end(world);
+        self.apply(world, model_manager, commands, now, auto_load);
+        if pass.skipped > 0 {
+            log_warning!(
+                "Script frame budget of {:?} spent; skipped {} calls",
+                self.settings.frame_budget,
+                pass.skipped
+            );
+        }
+    }
+
+    /// Calls `function` in `entity`'s script with its state bound as `this`. Missing
+    /// functions are ignored; errors are logged with the script name and line. `false` if
+    /// the call was skipped or cut off by the frame budget.
+    fn call(
+        &mut self,
+        pass: &mut Pass,
+        entity: Entity,
+        function: &str,
+        args: impl rhai::FuncArgs,
+    ) -> bool {
+        if pass.exhausted {
+            pass.skipped += 1;
+            return false;
+        }
+        let Some(name) = self.entities.get(&entity).map(|e| e.script.clone()) else {
+            return true;
+        };
+        let Some(ast) = self.script(&name) else {
+            return true;
+        };
+        if !ast.iter_functions().any(|f| f.name == function) {
+            return true;
+        }
+        let Some(state) = self.entities.get_mut(&entity) else {
+            return true;
+        };
+        self.frame
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .caller = Some(entity);
+        let options = CallFnOptions::new()
+            .eval_ast(false)
+            .bind_this_ptr(&mut state.state);
+        let result = self.engine.call_fn_with_options::<Dynamic>(
+            options,
+            &mut Scope::new(),
+            &ast,
+            function,
+            args,
+        );
+        match result.map_err(|e| *e) {
+            Ok(_) => {}
+            Err(EvalAltResult::ErrorTerminated(..)) => {
+                pass.exhausted = true;
+                pass.skipped += 1;
+                log_warning!(
+                    "{}{}: {} ran past the frame budget and was aborted",
+                    name,
+                    Self::EXTENSION,
+                    function
+                );
+                return false;
+            }
+            Err(EvalAltResult::ErrorTooManyOperations(pos)) => log_warning!(
+                "{}{}:{}: {} exceeded {} operations and was aborted",
+                name,
+                Self::EXTENSION,
+                pos.line().unwrap_or(0),
+                function,
+                self.settings.max_operations
+            ),
+            Err(e) => log_error!(
+                "{}{}:{}: {}: {}",
+                name,
+                Self::EXTENSION,
+                e.position().line().unwrap_or(0),
+                function,
+                e
+            ),
+        }
+        true
+    }
+
+    fn apply(
+        &mut self,
+        world: &mut World,
+        model_manager: &mut ModelManager,
+        commands: Vec<ScriptCommand>,
+        now: f64,
+        auto_load: Option<&AutoLoad>,
+    ) {
+        for command in commands {
+            match command {
+                ScriptCommand::Damage {
+                    target,
+                    amount,
+                    source,
+                } => {
+                    world.apply_damage(target, amount, source);
+                }
+                ScriptCommand::Spawn {
+                    model,
+                    position,
+                    script,
+                } => {
+                    let mut placement = Placement::at(position);
+                    if let Some(auto_load) = auto_load {
+                        placement = placement.with_auto_load(auto_load.clone());
+                    }
+                    match world.spawn_model(model_manager, &model, placement) {
+                        Ok(entity) => {
+                            if let Some(script) = script {
+                                world.insert_script(entity, ScriptBehavior::new(script));
+                            }
+                        }
+                        Err(e) => log_warning!("Script spawn failed: {}", e),
+                    }
+                }
+                ScriptCommand::Despawn(entity) => world.despawn(entity),
+                ScriptCommand::After {
+                    entity,
+                    delay,
+                    function,
+                } => self.delayed.push(DelayedCall {
+                    at: now + delay,
+                    entity,
+                    function,
+                }),
+                ScriptCommand::Subscribe {
+                    entity,
+                    kind,
+                    function,
+                } => {
+                    let duplicate = self.subscriptions.iter().any(|sub| {
+                        sub.entity == entity && sub.kind == kind && sub.function == function
+                    });
+                    if !duplicate {
+                        self.subscriptions.push(Subscription {
+                            entity,
+                            kind,
+                            function,
+                        });
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Default for ScriptHost {
+    fn default() -> Self {
+        Self::new(ScriptSettings::default())
+    }
+}
+
+/// Bookkeeping for one [`ScriptHost::update`].
+#[derive(Default)]
+struct Pass {
+    /// The frame budget ran out; remaining calls are skipped.
+    exhausted: bool,
+    skipped: usize,
+}
+
+fn world_now(frame: &SharedFrame) -> f64 {
+    frame.lock().unwrap_or_else(PoisonError::into_inner).now
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Trace = Arc<Mutex<Vec<String>>>;
+
+    /// A host whose scripts can `trace(text)`, an empty world and a model manager. Needs an
+    /// adapter for the model manager.
+    fn host(settings: ScriptSettings) -> Option<(ScriptHost, Trace, World, ModelManager)> {
+        crate::assets::loader::test_root();
+        let _ = crate::GPU::init(crate::GpuRequirements::default());
+        let (device, queue) =
+            crate::GPU::with_read_recovered(|gpu| (gpu.device().clone(), gpu.queue().clone()))
+                .ok()?;
+        let mut host = ScriptHost::new(settings);
+        let trace = Trace::default();
+        let log = trace.clone();
+        host.engine.register_fn("trace", move |text: &str| {
+            log.lock().unwrap().push(text.to_string());
+        });
+        Some((
+            host,
+            trace,
+            World::empty(),
+            ModelManager::new(queue, device),
+        ))
+    }
+
+    fn write(host: &ScriptHost, name: &str, source: &str) {
+        std::fs::create_dir_all(&host.dir).unwrap();
+        std::fs::write(host.path_of(name), source).unwrap();
+    }
+
+    fn scripted(world: &mut World, script: &str) -> Entity {
+        let entity = world.spawn();
+        world.insert_script(entity, ScriptBehavior::new(script));
+        entity
+    }
+
+    fn take(trace: &Trace) -> Vec<String> {
+        std::mem::take(&mut *trace.lock().unwrap())
+    }
+
+    /// Needs an adapter; passes without one.
+    #[test]
+    fn shipped_scripts_compile() {
+        let Some((mut host, ..)) = host(ScriptSettings::default()) else {
+            return;
+        };
+        for entry in std::fs::read_dir(&host.dir).unwrap() {
+            let name = ScriptHost::name_of(&entry.unwrap().path());
+            if let Some(name) = name.filter(|name| !name.starts_with("test_")) {
+                host.load(&name).unwrap();
+            }
+        }
+        assert!(host.scripts.contains_key("door"));
+    }
+
+    /// Needs an adapter; passes without one.
+    #[test]
+    fn runaway_scripts_are_cut_off_without_stopping_the_rest() {
+        let settings = ScriptSettings {
+            frame_budget: Duration::from_secs(5),
+            max_operations: 500,
+        };
+        let Some((mut host, trace, mut world, mut models)) = host(settings) else {
+            return;
+        };
+        write(
+            &host,
+            "test_spin",
+            "fn on_update(e, dt) { trace(`spin ${e}`); loop { } }",
+        );
+        write(
+            &host,
+            "test_count",
+            "fn on_spawn(e) { this.n = 0; }
+             fn on_update(e, dt) { this.n += 1; trace(`count ${e} ${this.n}`); }",
+        );
+        scripted(&mut world, "test_count");
+        scripted(&mut world, "test_spin");
+        scripted(&mut world, "test_count");
+
+        // The operation limit aborts the one call; everything else still runs.
+        for _ in 0..2 {
+            host.update(&mut world, &mut models, &[], None, 0.1);
+        }
+        assert_eq!(
+            take(&trace),
+            [
+                "count 0 1",
+                "spin 1",
+                "count 2 1",
+                "count 0 2",
+                "spin 1",
+                "count 2 2"
+            ]
+        );
+
+        // Out of frame budget, the calls after the runaway one wait for the next update.
+        host.settings = ScriptSettings {
+            frame_budget: Duration::ZERO,
+            max_operations: 10_000_000,
+        };
+        host.engine.set_max_operations(host.settings.max_operations);
+        let start = Instant::now();
+        host.update(&mut world, &mut models, &[], None, 0.1);
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert_eq!(take(&trace), ["count 0 3", "spin 1"]);
+        host.update(&mut world, &mut models, &[], None, 0.1);
+        assert_eq!(take(&trace), ["count 0 4", "spin 1"]);
+        // Skipped calls don't lose state: the third entity picks up where it was.
+        host.settings.frame_budget = Duration::from_secs(5);
+        world.scripts[1] = None;
+        host.update(&mut world, &mut models, &[], None, 0.1);
+        assert_eq!(take(&trace), ["count 0 5", "count 2 3"]);
+    }
+
+    /// Needs an adapter; passes without one.
+    #[test]
+    fn reloads_swap_behavior_and_keep_entity_state() {
+        let Some((mut host, trace, mut world, mut models)) = host(ScriptSettings::default()) else {
+            return;
+        };
+        let name = "test_reload";
+        write(
+            &host,
+            name,
+            "fn on_spawn(e) { this.n = 0; trace(`spawn`); }
+             fn on_update(e, dt) { this.n += 1; trace(`v1 ${this.n}`); }",
+        );
+        scripted(&mut world, name);
+        host.update(&mut world, &mut models, &[], None, 0.1);
+        host.update(&mut world, &mut models, &[], None, 0.1);
+        assert_eq!(take(&trace), ["spawn", "v1 1", "v1 2"]);
+
+        // A change reported by the watcher recompiles before the next update.
+        host.watch().unwrap();
+        write(
+            &host,
+            name,
+            "fn on_spawn(e) { this.n = 0; trace(`spawn`); }
+             fn on_update(e, dt) { this.n += 10; trace(`v2 ${this.n}`); }",
+        );
+        let start = Instant::now();
+        while !host.changed.lock().unwrap().contains(name) {
+            assert!(
+                start.elapsed() < Duration::from_secs(5),
+                "no change reported"
+            );
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        host.update(&mut world, &mut models, &[], None, 0.1);
+        assert_eq!(take(&trace), ["v2 12"]);
+
+        // A broken edit keeps the last good version running, and says where it broke.
+        write(&host, name, "fn on_update(e, dt) {\n    this.n += ;\n}");
+        host.changed.lock().unwrap().insert(name.to_string());
+        host.update(&mut world, &mut models, &[], None, 0.1);
+        assert_eq!(take(&trace), ["v2 22"]);
+        let error = host.load(name).unwrap_err().to_string();
+        assert!(error.contains("test_reload.rhai:2:"), "{}", error);
+
+        // Switching the entity to another script starts it over.
+        write(
+            &host,
+            "test_reload_other",
+            "fn on_spawn(e) { trace(`other ${this.n}`); }",
+        );
+        world.insert_script(Entity(0), ScriptBehavior::new("test_reload_other"));
+        host.update(&mut world, &mut models, &[], None, 0.1);
+        assert_eq!(take(&trace), ["other "]);
+    }
+
+    /// Needs an adapter; passes without one.
+    #[test]
+    fn callbacks_run_in_the_documented_order() {
+        let Some((mut host, trace, mut world, mut models)) = host(ScriptSettings::default()) else {
+            return;
+        };
+        write(
+            &host,
+            "test_events",
+            "fn on_spawn(e) { trace(`spawn ${e}`); subscribe(\"damage\", \"hurt\"); after(0.0, \"later\"); }
+             fn hurt(e, ev) { trace(`hurt ${e} ${ev.amount}`); }
+             fn on_event(e, ev) { trace(`event ${e} ${ev.kind}`); }
+             fn later(e) { trace(`later ${e}`); }
+             fn on_update(e, dt) { trace(`update ${e}`); }",
+        );
+        scripted(&mut world, "test_events");
+        scripted(&mut world, "test_events");
+
+        // Subscriptions and delayed calls made during an update take effect after it.
+        let sequence = [WorldEvent::Sequence("intro".into())];
+        host.update(&mut world, &mut models, &sequence, None, 0.1);
+        assert_eq!(
+            take(&trace),
+            [
+                "spawn 0",
+                "spawn 1",
+                "event 0 sequence",
+                "event 1 sequence",
+                "update 0",
+                "update 1"
+            ]
+        );
+
+        let damage = |amount| WorldEvent::DamageTaken {
+            target: Entity(0),
+            amount,
+            source: None,
+            remaining: 0.0,
+        };
+        host.update(
+            &mut world,
+            &mut models,
+            &[damage(1.0), damage(2.0)],
+            None,
+            0.1,
+        );
+        assert_eq!(
+            take(&trace),
+            [
+                "hurt 0 1.0",
+                "hurt 1 1.0",
+                "event 0 damage",
+                "event 1 damage",
+                "hurt 0 2.0",
+                "hurt 1 2.0",
+                "event 0 damage",
+                "event 1 damage",
+                "later 0",
+                "later 1",
+                "update 0",
+                "update 1"
+            ]
+        );
+    }
+}
@@ -0,0 +1,15 @@
+//! Gameplay scripts in [Rhai](https://rhai.rs), run for entities with a
+//! [`crate::ScriptBehavior`]. Only compiled with the `scripting` feature.
+//!
+//! Rhai rather than Lua: it's pure Rust, so the feature needs no C toolchain, scripts
+//! can't reach the file system or process unless a function is registered for it, and the
+//! engine can cap operations per call and abort a call from a progress callback, which is
+//! what the frame budget builds on.
+
+pub use rhai;
+
+pub mod api;
+pub use api::*;
+
+pub mod host;
+pub use host::*;
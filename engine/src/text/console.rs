@@ -0,0 +1,341 @@
+//! In-game command console drawn with the HUD text. While open it takes key presses and
+//! IME commits for its input line, and runs submitted lines against a registry of named
+//! [`ConsoleCommand`]s.
+
+use std::collections::{BTreeMap, VecDeque};
+
+use glam::Vec3;
+use winit::{
+    event::{Ime, WindowEvent},
+    keyboard::{KeyCode, PhysicalKey},
+};
+
+use crate::{ModelManager, Placement, World};
+
+use super::{TextAnchor, TextRegion, TextStack};
+
+/// Lines kept in the scrollback.
+const MAX_OUTPUT: usize = 200;
+/// Scrollback lines shown above the input line.
+const VISIBLE_LINES: usize = 12;
+/// Submitted lines kept for up and down.
+const MAX_HISTORY: usize = 100;
+const PROMPT: &str = "> ";
+const OUTPUT_COLOR: glyphon::Color = glyphon::Color::rgb(200, 200, 200);
+const INPUT_COLOR: glyphon::Color = glyphon::Color::rgb(255, 255, 255);
+
+/// Runs a command with the words after its name and returns what to print.
+pub type ConsoleHandler = fn(&mut World, &mut ModelManager, &[&str]) -> String;
+
+#[derive(Debug, Clone, Copy)]
+pub struct ConsoleCommand {
+    pub name: &'static str,
+    /// Arguments as `help` lists them, e.g. `<model> [x y z]`.
+    pub usage: &'static str,
+    pub handler: ConsoleHandler,
+}
+
+/// Built-in commands the application carries out, since they reach past the world.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConsoleAction {
+    /// Moves the camera, and the entity it follows, to a world position.
+    Teleport(Vec3),
+    TimeScale(f32),
+    Quit,
+}
+
+impl ConsoleAction {
+    /// `(name, usage)` of the commands parsed into actions, for `help`.
+    const COMMANDS: [(&'static str, &'static str); 3] = [
+        ("tp", "<x> <y> <z>"),
+        ("timescale", "<scale>"),
+        ("quit", ""),
+    ];
+
+    /// The action `name` with `args` asks for; `None` when `name` names no action.
+    fn parse(name: &str, args: &[&str]) -> Option<Result<Self, String>> {
+        let action = match name {
+            "tp" => parse_vec3(args).map(ConsoleAction::Teleport),
+            "timescale" => match args {
+                [scale] => scale
+                    .parse::<f32>()
+                    .ok()
+                    .filter(|s| s.is_finite() && *s >= 0.0)
+                    .map(ConsoleAction::TimeScale)
+                    .ok_or_else(|| format!("not a time scale: {}", scale)),
+                _ => Err("usage: timescale <scale>".to_string()),
+            },
+            "quit" => Ok(ConsoleAction::Quit),
+            _ => return None,
+        };
+        Some(action)
+    }
+}
+
+pub struct Console {
+    open: bool,
+    input: String,
+    /// IME composition not committed yet, shown after the input.
+    preedit: String,
+    history: VecDeque<String>,
+    /// Entry of `history` in the input while browsing it, counted from the newest.
+    browsing: Option<usize>,
+    output: VecDeque<String>,
+    /// Lines the view is scrolled up from the newest output.
+    scroll: usize,
+    commands: BTreeMap<&'static str, ConsoleCommand>,
+    /// Lines submitted since [`Console::run_submitted`] last ran.
+    submitted: Vec<String>,
+    actions: Vec<ConsoleAction>,
+}
+
+impl Default for Console {
+    /// A closed console with the built-in `spawn` and `stats` commands.
+    fn default() -> Self {
+        let mut console = Self::empty();
+        console.register(ConsoleCommand {
+            name: "spawn",
+            usage: "<model> [x y z]",
+            handler: spawn,
+        });
+        console.register(ConsoleCommand {
+            name: "stats",
+            usage: "",
+            handler: stats,
+        });
+        console
+    }
+}
+
+impl Console {
+    /// A closed console without any registered commands.
+    pub fn empty() -> Self {
+        Self {
+            open: false,
+            input: String::new(),
+            preedit: String::new(),
+            history: VecDeque::new(),
+            browsing: None,
+            output: VecDeque::new(),
+            scroll: 0,
+            commands: BTreeMap::new(),
+            submitted: Vec::new(),
+            actions: Vec::new(),
+        }
+    }
+
+    /// Adds `command`, replacing one registered under the same name.
+    pub fn register(&mut self, command: ConsoleCommand) {
+        self.commands.insert(command.name, command);
+    }
+    pub fn commands(&self) -> impl Iterator<Item = &ConsoleCommand> {
+        self.commands.values()
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+    pub fn set_open(&mut self, open: bool) {
+        self.open = open;
+        self.preedit.clear();
+        self.browsing = None;
+    }
+
+    /// Appends `line` to the scrollback, one entry per line of it.
+    pub fn print(&mut self, line: impl AsRef<str>) {
+        for line in line.as_ref().lines() {
+            self.output.push_back(line.to_string());
+        }
+        while self.output.len() > MAX_OUTPUT {
+            self.output.pop_front();
+        }
+        self.scroll = 0;
+    }
+    pub fn output(&self) -> impl Iterator<Item = &str> {
+        self.output.iter().map(String::as_str)
+    }
+
+    /// Edits the input line with `event` while open. `true` means the console took it and
+    /// nothing else should see it. Key releases always pass, so keys held when it opened
+    /// don't get stuck.
+    pub fn process_event(&mut self, event: &WindowEvent) -> bool {
+        if !self.open {
+            return false;
+        }
+        match event {
+            WindowEvent::KeyboardInput { event, .. } if event.state.is_pressed() => {
+                match event.physical_key {
+                    PhysicalKey::Code(KeyCode::Escape) => self.set_open(false),
+                    PhysicalKey::Code(KeyCode::Enter | KeyCode::NumpadEnter) => self.submit(),
+                    PhysicalKey::Code(KeyCode::Backspace) => {
+                        self.input.pop();
+                    }
+                    PhysicalKey::Code(KeyCode::ArrowUp) => self.browse_history(1),
+                    PhysicalKey::Code(KeyCode::ArrowDown) => self.browse_history(-1),
+                    PhysicalKey::Code(KeyCode::PageUp) => self.scroll_by(VISIBLE_LINES as isize),
+                    PhysicalKey::Code(KeyCode::PageDown) => {
+                        self.scroll_by(-(VISIBLE_LINES as isize))
+                    }
+                    _ => {
+                        if let Some(text) = &event.text {
+                            self.input.extend(text.chars().filter(|c| !c.is_control()));
+                        }
+                    }
+                }
+                true
+            }
+            WindowEvent::Ime(Ime::Preedit(text, _)) => {
+                self.preedit = text.clone();
+                true
+            }
+            WindowEvent::Ime(Ime::Commit(text)) => {
+                self.preedit.clear();
+                self.input.push_str(text);
+                true
+            }
+            WindowEvent::Ime(_) => true,
+            _ => false,
+        }
+    }
+    fn submit(&mut self) {
+        let line = std::mem::take(&mut self.input);
+        self.browsing = None;
+        if line.trim().is_empty() {
+            return;
+        }
+        if self.history.front() != Some(&line) {
+            self.history.push_front(line.clone());
+            self.history.truncate(MAX_HISTORY);
+        }
+        self.submitted.push(line);
+    }
+    /// Steps `by` entries older through the history, or newer when negative; stepping past
+    /// the newest clears the input.
+    fn browse_history(&mut self, by: isize) {
+        if self.history.is_empty() {
+            return;
+        }
+        let next = match self.browsing {
+            Some(index) => index as isize + by,
+            None if by > 0 => by - 1,
+            None => return,
+        };
+        if next < 0 {
+            self.browsing = None;
+            self.input.clear();
+            return;
+        }
+        let index = (next as usize).min(self.history.len() - 1);
+        self.browsing = Some(index);
+        self.input = self.history[index].clone();
+    }
+    fn scroll_by(&mut self, lines: isize) {
+        let max = self.output.len().saturating_sub(VISIBLE_LINES);
+        self.scroll = self.scroll.saturating_add_signed(lines).min(max);
+    }
+
+    /// Runs the lines submitted since the last call; see [`Console::run`].
+    pub fn run_submitted(&mut self, world: &mut World, model_manager: &mut ModelManager) {
+        for line in std::mem::take(&mut self.submitted) {
+            self.run(&line, world, model_manager);
+        }
+    }
+    /// Echoes `line` and runs it: `help`, a [`ConsoleAction`] command, or a registered
+    /// command, printing what it returns.
+    pub fn run(&mut self, line: &str, world: &mut World, model_manager: &mut ModelManager) {
+        self.print(format!("{}{}", PROMPT, line));
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let Some((&name, args)) = words.split_first() else {
+            return;
+        };
+        if name == "help" {
+            let help = self.help();
+            self.print(help);
+            return;
+        }
+        match ConsoleAction::parse(name, args) {
+            Some(Ok(action)) => self.actions.push(action),
+            Some(Err(e)) => self.print(e),
+            None => match self.commands.get(name) {
+                Some(command) => {
+                    let reply = (command.handler)(world, model_manager, args);
+                    self.print(reply);
+                }
+                None => self.print(format!("unknown command '{}', try help", name)),
+            },
+        }
+    }
+    fn help(&self) -> String {
+        let registered = self.commands.values().map(|c| (c.name, c.usage));
+        ConsoleAction::COMMANDS
+            .into_iter()
+            .chain(registered)
+            .map(|(name, usage)| format!("{} {}", name, usage).trim_end().to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+    /// [`ConsoleAction`]s run since the last call, in order.
+    pub fn take_actions(&mut self) -> Vec<ConsoleAction> {
+        std::mem::take(&mut self.actions)
+    }
+
+    /// The visible scrollback and the input line, laid out from `anchor`. Empty while
+    /// closed.
+    pub fn text_stack(&self, anchor: TextAnchor) -> TextStack {
+        let mut stack = TextStack::new(anchor);
+        if !self.open {
+            return stack;
+        }
+        let end = self.output.len() - self.scroll;
+        let start = end.saturating_sub(VISIBLE_LINES);
+        let lines: Vec<&str> = self.output.range(start..end).map(String::as_str).collect();
+        if !lines.is_empty() {
+            stack.push(TextRegion::new(lines.join("\n"), [0.0; 2], OUTPUT_COLOR));
+        }
+        stack.push(TextRegion::new(
+            format!("{}{}{}_", PROMPT, self.input, self.preedit),
+            [0.0; 2],
+            INPUT_COLOR,
+        ));
+        stack
+    }
+}
+
+fn parse_vec3(args: &[&str]) -> Result<Vec3, String> {
+    let coords: Vec<f32> = args.iter().filter_map(|a| a.parse().ok()).collect();
+    match coords[..] {
+        [x, y, z] if args.len() == 3 => Ok(Vec3::new(x, y, z)),
+        _ => Err(format!("expected <x> <y> <z>, got '{}'", args.join(" "))),
+    }
+}
+
+/// `spawn <model> [x y z]`: spawns a loaded model, at the origin without a position.
+fn spawn(world: &mut World, model_manager: &mut ModelManager, args: &[&str]) -> String {
+    let Some((model, position)) = args.split_first() else {
+        return "usage: spawn <model> [x y z]".to_string();
+    };
+    let position = match position {
+        [] => Vec3::ZERO,
+        position => match parse_vec3(position) {
+            Ok(position) => position,
+            Err(e) => return e,
+        },
+    };
+    match world.spawn_model(model_manager, model, Placement::at(position)) {
+        Ok(entity) => format!("spawned {} as entity {} at {}", model, entity.0, position),
+        Err(e) => format!("spawn {}: {}", model, e),
+    }
+}
+
+/// `stats`: entity, model, draw and chunk counts.
+fn stats(world: &mut World, model_manager: &mut ModelManager, _args: &[&str]) -> String {
+    let cull = world.terrain.cull_stats();
+    format!(
+        "{} entities, {} models, {} draw calls, {} chunks drawn, {} culled",
+        world.entity_count(),
+        model_manager.models.len(),
+        model_manager.draw_stats.draw_calls,
+        cull.drawn,
+        cull.culled
+    )
+}
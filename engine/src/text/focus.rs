@@ -0,0 +1,466 @@
+//! Focus-based navigation for menus on the text layer. Elements register a screen rect and
+//! an action payload; directional input moves focus spatially and activation hands the
+//! focused element's payload back to the caller.
+
+use glam::Vec2;
+
+use crate::TextRegion;
+
+/// Screen rectangle in logical pixels, y pointing down.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct UiRect {
+    pub min: Vec2,
+    pub max: Vec2,
+}
+
+impl UiRect {
+    pub fn new(pos: [f32; 2], size: [f32; 2]) -> Self {
+        let min = Vec2::from_array(pos);
+        Self {
+            min,
+            max: min + Vec2::from_array(size),
+        }
+    }
+    pub fn center(&self) -> Vec2 {
+        (self.min + self.max) * 0.5
+    }
+    pub fn contains(&self, point: Vec2) -> bool {
+        point.cmpge(self.min).all() && point.cmple(self.max).all()
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum NavDirection {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl NavDirection {
+    pub fn from_key(code: winit::keyboard::KeyCode) -> Option<Self> {
+        use winit::keyboard::KeyCode;
+        match code {
+            KeyCode::ArrowUp => Some(NavDirection::Up),
+            KeyCode::ArrowDown => Some(NavDirection::Down),
+            KeyCode::ArrowLeft => Some(NavDirection::Left),
+            KeyCode::ArrowRight => Some(NavDirection::Right),
+            _ => None,
+        }
+    }
+    fn vertical(&self) -> bool {
+        matches!(self, NavDirection::Up | NavDirection::Down)
+    }
+    fn sign(&self) -> f32 {
+        match self {
+            NavDirection::Up | NavDirection::Left => -1.0,
+            NavDirection::Down | NavDirection::Right => 1.0,
+        }
+    }
+}
+
+/// Device that last drove the UI.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum UiDevice {
+    #[default]
+    Mouse,
+    Keyboard,
+    Gamepad,
+}
+
+pub struct Focusable<A> {
+    pub rect: UiRect,
+    pub label: String,
+    pub action: A,
+}
+
+/// Focus state for one menu. `A` is the payload returned when an element is activated.
+pub struct UiFocusManager<A> {
+    elements: Vec<Focusable<A>>,
+    focused: Option<usize>,
+    wrap: bool,
+    device: UiDevice,
+    cursor: Option<Vec2>,
+    pub color: glyphon::Color,
+    pub focus_color: glyphon::Color,
+}
+
+impl<A> Default for UiFocusManager<A> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<A> UiFocusManager<A> {
+    /// How much a perpendicular offset counts against a candidate with no overlap.
+    const PERPENDICULAR_WEIGHT: f32 = 2.0;
+
+    pub fn new() -> Self {
+        Self {
+            elements: Vec::new(),
+            focused: None,
+            wrap: true,
+            device: UiDevice::default(),
+            cursor: None,
+            color: glyphon::Color::rgb(200, 200, 200),
+            focus_color: glyphon::Color::rgb(255, 210, 80),
+        }
+    }
+    /// Whether navigating past the last element in a direction continues from the other side.
+    pub fn with_wrap(mut self, wrap: bool) -> Self {
+        self.wrap = wrap;
+        self
+    }
+    pub fn set_wrap(&mut self, wrap: bool) {
+        self.wrap = wrap;
+    }
+
+    /// Removes every element. Focus is kept as an index so a rebuilt layout stays put.
+    pub fn clear(&mut self) {
+        self.elements.clear();
+    }
+    /// Adds an element and returns its index. The first element takes focus if nothing has it.
+    pub fn register(&mut self, rect: UiRect, label: impl Into<String>, action: A) -> usize {
+        self.elements.push(Focusable {
+            rect,
+            label: label.into(),
+            action,
+        });
+        let idx = self.elements.len() - 1;
+        if self.focused.is_none() {
+            self.focused = Some(idx);
+        }
+        if self.device == UiDevice::Mouse {
+            if let Some(cursor) = self.cursor {
+                if rect.contains(cursor) {
+                    self.focused = Some(idx);
+                }
+            }
+        }
+        idx
+    }
+    /// Lays `items` out top to bottom from `origin`, sizing rects from the label length.
+    pub fn register_column(
+        &mut self,
+        origin: [f32; 2],
+        line_height: f32,
+        items: impl IntoIterator<Item = (String, A)>,
+    ) {
+        for (i, (label, action)) in items.into_iter().enumerate() {
+            // Rough advance for the default font; only used for hit testing.
+            let width = (label.chars().count() + 2) as f32 * line_height * 0.5;
+            let rect = UiRect::new(
+                [origin[0], origin[1] + i as f32 * line_height],
+                [width, line_height],
+            );
+            self.register(rect, label, action);
+        }
+    }
+
+    pub fn elements(&self) -> &[Focusable<A>] {
+        &self.elements
+    }
+    /// Focused index, clamped to the elements registered since the last [`Self::clear`].
+    pub fn focused(&self) -> Option<usize> {
+        let last = self.elements.len().checked_sub(1)?;
+        self.focused.map(|i| i.min(last))
+    }
+    pub fn focused_element(&self) -> Option<&Focusable<A>> {
+        self.focused().map(|i| &self.elements[i])
+    }
+    pub fn set_focus(&mut self, idx: usize) {
+        if idx < self.elements.len() {
+            self.focused = Some(idx);
+        }
+    }
+    pub fn device(&self) -> UiDevice {
+        self.device
+    }
+    /// Last cursor position seen, in logical pixels.
+    pub fn cursor(&self) -> Option<Vec2> {
+        self.cursor
+    }
+
+    /// Moves focus to the nearest element in `dir`. Directional input takes over from the
+    /// mouse, so hovering stops moving focus until the cursor moves again.
+    pub fn navigate(&mut self, dir: NavDirection, device: UiDevice) -> bool {
+        self.device = device;
+        let Some(from) = self.focused() else {
+            if self.elements.is_empty() {
+                return false;
+            }
+            self.focused = Some(0);
+            return true;
+        };
+        let origin = self.elements[from].rect;
+        let ahead = self.best(from, |rect| Self::score(&origin, rect, dir));
+        let target = match ahead {
+            Some(idx) => Some(idx),
+            None if self.wrap => self.best(from, |rect| Self::wrap_score(&origin, rect, dir)),
+            None => None,
+        };
+        match target {
+            Some(idx) => {
+                self.focused = Some(idx);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn best(&self, from: usize, score: impl Fn(&UiRect) -> Option<f32>) -> Option<usize> {
+        self.elements
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i != from)
+            .filter_map(|(i, e)| score(&e.rect).map(|s| (i, s)))
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(i, _)| i)
+    }
+
+    /// Splits `a -> b` into the center offset along `dir`, the edge-to-edge gap along it, the
+    /// center offset across it, and how much of the smaller rect overlaps across it (`0..=1`).
+    fn axes(a: &UiRect, b: &UiRect, dir: NavDirection) -> (f32, f32, f32, f32) {
+        let (along, across) = if dir.vertical() { (1, 0) } else { (0, 1) };
+        let sign = dir.sign();
+        let center_delta = (b.center()[along] - a.center()[along]) * sign;
+        let gap = if sign > 0.0 {
+            b.min[along] - a.max[along]
+        } else {
+            a.min[along] - b.max[along]
+        };
+        let perpendicular = (b.center()[across] - a.center()[across]).abs();
+        let overlap =
+            (a.max[across].min(b.max[across]) - a.min[across].max(b.min[across])).max(0.0);
+        let extent = (a.max[across] - a.min[across])
+            .min(b.max[across] - b.min[across])
+            .max(f32::EPSILON);
+        (
+            center_delta,
+            gap.max(0.0),
+            perpendicular,
+            (overlap / extent).min(1.0),
+        )
+    }
+
+    /// Overlap-weighted distance to a candidate ahead of `from`; `None` if it isn't ahead.
+    fn score(from: &UiRect, rect: &UiRect, dir: NavDirection) -> Option<f32> {
+        let (center_delta, gap, perpendicular, overlap) = Self::axes(from, rect, dir);
+        if center_delta <= f32::EPSILON {
+            return None;
+        }
+        Some(gap + perpendicular * Self::PERPENDICULAR_WEIGHT * (1.0 - overlap))
+    }
+
+    /// Wrapping picks the element farthest behind `from`, lined up the same way.
+    fn wrap_score(from: &UiRect, rect: &UiRect, dir: NavDirection) -> Option<f32> {
+        let (center_delta, _, perpendicular, overlap) = Self::axes(from, rect, dir);
+        if center_delta >= -f32::EPSILON {
+            return None;
+        }
+        Some(center_delta + perpendicular * Self::PERPENDICULAR_WEIGHT * (1.0 - overlap))
+    }
+
+    /// Cursor moved to `pos` (logical pixels). Real movement hands control back to the mouse
+    /// and focuses the hovered element; returns whether focus changed.
+    pub fn on_cursor_moved(&mut self, pos: Vec2) -> bool {
+        if self.cursor == Some(pos) {
+            return false;
+        }
+        self.cursor = Some(pos);
+        self.device = UiDevice::Mouse;
+        match self.hit(pos) {
+            Some(idx) if self.focused() != Some(idx) => {
+                self.focused = Some(idx);
+                true
+            }
+            _ => false,
+        }
+    }
+    pub fn hit(&self, pos: Vec2) -> Option<usize> {
+        self.elements.iter().position(|e| e.rect.contains(pos))
+    }
+
+    /// Payload of the focused element.
+    pub fn activate(&self) -> Option<A>
+    where
+        A: Clone,
+    {
+        self.focused_element().map(|e| e.action.clone())
+    }
+    /// Mouse click at `pos`: focuses and activates the element under it, if any.
+    pub fn click(&mut self, pos: Vec2) -> Option<A>
+    where
+        A: Clone,
+    {
+        self.cursor = Some(pos);
+        self.device = UiDevice::Mouse;
+        let idx = self.hit(pos)?;
+        self.focused = Some(idx);
+        Some(self.elements[idx].action.clone())
+    }
+
+    /// One text region per element, the focused one tinted and marked.
+    pub fn regions(&self) -> Vec<TextRegion> {
+        let focused = self.focused();
+        self.elements
+            .iter()
+            .enumerate()
+            .map(|(i, e)| {
+                let (marker, color) = if Some(i) == focused {
+                    ("> ", self.focus_color)
+                } else {
+                    ("  ", self.color)
+                };
+                TextRegion::new(
+                    format!("{}{}", marker, e.label),
+                    e.rect.min.to_array(),
+                    color,
+                )
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use NavDirection::*;
+
+    /// A 3x3 grid of 100x40 cells, 20 apart, indexed row by row.
+    fn grid(wrap: bool) -> UiFocusManager<usize> {
+        let mut ui = UiFocusManager::new().with_wrap(wrap);
+        for row in 0..3 {
+            for col in 0..3 {
+                let pos = [col as f32 * 120.0, row as f32 * 60.0];
+                ui.register(UiRect::new(pos, [100.0, 40.0]), "", row * 3 + col);
+            }
+        }
+        ui
+    }
+
+    fn path(ui: &mut UiFocusManager<usize>, start: usize, dirs: &[NavDirection]) -> Vec<usize> {
+        ui.set_focus(start);
+        dirs.iter()
+            .map(|dir| {
+                ui.navigate(*dir, UiDevice::Keyboard);
+                ui.focused().unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn grid_navigation_moves_one_cell_at_a_time() {
+        let mut ui = grid(false);
+        assert_eq!(ui.focused(), Some(0));
+        assert_eq!(path(&mut ui, 4, &[Up]), [1]);
+        assert_eq!(path(&mut ui, 4, &[Down]), [7]);
+        assert_eq!(path(&mut ui, 4, &[Left]), [3]);
+        assert_eq!(path(&mut ui, 4, &[Right]), [5]);
+        assert_eq!(
+            path(&mut ui, 0, &[Right, Right, Down, Down, Left, Up]),
+            [1, 2, 5, 8, 7, 4]
+        );
+    }
+
+    #[test]
+    fn irregular_layouts_prefer_overlapping_neighbours() {
+        let mut ui = UiFocusManager::new().with_wrap(false);
+        // A wide title bar over two buttons, the right one lower and nudged right.
+        let title = ui.register(UiRect::new([0.0, 0.0], [400.0, 40.0]), "title", 0);
+        let left = ui.register(UiRect::new([0.0, 80.0], [150.0, 40.0]), "left", 1);
+        let right = ui.register(UiRect::new([260.0, 100.0], [150.0, 40.0]), "right", 2);
+        // Closer along the axis but well off to the side.
+        let aside = ui.register(UiRect::new([600.0, 50.0], [80.0, 20.0]), "aside", 3);
+
+        ui.set_focus(title);
+        ui.navigate(Down, UiDevice::Keyboard);
+        assert_eq!(ui.focused(), Some(left));
+
+        ui.set_focus(left);
+        ui.navigate(Right, UiDevice::Keyboard);
+        assert_eq!(ui.focused(), Some(right));
+        ui.navigate(Up, UiDevice::Keyboard);
+        assert_eq!(ui.focused(), Some(title));
+
+        // Off to the side, the aside is only reachable sideways.
+        ui.set_focus(right);
+        ui.navigate(Right, UiDevice::Keyboard);
+        assert_eq!(ui.focused(), Some(aside));
+    }
+
+    #[test]
+    fn wrap_around_continues_from_the_far_side() {
+        let mut ui = grid(true);
+        assert_eq!(path(&mut ui, 2, &[Right]), [0]);
+        assert_eq!(path(&mut ui, 0, &[Up]), [6]);
+        assert_eq!(path(&mut ui, 7, &[Down]), [1]);
+        assert_eq!(path(&mut ui, 3, &[Left]), [5]);
+
+        let mut ui = grid(false);
+        ui.set_focus(2);
+        assert!(!ui.navigate(Right, UiDevice::Keyboard));
+        assert_eq!(ui.focused(), Some(2));
+    }
+
+    #[test]
+    fn directional_input_suppresses_hover_until_the_mouse_moves() {
+        let mut ui = grid(false);
+        let over_four = Vec2::new(150.0, 80.0);
+        assert!(ui.on_cursor_moved(over_four));
+        assert_eq!((ui.focused(), ui.device()), (Some(4), UiDevice::Mouse));
+
+        ui.navigate(Right, UiDevice::Gamepad);
+        assert_eq!((ui.focused(), ui.device()), (Some(5), UiDevice::Gamepad));
+
+        // Rebuilding the menu under a still cursor keeps the gamepad's focus.
+        let mut rebuilt = grid(false);
+        std::mem::swap(&mut rebuilt.elements, &mut ui.elements);
+        ui.clear();
+        for element in rebuilt.elements {
+            ui.register(element.rect, element.label, element.action);
+        }
+        assert!(!ui.on_cursor_moved(over_four));
+        assert_eq!((ui.focused(), ui.device()), (Some(5), UiDevice::Gamepad));
+
+        // Moving the mouse, even within the same element, hands focus back to hover.
+        assert!(ui.on_cursor_moved(over_four + Vec2::ONE));
+        assert_eq!((ui.focused(), ui.device()), (Some(4), UiDevice::Mouse));
+        ui.clear();
+        ui.register(UiRect::new([0.0, 0.0], [10.0, 10.0]), "", 0);
+        ui.register(UiRect::new([140.0, 70.0], [20.0, 20.0]), "", 1);
+        assert_eq!(ui.focused(), Some(1));
+    }
+
+    #[test]
+    fn activation_returns_the_focused_payload() {
+        #[derive(Debug, Clone, PartialEq)]
+        enum Menu {
+            Resume,
+            Quit,
+        }
+        let mut ui = UiFocusManager::new();
+        assert_eq!(ui.activate(), None);
+        ui.register_column(
+            [0.0, 0.0],
+            20.0,
+            [
+                ("Resume".to_string(), Menu::Resume),
+                ("Quit".to_string(), Menu::Quit),
+            ],
+        );
+        assert_eq!(ui.activate(), Some(Menu::Resume));
+        ui.navigate(Down, UiDevice::Keyboard);
+        assert_eq!(ui.activate(), Some(Menu::Quit));
+
+        assert_eq!(ui.click(Vec2::new(5.0, 10.0)), Some(Menu::Resume));
+        assert_eq!(ui.focused(), Some(0));
+        assert_eq!(ui.click(Vec2::new(500.0, 500.0)), None);
+        assert_eq!(ui.focused(), Some(0));
+
+        let regions = ui.regions();
+        assert_eq!(regions[0].text, "> Resume");
+        assert_eq!(regions[1].text, "  Quit");
+        assert_eq!(regions[0].color, ui.focus_color);
+    }
+}
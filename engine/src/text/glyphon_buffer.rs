@@ -77,10 +77,40 @@ impl GlyphonBuffer {
     pub fn clear_lines(&mut self) {
         self.buffer.lines.clear();
     }
+    /// Replace the buffer contents, splitting `text` into lines
+    pub fn set_text(&mut self, font_system: &mut glyphon::FontSystem, text: &str) {
+        self.buffer
+            .set_text(font_system, text, glyphon::Attrs::new(), self.shaping);
+    }
+    /// Like [`GlyphonBuffer::set_text`] with `attrs` for the whole text.
+    pub fn set_text_with_attrs(
+        &mut self,
+        font_system: &mut glyphon::FontSystem,
+        text: &str,
+        attrs: glyphon::Attrs,
+    ) {
+        self.buffer.set_text(font_system, text, attrs, self.shaping);
+    }
+    pub fn set_metrics(
+        &mut self,
+        font_system: &mut glyphon::FontSystem,
+        metrics: glyphon::Metrics,
+    ) {
+        self.buffer.set_metrics(font_system, metrics);
+    }
     /// Clear all lines from the buffer
     pub fn shape(&mut self, font_system: &mut glyphon::FontSystem) {
         self.buffer.shape_until_scroll(font_system, false);
     }
+    /// Width of the widest shaped line and height of all of them, in the metrics' pixels.
+    pub fn measure(&self) -> (f32, f32) {
+        let line_height = self.buffer.metrics().line_height;
+        self.buffer
+            .layout_runs()
+            .fold((0.0, 0.0), |(width, height), run| {
+                (f32::max(width, run.line_w), height + line_height)
+            })
+    }
 }
 
 pub type GlyphonBufferCacheType = HashCache<GlyphonBuffer>;
@@ -118,4 +148,7 @@ impl CacheStorage<GlyphonBuffer> for GlyphonBufferManager {
     fn remove(&mut self, key: &CacheKey) -> Option<GlyphonBuffer> {
         self.inner.remove(key)
     }
+    fn iter(&self) -> impl Iterator<Item = (&CacheKey, &GlyphonBuffer)> {
+        self.inner.iter()
+    }
 }
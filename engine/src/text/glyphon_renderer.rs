@@ -1,45 +1,90 @@
-use glyphon::Attrs;
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
 
-use crate::DebugMode;
+use crate::{log_debug, log_warning, AssetPaths, DebugMode, EngineError};
 
-use super::{GlyphonBuffer, TextRegion};
+use super::{GlyphonBuffer, LabelFalloff, TextProjection, TextRegion};
 
+/// Default font size in logical pixels.
+pub const DEFAULT_FONT_SIZE: f32 = 16.0;
+/// Line height as a multiple of the font size.
+pub const LINE_HEIGHT: f32 = 1.25;
+
+/// Converts a logical length to physical pixels.
+pub fn logical_to_physical(value: f32, scale: f32) -> f32 {
+    value * scale
+}
+
+/// Rounds a physical coordinate to the pixel grid so glyphs don't land on half pixels and blur.
+pub fn snap_to_pixel(value: f32) -> f32 {
+    value.round()
+}
+
+/// Screen text renderer. Callers work in logical pixels; everything handed to glyphon is in
+/// physical pixels of the render target, which is `logical * scale_factor * ui_scale`.
 pub struct RenderText {
     buffer: GlyphonBuffer,
+    regions: Vec<GlyphonBuffer>,
+    /// Shapes regions for [`RenderText::measure`] without touching the prepared ones.
+    measure_buffer: GlyphonBuffer,
     font_system: glyphon::FontSystem,
+    cache: glyphon::Cache,
     atlas: glyphon::TextAtlas,
     renderer: glyphon::TextRenderer,
     swash_cache: glyphon::SwashCache,
     viewport: glyphon::Viewport,
+    resolution: glyphon::Resolution,
+    format: wgpu::TextureFormat,
+    depth_stencil: Option<wgpu::DepthStencilState>,
     font_size: f32,
+    scale_factor: f64,
+    ui_scale: f32,
+    /// Families regions asked for that aren't loaded, so each is warned about once.
+    missing_fonts: HashSet<String>,
+    label_falloff: LabelFalloff,
+}
+
+/// Where a region lands this prepare, in logical pixels, and what it's drawn with.
+struct Placement {
+    pos: [f32; 2],
+    scale: f32,
+    color: glyphon::Color,
 }
 
 impl RenderText {
+    /// `format` must match the color target the text is drawn into; `scale_factor` is the
+    /// window's DPI scale.
     pub fn new(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
-        swapchain_format: wgpu::TextureFormat,
+        format: wgpu::TextureFormat,
         depth_stencil: &Option<wgpu::DepthStencilState>,
+        scale_factor: f64,
     ) -> Self {
-        let font_size = 5.0;
+        let font_size = DEFAULT_FONT_SIZE;
         let swash_cache = glyphon::SwashCache::new();
         let cache = glyphon::Cache::new(device);
         let viewport = glyphon::Viewport::new(device, &cache);
-        let mut atlas = glyphon::TextAtlas::new(device, queue, &cache, swapchain_format);
-        let multisample = wgpu::MultisampleState::default();
-
-        let renderer = glyphon::TextRenderer::new(
-            &mut atlas,
-            device,
-            multisample,
-            depth_stencil.as_ref().cloned(),
-        );
+        let (atlas, renderer) = Self::create_pipeline(device, queue, &cache, format, depth_stencil);
 
         let mut font_system = glyphon::FontSystem::new();
+        Self::load_font_dir(&mut font_system);
 
         let buffer = GlyphonBuffer::new(
             &mut font_system,
-            Some(glyphon::Metrics::new(font_size, font_size)),
+            Some(glyphon::Metrics::new(font_size, font_size * LINE_HEIGHT)),
+            Some(glyphon::Shaping::Basic),
+            glyphon::cosmic_text::LineEnding::CrLf,
+            glyphon::AttrsList::new(glyphon::Attrs::new()),
+            Some(glyphon::cosmic_text::Align::Left),
+            None,
+        );
+
+        let measure_buffer = GlyphonBuffer::new(
+            &mut font_system,
+            None,
             Some(glyphon::Shaping::Basic),
             glyphon::cosmic_text::LineEnding::CrLf,
             glyphon::AttrsList::new(glyphon::Attrs::new()),
@@ -49,14 +94,170 @@ impl RenderText {
 
         RenderText {
             buffer,
+            regions: Vec::new(),
+            measure_buffer,
             font_system,
+            cache,
             atlas,
             renderer,
             swash_cache,
             viewport,
+            resolution: glyphon::Resolution {
+                width: 0,
+                height: 0,
+            },
+            format,
+            depth_stencil: depth_stencil.clone(),
             font_size,
+            scale_factor,
+            ui_scale: 1.0,
+            missing_fonts: HashSet::new(),
+            label_falloff: LabelFalloff::default(),
+        }
+    }
+    /// Directory fonts are loaded from at startup and [`RenderText::load_font`] resolves
+    /// relative paths against.
+    pub fn fonts_dir() -> PathBuf {
+        AssetPaths::dir("fonts")
+    }
+    /// Loads a .ttf/.otf font so regions can select its family by name.
+    pub fn load_font(&mut self, path: impl AsRef<Path>) -> Result<(), EngineError> {
+        let path = Self::fonts_dir().join(path);
+        let faces = Self::load_font_file(&mut self.font_system, &path)?;
+        self.missing_fonts.clear();
+        log_debug!("Loaded {} font faces from {}", faces, path.display());
+        Ok(())
+    }
+    fn load_font_file(
+        font_system: &mut glyphon::FontSystem,
+        path: &Path,
+    ) -> Result<usize, EngineError> {
+        let data = std::fs::read(path)
+            .map_err(|e| EngineError::AssetMissing(format!("{}: {}", path.display(), e)))?;
+        let before = font_system.db().len();
+        font_system.db_mut().load_font_data(data);
+        match font_system.db().len() - before {
+            0 => Err(EngineError::AssetLoadError(format!(
+                "{}: no font faces",
+                path.display()
+            ))),
+            faces => Ok(faces),
+        }
+    }
+    fn load_font_dir(font_system: &mut glyphon::FontSystem) {
+        let Ok(entries) = std::fs::read_dir(Self::fonts_dir()) else {
+            return;
+        };
+        for path in entries.filter_map(|entry| Some(entry.ok()?.path())) {
+            let is_font = path
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| {
+                    ext.eq_ignore_ascii_case("ttf") || ext.eq_ignore_ascii_case("otf")
+                });
+            if !is_font {
+                continue;
+            }
+            if let Err(e) = Self::load_font_file(font_system, &path) {
+                log_warning!("Skipping font: {}", e);
+            }
         }
     }
+    fn has_family(&self, family: &str) -> bool {
+        self.font_system
+            .db()
+            .faces()
+            .any(|face| face.families.iter().any(|(name, _)| name == family))
+    }
+    fn create_pipeline(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        cache: &glyphon::Cache,
+        format: wgpu::TextureFormat,
+        depth_stencil: &Option<wgpu::DepthStencilState>,
+    ) -> (glyphon::TextAtlas, glyphon::TextRenderer) {
+        let mut atlas = glyphon::TextAtlas::new(device, queue, cache, format);
+        let renderer = glyphon::TextRenderer::new(
+            &mut atlas,
+            device,
+            crate::RenderSettings::multisample(),
+            depth_stencil.as_ref().cloned(),
+        );
+        (atlas, renderer)
+    }
+    /// Rebuilds the atlas and pipeline when the target format changes, e.g. an sRGB swapchain
+    /// vs. a linear offscreen target.
+    pub fn set_format(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        format: wgpu::TextureFormat,
+    ) {
+        if format == self.format {
+            return;
+        }
+        let (atlas, renderer) =
+            Self::create_pipeline(device, queue, &self.cache, format, &self.depth_stencil);
+        self.atlas = atlas;
+        self.renderer = renderer;
+        self.format = format;
+    }
+    /// Rebuilds the pipeline with the active depth policy's state, if the text is depth tested.
+    pub fn apply_depth_policy(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        if self.depth_stencil.is_none() {
+            return;
+        }
+        self.depth_stencil = Some(crate::RenderSettings::depth_state(
+            crate::DepthVariant::Opaque,
+        ));
+        let (atlas, renderer) =
+            Self::create_pipeline(device, queue, &self.cache, self.format, &self.depth_stencil);
+        self.atlas = atlas;
+        self.renderer = renderer;
+    }
+    /// Rebuilds the pipeline with the active MSAA sample count.
+    pub fn apply_sample_count(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        let (atlas, renderer) =
+            Self::create_pipeline(device, queue, &self.cache, self.format, &self.depth_stencil);
+        self.atlas = atlas;
+        self.renderer = renderer;
+    }
+    pub fn format(&self) -> wgpu::TextureFormat {
+        self.format
+    }
+    pub fn scale_factor(&self) -> f64 {
+        self.scale_factor
+    }
+    /// Called on `WindowEvent::ScaleFactorChanged`; the following resize updates the viewport.
+    pub fn set_scale_factor(&mut self, scale_factor: f64) {
+        self.scale_factor = scale_factor;
+    }
+    pub fn ui_scale(&self) -> f32 {
+        self.ui_scale
+    }
+    /// User-facing text size multiplier, applied on top of the DPI scale.
+    pub fn set_ui_scale(&mut self, ui_scale: f32) {
+        self.ui_scale = ui_scale.max(0.1);
+    }
+    /// Logical to physical pixel ratio.
+    pub fn scale(&self) -> f32 {
+        self.scale_factor as f32 * self.ui_scale
+    }
+    pub fn font_size(&self) -> f32 {
+        self.font_size
+    }
+    /// Logical line height of the default font size.
+    pub fn line_height(&self) -> f32 {
+        self.font_size * LINE_HEIGHT
+    }
+    /// Size of the render target in logical pixels, for laying out regions.
+    pub fn logical_size(&self) -> (u32, u32) {
+        let scale = self.scale();
+        (
+            (self.resolution.width as f32 / scale) as u32,
+            (self.resolution.height as f32 / scale) as u32,
+        )
+    }
     pub fn create_buffer(
         &mut self,
         metrics: Option<glyphon::Metrics>,
@@ -77,9 +278,13 @@ impl RenderText {
         )
     }
     pub fn create_buffer_default(&mut self) -> GlyphonBuffer {
+        let metrics = glyphon::Metrics::new(
+            self.font_size * self.scale(),
+            self.font_size * LINE_HEIGHT * self.scale(),
+        );
         GlyphonBuffer::new(
             &mut self.font_system,
-            Some(glyphon::Metrics::new(self.font_size, self.font_size)),
+            Some(metrics),
             Some(glyphon::Shaping::Basic),
             glyphon::cosmic_text::LineEnding::CrLf,
             glyphon::AttrsList::new(glyphon::Attrs::new()),
@@ -87,14 +292,13 @@ impl RenderText {
             None,
         )
     }
+    /// `new_size` is the physical size of the render target.
     pub fn resize(&mut self, queue: &wgpu::Queue, new_size: winit::dpi::PhysicalSize<u32>) {
-        self.viewport.update(
-            queue,
-            glyphon::Resolution {
-                width: new_size.width,
-                height: new_size.height,
-            },
-        );
+        self.resolution = glyphon::Resolution {
+            width: new_size.width,
+            height: new_size.height,
+        };
+        self.viewport.update(queue, self.resolution);
     }
     pub fn set_buffer_lines(&mut self, lines: Vec<glyphon::BufferLine>) {
         self.buffer.set_lines(lines);
@@ -102,48 +306,127 @@ impl RenderText {
     pub fn shape_buffer(&mut self) {
         self.buffer.shape(&mut self.font_system);
     }
+    pub fn label_falloff(&self) -> LabelFalloff {
+        self.label_falloff
+    }
+    pub fn set_label_falloff(&mut self, falloff: LabelFalloff) {
+        self.label_falloff = falloff;
+    }
+    /// Shapes each region into its own buffer and uploads the glyphs. Positions, bounds and
+    /// font sizes are logical; the origin is snapped to whole physical pixels.
+    /// World-anchored regions are projected through `projection`, scaled and faded by the
+    /// [`LabelFalloff`], and dropped when behind the camera, off screen, or without a
+    /// projection.
     pub fn prepare_regions(
         &mut self,
         device: &wgpu::Device,
         queue: &wgpu::Queue,
         regions: &[TextRegion],
         surface_config: &wgpu::SurfaceConfiguration,
+        projection: Option<&TextProjection>,
     ) {
-        let buffer = &mut self.buffer;
-        let mut areas: Vec<glyphon::TextArea<'_>> = Vec::new();
-        let mut lines: Vec<glyphon::BufferLine> = Vec::new();
-        let shaping = glyphon::Shaping::Basic;
-        let ending = glyphon::cosmic_text::LineEnding::CrLf;
-        let attrs_list = glyphon::AttrsList::new(Attrs::new());
-
-        for region in regions {
-            lines.push(glyphon::BufferLine::new(
-                region.text.clone(),
-                ending,
-                attrs_list.clone(),
-                shaping,
-            ));
-        }
-
-        buffer.set_lines(lines);
-        buffer.shape(&mut self.font_system);
-
-        for region in regions.iter() {
-            areas.push(glyphon::TextArea {
-                buffer: buffer.get(),
-                left: region.pos[0],
-                top: region.pos[1],
-                scale: self.font_size,
-                bounds: region.bounds.unwrap_or(glyphon::TextBounds {
-                    left: 0,
-                    top: 0,
-                    right: surface_config.width as i32,
-                    bottom: surface_config.height as i32,
+        if self.resolution.width != surface_config.width
+            || self.resolution.height != surface_config.height
+        {
+            self.resize(
+                queue,
+                winit::dpi::PhysicalSize::new(surface_config.width, surface_config.height),
+            );
+        }
+        let scale = self.scale();
+        while self.regions.len() < regions.len() {
+            let buffer = self.create_buffer_default();
+            self.regions.push(buffer);
+        }
+        self.regions.truncate(regions.len());
+
+        for family in regions.iter().filter_map(|r| r.font_family.as_deref()) {
+            if !self.missing_fonts.contains(family) && !self.has_family(family) {
+                log_warning!("Font '{}' not loaded, using the default", family);
+                self.missing_fonts.insert(family.to_string());
+            }
+        }
+
+        let (width, height) = self.logical_size();
+        let size = [width as f32, height as f32];
+        let mut placements = Vec::with_capacity(regions.len());
+        for (buffer, region) in self.regions.iter_mut().zip(regions) {
+            let placement = match &region.anchor {
+                None => Some(Placement {
+                    pos: region.pos,
+                    scale: 1.0,
+                    color: region.color,
+                }),
+                Some(anchor) => projection.and_then(|projection| {
+                    let (pos, distance) = anchor.project(projection, size)?;
+                    let (scale, alpha) = self.label_falloff.at(distance)?;
+                    let color = region.color;
+                    let alpha = (color.a() as f32 * alpha).round() as u8;
+                    Some(Placement {
+                        pos,
+                        scale,
+                        color: glyphon::Color::rgba(color.r(), color.g(), color.b(), alpha),
+                    })
                 }),
-                default_color: region.color,
+            };
+            let Some(mut placement) = placement else {
+                placements.push(None);
+                continue;
+            };
+            Self::shape_region(
+                &mut self.font_system,
+                &self.missing_fonts,
+                buffer,
+                region,
+                self.font_size,
+                scale * placement.scale,
+            );
+            if region.anchor.is_some() {
+                // Centered above the anchor, and dropped once entirely off screen.
+                let (w, h) = buffer.measure();
+                let (w, h) = (w / scale, h / scale);
+                placement.pos = [placement.pos[0] - w / 2.0, placement.pos[1] - h];
+                let [x, y] = placement.pos;
+                if x > size[0] || y > size[1] || x + w < 0.0 || y + h < 0.0 {
+                    placements.push(None);
+                    continue;
+                }
+            }
+            placements.push(Some(placement));
+        }
+
+        let to_physical = |v: i32| snap_to_pixel(logical_to_physical(v as f32, scale)) as i32;
+        let areas = self
+            .regions
+            .iter()
+            .zip(regions)
+            .zip(&placements)
+            .filter_map(|((buffer, region), placement)| {
+                let placement = placement.as_ref()?;
+                Some((buffer, region, placement))
+            })
+            .map(|(buffer, region, placement)| glyphon::TextArea {
+                buffer: buffer.get(),
+                left: snap_to_pixel(logical_to_physical(placement.pos[0], scale)),
+                top: snap_to_pixel(logical_to_physical(placement.pos[1], scale)),
+                scale: 1.0,
+                bounds: region
+                    .bounds
+                    .map(|b| glyphon::TextBounds {
+                        left: to_physical(b.left),
+                        top: to_physical(b.top),
+                        right: to_physical(b.right),
+                        bottom: to_physical(b.bottom),
+                    })
+                    .unwrap_or(glyphon::TextBounds {
+                        left: 0,
+                        top: 0,
+                        right: self.resolution.width as i32,
+                        bottom: self.resolution.height as i32,
+                    }),
+                default_color: placement.color,
                 custom_glyphs: &[],
             });
-        }
 
         if let Err(e) = self.renderer.prepare(
             device,
@@ -157,6 +440,48 @@ impl RenderText {
             crate::log_error!("Error preparing text: {}", e);
         }
     }
+    /// Logical width of the widest line of `region` and height of all its lines, as
+    /// [`RenderText::prepare_regions`] would lay it out.
+    pub fn measure(&mut self, region: &TextRegion) -> [f32; 2] {
+        let scale = self.scale();
+        Self::shape_region(
+            &mut self.font_system,
+            &self.missing_fonts,
+            &mut self.measure_buffer,
+            region,
+            self.font_size,
+            scale,
+        );
+        let (width, height) = self.measure_buffer.measure();
+        [width / scale, height / scale]
+    }
+    /// Sets `buffer` to `region`'s text at its physical font size and shapes it.
+    fn shape_region(
+        font_system: &mut glyphon::FontSystem,
+        missing_fonts: &HashSet<String>,
+        buffer: &mut GlyphonBuffer,
+        region: &TextRegion,
+        font_size: f32,
+        scale: f32,
+    ) {
+        let size = logical_to_physical(region.font_size.unwrap_or(font_size), scale);
+        buffer.set_metrics(
+            font_system,
+            glyphon::Metrics::new(size, (size * LINE_HEIGHT).round()),
+        );
+        // Each region gets its own attrs, so fonts never leak between buffers.
+        let mut attrs = glyphon::Attrs::new();
+        if let Some(family) = region.font_family.as_deref() {
+            if !missing_fonts.contains(family) {
+                attrs = attrs.family(glyphon::Family::Name(family));
+            }
+        }
+        if let Some(weight) = region.font_weight {
+            attrs = attrs.weight(weight);
+        }
+        buffer.set_text_with_attrs(font_system, &region.text, attrs);
+        buffer.shape(font_system);
+    }
     pub fn prepare(
         &mut self,
         device: &wgpu::Device,
@@ -191,17 +516,105 @@ impl RenderText {
     }
 }
 
+impl RenderText {
+    /// Draws the prepared text into `rpass` without needing the world, e.g. for the loading screen.
+    pub fn draw(&self, rpass: &mut wgpu::RenderPass) {
+        if let Err(e) = self.renderer.render(&self.atlas, &self.viewport, rpass) {
+            crate::log_error!("Error rendering text: {}", e);
+        }
+    }
+}
+
 impl crate::RenderPass for RenderText {
     fn render(
         &self,
-        _managers: &mut crate::ModelManager,
+        _managers: &crate::ModelManager,
         rpass: &mut wgpu::RenderPass,
         _world: &crate::World,
         _uniform_bind_group: &wgpu::BindGroup,
         debug_mode: &DebugMode,
     ) {
-        if let Err(e) = self.renderer.render(&self.atlas, &self.viewport, rpass) {
-            crate::log_error!("Error rendering text: {}", e);
+        self.draw(rpass);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn logical_lengths_convert_at_common_scale_factors() {
+        for (scale, expected) in [(1.0, 24.0), (1.5, 36.0), (2.0, 48.0)] {
+            assert_eq!(logical_to_physical(24.0, scale), expected);
+        }
+        // A 1.5 scale lands odd logical positions on half pixels, which snapping resolves.
+        assert_eq!(logical_to_physical(7.0, 1.5), 10.5);
+        assert_eq!(snap_to_pixel(logical_to_physical(7.0, 1.5)), 11.0);
+    }
+
+    #[test]
+    fn snapping_is_deterministic_and_stays_within_half_a_pixel() {
+        for scale in [1.0, 1.25, 1.5, 1.75, 2.0] {
+            for step in 0..400 {
+                let logical = step as f32 * 0.37;
+                let physical = logical_to_physical(logical, scale);
+                let snapped = snap_to_pixel(physical);
+                assert_eq!(snapped.fract(), 0.0);
+                assert!((snapped - physical).abs() <= 0.5);
+                assert_eq!(snapped.to_bits(), snap_to_pixel(physical).to_bits());
+            }
+        }
+    }
+
+    fn render_text(scale_factor: f64) -> Option<RenderText> {
+        let _ = crate::GPU::init(crate::GpuRequirements::default());
+        let (device, queue) =
+            crate::GPU::with_read_recovered(|gpu| (gpu.device().clone(), gpu.queue().clone()))
+                .ok()?;
+        let format = wgpu::TextureFormat::Rgba8UnormSrgb;
+        Some(RenderText::new(
+            &device,
+            &queue,
+            format,
+            &None,
+            scale_factor,
+        ))
+    }
+
+    /// Needs an adapter; passes without one.
+    #[test]
+    fn measurements_stay_logical_across_scale_factor_changes() {
+        let Some(mut text) = render_text(1.0) else {
+            return;
+        };
+        let region = TextRegion::new(
+            "Health 100\nAmmo 24",
+            [0.0; 2],
+            glyphon::Color::rgb(255, 255, 255),
+        );
+        let before = text.measure(&region);
+        assert_eq!(before[1], 2.0 * text.line_height());
+
+        for scale_factor in [1.5, 2.0] {
+            text.set_scale_factor(scale_factor);
+            let [width, height] = text.measure(&region);
+            assert!(
+                (height - before[1]).abs() <= 1.0,
+                "{} at {}",
+                height,
+                scale_factor
+            );
+            // Hinting at the larger size may shift glyph advances by a fraction of a pixel each.
+            assert!(
+                (width - before[0]).abs() <= 1.0 + before[0] * 0.05,
+                "{} vs {} at {}",
+                width,
+                before[0],
+                scale_factor
+            );
         }
+
+        text.set_scale_factor(1.0);
+        assert_eq!(text.measure(&region), before);
     }
 }
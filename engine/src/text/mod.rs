@@ -1,3 +1,9 @@
+pub mod console;
+pub use console::*;
+
+pub mod focus;
+pub use focus::*;
+
 pub mod glyphon_buffer;
 pub use glyphon_buffer::*;
 
@@ -6,3 +12,6 @@ pub use glyphon_renderer::*;
 
 pub mod text_region;
 pub use text_region::*;
+
+pub mod text_layout;
+pub use text_layout::*;
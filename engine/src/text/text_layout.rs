@@ -0,0 +1,102 @@
+//! Anchored HUD layout. A [`TextStack`] places its regions one under the other from a
+//! [`TextAnchor`], measuring each with the glyphon buffer it will be drawn with, so lines
+//! can't overlap at any font size or scale. Layout reads the renderer's current logical
+//! size, so laying out again after a resize re-anchors right and bottom stacks.
+
+use crate::ScreenCorner;
+
+use super::{RenderText, TextRegion};
+
+/// A screen corner and an offset from it, in logical pixels pointing into the screen.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextAnchor {
+    pub corner: ScreenCorner,
+    pub offset: [f32; 2],
+}
+
+impl TextAnchor {
+    pub fn new(corner: ScreenCorner, offset: [f32; 2]) -> Self {
+        Self { corner, offset }
+    }
+    /// Text hangs left of right corners.
+    pub fn right_aligned(&self) -> bool {
+        matches!(
+            self.corner,
+            ScreenCorner::TopRight | ScreenCorner::BottomRight
+        )
+    }
+    /// Stacks grow upward from bottom corners.
+    pub fn bottom_aligned(&self) -> bool {
+        matches!(
+            self.corner,
+            ScreenCorner::BottomLeft | ScreenCorner::BottomRight
+        )
+    }
+    /// The anchor point on a `width` by `height` logical surface.
+    pub fn pos(&self, width: u32, height: u32) -> [f32; 2] {
+        let [x, y] = self.corner.pos(width, height, 0.0);
+        let [dx, dy] = self.offset;
+        [
+            if self.right_aligned() { x - dx } else { x + dx },
+            if self.bottom_aligned() {
+                y - dy
+            } else {
+                y + dy
+            },
+        ]
+    }
+}
+
+/// Regions laid out top to bottom from an anchor. Pushed regions keep everything but their
+/// position, so `text_region` helpers can be pushed with any position.
+pub struct TextStack {
+    pub anchor: TextAnchor,
+    /// Logical pixels between one region's last line and the next region.
+    pub spacing: f32,
+    regions: Vec<TextRegion>,
+}
+
+impl TextStack {
+    pub fn new(anchor: TextAnchor) -> Self {
+        Self {
+            anchor,
+            spacing: 0.0,
+            regions: Vec::new(),
+        }
+    }
+    pub fn with_spacing(mut self, spacing: f32) -> Self {
+        self.spacing = spacing;
+        self
+    }
+    pub fn push(&mut self, region: TextRegion) -> &mut Self {
+        self.regions.push(region);
+        self
+    }
+    pub fn is_empty(&self) -> bool {
+        self.regions.is_empty()
+    }
+    /// Positions the regions for `text`'s current size and hands them back in push order.
+    pub fn layout(self, text: &mut RenderText) -> Vec<TextRegion> {
+        let (width, height) = text.logical_size();
+        let [x, y] = self.anchor.pos(width, height);
+        let sizes: Vec<[f32; 2]> = self.regions.iter().map(|r| text.measure(r)).collect();
+        let gaps = sizes.len().saturating_sub(1) as f32 * self.spacing;
+        let total = sizes.iter().map(|[_, h]| h).sum::<f32>() + gaps;
+
+        let mut top = if self.anchor.bottom_aligned() {
+            y - total
+        } else {
+            y
+        };
+        let right = self.anchor.right_aligned();
+        self.regions
+            .into_iter()
+            .zip(sizes)
+            .map(|(mut region, [w, h])| {
+                region.pos = [if right { x - w } else { x }, top];
+                top += h + self.spacing;
+                region
+            })
+            .collect()
+    }
+}
@@ -1,8 +1,21 @@
+use glam::{FloatExt, Mat4, Vec3};
+
+/// A block of screen text. `pos`, `bounds` and `font_size` are in logical pixels and are
+/// converted to physical pixels by [`crate::RenderText`].
+#[derive(Debug, Clone)]
 pub struct TextRegion {
     pub text: String,
     pub pos: [f32; 2],
     pub color: glyphon::Color,
     pub bounds: Option<glyphon::TextBounds>,
+    /// Overrides the renderer's default font size.
+    pub font_size: Option<f32>,
+    /// Family loaded with [`crate::RenderText::load_font`]; the default font when unset or
+    /// not loaded.
+    pub font_family: Option<String>,
+    pub font_weight: Option<glyphon::Weight>,
+    /// Pins the region to a world position, see [`TextRegion::world_anchored`].
+    pub anchor: Option<WorldAnchor>,
 }
 
 impl TextRegion {
@@ -12,6 +25,118 @@ impl TextRegion {
             pos,
             color,
             bounds: None,
+            font_size: None,
+            font_family: None,
+            font_weight: None,
+            anchor: None,
+        }
+    }
+    /// Screen text that follows `world_pos`: each prepare projects it through the camera
+    /// and centers the text above the projected point, `pos` is ignored. Hidden behind the
+    /// camera, off screen, or past the renderer's [`LabelFalloff::far`].
+    pub fn world_anchored(
+        text: impl Into<String>,
+        world_pos: impl Into<Vec3>,
+        color: glyphon::Color,
+    ) -> Self {
+        Self {
+            anchor: Some(WorldAnchor {
+                position: world_pos.into(),
+                offset: [0.0; 2],
+            }),
+            ..Self::new(text, [0.0; 2], color)
+        }
+    }
+    /// Shifts a world-anchored region by `offset` logical pixels once projected; negative
+    /// `y` moves it up. Regions without an anchor move their `pos` instead.
+    pub fn with_pixel_offset(mut self, offset: [f32; 2]) -> Self {
+        match &mut self.anchor {
+            Some(anchor) => anchor.offset = offset,
+            None => self.pos = [self.pos[0] + offset[0], self.pos[1] + offset[1]],
+        }
+        self
+    }
+    pub fn with_font_size(mut self, font_size: f32) -> Self {
+        self.font_size = Some(font_size);
+        self
+    }
+    pub fn with_font_family(mut self, family: impl Into<String>) -> Self {
+        self.font_family = Some(family.into());
+        self
+    }
+    pub fn with_font_weight(mut self, weight: glyphon::Weight) -> Self {
+        self.font_weight = Some(weight);
+        self
+    }
+}
+
+/// Where a world-anchored [`TextRegion`] is pinned.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WorldAnchor {
+    pub position: Vec3,
+    /// Logical pixels added after projection.
+    pub offset: [f32; 2],
+}
+
+impl WorldAnchor {
+    /// Logical pixel position of the anchor on a viewport of logical `size`, with the
+    /// offset applied, and its distance from the eye. `None` behind the camera or outside
+    /// the depth range.
+    pub fn project(&self, projection: &TextProjection, size: [f32; 2]) -> Option<([f32; 2], f32)> {
+        let clip = projection.view_proj * self.position.extend(1.0);
+        if clip.w <= f32::EPSILON {
+            return None;
+        }
+        let ndc = clip.truncate() / clip.w;
+        if !(0.0..=1.0).contains(&ndc.z) {
+            return None;
+        }
+        let x = (ndc.x * 0.5 + 0.5) * size[0] + self.offset[0];
+        let y = (0.5 - ndc.y * 0.5) * size[1] + self.offset[1];
+        Some(([x, y], projection.eye.distance(self.position)))
+    }
+}
+
+/// The view world-anchored regions are projected through, see
+/// [`crate::camera::Camera::text_projection`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextProjection {
+    pub view_proj: Mat4,
+    pub eye: Vec3,
+}
+
+/// How world-anchored regions shrink and fade with distance from the camera.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LabelFalloff {
+    /// Distance up to which labels draw at full size and opacity.
+    pub near: f32,
+    /// Distance at which labels reach `min_scale` and are hidden beyond.
+    pub far: f32,
+    /// Size at `far` as a fraction of the full size; 1 keeps labels the same size.
+    pub min_scale: f32,
+    /// Fades labels out between `near` and `far`.
+    pub fade: bool,
+}
+
+impl Default for LabelFalloff {
+    fn default() -> Self {
+        Self {
+            near: 10.0,
+            far: 60.0,
+            min_scale: 0.5,
+            fade: true,
+        }
+    }
+}
+
+impl LabelFalloff {
+    /// Size factor and opacity of a label `distance` away; `None` past `far`.
+    pub fn at(&self, distance: f32) -> Option<(f32, f32)> {
+        if distance > self.far {
+            return None;
         }
+        let t = ((distance - self.near) / (self.far - self.near).max(f32::EPSILON)).clamp(0.0, 1.0);
+        let alpha = if self.fade { 1.0 - t } else { 1.0 };
+        Some((1.0f32.lerp(self.min_scale, t), alpha))
     }
 }
@@ -8,9 +8,21 @@ pub enum EngineError {
     #[error("AssetLoad error: {0}")]
     AssetLoadError(String),
 
+    #[error("Asset missing: {0}")]
+    AssetMissing(String),
+
     #[error("RwLock error: {0}")]
     RwLockError(String),
 
+    #[error("GPU resources are not initialized")]
+    GpuNotInitialized,
+
+    #[error("Shader {path} failed to compile: {detail}")]
+    ShaderCompileError { path: String, detail: String },
+
+    #[error("Missing {kind}: {key}")]
+    MissingResource { kind: &'static str, key: String },
+
     #[error("No suitable GPU adapter found")]
     AdapterNotFound,
 
@@ -49,4 +61,16 @@ pub enum EngineError {
 
     #[error("TobjLoadError: {0}")]
     TobjLoadError(#[from] tobj::LoadError),
+
+    #[error("Startup error: {0}")]
+    StartupError(String),
+
+    #[error("Frame plan error: {0}")]
+    FramePlanError(String),
+
+    #[error("Import error: {0}")]
+    ImportError(String),
+
+    #[error("Game module error: {0}")]
+    GameModuleError(String),
 }
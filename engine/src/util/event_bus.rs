@@ -1,11 +1,217 @@
-use crossbeam::channel::Receiver;
-use std::sync::Arc;
+use crossbeam::channel::{Receiver, Sender, TrySendError};
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, OnceLock,
+    },
+};
 use winit::event_loop::EventLoopProxy;
 
+/// Events a subscriber can fall behind by before its oldest ones are dropped.
+pub const EVENT_QUEUE_CAPACITY: usize = 256;
+
 #[derive(Debug, Clone)]
 pub enum ApplicationEvent {
     Shutdown,
     Projection,
+    /// A WGSL file under `assets/shaders` was written, see [`crate::Shader::watch`].
+    ShaderChanged(std::path::PathBuf),
+    /// A background texture decode finished, see [`crate::TextureLoader`].
+    TextureLoaded(crate::CacheKey),
+    /// Asks the app to present with this mode, see [`crate::SurfaceExt::set_present_mode`].
+    SetPresentMode(wgpu::PresentMode),
+    /// Swaps the camera between its perspective and orthographic lens, see
+    /// [`crate::camera::Camera::toggle_projection_mode`].
+    ToggleCameraProjection,
+    /// Draws the camera through this lens, see
+    /// [`crate::camera::Camera::set_projection_mode`].
+    SetCameraProjection(crate::camera::CameraProjection),
+    /// Freezes or resumes the world, see [`crate::Time::set_paused`].
+    Pause,
+    /// Runs one fixed world step while paused, see [`crate::Time::request_step`].
+    Step,
+    /// Multiplies the time handed to the world, see [`crate::Time::set_scale`].
+    SetTimeScale(f32),
+    /// The GPU device was lost; see [`crate::DeviceLoss`] and [`crate::GPU::recover`].
+    DeviceLost,
+    /// The entity under the cursor, read back from the id pass; see
+    /// [`crate::EntityPicker::pick_at`].
+    Picked(crate::Entity),
+}
+
+/// Events raised by the world during an update, drained by the application each frame.
+#[derive(Debug, Clone)]
+pub enum WorldEvent {
+    SnapshotStarved(crate::Entity),
+    EntityExpired(crate::Entity, crate::ExpiryReason),
+    /// An entity hit the ground at the given downward speed.
+    Landed(crate::Entity, f32),
+    /// Published by a sequence's `Publish` event key.
+    Sequence(String),
+    /// Damage landed on `target`, leaving it with `remaining` health.
+    DamageTaken {
+        target: crate::Entity,
+        amount: f32,
+        source: Option<crate::Entity>,
+        remaining: f32,
+    },
+    /// `entity`'s health reached zero. Follows its last [`WorldEvent::DamageTaken`].
+    EntityDied {
+        entity: crate::Entity,
+        source: Option<crate::Entity>,
+    },
+    /// A one-shot sound at a world position, see [`crate::World::play_sound_at`].
+    PlaySound {
+        key: crate::CacheKey,
+        position: glam::Vec3,
+    },
+}
+
+/// Events engine subsystems publish on the [`EventBus`] without knowing who listens.
+#[derive(Debug, Clone)]
+pub enum EngineEvent {
+    /// A watched asset file was modified or created, see [`crate::Shader::watch`].
+    AssetChanged(PathBuf),
+    /// A background texture decode finished, see [`crate::TextureLoader`].
+    TextureLoaded(crate::CacheKey),
+    /// The GPU device was lost, see [`crate::DeviceLoss`].
+    DeviceLost,
+}
+
+impl EngineEvent {
+    /// The event the app's winit loop answers this with, if any. Changed WGSL files
+    /// become [`ApplicationEvent::ShaderChanged`]; other assets have no handler yet.
+    pub fn application_event(&self) -> Option<ApplicationEvent> {
+        match self {
+            Self::AssetChanged(path) => {
+                crate::Shader::name_of(path).map(|_| ApplicationEvent::ShaderChanged(path.clone()))
+            }
+            Self::TextureLoaded(key) => Some(ApplicationEvent::TextureLoaded(*key)),
+            Self::DeviceLost => Some(ApplicationEvent::DeviceLost),
+        }
+    }
+}
+
+/// Hands an event to one subscriber; `false` once the subscriber is gone.
+type Deliver<E> = Box<dyn Fn(&E) -> bool + Send>;
+
+static SUBSCRIBERS: OnceLock<Mutex<HashMap<TypeId, Box<dyn Any + Send>>>> = OnceLock::new();
+static DROPPED: AtomicU64 = AtomicU64::new(0);
+
+/// Process-wide publish/subscribe, keyed by event type. Publishing never blocks: each
+/// subscriber has a queue of [`EVENT_QUEUE_CAPACITY`] events, and a full queue drops its
+/// oldest event to make room, counted in [`EventBus::dropped`].
+pub struct EventBus;
+
+impl EventBus {
+    /// Delivers `event` to every live subscriber of `E`.
+    pub fn publish<E: Clone + Send + 'static>(event: E) {
+        let mut subscribers = Self::subscribers()
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        let Some(entry) = subscribers.get_mut(&TypeId::of::<E>()) else {
+            return;
+        };
+        if let Some(deliverers) = entry.downcast_mut::<Vec<Deliver<E>>>() {
+            deliverers.retain(|deliver| deliver(&event));
+        }
+    }
+    /// A queue of every `E` published from now on, polled with
+    /// [`EventReceiver::try_recv`] or [`EventReceiver::drain`]. Dropping it unsubscribes.
+    pub fn subscribe<E: Clone + Send + 'static>() -> EventReceiver<E> {
+        let (sender, receiver) = crossbeam::channel::bounded(EVENT_QUEUE_CAPACITY);
+        let oldest = receiver.clone();
+        let alive = Arc::new(());
+        let token = Arc::downgrade(&alive);
+        Self::register(Box::new(move |event: &E| {
+            if token.strong_count() == 0 {
+                return false;
+            }
+            let mut event = event.clone();
+            loop {
+                match sender.try_send(event) {
+                    Ok(()) => return true,
+                    Err(TrySendError::Full(rejected)) => {
+                        if oldest.try_recv().is_ok() {
+                            DROPPED.fetch_add(1, Ordering::Relaxed);
+                        }
+                        event = rejected;
+                    }
+                    Err(TrySendError::Disconnected(_)) => return false,
+                }
+            }
+        }));
+        EventReceiver {
+            receiver,
+            _alive: alive,
+        }
+    }
+    /// Sends what `map` makes of each `E` on `sender`, until its receiver is dropped. For
+    /// bridges that are drained elsewhere, such as [`EventBusProxy`] feeding the winit
+    /// loop; `sender` should be unbounded, or a full channel drops the event.
+    pub fn forward<E, T>(sender: Sender<T>, map: impl Fn(&E) -> Option<T> + Send + 'static)
+    where
+        E: Clone + Send + 'static,
+        T: Send + 'static,
+    {
+        Self::register(Box::new(move |event: &E| {
+            let Some(mapped) = map(event) else {
+                return true;
+            };
+            match sender.try_send(mapped) {
+                Ok(()) => true,
+                Err(TrySendError::Full(_)) => {
+                    DROPPED.fetch_add(1, Ordering::Relaxed);
+                    true
+                }
+                Err(TrySendError::Disconnected(_)) => false,
+            }
+        }));
+    }
+    /// Events dropped so far because a subscriber fell behind.
+    pub fn dropped() -> u64 {
+        DROPPED.load(Ordering::Relaxed)
+    }
+    fn register<E: 'static>(deliver: Deliver<E>) {
+        let mut subscribers = Self::subscribers()
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        let entry = subscribers
+            .entry(TypeId::of::<E>())
+            .or_insert_with(|| Box::new(Vec::<Deliver<E>>::new()));
+        if let Some(deliverers) = entry.downcast_mut::<Vec<Deliver<E>>>() {
+            deliverers.push(deliver);
+        }
+    }
+    fn subscribers() -> &'static Mutex<HashMap<TypeId, Box<dyn Any + Send>>> {
+        SUBSCRIBERS.get_or_init(|| Mutex::new(HashMap::new()))
+    }
+}
+
+/// One subscription to the [`EventBus`], see [`EventBus::subscribe`].
+pub struct EventReceiver<E> {
+    receiver: Receiver<E>,
+    /// The bus holds a weak reference and drops the subscription once this is gone.
+    _alive: Arc<()>,
+}
+
+impl<E> EventReceiver<E> {
+    pub fn try_recv(&self) -> Option<E> {
+        self.receiver.try_recv().ok()
+    }
+    /// Every event queued since the last poll.
+    pub fn drain(&self) -> impl Iterator<Item = E> + '_ {
+        self.receiver.try_iter()
+    }
+    pub fn len(&self) -> usize {
+        self.receiver.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.receiver.is_empty()
+    }
 }
 
 pub trait EventProxyTrait<T: 'static + std::fmt::Debug> {
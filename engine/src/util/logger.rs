@@ -1,5 +1,42 @@
 use log::LevelFilter;
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::sync::Mutex;
+
+/// Number of formatted log lines kept for crash reports.
+pub const LOG_HISTORY_LEN: usize = 256;
+
+static LOG_HISTORY: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+static WARNED_ONCE: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+
+fn record_history(record: &log::Record) {
+    if let Ok(mut history) = LOG_HISTORY.lock() {
+        if history.len() >= LOG_HISTORY_LEN {
+            history.pop_front();
+        }
+        history.push_back(format!(
+            "[{} {}] {}",
+            record.level(),
+            record.target(),
+            record.args()
+        ));
+    }
+}
+
+/// The most recent log lines that passed the filter, oldest first.
+pub fn recent_log_lines() -> Vec<String> {
+    LOG_HISTORY
+        .lock()
+        .map(|history| history.iter().cloned().collect())
+        .unwrap_or_default()
+}
+/// Whether `message` is new to [`crate::log_warning_once`]; false for every later call
+/// with the same message.
+pub fn first_warning(message: &str) -> bool {
+    WARNED_ONCE
+        .lock()
+        .map(|mut warned| warned.insert(message.to_string()))
+        .unwrap_or(true)
+}
 pub struct LogLevelFilterFactory {
     filters: HashMap<&'static str, LevelFilter>,
     default_level: LevelFilter,
@@ -101,6 +138,9 @@ impl log::Log for Logger {
     }
 
     fn log(&self, record: &log::Record) {
+        if self.env_logger.matches(record) {
+            record_history(record);
+        }
         self.env_logger.log(record)
     }
 
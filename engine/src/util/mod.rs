@@ -12,3 +12,9 @@ pub use time::*;
 
 pub mod helpers;
 pub use helpers::*;
+
+pub mod settings;
+pub use settings::*;
+
+pub mod startup;
+pub use startup::*;
@@ -0,0 +1,297 @@
+//! Startup settings that change without a rebuild, read from [`EngineSettings::FILE`] under
+//! the asset root. Fields the file leaves out keep their defaults; fields it has that no
+//! setting matches are logged and ignored.
+
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    camera::CameraProjection, log_warning, AdapterSelection, AmbientSource, AssetPaths,
+    EngineError, GpuRequirements, Light,
+};
+
+/// Smallest and largest [`EngineSettings::render_scale`].
+pub const RENDER_SCALE_RANGE: (f32, f32) = (0.25, 2.0);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EngineSettings {
+    pub window_title: String,
+    /// Inner size of the main window in logical pixels.
+    pub window_size: (u32, u32),
+    /// Asked of the surface; the closest supported mode is used, see
+    /// [`crate::present_mode_fallbacks`].
+    pub present_mode: wgpu::PresentMode,
+    /// Scene and HDR targets are the window size times this; the final blit stretches
+    /// the result over the surface.
+    pub render_scale: f32,
+    /// Chunks streamed in around the camera, see [`crate::Terrain::update_streaming`].
+    pub view_distance: i32,
+    /// Vertical field of view of the perspective camera, in degrees.
+    pub fov_y: f32,
+    pub znear: f32,
+    pub zfar: f32,
+    /// Ambient light, see [`Light::set_ambient`].
+    pub ambient_color: [f32; 3],
+    pub ambient_intensity: f32,
+    /// With [`AmbientSource::Environment`], `ambient_color` only stands in until the
+    /// environment's average color is known.
+    pub ambient_source: AmbientSource,
+    /// Edited terrain chunks are saved here, relative to the working directory, and loaded
+    /// back when streamed in again; `None` forgets edits. See [`crate::ChunkStore`].
+    pub world_dir: Option<PathBuf>,
+    /// Which GPU to render on when there are several; `RUPY_GPU_ADAPTER` overrides it.
+    pub gpu_adapter: AdapterSelection,
+}
+
+impl Default for EngineSettings {
+    fn default() -> Self {
+        Self {
+            window_title: "RupyEngine".to_string(),
+            window_size: (1280, 720),
+            present_mode: wgpu::PresentMode::AutoVsync,
+            render_scale: 1.0,
+            view_distance: 4,
+            fov_y: 89.0,
+            znear: 0.1,
+            zfar: 100.0,
+            ambient_color: Light::DEFAULT_AMBIENT_COLOR,
+            ambient_intensity: Light::DEFAULT_AMBIENT_INTENSITY,
+            ambient_source: AmbientSource::Fixed,
+            world_dir: Some(PathBuf::from("saves").join("world")),
+            gpu_adapter: AdapterSelection::HighPerformance,
+        }
+    }
+}
+
+impl EngineSettings {
+    pub const FILE: &'static str = "settings.ron";
+
+    pub fn path() -> PathBuf {
+        AssetPaths::root().join(Self::FILE)
+    }
+
+    /// Settings from `source`, missing fields defaulted and out of range ones clamped.
+    pub fn parse(source: &str) -> Result<Self, ron::error::SpannedError> {
+        for field in Self::unknown_fields(source) {
+            log_warning!("Settings: unknown field '{}'", field);
+        }
+        let settings: Self = ron::de::from_str(source)?;
+        Ok(settings.validated())
+    }
+    /// Top-level fields of `source` no setting is named after.
+    pub fn unknown_fields(source: &str) -> Vec<String> {
+        let names = |value: ron::Value| match value {
+            ron::Value::Map(map) => map
+                .iter()
+                .filter_map(|(key, _)| match key {
+                    ron::Value::String(name) => Some(name.clone()),
+                    _ => None,
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+        let known = ron::ser::to_string(&Self::default())
+            .ok()
+            .and_then(|defaults| ron::de::from_str(&defaults).ok())
+            .map_or_else(Vec::new, names);
+        ron::de::from_str(source)
+            .map_or_else(|_| Vec::new(), names)
+            .into_iter()
+            .filter(|name| !known.contains(name))
+            .collect()
+    }
+    /// These settings with every field brought into its valid range; each change is
+    /// logged.
+    pub fn validated(mut self) -> Self {
+        let defaults = Self::default();
+        let (min_scale, max_scale) = RENDER_SCALE_RANGE;
+        let render_scale = if self.render_scale.is_finite() {
+            self.render_scale.clamp(min_scale, max_scale)
+        } else {
+            defaults.render_scale
+        };
+        if render_scale != self.render_scale {
+            log_warning!(
+                "Settings: render_scale {} out of range, using {}",
+                self.render_scale,
+                render_scale
+            );
+            self.render_scale = render_scale;
+        }
+        if self.view_distance < 1 {
+            log_warning!(
+                "Settings: view_distance {} under 1, using 1",
+                self.view_distance
+            );
+            self.view_distance = 1;
+        }
+        if self.window_size.0 == 0 || self.window_size.1 == 0 {
+            log_warning!(
+                "Settings: window_size {:?} is empty, using {:?}",
+                self.window_size,
+                defaults.window_size
+            );
+            self.window_size = defaults.window_size;
+        }
+        let fov_y = if self.fov_y.is_finite() {
+            self.fov_y.clamp(1.0, 179.0)
+        } else {
+            defaults.fov_y
+        };
+        if fov_y != self.fov_y {
+            log_warning!(
+                "Settings: fov_y {} out of range, using {}",
+                self.fov_y,
+                fov_y
+            );
+            self.fov_y = fov_y;
+        }
+        if !(self.znear > 0.0 && self.zfar > self.znear) {
+            log_warning!(
+                "Settings: znear {} and zfar {} don't make a depth range, using {} and {}",
+                self.znear,
+                self.zfar,
+                defaults.znear,
+                defaults.zfar
+            );
+            self.znear = defaults.znear;
+            self.zfar = defaults.zfar;
+        }
+        if !(self.ambient_intensity >= 0.0 && self.ambient_intensity.is_finite()) {
+            log_warning!(
+                "Settings: ambient_intensity {} out of range, using {}",
+                self.ambient_intensity,
+                defaults.ambient_intensity
+            );
+            self.ambient_intensity = defaults.ambient_intensity;
+        }
+        self
+    }
+    /// The perspective lens these settings describe.
+    pub fn projection(&self) -> CameraProjection {
+        CameraProjection::Perspective {
+            fovy: self.fov_y.to_radians(),
+            znear: self.znear,
+            zfar: self.zfar,
+        }
+    }
+
+    /// What [`crate::GPU::init`] asks for with these settings.
+    pub fn gpu_requirements(&self) -> GpuRequirements {
+        GpuRequirements::default().with_adapter(self.gpu_adapter)
+    }
+
+    /// Gives `light` the ambient term these settings describe.
+    pub fn apply_ambient(&self, light: &mut Light) {
+        light.set_ambient(self.ambient_color, self.ambient_intensity);
+        light.set_ambient_source(self.ambient_source);
+    }
+
+    pub fn to_ron(&self) -> Result<String, EngineError> {
+        ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|e| EngineError::AssetLoadError(e.to_string()))
+    }
+    /// The settings in [`EngineSettings::path`], or the defaults when there is no such
+    /// file.
+    pub fn load() -> Result<Self, EngineError> {
+        let path = Self::path();
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let source = std::fs::read_to_string(&path)?;
+        Self::parse(&source)
+            .map_err(|e| EngineError::AssetLoadError(format!("{}: {}", path.display(), e)))
+    }
+    pub fn save(&self) -> Result<(), EngineError> {
+        std::fs::write(Self::path(), self.to_ron()?)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_fields_keep_their_defaults() {
+        assert_eq!(
+            EngineSettings::parse("()").unwrap(),
+            EngineSettings::default()
+        );
+        let settings =
+            EngineSettings::parse("(window_title: \"Demo\", view_distance: 9, world_dir: None)")
+                .unwrap();
+        assert_eq!(
+            settings,
+            EngineSettings {
+                window_title: "Demo".to_string(),
+                view_distance: 9,
+                world_dir: None,
+                ..EngineSettings::default()
+            }
+        );
+    }
+
+    #[test]
+    fn defaults_round_trip() {
+        let defaults = EngineSettings::default();
+        assert_eq!(
+            EngineSettings::parse(&defaults.to_ron().unwrap()).unwrap(),
+            defaults
+        );
+    }
+
+    #[test]
+    fn unknown_fields_are_reported_not_rejected() {
+        let source = "(vsync: true, view_distance: 2)";
+        assert_eq!(
+            EngineSettings::unknown_fields(source),
+            vec!["vsync".to_string()]
+        );
+        assert_eq!(EngineSettings::parse(source).unwrap().view_distance, 2);
+    }
+
+    #[test]
+    fn out_of_range_values_are_clamped() {
+        let (min_scale, max_scale) = RENDER_SCALE_RANGE;
+        let defaults = EngineSettings::default();
+        let clamp = |settings: EngineSettings| settings.validated();
+
+        let low = clamp(EngineSettings {
+            render_scale: 0.01,
+            view_distance: -3,
+            fov_y: 0.0,
+            ..EngineSettings::default()
+        });
+        assert_eq!(low.render_scale, min_scale);
+        assert_eq!(low.view_distance, 1);
+        assert_eq!(low.fov_y, 1.0);
+
+        let high = clamp(EngineSettings {
+            render_scale: 8.0,
+            fov_y: 270.0,
+            ..EngineSettings::default()
+        });
+        assert_eq!(high.render_scale, max_scale);
+        assert_eq!(high.fov_y, 179.0);
+
+        let broken = clamp(EngineSettings {
+            render_scale: f32::NAN,
+            window_size: (0, 600),
+            znear: 10.0,
+            zfar: 1.0,
+            ambient_intensity: -1.0,
+            ..EngineSettings::default()
+        });
+        assert_eq!(broken.render_scale, defaults.render_scale);
+        assert_eq!(broken.window_size, defaults.window_size);
+        assert_eq!((broken.znear, broken.zfar), (defaults.znear, defaults.zfar));
+        assert_eq!(broken.ambient_intensity, defaults.ambient_intensity);
+
+        assert_eq!(clamp(defaults.clone()), defaults);
+        let parsed = EngineSettings::parse("(render_scale: 3.0, view_distance: 0)").unwrap();
+        assert_eq!((parsed.render_scale, parsed.view_distance), (max_scale, 1));
+    }
+}
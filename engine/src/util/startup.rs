@@ -0,0 +1,474 @@
+//! Staged startup: heavy initialization split into named tasks that run a little per
+//! frame, so the app can keep a loading screen on screen while they finish.
+
+use std::{
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use crate::{log_debug, log_error, recent_log_lines, EngineError};
+
+/// What one call of a task's step function achieved.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TaskProgress {
+    Done,
+    /// Needs another call; the fraction of the task finished so far.
+    Partial(f32),
+}
+
+type TaskFn<C> = Box<dyn FnMut(&mut C) -> Result<TaskProgress, EngineError>>;
+
+/// A named unit of startup work over the context `C`. Runs only after every task named in
+/// [`InitTask::after`] has finished.
+pub struct InitTask<C> {
+    name: &'static str,
+    after: Vec<&'static str>,
+    run: TaskFn<C>,
+    progress: f32,
+    done: bool,
+}
+
+impl<C> InitTask<C> {
+    pub fn new(
+        name: &'static str,
+        run: impl FnMut(&mut C) -> Result<TaskProgress, EngineError> + 'static,
+    ) -> Self {
+        Self {
+            name,
+            after: Vec::new(),
+            run: Box::new(run),
+            progress: 0.0,
+            done: false,
+        }
+    }
+    /// Task that finishes in a single call.
+    pub fn once(
+        name: &'static str,
+        mut run: impl FnMut(&mut C) -> Result<(), EngineError> + 'static,
+    ) -> Self {
+        Self::new(name, move |ctx| run(ctx).map(|_| TaskProgress::Done))
+    }
+    pub fn after(mut self, task: &'static str) -> Self {
+        self.after.push(task);
+        self
+    }
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+    pub fn progress(&self) -> f32 {
+        self.progress
+    }
+    pub fn done(&self) -> bool {
+        self.done
+    }
+}
+
+/// A task failed; the loading screen shows this instead of panicking.
+#[derive(Debug, Clone)]
+pub struct StartupFailure {
+    pub task: &'static str,
+    pub message: String,
+    /// Report with the error and recent log lines, if it could be written.
+    pub report: Option<PathBuf>,
+}
+
+impl StartupFailure {
+    fn new(task: &'static str, message: String) -> Self {
+        log_error!("Startup task '{}' failed: {}", task, message);
+        let mut failure = Self {
+            task,
+            message,
+            report: None,
+        };
+        match failure.write() {
+            Ok(path) => failure.report = Some(path),
+            Err(e) => log_error!("Startup failure report not written: {}", e),
+        }
+        failure
+    }
+
+    /// Writes `crash/startup-<unix seconds>.txt` with the error and the recent log.
+    fn write(&self) -> Result<PathBuf, EngineError> {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        let mut out = format!(
+            "Startup task '{}' failed: {}\n\nRecent log:\n",
+            self.task, self.message
+        );
+        for line in recent_log_lines() {
+            out.push_str(&format!("  {}\n", line));
+        }
+        let dir = PathBuf::from("crash");
+        std::fs::create_dir_all(&dir)?;
+        let path = dir.join(format!("startup-{}.txt", timestamp));
+        std::fs::write(&path, out)?;
+        Ok(path)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum StartupStatus {
+    Running,
+    Finished,
+    Failed(StartupFailure),
+}
+
+/// Runs [`InitTask`]s in dependency order, one frame's budget at a time.
+pub struct Startup<C> {
+    tasks: Vec<InitTask<C>>,
+    order: Option<Vec<usize>>,
+    cursor: usize,
+    budget: Duration,
+    failure: Option<StartupFailure>,
+}
+
+impl<C> Default for Startup<C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C> Startup<C> {
+    /// Roughly one frame at 60Hz; a single task step can overrun it.
+    pub const DEFAULT_BUDGET: Duration = Duration::from_millis(16);
+
+    pub fn new() -> Self {
+        Self {
+            tasks: Vec::new(),
+            order: None,
+            cursor: 0,
+            budget: Self::DEFAULT_BUDGET,
+            failure: None,
+        }
+    }
+    pub fn with_budget(mut self, budget: Duration) -> Self {
+        self.budget = budget;
+        self
+    }
+    /// Adds a task. Tasks added after the first [`Startup::step`] are ignored.
+    pub fn add(&mut self, task: InitTask<C>) -> &mut Self {
+        if self.order.is_none() {
+            self.tasks.push(task);
+        }
+        self
+    }
+    pub fn tasks(&self) -> &[InitTask<C>] {
+        &self.tasks
+    }
+
+    /// Task indices with each task after its dependencies, otherwise in insertion order.
+    fn resolve(&self) -> Result<Vec<usize>, StartupFailure> {
+        for task in &self.tasks {
+            if let Some(missing) = task
+                .after
+                .iter()
+                .find(|dep| !self.tasks.iter().any(|t| t.name == **dep))
+            {
+                return Err(StartupFailure::new(
+                    task.name,
+                    format!("depends on unknown task '{}'", missing),
+                ));
+            }
+        }
+        let mut order = Vec::with_capacity(self.tasks.len());
+        let mut placed = vec![false; self.tasks.len()];
+        while order.len() < self.tasks.len() {
+            let ready = (0..self.tasks.len()).find(|i| {
+                !placed[*i]
+                    && self.tasks[*i].after.iter().all(|dep| {
+                        self.tasks
+                            .iter()
+                            .enumerate()
+                            .all(|(j, t)| t.name != *dep || placed[j])
+                    })
+            });
+            match ready {
+                Some(i) => {
+                    placed[i] = true;
+                    order.push(i);
+                }
+                None => {
+                    let stuck = (0..self.tasks.len()).find(|i| !placed[*i]).unwrap_or(0);
+                    return Err(StartupFailure::new(
+                        self.tasks[stuck].name,
+                        "dependency cycle".to_string(),
+                    ));
+                }
+            }
+        }
+        Ok(order)
+    }
+
+    /// Runs tasks until they're all done, one fails, or the frame budget is spent.
+    pub fn step(&mut self, ctx: &mut C) -> StartupStatus {
+        if let Some(failure) = &self.failure {
+            return StartupStatus::Failed(failure.clone());
+        }
+        if self.order.is_none() {
+            match self.resolve() {
+                Ok(order) => self.order = Some(order),
+                Err(failure) => {
+                    self.failure = Some(failure.clone());
+                    return StartupStatus::Failed(failure);
+                }
+            }
+        }
+        let order = self.order.as_ref().expect("resolved above");
+        let start = Instant::now();
+        while let Some(&idx) = order.get(self.cursor) {
+            let task = &mut self.tasks[idx];
+            match (task.run)(ctx) {
+                Ok(TaskProgress::Done) => {
+                    task.progress = 1.0;
+                    task.done = true;
+                    self.cursor += 1;
+                    log_debug!("Startup task '{}' done in {:?}", task.name, start.elapsed());
+                }
+                Ok(TaskProgress::Partial(progress)) => {
+                    task.progress = progress.clamp(0.0, 1.0);
+                }
+                Err(e) => {
+                    let failure = StartupFailure::new(task.name, e.to_string());
+                    self.failure = Some(failure.clone());
+                    return StartupStatus::Failed(failure);
+                }
+            }
+            if start.elapsed() >= self.budget {
+                break;
+            }
+        }
+        if self.finished() {
+            StartupStatus::Finished
+        } else {
+            StartupStatus::Running
+        }
+    }
+
+    pub fn finished(&self) -> bool {
+        self.failure.is_none() && self.tasks.iter().all(|t| t.done)
+    }
+    pub fn failure(&self) -> Option<&StartupFailure> {
+        self.failure.as_ref()
+    }
+    /// Overall progress in `0..=1`, every task weighted equally.
+    pub fn progress(&self) -> f32 {
+        if self.tasks.is_empty() {
+            return 1.0;
+        }
+        self.tasks.iter().map(|t| t.progress).sum::<f32>() / self.tasks.len() as f32
+    }
+    /// Task currently being worked on.
+    pub fn current(&self) -> Option<&InitTask<C>> {
+        let idx = *self.order.as_ref()?.get(self.cursor)?;
+        self.tasks.get(idx)
+    }
+    /// One line per task for the loading screen, e.g. `"[done] world"` / `"[ 40%] scene"`.
+    pub fn lines(&self) -> Vec<String> {
+        let order: Vec<usize> = match &self.order {
+            Some(order) => order.clone(),
+            None => (0..self.tasks.len()).collect(),
+        };
+        order
+            .into_iter()
+            .map(|i| {
+                let task = &self.tasks[i];
+                if task.done {
+                    format!("[done] {}", task.name)
+                } else {
+                    format!(
+                        "[{:>3}%] {}",
+                        (task.progress * 100.0).round() as u32,
+                        task.name
+                    )
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct Ctx {
+        log: Vec<&'static str>,
+        renderer: Option<u32>,
+        world: Option<u32>,
+    }
+
+    /// Task that logs its name and finishes.
+    fn logged(name: &'static str) -> InitTask<Ctx> {
+        InitTask::once(name, move |ctx: &mut Ctx| {
+            ctx.log.push(name);
+            Ok(())
+        })
+    }
+
+    /// With no budget each step runs exactly one task call.
+    fn stepped() -> Startup<Ctx> {
+        Startup::new().with_budget(Duration::ZERO)
+    }
+
+    fn run(startup: &mut Startup<Ctx>, ctx: &mut Ctx) -> StartupStatus {
+        for _ in 0..100 {
+            match startup.step(ctx) {
+                StartupStatus::Running => continue,
+                status => return status,
+            }
+        }
+        panic!("startup never finished");
+    }
+
+    fn remove_report(failure: &StartupFailure) {
+        if let Some(report) = &failure.report {
+            let _ = std::fs::remove_file(report);
+            let _ = report.parent().map(std::fs::remove_dir);
+        }
+    }
+
+    #[test]
+    fn tasks_run_after_their_dependencies_and_otherwise_in_insertion_order() {
+        let mut startup = stepped();
+        startup
+            .add(logged("scene").after("world").after("shaders"))
+            .add(logged("world").after("renderer"))
+            .add(logged("renderer"))
+            .add(logged("shaders").after("renderer"))
+            .add(logged("audio"));
+        let mut ctx = Ctx::default();
+        assert!(matches!(
+            run(&mut startup, &mut ctx),
+            StartupStatus::Finished
+        ));
+        assert_eq!(ctx.log, ["renderer", "world", "shaders", "scene", "audio"]);
+        assert_eq!(
+            startup.lines(),
+            [
+                "[done] renderer",
+                "[done] world",
+                "[done] shaders",
+                "[done] scene",
+                "[done] audio"
+            ]
+        );
+    }
+
+    #[test]
+    fn progress_counts_partial_tasks() {
+        let mut startup = stepped();
+        let mut chunks = 0;
+        startup
+            .add(logged("renderer"))
+            .add(InitTask::new("terrain", move |_: &mut Ctx| {
+                chunks += 1;
+                Ok(match chunks {
+                    4 => TaskProgress::Done,
+                    n => TaskProgress::Partial(n as f32 / 4.0),
+                })
+            }));
+        let mut ctx = Ctx::default();
+        assert_eq!(startup.progress(), 0.0);
+
+        let expected = [0.5, 0.625, 0.75, 0.875];
+        for progress in expected {
+            assert!(matches!(startup.step(&mut ctx), StartupStatus::Running));
+            assert_eq!(startup.progress(), progress);
+        }
+        assert_eq!(startup.current().map(|t| t.name()), Some("terrain"));
+        assert_eq!(startup.lines(), ["[done] renderer", "[ 75%] terrain"]);
+
+        assert!(matches!(startup.step(&mut ctx), StartupStatus::Finished));
+        assert_eq!(startup.progress(), 1.0);
+        assert!(startup.current().is_none());
+    }
+
+    #[test]
+    fn failures_stop_startup_and_are_reported() {
+        let mut startup = stepped();
+        startup
+            .add(logged("renderer"))
+            .add(InitTask::once("materials", |_: &mut Ctx| {
+                Err(EngineError::AssetMissing("ground.mat.ron".into()))
+            }))
+            .add(logged("scene").after("materials"));
+        let mut ctx = Ctx::default();
+        let StartupStatus::Failed(failure) = run(&mut startup, &mut ctx) else {
+            panic!("materials should fail");
+        };
+        remove_report(&failure);
+        assert_eq!(failure.task, "materials");
+        assert!(
+            failure.message.contains("ground.mat.ron"),
+            "{}",
+            failure.message
+        );
+        assert_eq!(ctx.log, ["renderer"]);
+        assert!(!startup.finished());
+
+        // Later steps keep reporting the same failure without running anything.
+        assert!(matches!(startup.step(&mut ctx), StartupStatus::Failed(_)));
+        assert_eq!(ctx.log, ["renderer"]);
+    }
+
+    #[test]
+    fn unknown_dependencies_and_cycles_fail_before_any_task_runs() {
+        let mut startup = stepped();
+        startup
+            .add(logged("renderer"))
+            .add(logged("scene").after("wrold"));
+        let mut ctx = Ctx::default();
+        let StartupStatus::Failed(failure) = startup.step(&mut ctx) else {
+            panic!("unknown dependency accepted");
+        };
+        remove_report(&failure);
+        assert_eq!(failure.task, "scene");
+        assert!(failure.message.contains("wrold"));
+
+        let mut startup = stepped();
+        startup
+            .add(logged("a").after("b"))
+            .add(logged("b").after("a"));
+        let StartupStatus::Failed(failure) = startup.step(&mut ctx) else {
+            panic!("cycle accepted");
+        };
+        remove_report(&failure);
+        assert_eq!(failure.message, "dependency cycle");
+        assert!(ctx.log.is_empty());
+    }
+
+    #[test]
+    fn state_is_only_visible_once_its_task_finished() {
+        let mut startup = stepped();
+        let mut calls = 0;
+        startup
+            .add(InitTask::new("renderer", move |ctx: &mut Ctx| {
+                calls += 1;
+                if calls < 3 {
+                    return Ok(TaskProgress::Partial(calls as f32 / 3.0));
+                }
+                ctx.renderer = Some(1);
+                Ok(TaskProgress::Done)
+            }))
+            .add(
+                InitTask::once("world", |ctx: &mut Ctx| {
+                    // Ordering guarantees the renderer is complete here.
+                    let renderer = ctx.renderer.expect("renderer not ready");
+                    ctx.world = Some(renderer + 1);
+                    Ok(())
+                })
+                .after("renderer"),
+            );
+        let mut ctx = Ctx::default();
+        for _ in 0..2 {
+            startup.step(&mut ctx);
+            assert_eq!((ctx.renderer, ctx.world), (None, None));
+        }
+        startup.step(&mut ctx);
+        assert_eq!((ctx.renderer, ctx.world), (Some(1), None));
+        assert!(matches!(startup.step(&mut ctx), StartupStatus::Finished));
+        assert_eq!(ctx.world, Some(2));
+    }
+}
@@ -8,17 +8,30 @@ pub struct Time {
     pub delta_time: f64,
     pub fps: f64,
     pub elapsed: f64,
+    /// Seconds of scaled time, standing still while paused; what the world's clock and
+    /// anything animated alongside it, like the sun, should follow.
+    pub scaled_elapsed: f64,
     frame_count: u32,
+    scale: f32,
+    paused: bool,
+    step_requested: bool,
 }
 
 impl Time {
+    /// Range [`Time::set_scale`] clamps to.
+    pub const SCALE_RANGE: (f32, f32) = (0.05, 8.0);
+
     pub fn new() -> Self {
         Self {
             last_update: Instant::now(),
             delta_time: 0.0,
             fps: 0.0,
             elapsed: 0.0,
+            scaled_elapsed: 0.0,
             frame_count: 0,
+            scale: 1.0,
+            paused: false,
+            step_requested: false,
         }
     }
 
@@ -29,6 +42,7 @@ impl Time {
 
         self.delta_time = duration.as_secs_f64();
         self.elapsed += self.delta_time;
+        self.scaled_elapsed += self.scaled_delta();
         self.frame_count += 1;
 
         if self.delta_time > 0.0 {
@@ -36,16 +50,53 @@ impl Time {
         }
     }
 
+    /// The last frame's seconds multiplied by the scale; zero while paused. Cameras and
+    /// other view-only work keep using [`Time::delta_time`].
+    pub fn scaled_delta(&self) -> f64 {
+        if self.paused {
+            0.0
+        } else {
+            self.delta_time * self.scale as f64
+        }
+    }
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+    /// Multiplies the time handed to the world, e.g. `0.25` for slow motion.
+    pub fn set_scale(&mut self, scale: f32) {
+        let (min, max) = Self::SCALE_RANGE;
+        self.scale = if scale.is_finite() {
+            scale.clamp(min, max)
+        } else {
+            1.0
+        };
+    }
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+        self.step_requested = false;
+    }
+    /// Asks for one fixed world step while paused; ignored while running.
+    pub fn request_step(&mut self) {
+        self.step_requested = self.paused;
+    }
+    /// Whether a step was requested since the last call.
+    pub fn take_step(&mut self) -> bool {
+        std::mem::take(&mut self.step_requested)
+    }
+
     pub fn text_region(&self, position: [f32; 2]) -> TextRegion {
-        let text_area = TextRegion::new(
-            format!(
-                "FPS: {:.1} Frame time: {:.3}ms",
-                self.fps,
-                self.delta_time * 1000.0,
-            ),
-            position,
-            glyphon::Color::rgb(1, 1, 1),
+        let mut text = format!(
+            "FPS: {:.1} Frame time: {:.3}ms Time scale: {:.2}x",
+            self.fps,
+            self.delta_time * 1000.0,
+            self.scale,
         );
-        text_area
+        if self.paused {
+            text.push_str(" (paused)");
+        }
+        TextRegion::new(text, position, glyphon::Color::rgb(1, 1, 1))
     }
 }
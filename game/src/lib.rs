@@ -0,0 +1,44 @@
+//! App-layer gameplay, loaded by the app's `GameModuleHost` when it's built with the
+//! `hot-reload-game` feature. Edit, `cargo build -p game` (or keep `rupy-cli dev` running)
+//! and the running app picks the new build up, keeping the state below.
+
+use engine::game_module::{EngineApi, GameEvent, GameEventKind, GameModule};
+
+#[derive(Debug, Default)]
+struct Game {
+    landings: u64,
+    hardest_landing: f32,
+}
+
+impl GameModule for Game {
+    fn init(&mut self, api: &mut EngineApi) {
+        api.log(&format!(
+            "game module up, {} landings so far",
+            self.landings
+        ));
+    }
+    fn update(&mut self, _api: &mut EngineApi, _dt: f32) {}
+    fn on_event(&mut self, api: &mut EngineApi, event: &GameEvent) {
+        if event.kind != GameEventKind::Landed {
+            return;
+        }
+        self.landings += 1;
+        if event.value > self.hardest_landing {
+            self.hardest_landing = event.value;
+            api.log(&format!("hardest landing yet: {:.1} m/s", event.value));
+        }
+    }
+    fn save_state(&self) -> Vec<u8> {
+        let mut state = self.landings.to_le_bytes().to_vec();
+        state.extend_from_slice(&self.hardest_landing.to_le_bytes());
+        state
+    }
+    fn load_state(&mut self, state: &[u8]) {
+        if let (Some(landings), Some(hardest)) = (state.get(0..8), state.get(8..12)) {
+            self.landings = u64::from_le_bytes(landings.try_into().unwrap_or_default());
+            self.hardest_landing = f32::from_le_bytes(hardest.try_into().unwrap_or_default());
+        }
+    }
+}
+
+engine::export_game!(Game);